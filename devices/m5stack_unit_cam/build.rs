@@ -1,4 +1,48 @@
 fn main() {
+    // ビルド情報はホストビルドでも埋め込む（テレメトリで稼働中のファームウェアを確認するため）
+    emit_build_info();
+
+    // ESP-IDF関連のビルド設定は"esp"フィーチャーが有効の時のみ実行
+    #[cfg(feature = "esp")]
+    build_esp_config();
+}
+
+/// gitハッシュ・ビルド時刻・有効なフィーチャーを環境変数として埋め込みます
+fn emit_build_info() {
+    let git = |args: &[&str]| {
+        std::process::Command::new("git")
+            .args(args)
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+    };
+    let git_hash = match git(&["rev-parse", "--short=8", "HEAD"]) {
+        Some(hash) if git(&["status", "--porcelain", "--untracked-files=no"]).is_some_and(|s| !s.is_empty()) => {
+            format!("{}-dirty", hash)
+        }
+        Some(hash) => hash,
+        None => "unknown".to_string(),
+    };
+    let build_timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or(0);
+    let mut features: Vec<String> = std::env::vars()
+        .filter_map(|(key, _)| {
+            key.strip_prefix("CARGO_FEATURE_")
+                .map(|name| name.to_ascii_lowercase().replace('_', "-"))
+        })
+        .collect();
+    features.sort();
+
+    println!("cargo:rustc-env=FARMVERSE_GIT_HASH={}", git_hash);
+    println!("cargo:rustc-env=FARMVERSE_BUILD_TIMESTAMP={}", build_timestamp);
+    println!("cargo:rustc-env=FARMVERSE_FEATURES={}", features.join("+"));
+}
+
+#[cfg(feature = "esp")]
+fn build_esp_config() {
     // Check if the `cfg.toml` file exists and has been filled out.
     if !std::path::Path::new("cfg.toml").exists() {
         panic!("You need to create a `cfg.toml` file with your Wi-Fi credentials! Use `cfg.toml.example` as a template.");
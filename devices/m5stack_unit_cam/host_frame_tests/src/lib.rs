@@ -4,42 +4,184 @@ extern crate thiserror;
 
 #[path = "../../src/core/capture_policy.rs"]
 mod capture_policy;
+#[path = "../../src/hardware/camera/fb_policy.rs"]
+mod fb_policy;
+#[path = "../../src/hardware/camera/health.rs"]
+mod health;
 #[path = "../../src/hardware/camera/ov2640_sequence.rs"]
 mod ov2640_sequence;
+#[path = "../../src/hardware/camera/warmup.rs"]
+mod warmup;
+#[path = "../../src/hardware/camera/recovery.rs"]
+mod recovery;
 #[path = "../../src/communication/esp_now/frame_codec.rs"]
 mod frame_codec;
 #[path = "../../src/communication/esp_now/frame.rs"]
 mod frame;
 #[path = "../../src/communication/esp_now/retry_policy.rs"]
 mod retry_policy;
+#[path = "../../src/communication/esp_now/fec.rs"]
+mod fec;
+#[path = "../../src/core/config_staging.rs"]
+mod config_staging;
 #[path = "../../src/core/config_validation.rs"]
 mod config_validation;
+#[path = "../../src/core/debug_flags.rs"]
+mod debug_flags;
 #[path = "../../src/core/data_prep.rs"]
 mod data_prep;
+#[path = "../../src/core/device_identity.rs"]
+mod device_identity;
 #[path = "../../src/core/domain_logic.rs"]
 mod domain_logic;
+#[path = "../../src/core/image_hash.rs"]
+mod image_hash;
+#[path = "../../src/core/image_pipeline.rs"]
+mod image_pipeline;
+#[path = "../../src/core/image_retention.rs"]
+mod image_retention;
+#[path = "../../src/power/sleep/drift.rs"]
+mod drift;
+#[path = "../../src/power/sleep/alignment.rs"]
+mod alignment;
+#[path = "../../src/communication/esp_now/probe.rs"]
+mod probe;
+#[path = "../../src/communication/esp_now/command_window.rs"]
+mod command_window;
+#[path = "../../src/communication/esp_now/channel_hop.rs"]
+mod channel_hop;
+#[path = "../../src/communication/esp_now/privacy.rs"]
+mod privacy;
+#[path = "../../src/communication/esp_now/phy_rate.rs"]
+mod phy_rate;
+#[path = "../../src/communication/esp_now/radio.rs"]
+mod radio;
+#[path = "../../src/communication/esp_now/relay.rs"]
+mod relay;
+#[path = "../../src/communication/esp_now/chaos.rs"]
+mod chaos;
+#[path = "../../src/communication/esp_now/pacing.rs"]
+mod pacing;
+#[path = "../../src/communication/esp_now/rtt.rs"]
+mod rtt;
+#[path = "../../src/communication/esp_now/telemetry.rs"]
+mod telemetry;
+#[path = "../../src/communication/esp_now/downlink_auth.rs"]
+mod downlink_auth;
+#[path = "../../src/communication/esp_now/control_frame.rs"]
+mod control_frame;
+#[path = "../../src/communication/esp_now/file_transfer.rs"]
+mod file_transfer;
+#[path = "../../src/core/trace.rs"]
+mod trace;
+#[path = "../../src/core/lifecycle.rs"]
+mod lifecycle;
+#[path = "../../src/core/nvs_health.rs"]
+mod nvs_health;
+#[path = "../../src/core/panic_report.rs"]
+mod panic_report;
+#[path = "../../src/core/build_info.rs"]
+mod build_info;
+#[path = "../../src/core/timelapse.rs"]
+mod timelapse;
+#[path = "../../src/core/soak.rs"]
+mod soak;
+#[path = "../../src/core/thumbnail_ring.rs"]
+mod thumbnail_ring;
+#[path = "../../src/hardware/led/pattern.rs"]
+mod led_pattern;
 #[path = "../../src/mac_address.rs"]
 mod mac_address;
+#[path = "../../src/units.rs"]
+mod units;
+#[path = "../../src/sys_wrappers/mod.rs"]
+mod sys_wrappers;
 
 #[cfg(test)]
 mod tests {
+    use super::config_staging::{
+        decide_on_boot, BootDecision, GatewayConfirmation, RemoteConfig, RemoteConfigError, StagingState,
+    };
+    use super::debug_flags::{metadata_field, split_debug_field, DebugFlags, DebugFlagsError, RuntimeDebug};
     use super::config_validation::{
         parse_camera_warmup_frames, parse_receiver_mac, parse_target_minute_last_digit,
-        parse_target_second_tens_digit, validate_wifi_ssid, ValidationError,
+        parse_target_second_tens_digit, validate_sleep_bounds, validate_wifi_ssid, ValidationError,
     };
     use super::capture_policy::{
-        should_capture_image, should_capture_image_with_overrides, INVALID_VOLTAGE_PERCENT,
-        LOW_VOLTAGE_THRESHOLD_PERCENT,
+        should_capture_image, should_capture_image_with_overrides, voltage_skip_reason, CaptureSkipReason,
+        INVALID_VOLTAGE_PERCENT, LOW_VOLTAGE_THRESHOLD_PERCENT,
     };
     use super::data_prep::{prepare_image_payload, simple_image_hash, DUMMY_HASH};
-    use super::domain_logic::{clamp_wifi_tx_power_dbm, resolve_sleep_duration_seconds, voltage_to_percentage};
+    use super::fb_policy::{
+        resolve_fb_location, FbLocation, FrameBufferFailure, FrameBufferPlacement, FrameBufferStage,
+        HeapSnapshot,
+    };
+    use super::drift::{compensated_sleep_micros, ClockSample, DriftEstimator, WakeReference, MAX_DRIFT_PPM};
+    use super::downlink_auth::{
+        security_metadata_fields, verify_broadcast_config, verify_config_update, verify_file_chunk, verify_key_rotation,
+        verify_signed_sleep_command, BroadcastConfig, ConfigUpdate, DeviceKeys, DownlinkKey, DownlinkRejection,
+        FileChunk, SignedSleepCommand, KEY_ROTATION_LEN, SIGNED_SLEEP_COMMAND_LEN,
+    };
+    use super::control_frame::{
+        crc32, is_control_frame, parse_control_frame, parse_legacy_sleep_seconds, ControlCommand, ControlFrameError,
+    };
+    use super::file_transfer::{ChunkOutcome, FileAssembly};
+    use super::trace::TraceContext;
+    use super::lifecycle::{BootReason, EventLog, WakeCause, EVENT_LOG_CAPACITY};
+    use super::build_info::{config_hash, BuildInfo};
+    use super::nvs_health::{is_nvs_error, metadata_field as nvs_metadata_field, NvsRecovery, NvsUsage};
+    use super::panic_report::{PanicRecord, PANIC_LOCATION_CAPACITY, PANIC_MESSAGE_CAPACITY};
+    use super::timelapse::{SequenceState, TimelapseSettings};
+    use super::soak::{SoakLog, SoakOutcome};
+    use super::thumbnail_ring::{
+        jpeg_dimensions, thumbnail_dimensions, MemoryThumbnailStorage, Thumbnail, ThumbnailError, ThumbnailRing,
+        ThumbnailStorage, MAX_THUMBNAIL_LEN,
+    };
+    use super::sys_wrappers::mock::MockSys;
+    use super::sys_wrappers::{sta_mac_or_fallback, Entropy, SysError, WifiRadio, FALLBACK_STA_MAC};
+    use super::led_pattern::{LedEvent, LedPattern, LedPatterns, LedState};
+    use super::probe::{
+        defer_wait_ms, encode_ping, parse_defer, parse_pong, parse_reject, reject_backoff_seconds, reject_metadata_fields,
+        ChunkSizeAdvert, Defer, Pong, ProbeOutcome, Reject, RejectReason, CAPABILITY_CHANNEL_HOP, CAPABILITY_CHUNK_SIZE, CAPABILITY_COMMAND_WINDOW, CAPABILITY_GATEWAY_CLOCK,
+        CAPABILITY_LOSS_REPORT, CAPABILITY_PRIVACY,
+    };
+    use super::command_window::{parse_hash_ack, window_saved_metadata_field, HashAck};
+    use super::channel_hop::{HopPlan, HopSchedule, HOP_BACKOFF_CYCLES, MAX_HOP_MISSES};
+    use super::privacy::{build_dummy_frame, pad_frame, PrivacyParams};
+    use super::chaos::{ChaosAction, ChaosInjector, ChaosParams, DEFAULT_CHAOS_SEED};
+    use super::pacing::{
+        ChunkPacer, GapWait, PacingMode, PacingParams, SendOutcome, BACKOFF_BASE_MS, LIGHT_SLEEP_MIN_GAP_MS,
+    };
+    use super::radio::{EspNowRate, RadioSettings};
+    use super::alignment::{
+        alignment_error_us, next_boundary_us, plan_aligned_sleep, remaining_wait_us, AlignedSleep,
+        AlignmentSettings,
+    };
+    use super::domain_logic::{
+        clamp_sleep_duration_seconds, clamp_wifi_tx_power_dbm, resolve_sleep_duration_seconds, voltage_to_percentage,
+    };
+    use super::fec::{
+        encode_group_parity, recover_group, select_fec_params, FecError, FecParams, ParityChunk,
+        FEC_PARITY_HEADER_LEN,
+    };
     use super::frame::ImageFrame;
+    use super::image_hash::ImageHashAlgo;
+    use super::image_retention::{retention_metadata_fields, RetainedImage, MAX_RESENDS_PER_CYCLE};
+    use super::image_pipeline::{
+        analyze_jpeg, assess_image, ImageQuality, QualityAssessment, QualityError,
+        QualityThresholds, SkipReason,
+    };
     use super::frame_codec::{
         build_hash_payload, build_sensor_data_frame, calculate_xor_checksum,
-        payload_size_candidates, safe_initial_payload_size, END_MARKER, ESP_NOW_MAX_SIZE,
+        payload_size_candidates, payload_size_plan, safe_initial_payload_size, FrameType, END_MARKER, ESP_NOW_MAX_SIZE,
         FRAME_OVERHEAD, START_MARKER,
     };
     use super::mac_address::MacAddress;
+    use super::relay::{add_relay_hop, RelayBuffer, RelayEvent};
+    use super::telemetry::{
+        TelemetryField, TelemetryFields, TELEMETRY_CAP_PASSTHROUGH, TELEMETRY_CAP_TDS, TELEMETRY_CAP_TEMPERATURE,
+    };
+    use super::units::{Celsius, Millivolts, Percent, Ppm};
     use super::retry_policy::{no_mem_retry_delay_ms, retry_count_for_chunk, retry_delay_ms};
     use super::ov2640_sequence::{
         deep_sleep_standby_sequence, resume_sequence, standby_clkrc_write, standby_sequence,
@@ -72,12 +214,34 @@ mod tests {
         assert_eq!(checksum, 0x0C040404);
     }
 
+    #[test]
+    fn frame_types_match_gateway_values() {
+        // ゲートウェイの FrameType と同じ値（変更するとゲートウェイ側でフレームを解釈できなくなる）
+        let expected = |frame_type: FrameType| match frame_type {
+            FrameType::Hash => 1,
+            FrameType::Data => 2,
+            FrameType::Eof => 3,
+            FrameType::Fec => 4,
+            FrameType::Abort => 7,
+            FrameType::Dummy => 8,
+            FrameType::Urgent => 10,
+        };
+        for frame_type in FrameType::ALL {
+            assert_eq!(frame_type.to_byte(), expected(frame_type));
+            assert_eq!(FrameType::from_byte(frame_type.to_byte()), Some(frame_type));
+        }
+        // 網羅的な match に加えた型は ALL にも加える
+        assert_eq!(FrameType::ALL.len(), 7);
+        assert_eq!(FrameType::from_byte(5), None);
+        assert_eq!(FrameType::from_byte(0), None);
+    }
+
     #[test]
     fn frame_structure_matches_sensor_data_protocol() {
         let mac = [0x10, 0x11, 0x12, 0x13, 0x14, 0x15];
         let sequence = 0x01020304;
         let payload = [0xAA, 0xBB, 0xCC];
-        let frame = build_sensor_data_frame(2, mac, sequence, &payload);
+        let frame = build_sensor_data_frame(FrameType::Data, mac, sequence, &payload);
 
         let expected_len = 4 + 6 + 1 + 4 + 4 + payload.len() + 4 + 4;
         assert_eq!(frame.len(), expected_len);
@@ -118,21 +282,33 @@ mod tests {
 
     #[test]
     fn hash_payload_uses_dummy_values_when_missing_optional_fields() {
-        let payload = build_hash_payload("abc", 42, None, None, "2026/02/11 12:00:00.000");
+        let payload = build_hash_payload("abc", Percent::new(42), None, None, "2026/02/11 12:00:00.000");
         assert_eq!(
             payload,
-            "HASH:abc,VOLT:42,TEMP:-999.0,TDS_VOLT:-999.0,2026/02/11 12:00:00.000"
+            "HASH:abc,VOLT:42,TEMP:-999.0,TDS_VOLT:-999.0,2026/02/11 12:00:00.000,TLM_V:1,TLM_CAPS:4"
         );
     }
 
     #[test]
     fn hash_payload_uses_provided_optional_fields() {
-        let payload =
-            build_hash_payload("abc", 42, Some(25.2), Some(1.7), "2026/02/11 12:00:00.000");
+        let payload = build_hash_payload(
+            "abc",
+            Percent::new(42),
+            Some(Celsius::new(25.2)),
+            Some(Millivolts::from_volts(1.7)),
+            "2026/02/11 12:00:00.000",
+        );
         assert_eq!(
             payload,
-            "HASH:abc,VOLT:42,TEMP:25.2,TDS_VOLT:1.7,2026/02/11 12:00:00.000"
+            "HASH:abc,VOLT:42,TEMP:25.2,TDS_VOLT:1.7,2026/02/11 12:00:00.000,TLM_V:1,TLM_CAPS:7"
+        );
+        let telemetry = TelemetryFields::parse(&payload);
+        assert_eq!(telemetry.version(), 1);
+        assert_eq!(
+            telemetry.capabilities(),
+            TELEMETRY_CAP_TEMPERATURE | TELEMETRY_CAP_TDS | TELEMETRY_CAP_PASSTHROUGH
         );
+        assert_eq!(telemetry.fields()[4], TelemetryField::Bare("2026/02/11 12:00:00.000"));
     }
 
     #[test]
@@ -167,6 +343,41 @@ mod tests {
         assert_eq!(voltage_to_percentage(1500.0, 500.0, 2500.0), 50);
     }
 
+    #[test]
+    fn millivolts_convert_to_and_from_volts() {
+        let tds = Millivolts::from_volts(1.7);
+        assert!((tds.value() - 1700.0).abs() < 1e-3);
+        assert!((tds.as_volts() - 1.7).abs() < 1e-6);
+        assert_eq!(Millivolts::from(1234u16), Millivolts::new(1234.0));
+        assert_eq!(Millivolts::new(1234.4).to_string(), "1234 mV");
+    }
+
+    #[test]
+    fn percent_clamps_and_keeps_the_invalid_marker_apart() {
+        assert_eq!(Percent::new(150).value(), 100);
+        assert!(Percent::new(100).is_valid());
+        assert!(!Percent::INVALID.is_valid());
+        assert_eq!(Percent::INVALID.value(), INVALID_VOLTAGE_PERCENT);
+        assert_eq!(Percent::INVALID.to_string(), "255%");
+        assert_eq!(
+            Percent::of_range(Millivolts::new(1500.0), Millivolts::new(500.0), Millivolts::new(2500.0)),
+            Percent::new(50)
+        );
+    }
+
+    #[test]
+    fn tds_ppm_is_compensated_to_the_reference_temperature() {
+        let at_reference = Ppm::from_tds_voltage(Millivolts::from_volts(1.0), Some(Celsius::REFERENCE));
+        assert!((at_reference.value() - 367.475).abs() < 0.01);
+        // 温度が分からなければ補正しない
+        assert_eq!(Ppm::from_tds_voltage(Millivolts::from_volts(1.0), None), at_reference);
+        // 35℃では電圧が1.2倍になるため、補正後は25℃の1.0Vと同じ値になる
+        let warm = Ppm::from_tds_voltage(Millivolts::from_volts(1.2), Some(Celsius::new(35.0)));
+        assert!((warm.value() - at_reference.value()).abs() < 0.01);
+        assert_eq!(Ppm::from_tds_voltage(Millivolts::new(0.0), None).value(), 0.0);
+        assert_eq!(Celsius::new(25.24).to_string(), "25.2 ℃");
+    }
+
     #[test]
     fn resolve_sleep_duration_prefers_received_positive_value() {
         assert_eq!(resolve_sleep_duration_seconds(Some(123), 999), 123);
@@ -310,25 +521,41 @@ mod tests {
 
     #[test]
     fn prepare_image_payload_uses_dummy_for_none() {
-        let (data, hash) = prepare_image_payload(None);
+        let (data, hash) = prepare_image_payload(None, ImageHashAlgo::Sha256);
         assert!(data.is_empty());
         assert_eq!(hash, DUMMY_HASH);
     }
 
     #[test]
     fn prepare_image_payload_uses_dummy_for_empty_data() {
-        let (data, hash) = prepare_image_payload(Some(vec![]));
+        let (data, hash) = prepare_image_payload(Some(vec![]), ImageHashAlgo::Xxh64);
         assert!(data.is_empty());
         assert_eq!(hash, DUMMY_HASH);
     }
 
     #[test]
     fn prepare_image_payload_returns_data_and_hash_for_valid_data() {
-        let (data, hash) = prepare_image_payload(Some(vec![1, 2, 3]));
+        let (data, hash) = prepare_image_payload(Some(vec![1, 2, 3]), ImageHashAlgo::Sum);
         assert_eq!(data, vec![1, 2, 3]);
         assert_eq!(hash, "0000000300000006");
     }
 
+    #[test]
+    fn image_hash_algorithms_match_reference_values() {
+        assert_eq!(ImageHashAlgo::Xxh64.hash(b"abc"), "44bc2cf5ad770999");
+        assert_eq!(
+            ImageHashAlgo::Sha256.hash(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        // 16MBを超える画像でもバイト和は折り返す
+        assert_eq!(ImageHashAlgo::Sum.hash(&vec![0xff; 0x0101_0102]), "01010102000000fe");
+
+        assert_eq!(ImageHashAlgo::parse("SHA256"), Some(ImageHashAlgo::Sha256));
+        assert_eq!(ImageHashAlgo::parse(" xxh64 "), Some(ImageHashAlgo::Xxh64));
+        assert_eq!(ImageHashAlgo::parse("md5"), None);
+        assert_eq!(ImageHashAlgo::default().metadata_field(), ",HASH_ALGO:XXH64");
+    }
+
     #[test]
     fn should_capture_image_rejects_low_voltage_threshold_and_below() {
         assert!(!should_capture_image(LOW_VOLTAGE_THRESHOLD_PERCENT));
@@ -371,6 +598,32 @@ mod tests {
         assert!(should_capture_image_with_overrides(50, false, false));
     }
 
+    #[test]
+    fn voltage_skip_reason_distinguishes_low_and_invalid_voltage() {
+        assert_eq!(voltage_skip_reason(50, false, false), None);
+        assert_eq!(voltage_skip_reason(0, true, false), None);
+        assert_eq!(voltage_skip_reason(LOW_VOLTAGE_THRESHOLD_PERCENT, false, false), Some(CaptureSkipReason::LowVoltage));
+        assert_eq!(voltage_skip_reason(INVALID_VOLTAGE_PERCENT, false, true), Some(CaptureSkipReason::InvalidVoltage));
+        assert_eq!(CaptureSkipReason::LowVoltage.metadata_field(), ",SKIP:LOW_VOLTAGE");
+        assert_eq!(CaptureSkipReason::Quality(SkipReason::Blurry).metadata_field(), ",SKIP:BLUR");
+    }
+
+    #[test]
+    fn clamp_sleep_duration_reports_out_of_range_request() {
+        assert_eq!(clamp_sleep_duration_seconds(600, 10, 43200), (600, None));
+        // 入力ミス（60000秒）は上限へ補正
+        assert_eq!(clamp_sleep_duration_seconds(60000, 10, 43200), (43200, Some(60000)));
+        assert_eq!(clamp_sleep_duration_seconds(1, 10, 43200), (10, Some(1)));
+    }
+
+    #[test]
+    fn validate_sleep_bounds_rejects_inverted_or_unbounded_range() {
+        assert_eq!(validate_sleep_bounds(10, 43200), Ok(()));
+        assert_eq!(validate_sleep_bounds(0, 60), Err(ValidationError::InvalidSleepBounds(0, 60)));
+        assert_eq!(validate_sleep_bounds(600, 60), Err(ValidationError::InvalidSleepBounds(600, 60)));
+        assert_eq!(validate_sleep_bounds(10, 90000), Err(ValidationError::InvalidSleepBounds(10, 90000)));
+    }
+
     #[test]
     fn clamp_wifi_tx_power_dbm_limits_range() {
         assert_eq!(clamp_wifi_tx_power_dbm(-10), 2);
@@ -414,4 +667,1804 @@ mod tests {
         assert_eq!(seq[1].reg, 0xD3); // R_DVP_SP
         assert_eq!(seq[1].value, 0x00);
     }
+
+    fn fec_test_chunks() -> Vec<Vec<u8>> {
+        vec![
+            vec![0x01, 0x02, 0x03, 0x04],
+            vec![0x10, 0x20, 0x30, 0x40],
+            vec![0xAA, 0xBB, 0xCC, 0xDD],
+            vec![0x55, 0x66],
+        ]
+    }
+
+    fn fec_encode(chunks: &[Vec<u8>], parity_count: u8) -> Vec<ParityChunk> {
+        let refs: Vec<&[u8]> = chunks.iter().map(|c| c.as_slice()).collect();
+        encode_group_parity(100, &refs, parity_count)
+    }
+
+    #[test]
+    fn fec_params_reject_invalid_combinations() {
+        assert!(FecParams::new(8, 2).is_ok());
+        assert!(FecParams::new(0, 1).is_err());
+        assert!(FecParams::new(4, 0).is_err());
+        assert!(FecParams::new(2, 3).is_err());
+        assert!(FecParams::new(16, 5).is_err());
+    }
+
+    #[test]
+    fn fec_announce_payload_roundtrip() {
+        let params = FecParams::new(8, 2).unwrap();
+        let payload = params.to_announce_payload();
+        assert_eq!(payload, [0, 8, 2]);
+        assert_eq!(FecParams::from_announce_payload(&payload), Ok(params));
+        assert_eq!(FecParams::from_announce_payload(&[0, 8]), Err(FecError::TooShort(2)));
+        assert_eq!(FecParams::from_announce_payload(&[9, 8, 2]), Err(FecError::UnknownKind(9)));
+    }
+
+    #[test]
+    fn fec_parity_payload_roundtrip() {
+        let parities = fec_encode(&fec_test_chunks(), 2);
+        assert_eq!(parities.len(), 2);
+        for parity in &parities {
+            let payload = parity.to_payload();
+            assert_eq!(payload.len(), FEC_PARITY_HEADER_LEN + parity.parity.len());
+            assert_eq!(ParityChunk::from_payload(&payload).unwrap(), *parity);
+        }
+        assert_eq!(ParityChunk::from_payload(&[1, 0, 0]), Err(FecError::TooShort(3)));
+    }
+
+    #[test]
+    fn fec_parity_covers_interleaved_chunks() {
+        let parities = fec_encode(&fec_test_chunks(), 2);
+        assert_eq!(parities[0].first_seq, 100);
+        assert_eq!(parities[0].data_count, 4);
+        // parity0 = chunk0 ^ chunk2, parity1 = chunk1 ^ chunk3 (0埋め)
+        assert_eq!(parities[0].parity, vec![0xAB, 0xB9, 0xCF, 0xD9]);
+        assert_eq!(parities[1].parity, vec![0x45, 0x46, 0x30, 0x40]);
+        assert_eq!(parities[1].length_xor, 4 ^ 2);
+    }
+
+    #[test]
+    fn fec_recovers_single_loss_per_parity() {
+        let chunks = fec_test_chunks();
+        let parities = fec_encode(&chunks, 2);
+
+        // 連続2チャンク（異なるパリティ担当）の欠落を復元
+        let mut received: Vec<Option<Vec<u8>>> = chunks.iter().cloned().map(Some).collect();
+        received[2] = None;
+        received[3] = None;
+        assert_eq!(recover_group(&mut received, &parities), 2);
+        let restored: Vec<Vec<u8>> = received.into_iter().map(|c| c.unwrap()).collect();
+        assert_eq!(restored, chunks);
+    }
+
+    #[test]
+    fn fec_cannot_recover_two_losses_in_same_parity_class() {
+        let chunks = fec_test_chunks();
+        let parities = fec_encode(&chunks, 2);
+
+        let mut received: Vec<Option<Vec<u8>>> = chunks.iter().cloned().map(Some).collect();
+        received[0] = None;
+        received[2] = None;
+        assert_eq!(recover_group(&mut received, &parities), 0);
+        assert!(received[0].is_none());
+        assert!(received[2].is_none());
+    }
+
+    #[test]
+    fn fec_recovers_with_partial_group_and_missing_parity() {
+        let chunks = vec![vec![1, 2, 3], vec![4, 5, 6, 7, 8]];
+        let parities = fec_encode(&chunks, 1);
+        assert_eq!(parities.len(), 1);
+
+        let mut received = vec![Some(chunks[0].clone()), None];
+        assert_eq!(recover_group(&mut received, &parities), 1);
+        assert_eq!(received[1].as_deref(), Some(&[4, 5, 6, 7, 8][..]));
+
+        // パリティが届かなかった場合は何もしない
+        let mut received = vec![Some(chunks[0].clone()), None];
+        assert_eq!(recover_group(&mut received, &[]), 0);
+    }
+
+    #[test]
+    fn fec_select_params_scales_with_loss_rate() {
+        assert_eq!(select_fec_params(0, 2, 20), None);
+        assert_eq!(select_fec_params(8, 0, 20), None);
+        assert_eq!(select_fec_params(8, 2, 0), None);
+        assert_eq!(select_fec_params(8, 4, 5), Some(FecParams::new(8, 1).unwrap()));
+        assert_eq!(select_fec_params(8, 4, 20), Some(FecParams::new(8, 4).unwrap()));
+        assert_eq!(select_fec_params(8, 2, 50), Some(FecParams::new(8, 2).unwrap()));
+        assert_eq!(select_fec_params(2, 4, 100), Some(FecParams::new(2, 2).unwrap()));
+    }
+
+    /// テスト用ビットライター（MSBファースト、0xFFのバイトスタッフィング付き）
+    struct JpegBitWriter {
+        bytes: Vec<u8>,
+        current: u8,
+        filled: u8,
+    }
+
+    impl JpegBitWriter {
+        fn new() -> Self {
+            Self { bytes: Vec::new(), current: 0, filled: 0 }
+        }
+
+        fn write(&mut self, value: u32, bits: u8) {
+            for i in (0..bits).rev() {
+                self.current = (self.current << 1) | ((value >> i) & 1) as u8;
+                self.filled += 1;
+                if self.filled == 8 {
+                    self.push_byte();
+                }
+            }
+        }
+
+        fn push_byte(&mut self) {
+            self.bytes.push(self.current);
+            if self.current == 0xFF {
+                self.bytes.push(0x00);
+            }
+            self.current = 0;
+            self.filled = 0;
+        }
+
+        fn finish(mut self) -> Vec<u8> {
+            while self.filled != 0 {
+                self.write(1, 1);
+            }
+            self.bytes
+        }
+    }
+
+    fn jpeg_bit_size(value: i32) -> u8 {
+        (32 - value.unsigned_abs().leading_zeros()) as u8
+    }
+
+    fn jpeg_value_bits(value: i32, size: u8) -> u32 {
+        if value < 0 {
+            (value + (1 << size) - 1) as u32
+        } else {
+            value as u32
+        }
+    }
+
+    /// AC符号表のシンボル（EOB, ラン0-15×サイズ1-10, ZRL）。符号長はすべて8ビット
+    fn jpeg_ac_symbols() -> Vec<u8> {
+        let mut symbols = vec![0x00];
+        for run in 0..16u8 {
+            for size in 1..=10u8 {
+                symbols.push((run << 4) | size);
+            }
+        }
+        symbols.push(0xF0);
+        symbols
+    }
+
+    /// 8x8ブロックを横に並べたグレースケールのベースラインJPEGを生成する
+    ///
+    /// 各ブロックは (DC係数, [(ジグザグ位置, AC係数)]) で指定し、量子化テーブルはすべて1。
+    fn build_test_jpeg(blocks: &[(i32, Vec<(usize, i32)>)]) -> Vec<u8> {
+        let ac_symbols = jpeg_ac_symbols();
+        let mut jpeg = vec![0xFF, 0xD8];
+
+        // DQT（8ビット精度、すべて1）
+        jpeg.extend_from_slice(&[0xFF, 0xDB, 0x00, 67, 0x00]);
+        jpeg.extend_from_slice(&[1; 64]);
+
+        // SOF0（8x(8*N)、1成分）
+        let width = (blocks.len() * 8) as u16;
+        jpeg.extend_from_slice(&[0xFF, 0xC0, 0x00, 11, 8, 0x00, 8]);
+        jpeg.extend_from_slice(&width.to_be_bytes());
+        jpeg.extend_from_slice(&[1, 1, 0x11, 0]);
+
+        // DHT DC（サイズ0-11を4ビット符号で）
+        let mut dc_counts = [0u8; 16];
+        dc_counts[3] = 12;
+        jpeg.extend_from_slice(&[0xFF, 0xC4, 0x00, (2 + 17 + 12) as u8, 0x00]);
+        jpeg.extend_from_slice(&dc_counts);
+        jpeg.extend((0..12u8).collect::<Vec<_>>());
+
+        // DHT AC（全シンボルを8ビット符号で）
+        let mut ac_counts = [0u8; 16];
+        ac_counts[7] = ac_symbols.len() as u8;
+        jpeg.extend_from_slice(&[0xFF, 0xC4, 0x00, (2 + 17 + ac_symbols.len()) as u8, 0x10]);
+        jpeg.extend_from_slice(&ac_counts);
+        jpeg.extend_from_slice(&ac_symbols);
+
+        // SOS
+        jpeg.extend_from_slice(&[0xFF, 0xDA, 0x00, 8, 1, 1, 0x00, 0, 63, 0]);
+
+        let mut writer = JpegBitWriter::new();
+        let mut prediction = 0;
+        for (dc, acs) in blocks {
+            let diff = dc - prediction;
+            prediction = *dc;
+            let size = jpeg_bit_size(diff);
+            writer.write(size as u32, 4);
+            writer.write(jpeg_value_bits(diff, size), size);
+
+            let mut last = 0;
+            for &(k, value) in acs {
+                let mut run = k - last - 1;
+                while run > 15 {
+                    writer.write(ac_symbols.iter().position(|&s| s == 0xF0).unwrap() as u32, 8);
+                    run -= 16;
+                }
+                let size = jpeg_bit_size(value);
+                let symbol = ((run as u8) << 4) | size;
+                writer.write(ac_symbols.iter().position(|&s| s == symbol).unwrap() as u32, 8);
+                writer.write(jpeg_value_bits(value, size), size);
+                last = k;
+            }
+            if last < 63 {
+                writer.write(0, 8); // EOB
+            }
+        }
+        jpeg.extend_from_slice(&writer.finish());
+        jpeg.extend_from_slice(&[0xFF, 0xD9]);
+        jpeg
+    }
+
+    #[test]
+    fn image_quality_flat_gray_has_mid_luma_and_no_sharpness() {
+        let jpeg = build_test_jpeg(&[(0, vec![]), (0, vec![])]);
+        assert_eq!(
+            analyze_jpeg(&jpeg),
+            Ok(ImageQuality { mean_luma: 128, sharpness: 0 })
+        );
+    }
+
+    #[test]
+    fn image_quality_dark_and_textured_blocks() {
+        // DC=-800 → 平均 -100 + 128 = 28
+        let dark = build_test_jpeg(&[(-800, vec![]), (-800, vec![])]);
+        assert_eq!(analyze_jpeg(&dark).unwrap().mean_luma, 28);
+
+        // ブロック平均のAC絶対値合計がシャープネスになる（ZRLを含む）
+        let textured = build_test_jpeg(&[
+            (80, vec![(1, 50), (5, -30), (40, 7)]),
+            (-80, vec![(2, -13)]),
+        ]);
+        assert_eq!(
+            analyze_jpeg(&textured),
+            Ok(ImageQuality { mean_luma: 128, sharpness: (87 + 13) / 2 })
+        );
+    }
+
+    #[test]
+    fn image_quality_rejects_invalid_jpeg() {
+        assert_eq!(analyze_jpeg(b"not a jpeg"), Err(QualityError::NotJpeg));
+
+        let jpeg = build_test_jpeg(&[(0, vec![(1, 100)]), (0, vec![])]);
+        let truncated = &jpeg[..jpeg.len() - 8];
+        assert!(analyze_jpeg(truncated).is_err());
+
+        let mut progressive = jpeg.clone();
+        let sof = progressive.windows(2).position(|w| w == [0xFF, 0xC0]).unwrap();
+        progressive[sof + 1] = 0xC2;
+        assert_eq!(
+            analyze_jpeg(&progressive),
+            Err(QualityError::Unsupported("プログレッシブJPEG"))
+        );
+    }
+
+    #[test]
+    fn image_quality_assessment_applies_thresholds_only_when_enabled() {
+        let dark = build_test_jpeg(&[(-800, vec![])]);
+        let thresholds = QualityThresholds { min_luma: 40, ..QualityThresholds::default() };
+
+        let assessment = assess_image(Some(&dark), &thresholds).unwrap();
+        assert_eq!(assessment.skip_reason, None);
+        assert_eq!(assessment.metadata_fields(), ",LUMA:28,SHARP:0");
+
+        let thresholds = QualityThresholds { skip_enabled: true, ..thresholds };
+        let assessment = assess_image(Some(&dark), &thresholds).unwrap();
+        assert_eq!(assessment.skip_reason, Some(SkipReason::TooDark));
+        assert_eq!(assessment.metadata_fields(), ",LUMA:28,SHARP:0,SKIP:DARK");
+
+        let blurry = QualityThresholds { skip_enabled: true, min_sharpness: 10, ..QualityThresholds::default() };
+        let flat = build_test_jpeg(&[(0, vec![])]);
+        assert_eq!(
+            assess_image(Some(&flat), &blurry).unwrap().skip_reason,
+            Some(SkipReason::Blurry)
+        );
+
+        // 画像なしは評価しない
+        assert_eq!(assess_image(None, &thresholds), Ok(QualityAssessment::default()));
+        assert_eq!(QualityAssessment::default().metadata_fields(), "");
+    }
+
+    #[test]
+    fn sleep_drift_estimate_converges_and_offsets_compensation() {
+        let mut estimator = DriftEstimator::default();
+        assert_eq!(estimator.compensation_micros(-2_000, 60_000_000), -2_000);
+
+        // 60秒スリープで60ms遅れて起床 → 1000ppm
+        assert_eq!(estimator.observe(60_000_000, 60_000), Some(1_000));
+        // 以降は指数移動平均で 2000ppm に近づく
+        assert_eq!(estimator.observe(60_000_000, 120_000), Some(1_250));
+        for _ in 0..30 {
+            estimator.observe(60_000_000, 120_000);
+        }
+        let converged = estimator.drift_ppm().unwrap();
+        assert!((1_990..=2_000).contains(&converged), "converged={}", converged);
+
+        // 遅れを打ち消すためスリープを短くする
+        let restored = DriftEstimator::from_ppm(Some(2_000));
+        assert_eq!(restored.compensation_micros(-2_000, 60_000_000), -122_000);
+        assert_eq!(compensated_sleep_micros(60_000_000, -122_000), 59_878_000);
+    }
+
+    #[test]
+    fn sleep_drift_rejects_unreliable_observations() {
+        let mut estimator = DriftEstimator::default();
+        // 短すぎるスリープ、異常な誤差は捨てる
+        assert_eq!(estimator.observe(1_000_000, 1_000), None);
+        assert_eq!(estimator.observe(60_000_000, 60_000_000), None);
+        assert_eq!(estimator.drift_ppm(), None);
+        assert_eq!(DriftEstimator::from_ppm(Some(MAX_DRIFT_PPM + 1)).drift_ppm(), None);
+        // 補正後も最短1秒は確保する
+        assert_eq!(compensated_sleep_micros(1_000_000, -5_000_000), 1_000_000);
+    }
+
+    #[test]
+    fn sleep_drift_measures_wake_error_against_gateway_clock() {
+        let reference = WakeReference {
+            sample: ClockSample { gateway_us: 1_000_000_000, local_us: 5_000_000 },
+            programmed_sleep_us: 60_000_000,
+        };
+        // RTCが遅れ、デバイスの経過61秒の間にゲートウェイでは61.06秒経過 → 60ms遅れて起床
+        let current = ClockSample { gateway_us: 1_061_060_000, local_us: 66_000_000 };
+        assert_eq!(reference.wake_error_us(current), Some(60_000));
+        let mut estimator = DriftEstimator::default();
+        assert_eq!(estimator.observe(reference.programmed_sleep_us, 60_000), Some(1_000));
+
+        // ゲートウェイの再起動、スリープしなかった起動は計測しない
+        let restarted = ClockSample { gateway_us: 3_000_000, ..current };
+        assert_eq!(reference.wake_error_us(restarted), None);
+        let no_sleep = ClockSample { gateway_us: 1_002_000_000, local_us: 7_000_000 };
+        assert_eq!(reference.wake_error_us(no_sleep), None);
+    }
+
+    #[test]
+    fn capture_alignment_plans_early_wake_before_boundary() {
+        // 2024-06-01T00:00:10Z
+        let now = 1_717_200_010_000_000;
+        let settings = AlignmentSettings::from_config(60, 1_500).unwrap();
+        assert_eq!(AlignmentSettings::from_config(0, 1_500), None);
+
+        // 600秒後の直後の毎分00秒（00:10:10 → 00:11:00）の1.5秒前に起床
+        assert_eq!(
+            plan_aligned_sleep(now, 600_000_000, settings),
+            Some(AlignedSleep {
+                sleep_us: 648_500_000,
+                target_unix_us: 1_717_200_660_000_000,
+            })
+        );
+        // 余裕を確保できない境界は飛ばす（00:00:59.000 + 1.5秒 → 00:02:00）
+        let near = 1_717_200_059_000_000;
+        let plan = plan_aligned_sleep(near, 60_000_000, settings).unwrap();
+        assert_eq!(plan.target_unix_us, 1_717_200_180_000_000);
+        assert_eq!(plan.sleep_us, 119_500_000);
+        // 時刻が未同期なら整列しない
+        assert_eq!(plan_aligned_sleep(5_000_000, 60_000_000, settings), None);
+    }
+
+    #[test]
+    fn capture_alignment_wait_and_error() {
+        assert_eq!(next_boundary_us(120_000_000, 60_000_000), 120_000_000);
+        assert_eq!(next_boundary_us(120_000_001, 60_000_000), 180_000_000);
+        assert_eq!(remaining_wait_us(100, 1_600), 1_500);
+        assert_eq!(remaining_wait_us(2_000, 1_600), 0);
+        assert_eq!(alignment_error_us(1_000_000, 1_000_350), 350);
+        assert_eq!(alignment_error_us(1_000_000, 999_800), -200);
+    }
+
+    #[test]
+    fn remote_config_staging_tries_once_then_rolls_back() {
+        // ステージング → 試行（先に状態を保存）
+        assert_eq!(
+            decide_on_boot(StagingState::Staged),
+            (BootDecision::TryStaged, StagingState::Trial)
+        );
+        // 確定されないまま再起動 → ロールバック
+        assert_eq!(
+            decide_on_boot(StagingState::Trial),
+            (BootDecision::RolledBack, StagingState::Idle)
+        );
+        assert_eq!(
+            decide_on_boot(StagingState::from_u8(0xFF)),
+            (BootDecision::UseCommitted, StagingState::Idle)
+        );
+    }
+
+    #[test]
+    fn remote_config_trial_queued_but_never_acked_rolls_back() {
+        // 送信キューへの投入は成功したが、HASHフレームへの応答もスリープコマンドも届かない
+        let unconfirmed = GatewayConfirmation::default();
+        assert!(!unconfirmed.confirms_trial(true));
+        // 確定しないまま再起動すると試行状態のまま → ロールバック
+        let (_, state) = decide_on_boot(StagingState::Staged);
+        assert_eq!(decide_on_boot(state), (BootDecision::RolledBack, StagingState::Idle));
+
+        // どちらかの確認が届けば確定する。送信に失敗していれば確認があっても確定しない
+        let hash_acked = GatewayConfirmation { hash_acked: true, ..GatewayConfirmation::default() };
+        let sleep_command = GatewayConfirmation { sleep_command_received: true, ..GatewayConfirmation::default() };
+        assert!(hash_acked.confirms_trial(true));
+        assert!(sleep_command.confirms_trial(true));
+        assert!(!hash_acked.confirms_trial(false));
+    }
+
+    #[test]
+    fn remote_config_encode_decode_and_diff() {
+        let config = RemoteConfig {
+            receiver_mac: Some(MacAddress::new([0x24, 0x0a, 0xc4, 0x01, 0x02, 0x03])),
+            sleep_duration_seconds: Some(600),
+            broadcast_id: None,
+            jpeg_quality: None,
+            frame_size: None,
+        };
+        let encoded = config.encode();
+        assert_eq!(encoded, "RECEIVER_MAC=24:0a:c4:01:02:03;SLEEP=600");
+        assert_eq!(RemoteConfig::decode(&encoded), Ok(config.clone()));
+        assert_eq!(RemoteConfig::decode(""), Ok(RemoteConfig::default()));
+
+        let previous = RemoteConfig {
+            sleep_duration_seconds: Some(600),
+            ..RemoteConfig::default()
+        };
+        assert_eq!(config.diff(&previous).len(), 1);
+
+        assert_eq!(
+            RemoteConfig::decode("SLEEP=0"),
+            Err(RemoteConfigError::InvalidValue("SLEEP=0".to_string()))
+        );
+        assert_eq!(
+            RemoteConfig::decode("CHANNEL=6"),
+            Err(RemoteConfigError::UnknownKey("CHANNEL".to_string()))
+        );
+        assert!(RemoteConfig::decode("RECEIVER_MAC=zz").is_err());
+    }
+
+    #[test]
+    fn camera_fb_placement_resolves_against_psram_availability() {
+        assert_eq!(FrameBufferPlacement::parse(" Prefer_PSRAM "), Some(FrameBufferPlacement::PreferPsram));
+        assert_eq!(FrameBufferPlacement::parse("spiram"), None);
+
+        assert_eq!(resolve_fb_location(FrameBufferPlacement::PsramOnly, true), Some(FbLocation::Psram));
+        assert_eq!(resolve_fb_location(FrameBufferPlacement::PsramOnly, false), None);
+        assert_eq!(resolve_fb_location(FrameBufferPlacement::PreferPsram, true), Some(FbLocation::Psram));
+        assert_eq!(resolve_fb_location(FrameBufferPlacement::PreferPsram, false), Some(FbLocation::Dram));
+        assert_eq!(resolve_fb_location(FrameBufferPlacement::InternalOnly, true), Some(FbLocation::Dram));
+    }
+
+    #[test]
+    fn camera_fb_failure_metadata_includes_heap_stats() {
+        let failure = FrameBufferFailure {
+            stage: FrameBufferStage::Capture,
+            placement: FrameBufferPlacement::InternalOnly,
+            heap: HeapSnapshot {
+                internal_free: 81_234,
+                internal_largest: 30_720,
+                psram_free: 0,
+                psram_largest: 0,
+            },
+        };
+        assert_eq!(
+            failure.metadata_fields(),
+            ",FB_FAIL:CAPTURE,FB_POLICY:INTERNAL,HEAP_FREE:81234,HEAP_LARGEST:30720,PSRAM_FREE:0,PSRAM_LARGEST:0"
+        );
+    }
+
+    #[test]
+    fn probe_ping_pong_wire_format() {
+        assert_eq!(
+            encode_ping(0x0102_0304, None, false, false, false, false, false, false),
+            [0x05, b'P', b'I', b'N', b'G', 0x04, 0x03, 0x02, 0x01]
+        );
+        assert_eq!(
+            parse_pong(&[0x06, b'P', b'O', b'N', b'G', 0x04, 0x03, 0x02, 0x01, 75]),
+            Some(Pong {
+                nonce: 0x0102_0304,
+                queue_free_percent: 75,
+                radio: None,
+                privacy: false,
+                chunk_size: None,
+                hop: None,
+            })
+        );
+        // スリープコマンド（u32 LE）やPing自身はPongとみなさない
+        assert_eq!(parse_pong(&600u32.to_le_bytes()), None);
+        assert_eq!(parse_pong(&encode_ping(1, None, false, false, false, false, false, false)), None);
+    }
+
+    #[test]
+    fn probe_ping_pong_carry_radio_settings() {
+        let radio = RadioSettings {
+            rate: EspNowRate::parse("24m"),
+            tx_power_dbm: 8,
+            ampdu_tx: false,
+        };
+        assert_eq!(&encode_ping(1, Some(&radio), false, false, false, false, false, false)[9..], &[0x09, 8, 0]);
+        // ゲートウェイは24M固定・AMPDU有効
+        let pong = parse_pong(&[0x06, b'P', b'O', b'N', b'G', 1, 0, 0, 0, 50, 0x09, 20, 0x01]).unwrap();
+        let gateway = pong.radio.unwrap();
+        assert_eq!(gateway.summary(), "rate=24M txp=20dBm ampdu=on");
+        assert_eq!(radio.mismatches(&gateway), vec!["ampdu"]);
+        assert!(EspNowRate::parse("100M").is_none());
+
+        let reachable = ProbeOutcome::Reachable {
+            rtt_ms: 12,
+            attempts: 1,
+            queue_free_percent: 50,
+            radio_mismatch: true,
+            deferred_ms: 0,
+        };
+        assert!(reachable.metadata_fields(0).ends_with(",RADIO_MISMATCH:1"));
+    }
+
+    #[test]
+    fn probe_outcome_metadata_fields() {
+        let reachable = ProbeOutcome::Reachable {
+            rtt_ms: 12,
+            attempts: 1,
+            queue_free_percent: 90,
+            radio_mismatch: false,
+            deferred_ms: 0,
+        };
+        assert!(reachable.is_reachable());
+        assert_eq!(reachable.metadata_fields(0), ",PROBE_RTT_MS:12,PROBE_TRIES:1,GW_QUEUE_FREE:90");
+        assert_eq!(
+            reachable.metadata_fields(3),
+            ",PROBE_RTT_MS:12,PROBE_TRIES:1,GW_QUEUE_FREE:90,PROBE_SKIPPED:3"
+        );
+        assert_eq!(ProbeOutcome::Unreachable { attempts: 2 }.metadata_fields(0), ",PROBE_FAIL:2");
+    }
+
+    #[test]
+    fn control_frame_sleep_command_is_length_and_crc_checked() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+
+        // ゲートウェイの `ControlCommand::Sleep { sleep_seconds: 600 }.serialize()` と同じバイト列
+        let frame = [
+            0x0B, b'C', b'T', b'R', b'L', 0x01, 0x04, 0x58, 0x02, 0x00, 0x00, 0x81, 0xF7, 0xDC, 0xB5,
+        ];
+        assert!(is_control_frame(&frame));
+        assert_eq!(parse_control_frame(&frame), Ok(ControlCommand::Sleep { sleep_seconds: 600 }));
+
+        assert_eq!(parse_control_frame(&frame[..14]), Err(ControlFrameError::InvalidLength(14)));
+        let mut corrupted = frame;
+        corrupted[7] = 0x59;
+        assert_eq!(parse_control_frame(&corrupted), Err(ControlFrameError::CrcMismatch));
+
+        let mut unknown = frame[..11].to_vec();
+        unknown[5] = 0x7F;
+        let crc = crc32(&unknown);
+        unknown.extend_from_slice(&crc.to_le_bytes());
+        assert_eq!(parse_control_frame(&unknown), Err(ControlFrameError::UnknownCommand(0x7F)));
+
+        // 他のダウンリンク（Pong・4バイトのu32）は制御フレームとみなさない
+        assert!(!is_control_frame(&[0x06, b'P', b'O', b'N', b'G', 1, 0, 0, 0, 50]));
+        assert!(!is_control_frame(&600u32.to_le_bytes()));
+    }
+
+    #[test]
+    fn control_frame_resend_last_has_no_args() {
+        // ゲートウェイの `ControlCommand::ResendLast.serialize()` と同じバイト列
+        let frame = [0x0B, b'C', b'T', b'R', b'L', 0x02, 0x00, 0x42, 0x4B, 0x86, 0xF1];
+        assert_eq!(parse_control_frame(&frame), Ok(ControlCommand::ResendLast));
+
+        let mut with_args = frame[..7].to_vec();
+        with_args[6] = 1;
+        with_args.push(0);
+        let crc = crc32(&with_args);
+        with_args.extend_from_slice(&crc.to_le_bytes());
+        assert_eq!(
+            parse_control_frame(&with_args),
+            Err(ControlFrameError::InvalidArgs { command_id: 0x02, len: 1 })
+        );
+    }
+
+    #[test]
+    fn thumbnail_ring_keeps_latest_and_survives_reopen() {
+        let mut ring = ThumbnailRing::open(MemoryThumbnailStorage::new(3));
+        for i in 0..5u8 {
+            assert_eq!(ring.push(1_700_000_000 + u32::from(i), vec![i; 100]), Ok(u32::from(i)));
+        }
+        let seqs: Vec<u32> = ring.thumbnails().iter().map(|thumbnail| thumbnail.seq).collect();
+        assert_eq!(seqs, vec![2, 3, 4]);
+        assert_eq!(ring.push(0, vec![0; MAX_THUMBNAIL_LEN + 1]), Err(ThumbnailError::TooLarge(MAX_THUMBNAIL_LEN + 1)));
+
+        // 書き込み途中で壊れたスロットは無視し、開き直したら続きの通し番号から保持する
+        let mut storage = MemoryThumbnailStorage::new(3);
+        for slot in 0..3 {
+            storage.write_slot(slot, &ring.thumbnails()[slot].encode_slot()).unwrap();
+        }
+        let mut corrupt = storage.read_slot(1).unwrap();
+        *corrupt.last_mut().unwrap() ^= 0xFF;
+        storage.write_slot(1, &corrupt).unwrap();
+        let mut reopened = ThumbnailRing::open(storage);
+        assert_eq!(reopened.thumbnails().len(), 2);
+        assert_eq!(reopened.push(0, vec![9; 10]), Ok(5));
+
+        let thumbnail = Thumbnail { seq: 7, captured_unix: 1_700_000_000, jpeg: vec![1, 2, 3] };
+        assert_eq!(Thumbnail::decode_slot(&thumbnail.encode_slot()), Some(thumbnail.clone()));
+        assert_eq!(Thumbnail::decode_slot(&[0xFF; 64]), None);
+        assert_eq!(thumbnail.metadata_fields(2, 3), ",THUMB:2/3,THUMB_SEQ:7,THUMB_AT:1700000000");
+    }
+
+    #[test]
+    fn thumbnail_dimensions_come_from_jpeg_sof() {
+        // SOI, APP0（長さ4）, SOF0（高さ240・幅320）
+        let jpeg = [
+            0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x04, 0x00, 0x00, 0xFF, 0xC0, 0x00, 0x0B, 0x08, 0x00, 0xF0, 0x01, 0x40,
+            0x01, 0x01, 0x11, 0x00,
+        ];
+        assert_eq!(jpeg_dimensions(&jpeg), Some((320, 240)));
+        assert_eq!(jpeg_dimensions(&jpeg[..12]), None);
+        assert_eq!(jpeg_dimensions(&[0x00, 0x01, 0x02, 0x03]), None);
+        assert_eq!(thumbnail_dimensions(320, 240), (40, 30));
+        assert_eq!(thumbnail_dimensions(4, 4), (1, 1));
+
+        // ゲートウェイのサムネイル要求は引数なし
+        let mut frame = vec![0x0B, b'C', b'T', b'R', b'L', 0x03, 0x00];
+        let crc = crc32(&frame);
+        frame.extend_from_slice(&crc.to_le_bytes());
+        assert_eq!(parse_control_frame(&frame), Ok(ControlCommand::GetThumbs));
+    }
+
+    #[test]
+    fn retained_image_limits_resends_and_reports_state() {
+        let mut retained = RetainedImage::new(
+            0x0123_4567_89ab_cdef,
+            vec![0xFF; 2048],
+            "abcd".to_string(),
+            ",HASH_ALGO:XXH64".to_string(),
+            None,
+        );
+        assert_eq!(retained.bytes_held(), 2048);
+        assert_eq!(
+            retention_metadata_fields(retained.bytes_held(), retained.frame_id()),
+            ",RETAIN_BYTES:2048,RETAIN_FRAME:0123456789abcdef"
+        );
+        assert_eq!(retained.begin_resend().as_deref(), Some(",HASH_ALGO:XXH64,RESEND:1"));
+        assert_eq!(retained.begin_resend().as_deref(), Some(",HASH_ALGO:XXH64,RESEND:2"));
+        assert_eq!(MAX_RESENDS_PER_CYCLE, 2);
+        assert_eq!(retained.begin_resend(), None);
+    }
+
+    #[test]
+    fn legacy_sleep_command_parses_binary_and_text() {
+        assert_eq!(parse_legacy_sleep_seconds(&600u32.to_le_bytes()), Some(600));
+        assert_eq!(parse_legacy_sleep_seconds(b"3600"), Some(3600));
+        assert_eq!(parse_legacy_sleep_seconds(b" 60\n"), Some(60));
+        assert_eq!(parse_legacy_sleep_seconds(b"sleep"), None);
+    }
+
+    /// ゲートウェイと同じ形式の署名付きスリープコマンドを組み立てる
+    fn signed_sleep_command(key: &[u8; 32], mac: &[u8; 6], counter: u32, sleep_seconds: u32) -> Vec<u8> {
+        use hmac::{Hmac, Mac};
+        let mut data = vec![0x07];
+        data.extend_from_slice(&counter.to_le_bytes());
+        data.extend_from_slice(&sleep_seconds.to_le_bytes());
+        let mut hmac = <Hmac<sha2::Sha256> as Mac>::new_from_slice(key).unwrap();
+        hmac.update(mac);
+        hmac.update(&data);
+        let tag = hmac.finalize().into_bytes();
+        data.extend_from_slice(&tag[..8]);
+        data
+    }
+
+    #[test]
+    fn downlink_auth_rejects_replay_and_foreign_commands() {
+        let raw_key = [0xABu8; 32];
+        let key = DownlinkKey::from_hex(&"ab".repeat(32)).unwrap();
+        assert!(DownlinkKey::from_hex("abcd").is_none());
+        assert!(DownlinkKey::from_hex(&"zz".repeat(32)).is_none());
+
+        let mac = [0x24, 0x0A, 0xC4, 0x00, 0x00, 0x01];
+        let data = signed_sleep_command(&raw_key, &mac, 42, 600);
+        assert_eq!(data.len(), SIGNED_SLEEP_COMMAND_LEN);
+        assert_eq!(
+            verify_signed_sleep_command(&data, &key, &mac, 41),
+            Ok(SignedSleepCommand {
+                counter: 42,
+                sleep_seconds: 600
+            })
+        );
+        // 受理済みカウンタ以下はリプレイ
+        assert_eq!(
+            verify_signed_sleep_command(&data, &key, &mac, 42),
+            Err(DownlinkRejection::Replay {
+                counter: 42,
+                last_accepted: 42
+            })
+        );
+        // 他デバイス宛て・改ざん・署名なしの従来形式は拒否
+        let other_mac = [0x24, 0x0A, 0xC4, 0x00, 0x00, 0x02];
+        assert_eq!(
+            verify_signed_sleep_command(&data, &key, &other_mac, 0),
+            Err(DownlinkRejection::BadSignature)
+        );
+        let mut tampered = data.clone();
+        tampered[5] ^= 0xFF;
+        assert_eq!(
+            verify_signed_sleep_command(&tampered, &key, &mac, 0),
+            Err(DownlinkRejection::BadSignature)
+        );
+        assert_eq!(
+            verify_signed_sleep_command(&600u32.to_le_bytes(), &key, &mac, 0),
+            Err(DownlinkRejection::BadSignature)
+        );
+
+        assert_eq!(security_metadata_fields(0, 0), "");
+        assert_eq!(security_metadata_fields(2, 1), ",SEC_REPLAY:2,SEC_BAD_SIG:1");
+    }
+
+    /// ゲートウェイと同じ形式の鍵更新を組み立てる
+    fn key_rotation(key: &[u8; 32], mac: &[u8; 6], counter: u32, epoch: u32, new_key: &[u8; 32]) -> Vec<u8> {
+        use hmac::{Hmac, Mac};
+        let mut stream = <Hmac<sha2::Sha256> as Mac>::new_from_slice(key).unwrap();
+        stream.update(b"KEYWRAP");
+        stream.update(mac);
+        stream.update(&counter.to_le_bytes());
+        let stream = stream.finalize().into_bytes();
+        let mut data = vec![0x0C];
+        data.extend_from_slice(&counter.to_le_bytes());
+        data.extend_from_slice(&epoch.to_le_bytes());
+        data.extend_from_slice(&(counter + 1).to_le_bytes());
+        data.extend(new_key.iter().zip(stream.iter()).map(|(byte, mask)| byte ^ mask));
+        let mut hmac = <Hmac<sha2::Sha256> as Mac>::new_from_slice(key).unwrap();
+        hmac.update(mac);
+        hmac.update(&data);
+        let tag = hmac.finalize().into_bytes();
+        data.extend_from_slice(&tag[..8]);
+        data
+    }
+
+    #[test]
+    fn key_rotation_switches_keys_at_activate_counter() {
+        let raw_key = [0xABu8; 32];
+        let new_raw_key = [0xCDu8; 32];
+        let provisioned = DownlinkKey::from_hex(&"ab".repeat(32)).unwrap();
+        let new_key = DownlinkKey::from_hex(&"cd".repeat(32)).unwrap();
+        let mac = [0x24, 0x0A, 0xC4, 0x00, 0x00, 0x01];
+
+        let data = key_rotation(&raw_key, &mac, 10, 1, &new_raw_key);
+        assert_eq!(data.len(), KEY_ROTATION_LEN);
+        let rotation = verify_key_rotation(&data, &provisioned, &mac, 9).unwrap();
+        assert_eq!((rotation.counter, rotation.epoch, rotation.activate_counter), (10, 1, 11));
+        assert_eq!(rotation.new_key, new_key);
+        // 更新待ちの鍵・他デバイス宛て・リプレイは拒否
+        assert_eq!(verify_key_rotation(&data, &new_key, &mac, 9), Err(DownlinkRejection::BadSignature));
+        assert_eq!(
+            verify_key_rotation(&data, &provisioned, &[0; 6], 9),
+            Err(DownlinkRejection::BadSignature)
+        );
+        assert_eq!(
+            verify_key_rotation(&data, &provisioned, &mac, 10),
+            Err(DownlinkRejection::Replay { counter: 10, last_accepted: 10 })
+        );
+
+        let mut keys = DeviceKeys::new(provisioned.clone());
+        assert_eq!(keys.metadata_fields(), ",KEY_EPOCH:0");
+        assert!(keys.stage(rotation.clone()));
+        assert_eq!(keys.metadata_fields(), ",KEY_EPOCH:0,KEY_PENDING:1");
+        // 切り替えカウンタより前のコマンドは現在の鍵で検証する
+        assert_eq!(keys.key_for(10), &provisioned);
+        assert_eq!(keys.key_for(11), &new_key);
+        let command = signed_sleep_command(&new_raw_key, &mac, 11, 600);
+        assert!(verify_signed_sleep_command(&command, keys.key_for(11), &mac, 10).is_ok());
+
+        // 電源断後も記録から同じ状態に戻る
+        let restored = DeviceKeys::restore(provisioned.clone(), Some(&keys.to_record()));
+        assert_eq!(restored, keys);
+
+        assert!(!keys.accept(10));
+        assert!(keys.accept(11));
+        assert_eq!(keys.metadata_fields(), ",KEY_EPOCH:1");
+        assert_eq!(keys.key_for(12), &new_key);
+        // 適用済みの世代の送り直しは無視する
+        assert!(!keys.stage(rotation));
+
+        let restored = DeviceKeys::restore(provisioned.clone(), Some(&keys.to_record()));
+        assert_eq!(restored, keys);
+        // 壊れた記録はプロビジョニングした鍵に戻す
+        assert_eq!(DeviceKeys::restore(provisioned.clone(), Some(&[0u8; 3])), DeviceKeys::new(provisioned));
+    }
+
+    #[test]
+    fn trace_context_metadata_fields() {
+        let mut trace = TraceContext::from_entropy(0x0123_4567, 0x89AB_CDEF);
+        assert_eq!(trace.trace_id, 0x0123_4567_89AB_CDEF);
+        assert_eq!(trace.metadata_fields(), ",TRACE:0123456789abcdef");
+
+        trace.capture_ms = Some(180);
+        assert_eq!(trace.metadata_fields(), ",TRACE:0123456789abcdef,CAP_MS:180");
+
+        // 全ビット0は無効なトレースIDのため避ける
+        assert_eq!(TraceContext::from_entropy(0, 0).trace_id, 1);
+    }
+
+    #[test]
+    fn lifecycle_event_log_reports_boot_reason_and_previous_uptime() {
+        let mut log = EventLog::new();
+        let report = log.record_boot(BootReason::PowerOn, WakeCause::None);
+        assert_eq!(report.metadata_fields(), ",BOOT:POWERON,WAKE:NONE,PREV_UP_S:0,BOOT_LOG:POWERON");
+
+        // Deep sleep復帰はログに残さず、前回の稼働時間のみ報告する
+        log.record_uptime(42);
+        let report = log.record_boot(BootReason::DeepSleep, WakeCause::Timer);
+        assert_eq!(report.previous_uptime_s, Some(42));
+        assert_eq!(report.metadata_fields(), ",BOOT:DEEPSLEEP,WAKE:TIMER,PREV_UP_S:42,BOOT_LOG:POWERON");
+
+        log.record_uptime(7);
+        let report = log.record_boot(BootReason::Brownout, WakeCause::None);
+        assert!(report.reason.is_abnormal());
+        assert_eq!(report.previous_uptime_s, Some(7));
+        assert_eq!(report.recent_events, vec![BootReason::PowerOn, BootReason::Brownout]);
+    }
+
+    #[test]
+    fn lifecycle_event_log_keeps_latest_events_and_discards_garbage() {
+        let mut log = EventLog::new();
+        for _ in 0..EVENT_LOG_CAPACITY {
+            log.record_boot(BootReason::Panic, WakeCause::None);
+        }
+        log.record_boot(BootReason::TaskWatchdog, WakeCause::None);
+        let events = log.events();
+        assert_eq!(events.len(), EVENT_LOG_CAPACITY);
+        assert_eq!(events.last(), Some(&BootReason::TaskWatchdog));
+
+        // 電源投入直後の不定値は破棄し、前回の稼働時間は不明とする
+        let mut garbage: EventLog = unsafe { std::mem::transmute([0xA5u8; std::mem::size_of::<EventLog>()]) };
+        let report = garbage.record_boot(BootReason::PowerOn, WakeCause::None);
+        assert_eq!(report.previous_uptime_s, None);
+        assert_eq!(report.metadata_fields(), ",BOOT:POWERON,WAKE:NONE,BOOT_LOG:POWERON");
+    }
+
+    #[test]
+    fn led_pattern_parses_config_values() {
+        assert_eq!(LedPattern::parse("off"), Some(LedPattern::Off));
+        assert_eq!(LedPattern::parse(" ON "), Some(LedPattern::Solid));
+        assert_eq!(
+            LedPattern::parse("300/300x3"),
+            Some(LedPattern::Blink { on_ms: 300, off_ms: 300, count: 3 })
+        );
+        assert_eq!(
+            LedPattern::parse("50/950"),
+            Some(LedPattern::Blink { on_ms: 50, off_ms: 950, count: 0 })
+        );
+        for invalid in ["", "blink", "0/100", "100/0", "100/100x0", "100x2", "100/100x"] {
+            assert_eq!(LedPattern::parse(invalid), None, "{}", invalid);
+        }
+        assert_eq!(
+            LedPatterns::default().for_event(LedEvent::Error),
+            LedPattern::Blink { on_ms: 300, off_ms: 300, count: 3 }
+        );
+    }
+
+    #[test]
+    fn led_pattern_state_follows_elapsed_time() {
+        let blink = LedPattern::Blink { on_ms: 100, off_ms: 200, count: 2 };
+        assert_eq!(blink.state_at(0), LedState { on: true, hold_ms: Some(100) });
+        assert_eq!(blink.state_at(130), LedState { on: false, hold_ms: Some(170) });
+        assert_eq!(blink.state_at(300), LedState { on: true, hold_ms: Some(100) });
+        assert!(!blink.is_finished(599));
+        assert_eq!(blink.state_at(600), LedState { on: false, hold_ms: None });
+        assert!(blink.is_finished(600));
+
+        let forever = LedPattern::Blink { on_ms: 50, off_ms: 50, count: 0 };
+        assert_eq!(forever.state_at(10_020), LedState { on: true, hold_ms: Some(30) });
+        assert!(!forever.is_finished(u64::MAX / 2));
+        assert_eq!(LedPattern::Solid.state_at(5), LedState { on: true, hold_ms: None });
+    }
+
+    #[test]
+    fn build_info_metadata_includes_config_hash() {
+        let info = BuildInfo {
+            git_hash: "1a2b3c4d-dirty",
+            build_timestamp: "1760000000",
+            features: "esp+qemu-smoke",
+        };
+        assert_eq!(
+            info.metadata_fields(0xdeadbeef),
+            ",FW:1a2b3c4d-dirty,BUILT:1760000000,FEAT:esp+qemu-smoke,CFG:deadbeef"
+        );
+        let no_features = BuildInfo { features: "", ..info };
+        assert_eq!(no_features.metadata_fields(1), ",FW:1a2b3c4d-dirty,BUILT:1760000000,CFG:00000001");
+
+        // FNV-1a の既知の値（設定が同じなら常に同じハッシュ）
+        assert_eq!(config_hash(""), 0x811c_9dc5);
+        assert_eq!(config_hash("a"), 0xe40c_292c);
+        assert_ne!(config_hash("sleep=60"), config_hash("sleep=61"));
+    }
+
+    #[test]
+    fn timelapse_settings_validate_sequence_name() {
+        assert_eq!(TimelapseSettings::from_config("  ", 600), Ok(None));
+        assert_eq!(
+            TimelapseSettings::from_config(" tomato-bed_1 ", 600),
+            Ok(Some(TimelapseSettings { name: "tomato-bed_1".to_string(), interval_seconds: 600 }))
+        );
+        // ディレクトリ名やメタデータの区切りに使う文字は受け付けない
+        for invalid in ["bed/1", "bed,1", "bed:1", "../x", &"a".repeat(33)] {
+            assert_eq!(TimelapseSettings::from_config(invalid, 600), Err(invalid.to_string()));
+        }
+    }
+
+    #[test]
+    fn timelapse_sequence_counts_frames_and_restarts_on_rename() {
+        let bed = TimelapseSettings { name: "bed".to_string(), interval_seconds: 600 };
+        let mut state = SequenceState::new();
+
+        let first = state.next_frame(&bed, 0xabcd);
+        assert_eq!(first.metadata_fields(), ",SEQ:bed,SEQ_RUN:0000abcd,SEQ_IDX:0,SEQ_INT:600");
+        // 実行中は新しい実行IDを使わない
+        let second = state.next_frame(&bed, 0x1111);
+        assert_eq!((second.run_id, second.index), (0xabcd, 1));
+
+        let renamed = TimelapseSettings { name: "bed-2".to_string(), ..bed };
+        let restarted = state.next_frame(&renamed, 0x2222);
+        assert_eq!((restarted.run_id, restarted.index), (0x2222, 0));
+    }
+
+    #[test]
+    fn panic_record_is_reported_once_in_lifecycle_metadata() {
+        let mut record = PanicRecord::new();
+        record.store("index out of bounds: len is 3, idx\n7", "src/core/data_service.rs:120");
+
+        let mut log = EventLog::new();
+        let mut report = log.record_boot(BootReason::Panic, WakeCause::None);
+        report.panic = record.take();
+        // メタデータの区切り文字と改行は置き換える
+        assert_eq!(
+            report.metadata_fields(),
+            ",BOOT:PANIC,WAKE:NONE,PREV_UP_S:0,BOOT_LOG:PANIC\
+             ,PANIC_MSG:index out of bounds: len is 3; idx 7,PANIC_AT:src/core/data_service.rs:120"
+        );
+        assert_eq!(record.take(), None);
+    }
+
+    #[test]
+    fn panic_record_truncates_and_discards_garbage() {
+        let mut record = PanicRecord::new();
+        let long_path = format!("{}/src/main.rs:42", "/home/build/.cargo/registry".repeat(3));
+        record.store(&"é".repeat(PANIC_MESSAGE_CAPACITY), &long_path);
+        let report = record.take().unwrap();
+        assert_eq!(report.message, "é".repeat(PANIC_MESSAGE_CAPACITY / 2));
+        assert!(report.location.len() <= PANIC_LOCATION_CAPACITY);
+        assert!(report.location.ends_with("/src/main.rs:42"));
+
+        // 電源投入直後の不定値は記録として扱わない
+        let mut garbage: PanicRecord = unsafe { std::mem::transmute([0xA5u8; std::mem::size_of::<PanicRecord>()]) };
+        assert_eq!(garbage.take(), None);
+    }
+
+    #[test]
+    fn privacy_mode_is_negotiated_through_ping_pong() {
+        let ping = encode_ping(1, None, true, false, false, false, false, false);
+        assert_eq!(ping.len(), 10);
+        assert_eq!(ping.last(), Some(&CAPABILITY_PRIVACY));
+
+        // 受け入れたゲートウェイは無線設定の後ろに機能フラグを付けて返す
+        let pong = parse_pong(&[0x06, b'P', b'O', b'N', b'G', 1, 0, 0, 0, 50, 0x09, 20, 0x01, 0x01]).unwrap();
+        assert!(pong.privacy);
+        assert!(pong.radio.is_some());
+        // 旧ゲートウェイのPongでは無効のまま
+        assert!(!parse_pong(&[0x06, b'P', b'O', b'N', b'G', 1, 0, 0, 0, 50]).unwrap().privacy);
+        assert_eq!(parse_pong(&[0x06, b'P', b'O', b'N', b'G', 1, 0, 0, 0, 50, 0, 0]), None);
+    }
+
+    #[test]
+    fn advertised_chunk_size_replaces_fallback_sizes() {
+        let ping = encode_ping(1, None, false, true, false, true, false, false);
+        assert_eq!(ping.last(), Some(&(CAPABILITY_CHANNEL_HOP | CAPABILITY_CHUNK_SIZE)));
+
+        // 無線設定・機能フラグの後ろに大きさの通知（推奨150、上限223）、続けて予定
+        let pong = parse_pong(&[
+            0x06, b'P', b'O', b'N', b'G', 1, 0, 0, 0, 50, 0x09, 20, 0x01, 0x0A, 150, 0, 223, 0, 1, 60, 0, 15, 0, 1, 6,
+        ])
+        .unwrap();
+        assert_eq!(pong.chunk_size, Some(ChunkSizeAdvert { preferred: 150, max: 223 }));
+        assert_eq!(pong.hop.unwrap().channels(), &[6]);
+        let without_hop =
+            parse_pong(&[0x06, b'P', b'O', b'N', b'G', 1, 0, 0, 0, 50, 0x09, 20, 0x01, 0x08, 200, 0, 223, 0]).unwrap();
+        assert_eq!(without_hop.chunk_size.map(|advert| advert.preferred), Some(200));
+        assert_eq!(without_hop.hop, None);
+        // 通知が欠けている・推奨が上限を超えるPongは受け付けない
+        assert_eq!(parse_pong(&[0x06, b'P', b'O', b'N', b'G', 1, 0, 0, 0, 50, 0x09, 20, 0x01, 0x08, 200, 0]), None);
+        assert_eq!(
+            parse_pong(&[0x06, b'P', b'O', b'N', b'G', 1, 0, 0, 0, 50, 0x09, 20, 0x01, 0x08, 240, 0, 223, 0]),
+            None
+        );
+
+        // 通知があればその大きさだけで送り、設定の初期チャンクサイズより大きくはしない
+        assert_eq!(payload_size_plan(999, Some(150)), [150]);
+        assert_eq!(payload_size_plan(120, Some(223)), [120]);
+        assert_eq!(payload_size_plan(999, None), [223, 150, 100, 50, 30]);
+    }
+
+    #[test]
+    fn hash_ack_lets_device_skip_command_window() {
+        let ping = encode_ping(1, None, false, false, true, false, false, false);
+        assert_eq!(ping.last(), Some(&CAPABILITY_COMMAND_WINDOW));
+
+        let ack = parse_hash_ack(&[0x0D, b'H', b'A', b'C', b'K', 0, 0]).unwrap();
+        assert_eq!(ack, HashAck { commands_pending: false, pending_count: 0, loss_percent: None, gateway_us: None });
+        assert!(ack.skips_command_window());
+        let ack = parse_hash_ack(&[0x0D, b'H', b'A', b'C', b'K', 1, 2]).unwrap();
+        assert!(!ack.skips_command_window());
+        assert_eq!(ack.pending_count, 2);
+        // スリープコマンドや長さの違うデータは応答ではない
+        assert_eq!(parse_hash_ack(&[0x0D, b'H', b'A', b'C', b'K', 0]), None);
+        assert_eq!(parse_hash_ack(&[0x0D, b'S', b'L', b'P', b'!', 0, 0]), None);
+
+        // 損失率の受け取りを示すと、前回の転送の損失率が末尾に付く
+        let ping = encode_ping(1, None, false, false, true, false, true, false);
+        assert_eq!(ping.last(), Some(&(CAPABILITY_COMMAND_WINDOW | CAPABILITY_LOSS_REPORT)));
+        let ack = parse_hash_ack(&[0x0D, b'H', b'A', b'C', b'K', 0, 0, 12]).unwrap();
+        assert_eq!(ack.loss_percent, Some(12));
+        assert_eq!(parse_hash_ack(&[0x0D, b'H', b'A', b'C', b'K', 0, 0, 12, 0]), None);
+
+        // ゲートウェイの時計は損失率の後ろに付き、未観測の損失率は0xFF
+        let ping = encode_ping(1, None, false, false, true, false, true, true);
+        assert_eq!(
+            ping.last(),
+            Some(&(CAPABILITY_COMMAND_WINDOW | CAPABILITY_LOSS_REPORT | CAPABILITY_GATEWAY_CLOCK))
+        );
+        let mut data = vec![0x0D, b'H', b'A', b'C', b'K', 0, 0, 0xFF];
+        data.extend_from_slice(&5_000_000u64.to_le_bytes());
+        let ack = parse_hash_ack(&data).unwrap();
+        assert_eq!((ack.loss_percent, ack.gateway_us), (None, Some(5_000_000)));
+        assert_eq!(parse_hash_ack(&data[..data.len() - 1]), None);
+
+        assert_eq!(window_saved_metadata_field(0), "");
+        assert_eq!(window_saved_metadata_field(4000), ",WINDOW_SAVED_MS:4000");
+    }
+
+    #[test]
+    fn channel_hop_schedule_plans_wake_channel_and_falls_back_to_home() {
+        let ping = encode_ping(1, None, false, true, false, false, false, false);
+        assert_eq!(ping.last(), Some(&CAPABILITY_CHANNEL_HOP));
+
+        // 無線設定・機能フラグの後ろに予定（ホーム1、60秒スロット、現在のスロットは15秒経過、6→11→1）
+        let pong = parse_pong(&[
+            0x06, b'P', b'O', b'N', b'G', 1, 0, 0, 0, 50, 0x09, 20, 0x01, 0x02, 1, 60, 0, 15, 0, 3, 6, 11, 1,
+        ])
+        .unwrap();
+        let schedule = pong.hop.unwrap();
+        assert_eq!(schedule.channels(), &[6, 11, 1]);
+        assert_eq!(schedule.channel_after(0), 6);
+        assert_eq!(schedule.channel_after(45_000), 11);
+        assert_eq!(schedule.channel_after(45_000 + 120_000), 6);
+        // 機能フラグなしの予定や長さの合わない予定は受け付けない
+        let unflagged = [0x06, b'P', b'O', b'N', b'G', 1, 0, 0, 0, 50, 0x09, 20, 0x01, 0x00, 1, 60, 0, 15, 0, 1, 6];
+        assert_eq!(parse_pong(&unflagged), None);
+        assert_eq!(HopSchedule::from_wire(&[1, 60, 0, 15, 0, 3, 6, 11]), None);
+        assert_eq!(HopSchedule::from_wire(&[1, 60, 0, 15, 0, 1, 14]), None);
+
+        let mut plan = HopPlan::new();
+        assert!(plan.probe_channels().is_empty());
+        plan.plan(&schedule, 50_000);
+        assert_eq!(plan.probe_channels(), vec![11, 1, 6]);
+        plan.record_probe(Some(11));
+        assert_eq!(plan.metadata_fields(), ",HOP_CH:11");
+
+        // 予定のチャンネルで続けて届かなければ、しばらくホームチャンネルから試す
+        for _ in 0..MAX_HOP_MISSES {
+            assert_eq!(plan.probe_channels()[0], 11);
+            plan.record_probe(Some(1));
+            assert_eq!(plan.metadata_fields(), ",HOP_CH:1,HOP_MISS:11");
+        }
+        for _ in 0..HOP_BACKOFF_CYCLES {
+            assert_eq!(plan.probe_channels(), vec![1, 6, 11]);
+            plan.record_probe(Some(1));
+            assert_eq!(plan.metadata_fields(), ",HOP_CH:1");
+        }
+        assert_eq!(plan.probe_channels()[0], 11);
+    }
+
+    #[test]
+    fn privacy_frames_are_full_size() {
+        let mac = [0x24, 0x6F, 0x28, 0x12, 0x34, 0x56];
+        let mut frame = build_sensor_data_frame(FrameType::Eof, mac, 7, b"EOF");
+        let original_len = frame.len();
+        pad_frame(&mut frame);
+        assert_eq!(frame.len(), ESP_NOW_MAX_SIZE);
+        assert_eq!(&frame[original_len - 4..original_len], &[0xCD, 0xEF, 0x56, 0x78]);
+
+        let dummy = build_dummy_frame(mac, 0xDEAD_BEEF, 42);
+        assert_eq!(dummy.len(), ESP_NOW_MAX_SIZE);
+        assert_eq!(dummy[10], FrameType::Dummy.to_byte());
+        assert_ne!(build_dummy_frame(mac, 0xDEAD_BEEF, 43), dummy);
+
+        let params = PrivacyParams { max_dummy_frames: 2, max_jitter_ms: 20 };
+        assert!((0..100).all(|r| params.dummy_count(r) <= 2 && params.jitter_ms(r) <= 20));
+        let disabled = PrivacyParams { max_dummy_frames: 0, max_jitter_ms: 0 };
+        assert_eq!((disabled.dummy_count(7), disabled.jitter_ms(7)), (0, 0));
+    }
+
+    fn broadcast_config_message(key: &[u8; 32], config_id: u32, config: &str) -> Vec<u8> {
+        use hmac::{Hmac, Mac};
+        let mut data = vec![0x08];
+        data.extend_from_slice(&config_id.to_le_bytes());
+        data.push(config.len() as u8);
+        data.extend_from_slice(config.as_bytes());
+        let mut hmac = <Hmac<sha2::Sha256> as Mac>::new_from_slice(key).unwrap();
+        hmac.update(&[0xFF; 6]);
+        hmac.update(&data);
+        let tag = hmac.finalize().into_bytes();
+        data.extend_from_slice(&tag[..8]);
+        data
+    }
+
+    #[test]
+    fn broadcast_config_is_verified_against_broadcast_address_and_applied_id() {
+        let key = DownlinkKey::from_hex(&"ab".repeat(32)).unwrap();
+        let data = broadcast_config_message(&[0xAB; 32], 40, "SLEEP=900");
+        assert_eq!(
+            verify_broadcast_config(&data, &key, 39),
+            Ok(BroadcastConfig { config_id: 40, config: "SLEEP=900".to_string() })
+        );
+        assert_eq!(
+            verify_broadcast_config(&data, &key, 40),
+            Err(DownlinkRejection::Replay { counter: 40, last_accepted: 40 })
+        );
+        let mut tampered = data.clone();
+        tampered[7] ^= 0x01;
+        assert_eq!(verify_broadcast_config(&tampered, &key, 0), Err(DownlinkRejection::BadSignature));
+        let other_key = DownlinkKey::from_hex(&"cd".repeat(32)).unwrap();
+        assert_eq!(verify_broadcast_config(&data, &other_key, 0), Err(DownlinkRejection::BadSignature));
+        // 宛先付きのスリープコマンドは一斉配信として扱わない
+        let sleep = signed_sleep_command(&[0xAB; 32], &[0xFF; 6], 41, 900);
+        assert_eq!(verify_broadcast_config(&sleep, &key, 0), Err(DownlinkRejection::BadSignature));
+    }
+
+    #[test]
+    fn broadcast_config_overlays_current_remote_config() {
+        let current = RemoteConfig::decode("RECEIVER_MAC=24:0a:c4:01:02:03;SLEEP=600").unwrap();
+        let updated = current.with_broadcast(40, "SLEEP=900;BCAST=1").unwrap();
+        assert_eq!(updated.encode(), "RECEIVER_MAC=24:0a:c4:01:02:03;SLEEP=900;BCAST=40");
+        assert_eq!(RemoteConfig::decode(&updated.encode()), Ok(updated.clone()));
+        assert_eq!(updated.diff(&current).len(), 2);
+        assert!(current.with_broadcast(41, "CHANNEL=6").is_err());
+    }
+
+    fn config_update_message(key: Option<&[u8; 32]>, target_mac: &[u8; 6], counter: u32, config: &str) -> Vec<u8> {
+        use hmac::{Hmac, Mac};
+        let mut data = vec![0x09];
+        data.extend_from_slice(&counter.to_le_bytes());
+        data.push(config.len() as u8);
+        data.extend_from_slice(config.as_bytes());
+        if let Some(key) = key {
+            let mut hmac = <Hmac<sha2::Sha256> as Mac>::new_from_slice(key).unwrap();
+            hmac.update(target_mac);
+            hmac.update(&data);
+            let tag = hmac.finalize().into_bytes();
+            data.extend_from_slice(&tag[..8]);
+        }
+        data
+    }
+
+    #[test]
+    fn config_update_requires_signature_only_when_key_is_configured() {
+        let key = DownlinkKey::from_hex(&"ab".repeat(32)).unwrap();
+        let own_mac = [0x24, 0x0a, 0xc4, 0x01, 0x02, 0x03];
+        let signed = config_update_message(Some(&[0xAB; 32]), &own_mac, 12, "JPEG_Q=20;FRAME_SIZE=VGA");
+        let expected = ConfigUpdate { counter: 12, config: "JPEG_Q=20;FRAME_SIZE=VGA".to_string() };
+        assert_eq!(verify_config_update(&signed, Some(&key), &own_mac, 11), Ok(expected));
+        assert_eq!(
+            verify_config_update(&signed, Some(&key), &own_mac, 12),
+            Err(DownlinkRejection::Replay { counter: 12, last_accepted: 12 })
+        );
+        // 他デバイス宛ての流用・署名なしは拒否
+        assert_eq!(
+            verify_config_update(&signed, Some(&key), &[0x24, 0x0a, 0xc4, 0x01, 0x02, 0x04], 11),
+            Err(DownlinkRejection::BadSignature)
+        );
+        let unsigned = config_update_message(None, &own_mac, 0, "JPEG_Q=20");
+        assert_eq!(
+            verify_config_update(&unsigned, Some(&key), &own_mac, 0),
+            Err(DownlinkRejection::BadSignature)
+        );
+
+        // 鍵が未設定なら署名なしの形式のみ受理
+        assert_eq!(
+            verify_config_update(&unsigned, None, &own_mac, 0),
+            Ok(ConfigUpdate { counter: 0, config: "JPEG_Q=20".to_string() })
+        );
+        assert_eq!(verify_config_update(&signed, None, &own_mac, 0), Err(DownlinkRejection::BadSignature));
+    }
+
+    fn file_chunk_message(key: Option<&[u8; 32]>, target_mac: &[u8; 6], counter: u32, chunk: &FileChunk) -> Vec<u8> {
+        use hmac::{Hmac, Mac};
+        let mut data = vec![0x0E];
+        data.extend_from_slice(&counter.to_le_bytes());
+        data.extend_from_slice(&chunk.file_crc.to_le_bytes());
+        data.extend_from_slice(&chunk.total_len.to_le_bytes());
+        data.extend_from_slice(&chunk.offset.to_le_bytes());
+        data.push(chunk.slot.len() as u8);
+        data.push(chunk.data.len() as u8);
+        data.extend_from_slice(chunk.slot.as_bytes());
+        data.extend_from_slice(&chunk.data);
+        if let Some(key) = key {
+            let mut hmac = <Hmac<sha2::Sha256> as Mac>::new_from_slice(key).unwrap();
+            hmac.update(target_mac);
+            hmac.update(&data);
+            let tag = hmac.finalize().into_bytes();
+            data.extend_from_slice(&tag[..8]);
+        }
+        data
+    }
+
+    fn file_chunks(slot: &str, contents: &[u8], chunk_len: usize) -> Vec<FileChunk> {
+        contents
+            .chunks(chunk_len)
+            .enumerate()
+            .map(|(index, data)| FileChunk {
+                counter: 0,
+                slot: slot.to_string(),
+                file_crc: crc32(contents),
+                total_len: contents.len() as u16,
+                offset: (index * chunk_len) as u16,
+                data: data.to_vec(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn file_chunk_is_verified_like_config_update() {
+        let key = DownlinkKey::from_hex(&"ab".repeat(32)).unwrap();
+        let own_mac = [0x24, 0x0a, 0xc4, 0x01, 0x02, 0x03];
+        let chunk = file_chunks("calib", b"0123456789", 4).remove(1);
+        let signed = file_chunk_message(Some(&[0xAB; 32]), &own_mac, 12, &chunk);
+        assert_eq!(
+            verify_file_chunk(&signed, Some(&key), &own_mac, 11),
+            Ok(FileChunk { counter: 12, ..chunk.clone() })
+        );
+        assert_eq!(
+            verify_file_chunk(&signed, Some(&key), &own_mac, 12),
+            Err(DownlinkRejection::Replay { counter: 12, last_accepted: 12 })
+        );
+        assert_eq!(
+            verify_file_chunk(&signed, Some(&key), &[0x24, 0x0a, 0xc4, 0x01, 0x02, 0x04], 11),
+            Err(DownlinkRejection::BadSignature)
+        );
+
+        let unsigned = file_chunk_message(None, &own_mac, 0, &chunk);
+        assert_eq!(verify_file_chunk(&unsigned, None, &own_mac, 0), Ok(chunk));
+        assert_eq!(verify_file_chunk(&unsigned, Some(&key), &own_mac, 0), Err(DownlinkRejection::BadSignature));
+        assert_eq!(verify_file_chunk(&unsigned[..20], None, &own_mac, 0), Err(DownlinkRejection::BadSignature));
+    }
+
+    #[test]
+    fn file_assembly_accepts_chunks_in_order_and_verifies_crc() {
+        let contents = b"calibration table v1";
+        let chunks = file_chunks("calib", contents, 8);
+        let mut assembly = FileAssembly::default();
+        assert_eq!(assembly.metadata_fields(), "");
+
+        // 先頭以外から始まる別のファイルの断片は受け取らない
+        assert_eq!(assembly.accept(&chunks[1]), ChunkOutcome::OutOfOrder { expected: 0 });
+        assert_eq!(assembly.accept(&chunks[0]), ChunkOutcome::Appended { received: 8 });
+        assert_eq!(assembly.accept(&chunks[0]), ChunkOutcome::Duplicate);
+        assert_eq!(assembly.accept(&chunks[2]), ChunkOutcome::OutOfOrder { expected: 8 });
+        assert_eq!(
+            assembly.metadata_fields(),
+            format!(",FILE_SLOT:calib,FILE_OFF:8,FILE_CRC:{:08x}", crc32(contents))
+        );
+        assert_eq!(assembly.accept(&chunks[1]), ChunkOutcome::Appended { received: 16 });
+        assert_eq!(assembly.accept(&chunks[2]), ChunkOutcome::Complete);
+        assert!(assembly.is_complete());
+        assert_eq!(assembly.take_completed().as_deref(), Some(&contents[..]));
+        assert_eq!(assembly.take_completed(), None);
+        // 報告用の受信済みの位置は残す
+        assert!(assembly.metadata_fields().contains(",FILE_OFF:20,"));
+        assert_eq!(assembly.accept(&chunks[2]), ChunkOutcome::Duplicate);
+
+        // CRCが一致しなければ内容を破棄して先頭から受信し直す
+        let mut corrupt = file_chunks("tds_curve", b"abcdef", 3);
+        corrupt[1].data = b"xyz".to_vec();
+        let mut assembly = FileAssembly::default();
+        assert_eq!(assembly.accept(&corrupt[0]), ChunkOutcome::Appended { received: 3 });
+        assert_eq!(assembly.accept(&corrupt[1]), ChunkOutcome::CrcMismatch);
+        assert!(assembly.metadata_fields().contains(",FILE_OFF:0,"));
+
+        let invalid = FileChunk { slot: "calib/tds".to_string(), ..chunks[0].clone() };
+        assert_eq!(FileAssembly::default().accept(&invalid), ChunkOutcome::Invalid);
+    }
+
+    #[test]
+    fn file_assembly_record_roundtrip_resumes_transfer() {
+        let contents = b"calibration table v1";
+        let chunks = file_chunks("calib", contents, 8);
+        let mut assembly = FileAssembly::default();
+        assembly.accept(&chunks[0]);
+
+        let restored = FileAssembly::from_record(&assembly.to_record()).unwrap();
+        assert_eq!(restored, assembly);
+        let mut restored = restored;
+        assert_eq!(restored.accept(&chunks[1]), ChunkOutcome::Appended { received: 16 });
+        assert_eq!(restored.accept(&chunks[2]), ChunkOutcome::Complete);
+
+        // 受信を終えたファイルの記録は内容を含めない
+        let record = restored.to_record();
+        assert_eq!(record.len(), 9 + "calib".len());
+        let completed = FileAssembly::from_record(&record).unwrap();
+        assert!(completed.is_complete());
+        assert_eq!(completed.metadata_fields(), restored.metadata_fields());
+
+        assert_eq!(FileAssembly::from_record(&record[..5]), None);
+        let mut truncated = assembly.to_record();
+        truncated.pop();
+        assert_eq!(FileAssembly::from_record(&truncated), None);
+    }
+
+    #[test]
+    fn camera_settings_update_overlays_remote_config() {
+        let current = RemoteConfig::decode("SLEEP=600;BCAST=40").unwrap();
+        let updated = current.with_update("JPEG_Q=20;FRAME_SIZE=vga;BCAST=1").unwrap();
+        assert_eq!(updated.encode(), "SLEEP=600;BCAST=40;JPEG_Q=20;FRAME_SIZE=VGA");
+        assert_eq!(RemoteConfig::decode(&updated.encode()), Ok(updated.clone()));
+        assert_eq!(updated.diff(&current).len(), 2);
+
+        assert!(RemoteConfig::decode("JPEG_Q=0").is_err());
+        assert!(RemoteConfig::decode("JPEG_Q=64").is_err());
+        assert!(RemoteConfig::decode("FRAME_SIZE=TOO_LONG_NAME").is_err());
+        assert!(RemoteConfig::decode("FRAME_SIZE=V-GA").is_err());
+    }
+
+    #[test]
+    fn gateway_defer_backs_off_within_budget() {
+        let defer = parse_defer(&[0x0A, b'B', b'U', b'S', b'Y', 7, 0, 0, 0, 0xb8, 0x0b, 0, 0]).unwrap();
+        assert_eq!(defer, Defer { nonce: 7, retry_after_ms: 3000 });
+        assert!(parse_pong(&[0x0A, b'B', b'U', b'S', b'Y', 7, 0, 0, 0, 0xb8, 0x0b, 0, 0]).is_none());
+        assert!(parse_defer(&[0x06, b'P', b'O', b'N', b'G', 7, 0, 0, 0, 50]).is_none());
+
+        // 待機時間の合計が上限を超える延期要求には従わない
+        assert_eq!(defer_wait_ms(0, 3000, 20000), Some(3000));
+        assert_eq!(defer_wait_ms(17000, 3000, 20000), Some(3000));
+        assert_eq!(defer_wait_ms(18000, 3000, 20000), None);
+        assert_eq!(defer_wait_ms(0, 3000, 0), None);
+
+        let reachable = ProbeOutcome::Reachable {
+            rtt_ms: 12,
+            attempts: 1,
+            queue_free_percent: 90,
+            radio_mismatch: false,
+            deferred_ms: 6000,
+        };
+        assert_eq!(
+            reachable.metadata_fields(0),
+            ",PROBE_RTT_MS:12,PROBE_TRIES:1,GW_QUEUE_FREE:90,PROBE_DEFER_MS:6000"
+        );
+        let deferred = ProbeOutcome::Deferred { deferred_ms: 18000 };
+        assert!(!deferred.is_reachable());
+        assert_eq!(deferred.metadata_fields(0), ",PROBE_DEFER_MS:18000");
+    }
+
+    #[test]
+    fn gateway_reject_skips_transfer_until_retry_after() {
+        // ゲートウェイの `RejectMessage { reason: UnknownDevice, nonce: 7, retry_after_ms: 600_000 }.serialize()`
+        let data = [0x0F, b'R', b'J', b'C', b'T', 0x02, 7, 0, 0, 0, 0xC0, 0x27, 0x09, 0];
+        let reject = parse_reject(&data).unwrap();
+        assert_eq!(
+            reject,
+            Reject { reason: RejectReason::UnknownDevice, nonce: 7, retry_after_ms: 600_000 }
+        );
+        assert!(parse_reject(&data[..13]).is_none());
+        let mut unknown_reason = data;
+        unknown_reason[5] = 0x7F;
+        assert!(parse_reject(&unknown_reason).is_none());
+        assert!(parse_defer(&data).is_none());
+        assert!(parse_pong(&data).is_none());
+        for reason in [RejectReason::Quota, RejectReason::UnknownDevice, RejectReason::ChecksumStorm] {
+            assert_eq!(RejectReason::from_u8(reason.as_u8()), Some(reason));
+        }
+
+        // 再試行までの時間が通常のスリープより長ければそちらに合わせる（秒未満は切り上げ）
+        assert_eq!(reject_backoff_seconds(300, 600_000), 600);
+        assert_eq!(reject_backoff_seconds(300, 30_000), 300);
+        assert_eq!(reject_backoff_seconds(10, 30_001), 31);
+
+        let rejected = ProbeOutcome::Rejected { reason: RejectReason::Quota, retry_after_ms: 5000 };
+        assert!(!rejected.is_reachable());
+        assert_eq!(rejected.metadata_fields(2), ",PROBE_REJECT:QUOTA,PROBE_SKIPPED:2");
+        assert_eq!(
+            reject_metadata_fields(3, Some(RejectReason::ChecksumStorm)),
+            ",GW_REJECTS:3,GW_REJECT_LAST:CHECKSUM_STORM"
+        );
+        assert_eq!(reject_metadata_fields(0, Some(RejectReason::Quota)), "");
+        assert_eq!(reject_metadata_fields(1, None), "");
+    }
+
+    #[test]
+    fn runtime_debug_flags_count_down_and_expire() {
+        let runtime = RuntimeDebug::parse("force_camera+DEBUG/2").unwrap();
+        assert!(runtime.flags.debug_mode && runtime.flags.force_camera_test);
+        assert!(!runtime.flags.bypass_voltage_threshold);
+        assert_eq!(runtime.flags.names(), "DEBUG+FORCE_CAMERA");
+        assert_eq!(RuntimeDebug::from_nvs(runtime.to_nvs()), runtime);
+
+        // 起動ごとに1サイクル消費し、使い切ると解除する
+        let (active, next) = runtime.begin_cycle();
+        assert_eq!(active, runtime.flags);
+        assert_eq!(metadata_field(active, next.remaining_cycles), ",DEBUG:DEBUG+FORCE_CAMERA/1");
+        let (active, next) = next.begin_cycle();
+        assert_eq!(active, runtime.flags);
+        assert_eq!(next, RuntimeDebug::default());
+        let (active, _) = next.begin_cycle();
+        assert!(!active.any());
+        assert_eq!(metadata_field(active, 0), "");
+
+        assert_eq!(RuntimeDebug::parse("OFF").unwrap(), RuntimeDebug::default());
+        assert!(matches!(RuntimeDebug::parse("DEBUG"), Err(DebugFlagsError::InvalidCycles(_))));
+        assert!(matches!(RuntimeDebug::parse("DEBUG/0"), Err(DebugFlagsError::InvalidCycles(_))));
+        assert!(matches!(RuntimeDebug::parse("DEBUG/101"), Err(DebugFlagsError::InvalidCycles(_))));
+        assert!(matches!(RuntimeDebug::parse("VERBOSE/1"), Err(DebugFlagsError::UnknownFlag(_))));
+        // 壊れたNVS値でも上限を超えて有効にしない
+        assert_eq!(RuntimeDebug::from_nvs(0xffff_0007).remaining_cycles, 100);
+        assert_eq!(DebugFlags::from_bits(0x07).names(), "DEBUG+FORCE_CAMERA+BYPASS_VOLTAGE");
+    }
+
+    #[test]
+    fn nvs_recoveries_are_reported_in_hash_metadata() {
+        // ESP_ERR_NVS_NO_FREE_PAGES / ESP_ERR_NVS_NEW_VERSION_FOUND はNVSのエラー、ESP_ERR_NO_MEM は対象外
+        assert!(is_nvs_error(0x110d));
+        assert!(is_nvs_error(0x1110));
+        assert!(!is_nvs_error(0x101));
+
+        let usage = NvsUsage { used_entries: 620, free_entries: 10, total_entries: 630 };
+        assert!(usage.is_full());
+        assert!(!NvsUsage { free_entries: 400, ..usage }.is_full());
+
+        assert_eq!(nvs_metadata_field(&[]), "");
+        assert_eq!(
+            nvs_metadata_field(&[NvsRecovery::PartitionErased(0x110d), NvsRecovery::ConfigFull]),
+            ",NVS_RECOVERED:PARTITION+CONFIG_FULL"
+        );
+        assert_eq!(nvs_metadata_field(&[NvsRecovery::ConfigCorrupted]), ",NVS_RECOVERED:CONFIG_CORRUPT");
+    }
+
+    #[test]
+    fn debug_field_is_split_from_config_update() {
+        assert_eq!(
+            split_debug_field("JPEG_Q=20;DEBUG=BYPASS_VOLTAGE/3;FRAME_SIZE=VGA"),
+            (Some("BYPASS_VOLTAGE/3"), "JPEG_Q=20;FRAME_SIZE=VGA".to_string())
+        );
+        assert_eq!(split_debug_field("DEBUG=OFF"), (Some("OFF"), String::new()));
+        assert_eq!(split_debug_field("SLEEP=600"), (None, "SLEEP=600".to_string()));
+    }
+
+    #[test]
+    fn relay_buffers_child_frames_until_complete_and_records_hop() {
+        let child = [0x24, 0x6f, 0x28, 0x00, 0x00, 0x01];
+        let relay_mac = [0x24, 0x6f, 0x28, 0x00, 0x00, 0x02];
+        let mut buffer = RelayBuffer::new(child);
+
+        // 他のデバイスのフレームと壊れたフレームは扱わない
+        assert_eq!(buffer.accept(&build_sensor_data_frame(FrameType::Data, relay_mac, 1, b"xx")), RelayEvent::Ignored);
+        let mut corrupted = build_sensor_data_frame(FrameType::Data, child, 1, b"abc");
+        corrupted[19] ^= 0xff;
+        assert_eq!(buffer.accept(&corrupted), RelayEvent::Ignored);
+
+        // 中断された送信は破棄し、送り直された分だけ溜める
+        assert_eq!(buffer.accept(&build_sensor_data_frame(FrameType::Data, child, 1, b"stale")), RelayEvent::Accepted);
+        assert_eq!(buffer.accept(&build_sensor_data_frame(FrameType::Abort, child, 2, b"RETRY")), RelayEvent::Aborted);
+        assert_eq!(buffer.accept(&build_sensor_data_frame(FrameType::Data, child, 3, b"abc")), RelayEvent::Accepted);
+        assert_eq!(buffer.accept(&build_sensor_data_frame(FrameType::Data, child, 5, b"ghi")), RelayEvent::Accepted);
+        assert_eq!(buffer.accept(&build_sensor_data_frame(FrameType::Hash, child, 6, b"HASH:ab,VOLT:80")), RelayEvent::Accepted);
+        // 詰め物付きのEOFでも欠番（seq=4）がある間はそろわない
+        let mut eof = build_sensor_data_frame(FrameType::Eof, child, 7, b"EOF");
+        eof.resize(250, 0);
+        assert_eq!(buffer.accept(&eof), RelayEvent::Accepted);
+        assert_eq!(buffer.missing_frames(), 1);
+        assert_eq!(buffer.accept(&build_sensor_data_frame(FrameType::Data, child, 4, b"def")), RelayEvent::Complete);
+
+        let transfer = buffer.into_transfer(relay_mac).unwrap();
+        assert_eq!(transfer.origin_mac, child);
+        assert_eq!(transfer.image, b"abcdefghi");
+        assert_eq!(transfer.hash_payload, "HASH:ab,VOLT:80,RELAY_HOPS:1,RELAY_VIA:246f28000002");
+    }
+
+    #[test]
+    fn soak_log_reports_cumulative_outcomes_and_heap() {
+        let mut log = SoakLog::new();
+        assert_eq!(log.metadata_fields(), "");
+
+        log.record(SoakOutcome::classify(true, true, true), 120_000);
+        log.record(SoakOutcome::classify(true, false, false), 118_000);
+        log.record(SoakOutcome::classify(false, true, true), 117_500);
+        log.record(SoakOutcome::classify(true, true, false), 117_000);
+        log.record(SoakOutcome::Success, 116_800);
+        assert_eq!(log.cycles(), 5);
+        assert_eq!(
+            log.metadata_fields(),
+            ",SOAK:5,SOAK_OK:2,SOAK_ERR:CAPTURE=1+PROBE=1+SEND=1,HEAP_FREE:116800"
+        );
+        assert_eq!(log.summary(), "5 cycles, 2 ok, CAPTURE=1+PROBE=1+SEND=1");
+    }
+
+    #[test]
+    fn relay_hops_are_appended_and_limited() {
+        let mac = [0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0xff];
+        assert_eq!(
+            add_relay_hop("HASH:ab,RELAY_HOPS:1,RELAY_VIA:246f28000002,VOLT:80", mac).as_deref(),
+            Some("HASH:ab,VOLT:80,RELAY_HOPS:2,RELAY_VIA:246f28000002+aabbccddeeff")
+        );
+        assert_eq!(add_relay_hop("HASH:ab,RELAY_HOPS:3,RELAY_VIA:a+b+c", mac), None);
+    }
+
+    #[test]
+    fn telemetry_round_trip_keeps_unknown_fields() {
+        // 新しいファームウェアの子機が送る、このファームウェアが知らない項目
+        let payload = "HASH:ab,VOLT:80,2026/02/11 12:00:00.000,HUMIDITY:61.5,RSSI:-67,Q_SCORE:0.82,TLM_V:2,TLM_CAPS:7,,x:y";
+        assert_eq!(TelemetryFields::parse(payload).to_string(), payload);
+        assert_eq!(TelemetryFields::parse(payload).get("HUMIDITY"), Some("61.5"));
+        assert_eq!(TelemetryFields::parse("HASH:ab,VOLT:80").version(), 0);
+
+        // 中継しても経路以外の項目は並び順・表記を変えない
+        let relayed = add_relay_hop(payload, [0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0xff]).unwrap();
+        assert_eq!(relayed, format!("{},RELAY_HOPS:1,RELAY_VIA:aabbccddeeff", payload));
+    }
+
+    #[test]
+    fn sys_wrappers_mock_stubs_radio_and_entropy() {
+        let mut sys = MockSys::new();
+        assert_eq!(sta_mac_or_fallback(&sys), ([0x34, 0xab, 0x95, 0xfb, 0x3f, 0xc4], None));
+
+        sys.failing_channels = vec![11];
+        assert_eq!(sys.set_channel(6), Ok(()));
+        assert_eq!(sys.set_channel(11), Err(SysError(-1)));
+        assert_eq!(sys.tuned_channels(), vec![6]);
+        assert_eq!((sys.random_u32(), sys.random_u32()), (1, 2));
+
+        sys.mac = None;
+        assert_eq!(sta_mac_or_fallback(&sys), (FALLBACK_STA_MAC, Some(SysError(-1))));
+    }
+
+    #[test]
+    fn chaos_spec_is_parsed_and_validated() {
+        let params = ChaosParams::parse("drop=5, dup=2,REORDER=1,corrupt=1,seed=42").unwrap().unwrap();
+        assert_eq!(params.encode(), "drop=5,dup=2,reorder=1,corrupt=1,seed=42");
+        assert_eq!(ChaosParams::parse("drop=10").unwrap().unwrap().seed, DEFAULT_CHAOS_SEED);
+        assert_eq!(ChaosParams::parse(""), Ok(None));
+        assert_eq!(ChaosParams::parse("off"), Ok(None));
+        assert!(ChaosParams::parse("drop=60,corrupt=50").is_err());
+        assert!(ChaosParams::parse("loss=5").is_err());
+    }
+
+    #[test]
+    fn chaos_faults_are_reproducible_from_the_seed() {
+        let params = ChaosParams::parse("drop=25,dup=25,reorder=25,corrupt=25,seed=9").unwrap().unwrap();
+        let mut a = ChaosInjector::new(params);
+        let mut b = ChaosInjector::new(params);
+        let actions: Vec<ChaosAction> = (0..100).map(|_| a.decide()).collect();
+        assert_eq!(actions, (0..100).map(|_| b.decide()).collect::<Vec<_>>());
+        assert!(!actions.contains(&ChaosAction::Pass));
+
+        let mut reorder = ChaosInjector::new(ChaosParams::parse("reorder=100").unwrap().unwrap());
+        assert!(reorder.apply(b"chunk1").is_empty());
+        assert_eq!(reorder.apply(b"chunk2"), vec![b"chunk2".to_vec(), b"chunk1".to_vec()]);
+        assert!(reorder.apply(b"eof").is_empty());
+        assert_eq!(reorder.flush(), Some(b"eof".to_vec()));
+
+        let mut corrupt = ChaosInjector::new(ChaosParams::parse("corrupt=100,seed=5").unwrap().unwrap());
+        let frame = [0xFFu8; 32];
+        let corrupted = corrupt.apply(&frame);
+        let flipped: u32 = corrupted[0].iter().map(|byte| byte.count_zeros()).sum();
+        assert_eq!(flipped, 1);
+        assert_eq!(corrupt.stats().corrupted, 1);
+    }
+
+    #[test]
+    fn chunk_pacing_uses_min_gap_on_success_and_backs_off_on_failure() {
+        let params = PacingParams { max_backoff_ms: 100, callback_timeout_ms: 50, mode: PacingMode::Delay };
+        let mut pacer = ChunkPacer::new(5, params);
+        assert_eq!(pacer.record(SendOutcome::Delivered), 5);
+        assert_eq!(pacer.record(SendOutcome::Failed), BACKOFF_BASE_MS);
+        assert_eq!(pacer.record(SendOutcome::NoCallback), BACKOFF_BASE_MS * 2);
+        assert_eq!(pacer.record(SendOutcome::Failed), BACKOFF_BASE_MS * 4);
+        assert_eq!(pacer.record(SendOutcome::Failed), 100);
+        assert_eq!(pacer.consecutive_failures(), 4);
+        assert_eq!(pacer.record(SendOutcome::Delivered), 5);
+
+        let stats = pacer.stats();
+        assert_eq!((stats.delivered, stats.failed, stats.no_callback), (2, 3, 1));
+        assert_eq!(stats.waited_ms, 5 + 20 + 40 + 80 + 100 + 5);
+    }
+
+    #[test]
+    fn chunk_pacing_backoff_never_drops_below_the_min_gap() {
+        let params = PacingParams { max_backoff_ms: 10, callback_timeout_ms: 50, mode: PacingMode::Delay };
+        let mut pacer = ChunkPacer::new(50, params);
+        assert_eq!(pacer.record(SendOutcome::Failed), 50);
+        for _ in 0..300 {
+            pacer.record(SendOutcome::Failed);
+        }
+        assert_eq!(pacer.next_gap_ms(), 50);
+        assert_eq!(ChunkPacer::new(0, PacingParams::default()).record(SendOutcome::Delivered), 0);
+    }
+
+    #[test]
+    fn light_sleep_pacing_sleeps_only_after_a_completed_send() {
+        assert_eq!(PacingMode::parse(" Light_Sleep "), Some(PacingMode::LightSleep));
+        assert_eq!(PacingMode::parse("delay"), Some(PacingMode::Delay));
+        assert_eq!(PacingMode::parse("sleep"), None);
+
+        let params = PacingParams { mode: PacingMode::LightSleep, ..PacingParams::default() };
+        let mut pacer = ChunkPacer::new(5, params);
+        assert_eq!(pacer.plan_gap(5, SendOutcome::Delivered), GapWait { sleep_ms: 4, delay_ms: 1 });
+        assert_eq!(pacer.plan_gap(40, SendOutcome::Failed), GapWait { sleep_ms: 39, delay_ms: 1 });
+        // コールバックが返らなければ無線がまだ送信中かもしれないので眠らない
+        assert_eq!(pacer.plan_gap(40, SendOutcome::NoCallback), GapWait { sleep_ms: 0, delay_ms: 40 });
+        let short = LIGHT_SLEEP_MIN_GAP_MS - 1;
+        assert_eq!(pacer.plan_gap(short, SendOutcome::Delivered), GapWait { sleep_ms: 0, delay_ms: short });
+
+        let delay = ChunkPacer::new(5, PacingParams::default());
+        assert_eq!(delay.plan_gap(5, SendOutcome::Delivered), GapWait { sleep_ms: 0, delay_ms: 5 });
+
+        pacer.record(SendOutcome::Delivered);
+        pacer.record_sleep(4);
+        assert_eq!((pacer.stats().waited_ms, pacer.stats().slept_ms), (5, 4));
+    }
+
+    #[test]
+    fn ack_timeout_follows_measured_rtt() {
+        use super::rtt::*;
+        let params = PacingParams { max_backoff_ms: 100, callback_timeout_ms: 50, mode: PacingMode::Delay };
+        let mut pacer = ChunkPacer::new(5, params);
+        assert_eq!(pacer.callback_timeout_ms(), 50);
+
+        // 最初の測定: SRTT=R, RTTVAR=R/2 → RTO = 3R
+        pacer.record_rtt(40_000);
+        assert_eq!(pacer.callback_timeout_ms(), 120);
+        // 続く測定は 1/8・1/4 で反映する
+        pacer.record_rtt(8_000);
+        assert_eq!(pacer.rtt().srtt_us(), Some(36_000));
+        assert_eq!(pacer.rtt().rttvar_us(), 23_000);
+        assert_eq!(pacer.callback_timeout_ms(), 128);
+
+        // タイムアウトが続くと次の測定まで倍にする（上限あり）
+        pacer.record(SendOutcome::NoCallback);
+        assert_eq!(pacer.callback_timeout_ms(), 256);
+        for _ in 0..10 {
+            pacer.record(SendOutcome::NoCallback);
+        }
+        assert_eq!(pacer.callback_timeout_ms(), MAX_ACK_TIMEOUT_MS);
+        pacer.record_rtt(36_000);
+        assert!(pacer.callback_timeout_ms() < 256);
+
+        // RTCメモリに保持した値から次のサイクルを始める
+        let (srtt_us, rttvar_us) = pacer.rtt().to_rtc();
+        let resumed = ChunkPacer::new(5, params).with_rtt(RttEstimator::restore(srtt_us, rttvar_us));
+        assert_eq!(resumed.callback_timeout_ms(), pacer.rtt().timeout_ms(50));
+        assert_eq!(RttEstimator::restore(0, 0), RttEstimator::new());
+
+        // 速いリンクでも下限を下回らない
+        let mut fast = RttEstimator::new();
+        for _ in 0..20 {
+            fast.record_sample(500);
+        }
+        assert_eq!(fast.timeout_ms(50), MIN_ACK_TIMEOUT_MS);
+    }
+
+    #[test]
+    fn camera_recovery_only_retries_sccb_error_codes() {
+        use super::recovery::*;
+        assert!(is_sccb_error_code(ESP_FAIL));
+        assert!(is_sccb_error_code(ESP_ERR_TIMEOUT));
+        assert!(is_sccb_error_code(ESP_ERR_CAMERA_NOT_DETECTED));
+        assert!(!is_sccb_error_code(ESP_ERR_CAMERA_NOT_SUPPORTED));
+        // ESP_ERR_NO_MEM はフレームバッファ確保失敗として別に扱う
+        assert!(!is_sccb_error_code(0x101));
+    }
+
+    #[test]
+    fn camera_recovery_power_cycles_after_the_first_reinit_and_gives_up_at_the_limit() {
+        use super::recovery::*;
+        let mut recovery = CameraRecovery::new(3, true);
+        assert_eq!(recovery.metadata_fields(), "");
+        assert_eq!(recovery.next_step(ESP_ERR_CAMERA_NOT_DETECTED), RecoveryStep::Reinit);
+        assert_eq!(recovery.next_step(ESP_FAIL), RecoveryStep::PowerCycleAndReinit);
+        assert_eq!(recovery.next_step(ESP_FAIL), RecoveryStep::PowerCycleAndReinit);
+        assert_eq!(recovery.next_step(ESP_ERR_TIMEOUT), RecoveryStep::GiveUp);
+        assert_eq!((recovery.attempts(), recovery.power_cycles()), (3, 2));
+        assert_eq!(
+            recovery.metadata_fields(),
+            ",CAM_REINIT:3,CAM_PWDN:2,CAM_RECOVERED:0,CAM_ERR:0x107"
+        );
+
+        let mut without_pwdn = CameraRecovery::new(2, false);
+        assert_eq!(without_pwdn.next_step(ESP_FAIL), RecoveryStep::Reinit);
+        assert_eq!(without_pwdn.next_step(ESP_FAIL), RecoveryStep::Reinit);
+        without_pwdn.mark_recovered();
+        assert!(without_pwdn.recovered());
+        assert!(without_pwdn.metadata_fields().starts_with(",CAM_REINIT:2,CAM_PWDN:0,CAM_RECOVERED:1"));
+
+        // 再初期化しなかったサイクルでは復旧扱いにしない
+        let mut untouched = CameraRecovery::new(2, true);
+        untouched.mark_recovered();
+        assert!(!untouched.recovered());
+        assert_eq!(CameraRecovery::new(0, true).next_step(ESP_FAIL), RecoveryStep::GiveUp);
+    }
+
+    #[test]
+    fn camera_health_tracks_fb_get_time_and_failure_streak() {
+        use super::health::*;
+        let mut health = CameraHealth::new();
+        health.record(CaptureOutcome::Failed);
+        assert_eq!(health.metadata_fields(DEFAULT_DEGRADED_STREAK), ",CAM_FAIL_STREAK:1");
+
+        health.record(CaptureOutcome::Captured { fb_get_ms: 120 });
+        health.record(CaptureOutcome::Captured { fb_get_ms: 200 });
+        // 移動平均は新しい値を1/4だけ反映する
+        assert_eq!(health.average_fb_get_ms(), Some(140));
+        assert_eq!(health.fail_streak(), 0);
+
+        for _ in 0..3 {
+            health.record(CaptureOutcome::Failed);
+        }
+        assert!(health.is_degraded(3));
+        assert!(!health.is_degraded(4));
+        assert!(!health.is_degraded(0));
+        assert_eq!(
+            health.metadata_fields(3),
+            ",CAM_FB_MS:200,CAM_FB_AVG_MS:140,CAM_FAIL_STREAK:3,CAM_DEGRADED:1"
+        );
+        // 撮影できれば連続失敗回数はリセットされる
+        health.record(CaptureOutcome::Captured { fb_get_ms: 100 });
+        assert_eq!(health.metadata_fields(3), ",CAM_FB_MS:100,CAM_FB_AVG_MS:130,CAM_FAIL_STREAK:0");
+    }
+
+    #[test]
+    fn device_id_must_be_a_hyphenated_uuid() {
+        use super::device_identity::*;
+        assert!(is_valid_device_id("3f2a9c1e-0b7d-4e58-9a61-2c4f8d0e7b15"));
+        assert!(is_valid_device_id("3F2A9C1E-0B7D-4E58-9A61-2C4F8D0E7B15"));
+        assert!(!is_valid_device_id("3f2a9c1e0b7d4e589a612c4f8d0e7b15"));
+        assert!(!is_valid_device_id("3f2a9c1e-0b7d-4e58-9a61-2c4f8d0e7b1g"));
+        assert_eq!(
+            metadata_field("3F2A9C1E-0B7D-4E58-9A61-2C4F8D0E7B15"),
+            ",DEV_ID:3f2a9c1e-0b7d-4e58-9a61-2c4f8d0e7b15"
+        );
+    }
+
+    #[test]
+    fn camera_warmup_strategies_stop_by_count_time_or_stable_exposure() {
+        use super::warmup::*;
+        assert_eq!(WarmupStrategy::from_config("Duration", Some(2), 800), Some(WarmupStrategy::Duration { ms: 800 }));
+        assert_eq!(WarmupStrategy::from_config("frames", None, 800), Some(WarmupStrategy::Frames(0)));
+        assert_eq!(WarmupStrategy::from_config("fast", Some(2), 800), None);
+        assert_eq!(WarmupStrategy::from_config("auto", Some(2), MAX_WARMUP_MS + 1), None);
+
+        let mut frames = Warmup::new(WarmupStrategy::Frames(2));
+        for _ in 0..2 {
+            assert_eq!(frames.next_step(0), WarmupStep::Discard { delay_after_ms: FRAME_WARMUP_DELAY_MS });
+            frames.record_discard(Some(100));
+        }
+        assert_eq!(frames.next_step(0), WarmupStep::Done);
+
+        let mut duration = Warmup::new(WarmupStrategy::Duration { ms: 500 });
+        assert_eq!(duration.next_step(499), WarmupStep::Discard { delay_after_ms: 0 });
+        duration.record_discard(None);
+        assert_eq!(duration.next_step(500), WarmupStep::Done);
+
+        let mut auto = Warmup::new(WarmupStrategy::Auto { max_ms: 1500 });
+        for aec in [300, 200, 204, 205] {
+            assert_eq!(auto.next_step(100), WarmupStep::Discard { delay_after_ms: 0 });
+            auto.record_discard(Some(aec));
+        }
+        assert!(auto.stabilized());
+        assert_eq!(auto.next_step(100), WarmupStep::Done);
+        assert_eq!(auto.discarded(), 4);
+
+        let mut unstable = Warmup::new(WarmupStrategy::Auto { max_ms: 1500 });
+        for aec in [Some(200), None, Some(200), Some(200)] {
+            unstable.record_discard(aec);
+        }
+        assert!(!unstable.stabilized());
+        assert_eq!(unstable.next_step(1500), WarmupStep::Done);
+    }
 }
@@ -0,0 +1,29 @@
+#!/bin/bash
+
+# M5Stack Unit Cam ホストマシンユニットテスト実行スクリプト
+# Before first use, make this script executable with: chmod +x run_tests.sh
+set -e  # エラーで停止
+
+echo "================================"
+echo "M5Stack Unit Cam ユニットテスト実行"
+echo "================================"
+echo ""
+
+# ホストアーキテクチャを検出
+HOST_TARGET=$(rustc --version --verbose | grep host | awk '{print $2}')
+echo "🖥️  ホストターゲット: $HOST_TARGET"
+echo ""
+
+# ESP-IDF非依存のモジュールがホストでビルドできることを確認
+echo "🔨 ハードウェア非依存ライブラリのビルド..."
+cargo +stable build --lib --target "$HOST_TARGET" --no-default-features --features host
+
+# ロジックのテストは host_frame_tests クレートにまとめています
+echo "🧪 すべてのユニットテスト実行..."
+cd host_frame_tests
+RUST_BACKTRACE=1 cargo +stable test --target "$HOST_TARGET"
+
+echo ""
+echo "================================"
+echo "✅ すべてのテスト完了"
+echo "================================"
@@ -0,0 +1,184 @@
+//! ゲートウェイの受信チャンネルの時間分割（チャンネルホッピング）
+//!
+//! チャンネルホッピングを有効にしたゲートウェイは、Pongに受信チャンネルの予定を付加します。
+//!
+//! ```text
+//! [HOME(1)] [SLOT_SECS(2, LE)] [ELAPSED_SECS(2, LE)] [COUNT(1)] [CHANNELS(COUNT)]
+//! CHANNELS: 現在のスロットから1周分のチャンネル（ELAPSED_SECS は現在のスロットの経過秒数）
+//! ```
+//!
+//! デバイスはスリープ前に起床時のスロットのチャンネルを計算し、起床後はそのチャンネル、
+//! ホームチャンネル、予定の他のチャンネルの順に疎通確認します。予定のチャンネルで続けて
+//! 届かなかった場合は、しばらくホームチャンネルから試します。
+
+/// 予定の最大スロット数（ゲートウェイの MAX_HOP_SLOTS と同じ）
+pub const MAX_HOP_SLOTS: usize = 8;
+/// 予定のヘッダ（ホームチャンネル・スロット長・経過秒数・スロット数）のバイト長
+pub const HOP_SCHEDULE_HEADER_LEN: usize = 6;
+/// 予定のチャンネルで続けて届かなかった場合にホームチャンネルから試す回数の閾値
+pub const MAX_HOP_MISSES: u8 = 2;
+/// ホームチャンネルから試すサイクル数
+pub const HOP_BACKOFF_CYCLES: u8 = 5;
+
+/// Pongで通知されたゲートウェイの受信チャンネルの予定
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HopSchedule {
+    /// ホームチャンネル
+    pub home: u8,
+    /// 1スロットの秒数
+    pub slot_secs: u16,
+    /// Pong送信時点の現在のスロットの経過秒数
+    pub elapsed_secs: u16,
+    channels: [u8; MAX_HOP_SLOTS],
+    len: u8,
+}
+
+impl HopSchedule {
+    /// 予定のワイヤ表現を解析します（長さや値が不正なら None）
+    pub fn from_wire(data: &[u8]) -> Option<Self> {
+        if data.len() < HOP_SCHEDULE_HEADER_LEN {
+            return None;
+        }
+        let len = data[5] as usize;
+        if len == 0 || len > MAX_HOP_SLOTS || data.len() != HOP_SCHEDULE_HEADER_LEN + len {
+            return None;
+        }
+        let slot_secs = u16::from_le_bytes([data[1], data[2]]);
+        let elapsed_secs = u16::from_le_bytes([data[3], data[4]]);
+        let listed = &data[HOP_SCHEDULE_HEADER_LEN..];
+        if slot_secs == 0 || !(1..=13).contains(&data[0]) || listed.iter().any(|channel| !(1..=13).contains(channel)) {
+            return None;
+        }
+        let mut channels = [0u8; MAX_HOP_SLOTS];
+        channels[..len].copy_from_slice(listed);
+        Some(Self {
+            home: data[0],
+            slot_secs,
+            elapsed_secs,
+            channels,
+            len: len as u8,
+        })
+    }
+
+    /// 現在のスロットから1周分のチャンネル
+    pub fn channels(&self) -> &[u8] {
+        &self.channels[..self.len as usize]
+    }
+
+    /// Pong受信から `elapsed_ms` 後にゲートウェイが受信しているチャンネル
+    pub fn channel_after(&self, elapsed_ms: u64) -> u8 {
+        let slot_ms = self.slot_secs as u64 * 1000;
+        let offset_ms = self.elapsed_secs as u64 * 1000 + elapsed_ms;
+        self.channels[((offset_ms / slot_ms) % self.len as u64) as usize]
+    }
+}
+
+/// 起床時に疎通確認するチャンネルの計画（Deep sleep を跨いでRTCメモリに保持）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HopPlan {
+    /// 起床時に最初に試すチャンネル（0は予定なし）
+    wake_channel: u8,
+    /// ホームチャンネル（0は予定を受け取っていない）
+    home: u8,
+    /// 予定のチャンネル（重複なし）
+    channels: [u8; MAX_HOP_SLOTS],
+    len: u8,
+    /// 予定のチャンネルで続けて届かなかった回数
+    misses: u8,
+    /// ホームチャンネルから試す残りサイクル数
+    backoff_cycles: u8,
+    /// 今回ゲートウェイに届いたチャンネル（0は届かなかった）
+    reached_channel: u8,
+    /// 今回予定のチャンネルで届かなかった場合のそのチャンネル（0はなし）
+    missed_channel: u8,
+}
+
+impl Default for HopPlan {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HopPlan {
+    /// 予定を受け取っていない計画
+    pub const fn new() -> Self {
+        Self {
+            wake_channel: 0,
+            home: 0,
+            channels: [0; MAX_HOP_SLOTS],
+            len: 0,
+            misses: 0,
+            backoff_cycles: 0,
+            reached_channel: 0,
+            missed_channel: 0,
+        }
+    }
+
+    /// 予定のチャンネルから試すか（予定があり、ホームチャンネルからに戻していない）
+    fn follows_schedule(&self) -> bool {
+        self.wake_channel != 0 && self.backoff_cycles == 0
+    }
+
+    /// 疎通確認するチャンネルを試す順に返します（予定を受け取っていなければ空）
+    pub fn probe_channels(&self) -> Vec<u8> {
+        if self.home == 0 {
+            return Vec::new();
+        }
+        let mut order = Vec::with_capacity(self.len as usize + 1);
+        if self.follows_schedule() {
+            order.push(self.wake_channel);
+        }
+        order.push(self.home);
+        for &channel in &self.channels[..self.len as usize] {
+            if !order.contains(&channel) {
+                order.push(channel);
+            }
+        }
+        order
+    }
+
+    /// 疎通確認の結果を記録します（`reached` はPongを受信したチャンネル）
+    pub fn record_probe(&mut self, reached: Option<u8>) {
+        self.reached_channel = reached.unwrap_or(0);
+        self.missed_channel = 0;
+        if self.follows_schedule() {
+            if reached == Some(self.wake_channel) {
+                self.misses = 0;
+            } else {
+                self.missed_channel = self.wake_channel;
+                self.misses = self.misses.saturating_add(1);
+                if self.misses >= MAX_HOP_MISSES {
+                    self.misses = 0;
+                    self.backoff_cycles = HOP_BACKOFF_CYCLES;
+                }
+            }
+        } else {
+            self.backoff_cycles = self.backoff_cycles.saturating_sub(1);
+        }
+    }
+
+    /// 予定から `ms_until_probe` 後（次の起床後の疎通確認）のチャンネルを計画します
+    pub fn plan(&mut self, schedule: &HopSchedule, ms_until_probe: u64) {
+        self.wake_channel = schedule.channel_after(ms_until_probe);
+        self.home = schedule.home;
+        self.len = 0;
+        for &channel in schedule.channels() {
+            if !self.channels[..self.len as usize].contains(&channel) {
+                self.channels[self.len as usize] = channel;
+                self.len += 1;
+            }
+        }
+    }
+
+    /// HASHフレームに付加するメタデータ（`,HOP_CH:6,HOP_MISS:11` 形式。予定がなければ空）
+    pub fn metadata_fields(&self) -> String {
+        let mut fields = String::new();
+        if self.reached_channel != 0 {
+            fields.push_str(&format!(",HOP_CH:{}", self.reached_channel));
+        }
+        if self.missed_channel != 0 {
+            fields.push_str(&format!(",HOP_MISS:{}", self.missed_channel));
+        }
+        fields
+    }
+}
@@ -0,0 +1,220 @@
+//! 送信フレームへの故障注入（`chaos` フィーチャー）
+//!
+//! 再送・FEC・再組み立ての頑健性を実地試験の前に机上で確かめるため、ESP-NOWで送信するフレームを
+//! 設定した割合で破棄・重複・入れ替え・破損させてから送信します。判定はシード付きの擬似乱数で行うため、
+//! 同じシードなら同じ故障の並びが再現します。設定は `cfg.toml` の `esp_now_chaos` で行います。
+//!
+//! 指定の形式は `drop=5,dup=2,reorder=1,corrupt=1,seed=42`（割合は%、合計100以下、省略した項目は0、
+//! シードの省略時は1）です。ゲートウェイの `chaos`・`CHAOS` コマンドと同じ形式です。
+
+/// シードを省略したときの既定値
+pub const DEFAULT_CHAOS_SEED: u32 = 1;
+
+/// 故障注入の割合（%）とシード
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChaosParams {
+    /// 破棄する割合
+    pub drop_percent: u8,
+    /// 2回送信する割合
+    pub duplicate_percent: u8,
+    /// 次のフレームの後ろへ回す割合
+    pub reorder_percent: u8,
+    /// 1ビット反転させる割合
+    pub corrupt_percent: u8,
+    /// 擬似乱数のシード
+    pub seed: u32,
+}
+
+impl ChaosParams {
+    /// `drop=N,dup=N,reorder=N,corrupt=N,seed=N` 形式の指定を解析します
+    ///
+    /// 空の指定と `off` は無効（`None`）です。
+    pub fn parse(spec: &str) -> Result<Option<Self>, String> {
+        let spec = spec.trim();
+        if spec.is_empty() || spec.eq_ignore_ascii_case("off") {
+            return Ok(None);
+        }
+        let mut params = Self {
+            drop_percent: 0,
+            duplicate_percent: 0,
+            reorder_percent: 0,
+            corrupt_percent: 0,
+            seed: DEFAULT_CHAOS_SEED,
+        };
+        for field in spec.split(',') {
+            let invalid = || format!("invalid field '{}'", field);
+            let (key, value) = field.split_once('=').ok_or_else(invalid)?;
+            let key = key.trim().to_ascii_lowercase();
+            let value = value.trim();
+            if key == "seed" {
+                params.seed = value.parse().map_err(|_| invalid())?;
+                continue;
+            }
+            let percent = value.parse::<u8>().ok().filter(|percent| *percent <= 100).ok_or_else(invalid)?;
+            match key.as_str() {
+                "drop" => params.drop_percent = percent,
+                "dup" => params.duplicate_percent = percent,
+                "reorder" => params.reorder_percent = percent,
+                "corrupt" => params.corrupt_percent = percent,
+                _ => return Err(invalid()),
+            }
+        }
+        let total = params.total_percent();
+        if total > 100 {
+            return Err(format!("percentages add up to {} (max 100)", total));
+        }
+        Ok(Some(params))
+    }
+
+    /// 割合の合計
+    fn total_percent(&self) -> u32 {
+        [self.drop_percent, self.duplicate_percent, self.reorder_percent, self.corrupt_percent]
+            .iter()
+            .map(|&percent| percent as u32)
+            .sum()
+    }
+
+    /// 指定の形式の文字列
+    pub fn encode(&self) -> String {
+        format!(
+            "drop={},dup={},reorder={},corrupt={},seed={}",
+            self.drop_percent, self.duplicate_percent, self.reorder_percent, self.corrupt_percent, self.seed
+        )
+    }
+}
+
+/// 1フレームに対する故障の種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChaosAction {
+    /// そのまま送信する
+    Pass,
+    /// 送信しない
+    Drop,
+    /// 2回送信する
+    Duplicate,
+    /// 次に送信するフレームの後ろへ回す
+    Reorder,
+    /// 1ビット反転させて送信する
+    Corrupt,
+}
+
+/// 注入した故障の件数
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ChaosStats {
+    pub passed: u32,
+    pub dropped: u32,
+    pub duplicated: u32,
+    pub reordered: u32,
+    pub corrupted: u32,
+}
+
+impl ChaosStats {
+    /// 応答・ログ用の要約
+    pub fn summary(&self) -> String {
+        format!(
+            "passed={} dropped={} duplicated={} reordered={} corrupted={}",
+            self.passed, self.dropped, self.duplicated, self.reordered, self.corrupted
+        )
+    }
+}
+
+/// シード付きの擬似乱数で故障を注入する
+#[derive(Debug)]
+pub struct ChaosInjector {
+    params: ChaosParams,
+    state: u32,
+    /// 入れ替えのため後ろへ回しているフレーム
+    held: Option<Vec<u8>>,
+    stats: ChaosStats,
+}
+
+impl ChaosInjector {
+    /// 指定の割合とシードで作成します
+    pub fn new(params: ChaosParams) -> Self {
+        // xorshiftは0から抜け出せないため、0は固定値に置き換える
+        let state = if params.seed == 0 { 0x9E37_79B9 } else { params.seed };
+        Self {
+            params,
+            state,
+            held: None,
+            stats: ChaosStats::default(),
+        }
+    }
+
+    /// 注入した故障の件数
+    pub fn stats(&self) -> ChaosStats {
+        self.stats
+    }
+
+    fn next_random(&mut self) -> u32 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 17;
+        self.state ^= self.state << 5;
+        self.state
+    }
+
+    /// 次のフレームに注入する故障を決めます
+    pub fn decide(&mut self) -> ChaosAction {
+        let roll = (self.next_random() % 100) as u8;
+        let p = self.params;
+        let thresholds = [
+            (p.drop_percent, ChaosAction::Drop),
+            (p.duplicate_percent, ChaosAction::Duplicate),
+            (p.reorder_percent, ChaosAction::Reorder),
+            (p.corrupt_percent, ChaosAction::Corrupt),
+        ];
+        let mut upper = 0u8;
+        for (percent, action) in thresholds {
+            upper = upper.saturating_add(percent);
+            if roll < upper {
+                return action;
+            }
+        }
+        ChaosAction::Pass
+    }
+
+    /// フレームに故障を注入し、送信するフレームを順に返します
+    ///
+    /// 後ろへ回したフレームは、次に送信するフレームの直後に返します（すでに1件回している間は入れ替えない）。
+    pub fn apply(&mut self, frame: &[u8]) -> Vec<Vec<u8>> {
+        let mut action = self.decide();
+        if action == ChaosAction::Reorder && self.held.is_some() {
+            action = ChaosAction::Pass;
+        }
+        let mut frames = match action {
+            ChaosAction::Drop => {
+                self.stats.dropped += 1;
+                return Vec::new();
+            }
+            ChaosAction::Reorder => {
+                self.stats.reordered += 1;
+                self.held = Some(frame.to_vec());
+                return Vec::new();
+            }
+            ChaosAction::Pass => {
+                self.stats.passed += 1;
+                vec![frame.to_vec()]
+            }
+            ChaosAction::Duplicate => {
+                self.stats.duplicated += 1;
+                vec![frame.to_vec(), frame.to_vec()]
+            }
+            ChaosAction::Corrupt => {
+                self.stats.corrupted += 1;
+                let mut corrupted = frame.to_vec();
+                if !corrupted.is_empty() {
+                    let bit = self.next_random() as usize % (corrupted.len() * 8);
+                    corrupted[bit / 8] ^= 1 << (bit % 8);
+                }
+                vec![corrupted]
+            }
+        };
+        frames.extend(self.held.take());
+        frames
+    }
+
+    /// 後ろへ回しているフレームを取り出します（転送の最後に送信する）
+    pub fn flush(&mut self) -> Option<Vec<u8>> {
+        self.held.take()
+    }
+}
@@ -0,0 +1,73 @@
+//! 転送後のコマンド待機の短縮
+//!
+//! 疎通確認のPingで `CAPABILITY_COMMAND_WINDOW` を示すと、ゲートウェイはHASHフレームの受信時に
+//! 送信待ちのコマンド（ホストからのスリープコマンドを含む）の有無と件数を返します。
+//! コマンドがなければスリープコマンドを待たずに既定のスリープ時間でスリープし、短縮した待機時間を
+//! 次のHASHフレームの `WINDOW_SAVED_MS` で報告します。応答が届かなければ従来どおり待機します。
+//! `CAPABILITY_LOSS_REPORT` を示すと、応答の末尾にゲートウェイのギャップマップから求めた前回の転送の
+//! DATA/FECの損失率が付き、次の起動のFECパラメータはこの値から決めます。
+//! `CAPABILITY_GATEWAY_CLOCK` を示すと、損失率の後ろにゲートウェイの稼働時間が付き、
+//! スリープ中のRTCドリフトの計測に使います（`power::sleep::drift`）。
+
+/// HASHフレームへの応答のメッセージタイプ（ゲートウェイの MessageType::HashAck と同じ）
+pub const HASH_ACK_MESSAGE_TYPE: u8 = 0x0D;
+/// HASHフレームへの応答の識別子
+const HASH_ACK_MAGIC: [u8; 4] = *b"HACK";
+/// HASHフレームへの応答のメッセージ長: [TYPE(1)] ["HACK"(4)] [COMMANDS_PENDING(1)] [PENDING_COUNT(1)]
+pub const HASH_ACK_MESSAGE_LEN: usize = 7;
+/// 損失率を付加したHASHフレームへの応答のメッセージ長: [...] [LOSS_PERCENT(1)]
+pub const HASH_ACK_WITH_LOSS_LEN: usize = HASH_ACK_MESSAGE_LEN + 1;
+/// ゲートウェイの時計を付加したHASHフレームへの応答のメッセージ長: [...] [LOSS_PERCENT(1)] [GATEWAY_US(8, LE)]
+pub const HASH_ACK_WITH_CLOCK_LEN: usize = HASH_ACK_WITH_LOSS_LEN + 8;
+/// 損失率が未観測であることを示す値（時計を付加した応答のみ）
+const LOSS_UNKNOWN: u8 = u8::MAX;
+
+/// ゲートウェイからのHASHフレームへの応答
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HashAck {
+    /// 転送後に送られるコマンドがある
+    pub commands_pending: bool,
+    /// 送信待ちのコマンド数の目安
+    pub pending_count: u8,
+    /// ゲートウェイが観測した前回の転送の損失率（%、付加されていなければ None）
+    pub loss_percent: Option<u8>,
+    /// 応答を送った時点のゲートウェイの稼働時間（マイクロ秒、付加されていなければ None）
+    pub gateway_us: Option<u64>,
+}
+
+impl HashAck {
+    /// コマンド待機を省略してよいか
+    pub fn skips_command_window(&self) -> bool {
+        !self.commands_pending
+    }
+}
+
+/// 受信データをHASHフレームへの応答として解析します（応答でなければ None）
+pub fn parse_hash_ack(data: &[u8]) -> Option<HashAck> {
+    if !matches!(data.len(), HASH_ACK_MESSAGE_LEN | HASH_ACK_WITH_LOSS_LEN | HASH_ACK_WITH_CLOCK_LEN)
+        || data[0] != HASH_ACK_MESSAGE_TYPE
+        || data[1..5] != HASH_ACK_MAGIC
+    {
+        return None;
+    }
+    Some(HashAck {
+        commands_pending: data[5] != 0,
+        pending_count: data[6],
+        loss_percent: data
+            .get(HASH_ACK_MESSAGE_LEN)
+            .filter(|&&loss| loss != LOSS_UNKNOWN)
+            .map(|&loss| loss.min(100)),
+        gateway_us: data
+            .get(HASH_ACK_WITH_LOSS_LEN..)
+            .and_then(|clock| clock.try_into().ok())
+            .map(u64::from_le_bytes),
+    })
+}
+
+/// HASHフレームに付加する短縮した待機時間（短縮していなければ空）
+pub fn window_saved_metadata_field(saved_ms: u32) -> String {
+    if saved_ms == 0 {
+        return String::new();
+    }
+    format!(",WINDOW_SAVED_MS:{}", saved_ms)
+}
@@ -0,0 +1,558 @@
+//! ゲートウェイからの制御メッセージの認証（リプレイ対策）
+//!
+//! 傍受したスリープコマンドを再送してデバイスを眠らせ続ける攻撃を防ぐため、
+//! 認証鍵を設定した場合は署名付きスリープコマンドのみ受理します。
+//! カウンタは最後に受理した値より大きいものだけを受理し、受理した値はNVSに保存します。
+//!
+//! ゲートウェイは鍵更新メッセージで新しい鍵を配送します。新しい鍵は指定されたカウンタ以上の
+//! コマンドから使い、それまでは現在の鍵で検証します（`DeviceKeys`）。一斉配信は
+//! プロビジョニングした鍵のまま検証します。
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+/// 署名付きスリープコマンドのメッセージタイプ（ゲートウェイの MessageType::SignedSleepCommand と同じ）
+pub const SIGNED_SLEEP_COMMAND_TYPE: u8 = 0x07;
+/// 署名の対象となる本文長: [TYPE(1)] [COUNTER(4, LE)] [SLEEP_SECONDS(4, LE)]
+const SIGNED_BODY_LEN: usize = 9;
+/// タグ長（HMAC-SHA256の先頭8バイト）
+const TAG_LEN: usize = 8;
+/// 署名付きスリープコマンドのメッセージ長
+pub const SIGNED_SLEEP_COMMAND_LEN: usize = SIGNED_BODY_LEN + TAG_LEN;
+/// 認証鍵のバイト長
+pub const DOWNLINK_KEY_LEN: usize = 32;
+/// 設定の一斉配信のメッセージタイプ（ゲートウェイの MessageType::BroadcastConfig と同じ）
+pub const BROADCAST_CONFIG_TYPE: u8 = 0x08;
+/// 一斉配信の署名に使う宛先（ブロードキャストアドレス）
+const BROADCAST_MAC: [u8; 6] = [0xFF; 6];
+/// 一斉配信のヘッダ長: [TYPE(1)] [CONFIG_ID(4, LE)] [CONFIG_LEN(1)]
+const BROADCAST_HEADER_LEN: usize = 6;
+/// デバイスごとの設定更新のメッセージタイプ（ゲートウェイの MessageType::ConfigUpdate と同じ）
+pub const CONFIG_UPDATE_TYPE: u8 = 0x09;
+/// 設定更新のヘッダ長: [TYPE(1)] [COUNTER(4, LE)] [CONFIG_LEN(1)]
+const CONFIG_UPDATE_HEADER_LEN: usize = 6;
+/// ファイルの断片のメッセージタイプ（ゲートウェイの MessageType::FileChunk と同じ）
+pub const FILE_CHUNK_TYPE: u8 = 0x0E;
+/// ファイルの断片のヘッダ長:
+/// [TYPE(1)] [COUNTER(4, LE)] [FILE_CRC(4, LE)] [TOTAL_LEN(2, LE)] [OFFSET(2, LE)] [SLOT_LEN(1)] [DATA_LEN(1)]
+const FILE_CHUNK_HEADER_LEN: usize = 15;
+/// 鍵更新のメッセージタイプ（ゲートウェイの MessageType::KeyRotation と同じ）
+pub const KEY_ROTATION_TYPE: u8 = 0x0C;
+/// 鍵更新の本文長: [TYPE(1)] [COUNTER(4, LE)] [EPOCH(4, LE)] [ACTIVATE_COUNTER(4, LE)] [WRAPPED_KEY(32)]
+const KEY_ROTATION_BODY_LEN: usize = 13 + DOWNLINK_KEY_LEN;
+/// 鍵更新のメッセージ長
+pub const KEY_ROTATION_LEN: usize = KEY_ROTATION_BODY_LEN + TAG_LEN;
+/// 鍵の暗号化に使う鍵ストリームのラベル
+const KEY_WRAP_LABEL: &[u8] = b"KEYWRAP";
+/// NVSに保存する鍵の記録のバイト長（ゲートウェイと同じ形式）
+///
+/// `[EPOCH(4)] [KEY(32)] [PENDING_EPOCH(4)] [PENDING_KEY(32)] [ACTIVATE_COUNTER(4)]`
+/// （世代0はプロビジョニングした鍵で、鍵の欄は使わない。更新待ちがなければ PENDING_EPOCH は0）
+pub const DEVICE_KEY_RECORD_LEN: usize = 4 + DOWNLINK_KEY_LEN + 4 + DOWNLINK_KEY_LEN + 4;
+
+/// ダウンリンク認証鍵
+#[derive(Clone, PartialEq, Eq)]
+pub struct DownlinkKey([u8; DOWNLINK_KEY_LEN]);
+
+impl DownlinkKey {
+    /// 16進文字列（64文字）から鍵を作成します
+    pub fn from_hex(hex: &str) -> Option<Self> {
+        let hex = hex.trim().as_bytes();
+        if hex.len() != DOWNLINK_KEY_LEN * 2 {
+            return None;
+        }
+        let mut key = [0u8; DOWNLINK_KEY_LEN];
+        for (byte, pair) in key.iter_mut().zip(hex.chunks_exact(2)) {
+            let digits = std::str::from_utf8(pair).ok()?;
+            *byte = u8::from_str_radix(digits, 16).ok()?;
+        }
+        Some(Self(key))
+    }
+
+    /// 新しい鍵をこの鍵で復号します（ゲートウェイの `DownlinkKey::wrap_key` と同じ操作）
+    fn unwrap_key(&self, own_mac: &[u8; 6], counter: u32, wrapped: &[u8]) -> Self {
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(&self.0)
+            .expect("HMAC accepts keys of any length");
+        mac.update(KEY_WRAP_LABEL);
+        mac.update(own_mac);
+        mac.update(&counter.to_le_bytes());
+        let stream = mac.finalize().into_bytes();
+        let mut key = [0u8; DOWNLINK_KEY_LEN];
+        for ((byte, wrapped), mask) in key.iter_mut().zip(wrapped).zip(stream.iter()) {
+            *byte = wrapped ^ mask;
+        }
+        Self(key)
+    }
+
+    fn tag(&self, own_mac: &[u8; 6], body: &[u8]) -> [u8; TAG_LEN] {
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(&self.0)
+            .expect("HMAC accepts keys of any length");
+        mac.update(own_mac);
+        mac.update(body);
+        let digest = mac.finalize().into_bytes();
+        let mut tag = [0u8; TAG_LEN];
+        tag.copy_from_slice(&digest[..TAG_LEN]);
+        tag
+    }
+}
+
+/// 受信したタグが期待値と一致するか（定数時間比較）
+fn tag_matches(expected: &[u8; TAG_LEN], tag: &[u8]) -> bool {
+    tag.len() == TAG_LEN && expected.iter().zip(tag).fold(0u8, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+impl std::fmt::Debug for DownlinkKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("DownlinkKey(..)")
+    }
+}
+
+/// 検証済みのスリープコマンド
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SignedSleepCommand {
+    /// コマンドカウンタ
+    pub counter: u32,
+    /// スリープ時間（秒）
+    pub sleep_seconds: u32,
+}
+
+/// 検証済みの設定の一斉配信
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BroadcastConfig {
+    /// 設定ID（適用後にHASHフレームで報告する）
+    pub config_id: u32,
+    /// 設定文字列（リモート設定と同じ `KEY=VALUE;...` 形式）
+    pub config: String,
+}
+
+/// 検証済みのデバイスごとの設定更新
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigUpdate {
+    /// コマンドカウンタ（署名なしは0）
+    pub counter: u32,
+    /// 設定文字列（リモート設定と同じ `KEY=VALUE;...` 形式）
+    pub config: String,
+}
+
+/// 検証済みのファイルの断片
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileChunk {
+    /// コマンドカウンタ（署名なしは0）
+    pub counter: u32,
+    /// 保存先のスロット名
+    pub slot: String,
+    /// ファイル全体のCRC-32
+    pub file_crc: u32,
+    /// ファイル全体の長さ
+    pub total_len: u16,
+    /// 断片の先頭位置
+    pub offset: u16,
+    /// 断片のデータ
+    pub data: Vec<u8>,
+}
+
+/// 検証済みの鍵更新
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyRotation {
+    /// コマンドカウンタ
+    pub counter: u32,
+    /// 新しい鍵の世代
+    pub epoch: u32,
+    /// 新しい鍵に切り替えるコマンドカウンタ
+    pub activate_counter: u32,
+    /// 新しい鍵
+    pub new_key: DownlinkKey,
+}
+
+/// 制御メッセージを拒否した理由
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DownlinkRejection {
+    /// 署名付き形式ではない、またはタグが一致しない
+    BadSignature,
+    /// 受理済みのカウンタ以下（再送・リプレイ）
+    Replay {
+        /// 受信したカウンタ
+        counter: u32,
+        /// 最後に受理したカウンタ
+        last_accepted: u32,
+    },
+}
+
+/// 受信データが署名付きスリープコマンドの形式かどうか（タグは確認しない）
+pub fn is_signed_sleep_command(data: &[u8]) -> bool {
+    data.len() == SIGNED_SLEEP_COMMAND_LEN && data[0] == SIGNED_SLEEP_COMMAND_TYPE
+}
+
+/// 署名付きスリープコマンドを検証します
+///
+/// # 引数
+/// * `own_mac` - 自デバイスのMAC（他デバイス宛てのコマンドの流用を防ぐ）
+/// * `last_accepted` - 最後に受理したカウンタ（未受理は0）
+pub fn verify_signed_sleep_command(
+    data: &[u8],
+    key: &DownlinkKey,
+    own_mac: &[u8; 6],
+    last_accepted: u32,
+) -> Result<SignedSleepCommand, DownlinkRejection> {
+    if !is_signed_sleep_command(data) {
+        return Err(DownlinkRejection::BadSignature);
+    }
+    let (body, tag) = data.split_at(SIGNED_BODY_LEN);
+    let expected = key.tag(own_mac, body);
+    if !tag_matches(&expected, tag) {
+        return Err(DownlinkRejection::BadSignature);
+    }
+    let counter = u32::from_le_bytes([body[1], body[2], body[3], body[4]]);
+    if counter <= last_accepted {
+        return Err(DownlinkRejection::Replay {
+            counter,
+            last_accepted,
+        });
+    }
+    Ok(SignedSleepCommand {
+        counter,
+        sleep_seconds: u32::from_le_bytes([body[5], body[6], body[7], body[8]]),
+    })
+}
+
+/// 受信データが設定の一斉配信の形式かどうか（タグは確認しない）
+pub fn is_broadcast_config(data: &[u8]) -> bool {
+    data.len() >= BROADCAST_HEADER_LEN
+        && data[0] == BROADCAST_CONFIG_TYPE
+        && data.len() == BROADCAST_HEADER_LEN + data[5] as usize + TAG_LEN
+}
+
+/// 設定の一斉配信を検証します
+///
+/// 全デバイス宛てのため、タグはブロードキャストアドレスに対して計算されています。
+///
+/// # 引数
+/// * `applied_id` - 適用済みの設定ID（これ以下の配信はリプレイとして拒否）
+pub fn verify_broadcast_config(
+    data: &[u8],
+    key: &DownlinkKey,
+    applied_id: u32,
+) -> Result<BroadcastConfig, DownlinkRejection> {
+    if !is_broadcast_config(data) {
+        return Err(DownlinkRejection::BadSignature);
+    }
+    let (body, tag) = data.split_at(data.len() - TAG_LEN);
+    let expected = key.tag(&BROADCAST_MAC, body);
+    if !tag_matches(&expected, tag) {
+        return Err(DownlinkRejection::BadSignature);
+    }
+    let config_id = u32::from_le_bytes([body[1], body[2], body[3], body[4]]);
+    if config_id <= applied_id {
+        return Err(DownlinkRejection::Replay {
+            counter: config_id,
+            last_accepted: applied_id,
+        });
+    }
+    let config = std::str::from_utf8(&body[BROADCAST_HEADER_LEN..])
+        .map_err(|_| DownlinkRejection::BadSignature)?
+        .to_string();
+    Ok(BroadcastConfig { config_id, config })
+}
+
+/// 受信データが設定更新かどうか（タグの有無は問わない）
+pub fn is_config_update(data: &[u8]) -> bool {
+    data.len() >= CONFIG_UPDATE_HEADER_LEN && data[0] == CONFIG_UPDATE_TYPE
+}
+
+/// デバイスごとの設定更新を検証します
+///
+/// 鍵を設定している場合は署名付きスリープコマンドと同じく自デバイスのMACに対するタグと
+/// カウンタを確認し、鍵がない場合は署名なしの形式のみ受理します。
+///
+/// # 引数
+/// * `key` - 認証鍵（未設定はNone）
+/// * `own_mac` - 自デバイスのMAC
+/// * `last_accepted` - 最後に受理したカウンタ（未受理は0）
+pub fn verify_config_update(
+    data: &[u8],
+    key: Option<&DownlinkKey>,
+    own_mac: &[u8; 6],
+    last_accepted: u32,
+) -> Result<ConfigUpdate, DownlinkRejection> {
+    if !is_config_update(data) {
+        return Err(DownlinkRejection::BadSignature);
+    }
+    let body_len = CONFIG_UPDATE_HEADER_LEN + data[5] as usize;
+    let counter = u32::from_le_bytes([data[1], data[2], data[3], data[4]]);
+    if let Some(key) = key {
+        if data.len() != body_len + TAG_LEN {
+            return Err(DownlinkRejection::BadSignature);
+        }
+        let (body, tag) = data.split_at(body_len);
+        let expected = key.tag(own_mac, body);
+        if !tag_matches(&expected, tag) {
+            return Err(DownlinkRejection::BadSignature);
+        }
+        if counter <= last_accepted {
+            return Err(DownlinkRejection::Replay {
+                counter,
+                last_accepted,
+            });
+        }
+    } else if data.len() != body_len {
+        return Err(DownlinkRejection::BadSignature);
+    }
+    let config = std::str::from_utf8(&data[CONFIG_UPDATE_HEADER_LEN..body_len])
+        .map_err(|_| DownlinkRejection::BadSignature)?
+        .to_string();
+    Ok(ConfigUpdate { counter, config })
+}
+
+/// 受信データがファイルの断片かどうか（タグの有無は問わない）
+pub fn is_file_chunk(data: &[u8]) -> bool {
+    data.len() >= FILE_CHUNK_HEADER_LEN && data[0] == FILE_CHUNK_TYPE
+}
+
+/// ファイルの断片を検証します
+///
+/// 設定更新と同じく、鍵を設定している場合は自デバイスのMACに対するタグとカウンタを確認し、
+/// 鍵がない場合は署名なしの形式のみ受理します。
+///
+/// # 引数
+/// * `key` - 認証鍵（未設定はNone）
+/// * `own_mac` - 自デバイスのMAC
+/// * `last_accepted` - 最後に受理したカウンタ（未受理は0）
+pub fn verify_file_chunk(
+    data: &[u8],
+    key: Option<&DownlinkKey>,
+    own_mac: &[u8; 6],
+    last_accepted: u32,
+) -> Result<FileChunk, DownlinkRejection> {
+    if !is_file_chunk(data) {
+        return Err(DownlinkRejection::BadSignature);
+    }
+    let slot_len = data[13] as usize;
+    let body_len = FILE_CHUNK_HEADER_LEN + slot_len + data[14] as usize;
+    let counter = command_counter(data);
+    if let Some(key) = key {
+        if data.len() != body_len + TAG_LEN {
+            return Err(DownlinkRejection::BadSignature);
+        }
+        let (body, tag) = data.split_at(body_len);
+        let expected = key.tag(own_mac, body);
+        if !tag_matches(&expected, tag) {
+            return Err(DownlinkRejection::BadSignature);
+        }
+        if counter <= last_accepted {
+            return Err(DownlinkRejection::Replay {
+                counter,
+                last_accepted,
+            });
+        }
+    } else if data.len() != body_len {
+        return Err(DownlinkRejection::BadSignature);
+    }
+    let slot_end = FILE_CHUNK_HEADER_LEN + slot_len;
+    let slot = std::str::from_utf8(&data[FILE_CHUNK_HEADER_LEN..slot_end])
+        .map_err(|_| DownlinkRejection::BadSignature)?
+        .to_string();
+    Ok(FileChunk {
+        counter,
+        slot,
+        file_crc: u32::from_le_bytes([data[5], data[6], data[7], data[8]]),
+        total_len: u16::from_le_bytes([data[9], data[10]]),
+        offset: u16::from_le_bytes([data[11], data[12]]),
+        data: data[slot_end..body_len].to_vec(),
+    })
+}
+
+/// 受信データが鍵更新の形式かどうか（タグは確認しない）
+pub fn is_key_rotation(data: &[u8]) -> bool {
+    data.len() == KEY_ROTATION_LEN && data[0] == KEY_ROTATION_TYPE
+}
+
+/// 鍵更新を現在の鍵で検証し、新しい鍵を復号します
+///
+/// # 引数
+/// * `key` - 現在の鍵（切り替え前の更新待ちの鍵ではない）
+/// * `own_mac` - 自デバイスのMAC
+/// * `last_accepted` - 最後に受理したカウンタ（未受理は0）
+pub fn verify_key_rotation(
+    data: &[u8],
+    key: &DownlinkKey,
+    own_mac: &[u8; 6],
+    last_accepted: u32,
+) -> Result<KeyRotation, DownlinkRejection> {
+    if !is_key_rotation(data) {
+        return Err(DownlinkRejection::BadSignature);
+    }
+    let (body, tag) = data.split_at(KEY_ROTATION_BODY_LEN);
+    let expected = key.tag(own_mac, body);
+    if !tag_matches(&expected, tag) {
+        return Err(DownlinkRejection::BadSignature);
+    }
+    let counter = command_counter(body);
+    if counter <= last_accepted {
+        return Err(DownlinkRejection::Replay {
+            counter,
+            last_accepted,
+        });
+    }
+    let epoch = u32::from_le_bytes([body[5], body[6], body[7], body[8]]);
+    let activate_counter = u32::from_le_bytes([body[9], body[10], body[11], body[12]]);
+    // 世代0はプロビジョニングした鍵のため、更新先にはならない
+    if epoch == 0 || activate_counter <= counter {
+        return Err(DownlinkRejection::BadSignature);
+    }
+    Ok(KeyRotation {
+        counter,
+        epoch,
+        activate_counter,
+        new_key: key.unwrap_key(own_mac, counter, &body[13..]),
+    })
+}
+
+/// 署名付きメッセージのコマンドカウンタ（タイプの直後の4バイト）。検証前の鍵の選択に使う
+pub fn command_counter(data: &[u8]) -> u32 {
+    match data.get(1..5) {
+        Some(bytes) => u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+        None => 0,
+    }
+}
+
+/// 切り替え待ちの新しい鍵
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct PendingKey {
+    epoch: u32,
+    key: DownlinkKey,
+    activate_counter: u32,
+}
+
+/// デバイスのダウンリンク認証鍵（現在の鍵と切り替え待ちの鍵）
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceKeys {
+    epoch: u32,
+    current: DownlinkKey,
+    pending: Option<PendingKey>,
+}
+
+impl DeviceKeys {
+    /// プロビジョニングした鍵（世代0）から始めます
+    pub fn new(provisioned: DownlinkKey) -> Self {
+        Self {
+            epoch: 0,
+            current: provisioned,
+            pending: None,
+        }
+    }
+
+    /// NVSの記録から復元します（記録がない・壊れている場合はプロビジョニングした鍵）
+    pub fn restore(provisioned: DownlinkKey, record: Option<&[u8]>) -> Self {
+        let Some(record) = record.filter(|record| record.len() == DEVICE_KEY_RECORD_LEN) else {
+            return Self::new(provisioned);
+        };
+        let read_u32 = |offset: usize| {
+            u32::from_le_bytes([record[offset], record[offset + 1], record[offset + 2], record[offset + 3]])
+        };
+        let read_key = |offset: usize| {
+            let mut key = [0u8; DOWNLINK_KEY_LEN];
+            key.copy_from_slice(&record[offset..offset + DOWNLINK_KEY_LEN]);
+            DownlinkKey(key)
+        };
+        let epoch = read_u32(0);
+        let pending_offset = 4 + DOWNLINK_KEY_LEN;
+        let pending_epoch = read_u32(pending_offset);
+        Self {
+            epoch,
+            current: if epoch == 0 { provisioned } else { read_key(4) },
+            pending: (pending_epoch > epoch).then(|| PendingKey {
+                epoch: pending_epoch,
+                key: read_key(pending_offset + 4),
+                activate_counter: read_u32(pending_offset + 4 + DOWNLINK_KEY_LEN),
+            }),
+        }
+    }
+
+    /// NVSに保存する記録
+    pub fn to_record(&self) -> [u8; DEVICE_KEY_RECORD_LEN] {
+        let mut record = [0u8; DEVICE_KEY_RECORD_LEN];
+        record[..4].copy_from_slice(&self.epoch.to_le_bytes());
+        if self.epoch > 0 {
+            record[4..4 + DOWNLINK_KEY_LEN].copy_from_slice(&self.current.0);
+        }
+        if let Some(pending) = &self.pending {
+            let offset = 4 + DOWNLINK_KEY_LEN;
+            record[offset..offset + 4].copy_from_slice(&pending.epoch.to_le_bytes());
+            record[offset + 4..offset + 4 + DOWNLINK_KEY_LEN].copy_from_slice(&pending.key.0);
+            record[DEVICE_KEY_RECORD_LEN - 4..].copy_from_slice(&pending.activate_counter.to_le_bytes());
+        }
+        record
+    }
+
+    /// 現在の鍵の世代
+    pub fn epoch(&self) -> u32 {
+        self.epoch
+    }
+
+    /// 現在の鍵（鍵更新の検証に使う）
+    pub fn current(&self) -> &DownlinkKey {
+        &self.current
+    }
+
+    /// 指定したカウンタのコマンドを検証する鍵
+    pub fn key_for(&self, counter: u32) -> &DownlinkKey {
+        match &self.pending {
+            Some(pending) if counter >= pending.activate_counter => &pending.key,
+            _ => &self.current,
+        }
+    }
+
+    /// 新しい鍵を切り替え待ちにします（同じ世代の送り直しは上書き）
+    ///
+    /// # 戻り値
+    /// * 記録を更新した場合はtrue（古い世代の鍵更新は無視する）
+    pub fn stage(&mut self, rotation: KeyRotation) -> bool {
+        if rotation.epoch <= self.epoch {
+            return false;
+        }
+        self.pending = Some(PendingKey {
+            epoch: rotation.epoch,
+            key: rotation.new_key,
+            activate_counter: rotation.activate_counter,
+        });
+        true
+    }
+
+    /// 受理したコマンドのカウンタが切り替えカウンタ以上なら新しい鍵を現在の鍵にします
+    ///
+    /// # 戻り値
+    /// * 切り替えた場合はtrue
+    pub fn accept(&mut self, counter: u32) -> bool {
+        match self.pending.take() {
+            Some(pending) if counter >= pending.activate_counter => {
+                self.epoch = pending.epoch;
+                self.current = pending.key;
+                true
+            }
+            pending => {
+                self.pending = pending;
+                false
+            }
+        }
+    }
+
+    /// HASHフレームに付加する鍵の世代
+    pub fn metadata_fields(&self) -> String {
+        match &self.pending {
+            Some(pending) => format!(",KEY_EPOCH:{},KEY_PENDING:{}", self.epoch, pending.epoch),
+            None => format!(",KEY_EPOCH:{}", self.epoch),
+        }
+    }
+}
+
+/// HASHフレームに付加するセキュリティ警告（拒否がなければ空文字列）
+pub fn security_metadata_fields(replays: u32, bad_signatures: u32) -> String {
+    let mut fields = String::new();
+    if replays > 0 {
+        fields.push_str(&format!(",SEC_REPLAY:{}", replays));
+    }
+    if bad_signatures > 0 {
+        fields.push_str(&format!(",SEC_BAD_SIG:{}", bad_signatures));
+    }
+    fields
+}
@@ -0,0 +1,243 @@
+//! 画像チャンクの前方誤り訂正（XORパリティ）
+//!
+//! K個のデータチャンクごとにM個のパリティチャンクを付加します。
+//! パリティjはグループ内で `index % M == j` のチャンクをXORしたもので、
+//! 各パリティの担当チャンクが1つだけ欠落していれば再送なしで復元できます
+//! （連続したM個までのバースト欠落に対応）。
+//!
+//! ワイヤフォーマット（`FrameType::Fec` のペイロード）:
+//! - 告知: `[FEC_KIND_ANNOUNCE, K, M]` 画像チャンク送信前に1回
+//! - パリティ: `[FEC_KIND_PARITY, first_seq(u32 LE), data_count, parity_index,
+//!   parity_count, length_xor(u16 LE), parity...]`
+//!
+//! グループのデータチャンクは `first_seq` から連続したシーケンス番号を持ち、
+//! 直後にパリティチャンクが続きます。
+
+/// セッション告知ペイロードの種別
+pub const FEC_KIND_ANNOUNCE: u8 = 0;
+/// パリティペイロードの種別
+pub const FEC_KIND_PARITY: u8 = 1;
+/// 告知ペイロード長
+pub const FEC_ANNOUNCE_LEN: usize = 3;
+/// パリティペイロードのヘッダー長
+pub const FEC_PARITY_HEADER_LEN: usize = 1 + 4 + 1 + 1 + 1 + 2;
+/// 1グループあたりのパリティ数上限
+pub const MAX_PARITY_PER_GROUP: u8 = 4;
+
+/// FECに関するエラー
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+pub enum FecError {
+    #[error("FECペイロードが短すぎます: {0}バイト")]
+    TooShort(usize),
+
+    #[error("不明なFECペイロード種別です: {0}")]
+    UnknownKind(u8),
+
+    #[error("無効なFECパラメータです: K={data_chunks}, M={parity_chunks}")]
+    InvalidParams { data_chunks: u8, parity_chunks: u8 },
+}
+
+/// セッション単位のFECパラメータ
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FecParams {
+    /// グループあたりのデータチャンク数（K）
+    pub data_chunks: u8,
+    /// グループあたりのパリティチャンク数（M）
+    pub parity_chunks: u8,
+}
+
+impl FecParams {
+    /// パラメータを検証して作成します（1 <= M <= min(K, MAX_PARITY_PER_GROUP)）
+    pub fn new(data_chunks: u8, parity_chunks: u8) -> Result<Self, FecError> {
+        if data_chunks == 0
+            || parity_chunks == 0
+            || parity_chunks > data_chunks
+            || parity_chunks > MAX_PARITY_PER_GROUP
+        {
+            return Err(FecError::InvalidParams {
+                data_chunks,
+                parity_chunks,
+            });
+        }
+        Ok(Self {
+            data_chunks,
+            parity_chunks,
+        })
+    }
+
+    /// 告知ペイロードを生成します
+    pub fn to_announce_payload(self) -> [u8; FEC_ANNOUNCE_LEN] {
+        [FEC_KIND_ANNOUNCE, self.data_chunks, self.parity_chunks]
+    }
+
+    /// 告知ペイロードを解析します
+    pub fn from_announce_payload(payload: &[u8]) -> Result<Self, FecError> {
+        if payload.len() < FEC_ANNOUNCE_LEN {
+            return Err(FecError::TooShort(payload.len()));
+        }
+        if payload[0] != FEC_KIND_ANNOUNCE {
+            return Err(FecError::UnknownKind(payload[0]));
+        }
+        Self::new(payload[1], payload[2])
+    }
+}
+
+/// 観測した損失率からセッションのFECパラメータを決定します
+///
+/// `group_size` または `max_parity` が0の場合、もしくは損失が観測されていない場合はFECを使いません。
+/// パリティ数はグループ内の期待損失数の2倍（切り上げ）を `max_parity` で頭打ちにします。
+pub fn select_fec_params(group_size: u8, max_parity: u8, loss_percent: u8) -> Option<FecParams> {
+    if group_size == 0 || max_parity == 0 || loss_percent == 0 {
+        return None;
+    }
+    let loss_percent = loss_percent.min(100) as u32;
+    let expected_losses_x2 = group_size as u32 * loss_percent * 2;
+    let parity = expected_losses_x2.div_ceil(100).max(1);
+    let parity = parity
+        .min(max_parity as u32)
+        .min(MAX_PARITY_PER_GROUP as u32)
+        .min(group_size as u32) as u8;
+    FecParams::new(group_size, parity).ok()
+}
+
+/// パリティチャンク
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParityChunk {
+    /// グループ先頭データチャンクのシーケンス番号
+    pub first_seq: u32,
+    /// グループ内のデータチャンク数（最終グループはK未満）
+    pub data_count: u8,
+    /// このパリティの番号（0..parity_count）
+    pub parity_index: u8,
+    /// グループ内のパリティ数
+    pub parity_count: u8,
+    /// 担当チャンク長のXOR（復元チャンクの長さ算出用）
+    pub length_xor: u16,
+    /// 担当チャンクのXOR（最長チャンク長、短いチャンクは0埋め）
+    pub parity: Vec<u8>,
+}
+
+impl ParityChunk {
+    /// ペイロードへエンコードします
+    pub fn to_payload(&self) -> Vec<u8> {
+        let mut payload = Vec::with_capacity(FEC_PARITY_HEADER_LEN + self.parity.len());
+        payload.push(FEC_KIND_PARITY);
+        payload.extend_from_slice(&self.first_seq.to_le_bytes());
+        payload.push(self.data_count);
+        payload.push(self.parity_index);
+        payload.push(self.parity_count);
+        payload.extend_from_slice(&self.length_xor.to_le_bytes());
+        payload.extend_from_slice(&self.parity);
+        payload
+    }
+
+    /// ペイロードからデコードします
+    pub fn from_payload(payload: &[u8]) -> Result<Self, FecError> {
+        if payload.len() < FEC_PARITY_HEADER_LEN {
+            return Err(FecError::TooShort(payload.len()));
+        }
+        if payload[0] != FEC_KIND_PARITY {
+            return Err(FecError::UnknownKind(payload[0]));
+        }
+        Ok(Self {
+            first_seq: u32::from_le_bytes([payload[1], payload[2], payload[3], payload[4]]),
+            data_count: payload[5],
+            parity_index: payload[6],
+            parity_count: payload[7],
+            length_xor: u16::from_le_bytes([payload[8], payload[9]]),
+            parity: payload[FEC_PARITY_HEADER_LEN..].to_vec(),
+        })
+    }
+
+    /// このパリティが担当するグループ内インデックスかどうか
+    pub fn covers(&self, index: usize) -> bool {
+        self.parity_count > 0 && index % self.parity_count as usize == self.parity_index as usize
+    }
+}
+
+/// 1グループ分のデータチャンクからパリティチャンクを生成します
+///
+/// # 引数
+/// * `first_seq` - グループ先頭データチャンクのシーケンス番号
+/// * `chunks` - グループのデータチャンク（1〜255個）
+/// * `parity_count` - 生成するパリティ数
+pub fn encode_group_parity(first_seq: u32, chunks: &[&[u8]], parity_count: u8) -> Vec<ParityChunk> {
+    let data_count = chunks.len().min(u8::MAX as usize) as u8;
+    let parity_count = parity_count.min(data_count);
+
+    (0..parity_count)
+        .map(|parity_index| {
+            let mut parity = ParityChunk {
+                first_seq,
+                data_count,
+                parity_index,
+                parity_count,
+                length_xor: 0,
+                parity: Vec::new(),
+            };
+            for (index, chunk) in chunks.iter().enumerate().take(data_count as usize) {
+                if parity.covers(index) {
+                    parity.length_xor ^= chunk.len() as u16;
+                    xor_into(&mut parity.parity, chunk);
+                }
+            }
+            parity
+        })
+        .collect()
+}
+
+/// 欠落チャンクをパリティから復元します
+///
+/// # 引数
+/// * `chunks` - グループのデータチャンク（欠落は `None`）
+/// * `parities` - 受信できたパリティチャンク
+///
+/// # 戻り値
+/// 復元したチャンク数
+pub fn recover_group(chunks: &mut [Option<Vec<u8>>], parities: &[ParityChunk]) -> usize {
+    let mut recovered = 0;
+
+    for parity in parities {
+        let mut missing = None;
+        let mut missing_count = 0;
+        for (index, chunk) in chunks.iter().enumerate() {
+            if parity.covers(index) && chunk.is_none() {
+                missing = Some(index);
+                missing_count += 1;
+            }
+        }
+
+        // 担当チャンクの欠落がちょうど1つのときのみ復元可能
+        let Some(missing_index) = missing else {
+            continue;
+        };
+        if missing_count != 1 {
+            continue;
+        }
+
+        let mut data = parity.parity.clone();
+        let mut length = parity.length_xor;
+        for (index, chunk) in chunks.iter().enumerate() {
+            if let Some(chunk) = chunk {
+                if parity.covers(index) {
+                    length ^= chunk.len() as u16;
+                    xor_into(&mut data, chunk);
+                }
+            }
+        }
+        data.truncate(length as usize);
+        chunks[missing_index] = Some(data);
+        recovered += 1;
+    }
+
+    recovered
+}
+
+fn xor_into(acc: &mut Vec<u8>, chunk: &[u8]) {
+    if acc.len() < chunk.len() {
+        acc.resize(chunk.len(), 0);
+    }
+    for (a, b) in acc.iter_mut().zip(chunk.iter()) {
+        *a ^= b;
+    }
+}
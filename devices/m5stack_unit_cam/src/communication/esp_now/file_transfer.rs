@@ -0,0 +1,178 @@
+//! ゲートウェイからのファイル転送（校正テーブルなどの資産）の組み立て
+//!
+//! ゲートウェイは転送後のコマンド待機中にファイルを断片に分けて先頭から順に送ります。
+//! 受信済みの位置の続きの断片だけを受け取り、全体が揃ったらファイル全体のCRC-32を確認します。
+//! 受信途中の状態はNVSに保存して次回の起動に引き継ぎ、HASHフレームの `FILE_SLOT`・`FILE_OFF`・
+//! `FILE_CRC` で受信済みの位置を報告します。ゲートウェイは報告された位置から送信を再開し、
+//! `FILE_OFF` がファイルの長さに達した報告で転送を終えます。
+
+use super::control_frame::crc32;
+use super::downlink_auth::FileChunk;
+
+/// 受け取るファイルの最大長（ゲートウェイの `MAX_FILE_LEN` と同じ）
+pub const MAX_FILE_LEN: usize = 4096;
+/// スロット名の最大長（NVSキーの上限15文字に収まる長さ）
+pub const MAX_FILE_SLOT_LEN: usize = 12;
+/// NVSに保存する記録のヘッダ長: [FILE_CRC(4)] [TOTAL_LEN(2)] [RECEIVED(2)] [SLOT_LEN(1)]
+const RECORD_HEADER_LEN: usize = 9;
+
+/// 断片を受け取った結果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChunkOutcome {
+    /// 受信済みの位置の続きとして追加した
+    Appended { received: usize },
+    /// 全体が揃い、CRC-32が一致した
+    Complete,
+    /// 受信済みの断片（再送）
+    Duplicate,
+    /// 受信済みの位置と一致しない（途中の断片が届かなかった）
+    OutOfOrder { expected: usize },
+    /// 全体が揃ったがCRC-32が一致しなかった（受信した内容は破棄）
+    CrcMismatch,
+    /// スロット名・長さが不正
+    Invalid,
+}
+
+/// 受信中（または受信を終えた）ファイル
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FileAssembly {
+    slot: String,
+    file_crc: u32,
+    total_len: usize,
+    received: usize,
+    /// 受信途中の内容（受信を終えたファイルは保存後に空にする）
+    data: Vec<u8>,
+}
+
+/// スロット名として使えるか（英数字と `_`）
+pub fn is_valid_slot(slot: &str) -> bool {
+    (1..=MAX_FILE_SLOT_LEN).contains(&slot.len())
+        && slot.bytes().all(|byte| byte.is_ascii_alphanumeric() || byte == b'_')
+}
+
+impl FileAssembly {
+    /// 受信中のファイルがあるか
+    pub fn is_active(&self) -> bool {
+        !self.slot.is_empty()
+    }
+
+    /// 全体を受信してCRC-32を確認したか
+    pub fn is_complete(&self) -> bool {
+        self.is_active() && self.received == self.total_len
+    }
+
+    /// 保存先のスロット名
+    pub fn slot(&self) -> &str {
+        &self.slot
+    }
+
+    /// 受信した内容（受信を終えたファイルは保存するまで全体を保持）
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// 断片を受け取ります
+    ///
+    /// 別のファイル（スロット名・CRC-32・長さのいずれかが違う）の先頭の断片を受け取った場合は、
+    /// 受信中のファイルを破棄して新しいファイルの受信を始めます。
+    pub fn accept(&mut self, chunk: &FileChunk) -> ChunkOutcome {
+        let total_len = chunk.total_len as usize;
+        if !is_valid_slot(&chunk.slot) || total_len == 0 || total_len > MAX_FILE_LEN {
+            return ChunkOutcome::Invalid;
+        }
+        let same_file = self.slot == chunk.slot && self.file_crc == chunk.file_crc && self.total_len == total_len;
+        if !same_file {
+            if chunk.offset != 0 {
+                return ChunkOutcome::OutOfOrder { expected: 0 };
+            }
+            *self = Self {
+                slot: chunk.slot.clone(),
+                file_crc: chunk.file_crc,
+                total_len,
+                received: 0,
+                data: Vec::with_capacity(total_len),
+            };
+        }
+
+        let offset = chunk.offset as usize;
+        let end = offset + chunk.data.len();
+        if end > self.total_len {
+            return ChunkOutcome::Invalid;
+        }
+        if end <= self.received {
+            return ChunkOutcome::Duplicate;
+        }
+        if offset != self.received {
+            return ChunkOutcome::OutOfOrder { expected: self.received };
+        }
+        self.data.extend_from_slice(&chunk.data);
+        self.received = end;
+        if self.received < self.total_len {
+            return ChunkOutcome::Appended { received: self.received };
+        }
+        if crc32(&self.data) != self.file_crc {
+            self.data.clear();
+            self.received = 0;
+            return ChunkOutcome::CrcMismatch;
+        }
+        ChunkOutcome::Complete
+    }
+
+    /// 受信を終えたファイルの内容を取り出します（報告用に受信済みの位置は残す）
+    pub fn take_completed(&mut self) -> Option<Vec<u8>> {
+        (self.is_complete() && !self.data.is_empty()).then(|| std::mem::take(&mut self.data))
+    }
+
+    /// HASHフレームに付加するメタデータ（受信中のファイルがなければ空文字列）
+    pub fn metadata_fields(&self) -> String {
+        if !self.is_active() {
+            return String::new();
+        }
+        format!(
+            ",FILE_SLOT:{},FILE_OFF:{},FILE_CRC:{:08x}",
+            self.slot, self.received, self.file_crc
+        )
+    }
+
+    /// NVSに保存する記録
+    ///
+    /// `[FILE_CRC(4)] [TOTAL_LEN(2)] [RECEIVED(2)] [SLOT_LEN(1)] [SLOT] [DATA]`
+    /// （受信を終えたファイルは内容を別のキーに保存するため DATA を含めない）
+    pub fn to_record(&self) -> Vec<u8> {
+        let mut record = Vec::with_capacity(RECORD_HEADER_LEN + self.slot.len() + self.data.len());
+        record.extend_from_slice(&self.file_crc.to_le_bytes());
+        record.extend_from_slice(&(self.total_len as u16).to_le_bytes());
+        record.extend_from_slice(&(self.received as u16).to_le_bytes());
+        record.push(self.slot.len() as u8);
+        record.extend_from_slice(self.slot.as_bytes());
+        if !self.is_complete() {
+            record.extend_from_slice(&self.data);
+        }
+        record
+    }
+
+    /// NVSの記録から復元します（壊れた記録はNone）
+    pub fn from_record(record: &[u8]) -> Option<Self> {
+        let header = record.get(..RECORD_HEADER_LEN)?;
+        let file_crc = u32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+        let total_len = u16::from_le_bytes([header[4], header[5]]) as usize;
+        let received = u16::from_le_bytes([header[6], header[7]]) as usize;
+        let slot_end = RECORD_HEADER_LEN + header[8] as usize;
+        let slot = std::str::from_utf8(record.get(RECORD_HEADER_LEN..slot_end)?).ok()?;
+        let data = &record[slot_end..];
+        if !is_valid_slot(slot) || total_len > MAX_FILE_LEN || received > total_len {
+            return None;
+        }
+        let complete = received == total_len;
+        if (complete && !data.is_empty()) || (!complete && data.len() != received) {
+            return None;
+        }
+        Some(Self {
+            slot: slot.to_string(),
+            file_crc,
+            total_len,
+            received,
+            data: data.to_vec(),
+        })
+    }
+}
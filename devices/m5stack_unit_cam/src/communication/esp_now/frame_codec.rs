@@ -1,6 +1,54 @@
+use crate::units::{Celsius, Millivolts, Percent};
+
+use super::telemetry::{
+    telemetry_header_fields, TELEMETRY_CAP_PASSTHROUGH, TELEMETRY_CAP_TDS, TELEMETRY_CAP_TEMPERATURE,
+};
+
 pub const START_MARKER: [u8; 4] = [0xFA, 0xCE, 0xAA, 0xBB];
 pub const END_MARKER: [u8; 4] = [0xCD, 0xEF, 0x56, 0x78];
 
+/// フレームタイプ（値はゲートウェイの `FrameType` と同じ）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameType {
+    /// 画像ハッシュとセンサー値
+    Hash = 1,
+    /// 画像チャンク
+    Data = 2,
+    /// 転送終了
+    Eof = 3,
+    /// FEC告知/パリティ
+    Fec = 4,
+    /// 転送中断（ゲートウェイはキュー内の残りチャンクを破棄する）
+    Abort = 7,
+    /// プライバシーモードのダミー（ゲートウェイで破棄される）
+    Dummy = 8,
+    /// 緊急通知（画像転送の途中にも割り込んで送信でき、ゲートウェイは溜まったチャンクより先に転送する）
+    Urgent = 10,
+}
+
+impl FrameType {
+    /// デバイスが送信するすべてのフレームタイプ
+    pub const ALL: [FrameType; 7] = [
+        FrameType::Hash,
+        FrameType::Data,
+        FrameType::Eof,
+        FrameType::Fec,
+        FrameType::Abort,
+        FrameType::Dummy,
+        FrameType::Urgent,
+    ];
+
+    /// バイト値からフレームタイプを取得
+    pub fn from_byte(byte: u8) -> Option<Self> {
+        Self::ALL.into_iter().find(|frame_type| frame_type.to_byte() == byte)
+    }
+
+    /// フレームタイプをバイト値に変換
+    pub fn to_byte(self) -> u8 {
+        self as u8
+    }
+}
+
 pub const FRAME_OVERHEAD: usize = 4 + 6 + 1 + 4 + 4 + 4 + 4;
 pub const ESP_NOW_MAX_SIZE: usize = 250;
 
@@ -13,18 +61,42 @@ pub fn payload_size_candidates(initial_chunk_size: usize) -> [usize; 5] {
     [safe_initial_payload_size(initial_chunk_size), 150, 100, 50, 30]
 }
 
+/// 試すペイロード長の順序（ゲートウェイが大きさを通知していればその大きさのみ）
+///
+/// 通知された推奨の大きさと設定の初期チャンクサイズの小さい方を使い、通知がなければ
+/// `payload_size_candidates` の順に小さくして試します。
+pub fn payload_size_plan(initial_chunk_size: usize, advertised: Option<usize>) -> Vec<usize> {
+    match advertised {
+        Some(preferred) => vec![safe_initial_payload_size(initial_chunk_size.min(preferred))],
+        None => payload_size_candidates(initial_chunk_size).to_vec(),
+    }
+}
+
+/// HASHペイロードを組み立てます（`TDS_VOLT` はボルト単位で書きます）
 pub fn build_hash_payload(
     hash: &str,
-    voltage_percentage: u8,
-    temperature_celsius: Option<f32>,
-    tds_voltage: Option<f32>,
+    voltage_percentage: Percent,
+    temperature: Option<Celsius>,
+    tds_voltage: Option<Millivolts>,
     timestamp: &str,
 ) -> String {
-    let temp_data = temperature_celsius.unwrap_or(-999.0);
-    let tds_data = tds_voltage.unwrap_or(-999.0);
+    let temp_data = temperature.map_or(-999.0, Celsius::value);
+    let tds_data = tds_voltage.map_or(-999.0, Millivolts::as_volts);
+    let mut capabilities = TELEMETRY_CAP_PASSTHROUGH;
+    if temperature.is_some() {
+        capabilities |= TELEMETRY_CAP_TEMPERATURE;
+    }
+    if tds_voltage.is_some() {
+        capabilities |= TELEMETRY_CAP_TDS;
+    }
     format!(
-        "HASH:{},VOLT:{},TEMP:{:.1},TDS_VOLT:{:.1},{}",
-        hash, voltage_percentage, temp_data, tds_data, timestamp
+        "HASH:{},VOLT:{},TEMP:{:.1},TDS_VOLT:{:.1},{}{}",
+        hash,
+        voltage_percentage.value(),
+        temp_data,
+        tds_data,
+        timestamp,
+        telemetry_header_fields(capabilities)
     )
 }
 
@@ -41,7 +113,7 @@ pub fn calculate_xor_checksum(data: &[u8]) -> u32 {
 }
 
 pub fn build_sensor_data_frame(
-    frame_type: u8,
+    frame_type: FrameType,
     mac_address: [u8; 6],
     sequence: u32,
     data: &[u8],
@@ -50,7 +122,7 @@ pub fn build_sensor_data_frame(
 
     frame.extend_from_slice(&START_MARKER);
     frame.extend_from_slice(&mac_address);
-    frame.push(frame_type);
+    frame.push(frame_type.to_byte());
     frame.extend_from_slice(&sequence.to_le_bytes());
 
     let data_len = data.len() as u32;
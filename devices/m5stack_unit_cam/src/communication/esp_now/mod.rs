@@ -8,9 +8,53 @@ pub mod frame;
 pub mod frame_codec;
 /// 送信リトライポリシー
 pub mod retry_policy;
+/// 画像チャンクの前方誤り訂正
+pub mod fec;
+/// 転送前のゲートウェイ疎通確認
+pub mod probe;
+/// 転送後のコマンド待機の短縮
+pub mod command_window;
+/// ゲートウェイの受信チャンネルの時間分割
+pub mod channel_hop;
+/// ESP-NOWの送信レート名（ゲートウェイ・XIAOと共有）
+pub mod phy_rate;
+/// ESP-NOWの無線設定（送信レート・送信パワー・AMPDU）
+pub mod radio;
+/// ゲートウェイからの制御メッセージの認証
+pub mod downlink_auth;
+/// ゲートウェイからの制御フレーム
+pub mod control_frame;
+/// ゲートウェイからのファイル転送
+pub mod file_transfer;
+/// チャンク長と送信タイミングの秘匿
+pub mod privacy;
+/// 圏外のカメラの中継
+pub mod relay;
+/// 送信フレームへの故障注入
+pub mod chaos;
+/// 送信完了コールバックに合わせたチャンクの送信間隔
+pub mod pacing;
+/// 送信完了コールバックまでの往復時間の推定
+pub mod rtt;
+/// HASHペイロードのテレメトリ
+pub mod telemetry;
 
 pub use sender::*;
 pub use receiver::*;
 pub use frame::*;
 pub use frame_codec::*;
 pub use retry_policy::*;
+pub use fec::*;
+pub use probe::*;
+pub use command_window::*;
+pub use channel_hop::*;
+pub use radio::*;
+pub use downlink_auth::*;
+pub use control_frame::*;
+pub use file_transfer::*;
+pub use privacy::*;
+pub use relay::*;
+pub use chaos::*;
+pub use pacing::*;
+pub use rtt::*;
+pub use telemetry::*;
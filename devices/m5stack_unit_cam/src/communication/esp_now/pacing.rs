@@ -0,0 +1,217 @@
+//! 送信完了コールバックに合わせた画像チャンクの送信間隔
+//!
+//! 固定の待機時間では、良好なリンクでは待ちすぎ、悪いリンクでは詰め込みすぎになります。
+//! そこでチャンクごとにESP-NOWの送信完了コールバックを待ち、成功なら最小間隔だけ空けて
+//! 次のチャンクを送り、失敗（ゲートウェイからのACKなし）が続くほど間隔を倍に延ばします。
+//! 時間内にコールバックが返らない場合は、リンクの状態が分からないため失敗と同じく扱います。
+//! コールバックを待つ時間は、測定した往復時間から決めます（`rtt`）。
+//!
+//! チャンク間の待機は既定ではFreeRTOSの遅延（CPUは全速のまま）ですが、`PacingMode::LightSleep` では
+//! 送信完了を確認できた後の十分に長い待機をlight sleepに置き換え、長い転送中の消費電流を下げます。
+
+use super::rtt::RttEstimator;
+
+/// 失敗後の最初の待機時間（ミリ秒、最小間隔の方が長ければ最小間隔）
+pub const BACKOFF_BASE_MS: u32 = 20;
+
+/// light sleepで待つ最短の待機時間（ミリ秒）。これより短い待機は起床とRFの再開に見合わない
+pub const LIGHT_SLEEP_MIN_GAP_MS: u32 = 3;
+
+/// light sleepからの起床とRFの再開に見込む時間（ミリ秒、待機時間の残りは通常の遅延で待つ）
+pub const LIGHT_SLEEP_WAKE_MARGIN_MS: u32 = 1;
+
+/// チャンク間の待ち方
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PacingMode {
+    /// FreeRTOSの遅延で待つ
+    #[default]
+    Delay,
+    /// 送信完了を確認できた十分に長い待機はlight sleepで待つ
+    LightSleep,
+}
+
+impl PacingMode {
+    /// 設定値（`delay` / `light_sleep`）を解釈します
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "delay" => Some(Self::Delay),
+            "light_sleep" => Some(Self::LightSleep),
+            _ => None,
+        }
+    }
+}
+
+/// 送信間隔の設定（最小間隔はチャンク間遅延の設定を使う）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PacingParams {
+    /// 失敗が続いたときの待機時間の上限（ミリ秒）
+    pub max_backoff_ms: u32,
+    /// 送信完了コールバックを待つ時間（ミリ秒、往復時間を測定するまでの値）
+    pub callback_timeout_ms: u32,
+    /// チャンク間の待ち方
+    pub mode: PacingMode,
+}
+
+impl Default for PacingParams {
+    fn default() -> Self {
+        Self {
+            max_backoff_ms: 500,
+            callback_timeout_ms: 50,
+            mode: PacingMode::Delay,
+        }
+    }
+}
+
+/// チャンク間の待機の内訳
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GapWait {
+    /// light sleepで待つ時間（ミリ秒）
+    pub sleep_ms: u32,
+    /// 通常の遅延で待つ時間（ミリ秒、起床の見込み時間を含む）
+    pub delay_ms: u32,
+}
+
+/// チャンクの送信完了コールバックの結果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SendOutcome {
+    /// ゲートウェイが受信した
+    Delivered,
+    /// ゲートウェイからのACKがなかった
+    Failed,
+    /// 時間内にコールバックが返らなかった
+    NoCallback,
+}
+
+/// 転送中の送信完了コールバックの集計
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PacingStats {
+    pub delivered: u32,
+    pub failed: u32,
+    pub no_callback: u32,
+    /// チャンク間で待った時間の合計（ミリ秒）
+    pub waited_ms: u32,
+    /// そのうちlight sleepで待った時間の合計（ミリ秒）
+    pub slept_ms: u32,
+}
+
+impl PacingStats {
+    /// ログ用の要約
+    pub fn summary(&self) -> String {
+        format!(
+            "成功={}, 失敗={}, コールバックなし={}, 待機合計={}ms (light sleep {}ms)",
+            self.delivered, self.failed, self.no_callback, self.waited_ms, self.slept_ms
+        )
+    }
+}
+
+/// 送信完了コールバックの結果から次のチャンクまでの待機時間を決めます
+#[derive(Debug, Clone)]
+pub struct ChunkPacer {
+    min_gap_ms: u32,
+    params: PacingParams,
+    consecutive_failures: u8,
+    stats: PacingStats,
+    rtt: RttEstimator,
+}
+
+impl ChunkPacer {
+    /// `min_gap_ms` は成功時に空ける最小間隔（ミリ秒）
+    pub fn new(min_gap_ms: u32, params: PacingParams) -> Self {
+        Self {
+            min_gap_ms,
+            params,
+            consecutive_failures: 0,
+            stats: PacingStats::default(),
+            rtt: RttEstimator::new(),
+        }
+    }
+
+    /// 前回までの往復時間の推定値から始めます
+    pub fn with_rtt(mut self, rtt: RttEstimator) -> Self {
+        self.rtt = rtt;
+        self
+    }
+
+    /// コールバックを待つ時間（ミリ秒）
+    pub fn callback_timeout_ms(&self) -> u32 {
+        self.rtt.timeout_ms(self.params.callback_timeout_ms)
+    }
+
+    /// 1フレームだけを送ったチャンクの、送信からコールバックまでの時間を記録します
+    pub fn record_rtt(&mut self, rtt_us: u32) {
+        self.rtt.record_sample(rtt_us);
+    }
+
+    /// 往復時間の推定値
+    pub fn rtt(&self) -> RttEstimator {
+        self.rtt
+    }
+
+    /// 連続した失敗（コールバックなしを含む）の回数
+    pub fn consecutive_failures(&self) -> u8 {
+        self.consecutive_failures
+    }
+
+    /// チャンクの結果を記録し、次のチャンクまでの待機時間（ミリ秒）を返します
+    pub fn record(&mut self, outcome: SendOutcome) -> u32 {
+        match outcome {
+            SendOutcome::Delivered => {
+                self.stats.delivered = self.stats.delivered.saturating_add(1);
+                self.consecutive_failures = 0;
+            }
+            SendOutcome::Failed => {
+                self.stats.failed = self.stats.failed.saturating_add(1);
+                self.consecutive_failures = self.consecutive_failures.saturating_add(1);
+            }
+            SendOutcome::NoCallback => {
+                self.stats.no_callback = self.stats.no_callback.saturating_add(1);
+                self.consecutive_failures = self.consecutive_failures.saturating_add(1);
+                self.rtt.record_timeout();
+            }
+        }
+        let wait_ms = self.next_gap_ms();
+        self.stats.waited_ms = self.stats.waited_ms.saturating_add(wait_ms);
+        wait_ms
+    }
+
+    /// 現在の状態での次のチャンクまでの待機時間（ミリ秒）
+    ///
+    /// 失敗がなければ最小間隔、失敗が続くたびに倍にして上限で止めます（上限は最小間隔を下回らない）。
+    pub fn next_gap_ms(&self) -> u32 {
+        if self.consecutive_failures == 0 {
+            return self.min_gap_ms;
+        }
+        let shift = u32::from(self.consecutive_failures - 1).min(16);
+        BACKOFF_BASE_MS
+            .max(self.min_gap_ms)
+            .saturating_mul(1 << shift)
+            .min(self.params.max_backoff_ms.max(self.min_gap_ms))
+    }
+
+    /// 待機時間をlight sleepと通常の遅延に振り分けます
+    ///
+    /// light sleepにするのは、`PacingMode::LightSleep` で直前のチャンクの送信完了コールバックが返っていて
+    /// （コールバックなしでは無線がまだ送信中かもしれない）、待機時間が `LIGHT_SLEEP_MIN_GAP_MS` 以上の場合だけです。
+    pub fn plan_gap(&self, gap_ms: u32, outcome: SendOutcome) -> GapWait {
+        let sleep_allowed = self.params.mode == PacingMode::LightSleep
+            && outcome != SendOutcome::NoCallback
+            && gap_ms >= LIGHT_SLEEP_MIN_GAP_MS;
+        if !sleep_allowed {
+            return GapWait { sleep_ms: 0, delay_ms: gap_ms };
+        }
+        GapWait {
+            sleep_ms: gap_ms - LIGHT_SLEEP_WAKE_MARGIN_MS,
+            delay_ms: LIGHT_SLEEP_WAKE_MARGIN_MS,
+        }
+    }
+
+    /// 実際にlight sleepで待った時間を記録します
+    pub fn record_sleep(&mut self, slept_ms: u32) {
+        self.stats.slept_ms = self.stats.slept_ms.saturating_add(slept_ms);
+    }
+
+    /// これまでの集計
+    pub fn stats(&self) -> PacingStats {
+        self.stats
+    }
+}
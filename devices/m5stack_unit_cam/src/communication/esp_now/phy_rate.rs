@@ -0,0 +1,76 @@
+//! ESP-NOWの送信レート名と `wifi_phy_rate_t` の対応
+//!
+//! ゲートウェイ（`server/usb_cdc_receiver`）と XIAO ESP32S3 Sense も `#[path]` でこのファイルを共有し、
+//! 名前とコードの対応を揃えています。
+
+/// 指定可能な送信レート（名前, `wifi_phy_rate_t` の値）
+const PHY_RATES: &[(&str, u8)] = &[
+    ("1M", 0x00),
+    ("2M", 0x01),
+    ("5.5M", 0x02),
+    ("11M", 0x03),
+    ("6M", 0x0B),
+    ("9M", 0x0F),
+    ("12M", 0x0A),
+    ("18M", 0x0E),
+    ("24M", 0x09),
+    ("36M", 0x0D),
+    ("48M", 0x08),
+    ("54M", 0x0C),
+    ("MCS0", 0x10),
+    ("MCS1", 0x11),
+    ("MCS2", 0x12),
+    ("MCS3", 0x13),
+    ("MCS4", 0x14),
+    ("MCS5", 0x15),
+    ("MCS6", 0x16),
+    ("MCS7", 0x17),
+];
+
+/// ESP-NOWの送信レート（`esp_wifi_config_espnow_rate` に渡す値）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EspNowRate(u8);
+
+impl EspNowRate {
+    /// レート名（`"24M"`、`"MCS3"` など、大文字小文字を区別しない）から変換します
+    pub fn parse(name: &str) -> Option<Self> {
+        let name = name.trim();
+        PHY_RATES
+            .iter()
+            .find(|(label, _)| label.eq_ignore_ascii_case(name))
+            .map(|&(_, code)| Self(code))
+    }
+
+    /// `wifi_phy_rate_t` の値から変換します（一覧にない値もそのまま保持します）
+    pub fn from_phy_rate(code: u8) -> Self {
+        Self(code)
+    }
+
+    /// `wifi_phy_rate_t` の値
+    pub fn phy_rate(self) -> u8 {
+        self.0
+    }
+
+    /// レート名（一覧にない値は `"0x.."`）
+    pub fn name(self) -> String {
+        PHY_RATES
+            .iter()
+            .find(|&&(_, code)| code == self.0)
+            .map(|(label, _)| label.to_string())
+            .unwrap_or_else(|| format!("0x{:02X}", self.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_rate_names() {
+        assert_eq!(EspNowRate::parse("24m").map(EspNowRate::phy_rate), Some(0x09));
+        assert_eq!(EspNowRate::parse(" MCS7 ").map(EspNowRate::phy_rate), Some(0x17));
+        assert_eq!(EspNowRate::parse("5.5M").unwrap().name(), "5.5M");
+        assert_eq!(EspNowRate::parse("100M"), None);
+        assert_eq!(EspNowRate::parse(""), None);
+    }
+}
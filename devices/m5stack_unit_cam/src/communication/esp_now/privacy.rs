@@ -0,0 +1,60 @@
+//! プライバシーモード（チャンク長と送信タイミングの秘匿）
+//!
+//! フレーム長と送信間隔からは撮影の有無や画像サイズを推測できるため、有効時はすべての
+//! フレームをESP-NOWの最大長まで詰め、画像チャンクの間に個数と間隔を乱数で決めたダミー
+//! フレームを挟みます。詰め物とダミーフレームはゲートウェイが再組み立ての前に取り除きます。
+//!
+//! 未対応のゲートウェイは詰め物ごとPCへ転送してしまうため、Pongでゲートウェイの受け入れを
+//! 確認できた場合のみ有効にします。
+
+use super::frame_codec::{build_sensor_data_frame, FrameType, ESP_NOW_MAX_SIZE, FRAME_OVERHEAD};
+
+
+/// ダミーフレームのペイロード長（フレーム全体がESP-NOWの最大長になる）
+pub const DUMMY_PAYLOAD_LEN: usize = ESP_NOW_MAX_SIZE - FRAME_OVERHEAD;
+
+/// プライバシーモードの設定
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PrivacyParams {
+    /// チャンクの間に挟むダミーフレームの最大数
+    pub max_dummy_frames: u8,
+    /// ダミーフレームの前に置く待機時間の最大値（ミリ秒）
+    pub max_jitter_ms: u32,
+}
+
+impl PrivacyParams {
+    /// 乱数からチャンクの間に挟むダミーフレーム数を決めます（0〜最大数）
+    pub fn dummy_count(&self, random: u32) -> u8 {
+        (random % (self.max_dummy_frames as u32 + 1)) as u8
+    }
+
+    /// 乱数からダミーフレーム前の待機時間を決めます（0〜最大値）
+    pub fn jitter_ms(&self, random: u32) -> u32 {
+        random % self.max_jitter_ms.saturating_add(1)
+    }
+}
+
+/// フレームをESP-NOWの最大長まで詰めます（詰め物は終了マーカーの後ろに付く）
+pub fn pad_frame(frame: &mut Vec<u8>) {
+    if frame.len() < ESP_NOW_MAX_SIZE {
+        frame.resize(ESP_NOW_MAX_SIZE, 0);
+    }
+}
+
+/// ダミーフレームを作成します
+///
+/// ペイロードは `seed` から生成した擬似乱数で埋め、送信ごとに内容が変わるようにします。
+/// シーケンス番号は画像フレームの連番を消費しないよう、呼び出し側が乱数を渡します。
+pub fn build_dummy_frame(mac_address: [u8; 6], sequence: u32, seed: u32) -> Vec<u8> {
+    // xorshiftは0から抜け出せないため、0は固定値に置き換える
+    let mut state = if seed == 0 { 0x9E37_79B9 } else { seed };
+    let payload: Vec<u8> = (0..DUMMY_PAYLOAD_LEN)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as u8
+        })
+        .collect();
+    build_sensor_data_frame(FrameType::Dummy, mac_address, sequence, &payload)
+}
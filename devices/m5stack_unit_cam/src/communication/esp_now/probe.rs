@@ -0,0 +1,371 @@
+//! 画像転送前のゲートウェイ疎通確認（Ping/Pong）
+//!
+//! ゲートウェイが停止していると数百チャンクの送信で1サイクル分の電力を無駄にするため、
+//! 転送前に小さなPingを送り、Pongが返った場合のみ転送します。
+//! Ping/Pongには双方が適用した無線設定と機能フラグを付加でき、付加のない旧形式とも互換です。
+//! チャンネルホッピングを要求したPingには、ゲートウェイが機能フラグの後に受信チャンネルの予定を付加します。
+//! DATAチャンクの大きさの通知を要求したPingには、ゲートウェイが推奨と上限のペイロード長を予定の前に付加し、
+//! 転送はその大きさで行います（大きさを順に小さくして試す必要がなくなる）。
+//! コマンド待機の短縮を示したPingの後は、ゲートウェイがHASHフレームに応答します（`command_window`）。
+//! 同時転送数が上限に達したゲートウェイはPongの代わりに延期要求を返すため、指示された時間だけ
+//! 待ってから疎通確認をやり直します（延期は試行回数に数えず、待機時間の合計で打ち切ります）。
+//! ゲートウェイがデータを破棄したときの拒否メッセージ（Pingへの応答、または転送中）を受けた場合は、
+//! 転送を打ち切って再試行までの時間が過ぎるまでスリープします。
+
+use super::channel_hop::HopSchedule;
+use super::radio::{RadioSettings, RADIO_SETTINGS_LEN};
+
+/// Pingのメッセージタイプ（ゲートウェイの MessageType::Ping と同じ）
+pub const PING_MESSAGE_TYPE: u8 = 0x05;
+/// Pongのメッセージタイプ
+pub const PONG_MESSAGE_TYPE: u8 = 0x06;
+/// 延期要求のメッセージタイプ（ゲートウェイの MessageType::Defer と同じ）
+pub const DEFER_MESSAGE_TYPE: u8 = 0x0A;
+/// 拒否メッセージのメッセージタイプ（ゲートウェイの MessageType::Reject と同じ）
+pub const REJECT_MESSAGE_TYPE: u8 = 0x0F;
+/// Pingの識別子
+const PING_MAGIC: [u8; 4] = *b"PING";
+/// Pongの識別子
+const PONG_MAGIC: [u8; 4] = *b"PONG";
+/// 延期要求の識別子
+const DEFER_MAGIC: [u8; 4] = *b"BUSY";
+/// 拒否メッセージの識別子
+const REJECT_MAGIC: [u8; 4] = *b"RJCT";
+/// Pingメッセージ長: [TYPE(1)] ["PING"(4)] [NONCE(4, LE)]
+pub const PING_MESSAGE_LEN: usize = 9;
+/// Pongメッセージ長: [TYPE(1)] ["PONG"(4)] [NONCE(4, LE)] [QUEUE_FREE_PERCENT(1)]
+pub const PONG_MESSAGE_LEN: usize = 10;
+/// 延期要求メッセージ長: [TYPE(1)] ["BUSY"(4)] [NONCE(4, LE)] [RETRY_AFTER_MS(4, LE)]
+pub const DEFER_MESSAGE_LEN: usize = 13;
+/// 拒否メッセージ長: [TYPE(1)] ["RJCT"(4)] [REASON(1)] [NONCE(4, LE)] [RETRY_AFTER_MS(4, LE)]
+pub const REJECT_MESSAGE_LEN: usize = 14;
+/// 機能フラグ: プライバシーモード（ゲートウェイの CAPABILITY_PRIVACY と同じ）
+pub const CAPABILITY_PRIVACY: u8 = 0x01;
+/// 機能フラグ: チャンネルホッピング（ゲートウェイの CAPABILITY_CHANNEL_HOP と同じ）
+pub const CAPABILITY_CHANNEL_HOP: u8 = 0x02;
+/// 機能フラグ: コマンド待機の短縮（ゲートウェイの CAPABILITY_COMMAND_WINDOW と同じ）
+pub const CAPABILITY_COMMAND_WINDOW: u8 = 0x04;
+/// 機能フラグ: DATAチャンクの大きさの通知（ゲートウェイの CAPABILITY_CHUNK_SIZE と同じ）
+pub const CAPABILITY_CHUNK_SIZE: u8 = 0x08;
+/// 機能フラグ: HASHフレームへの応答で前回の転送の損失率を受け取る（ゲートウェイの CAPABILITY_LOSS_REPORT と同じ）
+pub const CAPABILITY_LOSS_REPORT: u8 = 0x10;
+/// 機能フラグ: HASHフレームへの応答でゲートウェイの時計を受け取る（ゲートウェイの CAPABILITY_GATEWAY_CLOCK と同じ）
+pub const CAPABILITY_GATEWAY_CLOCK: u8 = 0x20;
+/// DATAチャンクの大きさの通知の長さ: [PREFERRED(2, LE)] [MAX(2, LE)]
+pub const CHUNK_SIZE_ADVERT_LEN: usize = 4;
+
+/// Pingメッセージを生成します（無線設定、機能フラグの順に末尾へ付加）
+#[allow(clippy::too_many_arguments)]
+pub fn encode_ping(
+    nonce: u32,
+    radio: Option<&RadioSettings>,
+    privacy: bool,
+    channel_hop: bool,
+    command_window: bool,
+    chunk_size: bool,
+    loss_report: bool,
+    gateway_clock: bool,
+) -> Vec<u8> {
+    let mut message = Vec::with_capacity(PING_MESSAGE_LEN + RADIO_SETTINGS_LEN + 1);
+    message.push(PING_MESSAGE_TYPE);
+    message.extend_from_slice(&PING_MAGIC);
+    message.extend_from_slice(&nonce.to_le_bytes());
+    if let Some(radio) = radio {
+        message.extend_from_slice(&radio.to_wire());
+    }
+    let mut flags = 0;
+    if privacy {
+        flags |= CAPABILITY_PRIVACY;
+    }
+    if channel_hop {
+        flags |= CAPABILITY_CHANNEL_HOP;
+    }
+    if command_window {
+        flags |= CAPABILITY_COMMAND_WINDOW;
+    }
+    if chunk_size {
+        flags |= CAPABILITY_CHUNK_SIZE;
+    }
+    if loss_report {
+        flags |= CAPABILITY_LOSS_REPORT;
+    }
+    if gateway_clock {
+        flags |= CAPABILITY_GATEWAY_CLOCK;
+    }
+    if flags != 0 {
+        message.push(flags);
+    }
+    message
+}
+
+/// ゲートウェイが通知したDATAチャンクのペイロード長
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkSizeAdvert {
+    /// 推奨のペイロード長（バイト）
+    pub preferred: u16,
+    /// 受け取れるペイロード長の上限（バイト）
+    pub max: u16,
+}
+
+impl ChunkSizeAdvert {
+    /// ワイヤ表現から復元します（長さが違う、または推奨が0か上限を超える場合は None）
+    pub fn from_wire(data: &[u8]) -> Option<Self> {
+        if data.len() != CHUNK_SIZE_ADVERT_LEN {
+            return None;
+        }
+        let preferred = u16::from_le_bytes([data[0], data[1]]);
+        let max = u16::from_le_bytes([data[2], data[3]]);
+        (preferred > 0 && preferred <= max).then_some(Self { preferred, max })
+    }
+}
+
+/// ゲートウェイからのPong
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pong {
+    /// 対応するPingのnonce
+    pub nonce: u32,
+    /// ゲートウェイのデータキュー空き率（%）
+    pub queue_free_percent: u8,
+    /// ゲートウェイが適用した無線設定（旧ゲートウェイは None）
+    pub radio: Option<RadioSettings>,
+    /// ゲートウェイがプライバシーモードを受け入れた（旧ゲートウェイは false）
+    pub privacy: bool,
+    /// ゲートウェイが通知したDATAチャンクの大きさ（旧ゲートウェイは None）
+    pub chunk_size: Option<ChunkSizeAdvert>,
+    /// ゲートウェイの受信チャンネルの予定（チャンネルホッピングが無効なら None）
+    pub hop: Option<HopSchedule>,
+}
+
+/// 受信データをPongとして解析します（Pongでなければ None）
+pub fn parse_pong(data: &[u8]) -> Option<Pong> {
+    if data.len() < PONG_MESSAGE_LEN || data[0] != PONG_MESSAGE_TYPE || data[1..5] != PONG_MAGIC {
+        return None;
+    }
+    let extensions = &data[PONG_MESSAGE_LEN..];
+    let (radio, flags, chunk_size, hop) = match extensions.len() {
+        0 => (None, 0, None, None),
+        1 => (None, extensions[0], None, None),
+        RADIO_SETTINGS_LEN => (RadioSettings::from_wire(extensions), 0, None, None),
+        len if len > RADIO_SETTINGS_LEN => {
+            let flags = extensions[RADIO_SETTINGS_LEN];
+            let mut additions = &extensions[RADIO_SETTINGS_LEN + 1..];
+            // 大きさの通知、予定の順にフラグが立っているものだけが続く
+            let chunk_size = if flags & CAPABILITY_CHUNK_SIZE != 0 {
+                if additions.len() < CHUNK_SIZE_ADVERT_LEN {
+                    return None;
+                }
+                let (advert, rest) = additions.split_at(CHUNK_SIZE_ADVERT_LEN);
+                additions = rest;
+                Some(ChunkSizeAdvert::from_wire(advert)?)
+            } else {
+                None
+            };
+            let hop = match (additions.is_empty(), flags & CAPABILITY_CHANNEL_HOP != 0) {
+                (true, _) => None,
+                (false, true) => Some(HopSchedule::from_wire(additions)?),
+                (false, false) => return None,
+            };
+            (RadioSettings::from_wire(&extensions[..RADIO_SETTINGS_LEN]), flags, chunk_size, hop)
+        }
+        _ => return None,
+    };
+    Some(Pong {
+        nonce: u32::from_le_bytes([data[5], data[6], data[7], data[8]]),
+        queue_free_percent: data[9],
+        radio,
+        privacy: flags & CAPABILITY_PRIVACY != 0,
+        chunk_size,
+        hop,
+    })
+}
+
+/// ゲートウェイからの延期要求（同時転送数の上限に達している）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Defer {
+    /// 対応するPingのnonce
+    pub nonce: u32,
+    /// 再試行までの待機時間の目安（ミリ秒）
+    pub retry_after_ms: u32,
+}
+
+/// 受信データを延期要求として解析します（延期要求でなければ None）
+pub fn parse_defer(data: &[u8]) -> Option<Defer> {
+    if data.len() != DEFER_MESSAGE_LEN || data[0] != DEFER_MESSAGE_TYPE || data[1..5] != DEFER_MAGIC {
+        return None;
+    }
+    Some(Defer {
+        nonce: u32::from_le_bytes([data[5], data[6], data[7], data[8]]),
+        retry_after_ms: u32::from_le_bytes([data[9], data[10], data[11], data[12]]),
+    })
+}
+
+/// ゲートウェイがデータを拒否した理由（ゲートウェイの RejectReason と同じ）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RejectReason {
+    /// 受け付け数の上限（データキュー溢れ）
+    Quota,
+    /// ゲートウェイに登録されていないデバイス
+    UnknownDevice,
+    /// チェックサムエラーの多発
+    ChecksumStorm,
+}
+
+impl RejectReason {
+    /// u8から変換します
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0x01 => Some(Self::Quota),
+            0x02 => Some(Self::UnknownDevice),
+            0x03 => Some(Self::ChecksumStorm),
+            _ => None,
+        }
+    }
+
+    /// u8に変換します
+    pub fn as_u8(self) -> u8 {
+        match self {
+            Self::Quota => 0x01,
+            Self::UnknownDevice => 0x02,
+            Self::ChecksumStorm => 0x03,
+        }
+    }
+
+    /// ログとメタデータ用の名前
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Quota => "QUOTA",
+            Self::UnknownDevice => "UNKNOWN_DEVICE",
+            Self::ChecksumStorm => "CHECKSUM_STORM",
+        }
+    }
+}
+
+/// ゲートウェイからの拒否メッセージ
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Reject {
+    pub reason: RejectReason,
+    /// Pingへの応答ならそのnonce、転送中の拒否は0
+    pub nonce: u32,
+    /// 再試行までの待機時間の目安（ミリ秒）
+    pub retry_after_ms: u32,
+}
+
+/// 受信データを拒否メッセージとして解析します（拒否メッセージでなければ None）
+pub fn parse_reject(data: &[u8]) -> Option<Reject> {
+    if data.len() != REJECT_MESSAGE_LEN || data[0] != REJECT_MESSAGE_TYPE || data[1..5] != REJECT_MAGIC {
+        return None;
+    }
+    Some(Reject {
+        reason: RejectReason::from_u8(data[5])?,
+        nonce: u32::from_le_bytes([data[6], data[7], data[8], data[9]]),
+        retry_after_ms: u32::from_le_bytes([data[10], data[11], data[12], data[13]]),
+    })
+}
+
+/// 拒否を受けたサイクルのスリープ時間（秒）
+///
+/// 通常のスリープ時間より再試行までの時間が長ければ、そちらに合わせます（秒未満は切り上げ）。
+pub fn reject_backoff_seconds(sleep_duration_seconds: u64, retry_after_ms: u32) -> u64 {
+    sleep_duration_seconds.max(u64::from(retry_after_ms).div_ceil(1000))
+}
+
+/// HASHフレームに付加する受けた拒否の数と最後の理由（拒否がなければ空文字列）
+pub fn reject_metadata_fields(rejects: u32, last_reason: Option<RejectReason>) -> String {
+    match last_reason {
+        Some(reason) if rejects > 0 => format!(",GW_REJECTS:{},GW_REJECT_LAST:{}", rejects, reason.as_str()),
+        _ => String::new(),
+    }
+}
+
+/// 疎通確認Pingへの応答
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProbeReply {
+    /// 転送してよい
+    Pong(Pong),
+    /// 待ってから疎通確認をやり直す
+    Defer(Defer),
+    /// 転送せず、再試行までの時間を待つ
+    Reject(Reject),
+}
+
+/// 延期要求に従って待機する時間を返します
+///
+/// これまでの待機時間と合わせて `budget_ms` を超える場合は None（転送を見送る）。
+pub fn defer_wait_ms(deferred_ms: u32, retry_after_ms: u32, budget_ms: u32) -> Option<u32> {
+    (deferred_ms.saturating_add(retry_after_ms) <= budget_ms).then_some(retry_after_ms)
+}
+
+/// 疎通確認の結果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProbeOutcome {
+    /// Pongを受信した
+    Reachable {
+        /// Ping送信からPong受信までの時間（ミリ秒）
+        rtt_ms: u32,
+        /// 応答を得るまでの試行回数
+        attempts: u8,
+        /// ゲートウェイのデータキュー空き率（%）
+        queue_free_percent: u8,
+        /// ゲートウェイと無線設定（送信レート・AMPDU）が一致しない
+        radio_mismatch: bool,
+        /// ゲートウェイの延期要求で待機した時間の合計（ミリ秒）
+        deferred_ms: u32,
+    },
+    /// ゲートウェイが延期を要求し続け、待機時間の上限に達した
+    Deferred {
+        /// 待機した時間の合計（ミリ秒）
+        deferred_ms: u32,
+    },
+    /// ゲートウェイが拒否メッセージを返した
+    Rejected {
+        reason: RejectReason,
+        /// 再試行までの待機時間の目安（ミリ秒）
+        retry_after_ms: u32,
+    },
+    /// すべての試行でPongがなかった
+    Unreachable {
+        /// 試行回数
+        attempts: u8,
+    },
+}
+
+impl ProbeOutcome {
+    /// ゲートウェイに到達できたかどうか
+    pub fn is_reachable(&self) -> bool {
+        matches!(self, ProbeOutcome::Reachable { .. })
+    }
+
+    /// HASHフレームに付加するメタデータ（`,PROBE_RTT_MS:...` 形式）
+    ///
+    /// `skipped_cycles` は前回の転送成功以降に疎通確認の失敗で転送を見送った回数です。
+    pub fn metadata_fields(&self, skipped_cycles: u32) -> String {
+        let mut fields = match self {
+            ProbeOutcome::Reachable {
+                rtt_ms,
+                attempts,
+                queue_free_percent,
+                radio_mismatch,
+                deferred_ms,
+            } => {
+                let mut fields = format!(
+                    ",PROBE_RTT_MS:{},PROBE_TRIES:{},GW_QUEUE_FREE:{}",
+                    rtt_ms, attempts, queue_free_percent
+                );
+                if *radio_mismatch {
+                    fields.push_str(",RADIO_MISMATCH:1");
+                }
+                if *deferred_ms > 0 {
+                    fields.push_str(&format!(",PROBE_DEFER_MS:{}", deferred_ms));
+                }
+                fields
+            }
+            ProbeOutcome::Unreachable { attempts } => format!(",PROBE_FAIL:{}", attempts),
+            ProbeOutcome::Deferred { deferred_ms } => format!(",PROBE_DEFER_MS:{}", deferred_ms),
+            ProbeOutcome::Rejected { reason, .. } => format!(",PROBE_REJECT:{}", reason.as_str()),
+        };
+        if skipped_cycles > 0 {
+            fields.push_str(&format!(",PROBE_SKIPPED:{}", skipped_cycles));
+        }
+        fields
+    }
+}
@@ -1,12 +1,138 @@
 use esp_idf_svc::hal::delay::FreeRtos;
 use log::{info, warn};
-use std::sync::{Arc, Mutex};
-use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU8, Ordering};
+
+use super::channel_hop::HopSchedule;
+use super::command_window::{parse_hash_ack, HashAck};
+use super::control_frame::{is_control_frame, parse_control_frame, ControlCommand};
+#[cfg(feature = "legacy-sleep-command")]
+use super::control_frame::parse_legacy_sleep_seconds;
+use super::downlink_auth::{
+    command_counter, is_broadcast_config, is_config_update, is_file_chunk, is_key_rotation, is_signed_sleep_command,
+    verify_broadcast_config, verify_config_update, verify_file_chunk, verify_key_rotation, verify_signed_sleep_command,
+    BroadcastConfig, ConfigUpdate, DeviceKeys, DownlinkKey, DownlinkRejection, DEVICE_KEY_RECORD_LEN,
+};
+use super::file_transfer::{ChunkOutcome, FileAssembly};
+use super::probe::{
+    parse_defer, parse_pong, parse_reject, reject_metadata_fields, ChunkSizeAdvert, Defer, Pong, ProbeReply, Reject,
+    RejectReason,
+};
+use super::radio::{RadioSettings, RADIO_SETTINGS_LEN};
+use super::relay::{RelayBuffer, RelayEvent};
+use crate::core::config_staging::GatewayConfirmation;
+use crate::core::rtc_manager::RtcManager;
+use crate::power::sleep::drift::ClockSample;
 
 /// 受信したスリープコマンドのデータ
 static RECEIVED_SLEEP_DURATION: AtomicU32 = AtomicU32::new(0);
 static SLEEP_COMMAND_RECEIVED: AtomicBool = AtomicBool::new(false);
 
+/// 受信したPong（疎通確認の応答）
+static PONG_RECEIVED: AtomicBool = AtomicBool::new(false);
+static PONG_NONCE: AtomicU32 = AtomicU32::new(0);
+static PONG_QUEUE_FREE_PERCENT: AtomicU8 = AtomicU8::new(0);
+/// Pongに付加された無線設定（下位3バイト: ワイヤ表現、最上位バイト: 付加あり=1）
+static PONG_RADIO: AtomicU32 = AtomicU32::new(0);
+/// Pongでゲートウェイがプライバシーモードを受け入れたか
+static PONG_PRIVACY: AtomicBool = AtomicBool::new(false);
+/// Pongに付加されたDATAチャンクの大きさ（下位16ビット: 推奨、上位16ビット: 上限、0は通知なし）
+static PONG_CHUNK_SIZE: AtomicU32 = AtomicU32::new(0);
+/// Pongに付加された受信チャンネルの予定と受信時刻
+static PONG_HOP: Mutex<Option<(HopSchedule, std::time::Instant)>> = Mutex::new(None);
+
+/// 受信したHASHフレームへの応答（最初に受信した応答のみ。HASHの再送への応答では上書きしない）
+static HASH_ACK_RECEIVED: AtomicBool = AtomicBool::new(false);
+static HASH_ACK_PENDING: AtomicBool = AtomicBool::new(false);
+static HASH_ACK_COUNT: AtomicU8 = AtomicU8::new(0);
+/// HASHフレームへの応答に付加された前回の転送の損失率（%、`NO_LOSS_REPORT` は付加なし）
+static HASH_ACK_LOSS: AtomicU8 = AtomicU8::new(NO_LOSS_REPORT);
+const NO_LOSS_REPORT: u8 = u8::MAX;
+/// HASHフレームへの応答に付加されたゲートウェイの時計と、受信時のデバイスの時計
+static HASH_ACK_CLOCK: Mutex<Option<ClockSample>> = Mutex::new(None);
+/// 転送の開始後にHASHフレームへの応答を受信したか（試行設定の確定に使う。`take_hash_ack` では消えない）
+static CONFIRMED_BY_HASH_ACK: AtomicBool = AtomicBool::new(false);
+/// 転送の開始後にスリープコマンドを受信したか（`reset_receiver_state` では消えない）
+static CONFIRMED_BY_SLEEP_COMMAND: AtomicBool = AtomicBool::new(false);
+
+/// 受信した画像の再送要求（コマンド待機中に取り出して送り直す）
+static RESEND_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// 受信したサムネイル要求（コマンド待機中に取り出して送る）
+static THUMBS_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// コマンド待機中に受け付ける要求
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WindowRequest {
+    /// 直前に送信した画像の再送（`RESEND_LAST`）
+    ResendLast,
+    /// 保持している直近のサムネイルの送信（`GET_THUMBS`）
+    SendThumbnails,
+}
+
+/// 受信した延期要求（同時転送数の上限による疎通確認の応答）
+static DEFER_RECEIVED: AtomicBool = AtomicBool::new(false);
+static DEFER_NONCE: AtomicU32 = AtomicU32::new(0);
+static DEFER_RETRY_AFTER_MS: AtomicU32 = AtomicU32::new(0);
+
+/// 受信した拒否メッセージ（Pingへの応答、または転送中にゲートウェイがデータを破棄した）
+static REJECT_RECEIVED: AtomicBool = AtomicBool::new(false);
+static REJECT_NONCE: AtomicU32 = AtomicU32::new(0);
+static REJECT_REASON: AtomicU8 = AtomicU8::new(0);
+static REJECT_RETRY_AFTER_MS: AtomicU32 = AtomicU32::new(0);
+/// 受けた拒否の数と最後の理由（次回の送信成功時に報告してリセット）
+#[link_section = ".rtc.data"]
+static GATEWAY_REJECTS: AtomicU32 = AtomicU32::new(0);
+#[link_section = ".rtc.data"]
+static LAST_GATEWAY_REJECT_REASON: AtomicU8 = AtomicU8::new(0);
+
+/// ダウンリンク認証（鍵と自デバイスのMAC）。設定時は署名付きスリープコマンドのみ受理
+static DOWNLINK_AUTH: OnceLock<(DownlinkKey, [u8; 6])> = OnceLock::new();
+/// 最後に受理したコマンドカウンタ
+static LAST_ACCEPTED_COUNTER: AtomicU32 = AtomicU32::new(0);
+/// デバイスごとの認証鍵（鍵更新で切り替える。一斉配信は DOWNLINK_AUTH の鍵で検証する）
+static DEVICE_KEYS: Mutex<Option<DeviceKeys>> = Mutex::new(None);
+/// 鍵の記録を更新した（NVSへの保存待ち）
+static KEY_RECORD_CHANGED: AtomicBool = AtomicBool::new(false);
+
+/// 適用済みの一斉配信の設定ID（これ以下の配信は無視する）
+static APPLIED_BROADCAST_ID: AtomicU32 = AtomicU32::new(0);
+/// 受信した一斉配信（メインループが取り出してステージングする）
+static RECEIVED_BROADCAST: Mutex<Option<BroadcastConfig>> = Mutex::new(None);
+/// 受信したデバイスごとの設定更新（メインループが取り出してステージングする）
+static RECEIVED_CONFIG_UPDATE: Mutex<Option<ConfigUpdate>> = Mutex::new(None);
+
+/// 受信中のファイル（起動時にNVSの記録から復元する）
+static FILE_ASSEMBLY: Mutex<Option<FileAssembly>> = Mutex::new(None);
+/// 受信中のファイルが変わった（NVSへの保存待ち）
+static FILE_CHANGED: AtomicBool = AtomicBool::new(false);
+
+/// 中継する子機のフレームの受信バッファ（受信窓の間のみSome）
+static RELAY_BUFFER: Mutex<Option<RelayBuffer>> = Mutex::new(None);
+/// 中継する子機の転送がそろったか
+static RELAY_COMPLETE: AtomicBool = AtomicBool::new(false);
+
+/// 拒否した制御メッセージ数（次回の送信成功時に報告してリセット）
+#[link_section = ".rtc.data"]
+static DOWNLINK_REPLAY_REJECTS: AtomicU32 = AtomicU32::new(0);
+#[link_section = ".rtc.data"]
+static DOWNLINK_BAD_SIGNATURE_REJECTS: AtomicU32 = AtomicU32::new(0);
+
+/// 記録済みの拒否数（リプレイ, 署名不正）
+pub fn downlink_rejections() -> (u32, u32) {
+    (
+        DOWNLINK_REPLAY_REJECTS.load(Ordering::Relaxed),
+        DOWNLINK_BAD_SIGNATURE_REJECTS.load(Ordering::Relaxed),
+    )
+}
+
+/// 送信成功後に拒否数をリセットします
+pub fn clear_downlink_rejections() {
+    DOWNLINK_REPLAY_REJECTS.store(0, Ordering::Relaxed);
+    DOWNLINK_BAD_SIGNATURE_REJECTS.store(0, Ordering::Relaxed);
+    GATEWAY_REJECTS.store(0, Ordering::Relaxed);
+}
+
 /// ESP-NOW受信者（シンプル実装）
 pub struct EspNowReceiver {
     /// プレースホルダー - 実際のESP-NOW受信はコールバックで処理
@@ -17,8 +143,8 @@ impl EspNowReceiver {
     /// 新しいESP-NOW受信者を作成
     pub fn new(_esp_now: Arc<Mutex<esp_idf_svc::espnow::EspNow<'static>>>) -> Result<Self, esp_idf_sys::EspError> {
         // ESP-NOW受信コールバックを設定
-        unsafe {
-            esp_idf_sys::esp_now_register_recv_cb(Some(esp_now_recv_cb));
+        if let Err(e) = crate::sys_wrappers::esp::register_esp_now_recv_cb(Some(esp_now_recv_cb)) {
+            warn!("ESP-NOW受信コールバックの登録に失敗しました (error={})", e.code());
         }
 
         Ok(Self {
@@ -33,8 +159,267 @@ impl EspNowReceiver {
         info!("ESP-NOW受信状態をリセットしました");
     }
 
+    /// ダウンリンク認証を有効化します（以降は署名付きスリープコマンドのみ受理）
+    ///
+    /// # 引数
+    /// * `own_mac` - 自デバイスのSTA MAC
+    /// * `last_accepted` - NVSに保存された最後に受理したカウンタ
+    /// * `key_record` - NVSに保存された鍵の記録（鍵更新を受理していなければNone）
+    pub fn enable_downlink_auth(key: DownlinkKey, own_mac: [u8; 6], last_accepted: u32, key_record: Option<&[u8]>) {
+        LAST_ACCEPTED_COUNTER.fetch_max(last_accepted, Ordering::SeqCst);
+        let keys = DeviceKeys::restore(key.clone(), key_record);
+        let epoch = keys.epoch();
+        if let Ok(mut device_keys) = DEVICE_KEYS.lock() {
+            device_keys.get_or_insert(keys);
+        }
+        if DOWNLINK_AUTH.set((key, own_mac)).is_ok() {
+            info!(
+                "ダウンリンク認証を有効化しました（受理済みカウンタ: {}, 鍵の世代: {}）",
+                last_accepted, epoch
+            );
+        }
+    }
+
+    /// 更新した鍵の記録を取り出します（NVSへの保存用。変更がなければNone）
+    pub fn take_key_record() -> Option<[u8; DEVICE_KEY_RECORD_LEN]> {
+        if !KEY_RECORD_CHANGED.swap(false, Ordering::SeqCst) {
+            return None;
+        }
+        Some(DEVICE_KEYS.lock().ok()?.as_ref()?.to_record())
+    }
+
+    /// HASHフレームに付加する鍵の世代（認証が無効なら空文字列）
+    pub fn key_metadata_fields() -> String {
+        DEVICE_KEYS
+            .lock()
+            .ok()
+            .and_then(|keys| keys.as_ref().map(DeviceKeys::metadata_fields))
+            .unwrap_or_default()
+    }
+
+    /// 最後に受理したコマンドカウンタ（NVSへの保存用）
+    pub fn last_accepted_counter() -> u32 {
+        LAST_ACCEPTED_COUNTER.load(Ordering::SeqCst)
+    }
+
+    /// 適用済みの一斉配信の設定IDを設定します（起動時にリモート設定から復元）
+    pub fn set_applied_broadcast_id(config_id: u32) {
+        APPLIED_BROADCAST_ID.fetch_max(config_id, Ordering::SeqCst);
+    }
+
+    /// 受信した一斉配信を取り出します
+    pub fn take_broadcast_config() -> Option<BroadcastConfig> {
+        RECEIVED_BROADCAST.lock().ok()?.take()
+    }
+
+    /// 受信したデバイスごとの設定更新を取り出します
+    pub fn take_config_update() -> Option<ConfigUpdate> {
+        RECEIVED_CONFIG_UPDATE.lock().ok()?.take()
+    }
+
+    /// NVSに保存した受信途中のファイルを引き継ぎます
+    pub fn resume_file(assembly: FileAssembly) {
+        if let Ok(mut current) = FILE_ASSEMBLY.lock() {
+            *current = Some(assembly);
+        }
+    }
+
+    /// HASHフレームに付加するファイル転送の受信状況（受信中のファイルがなければ空文字列）
+    pub fn file_metadata_fields() -> String {
+        FILE_ASSEMBLY
+            .lock()
+            .ok()
+            .and_then(|assembly| assembly.as_ref().map(FileAssembly::metadata_fields))
+            .unwrap_or_default()
+    }
+
+    /// 変わった受信状況を取り出します（NVSへの保存用。変更がなければNone）
+    ///
+    /// 受信を終えたファイルは内容を含めて返し、以降は受信済みの位置の報告だけを保持します。
+    pub fn take_file_progress() -> Option<FileAssembly> {
+        if !FILE_CHANGED.swap(false, Ordering::SeqCst) {
+            return None;
+        }
+        let mut current = FILE_ASSEMBLY.lock().ok()?;
+        let assembly = current.as_mut()?;
+        let progress = assembly.clone();
+        assembly.take_completed();
+        Some(progress)
+    }
+
+    /// 受信を終えたファイルの報告を取り下げます（報告したHASHフレームの送信後。取り下げたらtrue）
+    pub fn clear_completed_file() -> bool {
+        let Ok(mut current) = FILE_ASSEMBLY.lock() else {
+            return false;
+        };
+        if current.as_ref().is_some_and(FileAssembly::is_complete) && !FILE_CHANGED.load(Ordering::SeqCst) {
+            *current = None;
+            return true;
+        }
+        false
+    }
+
+    /// 指定したnonceのPong・延期要求・拒否メッセージを待機します（タイムアウト付き）
+    ///
+    /// 待機前に受信済みの応答は破棄されないため、Ping送信前に `clear_pong` を呼んでください。
+    pub fn wait_for_probe_reply(&self, nonce: u32, timeout_ms: u32) -> Option<ProbeReply> {
+        const CHECK_INTERVAL_MS: u32 = 10;
+        let mut elapsed_ms = 0;
+        while elapsed_ms < timeout_ms {
+            if PONG_RECEIVED.load(Ordering::SeqCst) && PONG_NONCE.load(Ordering::SeqCst) == nonce {
+                let radio = PONG_RADIO.load(Ordering::SeqCst).to_le_bytes();
+                return Some(ProbeReply::Pong(Pong {
+                    nonce,
+                    queue_free_percent: PONG_QUEUE_FREE_PERCENT.load(Ordering::SeqCst),
+                    radio: if radio[RADIO_SETTINGS_LEN] != 0 {
+                        RadioSettings::from_wire(&radio[..RADIO_SETTINGS_LEN])
+                    } else {
+                        None
+                    },
+                    privacy: PONG_PRIVACY.load(Ordering::SeqCst),
+                    chunk_size: match PONG_CHUNK_SIZE.load(Ordering::SeqCst) {
+                        0 => None,
+                        packed => Some(ChunkSizeAdvert {
+                            preferred: packed as u16,
+                            max: (packed >> 16) as u16,
+                        }),
+                    },
+                    hop: Self::hop_schedule().map(|(schedule, _)| schedule),
+                }));
+            }
+            if DEFER_RECEIVED.load(Ordering::SeqCst) && DEFER_NONCE.load(Ordering::SeqCst) == nonce {
+                return Some(ProbeReply::Defer(Defer {
+                    nonce,
+                    retry_after_ms: DEFER_RETRY_AFTER_MS.load(Ordering::SeqCst),
+                }));
+            }
+            if REJECT_RECEIVED.load(Ordering::SeqCst) && REJECT_NONCE.load(Ordering::SeqCst) == nonce {
+                if let Some(reject) = Self::take_reject() {
+                    return Some(ProbeReply::Reject(reject));
+                }
+            }
+            FreeRtos::delay_ms(CHECK_INTERVAL_MS);
+            elapsed_ms += CHECK_INTERVAL_MS;
+        }
+        None
+    }
+
+    /// 受信済みの拒否メッセージ（取り出さずに確認する。なければ None）
+    pub fn pending_reject() -> Option<Reject> {
+        if !REJECT_RECEIVED.load(Ordering::SeqCst) {
+            return None;
+        }
+        Some(Reject {
+            reason: RejectReason::from_u8(REJECT_REASON.load(Ordering::SeqCst))?,
+            nonce: REJECT_NONCE.load(Ordering::SeqCst),
+            retry_after_ms: REJECT_RETRY_AFTER_MS.load(Ordering::SeqCst),
+        })
+    }
+
+    /// 受信済みの拒否メッセージを取り出します（なければ None）
+    pub fn take_reject() -> Option<Reject> {
+        let reject = Self::pending_reject();
+        REJECT_RECEIVED.store(false, Ordering::SeqCst);
+        reject
+    }
+
+    /// HASHフレームに付加する前回の送信成功以降に受けた拒否（`,GW_REJECTS:...` 形式）
+    pub fn reject_metadata_fields() -> String {
+        reject_metadata_fields(
+            GATEWAY_REJECTS.load(Ordering::Relaxed),
+            RejectReason::from_u8(LAST_GATEWAY_REJECT_REASON.load(Ordering::Relaxed)),
+        )
+    }
+
+    /// 最後に受信したPongの受信チャンネルの予定と受信時刻（予定がなければ None）
+    pub fn hop_schedule() -> Option<(HopSchedule, std::time::Instant)> {
+        *PONG_HOP.lock().ok()?
+    }
+
+    /// 受信済みのPong・延期要求・拒否メッセージを破棄します
+    pub fn clear_pong() {
+        PONG_RECEIVED.store(false, Ordering::SeqCst);
+        DEFER_RECEIVED.store(false, Ordering::SeqCst);
+        REJECT_RECEIVED.store(false, Ordering::SeqCst);
+    }
+
+    /// 受信済みのHASHフレームへの応答とゲートウェイからの確認を破棄します（転送前に呼ぶ）
+    pub fn clear_hash_ack() {
+        HASH_ACK_RECEIVED.store(false, Ordering::SeqCst);
+        CONFIRMED_BY_HASH_ACK.store(false, Ordering::SeqCst);
+        CONFIRMED_BY_SLEEP_COMMAND.store(false, Ordering::SeqCst);
+        HASH_ACK_LOSS.store(NO_LOSS_REPORT, Ordering::SeqCst);
+        if let Ok(mut clock) = HASH_ACK_CLOCK.lock() {
+            *clock = None;
+        }
+    }
+
+    /// HASHフレームへの応答で受け取ったゲートウェイの時計を取り出します（届いていなければ None）
+    pub fn take_gateway_clock() -> Option<ClockSample> {
+        HASH_ACK_CLOCK.lock().ok()?.take()
+    }
+
+    /// HASHフレームへの応答でゲートウェイが報告した前回の転送の損失率を取り出します（届いていなければ None）
+    pub fn take_reported_loss_percent() -> Option<u8> {
+        Some(HASH_ACK_LOSS.swap(NO_LOSS_REPORT, Ordering::SeqCst)).filter(|&loss| loss != NO_LOSS_REPORT)
+    }
+
+    /// 転送の開始後に受信したゲートウェイからの確認を返します
+    ///
+    /// まだ届いていなければ `timeout_ms` の間、HASHフレームへの応答を待ちます
+    /// （スリープコマンドを待たないタイムラプスでは、応答が送信直後に届くため）。
+    pub fn wait_for_confirmation(timeout_ms: u32) -> GatewayConfirmation {
+        const CHECK_INTERVAL_MS: u32 = 50;
+        let mut elapsed_ms = 0;
+        loop {
+            let confirmation = GatewayConfirmation {
+                hash_acked: CONFIRMED_BY_HASH_ACK.load(Ordering::SeqCst),
+                sleep_command_received: CONFIRMED_BY_SLEEP_COMMAND.load(Ordering::SeqCst),
+            };
+            if confirmation.hash_acked || confirmation.sleep_command_received || elapsed_ms >= timeout_ms {
+                return confirmation;
+            }
+            FreeRtos::delay_ms(CHECK_INTERVAL_MS);
+            elapsed_ms += CHECK_INTERVAL_MS;
+        }
+    }
+
+    /// 受信したHASHフレームへの応答を取り出します（届いていなければ None）
+    pub fn take_hash_ack() -> Option<HashAck> {
+        if !HASH_ACK_RECEIVED.swap(false, Ordering::SeqCst) {
+            return None;
+        }
+        let loss = HASH_ACK_LOSS.load(Ordering::SeqCst);
+        Some(HashAck {
+            commands_pending: HASH_ACK_PENDING.load(Ordering::SeqCst),
+            pending_count: HASH_ACK_COUNT.load(Ordering::SeqCst),
+            loss_percent: Some(loss).filter(|&loss| loss != NO_LOSS_REPORT),
+            gateway_us: HASH_ACK_CLOCK.lock().ok().and_then(|clock| clock.map(|sample| sample.gateway_us)),
+        })
+    }
+
+    /// 子機のフレームの受信窓を開き、転送がそろうまで待機します（タイムアウト付き）
+    ///
+    /// 受信窓を閉じて溜めたフレームを返します（そろっていない場合も返す）。
+    pub fn receive_relay(&self, child_mac: [u8; 6], timeout_ms: u32) -> Option<RelayBuffer> {
+        const CHECK_INTERVAL_MS: u32 = 50;
+        RELAY_COMPLETE.store(false, Ordering::SeqCst);
+        if let Ok(mut buffer) = RELAY_BUFFER.lock() {
+            *buffer = Some(RelayBuffer::new(child_mac));
+        }
+        let mut elapsed_ms = 0;
+        while elapsed_ms < timeout_ms && !RELAY_COMPLETE.load(Ordering::SeqCst) {
+            FreeRtos::delay_ms(CHECK_INTERVAL_MS);
+            elapsed_ms += CHECK_INTERVAL_MS;
+        }
+        RELAY_BUFFER.lock().ok()?.take()
+    }
+
     /// スリープコマンドを待機（タイムアウト付き）
-    pub fn wait_for_sleep_command(&self, timeout_seconds: u32) -> Option<u32> {
+    ///
+    /// 待機中に画像の再送要求やサムネイル要求を受信すると `on_request` を呼び出します。
+    /// 要求に応えて送信した時間は待機時間に含めません。
+    pub fn wait_for_sleep_command(&self, timeout_seconds: u32, mut on_request: impl FnMut(WindowRequest)) -> Option<u32> {
         info!("スリープコマンドを{}秒間待機中...", timeout_seconds);
         
         let timeout_ms = timeout_seconds * 1000;
@@ -42,6 +427,14 @@ impl EspNowReceiver {
         let mut elapsed_ms = 0;
 
         while elapsed_ms < timeout_ms {
+            // 要求はスリープコマンドと続けて届いても送ってから眠る（ゲートウェイは要求を先に送る）
+            if RESEND_REQUESTED.swap(false, Ordering::SeqCst) {
+                on_request(WindowRequest::ResendLast);
+            }
+            if THUMBS_REQUESTED.swap(false, Ordering::SeqCst) {
+                on_request(WindowRequest::SendThumbnails);
+            }
+
             // 受信データをチェック
             if SLEEP_COMMAND_RECEIVED.load(Ordering::SeqCst) {
                 let sleep_duration = RECEIVED_SLEEP_DURATION.load(Ordering::SeqCst);
@@ -70,8 +463,6 @@ extern "C" fn esp_now_recv_cb(
     data: *const u8,
     data_len: i32,
 ) {
-    info!("=== ESP-NOW受信コールバック ===");
-    
     if data_len <= 0 {
         warn!("ESP-NOW受信: データ長が無効 ({})", data_len);
         return;
@@ -79,6 +470,12 @@ extern "C" fn esp_now_recv_cb(
 
     unsafe {
         let data_slice = std::slice::from_raw_parts(data, data_len as usize);
+
+        // 中継の受信窓の間は子機のフレームを溜める（チャンクごとにログを出さない）
+        if accept_relay_frame(data_slice) {
+            return;
+        }
+        info!("=== ESP-NOW受信コールバック ===");
         
         // 送信者MACアドレスを取得（安全な方法）
         let sender_mac = if !recv_info.is_null() {
@@ -91,42 +488,393 @@ extern "C" fn esp_now_recv_cb(
             "UNKNOWN".to_string()
         };
         
+        // 疎通確認の応答（スリープコマンドとは長さと識別子で区別）
+        if let Some(pong) = parse_pong(data_slice) {
+            info!("Pong受信: 送信者={}, nonce={}", sender_mac, pong.nonce);
+            PONG_NONCE.store(pong.nonce, Ordering::SeqCst);
+            PONG_QUEUE_FREE_PERCENT.store(pong.queue_free_percent, Ordering::SeqCst);
+            let radio = pong.radio.map_or(0, |radio| {
+                let [rate, tx_power, flags] = radio.to_wire();
+                u32::from_le_bytes([rate, tx_power, flags, 1])
+            });
+            PONG_RADIO.store(radio, Ordering::SeqCst);
+            PONG_PRIVACY.store(pong.privacy, Ordering::SeqCst);
+            let chunk_size = pong.chunk_size.map_or(0, |chunk_size| {
+                u32::from(chunk_size.preferred) | u32::from(chunk_size.max) << 16
+            });
+            PONG_CHUNK_SIZE.store(chunk_size, Ordering::SeqCst);
+            if let Ok(mut hop) = PONG_HOP.lock() {
+                *hop = pong.hop.map(|schedule| (schedule, std::time::Instant::now()));
+            }
+            PONG_RECEIVED.store(true, Ordering::SeqCst);
+            return;
+        }
+        if let Some(ack) = parse_hash_ack(data_slice) {
+            info!(
+                "HASH応答受信: 送信者={}, コマンド待ち={} ({}件)",
+                sender_mac, ack.commands_pending, ack.pending_count
+            );
+            if !HASH_ACK_RECEIVED.load(Ordering::SeqCst) {
+                HASH_ACK_PENDING.store(ack.commands_pending, Ordering::SeqCst);
+                HASH_ACK_COUNT.store(ack.pending_count, Ordering::SeqCst);
+                HASH_ACK_LOSS.store(ack.loss_percent.unwrap_or(NO_LOSS_REPORT), Ordering::SeqCst);
+                if let (Some(gateway_us), Ok(mut clock)) = (ack.gateway_us, HASH_ACK_CLOCK.lock()) {
+                    *clock = Some(ClockSample {
+                        gateway_us,
+                        local_us: RtcManager::now_unix_us(),
+                    });
+                }
+                HASH_ACK_RECEIVED.store(true, Ordering::SeqCst);
+            }
+            CONFIRMED_BY_HASH_ACK.store(true, Ordering::SeqCst);
+            return;
+        }
+        if let Some(defer) = parse_defer(data_slice) {
+            info!(
+                "延期要求受信: 送信者={}, nonce={}, 再試行まで{}ms",
+                sender_mac, defer.nonce, defer.retry_after_ms
+            );
+            DEFER_NONCE.store(defer.nonce, Ordering::SeqCst);
+            DEFER_RETRY_AFTER_MS.store(defer.retry_after_ms, Ordering::SeqCst);
+            DEFER_RECEIVED.store(true, Ordering::SeqCst);
+            return;
+        }
+        if let Some(reject) = parse_reject(data_slice) {
+            warn!(
+                "拒否メッセージ受信: 送信者={}, 理由={}, nonce={}, 再試行まで{}ms",
+                sender_mac,
+                reject.reason.as_str(),
+                reject.nonce,
+                reject.retry_after_ms
+            );
+            REJECT_NONCE.store(reject.nonce, Ordering::SeqCst);
+            REJECT_REASON.store(reject.reason.as_u8(), Ordering::SeqCst);
+            REJECT_RETRY_AFTER_MS.store(reject.retry_after_ms, Ordering::SeqCst);
+            REJECT_RECEIVED.store(true, Ordering::SeqCst);
+            GATEWAY_REJECTS.fetch_add(1, Ordering::Relaxed);
+            LAST_GATEWAY_REJECT_REASON.store(reject.reason.as_u8(), Ordering::Relaxed);
+            return;
+        }
+
         info!("送信者MAC: {}", sender_mac);
         info!("データサイズ: {}", data_len);
         info!("データ内容: {:02X?}", data_slice);
-        
-        // バイナリ形式の場合（4バイトのu32）
-        if data_len == 4 {
-            let sleep_seconds = u32::from_le_bytes([data_slice[0], data_slice[1], data_slice[2], data_slice[3]]);
-            info!("バイナリ形式でのスリープ時間: {}秒", sleep_seconds);
-            if sleep_seconds > 0 && sleep_seconds <= 86400 {
-                info!("✓ 有効なバイナリスリープコマンド受信: {}秒", sleep_seconds);
-                RECEIVED_SLEEP_DURATION.store(sleep_seconds, Ordering::SeqCst);
-                SLEEP_COMMAND_RECEIVED.store(true, Ordering::SeqCst);
-                return;
-            } else {
-                warn!("無効なバイナリスリープ時間: {}", sleep_seconds);
+
+        // 設定の一斉配信は署名を確認できる場合のみ受理する
+        if is_broadcast_config(data_slice) {
+            match DOWNLINK_AUTH.get() {
+                Some((key, _)) => handle_broadcast_config(data_slice, key, &sender_mac),
+                None => warn!("設定の一斉配信を受信しましたが、downlink_auth_key が未設定のため無視します"),
             }
+            return;
         }
 
-        // 文字列形式の場合
-        if let Ok(command_str) = std::str::from_utf8(data_slice) {
-            info!("文字列形式でのコマンド: '{}'", command_str);
-            
-            // 数値のみの場合（秒数）
-            if let Ok(sleep_seconds) = command_str.trim().parse::<u32>() {
-                info!("文字列形式でのスリープ時間: {}秒", sleep_seconds);
-                if sleep_seconds > 0 && sleep_seconds <= 86400 { // 最大24時間
-                    info!("✓ 有効な文字列スリープコマンド受信: {}秒", sleep_seconds);
-                    RECEIVED_SLEEP_DURATION.store(sleep_seconds, Ordering::SeqCst);
-                    SLEEP_COMMAND_RECEIVED.store(true, Ordering::SeqCst);
+        if is_config_update(data_slice) {
+            handle_config_update(data_slice, &sender_mac);
+            return;
+        }
+
+        if is_file_chunk(data_slice) {
+            handle_file_chunk(data_slice, &sender_mac);
+            return;
+        }
+
+        if is_control_frame(data_slice) {
+            match parse_control_frame(data_slice) {
+                // 再送要求は保持中の画像を送り直すだけのため、ダウンリンク認証の有無によらず受理する
+                Ok(ControlCommand::ResendLast) => {
+                    info!("✓ 画像の再送要求を受信（送信者={}）", sender_mac);
+                    RESEND_REQUESTED.store(true, Ordering::SeqCst);
+                    return;
+                }
+                // サムネイル要求も保持中の画像を送るだけのため、同じく受理する
+                Ok(ControlCommand::GetThumbs) => {
+                    info!("✓ サムネイル要求を受信（送信者={}）", sender_mac);
+                    THUMBS_REQUESTED.store(true, Ordering::SeqCst);
+                    return;
+                }
+                Ok(ControlCommand::Sleep { .. }) if DOWNLINK_AUTH.get().is_some() => {}
+                Ok(ControlCommand::Sleep { sleep_seconds }) => {
+                    accept_sleep_seconds(sleep_seconds, "制御フレーム");
+                    return;
+                }
+                Err(e) => {
+                    warn!("✗ 制御フレームを拒否しました（送信者={}）: {}", sender_mac, e);
                     return;
-                } else {
-                    warn!("無効な文字列スリープ時間: {}", sleep_seconds);
                 }
             }
         }
 
+        // 認証有効時は署名付きスリープコマンドのみ受理する
+        if let Some((key, own_mac)) = DOWNLINK_AUTH.get() {
+            if is_key_rotation(data_slice) {
+                handle_key_rotation(data_slice, own_mac, &sender_mac);
+            } else {
+                let key = signing_key(command_counter(data_slice)).unwrap_or_else(|| key.clone());
+                handle_authenticated_command(data_slice, &key, own_mac, &sender_mac);
+            }
+            return;
+        }
+        if is_key_rotation(data_slice) {
+            warn!("鍵更新を受信しましたが、downlink_auth_key が未設定のため無視します");
+            return;
+        }
+        if is_signed_sleep_command(data_slice) {
+            warn!("署名付きスリープコマンドを受信しましたが、downlink_auth_key が未設定のため無視します");
+            return;
+        }
+
+        #[cfg(feature = "legacy-sleep-command")]
+        if let Some(sleep_seconds) = parse_legacy_sleep_seconds(data_slice) {
+            accept_sleep_seconds(sleep_seconds, "旧形式");
+            return;
+        }
+
         warn!("✗ 無効なスリープコマンド形式: {:02X?}", data_slice);
     }
 }
+
+/// スリープ時間が有効（24時間以内）であれば受理します
+fn accept_sleep_seconds(sleep_seconds: u32, format: &str) {
+    if sleep_seconds > 0 && sleep_seconds <= 86400 {
+        info!("✓ 有効なスリープコマンド受信（{}）: {}秒", format, sleep_seconds);
+        RECEIVED_SLEEP_DURATION.store(sleep_seconds, Ordering::SeqCst);
+        SLEEP_COMMAND_RECEIVED.store(true, Ordering::SeqCst);
+        CONFIRMED_BY_SLEEP_COMMAND.store(true, Ordering::SeqCst);
+    } else {
+        warn!("無効なスリープ時間（{}）: {}", format, sleep_seconds);
+    }
+}
+
+/// 受信窓を開いていれば子機のフレームを溜めます
+///
+/// # 戻り値
+/// * 子機のフレームとして扱った場合はtrue
+fn accept_relay_frame(data: &[u8]) -> bool {
+    let Ok(mut guard) = RELAY_BUFFER.lock() else {
+        return false;
+    };
+    let Some(buffer) = guard.as_mut() else {
+        return false;
+    };
+    match buffer.accept(data) {
+        RelayEvent::Ignored => false,
+        RelayEvent::Accepted => true,
+        RelayEvent::Complete => {
+            info!("中継: 子機の転送を受信しました（{} bytes）", buffer.image_bytes());
+            RELAY_COMPLETE.store(true, Ordering::SeqCst);
+            true
+        }
+        RelayEvent::Aborted => {
+            warn!("中継: 子機が転送を中断したため、受信済みのフレームを破棄しました");
+            true
+        }
+        RelayEvent::Overflow => {
+            warn!("中継: 子機の画像が上限を超えたため中継しません");
+            true
+        }
+    }
+}
+
+/// 署名付きスリープコマンドを検証し、受理した場合のみスリープ時間を設定します
+fn handle_authenticated_command(data: &[u8], key: &DownlinkKey, own_mac: &[u8; 6], sender_mac: &str) {
+    let last_accepted = LAST_ACCEPTED_COUNTER.load(Ordering::SeqCst);
+    match verify_signed_sleep_command(data, key, own_mac, last_accepted) {
+        Ok(command) if command.sleep_seconds > 0 && command.sleep_seconds <= 86400 => {
+            info!(
+                "✓ 署名付きスリープコマンド受信: {}秒（カウンタ {}）",
+                command.sleep_seconds, command.counter
+            );
+            accept_counter(command.counter);
+            RECEIVED_SLEEP_DURATION.store(command.sleep_seconds, Ordering::SeqCst);
+            SLEEP_COMMAND_RECEIVED.store(true, Ordering::SeqCst);
+            CONFIRMED_BY_SLEEP_COMMAND.store(true, Ordering::SeqCst);
+        }
+        Ok(command) => warn!("無効な署名付きスリープ時間: {}", command.sleep_seconds),
+        Err(DownlinkRejection::Replay { counter, last_accepted }) => {
+            DOWNLINK_REPLAY_REJECTS.fetch_add(1, Ordering::Relaxed);
+            warn!(
+                "⚠ セキュリティ警告: リプレイされたコマンドを拒否しました（送信者={}, カウンタ {} <= 受理済み {}）",
+                sender_mac, counter, last_accepted
+            );
+        }
+        Err(DownlinkRejection::BadSignature) => {
+            DOWNLINK_BAD_SIGNATURE_REJECTS.fetch_add(1, Ordering::Relaxed);
+            warn!(
+                "⚠ セキュリティ警告: 署名が不正なコマンドを拒否しました（送信者={}, {}バイト）",
+                sender_mac,
+                data.len()
+            );
+        }
+    }
+}
+
+/// 指定したカウンタのコマンドを検証する鍵（鍵更新の切り替え前後で異なる）
+fn signing_key(counter: u32) -> Option<DownlinkKey> {
+    let keys = DEVICE_KEYS.lock().ok()?;
+    keys.as_ref().map(|keys| keys.key_for(counter).clone())
+}
+
+/// 受理したカウンタを記録し、切り替えカウンタに達していれば新しい鍵に切り替えます
+fn accept_counter(counter: u32) {
+    LAST_ACCEPTED_COUNTER.fetch_max(counter, Ordering::SeqCst);
+    let Ok(mut keys) = DEVICE_KEYS.lock() else {
+        return;
+    };
+    if let Some(keys) = keys.as_mut().filter(|keys| keys.accept(counter)) {
+        info!("✓ 認証鍵を切り替えました（世代 {}）", keys.epoch());
+        KEY_RECORD_CHANGED.store(true, Ordering::SeqCst);
+    }
+}
+
+/// 鍵更新を現在の鍵で検証し、新しい鍵を切り替え待ちにします
+fn handle_key_rotation(data: &[u8], own_mac: &[u8; 6], sender_mac: &str) {
+    let Ok(mut guard) = DEVICE_KEYS.lock() else {
+        return;
+    };
+    let Some(keys) = guard.as_mut() else {
+        return;
+    };
+    let last_accepted = LAST_ACCEPTED_COUNTER.load(Ordering::SeqCst);
+    match verify_key_rotation(data, keys.current(), own_mac, last_accepted) {
+        Ok(rotation) => {
+            LAST_ACCEPTED_COUNTER.fetch_max(rotation.counter, Ordering::SeqCst);
+            let (epoch, activate_counter) = (rotation.epoch, rotation.activate_counter);
+            if keys.stage(rotation) {
+                info!("✓ 鍵更新を受信: 世代 {}（カウンタ {} 以降で切り替え）", epoch, activate_counter);
+                KEY_RECORD_CHANGED.store(true, Ordering::SeqCst);
+            } else {
+                info!("適用済みの鍵更新を無視します（世代 {}）", epoch);
+            }
+        }
+        Err(DownlinkRejection::Replay { counter, last_accepted }) => {
+            DOWNLINK_REPLAY_REJECTS.fetch_add(1, Ordering::Relaxed);
+            warn!(
+                "⚠ セキュリティ警告: リプレイされた鍵更新を拒否しました（送信者={}, カウンタ {} <= 受理済み {}）",
+                sender_mac, counter, last_accepted
+            );
+        }
+        Err(DownlinkRejection::BadSignature) => {
+            DOWNLINK_BAD_SIGNATURE_REJECTS.fetch_add(1, Ordering::Relaxed);
+            warn!("⚠ セキュリティ警告: 署名が不正な鍵更新を拒否しました（送信者={}）", sender_mac);
+        }
+    }
+}
+
+/// 設定の一斉配信を検証し、未適用の設定であればメインループへ渡します
+fn handle_broadcast_config(data: &[u8], key: &DownlinkKey, sender_mac: &str) {
+    match verify_broadcast_config(data, key, APPLIED_BROADCAST_ID.load(Ordering::SeqCst)) {
+        Ok(broadcast) => {
+            info!("✓ 設定の一斉配信を受信: {}（設定ID {}）", broadcast.config, broadcast.config_id);
+            if let Ok(mut received) = RECEIVED_BROADCAST.lock() {
+                *received = Some(broadcast);
+            }
+        }
+        // 適用済みのデバイスにも再配信は届くため、リプレイとしては数えない
+        Err(DownlinkRejection::Replay { counter, .. }) => {
+            info!("適用済みの一斉配信を無視します（設定ID {}）", counter);
+        }
+        Err(DownlinkRejection::BadSignature) => {
+            DOWNLINK_BAD_SIGNATURE_REJECTS.fetch_add(1, Ordering::Relaxed);
+            warn!(
+                "⚠ セキュリティ警告: 署名が不正な一斉配信を拒否しました（送信者={}, {}バイト）",
+                sender_mac,
+                data.len()
+            );
+        }
+    }
+}
+
+/// デバイスごとの設定更新を検証し、受理した場合はメインループへ渡します
+///
+/// 認証有効時はスリープコマンドと同じカウンタを消費します。
+fn handle_config_update(data: &[u8], sender_mac: &str) {
+    let auth = DOWNLINK_AUTH.get();
+    let own_mac = auth.map_or([0u8; 6], |(_, own_mac)| *own_mac);
+    let key = auth.map(|(key, _)| signing_key(command_counter(data)).unwrap_or_else(|| key.clone()));
+    let last_accepted = LAST_ACCEPTED_COUNTER.load(Ordering::SeqCst);
+    match verify_config_update(data, key.as_ref(), &own_mac, last_accepted) {
+        Ok(update) => {
+            info!("✓ 設定更新を受信: {}（カウンタ {}）", update.config, update.counter);
+            accept_counter(update.counter);
+            // 同じ待機中に複数の更新（画質設定とデバッグフラグなど）を受信した場合は後の項目を優先して連結する
+            if let Ok(mut received) = RECEIVED_CONFIG_UPDATE.lock() {
+                *received = Some(match received.take() {
+                    Some(previous) => ConfigUpdate {
+                        counter: update.counter.max(previous.counter),
+                        config: format!("{};{}", previous.config, update.config),
+                    },
+                    None => update,
+                });
+            }
+        }
+        Err(DownlinkRejection::Replay { counter, last_accepted }) => {
+            DOWNLINK_REPLAY_REJECTS.fetch_add(1, Ordering::Relaxed);
+            warn!(
+                "⚠ セキュリティ警告: リプレイされた設定更新を拒否しました（送信者={}, カウンタ {} <= 受理済み {}）",
+                sender_mac, counter, last_accepted
+            );
+        }
+        Err(DownlinkRejection::BadSignature) => {
+            DOWNLINK_BAD_SIGNATURE_REJECTS.fetch_add(1, Ordering::Relaxed);
+            warn!(
+                "⚠ セキュリティ警告: 署名が不正な設定更新を拒否しました（送信者={}, {}バイト）",
+                sender_mac,
+                data.len()
+            );
+        }
+    }
+}
+
+/// ファイルの断片を検証し、受信中のファイルに追加します
+fn handle_file_chunk(data: &[u8], sender_mac: &str) {
+    let auth = DOWNLINK_AUTH.get();
+    let own_mac = auth.map_or([0u8; 6], |(_, own_mac)| *own_mac);
+    let key = auth.map(|(key, _)| signing_key(command_counter(data)).unwrap_or_else(|| key.clone()));
+    let last_accepted = LAST_ACCEPTED_COUNTER.load(Ordering::SeqCst);
+    let chunk = match verify_file_chunk(data, key.as_ref(), &own_mac, last_accepted) {
+        Ok(chunk) => chunk,
+        Err(DownlinkRejection::Replay { counter, last_accepted }) => {
+            DOWNLINK_REPLAY_REJECTS.fetch_add(1, Ordering::Relaxed);
+            warn!(
+                "⚠ セキュリティ警告: リプレイされたファイルの断片を拒否しました（送信者={}, カウンタ {} <= 受理済み {}）",
+                sender_mac, counter, last_accepted
+            );
+            return;
+        }
+        Err(DownlinkRejection::BadSignature) => {
+            DOWNLINK_BAD_SIGNATURE_REJECTS.fetch_add(1, Ordering::Relaxed);
+            warn!(
+                "⚠ セキュリティ警告: 署名が不正なファイルの断片を拒否しました（送信者={}, {}バイト）",
+                sender_mac,
+                data.len()
+            );
+            return;
+        }
+    };
+    accept_counter(chunk.counter);
+    let Ok(mut current) = FILE_ASSEMBLY.lock() else {
+        return;
+    };
+    let assembly = current.get_or_insert_with(FileAssembly::default);
+    match assembly.accept(&chunk) {
+        ChunkOutcome::Appended { received } => {
+            info!("✓ ファイル {} を受信中: {}/{} bytes", chunk.slot, received, chunk.total_len);
+            FILE_CHANGED.store(true, Ordering::SeqCst);
+        }
+        ChunkOutcome::Complete => {
+            info!("✓ ファイル {} を受信しました（{} bytes、CRC一致）", chunk.slot, chunk.total_len);
+            FILE_CHANGED.store(true, Ordering::SeqCst);
+        }
+        ChunkOutcome::Duplicate => info!("ファイル {} の受信済みの断片（位置 {}）を無視します", chunk.slot, chunk.offset),
+        ChunkOutcome::OutOfOrder { expected } => warn!(
+            "ファイル {} の断片の位置が受信済みの位置と一致しません（受信 {}, 期待 {}）",
+            chunk.slot, chunk.offset, expected
+        ),
+        ChunkOutcome::CrcMismatch => {
+            warn!("✗ ファイル {} のCRCが一致しないため、受信した内容を破棄しました", chunk.slot);
+            FILE_CHANGED.store(true, Ordering::SeqCst);
+        }
+        ChunkOutcome::Invalid => warn!("✗ 不正なファイルの断片を拒否しました（スロット={:?}）", chunk.slot),
+    }
+}
@@ -0,0 +1,221 @@
+//! 圏外のカメラの中継（ストア・アンド・フォワード）
+//!
+//! ゲートウェイの電波が届かないカメラ（子機）は、届く位置にあるカメラ（中継機）のMACアドレスを
+//! `receiver_mac` に設定して通常どおり送信します。中継機は自分の転送の前に受信窓を開き、
+//! 子機のフレームを画像全体がそろうまでメモリに溜めてから、子機のMACアドレスのフレームとして
+//! 自分のチャンクサイズで送り直します。ゲートウェイは転送をフレーム内のMACアドレスで集約するため、
+//! 中継された画像も子機の画像として扱われます。
+//!
+//! HASHフレームには中継の経路（`RELAY_HOPS:<段数>,RELAY_VIA:<中継機のMAC>[+...]`）を付加します。
+//! 子機が中継機を兼ねる多段中継では既存の経路に追記し、`MAX_RELAY_HOPS` を超える転送は中継しません。
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use super::frame_codec::{calculate_xor_checksum, FrameType, END_MARKER, FRAME_OVERHEAD, START_MARKER};
+use super::telemetry::TelemetryFields;
+
+/// 中継する画像の上限（自分の画像と同時にメモリに保持するため）
+pub const MAX_RELAY_IMAGE_BYTES: usize = 64 * 1024;
+/// 中継の最大段数（設定の誤りによる中継の循環を防ぐ）
+pub const MAX_RELAY_HOPS: u8 = 3;
+
+const RELAY_HOPS_KEY: &str = "RELAY_HOPS";
+const RELAY_VIA_KEY: &str = "RELAY_VIA";
+
+/// 解析したフレーム
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParsedFrame<'a> {
+    /// フレームの送信元MACアドレス
+    pub mac_address: [u8; 6],
+    /// フレームタイプ（未知の値もそのまま保持する）
+    pub frame_type: u8,
+    /// シーケンス番号
+    pub sequence: u32,
+    /// ペイロード
+    pub data: &'a [u8],
+}
+
+/// フレームを解析します（プライバシーモードの詰め物は無視し、チェックサムが合わなければNone）
+pub fn parse_frame(bytes: &[u8]) -> Option<ParsedFrame<'_>> {
+    if bytes.len() < FRAME_OVERHEAD || bytes[..4] != START_MARKER {
+        return None;
+    }
+    let mac_address: [u8; 6] = bytes[4..10].try_into().ok()?;
+    let frame_type = bytes[10];
+    let sequence = u32::from_le_bytes(bytes[11..15].try_into().ok()?);
+    let data_len = u32::from_le_bytes(bytes[15..19].try_into().ok()?) as usize;
+    let data_end = 19usize.checked_add(data_len)?;
+    if bytes.len() < data_end + 8 {
+        return None;
+    }
+    let data = &bytes[19..data_end];
+    let checksum = u32::from_le_bytes(bytes[data_end..data_end + 4].try_into().ok()?);
+    if bytes[data_end + 4..data_end + 8] != END_MARKER || checksum != calculate_xor_checksum(data) {
+        return None;
+    }
+    Some(ParsedFrame {
+        mac_address,
+        frame_type,
+        sequence,
+        data,
+    })
+}
+
+/// 受信したフレームの扱い
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RelayEvent {
+    /// 子機以外のフレーム、または解析できないフレーム
+    Ignored,
+    /// 溜めた
+    Accepted,
+    /// 画像全体がそろった
+    Complete,
+    /// 子機が転送を中断した（チャンクサイズを変えて送り直すため溜めた分を破棄した）
+    Aborted,
+    /// 上限を超えたため溜めた分を破棄した
+    Overflow,
+}
+
+/// 中継して送り直す転送
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RelayedTransfer {
+    /// 子機のMACアドレス（送り直すフレームのMACアドレス）
+    pub origin_mac: [u8; 6],
+    /// 画像データ
+    pub image: Vec<u8>,
+    /// 中継の経路を付加したHASHペイロード
+    pub hash_payload: String,
+}
+
+/// 子機1台分の受信バッファ
+#[derive(Debug, Clone)]
+pub struct RelayBuffer {
+    child_mac: [u8; 6],
+    chunks: BTreeMap<u32, Vec<u8>>,
+    /// 受信したシーケンス番号（欠番の判定用）
+    sequences: BTreeSet<u32>,
+    hash_payload: Option<String>,
+    eof: bool,
+    bytes: usize,
+    overflowed: bool,
+}
+
+impl RelayBuffer {
+    /// 子機のMACアドレスを指定して作成します
+    pub fn new(child_mac: [u8; 6]) -> Self {
+        Self {
+            child_mac,
+            chunks: BTreeMap::new(),
+            sequences: BTreeSet::new(),
+            hash_payload: None,
+            eof: false,
+            bytes: 0,
+            overflowed: false,
+        }
+    }
+
+    /// 子機のMACアドレス
+    pub fn child_mac(&self) -> [u8; 6] {
+        self.child_mac
+    }
+
+    /// 受信したフレームを溜めます
+    pub fn accept(&mut self, bytes: &[u8]) -> RelayEvent {
+        let Some(frame) = parse_frame(bytes) else {
+            return RelayEvent::Ignored;
+        };
+        let frame_type = FrameType::from_byte(frame.frame_type);
+        // ダミーフレームはシーケンス番号が乱数のため欠番判定に含めない
+        if frame.mac_address != self.child_mac || frame_type == Some(FrameType::Dummy) {
+            return RelayEvent::Ignored;
+        }
+        if frame_type == Some(FrameType::Abort) {
+            *self = Self::new(self.child_mac);
+            return RelayEvent::Aborted;
+        }
+        if self.overflowed {
+            return RelayEvent::Overflow;
+        }
+        self.sequences.insert(frame.sequence);
+        match frame_type {
+            Some(FrameType::Data) => {
+                if !self.chunks.contains_key(&frame.sequence) {
+                    self.bytes += frame.data.len();
+                    self.chunks.insert(frame.sequence, frame.data.to_vec());
+                }
+                if self.bytes > MAX_RELAY_IMAGE_BYTES {
+                    *self = Self::new(self.child_mac);
+                    self.overflowed = true;
+                    return RelayEvent::Overflow;
+                }
+            }
+            Some(FrameType::Hash) => self.hash_payload = Some(String::from_utf8_lossy(frame.data).into_owned()),
+            Some(FrameType::Eof) => self.eof = true,
+            // FECのパリティは欠番の判定にのみ使う（送り直すときは自分のFEC設定で付け直す）
+            _ => {}
+        }
+        if self.is_complete() {
+            RelayEvent::Complete
+        } else {
+            RelayEvent::Accepted
+        }
+    }
+
+    /// 欠けているフレームの数（最初と最後のシーケンス番号の間）
+    pub fn missing_frames(&self) -> u32 {
+        match (self.sequences.first(), self.sequences.last()) {
+            (Some(first), Some(last)) => {
+                last.saturating_sub(*first).saturating_add(1).saturating_sub(self.sequences.len() as u32)
+            }
+            _ => 0,
+        }
+    }
+
+    /// HASH・EOFを受信し、欠番がないか
+    pub fn is_complete(&self) -> bool {
+        self.eof && self.hash_payload.is_some() && self.missing_frames() == 0
+    }
+
+    /// 溜めた画像のバイト数
+    pub fn image_bytes(&self) -> usize {
+        self.bytes
+    }
+
+    /// そろった転送を、中継の経路を付加して取り出します
+    ///
+    /// そろっていない場合と、中継の段数が上限に達している場合はNoneを返します。
+    pub fn into_transfer(self, relay_mac: [u8; 6]) -> Option<RelayedTransfer> {
+        if !self.is_complete() {
+            return None;
+        }
+        let hash_payload = add_relay_hop(self.hash_payload.as_deref()?, relay_mac)?;
+        Some(RelayedTransfer {
+            origin_mac: self.child_mac,
+            image: self.chunks.into_values().flatten().collect(),
+            hash_payload,
+        })
+    }
+}
+
+/// HASHペイロードに中継の経路を追記します（段数が上限に達していればNone）
+pub fn add_relay_hop(payload: &str, relay_mac: [u8; 6]) -> Option<String> {
+    // 経路以外の項目（子機のファームウェアが新しく、知らない項目を含む）はそのまま残す
+    let mut fields = TelemetryFields::parse(payload);
+    let hops: u8 = fields.take(RELAY_HOPS_KEY).and_then(|value| value.parse().ok()).unwrap_or(0);
+    let mut via: Vec<String> = fields
+        .take(RELAY_VIA_KEY)
+        .map(|value| value.split('+').filter(|mac| !mac.is_empty()).map(str::to_string).collect())
+        .unwrap_or_default();
+    if hops >= MAX_RELAY_HOPS {
+        return None;
+    }
+    via.push(relay_mac.iter().map(|byte| format!("{:02x}", byte)).collect());
+    Some(format!(
+        "{},{}:{},{}:{}",
+        fields,
+        RELAY_HOPS_KEY,
+        hops + 1,
+        RELAY_VIA_KEY,
+        via.join("+")
+    ))
+}
@@ -0,0 +1,104 @@
+//! 送信完了コールバックまでの往復時間（RTT）の推定と待機時間の決定
+//!
+//! 固定の待機時間では、LRモードや混雑したチャンネルでACKが遅いときに届いたチャンクを
+//! タイムアウト（失敗）と扱い、不要に間隔を延ばしてしまいます。チャンクを送信してから
+//! ゲートウェイのACKを伝える送信完了コールバックまでの時間を測り、TCP（RFC 6298）と同じく
+//! 平滑化RTT（SRTT）とRTTのばらつき（RTTVAR）から待機時間（RTO）を決めます。
+//!
+//! - 同時に複数のフレームを送ったチャンク（重要チャンクの重複送信・FECパリティ）は
+//!   どのフレームのACKか区別できないため測定に使わない（Karnのアルゴリズム）
+//! - タイムアウトしたら次の測定まで待機時間を倍にする
+//! - 推定値はRTCメモリに保持し、次のサイクルの最初のチャンクから使う
+
+/// 待機時間の下限（ミリ秒）
+pub const MIN_ACK_TIMEOUT_MS: u32 = 10;
+/// 待機時間の上限（ミリ秒）
+pub const MAX_ACK_TIMEOUT_MS: u32 = 1000;
+/// タイムアウトが続いたときに待機時間を倍にする回数の上限
+const MAX_BACKOFF_SHIFT: u8 = 6;
+
+/// RTTの推定値（マイクロ秒）
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RttEstimator {
+    /// 平滑化RTT（測定がなければNone）
+    srtt_us: Option<u32>,
+    /// RTTのばらつき
+    rttvar_us: u32,
+    /// タイムアウトで待機時間を倍にした回数（測定できたら0に戻す）
+    backoff_shift: u8,
+}
+
+impl RttEstimator {
+    /// 測定のない状態を作成します
+    pub const fn new() -> Self {
+        Self {
+            srtt_us: None,
+            rttvar_us: 0,
+            backoff_shift: 0,
+        }
+    }
+
+    /// RTCメモリに保持した値から復元します（`srtt_us` が0なら測定のない状態）
+    pub fn restore(srtt_us: u32, rttvar_us: u32) -> Self {
+        Self {
+            srtt_us: (srtt_us > 0).then_some(srtt_us),
+            rttvar_us,
+            backoff_shift: 0,
+        }
+    }
+
+    /// RTCメモリに保持する値（SRTT, RTTVAR、測定がなければSRTTは0）
+    pub fn to_rtc(self) -> (u32, u32) {
+        (self.srtt_us.unwrap_or(0), self.rttvar_us)
+    }
+
+    /// 平滑化RTT（マイクロ秒）
+    pub fn srtt_us(&self) -> Option<u32> {
+        self.srtt_us
+    }
+
+    /// RTTのばらつき（マイクロ秒）
+    pub fn rttvar_us(&self) -> u32 {
+        self.rttvar_us
+    }
+
+    /// 送信から送信完了コールバックまでの時間を反映します
+    pub fn record_sample(&mut self, rtt_us: u32) {
+        let rtt_us = rtt_us.max(1);
+        match self.srtt_us {
+            None => {
+                self.srtt_us = Some(rtt_us);
+                self.rttvar_us = rtt_us / 2;
+            }
+            Some(srtt_us) => {
+                // RTTVAR = 3/4 RTTVAR + 1/4 |SRTT - R|、SRTT = 7/8 SRTT + 1/8 R
+                let error_us = srtt_us.abs_diff(rtt_us);
+                self.rttvar_us = ((u64::from(self.rttvar_us) * 3 + u64::from(error_us)) / 4) as u32;
+                self.srtt_us = Some(((u64::from(srtt_us) * 7 + u64::from(rtt_us)) / 8) as u32);
+            }
+        }
+        self.backoff_shift = 0;
+    }
+
+    /// 時間内に送信完了コールバックが返らなかったことを反映します（次の測定まで待機時間を倍にする）
+    pub fn record_timeout(&mut self) {
+        self.backoff_shift = self.backoff_shift.saturating_add(1).min(MAX_BACKOFF_SHIFT);
+    }
+
+    /// 送信完了コールバックを待つ時間（ミリ秒）
+    ///
+    /// SRTT + 4×RTTVAR を切り上げ、下限と上限の間に収めます。測定がなければ `initial_ms`
+    /// （設定の `esp_now_send_cb_timeout_ms`）を使います。
+    pub fn timeout_ms(&self, initial_ms: u32) -> u32 {
+        let base_ms = match self.srtt_us {
+            Some(srtt_us) => {
+                let rto_us = u64::from(srtt_us) + 4 * u64::from(self.rttvar_us);
+                rto_us.div_ceil(1000).min(u64::from(MAX_ACK_TIMEOUT_MS)) as u32
+            }
+            None => initial_ms,
+        };
+        base_ms
+            .saturating_mul(1 << self.backoff_shift)
+            .clamp(MIN_ACK_TIMEOUT_MS, MAX_ACK_TIMEOUT_MS)
+    }
+}
@@ -1,20 +1,158 @@
 use crate::mac_address::MacAddress;
 use crate::communication::esp_now::frame_codec::{
-    build_hash_payload, build_sensor_data_frame, calculate_xor_checksum, payload_size_candidates,
-    ESP_NOW_MAX_SIZE, FRAME_OVERHEAD,
+    build_hash_payload, build_sensor_data_frame, calculate_xor_checksum, payload_size_plan,
+    FrameType, ESP_NOW_MAX_SIZE, FRAME_OVERHEAD,
 };
+use crate::communication::esp_now::channel_hop::HopPlan;
+#[cfg(feature = "chaos")]
+use crate::communication::esp_now::chaos::{ChaosInjector, ChaosParams};
+use crate::communication::esp_now::fec::{encode_group_parity, FecParams, FEC_PARITY_HEADER_LEN};
+use crate::communication::esp_now::pacing::{ChunkPacer, PacingParams, SendOutcome};
+use crate::communication::esp_now::privacy::{build_dummy_frame, pad_frame, PrivacyParams};
+use crate::communication::esp_now::probe::{defer_wait_ms, encode_ping, ProbeOutcome, ProbeReply, RejectReason};
+use crate::communication::esp_now::receiver::EspNowReceiver;
+use crate::communication::esp_now::relay::RelayedTransfer;
+use crate::communication::esp_now::rtt::RttEstimator;
+use crate::communication::network_manager::NetworkManager;
+use crate::sys_wrappers::esp::{self as sys, EspSys};
+use crate::sys_wrappers::{sta_mac_or_fallback, Entropy};
+use crate::units::{Celsius, Millivolts, Percent};
 use crate::communication::esp_now::retry_policy::{
     no_mem_retry_delay_ms, retry_count_for_chunk, retry_delay_ms,
 };
 use esp_idf_svc::hal::delay::FreeRtos;
 use esp_idf_svc::espnow::EspNow;
 use log::{error, info, warn};
-use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU8, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
 
 // ESP-NOW関連定数
 /// ESP-NOWメモリ不足エラーコード
 const ESP_ERR_ESPNOW_NO_MEM: i32 = 12391;
 
+/// ゲートウェイが報告した前回の転送の損失率（%、HASHフレームへの応答でギャップマップから求めた値）
+/// 次回起動時のFECパラメータ決定に使うため、RTCメモリに保持します。
+#[link_section = ".rtc.data"]
+static LAST_SESSION_LOSS_PERCENT: AtomicU8 = AtomicU8::new(0);
+
+/// 前回の転送の損失率（%）を取得します
+pub fn last_session_loss_percent() -> u8 {
+    LAST_SESSION_LOSS_PERCENT.load(Ordering::Relaxed)
+}
+
+/// ゲートウェイが報告した損失率（%）を次回起動用に保存します
+pub fn store_session_loss_percent(loss_percent: u8) {
+    LAST_SESSION_LOSS_PERCENT.store(loss_percent.min(100), Ordering::Relaxed);
+}
+
+/// 前回までに測定した送信完了コールバックまでの平滑化RTT（マイクロ秒、0は測定なし）とばらつき
+/// 次回起動時の最初のチャンクから待機時間に使うため、RTCメモリに保持します。
+#[link_section = ".rtc.data"]
+static LAST_ACK_SRTT_US: AtomicU32 = AtomicU32::new(0);
+#[link_section = ".rtc.data"]
+static LAST_ACK_RTTVAR_US: AtomicU32 = AtomicU32::new(0);
+
+/// 前回までの送信完了コールバックまでの往復時間の推定値を取得します
+fn last_ack_rtt() -> RttEstimator {
+    RttEstimator::restore(
+        LAST_ACK_SRTT_US.load(Ordering::Relaxed),
+        LAST_ACK_RTTVAR_US.load(Ordering::Relaxed),
+    )
+}
+
+/// 往復時間の推定値を次回起動用に保存します
+fn store_ack_rtt(rtt: &RttEstimator) {
+    let (srtt_us, rttvar_us) = rtt.to_rtc();
+    LAST_ACK_SRTT_US.store(srtt_us, Ordering::Relaxed);
+    LAST_ACK_RTTVAR_US.store(rttvar_us, Ordering::Relaxed);
+}
+
+/// light sleepが拒否された（以降のチャンク間隔は通常の遅延で待つ）
+static LIGHT_SLEEP_REJECTED: AtomicBool = AtomicBool::new(false);
+
+/// チャンク間の待機時間を、設定に応じてlight sleepと通常の遅延で待ちます
+fn wait_chunk_gap(pacer: &mut ChunkPacer, gap_ms: u32, outcome: SendOutcome) {
+    let mut wait = pacer.plan_gap(gap_ms, outcome);
+    if wait.sleep_ms > 0 && !LIGHT_SLEEP_REJECTED.load(Ordering::Relaxed) {
+        match sys::light_sleep(u64::from(wait.sleep_ms) * 1_000) {
+            Ok(()) => pacer.record_sleep(wait.sleep_ms),
+            Err(e) => {
+                warn!("light sleepに入れません (error={})。チャンク間隔は通常の待機に戻します", e.code());
+                LIGHT_SLEEP_REJECTED.store(true, Ordering::Relaxed);
+                wait.delay_ms += wait.sleep_ms;
+            }
+        }
+    } else {
+        wait.delay_ms += wait.sleep_ms;
+    }
+    if wait.delay_ms > 0 {
+        FreeRtos::delay_ms(wait.delay_ms);
+    }
+}
+
+/// 送信を受け付けたフレーム数（送信完了コールバックとの突き合わせに使う）
+static SEND_ISSUED: AtomicU32 = AtomicU32::new(0);
+/// 送信完了コールバックを受けたフレーム数
+static SEND_COMPLETED: AtomicU32 = AtomicU32::new(0);
+/// 送信完了コールバックで失敗（ゲートウェイからのACKなし）が報告されたフレーム数
+static SEND_CB_FAILURES: AtomicU32 = AtomicU32::new(0);
+
+/// ESP-NOW送信完了コールバック
+extern "C" fn esp_now_send_cb(_mac_addr: *const u8, status: esp_idf_sys::esp_now_send_status_t) {
+    if status != esp_idf_sys::esp_now_send_status_t_ESP_NOW_SEND_SUCCESS {
+        SEND_CB_FAILURES.fetch_add(1, Ordering::SeqCst);
+    }
+    SEND_COMPLETED.fetch_add(1, Ordering::SeqCst);
+}
+
+/// 疎通確認の失敗で転送を見送ったサイクル数（次回の転送成功時に報告してリセット）
+#[link_section = ".rtc.data"]
+static PROBE_SKIPPED_CYCLES: AtomicU32 = AtomicU32::new(0);
+
+/// 疎通確認の失敗による転送見送りを記録します
+pub fn record_probe_skip() -> u32 {
+    PROBE_SKIPPED_CYCLES.fetch_add(1, Ordering::Relaxed).saturating_add(1)
+}
+
+/// 記録済みの転送見送り回数
+pub fn probe_skipped_cycles() -> u32 {
+    PROBE_SKIPPED_CYCLES.load(Ordering::Relaxed)
+}
+
+/// 転送成功後に見送り回数をリセットします
+pub fn clear_probe_skips() {
+    PROBE_SKIPPED_CYCLES.store(0, Ordering::Relaxed);
+}
+
+/// 起床後の疎通確認で試すチャンネルの計画（チャンネルホッピング）
+#[link_section = ".rtc.data"]
+static mut HOP_PLAN: HopPlan = HopPlan::new();
+
+/// 今回の起動から疎通確認までの時間（次の起床時のチャンネルの計画に使う）
+static PROBE_UPTIME_MS: AtomicU32 = AtomicU32::new(0);
+
+/// 疎通確認したチャンネルのメタデータ（`,HOP_CH:...` 形式。チャンネルホッピングが無効なら空）
+pub fn hop_metadata_fields() -> String {
+    // メインタスクからのみアクセスする
+    unsafe { (*std::ptr::addr_of!(HOP_PLAN)).metadata_fields() }
+}
+
+/// 最後のPongの予定から、`sleep_duration_sec` 後の起床時に最初に試すチャンネルを計画します
+///
+/// 起床から疎通確認までの時間は今回の起動と同じとみなします。Pongに予定がなければ前回の計画を保持します。
+pub fn plan_hop_wake_channel(sleep_duration_sec: u64) {
+    let Some((schedule, received_at)) = EspNowReceiver::hop_schedule() else {
+        return;
+    };
+    let ms_until_probe = (received_at.elapsed().as_millis() as u64)
+        .saturating_add(sleep_duration_sec.saturating_mul(1000))
+        .saturating_add(PROBE_UPTIME_MS.load(Ordering::Relaxed) as u64);
+    // メインタスクからのみアクセスする
+    let plan = unsafe { &mut *std::ptr::addr_of_mut!(HOP_PLAN) };
+    plan.plan(&schedule, ms_until_probe);
+    info!("次の起床時の疎通確認チャンネル: {:?}", plan.probe_channels());
+}
+
 /// ESP-NOW送信エラー
 #[derive(Debug, thiserror::Error)]
 pub enum EspNowError {
@@ -29,6 +167,9 @@ pub enum EspNowError {
 
     #[error("送信タイムアウトエラー")]
     SendTimeout,
+
+    #[error("ゲートウェイに拒否されました: {}", .0.as_str())]
+    Rejected(RejectReason),
 }
 
 /// ESP-NOW送信機
@@ -36,6 +177,21 @@ pub struct EspNowSender {
     esp_now: Arc<Mutex<EspNow<'static>>>,
     peer_mac: MacAddress,
     sequence_number: Mutex<u32>,
+    fec_params: Option<FecParams>,
+    privacy_params: Option<PrivacyParams>,
+    pacing_params: PacingParams,
+    privacy_active: AtomicBool,
+    /// ゲートウェイにチャンネルホッピングの予定を要求する
+    channel_hop: bool,
+    /// ゲートウェイにHASHフレームへの応答（コマンド待機の短縮）を要求する
+    command_window: bool,
+    /// ゲートウェイが通知したDATAチャンクの推奨ペイロード長（0は通知なし）
+    advertised_chunk_size: AtomicU32,
+    /// 中継中の子機のMACアドレス（送信するフレームのMACアドレスに使う）
+    relay_origin: Mutex<Option<[u8; 6]>>,
+    /// 送信フレームへの故障注入
+    #[cfg(feature = "chaos")]
+    chaos: Mutex<Option<ChaosInjector>>,
 }
 
 impl EspNowSender {
@@ -45,11 +201,67 @@ impl EspNowSender {
             esp_now,
             peer_mac,
             sequence_number: Mutex::new(1),
+            fec_params: None,
+            privacy_params: None,
+            pacing_params: PacingParams::default(),
+            privacy_active: AtomicBool::new(false),
+            channel_hop: false,
+            command_window: false,
+            advertised_chunk_size: AtomicU32::new(0),
+            relay_origin: Mutex::new(None),
+            #[cfg(feature = "chaos")]
+            chaos: Mutex::new(None),
         };
         sender.add_peer(&sender.peer_mac)?;
+        if let Err(e) = sys::register_esp_now_send_cb(Some(esp_now_send_cb)) {
+            warn!("ESP-NOW送信完了コールバックの登録に失敗しました (error={})", e.code());
+        }
         Ok(sender)
     }
 
+    /// 画像チャンク送信に使うFECパラメータを設定します（`None`で無効）
+    pub fn set_fec_params(&mut self, fec_params: Option<FecParams>) {
+        self.fec_params = fec_params;
+    }
+
+    /// 送信完了コールバックに合わせたチャンク間隔を設定します
+    pub fn set_pacing_params(&mut self, pacing_params: PacingParams) {
+        self.pacing_params = pacing_params;
+    }
+
+    /// プライバシーモードを要求します（`None`で無効）
+    ///
+    /// 実際に有効になるのは、疎通確認のPongでゲートウェイが受け入れた後です。
+    pub fn set_privacy_params(&mut self, privacy_params: Option<PrivacyParams>) {
+        self.privacy_params = privacy_params;
+    }
+
+    /// チャンネルホッピングを有効にします
+    ///
+    /// Pingで予定を要求し、前回のPongの予定から計画したチャンネルで疎通確認します。
+    pub fn set_channel_hop(&mut self, enabled: bool) {
+        self.channel_hop = enabled;
+    }
+
+    /// コマンド待機の短縮を有効にします（Pingでゲートウェイに示す）
+    pub fn set_command_window(&mut self, enabled: bool) {
+        self.command_window = enabled;
+    }
+
+    /// 送信フレームへの故障注入を設定します（`None`で無効）
+    #[cfg(feature = "chaos")]
+    pub fn set_chaos_params(&mut self, params: Option<ChaosParams>) {
+        if let Some(params) = params {
+            warn!("故障注入有効: {}", params.encode());
+        }
+        *self.chaos.get_mut().unwrap_or_else(PoisonError::into_inner) = params.map(ChaosInjector::new);
+    }
+
+    /// ゲートウェイが受け入れたプライバシーモードの設定
+    fn active_privacy_params(&self) -> Option<PrivacyParams> {
+        self.privacy_params.filter(|_| self.privacy_active.load(Ordering::Relaxed))
+    }
+
     /// ピアを追加します
     fn add_peer(&self, peer_mac: &MacAddress) -> Result<(), EspNowError> {
         info!("ESP-NOWピア追加: MAC={:02X}:{:02X}:{:02X}:{:02X}:{:02X}:{:02X}", 
@@ -66,7 +278,7 @@ impl EspNowSender {
         };
 
         {
-            let esp_now_guard = self.esp_now.lock().unwrap();
+            let esp_now_guard = self.esp_now.lock().unwrap_or_else(PoisonError::into_inner);
             esp_now_guard.add_peer(peer_info)
                 .map_err(|e| {
                     error!("ESP-NOWピア追加失敗: {:?}", e);
@@ -79,17 +291,57 @@ impl EspNowSender {
     }
 
     /// データを送信
+    ///
+    /// 故障注入が有効な場合は、注入後のフレーム（破棄なら0件）を送信します。
     pub fn send(&self, data: &[u8], _timeout_ms: u32) -> Result<(), EspNowError> {
+        #[cfg(feature = "chaos")]
+        if let Some(frames) = self.inject_chaos(data) {
+            for frame in frames {
+                self.send_frame(&frame)?;
+            }
+            return Ok(());
+        }
+        self.send_frame(data)
+    }
+
+    /// 送信フレームに故障を注入し、送信するフレームを返します（無効ならNone）
+    #[cfg(feature = "chaos")]
+    fn inject_chaos(&self, data: &[u8]) -> Option<Vec<Vec<u8>>> {
+        let mut chaos = self.chaos.lock().unwrap_or_else(PoisonError::into_inner);
+        chaos.as_mut().map(|injector| injector.apply(data))
+    }
+
+    /// 入れ替えのため後ろへ回しているフレームを送信し、注入した故障の件数をログに出します
+    #[cfg(feature = "chaos")]
+    fn flush_chaos(&self) {
+        let (held, stats) = {
+            let mut chaos = self.chaos.lock().unwrap_or_else(PoisonError::into_inner);
+            let Some(injector) = chaos.as_mut() else {
+                return;
+            };
+            (injector.flush(), injector.stats())
+        };
+        if let Some(frame) = held {
+            if let Err(e) = self.send_frame(&frame) {
+                warn!("入れ替えたフレームの送信失敗: {:?}", e);
+            }
+        }
+        info!("故障注入: {}", stats.summary());
+    }
+
+    /// 1フレームをESP-NOWで送信
+    fn send_frame(&self, data: &[u8]) -> Result<(), EspNowError> {
         // データサイズの事前チェック
         if data.len() > 250 {
             error!("ESP-NOWデータサイズ制限超過: {}バイト (最大250バイト)", data.len());
-            return Err(EspNowError::SendFailed(esp_idf_sys::EspError::from(esp_idf_sys::ESP_ERR_INVALID_ARG).unwrap()));
+            return Err(EspNowError::SendFailed(esp_idf_sys::EspError::from_infallible::<{ esp_idf_sys::ESP_ERR_INVALID_ARG }>()));
         }
         
         {
-            let esp_now_guard = self.esp_now.lock().unwrap();
+            let esp_now_guard = self.esp_now.lock().unwrap_or_else(PoisonError::into_inner);
             match esp_now_guard.send(self.peer_mac.0, data) {
                 Ok(()) => {
+                    SEND_ISSUED.fetch_add(1, Ordering::SeqCst);
                     // 正常送信時は詳細ログを出力しない（スパム防止）
                     Ok(())
                 }
@@ -105,6 +357,187 @@ impl EspNowSender {
         }
     }
 
+    /// 送信を受け付けたフレームすべての送信完了コールバックを待ち、結果を返します
+    ///
+    /// `failures_before` はチャンク送信前の失敗数で、待つ間に失敗が報告されれば `Failed` を返します。
+    /// 時間内にそろわなければ、以降の待機が遅れたコールバックを待ち続けないよう数を合わせます。
+    fn wait_send_completion(&self, failures_before: u32, timeout_ms: u32) -> SendOutcome {
+        let started = std::time::Instant::now();
+        loop {
+            let pending = SEND_ISSUED.load(Ordering::SeqCst).wrapping_sub(SEND_COMPLETED.load(Ordering::SeqCst));
+            // 遅れて届いたコールバックで完了数が上回ることがある
+            if pending == 0 || pending > u32::MAX / 2 {
+                return if SEND_CB_FAILURES.load(Ordering::SeqCst) == failures_before {
+                    SendOutcome::Delivered
+                } else {
+                    SendOutcome::Failed
+                };
+            }
+            if started.elapsed().as_millis() >= u128::from(timeout_ms) {
+                SEND_ISSUED.store(SEND_COMPLETED.load(Ordering::SeqCst), Ordering::SeqCst);
+                return SendOutcome::NoCallback;
+            }
+            // コールバックはWi-Fiタスク（より高い優先度）で呼ばれるため、ティック単位で待たずに譲る
+            std::thread::yield_now();
+        }
+    }
+
+    /// 画像転送前にゲートウェイの疎通を確認します
+    ///
+    /// Pingを送信して `timeout_ms` だけPongを待ち、応答がなければ `attempts` 回まで繰り返します。
+    /// Pingには適用中の無線設定を付加し、Pongで返ったゲートウェイの設定と比較します。
+    /// プライバシーモードを要求している場合は、Pongで受け入れられたときに有効にします。
+    /// 延期要求を受けた場合は指示された時間だけ待ってやり直し（試行回数には数えない）、
+    /// 待機時間の合計が `defer_budget_ms` を超える場合と拒否メッセージを受けた場合は転送を見送ります。
+    ///
+    /// チャンネルホッピングが有効で予定を受け取っている場合は、計画したチャンネルから順に
+    /// チャンネルを切り替えて疎通確認し、応答があったチャンネルで転送します。
+    pub fn probe_gateway(
+        &self,
+        receiver: &EspNowReceiver,
+        attempts: u8,
+        timeout_ms: u32,
+        defer_budget_ms: u32,
+    ) -> ProbeOutcome {
+        PROBE_UPTIME_MS.store(
+            (sys::uptime_us() / 1000) as u32,
+            Ordering::Relaxed,
+        );
+        // メインタスクからのみアクセスする
+        let plan = unsafe { &mut *std::ptr::addr_of_mut!(HOP_PLAN) };
+        let channels = if self.channel_hop { plan.probe_channels() } else { Vec::new() };
+        if channels.is_empty() {
+            return self.probe_current_channel(receiver, attempts, timeout_ms, defer_budget_ms);
+        }
+        let mut total_attempts = 0u8;
+        for channel in channels {
+            if let Err(e) = NetworkManager::set_channel(channel) {
+                warn!("{:?}", e);
+                continue;
+            }
+            match self.probe_current_channel(receiver, attempts, timeout_ms, defer_budget_ms) {
+                ProbeOutcome::Unreachable { attempts: tried } => {
+                    warn!("チャンネル {} ではゲートウェイに届きませんでした", channel);
+                    total_attempts = total_attempts.saturating_add(tried);
+                }
+                outcome => {
+                    info!("チャンネル {} でゲートウェイに到達しました", channel);
+                    plan.record_probe(Some(channel));
+                    return outcome;
+                }
+            }
+        }
+        plan.record_probe(None);
+        ProbeOutcome::Unreachable { attempts: total_attempts }
+    }
+
+    /// 現在のチャンネルでゲートウェイの疎通を確認します
+    fn probe_current_channel(
+        &self,
+        receiver: &EspNowReceiver,
+        attempts: u8,
+        timeout_ms: u32,
+        defer_budget_ms: u32,
+    ) -> ProbeOutcome {
+        let local_radio = NetworkManager::applied_radio_settings();
+        let request_privacy = self.privacy_params.is_some();
+        let mut deferred_ms = 0u32;
+        let mut attempt = 0u8;
+        while attempt < attempts {
+            attempt += 1;
+            let nonce = EspSys.random_u32();
+            EspNowReceiver::clear_pong();
+            let started = std::time::Instant::now();
+            let ping = encode_ping(
+                nonce,
+                local_radio.as_ref(),
+                request_privacy,
+                self.channel_hop,
+                self.command_window,
+                true,
+                true,
+                true,
+            );
+            if let Err(e) = self.send(&ping, timeout_ms) {
+                warn!("Ping送信に失敗しました (試行 {}/{}): {:?}", attempt, attempts, e);
+                continue;
+            }
+            let pong = match receiver.wait_for_probe_reply(nonce, timeout_ms) {
+                Some(ProbeReply::Pong(pong)) => pong,
+                Some(ProbeReply::Reject(reject)) => {
+                    warn!(
+                        "ゲートウェイに拒否されたため転送を見送ります（理由 {}、再試行まで {}ms）",
+                        reject.reason.as_str(),
+                        reject.retry_after_ms
+                    );
+                    return ProbeOutcome::Rejected {
+                        reason: reject.reason,
+                        retry_after_ms: reject.retry_after_ms,
+                    };
+                }
+                Some(ProbeReply::Defer(defer)) => {
+                    let Some(wait_ms) = defer_wait_ms(deferred_ms, defer.retry_after_ms, defer_budget_ms) else {
+                        warn!(
+                            "ゲートウェイが混雑しているため転送を見送ります（待機 {}ms、次の指示 {}ms、上限 {}ms）",
+                            deferred_ms, defer.retry_after_ms, defer_budget_ms
+                        );
+                        return ProbeOutcome::Deferred { deferred_ms };
+                    };
+                    info!("ゲートウェイが混雑しているため {}ms 待って再試行します", wait_ms);
+                    FreeRtos::delay_ms(wait_ms);
+                    deferred_ms += wait_ms;
+                    attempt -= 1;
+                    continue;
+                }
+                None => {
+                    warn!("Pong応答なし (試行 {}/{}, {}ms)", attempt, attempts, timeout_ms);
+                    continue;
+                }
+            };
+            let rtt_ms = started.elapsed().as_millis() as u32;
+            info!(
+                "ゲートウェイ疎通OK: RTT={}ms, 試行={}, キュー空き={}%",
+                rtt_ms, attempt, pong.queue_free_percent
+            );
+            let mut radio_mismatch = false;
+            if let (Some(local), Some(gateway)) = (local_radio, pong.radio) {
+                let mismatches = local.mismatches(&gateway);
+                if !mismatches.is_empty() {
+                    warn!(
+                        "ゲートウェイと無線設定が一致しません ({}): 自機 {}, ゲートウェイ {}",
+                        mismatches.join(","),
+                        local.summary(),
+                        gateway.summary()
+                    );
+                    radio_mismatch = true;
+                }
+            }
+            if request_privacy {
+                if pong.privacy {
+                    info!("プライバシーモード有効（フレームの詰め物とダミーフレーム）");
+                } else {
+                    warn!("ゲートウェイがプライバシーモードに対応していないため、通常の送信を行います");
+                }
+                self.privacy_active.store(pong.privacy, Ordering::Relaxed);
+            }
+            if let Some(chunk_size) = pong.chunk_size {
+                info!("ゲートウェイのチャンクサイズ: 推奨{}バイト, 上限{}バイト", chunk_size.preferred, chunk_size.max);
+            }
+            self.advertised_chunk_size.store(
+                pong.chunk_size.map_or(0, |chunk_size| u32::from(chunk_size.preferred)),
+                Ordering::Relaxed,
+            );
+            return ProbeOutcome::Reachable {
+                rtt_ms,
+                attempts: attempt,
+                queue_free_percent: pong.queue_free_percent,
+                radio_mismatch,
+                deferred_ms,
+            };
+        }
+        ProbeOutcome::Unreachable { attempts }
+    }
+
     /// リトライ機能付きのデータ送信（メモリ不足対策強化版）
     pub fn send_with_retry(
         &self,
@@ -172,17 +605,31 @@ impl EspNowSender {
     }
 
     /// 画像データをチャンクに分割して送信する（アダプティブ実装）
+    ///
+    /// チャンクごとに送信完了コールバックを待ち、成功なら `delay_between_chunks_ms` だけ空けて次を送り、
+    /// 失敗やタイムアウトが続くと間隔を延ばします（`pacing`）。
+    /// 送信中にゲートウェイから拒否メッセージを受けた場合は打ち切ります（拒否は受信側に残し、呼び出し元が取り出す）。
     pub fn send_image_chunks(
         &self,
-        data: Vec<u8>,
+        data: &[u8],
         initial_chunk_size: usize,
         delay_between_chunks_ms: u32,
     ) -> Result<(), EspNowError> {
         // 有効なペイロードサイズを計算
-        // 段階的にペイロードサイズを小さくして試行
-        let payload_sizes = payload_size_candidates(initial_chunk_size);
+        // ゲートウェイが大きさを通知していればその大きさ、なければ段階的にペイロードサイズを小さくして試行
+        let advertised = match self.advertised_chunk_size.load(Ordering::Relaxed) {
+            0 => None,
+            size => Some(size as usize),
+        };
+        let payload_sizes = payload_size_plan(initial_chunk_size, advertised);
 
-        for &payload_size in &payload_sizes {
+        for &candidate_size in &payload_sizes {
+            // FEC有効時はパリティヘッダー分だけデータペイロードを小さくする
+            let payload_size = if self.fec_params.is_some() {
+                candidate_size.min(ESP_NOW_MAX_SIZE - FRAME_OVERHEAD - FEC_PARITY_HEADER_LEN)
+            } else {
+                candidate_size
+            };
             let total_frame_size = FRAME_OVERHEAD + payload_size;
             if total_frame_size > ESP_NOW_MAX_SIZE {
                 continue;
@@ -198,6 +645,14 @@ impl EspNowSender {
 
             let mut success = true;
 
+            if let Some(params) = self.fec_params {
+                info!("FEC有効: K={}, M={}", params.data_chunks, params.parity_chunks);
+                self.send_fec_announce(params);
+            }
+            let mut fec_group: Vec<&[u8]> = Vec::new();
+            let mut fec_group_first_seq = 0;
+            let mut pacer = ChunkPacer::new(delay_between_chunks_ms, self.pacing_params).with_rtt(last_ack_rtt());
+
             for (i, chunk) in data.chunks(payload_size).enumerate() {
                 if i % 20 == 0 { // 20チャンクごとに進捗表示
                     info!("チャンク送信進捗: {}/{}", i + 1, total_chunks);
@@ -208,14 +663,10 @@ impl EspNowSender {
                     info!("最初のチャンク詳細: サイズ={}バイト, プレビュー={:02X?}", chunk.len(), &chunk[..std::cmp::min(10, chunk.len())]);
                 }
 
-                let frame = match self.create_sensor_data_frame(2, chunk) { // FRAME_TYPE_DATA = 2
-                    Ok(f) => f,
-                    Err(e) => {
-                        error!("チャンク{} フレーム作成失敗: {:?}", i + 1, e);
-                        success = false;
-                        break;
-                    }
-                };
+                let (sequence, frame) = self.create_sequenced_frame(FrameType::Data, chunk);
+                if fec_group.is_empty() {
+                    fec_group_first_seq = sequence;
+                }
                 
                 // 重要なチャンク（最初の3チャンク）は重複送信で信頼性向上
                 let retry_count = retry_count_for_chunk(i);
@@ -223,8 +674,12 @@ impl EspNowSender {
                     warn!("重要チャンク{}: 信頼性向上のため複数回送信", i + 1);
                 }
                 
+                let failures_before = SEND_CB_FAILURES.load(Ordering::SeqCst);
+                let sent_at = std::time::Instant::now();
                 let mut chunk_success = false;
+                let mut attempts_used = 0;
                 for attempt in 1..=retry_count {
+                    attempts_used = attempt;
                     match self.send_with_retry(&frame, 1000, 3) {
                         Ok(()) => {
                             if retry_count > 1 {
@@ -248,21 +703,67 @@ impl EspNowSender {
                     success = false;
                     break;
                 }
+
+                let mut parity_sent = false;
+                if let Some(params) = self.fec_params {
+                    fec_group.push(chunk);
+                    if fec_group.len() == params.data_chunks as usize {
+                        self.send_fec_parity(fec_group_first_seq, &fec_group, params);
+                        fec_group.clear();
+                        parity_sent = true;
+                    }
+                }
                 
-                // チャンク間の遅延
-                FreeRtos::delay_ms(delay_between_chunks_ms);
+                // 送信完了コールバックの結果に合わせたチャンク間隔（1フレームだけ送ったチャンクで往復時間を測る）
+                let outcome = self.wait_send_completion(failures_before, pacer.callback_timeout_ms());
+                if outcome == SendOutcome::Delivered && attempts_used == 1 && !parity_sent {
+                    pacer.record_rtt(sent_at.elapsed().as_micros().min(u128::from(u32::MAX)) as u32);
+                }
+                let gap_ms = pacer.record(outcome);
+                if outcome != SendOutcome::Delivered && pacer.consecutive_failures() == 1 {
+                    warn!("チャンク{}の送信完了: {:?}、間隔を延ばします", i + 1, outcome);
+                }
+                wait_chunk_gap(&mut pacer, gap_ms, outcome);
+                self.send_privacy_dummies();
+
+                // ゲートウェイがデータを破棄した（小さなチャンクで再試行しても受け付けられない）
+                if let Some(reject) = EspNowReceiver::pending_reject() {
+                    warn!(
+                        "チャンク{}の送信後にゲートウェイから拒否されたため転送を打ち切ります（理由 {}）",
+                        i + 1,
+                        reject.reason.as_str()
+                    );
+                    store_ack_rtt(&pacer.rtt());
+                    return Err(EspNowError::Rejected(reject.reason));
+                }
             }
+            store_ack_rtt(&pacer.rtt());
             
             if success {
+                if let Some(params) = self.fec_params {
+                    if !fec_group.is_empty() {
+                        self.send_fec_parity(fec_group_first_seq, &fec_group, params);
+                    }
+                }
                 info!("画像データ送信完了: {}チャンク送信 (ペイロードサイズ: {}バイト)", total_chunks, payload_size);
+                info!(
+                    "チャンク送信完了コールバック: {}, SRTT={}µs, RTTVAR={}µs, 待機時間={}ms",
+                    pacer.stats().summary(),
+                    pacer.rtt().srtt_us().map_or_else(|| "-".to_string(), |srtt_us| srtt_us.to_string()),
+                    pacer.rtt().rttvar_us(),
+                    pacer.callback_timeout_ms()
+                );
                 return Ok(());
             } else {
+                // 送信済みのチャンクは再試行分と混ざらないようゲートウェイで破棄させる
+                self.send_abort_frame("RETRY");
                 warn!("ペイロードサイズ{}バイトで送信失敗、より小さなサイズで再試行します", payload_size);
                 FreeRtos::delay_ms(1000); // 再試行前の待機
             }
         }
         
         error!("全てのチャンクサイズで送信失敗");
+        self.send_abort_frame("SEND_FAILED");
         Err(EspNowError::SendTimeout)
     }
 
@@ -270,30 +771,60 @@ impl EspNowSender {
     pub fn send_hash_frame(
         &self,
         hash: &str,
-        voltage_percentage: u8,
-        temperature_celsius: Option<f32>,
-        tds_voltage: Option<f32>,
+        voltage_percentage: Percent,
+        temperature: Option<Celsius>,
+        tds_voltage: Option<Millivolts>,
         timestamp: &str,
+        metadata_fields: &str,
     ) -> Result<(), EspNowError> {
-        let hash_data = build_hash_payload(
+        let mut hash_data = build_hash_payload(
             hash,
             voltage_percentage,
-            temperature_celsius,
+            temperature,
             tds_voltage,
             timestamp,
         );
+        hash_data.push_str(metadata_fields);
+        self.send_hash_payload(&hash_data)
+    }
+
+    /// 組み立て済みのHASHペイロードを送信
+    fn send_hash_payload(&self, hash_data: &str) -> Result<(), EspNowError> {
         info!("ハッシュフレーム送信（sensor_data_receiver準拠）: {}", hash_data);
 
-        let frame = self.create_sensor_data_frame(1, hash_data.as_bytes())?; // FRAME_TYPE_HASH = 1
+        let frame = self.create_sensor_data_frame(FrameType::Hash, hash_data.as_bytes())?;
         self.send_with_retry(&frame, 1000, 3)?;
         Ok(())
     }
 
+    /// 中継した子機の転送を、子機のMACアドレスのフレームとして送り直します
+    ///
+    /// チャンク・HASH・EOFの順に、自分のチャンクサイズとFEC設定で送信します。
+    pub fn send_relayed_transfer(
+        &self,
+        transfer: RelayedTransfer,
+        initial_chunk_size: usize,
+        delay_between_chunks_ms: u32,
+    ) -> Result<(), EspNowError> {
+        info!(
+            "中継転送を開始: 子機={}, {} bytes",
+            MacAddress::new(transfer.origin_mac),
+            transfer.image.len()
+        );
+        *self.relay_origin.lock().unwrap_or_else(PoisonError::into_inner) = Some(transfer.origin_mac);
+        let result = self
+            .send_image_chunks(&transfer.image, initial_chunk_size, delay_between_chunks_ms)
+            .and_then(|()| self.send_hash_payload(&transfer.hash_payload))
+            .and_then(|()| self.send_eof_marker());
+        *self.relay_origin.lock().unwrap_or_else(PoisonError::into_inner) = None;
+        result
+    }
+
     /// 画像送信終了マーカーを送信
     pub fn send_eof_marker(&self) -> Result<(), EspNowError> {
         info!("EOF フレーム送信開始（sensor_data_receiver準拠）");
 
-        let frame = self.create_sensor_data_frame(3, b"EOF")?; // FRAME_TYPE_EOF = 3
+        let frame = self.create_sensor_data_frame(FrameType::Eof, b"EOF")?;
 
         // 複数回送信で信頼性を向上
         for attempt in 1..=3 {
@@ -315,33 +846,94 @@ impl EspNowSender {
             }
         }
 
+        #[cfg(feature = "chaos")]
+        self.flush_chaos();
+
         info!("EOF フレーム送信完了");
         Ok(())
     }
 
-    fn create_sensor_data_frame(&self, frame_type: u8, data: &[u8]) -> Result<Vec<u8>, EspNowError> {
-        let mac_address = self.get_local_mac_address();
+    /// 転送中断フレームを送信（失敗しても処理は継続）
+    fn send_abort_frame(&self, reason: &str) {
+        let (_, frame) = self.create_sequenced_frame(FrameType::Abort, reason.as_bytes());
+        if let Err(e) = self.send_with_retry(&frame, 1000, 3) {
+            warn!("中断フレーム送信失敗: {:?}", e);
+        }
+    }
+
+    /// プライバシーモード時、チャンクの間に乱数個のダミーフレームを乱数の間隔で送信（失敗しても転送は継続）
+    fn send_privacy_dummies(&self) {
+        let Some(params) = self.active_privacy_params() else {
+            return;
+        };
+        let count = params.dummy_count(EspSys.random_u32());
+        for _ in 0..count {
+            FreeRtos::delay_ms(params.jitter_ms(EspSys.random_u32()));
+            // 画像フレームの連番を消費しないよう、シーケンス番号も乱数にする
+            let (sequence, seed) = (EspSys.random_u32(), EspSys.random_u32());
+            let frame = build_dummy_frame(self.frame_mac_address(), sequence, seed);
+            if let Err(e) = self.send(&frame, 1000) {
+                warn!("ダミーフレーム送信失敗: {:?}", e);
+            }
+        }
+    }
+
+    /// FECセッション告知フレームを送信（失敗してもデータ送信は継続）
+    fn send_fec_announce(&self, params: FecParams) {
+        let (_, frame) = self.create_sequenced_frame(FrameType::Fec, &params.to_announce_payload());
+        if let Err(e) = self.send_with_retry(&frame, 1000, 3) {
+            warn!("FEC告知フレーム送信失敗: {:?}", e);
+        }
+    }
+
+    /// グループのパリティフレームを送信（失敗してもデータ送信は継続）
+    fn send_fec_parity(&self, first_seq: u32, group: &[&[u8]], params: FecParams) {
+        for parity in encode_group_parity(first_seq, group, params.parity_chunks) {
+            let (_, frame) = self.create_sequenced_frame(FrameType::Fec, &parity.to_payload());
+            if let Err(e) = self.send_with_retry(&frame, 1000, 3) {
+                warn!(
+                    "FECパリティ送信失敗 (seq={}, index={}): {:?}",
+                    first_seq, parity.parity_index, e
+                );
+            }
+        }
+    }
+
+    fn create_sensor_data_frame(&self, frame_type: FrameType, data: &[u8]) -> Result<Vec<u8>, EspNowError> {
+        Ok(self.create_sequenced_frame(frame_type, data).1)
+    }
+
+    /// シーケンス番号を払い出してフレームを作成し、番号とフレームを返します
+    ///
+    /// プライバシーモード時はESP-NOWの最大長まで詰めます。
+    fn create_sequenced_frame(&self, frame_type: FrameType, data: &[u8]) -> (u32, Vec<u8>) {
+        let mac_address = self.frame_mac_address();
         let sequence = self.get_next_sequence_number();
-        Ok(build_sensor_data_frame(frame_type, mac_address, sequence, data))
+        let mut frame = build_sensor_data_frame(frame_type, mac_address, sequence, data);
+        if self.active_privacy_params().is_some() {
+            pad_frame(&mut frame);
+        }
+        (sequence, frame)
+    }
+
+    /// フレームに入れるMACアドレス（中継中は子機のMACアドレス）
+    fn frame_mac_address(&self) -> [u8; 6] {
+        self.relay_origin
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .unwrap_or_else(|| self.get_local_mac_address())
     }
 
     fn get_local_mac_address(&self) -> [u8; 6] {
-        let mut mac = [0u8; 6];
-        unsafe {
-            let result = esp_idf_sys::esp_wifi_get_mac(
-                esp_idf_sys::wifi_interface_t_WIFI_IF_STA,
-                mac.as_mut_ptr(),
-            );
-            if result != 0 {
-                warn!("MACアドレス取得失敗、デフォルト値を使用: {:?}", result);
-                mac = [0x24, 0x6F, 0x28, 0x12, 0x34, 0x56];
-            }
+        let (mac, error) = sta_mac_or_fallback(&EspSys);
+        if let Some(e) = error {
+            warn!("MACアドレス取得失敗、デフォルト値を使用: {:?}", e.code());
         }
         mac
     }
 
     fn get_next_sequence_number(&self) -> u32 {
-        let mut guard = self.sequence_number.lock().unwrap();
+        let mut guard = self.sequence_number.lock().unwrap_or_else(PoisonError::into_inner);
         let current = *guard;
         *guard = guard.wrapping_add(1);
         current
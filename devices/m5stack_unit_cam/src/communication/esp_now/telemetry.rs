@@ -0,0 +1,116 @@
+//! HASHペイロードのテレメトリ（タグ付きフィールドの並び）
+//!
+//! HASHペイロードは `KEY:VALUE` をカンマで区切った並びです（撮影時刻のようにキーのない項目も含む）。
+//! 形式のバージョン（`TLM_V`）と機能ビット（`TLM_CAPS`、16進数）を付加し、受け取った側は
+//! 知っているキーだけを引いて、知らない項目は並び順・表記を変えずに扱います。
+//! 中継機は子機のHASHペイロードを解析し直しても、新しいファームウェアの項目をそのまま転送します。
+
+/// このファームウェアのテレメトリのバージョン
+pub const TELEMETRY_VERSION: u32 = 1;
+
+/// バージョンのキー
+pub const TELEMETRY_VERSION_KEY: &str = "TLM_V";
+/// 機能ビットのキー
+pub const TELEMETRY_CAPS_KEY: &str = "TLM_CAPS";
+
+/// 機能ビット: 温度センサーの値がある（なければ `TEMP` はダミー値）
+pub const TELEMETRY_CAP_TEMPERATURE: u32 = 1 << 0;
+/// 機能ビット: TDSセンサーの値がある（なければ `TDS_VOLT` はダミー値）
+pub const TELEMETRY_CAP_TDS: u32 = 1 << 1;
+/// 機能ビット: 中継時に知らない項目をそのまま転送する
+pub const TELEMETRY_CAP_PASSTHROUGH: u32 = 1 << 2;
+
+/// HASHペイロードに付加するバージョンと機能ビット
+pub fn telemetry_header_fields(capabilities: u32) -> String {
+    format!(
+        ",{}:{},{}:{:x}",
+        TELEMETRY_VERSION_KEY, TELEMETRY_VERSION, TELEMETRY_CAPS_KEY, capabilities
+    )
+}
+
+/// テレメトリの1項目
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TelemetryField<'a> {
+    /// `KEY:VALUE` 形式の項目
+    Tagged { key: &'a str, value: &'a str },
+    /// キーのない項目（撮影時刻など）
+    Bare(&'a str),
+}
+
+impl<'a> TelemetryField<'a> {
+    fn parse(item: &'a str) -> Self {
+        match item.split_once(':') {
+            Some((key, value)) if is_key(key) => TelemetryField::Tagged { key, value },
+            _ => TelemetryField::Bare(item),
+        }
+    }
+}
+
+/// キーは英大文字・数字・`_` のみ（`2026/02/11 12:00:00` のような時刻をキーと誤認しない）
+fn is_key(key: &str) -> bool {
+    !key.is_empty() && key.bytes().all(|byte| byte.is_ascii_uppercase() || byte.is_ascii_digit() || byte == b'_')
+}
+
+/// 解析したHASHペイロード（元の並びと表記を保持）
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TelemetryFields<'a> {
+    fields: Vec<TelemetryField<'a>>,
+}
+
+impl<'a> TelemetryFields<'a> {
+    /// HASHペイロードを解析します
+    pub fn parse(payload: &'a str) -> Self {
+        Self {
+            fields: payload.split(',').map(TelemetryField::parse).collect(),
+        }
+    }
+
+    /// すべての項目（元の並び順）
+    pub fn fields(&self) -> &[TelemetryField<'a>] {
+        &self.fields
+    }
+
+    /// キーの値（前後の空白を除く、同じキーが複数あれば最初の値）
+    pub fn get(&self, key: &str) -> Option<&'a str> {
+        self.fields.iter().find_map(|field| match field {
+            TelemetryField::Tagged { key: field_key, value } if *field_key == key => Some(value.trim()),
+            _ => None,
+        })
+    }
+
+    /// キーの項目をすべて取り除き、最初の値を返します
+    pub fn take(&mut self, key: &str) -> Option<&'a str> {
+        let value = self.get(key);
+        self.fields
+            .retain(|field| !matches!(field, TelemetryField::Tagged { key: field_key, .. } if *field_key == key));
+        value
+    }
+
+    /// 形式のバージョン（`TLM_V` がなければ0）
+    pub fn version(&self) -> u32 {
+        self.get(TELEMETRY_VERSION_KEY).and_then(|value| value.parse().ok()).unwrap_or(0)
+    }
+
+    /// 機能ビット（`TLM_CAPS` がなければ0）
+    pub fn capabilities(&self) -> u32 {
+        self.get(TELEMETRY_CAPS_KEY)
+            .and_then(|value| u32::from_str_radix(value, 16).ok())
+            .unwrap_or(0)
+    }
+}
+
+impl std::fmt::Display for TelemetryFields<'_> {
+    /// ペイロードに戻します（取り除いた項目以外は解析前と一致する）
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (index, field) in self.fields.iter().enumerate() {
+            if index > 0 {
+                f.write_str(",")?;
+            }
+            match field {
+                TelemetryField::Tagged { key, value } => write!(f, "{}:{}", key, value)?,
+                TelemetryField::Bare(item) => f.write_str(item)?,
+            }
+        }
+        Ok(())
+    }
+}
@@ -6,8 +6,13 @@ use esp_idf_svc::{
 };
 use esp_idf_svc::hal::modem::Modem;
 use log::info;
-use std::sync::{Arc, Mutex};
-use crate::communication::esp_now::EspNowReceiver;
+use std::sync::{Arc, Mutex, OnceLock};
+use crate::communication::esp_now::{EspNowRate, EspNowReceiver, RadioSettings};
+use crate::sys_wrappers::esp::{self as sys, EspSys};
+use crate::sys_wrappers::WifiRadio;
+
+/// 起動時に適用した無線設定（疎通確認のPingに付加）
+static APPLIED_RADIO_SETTINGS: OnceLock<RadioSettings> = OnceLock::new();
 
 /// WiFiとESP-NOWの初期化を管理するモジュール
 pub struct NetworkManager;
@@ -19,6 +24,7 @@ impl NetworkManager {
         sysloop: &EspSystemEventLoop,
         nvs_partition: &EspDefaultNvsPartition,
         wifi_tx_power_dbm: i8,
+        esp_now_phy_rate: Option<EspNowRate>,
     ) -> anyhow::Result<BlockingWifi<EspWifi<'static>>> {
         info!("ESP-NOW用にWiFiをSTAモードで準備します。");
         
@@ -27,11 +33,9 @@ impl NetworkManager {
             sysloop.clone(),
         )?;
 
-        // 空のSSID/パスワードでWiFiを設定（ESP-NOW用）
+        // 空のSSID/パスワード（既定値）でWiFiを設定（ESP-NOW用）
         wifi.set_configuration(&esp_idf_svc::wifi::Configuration::Client(
             esp_idf_svc::wifi::ClientConfiguration {
-                ssid: "".try_into().unwrap(),
-                password: "".try_into().unwrap(),
                 auth_method: esp_idf_svc::wifi::AuthMethod::None,
                 ..Default::default()
             },
@@ -40,20 +44,28 @@ impl NetworkManager {
         wifi.start()?;
         info!("WiFiがESP-NOW用にSTAモードで起動しました。");
 
-        // WiFi送信パワーを設定（ESP-IDFは0.25dBm単位）
-        unsafe {
-            let scaled: i16 = i16::from(wifi_tx_power_dbm) * 4;
-            let power_quarter_dbm = scaled as i8;
-            let err = esp_idf_svc::sys::esp_wifi_set_max_tx_power(power_quarter_dbm);
-            if err != esp_idf_svc::sys::ESP_OK {
+        // WiFi送信パワーを設定
+        match sys::set_max_tx_power_dbm(wifi_tx_power_dbm) {
+            Ok(()) => info!("WiFi送信パワーを {}dBm に設定しました", wifi_tx_power_dbm),
+            Err(e) => log::warn!(
+                "WiFi送信パワー設定に失敗しました (error={})。デフォルト値で継続します",
+                e.code()
+            ),
+        }
+
+        // ESP-NOWの送信レートを固定（未指定ならESP-IDFの既定値）
+        if let Some(rate) = esp_now_phy_rate {
+            if let Err(e) = sys::set_espnow_rate(rate.phy_rate()) {
                 log::warn!(
-                    "WiFi送信パワー設定に失敗しました (error={})。デフォルト値で継続します",
-                    err
+                    "ESP-NOW送信レート {} の設定に失敗しました (error={})。デフォルト値で継続します",
+                    rate.name(),
+                    e.code()
                 );
-            } else {
-                info!("WiFi送信パワーを {}dBm に設定しました", wifi_tx_power_dbm);
             }
         }
+        let radio_settings = Self::read_radio_settings(esp_now_phy_rate);
+        info!("ESP-NOW無線設定: {}", radio_settings.summary());
+        let _ = APPLIED_RADIO_SETTINGS.set(radio_settings);
 
         // WiFi状態の詳細確認
         let wifi_status = wifi.is_started();
@@ -66,14 +78,37 @@ impl NetworkManager {
               mac_addr[3], mac_addr[4], mac_addr[5]);
 
         // WiFi Power Saveを無効化
-        unsafe {
-            esp_idf_svc::sys::esp_wifi_set_ps(esp_idf_svc::sys::wifi_ps_type_t_WIFI_PS_NONE);
+        if let Err(e) = sys::disable_wifi_power_save() {
+            log::warn!("Wi-Fi Power Save の無効化に失敗しました (error={})", e.code());
         }
         info!("Wi-Fi Power Save を無効化しました (ESP-NOW用)");
 
         Ok(wifi)
     }
 
+    /// 適用中の無線設定（WiFi初期化前は None）
+    pub fn applied_radio_settings() -> Option<RadioSettings> {
+        APPLIED_RADIO_SETTINGS.get().copied()
+    }
+
+    /// 送受信チャンネルを切り替えます（ピアはチャンネル0＝現在のチャンネルで登録済み）
+    pub fn set_channel(channel: u8) -> anyhow::Result<()> {
+        EspSys
+            .set_channel(channel)
+            .map_err(|e| anyhow::anyhow!("チャンネル {} への切り替えに失敗しました (error={})", channel, e.code()))
+    }
+
+    /// 実際に有効な無線設定を読み取ります（AMPDUは sdkconfig の CONFIG_ESP_WIFI_AMPDU_TX_ENABLED）
+    fn read_radio_settings(rate: Option<EspNowRate>) -> RadioSettings {
+        #[allow(unexpected_cfgs)]
+        let ampdu_tx = cfg!(esp_idf_esp_wifi_ampdu_tx_enabled);
+        RadioSettings {
+            rate,
+            tx_power_dbm: sys::max_tx_power_dbm().unwrap_or(0),
+            ampdu_tx,
+        }
+    }
+
     /// ESP-NOW初期化（送信＆受信機能付き）
     pub fn initialize_esp_now(
         _wifi: &BlockingWifi<EspWifi<'static>>,
@@ -81,9 +116,8 @@ impl NetworkManager {
         info!("ESP-NOWを初期化中（送信＆受信機能付き）...");
         
         // ESP-NOWのメモリ設定を最適化
-        unsafe {
-            // ESP-NOWの送信バッファサイズを調整（デフォルトより小さく）
-            esp_idf_svc::sys::esp_wifi_set_storage(esp_idf_svc::sys::wifi_storage_t_WIFI_STORAGE_RAM);
+        if let Err(e) = sys::set_wifi_storage_ram() {
+            log::warn!("WiFi設定の保存先をRAMに変更できませんでした (error={})", e.code());
         }
         
         let esp_now = EspNow::take()?;
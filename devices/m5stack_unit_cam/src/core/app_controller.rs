@@ -1,22 +1,66 @@
 use log::{error, info, warn};
+use std::sync::atomic::{AtomicU32, Ordering};
 use std::sync::Arc;
 
 use crate::core::config::AppConfig;
-use crate::core::resolve_sleep_duration_seconds;
-use crate::communication::esp_now::EspNowReceiver;
+use crate::core::{clamp_sleep_duration_seconds, resolve_sleep_duration_seconds, RtcManager};
+use crate::communication::esp_now::{window_saved_metadata_field, EspNowReceiver, WindowRequest};
 use crate::power::sleep::{DeepSleep, DeepSleepPlatform};
 
+/// 許容範囲外のため補正したスリープコマンドの要求値（0はなし、次回の送信成功時に報告してリセット）
+#[link_section = ".rtc.data"]
+static CLAMPED_SLEEP_REQUEST: AtomicU32 = AtomicU32::new(0);
+
+/// 前回補正したスリープコマンドの要求値（秒）
+pub fn clamped_sleep_request() -> Option<u32> {
+    Some(CLAMPED_SLEEP_REQUEST.load(Ordering::Relaxed)).filter(|&seconds| seconds > 0)
+}
+
+/// 送信成功後に補正の記録をリセットします
+pub fn clear_clamped_sleep_request() {
+    CLAMPED_SLEEP_REQUEST.store(0, Ordering::Relaxed);
+}
+
+/// HASHフレームへの応答でコマンド待機を省略して短縮した時間の累計（ミリ秒、次回の送信成功時に報告してリセット）
+#[link_section = ".rtc.data"]
+static WINDOW_SAVED_MS: AtomicU32 = AtomicU32::new(0);
+
+/// HASHフレームに付加する短縮した待機時間（短縮していなければ空）
+pub fn window_saved_metadata() -> String {
+    window_saved_metadata_field(WINDOW_SAVED_MS.load(Ordering::Relaxed))
+}
+
+/// 送信成功後に短縮した待機時間の記録をリセットします
+pub fn clear_window_saved() {
+    WINDOW_SAVED_MS.store(0, Ordering::Relaxed);
+}
+
 /// アプリケーションの主要な制御フローを管理するモジュール
 pub struct AppController;
 
 impl AppController {
+    /// 送信後にサーバーからのコマンドを待機するか（タイムラプス・デバイス側のスリープ時間固定では待機しない）
+    pub fn waits_for_server_command(config: &AppConfig) -> bool {
+        config.timelapse.is_none() && !config.force_sleep_duration_by_device
+    }
+
     /// スリープコマンドを受信して、Deep Sleep の秒数を決定
+    ///
+    /// 待機中に画像の再送要求やサムネイル要求を受信すると `on_request` を呼び出します。
     pub fn resolve_sleep_duration(
         esp_now_receiver: &EspNowReceiver,
         config: &Arc<AppConfig>,
+        on_request: impl FnMut(WindowRequest),
     ) -> anyhow::Result<u64> {
         info!("=== サーバーからのスリープコマンド待機開始 ===");
         info!("設定されたデフォルトスリープ時間: {}秒", config.sleep_duration_seconds);
+        if let Some(timelapse) = &config.timelapse {
+            info!(
+                "タイムラプス '{}' のため、サーバー応答を待たず撮影間隔 {}秒 を使用します。",
+                timelapse.name, timelapse.interval_seconds
+            );
+            return Ok(timelapse.interval_seconds);
+        }
         if config.force_sleep_duration_by_device {
             warn!(
                 "force_sleep_duration_by_device=true のため、サーバー応答を無視して {}秒 を使用します。",
@@ -24,20 +68,44 @@ impl AppController {
             );
             return Ok(config.sleep_duration_seconds);
         }
+        match EspNowReceiver::take_hash_ack() {
+            Some(ack) if ack.skips_command_window() => {
+                let saved_ms = (config.sleep_command_timeout_seconds * 1000).min(u32::MAX as u64) as u32;
+                let total_ms = WINDOW_SAVED_MS.load(Ordering::Relaxed).saturating_add(saved_ms);
+                WINDOW_SAVED_MS.store(total_ms, Ordering::Relaxed);
+                info!(
+                    "ゲートウェイに送信待ちのコマンドがないため、待機せずにデフォルト時間 {}秒 でスリープします。",
+                    config.sleep_duration_seconds
+                );
+                return Ok(resolve_sleep_duration_seconds(None, config.sleep_duration_seconds));
+            }
+            Some(ack) => info!("ゲートウェイに送信待ちのコマンドがあります（{}件）", ack.pending_count),
+            None => {}
+        }
         info!("スリープコマンド待機タイムアウト: {}秒", config.sleep_command_timeout_seconds);
         
         // ESP-NOW受信状態をリセット（前回の受信データをクリア）
         EspNowReceiver::reset_receiver_state();
         
-        let received = esp_now_receiver.wait_for_sleep_command(config.sleep_command_timeout_seconds as u32);
+        let received = esp_now_receiver.wait_for_sleep_command(config.sleep_command_timeout_seconds as u32, on_request);
         let target_duration = resolve_sleep_duration_seconds(received, config.sleep_duration_seconds);
 
         match received {
             Some(duration_seconds) if duration_seconds > 0 => {
-                info!(
-                    "✓ サーバーからスリープ時間を受信: {}秒。Deep Sleep に入ります。",
-                    duration_seconds
+                let (clamped, requested) = clamp_sleep_duration_seconds(
+                    target_duration,
+                    config.min_sleep_duration_seconds,
+                    config.max_sleep_duration_seconds,
                 );
+                if let Some(requested) = requested {
+                    warn!(
+                        "受信したスリープ時間 {}秒 は許容範囲 ({}-{}秒) 外のため {}秒 に補正します。",
+                        requested, config.min_sleep_duration_seconds, config.max_sleep_duration_seconds, clamped
+                    );
+                    CLAMPED_SLEEP_REQUEST.store(duration_seconds, Ordering::Relaxed);
+                }
+                info!("✓ サーバーからスリープ時間を受信: {}秒。Deep Sleep に入ります。", clamped);
+                return Ok(clamped);
             }
             Some(_) => {
                 warn!("無効なスリープ時間 (0秒) を受信。デフォルト時間を使用します。");
@@ -65,6 +133,7 @@ impl AppController {
         error_msg: &str,
     ) -> anyhow::Result<()> {
         error!("{}", error_msg);
+        RtcManager::record_uptime();
         deep_sleep_controller.sleep_for_duration(config.sleep_duration_seconds)?;
         Ok(())
     }
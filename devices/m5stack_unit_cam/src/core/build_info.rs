@@ -0,0 +1,58 @@
+//! ファームウェアのビルド情報と実効設定のハッシュ
+//!
+//! 現場で動作しているファームウェアと設定を確認できるよう、`build.rs` が埋め込んだ
+//! ビルド情報（gitハッシュ・ビルド時刻・有効なフィーチャー）と、起動時に確定した設定の
+//! ハッシュを起動後最初のHASHフレームで報告します。
+
+/// ビルド情報が埋め込まれていない場合の表記
+const UNKNOWN: &str = "unknown";
+
+/// コンパイル時に埋め込んだビルド情報
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BuildInfo {
+    /// gitの短縮コミットハッシュ（未コミットの変更があれば `-dirty` 付き）
+    pub git_hash: &'static str,
+    /// ビルド時刻（UNIX秒）
+    pub build_timestamp: &'static str,
+    /// 有効なフィーチャー（`+` 区切り）
+    pub features: &'static str,
+}
+
+impl BuildInfo {
+    /// このファームウェアのビルド情報
+    pub const fn current() -> Self {
+        Self {
+            git_hash: match option_env!("FARMVERSE_GIT_HASH") {
+                Some(hash) => hash,
+                None => UNKNOWN,
+            },
+            build_timestamp: match option_env!("FARMVERSE_BUILD_TIMESTAMP") {
+                Some(timestamp) => timestamp,
+                None => UNKNOWN,
+            },
+            features: match option_env!("FARMVERSE_FEATURES") {
+                Some(features) => features,
+                None => "",
+            },
+        }
+    }
+
+    /// HASHフレームに付加するメタデータ
+    ///
+    /// 形式: `,FW:<gitハッシュ>,BUILT:<UNIX秒>[,FEAT:<a+b>],CFG:<設定ハッシュ8桁>`
+    pub fn metadata_fields(&self, config_hash: u32) -> String {
+        let mut fields = format!(",FW:{},BUILT:{}", self.git_hash, self.build_timestamp);
+        if !self.features.is_empty() {
+            fields.push_str(&format!(",FEAT:{}", self.features));
+        }
+        fields.push_str(&format!(",CFG:{:08x}", config_hash));
+        fields
+    }
+}
+
+/// 設定の表現（`Debug` 出力）からハッシュを求めます（FNV-1a 32ビット）
+///
+/// 同じ設定なら常に同じ値になるため、デバイス間やビルド間で設定の違いを見分けられます。
+pub fn config_hash(config_repr: &str) -> u32 {
+    config_repr.bytes().fold(0x811c_9dc5, |hash, byte| (hash ^ byte as u32).wrapping_mul(0x0100_0193))
+}
@@ -1,5 +1,47 @@
+//! 撮影するかどうかの判定と、撮影しなかった理由
+//!
+//! 撮影しなかったサイクルも画像なしで転送するため、理由を `SKIP:<理由>` としてHASHフレームに付加し、
+//! ホストでデバイスごとの傾向（電池切れが続く・カメラの故障など）を集計できるようにします。
+//! 画像品質による送信スキップ（`DARK`・`BRIGHT`・`BLUR`）も同じキーで報告します。
+
+use super::image_pipeline::SkipReason;
+use crate::units::Percent;
+
 pub const LOW_VOLTAGE_THRESHOLD_PERCENT: u8 = 8;
-pub const INVALID_VOLTAGE_PERCENT: u8 = 255;
+pub const INVALID_VOLTAGE_PERCENT: u8 = Percent::INVALID.value();
+
+/// 撮影・画像送信をしなかった理由
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaptureSkipReason {
+    /// 電池残量が閾値以下
+    LowVoltage,
+    /// 電圧の測定値が異常
+    InvalidVoltage,
+    /// カメラが初期化されていない
+    NoCamera,
+    /// リトライしても撮影に失敗した
+    CameraFailed,
+    /// 画像品質が閾値を満たさない
+    Quality(SkipReason),
+}
+
+impl CaptureSkipReason {
+    /// テレメトリ用の文字列表現
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CaptureSkipReason::LowVoltage => "LOW_VOLTAGE",
+            CaptureSkipReason::InvalidVoltage => "INVALID_VOLTAGE",
+            CaptureSkipReason::NoCamera => "NO_CAMERA",
+            CaptureSkipReason::CameraFailed => "CAMERA_FAILED",
+            CaptureSkipReason::Quality(reason) => reason.as_str(),
+        }
+    }
+
+    /// HASHフレームに付加するフィールド（`,SKIP:<理由>`）
+    pub fn metadata_field(&self) -> String {
+        format!(",SKIP:{}", self.as_str())
+    }
+}
 
 pub fn should_capture_image(voltage_percent: u8) -> bool {
     voltage_percent > LOW_VOLTAGE_THRESHOLD_PERCENT && voltage_percent < INVALID_VOLTAGE_PERCENT
@@ -13,8 +55,23 @@ pub fn should_capture_image_with_overrides(
     if force_camera_test {
         return true;
     }
-    if voltage_percent >= INVALID_VOLTAGE_PERCENT {
+    if voltage_percent == INVALID_VOLTAGE_PERCENT {
         return false;
     }
     bypass_voltage_threshold || should_capture_image(voltage_percent)
 }
+
+/// 電圧条件で撮影しない場合の理由（撮影する場合はNone）
+pub fn voltage_skip_reason(
+    voltage_percent: u8,
+    force_camera_test: bool,
+    bypass_voltage_threshold: bool,
+) -> Option<CaptureSkipReason> {
+    if should_capture_image_with_overrides(voltage_percent, force_camera_test, bypass_voltage_threshold) {
+        None
+    } else if voltage_percent <= LOW_VOLTAGE_THRESHOLD_PERCENT {
+        Some(CaptureSkipReason::LowVoltage)
+    } else {
+        Some(CaptureSkipReason::InvalidVoltage)
+    }
+}
@@ -0,0 +1,66 @@
+use esp_idf_svc::nvs::{EspDefaultNvsPartition, EspNvs, NvsDefault};
+use esp_idf_svc::sys::EspError;
+use log::{info, warn};
+
+use crate::communication::esp_now::DEVICE_KEY_RECORD_LEN;
+
+/// NVS名前空間
+const NVS_NAMESPACE: &str = "downlink";
+/// 最後に受理したコマンドカウンタのキー
+const NVS_KEY_LAST_COUNTER: &str = "last_ctr";
+/// 鍵更新で受け取った認証鍵の記録のキー
+const NVS_KEY_DEVICE_KEYS: &str = "keys";
+
+/// 受理済みコマンドカウンタと認証鍵のNVS永続化
+///
+/// 電源断後に古いコマンドを再受理しないよう、RTCメモリではなくNVSに保存します。
+/// 鍵更新で受け取った鍵も、電源断後にゲートウェイと鍵が食い違わないよう同じ名前空間に保存します。
+pub struct CommandCounterStore {
+    nvs: EspNvs<NvsDefault>,
+    last_accepted: u32,
+}
+
+impl CommandCounterStore {
+    /// NVSを開き、最後に受理したカウンタを読み込みます
+    pub fn open(partition: EspDefaultNvsPartition) -> Result<Self, EspError> {
+        let nvs = EspNvs::new(partition, NVS_NAMESPACE, true)?;
+        let last_accepted = nvs.get_u32(NVS_KEY_LAST_COUNTER)?.unwrap_or(0);
+        info!("受理済みコマンドカウンタ: {}", last_accepted);
+        Ok(Self { nvs, last_accepted })
+    }
+
+    /// 最後に受理したカウンタ
+    pub fn last_accepted(&self) -> u32 {
+        self.last_accepted
+    }
+
+    /// 受理したカウンタを保存します（保存済みの値以下なら何もしない）
+    pub fn record_accepted(&mut self, counter: u32) {
+        if counter <= self.last_accepted {
+            return;
+        }
+        match self.nvs.set_u32(NVS_KEY_LAST_COUNTER, counter) {
+            Ok(()) => self.last_accepted = counter,
+            Err(e) => warn!("コマンドカウンタの保存に失敗しました: {:?}", e),
+        }
+    }
+
+    /// 保存済みの鍵の記録（鍵更新を受理していなければNone）
+    pub fn key_record(&self) -> Option<Vec<u8>> {
+        let mut buffer = [0u8; DEVICE_KEY_RECORD_LEN];
+        match self.nvs.get_raw(NVS_KEY_DEVICE_KEYS, &mut buffer) {
+            Ok(record) => record.map(<[u8]>::to_vec),
+            Err(e) => {
+                warn!("認証鍵の記録を読み込めません: {:?}", e);
+                None
+            }
+        }
+    }
+
+    /// 鍵の記録を保存します
+    pub fn record_keys(&mut self, record: &[u8]) {
+        if let Err(e) = self.nvs.set_raw(NVS_KEY_DEVICE_KEYS, record) {
+            warn!("認証鍵の記録の保存に失敗しました: {:?}", e);
+        }
+    }
+}
@@ -1,8 +1,22 @@
 use crate::mac_address::MacAddress;
 use crate::core::config_validation::{
-    parse_camera_warmup_frames, parse_receiver_mac, ValidationError,
+    parse_camera_warmup_frames, parse_receiver_mac, validate_sleep_bounds, ValidationError,
 };
 use crate::core::clamp_wifi_tx_power_dbm;
+use crate::core::build_info::config_hash;
+use crate::core::config_staging::{RemoteConfig, MAX_JPEG_QUALITY};
+use crate::core::debug_flags::DebugFlags;
+use crate::communication::esp_now::{ChaosParams, DownlinkKey, EspNowRate, PacingMode, PacingParams, PrivacyParams};
+use crate::core::image_hash::ImageHashAlgo;
+use crate::core::image_pipeline::QualityThresholds;
+use crate::core::thumbnail_ring::MAX_THUMBNAIL_RING_LEN;
+use crate::core::timelapse::TimelapseSettings;
+use crate::hardware::camera::fb_policy::{FrameBufferPlacement, MAX_FB_COUNT, MIN_FB_COUNT};
+use crate::hardware::camera::health::MAX_DEGRADED_STREAK;
+use crate::hardware::camera::warmup::{WarmupStrategy, DEFAULT_WARMUP_MS, MAX_WARMUP_MS};
+use crate::hardware::camera::recovery::MAX_REINIT_ATTEMPTS;
+use crate::hardware::led::{LedPattern, LedPatterns};
+use crate::power::sleep::AlignmentSettings;
 use log::warn;
 
 /// カメラのSCCBスタンバイ方式
@@ -37,9 +51,21 @@ pub struct Config {
     #[default(0)] // デフォルトは補正なし
     sleep_compensation_micros: i64,
 
+    #[default(0)] // 撮影時刻を揃える境界の間隔（秒, 0で無効）
+    capture_align_interval_seconds: u32,
+
+    #[default(1500)] // 境界より早く起床する余裕（ミリ秒）
+    capture_align_early_wake_ms: u32,
+
     #[default("SVGA")]
     frame_size: &'static str,
 
+    #[default(0)] // JPEG品質（1〜63、小さいほど高画質。0でドライバの既定値）
+    jpeg_quality: u8,
+
+    #[default("xxh64")] // 画像ハッシュのアルゴリズム（sum/xxh64/sha256）
+    image_hash_algo: &'static str,
+
     #[default(false)]
     auto_exposure_enabled: bool,
 
@@ -52,6 +78,30 @@ pub struct Config {
     #[default(255)]
     camera_warmup_frames: u8,
 
+    #[default("frames")] // ウォームアップ方式: frames / duration / auto
+    camera_warmup_mode: &'static str,
+
+    #[default(DEFAULT_WARMUP_MS)] // duration / auto 方式のウォームアップ時間（ミリ秒）
+    camera_warmup_ms: u16,
+
+    #[default("internal")] // フレームバッファ配置: psram / prefer_psram / internal
+    camera_fb_placement: &'static str,
+
+    #[default(1)] // フレームバッファ数（1-3）
+    camera_fb_count: u8,
+
+    #[default(2)] // SCCBエラー時のカメラ再初期化の上限回数（0で再初期化しない）
+    camera_reinit_max_attempts: u8,
+
+    #[default(-1)] // センサーのPWDNピン（-1で電源を入れ直さない）
+    camera_pwdn_gpio: i8,
+
+    #[default(3)] // カメラ劣化の警告を出す撮影の連続失敗回数（0で警告しない）
+    camera_degraded_streak: u8,
+
+    #[default(0)] // 保持する直近のサムネイルの枚数（0で保持しない）
+    thumbnail_ring_len: u8,
+
     #[default(255)]
     target_minute_last_digit: u8,
 
@@ -73,6 +123,12 @@ pub struct Config {
     #[default(false)]
     force_sleep_duration_by_device: bool,
 
+    #[default(10)] // サーバーから受け付けるスリープ時間の下限（秒）
+    min_sleep_duration_seconds: u64,
+
+    #[default(43200)] // サーバーから受け付けるスリープ時間の上限（秒、12時間）
+    max_sleep_duration_seconds: u64,
+
     // ADC電圧測定設定
     #[default(128)] // UnitCam GPIO0 の実測値に合わせて調整
     adc_voltage_min_mv: u16,
@@ -84,9 +140,71 @@ pub struct Config {
     #[default(250)] // チャンクサイズ（バイト）
     esp_now_chunk_size: u16,
 
-    #[default(50)] // チャンク間遅延（ミリ秒）
+    #[default(50)] // 送信完了コールバックの成功後に空けるチャンク間の最小間隔（ミリ秒）
     esp_now_chunk_delay_ms: u32,
 
+    #[default(500)] // 送信完了コールバックの失敗が続いたときのチャンク間隔の上限（ミリ秒）
+    esp_now_chunk_backoff_max_ms: u32,
+
+    #[default(50)] // チャンクごとに送信完了コールバックを待つ時間（ミリ秒）
+    esp_now_send_cb_timeout_ms: u32,
+
+    #[default("delay")] // チャンク間の待ち方（delay / light_sleep）
+    esp_now_chunk_pacing: &'static str,
+
+    #[default(0)] // FECグループのデータチャンク数（0で無効）
+    esp_now_fec_group_size: u8,
+
+    #[default(2)] // FECグループあたりの最大パリティ数
+    esp_now_fec_max_parity: u8,
+
+    #[default(2)] // 転送前の疎通確認の試行回数（0で無効）
+    esp_now_probe_attempts: u8,
+
+    #[default(300)] // 疎通確認1回あたりのPong待機時間（ミリ秒）
+    esp_now_probe_timeout_ms: u32,
+
+    #[default(20000)] // ゲートウェイの延期要求に従って待機する時間の上限（ミリ秒）
+    esp_now_defer_max_wait_ms: u32,
+
+    #[default(false)] // プライバシーモード（フレームの詰め物とダミーフレーム、疎通確認が必要）
+    esp_now_privacy_mode: bool,
+
+    #[default(2)] // チャンクの間に挟むダミーフレームの最大数
+    esp_now_privacy_max_dummy_frames: u8,
+
+    #[default(20)] // ダミーフレーム前の待機時間の最大値（ミリ秒）
+    esp_now_privacy_max_jitter_ms: u32,
+
+    #[default(false)] // ゲートウェイの受信チャンネルの時間分割に追従する（疎通確認が必要）
+    esp_now_channel_hop: bool,
+
+    #[default("")] // 送信フレームへの故障注入（"drop=5,dup=2,reorder=1,corrupt=1,seed=42"、`chaos` フィーチャーのみ）
+    esp_now_chaos: &'static str,
+
+    #[default("")] // ダウンリンク認証鍵（64文字の16進数、空なら署名なしのコマンドも受理）
+    downlink_auth_key: &'static str,
+
+    // 中継設定（子機のMACアドレスが空なら無効）
+    #[default("")] // 中継する圏外のカメラ（子機）のMACアドレス
+    relay_child_mac: &'static str,
+
+    #[default(20000)] // 子機のフレームを待つ受信窓（ミリ秒）
+    relay_window_ms: u32,
+
+    // 画像品質チェック設定
+    #[default(false)] // 閾値を満たさない画像の送信をスキップ
+    image_quality_skip_enabled: bool,
+
+    #[default(0)] // 平均輝度の下限（0-255）
+    image_quality_min_luma: u8,
+
+    #[default(255)] // 平均輝度の上限（0-255）
+    image_quality_max_luma: u8,
+
+    #[default(0)] // シャープネスの下限（0で無効）
+    image_quality_min_sharpness: u16,
+
     // テスト・デバッグ設定
     #[default(false)]
     force_voltage_percent_50: bool,
@@ -103,6 +221,33 @@ pub struct Config {
     // WiFi送信パワー設定（dBm）
     #[default(8)]
     wifi_tx_power_dbm: i8,
+
+    // ESP-NOW送信レート（"1M"〜"54M"、"MCS0"〜"MCS7"。空ならESP-IDFの既定値）
+    #[default("")]
+    esp_now_phy_rate: &'static str,
+
+    // ステータスLEDの点灯パターン（"off" / "on" / "点灯ms/消灯ms" / "点灯ms/消灯msx回数"）
+    #[default("on")]
+    led_pattern_capture: &'static str,
+
+    #[default("on")]
+    led_pattern_transmit: &'static str,
+
+    #[default("100/100x2")]
+    led_pattern_success: &'static str,
+
+    #[default("300/300x3")]
+    led_pattern_error: &'static str,
+
+    #[default("50/450x3")]
+    led_pattern_low_battery: &'static str,
+
+    // タイムラプス設定（シーケンス名が空なら無効）
+    #[default("")]
+    timelapse_sequence: &'static str,
+
+    #[default(0)] // 撮影間隔（秒、0で sleep_duration_seconds）
+    timelapse_interval_seconds: u64,
 }
 
 /// 設定エラー
@@ -112,8 +257,44 @@ pub enum ConfigError {
     InvalidReceiverMac(String),
     #[error("camera_warmup_frames の値が無効です (0-10): {0}")]
     InvalidCameraWarmupFrames(u8),
+    #[error("camera_warmup_mode の値が無効です: {0} (有効値: frames/duration/auto)")]
+    InvalidCameraWarmupMode(String),
+    #[error("camera_warmup_ms の値が無効です (0-10000): {0}")]
+    InvalidCameraWarmupMs(u16),
     #[error("camera_standby_mode の値が無効です: {0} (有効値: auto/off/minimal/full)")]
     InvalidCameraStandbyMode(String),
+    #[error("camera_fb_placement の値が無効です: {0} (有効値: psram/prefer_psram/internal)")]
+    InvalidCameraFbPlacement(String),
+    #[error("camera_fb_count の値が無効です (1-3): {0}")]
+    InvalidCameraFbCount(u8),
+    #[error("camera_reinit_max_attempts の値が無効です (0-5): {0}")]
+    InvalidCameraReinitMaxAttempts(u8),
+    #[error("camera_pwdn_gpio の値が無効です (-1 または 0-33): {0}")]
+    InvalidCameraPwdnGpio(i8),
+    #[error("camera_degraded_streak の値が無効です (0-100): {0}")]
+    InvalidCameraDegradedStreak(u8),
+    #[error("thumbnail_ring_len の値が無効です (0-32): {0}")]
+    InvalidThumbnailRingLen(u8),
+    #[error("jpeg_quality の値が無効です (0-63): {0}")]
+    InvalidJpegQuality(u8),
+    #[error("image_hash_algo の値が無効です: {0} (有効値: sum/xxh64/sha256)")]
+    InvalidImageHashAlgo(String),
+    #[error("downlink_auth_key の値が無効です（64文字の16進数）")]
+    InvalidDownlinkAuthKey,
+    #[error("relay_child_mac の値が無効です: {0}")]
+    InvalidRelayChildMac(String),
+    #[error("スリープ時間の許容範囲が無効です (1 <= min <= max <= 86400): {0}-{1}")]
+    InvalidSleepBounds(u64, u64),
+    #[error("esp_now_phy_rate の値が無効です: {0} (例: 1M/24M/54M/MCS3)")]
+    InvalidEspNowPhyRate(String),
+    #[error("esp_now_chunk_pacing の値が無効です: {0} (有効値: delay/light_sleep)")]
+    InvalidEspNowChunkPacing(String),
+    #[error("{0} の値が無効です: {1} (例: off/on/300/300x3)")]
+    InvalidLedPattern(&'static str, String),
+    #[error("timelapse_sequence の値が無効です: {0} (英数字・-・_ のみ、32文字以内)")]
+    InvalidTimelapseSequence(String),
+    #[error("esp_now_chaos の値が無効です: {0} (例: drop=5,dup=2,reorder=1,corrupt=1,seed=42)")]
+    InvalidEspNowChaos(String),
 }
 
 /// アプリケーション設定を表す構造体
@@ -125,9 +306,21 @@ pub struct AppConfig {
     /// ディープスリープ時間（秒）
     pub sleep_duration_seconds: u64,
 
+    /// ディープスリープ時間の補正量（マイクロ秒）
+    pub sleep_compensation_micros: i64,
+
+    /// 撮影時刻の壁時計境界への整列（Noneで無効）
+    pub capture_alignment: Option<AlignmentSettings>,
+
     /// フレームサイズ
     pub frame_size: String,
 
+    /// JPEG品質（Noneでドライバの既定値）
+    pub jpeg_quality: Option<u8>,
+
+    /// 画像ハッシュのアルゴリズム（HASHフレームの `HASH_ALGO` で宣言）
+    pub image_hash_algo: ImageHashAlgo,
+
     /// 自動露出設定
     pub auto_exposure_enabled: bool,
 
@@ -140,6 +333,27 @@ pub struct AppConfig {
     /// カメラウォームアップフレーム数
     pub camera_warmup_frames: Option<u8>,
 
+    /// カメラウォームアップ方式
+    pub camera_warmup: WarmupStrategy,
+
+    /// フレームバッファの配置ポリシー
+    pub camera_fb_placement: FrameBufferPlacement,
+
+    /// フレームバッファ数
+    pub camera_fb_count: u8,
+
+    /// SCCBエラー時のカメラ再初期化の上限回数
+    pub camera_reinit_max_attempts: u8,
+
+    /// センサーのPWDNピン（Noneなら再初期化時に電源を入れ直さない）
+    pub camera_pwdn_gpio: Option<i32>,
+
+    /// カメラ劣化の警告を出す撮影の連続失敗回数（0なら警告しない）
+    pub camera_degraded_streak: u8,
+
+    /// 保持する直近のサムネイルの枚数（0なら保持しない）
+    pub thumbnail_ring_len: u8,
+
     /// タイムゾーン
     pub timezone: String,
 
@@ -149,6 +363,12 @@ pub struct AppConfig {
     /// サーバー応答を無視して sleep_duration_seconds を強制使用
     pub force_sleep_duration_by_device: bool,
 
+    /// サーバーから受け付けるスリープ時間の下限（秒、範囲外は補正）
+    pub min_sleep_duration_seconds: u64,
+
+    /// サーバーから受け付けるスリープ時間の上限（秒、範囲外は補正）
+    pub max_sleep_duration_seconds: u64,
+
     /// ADC電圧測定最小値（mV）
     pub adc_voltage_min_mv: u16,
 
@@ -158,9 +378,48 @@ pub struct AppConfig {
     /// ESP-NOW画像送信チャンクサイズ（バイト）
     pub esp_now_chunk_size: u16,
 
-    /// ESP-NOWチャンク間遅延時間（ミリ秒）
+    /// ESP-NOWチャンク間の最小間隔（ミリ秒、送信完了コールバックの成功後）
     pub esp_now_chunk_delay_ms: u32,
 
+    /// 送信完了コールバックに合わせたチャンク間隔の設定
+    pub esp_now_pacing: PacingParams,
+
+    /// FECグループのデータチャンク数（0でFEC無効）
+    pub esp_now_fec_group_size: u8,
+
+    /// FECグループあたりの最大パリティ数
+    pub esp_now_fec_max_parity: u8,
+
+    /// 転送前の疎通確認の試行回数（0で無効）
+    pub esp_now_probe_attempts: u8,
+
+    /// 疎通確認1回あたりのPong待機時間（ミリ秒）
+    pub esp_now_probe_timeout_ms: u32,
+
+    /// ゲートウェイの延期要求に従って待機する時間の上限（ミリ秒）
+    pub esp_now_defer_max_wait_ms: u32,
+
+    /// プライバシーモードの設定（無効ならNone）
+    pub esp_now_privacy: Option<PrivacyParams>,
+
+    /// ゲートウェイの受信チャンネルの時間分割に追従する
+    pub esp_now_channel_hop: bool,
+
+    /// 送信フレームへの故障注入（無効ならNone）
+    pub esp_now_chaos: Option<ChaosParams>,
+
+    /// ダウンリンク認証鍵（設定時は署名付きスリープコマンドのみ受理）
+    pub downlink_auth_key: Option<DownlinkKey>,
+
+    /// 中継する子機のMACアドレス（中継しないならNone）
+    pub relay_child_mac: Option<MacAddress>,
+
+    /// 子機のフレームを待つ受信窓（ミリ秒）
+    pub relay_window_ms: u32,
+
+    /// 画像品質チェックの閾値
+    pub image_quality_thresholds: QualityThresholds,
+
     /// 電圧チェックを無視してカメラテストを強制実行
     pub force_camera_test: bool,
 
@@ -175,6 +434,15 @@ pub struct AppConfig {
 
     /// WiFi送信パワー（dBm, 2-20 にクランプ）
     pub wifi_tx_power_dbm: i8,
+
+    /// ESP-NOW送信レート（Noneで既定値）
+    pub esp_now_phy_rate: Option<EspNowRate>,
+
+    /// イベントごとのステータスLED点灯パターン
+    pub led_patterns: LedPatterns,
+
+    /// タイムラプス撮影のシーケンス（Noneで無効、有効時は撮影間隔でスリープ）
+    pub timelapse: Option<TimelapseSettings>,
 }
 
 impl AppConfig {
@@ -188,9 +456,17 @@ impl AppConfig {
 
         // ディープスリープ時間を設定
         let sleep_duration_seconds = config.sleep_duration_seconds;
+        let sleep_compensation_micros = config.sleep_compensation_micros;
 
         // フレームサイズを設定
         let frame_size = config.frame_size.to_string();
+        let jpeg_quality = match config.jpeg_quality {
+            0 => None,
+            quality if quality <= MAX_JPEG_QUALITY => Some(quality),
+            quality => return Err(ConfigError::InvalidJpegQuality(quality)),
+        };
+        let image_hash_algo = ImageHashAlgo::parse(config.image_hash_algo)
+            .ok_or_else(|| ConfigError::InvalidImageHashAlgo(config.image_hash_algo.trim().to_string()))?;
 
         // 自動露出設定を取得
         let auto_exposure_enabled = config.auto_exposure_enabled;
@@ -207,6 +483,42 @@ impl AppConfig {
         // カメラウォームアップフレーム数を取得・検証
         let camera_warmup_frames =
             parse_camera_warmup_frames(config.camera_warmup_frames).map_err(map_validation_error)?;
+        if config.camera_warmup_ms > MAX_WARMUP_MS {
+            return Err(ConfigError::InvalidCameraWarmupMs(config.camera_warmup_ms));
+        }
+        let camera_warmup =
+            WarmupStrategy::from_config(config.camera_warmup_mode, camera_warmup_frames, config.camera_warmup_ms)
+                .ok_or_else(|| {
+                    ConfigError::InvalidCameraWarmupMode(config.camera_warmup_mode.trim().to_ascii_lowercase())
+                })?;
+
+        // フレームバッファ配置ポリシーと数を取得・検証
+        let camera_fb_placement = FrameBufferPlacement::parse(config.camera_fb_placement).ok_or_else(|| {
+            ConfigError::InvalidCameraFbPlacement(config.camera_fb_placement.trim().to_ascii_lowercase())
+        })?;
+        let camera_fb_count = config.camera_fb_count;
+        if !(MIN_FB_COUNT..=MAX_FB_COUNT).contains(&camera_fb_count) {
+            return Err(ConfigError::InvalidCameraFbCount(camera_fb_count));
+        }
+
+        // SCCBエラー時の再初期化設定を取得・検証（PWDNは出力可能なGPIO0-33のみ）
+        let camera_reinit_max_attempts = config.camera_reinit_max_attempts;
+        if camera_reinit_max_attempts > MAX_REINIT_ATTEMPTS {
+            return Err(ConfigError::InvalidCameraReinitMaxAttempts(camera_reinit_max_attempts));
+        }
+        let camera_pwdn_gpio = match config.camera_pwdn_gpio {
+            -1 => None,
+            pin @ 0..=33 => Some(i32::from(pin)),
+            pin => return Err(ConfigError::InvalidCameraPwdnGpio(pin)),
+        };
+        let camera_degraded_streak = config.camera_degraded_streak;
+        if camera_degraded_streak > MAX_DEGRADED_STREAK {
+            return Err(ConfigError::InvalidCameraDegradedStreak(camera_degraded_streak));
+        }
+        let thumbnail_ring_len = config.thumbnail_ring_len;
+        if thumbnail_ring_len > MAX_THUMBNAIL_RING_LEN {
+            return Err(ConfigError::InvalidThumbnailRingLen(thumbnail_ring_len));
+        }
 
         // タイムゾーンを取得
         let timezone = config.timezone.to_string();
@@ -214,6 +526,10 @@ impl AppConfig {
         // スリープコマンドタイムアウトを取得
         let sleep_command_timeout_seconds = config.sleep_command_timeout_seconds;
         let force_sleep_duration_by_device = config.force_sleep_duration_by_device;
+        let min_sleep_duration_seconds = config.min_sleep_duration_seconds;
+        let max_sleep_duration_seconds = config.max_sleep_duration_seconds;
+        validate_sleep_bounds(min_sleep_duration_seconds, max_sleep_duration_seconds)
+            .map_err(map_validation_error)?;
 
         // ADC電圧測定設定を取得
         let adc_voltage_min_mv = config.adc_voltage_min_mv;
@@ -222,6 +538,52 @@ impl AppConfig {
         // ESP-NOW 画像送信設定を取得
         let esp_now_chunk_size = config.esp_now_chunk_size;
         let esp_now_chunk_delay_ms = config.esp_now_chunk_delay_ms;
+        let esp_now_fec_group_size = config.esp_now_fec_group_size;
+        let esp_now_fec_max_parity = config.esp_now_fec_max_parity;
+        let esp_now_chunk_pacing = PacingMode::parse(config.esp_now_chunk_pacing).ok_or_else(|| {
+            ConfigError::InvalidEspNowChunkPacing(config.esp_now_chunk_pacing.trim().to_ascii_lowercase())
+        })?;
+
+        // 画像品質チェック設定
+        let image_quality_thresholds = QualityThresholds {
+            skip_enabled: config.image_quality_skip_enabled,
+            min_luma: config.image_quality_min_luma,
+            max_luma: config.image_quality_max_luma,
+            min_sharpness: config.image_quality_min_sharpness,
+        };
+
+        // ダウンリンク認証鍵（空なら無効）
+        let downlink_auth_key = match config.downlink_auth_key.trim() {
+            "" => None,
+            hex => Some(DownlinkKey::from_hex(hex).ok_or(ConfigError::InvalidDownlinkAuthKey)?),
+        };
+
+        // 中継する子機（空なら無効）
+        let relay_child_mac = match config.relay_child_mac.trim() {
+            "" => None,
+            mac => Some(
+                MacAddress::from_str(mac).map_err(|_| ConfigError::InvalidRelayChildMac(mac.to_string()))?,
+            ),
+        };
+
+        // プライバシーモード（ゲートウェイの受け入れは疎通確認で確認する）
+        let esp_now_privacy = config.esp_now_privacy_mode.then_some(PrivacyParams {
+            max_dummy_frames: config.esp_now_privacy_max_dummy_frames,
+            max_jitter_ms: config.esp_now_privacy_max_jitter_ms,
+        });
+        if esp_now_privacy.is_some() && config.esp_now_probe_attempts == 0 {
+            warn!("esp_now_privacy_mode は疎通確認が無効（esp_now_probe_attempts = 0）のため適用されません");
+        }
+        if config.esp_now_channel_hop && config.esp_now_probe_attempts == 0 {
+            warn!("esp_now_channel_hop は疎通確認が無効（esp_now_probe_attempts = 0）のため適用されません");
+        }
+
+        // 送信フレームへの故障注入（`chaos` フィーチャーなしのビルドでは無視する）
+        let esp_now_chaos = ChaosParams::parse(config.esp_now_chaos)
+            .map_err(|_| ConfigError::InvalidEspNowChaos(config.esp_now_chaos.trim().to_string()))?;
+        if esp_now_chaos.is_some() && !cfg!(feature = "chaos") {
+            warn!("esp_now_chaos は `chaos` フィーチャーなしのビルドのため適用されません");
+        }
 
         // テスト・デバッグ設定
         let force_voltage_percent_50 = config.force_voltage_percent_50;
@@ -232,28 +594,125 @@ impl AppConfig {
         // WiFi送信パワー（安全範囲へクランプ）
         let wifi_tx_power_dbm = clamp_wifi_tx_power_dbm(config.wifi_tx_power_dbm);
 
+        // ESP-NOW送信レート（空なら既定値）
+        let esp_now_phy_rate = match config.esp_now_phy_rate.trim() {
+            "" => None,
+            name => Some(
+                EspNowRate::parse(name).ok_or_else(|| ConfigError::InvalidEspNowPhyRate(name.to_string()))?,
+            ),
+        };
+
+        // ステータスLEDの点灯パターン
+        let led_patterns = LedPatterns {
+            capture: parse_led_pattern("led_pattern_capture", config.led_pattern_capture)?,
+            transmit: parse_led_pattern("led_pattern_transmit", config.led_pattern_transmit)?,
+            success: parse_led_pattern("led_pattern_success", config.led_pattern_success)?,
+            error: parse_led_pattern("led_pattern_error", config.led_pattern_error)?,
+            low_battery: parse_led_pattern("led_pattern_low_battery", config.led_pattern_low_battery)?,
+        };
+
+        // タイムラプス設定（撮影間隔の省略時は通常のスリープ時間）
+        let timelapse_interval_seconds = match config.timelapse_interval_seconds {
+            0 => sleep_duration_seconds,
+            seconds => seconds,
+        };
+        let timelapse = TimelapseSettings::from_config(config.timelapse_sequence, timelapse_interval_seconds)
+            .map_err(ConfigError::InvalidTimelapseSequence)?;
+
         Ok(AppConfig {
             receiver_mac,
             sleep_duration_seconds,
+            sleep_compensation_micros,
+            capture_alignment: AlignmentSettings::from_config(
+                config.capture_align_interval_seconds,
+                config.capture_align_early_wake_ms,
+            ),
             frame_size,
+            jpeg_quality,
+            image_hash_algo,
             auto_exposure_enabled,
             camera_soft_standby_enabled,
             camera_standby_mode,
             camera_warmup_frames,
+            camera_warmup,
+            camera_fb_placement,
+            camera_fb_count,
+            camera_reinit_max_attempts,
+            camera_pwdn_gpio,
+            camera_degraded_streak,
+            thumbnail_ring_len,
             timezone,
             sleep_command_timeout_seconds,
             force_sleep_duration_by_device,
+            min_sleep_duration_seconds,
+            max_sleep_duration_seconds,
             adc_voltage_min_mv,
             adc_voltage_max_mv,
             esp_now_chunk_size,
             esp_now_chunk_delay_ms,
+            esp_now_pacing: PacingParams {
+                max_backoff_ms: config.esp_now_chunk_backoff_max_ms,
+                callback_timeout_ms: config.esp_now_send_cb_timeout_ms,
+                mode: esp_now_chunk_pacing,
+            },
+            esp_now_fec_group_size,
+            esp_now_fec_max_parity,
+            esp_now_probe_attempts: config.esp_now_probe_attempts,
+            esp_now_probe_timeout_ms: config.esp_now_probe_timeout_ms,
+            esp_now_defer_max_wait_ms: config.esp_now_defer_max_wait_ms,
+            esp_now_privacy,
+            esp_now_channel_hop: config.esp_now_channel_hop,
+            esp_now_chaos,
+            downlink_auth_key,
+            relay_child_mac,
+            relay_window_ms: config.relay_window_ms,
+            image_quality_thresholds,
             force_voltage_percent_50,
             force_camera_test,
             bypass_voltage_threshold,
             debug_mode,
             wifi_tx_power_dbm,
+            esp_now_phy_rate,
+            led_patterns,
+            timelapse,
         })
     }
+
+    /// 実効設定のハッシュ（テレメトリで現場の設定を確認するため）
+    pub fn config_hash(&self) -> u32 {
+        config_hash(&format!("{:?}", self))
+    }
+
+    /// リモート設定で上書きした設定を返します（未指定の項目はそのまま）
+    pub fn with_remote_overrides(&self, remote: &RemoteConfig) -> AppConfig {
+        let mut config = self.clone();
+        if let Some(mac) = &remote.receiver_mac {
+            config.receiver_mac = mac.clone();
+        }
+        if let Some(seconds) = remote.sleep_duration_seconds {
+            config.sleep_duration_seconds = seconds;
+        }
+        if let Some(quality) = remote.jpeg_quality {
+            config.jpeg_quality = Some(quality);
+        }
+        if let Some(frame_size) = &remote.frame_size {
+            config.frame_size = frame_size.clone();
+        }
+        config
+    }
+
+    /// ダウンリンクで有効にしたデバッグフラグを重ねた設定を返します（cfg.tomlで有効なフラグはそのまま）
+    pub fn with_debug_flags(&self, flags: DebugFlags) -> AppConfig {
+        let mut config = self.clone();
+        config.debug_mode |= flags.debug_mode;
+        config.force_camera_test |= flags.force_camera_test;
+        config.bypass_voltage_threshold |= flags.bypass_voltage_threshold;
+        config
+    }
+}
+
+fn parse_led_pattern(key: &'static str, value: &str) -> Result<LedPattern, ConfigError> {
+    LedPattern::parse(value).ok_or_else(|| ConfigError::InvalidLedPattern(key, value.trim().to_string()))
 }
 
 fn map_validation_error(err: ValidationError) -> ConfigError {
@@ -263,6 +722,7 @@ fn map_validation_error(err: ValidationError) -> ConfigError {
         ),
         ValidationError::InvalidReceiverMac(v) => ConfigError::InvalidReceiverMac(v),
         ValidationError::InvalidCameraWarmupFrames(v) => ConfigError::InvalidCameraWarmupFrames(v),
+        ValidationError::InvalidSleepBounds(min, max) => ConfigError::InvalidSleepBounds(min, max),
         ValidationError::InvalidTargetMinuteLastDigit(_)
         | ValidationError::InvalidTargetSecondLastDigit(_)
         | ValidationError::MissingWifiSsid => {
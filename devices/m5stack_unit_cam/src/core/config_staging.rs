@@ -0,0 +1,230 @@
+//! リモート設定の2段階適用（ステージング → 試行起動 → 確定 / ロールバック）
+//!
+//! 新しい設定はまずNVSにステージングし、次回起動時に1回だけ試行します。
+//! 試行中の送信にゲートウェイが応答した場合（HASHフレームへの応答またはスリープコマンドを受信）のみ確定し、
+//! 確定されないまま再起動した場合は直前の確定済み設定へ自動で戻します。
+//! 送信関数の成功は送信キューへの投入までしか示さないため、確定の判断には使いません。
+
+use crate::mac_address::MacAddress;
+
+/// 項目の区切り文字
+const FIELD_SEPARATOR: char = ';';
+/// JPEG品質の上限（esp32-cameraの範囲。小さいほど高画質）
+pub const MAX_JPEG_QUALITY: u8 = 63;
+/// フレームサイズ名の最大長
+const MAX_FRAME_SIZE_LEN: usize = 8;
+
+/// ステージング状態
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StagingState {
+    /// ステージングされた設定なし
+    Idle = 0,
+    /// ステージング済み（未試行）
+    Staged = 1,
+    /// 試行起動中（未確定）
+    Trial = 2,
+}
+
+impl StagingState {
+    /// NVSの保存値から復元します（不明な値はIdle扱い）
+    pub fn from_u8(value: u8) -> Self {
+        match value {
+            1 => StagingState::Staged,
+            2 => StagingState::Trial,
+            _ => StagingState::Idle,
+        }
+    }
+}
+
+/// 起動時の判定結果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BootDecision {
+    /// 確定済み設定を使用
+    UseCommitted,
+    /// ステージングされた設定を試行
+    TryStaged,
+    /// 前回の試行が確定されなかったためロールバック
+    RolledBack,
+}
+
+/// 起動時の状態遷移を決定します
+///
+/// # 戻り値
+/// 判定結果と、起動処理を続ける前にNVSへ保存すべき新しい状態
+pub fn decide_on_boot(state: StagingState) -> (BootDecision, StagingState) {
+    match state {
+        StagingState::Idle => (BootDecision::UseCommitted, StagingState::Idle),
+        // 試行前に状態を保存し、試行中の再起動を検出できるようにする
+        StagingState::Staged => (BootDecision::TryStaged, StagingState::Trial),
+        StagingState::Trial => (BootDecision::RolledBack, StagingState::Idle),
+    }
+}
+
+/// 試行中の送信に対するゲートウェイからの確認
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GatewayConfirmation {
+    /// HASHフレームへの応答を受信した
+    pub hash_acked: bool,
+    /// スリープコマンドを受信した
+    pub sleep_command_received: bool,
+}
+
+impl GatewayConfirmation {
+    /// 試行中の設定を確定するか（送信を終え、ゲートウェイからの確認が届いた場合のみ）
+    pub fn confirms_trial(self, transmitted: bool) -> bool {
+        transmitted && (self.hash_acked || self.sleep_command_received)
+    }
+}
+
+/// リモート設定の解析エラー
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum RemoteConfigError {
+    #[error("不明な設定項目: {0}")]
+    UnknownKey(String),
+    #[error("設定値が不正です: {0}")]
+    InvalidValue(String),
+}
+
+/// リモートから変更可能な設定項目（未指定の項目はcfg.tomlの値を使用）
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RemoteConfig {
+    /// 受信機のMACアドレス
+    pub receiver_mac: Option<MacAddress>,
+    /// ディープスリープ時間（秒）
+    pub sleep_duration_seconds: Option<u64>,
+    /// 適用済みの一斉配信の設定ID（ゲートウェイへ報告する）
+    pub broadcast_id: Option<u32>,
+    /// JPEG品質（1〜63）
+    pub jpeg_quality: Option<u8>,
+    /// フレームサイズ（esp32-cameraの名称）
+    pub frame_size: Option<String>,
+}
+
+impl RemoteConfig {
+    /// NVS保存用の文字列に変換します
+    ///
+    /// 形式: `RECEIVER_MAC=xx:xx:xx:xx:xx:xx;SLEEP=<秒>;BCAST=<設定ID>;JPEG_Q=<品質>;FRAME_SIZE=<名称>`
+    /// （未指定の項目は省略）
+    pub fn encode(&self) -> String {
+        let mut fields = Vec::new();
+        if let Some(mac) = &self.receiver_mac {
+            fields.push(format!("RECEIVER_MAC={}", mac));
+        }
+        if let Some(seconds) = self.sleep_duration_seconds {
+            fields.push(format!("SLEEP={}", seconds));
+        }
+        if let Some(id) = self.broadcast_id {
+            fields.push(format!("BCAST={}", id));
+        }
+        if let Some(quality) = self.jpeg_quality {
+            fields.push(format!("JPEG_Q={}", quality));
+        }
+        if let Some(frame_size) = &self.frame_size {
+            fields.push(format!("FRAME_SIZE={}", frame_size));
+        }
+        fields.join(&FIELD_SEPARATOR.to_string())
+    }
+
+    /// `encode` の形式から復元します
+    pub fn decode(encoded: &str) -> Result<Self, RemoteConfigError> {
+        let mut config = RemoteConfig::default();
+        for field in encoded.split(FIELD_SEPARATOR).filter(|f| !f.is_empty()) {
+            let (key, value) = field
+                .split_once('=')
+                .ok_or_else(|| RemoteConfigError::InvalidValue(field.to_string()))?;
+            match key {
+                "RECEIVER_MAC" => {
+                    let mac = MacAddress::from_str(value)
+                        .map_err(|_| RemoteConfigError::InvalidValue(field.to_string()))?;
+                    config.receiver_mac = Some(mac);
+                }
+                "SLEEP" => {
+                    let seconds = value
+                        .parse::<u64>()
+                        .ok()
+                        .filter(|s| *s > 0)
+                        .ok_or_else(|| RemoteConfigError::InvalidValue(field.to_string()))?;
+                    config.sleep_duration_seconds = Some(seconds);
+                }
+                "BCAST" => {
+                    let id = value
+                        .parse::<u32>()
+                        .map_err(|_| RemoteConfigError::InvalidValue(field.to_string()))?;
+                    config.broadcast_id = Some(id);
+                }
+                "JPEG_Q" => {
+                    let quality = value
+                        .parse::<u8>()
+                        .ok()
+                        .filter(|q| (1..=MAX_JPEG_QUALITY).contains(q))
+                        .ok_or_else(|| RemoteConfigError::InvalidValue(field.to_string()))?;
+                    config.jpeg_quality = Some(quality);
+                }
+                "FRAME_SIZE" => {
+                    let valid = !value.is_empty()
+                        && value.len() <= MAX_FRAME_SIZE_LEN
+                        && value.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_');
+                    if !valid {
+                        return Err(RemoteConfigError::InvalidValue(field.to_string()));
+                    }
+                    config.frame_size = Some(value.to_ascii_uppercase());
+                }
+                _ => return Err(RemoteConfigError::UnknownKey(key.to_string())),
+            }
+        }
+        Ok(config)
+    }
+
+    /// ゲートウェイから受信した設定更新を重ねた新しい設定を作成します
+    ///
+    /// 更新に含まれない項目は現在の値を引き継ぎます。一斉配信の設定IDは更新文字列からは変更しません。
+    pub fn with_update(&self, update: &str) -> Result<Self, RemoteConfigError> {
+        let update = RemoteConfig::decode(update)?;
+        Ok(RemoteConfig {
+            receiver_mac: update.receiver_mac.or_else(|| self.receiver_mac.clone()),
+            sleep_duration_seconds: update.sleep_duration_seconds.or(self.sleep_duration_seconds),
+            broadcast_id: self.broadcast_id,
+            jpeg_quality: update.jpeg_quality.or(self.jpeg_quality),
+            frame_size: update.frame_size.or_else(|| self.frame_size.clone()),
+        })
+    }
+
+    /// 一斉配信された設定を重ねた新しい設定を作成します（署名で保護された設定IDを記録）
+    pub fn with_broadcast(&self, config_id: u32, broadcast: &str) -> Result<Self, RemoteConfigError> {
+        Ok(RemoteConfig {
+            broadcast_id: Some(config_id),
+            ..self.with_update(broadcast)?
+        })
+    }
+
+    /// 確定済み設定との差分（ログ出力用）
+    pub fn diff(&self, previous: &RemoteConfig) -> Vec<String> {
+        let mut changes = Vec::new();
+        if self.receiver_mac != previous.receiver_mac {
+            changes.push(format!(
+                "receiver_mac: {:?} -> {:?}",
+                previous.receiver_mac.as_ref().map(|m| m.to_string()),
+                self.receiver_mac.as_ref().map(|m| m.to_string())
+            ));
+        }
+        if self.sleep_duration_seconds != previous.sleep_duration_seconds {
+            changes.push(format!(
+                "sleep_duration_seconds: {:?} -> {:?}",
+                previous.sleep_duration_seconds, self.sleep_duration_seconds
+            ));
+        }
+        if self.jpeg_quality != previous.jpeg_quality {
+            changes.push(format!("jpeg_quality: {:?} -> {:?}", previous.jpeg_quality, self.jpeg_quality));
+        }
+        if self.frame_size != previous.frame_size {
+            changes.push(format!("frame_size: {:?} -> {:?}", previous.frame_size, self.frame_size));
+        }
+        if self.broadcast_id != previous.broadcast_id {
+            changes.push(format!(
+                "broadcast_id: {:?} -> {:?}",
+                previous.broadcast_id, self.broadcast_id
+            ));
+        }
+        changes
+    }
+}
@@ -0,0 +1,173 @@
+use esp_idf_svc::nvs::{EspDefaultNvsPartition, EspNvs, NvsDefault};
+use esp_idf_svc::sys::EspError;
+use log::{error, info, warn};
+
+use super::config_staging::{decide_on_boot, BootDecision, RemoteConfig, StagingState};
+use super::nvs_health::{NvsRecovery, NvsUsage};
+
+/// NVS名前空間
+const NVS_NAMESPACE: &str = "remote_cfg";
+/// 確定済み設定のキー
+const NVS_KEY_COMMITTED: &str = "committed";
+/// ステージング中の設定のキー
+const NVS_KEY_STAGED: &str = "staged";
+/// ステージング状態のキー
+const NVS_KEY_STATE: &str = "state";
+/// 設定文字列の読み込みバッファサイズ
+const CONFIG_BUFFER_SIZE: usize = 128;
+
+/// 起動時に有効となるリモート設定
+#[derive(Debug, Clone, Default)]
+pub struct ActiveRemoteConfig {
+    /// 適用するリモート設定（なければcfg.tomlのみ）
+    pub config: Option<RemoteConfig>,
+    /// 試行中の設定かどうか（ゲートウェイの確認後に `commit_trial` で確定）
+    pub is_trial: bool,
+    /// 前回の試行設定をロールバックしたかどうか
+    pub rolled_back: bool,
+}
+
+/// リモート設定のNVSストア
+///
+/// `stage` で保存した設定は次回起動時に1回だけ試行され、
+/// `commit_trial` が呼ばれないまま再起動すると確定済み設定へロールバックします。
+pub struct RemoteConfigStore {
+    nvs: EspNvs<NvsDefault>,
+}
+
+impl RemoteConfigStore {
+    /// NVSを開きます
+    pub fn open(partition: EspDefaultNvsPartition) -> Result<Self, EspError> {
+        Ok(Self {
+            nvs: EspNvs::new(partition, NVS_NAMESPACE, true)?,
+        })
+    }
+
+    /// 保存内容を検査し、破損または空き不足なら名前空間を消去してcfg.tomlの設定に戻します
+    ///
+    /// `boot` の前に呼び出します。修復した場合はその内容を返します。
+    pub fn check_health(&mut self, usage: Option<NvsUsage>) -> Option<NvsRecovery> {
+        let recovery = if !self.is_readable() {
+            NvsRecovery::ConfigCorrupted
+        } else if usage.is_some_and(|usage| usage.is_full()) {
+            NvsRecovery::ConfigFull
+        } else {
+            return None;
+        };
+        warn!("リモート設定のNVSを初期化します（{}）", recovery.as_str());
+        for key in [NVS_KEY_STATE, NVS_KEY_STAGED, NVS_KEY_COMMITTED] {
+            if let Err(e) = self.nvs.remove(key) {
+                error!("リモート設定 {} の削除に失敗しました: {:?}", key, e);
+            }
+        }
+        Some(recovery)
+    }
+
+    /// 状態と保存済みの設定をすべて読み込めるか
+    fn is_readable(&self) -> bool {
+        if let Err(e) = self.nvs.get_u8(NVS_KEY_STATE) {
+            warn!("リモート設定の状態を読み込めません: {:?}", e);
+            return false;
+        }
+        [NVS_KEY_STAGED, NVS_KEY_COMMITTED].into_iter().all(|key| {
+            let mut buffer = [0u8; CONFIG_BUFFER_SIZE];
+            match self.nvs.get_str(key, &mut buffer) {
+                Ok(Some(encoded)) => RemoteConfig::decode(encoded).is_ok(),
+                Ok(None) => true,
+                Err(_) => false,
+            }
+        })
+    }
+
+    /// 起動時の状態遷移を行い、今回使用する設定を返します
+    pub fn boot(&mut self) -> ActiveRemoteConfig {
+        let state = match self.nvs.get_u8(NVS_KEY_STATE) {
+            Ok(value) => StagingState::from_u8(value.unwrap_or(0)),
+            Err(e) => {
+                warn!("リモート設定の状態を読み込めません（確定済み設定を使用）: {:?}", e);
+                StagingState::Idle
+            }
+        };
+        let (decision, next_state) = decide_on_boot(state);
+        if next_state != state {
+            if let Err(e) = self.nvs.set_u8(NVS_KEY_STATE, next_state as u8) {
+                // 試行状態を保存できなければ、ロールバックできないため試行しない
+                error!("リモート設定の状態を保存できません: {:?}", e);
+                return self.committed_only(false);
+            }
+        }
+
+        match decision {
+            BootDecision::UseCommitted => self.committed_only(false),
+            BootDecision::TryStaged => {
+                let Some(staged) = self.read_config(NVS_KEY_STAGED) else {
+                    warn!("ステージングされた設定を読み込めません（確定済み設定を使用）");
+                    let _ = self.nvs.set_u8(NVS_KEY_STATE, StagingState::Idle as u8);
+                    return self.committed_only(false);
+                };
+                let committed = self.read_config(NVS_KEY_COMMITTED).unwrap_or_default();
+                for change in staged.diff(&committed) {
+                    info!("リモート設定を試行します: {}", change);
+                }
+                ActiveRemoteConfig {
+                    config: Some(staged),
+                    is_trial: true,
+                    rolled_back: false,
+                }
+            }
+            BootDecision::RolledBack => {
+                warn!("前回の試行設定が確定されなかったため、確定済み設定へロールバックします");
+                if let Err(e) = self.nvs.remove(NVS_KEY_STAGED) {
+                    warn!("ステージングされた設定の削除に失敗しました: {:?}", e);
+                }
+                self.committed_only(true)
+            }
+        }
+    }
+
+    /// 新しい設定をステージングします（次回起動時に試行）
+    pub fn stage(&mut self, config: &RemoteConfig) -> Result<(), EspError> {
+        self.nvs.set_str(NVS_KEY_STAGED, &config.encode())?;
+        self.nvs.set_u8(NVS_KEY_STATE, StagingState::Staged as u8)?;
+        info!("リモート設定をステージングしました: {}", config.encode());
+        Ok(())
+    }
+
+    /// 試行中の設定を確定します（ゲートウェイが送信を確認した後に呼び出す）
+    pub fn commit_trial(&mut self, active: &ActiveRemoteConfig) -> Result<(), EspError> {
+        let Some(config) = active.config.as_ref().filter(|_| active.is_trial) else {
+            return Ok(());
+        };
+        self.nvs.set_str(NVS_KEY_COMMITTED, &config.encode())?;
+        self.nvs.set_u8(NVS_KEY_STATE, StagingState::Idle as u8)?;
+        self.nvs.remove(NVS_KEY_STAGED)?;
+        info!("リモート設定を確定しました: {}", config.encode());
+        Ok(())
+    }
+
+    fn committed_only(&self, rolled_back: bool) -> ActiveRemoteConfig {
+        ActiveRemoteConfig {
+            config: self.read_config(NVS_KEY_COMMITTED),
+            is_trial: false,
+            rolled_back,
+        }
+    }
+
+    fn read_config(&self, key: &str) -> Option<RemoteConfig> {
+        let mut buffer = [0u8; CONFIG_BUFFER_SIZE];
+        let encoded = match self.nvs.get_str(key, &mut buffer) {
+            Ok(value) => value?,
+            Err(e) => {
+                warn!("リモート設定 {} の読み込みに失敗しました: {:?}", key, e);
+                return None;
+            }
+        };
+        match RemoteConfig::decode(encoded) {
+            Ok(config) => Some(config),
+            Err(e) => {
+                warn!("リモート設定 {} が不正です: {}", key, e);
+                None
+            }
+        }
+    }
+}
@@ -8,8 +8,12 @@ pub enum ValidationError {
     InvalidTargetMinuteLastDigit(u8),
     InvalidTargetSecondLastDigit(u8),
     MissingWifiSsid,
+    InvalidSleepBounds(u64, u64),
 }
 
+/// スリープコマンドの許容範囲の上限（24時間）
+pub const MAX_SLEEP_BOUND_SECONDS: u64 = 86_400;
+
 pub fn parse_receiver_mac(receiver_mac: &str) -> Result<MacAddress, ValidationError> {
     if receiver_mac == "11:22:33:44:55:66" || receiver_mac.is_empty() {
         return Err(ValidationError::MissingReceiverMac);
@@ -51,6 +55,14 @@ pub fn parse_target_second_tens_digit(value: u8) -> Result<Option<u8>, Validatio
     }
 }
 
+pub fn validate_sleep_bounds(min_seconds: u64, max_seconds: u64) -> Result<(), ValidationError> {
+    if min_seconds == 0 || min_seconds > max_seconds || max_seconds > MAX_SLEEP_BOUND_SECONDS {
+        Err(ValidationError::InvalidSleepBounds(min_seconds, max_seconds))
+    } else {
+        Ok(())
+    }
+}
+
 pub fn validate_wifi_ssid(ssid: &str) -> Result<(), ValidationError> {
     if ssid.is_empty() {
         Err(ValidationError::MissingWifiSsid)
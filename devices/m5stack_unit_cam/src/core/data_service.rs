@@ -1,27 +1,105 @@
+use std::time::Instant;
+
 use esp_idf_svc::hal::delay::FreeRtos;
 use log::{error, info, warn};
 
-use crate::communication::esp_now::EspNowSender;
-use crate::core::{
-    should_capture_image_with_overrides, INVALID_VOLTAGE_PERCENT, LOW_VOLTAGE_THRESHOLD_PERCENT,
+use crate::communication::esp_now::{
+    downlink_rejections, hop_metadata_fields, probe_skipped_cycles, security_metadata_fields, EspNowReceiver,
+    EspNowSender, ProbeOutcome,
 };
+use crate::core::{should_capture_image_with_overrides, CaptureSkipReason, LOW_VOLTAGE_THRESHOLD_PERCENT};
 use crate::core::config::{AppConfig, CameraStandbyMode};
-use crate::core::prepare_image_payload;
-use crate::hardware::camera::CameraController;
-use crate::hardware::led::StatusLed;
+use crate::core::{
+    assess_image, clamped_sleep_request, prepare_image_payload, retention_metadata_fields, window_saved_metadata,
+    CaptureAlignment, DebugFlags, LifecycleReport, NvsRecovery, QualityAssessment, RetainedImage, RtcManager, SequenceFrame,
+    DeviceThumbnailRing, TraceContext, MAX_RESENDS_PER_CYCLE,
+};
+use crate::core::{debug_flags, device_identity, nvs_health};
+use crate::hardware::camera::{
+    CameraController, CameraError, CameraRecovery, FrameBufferFailure, FrameBufferStage, Warmup, WarmupStep,
+};
+use crate::hardware::led::{LedEvent, StatusLed};
+use crate::power::sleep::alignment::is_clock_valid;
+use crate::units::{Celsius, Millivolts, Percent, Ppm};
 
 /// 測定データ構造体
 #[derive(Debug)]
 pub struct MeasuredData {
-    pub voltage_percent: u8,
+    pub voltage_percent: Percent,
     pub image_data: Option<Vec<u8>>,
+    /// 撮影しなかった理由（撮影した場合はNone。画像品質によるスキップは送信時に判定する）
+    pub capture_skip: Option<CaptureSkipReason>,
+    /// プロビジョニングで書き込んだデバイスID（書き込まれていなければNone）
+    pub device_id: Option<String>,
+    /// 温度センサーの値（未接続ならNone）
+    pub temperature: Option<Celsius>,
+    /// TDSセンサーの出力電圧（温度補正前、未接続ならNone）
+    pub tds_voltage: Option<Millivolts>,
+    /// スリープ補正に使用中のRTCドリフト推定値（ppm）
+    pub sleep_drift_ppm: Option<i32>,
+    /// 起動時にリモート設定をロールバックしたかどうか
+    pub config_rollback: bool,
+    /// 起動時に行ったNVSの自己修復
+    pub nvs_recoveries: Vec<NvsRecovery>,
+    /// 適用中の一斉配信の設定ID（ゲートウェイが適用状況の集計に使用）
+    pub broadcast_id: Option<u32>,
+    /// 適用中のカメラ画質設定（JPEG品質, フレームサイズ）。ゲートウェイが設定更新の適用確認に使用
+    pub camera_settings: Option<(Option<u8>, String)>,
+    /// ダウンリンクで有効にしたデバッグフラグと残りサイクル数
+    pub runtime_debug: Option<(DebugFlags, u16)>,
+    /// 今回のサイクルで発生したフレームバッファ確保失敗
+    pub fb_failure: Option<FrameBufferFailure>,
+    /// 今回のサイクルで行ったSCCBエラーからのカメラ再初期化
+    pub camera_recovery: Option<CameraRecovery>,
+    /// フレームバッファの取得時間と撮影の連続失敗回数のメタデータ（撮影しなかったサイクルはNone）
+    pub camera_health: Option<String>,
+    /// 撮影時刻の壁時計境界からの誤差（マイクロ秒）
+    pub align_error_us: Option<i64>,
+    /// 転送前の疎通確認の結果
+    pub probe: Option<ProbeOutcome>,
+    /// サイクルのトレース情報
+    pub trace: Option<TraceContext>,
+    /// 撮影した時刻（画像がない場合はNone）
+    pub captured_at: Option<Instant>,
+    /// 起動理由などのライフサイクル情報（起動後最初の送信のみ）
+    pub lifecycle: Option<LifecycleReport>,
+    /// ビルド情報と設定ハッシュのメタデータ（起動後最初の送信のみ）
+    pub build_info: Option<String>,
+    /// タイムラプスのシーケンス情報（画像がある場合のみ）
+    pub sequence: Option<SequenceFrame>,
+    /// 送信後のコマンド待機中に再送できるよう画像を保持するか
+    pub retain_for_resend: bool,
+    /// ソークテストのサイクル結果の累計のメタデータ（`soak-test` フィーチャーのみ）
+    pub soak: Option<String>,
 }
 
 impl MeasuredData {
-    pub fn new(voltage_percent: u8, image_data: Option<Vec<u8>>) -> Self {
+    pub fn new(voltage_percent: Percent, image_data: Option<Vec<u8>>) -> Self {
         Self {
             voltage_percent,
             image_data,
+            capture_skip: None,
+            device_id: None,
+            temperature: None,
+            tds_voltage: None,
+            sleep_drift_ppm: None,
+            config_rollback: false,
+            nvs_recoveries: Vec::new(),
+            broadcast_id: None,
+            camera_settings: None,
+            runtime_debug: None,
+            fb_failure: None,
+            camera_recovery: None,
+            camera_health: None,
+            align_error_us: None,
+            probe: None,
+            trace: None,
+            captured_at: None,
+            lifecycle: None,
+            build_info: None,
+            sequence: None,
+            retain_for_resend: false,
+            soak: None,
         }
     }
 }
@@ -32,36 +110,38 @@ pub struct DataService;
 impl DataService {
     /// ADC電圧レベルに基づいて画像キャプチャを実行
     pub fn capture_image_if_voltage_sufficient(
-        voltage_percent: u8,
+        voltage_percent: Percent,
         camera: Option<&CameraController>,
         app_config: &AppConfig,
         led: &mut StatusLed,
+        alignment: Option<&mut CaptureAlignment>,
     ) -> anyhow::Result<Option<Vec<u8>>> {
         if app_config.debug_mode {
             info!(
-                "debug: capture check voltage={}%, force_camera_test={}, bypass_voltage_threshold={}",
+                "debug: capture check voltage={}, force_camera_test={}, bypass_voltage_threshold={}",
                 voltage_percent, app_config.force_camera_test, app_config.bypass_voltage_threshold
             );
         }
 
         let should_capture = should_capture_image_with_overrides(
-            voltage_percent,
+            voltage_percent.value(),
             app_config.force_camera_test,
             app_config.bypass_voltage_threshold,
         );
 
         // ADC電圧条件をチェック
         if !should_capture {
-            if voltage_percent <= LOW_VOLTAGE_THRESHOLD_PERCENT {
-                warn!("ADC電圧が低すぎるため画像キャプチャをスキップします: {}%", voltage_percent);
-            } else if voltage_percent >= INVALID_VOLTAGE_PERCENT {
-                warn!("ADC電圧測定値が異常です: {}%", voltage_percent);
+            if voltage_percent.value() <= LOW_VOLTAGE_THRESHOLD_PERCENT {
+                warn!("ADC電圧が低すぎるため画像キャプチャをスキップします: {}", voltage_percent);
+                led.indicate(LedEvent::LowBattery)?;
+            } else if !voltage_percent.is_valid() {
+                warn!("ADC電圧測定値が異常です: {}", voltage_percent);
             }
             return Ok(None);
         }
 
-        info!("電圧条件OK({}%)、画像キャプチャを開始", voltage_percent);
-        led.turn_on()?;
+        info!("電圧条件OK({})、画像キャプチャを開始", voltage_percent);
+        led.indicate(LedEvent::Capture)?;
         let camera = camera.ok_or_else(|| anyhow::anyhow!("カメラコントローラー未初期化"))?;
 
         FreeRtos::delay_ms(100); // カメラの安定化を待つ
@@ -75,19 +155,45 @@ impl DataService {
             FreeRtos::delay_ms(30);
         }
 
-        // カメラウォームアップ（設定回数分画像を捨てる）
-        let warmup_count = app_config.camera_warmup_frames.unwrap_or(0);
-        for i in 0..warmup_count {
-            let _ = camera.capture_image();
-            info!("ウォームアップキャプチャ {} / {}", i + 1, warmup_count);
-            FreeRtos::delay_ms(1000);
+        // カメラウォームアップ（設定した方式で画像を捨てる）
+        let warmup_started = Instant::now();
+        let mut warmup = Warmup::new(app_config.camera_warmup);
+        while let WarmupStep::Discard { delay_after_ms } =
+            warmup.next_step(warmup_started.elapsed().as_millis() as u32)
+        {
+            let captured = camera.capture_image().is_ok();
+            warmup.record_discard(captured.then(|| camera.get_current_aec_value()));
+            if delay_after_ms > 0 {
+                FreeRtos::delay_ms(delay_after_ms);
+            }
+        }
+        if warmup.discarded() > 0 {
+            info!(
+                "ウォームアップ完了: 方式={}, 捨てフレーム={}, {} ms{}",
+                app_config.camera_warmup.as_str(),
+                warmup.discarded(),
+                warmup_started.elapsed().as_millis(),
+                if warmup.stabilized() { "（露出値が安定）" } else { "" }
+            );
+        }
+
+        // 壁時計の境界に揃える場合は、ウォームアップ後に境界まで待ってから撮影する
+        if let Some(alignment) = alignment {
+            alignment.wait_for_boundary();
         }
 
         let frame_buffer = camera.capture_image()?;
-        let image_data = frame_buffer.data().to_vec();
+        let mut image_data = Vec::new();
+        if image_data.try_reserve_exact(frame_buffer.data().len()).is_err() {
+            return Err(CameraError::FrameBufferUnavailable(
+                camera.frame_buffer_failure(FrameBufferStage::Copy),
+            )
+            .into());
+        }
+        image_data.extend_from_slice(frame_buffer.data());
         info!("画像キャプチャ完了: {} bytes", image_data.len());
 
-        led.turn_off()?;
+        led.clear()?;
         Ok(Some(image_data))
     }
 
@@ -97,17 +203,219 @@ impl DataService {
         esp_now_sender: &EspNowSender,
         led: &mut StatusLed,
         measured_data: MeasuredData,
-    ) -> anyhow::Result<()> {
-        led.turn_on()?;
+    ) -> anyhow::Result<Option<RetainedImage>> {
+        led.indicate(LedEvent::Transmit)?;
+
+        // 画像品質を評価し、閾値を満たさない画像は送信しない
+        let assessment = match assess_image(
+            measured_data.image_data.as_deref(),
+            &app_config.image_quality_thresholds,
+        ) {
+            Ok(assessment) => assessment,
+            Err(e) => {
+                warn!("画像品質の評価に失敗しました（送信は継続）: {}", e);
+                QualityAssessment::default()
+            }
+        };
+        if let Some(quality) = assessment.quality {
+            info!(
+                "画像品質: 平均輝度={}, シャープネス={}",
+                quality.mean_luma, quality.sharpness
+            );
+        }
+        let image_data = match assessment.skip_reason {
+            Some(reason) => {
+                warn!("画像品質が閾値を満たさないため画像送信をスキップします: {}", reason.as_str());
+                None
+            }
+            None => measured_data.image_data,
+        };
+
+        // HASHフレームに付加するメタデータ（画像品質・撮影しなかった理由・デバイスID・スリープドリフト・設定ロールバック・一斉配信の設定ID・デバッグフラグ・FB確保失敗・撮影整列誤差・疎通確認・制御メッセージの拒否・ファイル転送の受信状況・スリープ時間の補正・コマンド待機の短縮・トレース・起動理由・ビルド情報・タイムラプス）
+        let mut metadata_fields = assessment.metadata_fields();
+        if let Some(reason) = measured_data.capture_skip {
+            metadata_fields.push_str(&reason.metadata_field());
+        }
+        if let Some(device_id) = &measured_data.device_id {
+            metadata_fields.push_str(&device_identity::metadata_field(device_id));
+        }
+        if let Some(drift_ppm) = measured_data.sleep_drift_ppm {
+            metadata_fields.push_str(&format!(",DRIFT_PPM:{}", drift_ppm));
+        }
+        if measured_data.config_rollback {
+            metadata_fields.push_str(",CONFIG_ROLLBACK:1");
+        }
+        metadata_fields.push_str(&nvs_health::metadata_field(&measured_data.nvs_recoveries));
+        if let Some(broadcast_id) = measured_data.broadcast_id {
+            metadata_fields.push_str(&format!(",BCAST_ID:{}", broadcast_id));
+        }
+        if let Some((jpeg_quality, frame_size)) = &measured_data.camera_settings {
+            if let Some(quality) = jpeg_quality {
+                metadata_fields.push_str(&format!(",JPEG_Q:{}", quality));
+            }
+            metadata_fields.push_str(&format!(",FRAME_SIZE:{}", frame_size));
+        }
+        if let Some((flags, remaining_cycles)) = measured_data.runtime_debug {
+            metadata_fields.push_str(&debug_flags::metadata_field(flags, remaining_cycles));
+        }
+        if let Some(failure) = &measured_data.fb_failure {
+            metadata_fields.push_str(&failure.metadata_fields());
+        }
+        if let Some(recovery) = &measured_data.camera_recovery {
+            metadata_fields.push_str(&recovery.metadata_fields());
+        }
+        if let Some(health) = &measured_data.camera_health {
+            metadata_fields.push_str(health);
+        }
+        if let Some(error_us) = measured_data.align_error_us {
+            metadata_fields.push_str(&format!(",ALIGN_ERR_US:{}", error_us));
+        }
+        if let Some(probe) = &measured_data.probe {
+            metadata_fields.push_str(&probe.metadata_fields(probe_skipped_cycles()));
+            metadata_fields.push_str(&hop_metadata_fields());
+        }
+        let (replays, bad_signatures) = downlink_rejections();
+        metadata_fields.push_str(&security_metadata_fields(replays, bad_signatures));
+        metadata_fields.push_str(&EspNowReceiver::reject_metadata_fields());
+        metadata_fields.push_str(&EspNowReceiver::key_metadata_fields());
+        metadata_fields.push_str(&EspNowReceiver::file_metadata_fields());
+        if let Some(requested) = clamped_sleep_request() {
+            metadata_fields.push_str(&format!(",SLEEP_CLAMPED:{}", requested));
+        }
+        metadata_fields.push_str(&window_saved_metadata());
+        if let Some(trace) = &measured_data.trace {
+            metadata_fields.push_str(&trace.metadata_fields());
+        }
+        if let Some(lifecycle) = &measured_data.lifecycle {
+            metadata_fields.push_str(&lifecycle.metadata_fields());
+        }
+        if let Some(build_info) = &measured_data.build_info {
+            metadata_fields.push_str(build_info);
+        }
+        if let Some(soak) = &measured_data.soak {
+            metadata_fields.push_str(soak);
+        }
+        // 品質チェックで画像を送らない場合は通し番号も付けない（受信側で欠番として扱える）
+        if let (Some(sequence), Some(_)) = (&measured_data.sequence, &image_data) {
+            metadata_fields.push_str(&sequence.metadata_fields());
+        }
 
         // 画像データの処理と送信
-        let (image_data, _hash) = prepare_image_payload(measured_data.image_data);
+        let (image_data, hash) = prepare_image_payload(image_data, app_config.image_hash_algo);
         if image_data.is_empty() {
             warn!("画像データなし、ダミーデータを送信");
         } else {
-            info!("画像データを送信中: {} bytes", image_data.len());
+            info!("画像データを送信中: {} bytes ({})", image_data.len(), app_config.image_hash_algo);
+            metadata_fields.push_str(&app_config.image_hash_algo.metadata_field());
+        }
+
+        // コマンド待機中の再送要求に備えて画像を保持する（識別子はトレースID）
+        let frame_id = measured_data.trace.as_ref().map_or(0, |trace| trace.trace_id);
+        let retain = measured_data.retain_for_resend && !image_data.is_empty();
+        if retain {
+            metadata_fields.push_str(&retention_metadata_fields(image_data.len(), frame_id));
         }
 
+        Self::send_transfer(
+            app_config,
+            esp_now_sender,
+            led,
+            &image_data,
+            &hash,
+            measured_data.voltage_percent,
+            measured_data.temperature,
+            measured_data.tds_voltage,
+            &metadata_fields,
+            measured_data.captured_at,
+        )?;
+
+        Ok(retain.then(|| {
+            RetainedImage::new(frame_id, image_data, hash, metadata_fields, measured_data.captured_at)
+        }))
+    }
+
+    /// 保持中の画像を撮影し直さずに送り直します（HASHフレームに `RESEND:<回数>` を付加）
+    pub fn resend_retained_image(
+        app_config: &AppConfig,
+        esp_now_sender: &EspNowSender,
+        led: &mut StatusLed,
+        voltage_percent: Percent,
+        retained: &mut RetainedImage,
+    ) -> anyhow::Result<()> {
+        let Some(metadata_fields) = retained.begin_resend() else {
+            warn!("再送の上限（{}回）に達したため再送要求を無視します", MAX_RESENDS_PER_CYCLE);
+            return Ok(());
+        };
+        info!(
+            "保持中の画像を再送します: {} bytes (frame {:016x})",
+            retained.bytes_held(),
+            retained.frame_id()
+        );
+        led.indicate(LedEvent::Transmit)?;
+        Self::send_transfer(
+            app_config,
+            esp_now_sender,
+            led,
+            retained.data(),
+            retained.hash(),
+            voltage_percent,
+            None,
+            None,
+            &metadata_fields,
+            retained.captured_at(),
+        )
+    }
+
+    /// 保持中のサムネイルを古い順に送ります（HASHフレームに `THUMB:<番号>/<枚数>` と撮影時刻を付加）
+    pub fn send_thumbnails(
+        app_config: &AppConfig,
+        esp_now_sender: &EspNowSender,
+        led: &mut StatusLed,
+        voltage_percent: Percent,
+        ring: &DeviceThumbnailRing,
+    ) -> anyhow::Result<()> {
+        let thumbnails = ring.thumbnails();
+        if thumbnails.is_empty() {
+            warn!("サムネイル要求を受信しましたが、保持中のサムネイルがありません");
+            return Ok(());
+        }
+        info!("保持中のサムネイルを{}枚送信します", thumbnails.len());
+        led.indicate(LedEvent::Transmit)?;
+        let count = thumbnails.len();
+        for (index, thumbnail) in thumbnails.into_iter().enumerate() {
+            let metadata_fields = thumbnail.metadata_fields(index + 1, count);
+            let (jpeg, hash) = prepare_image_payload(Some(thumbnail.jpeg), app_config.image_hash_algo);
+            info!("サムネイル {}/{} を送信中: {} bytes (通し番号 {})", index + 1, count, jpeg.len(), thumbnail.seq);
+            Self::send_transfer(
+                app_config,
+                esp_now_sender,
+                led,
+                &jpeg,
+                &hash,
+                voltage_percent,
+                None,
+                None,
+                &(metadata_fields + &app_config.image_hash_algo.metadata_field()),
+                None,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// 画像チャンク・HASHフレーム・EOFマーカーを順に送信します
+    #[allow(clippy::too_many_arguments)]
+    fn send_transfer(
+        app_config: &AppConfig,
+        esp_now_sender: &EspNowSender,
+        led: &mut StatusLed,
+        image_data: &[u8],
+        hash: &str,
+        voltage_percent: Percent,
+        temperature: Option<Celsius>,
+        tds_voltage: Option<Millivolts>,
+        metadata_fields: &str,
+        captured_at: Option<Instant>,
+    ) -> anyhow::Result<()> {
         // 設定されたサーバーMACアドレスを使用
         info!("設定されたサーバーMACアドレス: {}", app_config.receiver_mac);
         
@@ -122,26 +430,48 @@ impl DataService {
             }
             Err(e) => {
                 error!("画像データの送信に失敗しました: {:?}", e);
-                led.blink_error()?;
+                led.indicate(LedEvent::Error)?;
                 return Err(anyhow::anyhow!("データ送信エラー: {:?}", e));
             }
         }
 
+        // ゲートウェイでエンドツーエンド遅延を求めるため、HASH送信時点での撮影からの経過時間を付加
+        let mut metadata_fields = metadata_fields.to_string();
+        if let Some(captured_at) = captured_at {
+            let capture_age_ms = captured_at.elapsed().as_millis().min(u32::MAX as u128);
+            metadata_fields.push_str(&format!(",CAPTURE_AGE_MS:{}", capture_age_ms));
+            // 時刻同期済みなら撮影時刻も付加し、ゲートウェイでデバイス間の時計のずれを推定できるようにする
+            let now_unix_us = RtcManager::now_unix_us();
+            if is_clock_valid(now_unix_us) {
+                let captured_unix_ms = (now_unix_us / 1000).saturating_sub(capture_age_ms as u64);
+                metadata_fields.push_str(&format!(",CAPTURE_UNIX_MS:{}", captured_unix_ms));
+            }
+        }
+
+        if let Some(tds_voltage) = tds_voltage {
+            info!(
+                "TDS: {}（温度補正後 {}）",
+                tds_voltage,
+                Ppm::from_tds_voltage(tds_voltage, temperature)
+            );
+        }
+
         // HASHフレームを送信（サーバーがスリープコマンドを送信するために必要）
         let current_time = "2025/06/22 12:00:00.000"; // 簡易タイムスタンプ
         match esp_now_sender.send_hash_frame(
-            &_hash,
-            measured_data.voltage_percent,
-            None,
-            None,
+            hash,
+            voltage_percent,
+            temperature,
+            tds_voltage,
             current_time,
+            &metadata_fields,
         ) {
             Ok(_) => {
                 info!("HASHフレームの送信が完了しました");
             }
             Err(e) => {
                 error!("HASHフレームの送信に失敗しました: {:?}", e);
-                led.blink_error()?;
+                led.indicate(LedEvent::Error)?;
                 return Err(anyhow::anyhow!("HASHフレーム送信エラー: {:?}", e));
             }
         }
@@ -150,7 +480,7 @@ impl DataService {
         match esp_now_sender.send_eof_marker() {
             Ok(_) => {
                 info!("EOFマーカーの送信が完了しました");
-                led.blink_success()?;
+                led.indicate(LedEvent::Success)?;
                 
                 // EOFマーカーが確実にサーバーに届くまで追加待機
                 info!("EOFマーカー最終配信確認のため追加待機中...");
@@ -159,7 +489,7 @@ impl DataService {
             }
             Err(e) => {
                 error!("EOFマーカーの送信に失敗しました: {:?}", e);
-                led.blink_error()?;
+                led.indicate(LedEvent::Error)?;
                 return Err(anyhow::anyhow!("EOFマーカー送信エラー: {:?}", e));
             }
         }
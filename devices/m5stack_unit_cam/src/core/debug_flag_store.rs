@@ -0,0 +1,53 @@
+use esp_idf_svc::nvs::{EspDefaultNvsPartition, EspNvs, NvsDefault};
+use esp_idf_svc::sys::EspError;
+use log::{info, warn};
+
+use super::debug_flags::{DebugFlags, RuntimeDebug};
+
+/// NVS名前空間
+const NVS_NAMESPACE: &str = "debug";
+/// 一時フラグと残りサイクル数のキー
+const NVS_KEY_RUNTIME: &str = "runtime";
+
+/// ダウンリンクで有効にしたデバッグフラグのNVS永続化
+pub struct DebugFlagStore {
+    nvs: EspNvs<NvsDefault>,
+}
+
+impl DebugFlagStore {
+    /// NVSを開きます
+    pub fn open(partition: EspDefaultNvsPartition) -> Result<Self, EspError> {
+        let nvs = EspNvs::new(partition, NVS_NAMESPACE, true)?;
+        Ok(Self { nvs })
+    }
+
+    /// 今回の起動で有効なフラグと残りサイクル数を返し、残りサイクル数を1減らして保存します
+    pub fn begin_cycle(&mut self) -> (DebugFlags, u16) {
+        let stored = match self.nvs.get_u32(NVS_KEY_RUNTIME) {
+            Ok(value) => RuntimeDebug::from_nvs(value.unwrap_or(0)),
+            Err(e) => {
+                warn!("デバッグフラグを読み込めません: {:?}", e);
+                return (DebugFlags::default(), 0);
+            }
+        };
+        let (active, next) = stored.begin_cycle();
+        if next != stored {
+            self.save(next);
+        }
+        if active.any() {
+            info!(
+                "ダウンリンクで有効にしたデバッグフラグ: {}（残り {} サイクル）",
+                active.names(),
+                next.remaining_cycles
+            );
+        }
+        (active, next.remaining_cycles)
+    }
+
+    /// 受信したフラグを保存します（次回の起動から有効）
+    pub fn save(&mut self, runtime: RuntimeDebug) {
+        if let Err(e) = self.nvs.set_u32(NVS_KEY_RUNTIME, runtime.to_nvs()) {
+            warn!("デバッグフラグの保存に失敗しました: {:?}", e);
+        }
+    }
+}
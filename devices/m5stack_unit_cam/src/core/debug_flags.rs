@@ -0,0 +1,182 @@
+//! ダウンリンクで切り替えるデバッグ・バイパスフラグ
+//!
+//! `debug_mode`・`force_camera_test`・`bypass_voltage_threshold` は cfg.toml の値に加えて、
+//! ゲートウェイの設定更新（`DEBUG=<フラグ>/<サイクル数>`）で一時的に有効にできます。
+//! 有効にしたフラグはNVSに保存し、指定したサイクル数を起動するごとに減らして自動で解除します。
+//! 設置済みのカメラを回収・再書き込みせずに現地で調査するためのものです。
+
+/// 設定更新でのキー
+pub const DEBUG_FIELD_KEY: &str = "DEBUG";
+/// 有効にできるサイクル数の上限（解除し忘れても電池を使い切らないため）
+pub const MAX_DEBUG_CYCLES: u16 = 100;
+
+const DEBUG_MODE_BIT: u8 = 0x01;
+const FORCE_CAMERA_BIT: u8 = 0x02;
+const BYPASS_VOLTAGE_BIT: u8 = 0x04;
+
+/// フラグ名とビット（報告はこの順に並べる）
+const FLAG_NAMES: [(&str, u8); 3] = [
+    ("DEBUG", DEBUG_MODE_BIT),
+    ("FORCE_CAMERA", FORCE_CAMERA_BIT),
+    ("BYPASS_VOLTAGE", BYPASS_VOLTAGE_BIT),
+];
+
+/// フラグの解析エラー
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum DebugFlagsError {
+    #[error("不明なデバッグフラグ: {0}")]
+    UnknownFlag(String),
+    #[error("デバッグフラグのサイクル数が不正です: {0}")]
+    InvalidCycles(String),
+}
+
+/// デバッグ・バイパスフラグ
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DebugFlags {
+    /// 詳細ログ（`debug_mode`）
+    pub debug_mode: bool,
+    /// 電圧に関係なく撮影し、カメラの失敗をエラーにする（`force_camera_test`）
+    pub force_camera_test: bool,
+    /// 低電圧でも撮影する（`bypass_voltage_threshold`）
+    pub bypass_voltage_threshold: bool,
+}
+
+impl DebugFlags {
+    /// ビット表現から復元します
+    pub fn from_bits(bits: u8) -> Self {
+        Self {
+            debug_mode: bits & DEBUG_MODE_BIT != 0,
+            force_camera_test: bits & FORCE_CAMERA_BIT != 0,
+            bypass_voltage_threshold: bits & BYPASS_VOLTAGE_BIT != 0,
+        }
+    }
+
+    /// ビット表現
+    pub fn bits(&self) -> u8 {
+        let mut bits = 0;
+        if self.debug_mode {
+            bits |= DEBUG_MODE_BIT;
+        }
+        if self.force_camera_test {
+            bits |= FORCE_CAMERA_BIT;
+        }
+        if self.bypass_voltage_threshold {
+            bits |= BYPASS_VOLTAGE_BIT;
+        }
+        bits
+    }
+
+    /// いずれかのフラグが有効か
+    pub fn any(&self) -> bool {
+        self.bits() != 0
+    }
+
+    /// `+` 区切りのフラグ名（`OFF` はすべて無効）から解析します
+    pub fn parse(text: &str) -> Result<Self, DebugFlagsError> {
+        if text.eq_ignore_ascii_case("OFF") {
+            return Ok(Self::default());
+        }
+        let mut bits = 0;
+        for name in text.split('+') {
+            let (_, bit) = FLAG_NAMES
+                .iter()
+                .find(|(flag, _)| flag.eq_ignore_ascii_case(name.trim()))
+                .ok_or_else(|| DebugFlagsError::UnknownFlag(name.to_string()))?;
+            bits |= bit;
+        }
+        Ok(Self::from_bits(bits))
+    }
+
+    /// `+` 区切りのフラグ名（無効なら `OFF`）
+    pub fn names(&self) -> String {
+        let bits = self.bits();
+        if bits == 0 {
+            return "OFF".to_string();
+        }
+        FLAG_NAMES
+            .iter()
+            .filter(|(_, bit)| bits & bit != 0)
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>()
+            .join("+")
+    }
+}
+
+/// NVSに保存する一時フラグと残りサイクル数
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RuntimeDebug {
+    /// 有効にするフラグ
+    pub flags: DebugFlags,
+    /// 有効にする残りサイクル数（0なら無効）
+    pub remaining_cycles: u16,
+}
+
+impl RuntimeDebug {
+    /// 設定更新の値（`<フラグ>/<サイクル数>` または `OFF`）を解析します
+    pub fn parse(value: &str) -> Result<Self, DebugFlagsError> {
+        let (flags, cycles) = match value.split_once('/') {
+            Some((flags, cycles)) => (flags, Some(cycles)),
+            None => (value, None),
+        };
+        let flags = DebugFlags::parse(flags)?;
+        if !flags.any() {
+            return Ok(Self::default());
+        }
+        let remaining_cycles = cycles
+            .and_then(|cycles| cycles.parse::<u16>().ok())
+            .filter(|cycles| (1..=MAX_DEBUG_CYCLES).contains(cycles))
+            .ok_or_else(|| DebugFlagsError::InvalidCycles(value.to_string()))?;
+        Ok(Self { flags, remaining_cycles })
+    }
+
+    /// NVSの保存値（下位8ビット: フラグ、上位16ビット: 残りサイクル数）
+    pub fn to_nvs(self) -> u32 {
+        self.flags.bits() as u32 | (self.remaining_cycles as u32) << 16
+    }
+
+    /// NVSの保存値から復元します（範囲外のサイクル数は上限に丸める）
+    pub fn from_nvs(value: u32) -> Self {
+        Self {
+            flags: DebugFlags::from_bits(value as u8),
+            remaining_cycles: ((value >> 16) as u16).min(MAX_DEBUG_CYCLES),
+        }
+    }
+
+    /// 今回の起動で有効なフラグと、NVSへ保存する次回の状態を返します
+    pub fn begin_cycle(&self) -> (DebugFlags, RuntimeDebug) {
+        if self.remaining_cycles == 0 || !self.flags.any() {
+            return (DebugFlags::default(), RuntimeDebug::default());
+        }
+        let remaining_cycles = self.remaining_cycles - 1;
+        let next = if remaining_cycles == 0 {
+            RuntimeDebug::default()
+        } else {
+            RuntimeDebug { remaining_cycles, ..*self }
+        };
+        (self.flags, next)
+    }
+}
+
+/// 設定更新の文字列から `DEBUG` 項目を取り出します
+///
+/// # 戻り値
+/// `DEBUG` 項目の値（なければNone）と、残りの項目（リモート設定として適用する）
+pub fn split_debug_field(update: &str) -> (Option<&str>, String) {
+    let mut debug = None;
+    let mut rest = Vec::new();
+    for field in update.split(';').filter(|field| !field.is_empty()) {
+        match field.split_once('=') {
+            Some((DEBUG_FIELD_KEY, value)) => debug = Some(value),
+            _ => rest.push(field),
+        }
+    }
+    (debug, rest.join(";"))
+}
+
+/// HASHフレームに付加するメタデータ（`,DEBUG:<フラグ>/<残りサイクル数>`、無効なら空）
+pub fn metadata_field(active: DebugFlags, remaining_cycles: u16) -> String {
+    if !active.any() {
+        return String::new();
+    }
+    format!(",DEBUG:{}/{}", active.names(), remaining_cycles)
+}
@@ -0,0 +1,27 @@
+//! プロビジョニングで書き込むデバイスID
+//!
+//! MACアドレスは基板を交換すると変わるため、データの連続性を保つ主キーには使えません。
+//! プロビジョニング時にUUIDをNVSへ書き込み、毎回のHASHフレームに `DEV_ID` として付加します。
+//! ゲートウェイとホストはデバイスIDを主キーにし、MACアドレスは変わりうる属性として扱います。
+//! NVSの書き込みはプロビジョニングツール（`tools/provision`）の `nvs.csv` で行います。
+
+/// デバイスIDのNVS名前空間
+pub const NVS_NAMESPACE: &str = "identity";
+/// デバイスIDのNVSキー
+pub const NVS_KEY_DEVICE_ID: &str = "device_id";
+/// デバイスIDの長さ（ハイフン付きのUUID）
+pub const DEVICE_ID_LEN: usize = 36;
+
+/// ハイフン付きのUUID（`8-4-4-4-12` 桁の16進数）か
+pub fn is_valid_device_id(id: &str) -> bool {
+    id.len() == DEVICE_ID_LEN
+        && id.bytes().enumerate().all(|(index, byte)| match index {
+            8 | 13 | 18 | 23 => byte == b'-',
+            _ => byte.is_ascii_hexdigit(),
+        })
+}
+
+/// HASHフレームに付加するメタデータ
+pub fn metadata_field(id: &str) -> String {
+    format!(",DEV_ID:{}", id.to_ascii_lowercase())
+}
@@ -0,0 +1,33 @@
+use esp_idf_svc::nvs::{EspDefaultNvsPartition, EspNvs, NvsDefault};
+use log::{info, warn};
+
+use super::device_identity::{is_valid_device_id, DEVICE_ID_LEN, NVS_KEY_DEVICE_ID, NVS_NAMESPACE};
+
+/// プロビジョニングで書き込んだデバイスIDを読み込みます
+///
+/// # 戻り値
+/// 書き込まれていない、または形式が不正な場合はNone（ゲートウェイはMACアドレスで識別する）
+pub fn load_device_id(partition: EspDefaultNvsPartition) -> Option<String> {
+    // 読み取り専用で開くと、プロビジョニングしていない（名前空間がない）場合はエラーになる
+    let nvs = match EspNvs::<NvsDefault>::new(partition, NVS_NAMESPACE, false) {
+        Ok(nvs) => nvs,
+        Err(_) => {
+            info!("デバイスIDが書き込まれていません（MACアドレスで識別されます）");
+            return None;
+        }
+    };
+    let mut buffer = [0u8; DEVICE_ID_LEN + 1];
+    let id = match nvs.get_str(NVS_KEY_DEVICE_ID, &mut buffer) {
+        Ok(id) => id?,
+        Err(e) => {
+            warn!("デバイスIDを読み込めません: {:?}", e);
+            return None;
+        }
+    };
+    if !is_valid_device_id(id) {
+        warn!("デバイスIDの形式が不正なため使用しません: {}", id);
+        return None;
+    }
+    info!("デバイスID: {}", id);
+    Some(id.to_ascii_lowercase())
+}
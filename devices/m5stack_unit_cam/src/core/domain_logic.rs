@@ -1,13 +1,7 @@
+use crate::units::{Millivolts, Percent};
+
 pub fn voltage_to_percentage(voltage_mv: f32, min_mv: f32, max_mv: f32) -> u8 {
-    let range_mv = max_mv - min_mv;
-    let percentage = if range_mv <= 0.0 {
-        0.0
-    } else {
-        ((voltage_mv - min_mv) / range_mv * 100.0)
-            .max(0.0)
-            .min(100.0)
-    };
-    percentage.round() as u8
+    Percent::of_range(Millivolts::new(voltage_mv), Millivolts::new(min_mv), Millivolts::new(max_mv)).value()
 }
 
 pub fn resolve_sleep_duration_seconds(received_seconds: Option<u32>, default_seconds: u64) -> u64 {
@@ -17,6 +11,14 @@ pub fn resolve_sleep_duration_seconds(received_seconds: Option<u32>, default_sec
     }
 }
 
+/// サーバーから受信したスリープ時間を許容範囲に収めます
+///
+/// 範囲外だった場合は補正後の値と元の要求値を返します。
+pub fn clamp_sleep_duration_seconds(seconds: u64, min_seconds: u64, max_seconds: u64) -> (u64, Option<u64>) {
+    let clamped = seconds.clamp(min_seconds, max_seconds);
+    (clamped, (clamped != seconds).then_some(seconds))
+}
+
 pub fn clamp_wifi_tx_power_dbm(dbm: i8) -> i8 {
     dbm.clamp(2, 20)
 }
@@ -0,0 +1,67 @@
+use esp_idf_svc::nvs::{EspDefaultNvsPartition, EspNvs, NvsDefault};
+use esp_idf_svc::sys::EspError;
+use log::{info, warn};
+
+use crate::communication::esp_now::{FileAssembly, MAX_FILE_LEN, MAX_FILE_SLOT_LEN};
+
+/// NVS名前空間（受信したファイルはスロット名をキーにして保存する）
+const NVS_NAMESPACE: &str = "files";
+/// 受信途中のファイルの記録のキー（スロット名と重ならないよう、スロット名に使えない `.` で始める）
+const NVS_KEY_PROGRESS: &str = ".progress";
+/// 記録のバッファ長（ヘッダ + スロット名 + 受信途中の内容）
+const PROGRESS_BUFFER_LEN: usize = 9 + MAX_FILE_SLOT_LEN + MAX_FILE_LEN;
+
+/// ゲートウェイから受信したファイルと受信途中の状態のNVS永続化
+pub struct FileStore {
+    nvs: EspNvs<NvsDefault>,
+}
+
+impl FileStore {
+    /// NVSを開きます
+    pub fn open(partition: EspDefaultNvsPartition) -> Result<Self, EspError> {
+        let nvs = EspNvs::new(partition, NVS_NAMESPACE, true)?;
+        Ok(Self { nvs })
+    }
+
+    /// 受信途中（または受信を終えて未報告）のファイルを読み込みます
+    pub fn load_progress(&self) -> Option<FileAssembly> {
+        let mut buffer = vec![0u8; PROGRESS_BUFFER_LEN];
+        let record = match self.nvs.get_raw(NVS_KEY_PROGRESS, &mut buffer) {
+            Ok(record) => record?,
+            Err(e) => {
+                warn!("ファイル転送の記録を読み込めません: {:?}", e);
+                return None;
+            }
+        };
+        let assembly = FileAssembly::from_record(record);
+        match &assembly {
+            Some(assembly) => info!("ファイル転送を引き継ぎます:{}", assembly.metadata_fields()),
+            None => warn!("ファイル転送の記録が壊れているため破棄します"),
+        }
+        assembly
+    }
+
+    /// 受信状況を保存し、受信を終えたファイルはスロットに保存します
+    pub fn save_progress(&mut self, assembly: &FileAssembly) {
+        if assembly.is_complete() {
+            match self.nvs.set_raw(assembly.slot(), assembly.data()) {
+                Ok(_) => info!("ファイル {} を保存しました（{} bytes）", assembly.slot(), assembly.data().len()),
+                Err(e) => {
+                    // 記録を更新しなければ、次回の起動で受信途中の状態から受信し直す
+                    warn!("ファイル {} の保存に失敗しました: {:?}", assembly.slot(), e);
+                    return;
+                }
+            }
+        }
+        if let Err(e) = self.nvs.set_raw(NVS_KEY_PROGRESS, &assembly.to_record()) {
+            warn!("ファイル転送の記録の保存に失敗しました: {:?}", e);
+        }
+    }
+
+    /// 受信を終えたファイルの報告を終えたら記録を削除します
+    pub fn clear_progress(&mut self) {
+        if let Err(e) = self.nvs.remove(NVS_KEY_PROGRESS) {
+            warn!("ファイル転送の記録の削除に失敗しました: {:?}", e);
+        }
+    }
+}
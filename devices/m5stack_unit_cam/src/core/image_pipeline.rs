@@ -0,0 +1,600 @@
+//! 撮影画像の品質評価
+//!
+//! JPEGをフルデコードせず、エントロピー符号の復号だけで輝度成分の係数を集計します。
+//! - 平均輝度: 輝度ブロックのDC係数（ブロック平均）から算出（0〜255）
+//! - シャープネス: 輝度ブロックあたりのAC係数絶対値の合計（逆量子化後）の平均
+//!
+//! ブレやピンぼけ、露出不足の画像はAC成分・DC成分が小さくなるため、
+//! 閾値を下回る画像の送信をスキップして送信電力を節約できます。
+
+/// 品質評価のエラー
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum QualityError {
+    #[error("JPEGデータではありません")]
+    NotJpeg,
+
+    #[error("JPEGデータが途中で終わっています")]
+    Truncated,
+
+    #[error("未対応のJPEG形式です: {0}")]
+    Unsupported(&'static str),
+
+    #[error("JPEGデータが不正です: {0}")]
+    Invalid(&'static str),
+}
+
+/// 画像品質の評価値
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImageQuality {
+    /// 平均輝度（0〜255）
+    pub mean_luma: u8,
+    /// シャープネス（相対値、大きいほど高周波成分が多い）
+    pub sharpness: u16,
+}
+
+/// 送信スキップの判定閾値
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QualityThresholds {
+    /// 閾値を満たさない画像の送信をスキップするかどうか
+    pub skip_enabled: bool,
+    /// 平均輝度の下限（これ未満は暗すぎる）
+    pub min_luma: u8,
+    /// 平均輝度の上限（これを超えると明るすぎる）
+    pub max_luma: u8,
+    /// シャープネスの下限（これ未満はブレ・ピンぼけ）
+    pub min_sharpness: u16,
+}
+
+impl Default for QualityThresholds {
+    fn default() -> Self {
+        Self {
+            skip_enabled: false,
+            min_luma: 0,
+            max_luma: u8::MAX,
+            min_sharpness: 0,
+        }
+    }
+}
+
+/// 送信スキップの理由
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SkipReason {
+    /// 暗すぎる
+    TooDark,
+    /// 明るすぎる
+    TooBright,
+    /// ブレ・ピンぼけ
+    Blurry,
+}
+
+impl SkipReason {
+    /// テレメトリ用の文字列表現
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SkipReason::TooDark => "DARK",
+            SkipReason::TooBright => "BRIGHT",
+            SkipReason::Blurry => "BLUR",
+        }
+    }
+}
+
+/// 画像1枚分の品質評価結果
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct QualityAssessment {
+    /// 評価値（画像なし・解析失敗時はNone）
+    pub quality: Option<ImageQuality>,
+    /// 送信をスキップする場合の理由
+    pub skip_reason: Option<SkipReason>,
+}
+
+impl QualityAssessment {
+    /// HASHフレーム（メタデータ）に付加するフィールドを生成します
+    ///
+    /// 形式: `,LUMA:<n>,SHARP:<n>[,SKIP:<reason>]`（評価値がない場合は空文字列）
+    pub fn metadata_fields(&self) -> String {
+        let mut fields = String::new();
+        if let Some(quality) = self.quality {
+            fields.push_str(&format!(",LUMA:{},SHARP:{}", quality.mean_luma, quality.sharpness));
+        }
+        if let Some(reason) = self.skip_reason {
+            fields.push_str(&format!(",SKIP:{}", reason.as_str()));
+        }
+        fields
+    }
+}
+
+/// 評価値が閾値を満たさない理由を返します（満たす場合はNone）
+pub fn check_thresholds(quality: &ImageQuality, thresholds: &QualityThresholds) -> Option<SkipReason> {
+    if quality.mean_luma < thresholds.min_luma {
+        Some(SkipReason::TooDark)
+    } else if quality.mean_luma > thresholds.max_luma {
+        Some(SkipReason::TooBright)
+    } else if quality.sharpness < thresholds.min_sharpness {
+        Some(SkipReason::Blurry)
+    } else {
+        None
+    }
+}
+
+/// 画像を評価し、スキップ有効時は閾値判定も行います
+///
+/// 解析できない画像は評価値なし・スキップなし（従来どおり送信）とします。
+pub fn assess_image(
+    image_data: Option<&[u8]>,
+    thresholds: &QualityThresholds,
+) -> Result<QualityAssessment, QualityError> {
+    let Some(data) = image_data.filter(|data| !data.is_empty()) else {
+        return Ok(QualityAssessment::default());
+    };
+    let quality = analyze_jpeg(data)?;
+    let skip_reason = if thresholds.skip_enabled {
+        check_thresholds(&quality, thresholds)
+    } else {
+        None
+    };
+    Ok(QualityAssessment {
+        quality: Some(quality),
+        skip_reason,
+    })
+}
+
+const MARKER_SOI: u8 = 0xD8;
+const MARKER_EOI: u8 = 0xD9;
+const MARKER_SOF0: u8 = 0xC0;
+const MARKER_SOF1: u8 = 0xC1;
+const MARKER_SOF2: u8 = 0xC2;
+const MARKER_DHT: u8 = 0xC4;
+const MARKER_SOS: u8 = 0xDA;
+const MARKER_DQT: u8 = 0xDB;
+const MARKER_DRI: u8 = 0xDD;
+const MAX_COMPONENTS: usize = 3;
+
+/// ベースラインJPEGの輝度成分を集計して品質を評価します
+pub fn analyze_jpeg(data: &[u8]) -> Result<ImageQuality, QualityError> {
+    if data.len() < 4 || data[0] != 0xFF || data[1] != MARKER_SOI {
+        return Err(QualityError::NotJpeg);
+    }
+
+    let mut quant: [Option<[u16; 64]>; 4] = [None; 4];
+    let mut dc_tables: [Option<HuffmanTable>; 4] = Default::default();
+    let mut ac_tables: [Option<HuffmanTable>; 4] = Default::default();
+    let mut frame: Option<FrameHeader> = None;
+    let mut restart_interval = 0u16;
+    let mut pos = 2;
+
+    loop {
+        // マーカー前のフィルバイトを読み飛ばす
+        while pos < data.len() && data[pos] == 0xFF && data.get(pos + 1) == Some(&0xFF) {
+            pos += 1;
+        }
+        if pos + 4 > data.len() {
+            return Err(QualityError::Truncated);
+        }
+        if data[pos] != 0xFF {
+            return Err(QualityError::Invalid("マーカーがありません"));
+        }
+        let marker = data[pos + 1];
+        if marker == MARKER_EOI {
+            return Err(QualityError::Invalid("スキャンがありません"));
+        }
+        let length = u16::from_be_bytes([data[pos + 2], data[pos + 3]]) as usize;
+        let segment = data
+            .get(pos + 4..pos + 2 + length)
+            .filter(|_| length >= 2)
+            .ok_or(QualityError::Truncated)?;
+        pos += 2 + length;
+
+        match marker {
+            MARKER_DQT => parse_dqt(segment, &mut quant)?,
+            MARKER_DHT => parse_dht(segment, &mut dc_tables, &mut ac_tables)?,
+            MARKER_SOF0 | MARKER_SOF1 => frame = Some(FrameHeader::parse(segment)?),
+            MARKER_SOF2 => return Err(QualityError::Unsupported("プログレッシブJPEG")),
+            0xC3 | 0xC5..=0xC7 | 0xC9..=0xCB | 0xCD..=0xCF => {
+                return Err(QualityError::Unsupported("ベースライン以外の符号化方式"))
+            }
+            MARKER_DRI => {
+                if segment.len() < 2 {
+                    return Err(QualityError::Truncated);
+                }
+                restart_interval = u16::from_be_bytes([segment[0], segment[1]]);
+            }
+            MARKER_SOS => {
+                let frame = frame.as_ref().ok_or(QualityError::Invalid("SOFより前にSOSがあります"))?;
+                let scan = ScanDecoder::new(frame, segment, &quant, &dc_tables, &ac_tables)?;
+                return scan.decode(&data[pos..], restart_interval);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// ハフマン復号表（JPEG仕様 F.2.2.3 の maxcode/valptr 方式）
+#[derive(Debug, Clone)]
+struct HuffmanTable {
+    max_code: [i32; 17],
+    val_ptr: [i32; 17],
+    min_code: [i32; 17],
+    values: Vec<u8>,
+}
+
+impl HuffmanTable {
+    fn new(counts: &[u8; 16], values: &[u8]) -> Self {
+        let mut table = Self {
+            max_code: [-1; 17],
+            val_ptr: [0; 17],
+            min_code: [0; 17],
+            values: values.to_vec(),
+        };
+        let mut code = 0i32;
+        let mut index = 0i32;
+        for length in 1..=16 {
+            let count = counts[length - 1] as i32;
+            table.val_ptr[length] = index;
+            table.min_code[length] = code;
+            if count > 0 {
+                code += count;
+                index += count;
+                table.max_code[length] = code - 1;
+            }
+            code <<= 1;
+        }
+        table
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Result<u8, QualityError> {
+        let mut code = reader.bit()? as i32;
+        for length in 1..=16 {
+            if code <= self.max_code[length] {
+                let index = self.val_ptr[length] + code - self.min_code[length];
+                return self
+                    .values
+                    .get(index as usize)
+                    .copied()
+                    .ok_or(QualityError::Invalid("ハフマン符号"));
+            }
+            code = (code << 1) | reader.bit()? as i32;
+        }
+        Err(QualityError::Invalid("ハフマン符号"))
+    }
+}
+
+fn parse_dqt(mut segment: &[u8], quant: &mut [Option<[u16; 64]>; 4]) -> Result<(), QualityError> {
+    while !segment.is_empty() {
+        let precision = segment[0] >> 4;
+        let id = (segment[0] & 0x0F) as usize;
+        let entry_size = if precision == 0 { 1 } else { 2 };
+        let body = segment
+            .get(1..1 + 64 * entry_size)
+            .ok_or(QualityError::Truncated)?;
+        let slot = quant.get_mut(id).ok_or(QualityError::Invalid("量子化テーブル番号"))?;
+        let mut table = [0u16; 64];
+        for (k, value) in table.iter_mut().enumerate() {
+            *value = if entry_size == 1 {
+                body[k] as u16
+            } else {
+                u16::from_be_bytes([body[2 * k], body[2 * k + 1]])
+            };
+        }
+        *slot = Some(table);
+        segment = &segment[1 + 64 * entry_size..];
+    }
+    Ok(())
+}
+
+fn parse_dht(
+    mut segment: &[u8],
+    dc_tables: &mut [Option<HuffmanTable>; 4],
+    ac_tables: &mut [Option<HuffmanTable>; 4],
+) -> Result<(), QualityError> {
+    while !segment.is_empty() {
+        if segment.len() < 17 {
+            return Err(QualityError::Truncated);
+        }
+        let class = segment[0] >> 4;
+        let id = (segment[0] & 0x0F) as usize;
+        let mut counts = [0u8; 16];
+        counts.copy_from_slice(&segment[1..17]);
+        let total: usize = counts.iter().map(|&c| c as usize).sum();
+        let values = segment.get(17..17 + total).ok_or(QualityError::Truncated)?;
+
+        let tables = if class == 0 { &mut *dc_tables } else { &mut *ac_tables };
+        let slot = tables.get_mut(id).ok_or(QualityError::Invalid("ハフマンテーブル番号"))?;
+        *slot = Some(HuffmanTable::new(&counts, values));
+        segment = &segment[17 + total..];
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Component {
+    id: u8,
+    h: u8,
+    v: u8,
+    quant_id: u8,
+}
+
+#[derive(Debug)]
+struct FrameHeader {
+    width: u16,
+    height: u16,
+    components: Vec<Component>,
+}
+
+impl FrameHeader {
+    fn parse(segment: &[u8]) -> Result<Self, QualityError> {
+        if segment.len() < 6 {
+            return Err(QualityError::Truncated);
+        }
+        if segment[0] != 8 {
+            return Err(QualityError::Unsupported("8ビット以外のサンプル精度"));
+        }
+        let height = u16::from_be_bytes([segment[1], segment[2]]);
+        let width = u16::from_be_bytes([segment[3], segment[4]]);
+        let count = segment[5] as usize;
+        if count == 0 || count > MAX_COMPONENTS || width == 0 || height == 0 {
+            return Err(QualityError::Invalid("フレームヘッダー"));
+        }
+        let body = segment.get(6..6 + count * 3).ok_or(QualityError::Truncated)?;
+        let components = body
+            .chunks(3)
+            .map(|c| Component {
+                id: c[0],
+                h: (c[1] >> 4).max(1),
+                v: (c[1] & 0x0F).max(1),
+                quant_id: c[2] & 0x03,
+            })
+            .collect();
+        Ok(Self {
+            width,
+            height,
+            components,
+        })
+    }
+}
+
+/// スキャン内の1成分の復号情報
+struct ScanComponent<'a> {
+    blocks_h: usize,
+    blocks_v: usize,
+    is_luma: bool,
+    quant: &'a [u16; 64],
+    dc_table: &'a HuffmanTable,
+    ac_table: &'a HuffmanTable,
+    dc_pred: i32,
+}
+
+struct ScanDecoder<'a> {
+    components: Vec<ScanComponent<'a>>,
+    mcu_count: usize,
+}
+
+impl<'a> ScanDecoder<'a> {
+    fn new(
+        frame: &FrameHeader,
+        segment: &[u8],
+        quant: &'a [Option<[u16; 64]>; 4],
+        dc_tables: &'a [Option<HuffmanTable>; 4],
+        ac_tables: &'a [Option<HuffmanTable>; 4],
+    ) -> Result<Self, QualityError> {
+        let count = *segment.first().ok_or(QualityError::Truncated)? as usize;
+        let body = segment.get(1..1 + count * 2).ok_or(QualityError::Truncated)?;
+        let h_max = frame.components.iter().map(|c| c.h).max().unwrap_or(1) as usize;
+        let v_max = frame.components.iter().map(|c| c.v).max().unwrap_or(1) as usize;
+        let luma_id = frame.components[0].id;
+
+        let mut components = Vec::with_capacity(count);
+        for selector in body.chunks(2) {
+            let component = frame
+                .components
+                .iter()
+                .find(|c| c.id == selector[0])
+                .ok_or(QualityError::Invalid("スキャン成分"))?;
+            let missing = QualityError::Invalid("未定義のテーブル参照");
+            components.push(ScanComponent {
+                // 単一成分スキャンはブロック単位で並ぶ
+                blocks_h: if count == 1 { 1 } else { component.h as usize },
+                blocks_v: if count == 1 { 1 } else { component.v as usize },
+                is_luma: component.id == luma_id,
+                quant: quant[component.quant_id as usize].as_ref().ok_or(missing.clone())?,
+                dc_table: dc_tables[(selector[1] >> 4) as usize & 0x03]
+                    .as_ref()
+                    .ok_or(missing.clone())?,
+                ac_table: ac_tables[(selector[1] & 0x0F) as usize & 0x03]
+                    .as_ref()
+                    .ok_or(missing)?,
+                dc_pred: 0,
+            });
+        }
+
+        let mcu_count = if count == 1 {
+            let component = frame
+                .components
+                .iter()
+                .find(|c| c.id == body[0])
+                .ok_or(QualityError::Invalid("スキャン成分"))?;
+            let width = (frame.width as usize * component.h as usize).div_ceil(h_max);
+            let height = (frame.height as usize * component.v as usize).div_ceil(v_max);
+            width.div_ceil(8) * height.div_ceil(8)
+        } else {
+            (frame.width as usize).div_ceil(8 * h_max) * (frame.height as usize).div_ceil(8 * v_max)
+        };
+
+        Ok(Self {
+            components,
+            mcu_count,
+        })
+    }
+
+    fn decode(mut self, entropy_data: &[u8], restart_interval: u16) -> Result<ImageQuality, QualityError> {
+        if !self.components.iter().any(|c| c.is_luma) {
+            return Err(QualityError::Unsupported("輝度成分を含まないスキャン"));
+        }
+
+        let mut reader = BitReader::new(entropy_data);
+        let mut dc_sum: i64 = 0;
+        let mut ac_sum: u64 = 0;
+        let mut luma_blocks: u64 = 0;
+
+        for mcu in 0..self.mcu_count {
+            if restart_interval > 0 && mcu > 0 && mcu % restart_interval as usize == 0 {
+                reader.restart()?;
+                for component in &mut self.components {
+                    component.dc_pred = 0;
+                }
+            }
+
+            for component in &mut self.components {
+                for _ in 0..component.blocks_h * component.blocks_v {
+                    let (dc, ac_energy) = decode_block(&mut reader, component)?;
+                    if component.is_luma {
+                        dc_sum += dc as i64;
+                        ac_sum += ac_energy;
+                        luma_blocks += 1;
+                    }
+                }
+            }
+        }
+
+        if luma_blocks == 0 {
+            return Err(QualityError::Invalid("輝度ブロックがありません"));
+        }
+
+        // DC係数（逆量子化後）はブロック平均 × 8（レベルシフト -128）
+        let mean = dc_sum as f64 / luma_blocks as f64 / 8.0 + 128.0;
+        let sharpness = ac_sum / luma_blocks;
+        Ok(ImageQuality {
+            mean_luma: mean.round().clamp(0.0, 255.0) as u8,
+            sharpness: sharpness.min(u16::MAX as u64) as u16,
+        })
+    }
+}
+
+/// 1ブロックを復号し、逆量子化後のDC値とAC絶対値の合計を返します
+fn decode_block(reader: &mut BitReader, component: &mut ScanComponent) -> Result<(i32, u64), QualityError> {
+    let size = component.dc_table.decode(reader)?;
+    if size > 11 {
+        return Err(QualityError::Invalid("DC係数"));
+    }
+    component.dc_pred += reader.receive_extend(size)?;
+    let dc = component.dc_pred * component.quant[0] as i32;
+
+    let mut ac_energy = 0u64;
+    let mut k = 1;
+    while k < 64 {
+        let symbol = component.ac_table.decode(reader)?;
+        let run = (symbol >> 4) as usize;
+        let size = symbol & 0x0F;
+        if size == 0 {
+            if run == 15 {
+                k += 16;
+                continue;
+            }
+            break; // EOB
+        }
+        k += run;
+        if k > 63 {
+            return Err(QualityError::Invalid("AC係数"));
+        }
+        let value = reader.receive_extend(size)?;
+        if component.is_luma {
+            ac_energy += value.unsigned_abs() as u64 * component.quant[k] as u64;
+        }
+        k += 1;
+    }
+    Ok((dc, ac_energy))
+}
+
+/// エントロピー符号化データのビット読み取り（バイトスタッフィング・RSTマーカー対応）
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    buffer: u32,
+    bits: u32,
+    at_marker: bool,
+    padding_bytes: usize,
+}
+
+impl<'a> BitReader<'a> {
+    /// マーカー到達後に許容する0埋めバイト数
+    const MAX_PADDING_BYTES: usize = 4;
+
+    fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            pos: 0,
+            buffer: 0,
+            bits: 0,
+            at_marker: false,
+            padding_bytes: 0,
+        }
+    }
+
+    fn fill(&mut self) -> Result<(), QualityError> {
+        while self.bits <= 24 {
+            let byte = if self.at_marker || self.pos >= self.data.len() {
+                self.at_marker = true;
+                self.padding_bytes += 1;
+                if self.padding_bytes > Self::MAX_PADDING_BYTES {
+                    return Err(QualityError::Truncated);
+                }
+                0
+            } else if self.data[self.pos] == 0xFF {
+                match self.data.get(self.pos + 1) {
+                    Some(0x00) => {
+                        self.pos += 2;
+                        0xFF
+                    }
+                    _ => {
+                        self.at_marker = true;
+                        continue;
+                    }
+                }
+            } else {
+                self.pos += 1;
+                self.data[self.pos - 1]
+            };
+            self.buffer |= (byte as u32) << (24 - self.bits);
+            self.bits += 8;
+        }
+        Ok(())
+    }
+
+    fn bit(&mut self) -> Result<u32, QualityError> {
+        if self.bits == 0 {
+            self.fill()?;
+        }
+        let bit = self.buffer >> 31;
+        self.buffer <<= 1;
+        self.bits -= 1;
+        Ok(bit)
+    }
+
+    fn receive_extend(&mut self, size: u8) -> Result<i32, QualityError> {
+        if size == 0 {
+            return Ok(0);
+        }
+        let mut value = 0i32;
+        for _ in 0..size {
+            value = (value << 1) | self.bit()? as i32;
+        }
+        if value < 1 << (size - 1) {
+            value -= (1 << size) - 1;
+        }
+        Ok(value)
+    }
+
+    /// RSTマーカーを読み飛ばし、ビットバッファをリセットします
+    fn restart(&mut self) -> Result<(), QualityError> {
+        self.buffer = 0;
+        self.bits = 0;
+        self.at_marker = false;
+        self.padding_bytes = 0;
+        match self.data.get(self.pos..self.pos + 2) {
+            Some([0xFF, 0xD0..=0xD7]) => {
+                self.pos += 2;
+                Ok(())
+            }
+            _ => Err(QualityError::Invalid("RSTマーカーがありません")),
+        }
+    }
+}
@@ -0,0 +1,85 @@
+//! 送信済み画像の保持と再送
+//!
+//! 送信を終えた画像はコマンド待機（スリープコマンドの受信まで）の間メモリに保持し、ゲートウェイの
+//! 再送要求（`RESEND_LAST`）で撮影し直さずに送り直します。撮影した画像のバッファをそのまま保持するため
+//! コピーは発生しません（PSRAMを有効にしたビルドではPSRAM上）。Deep sleepで失われるため、保持は
+//! 次の撮影までです。保持の状態はHASHフレームの `RETAIN_BYTES`・`RETAIN_FRAME` で報告します。
+
+use std::time::Instant;
+
+/// 1回のコマンド待機で受け付ける再送の上限
+pub const MAX_RESENDS_PER_CYCLE: u8 = 2;
+
+/// 保持中の送信済み画像
+#[derive(Debug)]
+pub struct RetainedImage {
+    frame_id: u64,
+    data: Vec<u8>,
+    hash: String,
+    metadata_fields: String,
+    captured_at: Option<Instant>,
+    resends: u8,
+}
+
+impl RetainedImage {
+    /// 送信した画像を保持します
+    ///
+    /// # 引数
+    /// * `frame_id` - 画像の識別子（サイクルのトレースID）
+    /// * `metadata_fields` - 送信したHASHフレームのメタデータ（撮影からの経過時間を除く）
+    pub fn new(
+        frame_id: u64,
+        data: Vec<u8>,
+        hash: String,
+        metadata_fields: String,
+        captured_at: Option<Instant>,
+    ) -> Self {
+        Self {
+            frame_id,
+            data,
+            hash,
+            metadata_fields,
+            captured_at,
+            resends: 0,
+        }
+    }
+
+    /// 画像の識別子
+    pub fn frame_id(&self) -> u64 {
+        self.frame_id
+    }
+
+    /// 保持しているバイト数
+    pub fn bytes_held(&self) -> usize {
+        self.data.len()
+    }
+
+    /// 画像データ
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// 画像のハッシュ
+    pub fn hash(&self) -> &str {
+        &self.hash
+    }
+
+    /// 撮影した時刻
+    pub fn captured_at(&self) -> Option<Instant> {
+        self.captured_at
+    }
+
+    /// 再送を1回記録し、再送のHASHフレームに付けるメタデータを返します（上限に達していればNone）
+    pub fn begin_resend(&mut self) -> Option<String> {
+        if self.resends >= MAX_RESENDS_PER_CYCLE {
+            return None;
+        }
+        self.resends += 1;
+        Some(format!("{},RESEND:{}", self.metadata_fields, self.resends))
+    }
+}
+
+/// 保持状態のHASHフレーム用メタデータ
+pub fn retention_metadata_fields(bytes_held: usize, frame_id: u64) -> String {
+    format!(",RETAIN_BYTES:{},RETAIN_FRAME:{:016x}", bytes_held, frame_id)
+}
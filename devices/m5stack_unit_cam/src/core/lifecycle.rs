@@ -0,0 +1,216 @@
+//! 起動理由などのライフサイクルイベントの記録
+//!
+//! 再起動の原因（パニック・ウォッチドッグ・電圧低下など）を知るため、起動ごとにリセット理由を
+//! RTCメモリ上の小さなイベントログに記録します。ログにはDeep sleep以外の起動理由だけを残し、
+//! 直近の稼働時間と合わせて起動後最初のHASHフレームで報告します。
+//!
+//! ログはパニックやウォッチドッグによるリセットでも残るよう初期化されない領域に置くため、
+//! 識別子で有効性を確認し、電源投入直後の不定値は破棄します。
+//! パニックハンドラーが記録したメッセージ（`panic_report`）も同じ報告に含めます。
+
+use super::panic_report::PanicReport;
+
+/// イベントログに保持する起動理由の数
+pub const EVENT_LOG_CAPACITY: usize = 4;
+
+/// イベントログの有効性を示す識別子
+const EVENT_LOG_MAGIC: u32 = 0x4C49_4645;
+
+/// 起動（リセット）理由
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum BootReason {
+    Unknown = 0,
+    PowerOn = 1,
+    External = 2,
+    Software = 3,
+    Panic = 4,
+    InterruptWatchdog = 5,
+    TaskWatchdog = 6,
+    Watchdog = 7,
+    DeepSleep = 8,
+    Brownout = 9,
+    Sdio = 10,
+}
+
+impl BootReason {
+    /// ログの保存値から変換します（不明な値はUnknown）
+    pub fn from_code(code: u8) -> Self {
+        match code {
+            1 => Self::PowerOn,
+            2 => Self::External,
+            3 => Self::Software,
+            4 => Self::Panic,
+            5 => Self::InterruptWatchdog,
+            6 => Self::TaskWatchdog,
+            7 => Self::Watchdog,
+            8 => Self::DeepSleep,
+            9 => Self::Brownout,
+            10 => Self::Sdio,
+            _ => Self::Unknown,
+        }
+    }
+
+    /// テレメトリ用の表記
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Unknown => "UNKNOWN",
+            Self::PowerOn => "POWERON",
+            Self::External => "EXT",
+            Self::Software => "SW",
+            Self::Panic => "PANIC",
+            Self::InterruptWatchdog => "INT_WDT",
+            Self::TaskWatchdog => "TASK_WDT",
+            Self::Watchdog => "WDT",
+            Self::DeepSleep => "DEEPSLEEP",
+            Self::Brownout => "BROWNOUT",
+            Self::Sdio => "SDIO",
+        }
+    }
+
+    /// 異常による再起動かどうか（パニック・ウォッチドッグ・電圧低下）
+    pub fn is_abnormal(&self) -> bool {
+        matches!(
+            self,
+            Self::Panic | Self::InterruptWatchdog | Self::TaskWatchdog | Self::Watchdog | Self::Brownout
+        )
+    }
+}
+
+/// Deep sleep からの復帰要因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WakeCause {
+    /// スリープ以外からの起動
+    None,
+    Timer,
+    Ext0,
+    Ext1,
+    Touchpad,
+    Ulp,
+    Gpio,
+    Uart,
+    Other,
+}
+
+impl WakeCause {
+    /// テレメトリ用の表記
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::None => "NONE",
+            Self::Timer => "TIMER",
+            Self::Ext0 => "EXT0",
+            Self::Ext1 => "EXT1",
+            Self::Touchpad => "TOUCH",
+            Self::Ulp => "ULP",
+            Self::Gpio => "GPIO",
+            Self::Uart => "UART",
+            Self::Other => "OTHER",
+        }
+    }
+}
+
+/// RTCメモリに保持するイベントログ
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct EventLog {
+    magic: u32,
+    /// 起動理由（古い順、`BootReason` の保存値）
+    events: [u8; EVENT_LOG_CAPACITY],
+    len: u8,
+    /// 直近に記録した起動からの稼働時間（秒）
+    last_uptime_s: u32,
+}
+
+impl Default for EventLog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EventLog {
+    /// 空のログを作成します
+    pub const fn new() -> Self {
+        Self {
+            magic: EVENT_LOG_MAGIC,
+            events: [0; EVENT_LOG_CAPACITY],
+            len: 0,
+            last_uptime_s: 0,
+        }
+    }
+
+    /// 起動理由を記録し、起動後最初のテレメトリで報告する内容を返します
+    ///
+    /// ログが無効（電源投入直後の不定値）の場合は初期化し、前回の稼働時間は不明とします。
+    pub fn record_boot(&mut self, reason: BootReason, wake: WakeCause) -> LifecycleReport {
+        let valid = self.magic == EVENT_LOG_MAGIC && self.len as usize <= EVENT_LOG_CAPACITY;
+        if !valid {
+            *self = Self::new();
+        }
+        let previous_uptime_s = valid.then_some(self.last_uptime_s);
+        self.last_uptime_s = 0;
+
+        // 定期的なDeep sleep復帰はログを埋めてしまうため記録しない
+        if reason != BootReason::DeepSleep {
+            if self.len as usize == EVENT_LOG_CAPACITY {
+                self.events.copy_within(1.., 0);
+                self.len -= 1;
+            }
+            self.events[self.len as usize] = reason as u8;
+            self.len += 1;
+        }
+
+        LifecycleReport {
+            reason,
+            wake,
+            previous_uptime_s,
+            recent_events: self.events(),
+            panic: None,
+        }
+    }
+
+    /// 起動からの稼働時間を記録します（次回起動時に前回の稼働時間として報告）
+    pub fn record_uptime(&mut self, uptime_s: u32) {
+        self.last_uptime_s = uptime_s;
+    }
+
+    /// 記録されている起動理由（古い順）
+    pub fn events(&self) -> Vec<BootReason> {
+        let len = (self.len as usize).min(EVENT_LOG_CAPACITY);
+        self.events[..len].iter().map(|&code| BootReason::from_code(code)).collect()
+    }
+}
+
+/// 起動後最初のテレメトリで報告するライフサイクル情報
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LifecycleReport {
+    /// 今回の起動理由
+    pub reason: BootReason,
+    /// Deep sleep からの復帰要因
+    pub wake: WakeCause,
+    /// 前回起動時の稼働時間（秒、ログが無効だった場合はNone）
+    pub previous_uptime_s: Option<u32>,
+    /// Deep sleep 以外の直近の起動理由（古い順）
+    pub recent_events: Vec<BootReason>,
+    /// 前回の稼働中に記録したパニック
+    pub panic: Option<PanicReport>,
+}
+
+impl LifecycleReport {
+    /// HASHフレームに付加するメタデータ
+    ///
+    /// 形式: `,BOOT:<理由>,WAKE:<要因>[,PREV_UP_S:<秒>][,BOOT_LOG:<理由|理由|...>][,PANIC_MSG:..,PANIC_AT:..]`
+    pub fn metadata_fields(&self) -> String {
+        let mut fields = format!(",BOOT:{},WAKE:{}", self.reason.as_str(), self.wake.as_str());
+        if let Some(uptime_s) = self.previous_uptime_s {
+            fields.push_str(&format!(",PREV_UP_S:{}", uptime_s));
+        }
+        if !self.recent_events.is_empty() {
+            let events: Vec<&str> = self.recent_events.iter().map(BootReason::as_str).collect();
+            fields.push_str(&format!(",BOOT_LOG:{}", events.join("|")));
+        }
+        if let Some(panic) = &self.panic {
+            fields.push_str(&panic.metadata_fields());
+        }
+        fields
+    }
+}
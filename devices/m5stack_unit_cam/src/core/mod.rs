@@ -1,22 +1,66 @@
 /// コアシステムモジュール
 pub mod app_controller;
+pub mod build_info;
 pub mod capture_policy;
+pub mod command_counter_store;
 pub mod config;
+pub mod config_staging;
+pub mod config_store;
 pub mod config_validation;
+pub mod debug_flag_store;
+pub mod debug_flags;
+pub mod device_identity;
+pub mod device_identity_store;
 pub mod data_service;
 pub mod data_prep;
 pub mod domain_logic;
+pub mod file_store;
+pub mod image_hash;
+pub mod image_pipeline;
+pub mod image_retention;
+pub mod lifecycle;
+pub mod nvs_health;
+pub mod nvs_recovery;
+pub mod panic_report;
 pub mod rtc_manager;
+pub mod soak;
+pub mod thumbnail_ring;
+pub mod thumbnail_store;
+pub mod timelapse;
+pub mod trace;
 
-pub use app_controller::AppController;
+pub use app_controller::{
+    clamped_sleep_request, clear_clamped_sleep_request, clear_window_saved, window_saved_metadata, AppController,
+};
+pub use build_info::BuildInfo;
 pub use capture_policy::{
     should_capture_image,
     should_capture_image_with_overrides,
+    voltage_skip_reason,
+    CaptureSkipReason,
     INVALID_VOLTAGE_PERCENT,
     LOW_VOLTAGE_THRESHOLD_PERCENT,
 };
+pub use command_counter_store::CommandCounterStore;
 pub use config::{AppConfig, ConfigError};
+pub use config_store::{ActiveRemoteConfig, RemoteConfigStore};
+pub use debug_flag_store::DebugFlagStore;
+pub use debug_flags::{DebugFlags, RuntimeDebug};
+pub use device_identity_store::load_device_id;
 pub use data_service::{DataService, MeasuredData};
 pub use data_prep::{prepare_image_payload, simple_image_hash, DUMMY_HASH};
-pub use domain_logic::{clamp_wifi_tx_power_dbm, resolve_sleep_duration_seconds, voltage_to_percentage};
-pub use rtc_manager::RtcManager;
+pub use domain_logic::{
+    clamp_sleep_duration_seconds, clamp_wifi_tx_power_dbm, resolve_sleep_duration_seconds, voltage_to_percentage,
+};
+pub use file_store::FileStore;
+pub use image_hash::ImageHashAlgo;
+pub use image_pipeline::{assess_image, QualityAssessment, QualityThresholds};
+pub use image_retention::{retention_metadata_fields, RetainedImage, MAX_RESENDS_PER_CYCLE};
+pub use lifecycle::LifecycleReport;
+pub use nvs_health::{NvsRecovery, NvsUsage};
+pub use nvs_recovery::{nvs_usage, take_nvs_partition};
+pub use rtc_manager::{CaptureAlignment, RtcManager};
+pub use soak::{SoakLog, SoakOutcome, SOAK_CYCLE_PAUSE_MS};
+pub use thumbnail_store::{open_thumbnail_ring, store_thumbnail, DeviceThumbnailRing};
+pub use timelapse::{SequenceFrame, TimelapseSettings};
+pub use trace::TraceContext;
@@ -0,0 +1,64 @@
+//! NVSの健全性チェックと自己修復の判定
+//!
+//! NVSパーティションの初期化に失敗した場合はパーティション全体を消去して初期化し直し、
+//! リモート設定の名前空間が読めない・空きエントリが足りない場合はその名前空間だけを消去して
+//! cfg.toml の設定に戻します。修復した内容はHASHフレームの `NVS_RECOVERED:<内容>` で報告し、
+//! 再書き込みせずに自己修復した個体を把握できるようにします。
+
+/// NVS関連のエラーコードの範囲（`ESP_ERR_NVS_BASE` から）
+const NVS_ERROR_BASE: i32 = 0x1100;
+const NVS_ERROR_END: i32 = 0x1200;
+
+/// 設定を書き込むのに必要な空きエントリ数（128バイトの文字列2件と状態1件）
+pub const MIN_FREE_ENTRIES: usize = 12;
+
+/// NVS関連のエラーコードか（破損・容量不足・バージョン不一致など）
+pub fn is_nvs_error(code: i32) -> bool {
+    (NVS_ERROR_BASE..NVS_ERROR_END).contains(&code)
+}
+
+/// 自己修復の内容
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NvsRecovery {
+    /// パーティションを初期化できず、全体を消去した（エラーコード）
+    PartitionErased(i32),
+    /// リモート設定の名前空間が破損していたため消去した
+    ConfigCorrupted,
+    /// 空きエントリが足りないためリモート設定の名前空間を消去した
+    ConfigFull,
+}
+
+impl NvsRecovery {
+    /// テレメトリでの表記
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NvsRecovery::PartitionErased(_) => "PARTITION",
+            NvsRecovery::ConfigCorrupted => "CONFIG_CORRUPT",
+            NvsRecovery::ConfigFull => "CONFIG_FULL",
+        }
+    }
+}
+
+/// NVSパーティションの使用状況（エントリ数）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NvsUsage {
+    pub used_entries: usize,
+    pub free_entries: usize,
+    pub total_entries: usize,
+}
+
+impl NvsUsage {
+    /// 設定を書き込めないほど空きが少ないか
+    pub fn is_full(&self) -> bool {
+        self.free_entries < MIN_FREE_ENTRIES
+    }
+}
+
+/// HASHフレームに付加するメタデータ（`,NVS_RECOVERED:<内容>[+...]`、修復していなければ空）
+pub fn metadata_field(recoveries: &[NvsRecovery]) -> String {
+    if recoveries.is_empty() {
+        return String::new();
+    }
+    let names: Vec<&str> = recoveries.iter().map(NvsRecovery::as_str).collect();
+    format!(",NVS_RECOVERED:{}", names.join("+"))
+}
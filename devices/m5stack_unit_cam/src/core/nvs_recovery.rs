@@ -0,0 +1,40 @@
+use esp_idf_svc::nvs::EspDefaultNvsPartition;
+use esp_idf_svc::sys::EspError;
+use log::{error, info, warn};
+
+use super::nvs_health::{is_nvs_error, NvsRecovery, NvsUsage};
+use crate::sys_wrappers::esp as sys;
+
+/// NVSパーティションを取得します
+///
+/// 初期化がNVSのエラー（ページの破損・空きページなし・フォーマットの不一致）で失敗した場合は、
+/// パーティション全体を消去して取得し直します。
+pub fn take_nvs_partition() -> Result<(EspDefaultNvsPartition, Option<NvsRecovery>), EspError> {
+    match EspDefaultNvsPartition::take() {
+        Ok(partition) => Ok((partition, None)),
+        Err(e) if is_nvs_error(e.code()) => {
+            error!("NVSパーティションを初期化できません（消去して初期化し直します）: {:?}", e);
+            sys::nvs_flash_erase()?;
+            let partition = EspDefaultNvsPartition::take()?;
+            info!("NVSパーティションを消去して初期化し直しました");
+            Ok((partition, Some(NvsRecovery::PartitionErased(e.code()))))
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// NVSパーティションの使用状況を取得します
+pub fn nvs_usage() -> Option<NvsUsage> {
+    let stats = match sys::nvs_stats() {
+        Ok(stats) => stats,
+        Err(e) => {
+            warn!("NVSの使用状況を取得できません: {:?}", e);
+            return None;
+        }
+    };
+    Some(NvsUsage {
+        used_entries: stats.used_entries as usize,
+        free_entries: stats.free_entries as usize,
+        total_entries: stats.total_entries as usize,
+    })
+}
@@ -0,0 +1,116 @@
+//! パニックの記録
+//!
+//! パニックハンドラーでメッセージと発生箇所をRTCメモリに書き込み、再起動後最初の
+//! ライフサイクル情報で報告します。現地でのパニックはシリアルログが残らないため、
+//! 原因を追える情報をテレメトリで受け取れるようにします。
+//!
+//! 記録はリセットで消えない領域に置くため、識別子で有効性を確認します。
+
+/// 保持するメッセージの最大バイト数
+pub const PANIC_MESSAGE_CAPACITY: usize = 96;
+
+/// 保持する発生箇所（`ファイル:行`）の最大バイト数
+pub const PANIC_LOCATION_CAPACITY: usize = 48;
+
+/// 記録の有効性を示す識別子
+const PANIC_RECORD_MAGIC: u32 = 0x5041_4E43;
+
+/// RTCメモリに保持するパニックの記録
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct PanicRecord {
+    magic: u32,
+    message: [u8; PANIC_MESSAGE_CAPACITY],
+    message_len: u8,
+    location: [u8; PANIC_LOCATION_CAPACITY],
+    location_len: u8,
+}
+
+impl Default for PanicRecord {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PanicRecord {
+    /// 空の記録を作成します
+    pub const fn new() -> Self {
+        Self {
+            magic: 0,
+            message: [0; PANIC_MESSAGE_CAPACITY],
+            message_len: 0,
+            location: [0; PANIC_LOCATION_CAPACITY],
+            location_len: 0,
+        }
+    }
+
+    /// パニックを記録します（パニックハンドラーから呼び出すため割り当てを行わない）
+    ///
+    /// HASHフレームの区切り文字（`,`）と制御文字は置き換え、容量を超える分は切り詰めます。
+    /// 発生箇所はファイル名が残るよう、長すぎる場合は先頭側を切り詰めます。
+    pub fn store(&mut self, message: &str, location: &str) {
+        self.message_len = copy_sanitized(&mut self.message, message);
+        self.location_len = copy_sanitized(&mut self.location, tail_within(location, PANIC_LOCATION_CAPACITY));
+        self.magic = PANIC_RECORD_MAGIC;
+    }
+
+    /// 記録を取り出して消去します（記録がない、または無効ならNone）
+    pub fn take(&mut self) -> Option<PanicReport> {
+        let valid = self.magic == PANIC_RECORD_MAGIC
+            && self.message_len as usize <= PANIC_MESSAGE_CAPACITY
+            && self.location_len as usize <= PANIC_LOCATION_CAPACITY;
+        let report = valid.then(|| PanicReport {
+            message: String::from_utf8_lossy(&self.message[..self.message_len as usize]).into_owned(),
+            location: String::from_utf8_lossy(&self.location[..self.location_len as usize]).into_owned(),
+        });
+        *self = Self::new();
+        report
+    }
+}
+
+/// 末尾から `max_len` バイト以内に収まる部分（文字の境界で区切る）
+fn tail_within(value: &str, max_len: usize) -> &str {
+    let mut start = value.len().saturating_sub(max_len);
+    while !value.is_char_boundary(start) {
+        start += 1;
+    }
+    &value[start..]
+}
+
+/// 文字単位で容量内に収まるだけコピーし、書き込んだバイト数を返します
+fn copy_sanitized(buffer: &mut [u8], value: &str) -> u8 {
+    let mut len = 0;
+    for c in value.chars() {
+        let c = match c {
+            ',' => ';',
+            c if c.is_control() => ' ',
+            c => c,
+        };
+        let mut encoded = [0; 4];
+        let bytes = c.encode_utf8(&mut encoded).as_bytes();
+        if len + bytes.len() > buffer.len() {
+            break;
+        }
+        buffer[len..len + bytes.len()].copy_from_slice(bytes);
+        len += bytes.len();
+    }
+    len as u8
+}
+
+/// 前回の稼働中に発生したパニック
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PanicReport {
+    /// パニックメッセージ
+    pub message: String,
+    /// 発生箇所（`ファイル:行`）
+    pub location: String,
+}
+
+impl PanicReport {
+    /// HASHフレームに付加するメタデータ
+    ///
+    /// 形式: `,PANIC_MSG:<メッセージ>,PANIC_AT:<ファイル:行>`
+    pub fn metadata_fields(&self) -> String {
+        format!(",PANIC_MSG:{},PANIC_AT:{}", self.message, self.location)
+    }
+}
@@ -1,7 +1,45 @@
 use chrono_tz::Tz;
-use log::info;
+use esp_idf_svc::hal::delay::FreeRtos;
+use log::{info, warn};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
 
-use crate::power::sleep::{DeepSleep, DeepSleepPlatform};
+use crate::core::lifecycle::{BootReason, EventLog, LifecycleReport, WakeCause};
+use crate::core::panic_report::PanicRecord;
+use crate::core::timelapse::{SequenceFrame, SequenceState, TimelapseSettings};
+use crate::hardware::camera::{CameraHealth, CaptureOutcome};
+use crate::power::sleep::alignment::{
+    alignment_error_us, is_clock_valid, remaining_wait_us, MAX_ALIGN_WAIT_US,
+};
+use crate::sys_wrappers::esp as sys;
+use crate::power::sleep::{
+    plan_aligned_sleep, AlignmentSettings, DeepSleep, DeepSleepError, DeepSleepPlatform,
+};
+
+/// 次回撮影の境界時刻（UNIX時刻、マイクロ秒、0は未設定）
+/// Deep sleep を跨いで参照するため、RTCメモリに保持します。
+#[link_section = ".rtc.data"]
+static CAPTURE_TARGET_UNIX_US: AtomicU64 = AtomicU64::new(0);
+
+/// 起動理由のイベントログ
+/// パニックやウォッチドッグによるリセットでも残るよう、起動時に初期化されないRTCメモリに保持します。
+#[link_section = ".rtc_noinit"]
+static mut EVENT_LOG: EventLog = EventLog::new();
+
+/// パニックハンドラーが書き込むパニックの記録（パニックによるリセットで消えない領域に保持）
+#[link_section = ".rtc_noinit"]
+static mut PANIC_RECORD: PanicRecord = PanicRecord::new();
+
+/// タイムラプスの通し番号（Deep sleep を跨いで保持し、電源断では新しい実行として数え直す）
+#[link_section = ".rtc.data"]
+static mut SEQUENCE_STATE: SequenceState = SequenceState::new();
+
+/// カメラの健全性（Deep sleep を跨いで保持し、電源断では記録し直す）
+#[link_section = ".rtc.data"]
+static mut CAMERA_HEALTH: CameraHealth = CameraHealth::new();
+
+/// 境界時刻の直前はFreeRTOSの遅延をやめてビジーウェイトする時間（マイクロ秒）
+const BUSY_WAIT_WINDOW_US: u64 = 20_000;
 
 /// RTC時刻管理モジュール
 pub struct RtcManager;
@@ -16,4 +54,183 @@ impl RtcManager {
         info!("RTCタイム管理を初期化しました");
         Ok(())
     }
+
+    /// 現在のUNIX時刻（マイクロ秒）。RTCが未設定の場合は起動からの経過時間相当
+    pub fn now_unix_us() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_micros() as u64)
+            .unwrap_or(0)
+    }
+
+    /// 今回の起動理由をイベントログに記録し、報告内容を返します（起動時に1回だけ呼び出す）
+    pub fn record_boot() -> LifecycleReport {
+        let reason = Self::boot_reason();
+        let wake = Self::wake_cause();
+        // メインタスクからのみアクセスする
+        let mut report = unsafe { (*std::ptr::addr_of_mut!(EVENT_LOG)).record_boot(reason, wake) };
+        report.panic = unsafe { (*std::ptr::addr_of_mut!(PANIC_RECORD)).take() };
+        if let Some(panic) = &report.panic {
+            warn!("前回の稼働中にパニックが発生しました: {} ({})", panic.message, panic.location);
+        }
+        if reason.is_abnormal() {
+            warn!(
+                "異常リセットから起動しました: {} (前回の稼働時間: {:?}秒)",
+                reason.as_str(),
+                report.previous_uptime_s
+            );
+        }
+        report
+    }
+
+    /// パニックの内容を記録します（パニックハンドラーから呼び出す）
+    pub fn record_panic(message: &str, location: &str) {
+        // パニック後は他のタスクがこの記録に触れる前にリセットされる
+        unsafe { (*std::ptr::addr_of_mut!(PANIC_RECORD)).store(message, location) };
+        Self::record_uptime();
+    }
+
+    /// 起動からの稼働時間をイベントログに記録します
+    ///
+    /// 異常リセット時も直前の値が残るよう、サイクルの区切りとスリープ直前に呼び出します。
+    pub fn record_uptime() {
+        let uptime_s = (sys::uptime_us() / 1_000_000).max(0) as u32;
+        unsafe { (*std::ptr::addr_of_mut!(EVENT_LOG)).record_uptime(uptime_s) };
+    }
+
+    /// 撮影した画像にタイムラプスの通し番号を割り当てます
+    pub fn next_sequence_frame(settings: &TimelapseSettings) -> SequenceFrame {
+        let new_run_id = sys::random_u32();
+        // メインタスクからのみアクセスする
+        unsafe { (*std::ptr::addr_of_mut!(SEQUENCE_STATE)).next_frame(settings, new_run_id) }
+    }
+
+    /// サイクルの撮影結果をカメラの健全性に記録し、記録後の値を返します
+    pub fn record_camera_outcome(outcome: CaptureOutcome) -> CameraHealth {
+        // メインタスクからのみアクセスする
+        unsafe {
+            let health = &mut *std::ptr::addr_of_mut!(CAMERA_HEALTH);
+            health.record(outcome);
+            *health
+        }
+    }
+
+    fn boot_reason() -> BootReason {
+        use esp_idf_svc::sys::*;
+        #[allow(non_upper_case_globals)]
+        match sys::reset_reason() {
+            esp_reset_reason_t_ESP_RST_POWERON => BootReason::PowerOn,
+            esp_reset_reason_t_ESP_RST_EXT => BootReason::External,
+            esp_reset_reason_t_ESP_RST_SW => BootReason::Software,
+            esp_reset_reason_t_ESP_RST_PANIC => BootReason::Panic,
+            esp_reset_reason_t_ESP_RST_INT_WDT => BootReason::InterruptWatchdog,
+            esp_reset_reason_t_ESP_RST_TASK_WDT => BootReason::TaskWatchdog,
+            esp_reset_reason_t_ESP_RST_WDT => BootReason::Watchdog,
+            esp_reset_reason_t_ESP_RST_DEEPSLEEP => BootReason::DeepSleep,
+            esp_reset_reason_t_ESP_RST_BROWNOUT => BootReason::Brownout,
+            esp_reset_reason_t_ESP_RST_SDIO => BootReason::Sdio,
+            _ => BootReason::Unknown,
+        }
+    }
+
+    fn wake_cause() -> WakeCause {
+        use esp_idf_svc::sys::*;
+        #[allow(non_upper_case_globals)]
+        match sys::wakeup_cause() {
+            esp_sleep_source_t_ESP_SLEEP_WAKEUP_UNDEFINED => WakeCause::None,
+            esp_sleep_source_t_ESP_SLEEP_WAKEUP_TIMER => WakeCause::Timer,
+            esp_sleep_source_t_ESP_SLEEP_WAKEUP_EXT0 => WakeCause::Ext0,
+            esp_sleep_source_t_ESP_SLEEP_WAKEUP_EXT1 => WakeCause::Ext1,
+            esp_sleep_source_t_ESP_SLEEP_WAKEUP_TOUCHPAD => WakeCause::Touchpad,
+            esp_sleep_source_t_ESP_SLEEP_WAKEUP_ULP => WakeCause::Ulp,
+            esp_sleep_source_t_ESP_SLEEP_WAKEUP_GPIO => WakeCause::Gpio,
+            esp_sleep_source_t_ESP_SLEEP_WAKEUP_UART => WakeCause::Uart,
+            _ => WakeCause::Other,
+        }
+    }
+
+    /// 次回撮影を壁時計の境界に揃えて Deep sleep します
+    ///
+    /// 整列が無効、または時刻が未同期の場合は通常のスリープを行います。
+    pub fn sleep_until_next_capture<P: DeepSleepPlatform>(
+        deep_sleep_controller: &DeepSleep<P>,
+        duration_seconds: u64,
+        alignment: Option<AlignmentSettings>,
+    ) -> Result<(), DeepSleepError> {
+        Self::record_uptime();
+        let plan = alignment.and_then(|settings| {
+            let requested_us = duration_seconds.saturating_mul(1_000_000);
+            let plan = plan_aligned_sleep(Self::now_unix_us(), requested_us, settings);
+            if plan.is_none() {
+                warn!("時刻が未同期のため撮影時刻の整列を行いません");
+            }
+            plan
+        });
+
+        match plan {
+            Some(plan) => {
+                info!(
+                    "撮影時刻を境界 {}us に整列: スリープ {}us（要求 {}秒）",
+                    plan.target_unix_us, plan.sleep_us, duration_seconds
+                );
+                CAPTURE_TARGET_UNIX_US.store(plan.target_unix_us, Ordering::Relaxed);
+                deep_sleep_controller.sleep_for_micros(plan.sleep_us);
+                Ok(())
+            }
+            None => {
+                CAPTURE_TARGET_UNIX_US.store(0, Ordering::Relaxed);
+                deep_sleep_controller.sleep_for_duration(duration_seconds)
+            }
+        }
+    }
+}
+
+/// 前回のスリープで計画した撮影境界への待機
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CaptureAlignment {
+    target_unix_us: u64,
+    error_us: Option<i64>,
+}
+
+impl CaptureAlignment {
+    /// RTCメモリに保存された撮影境界を取り出します（1回の起床につき1度だけ有効）
+    pub fn from_previous_sleep() -> Option<Self> {
+        let target_unix_us = CAPTURE_TARGET_UNIX_US.swap(0, Ordering::Relaxed);
+        if target_unix_us == 0 || !is_clock_valid(RtcManager::now_unix_us()) {
+            return None;
+        }
+        Some(Self {
+            target_unix_us,
+            error_us: None,
+        })
+    }
+
+    /// 境界時刻まで待機し、整列誤差を記録します
+    ///
+    /// 最初の呼び出しのみ待機します（撮影リトライ時は再計測しない）。
+    pub fn wait_for_boundary(&mut self) {
+        if self.error_us.is_some() {
+            return;
+        }
+        let remaining = remaining_wait_us(RtcManager::now_unix_us(), self.target_unix_us);
+        if remaining > MAX_ALIGN_WAIT_US {
+            // 時刻の飛びなどで待機が長すぎる場合は待たずに撮影する
+            warn!("撮影境界まで {}us あるため待機せずに撮影します", remaining);
+        } else if remaining > 0 {
+            if remaining > BUSY_WAIT_WINDOW_US {
+                FreeRtos::delay_ms(((remaining - BUSY_WAIT_WINDOW_US) / 1_000) as u32);
+            }
+            while RtcManager::now_unix_us() < self.target_unix_us {
+                std::hint::spin_loop();
+            }
+        }
+        let error_us = alignment_error_us(self.target_unix_us, RtcManager::now_unix_us());
+        info!("撮影時刻の整列誤差: {}us", error_us);
+        self.error_us = Some(error_us);
+    }
+
+    /// 記録した整列誤差（マイクロ秒）
+    pub fn error_us(&self) -> Option<i64> {
+        self.error_us
+    }
 }
@@ -0,0 +1,110 @@
+//! ソークテスト（長時間の信頼性試験）のサイクル記録
+//!
+//! `soak-test` フィーチャーを有効にしたビルドはディープスリープせず、撮影→送信→短い待機を
+//! 繰り返します。サイクルごとの結果と空きヒープを累計し、次のHASHフレームに
+//! `SOAK:<サイクル数>,SOAK_OK:<成功数>,SOAK_ERR:<種類>=<回数>[+...],HEAP_FREE:<バイト>` として
+//! 付加します（累計のため、途中の転送が失敗しても次の転送で取り戻せる）。
+//! ゲートウェイはデバイスごとに集計し、`SOAK_REPORT` コマンドで返します。
+
+/// サイクル間の待機時間（ミリ秒）
+pub const SOAK_CYCLE_PAUSE_MS: u32 = 5_000;
+
+/// 1サイクルの結果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SoakOutcome {
+    /// 撮影した画像を送信できた
+    Success,
+    /// 撮影に失敗した（画像なしで送信した場合も含む）
+    CaptureFailed,
+    /// ゲートウェイから応答がなく転送を見送った
+    ProbeSkipped,
+    /// 送信に失敗した
+    SendFailed,
+}
+
+impl SoakOutcome {
+    /// 失敗の種類の一覧（`SOAK_ERR` の並び順）
+    const ERRORS: [SoakOutcome; 3] = [SoakOutcome::CaptureFailed, SoakOutcome::ProbeSkipped, SoakOutcome::SendFailed];
+
+    /// テレメトリでの表記
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SoakOutcome::Success => "OK",
+            SoakOutcome::CaptureFailed => "CAPTURE",
+            SoakOutcome::ProbeSkipped => "PROBE",
+            SoakOutcome::SendFailed => "SEND",
+        }
+    }
+
+    /// サイクルの結果を決めます（撮影の失敗を最優先し、次に転送の見送り、送信の失敗）
+    pub fn classify(captured: bool, gateway_reachable: bool, transmitted: bool) -> Self {
+        if !captured {
+            SoakOutcome::CaptureFailed
+        } else if !gateway_reachable {
+            SoakOutcome::ProbeSkipped
+        } else if !transmitted {
+            SoakOutcome::SendFailed
+        } else {
+            SoakOutcome::Success
+        }
+    }
+}
+
+/// サイクル結果の累計
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SoakLog {
+    cycles: u32,
+    successes: u32,
+    /// `SoakOutcome::ERRORS` の順の失敗回数
+    errors: [u32; 3],
+    /// 直近のサイクル終了時の空きヒープ（バイト）
+    free_heap: Option<u32>,
+}
+
+impl SoakLog {
+    /// 空の記録を作成します
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// サイクルの結果と終了時の空きヒープを記録します
+    pub fn record(&mut self, outcome: SoakOutcome, free_heap: u32) {
+        self.cycles = self.cycles.saturating_add(1);
+        match SoakOutcome::ERRORS.iter().position(|error| *error == outcome) {
+            Some(index) => self.errors[index] = self.errors[index].saturating_add(1),
+            None => self.successes = self.successes.saturating_add(1),
+        }
+        self.free_heap = Some(free_heap);
+    }
+
+    /// 記録したサイクル数
+    pub fn cycles(&self) -> u32 {
+        self.cycles
+    }
+
+    /// ログ用の概要（`12 cycles, 11 ok, CAPTURE=0+PROBE=1+SEND=0`）
+    pub fn summary(&self) -> String {
+        format!("{} cycles, {} ok, {}", self.cycles, self.successes, self.error_field())
+    }
+
+    fn error_field(&self) -> String {
+        SoakOutcome::ERRORS
+            .iter()
+            .zip(self.errors)
+            .map(|(outcome, count)| format!("{}={}", outcome.as_str(), count))
+            .collect::<Vec<_>>()
+            .join("+")
+    }
+
+    /// HASHフレームに付加するメタデータ（サイクルを記録していなければ空）
+    pub fn metadata_fields(&self) -> String {
+        if self.cycles == 0 {
+            return String::new();
+        }
+        let mut fields = format!(",SOAK:{},SOAK_OK:{},SOAK_ERR:{}", self.cycles, self.successes, self.error_field());
+        if let Some(free_heap) = self.free_heap {
+            fields.push_str(&format!(",HEAP_FREE:{}", free_heap));
+        }
+        fields
+    }
+}
@@ -0,0 +1,237 @@
+//! 直近の撮影のサムネイルのリング
+//!
+//! カメラの向きやレンズの汚れを画像全体を取り寄せずに確かめられるよう、撮影した画像を1/8に縮小した
+//! サムネイルを撮影時刻と一緒に直近N枚保持し、ゲートウェイのサムネイル要求（`GET_THUMBS`）を
+//! コマンド待機中に受けたら古い順に送ります（HASHフレームに `THUMB:<番号>/<枚数>` を付加）。
+//! Deep sleepを跨いで保持するため、パーティションテーブルに `thumbs` パーティションがあれば
+//! フラッシュのスロットに書き込みます。なければメモリ（PSRAMを有効にしたビルドではPSRAM上）に
+//! 枚数を減らして保持します（起床中の撮影のみのため、ソークテストなどスリープしないビルド向け）。
+//!
+//! スロットの形式:
+//! ```text
+//! ["THMB"(4)] [SEQ(4, LE)] [CAPTURED_UNIX(4, LE)] [LEN(2, LE)] [CHECK(4, LE)] [JPEG(LEN)]
+//! CHECK = XXH64(JPEG, seed=0) の下位32ビット
+//! ```
+
+use xxhash_rust::xxh64::xxh64;
+
+use crate::sys_wrappers::{SysError, SysResult};
+
+/// スロットの識別子
+const THUMBNAIL_MAGIC: [u8; 4] = *b"THMB";
+/// スロットのヘッダのバイト長
+pub const THUMBNAIL_HEADER_LEN: usize = 18;
+/// 1スロットのバイト長（フラッシュの消去単位4KBの2倍）
+pub const THUMBNAIL_SLOT_LEN: usize = 8192;
+/// 1枚のサムネイル（JPEG）の上限
+pub const MAX_THUMBNAIL_LEN: usize = THUMBNAIL_SLOT_LEN - THUMBNAIL_HEADER_LEN;
+/// 設定で保持できる枚数の上限（`thumbs` パーティション256KBのスロット数）
+pub const MAX_THUMBNAIL_RING_LEN: u8 = 32;
+/// フラッシュのパーティションがない場合にメモリに保持する枚数の上限
+pub const MEMORY_RING_LEN: usize = 2;
+/// 縮小率（幅と高さをこの値で割る）
+pub const THUMBNAIL_SCALE: u16 = 8;
+/// サムネイルのJPEG品質（1-100、大きいほど高画質）
+pub const THUMBNAIL_JPEG_QUALITY: u8 = 30;
+
+/// サムネイルを保持しなかった理由
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum ThumbnailError {
+    #[error("サムネイルが大きすぎます: {0}バイト")]
+    TooLarge(usize),
+    #[error("JPEGの大きさを読み取れません")]
+    UnknownDimensions,
+    #[error("サムネイルの作成に失敗しました: {0}")]
+    Encode(SysError),
+    #[error("サムネイルの保存先にスロットがありません")]
+    NoSlots,
+    #[error("サムネイルの保存に失敗しました: {0}")]
+    Storage(SysError),
+}
+
+/// スロットの内容の確認値
+fn check_value(jpeg: &[u8]) -> u32 {
+    xxh64(jpeg, 0) as u32
+}
+
+/// 保持しているサムネイル
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Thumbnail {
+    /// 保持した順の通し番号
+    pub seq: u32,
+    /// 撮影時刻（UNIX秒、時刻が未同期なら0）
+    pub captured_unix: u32,
+    /// 縮小したJPEG
+    pub jpeg: Vec<u8>,
+}
+
+impl Thumbnail {
+    /// スロットに書き込む形式
+    pub fn encode_slot(&self) -> Vec<u8> {
+        let mut slot = Vec::with_capacity(THUMBNAIL_HEADER_LEN + self.jpeg.len());
+        slot.extend_from_slice(&THUMBNAIL_MAGIC);
+        slot.extend_from_slice(&self.seq.to_le_bytes());
+        slot.extend_from_slice(&self.captured_unix.to_le_bytes());
+        slot.extend_from_slice(&(self.jpeg.len() as u16).to_le_bytes());
+        slot.extend_from_slice(&check_value(&self.jpeg).to_le_bytes());
+        slot.extend_from_slice(&self.jpeg);
+        slot
+    }
+
+    /// スロットから読み込みます（未使用・書き込み途中・壊れたスロットはNone）
+    pub fn decode_slot(slot: &[u8]) -> Option<Self> {
+        if slot.len() < THUMBNAIL_HEADER_LEN || slot[..4] != THUMBNAIL_MAGIC {
+            return None;
+        }
+        let seq = u32::from_le_bytes(slot[4..8].try_into().ok()?);
+        let captured_unix = u32::from_le_bytes(slot[8..12].try_into().ok()?);
+        let len = u16::from_le_bytes(slot[12..14].try_into().ok()?) as usize;
+        let check = u32::from_le_bytes(slot[14..18].try_into().ok()?);
+        let jpeg = slot.get(THUMBNAIL_HEADER_LEN..THUMBNAIL_HEADER_LEN + len)?;
+        (len <= MAX_THUMBNAIL_LEN && check_value(jpeg) == check).then(|| Self {
+            seq,
+            captured_unix,
+            jpeg: jpeg.to_vec(),
+        })
+    }
+
+    /// 送信するHASHフレームのメタデータ（`index` は1から数える）
+    pub fn metadata_fields(&self, index: usize, count: usize) -> String {
+        format!(",THUMB:{}/{},THUMB_SEQ:{},THUMB_AT:{}", index, count, self.seq, self.captured_unix)
+    }
+}
+
+/// サムネイルのスロットの保存先
+pub trait ThumbnailStorage {
+    /// スロット数
+    fn slot_count(&self) -> usize;
+
+    /// スロットの内容を読み込みます（読み込めなければNone）
+    fn read_slot(&self, slot: usize) -> Option<Vec<u8>>;
+
+    /// スロットを書き換えます
+    fn write_slot(&mut self, slot: usize, data: &[u8]) -> SysResult<()>;
+}
+
+impl<S: ThumbnailStorage + ?Sized> ThumbnailStorage for Box<S> {
+    fn slot_count(&self) -> usize {
+        (**self).slot_count()
+    }
+
+    fn read_slot(&self, slot: usize) -> Option<Vec<u8>> {
+        (**self).read_slot(slot)
+    }
+
+    fn write_slot(&mut self, slot: usize, data: &[u8]) -> SysResult<()> {
+        (**self).write_slot(slot, data)
+    }
+}
+
+/// メモリ上のスロット（Deep sleepで失われる）
+#[derive(Debug, Default)]
+pub struct MemoryThumbnailStorage {
+    slots: Vec<Vec<u8>>,
+}
+
+impl MemoryThumbnailStorage {
+    /// 指定枚数のスロットを作成します
+    pub fn new(slot_count: usize) -> Self {
+        Self { slots: vec![Vec::new(); slot_count] }
+    }
+}
+
+impl ThumbnailStorage for MemoryThumbnailStorage {
+    fn slot_count(&self) -> usize {
+        self.slots.len()
+    }
+
+    fn read_slot(&self, slot: usize) -> Option<Vec<u8>> {
+        self.slots.get(slot).cloned()
+    }
+
+    fn write_slot(&mut self, slot: usize, data: &[u8]) -> SysResult<()> {
+        self.slots[slot] = data.to_vec();
+        Ok(())
+    }
+}
+
+/// 直近のサムネイルのリング（最も古いスロットから上書きする）
+pub struct ThumbnailRing<S> {
+    storage: S,
+    next_seq: u32,
+}
+
+impl<S: ThumbnailStorage> ThumbnailRing<S> {
+    /// 保存先のスロットを読み込み、続きの通し番号から保持します
+    pub fn open(storage: S) -> Self {
+        let next_seq = (0..storage.slot_count())
+            .filter_map(|slot| storage.read_slot(slot).as_deref().and_then(Thumbnail::decode_slot))
+            .map(|thumbnail| thumbnail.seq.wrapping_add(1))
+            .max()
+            .unwrap_or(0);
+        Self { storage, next_seq }
+    }
+
+    /// 保持できる枚数
+    pub fn capacity(&self) -> usize {
+        self.storage.slot_count()
+    }
+
+    /// サムネイルを保持し、通し番号を返します
+    pub fn push(&mut self, captured_unix: u32, jpeg: Vec<u8>) -> Result<u32, ThumbnailError> {
+        if jpeg.len() > MAX_THUMBNAIL_LEN {
+            return Err(ThumbnailError::TooLarge(jpeg.len()));
+        }
+        let capacity = self.capacity();
+        if capacity == 0 {
+            return Err(ThumbnailError::NoSlots);
+        }
+        let thumbnail = Thumbnail { seq: self.next_seq, captured_unix, jpeg };
+        let slot = thumbnail.seq as usize % capacity;
+        self.storage
+            .write_slot(slot, &thumbnail.encode_slot())
+            .map_err(ThumbnailError::Storage)?;
+        self.next_seq = self.next_seq.wrapping_add(1);
+        Ok(thumbnail.seq)
+    }
+
+    /// 保持しているサムネイル（古い順）
+    pub fn thumbnails(&self) -> Vec<Thumbnail> {
+        let mut thumbnails: Vec<Thumbnail> = (0..self.capacity())
+            .filter_map(|slot| self.storage.read_slot(slot).as_deref().and_then(Thumbnail::decode_slot))
+            .collect();
+        thumbnails.sort_by_key(|thumbnail| thumbnail.seq);
+        thumbnails
+    }
+}
+
+/// JPEGの幅と高さ（SOFマーカーから読み取る）
+pub fn jpeg_dimensions(data: &[u8]) -> Option<(u16, u16)> {
+    if data.get(..2)? != [0xFF, 0xD8] {
+        return None;
+    }
+    let mut pos = 2;
+    while pos + 4 <= data.len() {
+        if data[pos] != 0xFF {
+            return None;
+        }
+        let marker = data[pos + 1];
+        let len = u16::from_be_bytes([data[pos + 2], data[pos + 3]]) as usize;
+        if matches!(marker, 0xC0..=0xC2) {
+            let segment = data.get(pos + 4..pos + 2 + len)?;
+            let height = u16::from_be_bytes([*segment.get(1)?, *segment.get(2)?]);
+            let width = u16::from_be_bytes([*segment.get(3)?, *segment.get(4)?]);
+            return Some((width, height));
+        }
+        if marker == 0xDA {
+            return None;
+        }
+        pos += 2 + len;
+    }
+    None
+}
+
+/// 縮小後の幅と高さ（`THUMBNAIL_SCALE` 分の1、0にはしない）
+pub fn thumbnail_dimensions(width: u16, height: u16) -> (u16, u16) {
+    ((width / THUMBNAIL_SCALE).max(1), (height / THUMBNAIL_SCALE).max(1))
+}
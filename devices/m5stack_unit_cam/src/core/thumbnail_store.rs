@@ -0,0 +1,89 @@
+//! サムネイルのリングの保存先（`thumbs` パーティション、なければメモリ）と作成
+
+use log::{info, warn};
+
+use crate::sys_wrappers::esp::{self as sys, FlashPartition};
+use crate::sys_wrappers::SysResult;
+
+use super::thumbnail_ring::{
+    jpeg_dimensions, thumbnail_dimensions, MemoryThumbnailStorage, ThumbnailError, ThumbnailRing, ThumbnailStorage,
+    MAX_THUMBNAIL_LEN, MEMORY_RING_LEN, THUMBNAIL_HEADER_LEN, THUMBNAIL_JPEG_QUALITY, THUMBNAIL_SLOT_LEN,
+};
+
+/// サムネイルを保存するデータパーティションのラベル
+pub const THUMBNAIL_PARTITION_LABEL: &str = "thumbs";
+
+/// 保存先を問わないサムネイルのリング
+pub type DeviceThumbnailRing = ThumbnailRing<Box<dyn ThumbnailStorage>>;
+
+/// `thumbs` パーティションのスロット
+pub struct FlashThumbnailStorage {
+    partition: FlashPartition,
+    slot_count: usize,
+}
+
+impl FlashThumbnailStorage {
+    /// パーティションを開きます（パーティションがない、またはスロットが1つも取れなければNone）
+    pub fn open(max_slots: usize) -> Option<Self> {
+        let partition = FlashPartition::find(THUMBNAIL_PARTITION_LABEL)?;
+        let slot_count = (partition.size() / THUMBNAIL_SLOT_LEN).min(max_slots);
+        (slot_count > 0).then_some(Self { partition, slot_count })
+    }
+}
+
+impl ThumbnailStorage for FlashThumbnailStorage {
+    fn slot_count(&self) -> usize {
+        self.slot_count
+    }
+
+    fn read_slot(&self, slot: usize) -> Option<Vec<u8>> {
+        let offset = slot * THUMBNAIL_SLOT_LEN;
+        let mut header = vec![0u8; THUMBNAIL_HEADER_LEN];
+        self.partition.read(offset, &mut header).ok()?;
+        // 消去直後（0xFF）や壊れた長さは読み込まずにそのまま返す（`decode_slot` が破棄する）
+        let len = u16::from_le_bytes([header[12], header[13]]) as usize;
+        if len > MAX_THUMBNAIL_LEN {
+            return Some(header);
+        }
+        let mut data = vec![0u8; THUMBNAIL_HEADER_LEN + len];
+        self.partition.read(offset, &mut data).ok()?;
+        Some(data)
+    }
+
+    fn write_slot(&mut self, slot: usize, data: &[u8]) -> SysResult<()> {
+        self.partition.erase_and_write(slot * THUMBNAIL_SLOT_LEN, THUMBNAIL_SLOT_LEN, data)
+    }
+}
+
+/// 設定した枚数のリングを開きます（0なら保持しない）
+///
+/// `thumbs` パーティションがなければメモリに `MEMORY_RING_LEN` 枚まで保持します。
+pub fn open_thumbnail_ring(ring_len: u8) -> Option<DeviceThumbnailRing> {
+    if ring_len == 0 {
+        return None;
+    }
+    let storage: Box<dyn ThumbnailStorage> = match FlashThumbnailStorage::open(usize::from(ring_len)) {
+        Some(storage) => {
+            info!("サムネイルを{}枚までフラッシュに保持します", storage.slot_count());
+            Box::new(storage)
+        }
+        None => {
+            let slots = usize::from(ring_len).min(MEMORY_RING_LEN);
+            warn!(
+                "{}パーティションがないため、サムネイルはメモリに{}枚まで保持します（Deep sleepで失われます）",
+                THUMBNAIL_PARTITION_LABEL, slots
+            );
+            Box::new(MemoryThumbnailStorage::new(slots))
+        }
+    };
+    Some(ThumbnailRing::open(storage))
+}
+
+/// 撮影した画像を縮小してリングに保持し、通し番号を返します
+pub fn store_thumbnail(ring: &mut DeviceThumbnailRing, image: &[u8], captured_unix: u32) -> Result<u32, ThumbnailError> {
+    let (width, height) = jpeg_dimensions(image).ok_or(ThumbnailError::UnknownDimensions)?;
+    let (thumb_width, thumb_height) = thumbnail_dimensions(width, height);
+    let jpeg = sys::jpeg_thumbnail(image, thumb_width, thumb_height, THUMBNAIL_JPEG_QUALITY)
+        .map_err(ThumbnailError::Encode)?;
+    ring.push(captured_unix, jpeg)
+}
@@ -0,0 +1,122 @@
+//! タイムラプス撮影のシーケンス情報
+//!
+//! 設定で名前と撮影間隔を指定すると、撮影した画像ごとにシーケンス名・実行ID・通し番号を
+//! HASHフレームに付加します。受信側はこの値で画像をシーケンスごとに並べるため、
+//! 受信時刻から順序を推測する必要がありません。
+//!
+//! 通し番号はDeep sleepを跨いでRTCメモリに保持します。電源断などでRTCメモリが失われた場合や
+//! シーケンス名を変えた場合は、新しい実行IDで0から数え直します。
+
+use super::build_info::config_hash;
+
+/// シーケンス名の最大長
+pub const MAX_SEQUENCE_NAME_LEN: usize = 32;
+
+/// シーケンス状態の有効性を示す識別子
+const SEQUENCE_STATE_MAGIC: u32 = 0x5345_5131;
+
+/// タイムラプス設定
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimelapseSettings {
+    /// シーケンス名（英数字・`-`・`_`）
+    pub name: String,
+    /// 撮影間隔（秒）
+    pub interval_seconds: u64,
+}
+
+impl TimelapseSettings {
+    /// 設定値から作成します（名前が空なら無効としてNone）
+    ///
+    /// 受信側でディレクトリ名に使うため、名前は英数字・`-`・`_` のみ受け付けます。
+    pub fn from_config(name: &str, interval_seconds: u64) -> Result<Option<Self>, String> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Ok(None);
+        }
+        let valid_chars = name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !valid_chars || name.len() > MAX_SEQUENCE_NAME_LEN {
+            return Err(name.to_string());
+        }
+        Ok(Some(Self {
+            name: name.to_string(),
+            interval_seconds,
+        }))
+    }
+}
+
+/// シーケンス内の1枚の画像
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SequenceFrame {
+    /// シーケンス名
+    pub name: String,
+    /// 実行ID（通し番号を数え直すたびに変わる）
+    pub run_id: u32,
+    /// 実行内の通し番号（0始まり）
+    pub index: u32,
+    /// 撮影間隔（秒）
+    pub interval_seconds: u64,
+}
+
+impl SequenceFrame {
+    /// HASHフレームに付加するメタデータ
+    ///
+    /// 形式: `,SEQ:<名前>,SEQ_RUN:<実行ID 16進8桁>,SEQ_IDX:<番号>,SEQ_INT:<秒>`
+    pub fn metadata_fields(&self) -> String {
+        format!(
+            ",SEQ:{},SEQ_RUN:{:08x},SEQ_IDX:{},SEQ_INT:{}",
+            self.name, self.run_id, self.index, self.interval_seconds
+        )
+    }
+}
+
+/// RTCメモリに保持するシーケンスの進行状態
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct SequenceState {
+    magic: u32,
+    name_hash: u32,
+    run_id: u32,
+    next_index: u32,
+}
+
+impl Default for SequenceState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SequenceState {
+    /// 未開始の状態を作成します
+    pub const fn new() -> Self {
+        Self {
+            magic: 0,
+            name_hash: 0,
+            run_id: 0,
+            next_index: 0,
+        }
+    }
+
+    /// 撮影した画像に次の通し番号を割り当てます
+    ///
+    /// 状態が無効、またはシーケンス名が変わった場合は `new_run_id` で新しい実行を始めます。
+    pub fn next_frame(&mut self, settings: &TimelapseSettings, new_run_id: u32) -> SequenceFrame {
+        // シーケンス名の変更を検出するためのハッシュ
+        let name_hash = config_hash(&settings.name);
+        if self.magic != SEQUENCE_STATE_MAGIC || self.name_hash != name_hash {
+            *self = Self {
+                magic: SEQUENCE_STATE_MAGIC,
+                name_hash,
+                run_id: new_run_id,
+                next_index: 0,
+            };
+        }
+        let index = self.next_index;
+        self.next_index = self.next_index.wrapping_add(1);
+        SequenceFrame {
+            name: settings.name.clone(),
+            run_id: self.run_id,
+            index,
+            interval_seconds: settings.interval_seconds,
+        }
+    }
+}
@@ -0,0 +1,35 @@
+//! 転送サイクルのトレースID
+//!
+//! 撮影から保存までの各段階（撮影・無線転送・USB転送・保存）をホスト側で1つのトレースとして
+//! 扱えるよう、サイクルごとに64ビットのトレースIDを発行してHASHフレームに載せます。
+//! ゲートウェイは完了イベントにそのまま引き継ぎ、ホストはOpenTelemetryのトレースIDとして使用します。
+
+/// 1回の撮影・転送サイクルのトレース情報
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraceContext {
+    /// トレースID（0は無効値のため使用しない）
+    pub trace_id: u64,
+    /// 撮影にかかった時間（ミリ秒、撮影しなかった場合はNone）
+    pub capture_ms: Option<u32>,
+}
+
+impl TraceContext {
+    /// 乱数からトレースIDを生成します
+    pub fn from_entropy(high: u32, low: u32) -> Self {
+        let trace_id = ((high as u64) << 32) | low as u64;
+        Self {
+            // OpenTelemetryでは全ビット0のIDは無効
+            trace_id: trace_id.max(1),
+            capture_ms: None,
+        }
+    }
+
+    /// HASHフレームに付加するメタデータ
+    pub fn metadata_fields(&self) -> String {
+        let mut fields = format!(",TRACE:{:016x}", self.trace_id);
+        if let Some(capture_ms) = self.capture_ms {
+            fields.push_str(&format!(",CAP_MS:{}", capture_ms));
+        }
+        fields
+    }
+}
@@ -1,8 +1,16 @@
 use esp_camera_rs::{Camera, CameraParams, FrameBuffer};
+use esp_idf_svc::hal::delay::FreeRtos;
 use esp_idf_svc::hal::gpio;
 use esp_idf_sys::camera::*;
 use log::{error, info, warn}; // logクレートの必要な要素をインポート
+use std::sync::atomic::{AtomicU32, Ordering};
 use std::sync::Arc;
+use std::time::Instant;
+use crate::sys_wrappers::esp as sys;
+use super::fb_policy::{
+    resolve_fb_location, FbLocation, FrameBufferFailure, FrameBufferPlacement, FrameBufferStage,
+    HeapSnapshot,
+};
 use super::ov2640_sequence::{
     deep_sleep_standby_sequence, resume_sequence, standby_clkrc_write, standby_sequence, RegWrite,
 };
@@ -11,6 +19,7 @@ use super::ov3660_sequence::{
     resume_sequence as ov3660_resume_sequence, standby_sequence as ov3660_standby_sequence,
     REG_SYSTEM_CTRL0,
 };
+use super::recovery::{is_sccb_error_code, ESP_FAIL};
 
 const OV3660_CTRL_RUN: u8 = super::ov3660_sequence::CTRL_RUN;
 const OV3660_CTRL_STANDBY: u8 = super::ov3660_sequence::CTRL_STANDBY;
@@ -61,20 +70,46 @@ pub enum CustomFrameSize {
 
 #[derive(Clone, Debug)] // Added Clone
 pub struct M5UnitCamConfig {
-    // pub with_psram: bool, // Removed unused field
     pub frame_size: CustomFrameSize,
-    // pub jpeg_quality: i32, // JPEG品質設定は現在エラーを引き起こすため削除 (NO-EOI error)
+    /// JPEG品質（1〜63、Noneでドライバの既定値）。初期化パラメータでは指定せず初期化後にセンサーへ設定する
+    pub jpeg_quality: Option<u8>,
+    /// フレームバッファの配置ポリシー
+    pub fb_placement: FrameBufferPlacement,
+    /// フレームバッファ数
+    pub fb_count: usize,
+    /// センサーのPWDNピン（Noneなら再初期化時に電源を入れ直さない）
+    pub pwdn_gpio: Option<i32>,
 }
 
 impl Default for M5UnitCamConfig {
     fn default() -> Self {
         Self {
             frame_size: CustomFrameSize::Svga, // デフォルトはSVGA
-                                               // jpeg_quality: 12, // デフォルトのJPEG品質、現在は未使用
+            jpeg_quality: None,
+            fb_placement: FrameBufferPlacement::InternalOnly,
+            fb_count: 1,
+            pwdn_gpio: None,
         }
     }
 }
 
+/// 現在のヒープ状態を取得します
+pub fn capture_heap_snapshot() -> HeapSnapshot {
+    use crate::sys_wrappers::esp::{heap_free_size, heap_largest_free_block};
+    use esp_idf_sys::{MALLOC_CAP_8BIT, MALLOC_CAP_INTERNAL, MALLOC_CAP_SPIRAM};
+    HeapSnapshot {
+        internal_free: heap_free_size(MALLOC_CAP_INTERNAL | MALLOC_CAP_8BIT),
+        internal_largest: heap_largest_free_block(MALLOC_CAP_INTERNAL | MALLOC_CAP_8BIT),
+        psram_free: heap_free_size(MALLOC_CAP_SPIRAM),
+        psram_largest: heap_largest_free_block(MALLOC_CAP_SPIRAM),
+    }
+}
+
+/// PSRAMが利用可能かどうか
+fn psram_available() -> bool {
+    crate::sys_wrappers::esp::heap_total_size(esp_idf_sys::MALLOC_CAP_SPIRAM) > 0
+}
+
 impl M5UnitCamConfig {
     /// 文字列から framesize_t 定数を取得します
     pub fn from_string(size_str: &str) -> CustomFrameSize {
@@ -131,14 +166,44 @@ pub enum CameraError {
     #[error("未対応センサーのためスタンバイ制御できません: {0}")]
     UnsupportedSensor(String),
 
-    #[error("画像キャプチャに失敗しました")]
-    CaptureFailed,
+    #[error("画像キャプチャに失敗しました: {0:?}")]
+    CaptureFailed(FrameBufferFailure),
+
+    #[error("フレームバッファを確保できません: {0:?}")]
+    FrameBufferUnavailable(FrameBufferFailure),
+
+    #[error("カメラのSCCB通信に失敗しました (code=0x{code:X}): {detail}")]
+    SccbFailed { code: i32, detail: String },
+}
+
+impl CameraError {
+    /// フレームバッファ関連の失敗であれば、その記録を返します
+    pub fn frame_buffer_failure(&self) -> Option<FrameBufferFailure> {
+        match self {
+            CameraError::CaptureFailed(failure) | CameraError::FrameBufferUnavailable(failure) => {
+                Some(*failure)
+            }
+            _ => None,
+        }
+    }
+
+    /// SCCB通信の失敗であれば、そのエラーコードを返します（再初期化で回復する見込みがある）
+    pub fn sccb_error_code(&self) -> Option<i32> {
+        match self {
+            CameraError::SccbFailed { code, .. } => Some(*code),
+            _ => None,
+        }
+    }
 }
 
 /// M5Stack Unit Cam (ESP32)向けのカメラコントローラー
 pub struct CameraController {
     camera: Arc<Camera<'static>>,
     sensor_model: DetectedSensorModel,
+    fb_placement: FrameBufferPlacement,
+    config: M5UnitCamConfig,
+    /// 最後に取得できたフレームバッファの取得時間（ミリ秒、`u32::MAX` は未取得）
+    last_fb_get_ms: AtomicU32,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -161,39 +226,17 @@ impl CameraController {
         const SDA_GPIO: i32 = esp_idf_sys::gpio_num_t_GPIO_NUM_25;
         const SCL_GPIO: i32 = esp_idf_sys::gpio_num_t_GPIO_NUM_23;
 
-        let mut cfg: esp_idf_sys::i2c_config_t = Default::default();
-        cfg.mode = esp_idf_sys::i2c_mode_t_I2C_MODE_MASTER;
-        cfg.sda_io_num = SDA_GPIO;
-        cfg.scl_io_num = SCL_GPIO;
-        cfg.sda_pullup_en = true;
-        cfg.scl_pullup_en = true;
-        cfg.clk_flags = 0;
-        unsafe {
-            cfg.__bindgen_anon_1.master =
-                esp_idf_sys::i2c_config_t__bindgen_ty_1__bindgen_ty_1 { clk_speed: 100_000 };
-        }
-
-        let param_result = unsafe { esp_idf_sys::i2c_param_config(Self::SCCB_I2C_PORT, &cfg) };
-        if param_result != esp_idf_sys::ESP_OK {
+        if let Err(e) = sys::i2c_master_config(Self::SCCB_I2C_PORT, SDA_GPIO, SCL_GPIO, 100_000) {
             return Err(CameraError::InitFailed(format!(
                 "pre-probe SCCB param config failed: {}",
-                param_result
+                e.code()
             )));
         }
 
-        let install_result = unsafe {
-            esp_idf_sys::i2c_driver_install(
-                Self::SCCB_I2C_PORT,
-                esp_idf_sys::i2c_mode_t_I2C_MODE_MASTER,
-                0,
-                0,
-                0,
-            )
-        };
-        if install_result != esp_idf_sys::ESP_OK {
+        if let Err(e) = sys::i2c_master_driver_install(Self::SCCB_I2C_PORT) {
             return Err(CameraError::InitFailed(format!(
                 "pre-probe SCCB driver install failed: {}",
-                install_result
+                e.code()
             )));
         }
 
@@ -210,9 +253,8 @@ impl CameraController {
             }
         }
 
-        let delete_result = unsafe { esp_idf_sys::i2c_driver_delete(Self::SCCB_I2C_PORT) };
-        if delete_result != esp_idf_sys::ESP_OK {
-            warn!("pre-probe SCCB i2c_driver_delete failed: {}", delete_result);
+        if let Err(e) = sys::i2c_driver_delete(Self::SCCB_I2C_PORT) {
+            warn!("pre-probe SCCB i2c_driver_delete failed: {}", e.code());
         }
 
         apply_result
@@ -263,6 +305,20 @@ impl CameraController {
     ) -> Result<Self, CameraError> {
         info!("カメラを初期化しています");
 
+        let fb_location = resolve_fb_location(config.fb_placement, psram_available()).ok_or_else(|| {
+            CameraError::InitFailed("PSRAMが利用できないため、PSRAM専用のフレームバッファを確保できません".to_string())
+        })?;
+        let fb_location = match fb_location {
+            FbLocation::Psram => camera_fb_location_t_CAMERA_FB_IN_PSRAM,
+            FbLocation::Dram => camera_fb_location_t_CAMERA_FB_IN_DRAM,
+        };
+        info!(
+            "フレームバッファ: policy={}, location={:?}, count={}",
+            config.fb_placement.as_str(),
+            fb_location,
+            config.fb_count
+        );
+
         let camera_params = CameraParams::new()
             .set_reset_pin(reset_pin)
             .set_clock_pin(clock_pin)
@@ -282,10 +338,25 @@ impl CameraController {
             .set_scl_pin(scl_pin)
             .set_frame_size(config.frame_size as u32) // Cast to u32
             // .set_jpeg_quality(config.jpeg_quality) // 注意: この設定を有効にすると `cam_hal: NO-EOI` エラーが発生する (2025-05-09時点)
-            .set_fb_location(camera_fb_location_t_CAMERA_FB_IN_DRAM);
-
-        let camera =
-            Camera::new(&camera_params).map_err(|e| CameraError::InitFailed(format!("{:?}", e)))?;
+            .set_fb_location(fb_location)
+            .set_fb_count(config.fb_count);
+
+        let camera = Camera::new(&camera_params).map_err(|e| {
+            if e.code() == esp_idf_sys::ESP_ERR_NO_MEM {
+                CameraError::FrameBufferUnavailable(FrameBufferFailure {
+                    stage: FrameBufferStage::Init,
+                    placement: config.fb_placement,
+                    heap: capture_heap_snapshot(),
+                })
+            } else if is_sccb_error_code(e.code()) {
+                CameraError::SccbFailed {
+                    code: e.code(),
+                    detail: format!("{:?}", e),
+                }
+            } else {
+                CameraError::InitFailed(format!("{:?}", e))
+            }
+        })?;
 
         let sensor = camera.sensor();
         let pid = sensor.pid();
@@ -306,12 +377,100 @@ impl CameraController {
             sensor_model, pid, sccb_addr
         );
 
+        // 初期化パラメータの set_jpeg_quality は NO-EOI を起こすため、初期化後にセンサーへ設定する
+        if let Some(quality) = config.jpeg_quality {
+            match sensor.set_quality(quality as i32) {
+                Ok(()) => info!("JPEG品質を {} に設定しました", quality),
+                Err(e) => warn!("JPEG品質 {} の設定に失敗しました（既定値で継続）: {:?}", quality, e),
+            }
+        }
+
         Ok(Self {
             camera: Arc::new(camera),
             sensor_model,
+            fb_placement: config.fb_placement,
+            config,
+            last_fb_get_ms: AtomicU32::new(u32::MAX),
         })
     }
 
+    /// カメラドライバーを解放して初期化し直します
+    ///
+    /// `power_cycle` がtrueでPWDNピンが設定されていれば、解放後にPWDNピンで電源も入れ直します。
+    /// 失敗した場合はカメラは解放されたままになります。
+    pub fn reinit(self, power_cycle: bool) -> Result<Self, CameraError> {
+        let config = self.config.clone();
+        // CameraのDropでesp_camera_deinitされる
+        drop(self);
+        Self::reopen(config, power_cycle)
+    }
+
+    /// 解放済み（または初期化に失敗した）カメラを初期化し直します
+    pub fn reopen(config: M5UnitCamConfig, power_cycle: bool) -> Result<Self, CameraError> {
+        const PWDN_HOLD_MS: u32 = 100;
+        const POWER_ON_SETTLE_MS: u32 = 500;
+
+        let pwdn = config.pwdn_gpio.filter(|_| power_cycle);
+        warn!("カメラを再初期化します（電源再投入: {}）", pwdn.is_some());
+        if let Some(pwdn) = pwdn {
+            if let Err(e) = sys::gpio_set_output_level(pwdn, true) {
+                warn!("PWDNピン(GPIO{})による電源断に失敗しました: {}", pwdn, e.code());
+            }
+            FreeRtos::delay_ms(PWDN_HOLD_MS);
+            if let Err(e) = sys::gpio_set_output_level(pwdn, false) {
+                warn!("PWDNピン(GPIO{})による電源投入に失敗しました: {}", pwdn, e.code());
+            }
+        }
+        FreeRtos::delay_ms(POWER_ON_SETTLE_MS);
+
+        // SAFETY: これらのピンを所有していたカメラは解放済み（または初期化失敗で破棄済み）で、
+        // カメラ以外にこれらのピンを使うドライバーはない
+        unsafe {
+            Self::new(
+                gpio::Gpio15::new(),
+                gpio::Gpio27::new(),
+                gpio::Gpio32::new(),
+                gpio::Gpio35::new(),
+                gpio::Gpio34::new(),
+                gpio::Gpio5::new(),
+                gpio::Gpio39::new(),
+                gpio::Gpio18::new(),
+                gpio::Gpio36::new(),
+                gpio::Gpio19::new(),
+                gpio::Gpio22::new(),
+                gpio::Gpio26::new(),
+                gpio::Gpio21::new(),
+                gpio::Gpio25::new(),
+                gpio::Gpio23::new(),
+                config,
+            )
+        }
+    }
+
+    /// センサーのPIDレジスタを読み、SCCB通信が生きているか確認します
+    ///
+    /// 撮影失敗の原因がSCCBかどうかを切り分けるために使います。未対応センサーでは確認しません。
+    pub fn check_sccb(&self) -> Result<(), CameraError> {
+        let (pid_high, expected) = match self.sensor_model {
+            DetectedSensorModel::Ov2640 => {
+                self.select_bank_sensor_api(0x01).map_err(sccb_failed)?;
+                (self.read_reg_raw8(0x0A).map_err(sccb_failed)?, (Self::OV2640_PID >> 8) as u8)
+            }
+            DetectedSensorModel::Ov3660 => (
+                self.read_reg_by_16bit_addr(0x300A).map_err(sccb_failed)?,
+                (Self::OV3660_PID >> 8) as u8,
+            ),
+            DetectedSensorModel::Other(_) => return Ok(()),
+        };
+        if pid_high != expected {
+            return Err(CameraError::SccbFailed {
+                code: ESP_FAIL,
+                detail: format!("PID不一致 expected=0x{:02X} actual=0x{:02X}", expected, pid_high),
+            });
+        }
+        Ok(())
+    }
+
     /// 画像を撮影します
     ///
     /// 最初のフレームは捨てて、2枚目のフレームを返します。
@@ -321,9 +480,28 @@ impl CameraController {
     ///
     /// 画像キャプチャに失敗した場合にエラーを返します
     pub fn capture_image(&self) -> Result<FrameBuffer<'_>, CameraError> {
-        self.camera
-            .get_framebuffer()
-            .ok_or(CameraError::CaptureFailed)
+        let started = Instant::now();
+        let frame_buffer = self.camera.get_framebuffer().ok_or_else(|| {
+            // フレーム取得失敗はメモリ断片化と相関するため、その時点のヒープ状態を残す
+            CameraError::CaptureFailed(self.frame_buffer_failure(FrameBufferStage::Capture))
+        })?;
+        let elapsed_ms = started.elapsed().as_millis().min(u32::MAX as u128 - 1) as u32;
+        self.last_fb_get_ms.store(elapsed_ms, Ordering::Relaxed);
+        Ok(frame_buffer)
+    }
+
+    /// 最後に取得できたフレームバッファの取得時間（ミリ秒、取得できていなければNone）
+    pub fn last_fb_get_ms(&self) -> Option<u32> {
+        Some(self.last_fb_get_ms.load(Ordering::Relaxed)).filter(|&ms| ms != u32::MAX)
+    }
+
+    /// 現在のヒープ状態でフレームバッファ確保失敗を記録します
+    pub fn frame_buffer_failure(&self, stage: FrameBufferStage) -> FrameBufferFailure {
+        FrameBufferFailure {
+            stage,
+            placement: self.fb_placement,
+            heap: capture_heap_snapshot(),
+        }
     }
 
     /// 露光設定を行います。
@@ -632,6 +810,14 @@ impl CameraController {
     }
 }
 
+/// レジスタ読み書きの失敗をSCCB通信の失敗として扱います
+fn sccb_failed(e: CameraError) -> CameraError {
+    CameraError::SccbFailed {
+        code: ESP_FAIL,
+        detail: e.to_string(),
+    }
+}
+
 fn apply_reg_write(sensor: &esp_camera_rs::CameraSensor<'_>, write: RegWrite) -> Result<(), CameraError> {
     sensor
         .set_reg(write.reg, write.mask, write.value)
@@ -666,23 +852,12 @@ fn write_reg_raw_i2c(
     value: u8,
     timeout_ticks: u32,
 ) -> Result<(), CameraError> {
-    let payload = [reg, value];
-    let err = unsafe {
-        esp_idf_sys::i2c_master_write_to_device(
-            port,
-            dev_addr,
-            payload.as_ptr(),
-            payload.len(),
-            timeout_ticks,
-        )
-    };
-    if err != esp_idf_sys::ESP_OK {
-        return Err(CameraError::InitFailed(format!(
+    sys::i2c_write(port, dev_addr, &[reg, value], timeout_ticks).map_err(|e| {
+        CameraError::InitFailed(format!(
             "pre-probe SCCB write failed reg=0x{:02X} value=0x{:02X}: {}",
-            reg, value, err
-        )));
-    }
-    Ok(())
+            reg, value, e.code()
+        ))
+    })
 }
 
 fn read_reg_raw_i2c(
@@ -691,24 +866,9 @@ fn read_reg_raw_i2c(
     reg: u8,
     timeout_ticks: u32,
 ) -> Result<u8, CameraError> {
-    let addr = [reg];
     let mut data = [0u8; 1];
-    let err = unsafe {
-        esp_idf_sys::i2c_master_write_read_device(
-            port,
-            dev_addr,
-            addr.as_ptr(),
-            addr.len(),
-            data.as_mut_ptr(),
-            data.len(),
-            timeout_ticks,
-        )
-    };
-    if err != esp_idf_sys::ESP_OK {
-        return Err(CameraError::InitFailed(format!(
-            "pre-probe SCCB read failed reg=0x{:02X}: {}",
-            reg, err
-        )));
-    }
+    sys::i2c_write_read(port, dev_addr, &[reg], &mut data, timeout_ticks).map_err(|e| {
+        CameraError::InitFailed(format!("pre-probe SCCB read failed reg=0x{:02X}: {}", reg, e.code()))
+    })?;
     Ok(data[0])
 }
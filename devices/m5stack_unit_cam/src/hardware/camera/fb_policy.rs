@@ -0,0 +1,125 @@
+//! カメラフレームバッファの配置ポリシーと確保失敗時のテレメトリ
+
+/// フレームバッファ数の最小値
+pub const MIN_FB_COUNT: u8 = 1;
+/// フレームバッファ数の最大値
+pub const MAX_FB_COUNT: u8 = 3;
+
+/// フレームバッファの配置ポリシー
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FrameBufferPlacement {
+    /// PSRAMのみ（PSRAMがなければ初期化失敗）
+    PsramOnly,
+    /// PSRAMがあればPSRAM、なければ内部RAM
+    PreferPsram,
+    /// 内部RAMのみ
+    #[default]
+    InternalOnly,
+}
+
+impl FrameBufferPlacement {
+    /// 設定文字列から変換します（psram / prefer_psram / internal）
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "psram" => Some(FrameBufferPlacement::PsramOnly),
+            "prefer_psram" => Some(FrameBufferPlacement::PreferPsram),
+            "internal" => Some(FrameBufferPlacement::InternalOnly),
+            _ => None,
+        }
+    }
+
+    /// テレメトリ用の文字列表現
+    pub fn as_str(self) -> &'static str {
+        match self {
+            FrameBufferPlacement::PsramOnly => "PSRAM",
+            FrameBufferPlacement::PreferPsram => "PREFER_PSRAM",
+            FrameBufferPlacement::InternalOnly => "INTERNAL",
+        }
+    }
+}
+
+/// 実際のフレームバッファ配置先
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FbLocation {
+    /// PSRAM
+    Psram,
+    /// 内部RAM（DRAM）
+    Dram,
+}
+
+/// ポリシーとPSRAMの有無から配置先を決定します
+///
+/// # 戻り値
+/// PSRAM必須なのにPSRAMがない場合はNone
+pub fn resolve_fb_location(placement: FrameBufferPlacement, psram_available: bool) -> Option<FbLocation> {
+    match (placement, psram_available) {
+        (FrameBufferPlacement::PsramOnly, true) | (FrameBufferPlacement::PreferPsram, true) => {
+            Some(FbLocation::Psram)
+        }
+        (FrameBufferPlacement::PsramOnly, false) => None,
+        (FrameBufferPlacement::PreferPsram, false) | (FrameBufferPlacement::InternalOnly, _) => {
+            Some(FbLocation::Dram)
+        }
+    }
+}
+
+/// 確保失敗時点のヒープ状態（バイト）
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HeapSnapshot {
+    /// 内部RAMの空き
+    pub internal_free: usize,
+    /// 内部RAMの最大連続空きブロック
+    pub internal_largest: usize,
+    /// PSRAMの空き
+    pub psram_free: usize,
+    /// PSRAMの最大連続空きブロック
+    pub psram_largest: usize,
+}
+
+/// フレームバッファ確保に失敗した処理段階
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameBufferStage {
+    /// カメラ初期化（フレームバッファの事前確保）
+    Init,
+    /// フレーム取得
+    Capture,
+    /// 送信用バッファへのコピー
+    Copy,
+}
+
+impl FrameBufferStage {
+    /// テレメトリ用の文字列表現
+    pub fn as_str(self) -> &'static str {
+        match self {
+            FrameBufferStage::Init => "INIT",
+            FrameBufferStage::Capture => "CAPTURE",
+            FrameBufferStage::Copy => "COPY",
+        }
+    }
+}
+
+/// フレームバッファ確保失敗の記録
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameBufferFailure {
+    /// 失敗した段階
+    pub stage: FrameBufferStage,
+    /// 使用していた配置ポリシー
+    pub placement: FrameBufferPlacement,
+    /// 失敗時点のヒープ状態
+    pub heap: HeapSnapshot,
+}
+
+impl FrameBufferFailure {
+    /// HASHフレームに付加するメタデータ（`,FB_FAIL:...` 形式）
+    pub fn metadata_fields(&self) -> String {
+        format!(
+            ",FB_FAIL:{},FB_POLICY:{},HEAP_FREE:{},HEAP_LARGEST:{},PSRAM_FREE:{},PSRAM_LARGEST:{}",
+            self.stage.as_str(),
+            self.placement.as_str(),
+            self.heap.internal_free,
+            self.heap.internal_largest,
+            self.heap.psram_free,
+            self.heap.psram_largest
+        )
+    }
+}
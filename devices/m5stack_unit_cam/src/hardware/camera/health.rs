@@ -0,0 +1,92 @@
+//! カメラの健全性（フレームバッファの取得時間と撮影の連続失敗回数）
+//!
+//! リボンケーブルの接触不良などは、画像が届かなくなって数週間後に気付くことが多いため、
+//! `esp_camera_fb_get` にかかった時間の移動平均と撮影の連続失敗回数をDeep sleepを跨いで保持し、
+//! 毎回のHASHフレームで報告します。連続失敗回数が設定値に達したら `CAM_DEGRADED:1` を付加し、
+//! デバイスのログにも警告を出します。
+
+/// 既定の警告を出す連続失敗回数
+pub const DEFAULT_DEGRADED_STREAK: u8 = 3;
+/// 設定できる警告を出す連続失敗回数の最大値（0で警告しない）
+pub const MAX_DEGRADED_STREAK: u8 = 100;
+/// 移動平均に新しい値を反映する割合（1/N）
+const AVERAGE_WEIGHT: u32 = 4;
+
+/// 1回のサイクルの撮影結果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaptureOutcome {
+    /// 撮影できた（フレームバッファの取得にかかった時間、ミリ秒）
+    Captured { fb_get_ms: u32 },
+    /// リトライを含めて撮影できなかった
+    Failed,
+}
+
+/// Deep sleepを跨いで保持するカメラの健全性
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CameraHealth {
+    /// 最後に撮影できたときのフレームバッファの取得時間（ミリ秒）
+    last_fb_get_ms: Option<u32>,
+    /// フレームバッファの取得時間の移動平均（ミリ秒）
+    average_fb_get_ms: Option<u32>,
+    /// 撮影の連続失敗回数
+    fail_streak: u16,
+}
+
+impl CameraHealth {
+    /// 記録のない状態を作成します
+    pub const fn new() -> Self {
+        Self {
+            last_fb_get_ms: None,
+            average_fb_get_ms: None,
+            fail_streak: 0,
+        }
+    }
+
+    /// サイクルの撮影結果を記録します
+    pub fn record(&mut self, outcome: CaptureOutcome) {
+        match outcome {
+            CaptureOutcome::Captured { fb_get_ms } => {
+                self.last_fb_get_ms = Some(fb_get_ms);
+                self.average_fb_get_ms = Some(match self.average_fb_get_ms {
+                    Some(average) => {
+                        let average = average as i64;
+                        (average + (fb_get_ms as i64 - average) / AVERAGE_WEIGHT as i64) as u32
+                    }
+                    None => fb_get_ms,
+                });
+                self.fail_streak = 0;
+            }
+            CaptureOutcome::Failed => self.fail_streak = self.fail_streak.saturating_add(1),
+        }
+    }
+
+    /// 撮影の連続失敗回数
+    pub fn fail_streak(&self) -> u16 {
+        self.fail_streak
+    }
+
+    /// フレームバッファの取得時間の移動平均（ミリ秒、撮影できたことがなければNone）
+    pub fn average_fb_get_ms(&self) -> Option<u32> {
+        self.average_fb_get_ms
+    }
+
+    /// 連続失敗回数が `degraded_streak` に達したか（0なら常にfalse）
+    pub fn is_degraded(&self, degraded_streak: u8) -> bool {
+        degraded_streak > 0 && self.fail_streak >= u16::from(degraded_streak)
+    }
+
+    /// HASHフレームに付加するメタデータ
+    ///
+    /// 取得時間は撮影できたことがある場合のみ付加します。
+    pub fn metadata_fields(&self, degraded_streak: u8) -> String {
+        let mut fields = String::new();
+        if let (Some(last), Some(average)) = (self.last_fb_get_ms, self.average_fb_get_ms) {
+            fields.push_str(&format!(",CAM_FB_MS:{},CAM_FB_AVG_MS:{}", last, average));
+        }
+        fields.push_str(&format!(",CAM_FAIL_STREAK:{}", self.fail_streak));
+        if self.is_degraded(degraded_streak) {
+            fields.push_str(",CAM_DEGRADED:1");
+        }
+        fields
+    }
+}
@@ -1,8 +1,20 @@
 /// カメラ制御モジュール
 pub mod controller;
+/// フレームバッファ配置ポリシー
+pub mod fb_policy;
+/// フレームバッファの取得時間と撮影の連続失敗回数
+pub mod health;
 /// OV2640スタンバイ用レジスタシーケンス
 pub mod ov2640_sequence;
 /// OV3660スタンバイ用レジスタシーケンス
 pub mod ov3660_sequence;
+/// SCCBエラーからの再初期化による復旧
+pub mod recovery;
+/// 撮影前のウォームアップの方式
+pub mod warmup;
 
 pub use controller::*;
+pub use fb_policy::{FrameBufferFailure, FrameBufferPlacement, FrameBufferStage};
+pub use health::{CameraHealth, CaptureOutcome};
+pub use recovery::{CameraRecovery, RecoveryStep};
+pub use warmup::{Warmup, WarmupStep, WarmupStrategy};
@@ -0,0 +1,127 @@
+//! カメラのSCCB（I2C）エラーからの復旧
+//!
+//! センサーとのSCCB通信が途切れると、同じドライバーのまま撮影を繰り返しても回復しません。
+//! そこでSCCB起因のエラーを検出したら、カメラドライバーを完全に解放して初期化し直し
+//! （PWDNピンがあれば電源も入れ直し）、上限回数まで試してから諦めます。
+//! 試行の結果はHASHフレームのメタデータで報告します。
+
+/// ESP-IDFの汎用エラー（SCCBのレジスタ読み書き失敗もこの値を返す）
+pub const ESP_FAIL: i32 = -1;
+/// ドライバーの状態が不正
+pub const ESP_ERR_INVALID_STATE: i32 = 0x103;
+/// タイムアウト
+pub const ESP_ERR_TIMEOUT: i32 = 0x107;
+/// esp32-camera: SCCBでセンサーを検出できない
+pub const ESP_ERR_CAMERA_NOT_DETECTED: i32 = 0x20001;
+/// esp32-camera: フレームサイズの設定に失敗
+pub const ESP_ERR_CAMERA_FAILED_TO_SET_FRAME_SIZE: i32 = 0x20002;
+/// esp32-camera: 出力フォーマットの設定に失敗
+pub const ESP_ERR_CAMERA_FAILED_TO_SET_OUT_FORMAT: i32 = 0x20003;
+/// esp32-camera: 未対応のセンサー（再初期化しても回復しない）
+pub const ESP_ERR_CAMERA_NOT_SUPPORTED: i32 = 0x20004;
+
+/// 再初期化の回数の上限
+pub const MAX_REINIT_ATTEMPTS: u8 = 5;
+
+/// SCCB通信の失敗を示すエラーコードか（再初期化で回復する見込みがあるか）
+pub fn is_sccb_error_code(code: i32) -> bool {
+    matches!(
+        code,
+        ESP_FAIL
+            | ESP_ERR_INVALID_STATE
+            | ESP_ERR_TIMEOUT
+            | ESP_ERR_CAMERA_NOT_DETECTED
+            | ESP_ERR_CAMERA_FAILED_TO_SET_FRAME_SIZE
+            | ESP_ERR_CAMERA_FAILED_TO_SET_OUT_FORMAT
+    )
+}
+
+/// 次に行う復旧の手順
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecoveryStep {
+    /// ドライバーを解放して初期化し直す
+    Reinit,
+    /// PWDNピンで電源を入れ直してから初期化し直す
+    PowerCycleAndReinit,
+    /// 上限に達したので諦める
+    GiveUp,
+}
+
+/// 1回のサイクルで行った復旧の記録
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CameraRecovery {
+    max_attempts: u8,
+    power_cycle_available: bool,
+    attempts: u8,
+    power_cycles: u8,
+    recovered: bool,
+    last_error_code: Option<i32>,
+}
+
+impl CameraRecovery {
+    /// `max_attempts` は再初期化の上限、`power_cycle_available` はPWDNピンが設定されているか
+    pub fn new(max_attempts: u8, power_cycle_available: bool) -> Self {
+        Self {
+            max_attempts: max_attempts.min(MAX_REINIT_ATTEMPTS),
+            power_cycle_available,
+            ..Self::default()
+        }
+    }
+
+    /// SCCBエラーを記録し、次に行う手順を返します
+    ///
+    /// 最初は再初期化だけを試し、それでも失敗した2回目以降はPWDNピンがあれば電源も入れ直します。
+    pub fn next_step(&mut self, error_code: i32) -> RecoveryStep {
+        self.last_error_code = Some(error_code);
+        self.recovered = false;
+        if self.attempts >= self.max_attempts {
+            return RecoveryStep::GiveUp;
+        }
+        self.attempts += 1;
+        if self.power_cycle_available && self.attempts > 1 {
+            self.power_cycles += 1;
+            RecoveryStep::PowerCycleAndReinit
+        } else {
+            RecoveryStep::Reinit
+        }
+    }
+
+    /// 再初期化後に撮影できたことを記録します
+    pub fn mark_recovered(&mut self) {
+        if self.attempts > 0 {
+            self.recovered = true;
+        }
+    }
+
+    /// 再初期化の回数
+    pub fn attempts(&self) -> u8 {
+        self.attempts
+    }
+
+    /// 電源を入れ直した回数
+    pub fn power_cycles(&self) -> u8 {
+        self.power_cycles
+    }
+
+    /// 再初期化で撮影できるようになったか
+    pub fn recovered(&self) -> bool {
+        self.recovered
+    }
+
+    /// HASHフレームに付加するメタデータ（再初期化していなければ空）
+    pub fn metadata_fields(&self) -> String {
+        if self.attempts == 0 {
+            return String::new();
+        }
+        let mut fields = format!(
+            ",CAM_REINIT:{},CAM_PWDN:{},CAM_RECOVERED:{}",
+            self.attempts,
+            self.power_cycles,
+            u8::from(self.recovered)
+        );
+        if let Some(code) = self.last_error_code {
+            fields.push_str(&format!(",CAM_ERR:0x{:X}", code));
+        }
+        fields
+    }
+}
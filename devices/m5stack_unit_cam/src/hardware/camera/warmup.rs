@@ -0,0 +1,137 @@
+//! 撮影前のウォームアップ（捨てフレーム）の方式
+//!
+//! 従来の `frames` はフレームごとに1秒待つため、捨てる枚数の分だけ起きている時間が延びます。
+//! `duration` は指定した時間のあいだセンサーが出す速さでフレームを取得して捨て、`auto` はさらに
+//! 連続するフレームの露出値（AEC）が落ち着いた時点で打ち切ります（指定した時間が上限）。
+
+/// 既定のウォームアップ時間（ミリ秒）
+pub const DEFAULT_WARMUP_MS: u16 = 1500;
+/// 設定できるウォームアップ時間の上限（ミリ秒）
+pub const MAX_WARMUP_MS: u16 = 10_000;
+/// `frames` 方式でフレームごとに待つ時間（ミリ秒）
+pub const FRAME_WARMUP_DELAY_MS: u32 = 1000;
+/// `auto` 方式で露出値が落ち着いたとみなす連続フレーム数
+pub const AUTO_STABLE_FRAMES: usize = 3;
+/// `auto` 方式で落ち着いたとみなす露出値の変動幅（最大値に対する割合、%）
+pub const AUTO_STABLE_TOLERANCE_PERCENT: i32 = 5;
+
+/// ウォームアップの方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WarmupStrategy {
+    /// 指定した枚数を1秒おきに捨てる（従来の方式）
+    Frames(u8),
+    /// 指定した時間のあいだ待たずに取得して捨てる
+    Duration { ms: u16 },
+    /// 露出値が落ち着くまで待たずに取得して捨てる（指定した時間が上限）
+    Auto { max_ms: u16 },
+}
+
+impl WarmupStrategy {
+    /// 設定値から方式を決めます（`mode` は frames / duration / auto、不正ならNone）
+    pub fn from_config(mode: &str, frames: Option<u8>, duration_ms: u16) -> Option<Self> {
+        if duration_ms > MAX_WARMUP_MS {
+            return None;
+        }
+        match mode.trim().to_ascii_lowercase().as_str() {
+            "frames" => Some(WarmupStrategy::Frames(frames.unwrap_or(0))),
+            "duration" => Some(WarmupStrategy::Duration { ms: duration_ms }),
+            "auto" => Some(WarmupStrategy::Auto { max_ms: duration_ms }),
+            _ => None,
+        }
+    }
+
+    /// ログ用の表記
+    pub fn as_str(self) -> &'static str {
+        match self {
+            WarmupStrategy::Frames(_) => "frames",
+            WarmupStrategy::Duration { .. } => "duration",
+            WarmupStrategy::Auto { .. } => "auto",
+        }
+    }
+}
+
+/// 次に行うこと
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WarmupStep {
+    /// 次のフレームを取得して捨て、`delay_after_ms` 待つ
+    Discard { delay_after_ms: u32 },
+    /// ウォームアップを終えて撮影する
+    Done,
+}
+
+/// ウォームアップの進み具合
+#[derive(Debug, Clone)]
+pub struct Warmup {
+    strategy: WarmupStrategy,
+    discarded: u32,
+    recent_aec: Vec<i32>,
+    stabilized: bool,
+}
+
+impl Warmup {
+    pub fn new(strategy: WarmupStrategy) -> Self {
+        Self {
+            strategy,
+            discarded: 0,
+            recent_aec: Vec::with_capacity(AUTO_STABLE_FRAMES),
+            stabilized: false,
+        }
+    }
+
+    /// 捨てたフレーム数
+    pub fn discarded(&self) -> u32 {
+        self.discarded
+    }
+
+    /// `auto` 方式で露出値が落ち着いて打ち切ったか
+    pub fn stabilized(&self) -> bool {
+        self.stabilized
+    }
+
+    /// 次に行うことを返します（最初のフレームを捨てる前にも呼び出す）
+    ///
+    /// # 引数
+    /// * `elapsed_ms` - ウォームアップを始めてからの時間
+    pub fn next_step(&self, elapsed_ms: u32) -> WarmupStep {
+        match self.strategy {
+            WarmupStrategy::Frames(count) if self.discarded < u32::from(count) => WarmupStep::Discard {
+                delay_after_ms: FRAME_WARMUP_DELAY_MS,
+            },
+            WarmupStrategy::Duration { ms } if elapsed_ms < u32::from(ms) => {
+                WarmupStep::Discard { delay_after_ms: 0 }
+            }
+            WarmupStrategy::Auto { max_ms } if elapsed_ms < u32::from(max_ms) && !self.stabilized => {
+                WarmupStep::Discard { delay_after_ms: 0 }
+            }
+            _ => WarmupStep::Done,
+        }
+    }
+
+    /// フレームを捨てたことを記録します
+    ///
+    /// # 引数
+    /// * `aec` - 捨てたフレームの露出値（取得できなければNone、`auto` 方式のみ使用）
+    pub fn record_discard(&mut self, aec: Option<i32>) {
+        self.discarded += 1;
+        if !matches!(self.strategy, WarmupStrategy::Auto { .. }) {
+            return;
+        }
+        let Some(aec) = aec else {
+            self.recent_aec.clear();
+            return;
+        };
+        if self.recent_aec.len() == AUTO_STABLE_FRAMES {
+            self.recent_aec.remove(0);
+        }
+        self.recent_aec.push(aec);
+        self.stabilized = self.recent_aec.len() == AUTO_STABLE_FRAMES && is_stable(&self.recent_aec);
+    }
+}
+
+/// 露出値の変動幅が許容範囲に収まっているか
+fn is_stable(values: &[i32]) -> bool {
+    let (Some(min), Some(max)) = (values.iter().min(), values.iter().max()) else {
+        return false;
+    };
+    (max - min) * 100 <= max.abs() * AUTO_STABLE_TOLERANCE_PERCENT
+}
@@ -1,4 +1,6 @@
 /// ステータスLED制御モジュール
+pub mod pattern;
 pub mod status_led;
 
+pub use pattern::{LedEvent, LedPattern, LedPatterns};
 pub use status_led::*;
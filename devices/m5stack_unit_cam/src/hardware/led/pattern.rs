@@ -0,0 +1,132 @@
+//! ステータスLEDの点灯パターン
+//!
+//! イベント（撮影・送信・成功・エラー・低電圧）ごとの点灯パターンと、経過時間から
+//! LEDの状態を求めるパターンエンジンです。遅延で待たずに状態を求めるため、
+//! LEDタスクは次の切り替えまでの時間だけ待機すれば済みます。
+
+/// LEDで表示するイベント
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LedEvent {
+    /// 画像撮影中
+    Capture,
+    /// データ送信中
+    Transmit,
+    /// 送信成功
+    Success,
+    /// エラー
+    Error,
+    /// 電圧不足で撮影をスキップ
+    LowBattery,
+}
+
+/// 点灯パターン
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LedPattern {
+    /// 消灯
+    Off,
+    /// 点灯し続ける
+    Solid,
+    /// 点滅（`count` が0なら繰り返し続ける）
+    Blink { on_ms: u32, off_ms: u32, count: u8 },
+}
+
+/// ある時点でのLEDの状態
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LedState {
+    /// 点灯しているか
+    pub on: bool,
+    /// 次に状態が変わるまでの時間（ミリ秒、変わらなければNone）
+    pub hold_ms: Option<u32>,
+}
+
+impl LedPattern {
+    /// 設定文字列から変換します
+    ///
+    /// 形式: `off`、`on`、`<点灯ms>/<消灯ms>`（繰り返し）、`<点灯ms>/<消灯ms>x<回数>`
+    pub fn parse(value: &str) -> Option<Self> {
+        let value = value.trim().to_ascii_lowercase();
+        match value.as_str() {
+            "off" => return Some(Self::Off),
+            "on" | "solid" => return Some(Self::Solid),
+            _ => {}
+        }
+        let (timing, count) = match value.split_once('x') {
+            Some((timing, count)) => (timing, count.trim().parse::<u8>().ok().filter(|&c| c > 0)?),
+            None => (value.as_str(), 0),
+        };
+        let (on_ms, off_ms) = timing.split_once('/')?;
+        let on_ms: u32 = on_ms.trim().parse().ok()?;
+        let off_ms: u32 = off_ms.trim().parse().ok()?;
+        if on_ms == 0 || off_ms == 0 {
+            return None;
+        }
+        Some(Self::Blink { on_ms, off_ms, count })
+    }
+
+    /// 開始からの経過時間における状態
+    pub fn state_at(&self, elapsed_ms: u64) -> LedState {
+        match *self {
+            Self::Off => LedState { on: false, hold_ms: None },
+            Self::Solid => LedState { on: true, hold_ms: None },
+            Self::Blink { on_ms, off_ms, count } => {
+                let period = u64::from(on_ms) + u64::from(off_ms);
+                if count > 0 && elapsed_ms >= period * u64::from(count) {
+                    return LedState { on: false, hold_ms: None };
+                }
+                let phase = elapsed_ms % period;
+                if phase < u64::from(on_ms) {
+                    LedState { on: true, hold_ms: Some((u64::from(on_ms) - phase) as u32) }
+                } else {
+                    LedState { on: false, hold_ms: Some((period - phase) as u32) }
+                }
+            }
+        }
+    }
+
+    /// 回数指定の点滅が経過時間内に終わっているか（消灯・点灯し続けるパターンは常に true）
+    pub fn is_finished(&self, elapsed_ms: u64) -> bool {
+        match *self {
+            Self::Blink { count: 0, .. } => false,
+            Self::Blink { on_ms, off_ms, count } => {
+                elapsed_ms >= (u64::from(on_ms) + u64::from(off_ms)) * u64::from(count)
+            }
+            _ => true,
+        }
+    }
+}
+
+/// イベントごとの点灯パターン
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LedPatterns {
+    pub capture: LedPattern,
+    pub transmit: LedPattern,
+    pub success: LedPattern,
+    pub error: LedPattern,
+    pub low_battery: LedPattern,
+}
+
+impl Default for LedPatterns {
+    /// 従来の表示（撮影・送信中は点灯、成功は短く2回、エラーは3回点滅）に低電圧表示を加えたもの
+    fn default() -> Self {
+        Self {
+            capture: LedPattern::Solid,
+            transmit: LedPattern::Solid,
+            success: LedPattern::Blink { on_ms: 100, off_ms: 100, count: 2 },
+            error: LedPattern::Blink { on_ms: 300, off_ms: 300, count: 3 },
+            low_battery: LedPattern::Blink { on_ms: 50, off_ms: 450, count: 3 },
+        }
+    }
+}
+
+impl LedPatterns {
+    /// イベントに対応するパターン
+    pub fn for_event(&self, event: LedEvent) -> LedPattern {
+        match event {
+            LedEvent::Capture => self.capture,
+            LedEvent::Transmit => self.transmit,
+            LedEvent::Success => self.success,
+            LedEvent::Error => self.error,
+            LedEvent::LowBattery => self.low_battery,
+        }
+    }
+}
@@ -1,5 +1,14 @@
-use esp_idf_svc::hal::delay::FreeRtos;
-use esp_idf_svc::hal::gpio::{Output, PinDriver};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use esp_idf_svc::hal::gpio::{Gpio4, Output, PinDriver};
+use log::warn;
+
+use super::pattern::{LedEvent, LedPattern, LedPatterns};
+
+/// LEDタスクのスタックサイズ（バイト）
+const LED_TASK_STACK_SIZE: usize = 3072;
 
 /// LEDの制御に関するエラー
 #[derive(Debug, thiserror::Error)]
@@ -11,87 +20,135 @@ pub enum LedError {
     ControlFailed(String),
 }
 
+/// LEDタスクへの指示
+enum LedCommand {
+    /// パターンを直ちに切り替える
+    Show(LedPattern),
+    /// 回数指定の点滅は最後まで表示し、その後消灯する
+    Clear,
+}
+
 /// ステータスLED制御
+///
+/// 点灯パターンはバックグラウンドのLEDタスクが表示するため、各メソッドは待たずに戻ります。
 pub struct StatusLed {
-    led: PinDriver<'static, esp_idf_svc::hal::gpio::Gpio4, Output>,
+    commands: Sender<LedCommand>,
+    patterns: LedPatterns,
 }
 
 impl StatusLed {
-    /// 新しいステータスLEDコントローラーを作成します
+    /// 新しいステータスLEDコントローラーを作成し、LEDタスクを起動します
     ///
     /// # 引数
     ///
     /// * `pin` - GPIO4ピン
+    /// * `patterns` - イベントごとの点灯パターン
     ///
     /// # エラー
     ///
     /// LEDの初期化に失敗した場合にエラーを返します
-    pub fn new(pin: esp_idf_svc::hal::gpio::Gpio4) -> Result<Self, LedError> {
+    pub fn new(pin: Gpio4, patterns: LedPatterns) -> Result<Self, LedError> {
         let led = PinDriver::output(pin).map_err(|e| LedError::InitFailed(format!("{:?}", e)))?;
+        let (commands, receiver) = mpsc::channel();
+        thread::Builder::new()
+            .name("status_led".into())
+            .stack_size(LED_TASK_STACK_SIZE)
+            .spawn(move || run_led_task(led, receiver))
+            .map_err(|e| LedError::InitFailed(format!("LEDタスクを起動できません: {:?}", e)))?;
 
-        Ok(Self { led })
+        Ok(Self { commands, patterns })
     }
 
-    /// LEDを点灯させます
+    /// イベントに対応するパターンを表示します
     ///
     /// # エラー
     ///
-    /// LED制御に失敗した場合にエラーを返します
-    pub fn turn_on(&mut self) -> Result<(), LedError> {
-        self.led
-            .set_high()
-            .map_err(|e| LedError::ControlFailed(format!("{:?}", e)))
+    /// LEDタスクが停止している場合にエラーを返します
+    pub fn indicate(&mut self, event: LedEvent) -> Result<(), LedError> {
+        let pattern = self.patterns.for_event(event);
+        self.send(LedCommand::Show(pattern))
     }
 
-    /// LEDを消灯させます
+    /// 表示中の点滅が終わりしだい消灯します（点灯し続けるパターンは直ちに消灯）
     ///
     /// # エラー
     ///
-    /// LED制御に失敗した場合にエラーを返します
-    pub fn turn_off(&mut self) -> Result<(), LedError> {
-        self.led
-            .set_low()
-            .map_err(|e| LedError::ControlFailed(format!("{:?}", e)))
+    /// LEDタスクが停止している場合にエラーを返します
+    pub fn clear(&mut self) -> Result<(), LedError> {
+        self.send(LedCommand::Clear)
     }
 
-    /// LED点滅パターンを実行します（エラー表示）
+    /// LEDを点灯させます
     ///
     /// # エラー
     ///
-    /// LED制御に失敗した場合にエラーを返します
-    pub fn blink_error(&mut self) -> Result<(), LedError> {
-        for _ in 0..3 {
-            self.turn_on()?;
-            FreeRtos::delay_ms(300);
-            self.turn_off()?;
-            FreeRtos::delay_ms(300);
-        }
-        Ok(())
+    /// LEDタスクが停止している場合にエラーを返します
+    pub fn turn_on(&mut self) -> Result<(), LedError> {
+        self.send(LedCommand::Show(LedPattern::Solid))
     }
 
-    /// 成功時のLED点滅（短い点滅）
+    /// LEDを直ちに消灯させます
     ///
     /// # エラー
     ///
-    /// LED制御に失敗した場合にエラーを返します
-    pub fn blink_success(&mut self) -> Result<(), LedError> {
-        for _ in 0..2 {
-            self.turn_on()?;
-            FreeRtos::delay_ms(100);
-            self.turn_off()?;
-            FreeRtos::delay_ms(100);
-        }
-        Ok(())
+    /// LEDタスクが停止している場合にエラーを返します
+    pub fn turn_off(&mut self) -> Result<(), LedError> {
+        self.send(LedCommand::Show(LedPattern::Off))
     }
 
-    /// 送信中パターンを実行します
-    ///
-    /// # エラー
-    ///
-    /// LED制御に失敗した場合にエラーを返します
-    pub fn indicate_sending(&mut self) -> Result<(), LedError> {
-        self.turn_on()?;
-        FreeRtos::delay_ms(100);
-        self.turn_off()
+    fn send(&self, command: LedCommand) -> Result<(), LedError> {
+        self.commands
+            .send(command)
+            .map_err(|_| LedError::ControlFailed("LEDタスクが停止しています".to_string()))
+    }
+}
+
+/// パターンを表示し続けるLEDタスク（コントローラーが破棄されると消灯して終了）
+fn run_led_task(mut led: PinDriver<'static, Gpio4, Output>, commands: Receiver<LedCommand>) {
+    let mut pattern = LedPattern::Off;
+    let mut started = Instant::now();
+    let mut clear_pending = false;
+    let mut current_level: Option<bool> = None;
+
+    loop {
+        let elapsed_ms = started.elapsed().as_millis() as u64;
+        if clear_pending && pattern.is_finished(elapsed_ms) {
+            pattern = LedPattern::Off;
+            clear_pending = false;
+        }
+        let state = pattern.state_at(elapsed_ms);
+        if current_level != Some(state.on) {
+            let result = if state.on { led.set_high() } else { led.set_low() };
+            match result {
+                Ok(()) => current_level = Some(state.on),
+                Err(e) => warn!("LEDの点灯制御に失敗しました: {:?}", e),
+            }
+        }
+
+        let command = match state.hold_ms {
+            Some(hold_ms) => match commands.recv_timeout(Duration::from_millis(u64::from(hold_ms))) {
+                Ok(command) => command,
+                Err(RecvTimeoutError::Timeout) => continue,
+                Err(RecvTimeoutError::Disconnected) => break,
+            },
+            None => match commands.recv() {
+                Ok(command) => command,
+                Err(_) => break,
+            },
+        };
+        match command {
+            LedCommand::Show(next) => {
+                pattern = next;
+                started = Instant::now();
+                clear_pending = false;
+            }
+            // 繰り返し続ける点滅は終わりを待たない
+            LedCommand::Clear => match pattern {
+                LedPattern::Blink { count, .. } if count > 0 => clear_pending = true,
+                _ => pattern = LedPattern::Off,
+            },
+        }
     }
+
+    let _ = led.set_low();
 }
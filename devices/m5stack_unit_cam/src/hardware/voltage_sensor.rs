@@ -11,7 +11,7 @@ use esp_idf_svc::hal::{
 };
 use log::{error, info};
 use crate::core::config::CONFIG;
-use crate::core::voltage_to_percentage;
+use crate::units::{Millivolts, Percent};
 
 /// ADC電圧センサー管理モジュール
 pub struct VoltageSensor;
@@ -21,11 +21,11 @@ impl VoltageSensor {
     /// 
     /// # Returns
     /// - 0-100: 正常な電圧パーセンテージ
-    /// - 255: 測定エラー
+    /// - [`Percent::INVALID`]: 測定エラー
     pub fn measure_voltage_percentage(
         mut adc2: ADC2,
         mut gpio0: Gpio0,
-    ) -> anyhow::Result<(u8, ADC2, Gpio0)> {
+    ) -> anyhow::Result<(Percent, ADC2, Gpio0)> {
         info!("ADC2を初期化しています (GPIO0)");
         let adc_driver = AdcDriver::new(&mut adc2)?;
         let adc_config = AdcChannelConfig {
@@ -38,26 +38,26 @@ impl VoltageSensor {
         info!("ADC電圧を測定しパーセンテージを計算します...");
         let mut voltage_percent = match adc_channel.read() {
             Ok(voltage_mv_u16) => {
-                let voltage_mv = voltage_mv_u16 as f32;
-                info!("ADC電圧測定成功: {:.0} mV", voltage_mv);
+                let voltage = Millivolts::from(voltage_mv_u16);
+                info!("ADC電圧測定成功: {}", voltage);
 
-                let result = voltage_to_percentage(
-                    voltage_mv,
-                    CONFIG.adc_voltage_min_mv as f32,
-                    CONFIG.adc_voltage_max_mv as f32,
+                let result = Percent::of_range(
+                    voltage,
+                    Millivolts::new(CONFIG.adc_voltage_min_mv as f32),
+                    Millivolts::new(CONFIG.adc_voltage_max_mv as f32),
                 );
-                info!("計算されたパーセンテージ: {} %", result);
+                info!("計算されたパーセンテージ: {}", result);
                 result
             }
             Err(e) => {
-                error!("ADC読み取りエラー: {:?}. 電圧は255%として扱います。", e);
-                255
+                error!("ADC読み取りエラー: {:?}. 電圧は{}として扱います。", e, Percent::INVALID);
+                Percent::INVALID
             }
         };
 
         if CONFIG.force_voltage_percent_50 {
             info!("force_voltage_percent_50=true のため、電圧を 50% に強制します");
-            voltage_percent = 50;
+            voltage_percent = Percent::new(50);
         }
 
         // リソースを明示的に解放
@@ -8,24 +8,110 @@
  * - `hardware`: ハードウェア制御（カメラ、LED、電圧センサー、ピン設定）
  * - `communication`: 通信機能（ESP-NOW、ネットワーク管理）
  * - `power`: 電源管理（ディープスリープ）
+ * - `sys_wrappers`: ESP-IDFの生の呼び出しをまとめた安全なラッパー（ホストビルドではモックのみ）
+ * - `units`: テレメトリの物理量の型（mV、℃、ppm、%）
+ *
+ * "esp"フィーチャーを無効にしたホストビルド（`--no-default-features --features host`）では、
+ * 同じソースのうちESP-IDFに依存しないモジュールのみを公開します。
  */
 
 // 公開モジュール
+#[cfg(feature = "esp")]
 pub mod communication;
+#[cfg(feature = "esp")]
 pub mod core;
+#[cfg(feature = "esp")]
 pub mod hardware;
 pub mod mac_address;
+#[cfg(feature = "esp")]
 pub mod power;
+pub mod sys_wrappers;
+pub mod units;
 
 // 内部で使用する型をまとめてエクスポート
+#[cfg(feature = "esp")]
 pub use communication::esp_now::{EspNowError, EspNowSender, EspNowReceiver};
+#[cfg(feature = "esp")]
 pub use core::{AppConfig, ConfigError, DataService, MeasuredData};
+#[cfg(feature = "esp")]
 pub use hardware::camera::CameraController;
+#[cfg(feature = "esp")]
 pub use hardware::led::status_led::{LedError, StatusLed};
+#[cfg(feature = "esp")]
 pub use hardware::{CameraPins, VoltageSensor};
 pub use mac_address::MacAddress;
+pub use units::{Celsius, Millivolts, Percent, Ppm};
+#[cfg(feature = "esp")]
 pub use power::{DeepSleep, DeepSleepError};
 
+// ホストビルド用: ハードウェア非依存のモジュールのみ（モジュールパスはファームウェアと同じ）
+#[cfg(not(feature = "esp"))]
+pub mod core {
+    pub mod build_info;
+    pub mod capture_policy;
+    pub mod config_staging;
+    pub mod config_validation;
+    pub mod data_prep;
+    pub mod debug_flags;
+    pub mod device_identity;
+    pub mod domain_logic;
+    pub mod image_hash;
+    pub mod image_pipeline;
+    pub mod image_retention;
+    pub mod lifecycle;
+    pub mod nvs_health;
+    pub mod panic_report;
+    pub mod soak;
+    pub mod thumbnail_ring;
+    pub mod timelapse;
+    pub mod trace;
+}
+
+#[cfg(not(feature = "esp"))]
+pub mod communication {
+    pub mod esp_now {
+        pub mod channel_hop;
+        pub mod chaos;
+        pub mod control_frame;
+        pub mod downlink_auth;
+        pub mod fec;
+        pub mod frame;
+        pub mod frame_codec;
+        pub mod pacing;
+        pub mod privacy;
+        pub mod probe;
+        pub mod radio;
+        pub mod relay;
+        pub mod retry_policy;
+        pub mod rtt;
+        pub mod telemetry;
+    }
+}
+
+#[cfg(not(feature = "esp"))]
+pub mod hardware {
+    pub mod camera {
+        pub mod fb_policy;
+        pub mod health;
+        pub mod ov2640_sequence;
+        pub mod ov3660_sequence;
+        pub mod recovery;
+        pub mod warmup;
+    }
+    pub mod led {
+        pub mod pattern;
+    }
+}
+
+#[cfg(not(feature = "esp"))]
+pub mod power {
+    pub mod sleep {
+        pub mod alignment;
+        pub mod drift;
+        pub mod platform;
+    }
+}
+
 /// ライブラリのバージョン情報
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
 
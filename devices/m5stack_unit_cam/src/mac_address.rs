@@ -1,7 +1,7 @@
 use std::fmt;
 
 /// MACアドレスを表す構造体
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct MacAddress(pub(crate) [u8; 6]);
 
 impl MacAddress {
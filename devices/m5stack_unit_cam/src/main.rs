@@ -1,10 +1,10 @@
 use esp_idf_svc::{
+    espnow::EspNow,
     eventloop::EspSystemEventLoop,
     hal::peripherals::Peripherals,
     hal::reset::ResetReason,
-    nvs::EspDefaultNvsPartition,
 };
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 // 内部モジュール
 mod communication;
@@ -12,22 +12,49 @@ mod core;
 mod hardware;
 mod mac_address;
 mod power;
+mod sys_wrappers;
+mod units;
 
 // 使用するモジュールのインポート
 use communication::{NetworkManager, esp_now::EspNowSender};
-use core::{AppController, AppConfig, DataService, MeasuredData, RtcManager};
+use communication::esp_now::{
+    clear_downlink_rejections, clear_probe_skips, last_session_loss_percent, plan_hop_wake_channel, record_probe_skip,
+    reject_backoff_seconds, select_fec_params, store_session_loss_percent, BroadcastConfig, ConfigUpdate, EspNowReceiver,
+    ProbeOutcome, RejectReason, RelayedTransfer, WindowRequest,
+};
+use core::{
+    AppController, AppConfig, ActiveRemoteConfig, BuildInfo, CaptureAlignment, CaptureSkipReason, CommandCounterStore,
+    DataService, DebugFlagStore, FileStore, MeasuredData, RemoteConfigStore, RtcManager, RuntimeDebug, SoakLog, SoakOutcome,
+    TraceContext, clear_clamped_sleep_request, clear_window_saved, load_device_id, nvs_usage, open_thumbnail_ring,
+    store_thumbnail, take_nvs_partition, voltage_skip_reason, SOAK_CYCLE_PAUSE_MS,
+};
 use core::config::CameraStandbyMode;
-use hardware::camera::{CameraController, M5UnitCamConfig};
+use core::config_staging::GatewayConfirmation;
+use core::debug_flags::split_debug_field;
+use hardware::camera::{
+    CameraController, CameraError, CameraRecovery, CaptureOutcome, M5UnitCamConfig, RecoveryStep,
+};
 use hardware::VoltageSensor;
 use hardware::led::StatusLed;
 use log::{error, info, warn};
-use power::sleep::{DeepSleep, EspIdfDeepSleep};
+use mac_address::MacAddress;
+use power::sleep::{DeepSleep, DeepSleepPlatform, EspIdfDeepSleep, SleepDriftStore};
+use sys_wrappers::esp::EspSys;
+use sys_wrappers::Entropy;
+use units::Percent;
+
+/// 設定を読み込めない場合のフォールバックスリープ時間（秒）
+const FALLBACK_SLEEP_SECONDS: u64 = 600;
+
+/// 試行中の設定を確定する前に、ゲートウェイからの確認を待つ時間の上限（ミリ秒）
+const TRIAL_CONFIRM_TIMEOUT_MS: u32 = 500;
 
 /// アプリケーションのメインエントリーポイント
 fn main() -> anyhow::Result<()> {
     // ESP-IDFの基本初期化
     esp_idf_svc::sys::link_patches();
     esp_idf_svc::log::EspLogger::initialize_default();
+    install_panic_hook();
 
     if cfg!(feature = "qemu-smoke") {
         info!("QEMU smoke mode enabled");
@@ -35,20 +62,83 @@ fn main() -> anyhow::Result<()> {
         return Ok(());
     }
 
+    // エラーで終了すると再起動を繰り返して電池を消耗するため、スリープしてから次のサイクルで再試行する
+    let result = run();
+    if let Err(e) = &result {
+        error!("サイクルを完了できませんでした: {:?}", e);
+        fallback_deep_sleep();
+    }
+    result
+}
+
+/// 撮影から送信、スリープまでの1サイクル
+fn run() -> anyhow::Result<()> {
+
     // 設定ファイル読み込み
     let app_config = Arc::new(AppConfig::load().map_err(|e| {
         error!("設定ファイルの読み込みに失敗しました: {}", e);
         anyhow::anyhow!("設定ファイルの読み込みエラー: {}", e)
     })?);
+    // ペリフェラルとシステムリソースの初期化
+    info!("ペリフェラルを初期化しています");
+    let peripherals = Peripherals::take().map_err(|e| anyhow::anyhow!("ペリフェラルを取得できません: {:?}", e))?;
+    let sysloop = EspSystemEventLoop::take()?;
+    // NVSが破損・満杯でも再書き込みせずに復旧できるよう、消去して初期化し直す（修復内容はHASHで報告）
+    let (nvs_partition, partition_recovery) = take_nvs_partition()?;
+    let mut nvs_recoveries: Vec<_> = partition_recovery.into_iter().collect();
+    // プロビジョニングで書き込んだデバイスID（基板を交換してMACアドレスが変わっても同じIDを報告する）
+    let device_id = load_device_id(nvs_partition.clone());
+
+    // リモート設定（ステージング中の設定は1回だけ試行し、ゲートウェイの確認で確定）
+    let mut remote_config_store = match RemoteConfigStore::open(nvs_partition.clone()) {
+        Ok(store) => Some(store),
+        Err(e) => {
+            warn!("リモート設定ストアを開けません（cfg.tomlの設定のみ使用）: {:?}", e);
+            None
+        }
+    };
+    if let Some(recovery) = remote_config_store.as_mut().and_then(|store| store.check_health(nvs_usage())) {
+        nvs_recoveries.push(recovery);
+    }
+    let active_remote_config = remote_config_store
+        .as_mut()
+        .map(|store| store.boot())
+        .unwrap_or_default();
+    let app_config = match &active_remote_config.config {
+        Some(remote) => Arc::new(app_config.with_remote_overrides(remote)),
+        None => app_config,
+    };
+    let applied_broadcast_id = active_remote_config.config.as_ref().and_then(|remote| remote.broadcast_id);
+
+    // ダウンリンクで有効にしたデバッグフラグ（起動ごとに残りサイクル数を減らし、0で自動解除）
+    let mut debug_flag_store = match DebugFlagStore::open(nvs_partition.clone()) {
+        Ok(store) => Some(store),
+        Err(e) => {
+            warn!("デバッグフラグストアを開けません（cfg.tomlの設定のみ使用）: {:?}", e);
+            None
+        }
+    };
+    let runtime_debug = debug_flag_store.as_mut().map(|store| store.begin_cycle());
+    let app_config = match runtime_debug {
+        Some((flags, _)) if flags.any() => Arc::new(app_config.with_debug_flags(flags)),
+        _ => app_config,
+    };
     if app_config.debug_mode {
         info!("debug mode enabled");
     }
+    EspNowReceiver::set_applied_broadcast_id(applied_broadcast_id.unwrap_or(0));
 
-    // ペリフェラルとシステムリソースの初期化
-    info!("ペリフェラルを初期化しています");
-    let peripherals = Peripherals::take().unwrap();
-    let sysloop = EspSystemEventLoop::take()?;
-    let nvs_partition = EspDefaultNvsPartition::take()?;
+    // ゲートウェイからのファイル転送（受信途中の状態を引き継ぎ、続きから受信する）
+    let mut file_store = match FileStore::open(nvs_partition.clone()) {
+        Ok(store) => Some(store),
+        Err(e) => {
+            warn!("ファイルストアを開けません（ファイル転送は今回の起動中のみ）: {:?}", e);
+            None
+        }
+    };
+    if let Some(progress) = file_store.as_ref().and_then(FileStore::load_progress) {
+        EspNowReceiver::resume_file(progress);
+    }
 
     // 必要なピンを先に抽出
     let pins = peripherals.pins;
@@ -56,11 +146,22 @@ fn main() -> anyhow::Result<()> {
     let voltage_pin = pins.gpio0;
 
     // ステータスLEDの初期化
-    let mut led = StatusLed::new(led_pin)?;
+    let mut led = StatusLed::new(led_pin, app_config.led_patterns)?;
     led.turn_off()?;
 
-    // スリープコントローラーの初期化
-    let deep_sleep_controller = DeepSleep::new(app_config.clone(), EspIdfDeepSleep);
+    // スリープコントローラーの初期化（NVSに保存したRTCドリフト推定値で補正）
+    let mut deep_sleep_controller = DeepSleep::new(app_config.clone(), EspIdfDeepSleep);
+    let mut sleep_drift_store = match SleepDriftStore::open(nvs_partition.clone()) {
+        Ok(drift_store) => {
+            deep_sleep_controller.set_drift_estimator(drift_store.estimator());
+            Some(drift_store)
+        }
+        Err(e) => {
+            warn!("スリープドリフト推定値を読み込めません（設定値の補正のみ使用）: {:?}", e);
+            None
+        }
+    };
+    let sleep_drift_ppm = sleep_drift_store.as_ref().and_then(|store| store.estimator().drift_ppm());
 
     // タイムゾーン設定
     let timezone = app_config
@@ -70,6 +171,8 @@ fn main() -> anyhow::Result<()> {
 
     // RTCタイム管理
     RtcManager::check_and_initialize_rtc(&timezone, &deep_sleep_controller)?;
+    // 前回のスリープで撮影境界を計画していれば、その時刻に揃えて撮影する
+    let mut capture_alignment = CaptureAlignment::from_previous_sleep();
     
     info!("設定されている受信先MAC: {}", app_config.receiver_mac);
     info!("設定されているスリープ時間: {}秒", app_config.sleep_duration_seconds);
@@ -80,15 +183,31 @@ fn main() -> anyhow::Result<()> {
     );
     let reset_reason = ResetReason::get();
     info!("リセット理由: {:?}", reset_reason);
+    // 起動理由は起動後最初のHASHフレームでのみ報告する
+    let mut lifecycle = Some(RtcManager::record_boot());
+    let build_info = BuildInfo::current();
+    let config_hash = app_config.config_hash();
+    info!(
+        "ファームウェア: {} (built {}, features [{}]), 設定ハッシュ: {:08x}",
+        build_info.git_hash, build_info.build_timestamp, build_info.features, config_hash
+    );
+    let mut build_metadata = Some(build_info.metadata_fields(config_hash));
 
     let mut adc2 = peripherals.adc2;
     let mut gpio0 = voltage_pin;
-    let mut last_valid_voltage_percent: Option<u8> = None;
+    let mut last_valid_voltage_percent: Option<Percent> = None;
 
     // 起動直後はOV2640のSCCB応答が不安定な場合があるため待機する
     info!("カメラ電源安定化待ち: 1000ms");
     esp_idf_svc::hal::delay::FreeRtos::delay_ms(1000);
 
+    let camera_config = M5UnitCamConfig {
+        frame_size: M5UnitCamConfig::from_string(&app_config.frame_size),
+        jpeg_quality: app_config.jpeg_quality,
+        fb_placement: app_config.camera_fb_placement,
+        fb_count: app_config.camera_fb_count as usize,
+        pwdn_gpio: app_config.camera_pwdn_gpio,
+    };
     let camera = CameraController::new(
         pins.gpio15,
         pins.gpio27,
@@ -105,11 +224,29 @@ fn main() -> anyhow::Result<()> {
         pins.gpio21,
         pins.gpio25,
         pins.gpio23,
-        M5UnitCamConfig::default(),
+        camera_config.clone(),
     );
+    // SCCBエラーによる初期化失敗は、カメラを初期化し直して復旧を試みる（結果は最初のHASHフレームで報告する）
+    let new_camera_recovery =
+        || CameraRecovery::new(app_config.camera_reinit_max_attempts, app_config.camera_pwdn_gpio.is_some());
+    let mut init_recovery = new_camera_recovery();
     let camera = match camera {
+        Err(e) => match e.sccb_error_code() {
+            Some(code) => {
+                error!("カメラ初期化でSCCBエラーが発生しました: {:?}", e);
+                recover_camera(None, code, &camera_config, &mut init_recovery).ok_or(e)
+            }
+            None => Err(e),
+        },
+        ok => ok,
+    };
+    let mut init_recovery = Some(init_recovery);
+    // フレームバッファ確保失敗はHASHフレームのテレメトリで報告する
+    let mut fb_failure = None;
+    let mut camera = match camera {
         Ok(camera) => Some(camera),
         Err(e) => {
+            fb_failure = e.frame_buffer_failure();
             error!(
                 "カメラ初期化失敗。再書き込み直後は Unit Cam の電源を一度抜き差しして再起動してください: {:?}",
                 e
@@ -127,7 +264,7 @@ fn main() -> anyhow::Result<()> {
         VoltageSensor::measure_voltage_percentage(adc2, gpio0)?;
     adc2 = returned_adc2;
     gpio0 = returned_gpio0;
-    if initial_voltage_percent < crate::core::INVALID_VOLTAGE_PERCENT {
+    if initial_voltage_percent.is_valid() {
         last_valid_voltage_percent = Some(initial_voltage_percent);
     }
 
@@ -136,6 +273,7 @@ fn main() -> anyhow::Result<()> {
         &sysloop,
         &nvs_partition,
         app_config.wifi_tx_power_dbm,
+        app_config.esp_now_phy_rate,
     ).map_err(|e| {
         if let Err(sleep_err) = AppController::fallback_sleep(
             &deep_sleep_controller,
@@ -147,6 +285,33 @@ fn main() -> anyhow::Result<()> {
         e
     })?;
 
+    // ダウンリンク認証（受理済みカウンタはNVSに保存し、リプレイされたコマンドを拒否する）
+    let mut command_counter_store = None;
+    if let Some(key) = &app_config.downlink_auth_key {
+        let own_mac = wifi_connection.wifi().sta_netif().get_mac()?;
+        let (last_accepted, key_record) = match CommandCounterStore::open(nvs_partition.clone()) {
+            Ok(store) => {
+                let store = command_counter_store.insert(store);
+                (store.last_accepted(), store.key_record())
+            }
+            Err(e) => {
+                // 認証は有効のまま、今回の起動中に受理したカウンタのみでリプレイを判定する
+                warn!("コマンドカウンタを読み込めません: {:?}", e);
+                (0, None)
+            }
+        };
+        EspNowReceiver::enable_downlink_auth(key.clone(), own_mac, last_accepted, key_record.as_deref());
+    }
+
+    // ソークテストではディープスリープせずにサイクルを繰り返し、結果を累計して次の転送で報告する
+    let mut soak_log = SoakLog::new();
+    if cfg!(feature = "soak-test") {
+        info!("ソークテストモード: サイクル間の待機 {}ms", SOAK_CYCLE_PAUSE_MS);
+    }
+
+    // 直近の撮影のサムネイル（ゲートウェイのサムネイル要求に応えて送る）
+    let mut thumbnail_ring = open_thumbnail_ring(app_config.thumbnail_ring_len);
+
     loop {
         // ADC電圧測定
         let (measured_voltage_percent, returned_adc2, returned_gpio0) =
@@ -154,12 +319,13 @@ fn main() -> anyhow::Result<()> {
         adc2 = returned_adc2;
         gpio0 = returned_gpio0;
 
-        let voltage_percent = if measured_voltage_percent < crate::core::INVALID_VOLTAGE_PERCENT {
+        let voltage_percent = if measured_voltage_percent.is_valid() {
             last_valid_voltage_percent = Some(measured_voltage_percent);
             measured_voltage_percent
         } else if let Some(last_good) = last_valid_voltage_percent {
             warn!(
-                "ADC2読み取りが無効値(255%)のため、直近の有効値 {}% を使用します（WiFi競合対策）",
+                "ADC2読み取りが無効値({})のため、直近の有効値 {} を使用します（WiFi競合対策）",
+                measured_voltage_percent,
                 last_good
             );
             last_good
@@ -167,7 +333,12 @@ fn main() -> anyhow::Result<()> {
             measured_voltage_percent
         };
 
-        // 画像キャプチャ（短いリトライ付き）
+        // サイクルのトレースID（撮影からホストでの保存までを1つのトレースとして追跡）
+        let mut trace = TraceContext::from_entropy(EspSys.random_u32(), EspSys.random_u32());
+        let capture_started = std::time::Instant::now();
+
+        // 画像キャプチャ（短いリトライ付き。SCCBエラーならカメラを初期化し直してから再試行する）
+        let mut camera_recovery = init_recovery.take().unwrap_or_else(new_camera_recovery);
         let mut capture_result = None;
         let mut last_capture_err = None;
         for attempt in 1..=3 {
@@ -176,13 +347,32 @@ fn main() -> anyhow::Result<()> {
                 camera.as_ref(),
                 &app_config,
                 &mut led,
+                capture_alignment.as_mut(),
             ) {
                 Ok(data) => {
+                    if data.is_some() {
+                        camera_recovery.mark_recovered();
+                    }
                     capture_result = Some(data);
                     break;
                 }
                 Err(e) => {
                     error!("カメラ処理に失敗しました (attempt {}/3): {:?}", attempt, e);
+                    let camera_error = e.downcast_ref::<CameraError>();
+                    if let Some(failure) = camera_error.and_then(CameraError::frame_buffer_failure) {
+                        fb_failure = Some(failure);
+                    }
+                    // フレーム取得失敗はSCCB通信が途切れている場合があるため、センサーの応答を確認する
+                    let sccb_error_code = camera_error.and_then(|error| match error {
+                        CameraError::CaptureFailed(_) => camera
+                            .as_ref()
+                            .and_then(|camera| camera.check_sccb().err())
+                            .and_then(|error| error.sccb_error_code()),
+                        other => other.sccb_error_code(),
+                    });
+                    if let Some(code) = sccb_error_code {
+                        camera = recover_camera(camera.take(), code, &camera_config, &mut camera_recovery);
+                    }
                     last_capture_err = Some(e);
                     if attempt < 3 {
                         esp_idf_svc::hal::delay::FreeRtos::delay_ms(250);
@@ -191,6 +381,36 @@ fn main() -> anyhow::Result<()> {
             }
         }
 
+        // カメラの健全性（取得時間の移動平均と連続失敗回数）。電圧不足で撮影しなかったサイクルは数えない
+        let capture_outcome = match &capture_result {
+            Some(Some(_)) => camera
+                .as_ref()
+                .and_then(CameraController::last_fb_get_ms)
+                .map(|fb_get_ms| CaptureOutcome::Captured { fb_get_ms }),
+            Some(None) => None,
+            None => Some(CaptureOutcome::Failed),
+        };
+        let camera_health = capture_outcome.map(RtcManager::record_camera_outcome);
+        if let Some(health) = camera_health.filter(|health| health.is_degraded(app_config.camera_degraded_streak)) {
+            warn!(
+                "CameraDegraded: 撮影が{}回連続で失敗しています（リボンケーブルの接続を確認してください）",
+                health.fail_streak()
+            );
+        }
+
+        // 撮影しなかった理由（ホストでデバイスごとに集計できるようHASHフレームで報告する）
+        let capture_skip = match &capture_result {
+            Some(Some(_)) => None,
+            Some(None) => voltage_skip_reason(
+                voltage_percent.value(),
+                app_config.force_camera_test,
+                app_config.bypass_voltage_threshold,
+            ),
+            None if camera.is_none() => Some(CaptureSkipReason::NoCamera),
+            None => Some(CaptureSkipReason::CameraFailed),
+        };
+
+        let captured = capture_result.is_some();
         let image_data = match capture_result {
             Some(data) => data,
             None => {
@@ -204,8 +424,49 @@ fn main() -> anyhow::Result<()> {
                 None
             }
         };
-        info!("データ送信タスクを開始します");
-        let measured_data = MeasuredData::new(voltage_percent, image_data);
+        if image_data.is_some() {
+            trace.capture_ms = Some(capture_started.elapsed().as_millis().min(u32::MAX as u128) as u32);
+        }
+        if let (Some(ring), Some(image)) = (thumbnail_ring.as_mut(), image_data.as_deref()) {
+            // 時刻が未同期なら撮影時刻は0（ホストでは受信時刻で代用する）
+            let now_unix_us = RtcManager::now_unix_us();
+            let captured_unix = if power::sleep::alignment::is_clock_valid(now_unix_us) {
+                (now_unix_us / 1_000_000).min(u32::MAX as u64) as u32
+            } else {
+                0
+            };
+            match store_thumbnail(ring, image, captured_unix) {
+                Ok(seq) => info!("サムネイルを保持しました（通し番号 {}）", seq),
+                Err(e) => warn!("サムネイルを保持できません: {}", e),
+            }
+        }
+        info!("データ送信タスクを開始します (trace={:016x})", trace.trace_id);
+        let mut measured_data = MeasuredData::new(voltage_percent, image_data);
+        measured_data.capture_skip = capture_skip;
+        measured_data.trace = Some(trace);
+        measured_data.lifecycle = lifecycle.take();
+        measured_data.build_info = build_metadata.take();
+        RtcManager::record_uptime();
+        if measured_data.image_data.is_some() {
+            measured_data.captured_at = Some(capture_started);
+            measured_data.sequence = app_config.timelapse.as_ref().map(RtcManager::next_sequence_frame);
+        }
+        measured_data.device_id = device_id.clone();
+        measured_data.sleep_drift_ppm = sleep_drift_ppm;
+        measured_data.config_rollback = active_remote_config.rolled_back;
+        measured_data.nvs_recoveries = std::mem::take(&mut nvs_recoveries);
+        measured_data.broadcast_id = applied_broadcast_id;
+        measured_data.camera_settings = Some((app_config.jpeg_quality, app_config.frame_size.to_ascii_uppercase()));
+        measured_data.runtime_debug = runtime_debug;
+        measured_data.fb_failure = fb_failure;
+        measured_data.camera_recovery = Some(camera_recovery);
+        measured_data.camera_health =
+            camera_health.map(|health| health.metadata_fields(app_config.camera_degraded_streak));
+        measured_data.align_error_us = capture_alignment.and_then(|alignment| alignment.error_us());
+        measured_data.retain_for_resend = AppController::waits_for_server_command(&app_config);
+        if cfg!(feature = "soak-test") {
+            measured_data.soak = Some(soak_log.metadata_fields());
+        }
 
         // ESP-NOWはサイクルごとに再初期化して内部TXキューをクリーンに保つ
         info!("ESP-NOWセンダーを初期化中...");
@@ -221,7 +482,7 @@ fn main() -> anyhow::Result<()> {
             anyhow::anyhow!("ESP-NOW初期化に失敗: {:?}", e)
         })?;
 
-        let esp_now_sender = EspNowSender::new(esp_now_arc, app_config.receiver_mac.clone()).map_err(|e| {
+        let mut esp_now_sender = EspNowSender::new(Arc::clone(&esp_now_arc), app_config.receiver_mac.clone()).map_err(|e| {
             log::error!("ESP-NOWセンダー初期化に失敗: {:?}", e);
             if let Err(sleep_err) = AppController::fallback_sleep(
                 &deep_sleep_controller,
@@ -233,19 +494,196 @@ fn main() -> anyhow::Result<()> {
             anyhow::anyhow!("ESP-NOWセンダー初期化に失敗: {:?}", e)
         })?;
 
-        if let Err(e) = DataService::transmit_data(
-            &app_config,
-            &esp_now_sender,
-            &mut led,
-            measured_data,
-        ) {
-            error!("データ送信タスクでエラーが発生しました: {:?}", e);
-        }
+        // ゲートウェイが報告した前回の転送の損失率からFECパラメータを決定
+        let previous_loss_percent = last_session_loss_percent();
+        let fec_params = select_fec_params(
+            app_config.esp_now_fec_group_size,
+            app_config.esp_now_fec_max_parity,
+            previous_loss_percent,
+        );
+        info!("前回の転送の損失率: {}% / FEC: {:?}", previous_loss_percent, fec_params);
+        esp_now_sender.set_fec_params(fec_params);
+        esp_now_sender.set_pacing_params(app_config.esp_now_pacing);
+        esp_now_sender.set_privacy_params(app_config.esp_now_privacy);
+        esp_now_sender.set_channel_hop(app_config.esp_now_channel_hop);
+        esp_now_sender.set_command_window(AppController::waits_for_server_command(&app_config));
+        #[cfg(feature = "chaos")]
+        esp_now_sender.set_chaos_params(app_config.esp_now_chaos);
 
-        led.turn_off()?;
+        // 中継: 圏外の子機のフレームを受信窓の間溜める
+        let relayed_transfer = app_config.relay_child_mac.as_ref().and_then(|child| {
+            let relay_mac = wifi_connection.wifi().sta_netif().get_mac().ok()?;
+            receive_relay_transfer(&esp_now_arc, &esp_now_receiver, child, relay_mac, &app_config)
+        });
 
-        // スリープ管理（サーバーからのコマンド待機）
-        let sleep_duration_sec = AppController::resolve_sleep_duration(&esp_now_receiver, &app_config)?;
+        // 転送前にゲートウェイの疎通を確認し、応答がなければ転送せずにスリープする
+        let probe_outcome = if app_config.esp_now_probe_attempts > 0 {
+            let outcome = esp_now_sender.probe_gateway(
+                &esp_now_receiver,
+                app_config.esp_now_probe_attempts,
+                app_config.esp_now_probe_timeout_ms,
+                app_config.esp_now_defer_max_wait_ms,
+            );
+            measured_data.probe = Some(outcome);
+            Some(outcome)
+        } else {
+            None
+        };
+        let gateway_reachable = probe_outcome.is_none_or(|outcome| outcome.is_reachable());
+
+        let mut transmitted = false;
+        let sleep_duration_sec = if gateway_reachable {
+            // 自分の転送の後はスリープコマンドを待つため、中継する転送を先に送る
+            if let Some(transfer) = relayed_transfer {
+                if let Err(e) = esp_now_sender.send_relayed_transfer(
+                    transfer,
+                    app_config.esp_now_chunk_size as usize,
+                    app_config.esp_now_chunk_delay_ms,
+                ) {
+                    error!("中継転送に失敗しました: {:?}", e);
+                }
+            }
+            let mut retained_image = None;
+            let mut transfer_reject = None;
+            EspNowReceiver::clear_hash_ack();
+            transmitted = match DataService::transmit_data(&app_config, &esp_now_sender, &mut led, measured_data) {
+                Ok(retained) => {
+                    retained_image = retained;
+                    clear_probe_skips();
+                    clear_downlink_rejections();
+                    clear_clamped_sleep_request();
+                    clear_window_saved();
+                    if EspNowReceiver::clear_completed_file() {
+                        if let Some(store) = file_store.as_mut() {
+                            store.clear_progress();
+                        }
+                    }
+                    true
+                }
+                Err(e) => {
+                    error!("データ送信タスクでエラーが発生しました: {:?}", e);
+                    transfer_reject = EspNowReceiver::take_reject();
+                    false
+                }
+            };
+
+            // エラー・成功表示の点滅はスリープコマンド待機中に最後まで表示する
+            led.clear()?;
+
+            // スリープ管理（サーバーからのコマンド待機）。待機中の再送要求には保持中の画像を送り直し、
+            // サムネイル要求には保持中のサムネイルを送る
+            // 転送中に拒否された場合はHASHフレームを送っていないため、待たずに再試行までの時間だけスリープする
+            let sleep_duration_sec = if let Some(reject) = transfer_reject {
+                let backoff = reject_backoff_seconds(app_config.sleep_duration_seconds, reject.retry_after_ms);
+                warn!("ゲートウェイに拒否されたため {} 秒スリープします（理由 {}）", backoff, reject.reason.as_str());
+                backoff
+            } else {
+                AppController::resolve_sleep_duration(&esp_now_receiver, &app_config, |request| match request {
+                    WindowRequest::ResendLast => {
+                        let Some(retained) = retained_image.as_mut() else {
+                            warn!("画像の再送要求を受信しましたが、保持中の画像がありません");
+                            return;
+                        };
+                        if let Err(e) = DataService::resend_retained_image(
+                            &app_config,
+                            &esp_now_sender,
+                            &mut led,
+                            voltage_percent,
+                            retained,
+                        ) {
+                            error!("画像の再送に失敗しました: {:?}", e);
+                        }
+                    }
+                    WindowRequest::SendThumbnails => {
+                        let Some(ring) = thumbnail_ring.as_ref() else {
+                            warn!("サムネイル要求を受信しましたが、サムネイルを保持していません（thumbnail_ring_len=0）");
+                            return;
+                        };
+                        if let Err(e) =
+                            DataService::send_thumbnails(&app_config, &esp_now_sender, &mut led, voltage_percent, ring)
+                        {
+                            error!("サムネイルの送信に失敗しました: {:?}", e);
+                        }
+                    }
+                })?
+            };
+            // 損失率はコマンド待機中に届いたHASHフレームへの応答から取り出す（届かなければ前回の値を使い続ける）
+            if let Some(loss_percent) = EspNowReceiver::take_reported_loss_percent() {
+                store_session_loss_percent(loss_percent);
+            }
+            // 応答に付いたゲートウェイの時計で前回のスリープの起床誤差を計測し、今回のスリープの補正に反映する
+            if let (Some(sample), Some(store)) = (EspNowReceiver::take_gateway_clock(), sleep_drift_store.as_mut()) {
+                store.record_gateway_clock(sample);
+                deep_sleep_controller.set_drift_estimator(store.estimator());
+            }
+            if let Some(store) = command_counter_store.as_mut() {
+                store.record_accepted(EspNowReceiver::last_accepted_counter());
+                if let Some(record) = EspNowReceiver::take_key_record() {
+                    store.record_keys(&record);
+                }
+            }
+            if let (Some(store), Some(progress)) = (file_store.as_mut(), EspNowReceiver::take_file_progress()) {
+                store.save_progress(&progress);
+            }
+            // 送信の成功は送信キューへの投入までしか示さないため、試行設定はゲートウェイの確認が届いた場合のみ確定する
+            let confirmation = if active_remote_config.is_trial && transmitted {
+                EspNowReceiver::wait_for_confirmation(TRIAL_CONFIRM_TIMEOUT_MS)
+            } else {
+                GatewayConfirmation::default()
+            };
+            let trial_committed = active_remote_config.is_trial && confirmation.confirms_trial(transmitted);
+            if trial_committed {
+                commit_remote_config(remote_config_store.as_mut(), &active_remote_config);
+            } else if active_remote_config.is_trial && transmitted {
+                warn!("ゲートウェイからの確認がないため試行中の設定を確定しません（次回起動時にロールバック）");
+            }
+            // 確定できなかった試行設定の上には重ねない（次回起動でロールバックさせ、配信は再送を待つ）
+            if trial_committed || !active_remote_config.is_trial {
+                stage_received_config(
+                    remote_config_store.as_mut(),
+                    debug_flag_store.as_mut(),
+                    &active_remote_config,
+                    EspNowReceiver::take_broadcast_config(),
+                    EspNowReceiver::take_config_update(),
+                );
+            }
+            sleep_duration_sec
+        } else {
+            let skipped = record_probe_skip();
+            let reason = match probe_outcome {
+                Some(ProbeOutcome::Deferred { .. }) => "ゲートウェイが混雑している",
+                Some(ProbeOutcome::Rejected { reason: RejectReason::UnknownDevice, .. }) => {
+                    "ゲートウェイにこのデバイスが登録されていない"
+                }
+                Some(ProbeOutcome::Rejected { .. }) => "ゲートウェイに拒否された",
+                _ => "ゲートウェイから応答がない",
+            };
+            // Unit Cam には画像を保持するストレージがないため、今回の画像は破棄する
+            warn!(
+                "{}ため転送を見送ります（連続 {} 回、画像は保存先がないため破棄）",
+                reason, skipped
+            );
+            if relayed_transfer.is_some() {
+                warn!("中継した子機の画像も破棄します");
+            }
+            led.turn_off()?;
+            match probe_outcome {
+                // 再試行までの時間より前に起床しても再び拒否されるため、それまでスリープを延ばす
+                Some(ProbeOutcome::Rejected { retry_after_ms, .. }) => {
+                    reject_backoff_seconds(app_config.sleep_duration_seconds, retry_after_ms)
+                }
+                _ => app_config.sleep_duration_seconds,
+            }
+        };
+
+        if cfg!(feature = "soak-test") {
+            let free_heap = sys_wrappers::esp::free_heap();
+            soak_log.record(SoakOutcome::classify(captured, gateway_reachable, transmitted), free_heap);
+            info!("ソークテスト: {} (空きヒープ {} bytes)", soak_log.summary(), free_heap);
+            led.turn_off()?;
+            esp_idf_svc::hal::delay::FreeRtos::delay_ms(SOAK_CYCLE_PAUSE_MS);
+            continue;
+        }
 
         // 省電力要件: DeepSleep前にSCCBスタンバイへ移行する（A/Bテスト対応）。
         if let Some(cam) = camera.as_ref() {
@@ -274,9 +712,191 @@ fn main() -> anyhow::Result<()> {
             }
         }
 
-        deep_sleep_controller.sleep_for_duration(sleep_duration_sec)?;
+        if app_config.esp_now_channel_hop {
+            plan_hop_wake_channel(sleep_duration_sec);
+        }
+        RtcManager::sleep_until_next_capture(
+            &deep_sleep_controller,
+            sleep_duration_sec,
+            app_config.capture_alignment,
+        )?;
         break;
     }
 
     Ok(())
 }
+
+/// SCCBエラーのあとカメラを初期化し直します（失敗が続けば上限回数まで繰り返す）
+///
+/// 上限に達した場合は渡されたカメラをそのまま返し、再初期化に失敗した場合はNoneを返します。
+fn recover_camera(
+    mut camera: Option<CameraController>,
+    mut error_code: i32,
+    config: &M5UnitCamConfig,
+    recovery: &mut CameraRecovery,
+) -> Option<CameraController> {
+    loop {
+        let power_cycle = match recovery.next_step(error_code) {
+            RecoveryStep::Reinit => false,
+            RecoveryStep::PowerCycleAndReinit => true,
+            RecoveryStep::GiveUp => {
+                warn!("カメラの再初期化が上限（{}回）に達しました", recovery.attempts());
+                return camera;
+            }
+        };
+        let result = match camera.take() {
+            Some(camera) => camera.reinit(power_cycle),
+            None => CameraController::reopen(config.clone(), power_cycle),
+        };
+        match result {
+            Ok(camera) => {
+                info!("カメラを再初期化しました（{}回目）", recovery.attempts());
+                return Some(camera);
+            }
+            Err(e) => {
+                error!("カメラの再初期化に失敗しました（{}回目）: {:?}", recovery.attempts(), e);
+                error_code = e.sccb_error_code()?;
+            }
+        }
+    }
+}
+
+/// 子機のフレームを受信窓の間溜め、そろった転送を返します
+///
+/// そろった場合は子機へ自分のスリープ時間を返し、次の起床を揃えます。
+fn receive_relay_transfer(
+    esp_now: &Arc<Mutex<EspNow<'static>>>,
+    receiver: &EspNowReceiver,
+    child: &MacAddress,
+    relay_mac: [u8; 6],
+    config: &AppConfig,
+) -> Option<RelayedTransfer> {
+    info!("中継: 子機 {} のフレームを最大 {}ms 待機します", child, config.relay_window_ms);
+    let buffer = receiver.receive_relay(child.0, config.relay_window_ms)?;
+    if !buffer.is_complete() {
+        warn!(
+            "中継: 子機の転送がそろいませんでした（{} bytes、欠番 {}）",
+            buffer.image_bytes(),
+            buffer.missing_frames()
+        );
+        return None;
+    }
+    let sleep_seconds = config.sleep_duration_seconds.min(u32::MAX as u64) as u32;
+    match EspNowSender::new(Arc::clone(esp_now), child.clone()) {
+        Ok(child_sender) => {
+            if let Err(e) = child_sender.send(&sleep_seconds.to_le_bytes(), 1000) {
+                warn!("中継: 子機へのスリープコマンド送信に失敗しました: {:?}", e);
+            }
+        }
+        Err(e) => warn!("中継: 子機をピアに追加できません: {:?}", e),
+    }
+    let transfer = buffer.into_transfer(relay_mac);
+    if transfer.is_none() {
+        warn!("中継: 中継の段数が上限に達しているため中継しません");
+    }
+    transfer
+}
+
+/// 試行中のリモート設定を確定します（ゲートウェイが送信を確認した場合のみ呼び出す）
+fn commit_remote_config(store: Option<&mut RemoteConfigStore>, active: &ActiveRemoteConfig) {
+    if let Some(store) = store {
+        if let Err(e) = store.commit_trial(active) {
+            error!("リモート設定の確定に失敗しました（次回起動時にロールバック）: {:?}", e);
+        }
+    }
+}
+
+/// 受信した一斉配信・設定更新を現在の設定に重ねてステージングします（次回起動時に試行）
+///
+/// 両方を受信した場合は一斉配信の上にデバイスごとの設定更新を重ねます。
+/// 設定更新の `DEBUG` 項目はステージングせず、デバッグフラグとして保存します（次回起動から有効）。
+fn stage_received_config(
+    store: Option<&mut RemoteConfigStore>,
+    debug_store: Option<&mut DebugFlagStore>,
+    active: &ActiveRemoteConfig,
+    broadcast: Option<BroadcastConfig>,
+    update: Option<ConfigUpdate>,
+) {
+    let update = update.and_then(|update| {
+        let (debug, rest) = split_debug_field(&update.config);
+        if let Some(value) = debug {
+            save_runtime_debug(debug_store, value);
+        }
+        (!rest.is_empty()).then_some(ConfigUpdate { config: rest, ..update })
+    });
+    if broadcast.is_none() && update.is_none() {
+        return;
+    }
+    let Some(store) = store else {
+        warn!("リモート設定ストアがないため受信した設定を適用できません");
+        return;
+    };
+    let mut config = active.config.clone().unwrap_or_default();
+    if let Some(broadcast) = broadcast {
+        match config.with_broadcast(broadcast.config_id, &broadcast.config) {
+            Ok(next) => config = next,
+            Err(e) => warn!("一斉配信された設定が不正なため適用しません（設定ID {}）: {}", broadcast.config_id, e),
+        }
+    }
+    if let Some(update) = update {
+        match config.with_update(&update.config) {
+            Ok(next) => config = next,
+            Err(e) => warn!("設定更新が不正なため適用しません（{}）: {}", update.config, e),
+        }
+    }
+    if let Err(e) = store.stage(&config) {
+        error!("受信した設定のステージングに失敗しました: {:?}", e);
+    }
+}
+
+/// 設定更新で受信したデバッグフラグを保存します
+fn save_runtime_debug(store: Option<&mut DebugFlagStore>, value: &str) {
+    let runtime = match RuntimeDebug::parse(value) {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            warn!("デバッグフラグの指定が不正なため適用しません（{}）: {}", value, e);
+            return;
+        }
+    };
+    let Some(store) = store else {
+        warn!("デバッグフラグストアがないため受信したデバッグフラグを適用できません");
+        return;
+    };
+    info!(
+        "デバッグフラグを受信: {}（次回起動から {} サイクル）",
+        runtime.flags.names(),
+        runtime.remaining_cycles
+    );
+    store.save(runtime);
+}
+
+/// パニックの内容をRTCメモリに記録するパニックハンドラーを設定します
+///
+/// 記録は次回起動時のライフサイクル情報で報告します。表示は既定のハンドラーに任せます。
+fn install_panic_hook() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let payload = info.payload();
+        let message = payload
+            .downcast_ref::<&str>()
+            .copied()
+            .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+            .unwrap_or("non-string panic payload");
+        let location = info
+            .location()
+            .map(|location| format!("{}:{}", location.file(), location.line()))
+            .unwrap_or_default();
+        RtcManager::record_panic(message, &location);
+        default_hook(info);
+    }));
+}
+
+/// 設定値（読み込めなければ既定値）の間隔でDeep sleepします
+fn fallback_deep_sleep() {
+    let sleep_seconds = AppConfig::load()
+        .map(|config| config.sleep_duration_seconds)
+        .unwrap_or(FALLBACK_SLEEP_SECONDS);
+    warn!("フォールバックスリープ: {}秒後に再試行します", sleep_seconds);
+    RtcManager::record_uptime();
+    EspIdfDeepSleep.deep_sleep(sleep_seconds.saturating_mul(1_000_000));
+}
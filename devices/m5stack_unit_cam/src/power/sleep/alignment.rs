@@ -0,0 +1,87 @@
+//! 壁時計の境界に揃えた撮影スケジューリング
+//!
+//! タイムラプスの合成のため、複数デバイスの撮影時刻を「毎分00秒」などの境界に揃えます。
+//! Deep sleep は境界より `early_wake_us` だけ早く起床するよう計画し、
+//! 起床後は撮影直前まで待機して境界ちょうどに撮影します。
+
+/// 時刻同期済みとみなす最小のUNIX時刻（2024-01-01T00:00:00Z）
+pub const MIN_VALID_UNIX_SECONDS: u64 = 1_704_067_200;
+
+/// 起床後に境界まで待機する上限（マイクロ秒）。超える場合は時刻の飛びとみなして待たない
+pub const MAX_ALIGN_WAIT_US: u64 = 10_000_000;
+
+/// 撮影時刻の整列設定
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AlignmentSettings {
+    /// 整列する境界の間隔（マイクロ秒、例: 60秒なら毎分00秒）
+    pub interval_us: u64,
+    /// 境界より早く起床する余裕（マイクロ秒）。起動・カメラ準備の時間を見込む
+    pub early_wake_us: u64,
+}
+
+impl AlignmentSettings {
+    /// 設定値から作成します（間隔0は整列なし）
+    pub fn from_config(interval_seconds: u32, early_wake_ms: u32) -> Option<Self> {
+        (interval_seconds > 0).then(|| Self {
+            interval_us: interval_seconds as u64 * 1_000_000,
+            early_wake_us: early_wake_ms as u64 * 1_000,
+        })
+    }
+}
+
+/// 整列したスリープ計画
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AlignedSleep {
+    /// 要求するスリープ時間（マイクロ秒）
+    pub sleep_us: u64,
+    /// 撮影予定の境界時刻（UNIX時刻、マイクロ秒）
+    pub target_unix_us: u64,
+}
+
+/// 時刻同期済みのUNIX時刻かどうか
+pub fn is_clock_valid(now_unix_us: u64) -> bool {
+    now_unix_us / 1_000_000 >= MIN_VALID_UNIX_SECONDS
+}
+
+/// `at_or_after_us` 以降で最初の境界時刻
+pub fn next_boundary_us(at_or_after_us: u64, interval_us: u64) -> u64 {
+    if interval_us == 0 {
+        return at_or_after_us;
+    }
+    at_or_after_us.div_ceil(interval_us).saturating_mul(interval_us)
+}
+
+/// 要求スリープ時間後の最初の境界に撮影できるようスリープ時間を計画します
+///
+/// 境界までの余裕が `early_wake_us` に満たない場合は次の境界を使用します。
+///
+/// # 戻り値
+/// 時刻が未同期の場合はNone（整列せず通常のスリープを行う）
+pub fn plan_aligned_sleep(
+    now_unix_us: u64,
+    requested_sleep_us: u64,
+    settings: AlignmentSettings,
+) -> Option<AlignedSleep> {
+    if !is_clock_valid(now_unix_us) || settings.interval_us == 0 {
+        return None;
+    }
+    let earliest_wake = now_unix_us.saturating_add(requested_sleep_us);
+    let mut target = next_boundary_us(earliest_wake.saturating_add(settings.early_wake_us), settings.interval_us);
+    if target.saturating_sub(settings.early_wake_us) <= now_unix_us {
+        target = target.saturating_add(settings.interval_us);
+    }
+    Some(AlignedSleep {
+        sleep_us: target - settings.early_wake_us - now_unix_us,
+        target_unix_us: target,
+    })
+}
+
+/// 起床後、境界時刻までの残り待機時間（過ぎていれば0）
+pub fn remaining_wait_us(now_unix_us: u64, target_unix_us: u64) -> u64 {
+    target_unix_us.saturating_sub(now_unix_us)
+}
+
+/// 整列誤差（撮影時刻 − 境界時刻、マイクロ秒）
+pub fn alignment_error_us(target_unix_us: u64, captured_unix_us: u64) -> i64 {
+    captured_unix_us as i64 - target_unix_us as i64
+}
@@ -2,39 +2,46 @@ use crate::core::config::AppConfig;
 use log::info;
 use std::sync::Arc;
 
+use super::drift::{compensated_sleep_micros, DriftEstimator};
+use super::drift_store::arm_wake_reference;
+use super::platform::DeepSleepPlatform;
+
 #[derive(Debug, thiserror::Error)]
 pub enum DeepSleepError {
     #[error("Invalid sleep duration: {0}")]
     InvalidDuration(String),
 }
 
-/// Platform-agnostic deep-sleep abstraction.
-pub trait DeepSleepPlatform {
-    /// Enter deep sleep for the specified duration in microseconds.
-    fn deep_sleep(&self, duration_us: u64);
-}
-
 /// ESP-IDF specific deep sleep implementation.
 pub struct EspIdfDeepSleep;
 
 impl DeepSleepPlatform for EspIdfDeepSleep {
     fn deep_sleep(&self, duration_us: u64) {
         info!("Entering deep sleep for {} microseconds", duration_us);
-        unsafe {
-            esp_idf_svc::sys::esp_deep_sleep(duration_us);
-        }
+        crate::sys_wrappers::esp::deep_sleep(duration_us);
     }
 }
 
 /// Deep sleep controller with platform abstraction.
 pub struct DeepSleep<P: DeepSleepPlatform> {
     platform: P,
+    compensation_micros: i64,
+    drift: DriftEstimator,
 }
 
 impl<P: DeepSleepPlatform> DeepSleep<P> {
     /// Create a new `DeepSleep` controller.
-    pub fn new(_config: Arc<AppConfig>, platform: P) -> Self {
-        DeepSleep { platform }
+    pub fn new(config: Arc<AppConfig>, platform: P) -> Self {
+        DeepSleep {
+            platform,
+            compensation_micros: config.sleep_compensation_micros,
+            drift: DriftEstimator::default(),
+        }
+    }
+
+    /// Use a learned RTC drift estimate in addition to the configured compensation.
+    pub fn set_drift_estimator(&mut self, drift: DriftEstimator) {
+        self.drift = drift;
     }
 
     /// Sleep for a specified duration in seconds.
@@ -49,8 +56,23 @@ impl<P: DeepSleepPlatform> DeepSleep<P> {
             .checked_mul(1_000_000)
             .ok_or_else(|| DeepSleepError::InvalidDuration("Duration overflow".to_string()))?;
 
-        info!("Sleeping for {} seconds ({} microseconds)", duration_seconds, duration_us);
-        self.platform.deep_sleep(duration_us);
+        self.sleep_for_micros(duration_us);
         Ok(())
     }
+
+    /// Sleep for a duration in microseconds, applying configured and drift compensation.
+    pub fn sleep_for_micros(&self, duration_us: u64) {
+        let compensation_us = self.drift.compensation_micros(self.compensation_micros, duration_us);
+        let compensated_us = compensated_sleep_micros(duration_us, compensation_us);
+
+        info!(
+            "Sleeping for {} microseconds ({} requested, compensation {} us, drift {:?} ppm)",
+            compensated_us,
+            duration_us,
+            compensation_us,
+            self.drift.drift_ppm()
+        );
+        arm_wake_reference(compensated_us);
+        self.platform.deep_sleep(compensated_us);
+    }
 }
@@ -0,0 +1,119 @@
+//! Deep sleep のRTCドリフト推定
+//!
+//! 予定起床時刻と実際の起床時刻（外部の時刻基準で計測）の差から
+//! RTCドリフトをppm単位で推定し、指数移動平均で平滑化します。
+//! 推定値はスリープ時間の補正量（マイクロ秒）に換算して使用します。
+//!
+//! 時刻基準にはHASHフレームへの応答に付くゲートウェイの稼働時間を使います。応答を受信した時点の
+//! ゲートウェイの時計とデバイスの時計（Deep sleep 中もRTCで進むシステム時刻）の組を、スリープ直前に
+//! タイマーへ設定した時間とともに保持し、次の起床後の応答との間の経過時間の差をスリープ中の誤差とみなします。
+
+/// 指数移動平均で新しい観測値に与える重み（%）
+pub const DRIFT_EWMA_WEIGHT_PERCENT: i64 = 25;
+
+/// 採用するドリフトの上限（±ppm）。これを超える観測は時刻基準の異常とみなして捨てる
+pub const MAX_DRIFT_PPM: i32 = 50_000;
+
+/// 観測に使う最短スリープ時間（マイクロ秒）。短すぎると計測誤差が支配的になる
+pub const MIN_OBSERVED_SLEEP_US: u64 = 10_000_000;
+
+/// RTCドリフトの推定器
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DriftEstimator {
+    drift_ppm: Option<i32>,
+}
+
+impl DriftEstimator {
+    /// 保存済みの推定値から復元します
+    pub fn from_ppm(drift_ppm: Option<i32>) -> Self {
+        Self {
+            drift_ppm: drift_ppm.filter(|ppm| ppm.abs() <= MAX_DRIFT_PPM),
+        }
+    }
+
+    /// 現在の推定ドリフト（ppm、正の値は起床が遅れる）
+    pub fn drift_ppm(&self) -> Option<i32> {
+        self.drift_ppm
+    }
+
+    /// 1サイクル分の観測を反映します
+    ///
+    /// # 引数
+    /// * `sleep_duration_us` - 要求したスリープ時間
+    /// * `wake_error_us` - 実際の起床時刻 − 予定起床時刻
+    ///
+    /// # 戻り値
+    /// 観測を採用した場合は更新後の推定値
+    pub fn observe(&mut self, sleep_duration_us: u64, wake_error_us: i64) -> Option<i32> {
+        if sleep_duration_us < MIN_OBSERVED_SLEEP_US {
+            return None;
+        }
+        let observed = wake_error_us as i128 * 1_000_000 / sleep_duration_us as i128;
+        if observed.unsigned_abs() > MAX_DRIFT_PPM as u128 {
+            return None;
+        }
+        let observed = observed as i64;
+
+        let updated = match self.drift_ppm {
+            Some(current) => {
+                let current = current as i64;
+                current + (observed - current) * DRIFT_EWMA_WEIGHT_PERCENT / 100
+            }
+            None => observed,
+        };
+        self.drift_ppm = Some(updated as i32);
+        self.drift_ppm
+    }
+
+    /// スリープ時間に加える補正量（マイクロ秒）
+    ///
+    /// 設定値 `base_compensation_us` に、推定ドリフトを打ち消す量を加えます。
+    pub fn compensation_micros(&self, base_compensation_us: i64, sleep_duration_us: u64) -> i64 {
+        let drift_correction = self
+            .drift_ppm
+            .map(|ppm| -(ppm as i128 * sleep_duration_us as i128 / 1_000_000) as i64)
+            .unwrap_or(0);
+        base_compensation_us.saturating_add(drift_correction)
+    }
+}
+
+/// HASHフレームへの応答で受け取ったゲートウェイの時計と、受信時のデバイスの時計
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClockSample {
+    /// ゲートウェイの稼働時間（マイクロ秒）
+    pub gateway_us: u64,
+    /// 受信時のデバイスのシステム時刻（マイクロ秒）
+    pub local_us: u64,
+}
+
+/// 起床誤差の計測の基準（スリープ直前に保存し、次の起床後の応答と比べる）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WakeReference {
+    /// スリープ前に受け取った時計
+    pub sample: ClockSample,
+    /// タイマーへ設定したスリープ時間（補正後、マイクロ秒）
+    pub programmed_sleep_us: u64,
+}
+
+impl WakeReference {
+    /// 起床後に受け取った時計から起床誤差（マイクロ秒、正の値は起床が遅れた）を求めます
+    ///
+    /// ゲートウェイが再起動して時計が戻った場合や、デバイスの経過時間が設定したスリープ時間より
+    /// 短い場合（スリープせずに再起動した場合など）は計測できないため None を返します。
+    pub fn wake_error_us(&self, current: ClockSample) -> Option<i64> {
+        let gateway_elapsed = current.gateway_us.checked_sub(self.sample.gateway_us)?;
+        let local_elapsed = current.local_us.checked_sub(self.sample.local_us)?;
+        if local_elapsed < self.programmed_sleep_us {
+            return None;
+        }
+        Some(i64::try_from(gateway_elapsed).ok()? - i64::try_from(local_elapsed).ok()?)
+    }
+}
+
+/// 補正後のスリープ時間（マイクロ秒）を求めます（最短1秒）
+pub fn compensated_sleep_micros(sleep_duration_us: u64, compensation_us: i64) -> u64 {
+    const MIN_SLEEP_US: u64 = 1_000_000;
+    sleep_duration_us
+        .saturating_add_signed(compensation_us)
+        .max(MIN_SLEEP_US)
+}
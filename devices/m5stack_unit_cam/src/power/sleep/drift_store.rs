@@ -0,0 +1,97 @@
+use esp_idf_svc::nvs::{EspDefaultNvsPartition, EspNvs, NvsDefault};
+use esp_idf_svc::sys::EspError;
+use log::{info, warn};
+use std::sync::{Mutex, PoisonError};
+
+use super::drift::{ClockSample, DriftEstimator, WakeReference};
+
+/// NVS名前空間
+const NVS_NAMESPACE: &str = "sleep_cal";
+/// 推定ドリフト（ppm）のキー
+const NVS_KEY_DRIFT_PPM: &str = "drift_ppm";
+
+/// 前回のスリープの起床誤差を計測する基準（Deep sleep を跨いで保持）
+#[link_section = ".rtc.data"]
+static mut WAKE_REFERENCE: Option<WakeReference> = None;
+
+/// 今回の起動で受け取ったゲートウェイの時計（スリープ直前に基準として保存する）
+static PENDING_SAMPLE: Mutex<Option<ClockSample>> = Mutex::new(None);
+
+/// スリープ直前に、今回受け取った時計とタイマーへ設定する時間を次の起床の計測基準として保存します
+///
+/// 時計を受け取れなかった起動では基準を消し、2回分のスリープを1回分として計測しないようにします。
+pub fn arm_wake_reference(programmed_sleep_us: u64) {
+    let sample = PENDING_SAMPLE.lock().unwrap_or_else(PoisonError::into_inner).take();
+    let reference = sample.map(|sample| WakeReference {
+        sample,
+        programmed_sleep_us,
+    });
+    // スリープ直前にメインタスクからのみアクセスする
+    unsafe { *std::ptr::addr_of_mut!(WAKE_REFERENCE) = reference };
+}
+
+/// RTCドリフト推定値のNVS永続化
+///
+/// 電源断を挟んでも推定値を引き継ぐため、RTCメモリではなくNVSに保存します。
+/// 起床誤差はHASHフレームへの応答に付くゲートウェイの時計で計測します（`record_gateway_clock`）。
+pub struct SleepDriftStore {
+    nvs: EspNvs<NvsDefault>,
+    estimator: DriftEstimator,
+}
+
+impl SleepDriftStore {
+    /// NVSを開き、保存済みの推定値を読み込みます
+    pub fn open(partition: EspDefaultNvsPartition) -> Result<Self, EspError> {
+        let nvs = EspNvs::new(partition, NVS_NAMESPACE, true)?;
+        let stored = match nvs.get_i32(NVS_KEY_DRIFT_PPM) {
+            Ok(value) => value,
+            Err(e) => {
+                warn!("ドリフト推定値の読み込みに失敗しました（未補正で継続）: {:?}", e);
+                None
+            }
+        };
+        let estimator = DriftEstimator::from_ppm(stored);
+        info!("スリープドリフト推定値: {:?} ppm", estimator.drift_ppm());
+        Ok(Self { nvs, estimator })
+    }
+
+    /// 現在の推定器
+    pub fn estimator(&self) -> DriftEstimator {
+        self.estimator
+    }
+
+    /// HASHフレームへの応答で受け取った時計を記録します
+    ///
+    /// 前回のスリープ直前に保存した基準があれば起床誤差を求めて推定値に反映し、
+    /// 今回の時計は次のスリープの基準として保持します。
+    pub fn record_gateway_clock(&mut self, sample: ClockSample) {
+        // メインタスクからのみアクセスする
+        let reference = unsafe { (*std::ptr::addr_of_mut!(WAKE_REFERENCE)).take() };
+        if let Some(reference) = reference {
+            match reference.wake_error_us(sample) {
+                Some(error_us) => self.record_wake_observation(reference.programmed_sleep_us, error_us),
+                None => warn!("ゲートウェイの時計と前回の基準が合わないため起床誤差を計測しません"),
+            }
+        }
+        *PENDING_SAMPLE.lock().unwrap_or_else(PoisonError::into_inner) = Some(sample);
+    }
+
+    /// 起床誤差の観測を反映し、推定値をNVSへ保存します
+    ///
+    /// # 引数
+    /// * `sleep_duration_us` - 前回要求したスリープ時間
+    /// * `wake_error_us` - 実際の起床時刻 − 予定起床時刻（時刻基準で計測）
+    pub fn record_wake_observation(&mut self, sleep_duration_us: u64, wake_error_us: i64) {
+        let Some(drift_ppm) = self.estimator.observe(sleep_duration_us, wake_error_us) else {
+            warn!(
+                "起床誤差の観測を破棄しました: sleep={}us, error={}us",
+                sleep_duration_us, wake_error_us
+            );
+            return;
+        };
+        info!("起床誤差 {}us を反映、推定ドリフト: {} ppm", wake_error_us, drift_ppm);
+        if let Err(e) = self.nvs.set_i32(NVS_KEY_DRIFT_PPM, drift_ppm) {
+            warn!("ドリフト推定値の保存に失敗しました: {:?}", e);
+        }
+    }
+}
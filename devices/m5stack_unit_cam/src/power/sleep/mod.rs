@@ -1,3 +1,11 @@
+pub mod alignment;
 pub mod deep_sleep;
+pub mod drift;
+pub mod drift_store;
+pub mod platform;
 
+pub use alignment::{plan_aligned_sleep, AlignedSleep, AlignmentSettings};
 pub use deep_sleep::*;
+pub use drift::{compensated_sleep_micros, DriftEstimator};
+pub use drift_store::SleepDriftStore;
+pub use platform::DeepSleepPlatform;
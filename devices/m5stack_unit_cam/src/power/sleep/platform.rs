@@ -0,0 +1,29 @@
+/// Platform-agnostic deep-sleep abstraction.
+pub trait DeepSleepPlatform {
+    /// Enter deep sleep for the specified duration in microseconds.
+    fn deep_sleep(&self, duration_us: u64);
+}
+
+/// Deep sleep mock that records requested durations instead of sleeping.
+#[cfg(feature = "mock-hw")]
+#[derive(Debug, Default)]
+pub struct MockDeepSleep {
+    requests: std::sync::Mutex<Vec<u64>>,
+}
+
+#[cfg(feature = "mock-hw")]
+impl MockDeepSleep {
+    /// Durations (microseconds) requested so far, oldest first.
+    pub fn requested_durations(&self) -> Vec<u64> {
+        self.requests.lock().map(|r| r.clone()).unwrap_or_default()
+    }
+}
+
+#[cfg(feature = "mock-hw")]
+impl DeepSleepPlatform for MockDeepSleep {
+    fn deep_sleep(&self, duration_us: u64) {
+        if let Ok(mut requests) = self.requests.lock() {
+            requests.push(duration_us);
+        }
+    }
+}
@@ -0,0 +1,287 @@
+//! ESP-IDF呼び出しの実装（このモジュールの外には `unsafe` なESP-IDF呼び出しを置かない）
+
+use esp_idf_svc::sys::{self, esp, esp_err_t, EspError, ESP_OK};
+
+use super::{Entropy, SysError, SysResult, WifiRadio};
+
+/// ESP-IDFの戻り値をResultに変換します
+pub fn check(code: esp_err_t) -> SysResult<()> {
+    if code == ESP_OK {
+        Ok(())
+    } else {
+        Err(SysError(code))
+    }
+}
+
+/// ESP-IDFを使う `WifiRadio`・`Entropy` の実装（状態を持たない）
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EspSys;
+
+impl WifiRadio for EspSys {
+    fn sta_mac(&self) -> SysResult<[u8; 6]> {
+        sta_mac()
+    }
+
+    fn set_channel(&self, channel: u8) -> SysResult<()> {
+        set_wifi_channel(channel)
+    }
+}
+
+impl Entropy for EspSys {
+    fn random_u32(&self) -> u32 {
+        random_u32()
+    }
+}
+
+/// STAインターフェースのMACアドレス
+pub fn sta_mac() -> SysResult<[u8; 6]> {
+    let mut mac = [0u8; 6];
+    check(unsafe { sys::esp_wifi_get_mac(sys::wifi_interface_t_WIFI_IF_STA, mac.as_mut_ptr()) })?;
+    Ok(mac)
+}
+
+/// 送受信チャンネルを切り替えます（セカンダリチャンネルなし）
+pub fn set_wifi_channel(channel: u8) -> SysResult<()> {
+    check(unsafe { sys::esp_wifi_set_channel(channel, sys::wifi_second_chan_t_WIFI_SECOND_CHAN_NONE) })
+}
+
+/// Wi-Fi設定の保存先をRAMにします
+pub fn set_wifi_storage_ram() -> SysResult<()> {
+    check(unsafe { sys::esp_wifi_set_storage(sys::wifi_storage_t_WIFI_STORAGE_RAM) })
+}
+
+/// Wi-Fiのパワーセーブを無効にします
+pub fn disable_wifi_power_save() -> SysResult<()> {
+    check(unsafe { sys::esp_wifi_set_ps(sys::wifi_ps_type_t_WIFI_PS_NONE) })
+}
+
+/// 最大送信パワーを設定します（dBm。ESP-IDFは0.25dBm単位）
+pub fn set_max_tx_power_dbm(dbm: i8) -> SysResult<()> {
+    check(unsafe { sys::esp_wifi_set_max_tx_power((i16::from(dbm) * 4) as i8) })
+}
+
+/// 現在の最大送信パワー（dBm）
+pub fn max_tx_power_dbm() -> SysResult<i8> {
+    let mut power_quarter_dbm: i8 = 0;
+    check(unsafe { sys::esp_wifi_get_max_tx_power(&mut power_quarter_dbm) })?;
+    Ok(power_quarter_dbm / 4)
+}
+
+/// ESP-NOWの送信レート（`wifi_phy_rate_t` の値）を設定します
+pub fn set_espnow_rate(phy_rate: u8) -> SysResult<()> {
+    check(unsafe { sys::esp_wifi_config_espnow_rate(sys::wifi_interface_t_WIFI_IF_STA, phy_rate.into()) })
+}
+
+/// ESP-NOWの受信コールバックを登録します
+pub fn register_esp_now_recv_cb(recv_cb: sys::esp_now_recv_cb_t) -> SysResult<()> {
+    check(unsafe { sys::esp_now_register_recv_cb(recv_cb) })
+}
+
+/// ESP-NOWの送信完了コールバックを登録します
+pub fn register_esp_now_send_cb(send_cb: sys::esp_now_send_cb_t) -> SysResult<()> {
+    check(unsafe { sys::esp_now_register_send_cb(send_cb) })
+}
+
+/// ハードウェア乱数（Wi-Fi起動中は暗号論的に安全な乱数）
+pub fn random_u32() -> u32 {
+    unsafe { sys::esp_random() }
+}
+
+/// 起動からの経過時間（マイクロ秒）
+pub fn uptime_us() -> i64 {
+    unsafe { sys::esp_timer_get_time() }
+}
+
+/// ヒープ全体の空き容量（バイト）
+pub fn free_heap() -> u32 {
+    unsafe { sys::esp_get_free_heap_size() }
+}
+
+/// 指定した種類（`MALLOC_CAP_*`）のヒープの空き容量（バイト）
+pub fn heap_free_size(caps: u32) -> usize {
+    unsafe { sys::heap_caps_get_free_size(caps) }
+}
+
+/// 指定した種類（`MALLOC_CAP_*`）のヒープの最大の連続空き領域（バイト）
+pub fn heap_largest_free_block(caps: u32) -> usize {
+    unsafe { sys::heap_caps_get_largest_free_block(caps) }
+}
+
+/// 指定した種類（`MALLOC_CAP_*`）のヒープの総容量（バイト、なければ0）
+pub fn heap_total_size(caps: u32) -> usize {
+    unsafe { sys::heap_caps_get_total_size(caps) }
+}
+
+/// Deep sleepに入ります（タイマーで起床）
+pub fn deep_sleep(duration_us: u64) {
+    unsafe { sys::esp_deep_sleep(duration_us) }
+}
+
+/// タイマーで起床するlight sleepに入り、起床まで戻りません（Wi-Fiの状態は保持され、起床後にRFを再開します）
+pub fn light_sleep(duration_us: u64) -> SysResult<()> {
+    check(unsafe { sys::esp_sleep_enable_timer_wakeup(duration_us) })?;
+    check(unsafe { sys::esp_light_sleep_start() })
+}
+
+/// 今回の起動のリセット理由
+pub fn reset_reason() -> sys::esp_reset_reason_t {
+    unsafe { sys::esp_reset_reason() }
+}
+
+/// 今回の起床要因
+pub fn wakeup_cause() -> sys::esp_sleep_wakeup_cause_t {
+    unsafe { sys::esp_sleep_get_wakeup_cause() }
+}
+
+/// NVSパーティション全体を消去します
+pub fn nvs_flash_erase() -> Result<(), EspError> {
+    esp!(unsafe { sys::nvs_flash_erase() })
+}
+
+/// 既定のNVSパーティションの使用状況
+pub fn nvs_stats() -> Result<sys::nvs_stats_t, EspError> {
+    let mut stats = sys::nvs_stats_t::default();
+    esp!(unsafe { sys::nvs_get_stats(core::ptr::null(), &mut stats) })?;
+    Ok(stats)
+}
+
+/// ラベルで探したデータパーティション
+pub struct FlashPartition {
+    partition: *const sys::esp_partition_t,
+}
+
+impl FlashPartition {
+    /// ラベルでデータパーティションを探します（パーティションテーブルになければNone）
+    pub fn find(label: &str) -> Option<Self> {
+        let label = std::ffi::CString::new(label).ok()?;
+        let partition = unsafe {
+            sys::esp_partition_find_first(
+                sys::esp_partition_type_t_ESP_PARTITION_TYPE_DATA,
+                sys::esp_partition_subtype_t_ESP_PARTITION_SUBTYPE_ANY,
+                label.as_ptr(),
+            )
+        };
+        (!partition.is_null()).then_some(Self { partition })
+    }
+
+    /// パーティションの大きさ（バイト）
+    pub fn size(&self) -> usize {
+        unsafe { (*self.partition).size as usize }
+    }
+
+    /// `offset` から `buffer` の長さだけ読み込みます
+    pub fn read(&self, offset: usize, buffer: &mut [u8]) -> SysResult<()> {
+        check(unsafe { sys::esp_partition_read(self.partition, offset, buffer.as_mut_ptr().cast(), buffer.len()) })
+    }
+
+    /// `offset` から `erase_len` バイトを消去してから `data` を書き込みます（`offset`・`erase_len` は4KBの倍数）
+    pub fn erase_and_write(&self, offset: usize, erase_len: usize, data: &[u8]) -> SysResult<()> {
+        check(unsafe { sys::esp_partition_erase_range(self.partition, offset, erase_len) })?;
+        check(unsafe { sys::esp_partition_write(self.partition, offset, data.as_ptr().cast(), data.len()) })
+    }
+}
+
+// esp32-cameraコンポーネントの画像変換（img_converters.h）
+extern "C" {
+    fn jpg2rgb565(src: *const u8, src_len: usize, out: *mut u8, scale: u32) -> bool;
+    fn fmt2jpg(
+        src: *mut u8,
+        src_len: usize,
+        width: u16,
+        height: u16,
+        format: u32,
+        quality: u8,
+        out: *mut *mut u8,
+        out_len: *mut usize,
+    ) -> bool;
+}
+
+/// `jpg_scale_t` の1/8縮小
+const JPG_SCALE_8X: u32 = 3;
+/// `pixformat_t` のRGB565
+const PIXFORMAT_RGB565: u32 = 0;
+
+/// JPEGを1/8の大きさで展開し、JPEGに圧縮し直します
+///
+/// `width`・`height` は縮小後の大きさ。展開先のRGB565のバッファ（幅×高さ×2バイト）を確保できなければ
+/// `ESP_ERR_NO_MEM` を返します。
+pub fn jpeg_thumbnail(jpeg: &[u8], width: u16, height: u16, quality: u8) -> SysResult<Vec<u8>> {
+    // 展開側が端数を切り上げても溢れないよう、1画素ずつ余分に確保する
+    let rgb_len = (usize::from(width) + 1) * (usize::from(height) + 1) * 2;
+    let mut rgb = Vec::new();
+    rgb.try_reserve_exact(rgb_len).map_err(|_| SysError(sys::ESP_ERR_NO_MEM))?;
+    rgb.resize(rgb_len, 0);
+    if !unsafe { jpg2rgb565(jpeg.as_ptr(), jpeg.len(), rgb.as_mut_ptr(), JPG_SCALE_8X) } {
+        return Err(SysError(sys::ESP_FAIL));
+    }
+    let pixels_len = usize::from(width) * usize::from(height) * 2;
+    let mut out: *mut u8 = std::ptr::null_mut();
+    let mut out_len = 0usize;
+    let encoded = unsafe {
+        fmt2jpg(rgb.as_mut_ptr(), pixels_len, width, height, PIXFORMAT_RGB565, quality, &mut out, &mut out_len)
+    };
+    if !encoded || out.is_null() {
+        return Err(SysError(sys::ESP_FAIL));
+    }
+    let thumbnail = unsafe { std::slice::from_raw_parts(out, out_len) }.to_vec();
+    unsafe { sys::free(out.cast()) };
+    Ok(thumbnail)
+}
+
+/// ピンを出力に設定して出力レベルを変えます（ドライバーに割り当てられていないピン向け）
+pub fn gpio_set_output_level(pin: i32, high: bool) -> SysResult<()> {
+    check(unsafe { sys::gpio_reset_pin(pin) })?;
+    check(unsafe { sys::gpio_set_direction(pin, sys::gpio_mode_t_GPIO_MODE_OUTPUT) })?;
+    check(unsafe { sys::gpio_set_level(pin, u32::from(high)) })
+}
+
+/// I2Cをマスターとして設定します（内部プルアップ有効）
+pub fn i2c_master_config(port: sys::i2c_port_t, sda: i32, scl: i32, clk_speed: u32) -> SysResult<()> {
+    let mut cfg = sys::i2c_config_t {
+        mode: sys::i2c_mode_t_I2C_MODE_MASTER,
+        sda_io_num: sda,
+        scl_io_num: scl,
+        sda_pullup_en: true,
+        scl_pullup_en: true,
+        clk_flags: 0,
+        ..Default::default()
+    };
+    cfg.__bindgen_anon_1.master = sys::i2c_config_t__bindgen_ty_1__bindgen_ty_1 { clk_speed };
+    check(unsafe { sys::i2c_param_config(port, &cfg) })
+}
+
+/// I2Cマスターのドライバーをインストールします（バッファなし）
+pub fn i2c_master_driver_install(port: sys::i2c_port_t) -> SysResult<()> {
+    check(unsafe { sys::i2c_driver_install(port, sys::i2c_mode_t_I2C_MODE_MASTER, 0, 0, 0) })
+}
+
+/// I2Cのドライバーを削除します
+pub fn i2c_driver_delete(port: sys::i2c_port_t) -> SysResult<()> {
+    check(unsafe { sys::i2c_driver_delete(port) })
+}
+
+/// I2Cデバイスへ書き込みます
+pub fn i2c_write(port: sys::i2c_port_t, dev_addr: u8, data: &[u8], timeout_ticks: u32) -> SysResult<()> {
+    check(unsafe { sys::i2c_master_write_to_device(port, dev_addr, data.as_ptr(), data.len(), timeout_ticks) })
+}
+
+/// I2Cデバイスへ書き込んでから読み出します
+pub fn i2c_write_read(
+    port: sys::i2c_port_t,
+    dev_addr: u8,
+    write: &[u8],
+    read: &mut [u8],
+    timeout_ticks: u32,
+) -> SysResult<()> {
+    check(unsafe {
+        sys::i2c_master_write_read_device(
+            port,
+            dev_addr,
+            write.as_ptr(),
+            write.len(),
+            read.as_mut_ptr(),
+            read.len(),
+            timeout_ticks,
+        )
+    })
+}
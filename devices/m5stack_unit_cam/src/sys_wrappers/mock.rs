@@ -0,0 +1,64 @@
+use super::{Entropy, SysError, SysResult, WifiRadio};
+use std::sync::Mutex;
+
+/// ESP-IDF呼び出しのモック実装
+///
+/// 切り替えたチャンネルを記録し、乱数は決まった値から順に返します。
+#[derive(Debug)]
+pub struct MockSys {
+    /// STAインターフェースのMACアドレス（Noneは取得失敗）
+    pub mac: Option<[u8; 6]>,
+    /// 切り替えたチャンネル（古い順）
+    pub channels: Mutex<Vec<u8>>,
+    /// 切り替えに失敗させるチャンネル
+    pub failing_channels: Vec<u8>,
+    next_random: Mutex<u32>,
+}
+
+impl Default for MockSys {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MockSys {
+    /// すべての呼び出しが成功するモックを作成します
+    pub fn new() -> Self {
+        Self {
+            mac: Some([0x34, 0xab, 0x95, 0xfb, 0x3f, 0xc4]),
+            channels: Mutex::new(Vec::new()),
+            failing_channels: Vec::new(),
+            next_random: Mutex::new(1),
+        }
+    }
+
+    /// 切り替えたチャンネル（古い順）
+    pub fn tuned_channels(&self) -> Vec<u8> {
+        self.channels.lock().map(|channels| channels.clone()).unwrap_or_default()
+    }
+}
+
+impl WifiRadio for MockSys {
+    fn sta_mac(&self) -> SysResult<[u8; 6]> {
+        self.mac.ok_or(SysError(-1))
+    }
+
+    fn set_channel(&self, channel: u8) -> SysResult<()> {
+        if self.failing_channels.contains(&channel) {
+            return Err(SysError(-1));
+        }
+        if let Ok(mut channels) = self.channels.lock() {
+            channels.push(channel);
+        }
+        Ok(())
+    }
+}
+
+impl Entropy for MockSys {
+    fn random_u32(&self) -> u32 {
+        let mut next = self.next_random.lock().unwrap_or_else(std::sync::PoisonError::into_inner);
+        let value = *next;
+        *next = next.wrapping_add(1);
+        value
+    }
+}
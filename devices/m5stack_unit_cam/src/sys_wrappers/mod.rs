@@ -0,0 +1,52 @@
+//! ESP-IDFの生の呼び出し（`esp_idf_svc::sys`）をまとめた安全なラッパー
+//!
+//! `unsafe` なFFI呼び出しは `esp` モジュールにまとめ、他のモジュールはここの関数を使います。
+//! 無線や乱数に依存する処理は `WifiRadio`・`Entropy` を受け取り、ホストビルドでは `mock` に差し替えます。
+//! RTCメモリ上の `static mut` とESP-NOWコールバックのポインタの解釈は呼び出し元に残しています。
+
+#[cfg(feature = "esp")]
+pub mod esp;
+
+#[cfg(any(test, feature = "mock-hw"))]
+pub mod mock;
+
+/// MACアドレスを取得できなかった場合に使うアドレス
+pub const FALLBACK_STA_MAC: [u8; 6] = [0x24, 0x6F, 0x28, 0x12, 0x34, 0x56];
+
+/// ESP-IDF呼び出しの結果の型
+pub type SysResult<T> = Result<T, SysError>;
+
+/// ESP-IDFのエラーコード（`esp_err_t`）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+#[error("ESP-IDF error {0}")]
+pub struct SysError(pub i32);
+
+impl SysError {
+    /// エラーコード
+    pub fn code(self) -> i32 {
+        self.0
+    }
+}
+
+/// Wi-Fi（ESP-NOWの無線）の状態の取得と変更
+pub trait WifiRadio {
+    /// STAインターフェースのMACアドレス
+    fn sta_mac(&self) -> SysResult<[u8; 6]>;
+
+    /// 送受信チャンネルを切り替えます
+    fn set_channel(&self, channel: u8) -> SysResult<()>;
+}
+
+/// ハードウェア乱数
+pub trait Entropy {
+    /// 32ビットの乱数
+    fn random_u32(&self) -> u32;
+}
+
+/// STAインターフェースのMACアドレス（取得できなければ `FALLBACK_STA_MAC` と失敗の内容）
+pub fn sta_mac_or_fallback(radio: &impl WifiRadio) -> ([u8; 6], Option<SysError>) {
+    match radio.sta_mac() {
+        Ok(mac) => (mac, None),
+        Err(e) => (FALLBACK_STA_MAC, Some(e)),
+    }
+}
@@ -0,0 +1,147 @@
+//! テレメトリで扱う物理量の型
+//!
+//! 電圧（mVとVの取り違え）や温度補正の前後の値を生の `f32`/`u8` で渡すと混同しやすいため、
+//! 単位ごとに型を分けます。HASHペイロードの表記（`VOLT` は%、`TEMP` は℃、`TDS_VOLT` はV）は
+//! 変えず、文字列にする箇所で単位を変換します。
+
+use std::fmt;
+
+/// 電圧（ミリボルト）
+#[derive(Debug, Clone, Copy, Default, PartialEq, PartialOrd)]
+pub struct Millivolts(f32);
+
+impl Millivolts {
+    pub const fn new(millivolts: f32) -> Self {
+        Self(millivolts)
+    }
+
+    /// ボルト単位の値から生成します
+    pub fn from_volts(volts: f32) -> Self {
+        Self(volts * 1000.0)
+    }
+
+    /// ミリボルト単位の値
+    pub const fn value(self) -> f32 {
+        self.0
+    }
+
+    /// ボルト単位の値
+    pub fn as_volts(self) -> f32 {
+        self.0 / 1000.0
+    }
+}
+
+/// ADCの読み取り値（mV）から生成します
+impl From<u16> for Millivolts {
+    fn from(millivolts: u16) -> Self {
+        Self(f32::from(millivolts))
+    }
+}
+
+impl fmt::Display for Millivolts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:.0} mV", self.0)
+    }
+}
+
+/// 温度（℃）
+#[derive(Debug, Clone, Copy, Default, PartialEq, PartialOrd)]
+pub struct Celsius(f32);
+
+impl Celsius {
+    /// TDSの温度補正の基準温度
+    pub const REFERENCE: Celsius = Celsius(25.0);
+
+    pub const fn new(celsius: f32) -> Self {
+        Self(celsius)
+    }
+
+    pub const fn value(self) -> f32 {
+        self.0
+    }
+}
+
+impl fmt::Display for Celsius {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:.1} ℃", self.0)
+    }
+}
+
+/// 濃度（ppm）
+#[derive(Debug, Clone, Copy, Default, PartialEq, PartialOrd)]
+pub struct Ppm(f32);
+
+impl Ppm {
+    /// 温度補正の係数（1℃あたり）
+    pub const TDS_TEMPERATURE_COEFFICIENT: f32 = 0.02;
+
+    pub const fn new(ppm: f32) -> Self {
+        Self(ppm)
+    }
+
+    pub const fn value(self) -> f32 {
+        self.0
+    }
+
+    /// TDSセンサーの出力電圧（補正前）から、25℃相当に温度補正したTDS値を求めます
+    ///
+    /// 温度が分からない場合は基準温度として扱い、補正しません。
+    pub fn from_tds_voltage(raw: Millivolts, temperature: Option<Celsius>) -> Self {
+        let temperature = temperature.unwrap_or(Celsius::REFERENCE);
+        let compensation =
+            1.0 + Self::TDS_TEMPERATURE_COEFFICIENT * (temperature.value() - Celsius::REFERENCE.value());
+        let volts = raw.as_volts() / compensation;
+        let ppm = (133.42 * volts * volts * volts - 255.86 * volts * volts + 857.39 * volts) * 0.5;
+        Self(ppm.max(0.0))
+    }
+}
+
+impl fmt::Display for Ppm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:.0} ppm", self.0)
+    }
+}
+
+/// 百分率（0〜100、測定できなかった場合は [`Percent::INVALID`]）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Percent(u8);
+
+impl Percent {
+    /// 測定エラーを表す値（HASHペイロードでは `VOLT:255`）
+    pub const INVALID: Percent = Percent(255);
+
+    /// 0〜100の値から生成します（100を超える値は100にします）
+    pub const fn new(percent: u8) -> Self {
+        if percent > 100 {
+            Self(100)
+        } else {
+            Self(percent)
+        }
+    }
+
+    /// `min`〜`max` の範囲で `value` が占める割合（範囲外は0または100、範囲が不正なら0）
+    pub fn of_range(value: Millivolts, min: Millivolts, max: Millivolts) -> Self {
+        let range = max.value() - min.value();
+        let percentage = if range <= 0.0 {
+            0.0
+        } else {
+            ((value.value() - min.value()) / range * 100.0).clamp(0.0, 100.0)
+        };
+        Self(percentage.round() as u8)
+    }
+
+    pub const fn value(self) -> u8 {
+        self.0
+    }
+
+    /// 測定エラーでないかどうか
+    pub const fn is_valid(self) -> bool {
+        self.0 <= 100
+    }
+}
+
+impl fmt::Display for Percent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}%", self.0)
+    }
+}
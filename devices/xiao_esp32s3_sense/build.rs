@@ -1,4 +1,11 @@
 fn main() {
+    // ESP-IDF関連のビルド設定は"esp"フィーチャーが有効の時のみ実行
+    #[cfg(feature = "esp")]
+    build_esp_config();
+}
+
+#[cfg(feature = "esp")]
+fn build_esp_config() {
     // Check if the `cfg.toml` file exists and has been filled out.
     if !std::path::Path::new("cfg.toml").exists() {
         panic!("You need to create a `cfg.toml` file with your Wi-Fi credentials! Use `cfg.toml.example` as a template.");
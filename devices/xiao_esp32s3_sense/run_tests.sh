@@ -14,76 +14,22 @@ echo "  - utils::voltage_calc (電圧計算)"
 echo "  - utils::tds_calc (TDS計算)"
 echo "  - utils::streaming_protocol (通信プロトコル)"
 echo "  - mac_address (MACアドレス処理)"
+echo "  - config (設定値の変換)"
 echo "  - core::measured_data (測定データ)"
-echo "  - core::app_controller (アプリ制御)"
+echo "  - power::sleep (スリープモード選択、モック使用)"
 echo ""
 
-# targetディレクトリを作成
-mkdir -p target
-
-# utilsモジュールのテスト（ハードウェア非依存）
-echo "🧪 電圧計算ロジックのテスト..."
-cd src/utils
-echo "Compiling voltage_calc tests..."
-rustc +stable --test voltage_calc.rs --edition 2021 -o ../../target/voltage_tests
-echo "Running voltage_calc tests..."
-../../target/voltage_tests
-echo ""
-
-# TDS計算ロジックのテスト
-echo "🧪 TDS計算ロジックのテスト..."
-echo "Compiling tds_calc tests..."
-rustc +stable --test tds_calc.rs --edition 2021 -o ../../target/tds_tests
-echo "Running tds_calc tests..."
-../../target/tds_tests
+# ホストアーキテクチャを検出
+HOST_TARGET=$(rustc --version --verbose | grep host | awk '{print $2}')
+echo "🖥️  ホストターゲット: $HOST_TARGET"
 echo ""
 
-# ストリーミングプロトコルのテスト
-echo "🧪 ストリーミングプロトコルのテスト..."
-echo "Compiling streaming_protocol tests..."
-rustc +stable --test streaming_protocol.rs --edition 2021 -o ../../target/streaming_tests
-echo "Running streaming_protocol tests..."
-../../target/streaming_tests
-echo ""
-
-# MACアドレスモジュールのテスト
-echo "🧪 MACアドレス処理のテスト..."
-cd ../
-echo "Compiling mac_address tests..."
-rustc +stable --test mac_address.rs --edition 2021 -o ../target/mac_tests
-echo "Running mac_address tests..."
-../target/mac_tests
-echo ""
+# --targetオプションでホストターゲットを明示的に指定し、
+# "host"フィーチャーでESP-IDF非依存のモジュールのみをビルドします
+echo "🧪 すべてのユニットテスト実行..."
+RUST_BACKTRACE=1 cargo +stable test --lib --target "$HOST_TARGET" --no-default-features --features host
 
-# MeasuredDataモジュールのテスト
-echo "🧪 測定データ構造のテスト..."
-cd core
-echo "Compiling measured_data tests..."
-rustc +stable --test measured_data.rs --edition 2021 -o ../../target/measured_data_tests
-echo "Running measured_data tests..."
-../../target/measured_data_tests
 echo ""
-
-# AppControllerモジュールのテスト
-echo "🧪 アプリ制御ロジックのテスト..."
-echo "Compiling app_controller tests..."
-rustc +stable --test app_controller.rs --edition 2021 --extern thiserror=../../target/debug/deps/libthiserror-*.rlib -L ../../target/debug/deps -o ../../target/app_controller_tests 2>/dev/null || echo "⚠️  app_controller tests require dependencies (skipping standalone test)"
-if [ -f ../../target/app_controller_tests ]; then
-    echo "Running app_controller tests..."
-    ../../target/app_controller_tests
-fi
-echo ""
-
 echo "================================"
 echo "✅ すべてのテスト完了"
-echo ""
-echo "📝 Note: すべてのテストを実行するには:"
-echo "   ./run_tests.sh"
-echo ""
-echo "   個別のテストを実行するには、対応するバイナリを直接実行:"
-echo "   ./target/voltage_tests"
-echo "   ./target/tds_tests"
-echo "   ./target/streaming_tests"
-echo "   ./target/mac_tests"
-echo "   ./target/measured_data_tests"
 echo "================================"
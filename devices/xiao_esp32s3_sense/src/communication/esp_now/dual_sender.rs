@@ -0,0 +1,161 @@
+/// 2台の受信機への振り分け送信（Issue #12）
+///
+/// 範囲内に2台のゲートウェイがある場合、画像チャンクを交互または失敗率に応じて振り分けます。
+/// チャンク送信に失敗した経路はもう一方の経路で再送し、失敗率が閾値を超えた経路は
+/// 以降の振り分けから外します。HASH/EOFは両方のゲートウェイが転送を完了できるよう、
+/// 振り分け対象のすべての経路へ送信します。
+
+use crate::communication::esp_now::sender::{EspNowError, EspNowSender, ImageTransport};
+use crate::mac_address::MacAddress;
+use crate::utils::path_balancer::{DualSendMode, PathBalancer, PATH_COUNT};
+use crate::utils::{Celsius, Millivolts, Percent};
+use esp_idf_svc::espnow::EspNow;
+use log::{info, warn};
+use std::sync::{Arc, Mutex, PoisonError};
+
+/// 2台の受信機に振り分けて送信する送信機
+pub struct DualSender {
+    paths: [EspNowSender; PATH_COUNT],
+    balancer: Mutex<PathBalancer>,
+}
+
+impl DualSender {
+    /// 2台の受信機をピア登録して送信機を作成します
+    pub fn new(
+        esp_now: Arc<Mutex<EspNow<'static>>>,
+        primary_mac: MacAddress,
+        secondary_mac: MacAddress,
+        mode: DualSendMode,
+        max_failure_percent: u8,
+    ) -> Result<Self, EspNowError> {
+        let primary = EspNowSender::new(Arc::clone(&esp_now), primary_mac)?;
+        let secondary = EspNowSender::new(esp_now, secondary_mac)?;
+        info!(
+            "2系統送信: {} / {} (方式={:?}, 除外閾値={}%)",
+            primary.peer_mac(),
+            secondary.peer_mac(),
+            mode,
+            max_failure_percent
+        );
+        Ok(Self {
+            paths: [primary, secondary],
+            balancer: Mutex::new(PathBalancer::new(mode, max_failure_percent)),
+        })
+    }
+
+    /// 経路を選んでフレームを送信し、失敗した場合はもう一方の経路で再送します
+    fn send_frame(&self, frame: &[u8]) -> Result<(), EspNowError> {
+        let first = self.balancer.lock().unwrap_or_else(PoisonError::into_inner).select();
+        let mut last_error = EspNowError::SendTimeout;
+        for path in [first, (first + 1) % PATH_COUNT] {
+            if path != first && self.balancer.lock().unwrap_or_else(PoisonError::into_inner).is_excluded(path) {
+                break;
+            }
+            let result = self.paths[path].send_with_retry(frame, 1000, 3);
+            self.record(path, result.is_ok());
+            match result {
+                Ok(()) => return Ok(()),
+                Err(e) => last_error = e,
+            }
+        }
+        Err(last_error)
+    }
+
+    /// 振り分け対象のすべての経路へフレームを送信します（いずれかが成功すれば成功）
+    fn send_to_all(&self, frame: &[u8]) -> Result<(), EspNowError> {
+        let active = self.balancer.lock().unwrap_or_else(PoisonError::into_inner).active_paths();
+        let mut result = Err(EspNowError::SendTimeout);
+        for path in active {
+            match self.paths[path].send_with_retry(frame, 1000, 3) {
+                Ok(()) => {
+                    self.record(path, true);
+                    result = Ok(());
+                }
+                Err(e) => {
+                    self.record(path, false);
+                    if result.is_err() {
+                        result = Err(e);
+                    }
+                }
+            }
+        }
+        result
+    }
+
+    fn record(&self, path: usize, success: bool) {
+        let mut balancer = self.balancer.lock().unwrap_or_else(PoisonError::into_inner);
+        if balancer.record(path, success) {
+            let stats = balancer.stats(path);
+            warn!(
+                "送信経路{} ({}) を除外しました: 失敗率 {}% ({}/{})",
+                path,
+                self.paths[path].peer_mac(),
+                stats.failure_percent(),
+                stats.failures,
+                stats.attempts
+            );
+        }
+    }
+
+    /// 経路ごとの送信統計をログに出力します
+    pub fn log_path_stats(&self) {
+        let balancer = self.balancer.lock().unwrap_or_else(PoisonError::into_inner);
+        for (path, sender) in self.paths.iter().enumerate() {
+            let stats = balancer.stats(path);
+            info!(
+                "送信経路{} ({}): 送信={}, 失敗={}, 失敗率={}%{}",
+                path,
+                sender.peer_mac(),
+                stats.attempts,
+                stats.failures,
+                stats.failure_percent(),
+                if balancer.is_excluded(path) { " [除外]" } else { "" }
+            );
+        }
+    }
+}
+
+impl ImageTransport for DualSender {
+    fn send_image_chunks(
+        &self,
+        data: Vec<u8>,
+        initial_chunk_size: usize,
+        delay_between_chunks_ms: u32,
+    ) -> Result<(), EspNowError> {
+        // フレームの組み立て（送信元MAC・シーケンス番号）は経路によらないため主経路で行う
+        let result = self.paths[0].send_image_chunks_via(
+            data,
+            initial_chunk_size,
+            delay_between_chunks_ms,
+            |frame| self.send_frame(frame),
+        );
+        self.log_path_stats();
+        result
+    }
+
+    fn send_hash_frame(
+        &self,
+        hash: &str,
+        voltage_percentage: Percent,
+        temperature_celsius: Option<Celsius>,
+        tds_voltage: Option<Millivolts>,
+        timestamp: &str,
+        metadata_fields: &str,
+    ) -> Result<(), EspNowError> {
+        let frame = self.paths[0].create_hash_frame(
+            hash,
+            voltage_percentage,
+            temperature_celsius,
+            tds_voltage,
+            timestamp,
+            metadata_fields,
+        )?;
+        self.send_to_all(&frame)
+    }
+
+    fn send_eof_marker(&self) -> Result<(), EspNowError> {
+        info!("EOF フレーム送信開始（2系統）");
+        let frame = self.paths[0].create_eof_frame()?;
+        self.send_to_all(&frame)
+    }
+}
@@ -6,6 +6,9 @@ pub mod receiver;
 pub mod frame;
 /// ストリーミング送信モジュール（Issue #12）
 pub mod streaming;
+/// 2台の受信機への振り分け送信モジュール（Issue #12）
+pub mod dual_sender;
 
 pub use sender::*;
 pub use receiver::*;
+pub use dual_sender::DualSender;
@@ -3,6 +3,9 @@ use log::{info, warn};
 use std::sync::{Arc, Mutex};
 use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
 
+use crate::core::sleep_decision::SleepCommandSource;
+use crate::utils::{is_control_frame, parse_control_frame, ControlCommand};
+
 /// 受信したスリープコマンドのデータ
 static RECEIVED_SLEEP_DURATION: AtomicU32 = AtomicU32::new(0);
 static SLEEP_COMMAND_RECEIVED: AtomicBool = AtomicBool::new(false);
@@ -17,8 +20,8 @@ impl EspNowReceiver {
     /// 新しいESP-NOW受信者を作成
     pub fn new(_esp_now: Arc<Mutex<esp_idf_svc::espnow::EspNow<'static>>>) -> Result<Self, esp_idf_sys::EspError> {
         // ESP-NOW受信コールバックを設定
-        unsafe {
-            esp_idf_sys::esp_now_register_recv_cb(Some(esp_now_recv_cb));
+        if let Err(e) = crate::sys_wrappers::esp::register_esp_now_recv_cb(Some(esp_now_recv_cb)) {
+            warn!("ESP-NOW受信コールバックの登録に失敗しました (エラーコード: {})", e.code());
         }
 
         Ok(Self {
@@ -64,6 +67,12 @@ impl EspNowReceiver {
     }
 }
 
+impl SleepCommandSource for EspNowReceiver {
+    fn wait_for_sleep_command(&self, timeout_seconds: u32) -> Option<u32> {
+        EspNowReceiver::wait_for_sleep_command(self, timeout_seconds)
+    }
+}
+
 /// ESP-NOW受信コールバック
 extern "C" fn esp_now_recv_cb(
     recv_info: *const esp_idf_sys::esp_now_recv_info_t,
@@ -95,38 +104,46 @@ extern "C" fn esp_now_recv_cb(
         info!("データサイズ: {}", data_len);
         info!("データ内容: {:02X?}", data_slice);
         
-        // バイナリ形式の場合（4バイトのu32）
-        if data_len == 4 {
-            let sleep_seconds = u32::from_le_bytes([data_slice[0], data_slice[1], data_slice[2], data_slice[3]]);
-            info!("バイナリ形式でのスリープ時間: {}秒", sleep_seconds);
-            if sleep_seconds > 0 && sleep_seconds <= 86400 {
-                info!("✓ 有効なバイナリスリープコマンド受信: {}秒", sleep_seconds);
-                RECEIVED_SLEEP_DURATION.store(sleep_seconds, Ordering::SeqCst);
-                SLEEP_COMMAND_RECEIVED.store(true, Ordering::SeqCst);
-                return;
-            } else {
-                warn!("無効なバイナリスリープ時間: {}", sleep_seconds);
+        if is_control_frame(data_slice) {
+            match parse_control_frame(data_slice) {
+                Ok(ControlCommand::Sleep { sleep_seconds }) => {
+                    accept_sleep_seconds(sleep_seconds, "制御フレーム");
+                }
+                Ok(ControlCommand::ResendLast) => {
+                    warn!("画像の再送要求を受信しましたが、送信済み画像を保持していないため無視します");
+                }
+                Err(e) => warn!("✗ 制御フレームを破棄: {}", e),
             }
+            return;
         }
 
-        // 文字列形式の場合
-        if let Ok(command_str) = std::str::from_utf8(data_slice) {
-            info!("文字列形式でのコマンド: '{}'", command_str);
-            
-            // 数値のみの場合（秒数）
-            if let Ok(sleep_seconds) = command_str.trim().parse::<u32>() {
-                info!("文字列形式でのスリープ時間: {}秒", sleep_seconds);
-                if sleep_seconds > 0 && sleep_seconds <= 86400 { // 最大24時間
-                    info!("✓ 有効な文字列スリープコマンド受信: {}秒", sleep_seconds);
-                    RECEIVED_SLEEP_DURATION.store(sleep_seconds, Ordering::SeqCst);
-                    SLEEP_COMMAND_RECEIVED.store(true, Ordering::SeqCst);
-                    return;
-                } else {
-                    warn!("無効な文字列スリープ時間: {}", sleep_seconds);
-                }
-            }
+        // ゲートウェイからの拒否は次の送信（または送信中の受信待ち）で処理する
+        if crate::communication::esp_now::streaming::deliver_reject_message(data_slice) {
+            return;
+        }
+
+        // 画像転送中の受信側からのACK/NACK/打ち切りは送信の受信待ちで処理する
+        if crate::communication::esp_now::streaming::deliver_control_message(data_slice) {
+            return;
+        }
+
+        #[cfg(feature = "legacy-sleep-command")]
+        if let Some(sleep_seconds) = crate::utils::control_frame::parse_legacy_sleep_seconds(data_slice) {
+            accept_sleep_seconds(sleep_seconds, "旧形式");
+            return;
         }
 
         warn!("✗ 無効なスリープコマンド形式: {:02X?}", data_slice);
     }
 }
+
+/// 受信したスリープ時間が有効（最大24時間）であれば保存します
+fn accept_sleep_seconds(sleep_seconds: u32, format: &str) {
+    if sleep_seconds > 0 && sleep_seconds <= 86400 {
+        info!("✓ 有効なスリープコマンド受信（{}）: {}秒", format, sleep_seconds);
+        RECEIVED_SLEEP_DURATION.store(sleep_seconds, Ordering::SeqCst);
+        SLEEP_COMMAND_RECEIVED.store(true, Ordering::SeqCst);
+    } else {
+        warn!("無効なスリープ時間（{}）: {}", format, sleep_seconds);
+    }
+}
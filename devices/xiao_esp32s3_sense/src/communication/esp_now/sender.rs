@@ -1,8 +1,10 @@
 use crate::mac_address::MacAddress;
+use crate::utils::{Celsius, FrameType, Millivolts, Percent};
+use crate::sys_wrappers::{esp::EspSys, sta_mac_or_fallback};
 use esp_idf_svc::hal::delay::FreeRtos;
 use esp_idf_svc::espnow::EspNow;
 use log::{debug, error, info, warn};
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, PoisonError};
 
 // ESP-NOW関連定数
 /// ESP-NOWメモリ不足エラーコード
@@ -24,6 +26,34 @@ pub enum EspNowError {
     SendTimeout,
 }
 
+/// 画像・HASH・EOFの送信手段
+///
+/// 単一の受信機に送る `EspNowSender` と、2台の受信機に振り分ける `DualSender` を
+/// 測定データの送信処理から同じように扱うためのトレイトです。
+pub trait ImageTransport {
+    /// 画像データをチャンクに分割して送信
+    fn send_image_chunks(
+        &self,
+        data: Vec<u8>,
+        initial_chunk_size: usize,
+        delay_between_chunks_ms: u32,
+    ) -> Result<(), EspNowError>;
+
+    /// メタデータを含むハッシュフレームを送信
+    fn send_hash_frame(
+        &self,
+        hash: &str,
+        voltage_percentage: Percent,
+        temperature_celsius: Option<Celsius>,
+        tds_voltage: Option<Millivolts>,
+        timestamp: &str,
+        metadata_fields: &str,
+    ) -> Result<(), EspNowError>;
+
+    /// 画像送信終了マーカーを送信
+    fn send_eof_marker(&self) -> Result<(), EspNowError>;
+}
+
 /// ESP-NOW送信機
 pub struct EspNowSender {
     esp_now: Arc<Mutex<EspNow<'static>>>,
@@ -62,7 +92,7 @@ impl EspNowSender {
         };
 
         {
-            let esp_now_guard = self.esp_now.lock().unwrap();
+            let esp_now_guard = self.esp_now.lock().unwrap_or_else(PoisonError::into_inner);
             esp_now_guard.add_peer(peer_info)
                 .map_err(|e| {
                     error!("ESP-NOWピア追加失敗: {:?}", e);
@@ -79,11 +109,11 @@ impl EspNowSender {
         // データサイズの事前チェック
         if data.len() > 250 {
             error!("ESP-NOWデータサイズ制限超過: {}バイト (最大250バイト)", data.len());
-            return Err(EspNowError::SendFailed(esp_idf_sys::EspError::from(esp_idf_sys::ESP_ERR_INVALID_ARG).unwrap()));
+            return Err(EspNowError::SendFailed(esp_idf_sys::EspError::from_infallible::<{ esp_idf_sys::ESP_ERR_INVALID_ARG }>()));
         }
         
         {
-            let esp_now_guard = self.esp_now.lock().unwrap();
+            let esp_now_guard = self.esp_now.lock().unwrap_or_else(PoisonError::into_inner);
             match esp_now_guard.send(self.peer_mac.0, data) {
                 Ok(()) => {
                     // 正常送信時は詳細ログを出力しない（スパム防止）
@@ -166,6 +196,24 @@ impl EspNowSender {
         initial_chunk_size: usize,
         delay_between_chunks_ms: u32,
     ) -> Result<(), EspNowError> {
+        self.send_image_chunks_via(data, initial_chunk_size, delay_between_chunks_ms, |frame| {
+            self.send_with_retry(frame, 1000, 3)
+        })
+    }
+
+    /// 画像データをチャンクに分割し、フレームごとに `send_frame` で送信する
+    ///
+    /// 送信経路を選ぶ `DualSender` からも同じ分割・再試行の手順を使うために分けています。
+    pub(crate) fn send_image_chunks_via<F>(
+        &self,
+        data: Vec<u8>,
+        initial_chunk_size: usize,
+        delay_between_chunks_ms: u32,
+        send_frame: F,
+    ) -> Result<(), EspNowError>
+    where
+        F: Fn(&[u8]) -> Result<(), EspNowError>,
+    {
         // フレームヘッダーサイズを計算
         const FRAME_OVERHEAD: usize = 4 + 6 + 1 + 4 + 4 + 4 + 4; // START_MARKER + MAC + TYPE + SEQ + LEN + CHECKSUM + END_MARKER = 27バイト
         const ESP_NOW_MAX_SIZE: usize = 250; // ESP-NOWの最大サイズ
@@ -205,7 +253,7 @@ impl EspNowSender {
                 }
                 
                 // sensor_data_receiver準拠のフレーム構造で送信
-                let frame = match self.create_sensor_data_frame(2, chunk) { // FRAME_TYPE_DATA = 2
+                let frame = match self.create_sensor_data_frame(FrameType::Data, chunk) {
                     Ok(f) => f,
                     Err(e) => {
                         error!("チャンク{} フレーム作成失敗: {:?}", i + 1, e);
@@ -224,7 +272,7 @@ impl EspNowSender {
                 
                 let mut chunk_success = false;
                 for attempt in 1..=retry_count {
-                    match self.send_with_retry(&frame, 1000, 3) {
+                    match send_frame(&frame) {
                         Ok(()) => {
                             if retry_count > 1 {
                                 info!("重要チャンク{} 送信成功 (試行{}/{})", i + 1, attempt, retry_count);
@@ -269,31 +317,56 @@ impl EspNowSender {
     pub fn send_hash_frame(
         &self,
         hash: &str,
-        voltage_percentage: u8,
-        temperature_celsius: Option<f32>,
-        tds_voltage: Option<f32>,
+        voltage_percentage: Percent,
+        temperature_celsius: Option<Celsius>,
+        tds_voltage: Option<Millivolts>,
         timestamp: &str,
+        metadata_fields: &str,
     ) -> Result<(), EspNowError> {
-        // 温度データがない場合はダミー値-999.0を使用
-        let temp_data = temperature_celsius.unwrap_or(-999.0);
-        // TDS電圧データがない場合はダミー値-999.0を使用
-        let tds_data = tds_voltage.unwrap_or(-999.0);
-        let hash_data = format!("HASH:{},VOLT:{},TEMP:{:.1},TDS_VOLT:{:.1},{}", hash, voltage_percentage, temp_data, tds_data, timestamp);
-        info!("ハッシュフレーム送信（sensor_data_receiver準拠）: {}", hash_data);
-        
         // sensor_data_receiver準拠のフレーム構造で送信
-        let frame = self.create_sensor_data_frame(1, hash_data.as_bytes())?; // FRAME_TYPE_HASH = 1
+        let frame = self.create_hash_frame(hash, voltage_percentage, temperature_celsius, tds_voltage, timestamp, metadata_fields)?;
         
         self.send_with_retry(&frame, 1000, 3)?;
         Ok(())
     }
 
+    /// メタデータを含むHASHフレームを作成
+    ///
+    /// `metadata_fields` は `,HASH_ALGO:XXH64` のようにカンマで始まる追加フィールドで、タイムスタンプの後に付加します。
+    pub(crate) fn create_hash_frame(
+        &self,
+        hash: &str,
+        voltage_percentage: Percent,
+        temperature_celsius: Option<Celsius>,
+        tds_voltage: Option<Millivolts>,
+        timestamp: &str,
+        metadata_fields: &str,
+    ) -> Result<Vec<u8>, EspNowError> {
+        // 温度データがない場合はダミー値-999.0を使用
+        let temp_data = temperature_celsius.map_or(-999.0, Celsius::value);
+        // TDS電圧データがない場合はダミー値-999.0を使用（ボルト単位で書く）
+        let tds_data = tds_voltage.map_or(-999.0, Millivolts::as_volts);
+        let hash_data = format!("HASH:{},VOLT:{},TEMP:{:.1},TDS_VOLT:{:.1},{}{}", hash, voltage_percentage.value(), temp_data, tds_data, timestamp, metadata_fields);
+        info!("ハッシュフレーム送信（sensor_data_receiver準拠）: {}", hash_data);
+        self.create_sensor_data_frame(FrameType::Hash, hash_data.as_bytes())
+    }
+
+    /// EOFフレームを作成
+    pub(crate) fn create_eof_frame(&self) -> Result<Vec<u8>, EspNowError> {
+        self.create_sensor_data_frame(FrameType::Eof, b"EOF")
+    }
+
+    /// 送信先のMACアドレス
+    pub fn peer_mac(&self) -> &MacAddress {
+        &self.peer_mac
+    }
+
     /// 画像送信終了マーカーを送信（sensor_data_receiver準拠フレーム形式）
     pub fn send_eof_marker(&self) -> Result<(), EspNowError> {
         info!("EOF フレーム送信開始（sensor_data_receiver準拠）");
         
         // sensor_data_receiver準拠のフレーム構造で送信
-        let frame = self.create_sensor_data_frame(3, b"EOF")?; // FRAME_TYPE_EOF = 3
+        let frame = self.create_eof_frame()?;
         
         // 複数回送信で信頼性を向上
         for attempt in 1..=3 {
@@ -330,7 +403,7 @@ impl EspNowSender {
     /// - DATA: ペイロードデータ (可変長)
     /// - CHECKSUM: チェックサム (4 bytes, little-endian)
     /// - END_MARKER: [0xCD, 0xEF, 0x56, 0x78] (4 bytes)
-    fn create_sensor_data_frame(&self, frame_type: u8, data: &[u8]) -> Result<Vec<u8>, EspNowError> {
+    fn create_sensor_data_frame(&self, frame_type: FrameType, data: &[u8]) -> Result<Vec<u8>, EspNowError> {
         // フレームマーカー定数（sensor_data_receiver準拠）
         const START_MARKER: [u8; 4] = [0xFA, 0xCE, 0xAA, 0xBB];
         const END_MARKER: [u8; 4] = [0xCD, 0xEF, 0x56, 0x78];
@@ -345,7 +418,7 @@ impl EspNowSender {
         frame.extend_from_slice(&mac_address);
         
         // 3. フレームタイプ (1 byte)
-        frame.push(frame_type);
+        frame.push(frame_type.to_byte());
         
         // 4. シーケンス番号 (4 bytes, little-endian)
         let sequence = self.get_next_sequence_number();
@@ -374,17 +447,9 @@ impl EspNowSender {
     /// ローカルMACアドレスを取得
     fn get_local_mac_address(&self) -> [u8; 6] {
         // ESP32のWiFi MACアドレスを取得
-        let mut mac = [0u8; 6];
-        unsafe {
-            let result = esp_idf_svc::sys::esp_wifi_get_mac(
-                esp_idf_svc::sys::wifi_interface_t_WIFI_IF_STA, 
-                mac.as_mut_ptr()
-            );
-            if result != 0 {
-                warn!("MACアドレス取得失敗、デフォルト値を使用: {:?}", result);
-                // デフォルトMAC（テスト用）
-                mac = [0x24, 0x6F, 0x28, 0x12, 0x34, 0x56];
-            }
+        let (mac, error) = sta_mac_or_fallback(&EspSys);
+        if let Some(e) = error {
+            warn!("MACアドレス取得失敗、デフォルト値を使用: {:?}", e.code());
         }
         mac
     }
@@ -409,3 +474,30 @@ impl EspNowSender {
         checksum
     }
 }
+
+impl ImageTransport for EspNowSender {
+    fn send_image_chunks(
+        &self,
+        data: Vec<u8>,
+        initial_chunk_size: usize,
+        delay_between_chunks_ms: u32,
+    ) -> Result<(), EspNowError> {
+        EspNowSender::send_image_chunks(self, data, initial_chunk_size, delay_between_chunks_ms)
+    }
+
+    fn send_hash_frame(
+        &self,
+        hash: &str,
+        voltage_percentage: Percent,
+        temperature_celsius: Option<Celsius>,
+        tds_voltage: Option<Millivolts>,
+        timestamp: &str,
+        metadata_fields: &str,
+    ) -> Result<(), EspNowError> {
+        EspNowSender::send_hash_frame(self, hash, voltage_percentage, temperature_celsius, tds_voltage, timestamp, metadata_fields)
+    }
+
+    fn send_eof_marker(&self) -> Result<(), EspNowError> {
+        EspNowSender::send_eof_marker(self)
+    }
+}
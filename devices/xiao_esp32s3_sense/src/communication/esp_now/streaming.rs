@@ -4,13 +4,22 @@
 /// - チャンク分割送信機能
 /// - シーケンス管理とリトライ機構
 /// - チェックサム検証
+/// - 送信期限（次の撮影スロットまで）を過ぎる見込みの転送の打ち切り
+/// - 一定チャンクごとの受信待ち（送信を止めて受信側のACK/NACK/打ち切りを受け取る）
+/// - ゲートウェイの拒否メッセージによる打ち切り・待機・再ペアリング
 
 #[allow(dead_code)] // Issue #12 実装中のため一時的に警告を抑制
 
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
 use crate::hardware::camera::StreamingCameraConfig;
 use crate::communication::esp_now::sender::{EspNowSender, EspNowError};
-use crate::utils::streaming_protocol::{
-    MessageType, StreamingHeader, StreamingMessage, DeserializeError
+use crate::utils::reject_message::{parse_reject_message, RejectAction, RejectMessage, RejectReason};
+// メッセージ形式はutils::streaming_protocolに一本化（送信側で再定義しない）
+pub use crate::utils::streaming_protocol::{
+    DeserializeError, MessageType, StreamingHeader, StreamingMessage, HEADER_SIZE,
 };
 
 /// ストリーミング送信エラー
@@ -24,6 +33,16 @@ pub enum StreamingError {
     CameraError(&'static str),
     InvalidFrame(String),
     EspNowError(EspNowError),
+    /// 送信期限までに送り切れない見込みのため打ち切った
+    DeadlineExceeded { chunks_sent: u32, total_chunks: u32 },
+    /// 受信側から打ち切りを指示された
+    AbortedByReceiver { chunks_sent: u32, total_chunks: u32 },
+    /// ゲートウェイに拒否された（送信中なら打ち切った）
+    RejectedByGateway { reason: RejectReason, retry_after_ms: u32 },
+    /// 拒否を受けて再試行までの時間を待っている
+    BackingOff { remaining_ms: u64 },
+    /// ゲートウェイに登録されていないため、ペアリングをやり直すまで送信しない
+    PairingRequired,
 }
 
 impl From<EspNowError> for StreamingError {
@@ -38,8 +57,6 @@ impl From<DeserializeError> for StreamingError {
     }
 }
 
-// StreamingMessageのメソッドはutils::streaming_protocolで実装済み
-
 /// ストリーミング送信状態
 #[derive(Debug, PartialEq)]
 pub enum StreamingState {
@@ -58,6 +75,157 @@ pub struct StreamingStats {
     pub bytes_sent: u64,
     pub retries: u32,
     pub errors: u32,
+    /// 送信期限のために打ち切ったフレーム数
+    pub deadline_aborts: u32,
+    /// 受信待ちの回数
+    pub listen_gaps: u32,
+    /// 受信待ちで受け取ったACK数
+    pub acks_received: u32,
+    /// 受信待ちで受け取ったNACK数
+    pub nacks_received: u32,
+    /// ゲートウェイから受け取った拒否数
+    pub rejects_received: u32,
+}
+
+/// 受信待ちの間隔の既定値（チャンク数）
+///
+/// 受信側はこの間隔に合わせてまとめてACK（受信済みの最後のシーケンス番号）を返すと、
+/// 送信中でも取りこぼされません。
+pub const DEFAULT_LISTEN_EVERY_CHUNKS: u32 = 16;
+
+/// 受信待ちの時間の既定値
+pub const DEFAULT_LISTEN_GAP: Duration = Duration::from_millis(20);
+
+/// 受信待ちの間に確認する間隔
+const LISTEN_POLL_INTERVAL: Duration = Duration::from_millis(2);
+
+/// 受信待ちまでに溜める受信メッセージの上限（古いものから破棄）
+pub const CONTROL_INBOX_CAPACITY: usize = 16;
+
+/// 受信コールバックから送信中の転送へ渡す制御メッセージ（ACK/NACK/打ち切り）
+static CONTROL_INBOX: Mutex<VecDeque<StreamingMessage>> = Mutex::new(VecDeque::new());
+
+/// 受信したデータが転送の制御メッセージであれば受信待ちの確認用に溜める
+///
+/// ESP-NOWの受信コールバックから呼び出します。制御メッセージでなければ false を返します。
+pub fn deliver_control_message(data: &[u8]) -> bool {
+    let Ok(message) = StreamingMessage::deserialize(data) else {
+        return false;
+    };
+    if !matches!(
+        message.header.message_type,
+        MessageType::Ack | MessageType::Nack | MessageType::AbortFrame
+    ) {
+        return false;
+    }
+    let mut inbox = CONTROL_INBOX.lock().unwrap_or_else(|e| e.into_inner());
+    if inbox.len() >= CONTROL_INBOX_CAPACITY {
+        inbox.pop_front();
+    }
+    inbox.push_back(message);
+    true
+}
+
+fn take_control_messages() -> Vec<StreamingMessage> {
+    CONTROL_INBOX.lock().unwrap_or_else(|e| e.into_inner()).drain(..).collect()
+}
+
+/// 受信コールバックから送信機へ渡すゲートウェイの拒否（最新の1件のみ保持）
+static REJECT_INBOX: Mutex<Option<RejectMessage>> = Mutex::new(None);
+
+/// 受信したデータがゲートウェイの拒否メッセージであれば次の送信の確認用に保持する
+///
+/// ESP-NOWの受信コールバックから呼び出します。拒否メッセージでなければ false を返します。
+pub fn deliver_reject_message(data: &[u8]) -> bool {
+    let Some(reject) = parse_reject_message(data) else {
+        return false;
+    };
+    *REJECT_INBOX.lock().unwrap_or_else(|e| e.into_inner()) = Some(reject);
+    true
+}
+
+fn take_reject_message() -> Option<RejectMessage> {
+    REJECT_INBOX.lock().unwrap_or_else(|e| e.into_inner()).take()
+}
+
+/// 送信中の受信待ちの予定
+///
+/// ESP-NOWは送信中も受信できますが、数ミリ秒ごとにチャンクを送り続けると受信側の
+/// ACKや打ち切りの指示が届きにくくなるため、一定チャンクごとに送信を止めて受信を待ちます。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ListenSchedule {
+    /// 受信待ちの間隔（チャンク数）
+    pub every_chunks: u32,
+    /// 受信待ちの時間
+    pub gap: Duration,
+}
+
+impl Default for ListenSchedule {
+    fn default() -> Self {
+        Self {
+            every_chunks: DEFAULT_LISTEN_EVERY_CHUNKS,
+            gap: DEFAULT_LISTEN_GAP,
+        }
+    }
+}
+
+impl ListenSchedule {
+    /// 送信済みチャンク数がこの数のときに受信を待つか（最初のチャンクの前は待たない）
+    pub fn is_listen_point(&self, chunks_sent: u32) -> bool {
+        self.every_chunks > 0 && chunks_sent > 0 && chunks_sent % self.every_chunks == 0
+    }
+}
+
+/// 打ち切りの記録に残す件数（古いものから破棄）
+pub const ABORT_JOURNAL_LEN: usize = 8;
+
+/// 拒否の記録に残す件数（古いものから破棄）
+pub const REJECT_JOURNAL_LEN: usize = 8;
+
+/// 残りの送信時間を見積もるのに必要な送信済みチャンク数
+pub const MIN_CHUNKS_FOR_ESTIMATE: u32 = 4;
+
+/// 打ち切ったフレームの記録
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AbortRecord {
+    pub frame_id: u32,
+    pub chunks_sent: u32,
+    pub total_chunks: u32,
+    pub elapsed_ms: u64,
+}
+
+/// ゲートウェイに拒否されたフレームの記録（サーバーでの調査用）
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RejectRecord {
+    pub frame_id: u32,
+    pub reason: RejectReason,
+    pub retry_after_ms: u32,
+    pub chunks_sent: u32,
+    pub total_chunks: u32,
+}
+
+/// スリープ周期から1フレームの送信期限を求める
+///
+/// 撮影スロット（スリープ周期）から起床後の経過時間と、送信後に必要な時間
+/// （コマンド待機・スリープ準備）を差し引いた残りを送信に使える時間とします。
+pub fn transmission_budget(slot: Duration, elapsed_in_slot: Duration, reserve: Duration) -> Duration {
+    slot.saturating_sub(elapsed_in_slot.saturating_add(reserve))
+}
+
+/// 送信期限までに残りのチャンクを送り切れない見込みか
+///
+/// 送信済みチャンクの平均送信時間から残りの時間を見積もります。送信済みが少ない間は
+/// 見積もりが不安定なため、期限を過ぎた場合のみ打ち切ります。
+pub fn should_abort(elapsed: Duration, budget: Duration, chunks_sent: u32, total_chunks: u32) -> bool {
+    if elapsed >= budget {
+        return true;
+    }
+    if chunks_sent < MIN_CHUNKS_FOR_ESTIMATE {
+        return false;
+    }
+    let remaining_chunks = total_chunks.saturating_sub(chunks_sent);
+    let projected = elapsed / chunks_sent * remaining_chunks;
+    elapsed.saturating_add(projected) > budget
 }
 
 #[cfg(test)]
@@ -83,6 +251,21 @@ pub struct StreamingSender {
     sequence_id: u16,
     state: StreamingState,
     stats: StreamingStats,
+    /// 1フレームの送信期限（Noneは期限なし）
+    frame_budget: Option<Duration>,
+    /// 打ち切ったフレームを次回送り直すために保持するか
+    keep_backlog: bool,
+    backlog: Option<Vec<u8>>,
+    abort_journal: Vec<AbortRecord>,
+    /// 受信待ちの予定（Noneは待たない）
+    listen_schedule: Option<ListenSchedule>,
+    /// 受信側がACKした最後のシーケンス番号
+    last_acked_sequence: Option<u16>,
+    reject_journal: Vec<RejectRecord>,
+    /// 拒否を受けて送信を再開できる時刻
+    retry_not_before: Option<Instant>,
+    /// ゲートウェイに登録されていないと拒否された（ペアリングをやり直すまで送信しない）
+    pairing_required: bool,
 }
 
 impl StreamingSender {
@@ -99,6 +282,15 @@ impl StreamingSender {
             sequence_id: 0,
             state: StreamingState::Idle,
             stats: StreamingStats::default(),
+            frame_budget: None,
+            keep_backlog: false,
+            backlog: None,
+            abort_journal: Vec::new(),
+            listen_schedule: Some(ListenSchedule::default()),
+            last_acked_sequence: None,
+            reject_journal: Vec::new(),
+            retry_not_before: None,
+            pairing_required: false,
         })
     }
 
@@ -118,19 +310,171 @@ impl StreamingSender {
             sequence_id: 0,
             state: StreamingState::Idle,
             stats: StreamingStats::default(),
+            frame_budget: None,
+            keep_backlog: false,
+            backlog: None,
+            abort_journal: Vec::new(),
+            listen_schedule: Some(ListenSchedule::default()),
+            last_acked_sequence: None,
+            reject_journal: Vec::new(),
+            retry_not_before: None,
+            pairing_required: false,
         })
     }
     
+    /// 1フレームの送信期限を設定する（`transmission_budget` で求めた時間、Noneは期限なし）
+    pub fn set_frame_budget(&mut self, budget: Option<Duration>) {
+        self.frame_budget = budget;
+    }
+
+    /// 打ち切ったフレームを `take_backlog` で取り出せるように保持するか
+    pub fn set_backlog_enabled(&mut self, enabled: bool) {
+        self.keep_backlog = enabled;
+        if !enabled {
+            self.backlog = None;
+        }
+    }
+
+    /// 打ち切ったフレームの画像を取り出す（次の起床で送り直す）
+    pub fn take_backlog(&mut self) -> Option<Vec<u8>> {
+        self.backlog.take()
+    }
+
+    /// 打ち切ったフレームの記録（新しいものが最後）
+    pub fn abort_journal(&self) -> &[AbortRecord] {
+        &self.abort_journal
+    }
+
+    /// 送信中の受信待ちの予定を設定する（Noneは待たない）
+    pub fn set_listen_schedule(&mut self, schedule: Option<ListenSchedule>) {
+        self.listen_schedule = schedule;
+    }
+
+    /// 受信側がACKした最後のシーケンス番号
+    pub fn last_acked_sequence(&self) -> Option<u16> {
+        self.last_acked_sequence
+    }
+
+    /// ゲートウェイに拒否されたフレームの記録（新しいものが最後）
+    pub fn reject_journal(&self) -> &[RejectRecord] {
+        &self.reject_journal
+    }
+
+    /// ペアリングをやり直すまで送信しない状態か
+    pub fn is_pairing_required(&self) -> bool {
+        self.pairing_required
+    }
+
+    /// ペアリングをやり直したことを知らせ、送信を再開する
+    pub fn mark_paired(&mut self) {
+        self.pairing_required = false;
+    }
+
+    /// ゲートウェイの拒否を処理する（記録を残し、理由に応じて待機またはペアリング待ちにする）
+    fn apply_reject(&mut self, reject: RejectMessage, chunks_sent: u32, total_chunks: u32) -> StreamingError {
+        log::warn!(
+            "ゲートウェイがフレーム{}を拒否しました: {:?}, {}ms後に再試行, {}/{}チャンク送信済み",
+            self.frame_id,
+            reject.reason,
+            reject.retry_after_ms,
+            chunks_sent,
+            total_chunks
+        );
+        self.stats.rejects_received += 1;
+        if self.reject_journal.len() >= REJECT_JOURNAL_LEN {
+            self.reject_journal.remove(0);
+        }
+        self.reject_journal.push(RejectRecord {
+            frame_id: self.frame_id,
+            reason: reject.reason,
+            retry_after_ms: reject.retry_after_ms,
+            chunks_sent,
+            total_chunks,
+        });
+        match reject.reason.action() {
+            RejectAction::Backoff => {
+                self.retry_not_before =
+                    Some(Instant::now() + Duration::from_millis(u64::from(reject.retry_after_ms)));
+            }
+            RejectAction::Repair => self.pairing_required = true,
+        }
+        let (reason, retry_after_ms) = (reject.reason, reject.retry_after_ms);
+        self.state = StreamingState::Error(StreamingError::RejectedByGateway { reason, retry_after_ms });
+        StreamingError::RejectedByGateway { reason, retry_after_ms }
+    }
+
+    /// 拒否を受けた後の待機中かペアリング待ちなら、送信を始めずにエラーを返す
+    fn check_rejected(&mut self) -> Result<(), StreamingError> {
+        if let Some(reject) = take_reject_message() {
+            return Err(self.apply_reject(reject, 0, 0));
+        }
+        if self.pairing_required {
+            return Err(StreamingError::PairingRequired);
+        }
+        if let Some(retry_not_before) = self.retry_not_before {
+            let remaining = retry_not_before.saturating_duration_since(Instant::now());
+            if !remaining.is_zero() {
+                return Err(StreamingError::BackingOff { remaining_ms: remaining.as_millis() as u64 });
+            }
+            self.retry_not_before = None;
+        }
+        Ok(())
+    }
+
+    /// 送信を止めて受信を待ち、届いた制御メッセージを処理する
+    ///
+    /// 受信側から現在のフレームの打ち切りを指示された場合や、ゲートウェイに拒否された場合はエラーを返します。
+    fn listen_for_control(&mut self, gap: Duration, chunks_sent: u32, total_chunks: u32) -> Result<(), StreamingError> {
+        self.stats.listen_gaps += 1;
+        let started_at = Instant::now();
+        loop {
+            if let Some(reject) = take_reject_message() {
+                return Err(self.apply_reject(reject, chunks_sent, total_chunks));
+            }
+            for message in take_control_messages() {
+                match message.header.message_type {
+                    MessageType::Ack => {
+                        self.stats.acks_received += 1;
+                        self.last_acked_sequence = Some(message.header.sequence_id);
+                    }
+                    MessageType::Nack => {
+                        self.stats.nacks_received += 1;
+                        log::warn!("受信側からNACK: sequence_id={}", message.header.sequence_id);
+                    }
+                    MessageType::AbortFrame if message.header.frame_id == self.frame_id => {
+                        log::warn!(
+                            "受信側の指示でフレーム{}を打ち切ります: {}/{}チャンク送信済み",
+                            self.frame_id,
+                            chunks_sent,
+                            total_chunks
+                        );
+                        self.state =
+                            StreamingState::Error(StreamingError::AbortedByReceiver { chunks_sent, total_chunks });
+                        return Err(StreamingError::AbortedByReceiver { chunks_sent, total_chunks });
+                    }
+                    _ => {}
+                }
+            }
+            let elapsed = started_at.elapsed();
+            if elapsed >= gap {
+                return Ok(());
+            }
+            std::thread::sleep(LISTEN_POLL_INTERVAL.min(gap - elapsed));
+        }
+    }
+
     pub fn send_frame(&mut self, image_data: &[u8]) -> Result<(), StreamingError> {
         if image_data.is_empty() {
             return Err(StreamingError::CameraError("Empty image data"));
         }
+        self.check_rejected()?;
         
         self.state = StreamingState::Sending;
         self.frame_id = self.frame_id.wrapping_add(1);
+        let started_at = Instant::now();
         
         // Calculate total chunks needed
-        let total_chunks = ((image_data.len() + self.config.chunk_size - 1) / self.config.chunk_size) as u16;
+        let total_chunks = ((image_data.len() + self.config.chunk_size - 1) / self.config.chunk_size) as u32;
         
         // Send start frame message
         self.sequence_id = self.sequence_id.wrapping_add(1);
@@ -139,6 +483,17 @@ impl StreamingSender {
         
         // Send data chunks
         for chunk_index in 0..total_chunks {
+            if let Some(budget) = self.frame_budget {
+                if should_abort(started_at.elapsed(), budget, chunk_index, total_chunks) {
+                    return Err(self.abort_frame(image_data, chunk_index, total_chunks, started_at.elapsed()));
+                }
+            }
+            if let Some(schedule) = self.listen_schedule {
+                if schedule.is_listen_point(chunk_index) {
+                    self.listen_for_control(schedule.gap, chunk_index, total_chunks)?;
+                }
+            }
+
             let start_offset = (chunk_index as usize) * self.config.chunk_size;
             let end_offset = std::cmp::min(start_offset + self.config.chunk_size, image_data.len());
             let chunk_data = image_data[start_offset..end_offset].to_vec();
@@ -167,6 +522,42 @@ impl StreamingSender {
         Ok(())
     }
     
+    /// 送信期限のために転送を打ち切る（受信側へ通知し、記録と送り直し用の画像を残す）
+    fn abort_frame(&mut self, image_data: &[u8], chunks_sent: u32, total_chunks: u32, elapsed: Duration) -> StreamingError {
+        log::warn!(
+            "送信期限のためフレーム{}を打ち切ります: {}/{}チャンク送信済み, {}ms経過",
+            self.frame_id,
+            chunks_sent,
+            total_chunks,
+            elapsed.as_millis()
+        );
+        self.sequence_id = self.sequence_id.wrapping_add(1);
+        let abort_msg = StreamingMessage::abort_frame(self.frame_id, self.sequence_id, chunks_sent, total_chunks);
+        // 通知が届かなくても受信側はタイムアウトで破棄するため、失敗は記録のみ
+        if let Err(e) = self.send_message(&abort_msg) {
+            log::warn!("Abort Frameの送信に失敗しました: {:?}", e);
+            self.stats.errors += 1;
+        }
+
+        if self.abort_journal.len() >= ABORT_JOURNAL_LEN {
+            self.abort_journal.remove(0);
+        }
+        self.abort_journal.push(AbortRecord {
+            frame_id: self.frame_id,
+            chunks_sent,
+            total_chunks,
+            elapsed_ms: elapsed.as_millis() as u64,
+        });
+        if self.keep_backlog {
+            self.backlog = Some(image_data.to_vec());
+        }
+        self.stats.deadline_aborts += 1;
+
+        let error = StreamingError::DeadlineExceeded { chunks_sent, total_chunks };
+        self.state = StreamingState::Error(StreamingError::DeadlineExceeded { chunks_sent, total_chunks });
+        error
+    }
+
     fn send_message(&self, message: &StreamingMessage) -> Result<(), StreamingError> {
         let serialized = message.serialize();
         self.esp_now_sender.send(&serialized, 1000)?; // 1秒タイムアウト
@@ -310,7 +701,7 @@ mod tests {
     fn test_deserialize_error_conversion() {
         // DeserializeErrorからStreamingErrorへの変換テスト
         let short_data = vec![1, 2, 3]; // Too short
-        let result = StreamingMessage::deserialize(&short_data);
+        let result = StreamingMessage::deserialize(&short_data).map_err(StreamingError::from);
         
         assert!(result.is_err());
         match result {
@@ -352,4 +743,125 @@ mod tests {
         
         assert_eq!(reconstructed, original_data);
     }
+
+    #[test]
+    fn test_transmission_budget_and_abort_projection() {
+        let slot = Duration::from_secs(60);
+        assert_eq!(
+            transmission_budget(slot, Duration::from_secs(10), Duration::from_secs(5)),
+            Duration::from_secs(45)
+        );
+        assert_eq!(transmission_budget(slot, Duration::from_secs(58), Duration::from_secs(5)), Duration::ZERO);
+
+        let budget = Duration::from_secs(10);
+        // 送信済みが少ない間は期限を過ぎるまで続ける
+        assert!(!should_abort(Duration::from_secs(3), budget, 2, 100));
+        assert!(should_abort(Duration::from_secs(10), budget, 2, 100));
+        // 1チャンク1秒で残り6チャンクなら期限内、残り7チャンクなら打ち切る
+        assert!(!should_abort(Duration::from_secs(4), budget, 4, 10));
+        assert!(should_abort(Duration::from_secs(4), budget, 4, 11));
+    }
+
+    #[test]
+    fn test_send_frame_aborts_past_deadline_and_keeps_backlog() {
+        let config = StreamingCameraConfig::default().with_chunk_size(100);
+        let mut sender = StreamingSender::new(config).unwrap();
+        let image = vec![0x5A; 350];
+
+        sender.set_frame_budget(Some(Duration::from_secs(60)));
+        assert!(sender.send_frame(&image).is_ok());
+        assert_eq!(sender.take_backlog(), None);
+
+        sender.set_frame_budget(Some(Duration::ZERO));
+        sender.set_backlog_enabled(true);
+        let expected = StreamingError::DeadlineExceeded { chunks_sent: 0, total_chunks: 4 };
+        assert_eq!(sender.send_frame(&image), Err(expected));
+        assert!(sender.has_error());
+        assert_eq!(sender.get_stats().deadline_aborts, 1);
+        assert_eq!(sender.get_stats().frames_sent, 1);
+        assert_eq!(sender.abort_journal().len(), 1);
+        assert_eq!(sender.abort_journal()[0].frame_id, 2);
+        assert_eq!(sender.take_backlog(), Some(image.clone()));
+
+        for _ in 0..ABORT_JOURNAL_LEN {
+            let _ = sender.send_frame(&image);
+        }
+        assert_eq!(sender.abort_journal().len(), ABORT_JOURNAL_LEN);
+        assert_eq!(sender.abort_journal()[0].frame_id, 3);
+    }
+
+    #[test]
+    fn test_listen_gaps_receive_control_messages() {
+        let schedule = ListenSchedule::default();
+        assert!(!schedule.is_listen_point(0));
+        assert!(!schedule.is_listen_point(15));
+        assert!(schedule.is_listen_point(16));
+        assert!(schedule.is_listen_point(32));
+        assert!(!ListenSchedule { every_chunks: 0, gap: DEFAULT_LISTEN_GAP }.is_listen_point(16));
+
+        // 送信データや壊れたデータは制御メッセージとして扱わない
+        assert!(!deliver_control_message(&StreamingMessage::start_frame(1, 1).serialize()));
+        assert!(!deliver_control_message(&[0xFF; 4]));
+
+        let config = StreamingCameraConfig::default().with_chunk_size(10);
+        let mut sender = StreamingSender::new(config).unwrap();
+        sender.set_listen_schedule(Some(ListenSchedule { every_chunks: 16, gap: Duration::ZERO }));
+        assert!(deliver_control_message(&StreamingMessage::ack(5).serialize()));
+        assert!(deliver_control_message(&StreamingMessage::nack(6).serialize()));
+        // 別のフレームへの打ち切りの指示は無視する
+        assert!(deliver_control_message(&StreamingMessage::abort_frame(9, 0, 0, 0).serialize()));
+        assert!(deliver_control_message(&StreamingMessage::abort_frame(1, 0, 0, 0).serialize()));
+
+        let expected = StreamingError::AbortedByReceiver { chunks_sent: 16, total_chunks: 40 };
+        assert_eq!(sender.send_frame(&[0x5A; 400]), Err(expected));
+        assert!(sender.has_error());
+        assert_eq!(sender.get_stats().chunks_sent, 16);
+        assert_eq!(sender.get_stats().listen_gaps, 1);
+        assert_eq!(sender.get_stats().acks_received, 1);
+        assert_eq!(sender.get_stats().nacks_received, 1);
+        assert_eq!(sender.last_acked_sequence(), Some(5));
+
+        // 指示がなければ受信待ちを挟んで送り切る
+        assert!(sender.send_frame(&[0x5A; 400]).is_ok());
+        assert_eq!(sender.get_stats().listen_gaps, 3);
+    }
+
+    #[test]
+    fn test_gateway_reject_backs_off_or_requires_pairing() {
+        fn reject(reason: u8, retry_after_ms: u32) -> Vec<u8> {
+            let mut data = vec![0x0F, b'R', b'J', b'C', b'T', reason, 0, 0, 0, 0];
+            data.extend_from_slice(&retry_after_ms.to_le_bytes());
+            data
+        }
+        assert!(!deliver_reject_message(&StreamingMessage::ack(1).serialize()));
+
+        let config = StreamingCameraConfig::default().with_chunk_size(10);
+        let mut sender = StreamingSender::new(config).unwrap();
+        sender.set_listen_schedule(Some(ListenSchedule { every_chunks: 16, gap: Duration::ZERO }));
+
+        // 再試行までの時間が0なら次のフレームから送信できる
+        assert!(deliver_reject_message(&reject(0x01, 0)));
+        let expected = StreamingError::RejectedByGateway { reason: RejectReason::Quota, retry_after_ms: 0 };
+        assert_eq!(sender.send_frame(&[0x5A; 100]), Err(expected));
+        assert!(sender.send_frame(&[0x5A; 100]).is_ok());
+
+        // チェックサムエラーの多発なら再試行までの時間を待つ
+        assert!(deliver_reject_message(&reject(0x03, 60_000)));
+        let expected = StreamingError::RejectedByGateway { reason: RejectReason::ChecksumStorm, retry_after_ms: 60_000 };
+        assert_eq!(sender.send_frame(&[0x5A; 100]), Err(expected));
+        assert!(matches!(sender.send_frame(&[0x5A; 100]), Err(StreamingError::BackingOff { .. })));
+
+        // 未登録のデバイスならペアリングをやり直すまで送信しない
+        sender.retry_not_before = None;
+        assert!(deliver_reject_message(&reject(0x02, 600_000)));
+        assert!(sender.send_frame(&[0x5A; 100]).is_err());
+        assert!(sender.is_pairing_required());
+        assert_eq!(sender.send_frame(&[0x5A; 100]), Err(StreamingError::PairingRequired));
+        sender.mark_paired();
+        assert!(sender.send_frame(&[0x5A; 100]).is_ok());
+
+        assert_eq!(sender.get_stats().rejects_received, 3);
+        let reasons: Vec<_> = sender.reject_journal().iter().map(|record| record.reason).collect();
+        assert_eq!(reasons, vec![RejectReason::Quota, RejectReason::ChecksumStorm, RejectReason::UnknownDevice]);
+    }
 }
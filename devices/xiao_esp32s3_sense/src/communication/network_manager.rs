@@ -8,6 +8,8 @@ use esp_idf_svc::hal::modem::Modem;
 use log::info;
 use std::sync::{Arc, Mutex};
 use crate::communication::esp_now::EspNowReceiver;
+use crate::utils::EspNowRate;
+use crate::sys_wrappers::esp as sys;
 
 /// WiFiとESP-NOWの初期化を管理するモジュール
 pub struct NetworkManager;
@@ -20,6 +22,7 @@ impl NetworkManager {
         nvs_partition: &EspDefaultNvsPartition,
         wifi_tx_power_dbm: i8,
         wifi_init_delay_ms: u64,
+        esp_now_phy_rate: Option<EspNowRate>,
     ) -> anyhow::Result<BlockingWifi<EspWifi<'static>>> {
         info!("ESP-NOW用にWiFiをSTAモードで準備します。");
         
@@ -28,11 +31,9 @@ impl NetworkManager {
             sysloop.clone(),
         )?;
 
-        // 空のSSID/パスワードでWiFiを設定（ESP-NOW用）
+        // 空のSSID/パスワード（既定値）でWiFiを設定（ESP-NOW用）
         wifi.set_configuration(&esp_idf_svc::wifi::Configuration::Client(
             esp_idf_svc::wifi::ClientConfiguration {
-                ssid: "".try_into().unwrap(),
-                password: "".try_into().unwrap(),
                 auth_method: esp_idf_svc::wifi::AuthMethod::None,
                 ..Default::default()
             },
@@ -46,22 +47,23 @@ impl NetworkManager {
         esp_idf_svc::hal::delay::FreeRtos::delay_ms(wifi_init_delay_ms as u32); // 突入電流分散待機 2
 
         // WiFi送信パワーを設定（省電力化）
-        unsafe {
-            // ESP-IDF API expects power in 0.25 dBm units (quarter-dBm).
-            // config.rsで既に 2-20 dBm にクランプされているため、i16キャスト後の
-            // 8-80 は確実に i8 の範囲 (-128〜127) に収まる。
-            let scaled: i16 = i16::from(wifi_tx_power_dbm) * 4;
-            let power_quarter_dbm = scaled as i8;
+        match sys::set_max_tx_power_dbm(wifi_tx_power_dbm) {
+            Ok(()) => info!("WiFi送信パワーを {}dBm に設定しました。適用待機({}ms)...", wifi_tx_power_dbm, wifi_init_delay_ms),
+            // 送信パワー設定失敗時は、デフォルト値で動作するが、システム自体は停止させない
+            Err(e) => log::warn!("WiFi送信パワーの設定に失敗しました (エラーコード: {}) - デフォルトパワーで動作します", e.code()),
+        }
+        esp_idf_svc::hal::delay::FreeRtos::delay_ms(wifi_init_delay_ms as u32); // 突入電流分散待機 3
 
-            let err = esp_idf_svc::sys::esp_wifi_set_max_tx_power(power_quarter_dbm);
-            if err != esp_idf_svc::sys::ESP_OK {
-                // 送信パワー設定失敗時は、デフォルト値で動作するが、システム自体は停止させない
-                log::warn!("WiFi送信パワーの設定に失敗しました (エラーコード: {}) - デフォルトパワーで動作します", err);
-            } else {
-                info!("WiFi送信パワーを {}dBm に設定しました。適用待機({}ms)...", wifi_tx_power_dbm, wifi_init_delay_ms);
+        // ESP-NOWの送信レートを固定（未指定ならESP-IDFの既定値）
+        if let Some(rate) = esp_now_phy_rate {
+            match sys::set_espnow_rate(rate.phy_rate()) {
+                Ok(()) => info!("ESP-NOW送信レートを {} に固定しました", rate.name()),
+                Err(e) => log::warn!("ESP-NOW送信レート {} の設定に失敗しました (エラーコード: {}) - デフォルトレートで動作します", rate.name(), e.code()),
             }
         }
-        esp_idf_svc::hal::delay::FreeRtos::delay_ms(wifi_init_delay_ms as u32); // 突入電流分散待機 3
+        #[allow(unexpected_cfgs)]
+        let ampdu_tx = cfg!(esp_idf_esp_wifi_ampdu_tx_enabled);
+        info!("AMPDU送信: {}", if ampdu_tx { "有効" } else { "無効" });
         
         // WiFi状態の詳細確認
         let wifi_status = wifi.is_started();
@@ -74,8 +76,8 @@ impl NetworkManager {
               mac_addr[3], mac_addr[4], mac_addr[5]);
 
         // WiFi Power Saveを無効化
-        unsafe {
-            esp_idf_svc::sys::esp_wifi_set_ps(esp_idf_svc::sys::wifi_ps_type_t_WIFI_PS_NONE);
+        if let Err(e) = sys::disable_wifi_power_save() {
+            log::warn!("Wi-Fi Power Save の無効化に失敗しました (エラーコード: {})", e.code());
         }
         info!("Wi-Fi Power Save を無効化しました (ESP-NOW用)");
 
@@ -89,9 +91,8 @@ impl NetworkManager {
         info!("ESP-NOWを初期化中（送信＆受信機能付き）...");
         
         // ESP-NOWのメモリ設定を最適化
-        unsafe {
-            // ESP-NOWの送信バッファサイズを調整（デフォルトより小さく）
-            esp_idf_svc::sys::esp_wifi_set_storage(esp_idf_svc::sys::wifi_storage_t_WIFI_STORAGE_RAM);
+        if let Err(e) = sys::set_wifi_storage_ram() {
+            log::warn!("WiFi設定の保存先をRAMに変更できませんでした (エラーコード: {})", e.code());
         }
         
         let esp_now = EspNow::take()?;
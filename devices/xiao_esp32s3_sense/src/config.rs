@@ -1,4 +1,6 @@
+use crate::hardware::pin_map::{PinMap, PinMapError, TdsSensorPins, TempSensorPins};
 use crate::mac_address::MacAddress;
+use crate::utils::{DualSendMode, EspNowRate, ImageHashAlgo};
 
 /// アプリケーション設定
 ///
@@ -60,6 +62,13 @@ pub struct Config {
     #[default(4200)]
     adc_voltage_max_mv: u16,
 
+    // GPIO割り当て（起動時に PinMap::validate で検証）
+    #[default(4)]
+    voltage_adc_pin: u8,
+
+    #[default(21)]
+    status_led_pin: u8,
+
     // 温度センサー設定（DS18B20）
     #[default(true)]
     temp_sensor_enabled: bool,
@@ -108,11 +117,39 @@ pub struct Config {
     #[default(false)]
     debug_mode: bool,
 
+    /// この電圧（%）以下では周辺機器を初期化せずに長時間Deep Sleepする
+    #[default(0)]
+    critical_battery_percent: u8,
+
+    /// 電池残量が危機的な場合のDeep Sleep時間（秒、0なら sleep_duration_seconds_for_long）
+    #[default(0)]
+    critical_battery_sleep_seconds: u64,
+
     #[default(8)]
     wifi_tx_power_dbm: i8,
 
     #[default(200)]
     wifi_init_delay_ms: u64,
+
+    /// ESP-NOW送信レート（"1M"〜"54M"、"MCS0"〜"MCS7"。空ならESP-IDFの既定値）
+    #[default("")]
+    esp_now_phy_rate: &'static str,
+
+    /// 2台目の受信機のMACアドレス（空なら単一経路で送信）
+    #[default("")]
+    secondary_receiver_mac: &'static str,
+
+    /// 2系統送信の振り分け方式（"alternate" または "balance"）
+    #[default("alternate")]
+    dual_sender_mode: &'static str,
+
+    /// この失敗率（%）を超えた経路は振り分けから外す
+    #[default(50)]
+    dual_sender_max_failure_percent: u8,
+
+    /// 画像ハッシュのアルゴリズム（"sum"、"xxh64" または "sha256"）
+    #[default("xxh64")]
+    image_hash_algo: &'static str,
 }
 
 /// 設定エラー
@@ -130,11 +167,20 @@ pub enum ConfigError {
     MissingWifiSsid,
     #[error("WiFi パスワードが設定されていません")]
     MissingWifiPassword,
+    #[error("esp_now_phy_rate の値が無効です: {0} (例: 1M/24M/54M/MCS3)")]
+    InvalidEspNowPhyRate(String),
+    #[error("無効な2台目の受信機MACアドレス: {0}")]
+    InvalidSecondaryReceiverMac(String),
+    #[error("dual_sender_mode の値が無効です (alternate/balance): {0}")]
+    InvalidDualSenderMode(String),
+    #[error("image_hash_algo の値が無効です (sum/xxh64/sha256): {0}")]
+    InvalidImageHashAlgo(String),
+    #[error("GPIO割り当てが無効です: {0}")]
+    InvalidPinMap(#[from] PinMapError),
 }
 
 /// 目標時刻設定
 #[derive(Debug, Clone, Copy)] // Added Copy
-#[allow(dead_code)] // 将来的に時刻制御機能で使用予定
 pub struct TargetDigitsConfig {
     pub minute_last_digit: Option<u8>, // Changed to Option<u8>
     pub second_tens_digit: Option<u8>, // Changed to Option<u8>
@@ -194,6 +240,12 @@ pub struct AppConfig {
     /// ADC電圧最大値（ミリボルト）
     pub adc_voltage_max_mv: u16,
 
+    /// 電池電圧測定のADC入力GPIO番号（ADC1）
+    pub voltage_adc_pin: u8,
+
+    /// ステータスLEDのGPIO番号
+    pub status_led_pin: u8,
+
     // 温度センサー設定（DS18B20）
     /// 温度センサーの有効/無効
     pub temp_sensor_enabled: bool,
@@ -242,11 +294,32 @@ pub struct AppConfig {
     /// デバッグモード（詳細ログ出力）
     pub debug_mode: bool,
 
+    /// 電池残量が危機的とみなす電圧（%）
+    pub critical_battery_percent: u8,
+
+    /// 電池残量が危機的な場合のDeep Sleep時間（秒）
+    pub critical_battery_sleep_seconds: u64,
+
     /// WiFi送信パワー（dBm単位、範囲: 2-20）
     pub wifi_tx_power_dbm: i8,
 
     /// WiFi初期化時の各ステップ間の待機時間（ミリ秒）
     pub wifi_init_delay_ms: u64,
+
+    /// ESP-NOW送信レート（Noneで既定値）
+    pub esp_now_phy_rate: Option<EspNowRate>,
+
+    /// 2台目の受信機のMACアドレス（Noneで単一経路）
+    pub secondary_receiver_mac: Option<MacAddress>,
+
+    /// 2系統送信の振り分け方式
+    pub dual_sender_mode: DualSendMode,
+
+    /// 2系統送信で経路を除外する失敗率（%）
+    pub dual_sender_max_failure_percent: u8,
+
+    /// 画像ハッシュのアルゴリズム（HASHフレームの `HASH_ALGO` で宣言）
+    pub image_hash_algo: ImageHashAlgo,
 }
 
 /// メモリ管理設定
@@ -396,6 +469,12 @@ impl AppConfig {
         let bypass_voltage_threshold = config.bypass_voltage_threshold;
         let debug_mode = config.debug_mode;
 
+        // 危機的電圧でのスリープ時間を取得（0なら夜間の長時間スリープと同じ）
+        let critical_battery_sleep_seconds = match config.critical_battery_sleep_seconds {
+            0 => sleep_duration_seconds_for_long,
+            seconds => seconds,
+        };
+
         // WiFi送信パワー設定を取得（有効範囲 2〜20 dBm にクランプ）
         // esp_wifi_set_max_tx_power は範囲外の値でもエラーにならない場合があるが、
         // 安全のため仕様範囲内に収める
@@ -408,6 +487,29 @@ impl AppConfig {
             wifi_tx_power_dbm_raw
         };
 
+        // ESP-NOW送信レートを取得（空なら既定値）
+        let esp_now_phy_rate = match config.esp_now_phy_rate.trim() {
+            "" => None,
+            name => Some(
+                EspNowRate::parse(name).ok_or_else(|| ConfigError::InvalidEspNowPhyRate(name.to_string()))?,
+            ),
+        };
+
+        // 2系統送信の設定を取得（2台目の受信機が空なら無効）
+        let secondary_receiver_mac = match config.secondary_receiver_mac.trim() {
+            "" => None,
+            mac => Some(
+                MacAddress::from_str(mac)
+                    .map_err(|_| ConfigError::InvalidSecondaryReceiverMac(mac.to_string()))?,
+            ),
+        };
+        let dual_sender_mode = DualSendMode::parse(config.dual_sender_mode)
+            .ok_or_else(|| ConfigError::InvalidDualSenderMode(config.dual_sender_mode.to_string()))?;
+
+        // 画像ハッシュのアルゴリズムを取得
+        let image_hash_algo = ImageHashAlgo::parse(config.image_hash_algo)
+            .ok_or_else(|| ConfigError::InvalidImageHashAlgo(config.image_hash_algo.to_string()))?;
+
         // 温度センサー設定を取得
         let temp_sensor_enabled = config.temp_sensor_enabled;
         let temp_sensor_power_pin = config.temp_sensor_power_pin;
@@ -424,7 +526,7 @@ impl AppConfig {
         let tds_calibrate_reference_ec = config.tds_calibrate_reference_ec;
         let tds_temp_coefficient = config.tds_temp_coefficient;
 
-        Ok(AppConfig {
+        let app_config = AppConfig {
             receiver_mac,
             sleep_duration_seconds,
             sleep_duration_seconds_for_medium,
@@ -442,6 +544,8 @@ impl AppConfig {
             esp_now_chunk_delay_ms,
             adc_voltage_min_mv,
             adc_voltage_max_mv,
+            voltage_adc_pin: config.voltage_adc_pin,
+            status_led_pin: config.status_led_pin,
             temp_sensor_enabled,
             temp_sensor_power_pin,
             temp_sensor_data_pin,
@@ -457,9 +561,36 @@ impl AppConfig {
             force_camera_test,
             bypass_voltage_threshold,
             debug_mode,
+            critical_battery_percent: config.critical_battery_percent.min(100),
+            critical_battery_sleep_seconds,
             wifi_tx_power_dbm,
             wifi_init_delay_ms: config.wifi_init_delay_ms,
-        })
+            esp_now_phy_rate,
+            secondary_receiver_mac,
+            dual_sender_mode,
+            dual_sender_max_failure_percent: config.dual_sender_max_failure_percent.min(100),
+            image_hash_algo,
+        };
+        app_config.pin_map().validate()?;
+        Ok(app_config)
+    }
+
+    /// 設定されたセンサー・LEDのGPIO割り当て（無効なセンサーのピンは含まない）
+    pub fn pin_map(&self) -> PinMap {
+        // 負の値などGPIO番号にならない値は、検証で使用できないピンとして報告される
+        let gpio = |pin: i32| u8::try_from(pin).unwrap_or(u8::MAX);
+        PinMap {
+            voltage_adc_pin: self.voltage_adc_pin,
+            status_led_pin: self.status_led_pin,
+            temp_sensor: self.temp_sensor_enabled.then(|| TempSensorPins {
+                power_pin: gpio(self.temp_sensor_power_pin),
+                data_pin: gpio(self.temp_sensor_data_pin),
+            }),
+            tds_sensor: self.tds_sensor_enabled.then_some(TdsSensorPins {
+                power_pin: self.tds_sensor_power_pin,
+                adc_pin: self.tds_sensor_adc_pin,
+            }),
+        }
     }
 }
 
@@ -549,6 +680,8 @@ mod tests {
             esp_now_chunk_delay_ms: 10, // Default delay
             adc_voltage_min_mv: 3300, // Default min voltage
             adc_voltage_max_mv: 4200, // Default max voltage
+            voltage_adc_pin: 4,
+            status_led_pin: 21,
             // デフォルトのセンサー設定
             temp_sensor_enabled: true,
             temp_sensor_power_pin: 2,
@@ -565,8 +698,15 @@ mod tests {
             force_camera_test,
             bypass_voltage_threshold,
             debug_mode,
+            critical_battery_percent: 0,
+            critical_battery_sleep_seconds: sleep_duration_for_long,
             wifi_tx_power_dbm: 8,
             wifi_init_delay_ms: 1000,
+            esp_now_phy_rate: None,
+            secondary_receiver_mac: None,
+            dual_sender_mode: DualSendMode::Alternate,
+            dual_sender_max_failure_percent: 50,
+            image_hash_algo: ImageHashAlgo::default(),
         }))
     }
 
@@ -838,4 +978,41 @@ mod tests {
         assert_eq!(target_conf.minute_last_digit, None);
         assert_eq!(target_conf.second_tens_digit, Some(3));
     }
+
+    #[test]
+    fn test_pin_map_only_includes_enabled_sensors() {
+        let mut config = simulate_app_config_creation(
+            "00:11:22:33:44:55",
+            60,
+            1200,
+            3600,
+            "SVGA",
+            false,
+            255,
+            255,
+            255,
+            "ssid",
+            "pass",
+            "Asia/Tokyo",
+            false, // force_camera_test
+            false, // bypass_voltage_threshold
+            false, // debug_mode
+        )
+        .unwrap();
+        // TDSセンサーの電源ピン(GPIO4)が電圧測定のADC入力と重なる
+        assert!(matches!(
+            config.pin_map().validate(),
+            Err(PinMapError::Duplicate { pin: 4, .. })
+        ));
+
+        config.tds_sensor_enabled = false;
+        assert_eq!(config.pin_map().tds_sensor, None);
+        assert_eq!(config.pin_map().validate(), Ok(()));
+
+        config.temp_sensor_data_pin = -1;
+        assert!(matches!(
+            config.pin_map().validate(),
+            Err(PinMapError::UnavailablePin { role: "temp_sensor_data_pin", .. })
+        ));
+    }
 }
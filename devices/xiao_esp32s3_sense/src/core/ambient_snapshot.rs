@@ -0,0 +1,112 @@
+//! シャッター時点の環境スナップショット
+//!
+//! 温度・電池残量（照度センサーがあれば照度も）を撮影の直後に測り、HASHフレームの
+//! `AMB_*` フィールドとして画像のメタデータに載せます。撮影の数分前に測った値ではなく、
+//! 画像と同じ時点の値を記録するためです。
+//!
+//! `AMB_DT_MS` はシャッターから最も遅れて測った値までの時間（ms）です。
+
+use crate::utils::{Celsius, Percent};
+
+/// シャッター時点の環境の測定値
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct AmbientSnapshot {
+    pub temperature: Option<Celsius>,
+    pub battery: Option<Percent>,
+    /// 照度（lx、照度センサーがない機種ではNone）
+    pub lux: Option<f32>,
+    /// シャッターから最も遅れて測った値までの時間（ms）
+    pub max_offset_ms: u32,
+}
+
+impl AmbientSnapshot {
+    /// 温度を追加（`offset_ms` はシャッターから測定までの時間）
+    pub fn with_temperature(mut self, temperature: Option<Celsius>, offset_ms: u32) -> Self {
+        if temperature.is_some() {
+            self.temperature = temperature;
+            self.max_offset_ms = self.max_offset_ms.max(offset_ms);
+        }
+        self
+    }
+
+    /// 電池残量を追加（測定に失敗した値は載せない）
+    pub fn with_battery(mut self, battery: Percent, offset_ms: u32) -> Self {
+        if battery.is_valid() {
+            self.battery = Some(battery);
+            self.max_offset_ms = self.max_offset_ms.max(offset_ms);
+        }
+        self
+    }
+
+    /// 照度を追加
+    pub fn with_lux(mut self, lux: Option<f32>, offset_ms: u32) -> Self {
+        if lux.is_some() {
+            self.lux = lux;
+            self.max_offset_ms = self.max_offset_ms.max(offset_ms);
+        }
+        self
+    }
+
+    /// 測定値が1つもないか
+    pub fn is_empty(&self) -> bool {
+        self.temperature.is_none() && self.battery.is_none() && self.lux.is_none()
+    }
+
+    /// HASHフレームに付加するメタデータ（`,AMB_TEMP:23.5,AMB_BATT:82,AMB_DT_MS:640`、測定値がなければ空）
+    pub fn metadata_fields(&self) -> String {
+        if self.is_empty() {
+            return String::new();
+        }
+        let mut fields = String::new();
+        if let Some(temperature) = self.temperature {
+            fields.push_str(&format!(",AMB_TEMP:{:.1}", temperature.value()));
+        }
+        if let Some(battery) = self.battery {
+            fields.push_str(&format!(",AMB_BATT:{}", battery.value()));
+        }
+        if let Some(lux) = self.lux {
+            fields.push_str(&format!(",AMB_LUX:{:.0}", lux));
+        }
+        fields.push_str(&format!(",AMB_DT_MS:{}", self.max_offset_ms));
+        fields
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_metadata_fields_with_all_readings() {
+        let snapshot = AmbientSnapshot::default()
+            .with_battery(Percent::new(82), 15)
+            .with_temperature(Some(Celsius::new(23.46)), 640)
+            .with_lux(Some(1234.4), 20);
+
+        assert_eq!(
+            snapshot.metadata_fields(),
+            ",AMB_TEMP:23.5,AMB_BATT:82,AMB_LUX:1234,AMB_DT_MS:640"
+        );
+    }
+
+    #[test]
+    fn test_missing_readings_are_omitted() {
+        let snapshot = AmbientSnapshot::default()
+            .with_temperature(None, 900)
+            .with_battery(Percent::new(64), 12);
+
+        // 測れなかった値の時間は AMB_DT_MS に含めない
+        assert_eq!(snapshot.metadata_fields(), ",AMB_BATT:64,AMB_DT_MS:12");
+        assert_eq!(snapshot.temperature, None);
+    }
+
+    #[test]
+    fn test_empty_snapshot_adds_no_fields() {
+        let snapshot = AmbientSnapshot::default()
+            .with_temperature(None, 10)
+            .with_battery(Percent::INVALID, 10);
+
+        assert!(snapshot.is_empty());
+        assert_eq!(snapshot.metadata_fields(), "");
+    }
+}
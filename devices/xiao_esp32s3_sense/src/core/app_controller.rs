@@ -1,14 +1,20 @@
-use log::{error, info, warn};
+use log::{error, info};
 use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
 use crate::config::AppConfig;
 use crate::communication::esp_now::{EspNowReceiver};
+use crate::core::rtc_manager::RtcManager;
+use crate::core::sleep_decision::{resolve_sleep, SleepPolicy, SleepSchedule, FALLBACK_SLEEP_SECONDS};
 use crate::power::sleep::{SleepManager, SleepType, DeepSleepPlatform, LightSleepPlatform};
+use crate::sys_wrappers::{self, esp::EspSys};
 
 /// アプリケーションの主要な制御フローを管理するモジュール
 pub struct AppController;
 
 impl AppController {
     /// スリープコマンドを受信して最適なモード（Deep/Light）でスリープを実行
+    ///
+    /// スリープ時間の優先順位と理由コードは `sleep_decision` を参照してください。
     pub fn handle_sleep_with_server_command<D: DeepSleepPlatform, L: LightSleepPlatform>(
         esp_now_receiver: &EspNowReceiver,
         sleep_manager: &SleepManager<D, L>,
@@ -21,26 +27,30 @@ impl AppController {
         // ESP-NOW受信状態をリセット（前回の受信データをクリア）
         EspNowReceiver::reset_receiver_state();
         
-        let duration = match esp_now_receiver.wait_for_sleep_command(config.sleep_command_timeout_seconds as u32) {
-            Some(duration_seconds) => {
-                if duration_seconds > 0 {
-                    info!(
-                        "✓ サーバーからスリープ時間を受信: {}秒。",
-                        duration_seconds
-                    );
-                    duration_seconds as u64
-                } else {
-                    warn!("無効なスリープ時間 (0秒) を受信。デフォルト時間を使用します。");
-                    config.sleep_duration_seconds
-                }
-            }
-            None => {
-                warn!("✗ スリープコマンドを受信できませんでした。デフォルト時間を使用します。");
-                config.sleep_duration_seconds
-            }
+        let policy = SleepPolicy {
+            default_seconds: config.sleep_duration_seconds,
+            schedule: config
+                .target_digits_config
+                .and_then(|digits| SleepSchedule::new(digits.minute_last_digit, digits.second_tens_digit)),
         };
+        let decision = RtcManager::with_sleep_journal(|journal| {
+            resolve_sleep(
+                esp_now_receiver,
+                config.sleep_command_timeout_seconds as u32,
+                Self::now_unix,
+                &policy,
+                journal,
+            )
+        });
         
-        Self::secure_shutdown_and_sleep(sleep_manager, duration, config)
+        Self::secure_shutdown_and_sleep(sleep_manager, decision.seconds, config)
+    }
+
+    /// 現在時刻（UNIX秒、時刻が未同期なら起動からの経過程度の小さな値）
+    fn now_unix() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs())
     }
 
     /// 無線停止、GPIO Hold設定を行い、安全にスリープへ移行
@@ -52,50 +62,26 @@ impl AppController {
         info!("=== スリープ準備シーケンスを開始します ({}秒) ===", duration_seconds);
 
         // スリープタイプを判定（Deep SleepかLight Sleepか）
-        // 10分(600秒)がデフォルトの閾値
-        let is_light_sleep = duration_seconds <= 600;
+        // 実際に選択されるモードと停止処理を揃えるため、SleepManagerの判定を使う
+        let is_light_sleep = sleep_manager.sleep_type_for(duration_seconds) == SleepType::Light;
 
         if !is_light_sleep {
             info!("DEEP SLEEPのためのハードウェア遮断を実行します...");
-            // [PHASE 8] ステータスLED (GPIO 21) を強制的に消灯・固定
-            unsafe {
-                esp_idf_sys::gpio_set_level(21, 1);
-                esp_idf_sys::gpio_hold_en(21);
-                
-                // [PHASE 8] センサー用電源ピン (GPIO 2, 5) も確実にオフに固定
-                esp_idf_sys::gpio_set_level(2, 0); // Temp sensor power
-                esp_idf_sys::gpio_hold_en(2);
-                esp_idf_sys::gpio_set_level(5, 0); // TDS sensor power
-                esp_idf_sys::gpio_hold_en(5);
-            }
+            // [PHASE 8] ステータスLED (GPIO 21) を強制的に消灯し、センサー用電源ピン (GPIO 2, 5) もオフにして固定
+            sys_wrappers::park_pins_for_sleep(&EspSys, true);
             info!("✓ LEDとセンサー電源ピンをDeep Sleep用にHoldしました");
 
             // [PHASE 8] 無線機能を物理的に停止（電力ドレインの最大の原因の一つ）
-            unsafe {
-                let _ = esp_idf_sys::esp_now_deinit();
-                let _ = esp_idf_sys::esp_wifi_stop();
-                let _ = esp_idf_sys::esp_wifi_deinit();
-            }
+            sys_wrappers::esp::shutdown_radio();
             info!("✓ WiFi/ESP-NOWスタックを完全にシャットダウンしました");
         } else {
             info!("LIGHT SLEEPのため、周辺機器の状態を保持しますが、無線(RF)は完全に停止します。");
             // [PHASE 10] 無線機能を完全に停止（復帰後の再初期化を前提とする）
-            unsafe {
-                let _ = esp_idf_sys::esp_now_deinit();
-                let _ = esp_idf_sys::esp_wifi_stop();
-                let _ = esp_idf_sys::esp_wifi_deinit();
-                
-                // [PHASE 11] Light Sleep中もセンサー電源は不要なのでOFFにする
-                // リーク電流を防ぐために明示的にLow出力を行う
-                esp_idf_sys::gpio_set_level(2, 0); // Temp sensor power
-                esp_idf_sys::gpio_set_level(5, 0); // TDS sensor power
-                // Light SleepではGPIO状態が保持されるため、Holdは必須ではないが
-                // 念のためDeep Sleep同様に電源ラインは落としておく
-            }
-            // ステータスLEDは消灯
-            unsafe {
-                esp_idf_sys::gpio_set_level(21, 1);
-            }
+            sys_wrappers::esp::shutdown_radio();
+
+            // [PHASE 11] Light Sleep中もセンサー電源は不要なのでOFFにし、ステータスLEDは消灯
+            // Light SleepではGPIO状態が保持されるため、Holdはしない
+            sys_wrappers::park_pins_for_sleep(&EspSys, false);
         }
 
         // 最適化されたスリープを実行
@@ -109,7 +95,7 @@ impl AppController {
         error_msg: &str,
     ) -> anyhow::Result<SleepType> {
         error!("{}", error_msg);
-        sleep_manager.sleep_optimized(config.sleep_duration_seconds)
+        sleep_manager.sleep_with_fallback(config.sleep_duration_seconds, FALLBACK_SLEEP_SECONDS)
     }
 }
 
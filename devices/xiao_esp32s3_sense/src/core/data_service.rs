@@ -1,9 +1,10 @@
 use esp_idf_svc::hal::delay::FreeRtos;
 use log::{error, info, warn};
 
-use crate::communication::esp_now::EspNowSender;
+use crate::communication::esp_now::ImageTransport;
 use crate::config::AppConfig;
-use crate::core::MeasuredData;
+use crate::core::{AmbientSnapshot, MeasuredData};
+use crate::utils::Percent;
 use crate::hardware::camera::{CameraController, CamConfig, reset_camera_pins};
 use crate::hardware::led::StatusLed;
 
@@ -19,14 +20,14 @@ pub struct DataService;
 impl DataService {
     /// ADC電圧レベルに基づいて画像キャプチャを実行
     pub fn capture_image_if_voltage_sufficient(
-        voltage_percent: u8,
+        voltage_percent: Percent,
         camera_pins: crate::hardware::CameraPins,
         app_config: &AppConfig,
         led: &mut StatusLed,
     ) -> anyhow::Result<Option<Vec<u8>>> {
         // デバッグモードの場合は詳細ログを出力
         if app_config.debug_mode {
-            info!("🔧 デバッグ: 画像キャプチャ開始 - 電圧:{}%, force_camera_test:{}, bypass_voltage_threshold:{}", 
+            info!("🔧 デバッグ: 画像キャプチャ開始 - 電圧:{}, force_camera_test:{}, bypass_voltage_threshold:{}", 
                 voltage_percent, app_config.force_camera_test, app_config.bypass_voltage_threshold);
         }
 
@@ -36,11 +37,11 @@ impl DataService {
                 info!("🔧 デバッグ: 電圧閾値チェックをバイパス中");
             }
             true
-        } else if voltage_percent <= LOW_VOLTAGE_THRESHOLD_PERCENT {
-            warn!("ADC電圧が低すぎるため画像キャプチャをスキップします: {}%", voltage_percent);
+        } else if voltage_percent.value() <= LOW_VOLTAGE_THRESHOLD_PERCENT {
+            warn!("ADC電圧が低すぎるため画像キャプチャをスキップします: {}", voltage_percent);
             false
-        } else if voltage_percent >= 255 {
-            warn!("ADC電圧測定値が異常です: {}%", voltage_percent);
+        } else if !voltage_percent.is_valid() {
+            warn!("ADC電圧測定値が異常です: {}", voltage_percent);
             false
         } else {
             true
@@ -57,7 +58,7 @@ impl DataService {
             return Ok(None);
         }
 
-        info!("画像キャプチャを開始 (電圧:{}%, 強制実行:{})", voltage_percent, force_capture);
+        info!("画像キャプチャを開始 (電圧:{}, 強制実行:{})", voltage_percent, force_capture);
         led.turn_on()?;
 
         // カメラ初期化とキャプチャ
@@ -119,7 +120,7 @@ impl DataService {
     /// 測定データを送信
     pub fn transmit_data(
         app_config: &AppConfig,
-        esp_now_sender: &EspNowSender,
+        esp_now_sender: &dyn ImageTransport,
         led: &mut StatusLed,
         measured_data: MeasuredData,
     ) -> anyhow::Result<()> {
@@ -138,8 +139,7 @@ impl DataService {
                 (vec![], DUMMY_HASH.to_string())
             } else {
                 info!("画像データを送信中: {} bytes", data.len());
-                // 簡単なハッシュ計算（画像サイズとチェックサムベース）
-                let hash = format!("{:08x}{:08x}", data.len(), data.iter().map(|&b| b as u32).sum::<u32>());
+                let hash = app_config.image_hash_algo.hash(&data);
                 (data, hash)
             }
         } else {
@@ -172,12 +172,20 @@ impl DataService {
         let datetime = chrono::DateTime::from_timestamp(current_time, 0).unwrap_or_default();
         let formatted_time = datetime.format("%Y/%m/%d %H:%M:%S%.3f").to_string();
 
+        // シャッター時点の環境の測定値はハッシュアルゴリズムの後に付加する
+        let ambient_fields = measured_data
+            .ambient
+            .as_ref()
+            .map_or_else(String::new, AmbientSnapshot::metadata_fields);
+        let metadata_fields = format!("{}{}", app_config.image_hash_algo.metadata_field(), ambient_fields);
+
         match esp_now_sender.send_hash_frame(
             &_hash, 
             measured_data.voltage_percent, 
             measured_data.temperature_celsius,
             measured_data.tds_voltage,
-            &formatted_time
+            &formatted_time,
+            &metadata_fields,
         ) {
             Ok(_) => {
                 info!("HASHフレームの送信が完了しました");
@@ -215,11 +223,12 @@ impl DataService {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::utils::{Celsius, Millivolts, Ppm};
 
     #[test]
     fn test_measured_data_new() {
-        let data = MeasuredData::new(50, None);
-        assert_eq!(data.voltage_percent, 50);
+        let data = MeasuredData::new(Percent::new(50), None);
+        assert_eq!(data.voltage_percent, Percent::new(50));
         assert!(data.image_data.is_none());
         assert!(data.temperature_celsius.is_none());
         assert!(data.tds_voltage.is_none());
@@ -229,26 +238,26 @@ mod tests {
 
     #[test]
     fn test_measured_data_with_temperature() {
-        let data = MeasuredData::new(75, None)
-            .with_temperature(Some(25.5));
+        let data = MeasuredData::new(Percent::new(75), None)
+            .with_temperature(Some(Celsius::new(25.5)));
         
-        assert_eq!(data.voltage_percent, 75);
-        assert_eq!(data.temperature_celsius, Some(25.5));
+        assert_eq!(data.voltage_percent, Percent::new(75));
+        assert_eq!(data.temperature_celsius, Some(Celsius::new(25.5)));
     }
 
     #[test]
     fn test_measured_data_with_tds() {
-        let data = MeasuredData::new(80, None)
-            .with_tds_voltage(Some(1.5))
-            .with_tds(Some(450.0));
+        let data = MeasuredData::new(Percent::new(80), None)
+            .with_tds_voltage(Some(Millivolts::from_volts(1.5)))
+            .with_tds(Some(Ppm::new(450.0)));
         
-        assert_eq!(data.tds_voltage, Some(1.5));
-        assert_eq!(data.tds_ppm, Some(450.0));
+        assert_eq!(data.tds_voltage, Some(Millivolts::from_volts(1.5)));
+        assert_eq!(data.tds_ppm, Some(Ppm::new(450.0)));
     }
 
     #[test]
     fn test_measured_data_add_warning() {
-        let mut data = MeasuredData::new(30, None);
+        let mut data = MeasuredData::new(Percent::new(30), None);
         data.add_warning("Low voltage detected".to_string());
         data.add_warning("Sensor timeout".to_string());
         
@@ -259,7 +268,7 @@ mod tests {
 
     #[test]
     fn test_get_summary_voltage_only() {
-        let data = MeasuredData::new(85, None);
+        let data = MeasuredData::new(Percent::new(85), None);
         let summary = data.get_summary();
         
         assert_eq!(summary, "電圧:85%");
@@ -267,8 +276,8 @@ mod tests {
 
     #[test]
     fn test_get_summary_with_temperature() {
-        let data = MeasuredData::new(70, None)
-            .with_temperature(Some(23.7));
+        let data = MeasuredData::new(Percent::new(70), None)
+            .with_temperature(Some(Celsius::new(23.7)));
         let summary = data.get_summary();
         
         assert_eq!(summary, "電圧:70%, 温度:23.7°C");
@@ -276,9 +285,9 @@ mod tests {
 
     #[test]
     fn test_get_summary_with_tds() {
-        let data = MeasuredData::new(60, None)
-            .with_tds_voltage(Some(1.23))
-            .with_tds(Some(567.8));
+        let data = MeasuredData::new(Percent::new(60), None)
+            .with_tds_voltage(Some(Millivolts::from_volts(1.23)))
+            .with_tds(Some(Ppm::new(567.8)));
         let summary = data.get_summary();
         
         assert_eq!(summary, "電圧:60%, TDS電圧:1.23V, TDS:567.8ppm");
@@ -287,7 +296,7 @@ mod tests {
     #[test]
     fn test_get_summary_with_image() {
         let image_data = vec![1, 2, 3, 4, 5];
-        let data = MeasuredData::new(90, Some(image_data));
+        let data = MeasuredData::new(Percent::new(90), Some(image_data));
         let summary = data.get_summary();
         
         assert_eq!(summary, "電圧:90%, 画像:5bytes");
@@ -295,7 +304,7 @@ mod tests {
 
     #[test]
     fn test_get_summary_with_warnings() {
-        let mut data = MeasuredData::new(40, None);
+        let mut data = MeasuredData::new(Percent::new(40), None);
         data.add_warning("Warning 1".to_string());
         data.add_warning("Warning 2".to_string());
         let summary = data.get_summary();
@@ -306,10 +315,10 @@ mod tests {
     #[test]
     fn test_get_summary_full_data() {
         let image_data = vec![0; 1024];
-        let mut data = MeasuredData::new(95, Some(image_data))
-            .with_temperature(Some(26.3))
-            .with_tds_voltage(Some(2.15))
-            .with_tds(Some(890.5));
+        let mut data = MeasuredData::new(Percent::new(95), Some(image_data))
+            .with_temperature(Some(Celsius::new(26.3)))
+            .with_tds_voltage(Some(Millivolts::from_volts(2.15)))
+            .with_tds(Some(Ppm::new(890.5)));
         data.add_warning("Test warning".to_string());
         
         let summary = data.get_summary();
@@ -1,45 +1,60 @@
+use crate::core::ambient_snapshot::AmbientSnapshot;
+use crate::utils::{Celsius, Millivolts, Percent, Ppm};
+
 /// 測定データ構造体（ハードウェア非依存）
 #[derive(Debug, Clone, PartialEq)]
 pub struct MeasuredData {
-    pub voltage_percent: u8,
+    pub voltage_percent: Percent,
     pub image_data: Option<Vec<u8>>,
-    pub temperature_celsius: Option<f32>,
-    pub tds_voltage: Option<f32>,
-    pub tds_ppm: Option<f32>,
+    /// 補正後の温度
+    pub temperature_celsius: Option<Celsius>,
+    /// TDSセンサーの出力電圧
+    pub tds_voltage: Option<Millivolts>,
+    /// 温度補正後のTDS濃度
+    pub tds_ppm: Option<Ppm>,
+    /// シャッター時点の環境の測定値（撮影しなかった場合はNone）
+    pub ambient: Option<AmbientSnapshot>,
     pub sensor_warnings: Vec<String>,
 }
 
 impl MeasuredData {
     /// 新しいMeasuredDataインスタンスを作成
-    pub fn new(voltage_percent: u8, image_data: Option<Vec<u8>>) -> Self {
+    pub fn new(voltage_percent: Percent, image_data: Option<Vec<u8>>) -> Self {
         Self {
             voltage_percent,
             image_data,
             temperature_celsius: None,
             tds_voltage: None,
             tds_ppm: None,
+            ambient: None,
             sensor_warnings: Vec::new(),
         }
     }
 
     /// 温度データを追加
-    pub fn with_temperature(mut self, temperature: Option<f32>) -> Self {
+    pub fn with_temperature(mut self, temperature: Option<Celsius>) -> Self {
         self.temperature_celsius = temperature;
         self
     }
 
     /// TDS電圧データを追加
-    pub fn with_tds_voltage(mut self, voltage: Option<f32>) -> Self {
+    pub fn with_tds_voltage(mut self, voltage: Option<Millivolts>) -> Self {
         self.tds_voltage = voltage;
         self
     }
     
     /// TDSデータを追加
-    pub fn with_tds(mut self, tds: Option<f32>) -> Self {
+    pub fn with_tds(mut self, tds: Option<Ppm>) -> Self {
         self.tds_ppm = tds;
         self
     }
 
+    /// シャッター時点の環境の測定値を追加
+    pub fn with_ambient(mut self, ambient: Option<AmbientSnapshot>) -> Self {
+        self.ambient = ambient;
+        self
+    }
+
     /// 警告メッセージを追加
     pub fn add_warning(&mut self, warning: String) {
         self.sensor_warnings.push(warning);
@@ -47,18 +62,18 @@ impl MeasuredData {
 
     /// 測定データのサマリを取得
     pub fn get_summary(&self) -> String {
-        let mut parts = vec![format!("電圧:{}%", self.voltage_percent)];
+        let mut parts = vec![format!("電圧:{}", self.voltage_percent)];
 
         if let Some(temp) = self.temperature_celsius {
-            parts.push(format!("温度:{:.1}°C", temp));
+            parts.push(format!("温度:{}", temp));
         }
 
         if let Some(voltage) = self.tds_voltage {
-            parts.push(format!("TDS電圧:{:.2}V", voltage));
+            parts.push(format!("TDS電圧:{:.2}V", voltage.as_volts()));
         }
 
         if let Some(tds) = self.tds_ppm {
-            parts.push(format!("TDS:{:.1}ppm", tds));
+            parts.push(format!("TDS:{}", tds));
         }
 
         if let Some(ref image_data) = self.image_data {
@@ -79,65 +94,75 @@ mod tests {
 
     #[test]
     fn test_new_creates_minimal_data() {
-        let data = MeasuredData::new(50, None);
+        let data = MeasuredData::new(Percent::new(50), None);
         
-        assert_eq!(data.voltage_percent, 50);
+        assert_eq!(data.voltage_percent, Percent::new(50));
         assert_eq!(data.image_data, None);
         assert_eq!(data.temperature_celsius, None);
         assert_eq!(data.tds_voltage, None);
         assert_eq!(data.tds_ppm, None);
+        assert_eq!(data.ambient, None);
         assert_eq!(data.sensor_warnings.len(), 0);
     }
 
     #[test]
     fn test_new_with_image_data() {
         let image = vec![0xFF, 0xD8, 0xFF, 0xE0]; // JPEG header
-        let data = MeasuredData::new(75, Some(image.clone()));
+        let data = MeasuredData::new(Percent::new(75), Some(image.clone()));
         
-        assert_eq!(data.voltage_percent, 75);
+        assert_eq!(data.voltage_percent, Percent::new(75));
         assert_eq!(data.image_data, Some(image));
     }
 
     #[test]
     fn test_builder_pattern_with_temperature() {
-        let data = MeasuredData::new(60, None)
-            .with_temperature(Some(25.5));
+        let data = MeasuredData::new(Percent::new(60), None)
+            .with_temperature(Some(Celsius::new(25.5)));
         
-        assert_eq!(data.temperature_celsius, Some(25.5));
+        assert_eq!(data.temperature_celsius, Some(Celsius::new(25.5)));
     }
 
     #[test]
     fn test_builder_pattern_with_tds_voltage() {
-        let data = MeasuredData::new(70, None)
-            .with_tds_voltage(Some(2.5));
+        let data = MeasuredData::new(Percent::new(70), None)
+            .with_tds_voltage(Some(Millivolts::from_volts(2.5)));
         
-        assert_eq!(data.tds_voltage, Some(2.5));
+        assert_eq!(data.tds_voltage, Some(Millivolts::from_volts(2.5)));
     }
 
     #[test]
     fn test_builder_pattern_with_tds() {
-        let data = MeasuredData::new(80, None)
-            .with_tds(Some(450.0));
+        let data = MeasuredData::new(Percent::new(80), None)
+            .with_tds(Some(Ppm::new(450.0)));
         
-        assert_eq!(data.tds_ppm, Some(450.0));
+        assert_eq!(data.tds_ppm, Some(Ppm::new(450.0)));
     }
 
     #[test]
     fn test_builder_pattern_chaining() {
-        let data = MeasuredData::new(90, None)
-            .with_temperature(Some(26.3))
-            .with_tds_voltage(Some(1.8))
-            .with_tds(Some(320.5));
+        let data = MeasuredData::new(Percent::new(90), None)
+            .with_temperature(Some(Celsius::new(26.3)))
+            .with_tds_voltage(Some(Millivolts::from_volts(1.8)))
+            .with_tds(Some(Ppm::new(320.5)));
         
-        assert_eq!(data.voltage_percent, 90);
-        assert_eq!(data.temperature_celsius, Some(26.3));
-        assert_eq!(data.tds_voltage, Some(1.8));
-        assert_eq!(data.tds_ppm, Some(320.5));
+        assert_eq!(data.voltage_percent, Percent::new(90));
+        assert_eq!(data.temperature_celsius, Some(Celsius::new(26.3)));
+        assert_eq!(data.tds_voltage, Some(Millivolts::from_volts(1.8)));
+        assert_eq!(data.tds_ppm, Some(Ppm::new(320.5)));
+    }
+
+    #[test]
+    fn test_builder_pattern_with_ambient() {
+        let ambient = AmbientSnapshot::default().with_temperature(Some(Celsius::new(24.1)), 700);
+        let data = MeasuredData::new(Percent::new(80), None).with_ambient(Some(ambient));
+
+        assert_eq!(data.ambient, Some(ambient));
+        assert_eq!(data.get_summary(), "電圧:80%");
     }
 
     #[test]
     fn test_add_warning() {
-        let mut data = MeasuredData::new(50, None);
+        let mut data = MeasuredData::new(Percent::new(50), None);
         
         data.add_warning("温度センサーエラー".to_string());
         data.add_warning("TDSセンサー未接続".to_string());
@@ -149,7 +174,7 @@ mod tests {
 
     #[test]
     fn test_get_summary_minimal() {
-        let data = MeasuredData::new(50, None);
+        let data = MeasuredData::new(Percent::new(50), None);
         let summary = data.get_summary();
         
         assert_eq!(summary, "電圧:50%");
@@ -157,8 +182,8 @@ mod tests {
 
     #[test]
     fn test_get_summary_with_temperature() {
-        let data = MeasuredData::new(60, None)
-            .with_temperature(Some(25.7));
+        let data = MeasuredData::new(Percent::new(60), None)
+            .with_temperature(Some(Celsius::new(25.7)));
         let summary = data.get_summary();
         
         assert_eq!(summary, "電圧:60%, 温度:25.7°C");
@@ -166,8 +191,8 @@ mod tests {
 
     #[test]
     fn test_get_summary_with_tds_voltage() {
-        let data = MeasuredData::new(70, None)
-            .with_tds_voltage(Some(2.34));
+        let data = MeasuredData::new(Percent::new(70), None)
+            .with_tds_voltage(Some(Millivolts::from_volts(2.34)));
         let summary = data.get_summary();
         
         assert_eq!(summary, "電圧:70%, TDS電圧:2.34V");
@@ -175,8 +200,8 @@ mod tests {
 
     #[test]
     fn test_get_summary_with_tds() {
-        let data = MeasuredData::new(80, None)
-            .with_tds(Some(456.8));
+        let data = MeasuredData::new(Percent::new(80), None)
+            .with_tds(Some(Ppm::new(456.8)));
         let summary = data.get_summary();
         
         assert_eq!(summary, "電圧:80%, TDS:456.8ppm");
@@ -185,7 +210,7 @@ mod tests {
     #[test]
     fn test_get_summary_with_image() {
         let image = vec![0u8; 1024];
-        let data = MeasuredData::new(90, Some(image));
+        let data = MeasuredData::new(Percent::new(90), Some(image));
         let summary = data.get_summary();
         
         assert_eq!(summary, "電圧:90%, 画像:1024bytes");
@@ -193,7 +218,7 @@ mod tests {
 
     #[test]
     fn test_get_summary_with_warnings() {
-        let mut data = MeasuredData::new(40, None);
+        let mut data = MeasuredData::new(Percent::new(40), None);
         data.add_warning("低電圧".to_string());
         let summary = data.get_summary();
         
@@ -203,10 +228,10 @@ mod tests {
     #[test]
     fn test_get_summary_full() {
         let image = vec![0u8; 512];
-        let mut data = MeasuredData::new(95, Some(image))
-            .with_temperature(Some(28.3))
-            .with_tds_voltage(Some(3.1))
-            .with_tds(Some(650.2));
+        let mut data = MeasuredData::new(Percent::new(95), Some(image))
+            .with_temperature(Some(Celsius::new(28.3)))
+            .with_tds_voltage(Some(Millivolts::from_volts(3.1)))
+            .with_tds(Some(Ppm::new(650.2)));
         
         data.add_warning("テスト警告".to_string());
         
@@ -216,25 +241,25 @@ mod tests {
 
     #[test]
     fn test_voltage_boundary_values() {
-        let data_min = MeasuredData::new(0, None);
-        assert_eq!(data_min.voltage_percent, 0);
+        let data_min = MeasuredData::new(Percent::new(0), None);
+        assert_eq!(data_min.voltage_percent, Percent::new(0));
         
-        let data_max = MeasuredData::new(100, None);
-        assert_eq!(data_max.voltage_percent, 100);
+        let data_max = MeasuredData::new(Percent::new(100), None);
+        assert_eq!(data_max.voltage_percent, Percent::new(100));
     }
 
     #[test]
     fn test_temperature_negative() {
-        let data = MeasuredData::new(50, None)
-            .with_temperature(Some(-5.5));
+        let data = MeasuredData::new(Percent::new(50), None)
+            .with_temperature(Some(Celsius::new(-5.5)));
         
-        assert_eq!(data.temperature_celsius, Some(-5.5));
+        assert_eq!(data.temperature_celsius, Some(Celsius::new(-5.5)));
         assert_eq!(data.get_summary(), "電圧:50%, 温度:-5.5°C");
     }
 
     #[test]
     fn test_empty_image_data() {
-        let data = MeasuredData::new(50, Some(Vec::new()));
+        let data = MeasuredData::new(Percent::new(50), Some(Vec::new()));
         let summary = data.get_summary();
         
         assert_eq!(summary, "電圧:50%, 画像:0bytes");
@@ -242,8 +267,8 @@ mod tests {
 
     #[test]
     fn test_clone() {
-        let original = MeasuredData::new(75, None)
-            .with_temperature(Some(22.5));
+        let original = MeasuredData::new(Percent::new(75), None)
+            .with_temperature(Some(Celsius::new(22.5)));
         
         let cloned = original.clone();
         
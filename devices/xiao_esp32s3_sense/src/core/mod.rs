@@ -1,10 +1,19 @@
 /// コアシステムモジュール
+pub mod ambient_snapshot;
+#[cfg(feature = "esp")]
 pub mod app_controller;
+#[cfg(feature = "esp")]
 pub mod data_service;
 pub mod measured_data;
+#[cfg(feature = "esp")]
 pub mod rtc_manager;
+pub mod sleep_decision;
 
+pub use ambient_snapshot::AmbientSnapshot;
+#[cfg(feature = "esp")]
 pub use app_controller::AppController;
+#[cfg(feature = "esp")]
 pub use data_service::DataService;
 pub use measured_data::MeasuredData;
+#[cfg(feature = "esp")]
 pub use rtc_manager::RtcManager;
@@ -1,5 +1,8 @@
 use log::{info, warn};
+use crate::core::sleep_decision::SleepJournal;
+use crate::power::critical_battery::CriticalBatteryLog;
 use crate::power::sleep::DeepSleepPlatform;
+use crate::sys_wrappers::esp as sys;
 
 /// RTC時刻管理モジュール
 pub struct RtcManager;
@@ -9,14 +12,22 @@ pub struct RtcManager;
 #[link_section = ".rtc.data"]
 static mut RTC_BOOT_COUNT: u32 = 0;
 
+/// 危機的電圧での起動の記録（周辺機器の初期化前に書き込むため、起動カウンタとは別に保持）
+#[link_section = ".rtc.data"]
+static mut RTC_CRITICAL_BATTERY_LOG: CriticalBatteryLog = CriticalBatteryLog::new();
+
+/// 直近のスリープ時間の決定と理由
+#[link_section = ".rtc.data"]
+static mut RTC_SLEEP_JOURNAL: SleepJournal = SleepJournal::new();
+
 impl RtcManager {
     /// RTCの状態を確認し、起動カウンタを管理します
     pub fn check_and_initialize_rtc<P: DeepSleepPlatform>(
         _timezone: &chrono_tz::Tz,
         _deep_sleep_platform: &P,
     ) -> anyhow::Result<()> {
-        let cause = unsafe { esp_idf_sys::esp_sleep_get_wakeup_cause() };
-        let reset_reason = unsafe { esp_idf_sys::esp_reset_reason() };
+        let cause = sys::wakeup_cause();
+        let reset_reason = sys::reset_reason();
         
         let reason_str = match reset_reason {
             esp_idf_sys::esp_reset_reason_t_ESP_RST_POWERON => "POWERON (電源投入)",
@@ -58,4 +69,19 @@ impl RtcManager {
     pub fn increment_boot_count() {
         unsafe { RTC_BOOT_COUNT += 1; }
     }
+
+    /// 危機的電圧での起動をRTCメモリに記録
+    pub fn record_critical_battery(voltage_percent: u8) {
+        unsafe { (*std::ptr::addr_of_mut!(RTC_CRITICAL_BATTERY_LOG)).record(voltage_percent) }
+    }
+
+    /// 通常起動できたことを記録し、直前まで続いていた危機的電圧での起動回数を返す
+    pub fn recover_from_critical_battery() -> u32 {
+        unsafe { (*std::ptr::addr_of_mut!(RTC_CRITICAL_BATTERY_LOG)).recover() }
+    }
+
+    /// RTCメモリのスリープ決定のジャーナルを操作します
+    pub fn with_sleep_journal<T>(f: impl FnOnce(&mut SleepJournal) -> T) -> T {
+        unsafe { f(&mut *std::ptr::addr_of_mut!(RTC_SLEEP_JOURNAL)) }
+    }
 }
@@ -0,0 +1,377 @@
+//! スリープ時間の決定
+//!
+//! データ送信後にスリープコマンドを待機し、次の優先順位でスリープ時間を決めます。
+//!
+//! 1. サーバーのスリープコマンド（1秒以上の値を受信した場合）
+//! 2. スケジュール（`target_minute_last_digit`・`target_second_last_digit`）: 設定のデフォルト時間が
+//!    経過した後、分の下一桁・秒の上一桁が最初に一致する時刻まで（時刻が未同期なら使わない）
+//! 3. 設定のデフォルト（`sleep_duration_seconds`、0なら `FALLBACK_SLEEP_SECONDS`）
+//!
+//! どの値も最後に安全上限 `MAX_SLEEP_SECONDS` で制限します。採用した段と理由は理由コード
+//! （`SleepReason`）で表し、Deep sleepを跨いでRTCメモリのジャーナル（`SleepJournal`）に直近
+//! `SLEEP_JOURNAL_LEN` 回分を記録します。
+use log::{info, warn};
+
+/// 設定のスリープ時間が無効な場合のフォールバックスリープ時間（秒）
+pub const FALLBACK_SLEEP_SECONDS: u64 = 600;
+/// スリープ時間の安全上限（秒、スリープコマンドの受理上限と同じ24時間）
+pub const MAX_SLEEP_SECONDS: u64 = 86_400;
+/// ジャーナルに保持する決定の数
+pub const SLEEP_JOURNAL_LEN: usize = 8;
+/// これより前の時刻は未同期とみなす（2024-01-01 00:00:00 UTC）
+const MIN_VALID_UNIX_SECONDS: u64 = 1_704_067_200;
+/// スケジュールに一致する時刻を探す範囲（秒、分の下一桁と秒の上一桁は1時間以内に必ず一致する）
+const SCHEDULE_SEARCH_SECONDS: u64 = 3_600;
+
+/// スリープコマンドの受信元（ESP-NOW受信機、テストではモック）
+pub trait SleepCommandSource {
+    /// スリープコマンドを待機し、受信した秒数を返します（タイムアウトならNone）
+    fn wait_for_sleep_command(&self, timeout_seconds: u32) -> Option<u32>;
+}
+
+/// スリープ時間を決めた理由（ジャーナルに記録するコード）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum SleepReason {
+    /// サーバーのスリープコマンド
+    ServerCommand = 1,
+    /// スリープコマンドがなく、スケジュールの時刻まで
+    Schedule = 2,
+    /// スリープコマンドを受信できず、設定のデフォルト
+    DefaultNoCommand = 3,
+    /// 無効なスリープコマンド（0秒）を受信し、設定のデフォルト
+    DefaultZeroCommand = 4,
+    /// スケジュールがあるが時刻が未同期のため、設定のデフォルト
+    DefaultClockUnset = 5,
+    /// 設定のデフォルトが0秒のため、フォールバック時間
+    FallbackInvalidDefault = 6,
+}
+
+impl SleepReason {
+    /// u8から変換します
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            1 => Some(Self::ServerCommand),
+            2 => Some(Self::Schedule),
+            3 => Some(Self::DefaultNoCommand),
+            4 => Some(Self::DefaultZeroCommand),
+            5 => Some(Self::DefaultClockUnset),
+            6 => Some(Self::FallbackInvalidDefault),
+            _ => None,
+        }
+    }
+
+    /// ログ用の名前
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::ServerCommand => "SERVER",
+            Self::Schedule => "SCHEDULE",
+            Self::DefaultNoCommand => "DEFAULT_NO_CMD",
+            Self::DefaultZeroCommand => "DEFAULT_ZERO_CMD",
+            Self::DefaultClockUnset => "DEFAULT_NO_CLOCK",
+            Self::FallbackInvalidDefault => "FALLBACK",
+        }
+    }
+}
+
+/// 起動時刻のスケジュール（分の下一桁・秒の上一桁、Noneの桁は条件にしない）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SleepSchedule {
+    pub minute_last_digit: Option<u8>,
+    pub second_tens_digit: Option<u8>,
+}
+
+impl SleepSchedule {
+    /// いずれかの桁が指定されていればスケジュールを作成します
+    pub fn new(minute_last_digit: Option<u8>, second_tens_digit: Option<u8>) -> Option<Self> {
+        (minute_last_digit.is_some() || second_tens_digit.is_some()).then_some(Self {
+            minute_last_digit,
+            second_tens_digit,
+        })
+    }
+
+    /// 時刻（UNIX秒）がスケジュールに一致するか
+    ///
+    /// 分と秒の桁はタイムゾーンのオフセット（30分単位まで）で変わらないため、UTCのまま判定します。
+    fn matches(&self, unix_seconds: u64) -> bool {
+        let minute = (unix_seconds / 60 % 60) as u8;
+        let second = (unix_seconds % 60) as u8;
+        self.minute_last_digit.is_none_or(|digit| minute % 10 == digit)
+            && self.second_tens_digit.is_none_or(|digit| second / 10 == digit)
+    }
+
+    /// `after_unix` 以降で最初に一致する時刻（UNIX秒）
+    pub fn next_at_or_after(&self, after_unix: u64) -> u64 {
+        (after_unix..after_unix + SCHEDULE_SEARCH_SECONDS)
+            .find(|&unix_seconds| self.matches(unix_seconds))
+            .unwrap_or(after_unix)
+    }
+}
+
+/// スリープ時間を決めるための設定
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SleepPolicy {
+    /// 設定のデフォルトのスリープ時間（秒）
+    pub default_seconds: u64,
+    /// 起動時刻のスケジュール
+    pub schedule: Option<SleepSchedule>,
+}
+
+/// 決定したスリープ時間
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SleepDecision {
+    /// スリープ時間（秒、安全上限で制限済み）
+    pub seconds: u64,
+    /// 採用した段と理由
+    pub reason: SleepReason,
+    /// 安全上限で制限した場合の制限前の値
+    pub capped_from: Option<u64>,
+}
+
+/// 受信したスリープコマンドと現在時刻からスリープ時間を決めます
+pub fn decide_sleep(received: Option<u32>, now_unix: u64, policy: &SleepPolicy) -> SleepDecision {
+    let (seconds, reason) = match received {
+        Some(seconds) if seconds > 0 => (u64::from(seconds), SleepReason::ServerCommand),
+        _ => {
+            let (default_seconds, invalid_default) = if policy.default_seconds > 0 {
+                (policy.default_seconds, false)
+            } else {
+                (FALLBACK_SLEEP_SECONDS, true)
+            };
+            match policy.schedule {
+                Some(schedule) if now_unix >= MIN_VALID_UNIX_SECONDS => {
+                    let wake_at = schedule.next_at_or_after(now_unix.saturating_add(default_seconds));
+                    (wake_at - now_unix, SleepReason::Schedule)
+                }
+                _ if invalid_default => (default_seconds, SleepReason::FallbackInvalidDefault),
+                Some(_) => (default_seconds, SleepReason::DefaultClockUnset),
+                None if received.is_some() => (default_seconds, SleepReason::DefaultZeroCommand),
+                None => (default_seconds, SleepReason::DefaultNoCommand),
+            }
+        }
+    };
+    let capped = seconds.min(MAX_SLEEP_SECONDS);
+    SleepDecision {
+        seconds: capped,
+        reason,
+        capped_from: (capped != seconds).then_some(seconds),
+    }
+}
+
+/// スリープコマンドを待機してスリープ時間を決め、ジャーナルに記録します
+///
+/// スケジュールは待機を終えた時刻から計算するため、現在時刻は待機の後に `now_unix` で取得します。
+pub fn resolve_sleep<R: SleepCommandSource>(
+    receiver: &R,
+    timeout_seconds: u32,
+    now_unix: impl FnOnce() -> u64,
+    policy: &SleepPolicy,
+    journal: &mut SleepJournal,
+) -> SleepDecision {
+    let received = receiver.wait_for_sleep_command(timeout_seconds);
+    let decision = decide_sleep(received, now_unix(), policy);
+    match decision.reason {
+        SleepReason::ServerCommand => info!("✓ サーバーからスリープ時間を受信: {}秒", decision.seconds),
+        SleepReason::Schedule => info!("スケジュールの起動時刻までスリープします: {}秒", decision.seconds),
+        SleepReason::DefaultNoCommand => {
+            warn!("✗ スリープコマンドを受信できませんでした。デフォルト時間 {}秒 を使用します。", decision.seconds)
+        }
+        SleepReason::DefaultZeroCommand => {
+            warn!("無効なスリープ時間 (0秒) を受信。デフォルト時間 {}秒 を使用します。", decision.seconds)
+        }
+        SleepReason::DefaultClockUnset => {
+            warn!("時刻が未同期のためスケジュールを使わず、デフォルト時間 {}秒 を使用します。", decision.seconds)
+        }
+        SleepReason::FallbackInvalidDefault => {
+            warn!("設定のスリープ時間が0秒のため、{}秒 を使用します。", decision.seconds)
+        }
+    }
+    if let Some(requested) = decision.capped_from {
+        warn!("スリープ時間 {}秒 は上限を超えるため {}秒 に制限します。", requested, decision.seconds);
+    }
+    journal.record(&decision);
+    info!("直近のスリープ決定: {}", journal.summary());
+    decision
+}
+
+/// ジャーナルの1件（RTCメモリに置くため固定長）
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SleepJournalEntry {
+    /// `SleepReason` のコード
+    pub reason: u8,
+    /// 安全上限で制限したか
+    pub capped: bool,
+    /// スリープ時間（秒）
+    pub seconds: u32,
+}
+
+impl SleepJournalEntry {
+    const EMPTY: Self = Self { reason: 0, capped: false, seconds: 0 };
+}
+
+/// 直近のスリープ決定のジャーナル（RTCメモリに保持）
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SleepJournal {
+    entries: [SleepJournalEntry; SLEEP_JOURNAL_LEN],
+    next: u8,
+    len: u8,
+}
+
+impl Default for SleepJournal {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SleepJournal {
+    /// 空のジャーナルを作成します
+    pub const fn new() -> Self {
+        Self {
+            entries: [SleepJournalEntry::EMPTY; SLEEP_JOURNAL_LEN],
+            next: 0,
+            len: 0,
+        }
+    }
+
+    /// 決定を記録します（古いものから上書き）
+    pub fn record(&mut self, decision: &SleepDecision) {
+        let next = usize::from(self.next) % SLEEP_JOURNAL_LEN;
+        self.entries[next] = SleepJournalEntry {
+            reason: decision.reason as u8,
+            capped: decision.capped_from.is_some(),
+            seconds: decision.seconds.min(u64::from(u32::MAX)) as u32,
+        };
+        self.next = ((next + 1) % SLEEP_JOURNAL_LEN) as u8;
+        self.len = (usize::from(self.len) + 1).min(SLEEP_JOURNAL_LEN) as u8;
+    }
+
+    /// 記録した決定（古い順）
+    pub fn entries(&self) -> Vec<SleepJournalEntry> {
+        let len = usize::from(self.len).min(SLEEP_JOURNAL_LEN);
+        let start = (usize::from(self.next) + SLEEP_JOURNAL_LEN - len) % SLEEP_JOURNAL_LEN;
+        (0..len).map(|i| self.entries[(start + i) % SLEEP_JOURNAL_LEN]).collect()
+    }
+
+    /// ログ用の要約（古い順に `理由:秒`、制限した決定には `!` を付ける）
+    pub fn summary(&self) -> String {
+        self.entries()
+            .iter()
+            .map(|entry| {
+                let reason = SleepReason::from_u8(entry.reason).map_or("UNKNOWN", SleepReason::as_str);
+                format!("{}:{}{}", reason, entry.seconds, if entry.capped { "!" } else { "" })
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 2024-05-01 12:34:56 UTC
+    const NOW: u64 = 1_714_566_896;
+
+    struct MockReceiver(Option<u32>);
+
+    impl SleepCommandSource for MockReceiver {
+        fn wait_for_sleep_command(&self, _timeout_seconds: u32) -> Option<u32> {
+            self.0
+        }
+    }
+
+    fn policy(default_seconds: u64, schedule: Option<SleepSchedule>) -> SleepPolicy {
+        SleepPolicy { default_seconds, schedule }
+    }
+
+    #[test]
+    fn test_server_command_takes_precedence_over_schedule_and_default() {
+        let schedule = SleepSchedule::new(Some(0), Some(4));
+        let decision = decide_sleep(Some(120), NOW, &policy(600, schedule));
+        assert_eq!(decision, SleepDecision { seconds: 120, reason: SleepReason::ServerCommand, capped_from: None });
+
+        // 0秒のコマンドはスケジュールへ、スケジュールがなければデフォルトへ
+        assert_eq!(decide_sleep(Some(0), NOW, &policy(600, schedule)).reason, SleepReason::Schedule);
+        assert_eq!(decide_sleep(Some(0), NOW, &policy(600, None)).reason, SleepReason::DefaultZeroCommand);
+        assert_eq!(
+            decide_sleep(None, NOW, &policy(600, None)),
+            SleepDecision { seconds: 600, reason: SleepReason::DefaultNoCommand, capped_from: None }
+        );
+        // 時刻が未同期ならスケジュールを使わない
+        assert_eq!(
+            decide_sleep(None, 30, &policy(600, schedule)),
+            SleepDecision { seconds: 600, reason: SleepReason::DefaultClockUnset, capped_from: None }
+        );
+        assert_eq!(
+            decide_sleep(None, NOW, &policy(0, None)),
+            SleepDecision { seconds: FALLBACK_SLEEP_SECONDS, reason: SleepReason::FallbackInvalidDefault, capped_from: None }
+        );
+    }
+
+    #[test]
+    fn test_schedule_waits_default_then_next_matching_time() {
+        // 12:34:56 + 600秒 = 12:44:56 → 分の下一桁0・秒40台の最初は 12:50:40
+        let decision = decide_sleep(None, NOW, &policy(600, SleepSchedule::new(Some(0), Some(4))));
+        assert_eq!(decision.seconds, 944);
+        assert_eq!((NOW + decision.seconds) % 3600, 50 * 60 + 40);
+
+        // 秒の上一桁のみ: 12:44:56 は50秒台 → 12:45:00台の40秒
+        let seconds_only = SleepSchedule::new(None, Some(4)).unwrap();
+        assert_eq!(seconds_only.next_at_or_after(NOW + 600) - NOW, 600 + 44);
+        // 一致する時刻の中ならそのまま
+        let minute_only = SleepSchedule::new(Some(4), None).unwrap();
+        assert_eq!(minute_only.next_at_or_after(NOW + 600), NOW + 600);
+        assert_eq!(SleepSchedule::new(None, None), None);
+    }
+
+    #[test]
+    fn test_safety_cap_applies_to_every_source() {
+        let decision = decide_sleep(Some(200_000), NOW, &policy(600, None));
+        assert_eq!(decision.seconds, MAX_SLEEP_SECONDS);
+        assert_eq!(decision.capped_from, Some(200_000));
+
+        let decision = decide_sleep(None, NOW, &policy(100_000, None));
+        assert_eq!((decision.seconds, decision.reason), (MAX_SLEEP_SECONDS, SleepReason::DefaultNoCommand));
+        assert_eq!(decision.capped_from, Some(100_000));
+    }
+
+    #[test]
+    fn test_journal_keeps_latest_decisions_with_reason_codes() {
+        let mut journal = SleepJournal::new();
+        assert_eq!(journal.summary(), "");
+        let receiver = MockReceiver(None);
+        for _ in 0..SLEEP_JOURNAL_LEN {
+            resolve_sleep(&receiver, 1, || NOW, &policy(600, None), &mut journal);
+        }
+        resolve_sleep(&MockReceiver(Some(200_000)), 1, || NOW, &policy(600, None), &mut journal);
+
+        let entries = journal.entries();
+        assert_eq!(entries.len(), SLEEP_JOURNAL_LEN);
+        assert_eq!(entries[0].reason, SleepReason::DefaultNoCommand as u8);
+        let latest = entries[SLEEP_JOURNAL_LEN - 1];
+        assert_eq!(SleepReason::from_u8(latest.reason), Some(SleepReason::ServerCommand));
+        assert!(latest.capped);
+        assert!(journal.summary().ends_with("DEFAULT_NO_CMD:600 SERVER:86400!"));
+    }
+
+    #[cfg(feature = "mock-hw")]
+    #[test]
+    fn test_pipeline_drives_sleep_platform() {
+        use crate::power::sleep::{MockDeepSleep, MockLightSleep, SleepManager, SleepType};
+
+        let (deep, light) = (MockDeepSleep::default(), MockLightSleep::default());
+        let manager = SleepManager::new(&deep, &light, 60);
+        let mut journal = SleepJournal::new();
+        let schedule = SleepSchedule::new(Some(0), Some(4));
+
+        for (received, expected) in [(Some(30), SleepType::Light), (None, SleepType::Deep)] {
+            let decision = resolve_sleep(&MockReceiver(received), 10, || NOW, &policy(600, schedule), &mut journal);
+            assert_eq!(manager.sleep_optimized(decision.seconds).unwrap(), expected);
+        }
+        assert_eq!(light.requested_durations(), vec![30_000_000]);
+        assert_eq!(deep.requested_durations(), vec![944_000_000]);
+        assert_eq!(journal.summary(), "SERVER:30 SCHEDULE:944");
+    }
+}
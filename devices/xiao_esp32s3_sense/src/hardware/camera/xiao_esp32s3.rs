@@ -2,6 +2,8 @@
 /// 
 /// Issue #12のカメラ初期化実装
 
+use crate::sys_wrappers::{self, esp::EspSys};
+
 #[allow(dead_code)] // Issue #12 カメラ機能実装中のため一時的に警告を抑制
 
 /// XIAO ESP32S3 Sense用カメラピン構造体
@@ -138,69 +140,11 @@ pub fn get_camera_pins() -> CameraPins {
 /// 目的: 全てのカメラ関連ピン（D0-D7, XCLK, PCLK, VSYNC, HREF, SDA, SCL）を
 /// 入力モードかつプルダウン設定に強制変更することで、拡張ボード側へのリーク電流を遮断します。
 pub fn reset_camera_pins() {
-    use esp_idf_sys::{
-        gpio_config, gpio_config_t, gpio_int_type_t_GPIO_INTR_DISABLE,
-        gpio_mode_t_GPIO_MODE_INPUT, gpio_mode_t_GPIO_MODE_OUTPUT, // Added OUTPUT mode
-        gpio_pulldown_t_GPIO_PULLDOWN_DISABLE,
-        gpio_pullup_t_GPIO_PULLUP_DISABLE, gpio_hold_en,
-        gpio_set_direction, gpio_set_level, // Added set functions
-    };
-
     log::info!("カメラピンのリセット（High-Z / Hold）を開始します...");
 
-    let pin_mask: u64 = (1u64 << 10) | // XCLK
-                        (1u64 << 15) | // D0
-                        (1u64 << 17) | // D1
-                        (1u64 << 18) | // D2
-                        (1u64 << 16) | // D3
-                        (1u64 << 14) | // D4
-                        (1u64 << 12) | // D5
-                        (1u64 << 11) | // D6
-                        (1u64 << 48) | // D7
-                        (1u64 << 38) | // VSYNC
-                        (1u64 << 47) | // HREF
-                        (1u64 << 13) | // PCLK
-                        (1u64 << 40) | // SDA
-                        (1u64 << 39);  // SCL
-
-    let config = gpio_config_t {
-        pin_bit_mask: pin_mask,
-        mode: gpio_mode_t_GPIO_MODE_INPUT,
-        pull_up_en: gpio_pullup_t_GPIO_PULLUP_DISABLE,
-        pull_down_en: gpio_pulldown_t_GPIO_PULLDOWN_DISABLE,
-        intr_type: gpio_int_type_t_GPIO_INTR_DISABLE,
-    };
-
-    unsafe {
-        // 1. まず全ピンをInput/High-Zにしてリセット
-        let err = gpio_config(&config);
-        if err != 0 {
-            log::error!("カメラピンのリセットに失敗しました: {}", err);
-            return;
-        }
-
-        // 2. XCLK (GPIO 10) だけは Output Low に設定してクロックを確実に止める
-        // Floatingだとノイズでクロックが入る可能性があるため
-        gpio_set_direction(10, gpio_mode_t_GPIO_MODE_OUTPUT);
-        gpio_set_level(10, 0);
-
-        // 3. SDA/SCL (GPIO 40, 39) を明示的にOutput Lowに設定
-        // I2Cラインがフローティングだとプルアップ抵抗経由でカメラにリーク電流が流れる
-        gpio_set_direction(40, gpio_mode_t_GPIO_MODE_OUTPUT);
-        gpio_set_level(40, 0);
-        gpio_set_direction(39, gpio_mode_t_GPIO_MODE_OUTPUT);
-        gpio_set_level(39, 0);
-
-        // 各ピンをスリープ中に固定（隔離）
-        for pin in [10, 15, 17, 18, 16, 14, 12, 11, 48, 38, 47, 13, 40, 39] {
-            if pin < 32 {
-                gpio_hold_en(pin as i32);
-            } else {
-                // Pin 32以上は esp_idf_sys の定義に従う（通常は i32 キャストで動作）
-                gpio_hold_en(pin as i32);
-            }
-        }
-
-        log::info!("✓ カメラ用全ピンがHigh-Z・Hold状態にリセットされました");
+    // 全ピンをInput/High-Zにし、XCLK・SDA・SCLはLow出力にしてからスリープ中に固定（隔離）
+    match sys_wrappers::park_camera_pins(&EspSys) {
+        Ok(()) => log::info!("✓ カメラ用全ピンがHigh-Z・Hold状態にリセットされました"),
+        Err(e) => log::error!("カメラピンのリセットに失敗しました: {}", e.code()),
     }
 }
@@ -4,6 +4,8 @@ use esp_idf_svc::hal::adc::ADC1;
 use esp_idf_svc::hal::delay::FreeRtos;
 use log::{info, warn, error};
 use anyhow::Result;
+use crate::sys_wrappers::{self, esp::EspSys};
+use crate::utils::{Celsius, Millivolts, Ppm};
 
 /// EC/TDSセンサー管理構造体
 /// 
@@ -22,8 +24,8 @@ pub struct EcTdsSensor {
 pub struct EcTdsReading {
     /// EC値（μS/cm）
     pub ec_us_cm: f32,
-    /// TDS濃度（ppm）
-    pub tds_ppm: f32,
+    /// TDS濃度（温度補正後）
+    pub tds_ppm: Ppm,
     /// ADC生値
     pub adc_value: u16,
     /// 測定の信頼性（true: 正常、false: 警告あり）
@@ -36,7 +38,7 @@ impl From<EcReading> for EcTdsReading {
     fn from(reading: EcReading) -> Self {
         Self {
             ec_us_cm: reading.ec_us_cm,
-            tds_ppm: reading.tds_ppm,
+            tds_ppm: Ppm::new(reading.tds_ppm),
             adc_value: reading.adc_value,
             is_reliable: true, // esp-ec-sensorは内部で検証済み
             warning_message: None,
@@ -132,8 +134,8 @@ impl EcTdsSensor {
     /// * `delay_ms` - 各サンプル間の遅延時間（ミリ秒）
     /// 
     /// # 戻り値
-    /// - (voltage, 成功時はSome(f32)、失敗時はNone)
-    pub fn read_voltage(&mut self, samples: u8, delay_ms: u32) -> Result<Option<f32>> {
+    /// - 成功時はSome(電圧)、失敗時はNone（ライブラリの変換結果はmV単位）
+    pub fn read_voltage(&mut self, samples: u8, delay_ms: u32) -> Result<Option<Millivolts>> {
         if let Some(ref mut sensor) = self.sensor {
             // 単発のADC読み取り（平均化はライブラリ内で実施）
             match sensor.read_adc_averaged(samples, delay_ms) {
@@ -141,7 +143,8 @@ impl EcTdsSensor {
                     let voltage = sensor.adc_to_voltage(adc_value);
                     match voltage {
                         Ok(voltage) => {
-                            info!("✓ ADC電圧測定成功: {:.2} mV", voltage);
+                            let voltage = Millivolts::new(voltage);
+                            info!("✓ ADC電圧測定成功: {}", voltage);
                             Ok(Some(voltage))
                         }
                         Err(e) => {
@@ -166,14 +169,14 @@ impl EcTdsSensor {
     /// EC/TDS値を測定
     ///
     /// # 引数
-    /// * `temperature_celsius` - 温度補正用の温度値（℃）
+    /// * `temperature` - 温度補正用の温度値
     ///
     /// # 戻り値
     /// EC/TDS測定結果（EcTdsReading構造体）
     /// センサーエラー時はダミー値を返します
-    pub fn measure_ec_tds(&mut self, temperature_celsius: Option<f32>) -> Result<EcTdsReading> {
+    pub fn measure_ec_tds(&mut self, temperature: Option<Celsius>) -> Result<EcTdsReading> {
         if let Some(ref mut sensor) = self.sensor {
-            match sensor.measure(temperature_celsius) {
+            match sensor.measure(temperature.map(Celsius::value)) {
                 Ok(reading) => {
                     let mut result = EcTdsReading::from(reading);
                     
@@ -182,7 +185,7 @@ impl EcTdsSensor {
                     result.is_reliable = is_reliable;
                     result.warning_message = warning;
                     
-                    info!("🌊 EC/TDS測定完了: EC={:.1}μS/cm, TDS={:.1}ppm (ADC: {})", 
+                    info!("🌊 EC/TDS測定完了: EC={:.1}μS/cm, TDS={} (ADC: {})", 
                           result.ec_us_cm, result.tds_ppm, result.adc_value);
                     
                     if let Some(ref msg) = result.warning_message {
@@ -206,12 +209,9 @@ impl EcTdsSensor {
 
     /// センサーの電源を強制的にオフにする（Deep Sleepリーク対策）
     pub fn power_off(&self) -> Result<()> {
-        use esp_idf_sys::{gpio_set_direction, gpio_set_level, gpio_mode_t_GPIO_MODE_OUTPUT};
-        
         info!("EC/TDSセンサーの電源をオフにしています (GPIO{})", self.power_pin_number);
-        unsafe {
-            gpio_set_direction(self.power_pin_number as i32, gpio_mode_t_GPIO_MODE_OUTPUT);
-            gpio_set_level(self.power_pin_number as i32, 0);
+        if let Err(e) = sys_wrappers::drive_low(&EspSys, self.power_pin_number as i32) {
+            warn!("EC/TDSセンサーの電源ピンを設定できませんでした (エラーコード: {})", e.code());
         }
         Ok(())
     }
@@ -219,7 +219,7 @@ impl EcTdsSensor {
     /// デフォルトEC/TDS読み取り結果を取得
     fn get_default_reading(&self) -> Result<EcTdsReading> {
         let default_ec = 100.0; // 100 μS/cm
-        let default_tds = default_ec * (self.tds_factor / 1000.0);
+        let default_tds = Ppm::new(default_ec * (self.tds_factor / 1000.0));
         
         Ok(EcTdsReading {
             ec_us_cm: default_ec,
@@ -242,11 +242,11 @@ impl EcTdsSensor {
         }
 
         // TDS値の妥当性チェック（農業用途での一般的な範囲）
-        if reading.tds_ppm > 2000.0 {
-            return (true, Some(format!("TDS値が高いです: {:.1}ppm - 水質を確認してください", reading.tds_ppm)));
+        if reading.tds_ppm.value() > 2000.0 {
+            return (true, Some(format!("TDS値が高いです: {} - 水質を確認してください", reading.tds_ppm)));
         }
 
-        if reading.tds_ppm < 0.0 {
+        if reading.tds_ppm.value() < 0.0 {
             return (false, Some("TDS値が負の値です".to_string()));
         }
 
@@ -1,5 +1,5 @@
 use esp_idf_svc::hal::delay::FreeRtos;
-use esp_idf_svc::hal::gpio::{Output, PinDriver};
+use esp_idf_svc::hal::gpio::{AnyOutputPin, Output, PinDriver};
 
 /// LEDの制御に関するエラー
 #[derive(Debug, thiserror::Error)]
@@ -13,7 +13,7 @@ pub enum LedError {
 
 /// ステータスLED制御
 pub struct StatusLed {
-    led: PinDriver<'static, esp_idf_svc::hal::gpio::Gpio21, Output>,
+    led: PinDriver<'static, AnyOutputPin, Output>,
 }
 
 impl StatusLed {
@@ -21,12 +21,12 @@ impl StatusLed {
     ///
     /// # 引数
     ///
-    /// * `pin` - GPIO21ピン
+    /// * `pin` - LEDのピン（`status_led_pin` で設定、既定はGPIO21）
     ///
     /// # エラー
     ///
     /// LEDの初期化に失敗した場合にエラーを返します
-    pub fn new(pin: esp_idf_svc::hal::gpio::Gpio21) -> Result<Self, LedError> {
+    pub fn new(pin: AnyOutputPin) -> Result<Self, LedError> {
         let led = PinDriver::output(pin).map_err(|e| LedError::InitFailed(format!("{:?}", e)))?;
 
         Ok(Self { led })
@@ -1,12 +1,14 @@
 /// ハードウェア制御モジュール
 pub mod camera;
 pub mod led;
+pub mod pin_map;
 pub mod pins;
 pub mod voltage_sensor;
 pub mod temp_sensor;
 pub mod ec_sensor;
 
 // 公開API
+pub use pin_map::{PinMap, PinMapError};
 pub use pins::CameraPins;
 pub use voltage_sensor::VoltageSensor;
 pub use temp_sensor::{TempSensor, TemperatureReading};
@@ -0,0 +1,214 @@
+//! センサー・LEDのGPIO割り当て
+//!
+//! 配線はボードのリビジョンや外付けセンサーの構成で変わるため、GPIO番号は `cfg.toml` で指定し、
+//! 起動時に XIAO ESP32S3 Sense で使えるピンかどうかを検証します。
+//! ハードウェアに依存しないので、ホストビルドでも検証ロジックをテストできます。
+
+/// XIAO ESP32S3 Sense のカメラが占有するGPIO（`CameraPins` と同じ割り当て）
+pub const CAMERA_GPIOS: [u8; 14] = [10, 11, 12, 13, 14, 15, 16, 17, 18, 38, 39, 40, 47, 48];
+
+/// ピン割り当ての検証エラー
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum PinMapError {
+    #[error("{role} のGPIO{pin}はこのボードでは使用できません")]
+    UnavailablePin { role: &'static str, pin: u8 },
+    #[error("{role} のGPIO{pin}はADC1に対応していません (GPIO1-10)")]
+    NotAdc1Pin { role: &'static str, pin: u8 },
+    #[error("{role} のGPIO{pin}はカメラが使用しています")]
+    CameraPin { role: &'static str, pin: u8 },
+    #[error("GPIO{pin}が {first} と {second} で重複しています")]
+    Duplicate {
+        pin: u8,
+        first: &'static str,
+        second: &'static str,
+    },
+}
+
+/// ピンの用途
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PinKind {
+    /// デジタル入出力
+    Digital,
+    /// ADC1のアナログ入力（ADC2はWiFiと競合するため使わない）
+    Adc1,
+}
+
+/// センサー・LEDのGPIO番号
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PinMap {
+    /// 電池電圧測定のADC入力
+    pub voltage_adc_pin: u8,
+    /// ステータスLED
+    pub status_led_pin: u8,
+    /// 温度センサー（無効ならNone）
+    pub temp_sensor: Option<TempSensorPins>,
+    /// TDSセンサー（無効ならNone）
+    pub tds_sensor: Option<TdsSensorPins>,
+}
+
+/// 温度センサー（DS18B20）のGPIO番号
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TempSensorPins {
+    pub power_pin: u8,
+    pub data_pin: u8,
+}
+
+/// TDSセンサーのGPIO番号
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TdsSensorPins {
+    pub power_pin: u8,
+    pub adc_pin: u8,
+}
+
+impl Default for PinMap {
+    /// 標準の配線（`cfg.toml.template` の既定値）
+    fn default() -> Self {
+        Self {
+            voltage_adc_pin: 4,
+            status_led_pin: 21,
+            temp_sensor: Some(TempSensorPins {
+                power_pin: 2,
+                data_pin: 3,
+            }),
+            tds_sensor: Some(TdsSensorPins {
+                power_pin: 5,
+                adc_pin: 1,
+            }),
+        }
+    }
+}
+
+impl PinMap {
+    /// 用途ごとの割り当て（有効なセンサーのみ）
+    fn assignments(&self) -> Vec<(&'static str, u8, PinKind)> {
+        let mut assignments = vec![
+            ("voltage_adc_pin", self.voltage_adc_pin, PinKind::Adc1),
+            ("status_led_pin", self.status_led_pin, PinKind::Digital),
+        ];
+        if let Some(temp) = self.temp_sensor {
+            assignments.push(("temp_sensor_power_pin", temp.power_pin, PinKind::Digital));
+            assignments.push(("temp_sensor_data_pin", temp.data_pin, PinKind::Digital));
+        }
+        if let Some(tds) = self.tds_sensor {
+            assignments.push(("tds_sensor_power_pin", tds.power_pin, PinKind::Digital));
+            assignments.push(("tds_sensor_adc_pin", tds.adc_pin, PinKind::Adc1));
+        }
+        assignments
+    }
+
+    /// ボードで使えるピンか、用途に合うか、カメラや他の用途と重ならないかを検証します
+    pub fn validate(&self) -> Result<(), PinMapError> {
+        let assignments = self.assignments();
+        for (index, &(role, pin, kind)) in assignments.iter().enumerate() {
+            if !is_usable_gpio(pin) {
+                return Err(PinMapError::UnavailablePin { role, pin });
+            }
+            if CAMERA_GPIOS.contains(&pin) {
+                return Err(PinMapError::CameraPin { role, pin });
+            }
+            if kind == PinKind::Adc1 && !is_adc1_gpio(pin) {
+                return Err(PinMapError::NotAdc1Pin { role, pin });
+            }
+            if let Some(&(first, _, _)) = assignments[..index].iter().find(|(_, other, _)| *other == pin) {
+                return Err(PinMapError::Duplicate {
+                    pin,
+                    first,
+                    second: role,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// ESP32-S3（オクタルPSRAM搭載）で汎用に使えるGPIOか
+///
+/// GPIO22-25は存在せず、GPIO26-32はフラッシュ、GPIO33-37はオクタルPSRAMが使用します。
+pub fn is_usable_gpio(pin: u8) -> bool {
+    matches!(pin, 0..=21 | 38..=48)
+}
+
+/// ADC1のチャンネルがあるGPIOか
+pub fn is_adc1_gpio(pin: u8) -> bool {
+    matches!(pin, 1..=10)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_pin_map_is_valid() {
+        assert_eq!(PinMap::default().validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_rejects_pins_the_board_cannot_provide() {
+        let map = PinMap {
+            status_led_pin: 26,
+            ..PinMap::default()
+        };
+        assert_eq!(
+            map.validate(),
+            Err(PinMapError::UnavailablePin {
+                role: "status_led_pin",
+                pin: 26
+            })
+        );
+
+        let map = PinMap {
+            voltage_adc_pin: 13,
+            ..PinMap::default()
+        };
+        assert_eq!(
+            map.validate(),
+            Err(PinMapError::CameraPin {
+                role: "voltage_adc_pin",
+                pin: 13
+            })
+        );
+
+        // GPIO43はデジタル出力には使えるがADC1ではない
+        let map = PinMap {
+            voltage_adc_pin: 43,
+            ..PinMap::default()
+        };
+        assert_eq!(
+            map.validate(),
+            Err(PinMapError::NotAdc1Pin {
+                role: "voltage_adc_pin",
+                pin: 43
+            })
+        );
+        let map = PinMap {
+            status_led_pin: 43,
+            ..PinMap::default()
+        };
+        assert_eq!(map.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_duplicates_only_count_enabled_sensors() {
+        let map = PinMap {
+            tds_sensor: Some(TdsSensorPins {
+                power_pin: 4,
+                adc_pin: 1,
+            }),
+            ..PinMap::default()
+        };
+        assert_eq!(
+            map.validate(),
+            Err(PinMapError::Duplicate {
+                pin: 4,
+                first: "voltage_adc_pin",
+                second: "tds_sensor_power_pin"
+            })
+        );
+
+        let map = PinMap {
+            tds_sensor: None,
+            ..map
+        };
+        assert_eq!(map.validate(), Ok(()));
+    }
+}
@@ -3,6 +3,8 @@ use esp_idf_svc::hal::rmt::RmtChannel;
 use esp_idf_svc::hal::peripheral::Peripheral;
 use log::{info, warn, error};
 use anyhow::Result;
+use crate::sys_wrappers::{self, esp::EspSys};
+use crate::utils::Celsius;
 
 /// 温度センサー管理構造体
 /// 
@@ -18,10 +20,10 @@ pub struct TempSensor {
 /// 温度測定結果
 #[derive(Debug, Clone)]
 pub struct TemperatureReading {
-    /// 測定温度（℃）
-    pub temperature_celsius: f32,
-    /// 補正済み温度（℃）
-    pub corrected_temperature_celsius: f32,
+    /// 測定温度（補正前）
+    pub raw_temperature: Celsius,
+    /// 補正済み温度
+    pub corrected_temperature: Celsius,
     /// 測定の信頼性（true: 正常、false: 警告あり）
     pub is_reliable: bool,
     /// 警告メッセージ（ある場合）
@@ -83,12 +85,13 @@ impl TempSensor {
         if let Some(ref mut sensor) = self.sensor {
             match sensor.read_temperature() {
                 Ok(raw_temp) => {
-                    let corrected_temp = raw_temp + self.temperature_offset;
+                    let raw_temp = Celsius::new(raw_temp);
+                    let corrected_temp = raw_temp.offset_by(self.temperature_offset);
                     
                     // 妥当性チェック
                     let (is_reliable, warning) = self.validate_temperature(corrected_temp);
                     
-                    info!("🌡️ 温度測定: {:.1}°C (補正前: {:.1}°C, オフセット: {:.1}°C)", 
+                    info!("🌡️ 温度測定: {} (補正前: {}, オフセット: {:.1}°C)", 
                           corrected_temp, raw_temp, self.temperature_offset);
                     
                     if let Some(ref msg) = warning {
@@ -96,8 +99,8 @@ impl TempSensor {
                     }
 
                     Ok(TemperatureReading {
-                        temperature_celsius: raw_temp,
-                        corrected_temperature_celsius: corrected_temp,
+                        raw_temperature: raw_temp,
+                        corrected_temperature: corrected_temp,
                         is_reliable,
                         warning_message: warning,
                     })
@@ -117,39 +120,37 @@ impl TempSensor {
 
     /// センサーの電源を強制的にオフにする（Deep Sleepリーク対策）
     pub fn power_off(&self) -> Result<()> {
-        use esp_idf_sys::{gpio_set_direction, gpio_set_level, gpio_mode_t_GPIO_MODE_OUTPUT};
-        
         info!("温度センサーの電源をオフにしています (GPIO{})", self.power_pin);
-        unsafe {
-            gpio_set_direction(self.power_pin, gpio_mode_t_GPIO_MODE_OUTPUT);
-            gpio_set_level(self.power_pin, 0);
+        if let Err(e) = sys_wrappers::drive_low(&EspSys, self.power_pin) {
+            warn!("温度センサーの電源ピンを設定できませんでした (エラーコード: {})", e.code());
         }
         Ok(())
     }
 
     /// デフォルト温度読み取り結果を取得
     fn get_default_reading(&self) -> Result<TemperatureReading> {
-        let default_temp = 25.0;
-        let corrected_temp = default_temp + self.temperature_offset;
+        let default_temp = Celsius::new(25.0);
+        let corrected_temp = default_temp.offset_by(self.temperature_offset);
         
         Ok(TemperatureReading {
-            temperature_celsius: default_temp,
-            corrected_temperature_celsius: corrected_temp,
+            raw_temperature: default_temp,
+            corrected_temperature: corrected_temp,
             is_reliable: false,
             warning_message: Some("センサーが利用できないため、デフォルト温度を使用".to_string()),
         })
     }
 
     /// 温度の妥当性を検証
-    fn validate_temperature(&self, temperature: f32) -> (bool, Option<String>) {
+    fn validate_temperature(&self, temperature: Celsius) -> (bool, Option<String>) {
+        let celsius = temperature.value();
         // 妥当な温度範囲をチェック（-40°C ~ +85°C: DS18B20の仕様範囲）
-        if temperature < -40.0 || temperature > 85.0 {
-            return (false, Some(format!("温度が仕様範囲外です: {:.1}°C", temperature)));
+        if celsius < -40.0 || celsius > 85.0 {
+            return (false, Some(format!("温度が仕様範囲外です: {}", temperature)));
         }
 
         // 農業用途での一般的な範囲をチェック（-10°C ~ +60°C）
-        if temperature < -10.0 || temperature > 60.0 {
-            return (true, Some(format!("温度が一般的な農業用範囲外です: {:.1}°C", temperature)));
+        if celsius < -10.0 || celsius > 60.0 {
+            return (true, Some(format!("温度が一般的な農業用範囲外です: {}", temperature)));
         }
 
         (true, None)
@@ -10,6 +10,7 @@ use esp_idf_svc::hal::{
 };
 use log::{error, info};
 use crate::config::CONFIG;
+use crate::utils::{Millivolts, Percent};
 
 /// ADC電圧センサー管理モジュール
 pub struct VoltageSensor;
@@ -31,17 +32,7 @@ impl VoltageSensor {
     /// assert_eq!(percent, 50);
     /// ```
     pub fn calculate_voltage_percentage(voltage_mv: f32, min_mv: f32, max_mv: f32) -> u8 {
-        let range_mv = max_mv - min_mv;
-        
-        let percentage = if range_mv <= 0.0 {
-            0.0
-        } else {
-            ((voltage_mv - min_mv) / range_mv * 100.0)
-                .max(0.0)
-                .min(100.0)
-        };
-        
-        percentage.round() as u8
+        Percent::of_range(Millivolts::new(voltage_mv), Millivolts::new(min_mv), Millivolts::new(max_mv)).value()
     }
 
     /// ADC1を使用してGPIO PINからADC電圧を測定し、パーセンテージに変換
@@ -49,11 +40,11 @@ impl VoltageSensor {
     /// 
     /// # Returns
     /// - (電圧パーセンテージ, ADC1): 測定結果とADC1の所有権
-    ///   - 電圧パーセンテージ: 通常は 0–100 の値を取り、`255` は測定に失敗したことを示します
+    ///   - 電圧パーセンテージ: 通常は 0–100 の値を取り、[`Percent::INVALID`] は測定に失敗したことを示します
     pub fn measure_voltage_percentage<T: esp_idf_svc::hal::gpio::ADCPin<Adc = ADC1>>(
         mut adc: ADC1,
         mut gpio_pin: T,
-    ) -> anyhow::Result<(u8, ADC1, T)> {
+    ) -> anyhow::Result<(Percent, ADC1, T)> {
         info!("ADC1を初期化しています (WiFi競合回避)");
         let adc_driver = AdcDriver::new(&mut adc)?;
         let adc_config = AdcChannelConfig {
@@ -82,18 +73,18 @@ impl VoltageSensor {
         }
 
         let voltage_percent = if samples > 0 {
-            let avg_mv = (sum_mv / samples as u32) as f32;
-            info!("ADC電圧測定結果: 平均値={:.0} mV, サンプル数={}", avg_mv, samples);
+            let average = Millivolts::new((sum_mv / samples as u32) as f32);
+            info!("ADC電圧測定結果: 平均値={}, サンプル数={}", average, samples);
             
-            let min_mv = CONFIG.adc_voltage_min_mv as f32;
-            let max_mv = CONFIG.adc_voltage_max_mv as f32;
+            let min = Millivolts::new(CONFIG.adc_voltage_min_mv as f32);
+            let max = Millivolts::new(CONFIG.adc_voltage_max_mv as f32);
             
-            let result = Self::calculate_voltage_percentage(avg_mv, min_mv, max_mv);
-            info!("計算されたパーセンテージ: {} % (設定範囲: {} - {} mV)", result, min_mv, max_mv);
+            let result = Percent::of_range(average, min, max);
+            info!("計算されたパーセンテージ: {} (設定範囲: {} - {})", result, min, max);
             result
         } else {
-            error!("有効なADCサンプルが取得できませんでした。電圧は測定失敗値 (255 / u8::MAX) として扱います。");
-            255
+            error!("有効なADCサンプルが取得できませんでした。電圧は測定失敗値 ({}) として扱います。", Percent::INVALID);
+            Percent::INVALID
         };
 
         // ADCチャンネルを解放してADCドライバーからADC1を取り戻す
@@ -102,4 +93,28 @@ impl VoltageSensor {
 
         Ok((voltage_percent, adc, gpio_pin)) // ADC1とGPIOピンの所有権を返す
     }
+
+    /// 設定のGPIO番号（`voltage_adc_pin`、ADC1のGPIO1-10）で電圧を測定します
+    ///
+    /// `AnyIOPin` はADCチャンネルを持たないため、番号から対応するピン型を選びます。
+    /// 番号は `PinMap::validate` で検証済み（他の用途と重ならない）であることが前提です。
+    pub fn measure_voltage_percentage_on_gpio(adc: ADC1, pin_number: u8) -> anyhow::Result<(Percent, ADC1)> {
+        macro_rules! measure_on {
+            ($($number:literal => $gpio:ident),*) => {
+                match pin_number {
+                    $($number => {
+                        // SAFETY: 検証済みの割り当てではこのピンを使うのは電圧測定のみ
+                        let pin = unsafe { esp_idf_svc::hal::gpio::$gpio::new() };
+                        let (percent, adc, _) = Self::measure_voltage_percentage(adc, pin)?;
+                        Ok((percent, adc))
+                    })*
+                    _ => Err(anyhow::anyhow!("GPIO{}はADC1に対応していません", pin_number)),
+                }
+            };
+        }
+        measure_on!(
+            1 => Gpio1, 2 => Gpio2, 3 => Gpio3, 4 => Gpio4, 5 => Gpio5,
+            6 => Gpio6, 7 => Gpio7, 8 => Gpio8, 9 => Gpio9, 10 => Gpio10
+        )
+    }
 }
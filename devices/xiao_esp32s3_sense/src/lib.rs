@@ -8,32 +8,41 @@
  * - `hardware`: ハードウェア制御（カメラ、LED、電圧センサー、ピン設定）
  * - `communication`: 通信機能（ESP-NOW、ネットワーク管理）
  * - `power`: 電源管理（ディープスリープ）
+ * - `sys_wrappers`: ESP-IDFの呼び出し（unsafe）をまとめた薄いラッパーとピン操作の手順
  */
 
 // 公開モジュール
-#[cfg(not(test))]
+// ESP-IDF依存のモジュールは"esp"フィーチャー有効時のみコンパイル
+// （ホストCIでは `--no-default-features --features host` でハードウェア非依存部分のみビルド）
+#[cfg(feature = "esp")]
 pub mod communication;
-#[cfg(not(test))]
 pub mod config;
-pub mod core;  // Always public to support integration testing
-#[cfg(not(test))]
+pub mod core;
+#[cfg(feature = "esp")]
 pub mod hardware;
 pub mod mac_address;
-#[cfg(not(test))]
 pub mod power;
+pub mod sys_wrappers;
 pub mod utils;
 
+// ホストビルド用: ハードウェア非依存のモジュールのみ（モジュールパスはファームウェアと同じ）
+#[cfg(not(feature = "esp"))]
+pub mod hardware {
+    pub mod pin_map;
+}
+
 // 内部で使用する型をまとめてエクスポート
-#[cfg(not(test))]
+#[cfg(feature = "esp")]
 pub use communication::esp_now::{EspNowError, EspNowSender, EspNowReceiver};
-#[cfg(not(test))]
 pub use config::{AppConfig, ConfigError, MemoryConfig};
-pub use core::{DataService, MeasuredData};  // Always public to support integration testing
-#[cfg(not(test))]
+#[cfg(feature = "esp")]
+pub use core::DataService;
+pub use core::MeasuredData;
+#[cfg(feature = "esp")]
 pub use hardware::camera::CameraController;
-#[cfg(not(test))]
+#[cfg(feature = "esp")]
 pub use hardware::led::status_led::{LedError, StatusLed};
-#[cfg(not(test))]
+#[cfg(feature = "esp")]
 pub use hardware::{CameraPins, VoltageSensor};
 pub use mac_address::MacAddress;
 pub use utils::calculate_voltage_percentage;
@@ -1,11 +1,13 @@
 use esp_idf_svc::{
     eventloop::EspSystemEventLoop,
+    hal::gpio::AnyOutputPin,
     hal::peripherals::Peripherals,
     nvs::EspDefaultNvsPartition,
     wifi::{BlockingWifi, EspWifi},
     espnow::EspNow,
 };
 use std::sync::{Arc, Mutex};
+use std::time::Instant;
 
 // 内部モジュール
 mod communication;
@@ -14,16 +16,20 @@ mod core;
 mod hardware;
 mod mac_address;
 mod power;
+mod sys_wrappers;
 mod utils;
 
 // 使用するモジュールのインポート
-use communication::{NetworkManager, esp_now::{EspNowSender, EspNowReceiver}};
+use communication::{NetworkManager, esp_now::{DualSender, EspNowSender, EspNowReceiver, ImageTransport}};
 use config::AppConfig;
-use core::{AppController, DataService, MeasuredData, RtcManager};
+use core::{AmbientSnapshot, AppController, DataService, MeasuredData, RtcManager};
 use hardware::{CameraPins, VoltageSensor, TempSensor};
 use hardware::led::StatusLed;
 use log::{error, info, warn};
-use power::sleep::{SleepManager, EspIdfDeepSleep, EspIdfLightSleep, SleepType};
+use power::critical_battery;
+use power::sleep::{DeepSleepPlatform, SleepManager, EspIdfDeepSleep, EspIdfLightSleep, SleepType};
+use sys_wrappers::esp::EspSys;
+use utils::Millivolts;
 
 /// アプリケーションのメインエントリーポイント
 fn main() -> anyhow::Result<()> {
@@ -32,11 +38,7 @@ fn main() -> anyhow::Result<()> {
     esp_idf_svc::log::EspLogger::initialize_default();
 
     // [PHASE 8] スリープ中に固定されていたピンを解放
-    unsafe {
-        for pin in [2, 5, 21, 10, 15, 17, 18, 16, 14, 12, 11, 48, 38, 47, 13, 40, 39] {
-            esp_idf_sys::gpio_hold_dis(pin as i32);
-        }
-    }
+    sys_wrappers::release_pin_holds(&EspSys);
     info!("✓ スリープ解除に伴い全ピンの固定(Hold)を解除しました");
 
     // 設定ファイル読み込み
@@ -47,14 +49,42 @@ fn main() -> anyhow::Result<()> {
 
     // ペリフェラルとシステムリソースの初期化 (これらは一度だけ行う)
     info!("ペリフェラルを初期化しています");
-    let peripherals = Peripherals::take().expect("Failed to take peripherals");
+    let peripherals = Peripherals::take().map_err(|e| anyhow::anyhow!("ペリフェラルを取得できません: {:?}", e))?;
     let sysloop = EspSystemEventLoop::take()?;
     let nvs_partition = EspDefaultNvsPartition::take()?;
 
     let pins = peripherals.pins;
 
+    // 電圧測定（他の周辺機器を初期化する前に行い、電池残量が危機的ならここで終了する）
+    // センサー・LEDのGPIOは設定の番号から取り出す（AppConfig::load で検証済み）
+    let mut adc1 = peripherals.adc1;
+    let (boot_voltage_percent, returned_adc1) =
+        VoltageSensor::measure_voltage_percentage_on_gpio(adc1, app_config.voltage_adc_pin)?;
+    adc1 = returned_adc1;
+
+    if critical_battery::is_critical(
+        boot_voltage_percent.value(),
+        app_config.critical_battery_percent,
+        app_config.bypass_voltage_threshold,
+    ) {
+        RtcManager::record_critical_battery(boot_voltage_percent.value());
+        warn!(
+            "電池残量が危機的なため周辺機器を初期化せずに{}秒間Deep Sleepします",
+            app_config.critical_battery_sleep_seconds
+        );
+        EspIdfDeepSleep.deep_sleep(app_config.critical_battery_sleep_seconds * 1_000_000);
+        return Ok(());
+    }
+    let recovered_boots = RtcManager::recover_from_critical_battery();
+    if recovered_boots > 0 {
+        info!("✓ 電池残量が回復しました (危機的電圧での起動: 連続{}回)", recovered_boots);
+    }
+    // 起動直後の測定値は最初のループで使う
+    let mut pending_voltage_percent = Some(boot_voltage_percent);
+
     // ステータスLEDの初期化 (一度だけ)
-    let mut led = StatusLed::new(pins.gpio21)?;
+    // SAFETY: status_led_pin はカメラ・センサーのピンと重ならないことを検証済み
+    let mut led = StatusLed::new(unsafe { AnyOutputPin::new(app_config.status_led_pin as i32) })?;
     led.turn_off()?;
     
     if app_config.debug_mode {
@@ -62,7 +92,8 @@ fn main() -> anyhow::Result<()> {
     }
 
     // スリープマネージャーの初期化
-    let sleep_manager = SleepManager::new(EspIdfDeepSleep, EspIdfLightSleep, 600);
+    let sleep_manager = SleepManager::new(EspIdfDeepSleep, EspIdfLightSleep, 600)
+        .with_compensation_micros(app_config.sleep_compensation_micros);
 
     // タイムゾーン設定
     let timezone = app_config.timezone.parse().unwrap_or(chrono_tz::Asia::Tokyo);
@@ -73,8 +104,6 @@ fn main() -> anyhow::Result<()> {
     // WiFiリソース管理 (Light Sleep復帰後の再初期化対応)
     let mut wifi_resources: Option<(BlockingWifi<EspWifi<'static>>, Arc<Mutex<EspNow<'static>>>, EspNowReceiver)> = None;
 
-    let mut adc1 = peripherals.adc1;
-    let mut voltage_pin = pins.gpio4;
     let rmt0 = peripherals.rmt.channel0;
 
     info!("=== HYBRID SLEEP LOOPを開始します ===");
@@ -91,6 +120,7 @@ fn main() -> anyhow::Result<()> {
                 &nvs_partition,
                 app_config.wifi_tx_power_dbm,
                 app_config.wifi_init_delay_ms,
+                app_config.esp_now_phy_rate,
             )?;
             
             let (esp_now_arc, receiver) = NetworkManager::initialize_esp_now(&wifi_conn)?;
@@ -98,13 +128,16 @@ fn main() -> anyhow::Result<()> {
             info!("✓ WiFi/ESP-NOWリソースの初期化が完了しました");
         }
 
-        // 電圧測定
-        let (voltage_percent, returned_adc1, returned_vpin) = VoltageSensor::measure_voltage_percentage(
-            adc1,
-            voltage_pin,
-        )?;
-        adc1 = returned_adc1;
-        voltage_pin = returned_vpin;
+        // 電圧測定（初回は起動直後の測定値を使う）
+        let voltage_percent = match pending_voltage_percent.take() {
+            Some(voltage_percent) => voltage_percent,
+            None => {
+                let (voltage_percent, returned_adc1) =
+                    VoltageSensor::measure_voltage_percentage_on_gpio(adc1, app_config.voltage_adc_pin)?;
+                adc1 = returned_adc1;
+                voltage_percent
+            }
+        };
 
         /* デバッグのためスキップ
         // 低電圧チェック
@@ -117,25 +150,9 @@ fn main() -> anyhow::Result<()> {
         // データ収集
         let mut measured_data = MeasuredData::new(voltage_percent, None);
 
-        // 温度測定
-        if app_config.temp_sensor_enabled {
-            let channel_copy: esp_idf_svc::hal::rmt::CHANNEL0 = unsafe { std::mem::transmute_copy(&rmt0) };
-            if let Ok(mut sensor) = TempSensor::new(
-                app_config.temp_sensor_power_pin,
-                app_config.temp_sensor_data_pin,
-                app_config.temperature_offset_celsius,
-                channel_copy,
-            ) {
-                if let Ok(reading) = sensor.read_temperature() {
-                    measured_data = measured_data.with_temperature(Some(reading.corrected_temperature_celsius));
-                }
-                let _ = sensor.power_off();
-            }
-        }
-
-        // 起動カウンタ
+        // 起動カウンタ（TDSセンサー未使用のため `TDS_VOLT` 欄にボルト表記の数値として載せる）
         let boot_count = RtcManager::get_boot_count();
-        measured_data = measured_data.with_tds_voltage(Some(boot_count as f32));
+        measured_data = measured_data.with_tds_voltage(Some(Millivolts::from_volts(boot_count as f32)));
 
         // 画像キャプチャ
         let camera_pins = unsafe {
@@ -172,41 +189,74 @@ fn main() -> anyhow::Result<()> {
                 crate::hardware::camera::reset_camera_pins();
             }
         }
+        let shutter = Instant::now();
+
+        // シャッター直後の環境スナップショット（電池残量を測り直し、温度は撮影後に測る）
+        let (battery_at_shutter, returned_adc1) =
+            VoltageSensor::measure_voltage_percentage_on_gpio(adc1, app_config.voltage_adc_pin)?;
+        adc1 = returned_adc1;
+        let mut ambient = AmbientSnapshot::default().with_battery(battery_at_shutter, elapsed_ms(shutter));
+
+        // 温度測定
+        if app_config.temp_sensor_enabled {
+            let channel_copy: esp_idf_svc::hal::rmt::CHANNEL0 = unsafe { std::mem::transmute_copy(&rmt0) };
+            if let Ok(mut sensor) = TempSensor::new(
+                app_config.temp_sensor_power_pin,
+                app_config.temp_sensor_data_pin,
+                app_config.temperature_offset_celsius,
+                channel_copy,
+            ) {
+                if let Ok(reading) = sensor.read_temperature() {
+                    measured_data = measured_data.with_temperature(Some(reading.corrected_temperature));
+                    ambient = ambient.with_temperature(Some(reading.corrected_temperature), elapsed_ms(shutter));
+                }
+                let _ = sensor.power_off();
+            }
+        }
+
+        // 撮影した画像にだけ環境スナップショットを載せる
+        if measured_data.image_data.as_ref().is_some_and(|data| !data.is_empty()) {
+            info!("環境スナップショット: {:?}", ambient);
+            measured_data = measured_data.with_ambient(Some(ambient));
+        }
 
         // データ送信
         {
-            let (_, ref esp_now_arc, _) = wifi_resources.as_ref().unwrap();
-            let sender = EspNowSender::new(Arc::clone(esp_now_arc), app_config.receiver_mac.clone())?;
+            let (_, ref esp_now_arc, _) = wifi_resources
+                .as_ref()
+                .ok_or_else(|| anyhow::anyhow!("WiFi/ESP-NOWリソースが初期化されていません"))?;
+            // 2台目の受信機が設定されていれば2系統に振り分けて送信
+            let sender: Box<dyn ImageTransport> = match &app_config.secondary_receiver_mac {
+                Some(secondary_mac) => Box::new(DualSender::new(
+                    Arc::clone(esp_now_arc),
+                    app_config.receiver_mac.clone(),
+                    secondary_mac.clone(),
+                    app_config.dual_sender_mode,
+                    app_config.dual_sender_max_failure_percent,
+                )?),
+                None => Box::new(EspNowSender::new(Arc::clone(esp_now_arc), app_config.receiver_mac.clone())?),
+            };
             info!("データ送信中...");
-            let _ = DataService::transmit_data(&app_config, &sender, &mut led, measured_data);
+            let _ = DataService::transmit_data(&app_config, sender.as_ref(), &mut led, measured_data);
         }
 
         // スリープ管理
         led.turn_off()?;
         let sleep_type = {
-            let (_, _, ref receiver) = wifi_resources.as_ref().unwrap();
+            let (_, _, ref receiver) = wifi_resources
+                .as_ref()
+                .ok_or_else(|| anyhow::anyhow!("WiFi/ESP-NOWリソースが初期化されていません"))?;
             AppController::handle_sleep_with_server_command(receiver, &sleep_manager, &app_config)?
         };
 
         if sleep_type == SleepType::Light {
             // [PHASE 11] Light Sleep復帰後、Deep Sleepと同様にピンの固定を解除する
             // これにより reset_camera_pins() で固定されたピンを再利用可能にする
-            unsafe {
-                for pin in [2, 5, 21, 10, 15, 17, 18, 16, 14, 12, 11, 48, 38, 47, 13, 40, 39] {
-                    esp_idf_sys::gpio_hold_dis(pin as i32);
-                }
-            }
+            sys_wrappers::release_pin_holds(&EspSys);
             info!("✓ Light Sleep復帰に伴い全ピンの固定(Hold)を解除しました");
 
             // 復帰確認の点滅
-            unsafe {
-                for _ in 0..10 {
-                    esp_idf_sys::gpio_set_level(21, 0);
-                    esp_idf_sys::vTaskDelay(5);
-                    esp_idf_sys::gpio_set_level(21, 1);
-                    esp_idf_sys::vTaskDelay(5);
-                }
-            }
+            sys_wrappers::blink_status_led(&EspSys, 10, || sys_wrappers::esp::delay_ticks(5));
             
             // WiFiリソースを破棄 (スリープ前にdeinitされているため)
             wifi_resources = None;
@@ -221,4 +271,9 @@ fn main() -> anyhow::Result<()> {
     Ok(())
 }
 
-const LOW_VOLTAGE_THRESHOLD_PERCENT: u8 = 30;
\ No newline at end of file
+const LOW_VOLTAGE_THRESHOLD_PERCENT: u8 = 30;
+
+/// `since` からの経過時間（ms）
+fn elapsed_ms(since: Instant) -> u32 {
+    since.elapsed().as_millis().min(u32::MAX as u128) as u32
+}
\ No newline at end of file
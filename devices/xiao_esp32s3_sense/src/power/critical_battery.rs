@@ -0,0 +1,83 @@
+/// 電池残量が危機的な場合の起動処理
+///
+/// 電圧測定の直後、LED・RTC・センサーを初期化する前に判定し、該当すれば
+/// 何も初期化せずに長時間のDeep Sleepへ入ります。太陽光による電池の回復を優先するためです。
+use log::warn;
+
+use crate::utils::Percent;
+
+/// 電圧測定に失敗したことを示す値（`VoltageSensor::measure_voltage_percentage` が返す `Percent::INVALID`）
+pub const VOLTAGE_MEASUREMENT_FAILED: u8 = Percent::INVALID.value();
+
+/// 電池残量が危機的か判定します
+///
+/// 測定失敗（255）は電池残量が不明なため、危機的とはみなしません。
+pub fn is_critical(voltage_percent: u8, threshold_percent: u8, bypass: bool) -> bool {
+    !bypass && voltage_percent != VOLTAGE_MEASUREMENT_FAILED && voltage_percent <= threshold_percent
+}
+
+/// RTCメモリに保持する危機的電圧での起動の記録
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CriticalBatteryLog {
+    /// 電源投入以降に危機的電圧で起動した回数
+    pub total: u32,
+    /// 直近で連続して危機的電圧で起動した回数
+    pub consecutive: u32,
+    /// 最後に危機的と判定した電圧（%）
+    pub last_percent: u8,
+}
+
+impl CriticalBatteryLog {
+    /// 空の記録を作成します
+    pub const fn new() -> Self {
+        Self {
+            total: 0,
+            consecutive: 0,
+            last_percent: 0,
+        }
+    }
+
+    /// 危機的電圧での起動を記録します
+    pub fn record(&mut self, voltage_percent: u8) {
+        self.total = self.total.saturating_add(1);
+        self.consecutive = self.consecutive.saturating_add(1);
+        self.last_percent = voltage_percent;
+        warn!(
+            "電池残量が危機的です ({}%)。連続{}回目 (累計{}回)",
+            voltage_percent, self.consecutive, self.total
+        );
+    }
+
+    /// 通常起動できたときに連続回数を戻し、直前まで続いていた回数を返します
+    pub fn recover(&mut self) -> u32 {
+        std::mem::take(&mut self.consecutive)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_critical_ignores_failed_measurement_and_bypass() {
+        assert!(is_critical(0, 0, false));
+        assert!(is_critical(3, 5, false));
+        assert!(!is_critical(6, 5, false));
+        assert!(!is_critical(0, 0, true));
+        assert!(!is_critical(VOLTAGE_MEASUREMENT_FAILED, 100, false));
+    }
+
+    #[test]
+    fn test_log_counts_consecutive_critical_boots() {
+        let mut log = CriticalBatteryLog::new();
+        log.record(0);
+        log.record(1);
+        assert_eq!(log.consecutive, 2);
+        assert_eq!(log.last_percent, 1);
+
+        assert_eq!(log.recover(), 2);
+        log.record(0);
+        assert_eq!(log, CriticalBatteryLog { total: 3, consecutive: 1, last_percent: 0 });
+    }
+}
@@ -1,2 +1,3 @@
 /// 電源管理モジュール
+pub mod critical_battery;
 pub mod sleep;
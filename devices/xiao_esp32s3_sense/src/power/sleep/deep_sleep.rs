@@ -1,5 +1,8 @@
 use log::info;
 
+#[cfg(feature = "esp")]
+use crate::sys_wrappers::esp as sys;
+
 #[derive(Debug, thiserror::Error)]
 pub enum DeepSleepError {
     #[error("Invalid sleep duration: {0}")]
@@ -13,26 +16,115 @@ pub trait DeepSleepPlatform {
 }
 
 /// ESP-IDF specific deep sleep implementation.
+#[cfg(feature = "esp")]
 pub struct EspIdfDeepSleep;
 
+#[cfg(feature = "esp")]
 impl DeepSleepPlatform for EspIdfDeepSleep {
     fn deep_sleep(&self, duration_us: u64) {
         info!("Entering deep sleep for {} microseconds", duration_us);
-        unsafe {
-            // [PHASE 8] 確実な復帰のため、タイマーウェイクアップを明示的に設定
-            esp_idf_sys::esp_sleep_enable_timer_wakeup(duration_us);
-            
-            // [PHASE 8] ディープスリープを開始
-            info!("---[STARTING DEEP SLEEP]---");
-            esp_idf_sys::esp_deep_sleep_start();
-            
-            // 通常、ここは実行されない
-            info!("❌ CRITICAL: esp_deep_sleep_start() から戻ってしまいました。OS再起動を実行します。");
-            esp_idf_sys::esp_restart();
+        // [PHASE 8] 確実な復帰のため、タイマーウェイクアップを明示的に設定
+        let _ = sys::enable_timer_wakeup(duration_us);
+
+        // [PHASE 8] ディープスリープを開始
+        info!("---[STARTING DEEP SLEEP]---");
+        sys::deep_sleep_start();
+
+        // 通常、ここは実行されない
+        info!("❌ CRITICAL: esp_deep_sleep_start() から戻ってしまいました。OS再起動を実行します。");
+        sys::restart();
+    }
+}
+
+impl<P: DeepSleepPlatform + ?Sized> DeepSleepPlatform for &P {
+    fn deep_sleep(&self, duration_us: u64) {
+        (**self).deep_sleep(duration_us);
+    }
+}
+
+/// Why a mocked deep sleep ended.
+#[cfg(feature = "mock-hw")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WakeCause {
+    /// The timer expired after the requested duration.
+    Timer,
+    /// A scheduled external wake (e.g. GPIO) interrupted the sleep early.
+    External,
+}
+
+/// One mocked deep sleep/wake cycle.
+#[cfg(feature = "mock-hw")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MockWake {
+    /// Requested duration in microseconds.
+    pub requested_us: u64,
+    /// Time spent asleep on the virtual clock in microseconds.
+    pub slept_us: u64,
+    /// Why the sleep ended.
+    pub wake_cause: WakeCause,
+}
+
+#[cfg(feature = "mock-hw")]
+#[derive(Debug, Default)]
+struct MockDeepSleepState {
+    now_us: u64,
+    external_wake_at_us: Option<u64>,
+    wakes: Vec<MockWake>,
+}
+
+/// Deep sleep mock that advances a virtual clock and records each wake instead of sleeping.
+#[cfg(feature = "mock-hw")]
+#[derive(Debug, Default)]
+pub struct MockDeepSleep {
+    state: std::sync::Mutex<MockDeepSleepState>,
+}
+
+#[cfg(feature = "mock-hw")]
+impl MockDeepSleep {
+    /// Durations (microseconds) requested so far, oldest first.
+    pub fn requested_durations(&self) -> Vec<u64> {
+        self.wakes().iter().map(|wake| wake.requested_us).collect()
+    }
+
+    /// Sleep/wake cycles so far, oldest first.
+    pub fn wakes(&self) -> Vec<MockWake> {
+        self.state.lock().map(|s| s.wakes.clone()).unwrap_or_default()
+    }
+
+    /// Current virtual time in microseconds.
+    pub fn now_us(&self) -> u64 {
+        self.state.lock().map(|s| s.now_us).unwrap_or_default()
+    }
+
+    /// End the next sleep still in progress at `at_us` (virtual time) with `WakeCause::External`.
+    pub fn schedule_external_wake(&self, at_us: u64) {
+        if let Ok(mut state) = self.state.lock() {
+            state.external_wake_at_us = Some(at_us);
         }
     }
 }
 
+#[cfg(feature = "mock-hw")]
+impl DeepSleepPlatform for MockDeepSleep {
+    fn deep_sleep(&self, duration_us: u64) {
+        info!("Mock deep sleep for {} microseconds", duration_us);
+        let Ok(mut state) = self.state.lock() else {
+            return;
+        };
+        let timer_wake_us = state.now_us.saturating_add(duration_us);
+        let (wake_at_us, wake_cause) = match state.external_wake_at_us {
+            Some(at_us) if at_us >= state.now_us && at_us < timer_wake_us => {
+                state.external_wake_at_us = None;
+                (at_us, WakeCause::External)
+            }
+            _ => (timer_wake_us, WakeCause::Timer),
+        };
+        let slept_us = wake_at_us - state.now_us;
+        state.wakes.push(MockWake { requested_us: duration_us, slept_us, wake_cause });
+        state.now_us = wake_at_us;
+    }
+}
+
 /// Deep sleep controller with platform abstraction.
 pub struct DeepSleep<P: DeepSleepPlatform> {
     platform: P,
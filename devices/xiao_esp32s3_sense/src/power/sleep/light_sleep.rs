@@ -1,5 +1,8 @@
 use log::info;
 
+#[cfg(feature = "esp")]
+use crate::sys_wrappers::{self, esp as sys, esp::EspSys, Gpio};
+
 /// Platform-agnostic light-sleep abstraction.
 pub trait LightSleepPlatform {
     /// Enter light sleep for the specified duration in microseconds.
@@ -7,72 +10,77 @@ pub trait LightSleepPlatform {
 }
 
 /// ESP-IDF specific light sleep implementation.
+#[cfg(feature = "esp")]
 pub struct EspIdfLightSleep;
 
+#[cfg(feature = "esp")]
 impl LightSleepPlatform for EspIdfLightSleep {
     fn light_sleep(&self, duration_us: u64) {
         info!("Entering light sleep for {} microseconds", duration_us);
-        unsafe {
-            // タイマーウェイクアップを設定
-            esp_idf_sys::esp_sleep_enable_timer_wakeup(duration_us);
-            
-            // [IMPORTANT] ESP32-S3でPSRAMを使用している場合、VDD_SDIOドメインをONに保持する必要がある
-            esp_idf_sys::esp_sleep_pd_config(
-                esp_idf_sys::esp_sleep_pd_domain_t_ESP_PD_DOMAIN_VDDSDIO,
-                esp_idf_sys::esp_sleep_pd_option_t_ESP_PD_OPTION_ON,
-            );
-            
-            // WiFiモデムのPower DomainをOFFに設定（deinit済みのため不要）
-            esp_idf_sys::esp_sleep_pd_config(
-                esp_idf_sys::esp_sleep_pd_domain_t_ESP_PD_DOMAIN_MODEM,
-                esp_idf_sys::esp_sleep_pd_option_t_ESP_PD_OPTION_OFF,
-            );
-            
-            // RTC周辺機器ドメインをOFFに設定（GPIO HoldはRTC_PERIPHなしでも保持される）
-            esp_idf_sys::esp_sleep_pd_config(
-                esp_idf_sys::esp_sleep_pd_domain_t_ESP_PD_DOMAIN_RTC_PERIPH,
-                esp_idf_sys::esp_sleep_pd_option_t_ESP_PD_OPTION_OFF,
-            );
-            
-            // ライトスリープを開始（CPU実行を一時停止し、復帰後はこの次から再開される）
-            info!("---[ENTERING LIGHT SLEEP]---");
-            // ログのフラッシュを促すため、ごくわずかに待機
-            esp_idf_sys::vTaskDelay(10); 
-
-            let err = esp_idf_sys::esp_light_sleep_start();
-            
-            // 復帰確認用: LEDを消灯
-            esp_idf_sys::gpio_set_level(21, 1);
-
-            // 復帰直後、ホスト側のUSBシリアルスタックの再認識を待つため、長めに待機
-            // (USB-Serial-JTAGが切断されるため再接続の時間が必要)
-            esp_idf_sys::vTaskDelay(200); 
-            
-            // 復帰直後：まずは全ピンのHoldを解除（これをしないとUART出力すら出ない場合がある）
-            // Xiao ESP32S3 Senseで使用しているピンのHoldを解除
-            for pin in [2, 5, 21, 10, 15, 17, 18, 16, 14, 12, 11, 48, 38, 47, 13, 40, 39] {
-                esp_idf_sys::gpio_hold_dis(pin as i32);
-            }
-
-            if err != 0 {
-                // エラー時はログを試みるが、通常は成功するはず
-                // log::error!("!!! LIGHT SLEEP FAILURE: error code {} !!!", err);
-            }
-            
-            // LED信号（高速3回点滅）で物理的に復帰を通知
-            
-            // LED信号（高速3回点滅）で物理的に復帰を通知
-            // インスタンスを使わず raw GPIO (GPIO 21) で操作
-            for _ in 0..3 {
-                esp_idf_sys::gpio_set_level(21, 0); // ON
-                esp_idf_sys::vTaskDelay(5);
-                esp_idf_sys::gpio_set_level(21, 1); // OFF
-                esp_idf_sys::vTaskDelay(5);
-            }
-
-            info!("**********************************************");
-            info!("*** WAKING UP FROM LIGHT SLEEP (SUCCESS) ***");
-            info!("**********************************************");
+        // タイマーウェイクアップを設定
+        let _ = sys::enable_timer_wakeup(duration_us);
+
+        // [IMPORTANT] ESP32-S3でPSRAMを使用している場合、VDD_SDIOドメインをONに保持する必要がある
+        // WiFiモデム（deinit済み）とRTC周辺機器のドメインはOFF
+        sys::configure_light_sleep_domains();
+
+        // ライトスリープを開始（CPU実行を一時停止し、復帰後はこの次から再開される）
+        info!("---[ENTERING LIGHT SLEEP]---");
+        // ログのフラッシュを促すため、ごくわずかに待機
+        sys::delay_ticks(10);
+
+        let result = sys::light_sleep_start();
+
+        // 復帰確認用: LEDを消灯
+        let _ = EspSys.set_level(sys_wrappers::STATUS_LED_PIN, true);
+
+        // 復帰直後、ホスト側のUSBシリアルスタックの再認識を待つため、長めに待機
+        // (USB-Serial-JTAGが切断されるため再接続の時間が必要)
+        sys::delay_ticks(200);
+
+        // 復帰直後：まずは全ピンのHoldを解除（これをしないとUART出力すら出ない場合がある）
+        sys_wrappers::release_pin_holds(&EspSys);
+
+        if let Err(e) = result {
+            log::error!("!!! LIGHT SLEEP FAILURE: error code {} !!!", e.code());
+        }
+
+        // LED信号（高速3回点滅）で物理的に復帰を通知
+        sys_wrappers::blink_status_led(&EspSys, 3, || sys::delay_ticks(5));
+
+        info!("**********************************************");
+        info!("*** WAKING UP FROM LIGHT SLEEP (SUCCESS) ***");
+        info!("**********************************************");
+    }
+}
+
+impl<P: LightSleepPlatform + ?Sized> LightSleepPlatform for &P {
+    fn light_sleep(&self, duration_us: u64) {
+        (**self).light_sleep(duration_us);
+    }
+}
+
+/// Light sleep mock that records requested durations instead of sleeping.
+#[cfg(feature = "mock-hw")]
+#[derive(Debug, Default)]
+pub struct MockLightSleep {
+    requests: std::sync::Mutex<Vec<u64>>,
+}
+
+#[cfg(feature = "mock-hw")]
+impl MockLightSleep {
+    /// Durations (microseconds) requested so far, oldest first.
+    pub fn requested_durations(&self) -> Vec<u64> {
+        self.requests.lock().map(|r| r.clone()).unwrap_or_default()
+    }
+}
+
+#[cfg(feature = "mock-hw")]
+impl LightSleepPlatform for MockLightSleep {
+    fn light_sleep(&self, duration_us: u64) {
+        info!("Mock light sleep for {} microseconds", duration_us);
+        if let Ok(mut requests) = self.requests.lock() {
+            requests.push(duration_us);
         }
     }
 }
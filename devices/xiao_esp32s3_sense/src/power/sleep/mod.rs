@@ -1,10 +1,13 @@
-use log::info;
+use log::{info, warn};
 pub mod deep_sleep;
 pub mod light_sleep;
 
 pub use deep_sleep::*;
 pub use light_sleep::*;
 
+/// 補正後のDeep Sleep時間の下限（マイクロ秒）
+const MIN_COMPENSATED_SLEEP_US: u64 = 1_000_000;
+
 /// スリープの種類
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SleepType {
@@ -18,8 +21,10 @@ pub enum SleepType {
 pub struct SleepManager<D: DeepSleepPlatform, L: LightSleepPlatform> {
     deep_platform: D,
     light_platform: L,
-    /// Light Sleepを選択する最大秒数 (これを超えるとDeep Sleep)
+    /// Light Sleepを選択する最大秒数 (これ以上はDeep Sleep)
     light_sleep_threshold_sec: u64,
+    /// Deep Sleep時間に加える補正値（マイクロ秒、起動にかかる時間の差し引きなど）
+    compensation_micros: i64,
 }
 
 impl<D: DeepSleepPlatform, L: LightSleepPlatform> SleepManager<D, L> {
@@ -28,20 +33,132 @@ impl<D: DeepSleepPlatform, L: LightSleepPlatform> SleepManager<D, L> {
             deep_platform,
             light_platform,
             light_sleep_threshold_sec: threshold,
+            compensation_micros: 0,
         }
     }
 
-    /// 指定された秒数に最適なスリープモードを選択して実行します
-    pub fn sleep_optimized(&self, duration_sec: u64) -> anyhow::Result<SleepType> {
+    /// Deep Sleep時間の補正値（`sleep_compensation_micros`）を設定します
+    ///
+    /// Light Sleepは再起動を伴わないため補正しません。
+    pub fn with_compensation_micros(mut self, compensation_micros: i64) -> Self {
+        self.compensation_micros = compensation_micros;
+        self
+    }
+
+    /// 指定された秒数で選択されるスリープモード
+    ///
+    /// スリープ前の周辺機器の停止処理も、この判定に合わせて行います。
+    pub fn sleep_type_for(&self, duration_sec: u64) -> SleepType {
         if duration_sec < self.light_sleep_threshold_sec {
-            info!("選択されたスリープモード: LIGHT SLEEP ({}秒 < {}秒閾値)", duration_sec, self.light_sleep_threshold_sec);
-            self.light_platform.light_sleep(duration_sec * 1_000_000);
-            Ok(SleepType::Light)
+            SleepType::Light
         } else {
-            info!("選択されたスリープモード: DEEP SLEEP ({}秒 >= {}秒閾値)", duration_sec, self.light_sleep_threshold_sec);
-            self.deep_platform.deep_sleep(duration_sec * 1_000_000);
-            // 通常ここには戻らない
-            Ok(SleepType::Deep)
+            SleepType::Deep
+        }
+    }
+
+    /// 指定された秒数に最適なスリープモードを選択して実行します
+    ///
+    /// 0秒やマイクロ秒換算で桁あふれする時間はスリープせずにエラーを返します。
+    pub fn sleep_optimized(&self, duration_sec: u64) -> anyhow::Result<SleepType> {
+        if duration_sec == 0 {
+            return Err(DeepSleepError::InvalidDuration("Sleep duration must be greater than 0".to_string()).into());
+        }
+        let duration_us = duration_sec
+            .checked_mul(1_000_000)
+            .ok_or_else(|| DeepSleepError::InvalidDuration("Duration overflow".to_string()))?;
+
+        match self.sleep_type_for(duration_sec) {
+            SleepType::Light => {
+                info!("選択されたスリープモード: LIGHT SLEEP ({}秒 < {}秒閾値)", duration_sec, self.light_sleep_threshold_sec);
+                self.light_platform.light_sleep(duration_us);
+                Ok(SleepType::Light)
+            }
+            SleepType::Deep => {
+                let compensated_us = duration_us
+                    .saturating_add_signed(self.compensation_micros)
+                    .max(MIN_COMPENSATED_SLEEP_US);
+                info!(
+                    "選択されたスリープモード: DEEP SLEEP ({}秒 >= {}秒閾値, 補正 {}us)",
+                    duration_sec, self.light_sleep_threshold_sec, self.compensation_micros
+                );
+                self.deep_platform.deep_sleep(compensated_us);
+                // 通常ここには戻らない
+                Ok(SleepType::Deep)
+            }
         }
     }
+
+    /// 指定された秒数でスリープし、無効な時間なら `fallback_sec` でスリープします
+    pub fn sleep_with_fallback(&self, duration_sec: u64, fallback_sec: u64) -> anyhow::Result<SleepType> {
+        self.sleep_optimized(duration_sec).or_else(|e| {
+            warn!("スリープ時間 {}秒 は無効です ({})。{}秒でスリープします", duration_sec, e, fallback_sec);
+            self.sleep_optimized(fallback_sec)
+        })
+    }
+}
+
+#[cfg(all(test, feature = "mock-hw"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sleep_optimized_selects_mode_by_threshold() {
+        let manager = SleepManager::new(MockDeepSleep::default(), MockLightSleep::default(), 60);
+
+        assert_eq!(manager.sleep_optimized(10).unwrap(), SleepType::Light);
+        assert_eq!(manager.sleep_optimized(60).unwrap(), SleepType::Deep);
+
+        assert_eq!(manager.light_platform.requested_durations(), vec![10_000_000]);
+        assert_eq!(manager.deep_platform.requested_durations(), vec![60_000_000]);
+    }
+
+    #[test]
+    fn test_compensation_applies_to_deep_sleep_only() {
+        let (deep, light) = (MockDeepSleep::default(), MockLightSleep::default());
+        let manager = SleepManager::new(&deep, &light, 600).with_compensation_micros(-6_147_250);
+
+        assert_eq!(manager.sleep_type_for(599), SleepType::Light);
+        assert_eq!(manager.sleep_type_for(600), SleepType::Deep);
+        manager.sleep_optimized(599).unwrap();
+        manager.sleep_optimized(600).unwrap();
+        // 補正で1秒未満にはならない
+        manager.sleep_optimized(3).unwrap();
+        let long = SleepManager::new(&deep, &light, 1).with_compensation_micros(-6_147_250);
+        long.sleep_optimized(5).unwrap();
+
+        assert_eq!(light.requested_durations(), vec![599_000_000, 3_000_000]);
+        assert_eq!(deep.requested_durations(), vec![593_852_750, 1_000_000]);
+        assert_eq!(deep.now_us(), 593_852_750 + 1_000_000);
+    }
+
+    #[test]
+    fn test_invalid_duration_falls_back_without_sleeping() {
+        let (deep, light) = (MockDeepSleep::default(), MockLightSleep::default());
+        let manager = SleepManager::new(&deep, &light, 600);
+
+        assert!(manager.sleep_optimized(0).is_err());
+        assert!(manager.sleep_optimized(u64::MAX).is_err());
+        assert!(deep.wakes().is_empty());
+        assert!(light.requested_durations().is_empty());
+
+        assert_eq!(manager.sleep_with_fallback(0, 900).unwrap(), SleepType::Deep);
+        assert_eq!(manager.sleep_with_fallback(30, 900).unwrap(), SleepType::Light);
+        assert!(manager.sleep_with_fallback(0, 0).is_err());
+        assert_eq!(deep.requested_durations(), vec![900_000_000]);
+        assert_eq!(light.requested_durations(), vec![30_000_000]);
+    }
+
+    #[test]
+    fn test_external_wake_interrupts_deep_sleep() {
+        let manager = SleepManager::new(MockDeepSleep::default(), MockLightSleep::default(), 60);
+        manager.deep_platform.schedule_external_wake(10_000_000);
+
+        manager.sleep_optimized(60).unwrap();
+        manager.sleep_optimized(60).unwrap();
+
+        let wakes = manager.deep_platform.wakes();
+        assert_eq!((wakes[0].slept_us, wakes[0].wake_cause), (10_000_000, WakeCause::External));
+        assert_eq!((wakes[1].slept_us, wakes[1].wake_cause), (60_000_000, WakeCause::Timer));
+        assert_eq!(manager.deep_platform.now_us(), 70_000_000);
+    }
 }
@@ -0,0 +1,149 @@
+//! ESP-IDF呼び出しの実装（このモジュールの外には `unsafe` なESP-IDF呼び出しを置かない）
+
+use esp_idf_sys::{self as sys, esp_err_t, ESP_OK};
+
+use super::{Gpio, SysError, SysResult, WifiRadio};
+
+/// ESP-IDFの戻り値をResultに変換します
+pub fn check(code: esp_err_t) -> SysResult<()> {
+    if code == ESP_OK {
+        Ok(())
+    } else {
+        Err(SysError(code))
+    }
+}
+
+/// ESP-IDFを使う `WifiRadio`・`Gpio` の実装（状態を持たない）
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EspSys;
+
+impl WifiRadio for EspSys {
+    fn sta_mac(&self) -> SysResult<[u8; 6]> {
+        let mut mac = [0u8; 6];
+        check(unsafe { sys::esp_wifi_get_mac(sys::wifi_interface_t_WIFI_IF_STA, mac.as_mut_ptr()) })?;
+        Ok(mac)
+    }
+}
+
+impl Gpio for EspSys {
+    fn set_output(&self, pin: i32) -> SysResult<()> {
+        check(unsafe { sys::gpio_set_direction(pin, sys::gpio_mode_t_GPIO_MODE_OUTPUT) })
+    }
+
+    fn set_level(&self, pin: i32, high: bool) -> SysResult<()> {
+        check(unsafe { sys::gpio_set_level(pin, u32::from(high)) })
+    }
+
+    fn set_hold(&self, pin: i32, hold: bool) -> SysResult<()> {
+        check(unsafe {
+            if hold {
+                sys::gpio_hold_en(pin)
+            } else {
+                sys::gpio_hold_dis(pin)
+            }
+        })
+    }
+
+    fn configure_input(&self, pin_mask: u64) -> SysResult<()> {
+        let config = sys::gpio_config_t {
+            pin_bit_mask: pin_mask,
+            mode: sys::gpio_mode_t_GPIO_MODE_INPUT,
+            pull_up_en: sys::gpio_pullup_t_GPIO_PULLUP_DISABLE,
+            pull_down_en: sys::gpio_pulldown_t_GPIO_PULLDOWN_DISABLE,
+            intr_type: sys::gpio_int_type_t_GPIO_INTR_DISABLE,
+        };
+        check(unsafe { sys::gpio_config(&config) })
+    }
+}
+
+/// 最大送信パワーを設定します（dBm。ESP-IDFは0.25dBm単位）
+///
+/// config.rsで 2〜20dBm にクランプ済みのため、換算後の 8〜80 は i8 に収まります。
+pub fn set_max_tx_power_dbm(dbm: i8) -> SysResult<()> {
+    check(unsafe { sys::esp_wifi_set_max_tx_power((i16::from(dbm) * 4) as i8) })
+}
+
+/// ESP-NOWの送信レート（`wifi_phy_rate_t` の値）を設定します
+pub fn set_espnow_rate(phy_rate: u8) -> SysResult<()> {
+    check(unsafe { sys::esp_wifi_config_espnow_rate(sys::wifi_interface_t_WIFI_IF_STA, phy_rate.into()) })
+}
+
+/// Wi-Fiのパワーセーブを無効にします
+pub fn disable_wifi_power_save() -> SysResult<()> {
+    check(unsafe { sys::esp_wifi_set_ps(sys::wifi_ps_type_t_WIFI_PS_NONE) })
+}
+
+/// Wi-Fi設定の保存先をRAMにします
+pub fn set_wifi_storage_ram() -> SysResult<()> {
+    check(unsafe { sys::esp_wifi_set_storage(sys::wifi_storage_t_WIFI_STORAGE_RAM) })
+}
+
+/// ESP-NOWの受信コールバックを登録します
+pub fn register_esp_now_recv_cb(recv_cb: sys::esp_now_recv_cb_t) -> SysResult<()> {
+    check(unsafe { sys::esp_now_register_recv_cb(recv_cb) })
+}
+
+/// ESP-NOWとWi-Fiを停止してドライバーを解放します（スリープ前。失敗しても残りの停止を続ける）
+pub fn shutdown_radio() {
+    unsafe {
+        let _ = sys::esp_now_deinit();
+        let _ = sys::esp_wifi_stop();
+        let _ = sys::esp_wifi_deinit();
+    }
+}
+
+/// FreeRTOSのティック数だけ待機します
+pub fn delay_ticks(ticks: u32) {
+    unsafe { sys::vTaskDelay(ticks) }
+}
+
+/// スリープからのタイマー起床を設定します
+pub fn enable_timer_wakeup(duration_us: u64) -> SysResult<()> {
+    check(unsafe { sys::esp_sleep_enable_timer_wakeup(duration_us) })
+}
+
+/// Deep sleepを開始します（戻らない）
+pub fn deep_sleep_start() {
+    unsafe { sys::esp_deep_sleep_start() }
+}
+
+/// Light sleep中の電源ドメインを設定します
+///
+/// PSRAMのためVDD_SDIOはONに保ち、停止済みの無線（MODEM）とRTC周辺機器はOFFにします
+/// （GPIOのHoldはRTC周辺機器なしでも保持される）。
+pub fn configure_light_sleep_domains() {
+    unsafe {
+        sys::esp_sleep_pd_config(
+            sys::esp_sleep_pd_domain_t_ESP_PD_DOMAIN_VDDSDIO,
+            sys::esp_sleep_pd_option_t_ESP_PD_OPTION_ON,
+        );
+        sys::esp_sleep_pd_config(
+            sys::esp_sleep_pd_domain_t_ESP_PD_DOMAIN_MODEM,
+            sys::esp_sleep_pd_option_t_ESP_PD_OPTION_OFF,
+        );
+        sys::esp_sleep_pd_config(
+            sys::esp_sleep_pd_domain_t_ESP_PD_DOMAIN_RTC_PERIPH,
+            sys::esp_sleep_pd_option_t_ESP_PD_OPTION_OFF,
+        );
+    }
+}
+
+/// Light sleepを開始します（復帰後に戻る）
+pub fn light_sleep_start() -> SysResult<()> {
+    check(unsafe { sys::esp_light_sleep_start() })
+}
+
+/// チップを再起動します
+pub fn restart() {
+    unsafe { sys::esp_restart() }
+}
+
+/// 今回の起動のリセット理由
+pub fn reset_reason() -> sys::esp_reset_reason_t {
+    unsafe { sys::esp_reset_reason() }
+}
+
+/// 今回の起床要因
+pub fn wakeup_cause() -> sys::esp_sleep_wakeup_cause_t {
+    unsafe { sys::esp_sleep_get_wakeup_cause() }
+}
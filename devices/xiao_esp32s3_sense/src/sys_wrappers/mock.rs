@@ -0,0 +1,81 @@
+use super::{Gpio, SysError, SysResult, WifiRadio};
+use std::sync::Mutex;
+
+/// モックに対して行ったGPIOの操作
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GpioOp {
+    /// 出力に設定した
+    Output(i32),
+    /// 出力レベルを設定した（trueはHigh）
+    Level(i32, bool),
+    /// 固定を設定・解除した
+    Hold(i32, bool),
+    /// ビットマスクのピンを入力にした
+    ConfigureInput(u64),
+}
+
+/// ESP-IDF呼び出しのモック実装（GPIOの操作を順に記録する）
+#[derive(Debug)]
+pub struct MockSys {
+    /// STAインターフェースのMACアドレス（Noneは取得失敗）
+    pub mac: Option<[u8; 6]>,
+    /// trueならピンの入力設定（`configure_input`）を失敗させる
+    pub fail_configure: bool,
+    ops: Mutex<Vec<GpioOp>>,
+}
+
+impl Default for MockSys {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MockSys {
+    /// すべての呼び出しが成功するモックを作成します
+    pub fn new() -> Self {
+        Self {
+            mac: Some([0x34, 0xab, 0x95, 0xfb, 0x3f, 0xc4]),
+            fail_configure: false,
+            ops: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// 記録したGPIOの操作を取り出します（古い順）
+    pub fn take_ops(&self) -> Vec<GpioOp> {
+        self.ops.lock().map(|mut ops| std::mem::take(&mut *ops)).unwrap_or_default()
+    }
+
+    fn record(&self, op: GpioOp) -> SysResult<()> {
+        if let Ok(mut ops) = self.ops.lock() {
+            ops.push(op);
+        }
+        Ok(())
+    }
+}
+
+impl WifiRadio for MockSys {
+    fn sta_mac(&self) -> SysResult<[u8; 6]> {
+        self.mac.ok_or(SysError(-1))
+    }
+}
+
+impl Gpio for MockSys {
+    fn set_output(&self, pin: i32) -> SysResult<()> {
+        self.record(GpioOp::Output(pin))
+    }
+
+    fn set_level(&self, pin: i32, high: bool) -> SysResult<()> {
+        self.record(GpioOp::Level(pin, high))
+    }
+
+    fn set_hold(&self, pin: i32, hold: bool) -> SysResult<()> {
+        self.record(GpioOp::Hold(pin, hold))
+    }
+
+    fn configure_input(&self, pin_mask: u64) -> SysResult<()> {
+        if self.fail_configure {
+            return Err(SysError(-1));
+        }
+        self.record(GpioOp::ConfigureInput(pin_mask))
+    }
+}
@@ -0,0 +1,161 @@
+//! ESP-IDFの生の呼び出し（`esp_idf_sys`）をまとめた安全なラッパー
+//!
+//! `unsafe` なFFI呼び出しは `esp` モジュールにまとめ、他のモジュールはここの関数を使います。
+//! スリープ前後のピンの固定・解除のようにGPIOを順に操作する処理は `Gpio` を受け取り、
+//! ホストビルドでは `mock` に差し替えて操作の順序を確認できます。
+//! RTCメモリ上の `static mut` とESP-NOWコールバックのポインタの解釈は呼び出し元に残しています。
+
+#[cfg(feature = "esp")]
+pub mod esp;
+
+#[cfg(any(test, feature = "mock-hw"))]
+pub mod mock;
+
+/// ステータスLED（アクティブLow）
+pub const STATUS_LED_PIN: i32 = 21;
+/// センサー電源ピン（温度センサー, EC/TDSセンサー）
+pub const SENSOR_POWER_PINS: [i32; 2] = [2, 5];
+/// カメラのピン（XCLK, D0〜D7, VSYNC, HREF, PCLK, SDA, SCL）
+pub const CAMERA_PINS: [i32; 14] = [10, 15, 17, 18, 16, 14, 12, 11, 48, 38, 47, 13, 40, 39];
+/// スリープ中にLow出力に固定するカメラのピン（XCLK, SDA, SCL）
+///
+/// XCLKはノイズでクロックが入らないよう、SDA/SCLはプルアップ経由のリーク電流を防ぐため。
+pub const CAMERA_DRIVEN_LOW_PINS: [i32; 3] = [10, 40, 39];
+/// MACアドレスを取得できなかった場合に使うアドレス（テスト用）
+pub const FALLBACK_STA_MAC: [u8; 6] = [0x24, 0x6F, 0x28, 0x12, 0x34, 0x56];
+
+/// ESP-IDF呼び出しの結果の型
+pub type SysResult<T> = Result<T, SysError>;
+
+/// ESP-IDFのエラーコード（`esp_err_t`）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+#[error("ESP-IDF error {0}")]
+pub struct SysError(pub i32);
+
+impl SysError {
+    /// エラーコード
+    pub fn code(self) -> i32 {
+        self.0
+    }
+}
+
+/// Wi-Fi（ESP-NOWの無線）の状態の取得
+pub trait WifiRadio {
+    /// STAインターフェースのMACアドレス
+    fn sta_mac(&self) -> SysResult<[u8; 6]>;
+}
+
+/// GPIOの操作
+pub trait Gpio {
+    /// 出力に設定します
+    fn set_output(&self, pin: i32) -> SysResult<()>;
+
+    /// 出力レベルを設定します
+    fn set_level(&self, pin: i32, high: bool) -> SysResult<()>;
+
+    /// スリープ中の状態の固定（Hold）を設定・解除します
+    fn set_hold(&self, pin: i32, hold: bool) -> SysResult<()>;
+
+    /// ビットマスクのピンをプルアップ・プルダウンなしの入力（High-Z）にします
+    fn configure_input(&self, pin_mask: u64) -> SysResult<()>;
+}
+
+/// STAインターフェースのMACアドレス（取得できなければ `FALLBACK_STA_MAC` と失敗の内容）
+pub fn sta_mac_or_fallback(radio: &impl WifiRadio) -> ([u8; 6], Option<SysError>) {
+    match radio.sta_mac() {
+        Ok(mac) => (mac, None),
+        Err(e) => (FALLBACK_STA_MAC, Some(e)),
+    }
+}
+
+/// ピンをLow出力にします（センサーの電源を切る）
+pub fn drive_low(gpio: &impl Gpio, pin: i32) -> SysResult<()> {
+    gpio.set_output(pin)?;
+    gpio.set_level(pin, false)
+}
+
+/// 使用しているすべてのピンの固定を解除します（スリープからの復帰直後に呼び出す）
+pub fn release_pin_holds(gpio: &impl Gpio) {
+    for pin in SENSOR_POWER_PINS.into_iter().chain([STATUS_LED_PIN]).chain(CAMERA_PINS) {
+        let _ = gpio.set_hold(pin, false);
+    }
+}
+
+/// スリープ前にセンサーの電源とステータスLEDを切ります
+///
+/// Deep sleep（`hold`）ではスリープ中も状態が保たれるよう固定します。
+pub fn park_pins_for_sleep(gpio: &impl Gpio, hold: bool) {
+    for pin in [STATUS_LED_PIN].into_iter().chain(SENSOR_POWER_PINS) {
+        let _ = gpio.set_level(pin, pin == STATUS_LED_PIN);
+        if hold {
+            let _ = gpio.set_hold(pin, true);
+        }
+    }
+}
+
+/// カメラのピンをHigh-Zにして固定します（XCLK・SDA・SCLはLow出力）
+pub fn park_camera_pins(gpio: &impl Gpio) -> SysResult<()> {
+    let pin_mask = CAMERA_PINS.iter().fold(0u64, |mask, &pin| mask | (1u64 << pin));
+    gpio.configure_input(pin_mask)?;
+    for pin in CAMERA_DRIVEN_LOW_PINS {
+        let _ = drive_low(gpio, pin);
+    }
+    for pin in CAMERA_PINS {
+        let _ = gpio.set_hold(pin, true);
+    }
+    Ok(())
+}
+
+/// ステータスLEDを `times` 回点滅させます（`pause` は点灯・消灯の間の待機）
+pub fn blink_status_led(gpio: &impl Gpio, times: usize, mut pause: impl FnMut()) {
+    for _ in 0..times {
+        let _ = gpio.set_level(STATUS_LED_PIN, false);
+        pause();
+        let _ = gpio.set_level(STATUS_LED_PIN, true);
+        pause();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::mock::{GpioOp, MockSys};
+    use super::*;
+
+    #[test]
+    fn test_sleep_parking_holds_only_for_deep_sleep() {
+        let sys = MockSys::new();
+        park_pins_for_sleep(&sys, false);
+        assert_eq!(
+            sys.take_ops(),
+            [GpioOp::Level(21, true), GpioOp::Level(2, false), GpioOp::Level(5, false)]
+        );
+
+        park_pins_for_sleep(&sys, true);
+        let ops = sys.take_ops();
+        assert_eq!(ops.len(), 6);
+        assert_eq!(ops[1], GpioOp::Hold(21, true));
+
+        release_pin_holds(&sys);
+        let ops = sys.take_ops();
+        assert_eq!(ops.len(), 17);
+        assert!(ops.iter().all(|op| matches!(op, GpioOp::Hold(_, false))));
+    }
+
+    #[test]
+    fn test_camera_parking_stops_when_configuration_fails() {
+        let mut sys = MockSys::new();
+        park_camera_pins(&sys).unwrap();
+        let ops = sys.take_ops();
+        // GPIO10〜18, 38〜40, 47〜48
+        assert_eq!(ops[0], GpioOp::ConfigureInput(0x0001_81c0_0007_fc00));
+        assert_eq!(&ops[1..3], [GpioOp::Output(10), GpioOp::Level(10, false)]);
+        assert_eq!(ops.iter().filter(|op| matches!(op, GpioOp::Hold(_, true))).count(), 14);
+
+        sys.fail_configure = true;
+        assert_eq!(park_camera_pins(&sys), Err(SysError(-1)));
+        assert!(sys.take_ops().is_empty());
+
+        sys.mac = None;
+        assert_eq!(sta_mac_or_fallback(&sys), (FALLBACK_STA_MAC, Some(SysError(-1))));
+    }
+}
@@ -0,0 +1,142 @@
+//! ゲートウェイからの制御フレーム
+//!
+//! 形式はゲートウェイ・m5stack と同じです。
+//!
+//! ```text
+//! [MSG_TYPE=0x0B(1)] ["CTRL"(4)] [COMMAND_ID(1)] [ARGS_LEN(1)] [ARGS(ARGS_LEN)] [CRC32(4)]
+//! ```
+
+/// 制御フレームのメッセージタイプ
+pub const CONTROL_MESSAGE_TYPE: u8 = 0x0B;
+/// 制御フレームの識別子
+const CONTROL_MAGIC: [u8; 4] = *b"CTRL";
+/// ヘッダ（メッセージタイプ・識別子・コマンドID・引数長）のバイト長
+pub const CONTROL_FRAME_HEADER_LEN: usize = 7;
+/// 末尾のCRC-32のバイト長
+const CONTROL_FRAME_CRC_LEN: usize = 4;
+
+/// スリープコマンドのコマンドID
+pub const SLEEP_COMMAND_ID: u8 = 0x01;
+/// 画像の再送要求のコマンドID
+pub const RESEND_LAST_COMMAND_ID: u8 = 0x02;
+
+/// 制御フレームのコマンド
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ControlCommand {
+    /// 指定秒数のディープスリープ
+    Sleep { sleep_seconds: u32 },
+    /// 直前に送信した画像の再送（撮影し直さない）
+    ResendLast,
+}
+
+/// 制御フレームを受理しなかった理由
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum ControlFrameError {
+    #[error("制御フレームの長さが不正です: {0}バイト")]
+    InvalidLength(usize),
+    #[error("制御フレームのCRCが一致しません")]
+    CrcMismatch,
+    #[error("未知の制御コマンドです: 0x{0:02X}")]
+    UnknownCommand(u8),
+    #[error("制御コマンド 0x{command_id:02X} の引数長が不正です: {len}バイト")]
+    InvalidArgs { command_id: u8, len: usize },
+}
+
+/// 受信データが制御フレームの形式か（メッセージタイプと識別子のみ確認）
+pub fn is_control_frame(data: &[u8]) -> bool {
+    data.len() >= 5 && data[0] == CONTROL_MESSAGE_TYPE && data[1..5] == CONTROL_MAGIC
+}
+
+/// 制御フレームを解析します（`is_control_frame` を満たすデータを渡す）
+pub fn parse_control_frame(data: &[u8]) -> Result<ControlCommand, ControlFrameError> {
+    if data.len() < CONTROL_FRAME_HEADER_LEN + CONTROL_FRAME_CRC_LEN {
+        return Err(ControlFrameError::InvalidLength(data.len()));
+    }
+    let args_len = data[6] as usize;
+    let body_len = CONTROL_FRAME_HEADER_LEN + args_len;
+    if data.len() != body_len + CONTROL_FRAME_CRC_LEN {
+        return Err(ControlFrameError::InvalidLength(data.len()));
+    }
+    let (body, crc) = data.split_at(body_len);
+    if crc32(body).to_le_bytes() != crc {
+        return Err(ControlFrameError::CrcMismatch);
+    }
+    let command_id = data[5];
+    let args = &body[CONTROL_FRAME_HEADER_LEN..];
+    match command_id {
+        SLEEP_COMMAND_ID => {
+            let bytes: [u8; 4] = args
+                .try_into()
+                .map_err(|_| ControlFrameError::InvalidArgs { command_id, len: args.len() })?;
+            Ok(ControlCommand::Sleep {
+                sleep_seconds: u32::from_le_bytes(bytes),
+            })
+        }
+        RESEND_LAST_COMMAND_ID if args.is_empty() => Ok(ControlCommand::ResendLast),
+        RESEND_LAST_COMMAND_ID => Err(ControlFrameError::InvalidArgs { command_id, len: args.len() }),
+        _ => Err(ControlFrameError::UnknownCommand(command_id)),
+    }
+}
+
+/// 旧形式のスリープコマンド（4バイトのu32、または秒数の文字列）を解析します
+///
+/// 4バイトのデータは、u32として24時間以内の値でなければ文字列（`"600"` など）として解析します。
+pub fn parse_legacy_sleep_seconds(data: &[u8]) -> Option<u32> {
+    let binary = <[u8; 4]>::try_from(data)
+        .ok()
+        .map(u32::from_le_bytes)
+        .filter(|seconds| (1..=86_400).contains(seconds));
+    binary.or_else(|| std::str::from_utf8(data).ok()?.trim().parse().ok())
+}
+
+/// CRC-32（ISO-HDLC、zlibと同じ）
+pub fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0u32, |crc, &byte| {
+        (0..8).fold(crc ^ byte as u32, |crc, _| {
+            if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            }
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// ゲートウェイが送る600秒のスリープコマンド
+    const SLEEP_600: [u8; 15] = [
+        0x0B, b'C', b'T', b'R', b'L', 0x01, 0x04, 0x58, 0x02, 0x00, 0x00, 0x81, 0xF7, 0xDC, 0xB5,
+    ];
+
+    #[test]
+    fn test_parse_sleep_command_from_gateway() {
+        assert!(is_control_frame(&SLEEP_600));
+        assert_eq!(
+            parse_control_frame(&SLEEP_600),
+            Ok(ControlCommand::Sleep { sleep_seconds: 600 })
+        );
+    }
+
+    #[test]
+    fn test_rejects_corrupted_or_truncated_frames() {
+        let mut corrupted = SLEEP_600;
+        corrupted[7] ^= 0x01;
+        assert_eq!(parse_control_frame(&corrupted), Err(ControlFrameError::CrcMismatch));
+        assert_eq!(
+            parse_control_frame(&SLEEP_600[..14]),
+            Err(ControlFrameError::InvalidLength(14))
+        );
+        // 旧形式の4バイトは制御フレームではない
+        assert!(!is_control_frame(&600u32.to_le_bytes()));
+    }
+
+    #[test]
+    fn test_parse_legacy_sleep_seconds() {
+        assert_eq!(parse_legacy_sleep_seconds(&600u32.to_le_bytes()), Some(600));
+        assert_eq!(parse_legacy_sleep_seconds(b"3600"), Some(3600));
+        assert_eq!(parse_legacy_sleep_seconds(b"sleep"), None);
+    }
+}
@@ -0,0 +1,48 @@
+//! ESP-NOWフレームのフレームタイプ
+//!
+//! 値はゲートウェイ・m5stack の `FrameType` と同じです。
+
+/// フレームタイプ
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameType {
+    /// 画像ハッシュとセンサー値
+    Hash = 1,
+    /// 画像チャンク
+    Data = 2,
+    /// 転送終了
+    Eof = 3,
+}
+
+impl FrameType {
+    /// デバイスが送信するすべてのフレームタイプ
+    pub const ALL: [FrameType; 3] = [FrameType::Hash, FrameType::Data, FrameType::Eof];
+
+    /// バイト値からフレームタイプを取得
+    pub fn from_byte(byte: u8) -> Option<Self> {
+        Self::ALL.into_iter().find(|frame_type| frame_type.to_byte() == byte)
+    }
+
+    /// フレームタイプをバイト値に変換
+    pub fn to_byte(self) -> u8 {
+        self as u8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_types_match_gateway_values() {
+        let expected = |frame_type: FrameType| match frame_type {
+            FrameType::Hash => 1,
+            FrameType::Data => 2,
+            FrameType::Eof => 3,
+        };
+        for frame_type in FrameType::ALL {
+            assert_eq!(frame_type.to_byte(), expected(frame_type));
+            assert_eq!(FrameType::from_byte(frame_type.to_byte()), Some(frame_type));
+        }
+        assert_eq!(FrameType::from_byte(4), None);
+    }
+}
@@ -0,0 +1,88 @@
+//! 画像ハッシュのアルゴリズム
+//!
+//! cfg.toml の `image_hash_algo` で選んだアルゴリズムで画像ハッシュを計算し、HASHフレームの
+//! `HASH_ALGO:<名前>` で宣言します。名前とハッシュの形式は m5stack と同じです。
+
+use std::fmt;
+
+use sha2::{Digest, Sha256};
+use xxhash_rust::xxh64::xxh64;
+
+/// 画像ハッシュのアルゴリズム
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ImageHashAlgo {
+    /// 長さとバイト和（`HASH_ALGO` を送らない旧形式と同じ）
+    Sum,
+    /// xxHash64
+    #[default]
+    Xxh64,
+    /// SHA-256
+    Sha256,
+}
+
+/// 指定可能なアルゴリズム
+const ALGORITHMS: [ImageHashAlgo; 3] = [ImageHashAlgo::Sum, ImageHashAlgo::Xxh64, ImageHashAlgo::Sha256];
+
+impl ImageHashAlgo {
+    /// アルゴリズム名（`"xxh64"`、`"SHA256"` など、大文字小文字を区別しない）から変換します
+    pub fn parse(name: &str) -> Option<Self> {
+        let name = name.trim();
+        ALGORITHMS.into_iter().find(|algo| algo.as_str().eq_ignore_ascii_case(name))
+    }
+
+    /// HASHフレームでの名前
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ImageHashAlgo::Sum => "SUM",
+            ImageHashAlgo::Xxh64 => "XXH64",
+            ImageHashAlgo::Sha256 => "SHA256",
+        }
+    }
+
+    /// 画像のハッシュ（16進数の小文字）
+    pub fn hash(&self, data: &[u8]) -> String {
+        match self {
+            ImageHashAlgo::Sum => format!(
+                "{:08x}{:08x}",
+                data.len(),
+                data.iter().fold(0u32, |sum, &b| sum.wrapping_add(b as u32))
+            ),
+            ImageHashAlgo::Xxh64 => format!("{:016x}", xxh64(data, 0)),
+            ImageHashAlgo::Sha256 => format!("{:x}", Sha256::digest(data)),
+        }
+    }
+
+    /// HASHフレームに付加するメタデータ（`,HASH_ALGO:<名前>`）
+    pub fn metadata_field(&self) -> String {
+        format!(",HASH_ALGO:{}", self.as_str())
+    }
+}
+
+impl fmt::Display for ImageHashAlgo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_algorithm_names() {
+        assert_eq!(ImageHashAlgo::parse("sha256"), Some(ImageHashAlgo::Sha256));
+        assert_eq!(ImageHashAlgo::parse(" XXH64 "), Some(ImageHashAlgo::Xxh64));
+        assert_eq!(ImageHashAlgo::parse("md5"), None);
+        assert_eq!(ImageHashAlgo::default().metadata_field(), ",HASH_ALGO:XXH64");
+    }
+
+    #[test]
+    fn test_hash_reference_values() {
+        assert_eq!(ImageHashAlgo::Sum.hash(b"abc"), "0000000300000126");
+        assert_eq!(ImageHashAlgo::Xxh64.hash(b"abc"), "44bc2cf5ad770999");
+        assert_eq!(
+            ImageHashAlgo::Sha256.hash(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+}
@@ -4,8 +4,25 @@
 pub mod voltage_calc;
 pub mod tds_calc;
 pub mod streaming_protocol;
+pub mod frame_type;
+#[path = "../../../m5stack_unit_cam/src/communication/esp_now/phy_rate.rs"]
+pub mod espnow_rate;
+pub mod path_balancer;
+pub mod image_hash;
+pub mod control_frame;
+pub mod reject_message;
+pub mod units;
 
 // 便利な再エクスポート
 pub use voltage_calc::calculate_voltage_percentage;
 pub use tds_calc::{calculate_tds_from_ec, compensate_ec_temperature, calculate_ec_from_adc};
-pub use streaming_protocol::{MessageType, StreamingHeader, StreamingMessage, DeserializeError};
+pub use streaming_protocol::{
+    DeserializeError, MessageType, ReceivedChunks, StreamingHeader, StreamingMessage, HEADER_SIZE, WIDE_HEADER_SIZE,
+};
+pub use frame_type::FrameType;
+pub use espnow_rate::EspNowRate;
+pub use path_balancer::{DualSendMode, PathBalancer, PathStats};
+pub use image_hash::ImageHashAlgo;
+pub use control_frame::{is_control_frame, parse_control_frame, ControlCommand, ControlFrameError};
+pub use reject_message::{parse_reject_message, RejectAction, RejectMessage, RejectReason};
+pub use units::{Celsius, Millivolts, Percent, Ppm};
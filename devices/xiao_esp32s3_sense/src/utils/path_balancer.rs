@@ -0,0 +1,180 @@
+//! 2系統のESP-NOW送信経路の振り分け（ハードウェア非依存部分）
+//!
+//! 範囲内にゲートウェイが2台ある場合、画像チャンクを2つの受信機MACに振り分けます。
+//! 経路ごとに送信結果を数え、失敗率が閾値を超えた経路は以降の振り分けから外します。
+//! 最後に残った経路は外さず、送信を継続します。
+
+/// 経路数
+pub const PATH_COUNT: usize = 2;
+
+/// 失敗率を判定するまでに必要な最小送信数
+pub const MIN_SAMPLES_FOR_EXCLUSION: u32 = 8;
+
+/// 振り分け方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DualSendMode {
+    /// チャンクごとに交互に送信
+    Alternate,
+    /// 失敗率の低い経路を優先して送信
+    LoadBalance,
+}
+
+impl DualSendMode {
+    /// 設定値（`"alternate"` / `"balance"`）から変換します
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "alternate" => Some(Self::Alternate),
+            "balance" | "load_balance" => Some(Self::LoadBalance),
+            _ => None,
+        }
+    }
+}
+
+/// 経路ごとの送信統計
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PathStats {
+    /// 送信数
+    pub attempts: u32,
+    /// 失敗数
+    pub failures: u32,
+}
+
+impl PathStats {
+    /// 失敗率（%）
+    pub fn failure_percent(&self) -> u8 {
+        if self.attempts == 0 {
+            return 0;
+        }
+        (self.failures as u64 * 100 / self.attempts as u64).min(100) as u8
+    }
+}
+
+/// 2系統の送信経路の選択と除外を管理します
+#[derive(Debug, Clone)]
+pub struct PathBalancer {
+    mode: DualSendMode,
+    max_failure_percent: u8,
+    stats: [PathStats; PATH_COUNT],
+    excluded: [bool; PATH_COUNT],
+    next: usize,
+}
+
+impl PathBalancer {
+    /// 新しいバランサーを作成します
+    pub fn new(mode: DualSendMode, max_failure_percent: u8) -> Self {
+        Self {
+            mode,
+            max_failure_percent: max_failure_percent.min(100),
+            stats: [PathStats::default(); PATH_COUNT],
+            excluded: [false; PATH_COUNT],
+            next: 0,
+        }
+    }
+
+    /// 次に使う経路を選択します
+    pub fn select(&mut self) -> usize {
+        let active = self.active_paths();
+        match self.mode {
+            DualSendMode::Alternate => {
+                let path = if active.contains(&self.next) { self.next } else { active[0] };
+                self.next = (path + 1) % PATH_COUNT;
+                path
+            }
+            DualSendMode::LoadBalance => {
+                // 失敗率が同じなら送信数の少ない経路（初期状態では交互）
+                *active
+                    .iter()
+                    .min_by_key(|&&path| (self.stats[path].failure_percent(), self.stats[path].attempts))
+                    .unwrap_or(&0)
+            }
+        }
+    }
+
+    /// 送信結果を記録します。経路が新たに除外された場合は true を返します
+    pub fn record(&mut self, path: usize, success: bool) -> bool {
+        let Some(stats) = self.stats.get_mut(path) else {
+            return false;
+        };
+        stats.attempts = stats.attempts.saturating_add(1);
+        if !success {
+            stats.failures = stats.failures.saturating_add(1);
+        }
+        let should_exclude = !self.excluded[path]
+            && stats.attempts >= MIN_SAMPLES_FOR_EXCLUSION
+            && stats.failure_percent() > self.max_failure_percent
+            && self.active_paths().len() > 1;
+        if should_exclude {
+            self.excluded[path] = true;
+        }
+        should_exclude
+    }
+
+    /// 振り分け対象の経路（除外されていない経路、最低1つ）
+    pub fn active_paths(&self) -> Vec<usize> {
+        let active: Vec<usize> = (0..PATH_COUNT).filter(|&path| !self.excluded[path]).collect();
+        if active.is_empty() {
+            vec![0]
+        } else {
+            active
+        }
+    }
+
+    /// 経路が除外されているかどうか
+    pub fn is_excluded(&self, path: usize) -> bool {
+        self.excluded.get(path).copied().unwrap_or(false)
+    }
+
+    /// 経路の送信統計
+    pub fn stats(&self, path: usize) -> PathStats {
+        self.stats.get(path).copied().unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_alternate_mode_round_robins() {
+        let mut balancer = PathBalancer::new(DualSendMode::Alternate, 50);
+        let paths: Vec<usize> = (0..4).map(|_| balancer.select()).collect();
+        assert_eq!(paths, [0, 1, 0, 1]);
+    }
+
+    #[test]
+    fn test_load_balance_prefers_reliable_path() {
+        let mut balancer = PathBalancer::new(DualSendMode::LoadBalance, 90);
+        assert_eq!(balancer.select(), 0);
+        balancer.record(0, false);
+        balancer.record(1, true);
+        assert_eq!(balancer.select(), 1);
+        assert_eq!(balancer.stats(0).failure_percent(), 100);
+    }
+
+    #[test]
+    fn test_failing_path_is_excluded_but_last_path_remains() {
+        let mut balancer = PathBalancer::new(DualSendMode::Alternate, 50);
+        let mut excluded = false;
+        for _ in 0..MIN_SAMPLES_FOR_EXCLUSION {
+            excluded |= balancer.record(1, false);
+            balancer.record(0, true);
+        }
+        assert!(excluded);
+        assert!(balancer.is_excluded(1));
+        assert_eq!(balancer.active_paths(), vec![0]);
+        assert!((0..4).all(|_| balancer.select() == 0));
+
+        // 残った経路が失敗し続けても除外しない
+        for _ in 0..MIN_SAMPLES_FOR_EXCLUSION * 2 {
+            assert!(!balancer.record(0, false));
+        }
+        assert_eq!(balancer.active_paths(), vec![0]);
+    }
+
+    #[test]
+    fn test_parse_mode() {
+        assert_eq!(DualSendMode::parse("Alternate"), Some(DualSendMode::Alternate));
+        assert_eq!(DualSendMode::parse("balance"), Some(DualSendMode::LoadBalance));
+        assert_eq!(DualSendMode::parse("random"), None);
+    }
+}
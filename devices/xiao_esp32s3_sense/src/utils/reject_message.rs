@@ -0,0 +1,102 @@
+//! ゲートウェイからの拒否メッセージ
+//!
+//! ゲートウェイがデータを破棄したときに、理由と再試行までの時間を知らせます。
+//! 形式はゲートウェイ（`esp_now::reject`）と同じです。
+//!
+//! ```text
+//! [MSG_TYPE=0x0F(1)] ["RJCT"(4)] [REASON(1)] [NONCE(4, LE)] [RETRY_AFTER_MS(4, LE)]
+//! ```
+
+/// 拒否メッセージのメッセージタイプ
+pub const REJECT_MESSAGE_TYPE: u8 = 0x0F;
+/// 拒否メッセージの識別子
+const REJECT_MAGIC: [u8; 4] = *b"RJCT";
+/// 拒否メッセージのバイト長
+pub const REJECT_MESSAGE_LEN: usize = 14;
+
+/// 拒否の理由
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RejectReason {
+    /// 受け付け数の上限（データキュー溢れ）
+    Quota,
+    /// ゲートウェイに登録されていないデバイス
+    UnknownDevice,
+    /// チェックサムエラーの多発
+    ChecksumStorm,
+}
+
+/// 拒否を受けたデバイスの対応
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RejectAction {
+    /// 転送を打ち切り、再試行までの時間を待つ
+    Backoff,
+    /// 送信を止め、ペアリングをやり直す
+    Repair,
+}
+
+impl RejectReason {
+    /// u8から変換します
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0x01 => Some(Self::Quota),
+            0x02 => Some(Self::UnknownDevice),
+            0x03 => Some(Self::ChecksumStorm),
+            _ => None,
+        }
+    }
+
+    /// 理由に応じた対応
+    pub fn action(self) -> RejectAction {
+        match self {
+            Self::Quota | Self::ChecksumStorm => RejectAction::Backoff,
+            Self::UnknownDevice => RejectAction::Repair,
+        }
+    }
+}
+
+/// 拒否メッセージ
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RejectMessage {
+    pub reason: RejectReason,
+    /// Pingへの応答ならそのnonce、それ以外は0
+    pub nonce: u32,
+    /// 再試行までの待機時間の目安（ミリ秒）
+    pub retry_after_ms: u32,
+}
+
+/// 受信データを拒否メッセージとして解析します（形式が違えばNone）
+pub fn parse_reject_message(data: &[u8]) -> Option<RejectMessage> {
+    if data.len() != REJECT_MESSAGE_LEN || data[0] != REJECT_MESSAGE_TYPE || data[1..5] != REJECT_MAGIC {
+        return None;
+    }
+    Some(RejectMessage {
+        reason: RejectReason::from_u8(data[5])?,
+        nonce: u32::from_le_bytes(data[6..10].try_into().ok()?),
+        retry_after_ms: u32::from_le_bytes(data[10..14].try_into().ok()?),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_gateway_reject() {
+        // ゲートウェイが送るチェックサムエラー多発の拒否（nonce=7、30秒後に再試行）
+        let data = [0x0F, b'R', b'J', b'C', b'T', 0x03, 7, 0, 0, 0, 0x30, 0x75, 0, 0];
+        let reject = parse_reject_message(&data).unwrap();
+        assert_eq!(reject.reason, RejectReason::ChecksumStorm);
+        assert_eq!(reject.nonce, 7);
+        assert_eq!(reject.retry_after_ms, 30_000);
+        assert_eq!(reject.reason.action(), RejectAction::Backoff);
+        assert_eq!(RejectReason::UnknownDevice.action(), RejectAction::Repair);
+
+        assert_eq!(parse_reject_message(&data[..13]), None);
+        let mut unknown_reason = data;
+        unknown_reason[5] = 0x7F;
+        assert_eq!(parse_reject_message(&unknown_reason), None);
+        let mut control_frame = data;
+        control_frame[0] = 0x0B;
+        assert_eq!(parse_reject_message(&control_frame), None);
+    }
+}
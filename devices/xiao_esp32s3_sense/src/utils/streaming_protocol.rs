@@ -1,5 +1,21 @@
-/// ESP-NOW ストリーミングプロトコル（ハードウェア非依存部分）
-/// テスト可能な純粋関数を提供
+//! ESP-NOW ストリーミングプロトコル（ハードウェア非依存部分）
+//! テスト可能な純粋関数を提供
+//!
+//! メッセージ形式の定義はこのモジュールのみに置き、送信側（`communication::esp_now::streaming`）も
+//! ここのシリアライズ処理を使用する。
+
+/// ヘッダーサイズ（バイト）: [Type:1][SeqId:2][FrameId:4][ChunkIdx:2][TotalChunks:2][DataLen:2][Checksum:4]
+pub const HEADER_SIZE: usize = 1 + 2 + 4 + 2 + 2 + 2 + 4;
+
+/// 拡張ヘッダー（プロトコル v2）のサイズ（バイト）:
+/// [Type|0x80:1][SeqId:2][FrameId:4][ChunkIdx:4][TotalChunks:4][DataLen:2][Checksum:4]
+///
+/// チャンク数が65535を超える転送（233バイトのチャンクで約15MB超）のみ拡張ヘッダーで送り、
+/// それ以外は従来のヘッダーのまま送るため、既存の受信側との互換性を保ちます。
+pub const WIDE_HEADER_SIZE: usize = 1 + 2 + 4 + 4 + 4 + 2 + 4;
+
+/// 拡張ヘッダーを示すメッセージタイプのビット
+pub const WIDE_HEADER_FLAG: u8 = 0x80;
 
 /// デシリアライゼーションエラー型(ハードウェア非依存)
 /// 
@@ -7,7 +23,7 @@
 /// ハードウェア非依存のため、`no_std`環境でも使用可能。
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum DeserializeError {
-    /// データ長がヘッダーサイズ(17バイト)未満
+    /// データ長がヘッダーサイズ(`HEADER_SIZE`)未満
     DataTooShort,
     /// 無効なメッセージタイプ値を検出(値を含む)
     InvalidMessageType(u8),
@@ -31,6 +47,8 @@ pub enum MessageType {
     EndFrame = 3,
     Ack = 4,
     Nack = 5,
+    /// 送信期限を過ぎる見込みのため転送を打ち切った（chunk_index は送信済みのチャンク数）
+    AbortFrame = 6,
 }
 
 impl MessageType {
@@ -42,6 +60,7 @@ impl MessageType {
             3 => Some(MessageType::EndFrame),
             4 => Some(MessageType::Ack),
             5 => Some(MessageType::Nack),
+            6 => Some(MessageType::AbortFrame),
             _ => None,
         }
     }
@@ -53,8 +72,8 @@ pub struct StreamingHeader {
     pub message_type: MessageType,
     pub sequence_id: u16,
     pub frame_id: u32,
-    pub chunk_index: u16,
-    pub total_chunks: u16,
+    pub chunk_index: u32,
+    pub total_chunks: u32,
     pub data_length: u16,
     pub checksum: u32,
 }
@@ -64,8 +83,8 @@ impl StreamingHeader {
         message_type: MessageType,
         sequence_id: u16,
         frame_id: u32,
-        chunk_index: u16,
-        total_chunks: u16,
+        chunk_index: u32,
+        total_chunks: u32,
         data_length: u16,
     ) -> Self {
         Self {
@@ -81,34 +100,174 @@ impl StreamingHeader {
     
     /// チェックサムを計算して設定
     pub fn calculate_checksum(&mut self, data: &[u8]) {
+        self.checksum = self.compute_checksum(data);
+    }
+    
+    /// チェックサムを検証
+    pub fn verify_checksum(&self, data: &[u8]) -> bool {
+        self.compute_checksum(data) == self.checksum
+    }
+
+    fn compute_checksum(&self, data: &[u8]) -> u32 {
         let mut checksum: u32 = 0;
         checksum = checksum.wrapping_add(self.sequence_id as u32);
         checksum = checksum.wrapping_add(self.frame_id);
-        checksum = checksum.wrapping_add(self.chunk_index as u32);
-        checksum = checksum.wrapping_add(self.total_chunks as u32);
+        checksum = checksum.wrapping_add(self.chunk_index);
+        checksum = checksum.wrapping_add(self.total_chunks);
         checksum = checksum.wrapping_add(self.data_length as u32);
         
         for byte in data {
             checksum = checksum.wrapping_add(*byte as u32);
         }
         
-        self.checksum = checksum;
+        checksum
     }
-    
-    /// チェックサムを検証
-    pub fn verify_checksum(&self, data: &[u8]) -> bool {
-        let mut calculated: u32 = 0;
-        calculated = calculated.wrapping_add(self.sequence_id as u32);
-        calculated = calculated.wrapping_add(self.frame_id);
-        calculated = calculated.wrapping_add(self.chunk_index as u32);
-        calculated = calculated.wrapping_add(self.total_chunks as u32);
-        calculated = calculated.wrapping_add(self.data_length as u32);
-        
-        for byte in data {
-            calculated = calculated.wrapping_add(*byte as u32);
+
+    /// 拡張ヘッダー（v2）で送る必要があるか（チャンク番号・チャンク数が16ビットに収まらない）
+    pub fn is_wide(&self) -> bool {
+        self.chunk_index > u16::MAX as u32 || self.total_chunks > u16::MAX as u32
+    }
+
+    /// シリアライズしたヘッダーのバイト長
+    pub fn header_len(&self) -> usize {
+        if self.is_wide() {
+            WIDE_HEADER_SIZE
+        } else {
+            HEADER_SIZE
         }
-        
-        calculated == self.checksum
+    }
+
+    /// ヘッダーをバイト列にシリアライズする（リトルエンディアン）
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.header_len());
+        if self.is_wide() {
+            bytes.push(self.message_type as u8 | WIDE_HEADER_FLAG);
+            bytes.extend_from_slice(&self.sequence_id.to_le_bytes());
+            bytes.extend_from_slice(&self.frame_id.to_le_bytes());
+            bytes.extend_from_slice(&self.chunk_index.to_le_bytes());
+            bytes.extend_from_slice(&self.total_chunks.to_le_bytes());
+        } else {
+            bytes.push(self.message_type as u8);
+            bytes.extend_from_slice(&self.sequence_id.to_le_bytes());
+            bytes.extend_from_slice(&self.frame_id.to_le_bytes());
+            bytes.extend_from_slice(&(self.chunk_index as u16).to_le_bytes());
+            bytes.extend_from_slice(&(self.total_chunks as u16).to_le_bytes());
+        }
+        bytes.extend_from_slice(&self.data_length.to_le_bytes());
+        bytes.extend_from_slice(&self.checksum.to_le_bytes());
+        bytes
+    }
+
+    /// バイト配列の先頭からヘッダーをデシリアライズする（従来・拡張ヘッダーの両方）
+    pub fn from_bytes(data: &[u8]) -> Result<Self, DeserializeError> {
+        if data.len() < HEADER_SIZE {
+            return Err(DeserializeError::DataTooShort);
+        }
+        let wide = data[0] & WIDE_HEADER_FLAG != 0;
+        if wide && data.len() < WIDE_HEADER_SIZE {
+            return Err(DeserializeError::DataTooShort);
+        }
+        let type_value = data[0] & !WIDE_HEADER_FLAG;
+        let message_type = MessageType::from_u8(type_value)
+            .ok_or(DeserializeError::InvalidMessageType(data[0]))?;
+        let u32_at = |offset: usize| {
+            u32::from_le_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]])
+        };
+        let (chunk_index, total_chunks, rest) = if wide {
+            (u32_at(7), u32_at(11), 15)
+        } else {
+            (
+                u16::from_le_bytes([data[7], data[8]]) as u32,
+                u16::from_le_bytes([data[9], data[10]]) as u32,
+                11,
+            )
+        };
+        Ok(Self {
+            message_type,
+            sequence_id: u16::from_le_bytes([data[1], data[2]]),
+            frame_id: u32_at(3),
+            chunk_index,
+            total_chunks,
+            data_length: u16::from_le_bytes([data[rest], data[rest + 1]]),
+            checksum: u32_at(rest + 2),
+        })
+    }
+}
+
+/// 受信済みチャンク番号の範囲（受信側の管理用）
+///
+/// 連続して受信したチャンク番号を1つの範囲にまとめて保持するため、メモリ使用量はチャンク数ではなく
+/// 欠落（範囲の切れ目）の数に比例します。
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReceivedChunks {
+    /// 受信済みの範囲（開始, 終了）。両端を含み昇順、隣接する範囲は結合済み
+    ranges: Vec<(u32, u32)>,
+}
+
+impl ReceivedChunks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 受信したチャンク番号を記録する（新しく受信した番号ならtrue、重複ならfalse）
+    pub fn insert(&mut self, index: u32) -> bool {
+        // indexより後に始まる最初の範囲
+        let next = self.ranges.partition_point(|&(start, _)| start <= index);
+        if next > 0 && self.ranges[next - 1].1 >= index {
+            return false;
+        }
+        let joins_prev = next > 0 && self.ranges[next - 1].1 + 1 == index;
+        let joins_next = next < self.ranges.len() && index + 1 == self.ranges[next].0;
+        match (joins_prev, joins_next) {
+            (true, true) => {
+                self.ranges[next - 1].1 = self.ranges[next].1;
+                self.ranges.remove(next);
+            }
+            (true, false) => self.ranges[next - 1].1 = index,
+            (false, true) => self.ranges[next].0 = index,
+            (false, false) => self.ranges.insert(next, (index, index)),
+        }
+        true
+    }
+
+    /// チャンク番号を受信済みか
+    pub fn contains(&self, index: u32) -> bool {
+        let next = self.ranges.partition_point(|&(start, _)| start <= index);
+        next > 0 && self.ranges[next - 1].1 >= index
+    }
+
+    /// 受信済みのチャンク数
+    pub fn received_count(&self) -> u64 {
+        self.ranges.iter().map(|&(start, end)| (end - start) as u64 + 1).sum()
+    }
+
+    /// 保持している範囲の数
+    pub fn range_count(&self) -> usize {
+        self.ranges.len()
+    }
+
+    /// `total_chunks` 個のチャンクをすべて受信したか
+    pub fn is_complete(&self, total_chunks: u32) -> bool {
+        total_chunks == 0 || self.ranges == [(0, total_chunks - 1)]
+    }
+
+    /// `total_chunks` 個のうち未受信のチャンク番号の範囲（両端を含む）
+    pub fn missing_ranges(&self, total_chunks: u32) -> Vec<(u32, u32)> {
+        let mut missing = Vec::new();
+        let mut next = 0u32;
+        for &(start, end) in &self.ranges {
+            if start >= total_chunks {
+                break;
+            }
+            if start > next {
+                missing.push((next, start - 1));
+            }
+            next = end.saturating_add(1);
+        }
+        if next < total_chunks {
+            missing.push((next, total_chunks - 1));
+        }
+        missing
     }
 }
 
@@ -126,75 +285,16 @@ impl StreamingMessage {
     
     /// メッセージをバイト配列にシリアライズする
     pub fn serialize(&self) -> Vec<u8> {
-        let mut serialized = Vec::new();
-        
-        // ヘッダーをシリアライズ (15 bytes)
-        serialized.push(self.header.message_type as u8);
-        serialized.extend_from_slice(&self.header.sequence_id.to_le_bytes());
-        serialized.extend_from_slice(&self.header.frame_id.to_le_bytes());
-        serialized.extend_from_slice(&self.header.chunk_index.to_le_bytes());
-        serialized.extend_from_slice(&self.header.total_chunks.to_le_bytes());
-        serialized.extend_from_slice(&self.header.data_length.to_le_bytes());
-        serialized.extend_from_slice(&self.header.checksum.to_le_bytes());
-        
-        // データを追加
+        let mut serialized = Vec::with_capacity(self.header.header_len() + self.data.len());
+        serialized.extend_from_slice(&self.header.to_bytes());
         serialized.extend_from_slice(&self.data);
-        
         serialized
     }
     
     /// バイト配列からメッセージをデシリアライズする
     pub fn deserialize(data: &[u8]) -> Result<Self, DeserializeError> {
-        if data.len() < 17 {
-            return Err(DeserializeError::DataTooShort);
-        }
-        
-        let mut offset = 0;
-        
-        // ヘッダーをデシリアライズ
-        let message_type = MessageType::from_u8(data[offset])
-            .ok_or(DeserializeError::InvalidMessageType(data[offset]))?;
-        offset += 1;
-        
-        let sequence_id = u16::from_le_bytes([data[offset], data[offset + 1]]);
-        offset += 2;
-        
-        let frame_id = u32::from_le_bytes([
-            data[offset], data[offset + 1], data[offset + 2], data[offset + 3]
-        ]);
-        offset += 4;
-        
-        let chunk_index = u16::from_le_bytes([data[offset], data[offset + 1]]);
-        offset += 2;
-        
-        let total_chunks = u16::from_le_bytes([data[offset], data[offset + 1]]);
-        offset += 2;
-        
-        let data_length = u16::from_le_bytes([data[offset], data[offset + 1]]);
-        offset += 2;
-        
-        let checksum = u32::from_le_bytes([
-            data[offset], data[offset + 1], data[offset + 2], data[offset + 3]
-        ]);
-        offset += 4;
-        
-        // データ部分を抽出
-        let payload = if offset < data.len() {
-            data[offset..].to_vec()
-        } else {
-            Vec::new()
-        };
-        
-        let header = StreamingHeader {
-            message_type,
-            sequence_id,
-            frame_id,
-            chunk_index,
-            total_chunks,
-            data_length,
-            checksum,
-        };
-        
+        let header = StreamingHeader::from_bytes(data)?;
+        let payload = data[header.header_len()..].to_vec();
         Ok(StreamingMessage::new(header, payload))
     }
 
@@ -216,8 +316,8 @@ impl StreamingMessage {
     pub fn data_chunk(
         frame_id: u32,
         sequence_id: u16,
-        chunk_index: u16,
-        total_chunks: u16,
+        chunk_index: u32,
+        total_chunks: u32,
         data: Vec<u8>,
     ) -> Self {
         let data_length = data.len() as u16;
@@ -261,6 +361,20 @@ impl StreamingMessage {
         StreamingMessage::new(header, vec![])
     }
 
+    /// Abort Frameメッセージを作成（送信済みのチャンク数とチャンク総数を示す）
+    pub fn abort_frame(frame_id: u32, sequence_id: u16, chunks_sent: u32, total_chunks: u32) -> Self {
+        let mut header = StreamingHeader::new(
+            MessageType::AbortFrame,
+            sequence_id,
+            frame_id,
+            chunks_sent,
+            total_chunks,
+            0,
+        );
+        header.calculate_checksum(&[]);
+        StreamingMessage::new(header, vec![])
+    }
+
     /// NACKメッセージを作成
     pub fn nack(sequence_id: u16) -> Self {
         let mut header = StreamingHeader::new(
@@ -289,12 +403,13 @@ mod tests {
         assert_eq!(MessageType::from_u8(3), Some(MessageType::EndFrame));
         assert_eq!(MessageType::from_u8(4), Some(MessageType::Ack));
         assert_eq!(MessageType::from_u8(5), Some(MessageType::Nack));
+        assert_eq!(MessageType::from_u8(6), Some(MessageType::AbortFrame));
     }
 
     #[test]
     fn test_message_type_from_u8_invalid() {
         assert_eq!(MessageType::from_u8(0), None);
-        assert_eq!(MessageType::from_u8(6), None);
+        assert_eq!(MessageType::from_u8(7), None);
         assert_eq!(MessageType::from_u8(255), None);
     }
 
@@ -305,6 +420,7 @@ mod tests {
         assert_eq!(MessageType::EndFrame as u8, 3);
         assert_eq!(MessageType::Ack as u8, 4);
         assert_eq!(MessageType::Nack as u8, 5);
+        assert_eq!(MessageType::AbortFrame as u8, 6);
     }
 
     // StreamingHeader テスト
@@ -455,7 +571,7 @@ mod tests {
         let serialized = message.serialize();
         
         // ヘッダーサイズは17バイト (1+2+4+2+2+2+4)
-        assert_eq!(serialized.len(), 17);
+        assert_eq!(serialized.len(), HEADER_SIZE);
         
         // メッセージタイプ確認
         assert_eq!(serialized[0], MessageType::StartFrame as u8);
@@ -470,9 +586,26 @@ mod tests {
         assert_eq!(result.unwrap_err(), DeserializeError::DataTooShort);
     }
 
+    #[test]
+    fn test_min_length_matches_header_size() {
+        // 送信側のヘッダーサイズと受信側の最小長チェックが一致すること
+        assert_eq!(HEADER_SIZE, 17);
+        let serialized = StreamingMessage::end_frame(1, 2).serialize();
+        assert_eq!(serialized.len(), HEADER_SIZE);
+        assert!(StreamingMessage::deserialize(&serialized).is_ok());
+
+        // 1バイトでも欠けたヘッダーは拒否（以前の15バイト判定では読み出し範囲外になっていた）
+        for len in 0..HEADER_SIZE {
+            assert_eq!(
+                StreamingMessage::deserialize(&serialized[..len]),
+                Err(DeserializeError::DataTooShort)
+            );
+        }
+    }
+
     #[test]
     fn test_message_deserialize_invalid_message_type() {
-        let mut invalid_data = vec![0; 17]; // 17 bytes header
+        let mut invalid_data = vec![0; HEADER_SIZE];
         invalid_data[0] = 99; // Invalid message type
         
         let result = StreamingMessage::deserialize(&invalid_data);
@@ -497,7 +630,7 @@ mod tests {
         let serialized = message.serialize();
         
         // ヘッダー(17) + データ(256)
-        assert_eq!(serialized.len(), 17 + 256);
+        assert_eq!(serialized.len(), HEADER_SIZE + 256);
         
         let deserialized = StreamingMessage::deserialize(&serialized).unwrap();
         assert_eq!(deserialized.data, data);
@@ -511,6 +644,7 @@ mod tests {
             MessageType::EndFrame,
             MessageType::Ack,
             MessageType::Nack,
+            MessageType::AbortFrame,
         ];
         
         for msg_type in types {
@@ -606,7 +740,7 @@ mod tests {
         
         // チャンク分割
         let chunks = split_into_chunks(&image_data, TEST_CHUNK_SIZE);
-        let total_chunks = chunks.len() as u16;
+        let total_chunks = chunks.len() as u32;
         
         // 1. Start Frame
         let start_msg = StreamingMessage::start_frame(frame_id, sequence_id);
@@ -622,7 +756,7 @@ mod tests {
             let data_msg = StreamingMessage::data_chunk(
                 frame_id,
                 sequence_id,
-                chunk_idx as u16,
+                chunk_idx as u32,
                 total_chunks,
                 chunk.clone(),
             );
@@ -634,7 +768,7 @@ mod tests {
             // 検証
             assert_eq!(decoded.header.message_type, MessageType::DataChunk);
             assert_eq!(decoded.header.frame_id, frame_id);
-            assert_eq!(decoded.header.chunk_index, chunk_idx as u16);
+            assert_eq!(decoded.header.chunk_index, chunk_idx as u32);
             assert_eq!(decoded.header.total_chunks, total_chunks);
             assert!(decoded.header.verify_checksum(&decoded.data));
             
@@ -661,7 +795,7 @@ mod tests {
         let mut sequence_id = 100u16;
         
         let chunks = split_into_chunks(&image_data, TEST_CHUNK_SIZE);
-        let total_chunks = chunks.len() as u16;
+        let total_chunks = chunks.len() as u32;
         
         // Start Frame
         let start_msg = StreamingMessage::start_frame(frame_id, sequence_id);
@@ -675,7 +809,7 @@ mod tests {
             let data_msg = StreamingMessage::data_chunk(
                 frame_id,
                 sequence_id,
-                chunk_idx as u16,
+                chunk_idx as u32,
                 total_chunks,
                 chunk.clone(),
             );
@@ -757,7 +891,7 @@ mod tests {
         // チャンク順序の保持テスト
         let image_data = generate_test_image(1000);
         let chunks = split_into_chunks(&image_data, TEST_CHUNK_SIZE);
-        let total_chunks = chunks.len() as u16;
+        let total_chunks = chunks.len() as u32;
         let frame_id = 5;
         
         let mut chunk_messages = Vec::new();
@@ -767,7 +901,7 @@ mod tests {
             let msg = StreamingMessage::data_chunk(
                 frame_id,
                 idx as u16,
-                idx as u16,
+                idx as u32,
                 total_chunks,
                 chunk.clone(),
             );
@@ -780,7 +914,7 @@ mod tests {
             let bytes = msg.serialize();
             let decoded = StreamingMessage::deserialize(&bytes).unwrap();
             
-            assert_eq!(decoded.header.chunk_index, expected_idx as u16);
+            assert_eq!(decoded.header.chunk_index, expected_idx as u32);
             reconstructed.extend_from_slice(&decoded.data);
         }
         
@@ -811,7 +945,7 @@ mod tests {
         let bytes = msg.serialize();
         
         // ヘッダーサイズ検証
-        assert!(bytes.len() >= 17); // 17バイトヘッダー + データ
+        assert_eq!(bytes.len(), HEADER_SIZE + 2);
         
         // フォーマット検証
         assert_eq!(bytes[0], MessageType::DataChunk as u8);
@@ -822,11 +956,53 @@ mod tests {
         assert_eq!(u16::from_le_bytes([bytes[11], bytes[12]]), 2); // data_length
     }
     
+    #[test]
+    fn test_wide_header_for_more_than_65535_chunks() {
+        // 従来の範囲に収まる転送は従来のヘッダーのまま
+        assert!(!StreamingMessage::data_chunk(1, 0, 65_534, 65_535, vec![]).header.is_wide());
+
+        let msg = StreamingMessage::data_chunk(7, 3, 70_000, 80_000, vec![0xAA, 0xBB]);
+        let bytes = msg.serialize();
+        assert_eq!(bytes.len(), WIDE_HEADER_SIZE + 2);
+        assert_eq!(bytes[0], MessageType::DataChunk as u8 | WIDE_HEADER_FLAG);
+        assert_eq!(u32::from_le_bytes([bytes[7], bytes[8], bytes[9], bytes[10]]), 70_000);
+        assert_eq!(u32::from_le_bytes([bytes[11], bytes[12], bytes[13], bytes[14]]), 80_000);
+
+        let decoded = StreamingMessage::deserialize(&bytes).unwrap();
+        assert_eq!(decoded, msg);
+        assert!(decoded.header.verify_checksum(&decoded.data));
+        assert_eq!(
+            StreamingMessage::deserialize(&bytes[..WIDE_HEADER_SIZE - 1]),
+            Err(DeserializeError::DataTooShort)
+        );
+    }
+
+    #[test]
+    fn test_received_chunks_merges_runs() {
+        let mut received = ReceivedChunks::new();
+        for index in (0..100_000).filter(|index| index % 50_000 != 7) {
+            assert!(received.insert(index));
+        }
+        // 10万チャンクでも範囲は欠落の数+1個
+        assert_eq!(received.range_count(), 3);
+        assert_eq!(received.received_count(), 99_998);
+        assert_eq!(received.missing_ranges(100_010), vec![(7, 7), (50_007, 50_007), (100_000, 100_009)]);
+        assert!(!received.insert(10));
+        assert!(!received.contains(50_007));
+
+        // 遅れて届いたチャンクで前後の範囲を結合する
+        assert!(received.insert(50_007));
+        assert!(received.insert(7));
+        assert_eq!(received.range_count(), 1);
+        assert!(received.is_complete(100_000));
+        assert!(!received.is_complete(100_001));
+    }
+
     #[test]
     fn test_max_chunk_size() {
         // ESP-NOWの最大ペイロードサイズ(250バイト)を考慮
         // ヘッダー17バイト + データ = 最大233バイト/チャンク
-        let max_data_size = 233;
+        let max_data_size = 250 - HEADER_SIZE;
         let data = generate_test_image(max_data_size);
         
         let msg = StreamingMessage::data_chunk(1, 0, 0, 1, data.clone());
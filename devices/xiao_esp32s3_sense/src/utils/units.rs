@@ -0,0 +1,173 @@
+//! テレメトリの物理量の型
+//! 電圧（mVとVの取り違え）・温度・TDS濃度を生の `f32`/`u8` で受け渡すと混同しやすいため、
+//! 単位ごとに型を分けます。HASHフレームの表記（`VOLT` は%、`TEMP` は℃、`TDS_VOLT` はV）は
+//! 文字列にする箇所で変換し、受信側から見た形式は変えません。
+
+use std::fmt;
+
+/// 電圧（ミリボルト）
+#[derive(Debug, Clone, Copy, Default, PartialEq, PartialOrd)]
+pub struct Millivolts(f32);
+
+impl Millivolts {
+    pub const fn new(millivolts: f32) -> Self {
+        Self(millivolts)
+    }
+
+    /// ボルト単位の値から生成
+    pub fn from_volts(volts: f32) -> Self {
+        Self(volts * 1000.0)
+    }
+
+    /// ミリボルト単位の値
+    pub const fn value(self) -> f32 {
+        self.0
+    }
+
+    /// ボルト単位の値
+    pub fn as_volts(self) -> f32 {
+        self.0 / 1000.0
+    }
+}
+
+impl fmt::Display for Millivolts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:.0} mV", self.0)
+    }
+}
+
+/// 温度（℃）
+#[derive(Debug, Clone, Copy, Default, PartialEq, PartialOrd)]
+pub struct Celsius(f32);
+
+impl Celsius {
+    pub const fn new(celsius: f32) -> Self {
+        Self(celsius)
+    }
+
+    pub const fn value(self) -> f32 {
+        self.0
+    }
+
+    /// オフセットを加えた補正後の温度
+    pub fn offset_by(self, offset_celsius: f32) -> Self {
+        Self(self.0 + offset_celsius)
+    }
+}
+
+impl fmt::Display for Celsius {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:.1}°C", self.0)
+    }
+}
+
+/// 濃度（ppm）
+#[derive(Debug, Clone, Copy, Default, PartialEq, PartialOrd)]
+pub struct Ppm(f32);
+
+impl Ppm {
+    pub const fn new(ppm: f32) -> Self {
+        Self(ppm)
+    }
+
+    pub const fn value(self) -> f32 {
+        self.0
+    }
+}
+
+impl fmt::Display for Ppm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:.1}ppm", self.0)
+    }
+}
+
+/// 電池残量などの百分率（0〜100、測定できなかった場合は [`Percent::INVALID`]）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Percent(u8);
+
+impl Percent {
+    /// 測定に失敗したことを示す値（HASHフレームでは `VOLT:255`）
+    pub const INVALID: Percent = Percent(u8::MAX);
+
+    /// 0〜100の値から生成（100を超える値は100にする）
+    pub const fn new(percent: u8) -> Self {
+        if percent > 100 {
+            Self(100)
+        } else {
+            Self(percent)
+        }
+    }
+
+    /// `min`〜`max` の範囲で `value` が占める割合（範囲外は0または100、範囲が不正なら0）
+    pub fn of_range(value: Millivolts, min: Millivolts, max: Millivolts) -> Self {
+        let range = max.value() - min.value();
+        let percentage = if range <= 0.0 {
+            0.0
+        } else {
+            ((value.value() - min.value()) / range * 100.0).clamp(0.0, 100.0)
+        };
+        Self(percentage.round() as u8)
+    }
+
+    pub const fn value(self) -> u8 {
+        self.0
+    }
+
+    /// 測定に失敗した値でないか
+    pub const fn is_valid(self) -> bool {
+        self.0 <= 100
+    }
+}
+
+impl fmt::Display for Percent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}%", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_millivolts_volts_roundtrip() {
+        let voltage = Millivolts::from_volts(1.23);
+        assert!((voltage.value() - 1230.0).abs() < 1e-3);
+        assert!((voltage.as_volts() - 1.23).abs() < 1e-6);
+        assert_eq!(Millivolts::new(1629.4).to_string(), "1629 mV");
+    }
+
+    #[test]
+    fn test_celsius_offset_keeps_raw_value() {
+        let raw = Celsius::new(24.2);
+        let corrected = raw.offset_by(-0.5);
+        assert_eq!(raw.value(), 24.2);
+        assert!((corrected.value() - 23.7).abs() < 1e-5);
+        assert_eq!(corrected.to_string(), "23.7°C");
+    }
+
+    #[test]
+    fn test_ppm_display() {
+        assert_eq!(Ppm::new(456.84).to_string(), "456.8ppm");
+    }
+
+    #[test]
+    fn test_percent_of_range() {
+        let min = Millivolts::new(128.0);
+        let max = Millivolts::new(3130.0);
+        assert_eq!(Percent::of_range(Millivolts::new(1629.0), min, max), Percent::new(50));
+        assert_eq!(Percent::of_range(Millivolts::new(50.0), min, max), Percent::new(0));
+        assert_eq!(Percent::of_range(Millivolts::new(4000.0), min, max), Percent::new(100));
+        // 範囲が不正なら0%
+        assert_eq!(Percent::of_range(Millivolts::new(1500.0), max, min), Percent::new(0));
+    }
+
+    #[test]
+    fn test_percent_invalid_is_kept_apart_from_clamped_values() {
+        assert_eq!(Percent::new(200).value(), 100);
+        assert!(Percent::new(100).is_valid());
+        assert!(!Percent::INVALID.is_valid());
+        assert_eq!(Percent::INVALID.value(), 255);
+        assert_eq!(Percent::INVALID.to_string(), "255%");
+    }
+}
@@ -1,3 +1,5 @@
+use super::units::{Millivolts, Percent};
+
 /// 電圧計算ユーティリティ
 /// ハードウェア非依存の純粋関数を提供
 
@@ -19,17 +21,7 @@
 /// assert_eq!(percent, 50);
 /// ```
 pub fn calculate_voltage_percentage(voltage_mv: f32, min_mv: f32, max_mv: f32) -> u8 {
-    let range_mv = max_mv - min_mv;
-    
-    let percentage = if range_mv <= 0.0 {
-        0.0
-    } else {
-        ((voltage_mv - min_mv) / range_mv * 100.0)
-            .max(0.0)
-            .min(100.0)
-    };
-    
-    percentage.round() as u8
+    Percent::of_range(Millivolts::new(voltage_mv), Millivolts::new(min_mv), Millivolts::new(max_mv)).value()
 }
 
 #[cfg(test)]
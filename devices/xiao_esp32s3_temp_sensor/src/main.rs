@@ -5,7 +5,10 @@ use simple_ds18b20_temp_sensor::TempSensor;
 
 mod utils;
 #[cfg(feature = "wifi")]
-use utils::{format_hash_payload, needs_recalibration, parse_mac, EOF_MARKER};
+use utils::{
+    format_encoded_hash_payload, format_hash_payload, needs_recalibration, parse_mac, DeltaEncoder,
+    Telemetry, EOF_MARKER,
+};
 
 #[toml_cfg::toml_config]
 struct Config {
@@ -26,6 +29,10 @@ struct Config {
     /// 温度変化・経時変化によるRF性能低下を防ぐため定期的に実行する
     #[default(100)]
     recalibration_interval: u32,
+    /// テレメトリのフルスナップショット周期 (送信サイクル数)
+    /// 間のサイクルは前回のスナップショットから変わった項目だけを送る (0 = 差分エンコード無効)
+    #[default(6)]
+    telemetry_full_interval: u32,
 }
 
 // GPIO アサイン (XIAO ESP32-S3)
@@ -48,6 +55,11 @@ static mut DEEP_SLEEP_CYCLE: u32 = 0;
 #[link_section = ".rtc.data"]
 static mut RTC_MAGIC_VAL: u32 = 0;
 
+// 差分エンコードの基準 (前回のフルスナップショット)。POR 後は空になりフルスナップショットから始まる
+#[cfg(feature = "wifi")]
+#[link_section = ".rtc.data"]
+static mut DELTA_ENCODER: DeltaEncoder = DeltaEncoder::new();
+
 // =============================================================================
 // main
 // =============================================================================
@@ -353,7 +365,15 @@ fn send_temperature(
 ) -> Result<()> {
     // VOLT:100 = 電圧センサなしのプレースホルダ
     // TDS_VOLT:-999.0 = TDS センサなしのセンチネル値 (サーバー側で None として扱われる)
-    let hash_payload = format_hash_payload(temp);
+    let hash_payload = if CONFIG.telemetry_full_interval == 0 {
+        format_hash_payload(temp)
+    } else {
+        // 前回のスナップショットから変わった項目だけを送る (受信側で完全なレコードに戻す)
+        let encoder = unsafe { &mut *std::ptr::addr_of_mut!(DELTA_ENCODER) };
+        let encoded = encoder.encode(Telemetry::new(100, temp, -999.0), CONFIG.telemetry_full_interval);
+        info!("Telemetry {} ({} field(s))", encoded.marker(), encoded.fields.len());
+        format_encoded_hash_payload(&encoded)
+    };
 
     // Deep Sleep モード限定の待機:
     // - 送信前 300ms: WiFi を毎サイクル初期化するため温度計測がラジオ安定待ちを兼ねられない。
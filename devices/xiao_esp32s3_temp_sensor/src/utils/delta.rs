@@ -0,0 +1,227 @@
+//! テレメトリの差分エンコード
+//!
+//! センサー専用ノードは毎サイクルほぼ同じ値を送るため、前回のフルスナップショットから
+//! 変わった項目だけを送り、N サイクルごとにすべての項目を送り直す。
+//!
+//! - フルスナップショット: `VOLT:100,TEMP:25.0,TDS_VOLT:-999.0,<時刻>,SNAP:<番号>`
+//! - 差分: `TEMP:25.3,<時刻>,DELTA:<基準スナップショット番号>` (変化がなければ項目なし)
+//!
+//! 差分は直前の送信ではなくフルスナップショットを基準にする。ESP-NOW の送信は
+//! 受信確認を待たないため、差分が 1 つ欠けてもその後の差分から正しい値を復元できるようにするため。
+//! 受信側 (sensor_data_reciver) は番号ごとにスナップショットを保持して完全なレコードに戻す。
+
+/// HASH ペイロードの表記精度 (小数 1 桁) に丸めたテレメトリ
+///
+/// 表記上同じ値を「変化なし」とするため、浮動小数点ではなく 0.1 単位の整数で保持する。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Telemetry {
+    pub volt_percent: u8,
+    pub temp_deci: i16,
+    pub tds_volt_deci: i16,
+}
+
+impl Telemetry {
+    pub fn new(volt_percent: u8, temp_celsius: f32, tds_volt: f32) -> Self {
+        Self {
+            volt_percent,
+            temp_deci: to_deci(temp_celsius),
+            tds_volt_deci: to_deci(tds_volt),
+        }
+    }
+
+    /// HASH ペイロードの項目 (キーと表記)
+    fn fields(&self) -> [(&'static str, String); 3] {
+        [
+            ("VOLT", self.volt_percent.to_string()),
+            ("TEMP", format_deci(self.temp_deci)),
+            ("TDS_VOLT", format_deci(self.tds_volt_deci)),
+        ]
+    }
+}
+
+fn to_deci(value: f32) -> i16 {
+    (value * 10.0).round().clamp(i16::MIN as f32, i16::MAX as f32) as i16
+}
+
+fn format_deci(value: i16) -> String {
+    let sign = if value < 0 { "-" } else { "" };
+    let abs = value.unsigned_abs();
+    format!("{}{}.{}", sign, abs / 10, abs % 10)
+}
+
+/// エンコード結果の種別
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EncodedKind {
+    /// すべての項目を含むフルスナップショット
+    Snapshot,
+    /// スナップショットから変わった項目のみ
+    Delta,
+}
+
+/// 1 サイクル分のエンコード結果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EncodedTelemetry {
+    pub kind: EncodedKind,
+    /// スナップショット番号 (差分の場合は基準のスナップショット番号)
+    pub snapshot_seq: u16,
+    pub fields: Vec<(&'static str, String)>,
+}
+
+impl EncodedTelemetry {
+    /// 時刻の後ろに付ける種別の項目 (`SNAP:n` / `DELTA:n`)
+    pub fn marker(&self) -> String {
+        match self.kind {
+            EncodedKind::Snapshot => format!("SNAP:{}", self.snapshot_seq),
+            EncodedKind::Delta => format!("DELTA:{}", self.snapshot_seq),
+        }
+    }
+}
+
+/// 差分エンコードの状態 (RTC メモリに置き、Deep Sleep をまたいで保持する)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeltaEncoder {
+    snapshot: Option<Telemetry>,
+    snapshot_seq: u16,
+    cycles_since_snapshot: u32,
+}
+
+impl Default for DeltaEncoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DeltaEncoder {
+    pub const fn new() -> Self {
+        Self {
+            snapshot: None,
+            snapshot_seq: 0,
+            cycles_since_snapshot: 0,
+        }
+    }
+
+    /// 今サイクルのテレメトリをエンコードする
+    ///
+    /// スナップショットがない場合と、前回のスナップショットから `full_interval` サイクル経った場合は
+    /// フルスナップショットを送る (`full_interval` が 0 または 1 なら毎回フルスナップショット)。
+    pub fn encode(&mut self, telemetry: Telemetry, full_interval: u32) -> EncodedTelemetry {
+        match self.snapshot {
+            Some(snapshot) if self.cycles_since_snapshot + 1 < full_interval => {
+                self.cycles_since_snapshot += 1;
+                let fields = telemetry
+                    .fields()
+                    .into_iter()
+                    .zip(snapshot.fields())
+                    .filter(|(current, base)| current.1 != base.1)
+                    .map(|(current, _)| current)
+                    .collect();
+                EncodedTelemetry {
+                    kind: EncodedKind::Delta,
+                    snapshot_seq: self.snapshot_seq,
+                    fields,
+                }
+            }
+            _ => {
+                if self.snapshot.is_some() {
+                    self.snapshot_seq = self.snapshot_seq.wrapping_add(1);
+                }
+                self.snapshot = Some(telemetry);
+                self.cycles_since_snapshot = 0;
+                EncodedTelemetry {
+                    kind: EncodedKind::Snapshot,
+                    snapshot_seq: self.snapshot_seq,
+                    fields: telemetry.fields().to_vec(),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reading(temp: f32) -> Telemetry {
+        Telemetry::new(100, temp, -999.0)
+    }
+
+    #[test]
+    fn test_first_cycle_is_full_snapshot() {
+        let mut encoder = DeltaEncoder::new();
+        let encoded = encoder.encode(reading(25.0), 6);
+        assert_eq!(encoded.kind, EncodedKind::Snapshot);
+        assert_eq!(encoded.marker(), "SNAP:0");
+        assert_eq!(
+            encoded.fields,
+            vec![
+                ("VOLT", "100".to_string()),
+                ("TEMP", "25.0".to_string()),
+                ("TDS_VOLT", "-999.0".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_delta_contains_only_changed_fields() {
+        let mut encoder = DeltaEncoder::new();
+        encoder.encode(reading(25.0), 6);
+
+        // 表記上同じ値は変化なし
+        let unchanged = encoder.encode(reading(25.04), 6);
+        assert_eq!(unchanged.kind, EncodedKind::Delta);
+        assert_eq!(unchanged.marker(), "DELTA:0");
+        assert!(unchanged.fields.is_empty());
+
+        let changed = encoder.encode(reading(25.3), 6);
+        assert_eq!(changed.fields, vec![("TEMP", "25.3".to_string())]);
+    }
+
+    #[test]
+    fn test_delta_is_relative_to_snapshot_not_previous_cycle() {
+        // 差分が欠けても次の差分で正しい値に戻れるよう、基準はスナップショット
+        let mut encoder = DeltaEncoder::new();
+        encoder.encode(reading(25.0), 6);
+        encoder.encode(reading(26.0), 6);
+        let back = encoder.encode(reading(25.0), 6);
+        assert!(back.fields.is_empty());
+        let again = encoder.encode(reading(26.0), 6);
+        assert_eq!(again.fields, vec![("TEMP", "26.0".to_string())]);
+    }
+
+    #[test]
+    fn test_periodic_full_snapshot() {
+        let mut encoder = DeltaEncoder::new();
+        let kinds: Vec<_> = (0..7)
+            .map(|_| encoder.encode(reading(25.0), 3))
+            .map(|encoded| (encoded.kind, encoded.snapshot_seq))
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                (EncodedKind::Snapshot, 0),
+                (EncodedKind::Delta, 0),
+                (EncodedKind::Delta, 0),
+                (EncodedKind::Snapshot, 1),
+                (EncodedKind::Delta, 1),
+                (EncodedKind::Delta, 1),
+                (EncodedKind::Snapshot, 2),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_interval_one_always_full() {
+        let mut encoder = DeltaEncoder::new();
+        for _ in 0..3 {
+            assert_eq!(encoder.encode(reading(25.0), 1).kind, EncodedKind::Snapshot);
+            assert_eq!(encoder.encode(reading(25.0), 0).kind, EncodedKind::Snapshot);
+        }
+    }
+
+    #[test]
+    fn test_negative_values_keep_sign() {
+        assert_eq!(format_deci(to_deci(-0.5)), "-0.5");
+        assert_eq!(format_deci(to_deci(-999.0)), "-999.0");
+        assert_eq!(format_deci(to_deci(0.04)), "0.0");
+    }
+}
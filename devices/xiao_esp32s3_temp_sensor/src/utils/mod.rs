@@ -1,10 +1,12 @@
 /// ユーティリティモジュール
 /// ハードウェア非依存の純粋関数を提供
 
+pub mod delta;
 pub mod mac_utils;
 pub mod payload;
 pub mod recalibration;
 
+pub use delta::{DeltaEncoder, Telemetry};
 pub use mac_utils::parse_mac;
-pub use payload::{format_hash_payload, EOF_MARKER};
+pub use payload::{format_encoded_hash_payload, format_hash_payload, EOF_MARKER};
 pub use recalibration::needs_recalibration;
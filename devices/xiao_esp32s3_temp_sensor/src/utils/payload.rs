@@ -1,3 +1,8 @@
+use super::delta::EncodedTelemetry;
+
+const DUMMY_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+const TIMESTAMP_PLACEHOLDER: &str = "2000/01/01 00:00:00.000";
+
 /// ESP-NOW EOF フレームのペイロード
 /// usb_cdc_receiver の detect_frame_type がこのバイト列で EOF を検出する
 pub const EOF_MARKER: &[u8] = b"EOF!";
@@ -11,14 +16,27 @@ pub const EOF_MARKER: &[u8] = b"EOF!";
 /// - VOLT:100     電圧センサー非搭載のプレースホルダ
 /// - TDS_VOLT:-999.0  TDS センサー非搭載のセンチネル値（サーバー側で None として扱われる）
 pub fn format_hash_payload(temp: f32) -> String {
-    const DUMMY_HASH: &str =
-        "0000000000000000000000000000000000000000000000000000000000000000";
     format!(
-        "HASH:{},VOLT:100,TEMP:{:.1},TDS_VOLT:-999.0,2000/01/01 00:00:00.000",
-        DUMMY_HASH, temp
+        "HASH:{},VOLT:100,TEMP:{:.1},TDS_VOLT:-999.0,{}",
+        DUMMY_HASH, temp, TIMESTAMP_PLACEHOLDER
     )
 }
 
+/// 差分エンコードしたテレメトリの HASH テキストペイロードを生成する
+///
+/// 項目・時刻・種別 (`SNAP:n` / `DELTA:n`) の順に並べる。
+/// 差分で変化した項目がなければ `HASH:<hash>,<時刻>,DELTA:n` になる。
+pub fn format_encoded_hash_payload(encoded: &EncodedTelemetry) -> String {
+    let mut payload = format!("HASH:{},", DUMMY_HASH);
+    for (key, value) in &encoded.fields {
+        payload.push_str(&format!("{}:{},", key, value));
+    }
+    payload.push_str(TIMESTAMP_PLACEHOLDER);
+    payload.push(',');
+    payload.push_str(&encoded.marker());
+    payload
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -70,6 +88,25 @@ mod tests {
         assert!(format_hash_payload(25.0).ends_with("2000/01/01 00:00:00.000"));
     }
 
+    #[test]
+    fn test_encoded_snapshot_keeps_legacy_field_order() {
+        use crate::utils::delta::{DeltaEncoder, Telemetry};
+        let mut encoder = DeltaEncoder::new();
+        let p = format_encoded_hash_payload(&encoder.encode(Telemetry::new(100, 25.0, -999.0), 6));
+        assert_eq!(p, format!("{},SNAP:0", format_hash_payload(25.0)));
+    }
+
+    #[test]
+    fn test_encoded_delta_omits_unchanged_fields() {
+        use crate::utils::delta::{DeltaEncoder, Telemetry};
+        let mut encoder = DeltaEncoder::new();
+        encoder.encode(Telemetry::new(100, 25.0, -999.0), 6);
+        let unchanged = format_encoded_hash_payload(&encoder.encode(Telemetry::new(100, 25.0, -999.0), 6));
+        assert_eq!(unchanged, format!("HASH:{},2000/01/01 00:00:00.000,DELTA:0", DUMMY_HASH));
+        let changed = format_encoded_hash_payload(&encoder.encode(Telemetry::new(100, 24.5, -999.0), 6));
+        assert_eq!(changed, format!("HASH:{},TEMP:24.5,2000/01/01 00:00:00.000,DELTA:0", DUMMY_HASH));
+    }
+
     #[test]
     fn test_eof_marker_literal() {
         // usb_cdc_receiver の detect_frame_type が "EOF!" で EOF を検出する
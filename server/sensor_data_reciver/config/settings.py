@@ -19,6 +19,10 @@ class Config:
     IMAGE_TIMEOUT: float = 20.0
     MAX_BUFFER_SIZE: int = 10 * 1024 * 1024  # 10MB
     MAX_DATA_LEN: int = 512
+    # 保存後の後処理パイプライン（TOMLファイルのパス、空なら後処理なし）
+    POST_PROCESSING_PIPELINE: str = os.environ.get("POST_PROCESSING_PIPELINE", "")
+    # 受信画像のSQLiteインデックス（空なら記録しない）
+    IMAGE_INDEX_DB: str = os.environ.get("IMAGE_INDEX_DB", "images/index.sqlite3")
     
     # InfluxDB settings
     INFLUXDB_URL: str = os.environ.get("INFLUXDB_URL", "http://localhost:8086")
@@ -49,6 +53,9 @@ class Config:
     MEDIUM_SLEEP_DURATION_S: int = 3600  # 1 hour for low voltage (12:00未満)
     NORMAL_SLEEP_DURATION_S: int = 600  # 10 minutes for normal voltage
 
+    # ハッシュが一致しなかった画像の再送要求の上限（デバイスが画像を保持している場合のみ要求）
+    MAX_IMAGE_RESENDS: int = int(os.environ.get("MAX_IMAGE_RESENDS", "1"))
+
 
 # Global configuration instance
 config = Config()
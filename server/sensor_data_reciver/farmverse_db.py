@@ -0,0 +1,88 @@
+"""受信画像のインデックスを照会するコマンド
+
+使用例:
+    uv run farmverse_db.py last --device aa:bb:cc:dd:ee:ff
+    uv run farmverse_db.py last --failed --limit 20
+    uv run farmverse_db.py skips --days 7
+"""
+
+import argparse
+import os
+import sys
+import time
+from datetime import datetime
+from typing import List, Optional
+
+sys.path.append(os.path.dirname(os.path.abspath(__file__)))
+
+from config import config
+from storage.image_index import ImageIndex, ImageRecord
+
+
+def _format_time(timestamp: Optional[float]) -> str:
+    if timestamp is None:
+        return "-"
+    return datetime.fromtimestamp(timestamp).strftime("%Y-%m-%d %H:%M:%S")
+
+
+def format_record(record: ImageRecord) -> str:
+    """1件を1行に整形します"""
+    fields = [
+        _format_time(record.recorded_at),
+        record.device,
+        f"id={record.device_id or '-'}",
+        record.status,
+        f"{record.size}B",
+        f"chunks={record.chunks}",
+        f"volt={record.voltage if record.voltage is not None else '-'}",
+        f"temp={record.temperature if record.temperature is not None else '-'}",
+        f"captured={_format_time(record.captured_at)}",
+        f"hash={record.hash or '-'}",
+        record.path or f"reason={record.reason or record.skip_reason or '-'}",
+    ]
+    return "  ".join(fields)
+
+
+def main(argv: Optional[List[str]] = None) -> int:
+    parser = argparse.ArgumentParser(description="受信画像のインデックスを照会します")
+    parser.add_argument("--db", default=config.IMAGE_INDEX_DB, help="インデックスのパス（既定: IMAGE_INDEX_DB）")
+    subcommands = parser.add_subparsers(dest="command", required=True)
+    last = subcommands.add_parser("last", help="新しい順に画像を表示")
+    last.add_argument("--device", help="デバイスID、または送信元MACアドレス")
+    last.add_argument("--failed", action="store_true", help="検証に失敗した・中断した画像のみ")
+    last.add_argument("--limit", type=int, default=1, help="表示件数（既定: 1）")
+    skips = subcommands.add_parser("skips", help="撮影しなかったサイクルをデバイス・理由ごとに集計")
+    skips.add_argument("--device", help="デバイスID、または送信元MACアドレス")
+    skips.add_argument("--days", type=float, help="直近の日数（既定: すべて）")
+    args = parser.parse_args(argv)
+
+    if not args.db or not os.path.exists(args.db):
+        print(f"Image index not found: {args.db}", file=sys.stderr)
+        return 1
+
+    index = ImageIndex(args.db)
+    try:
+        if args.command == "skips":
+            since = time.time() - args.days * 86400 if args.days is not None else None
+            counts = index.skip_counts(device=args.device, since=since)
+        else:
+            records = index.last(device=args.device, failed=args.failed, limit=args.limit)
+    finally:
+        index.close()
+    if args.command == "skips":
+        if not counts:
+            print("No skipped captures recorded", file=sys.stderr)
+            return 1
+        for device, reason, count in counts:
+            print(f"{device}  {reason}  {count}")
+        return 0
+    if not records:
+        print("No images recorded", file=sys.stderr)
+        return 1
+    for record in records:
+        print(format_record(record))
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(main())
@@ -2,15 +2,25 @@
 
 from .image_processor import ImageReceiver, ensure_dir_exists, save_image
 from .streaming_image_processor import StreamingImageProcessor
-from .sleep_controller import determine_sleep_duration, format_sleep_command_to_gateway
+from .post_processing import PostProcessingPipeline, load_pipeline
+from .sleep_controller import (
+    determine_sleep_duration,
+    format_file_push_commands,
+    format_resend_command_to_gateway,
+    format_sleep_command_to_gateway,
+)
 from .voltage_processor import VoltageDataProcessor
 
 __all__ = [
     "ImageReceiver",
     "StreamingImageProcessor",
+    "PostProcessingPipeline",
+    "load_pipeline",
     "ensure_dir_exists", 
     "save_image",
     "determine_sleep_duration",
     "format_sleep_command_to_gateway",
+    "format_resend_command_to_gateway",
+    "format_file_push_commands",
     "VoltageDataProcessor"
 ]
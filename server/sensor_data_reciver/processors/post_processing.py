@@ -0,0 +1,220 @@
+"""
+Post-processing pipeline for saved images.
+
+完全に受信・保存できた画像に対して、登録したプロセッサー（サムネイル生成、
+日付ディレクトリへの移動、Webhook通知）を順に実行します。下流の自動処理が
+画像ディレクトリをポーリングせずに済むようにするためのものです。
+
+パイプラインはTOMLで記述し、``[[processor]]`` の並び順に実行します::
+
+    [[processor]]
+    type = "thumbnail"
+    max_size = 320             # 長辺のピクセル数
+    directory = "thumbnails"   # IMAGE_DIR からの相対パス
+
+    [[processor]]
+    type = "dated_directory"
+    pattern = "%Y/%m/%d"       # strftime 形式（IMAGE_DIR からの相対パス）
+
+    [[processor]]
+    type = "webhook"
+    url = "http://localhost:8080/images"
+    timeout_seconds = 5
+
+プロセッサーの失敗はログに記録し、後続のプロセッサーは続けて実行します。
+"""
+
+import asyncio
+import json
+import logging
+import os
+import shutil
+import tomllib
+import urllib.request
+from dataclasses import dataclass, field
+from datetime import datetime
+from typing import Dict, List, Optional, Protocol
+
+try:
+    from PIL import Image
+except ImportError:
+    Image = None
+
+
+logger = logging.getLogger(__name__)
+
+
+@dataclass
+class ImageContext:
+    """プロセッサーに渡す保存済み画像の情報"""
+    path: str
+    sender_mac: str
+    size: int
+    saved_at: datetime = field(default_factory=datetime.now)
+    # HASHフレームのメタデータ（"HASH:...,VOLT:..." 形式）
+    hash_data: Optional[str] = None
+    # タイムラプスのシーケンス画像か（シーケンスのディレクトリ構成は変更しない）
+    in_sequence: bool = False
+    # プロセッサーが生成した派生ファイル {種類: パス}
+    outputs: Dict[str, str] = field(default_factory=dict)
+
+
+class ImageProcessor(Protocol):
+    """パイプラインに登録するプロセッサー"""
+
+    name: str
+
+    def process(self, context: ImageContext) -> None:
+        """画像を処理します（画像を移動した場合は ``context.path`` を更新する）"""
+        ...
+
+
+class ThumbnailProcessor:
+    """長辺を ``max_size`` に縮小したサムネイルを生成します"""
+
+    name = "thumbnail"
+
+    def __init__(self, image_dir: str, max_size: int = 320, directory: str = "thumbnails"):
+        if max_size <= 0:
+            raise ValueError(f"thumbnail max_size must be positive, got {max_size}")
+        self.max_size = max_size
+        self.output_dir = os.path.join(image_dir, directory)
+
+    def process(self, context: ImageContext) -> None:
+        if Image is None:
+            logger.warning("PIL not available, skipping thumbnail generation")
+            return
+        os.makedirs(self.output_dir, exist_ok=True)
+        thumbnail_path = os.path.join(self.output_dir, os.path.basename(context.path))
+        with Image.open(context.path) as im:
+            im.thumbnail((self.max_size, self.max_size))
+            im.save(thumbnail_path)
+        context.outputs["thumbnail"] = thumbnail_path
+        logger.debug(f"Saved thumbnail: {thumbnail_path}")
+
+
+class DatedDirectoryProcessor:
+    """画像を保存日時のディレクトリ（例: ``2025/06/01``）へ移動します"""
+
+    name = "dated_directory"
+
+    def __init__(self, image_dir: str, pattern: str = "%Y/%m/%d"):
+        self.image_dir = image_dir
+        self.pattern = pattern
+
+    def process(self, context: ImageContext) -> None:
+        if context.in_sequence:
+            logger.debug(f"Keeping sequence image in place: {context.path}")
+            return
+        target_dir = os.path.join(self.image_dir, context.saved_at.strftime(self.pattern))
+        os.makedirs(target_dir, exist_ok=True)
+        target_path = os.path.join(target_dir, os.path.basename(context.path))
+        shutil.move(context.path, target_path)
+        logger.debug(f"Moved image to dated directory: {target_path}")
+        context.path = target_path
+
+
+class WebhookProcessor:
+    """保存した画像のメタデータをJSONでPOSTします"""
+
+    name = "webhook"
+
+    def __init__(self, url: str, timeout_seconds: float = 5.0):
+        if not url.startswith(("http://", "https://")):
+            raise ValueError(f"webhook url must be http(s), got {url!r}")
+        self.url = url
+        self.timeout_seconds = timeout_seconds
+
+    @staticmethod
+    def payload(context: ImageContext) -> Dict:
+        """Webhookで送るJSON本文"""
+        return {
+            "event": "image_saved",
+            "mac": context.sender_mac,
+            "path": context.path,
+            "size": context.size,
+            "saved_at": context.saved_at.isoformat(timespec="seconds"),
+            "metadata": context.hash_data,
+            "outputs": dict(context.outputs),
+        }
+
+    def process(self, context: ImageContext) -> None:
+        body = json.dumps(self.payload(context)).encode()
+        request = urllib.request.Request(
+            self.url, data=body, headers={"Content-Type": "application/json"}, method="POST"
+        )
+        with urllib.request.urlopen(request, timeout=self.timeout_seconds) as response:
+            logger.debug(f"Webhook {self.url} responded {response.status}")
+
+
+class PostProcessingPipeline:
+    """保存済み画像に対してプロセッサーを順に実行するパイプライン"""
+
+    def __init__(self, processors: Optional[List[ImageProcessor]] = None):
+        self.processors: List[ImageProcessor] = list(processors or [])
+
+    def register(self, processor: ImageProcessor) -> None:
+        """プロセッサーを末尾に追加します"""
+        self.processors.append(processor)
+
+    def __len__(self) -> int:
+        return len(self.processors)
+
+    def run(self, context: ImageContext) -> ImageContext:
+        """すべてのプロセッサーを実行します（同期処理）"""
+        for processor in self.processors:
+            try:
+                processor.process(context)
+            except Exception as e:
+                logger.error(
+                    f"Post-processor '{processor.name}' failed for {context.sender_mac} ({context.path}): {e}"
+                )
+        return context
+
+    async def run_async(self, context: ImageContext) -> ImageContext:
+        """イベントループを止めないよう、別スレッドでパイプラインを実行します"""
+        if not self.processors:
+            return context
+        loop = asyncio.get_running_loop()
+        return await loop.run_in_executor(None, self.run, context)
+
+
+def build_processor(spec: Dict, image_dir: str) -> ImageProcessor:
+    """TOMLの ``[[processor]]`` 1件からプロセッサーを生成します"""
+    options = {key: value for key, value in spec.items() if key != "type"}
+    processor_type = spec.get("type")
+    if processor_type == "thumbnail":
+        return ThumbnailProcessor(image_dir, **options)
+    if processor_type == "dated_directory":
+        return DatedDirectoryProcessor(image_dir, **options)
+    if processor_type == "webhook":
+        return WebhookProcessor(**options)
+    raise ValueError(f"Unknown post-processor type: {processor_type!r}")
+
+
+def parse_pipeline(text: str, image_dir: str) -> PostProcessingPipeline:
+    """TOMLのパイプライン記述を解析します（不正な記述は ValueError）"""
+    try:
+        document = tomllib.loads(text)
+    except tomllib.TOMLDecodeError as e:
+        raise ValueError(f"Invalid post-processing pipeline: {e}") from e
+    specs = document.get("processor", [])
+    if not isinstance(specs, list):
+        raise ValueError("Post-processing pipeline must use [[processor]] tables")
+    try:
+        return PostProcessingPipeline([build_processor(spec, image_dir) for spec in specs])
+    except TypeError as e:
+        raise ValueError(f"Invalid post-processor options: {e}") from e
+
+
+def load_pipeline(path: str, image_dir: str) -> PostProcessingPipeline:
+    """パイプライン記述ファイルを読み込みます（パスが空なら空のパイプライン）"""
+    if not path:
+        return PostProcessingPipeline()
+    with open(path, encoding="utf-8") as f:
+        pipeline = parse_pipeline(f.read(), image_dir)
+    logger.info(
+        f"Loaded post-processing pipeline from {path}: "
+        f"{', '.join(processor.name for processor in pipeline.processors) or 'no processors'}"
+    )
+    return pipeline
@@ -1,8 +1,9 @@
 """Sleep control logic module."""
 
 import logging
+import zlib
 from datetime import datetime
-from typing import Optional
+from typing import List, Optional
 
 import sys
 import os
@@ -18,6 +19,30 @@ def format_sleep_command_to_gateway(sender_mac: str, sleep_duration_s: int) -> s
     return f"CMD_SEND_ESP_NOW:{sender_mac}:{sleep_duration_s}\n"
 
 
+def format_resend_command_to_gateway(sender_mac: str) -> str:
+    """Formats the command asking the device to retransmit its last image."""
+    return f"RESEND_LAST {sender_mac}\n"
+
+
+# FILE_DATA 1行で送れるデータの最大長（ゲートウェイの MAX_FILE_DATA_LEN と同じ）
+FILE_DATA_MAX_BYTES = 96
+
+
+def format_file_push_commands(sender_mac: str, slot: str, contents: bytes) -> List[str]:
+    """
+    Formats the commands that push a file to the device's named slot.
+
+    The gateway verifies the CRC-32 once every FILE_DATA line has arrived and then delivers
+    the file on the device's next transfers, resuming from the offset the device reports.
+    Send the lines in order and wait for each CMD_FILE: (or CMD_ERR:) reply before the next.
+    """
+    commands = [f"PUSH_FILE {sender_mac} {slot} {len(contents)} {zlib.crc32(contents):08x}\n"]
+    for offset in range(0, len(contents), FILE_DATA_MAX_BYTES):
+        part = contents[offset:offset + FILE_DATA_MAX_BYTES]
+        commands.append(f"FILE_DATA {sender_mac} {offset} {part.hex()}\n")
+    return commands
+
+
 def determine_sleep_duration(voltage_percent: Optional[float]) -> int:
     """
     Determine sleep duration based on battery voltage percentage and current time.
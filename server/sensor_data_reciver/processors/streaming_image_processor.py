@@ -7,11 +7,12 @@ the need for complete image buffering, processing chunks as they arrive.
 
 import asyncio
 import io
+import json
 import logging
 import os
 import time
 from datetime import datetime
-from typing import Dict, Optional, Callable
+from typing import Callable, Dict, List, Optional, Tuple
 from dataclasses import dataclass, field
 try:
     from PIL import Image
@@ -23,10 +24,32 @@ import sys
 sys.path.append(os.path.dirname(os.path.dirname(os.path.abspath(__file__))))
 
 from config import config
+from utils.data_parser import DataParser
+from utils.image_hash import verify_image_file
+from processors.post_processing import ImageContext, PostProcessingPipeline
+from storage.image_index import (
+    STATUS_ABORTED,
+    STATUS_CORRUPT,
+    STATUS_PARTIAL,
+    STATUS_SKIPPED,
+    STATUS_UNVERIFIED,
+    STATUS_VERIFIED,
+    ImageIndex,
+    ImageRecord,
+)
 
 
 logger = logging.getLogger(__name__)
 
+# タイムラプスのシーケンスを保存するディレクトリ（IMAGE_DIR 直下）
+SEQUENCE_DIR_NAME = "sequences"
+# GET_THUMBS で取り寄せたサムネイルを保存するディレクトリ（IMAGE_DIR 直下）
+THUMBNAIL_DIR_NAME = "thumbnails"
+# シーケンスごとのマニフェストファイル名
+SEQUENCE_MANIFEST_NAME = "manifest.json"
+# ハッシュが一致しなかった画像のファイル名の接尾辞
+CORRUPT_SUFFIX = "_corrupt"
+
 
 @dataclass
 class StreamingImageMetadata:
@@ -39,6 +62,17 @@ class StreamingImageMetadata:
     sequence_numbers: list = field(default_factory=list)
     is_completed: bool = False
     hash_data: Optional[str] = None
+    # 一時ファイルに書き込んだチャンクの位置 {シーケンス番号: (オフセット, 長さ)}
+    chunk_slots: Dict[int, Tuple[int, int]] = field(default_factory=dict)
+    # 書き込み済みの最大番号より前に遅れて届いたチャンク {シーケンス番号: データ}
+    late_chunks: Dict[int, bytes] = field(default_factory=dict)
+    highest_sequence: Optional[int] = None
+    written_bytes: int = 0
+    duplicate_chunks: int = 0
+
+    def has_chunk(self, sequence_number: int) -> bool:
+        """同じシーケンス番号のチャンクを受信済みか"""
+        return sequence_number in self.chunk_slots or sequence_number in self.late_chunks
 
 
 @dataclass
@@ -70,14 +104,23 @@ class StreamingImageProcessor:
     メモリ効率を大幅に向上させます。
     """
     
-    def __init__(self, max_concurrent_streams: int = 5):
+    def __init__(
+        self,
+        max_concurrent_streams: int = 5,
+        post_processing: Optional[PostProcessingPipeline] = None,
+        image_index: Optional[ImageIndex] = None,
+    ):
         """
         Args:
             max_concurrent_streams: 同時処理可能なストリーム数
+            post_processing: 完全に受信できた画像の保存後に実行するパイプライン
+            image_index: 保存・中断した画像を記録するインデックス（Noneなら記録しない）
         """
         self.active_streams: Dict[str, StreamingImageMetadata] = {}
         self.streaming_stats = StreamingStats()
         self.max_concurrent_streams = max_concurrent_streams
+        self.post_processing = post_processing or PostProcessingPipeline()
+        self.image_index = image_index
         
         # チャンク処理用の一時ファイルディレクトリ
         self.temp_dir = os.path.join(config.IMAGE_DIR, "streaming_temp")
@@ -136,11 +179,13 @@ class StreamingImageProcessor:
     ) -> bool:
         """
         チャンクデータをリアルタイム処理
+
+        チャンクは到着順によらずシーケンス番号順に格納し、同じ番号の重複は無視します。
         
         Args:
             sender_mac: 送信元MACアドレス
             chunk_data: チャンクデータ
-            sequence_number: シーケンス番号
+            sequence_number: シーケンス番号（格納順のキー）
             callback: 処理完了時のコールバック関数
             
         Returns:
@@ -151,6 +196,13 @@ class StreamingImageProcessor:
             await self.start_image_stream(sender_mac)
         
         stream_meta = self.active_streams[sender_mac]
+
+        # 再送などで重複したチャンクは1回だけ格納する
+        if stream_meta.has_chunk(sequence_number):
+            stream_meta.duplicate_chunks += 1
+            stream_meta.last_chunk_time = time.time()
+            logger.debug(f"Duplicate chunk seq={sequence_number} from {sender_mac}, ignored")
+            return True
         
         # チャンク処理統計を更新
         stream_meta.total_chunks_received += 1
@@ -162,17 +214,30 @@ class StreamingImageProcessor:
         self.streaming_stats.update_chunk_stats(len(chunk_data))
         
         try:
-            # チャンクをテンポラリファイルに追記
-            temp_file_path = self._get_temp_file_path(sender_mac)
-            
-            # ファイル書き込み（非同期）
-            loop = asyncio.get_running_loop()
-            await loop.run_in_executor(
-                None, 
-                self._append_chunk_to_file, 
-                temp_file_path, 
-                chunk_data
-            )
+            if (
+                stream_meta.highest_sequence is None
+                or sequence_number > stream_meta.highest_sequence
+            ):
+                # 到着順が番号順のうちはテンポラリファイルに追記
+                temp_file_path = self._get_temp_file_path(sender_mac)
+                
+                # ファイル書き込み（非同期）
+                loop = asyncio.get_running_loop()
+                await loop.run_in_executor(
+                    None, 
+                    self._append_chunk_to_file, 
+                    temp_file_path, 
+                    chunk_data
+                )
+                stream_meta.chunk_slots[sequence_number] = (
+                    stream_meta.written_bytes,
+                    len(chunk_data),
+                )
+                stream_meta.written_bytes += len(chunk_data)
+                stream_meta.highest_sequence = sequence_number
+            else:
+                # 遅れて届いたチャンクは保持し、保存時に番号順へ並べ替える
+                stream_meta.late_chunks[sequence_number] = chunk_data
             
             # 最初のチャンクでJPEGヘッダーを検証
             if stream_meta.total_chunks_received == 1:
@@ -208,13 +273,23 @@ class StreamingImageProcessor:
             await self.abort_stream(sender_mac, f"Processing error: {e}")
             return False
     
-    async def finalize_image_stream(self, sender_mac: str, stats: Optional[Dict] = None) -> Optional[str]:
+    async def finalize_image_stream(
+        self,
+        sender_mac: str,
+        stats: Optional[Dict] = None,
+        gaps: Optional[List[Tuple[int, int]]] = None,
+    ) -> Optional[str]:
         """
         画像ストリームを完成・保存
         
         Args:
             sender_mac: 送信元MACアドレス
             stats: 追加統計情報
+            gaps: 不完全な転送の欠落したシーケンス番号の範囲（Noneは完全な転送）。
+                不完全な画像は ``_partial`` 付きで保存し、同名の ``.gaps.json`` に欠落範囲を書き出す。
+                完全な画像はHASHフレームのハッシュで検証し、一致しなければ ``_corrupt`` 付きで保存する。
+                サムネイル（HASHの ``THUMB``）は ``thumbnails`` ディレクトリにデバイスの通し番号で保存し、
+                回転と後処理はしない
             
         Returns:
             Optional[str]: 保存されたファイルパス（失敗時はNone）
@@ -233,20 +308,68 @@ class StreamingImageProcessor:
                 await self.abort_stream(sender_mac, "Temp file missing")
                 return None
             
-            # ファイルサイズの確認
+            # 遅れて届いたチャンクがあればシーケンス番号順に並べ直す
+            if stream_meta.late_chunks:
+                logger.info(
+                    f"Reordering {len(stream_meta.late_chunks)} late chunk(s) for {sender_mac}"
+                )
+                loop = asyncio.get_running_loop()
+                await loop.run_in_executor(
+                    None, self._reorder_temp_file, temp_file_path, stream_meta
+                )
+
+            thumbnail = (
+                DataParser.parse_thumbnail(stream_meta.hash_data) if stream_meta.hash_data else None
+            )
+
+            # ファイルサイズの確認（サムネイルは1KBに満たないことがある）
             file_size = os.path.getsize(temp_file_path)
-            if file_size < 1000:  # 1KB未満は不正
+            if file_size < 1000 and thumbnail is None:  # 1KB未満は不正
                 logger.error(f"Image file too small for {sender_mac}: {file_size} bytes")
                 await self.abort_stream(sender_mac, "File too small")
                 return None
             
+            # 完全に受信できた画像はHASHフレームのハッシュで検証する
+            loop = asyncio.get_running_loop()
+            hash_matched = None
+            if gaps is None and stream_meta.hash_data:
+                hash_matched = await loop.run_in_executor(
+                    None, verify_image_file, temp_file_path, stream_meta.hash_data
+                )
+                if hash_matched is False:
+                    logger.warning(f"Image hash mismatch for {sender_mac}: {stream_meta.hash_data}")
+                    if stats is not None:
+                        stats["hash_mismatch"] = stats.get("hash_mismatch", 0) + 1
+
             # 最終的な画像ファイルパスを生成
-            timestamp = datetime.now().strftime("%Y%m%d_%H%M%S_%f")
-            final_filename = f"{sender_mac.replace(':', '')}_{timestamp}.jpg"
-            final_file_path = os.path.join(config.IMAGE_DIR, final_filename)
+            # タイムラプスの画像はシーケンスごとのディレクトリに通し番号で保存する
+            if gaps is not None:
+                partial_suffix = "_partial"
+            elif hash_matched is False:
+                partial_suffix = CORRUPT_SUFFIX
+            else:
+                partial_suffix = ""
+            sequence = (
+                DataParser.parse_sequence(stream_meta.hash_data) if stream_meta.hash_data else None
+            )
+            if thumbnail is not None:
+                image_dir = os.path.join(config.IMAGE_DIR, THUMBNAIL_DIR_NAME)
+                os.makedirs(image_dir, exist_ok=True)
+                final_filename = f"{sender_mac.replace(':', '')}_{thumbnail['seq']:06d}{partial_suffix}.jpg"
+            elif sequence is not None:
+                image_dir = os.path.join(config.IMAGE_DIR, SEQUENCE_DIR_NAME, sequence["name"])
+                os.makedirs(image_dir, exist_ok=True)
+                final_filename = (
+                    f"{sender_mac.replace(':', '')}_{sequence['run']}_"
+                    f"{sequence['index']:06d}{partial_suffix}.jpg"
+                )
+            else:
+                image_dir = config.IMAGE_DIR
+                timestamp = datetime.now().strftime("%Y%m%d_%H%M%S_%f")
+                final_filename = f"{sender_mac.replace(':', '')}_{timestamp}{partial_suffix}.jpg"
+            final_file_path = os.path.join(image_dir, final_filename)
             
             # ファイル移動（非同期）
-            loop = asyncio.get_running_loop()
             await loop.run_in_executor(
                 None,
                 self._move_temp_to_final,
@@ -254,12 +377,52 @@ class StreamingImageProcessor:
                 final_file_path
             )
             
+            if gaps is not None:
+                await loop.run_in_executor(
+                    None, self._write_gap_map, final_file_path, stream_meta, gaps
+                )
+
+            if sequence is not None:
+                await loop.run_in_executor(
+                    None,
+                    self._update_sequence_manifest,
+                    image_dir,
+                    sequence,
+                    sender_mac,
+                    final_filename,
+                    gaps is not None,
+                )
+
             # 画像回転処理（既存のロジックを維持）
-            await self._create_rotated_image(
-                final_file_path, 
-                sender_mac
-            )
+            if thumbnail is None:
+                await self._create_rotated_image(
+                    final_file_path, 
+                    sender_mac
+                )
             
+            # 完全に受信でき、ハッシュの不一致がない画像のみ後処理する（移動した場合は移動先を返す）
+            if gaps is None and hash_matched is not False and thumbnail is None and self.post_processing:
+                context = await self.post_processing.run_async(
+                    ImageContext(
+                        path=final_file_path,
+                        sender_mac=sender_mac,
+                        size=file_size,
+                        hash_data=stream_meta.hash_data,
+                        in_sequence=sequence is not None,
+                    )
+                )
+                final_file_path = context.path
+
+            if gaps is not None:
+                index_status = STATUS_PARTIAL
+            elif hash_matched is False:
+                index_status = STATUS_CORRUPT
+            elif hash_matched:
+                index_status = STATUS_VERIFIED
+            else:
+                index_status = STATUS_UNVERIFIED
+            await self._record_in_index(stream_meta, file_size, index_status, path=final_file_path)
+
             # 統計更新
             self.streaming_stats.total_images_processed += 1
             processing_time = time.time() - stream_meta.started_at
@@ -277,6 +440,8 @@ class StreamingImageProcessor:
                 f"✓ Finalized streaming image for {sender_mac}: "
                 f"{final_filename} ({file_size} bytes, "
                 f"{stream_meta.total_chunks_received} chunks, "
+                f"{len(stream_meta.late_chunks)} reordered, "
+                f"{stream_meta.duplicate_chunks} duplicates, "
                 f"{processing_time:.2f}s)"
             )
             
@@ -306,8 +471,61 @@ class StreamingImageProcessor:
                 f"({stream_meta.total_chunks_received} chunks, "
                 f"{stream_meta.total_bytes_received} bytes)"
             )
+            await self._record_in_index(
+                stream_meta, stream_meta.total_bytes_received, STATUS_ABORTED, reason=reason
+            )
         
         await self._cleanup_stream(sender_mac)
+
+    async def _record_in_index(
+        self,
+        stream_meta: StreamingImageMetadata,
+        size: int,
+        status: str,
+        path: Optional[str] = None,
+        reason: Optional[str] = None,
+    ):
+        """画像をインデックスに記録（記録に失敗しても受信処理は続ける）"""
+        if self.image_index is None:
+            return
+        record = ImageRecord.from_transfer(
+            device=stream_meta.sender_mac,
+            started_at=stream_meta.started_at,
+            size=size,
+            status=status,
+            hash_data=stream_meta.hash_data,
+            path=path,
+            chunks=stream_meta.total_chunks_received,
+            reason=reason,
+        )
+        await self._write_index_record(record)
+
+    async def record_skipped_capture(self, sender_mac: str, hash_data: str):
+        """
+        画像なしで届いたサイクル（HASHフレームの ``SKIP`` に理由）をインデックスに記録
+
+        Args:
+            sender_mac: 送信元MACアドレス
+            hash_data: HASHフレームのメタデータ
+        """
+        if self.image_index is None:
+            return
+        record = ImageRecord.from_transfer(
+            device=sender_mac,
+            started_at=time.time(),
+            size=0,
+            status=STATUS_SKIPPED,
+            hash_data=hash_data,
+        )
+        await self._write_index_record(record)
+
+    async def _write_index_record(self, record: ImageRecord):
+        """インデックスへの書き込み（記録に失敗しても受信処理は続ける）"""
+        try:
+            loop = asyncio.get_running_loop()
+            await loop.run_in_executor(None, self.image_index.record, record)
+        except Exception as e:
+            logger.error(f"Failed to record image in index for {record.device}: {e}")
     
     async def _cleanup_stream(self, sender_mac: str):
         """ストリームのクリーンアップ"""
@@ -341,11 +559,82 @@ class StreamingImageProcessor:
         with open(file_path, 'ab') as f:
             f.write(chunk_data)
     
+    def _reorder_temp_file(self, file_path: str, stream_meta: StreamingImageMetadata):
+        """一時ファイルのチャンクと遅れて届いたチャンクをシーケンス番号順に書き直す（同期処理）"""
+        with open(file_path, 'rb') as f:
+            written = f.read()
+        chunks = dict(stream_meta.late_chunks)
+        for sequence_number, (offset, length) in stream_meta.chunk_slots.items():
+            chunks[sequence_number] = written[offset:offset + length]
+        with open(file_path, 'wb') as f:
+            for sequence_number in sorted(chunks):
+                f.write(chunks[sequence_number])
+
     def _move_temp_to_final(self, temp_path: str, final_path: str):
         """一時ファイルを最終ファイルに移動（同期処理）"""
         import shutil
         shutil.move(temp_path, final_path)
     
+    def _write_gap_map(self, image_path: str, stream_meta, gaps: List[Tuple[int, int]]):
+        """不完全な画像の欠落範囲をサイドカーファイルに書き出す"""
+        gap_map = {
+            "missing_ranges": [list(gap) for gap in gaps],
+            "missing_count": sum(end - start + 1 for start, end in gaps),
+            "received_chunks": stream_meta.total_chunks_received,
+            "received_bytes": stream_meta.total_bytes_received,
+        }
+        with open(f"{os.path.splitext(image_path)[0]}.gaps.json", "w") as f:
+            json.dump(gap_map, f)
+
+    def _update_sequence_manifest(
+        self,
+        sequence_dir: str,
+        sequence: Dict,
+        sender_mac: str,
+        filename: str,
+        partial: bool,
+    ):
+        """シーケンスのマニフェストに画像を追加し、実行・通し番号順に書き直す（同期処理）
+
+        実行（``run``）は最初に受信した順に並べ、同じ実行・番号の画像は再送として置き換える。
+        """
+        manifest_path = os.path.join(sequence_dir, SEQUENCE_MANIFEST_NAME)
+        manifest = {"sequence": sequence["name"], "interval_seconds": None, "runs": [], "frames": []}
+        if os.path.exists(manifest_path):
+            try:
+                with open(manifest_path) as f:
+                    manifest = json.load(f)
+            except (OSError, ValueError) as e:
+                logger.warning(f"Rebuilding unreadable sequence manifest {manifest_path}: {e}")
+
+        if sequence["interval_seconds"] is not None:
+            manifest["interval_seconds"] = sequence["interval_seconds"]
+        if sequence["run"] not in manifest["runs"]:
+            manifest["runs"].append(sequence["run"])
+
+        frame = {
+            "run": sequence["run"],
+            "index": sequence["index"],
+            "mac": sender_mac,
+            "file": filename,
+            "partial": partial,
+            "saved_at": datetime.now().isoformat(timespec="seconds"),
+        }
+        frames = [
+            existing
+            for existing in manifest["frames"]
+            if (existing["mac"], existing["run"], existing["index"])
+            != (sender_mac, sequence["run"], sequence["index"])
+        ]
+        frames.append(frame)
+        frames.sort(key=lambda item: (manifest["runs"].index(item["run"]), item["index"], item["mac"]))
+        manifest["frames"] = frames
+
+        temp_path = f"{manifest_path}.tmp"
+        with open(temp_path, "w") as f:
+            json.dump(manifest, f, indent=2)
+        os.replace(temp_path, manifest_path)
+
     def _validate_jpeg_header(self, chunk_data: bytes) -> tuple[bool, Optional[str]]:
         """JPEGヘッダーを検証し、結果と理由を返します。
 
@@ -3,18 +3,18 @@
 from .constants import (
     MAC_ADDRESS_LENGTH, FRAME_TYPE_LENGTH, SEQUENCE_NUM_LENGTH, 
     LENGTH_FIELD_BYTES, CHECKSUM_LENGTH, START_MARKER, END_MARKER,
-    FRAME_TYPE_HASH, FRAME_TYPE_DATA, FRAME_TYPE_EOF,
-    HEADER_LENGTH, FOOTER_LENGTH
+    FRAME_TYPE_HASH, FRAME_TYPE_DATA, FRAME_TYPE_EOF, FRAME_TYPE_FEC,
+    FRAME_TYPE_COMPLETE, FRAME_TYPE_STATS, FRAME_TYPE_ABORT, FRAME_TYPE_FLEET_SUMMARY, FRAME_TYPE_URGENT, HEADER_LENGTH, FOOTER_LENGTH
 )
 from .cycle_tracker import CycleTracker, SenderCycleState
-from .frame_parser import FrameParser
+from .frame_parser import FrameAbortInfo, FrameCompleteInfo, FrameParser
 from .serial_handler import SerialProtocol
 from .streaming_handler import StreamingSerialProtocol
 
 __all__ = [
     "MAC_ADDRESS_LENGTH", "FRAME_TYPE_LENGTH", "SEQUENCE_NUM_LENGTH", 
     "LENGTH_FIELD_BYTES", "CHECKSUM_LENGTH", "START_MARKER", "END_MARKER",
-    "FRAME_TYPE_HASH", "FRAME_TYPE_DATA", "FRAME_TYPE_EOF",
-    "HEADER_LENGTH", "FOOTER_LENGTH", "CycleTracker", "SenderCycleState",
-    "FrameParser", "SerialProtocol", "StreamingSerialProtocol"
+    "FRAME_TYPE_HASH", "FRAME_TYPE_DATA", "FRAME_TYPE_EOF", "FRAME_TYPE_FEC",
+    "FRAME_TYPE_COMPLETE", "FRAME_TYPE_STATS", "FRAME_TYPE_ABORT", "FRAME_TYPE_FLEET_SUMMARY", "FRAME_TYPE_URGENT", "HEADER_LENGTH", "FOOTER_LENGTH", "CycleTracker", "SenderCycleState",
+    "FrameAbortInfo", "FrameCompleteInfo", "FrameParser", "SerialProtocol", "StreamingSerialProtocol"
 ]
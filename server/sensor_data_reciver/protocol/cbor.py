@@ -0,0 +1,65 @@
+"""最小限のCBOR（RFC 8949）デコーダー
+
+ゲートウェイが送出するFLEET_SUMMARYフレームの解析用。定長の整数・文字列・配列・マップと
+単純値（null/true/false）のみ扱います。
+"""
+
+from typing import Any, Tuple
+
+
+def decode(data: bytes) -> Any:
+    """CBORのバイト列を1つの値として解析（余りのバイトがあればValueError）"""
+    value, offset = _decode_item(bytes(data), 0)
+    if offset != len(data):
+        raise ValueError(f"Trailing bytes after CBOR item: {len(data) - offset}")
+    return value
+
+
+def _read(data: bytes, offset: int, length: int) -> Tuple[bytes, int]:
+    end = offset + length
+    if end > len(data):
+        raise ValueError("Truncated CBOR data")
+    return data[offset:end], end
+
+
+def _decode_item(data: bytes, offset: int) -> Tuple[Any, int]:
+    head, offset = _read(data, offset, 1)
+    major, info = head[0] >> 5, head[0] & 0x1F
+
+    if major == 7:
+        simple = {20: False, 21: True, 22: None}
+        if info not in simple:
+            raise ValueError(f"Unsupported CBOR simple value: {info}")
+        return simple[info], offset
+
+    if info < 24:
+        argument = info
+    elif info <= 27:
+        raw, offset = _read(data, offset, 1 << (info - 24))
+        argument = int.from_bytes(raw, "big")
+    else:
+        raise ValueError(f"Unsupported CBOR length encoding: {info}")
+
+    if major == 0:
+        return argument, offset
+    if major == 1:
+        return -1 - argument, offset
+    if major == 2:
+        return _read(data, offset, argument)
+    if major == 3:
+        raw, offset = _read(data, offset, argument)
+        return raw.decode("utf-8"), offset
+    if major == 4:
+        items = []
+        for _ in range(argument):
+            item, offset = _decode_item(data, offset)
+            items.append(item)
+        return items, offset
+    if major == 5:
+        entries = {}
+        for _ in range(argument):
+            key, offset = _decode_item(data, offset)
+            value, offset = _decode_item(data, offset)
+            entries[key] = value
+        return entries, offset
+    raise ValueError(f"Unsupported CBOR major type: {major}")
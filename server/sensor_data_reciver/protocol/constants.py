@@ -15,6 +15,12 @@ END_MARKER = b"\xcd\xef\x56\x78"
 FRAME_TYPE_HASH = 1
 FRAME_TYPE_DATA = 2
 FRAME_TYPE_EOF = 3
+FRAME_TYPE_FEC = 4  # FEC告知/パリティ（protocol/fec.py）
+FRAME_TYPE_COMPLETE = 5  # ゲートウェイがHASH/EOFを集約した転送完了イベント
+FRAME_TYPE_STATS = 6  # ゲートウェイ統計（KEY:値のカンマ区切り）
+FRAME_TYPE_ABORT = 7  # ゲートウェイが送出する転送中断イベント
+FRAME_TYPE_FLEET_SUMMARY = 9  # ゲートウェイの登録デバイスごとの定期サマリー（CBOR）
+FRAME_TYPE_URGENT = 10  # デバイスの緊急通知（ゲートウェイが画像チャンクより先に転送）
 
 # Calculated frame lengths
 HEADER_LENGTH = len(START_MARKER) + MAC_ADDRESS_LENGTH + FRAME_TYPE_LENGTH + SEQUENCE_NUM_LENGTH + LENGTH_FIELD_BYTES
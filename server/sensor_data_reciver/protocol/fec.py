@@ -0,0 +1,182 @@
+"""画像チャンクFEC（XORパリティ）の受信側再構成
+
+送信側（m5stack_unit_cam の communication/esp_now/fec.rs）と同じワイヤフォーマットを扱う。
+
+- 告知ペイロード: [FEC_KIND_ANNOUNCE, K, M]
+- パリティペイロード: [FEC_KIND_PARITY, first_seq(u32 LE), data_count, parity_index,
+  parity_count, length_xor(u16 LE), parity...]
+
+告知フレームの次のシーケンス番号から、K個のデータチャンクとM個のパリティチャンクが
+交互に並ぶ。グループ単位でチャンクを保持し、欠落をパリティで復元してから順番に払い出す。
+"""
+
+from dataclasses import dataclass, field
+from typing import Dict, List, Optional, Tuple
+
+FEC_KIND_ANNOUNCE = 0
+FEC_KIND_PARITY = 1
+FEC_ANNOUNCE_LENGTH = 3
+FEC_PARITY_HEADER_LENGTH = 10
+
+
+@dataclass
+class ParityChunk:
+    """パリティチャンク"""
+
+    first_seq: int
+    data_count: int
+    parity_index: int
+    parity_count: int
+    length_xor: int
+    parity: bytes
+
+    @classmethod
+    def from_payload(cls, payload: bytes) -> "ParityChunk":
+        if len(payload) < FEC_PARITY_HEADER_LENGTH:
+            raise ValueError(f"FEC parity payload too short: {len(payload)}")
+        if payload[0] != FEC_KIND_PARITY:
+            raise ValueError(f"Not a FEC parity payload: kind={payload[0]}")
+        return cls(
+            first_seq=int.from_bytes(payload[1:5], byteorder="little"),
+            data_count=payload[5],
+            parity_index=payload[6],
+            parity_count=payload[7],
+            length_xor=int.from_bytes(payload[8:10], byteorder="little"),
+            parity=bytes(payload[FEC_PARITY_HEADER_LENGTH:]),
+        )
+
+    def covers(self, index: int) -> bool:
+        return self.parity_count > 0 and index % self.parity_count == self.parity_index
+
+
+def parse_announce(payload: bytes) -> Optional[Tuple[int, int]]:
+    """告知ペイロードから (K, M) を取得する。告知でなければ None"""
+    if len(payload) < FEC_ANNOUNCE_LENGTH or payload[0] != FEC_KIND_ANNOUNCE:
+        return None
+    data_chunks, parity_chunks = payload[1], payload[2]
+    if data_chunks == 0 or parity_chunks == 0 or parity_chunks > data_chunks:
+        return None
+    return data_chunks, parity_chunks
+
+
+def _xor_into(acc: bytearray, chunk: bytes) -> None:
+    if len(acc) < len(chunk):
+        acc.extend(b"\x00" * (len(chunk) - len(acc)))
+    for i, b in enumerate(chunk):
+        acc[i] ^= b
+
+
+def recover_group(chunks: List[Optional[bytes]], parities: List[ParityChunk]) -> int:
+    """欠落チャンクをパリティから復元し、復元数を返す"""
+    recovered = 0
+    for parity in parities:
+        missing = [i for i in range(len(chunks)) if parity.covers(i) and chunks[i] is None]
+        if len(missing) != 1:
+            continue
+
+        data = bytearray(parity.parity)
+        length = parity.length_xor
+        for i, chunk in enumerate(chunks):
+            if chunk is not None and parity.covers(i):
+                length ^= len(chunk)
+                _xor_into(data, chunk)
+        chunks[missing[0]] = bytes(data[:length])
+        recovered += 1
+    return recovered
+
+
+@dataclass
+class _Group:
+    data_count: int
+    parity_count: int
+    chunks: Dict[int, bytes] = field(default_factory=dict)
+    parities: Dict[int, ParityChunk] = field(default_factory=dict)
+
+    def is_complete(self) -> bool:
+        return len(self.chunks) >= self.data_count or len(self.parities) >= self.parity_count
+
+
+class FecReassembler:
+    """1送信元・1セッション分のFECグループ再構成"""
+
+    def __init__(self, data_chunks: int, parity_chunks: int, base_seq: int):
+        self.data_chunks = data_chunks
+        self.parity_chunks = parity_chunks
+        self.base_seq = base_seq
+        self.groups: Dict[int, _Group] = {}
+        self.next_group = 0
+        self.recovered_count = 0
+
+    @property
+    def _span(self) -> int:
+        return self.data_chunks + self.parity_chunks
+
+    def _group(self, group_index: int) -> _Group:
+        if group_index not in self.groups:
+            self.groups[group_index] = _Group(self.data_chunks, self.parity_chunks)
+        return self.groups[group_index]
+
+    def add_data(self, seq_num: int, chunk: bytes) -> List[Tuple[int, bytes]]:
+        """データチャンクを追加し、払い出し可能になった (seq, chunk) を順番に返す"""
+        offset = seq_num - self.base_seq
+        if offset < 0:
+            return [(seq_num, chunk)]
+
+        group_index, position = divmod(offset, self._span)
+        if group_index < self.next_group or position >= self.data_chunks:
+            # 払い出し済みグループの重複、またはパリティ位置のデータは無視
+            return []
+
+        self._group(group_index).chunks.setdefault(position, bytes(chunk))
+        return self._release(force_before=group_index)
+
+    def add_parity(self, parity: ParityChunk) -> List[Tuple[int, bytes]]:
+        """パリティチャンクを追加し、払い出し可能になった (seq, chunk) を順番に返す"""
+        offset = parity.first_seq - self.base_seq
+        if offset < 0 or offset % self._span != 0:
+            return []
+
+        group_index = offset // self._span
+        if group_index < self.next_group:
+            return []
+
+        group = self._group(group_index)
+        group.data_count = parity.data_count
+        group.parity_count = parity.parity_count
+        group.parities.setdefault(parity.parity_index, parity)
+        return self._release(force_before=group_index)
+
+    def flush(self) -> List[Tuple[int, bytes]]:
+        """保持中の全グループを払い出す（EOF受信時など）"""
+        if not self.groups:
+            return []
+        return self._release(force_before=max(self.groups) + 1)
+
+    def _release(self, force_before: int) -> List[Tuple[int, bytes]]:
+        released: List[Tuple[int, bytes]] = []
+        while True:
+            group = self.groups.get(self.next_group)
+            forced = self.next_group < force_before
+            if group is None:
+                if not forced:
+                    break
+                self.next_group += 1
+                continue
+            if not (forced or group.is_complete()):
+                break
+
+            released.extend(self._drain_group(self.next_group, group))
+            del self.groups[self.next_group]
+            self.next_group += 1
+        return released
+
+    def _drain_group(self, group_index: int, group: _Group) -> List[Tuple[int, bytes]]:
+        chunks: List[Optional[bytes]] = [group.chunks.get(i) for i in range(group.data_count)]
+        self.recovered_count += recover_group(chunks, list(group.parities.values()))
+
+        first_seq = self.base_seq + group_index * self._span
+        return [
+            (first_seq + i, chunk)
+            for i, chunk in enumerate(chunks)
+            if chunk is not None
+        ]
@@ -1,8 +1,10 @@
 """Frame parsing utilities."""
 
 import re
-from typing import Tuple
+from dataclasses import dataclass, field
+from typing import Dict, List, Optional, Tuple
 
+from . import cbor
 from .constants import START_MARKER, MAC_ADDRESS_LENGTH, FRAME_TYPE_LENGTH, SEQUENCE_NUM_LENGTH, LENGTH_FIELD_BYTES
 
 
@@ -11,6 +13,39 @@ class FrameSyncError(ValueError):
     pass
 
 
+@dataclass
+class FrameCompleteInfo:
+    """転送完了イベント（ゲートウェイがHASH/EOFを集約したもの）"""
+
+    frame_id: int
+    byte_count: int
+    dedupe_count: int
+    eof_received: bool
+    hash_payload: Optional[bytes]
+    # ゲートウェイでの無線受信時間・USB転送時間（ミリ秒、古いゲートウェイでは未送信）
+    air_ms: Optional[int] = None
+    usb_ms: Optional[int] = None
+    # ゲートウェイで受信できなかったシーケンス番号の範囲（両端を含む）
+    gaps: List[Tuple[int, int]] = field(default_factory=list)
+    # ゲートウェイの部分転送の救済で送出された不完全な転送
+    partial: bool = False
+    # 撮影からゲートウェイの完了イベント送出までの時間（ミリ秒、デバイスが撮影経過時間を送らない場合は未送信）
+    e2e_ms: Optional[int] = None
+    # ゲートウェイが解釈した範囲内のテレメトリ（VOLT・TEMP など、古いゲートウェイでは空）
+    decoded: Dict[str, float] = field(default_factory=dict)
+    # ゲートウェイが範囲外・解釈不能と判定したテレメトリのキー
+    data_quality: List[str] = field(default_factory=list)
+
+
+@dataclass
+class FrameAbortInfo:
+    """転送中断イベント（デバイスの中断通知、またはゲートウェイでの欠落検知）"""
+
+    frame_id: int
+    byte_count: int
+    reason: str
+
+
 class FrameParser:
     """フレーム解析クラス"""
     
@@ -66,3 +101,101 @@ class FrameParser:
         safe_mac = re.sub(r'[^\w\-_]', '', sender_mac_str.replace(':', ''))
         safe_timestamp = re.sub(r'[^\w\-_]', '', timestamp)
         return f"{safe_mac}_{safe_timestamp}.jpg"
+
+    @staticmethod
+    def parse_frame_complete(payload: bytes) -> FrameCompleteInfo:
+        """転送完了イベントのペイロードを解析
+
+        形式: ``FRAME_ID:<id>,BYTES:<n>,DEDUP:<n>,EOF:<0|1>[,AIR_MS:<n>,USB_MS:<n>][,GAPS:<a-b|c-d>][,PARTIAL:1][,E2E_MS:<n>][,DEC:<KEY=値|..>][,DQ:<KEY|..>][;<HASHペイロード>]``
+        """
+        summary, separator, hash_payload = bytes(payload).partition(b";")
+        try:
+            fields = dict(
+                item.split(":", 1) for item in summary.decode("ascii").split(",")
+            )
+            return FrameCompleteInfo(
+                frame_id=int(fields["FRAME_ID"]),
+                byte_count=int(fields["BYTES"]),
+                dedupe_count=int(fields["DEDUP"]),
+                eof_received=fields["EOF"] == "1",
+                hash_payload=hash_payload if separator else None,
+                air_ms=int(fields["AIR_MS"]) if "AIR_MS" in fields else None,
+                usb_ms=int(fields["USB_MS"]) if "USB_MS" in fields else None,
+                gaps=FrameParser._parse_gap_ranges(fields.get("GAPS", "")),
+                partial=fields.get("PARTIAL") == "1",
+                e2e_ms=int(fields["E2E_MS"]) if "E2E_MS" in fields else None,
+                decoded={
+                    key: float(value)
+                    for key, value in (item.split("=", 1) for item in filter(None, fields.get("DEC", "").split("|")))
+                },
+                data_quality=list(filter(None, fields.get("DQ", "").split("|"))),
+            )
+        except (UnicodeDecodeError, KeyError, ValueError) as e:
+            raise ValueError(f"Invalid frame complete payload: {summary!r}") from e
+
+    @staticmethod
+    def _parse_gap_ranges(value: str) -> List[Tuple[int, int]]:
+        """ギャップマップ（``10-12|40-40``）を解析"""
+        ranges = []
+        for item in filter(None, value.split("|")):
+            start, end = item.split("-", 1)
+            ranges.append((int(start), int(end)))
+        return ranges
+
+    @staticmethod
+    def parse_frame_abort(payload: bytes) -> FrameAbortInfo:
+        """転送中断イベントのペイロードを解析
+
+        形式: ``FRAME_ID:<id>,BYTES:<n>,REASON:<DEVICE|QUEUE_OVERFLOW|CHECKSUM_STORM>``
+        """
+        try:
+            fields = dict(
+                item.split(":", 1) for item in bytes(payload).decode("ascii").split(",")
+            )
+            return FrameAbortInfo(
+                frame_id=int(fields["FRAME_ID"]),
+                byte_count=int(fields["BYTES"]),
+                reason=fields["REASON"],
+            )
+        except (UnicodeDecodeError, KeyError, ValueError) as e:
+            raise ValueError(f"Invalid frame abort payload: {bytes(payload)!r}") from e
+
+    @staticmethod
+    def parse_stats(payload: bytes) -> Dict[str, str]:
+        """ゲートウェイ統計フレームのペイロードを解析
+
+        形式: ``KEY:値,KEY:値,...``（値の解釈は項目ごとに呼び出し側で行う）
+        """
+        try:
+            text = bytes(payload).decode("ascii")
+        except UnicodeDecodeError as e:
+            raise ValueError("Invalid stats payload: not ASCII") from e
+        fields = {}
+        for item in filter(None, text.split(",")):
+            key, separator, value = item.partition(":")
+            if not separator:
+                raise ValueError(f"Invalid stats field: {item!r}")
+            fields[key] = value
+        return fields
+
+    @staticmethod
+    def parse_fleet_summary(payload: bytes) -> Dict[str, object]:
+        """ゲートウェイの定期サマリー（CBOR）を解析
+
+        形式: ``{"v": 1, "period_s": 秒, "devices": [{"mac", "name", "frames", "aborts",
+        "success_pct", "rssi", "battery", "last_seen_s", "last_seen_unix_ms", "pending", "key_epoch",
+        "key_rotation", "skew_ms", "drift_ppm", "channel", "fw", "rollout", "frame_types", "retry_pct",
+        "group"}, ...],
+        "groups": [{"group", "devices", "frames", "aborts", "success_pct", "battery_min"}, ...],
+        "rollout": [{"channel", "fw", "targeted", "updated"}, ...],
+        "frame_types": {"HASH": [受信, 転送, エラー], ...}}``
+        """
+        try:
+            summary = cbor.decode(payload)
+        except (ValueError, UnicodeDecodeError) as e:
+            raise ValueError(f"Invalid fleet summary payload: {e}") from e
+        if not isinstance(summary, dict) or summary.get("v") != 1:
+            raise ValueError(f"Unsupported fleet summary: {summary!r}")
+        if not isinstance(summary.get("devices"), list):
+            raise ValueError("Fleet summary has no device list")
+        return summary
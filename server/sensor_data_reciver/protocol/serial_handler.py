@@ -6,7 +6,7 @@ import time
 from typing import Dict
 
 from .constants import (
-    START_MARKER, END_MARKER, FRAME_TYPE_HASH, FRAME_TYPE_DATA, FRAME_TYPE_EOF,
+    START_MARKER, END_MARKER, FRAME_TYPE_HASH, FRAME_TYPE_DATA, FRAME_TYPE_EOF, FRAME_TYPE_FEC, FRAME_TYPE_COMPLETE, FRAME_TYPE_STATS, FRAME_TYPE_ABORT, FRAME_TYPE_FLEET_SUMMARY, FRAME_TYPE_URGENT,
     MAC_ADDRESS_LENGTH, FRAME_TYPE_LENGTH, SEQUENCE_NUM_LENGTH, LENGTH_FIELD_BYTES,
     CHECKSUM_LENGTH
 )
@@ -365,6 +365,24 @@ class SerialProtocol(asyncio.Protocol):
                 elif frame_type == FRAME_TYPE_DATA:
                     frame_type_str = "DATA"
                     self._process_data_frame(sender_mac, chunk_data, seq_num)
+                elif frame_type == FRAME_TYPE_FEC:
+                    # FEC復元はストリーミングハンドラーのみ対応。冗長データなので破棄する
+                    frame_type_str = "FEC"
+                elif frame_type == FRAME_TYPE_COMPLETE:
+                    frame_type_str = "COMPLETE"
+                    self._process_complete_frame(sender_mac, chunk_data, seq_num)
+                elif frame_type == FRAME_TYPE_STATS:
+                    frame_type_str = "STATS"
+                    logger.info(f"Gateway stats: {chunk_data.decode('ascii', errors='replace')}")
+                elif frame_type == FRAME_TYPE_ABORT:
+                    frame_type_str = "ABORT"
+                    logger.warning(f"Transfer aborted for {sender_mac}: {chunk_data.decode('ascii', errors='replace')}")
+                elif frame_type == FRAME_TYPE_FLEET_SUMMARY:
+                    frame_type_str = "FLEET_SUMMARY"
+                    logger.info(f"Gateway fleet summary: {chunk_data.hex()}")
+                elif frame_type == FRAME_TYPE_URGENT:
+                    frame_type_str = "URGENT"
+                    logger.warning(f"Urgent alert from {sender_mac}: {chunk_data.decode('ascii', errors='replace')}")
                 else:
                     logger.warning(f"Unknown frame type {frame_type} from {sender_mac} (seq={seq_num}, data_len={data_len}, data_preview={chunk_data[:20].hex() if chunk_data else 'empty'})")
 
@@ -523,6 +541,19 @@ class SerialProtocol(asyncio.Protocol):
         else:
             logger.warning(f"No transport available to send sleep command for {sender_mac}")
 
+    def _process_complete_frame(self, sender_mac: str, chunk_data: bytes, seq_num: int):
+        """転送完了フレームの処理（ゲートウェイが集約したHASH+EOF）"""
+        try:
+            info = FrameParser.parse_frame_complete(chunk_data)
+        except ValueError as e:
+            logger.warning(f"Invalid COMPLETE frame from {sender_mac}: {e}")
+            return
+
+        if info.hash_payload is not None:
+            self._process_hash_frame(sender_mac, info.hash_payload, seq_num)
+        if info.eof_received:
+            self._process_eof_frame(sender_mac, seq_num)
+
     def _process_eof_frame(self, sender_mac: str, seq_num: int | None):
         """EOF フレームの処理"""
         current_time = time.time()
@@ -8,6 +8,7 @@ integrates with StreamingImageProcessor for real-time image processing.
 import asyncio
 import logging
 import time
+from datetime import datetime, timezone
 from typing import Dict
 
 from .constants import (
@@ -16,6 +17,12 @@ from .constants import (
     FRAME_TYPE_HASH,
     FRAME_TYPE_DATA,
     FRAME_TYPE_EOF,
+    FRAME_TYPE_FEC,
+    FRAME_TYPE_COMPLETE,
+    FRAME_TYPE_STATS,
+    FRAME_TYPE_ABORT,
+    FRAME_TYPE_FLEET_SUMMARY,
+    FRAME_TYPE_URGENT,
     MAC_ADDRESS_LENGTH,
     FRAME_TYPE_LENGTH,
     SEQUENCE_NUM_LENGTH,
@@ -25,7 +32,9 @@ from .constants import (
     FOOTER_LENGTH,
 )
 from .cycle_tracker import CycleTracker
+from .fec import FecReassembler, ParityChunk, parse_announce, FEC_KIND_ANNOUNCE
 from .frame_parser import FrameParser
+from .telemetry_reassembler import TelemetryReassembler
 
 # 絶対インポートを使用
 import sys
@@ -33,18 +42,55 @@ import os
 
 sys.path.append(os.path.dirname(os.path.dirname(os.path.abspath(__file__))))
 
-from processors.streaming_image_processor import StreamingImageProcessor
+from processors.streaming_image_processor import CORRUPT_SUFFIX, StreamingImageProcessor
+from processors.post_processing import load_pipeline
 from processors.voltage_processor import VoltageDataProcessor
 from processors.sleep_controller import (
     determine_sleep_duration,
+    format_resend_command_to_gateway,
     format_sleep_command_to_gateway,
 )
 from storage import influx_client
+from storage.image_index import open_index
 from utils.data_parser import DataParser
+from utils.tracing import PipelineTracer, build_pipeline_spans, parse_trace_id
 from config import config
 
 logger = logging.getLogger(__name__)
 
+# 異常による再起動とみなす起動理由（パニック・ウォッチドッグ・電圧低下）
+ABNORMAL_BOOT_REASONS = {"PANIC", "INT_WDT", "TASK_WDT", "WDT", "BROWNOUT"}
+
+# 時計のずれの外れ値とみなす閾値（フリートの中央値からのずれ・変化率。ゲートウェイの clock_skew と同じ）
+CLOCK_SKEW_WARN_MS = 2000
+CLOCK_DRIFT_WARN_PPM = 100
+
+
+def _format_lost_frames(frame_types) -> str:
+    """FLEET_SUMMARYの `frame_types`（`[受信, 転送, エラー]`）から失われたフレームのあるタイプを列挙
+
+    例: ``"EOF:3/40,DATA:2/1200"``（エラー数/受信数）。失われたフレームがなければ空文字列。
+    """
+    lost = []
+    for frame_type, counts in (frame_types or {}).items():
+        if isinstance(counts, list) and len(counts) == 3 and counts[2]:
+            lost.append(f"{frame_type}:{counts[2]}/{counts[0]}")
+    return ",".join(lost)
+
+
+def _format_retry_by_busy(retry_by_busy) -> str:
+    """FLEET_SUMMARYの `radio` の `retry_by_busy`（ビジー率25%ごとの `[受信, 再送]`）を再送率で列挙
+
+    例: ``"0-24%:1%,25-49%:3%,50-74%:12%"``（受信がない区分は省略）。
+    """
+    parts = []
+    for index, counts in enumerate(retry_by_busy or []):
+        if isinstance(counts, list) and len(counts) == 2 and counts[0]:
+            low = index * 25
+            high = 100 if index == 3 else low + 24
+            parts.append(f"{low}-{high}%:{counts[1] * 100 // counts[0]}%")
+    return ",".join(parts)
+
 
 class StreamingSerialProtocol(asyncio.Protocol):
     """
@@ -62,7 +108,11 @@ class StreamingSerialProtocol(asyncio.Protocol):
         self.frame_start_time = None
 
         # ストリーミング画像プロセッサー
-        self.streaming_processor = StreamingImageProcessor(max_concurrent_streams=5)
+        self.streaming_processor = StreamingImageProcessor(
+            max_concurrent_streams=5,
+            post_processing=load_pipeline(config.POST_PROCESSING_PIPELINE, config.IMAGE_DIR),
+            image_index=open_index(config.IMAGE_INDEX_DB),
+        )
 
         # 統計情報（下位互換性のため保持）
         self.stats = stats
@@ -82,12 +132,34 @@ class StreamingSerialProtocol(asyncio.Protocol):
         # EOF処理済みフラグ（重複EOF処理を防止）
         self.eof_processed = {}  # {sender_mac: timestamp}
 
+        # デバイスがコマンド待機中に保持している送信済み画像（HASHの RETAIN_FRAME）と再送要求の回数
+        self.retained_frames = {}  # {sender_mac: frame_id}
+        # プロビジョニングしたデバイスIDごとの最新のMACアドレス（基板交換の検出用）
+        self.device_macs = {}  # {device_id: sender_mac}
+        self.resend_counts = {}  # {sender_mac: count}
+        # 転送中のサムネイル（HASHの THUMB）。スリープコマンドは撮影した画像のEOFで送信済み
+        self.thumbnail_transfers = {}  # {sender_mac: thumbnail}
+
         # 最後のデータフレーム受信時間
         self.last_data_frame_time = {}  # {sender_mac: timestamp}
 
+        # FECセッション（告知フレーム受信時に作成、HASH/EOFで払い出し）
+        self.fec_reassemblers = {}  # {sender_mac: FecReassembler}
+
         # sender単位のサイクル状態トラッカー
         self.cycle_tracker = CycleTracker()
 
+        # 差分エンコードされたテレメトリの復元（送信元ごとに最新のフルスナップショットを保持）
+        self.telemetry_reassembler = TelemetryReassembler()
+
+        # 直近の統計フレームのゲートウェイの起動回数と稼働時間（再起動の検知用）
+        self.gateway_boot = None  # (boots, uptime_s)
+
+        # パイプラインのトレース（OTLPエンドポイント設定時のみエクスポート）
+        self.pipeline_tracer = PipelineTracer()
+        self.storage_spans = {}  # {sender_mac: (start, end)}
+        self.pending_gap_maps = {}  # {sender_mac: [(start, end), ...]} 欠落のある転送の保存時に使用
+
         # タイムアウトチェックタスク
         self.timeout_check_task = None
 
@@ -308,6 +380,7 @@ class StreamingSerialProtocol(asyncio.Protocol):
             )
 
         if frame_type == FRAME_TYPE_HASH:
+            await self._flush_fec(sender_mac)
             await self._process_streaming_hash_frame(sender_mac, chunk_data, seq_num)
 
         elif frame_type == FRAME_TYPE_DATA:
@@ -315,8 +388,27 @@ class StreamingSerialProtocol(asyncio.Protocol):
 
         elif frame_type == FRAME_TYPE_EOF:
             logger.info(f"Received EOF frame for {sender_mac}")
+            await self._flush_fec(sender_mac, end_session=True)
             await self._process_streaming_eof_frame(sender_mac, seq_num)
 
+        elif frame_type == FRAME_TYPE_FEC:
+            await self._process_streaming_fec_frame(sender_mac, chunk_data, seq_num)
+
+        elif frame_type == FRAME_TYPE_COMPLETE:
+            await self._process_streaming_complete_frame(sender_mac, chunk_data, seq_num)
+
+        elif frame_type == FRAME_TYPE_STATS:
+            self._process_stats_frame(chunk_data, seq_num)
+
+        elif frame_type == FRAME_TYPE_ABORT:
+            await self._process_streaming_abort_frame(sender_mac, chunk_data)
+
+        elif frame_type == FRAME_TYPE_FLEET_SUMMARY:
+            self._process_fleet_summary_frame(chunk_data, seq_num)
+
+        elif frame_type == FRAME_TYPE_URGENT:
+            self._process_urgent_frame(sender_mac, chunk_data)
+
         else:
             logger.warning(f"Unknown frame type {frame_type} from {sender_mac}")
 
@@ -328,6 +420,8 @@ class StreamingSerialProtocol(asyncio.Protocol):
             logger.warning(f"Could not decode HASH payload from {sender_mac}")
             return
 
+        # 差分（DELTA）は保持しているスナップショットで変わらなかった項目を補う
+        payload_str = self.telemetry_reassembler.reassemble(sender_mac, payload_str)
         payload_split = payload_str.split(",")
 
         if len(payload_split) < 2:
@@ -362,6 +456,141 @@ class StreamingSerialProtocol(asyncio.Protocol):
         else:
             logger.debug(f"No TDS voltage data for {sender_mac}")
 
+        # 画像品質メタデータ・撮影しなかった理由（電圧不足・カメラ故障・閾値外の画質では画像なしで理由のみ届く）
+        luma = DataParser.extract_value_from_payload(payload_str, "LUMA:")
+        sharpness = DataParser.extract_value_from_payload(payload_str, "SHARP:")
+        skip_reason = DataParser.extract_value_from_payload(payload_str, "SKIP:")
+        if luma is not None or sharpness is not None:
+            logger.info(f"Image quality from {sender_mac}: luma={luma}, sharpness={sharpness}")
+        if skip_reason is not None:
+            logger.warning(f"Image transmission skipped by {sender_mac}: reason={skip_reason}")
+            await self.streaming_processor.record_skipped_capture(sender_mac, payload_str)
+
+        # リモート設定の試行が確定されず、前回の設定へ戻った
+        if DataParser.extract_value_from_payload(payload_str, "CONFIG_ROLLBACK:") is not None:
+            logger.warning(f"Remote config rolled back on {sender_mac}")
+
+        # 起動時にNVSを消去して自己修復した（PARTITION / CONFIG_CORRUPT / CONFIG_FULL）
+        nvs_recovered = DataParser.extract_value_from_payload(payload_str, "NVS_RECOVERED:")
+        if nvs_recovered is not None:
+            logger.warning(f"{sender_mac} recovered its NVS at boot: {nvs_recovered}")
+
+        # 撮影時刻の壁時計境界からの誤差（整列有効時のみ）
+        align_error_us = DataParser.extract_value_from_payload(payload_str, "ALIGN_ERR_US:")
+        if align_error_us is not None:
+            logger.info(f"Capture alignment error for {sender_mac}: {align_error_us}us")
+
+        # 転送前の疎通確認（RTT・ゲートウェイのキュー空き率・応答なしで見送ったサイクル数）
+        probe_rtt_ms = DataParser.extract_value_from_payload(payload_str, "PROBE_RTT_MS:")
+        if probe_rtt_ms is not None:
+            queue_free = DataParser.extract_value_from_payload(payload_str, "GW_QUEUE_FREE:")
+            logger.debug(f"Gateway probe for {sender_mac}: rtt={probe_rtt_ms}ms queue_free={queue_free}%")
+        probe_skipped = DataParser.extract_value_from_payload(payload_str, "PROBE_SKIPPED:")
+        if probe_skipped is not None:
+            logger.warning(f"{sender_mac} skipped {probe_skipped} transfer(s) because the gateway did not answer")
+        if DataParser.extract_value_from_payload(payload_str, "RADIO_MISMATCH:") is not None:
+            logger.warning(f"{sender_mac} uses different ESP-NOW radio settings (rate/AMPDU) than the gateway")
+        # チャンネルホッピングの予定のチャンネルで届かなかった（HOP_CH は実際に届いたチャンネル）
+        hop_miss = DataParser.extract_value_from_payload(payload_str, "HOP_MISS:")
+        if hop_miss is not None:
+            hop_channel = DataParser.extract_value_from_payload(payload_str, "HOP_CH:")
+            logger.info(f"{sender_mac} missed the gateway on scheduled channel {hop_miss} (reached on {hop_channel or '-'})")
+
+        # 前回のスリープコマンドが許容範囲外でデバイス側で補正された（要求値）
+        sleep_clamped = DataParser.extract_value_from_payload(payload_str, "SLEEP_CLAMPED:")
+        if sleep_clamped is not None:
+            logger.warning(f"{sender_mac} clamped an out-of-range sleep command ({sleep_clamped}s requested)")
+
+        # 前回の転送後、ゲートウェイに送信待ちのコマンドがなくコマンド待機を省略した（短縮した待機時間）
+        window_saved = DataParser.extract_value_from_payload(payload_str, "WINDOW_SAVED_MS:")
+        if window_saved is not None:
+            logger.info(f"{sender_mac} skipped the command window after the previous transfer (saved {window_saved}ms awake)")
+
+        # 制御メッセージの拒否（リプレイ・署名不正）はセキュリティ警告として扱う
+        replay_rejects = DataParser.extract_value_from_payload(payload_str, "SEC_REPLAY:")
+        bad_signature_rejects = DataParser.extract_value_from_payload(payload_str, "SEC_BAD_SIG:")
+        if replay_rejects is not None or bad_signature_rejects is not None:
+            logger.warning(
+                f"SECURITY: {sender_mac} rejected downlink commands "
+                f"(replay={replay_rejects or 0}, bad_signature={bad_signature_rejects or 0})"
+            )
+
+        # カメラフレームバッファの確保失敗（失敗時点のヒープ状態付き）
+        fb_failure_stage = DataParser.extract_value_from_payload(payload_str, "FB_FAIL:")
+        if fb_failure_stage is not None:
+            heap_stats = {
+                key: DataParser.extract_value_from_payload(payload_str, f"{key}:")
+                for key in ("FB_POLICY", "HEAP_FREE", "HEAP_LARGEST", "PSRAM_FREE", "PSRAM_LARGEST")
+            }
+            logger.warning(
+                f"Frame buffer failure on {sender_mac}: stage={fb_failure_stage}, {heap_stats}"
+            )
+
+        # SCCBエラーからのカメラ再初期化（再初期化したサイクルのみ）
+        camera_reinit = DataParser.extract_value_from_payload(payload_str, "CAM_REINIT:")
+        if camera_reinit is not None:
+            recovery = {
+                key: DataParser.extract_value_from_payload(payload_str, f"{key}:")
+                for key in ("CAM_PWDN", "CAM_RECOVERED", "CAM_ERR")
+            }
+            logger.warning(
+                f"Camera re-initialized after SCCB error on {sender_mac}: attempts={camera_reinit}, {recovery}"
+            )
+
+        # カメラの劣化（撮影の連続失敗回数が設定値に達した）。取得時間の推移と共に警告する
+        if DataParser.extract_value_from_payload(payload_str, "CAM_DEGRADED:") == "1":
+            health = {
+                key: DataParser.extract_value_from_payload(payload_str, f"{key}:")
+                for key in ("CAM_FAIL_STREAK", "CAM_FB_MS", "CAM_FB_AVG_MS")
+            }
+            logger.warning(f"CameraDegraded on {sender_mac}: {health}")
+
+        # 起動理由（起動後最初のHASHのみ）。異常再起動は警告する
+        lifecycle = DataParser.parse_lifecycle(payload_str)
+        if lifecycle is not None:
+            if lifecycle["boot_reason"] in ABNORMAL_BOOT_REASONS or lifecycle["panic"]:
+                logger.warning(f"{sender_mac} rebooted abnormally: {lifecycle}")
+            else:
+                logger.info(f"Lifecycle of {sender_mac}: {lifecycle}")
+
+        # ファームウェアのビルド情報と設定ハッシュ（起動後最初のHASHのみ）
+        build_info = DataParser.parse_build_info(payload_str)
+        if build_info is not None:
+            logger.info(f"Firmware of {sender_mac}: {build_info}")
+
+        # プロビジョニングしたデバイスID（MACアドレスは基板を交換すると変わる）
+        device_id = DataParser.extract_value_from_payload(payload_str, "DEV_ID:")
+        if device_id is not None:
+            previous_mac = self.device_macs.get(device_id)
+            if previous_mac is not None and previous_mac != sender_mac:
+                logger.warning(f"Device {device_id} now reports MAC {sender_mac} (was {previous_mac})")
+            self.device_macs[device_id] = sender_mac
+
+        # 送信済み画像の保持（ハッシュが一致しない場合に再送を要求できる）
+        retained_frame = DataParser.extract_value_from_payload(payload_str, "RETAIN_FRAME:")
+        if retained_frame is not None:
+            self.retained_frames[sender_mac] = retained_frame
+            logger.debug(
+                f"{sender_mac} retains frame {retained_frame} "
+                f"({DataParser.extract_value_from_payload(payload_str, 'RETAIN_BYTES:')} bytes)"
+            )
+        else:
+            self.retained_frames.pop(sender_mac, None)
+        resend = DataParser.extract_value_from_payload(payload_str, "RESEND:")
+        if resend is not None:
+            logger.info(f"Received retransmission #{resend} of frame {retained_frame} from {sender_mac}")
+        thumbnail = DataParser.parse_thumbnail(payload_str)
+        if thumbnail is not None:
+            self.thumbnail_transfers[sender_mac] = thumbnail
+            # 続けて届くサムネイルのEOFを重複として捨てない
+            self.eof_processed.pop(sender_mac, None)
+            logger.info(
+                f"Receiving thumbnail {thumbnail['index']}/{thumbnail['count']} (seq {thumbnail['seq']}) "
+                f"from {sender_mac}"
+            )
+        else:
+            self.thumbnail_transfers.pop(sender_mac, None)
+
         # 電圧情報をキャッシュ
         self.voltage_cache[sender_mac] = voltage
 
@@ -384,7 +613,7 @@ class StreamingSerialProtocol(asyncio.Protocol):
         # InfluxDBに書き込み
         try:
             influx_client.write_sensor_data(
-                sender_mac, voltage, temperature, tds_voltage
+                sender_mac, voltage, temperature, tds_voltage, device_id
             )
             logger.info(f"Initiated InfluxDB write for {sender_mac}")
         except Exception as e:
@@ -487,6 +716,18 @@ class StreamingSerialProtocol(asyncio.Protocol):
                         f"Failed to unpack nested frame, treating as raw data: {e}"
                     )
 
+        reassembler = self.fec_reassemblers.get(sender_mac)
+        if reassembler is not None:
+            for released_seq, released_chunk in reassembler.add_data(seq_num, chunk_data):
+                await self._forward_data_chunk(sender_mac, released_chunk, released_seq)
+            return
+
+        await self._forward_data_chunk(sender_mac, chunk_data, seq_num)
+
+    async def _forward_data_chunk(
+        self, sender_mac: str, chunk_data: bytes, seq_num: int
+    ):
+        """データチャンクをストリーミングプロセッサーへ渡す"""
         # ストリーミングプロセッサーでチャンク処理
         success = await self.streaming_processor.process_chunk(
             sender_mac, chunk_data, seq_num, callback=self._chunk_processed_callback
@@ -503,6 +744,275 @@ class StreamingSerialProtocol(asyncio.Protocol):
         else:
             logger.warning(f"Failed to process chunk for {sender_mac}")
 
+    async def _process_streaming_fec_frame(
+        self, sender_mac: str, chunk_data: bytes, seq_num: int
+    ):
+        """FECフレーム処理（告知でセッション開始、パリティで欠落復元）"""
+        if not chunk_data:
+            logger.warning(f"Empty FEC frame from {sender_mac}")
+            return
+
+        if chunk_data[0] == FEC_KIND_ANNOUNCE:
+            params = parse_announce(chunk_data)
+            if params is None:
+                logger.warning(f"Invalid FEC announce from {sender_mac}: {chunk_data.hex()}")
+                return
+            # 送信リトライ等で再告知された場合は保持中のチャンクを払い出してから切り替える
+            await self._flush_fec(sender_mac, end_session=True)
+            data_chunks, parity_chunks = params
+            self.fec_reassemblers[sender_mac] = FecReassembler(
+                data_chunks, parity_chunks, seq_num + 1
+            )
+            logger.info(
+                f"FEC session started for {sender_mac}: K={data_chunks}, M={parity_chunks}"
+            )
+            return
+
+        reassembler = self.fec_reassemblers.get(sender_mac)
+        if reassembler is None:
+            logger.debug(f"FEC parity without session from {sender_mac}, ignoring")
+            return
+
+        try:
+            parity = ParityChunk.from_payload(chunk_data)
+        except ValueError as e:
+            logger.warning(f"Invalid FEC parity from {sender_mac}: {e}")
+            return
+
+        for released_seq, released_chunk in reassembler.add_parity(parity):
+            await self._forward_data_chunk(sender_mac, released_chunk, released_seq)
+
+    async def _flush_fec(self, sender_mac: str, end_session: bool = False):
+        """FECセッションで保持中のチャンクを払い出す"""
+        reassembler = self.fec_reassemblers.get(sender_mac)
+        if reassembler is None:
+            return
+
+        for released_seq, released_chunk in reassembler.flush():
+            await self._forward_data_chunk(sender_mac, released_chunk, released_seq)
+
+        if end_session:
+            if reassembler.recovered_count:
+                logger.info(
+                    f"FEC recovered {reassembler.recovered_count} chunk(s) for {sender_mac}"
+                )
+            del self.fec_reassemblers[sender_mac]
+
+    async def _process_streaming_complete_frame(
+        self, sender_mac: str, chunk_data: bytes, seq_num: int
+    ):
+        """転送完了フレーム処理（ゲートウェイが集約したHASH+EOF）"""
+        received_at = time.time()
+        try:
+            info = FrameParser.parse_frame_complete(chunk_data)
+        except ValueError as e:
+            logger.warning(f"Invalid COMPLETE frame from {sender_mac}: {e}")
+            return
+
+        logger.info(
+            f"Received COMPLETE frame from {sender_mac}: frame_id={info.frame_id}, "
+            f"bytes={info.byte_count}, dedupe={info.dedupe_count}, eof={info.eof_received}"
+            + (f", e2e={info.e2e_ms}ms" if info.e2e_ms is not None else "")
+        )
+
+        # ゲートウェイが範囲外と判定したテレメトリ（センサーの故障・配線の不良の疑い）
+        if info.data_quality:
+            logger.warning(
+                f"DataQuality: {sender_mac} reported implausible {', '.join(info.data_quality)} "
+                f"(frame_id={info.frame_id})"
+            )
+
+        if info.gaps or info.partial:
+            missing = sum(end - start + 1 for start, end in info.gaps)
+            logger.warning(
+                f"Incomplete transfer from {sender_mac}: frame_id={info.frame_id}, "
+                f"missing {missing} sequence(s) {info.gaps}, eof={info.eof_received}"
+            )
+            self.pending_gap_maps[sender_mac] = info.gaps
+
+        if info.hash_payload is not None:
+            await self._flush_fec(sender_mac)
+            await self._process_streaming_hash_frame(sender_mac, info.hash_payload, seq_num)
+
+        # 救済された転送はEOFがなくても受信済みの分で画像を保存する
+        if info.eof_received or info.partial:
+            await self._flush_fec(sender_mac, end_session=True)
+            await self._process_streaming_eof_frame(sender_mac, seq_num)
+        self.pending_gap_maps.pop(sender_mac, None)
+
+        self._export_pipeline_trace(sender_mac, info, received_at)
+
+    def _export_pipeline_trace(self, sender_mac: str, info, received_at: float):
+        """完了イベントとHASHのトレースIDからパイプラインのスパンをエクスポート"""
+        storage = self.storage_spans.pop(sender_mac, None)
+        if not self.pipeline_tracer.enabled or info.hash_payload is None:
+            return
+        payload_str = info.hash_payload.decode("ascii", errors="replace")
+        trace_id = parse_trace_id(payload_str)
+        if trace_id is None:
+            return
+        capture_ms = DataParser.extract_value_from_payload(payload_str, "CAP_MS:")
+        spans = build_pipeline_spans(
+            received_at,
+            info.air_ms,
+            info.usb_ms,
+            int(capture_ms) if capture_ms and capture_ms.isdigit() else None,
+            storage,
+        )
+        self.pipeline_tracer.export(trace_id, sender_mac, spans)
+
+    async def _process_streaming_abort_frame(self, sender_mac: str, chunk_data: bytes):
+        """転送中断フレーム処理（受信途中の画像とFECセッションを破棄）"""
+        try:
+            info = FrameParser.parse_frame_abort(chunk_data)
+        except ValueError as e:
+            logger.warning(f"Invalid ABORT frame from {sender_mac}: {e}")
+            return
+
+        logger.warning(
+            f"Transfer aborted for {sender_mac}: frame_id={info.frame_id}, "
+            f"bytes={info.byte_count}, reason={info.reason}"
+        )
+        # 中断された転送のチャンクは復元にも使わない
+        self.fec_reassemblers.pop(sender_mac, None)
+        self.last_data_frame_time.pop(sender_mac, None)
+        if sender_mac in self.streaming_processor.active_streams:
+            await self.streaming_processor.abort_stream(sender_mac, f"gateway abort ({info.reason})")
+
+    def _process_urgent_frame(self, sender_mac: str, chunk_data: bytes):
+        """緊急フレーム処理
+
+        画像転送の途中に割り込んで届くため、受信途中のストリームやFECセッションには触れない。
+        """
+        logger.warning(f"Urgent alert from {sender_mac}: {chunk_data.decode('ascii', errors='replace')}")
+
+    def _process_stats_frame(self, chunk_data: bytes, seq_num: int):
+        """ゲートウェイ統計フレーム処理"""
+        try:
+            stats = FrameParser.parse_stats(chunk_data)
+        except ValueError as e:
+            logger.warning(f"Invalid STATS frame from gateway: {e}")
+            return
+
+        summary = ", ".join(f"{key}={value}" for key, value in stats.items())
+        if "CPU_WARN" in stats:
+            # ゲートウェイのCPU使用率が閾値を超え続けている（中継がボトルネックになる前兆）
+            logger.warning(
+                f"Gateway CPU usage high: {stats['CPU_WARN']}% "
+                f"(limit {stats.get('CPU_LIMIT', '?')}%), tasks: {stats.get('CPU_TASKS', '')}"
+            )
+            return
+        if "MEM_TREND" in stats:
+            # ゲートウェイの空きヒープが減り続けている（長時間稼働での停止の前兆）
+            logger.warning(
+                f"Gateway heap trending towards exhaustion in {stats['MEM_TREND']}s "
+                f"({stats.get('MEM_LIMIT', '?')}): free={stats.get('HEAP_FREE', '?')} "
+                f"({stats.get('HEAP_SLOPE', '?')} B/h), largest block={stats.get('HEAP_LARGEST', '?')} "
+                f"({stats.get('LARGEST_SLOPE', '?')} B/h)"
+            )
+            return
+        if "RESETS" in stats:
+            logger.warning(f"Devices rebooted abnormally since last stats: {stats['RESETS']}")
+        self._check_gateway_reboot(stats)
+        logger.info(f"Gateway stats (seq: {seq_num}): {summary}")
+
+    def _check_gateway_reboot(self, stats: Dict[str, str]) -> bool:
+        """起動回数の増加や稼働時間の減少からゲートウェイの再起動を検知（検知したらTrue）"""
+        try:
+            boots = int(stats["BOOTS"])
+            uptime_s = int(stats["UPTIME_S"])
+        except (KeyError, ValueError):
+            return False
+        previous = self.gateway_boot
+        self.gateway_boot = (boots, uptime_s)
+        if previous is None or (boots == previous[0] and uptime_s >= previous[1]):
+            return False
+        logger.warning(
+            f"Gateway rebooted: boot #{previous[0]} -> #{boots}, uptime {previous[1]}s -> {uptime_s}s, "
+            f"reset reason: {stats.get('RESET_REASON', '?')}"
+        )
+        return True
+
+    def _process_fleet_summary_frame(self, chunk_data: bytes, seq_num: int):
+        """ゲートウェイの定期サマリー処理（デバイスごとに1行、受信がなかったデバイスは警告）"""
+        try:
+            summary = FrameParser.parse_fleet_summary(chunk_data)
+        except ValueError as e:
+            logger.warning(f"Invalid FLEET_SUMMARY frame from gateway: {e}")
+            return
+
+        devices = summary["devices"]
+        logger.info(
+            f"Fleet summary (seq: {seq_num}): {len(devices)} devices over {summary.get('period_s')}s"
+        )
+        for device in devices:
+            line = (
+                f"  {device.get('name') or '-'} ({device.get('mac')}): frames={device.get('frames')}, "
+                f"aborts={device.get('aborts')}, success={device.get('success_pct')}%, "
+                f"rssi={device.get('rssi')}dBm, battery={device.get('battery')}%, "
+                f"last_seen={device.get('last_seen_s')}s ago, pending={device.get('pending')}, "
+                f"key_epoch={device.get('key_epoch')}"
+            )
+            if device.get("key_rotation"):
+                line += f" (key rotation {device.get('key_rotation')})"
+            if device.get("skips"):
+                skips = ", ".join(f"{reason}={count}" for reason, count in device["skips"].items())
+                line += f", skips=({skips})"
+            if device.get("channel") or device.get("fw"):
+                line += f", channel={device.get('channel')}, fw={device.get('fw') or '-'}"
+                if device.get("rollout"):
+                    line += f" (rollout {device.get('rollout')})"
+            # ゲートウェイの時計がSNTPで同期済みの場合のみ
+            if device.get("last_seen_unix_ms") is not None:
+                last_seen_at = datetime.fromtimestamp(device["last_seen_unix_ms"] / 1000, tz=timezone.utc)
+                line += f", last_seen_at={last_seen_at.isoformat(timespec='seconds')}"
+            clock_outlier = False
+            if device.get("skew_ms") is not None:
+                line += f", clock_skew={device.get('skew_ms')}ms"
+                clock_outlier = abs(device["skew_ms"]) > CLOCK_SKEW_WARN_MS
+                if device.get("drift_ppm") is not None:
+                    line += f" (drift {device.get('drift_ppm')}ppm)"
+                    clock_outlier = clock_outlier or abs(device["drift_ppm"]) > CLOCK_DRIFT_WARN_PPM
+            if clock_outlier:
+                line += " [clock outlier]"
+            lost = _format_lost_frames(device.get("frame_types"))
+            if lost:
+                line += f", lost_frames={lost}"
+            if device.get("retry_pct") is not None:
+                line += f", retry={device.get('retry_pct')}%"
+            if device.get("group"):
+                line += f", group={device.get('group')}"
+            if not device.get("frames") or clock_outlier:
+                logger.warning(line)
+            else:
+                logger.info(line)
+        # GROUP_ADDで登録したグループごと
+        for group in summary.get("groups") or []:
+            logger.info(
+                f"  Group {group.get('group')}: {group.get('devices')} devices, frames={group.get('frames')}, "
+                f"aborts={group.get('aborts')}, success={group.get('success_pct')}%, "
+                f"battery_min={group.get('battery_min')}%"
+            )
+        for rollout in summary.get("rollout") or []:
+            logger.info(
+                f"  Rollout {rollout.get('channel')}: fw={rollout.get('fw')}, "
+                f"updated {rollout.get('updated')}/{rollout.get('targeted')} devices"
+            )
+        # `channel-monitor` フィーチャーのゲートウェイのみ
+        for radio in summary.get("radio") or []:
+            line = (
+                f"  Channel {radio.get('channel')}: busy={radio.get('busy_pct')}% "
+                f"(max {radio.get('busy_max_pct')}%), foreign={radio.get('foreign_pct')}%, "
+                f"samples={radio.get('samples')}"
+            )
+            retries = _format_retry_by_busy(radio.get("retry_by_busy"))
+            if retries:
+                line += f", retry by busy=({retries})"
+            logger.info(line)
+        lost = _format_lost_frames(summary.get("frame_types"))
+        if lost:
+            logger.warning(f"  Gateway lost frames since boot: {lost}")
+
     async def _process_streaming_eof_frame(
         self, sender_mac: str, seq_num: int | None
     ):
@@ -530,6 +1040,7 @@ class StreamingSerialProtocol(asyncio.Protocol):
             self.eof_processed[sender_mac] = current_time
 
             has_image = self.has_image_data_cache.get(sender_mac, True)
+            final_path = None
 
             if not has_image:
                 # 画像データなし (温度センサー等): 保存はスキップするが、
@@ -549,9 +1060,11 @@ class StreamingSerialProtocol(asyncio.Protocol):
                 await self.streaming_processor.abort_stream(sender_mac, "DRY_RUN mode")
             else:
                 # ストリーミング画像を完成・保存
+                storage_started = time.time()
                 final_path = await self.streaming_processor.finalize_image_stream(
-                    sender_mac, self.stats
+                    sender_mac, self.stats, gaps=self.pending_gap_maps.get(sender_mac)
                 )
+                self.storage_spans[sender_mac] = (storage_started, time.time())
 
                 if final_path:
                     # 統計更新
@@ -560,11 +1073,53 @@ class StreamingSerialProtocol(asyncio.Protocol):
                 else:
                     logger.error(f"Failed to finalize streaming image for {sender_mac}")
 
-            # EOFフレーム処理後にスリープコマンド送信
-            await self._send_sleep_command_after_eof(sender_mac)
+            # サムネイルはスリープコマンドを送った後の転送のため、再送もスリープコマンドも要求しない
+            is_thumbnail = self.thumbnail_transfers.pop(sender_mac, None) is not None
+            # ハッシュが一致せず画像が保持されていれば、スリープさせずに再送を要求する
+            if not is_thumbnail and not await self._request_resend_if_corrupt(sender_mac, final_path):
+                # EOFフレーム処理後にスリープコマンド送信
+                await self._send_sleep_command_after_eof(sender_mac)
         finally:
             self.cycle_tracker.complete_cycle(sender_mac)
 
+    async def _request_resend_if_corrupt(self, sender_mac: str, final_path) -> bool:
+        """ハッシュが一致しなかった画像の再送を要求（要求した場合はTrue）
+
+        デバイスはスリープコマンドを受信するまで送信済み画像を保持しているため、
+        スリープコマンドより先に ``RESEND_LAST`` を送る。再送された転送のEOFで改めて判定する。
+        """
+        retained_frame = self.retained_frames.pop(sender_mac, None)
+        corrupt = final_path is not None and os.path.splitext(final_path)[0].endswith(CORRUPT_SUFFIX)
+        if not corrupt or retained_frame is None:
+            self.resend_counts.pop(sender_mac, None)
+            return False
+
+        resends = self.resend_counts.get(sender_mac, 0)
+        if resends >= config.MAX_IMAGE_RESENDS:
+            logger.warning(
+                f"Frame {retained_frame} from {sender_mac} is still corrupt after {resends} resend(s); giving up"
+            )
+            self.resend_counts.pop(sender_mac, None)
+            return False
+        if not self.transport:
+            logger.warning(f"No transport available for resend request to {sender_mac}")
+            return False
+
+        command_to_gateway = format_resend_command_to_gateway(sender_mac)
+        try:
+            self.transport.write(command_to_gateway.encode("utf-8"))
+        except Exception as e:
+            logger.error(f"Error sending resend request for {sender_mac}: {e}")
+            return False
+        self.resend_counts[sender_mac] = resends + 1
+        # 再送された転送のEOFを重複として捨てない
+        self.eof_processed.pop(sender_mac, None)
+        logger.warning(
+            f"Image hash mismatch for {sender_mac}; requested resend of frame {retained_frame} "
+            f"({resends + 1}/{config.MAX_IMAGE_RESENDS})"
+        )
+        return True
+
     async def _chunk_processed_callback(
         self, sender_mac: str, chunk_data: bytes, seq_num: int
     ):
@@ -734,6 +1289,12 @@ class StreamingSerialProtocol(asyncio.Protocol):
             FRAME_TYPE_HASH: "HASH",
             FRAME_TYPE_DATA: "DATA",
             FRAME_TYPE_EOF: "EOF",
+            FRAME_TYPE_FEC: "FEC",
+            FRAME_TYPE_COMPLETE: "COMPLETE",
+            FRAME_TYPE_STATS: "STATS",
+            FRAME_TYPE_ABORT: "ABORT",
+            FRAME_TYPE_FLEET_SUMMARY: "FLEET_SUMMARY",
+            FRAME_TYPE_URGENT: "URGENT",
         }
         return type_map.get(frame_type, f"UNKNOWN({frame_type})")
 
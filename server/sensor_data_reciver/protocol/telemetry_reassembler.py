@@ -0,0 +1,72 @@
+"""Reassemble delta-encoded HASH telemetry into full records.
+
+Sensor-only nodes send a full snapshot (``...,SNAP:<n>``) every few cycles and, in
+between, only the fields that changed since that snapshot (``...,DELTA:<n>``).
+Deltas are relative to the snapshot rather than to the previous cycle, so a lost
+delta never corrupts the following ones.
+"""
+
+from __future__ import annotations
+
+import logging
+from typing import Dict, Optional, Tuple
+
+logger = logging.getLogger(__name__)
+
+# 差分エンコードの対象項目（HASHペイロードでの並び順）
+TELEMETRY_KEYS = ("VOLT", "TEMP", "TDS_VOLT")
+
+SNAPSHOT_KEY = "SNAP"
+DELTA_KEY = "DELTA"
+
+
+def _split_field(item: str) -> Tuple[Optional[str], str]:
+    key, sep, value = item.partition(":")
+    return (key, value) if sep else (None, item)
+
+
+class TelemetryReassembler:
+    """Keep the latest full snapshot per sender and fill in unchanged fields of deltas."""
+
+    def __init__(self) -> None:
+        self._snapshots: Dict[str, Tuple[str, Dict[str, str]]] = {}
+
+    def snapshot(self, sender_mac: str) -> Optional[Tuple[str, Dict[str, str]]]:
+        """送信元の最新スナップショット（番号, 項目）"""
+        return self._snapshots.get(sender_mac)
+
+    def reassemble(self, sender_mac: str, payload: str) -> str:
+        """
+        HASHペイロード（``HASH:`` を除いた部分）を完全なレコードに戻す
+
+        スナップショットは保持してそのまま返す。差分はスナップショットの値で欠けた項目を補い、
+        従来の並び（``<hash>,VOLT,TEMP,TDS_VOLT,...``）にして返す。基準のスナップショットが
+        ない差分は、含まれる項目だけの並びにして返す。SNAP/DELTA のないペイロードはそのまま返す。
+        """
+        items = payload.split(",")
+        fields = [_split_field(item) for item in items]
+        markers = {key: value for key, value in fields if key in (SNAPSHOT_KEY, DELTA_KEY)}
+
+        if SNAPSHOT_KEY in markers:
+            values = {key: value for key, value in fields if key in TELEMETRY_KEYS}
+            self._snapshots[sender_mac] = (markers[SNAPSHOT_KEY], values)
+            return payload
+
+        if DELTA_KEY not in markers:
+            return payload
+
+        base_seq = markers[DELTA_KEY]
+        changed = {key: value for key, value in fields[1:] if key in TELEMETRY_KEYS}
+        stored = self._snapshots.get(sender_mac)
+        if stored is not None and stored[0] == base_seq:
+            values = {**stored[1], **changed}
+        else:
+            logger.warning(
+                f"No snapshot {base_seq} for delta telemetry from {sender_mac} "
+                f"(have {stored[0] if stored else None}); unchanged fields are unavailable"
+            )
+            values = changed
+
+        rest = [item for item, (key, _) in zip(items[1:], fields[1:]) if key not in TELEMETRY_KEYS]
+        telemetry = [f"{key}:{values[key]}" for key in TELEMETRY_KEYS if key in values]
+        return ",".join([items[0], *telemetry, *rest])
@@ -0,0 +1,264 @@
+"""SQLite index of received images and their telemetry.
+
+受信した画像ごとに、送信元・受信/保存時刻・サイズ・ハッシュ・検証結果・電圧・温度を
+SQLiteに記録します。ファイル名を解析しなくても、デバイスごとの最新の画像や検証に失敗した
+画像をすぐに確認できるようにするためのものです。
+
+デバイスが撮影しなかった（または画像品質で送信を見送った）サイクルは、HASHフレームの ``SKIP`` を
+``skip_reason`` に入れて ``skipped`` として記録します。電池切れが続く・カメラが故障しているなど、
+デバイスごとに画像が届かない原因の傾向を ``skips`` で集計できます。
+
+プロビジョニング時に書き込んだデバイスID（HASHフレームの ``DEV_ID``）があれば ``device_id`` に
+記録し、照会はデバイスIDとMACアドレスのどちらでも行えます。基板を交換してMACアドレスが
+変わっても、デバイスIDで照会すれば同じデバイスの記録として続けて確認できます。
+
+記録は ``StreamingImageProcessor`` が画像の保存・中断のたびに行い、照会は
+``farmverse_db.py``（``just farmverse-db``）で行います::
+
+    just farmverse-db last --device aa:bb:cc:dd:ee:ff
+    just farmverse-db last --failed
+    just farmverse-db skips --device aa:bb:cc:dd:ee:ff
+"""
+
+import logging
+import os
+import sqlite3
+import threading
+import time
+from dataclasses import dataclass
+from typing import Dict, List, Optional, Tuple
+
+import sys
+sys.path.append(os.path.dirname(os.path.dirname(os.path.abspath(__file__))))
+
+from utils.data_parser import DataParser
+
+
+logger = logging.getLogger(__name__)
+
+# 検証結果
+STATUS_VERIFIED = "verified"  # HASHフレームのハッシュと一致
+STATUS_UNVERIFIED = "unverified"  # ハッシュがなく検証できない
+STATUS_CORRUPT = "corrupt"  # ハッシュが一致しない
+STATUS_PARTIAL = "partial"  # 欠落のある転送
+STATUS_ABORTED = "aborted"  # 保存前に中断
+STATUS_SKIPPED = "skipped"  # デバイスが撮影しなかった（理由は skip_reason）
+
+# 失敗とみなす検証結果（``--failed`` で表示）
+FAILED_STATUSES = (STATUS_CORRUPT, STATUS_PARTIAL, STATUS_ABORTED)
+
+SCHEMA = """
+CREATE TABLE IF NOT EXISTS images (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    device TEXT NOT NULL,
+    device_id TEXT,
+    started_at REAL NOT NULL,
+    recorded_at REAL NOT NULL,
+    captured_at REAL,
+    size INTEGER NOT NULL,
+    hash TEXT,
+    hash_algo TEXT,
+    status TEXT NOT NULL,
+    path TEXT,
+    voltage REAL,
+    temperature REAL,
+    chunks INTEGER NOT NULL DEFAULT 0,
+    reason TEXT,
+    skip_reason TEXT
+);
+CREATE INDEX IF NOT EXISTS images_device_recorded ON images (device, recorded_at);
+CREATE INDEX IF NOT EXISTS images_status_recorded ON images (status, recorded_at);
+"""
+
+# 後から追加した列（既存のインデックスには ALTER TABLE で追加する）
+ADDED_COLUMNS = (("device_id", "TEXT"), ("skip_reason", "TEXT"))
+
+# デバイスIDの列を追加する前に作成したインデックスへの追加（列の追加後に作成する）
+DEVICE_ID_INDEX = "CREATE INDEX IF NOT EXISTS images_device_id_recorded ON images (device_id, recorded_at)"
+
+
+@dataclass
+class ImageRecord:
+    """1枚の画像の記録"""
+    device: str
+    started_at: float
+    recorded_at: float
+    size: int
+    status: str
+    captured_at: Optional[float] = None
+    hash: Optional[str] = None
+    hash_algo: Optional[str] = None
+    path: Optional[str] = None
+    voltage: Optional[float] = None
+    temperature: Optional[float] = None
+    chunks: int = 0
+    reason: Optional[str] = None
+    device_id: Optional[str] = None
+    skip_reason: Optional[str] = None
+
+    @classmethod
+    def from_transfer(
+        cls,
+        device: str,
+        started_at: float,
+        size: int,
+        status: str,
+        hash_data: Optional[str] = None,
+        path: Optional[str] = None,
+        chunks: int = 0,
+        reason: Optional[str] = None,
+        recorded_at: Optional[float] = None,
+    ) -> "ImageRecord":
+        """HASHフレームのメタデータ（"HASH:...,VOLT:...,TEMP:..." 形式）から記録を作成します"""
+        metadata = hash_data or ""
+        captured_unix_ms = DataParser.extract_value_from_payload(metadata, "CAPTURE_UNIX_MS:")
+        # 撮影直後に測った温度があれば、撮影前の TEMP より優先する
+        ambient = DataParser.parse_ambient(metadata) or {}
+        temperature = ambient.get("temperature")
+        return cls(
+            device=device,
+            started_at=started_at,
+            recorded_at=time.time() if recorded_at is None else recorded_at,
+            size=size,
+            status=status,
+            captured_at=int(captured_unix_ms) / 1000 if captured_unix_ms and captured_unix_ms.isdigit() else None,
+            hash=DataParser.extract_value_from_payload(metadata, "HASH:"),
+            hash_algo=DataParser.extract_value_from_payload(metadata, "HASH_ALGO:"),
+            path=path,
+            voltage=DataParser.parse_voltage_data(metadata),
+            temperature=temperature if temperature is not None else DataParser.parse_temperature_data(metadata),
+            chunks=chunks,
+            reason=reason,
+            device_id=DataParser.extract_value_from_payload(metadata, "DEV_ID:"),
+            skip_reason=DataParser.extract_value_from_payload(metadata, "SKIP:"),
+        )
+
+
+class ImageIndex:
+    """受信画像の SQLite インデックス"""
+
+    def __init__(self, path: str):
+        """
+        Args:
+            path: データベースファイルのパス（":memory:" でメモリ上）
+        """
+        self.path = path
+        if path != ":memory:":
+            os.makedirs(os.path.dirname(os.path.abspath(path)), exist_ok=True)
+        # 記録はイベントループの別スレッドから行うため、接続はロックで保護して共有する
+        self._conn = sqlite3.connect(path, check_same_thread=False)
+        self._conn.row_factory = sqlite3.Row
+        self._lock = threading.Lock()
+        with self._lock:
+            self._conn.executescript(SCHEMA)
+            columns = {row["name"] for row in self._conn.execute("PRAGMA table_info(images)")}
+            for name, column_type in ADDED_COLUMNS:
+                if name not in columns:
+                    self._conn.execute(f"ALTER TABLE images ADD COLUMN {name} {column_type}")
+            self._conn.execute(DEVICE_ID_INDEX)
+            self._conn.commit()
+
+    def close(self) -> None:
+        with self._lock:
+            self._conn.close()
+
+    def record(self, record: ImageRecord) -> None:
+        """画像の記録を追加します"""
+        with self._lock, self._conn:
+            self._conn.execute(
+                "INSERT INTO images (device, device_id, started_at, recorded_at, captured_at, size, hash,"
+                " hash_algo, status, path, voltage, temperature, chunks, reason, skip_reason)"
+                " VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+                (
+                    record.device.lower(),
+                    record.device_id.lower() if record.device_id else None,
+                    record.started_at,
+                    record.recorded_at,
+                    record.captured_at,
+                    record.size,
+                    record.hash,
+                    record.hash_algo,
+                    record.status,
+                    record.path,
+                    record.voltage,
+                    record.temperature,
+                    record.chunks,
+                    record.reason,
+                    record.skip_reason,
+                ),
+            )
+
+    def last(self, device: Optional[str] = None, failed: bool = False, limit: int = 1) -> List[ImageRecord]:
+        """新しい順に記録を返します
+
+        Args:
+            device: デバイスID、または送信元MACアドレス（Noneなら全デバイス）
+            failed: 検証に失敗した・中断した画像のみ
+            limit: 最大件数
+        """
+        conditions = []
+        params: list = []
+        if device:
+            conditions.append("(device_id = ? OR device = ?)")
+            params.extend([device.lower(), device.lower()])
+        if failed:
+            conditions.append(f"status IN ({', '.join('?' for _ in FAILED_STATUSES)})")
+            params.extend(FAILED_STATUSES)
+        where = f" WHERE {' AND '.join(conditions)}" if conditions else ""
+        params.append(limit)
+        with self._lock:
+            rows = self._conn.execute(
+                f"SELECT * FROM images{where} ORDER BY recorded_at DESC, id DESC LIMIT ?", params
+            ).fetchall()
+        return [
+            ImageRecord(**{key: row[key] for key in row.keys() if key != "id"})
+            for row in rows
+        ]
+
+
+    def skip_counts(self, device: Optional[str] = None, since: Optional[float] = None) -> List[Tuple[str, str, int]]:
+        """撮影しなかったサイクルの数をデバイスと理由ごとに返します（多い順）
+
+        Args:
+            device: デバイスID、または送信元MACアドレス（Noneなら全デバイス）
+            since: この時刻（UNIX秒）以降の記録のみ
+
+        Returns:
+            ``(デバイス, 理由, 回数)`` のリスト。デバイスはデバイスID、なければMACアドレス
+        """
+        conditions = ["status = ?"]
+        params: list = [STATUS_SKIPPED]
+        if device:
+            conditions.append("(device_id = ? OR device = ?)")
+            params.extend([device.lower(), device.lower()])
+        if since is not None:
+            conditions.append("recorded_at >= ?")
+            params.append(since)
+        with self._lock:
+            rows = self._conn.execute(
+                "SELECT COALESCE(device_id, device) AS source, COALESCE(skip_reason, '-') AS skip_reason,"
+                f" COUNT(*) AS count FROM images WHERE {' AND '.join(conditions)}"
+                " GROUP BY source, skip_reason ORDER BY count DESC, source, skip_reason",
+                params,
+            ).fetchall()
+        return [(row["source"], row["skip_reason"], row["count"]) for row in rows]
+
+
+# 開いたインデックス（再接続のたびに開き直さない）
+_open_indexes: Dict[str, ImageIndex] = {}
+
+
+def open_index(path: str) -> Optional[ImageIndex]:
+    """インデックスを開きます（パスが空、または開けない場合はNone）"""
+    if not path:
+        return None
+    if path in _open_indexes:
+        return _open_indexes[path]
+    try:
+        index = ImageIndex(path)
+    except (sqlite3.Error, OSError) as e:
+        logger.error(f"Failed to open image index {path}: {e}")
+        return None
+    logger.info(f"Recording received images to index: {path}")
+    _open_indexes[path] = index
+    return index
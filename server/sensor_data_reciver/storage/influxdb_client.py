@@ -170,8 +170,12 @@ class InfluxDBClient:
         with self._init_lock:
             self._disable_client_locked()
     
-    def write_sensor_data(self, sender_mac: str, voltage: float = None, temperature: float = None, tds_voltage: float = None) -> bool:
-        """センサーデータをInfluxDBに書き込み（非同期実行・エラー耐性付き）"""
+    def write_sensor_data(self, sender_mac: str, voltage: float = None, temperature: float = None, tds_voltage: float = None, device_id: str = None) -> bool:
+        """センサーデータをInfluxDBに書き込み（非同期実行・エラー耐性付き）
+
+        device_id はプロビジョニングしたデバイスID（基板を交換しても変わらない）で、
+        あれば ``device_id`` タグとして ``mac_address`` タグと一緒に書き込みます。
+        """
         # テスト環境ではInfluxDB書き込みをスキップ
         if config.IS_TEST_ENV:
             logger.info(f"Test environment detected, skipping InfluxDB write for {sender_mac}")
@@ -194,7 +198,7 @@ class InfluxDBClient:
             
         # InfluxDBへの書き込みを非同期で実行し、エラーが発生しても処理を継続する
         # asyncio.gatherを使用した構造化タスク管理
-        write_task = self._write_sensor_data_async(sender_mac, voltage, temperature, tds_voltage)
+        write_task = self._write_sensor_data_async(sender_mac, voltage, temperature, tds_voltage, device_id)
         cleanup_task = self._cleanup_completed_tasks()
         
         # 両方のタスクを同時実行し、例外を適切に処理
@@ -211,7 +215,7 @@ class InfluxDBClient:
             logger.error(f"Error creating InfluxDB write task for {sender_mac}: {e}")
             return False
     
-    async def _write_sensor_data_async(self, sender_mac: str, voltage: float = None, temperature: float = None, tds_voltage: float = None):
+    async def _write_sensor_data_async(self, sender_mac: str, voltage: float = None, temperature: float = None, tds_voltage: float = None, device_id: str = None):
         """非同期でInfluxDBにデータを書き込み"""
         try:
             # 必要であればクライアントを再初期化する
@@ -230,6 +234,8 @@ class InfluxDBClient:
                 return
             
             point = Point("data").tag("mac_address", sender_mac)
+            if device_id:
+                point.tag("device_id", device_id)
             
             if voltage is not None:
                 point.field("voltage", float(voltage))
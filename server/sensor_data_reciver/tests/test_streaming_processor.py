@@ -6,7 +6,9 @@ image processing implementation.
 """
 
 import asyncio
+import json
 import os
+import random
 import tempfile
 import unittest
 from unittest.mock import MagicMock, patch
@@ -279,6 +281,89 @@ class TestStreamingImageProcessor(unittest.TestCase):
         self.assertIsInstance(stats["active_streams"], int)
         self.assertGreaterEqual(stats["uptime"], 0)
 
+    def _make_chunks(self, rng):
+        """可変長のチャンク（番号はFECフレーム分の飛びを含む）"""
+        chunks = []
+        for i in range(40):
+            body = bytes(rng.randrange(256) for _ in range(rng.randrange(50, 250)))
+            chunks.append((i + 1 + i // 8, (b'\xff\xd8' if i == 0 else b'') + body))
+        return chunks
+
+    @patch('processors.streaming_image_processor.Image', None)
+    async def test_reassembles_random_permutations_byte_exact(self):
+        """到着順を入れ替えても元の画像をバイト単位で復元するテスト"""
+        sender_mac = "aa:bb:cc:dd:ee:ff"
+        for seed in range(10):
+            rng = random.Random(seed)
+            chunks = self._make_chunks(rng)
+            expected = b''.join(data for _, data in chunks)
+
+            arrivals = list(chunks)
+            rng.shuffle(arrivals)
+            await self.processor.start_image_stream(sender_mac)
+            for sequence_number, data in arrivals:
+                self.assertTrue(
+                    await self.processor.process_chunk(sender_mac, data, sequence_number)
+                )
+
+            final_path = await self.processor.finalize_image_stream(sender_mac)
+            self.assertIsNotNone(final_path, f"seed={seed}")
+            with open(final_path, 'rb') as f:
+                self.assertEqual(f.read(), expected, f"seed={seed}")
+            os.remove(final_path)
+
+    @patch('processors.streaming_image_processor.Image', None)
+    async def test_duplicate_chunks_are_idempotent(self):
+        """重複したチャンクを何度受信しても1回分だけ格納するテスト"""
+        sender_mac = "aa:bb:cc:dd:ee:ff"
+        rng = random.Random(42)
+        chunks = self._make_chunks(rng)
+        expected = b''.join(data for _, data in chunks)
+
+        arrivals = chunks + rng.sample(chunks, 15)
+        rng.shuffle(arrivals)
+        await self.processor.start_image_stream(sender_mac)
+        for sequence_number, data in arrivals:
+            await self.processor.process_chunk(sender_mac, data, sequence_number)
+
+        stream_meta = self.processor.active_streams[sender_mac]
+        self.assertEqual(stream_meta.total_chunks_received, len(chunks))
+        self.assertEqual(stream_meta.total_bytes_received, len(expected))
+        self.assertEqual(stream_meta.duplicate_chunks, 15)
+
+        final_path = await self.processor.finalize_image_stream(sender_mac)
+        with open(final_path, 'rb') as f:
+            self.assertEqual(f.read(), expected)
+
+    @patch('processors.streaming_image_processor.Image', None)
+    async def test_sequence_frames_are_saved_with_manifest(self):
+        """タイムラプスの画像をシーケンスごとのディレクトリに番号順のマニフェスト付きで保存するテスト"""
+        sender_mac = "aa:bb:cc:dd:ee:ff"
+        image_data = b'\xff\xd8' + b'frame' * 300 + b'\xff\xd9'
+
+        # 再送（同じ実行・番号）と順不同の到着を含める
+        for run, index in [("0000abcd", 1), ("0000abcd", 0), ("0000abcd", 1), ("1234beef", 0)]:
+            hash_data = f"HASH:abc,VOLT:80,SEQ:garden-1,SEQ_RUN:{run},SEQ_IDX:{index},SEQ_INT:600"
+            await self.processor.start_image_stream(sender_mac, hash_data=hash_data)
+            await self.processor.process_chunk(sender_mac, image_data, 1)
+            final_path = await self.processor.finalize_image_stream(sender_mac)
+            self.assertEqual(
+                os.path.basename(final_path), f"aabbccddeeff_{run}_{index:06d}.jpg"
+            )
+
+        sequence_dir = os.path.join(self.temp_dir, "sequences", "garden-1")
+        with open(os.path.join(sequence_dir, "manifest.json")) as f:
+            manifest = json.load(f)
+        self.assertEqual(manifest["sequence"], "garden-1")
+        self.assertEqual(manifest["interval_seconds"], 600)
+        self.assertEqual(manifest["runs"], ["0000abcd", "1234beef"])
+        self.assertEqual(
+            [(frame["run"], frame["index"]) for frame in manifest["frames"]],
+            [("0000abcd", 0), ("0000abcd", 1), ("1234beef", 0)],
+        )
+        for frame in manifest["frames"]:
+            self.assertTrue(os.path.exists(os.path.join(sequence_dir, frame["file"])))
+
 
 class TestStreamingIntegration(unittest.TestCase):
     """統合テストケース"""
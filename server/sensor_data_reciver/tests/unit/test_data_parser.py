@@ -24,6 +24,79 @@ class TestDataParser:
         assert hash_value == "abc123"
         assert tds_volt_value == "0.5"
 
+    def test_parse_lifecycle(self):
+        """Lifecycle section parsing test."""
+        payload = "HASH:abc123,VOLT:75,BOOT:BROWNOUT,WAKE:NONE,PREV_UP_S:37,BOOT_LOG:POWERON|BROWNOUT"
+
+        assert DataParser.parse_lifecycle(payload) == {
+            "boot_reason": "BROWNOUT",
+            "wake_cause": "NONE",
+            "previous_uptime_s": 37,
+            "boot_log": ["POWERON", "BROWNOUT"],
+            "panic": None,
+        }
+        assert DataParser.parse_lifecycle("HASH:abc123,VOLT:75") is None
+
+        payload = "HASH:abc123,BOOT:PANIC,WAKE:NONE,PANIC_MSG:index out of bounds,PANIC_AT:src/main.rs:42"
+        assert DataParser.parse_lifecycle(payload)["panic"] == {
+            "message": "index out of bounds",
+            "location": "src/main.rs:42",
+        }
+
+    def test_parse_build_info(self):
+        """Firmware build info parsing test."""
+        payload = "HASH:abc123,VOLT:75,BOOT:POWERON,FW:1a2b3c4d,BUILT:1760000000,FEAT:esp+qemu-smoke,CFG:deadbeef"
+
+        assert DataParser.parse_build_info(payload) == {
+            "firmware": "1a2b3c4d",
+            "built_at": 1760000000,
+            "features": ["esp", "qemu-smoke"],
+            "config_hash": "deadbeef",
+        }
+        assert DataParser.parse_build_info("HASH:abc123,VOLT:75") is None
+
+    def test_parse_sequence(self):
+        """Time-lapse sequence metadata parsing test."""
+        payload = "HASH:abc123,VOLT:75,SEQ:garden-1,SEQ_RUN:0000abcd,SEQ_IDX:12,SEQ_INT:600"
+
+        assert DataParser.parse_sequence(payload) == {
+            "name": "garden-1",
+            "run": "0000abcd",
+            "index": 12,
+            "interval_seconds": 600,
+        }
+        assert DataParser.parse_sequence("HASH:abc123,VOLT:75") is None
+        # ディレクトリ名に使えない名前は受け付けない
+        assert DataParser.parse_sequence("SEQ:../x,SEQ_RUN:0000abcd,SEQ_IDX:1,SEQ_INT:60") is None
+
+    def test_parse_thumbnail(self):
+        """Recent-capture thumbnail metadata parsing test."""
+        payload = "HASH:abc123,VOLT:75,THUMB:2/3,THUMB_SEQ:41,THUMB_AT:1700000000,HASH_ALGO:XXH64"
+
+        assert DataParser.parse_thumbnail(payload) == {
+            "index": 2,
+            "count": 3,
+            "seq": 41,
+            "captured_at": 1700000000,
+        }
+        # 時刻が未同期のデバイスは撮影時刻0を送る
+        assert DataParser.parse_thumbnail("THUMB:1/1,THUMB_SEQ:0,THUMB_AT:0")["captured_at"] is None
+        assert DataParser.parse_thumbnail("HASH:abc123,VOLT:75") is None
+        assert DataParser.parse_thumbnail("THUMB:x/3,THUMB_SEQ:1,THUMB_AT:0") is None
+
+    def test_parse_ambient(self):
+        """Shutter-time ambient snapshot parsing test."""
+        payload = "HASH:abc123,VOLT:82,TEMP:23.1,HASH_ALGO:XXH64,AMB_TEMP:23.5,AMB_BATT:80,AMB_DT_MS:780"
+
+        assert DataParser.parse_ambient(payload) == {
+            "temperature": 23.5,
+            "battery": 80,
+            "lux": None,
+            "offset_ms": 780,
+        }
+        assert DataParser.parse_ambient("AMB_LUX:1234,AMB_DT_MS:5")["lux"] == 1234.0
+        assert DataParser.parse_ambient("HASH:abc123,VOLT:75,TEMP:23.1") is None
+
     def test_extract_value_from_payload_not_found(self):
         """Test extraction when prefix not found."""
         payload = "HASH:abc123,VOLT:75"
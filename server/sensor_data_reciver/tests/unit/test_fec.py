@@ -0,0 +1,86 @@
+import os
+import sys
+
+sys.path.insert(0, os.path.join(os.path.dirname(__file__), '..', '..'))
+
+from protocol.fec import (FEC_KIND_PARITY, FecReassembler, ParityChunk,
+                          parse_announce, recover_group)
+
+
+def make_parity(first_seq, chunks, parity_index, parity_count):
+    """送信側（fec.rs）と同じ規則でパリティを生成するヘルパー"""
+    covered = [c for i, c in enumerate(chunks) if i % parity_count == parity_index]
+    parity = bytearray(max(len(c) for c in covered))
+    length_xor = 0
+    for c in covered:
+        length_xor ^= len(c)
+        for i, b in enumerate(c):
+            parity[i] ^= b
+    return ParityChunk(first_seq, len(chunks), parity_index, parity_count, length_xor, bytes(parity))
+
+
+def test_parse_announce():
+    assert parse_announce(bytes([0, 8, 2])) == (8, 2)
+    assert parse_announce(bytes([0, 2, 3])) is None
+    assert parse_announce(bytes([1, 8, 2])) is None
+    assert parse_announce(bytes([0, 8])) is None
+
+
+def test_parity_payload_roundtrip():
+    payload = (bytes([FEC_KIND_PARITY]) + (100).to_bytes(4, "little") + bytes([4, 1, 2])
+               + (6).to_bytes(2, "little") + b"\xaa\xbb")
+    parity = ParityChunk.from_payload(payload)
+    assert parity.first_seq == 100
+    assert parity.data_count == 4
+    assert parity.parity_index == 1
+    assert parity.parity_count == 2
+    assert parity.length_xor == 6
+    assert parity.parity == b"\xaa\xbb"
+    assert parity.covers(1) and parity.covers(3) and not parity.covers(2)
+
+
+def test_recover_group_restores_short_chunk():
+    chunks = [b"\x01\x02\x03", b"\x04\x05\x06\x07\x08"]
+    parity = make_parity(10, chunks, 0, 1)
+    received = [chunks[0], None]
+    assert recover_group(received, [parity]) == 1
+    assert received == chunks
+
+
+def test_reassembler_recovers_lost_chunk_in_order():
+    chunks = [bytes([i]) * 4 for i in range(4)]
+    base = 11  # 告知フレーム seq=10 の次
+    reassembler = FecReassembler(4, 2, base)
+
+    released = []
+    for i, chunk in enumerate(chunks):
+        if i == 2:
+            continue  # 欠落
+        released += reassembler.add_data(base + i, chunk)
+    assert released == []  # パリティ到着までは保持
+
+    released += reassembler.add_parity(make_parity(base, chunks, 0, 2))
+    released += reassembler.add_parity(make_parity(base, chunks, 1, 2))
+    assert released == [(base + i, c) for i, c in enumerate(chunks)]
+    assert reassembler.recovered_count == 1
+
+
+def test_reassembler_releases_previous_group_when_next_group_starts():
+    chunks = [b"a", b"b"]
+    reassembler = FecReassembler(2, 1, 0)
+    # グループ0のデータ1つのみ受信、パリティも欠落
+    assert reassembler.add_data(0, chunks[0]) == []
+    # グループ1（seq=3から）のデータが届いたらグループ0を払い出す
+    released = reassembler.add_data(3, b"c")
+    assert released == [(0, b"a")]
+    # 最終グループは flush で払い出す
+    assert reassembler.flush() == [(3, b"c")]
+
+
+def test_reassembler_ignores_duplicates_and_passes_pre_session_data():
+    reassembler = FecReassembler(2, 1, 10)
+    assert reassembler.add_data(5, b"x") == [(5, b"x")]
+    assert reassembler.add_data(10, b"a") == []
+    assert reassembler.add_data(10, b"a") == []
+    assert reassembler.add_data(11, b"b") == [(10, b"a"), (11, b"b")]
+    assert reassembler.add_data(11, b"b") == []
@@ -70,3 +70,102 @@ def test_sanitize_filename_special_chars():
     timestamp = "2023/10/27_10:30:00_123456"
     expected_filename = "0102-0304-0506_20231027_103000_123456.jpg"
     assert FrameParser.sanitize_filename(mac_str, timestamp) == expected_filename
+
+def test_parse_frame_complete_with_hash():
+    payload = b"FRAME_ID:3,BYTES:1234,DEDUP:2,EOF:1;HASH:abcd,VOLT:80,TEMP:25.0"
+
+    info = FrameParser.parse_frame_complete(payload)
+
+    assert info.frame_id == 3
+    assert info.byte_count == 1234
+    assert info.dedupe_count == 2
+    assert info.eof_received is True
+    assert info.hash_payload == b"HASH:abcd,VOLT:80,TEMP:25.0"
+
+def test_parse_frame_complete_with_gateway_timing():
+    payload = b"FRAME_ID:3,BYTES:1234,DEDUP:0,EOF:1,AIR_MS:850,USB_MS:40;HASH:abcd,TRACE:0123456789abcdef"
+
+    info = FrameParser.parse_frame_complete(payload)
+
+    assert info.air_ms == 850
+    assert info.usb_ms == 40
+    assert info.hash_payload == b"HASH:abcd,TRACE:0123456789abcdef"
+    assert info.e2e_ms is None
+
+def test_parse_frame_complete_with_end_to_end_delay():
+    payload = b"FRAME_ID:3,BYTES:1234,DEDUP:0,EOF:1,AIR_MS:850,USB_MS:40,E2E_MS:12500;HASH:abcd,CAPTURE_AGE_MS:11800"
+
+    info = FrameParser.parse_frame_complete(payload)
+
+    assert info.e2e_ms == 12500
+    assert info.hash_payload == b"HASH:abcd,CAPTURE_AGE_MS:11800"
+
+def test_parse_frame_complete_without_hash():
+    info = FrameParser.parse_frame_complete(b"FRAME_ID:0,BYTES:0,DEDUP:0,EOF:1")
+
+    assert info.hash_payload is None
+    assert info.air_ms is None
+    assert info.eof_received is True
+
+def test_parse_frame_complete_with_gap_map():
+    payload = b"FRAME_ID:5,BYTES:9000,DEDUP:0,EOF:0,AIR_MS:10,USB_MS:2,GAPS:3-3|40-42,PARTIAL:1"
+
+    info = FrameParser.parse_frame_complete(payload)
+
+    assert info.gaps == [(3, 3), (40, 42)]
+    assert info.partial is True
+    assert info.eof_received is False
+    assert FrameParser.parse_frame_complete(b"FRAME_ID:0,BYTES:0,DEDUP:0,EOF:1").gaps == []
+
+def test_parse_frame_complete_with_decoded_telemetry():
+    payload = b"FRAME_ID:3,BYTES:1234,DEDUP:0,EOF:1,DEC:VOLT=80|TDS_VOLT=1.25,DQ:TEMP;HASH:abcd,VOLT:80,TEMP:150.0,TDS_VOLT:1.25"
+
+    info = FrameParser.parse_frame_complete(payload)
+
+    assert info.decoded == {"VOLT": 80.0, "TDS_VOLT": 1.25}
+    assert info.data_quality == ["TEMP"]
+    assert info.hash_payload == b"HASH:abcd,VOLT:80,TEMP:150.0,TDS_VOLT:1.25"
+    assert FrameParser.parse_frame_complete(b"FRAME_ID:0,BYTES:0,DEDUP:0,EOF:1").decoded == {}
+
+def test_parse_frame_complete_invalid():
+    with pytest.raises(ValueError):
+        FrameParser.parse_frame_complete(b"FRAME_ID:x,BYTES:0")
+
+def test_parse_frame_abort():
+    info = FrameParser.parse_frame_abort(b"FRAME_ID:4,BYTES:1200,REASON:DEVICE")
+
+    assert info.frame_id == 4
+    assert info.byte_count == 1200
+    assert info.reason == "DEVICE"
+
+def test_parse_frame_abort_invalid():
+    with pytest.raises(ValueError):
+        FrameParser.parse_frame_abort(b"FRAME_ID:4")
+
+def test_parse_stats():
+    stats = FrameParser.parse_stats(b"DATA_Q:3/32,CTRL_SEND_AVG_MS:12,CTRL_SENT:4")
+
+    assert stats == {"DATA_Q": "3/32", "CTRL_SEND_AVG_MS": "12", "CTRL_SENT": "4"}
+
+def test_parse_stats_invalid():
+    with pytest.raises(ValueError):
+        FrameParser.parse_stats(b"DATA_Q")
+
+
+def test_parse_fleet_summary():
+    # {"v": 1, "period_s": 3600, "devices": [{"mac": "34:ab", "frames": 3, "rssi": -65, "battery": null}]}
+    payload = (
+        b"\xa3\x61v\x01\x68period_s\x19\x0e\x10\x67devices\x81"
+        b"\xa4\x63mac\x6534:ab\x66frames\x03\x64rssi\x38\x40\x67battery\xf6"
+    )
+    summary = FrameParser.parse_fleet_summary(payload)
+
+    assert summary["period_s"] == 3600
+    assert summary["devices"] == [{"mac": "34:ab", "frames": 3, "rssi": -65, "battery": None}]
+
+
+def test_parse_fleet_summary_invalid():
+    with pytest.raises(ValueError):
+        FrameParser.parse_fleet_summary(b"\xa1\x61v\x02")
+    with pytest.raises(ValueError):
+        FrameParser.parse_fleet_summary(b"\xa3\x61v\x01")
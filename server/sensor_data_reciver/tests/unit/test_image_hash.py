@@ -0,0 +1,53 @@
+"""Tests for image hash verification."""
+
+import hashlib
+import sys
+import os
+sys.path.append(os.path.dirname(os.path.dirname(os.path.dirname(os.path.abspath(__file__)))))
+
+from utils.image_hash import verify_image_hash, xxh64
+
+
+class TestImageHash:
+    """verify_image_hash tests."""
+
+    def test_xxh64_matches_reference_values(self):
+        """Values match the xxhash-rust crate used on the devices."""
+        assert xxh64(b"") == 0xEF46DB3751D8E999
+        assert xxh64(b"abc") == 0x44BC2CF5AD770999
+        data = bytes(range(256)) * 3
+        assert xxh64(data) == 0x8E03C838C596036F
+        assert xxh64(data[:45]) == 0x10FDD84D6409ABDF
+
+    def test_verify_each_algorithm(self):
+        """Declared algorithms are verified and mismatches detected."""
+        data = b"\xff\xd8" + bytes(range(200)) + b"\xff\xd9"
+        payloads = {
+            "SUM": f"HASH:{len(data):08x}{sum(data):08x},VOLT:80",
+            "XXH64": f"HASH:{xxh64(data):016x},VOLT:80,HASH_ALGO:XXH64",
+            "SHA256": f"HASH:{hashlib.sha256(data).hexdigest()},VOLT:80,HASH_ALGO:SHA256",
+        }
+
+        for payload in payloads.values():
+            assert verify_image_hash(data, payload) is True
+            corrupted = data[:10] + b"\x00" + data[11:]
+            assert verify_image_hash(corrupted, payload) is False
+
+    def test_sum_detects_length_but_not_reordering(self):
+        """Legacy SUM hash (no HASH_ALGO) cannot see swapped chunks, XXH64 can."""
+        data = bytes(range(100))
+        swapped = data[50:] + data[:50]
+        sum_payload = f"HASH:{len(data):08x}{sum(data):08x},VOLT:80"
+        xxh_payload = f"HASH:{xxh64(data):016x},HASH_ALGO:XXH64"
+
+        assert verify_image_hash(swapped, sum_payload) is True
+        assert verify_image_hash(swapped, xxh_payload) is False
+        assert verify_image_hash(data[:-1], sum_payload) is False
+
+    def test_unverifiable_hashes(self):
+        """Dummy, malformed and unknown-algorithm hashes are not verified."""
+        data = b"image"
+        assert verify_image_hash(data, "HASH:" + "0" * 64 + ",VOLT:80") is None
+        assert verify_image_hash(data, "HASH:abc,VOLT:80") is None
+        assert verify_image_hash(data, "VOLT:80") is None
+        assert verify_image_hash(data, f"HASH:{xxh64(data):016x},HASH_ALGO:MD5") is None
@@ -0,0 +1,164 @@
+import asyncio
+import contextlib
+import io
+import os
+import sqlite3
+import sys
+import tempfile
+import unittest
+
+# テスト対象へのパスを通す
+sys.path.insert(0, os.path.join(os.path.dirname(__file__), "..", ".."))
+sys.path.insert(0, os.path.join(os.path.dirname(__file__), "..", "..", ".."))
+
+import farmverse_db
+from processors.streaming_image_processor import StreamingImageProcessor
+from storage.image_index import ImageIndex, ImageRecord
+
+MAC_A = "aa:bb:cc:dd:ee:01"
+MAC_B = "aa:bb:cc:dd:ee:02"
+
+
+class TestImageIndex(unittest.TestCase):
+    def setUp(self):
+        self.temp_dir = tempfile.TemporaryDirectory()
+        self.db_path = os.path.join(self.temp_dir.name, "index", "images.sqlite3")
+        self.index = ImageIndex(self.db_path)
+
+    def tearDown(self):
+        self.index.close()
+        self.temp_dir.cleanup()
+
+    def _record(self, device, status, recorded_at, **kwargs):
+        self.index.record(
+            ImageRecord.from_transfer(
+                device=device,
+                started_at=recorded_at - 2,
+                size=20000,
+                status=status,
+                recorded_at=recorded_at,
+                **kwargs,
+            )
+        )
+
+    def test_records_telemetry_from_hash_frame(self):
+        self._record(
+            MAC_A.upper(),
+            "verified",
+            1000.0,
+            hash_data="HASH:abc123,HASH_ALGO:sha256,VOLT:87,TEMP:23.5,CAPTURE_UNIX_MS:998500",
+            path="images/aabbccddee01_x.jpg",
+            chunks=12,
+        )
+        [record] = self.index.last(device=MAC_A)
+        self.assertEqual(record.device, MAC_A)
+        self.assertEqual(record.hash, "abc123")
+        self.assertEqual(record.hash_algo, "sha256")
+        self.assertEqual(record.voltage, 87.0)
+        self.assertEqual(record.temperature, 23.5)
+        self.assertEqual(record.captured_at, 998.5)
+        self.assertEqual(record.chunks, 12)
+
+    def test_shutter_time_temperature_takes_precedence(self):
+        self._record(MAC_A, "verified", 1000.0, hash_data="HASH:abc123,VOLT:87,TEMP:21.0,AMB_TEMP:23.5,AMB_DT_MS:780")
+        [record] = self.index.last(device=MAC_A)
+        self.assertEqual(record.temperature, 23.5)
+        self.assertEqual(record.voltage, 87.0)
+
+    def test_last_filters_by_device_and_failure(self):
+        self._record(MAC_A, "verified", 1000.0)
+        self._record(MAC_A, "corrupt", 1010.0)
+        self._record(MAC_B, "aborted", 1020.0, reason="Stream timeout")
+        self._record(MAC_A, "unverified", 1030.0)
+
+        self.assertEqual(self.index.last(device=MAC_A)[0].status, "unverified")
+        self.assertEqual([r.status for r in self.index.last(limit=10)], ["unverified", "aborted", "corrupt", "verified"])
+        failed = self.index.last(failed=True, limit=10)
+        self.assertEqual([(r.device, r.status) for r in failed], [(MAC_B, "aborted"), (MAC_A, "corrupt")])
+        self.assertEqual(failed[0].reason, "Stream timeout")
+        self.assertEqual(self.index.last(device=MAC_B, failed=True)[0].status, "aborted")
+
+    def test_device_id_follows_board_swap(self):
+        device_id = "3f2a9c1e-0b7d-4e58-9a61-2c4f8d0e7b15"
+        self._record(MAC_A, "verified", 1000.0, hash_data=f"HASH:abc,DEV_ID:{device_id.upper()}")
+        self._record(MAC_B, "verified", 1010.0, hash_data=f"HASH:def,DEV_ID:{device_id}")
+
+        records = self.index.last(device=device_id, limit=10)
+        self.assertEqual([(r.device, r.device_id) for r in records], [(MAC_B, device_id), (MAC_A, device_id)])
+        self.assertEqual(self.index.last(device=MAC_A)[0].device_id, device_id)
+
+    def test_adds_device_id_column_to_existing_index(self):
+        path = os.path.join(self.temp_dir.name, "old.sqlite3")
+        conn = sqlite3.connect(path)
+        conn.executescript(
+            "CREATE TABLE images (id INTEGER PRIMARY KEY AUTOINCREMENT, device TEXT NOT NULL,"
+            " started_at REAL NOT NULL, recorded_at REAL NOT NULL, captured_at REAL, size INTEGER NOT NULL,"
+            " hash TEXT, hash_algo TEXT, status TEXT NOT NULL, path TEXT, voltage REAL, temperature REAL,"
+            " chunks INTEGER NOT NULL DEFAULT 0, reason TEXT);"
+            f" INSERT INTO images (device, started_at, recorded_at, size, status) VALUES ('{MAC_A}', 1, 2, 3, 'verified');"
+        )
+        conn.close()
+
+        index = ImageIndex(path)
+        try:
+            [record] = index.last(device=MAC_A)
+            self.assertIsNone(record.device_id)
+            self.assertIsNone(record.skip_reason)
+        finally:
+            index.close()
+
+    def test_cli_prints_last_image(self):
+        self._record(MAC_A, "partial", 1000.0, path="images/aabbccddee01_partial.jpg")
+        output = io.StringIO()
+        with contextlib.redirect_stdout(output):
+            self.assertEqual(farmverse_db.main(["--db", self.db_path, "last", "--device", MAC_A]), 0)
+        self.assertIn("partial", output.getvalue())
+        self.assertIn("images/aabbccddee01_partial.jpg", output.getvalue())
+
+        with contextlib.redirect_stderr(io.StringIO()):
+            self.assertEqual(farmverse_db.main(["--db", self.db_path, "last", "--device", MAC_B]), 1)
+
+    def test_skip_counts_by_device_and_reason(self):
+        device_id = "3f2a9c1e-0b7d-4e58-9a61-2c4f8d0e7b15"
+        self._record(MAC_A, "skipped", 1000.0, hash_data="HASH:NO_IMAGE,VOLT:5,SKIP:LOW_VOLTAGE")
+        self._record(MAC_A, "skipped", 1010.0, hash_data="HASH:NO_IMAGE,VOLT:4,SKIP:LOW_VOLTAGE")
+        self._record(MAC_A, "verified", 1020.0, hash_data="HASH:abc,VOLT:60")
+        self._record(MAC_B, "skipped", 1030.0, hash_data=f"HASH:NO_IMAGE,SKIP:CAMERA_FAILED,DEV_ID:{device_id}")
+
+        self.assertEqual(self.index.last(device=MAC_B)[0].skip_reason, "CAMERA_FAILED")
+        self.assertEqual(
+            self.index.skip_counts(),
+            [(MAC_A, "LOW_VOLTAGE", 2), (device_id, "CAMERA_FAILED", 1)],
+        )
+        self.assertEqual(self.index.skip_counts(device=MAC_B), [(device_id, "CAMERA_FAILED", 1)])
+        self.assertEqual(self.index.skip_counts(since=1005.0), [(device_id, "CAMERA_FAILED", 1), (MAC_A, "LOW_VOLTAGE", 1)])
+
+        output = io.StringIO()
+        with contextlib.redirect_stdout(output):
+            self.assertEqual(farmverse_db.main(["--db", self.db_path, "skips", "--device", MAC_A]), 0)
+        self.assertEqual(output.getvalue(), f"{MAC_A}  LOW_VOLTAGE  2\n")
+
+    def test_processor_records_skipped_capture(self):
+        processor = StreamingImageProcessor(image_index=self.index)
+        asyncio.run(processor.record_skipped_capture(MAC_A, "NO_IMAGE,VOLT:7,SKIP:LOW_VOLTAGE"))
+
+        [record] = self.index.last(device=MAC_A)
+        self.assertEqual((record.status, record.skip_reason, record.size), ("skipped", "LOW_VOLTAGE", 0))
+        self.assertEqual(record.voltage, 7.0)
+
+    def test_processor_records_aborted_stream(self):
+        processor = StreamingImageProcessor(image_index=self.index)
+
+        async def run():
+            await processor.start_image_stream(MAC_B, "HASH:abc123,VOLT:40")
+            await processor.abort_stream(MAC_B, "Stream timeout")
+
+        asyncio.run(run())
+        [record] = self.index.last(device=MAC_B, failed=True)
+        self.assertEqual(record.status, "aborted")
+        self.assertEqual(record.reason, "Stream timeout")
+        self.assertEqual(record.voltage, 40.0)
+
+
+if __name__ == "__main__":
+    unittest.main()
@@ -77,7 +77,7 @@ class TestInfluxDBClientAsyncTasks:
             client._active_tasks.clear()
             
             # Verify the async methods were called with TDS voltage
-            mock_write_async.assert_called_once_with("aa:bb:cc:dd:ee:ff", 85.5, 22.3, 1.5)
+            mock_write_async.assert_called_once_with("aa:bb:cc:dd:ee:ff", 85.5, 22.3, 1.5, None)
             mock_cleanup.assert_called_once()
     
     @pytest.mark.asyncio
@@ -264,7 +264,7 @@ class TestInfluxDBClientAsyncTasks:
             client._active_tasks.clear()
             
             # Verify the async methods were called with TDS voltage
-            mock_write_async.assert_called_once_with("aa:bb:cc:dd:ee:ff", 85.5, 22.3, 3.2)
+            mock_write_async.assert_called_once_with("aa:bb:cc:dd:ee:ff", 85.5, 22.3, 3.2, None)
             mock_cleanup.assert_called_once()
 
     @pytest.mark.asyncio
@@ -298,7 +298,7 @@ class TestInfluxDBClientAsyncTasks:
             client._active_tasks.clear()
             
             # Verify the async methods were called with None for TDS voltage
-            mock_write_async.assert_called_once_with("aa:bb:cc:dd:ee:ff", 85.5, 22.3, None)
+            mock_write_async.assert_called_once_with("aa:bb:cc:dd:ee:ff", 85.5, 22.3, None, None)
             mock_cleanup.assert_called_once()
 
     @pytest.mark.asyncio
@@ -0,0 +1,153 @@
+import json
+import os
+import sys
+import tempfile
+import threading
+import unittest
+from datetime import datetime
+from http.server import BaseHTTPRequestHandler, HTTPServer
+
+# テスト対象へのパスを通す
+sys.path.insert(0, os.path.join(os.path.dirname(__file__), "..", ".."))
+sys.path.insert(0, os.path.join(os.path.dirname(__file__), "..", "..", ".."))
+
+from processors.post_processing import (
+    DatedDirectoryProcessor,
+    ImageContext,
+    PostProcessingPipeline,
+    WebhookProcessor,
+    parse_pipeline,
+)
+
+
+class _RecordingProcessor:
+    def __init__(self, name, calls, fail=False):
+        self.name = name
+        self.calls = calls
+        self.fail = fail
+
+    def process(self, context):
+        self.calls.append((self.name, context.path))
+        if self.fail:
+            raise RuntimeError("boom")
+
+
+class TestPostProcessingPipeline(unittest.TestCase):
+    def setUp(self):
+        self.temp_dir = tempfile.TemporaryDirectory()
+        self.image_dir = self.temp_dir.name
+        self.image_path = os.path.join(self.image_dir, "aabbccddeeff_20250601_120000_000000.jpg")
+        with open(self.image_path, "wb") as f:
+            f.write(b"\xff\xd8" + b"\x00" * 2000)
+
+    def tearDown(self):
+        self.temp_dir.cleanup()
+
+    def _context(self, **overrides):
+        values = dict(
+            path=self.image_path,
+            sender_mac="aa:bb:cc:dd:ee:ff",
+            size=2002,
+            saved_at=datetime(2025, 6, 1, 12, 0, 0),
+            hash_data="HASH:abcd,VOLT:80",
+        )
+        values.update(overrides)
+        return ImageContext(**values)
+
+    def test_parse_pipeline_keeps_declared_order(self):
+        pipeline = parse_pipeline(
+            """
+            [[processor]]
+            type = "thumbnail"
+            max_size = 160
+
+            [[processor]]
+            type = "dated_directory"
+            pattern = "%Y-%m"
+
+            [[processor]]
+            type = "webhook"
+            url = "http://localhost:9/hook"
+            """,
+            self.image_dir,
+        )
+        self.assertEqual(
+            [processor.name for processor in pipeline.processors],
+            ["thumbnail", "dated_directory", "webhook"],
+        )
+        self.assertEqual(pipeline.processors[0].max_size, 160)
+
+    def test_parse_pipeline_rejects_invalid_descriptions(self):
+        with self.assertRaises(ValueError):
+            parse_pipeline('[[processor]]\ntype = "unknown"', self.image_dir)
+        with self.assertRaises(ValueError):
+            parse_pipeline('[[processor]]\ntype = "thumbnail"\nwidth = 10', self.image_dir)
+        with self.assertRaises(ValueError):
+            parse_pipeline('[[processor]]\ntype = "webhook"\nurl = "file:///etc/passwd"', self.image_dir)
+        with self.assertRaises(ValueError):
+            parse_pipeline("[[processor]\n", self.image_dir)
+        self.assertEqual(len(parse_pipeline("", self.image_dir)), 0)
+
+    def test_failing_processor_does_not_stop_the_pipeline(self):
+        calls = []
+        pipeline = PostProcessingPipeline(
+            [_RecordingProcessor("first", calls, fail=True), _RecordingProcessor("second", calls)]
+        )
+        pipeline.run(self._context())
+        self.assertEqual([name for name, _ in calls], ["first", "second"])
+
+    def test_dated_directory_moves_image_and_updates_path(self):
+        context = self._context()
+        DatedDirectoryProcessor(self.image_dir, "%Y/%m/%d").process(context)
+
+        expected = os.path.join(self.image_dir, "2025", "06", "01", os.path.basename(self.image_path))
+        self.assertEqual(context.path, expected)
+        self.assertTrue(os.path.exists(expected))
+        self.assertFalse(os.path.exists(self.image_path))
+
+        # タイムラプスのシーケンス画像はマニフェストと対応させるため移動しない
+        sequence_context = self._context(path=expected, in_sequence=True)
+        DatedDirectoryProcessor(self.image_dir).process(sequence_context)
+        self.assertEqual(sequence_context.path, expected)
+
+    def test_webhook_posts_image_metadata(self):
+        received = []
+
+        class Handler(BaseHTTPRequestHandler):
+            def do_POST(self):
+                length = int(self.headers["Content-Length"])
+                received.append(json.loads(self.rfile.read(length)))
+                self.send_response(204)
+                self.end_headers()
+
+            def log_message(self, *args):
+                pass
+
+        server = HTTPServer(("127.0.0.1", 0), Handler)
+        thread = threading.Thread(target=server.handle_request)
+        thread.start()
+        try:
+            context = self._context(outputs={"thumbnail": "thumbnails/a.jpg"})
+            WebhookProcessor(f"http://127.0.0.1:{server.server_port}/hook").process(context)
+        finally:
+            thread.join(timeout=5)
+            server.server_close()
+
+        self.assertEqual(
+            received,
+            [
+                {
+                    "event": "image_saved",
+                    "mac": "aa:bb:cc:dd:ee:ff",
+                    "path": self.image_path,
+                    "size": 2002,
+                    "saved_at": "2025-06-01T12:00:00",
+                    "metadata": "HASH:abcd,VOLT:80",
+                    "outputs": {"thumbnail": "thumbnails/a.jpg"},
+                }
+            ],
+        )
+
+
+if __name__ == "__main__":
+    unittest.main()
@@ -1,5 +1,6 @@
 import os
 import sys
+import zlib
 
 import pytest
 
@@ -7,6 +8,7 @@ import pytest
 sys.path.insert(0, os.path.join(os.path.dirname(__file__), '..', '..'))
 
 from app import format_sleep_command_to_gateway, config
+from processors.sleep_controller import format_file_push_commands
 
 
 class TestSleepCommandFormatting:
@@ -81,6 +83,20 @@ class TestSleepCommandFormatting:
         assert mac_parts == expected_mac_parts
 
 
+class TestFilePushCommands:
+    """ファイル転送コマンドのフォーマットのテスト"""
+
+    def test_file_is_split_into_data_lines_with_crc(self):
+        contents = bytes(range(200))
+        commands = format_file_push_commands("aa:bb:cc:dd:ee:ff", "calib", contents)
+
+        assert commands[0] == f"PUSH_FILE aa:bb:cc:dd:ee:ff calib 200 {zlib.crc32(contents):08x}\n"
+        assert [line.split()[2] for line in commands[1:]] == ["0", "96", "192"]
+        assert bytes.fromhex("".join(line.split()[3] for line in commands[1:])) == contents
+        # ゲートウェイは1回の読み取り（256バイト）を1コマンドとして扱う
+        assert all(len(line) <= 256 for line in commands)
+
+
 class TestConfigValues:
     """設定値のテスト"""
     
@@ -14,11 +14,11 @@ sensor_data_receiver_path = os.path.abspath(os.path.join(os.path.dirname(__file_
 if sensor_data_receiver_path not in sys.path:
     sys.path.insert(0, sensor_data_receiver_path)
 
-from protocol.streaming_handler import StreamingSerialProtocol
+from protocol.streaming_handler import StreamingSerialProtocol, _format_lost_frames, _format_retry_by_busy
 from protocol.constants import (
     START_MARKER, SEQUENCE_NUM_LENGTH, 
     LENGTH_FIELD_BYTES, CHECKSUM_LENGTH, END_MARKER,
-    FRAME_TYPE_HASH
+    FRAME_TYPE_HASH, FRAME_TYPE_URGENT
 )
 
 class TestStreamingHandler(unittest.IsolatedAsyncioTestCase):
@@ -291,5 +291,173 @@ class TestStreamingHandler(unittest.IsolatedAsyncioTestCase):
         create_task.assert_not_called()
         self.assertIsNone(self.protocol._buffer_processing_task)
 
+    async def test_fec_session_recovers_lost_chunk(self):
+        """FEC告知後に欠落したDATAチャンクがパリティから復元されて順番通り処理されることをテスト"""
+        sender_mac = "01:02:03:04:05:06"
+        chunks = [b"\x11" * 4, b"\x22" * 4]
+        parity = bytes(a ^ b for a, b in zip(chunks[0], chunks[1]))
+
+        # 告知 (seq=10, K=2, M=1)
+        await self.protocol._process_streaming_fec_frame(sender_mac, bytes([0, 2, 1]), 10)
+        # seq=11 のみ受信、seq=12 は欠落
+        await self.protocol._process_streaming_data_frame(sender_mac, chunks[0], 11)
+        self.protocol.streaming_processor.process_chunk.assert_not_called()
+
+        parity_payload = (
+            bytes([1]) + (11).to_bytes(4, "little") + bytes([2, 0, 1])
+            + (4 ^ 4).to_bytes(2, "little") + parity
+        )
+        await self.protocol._process_streaming_fec_frame(sender_mac, parity_payload, 13)
+
+        calls = self.protocol.streaming_processor.process_chunk.call_args_list
+        self.assertEqual([(c.args[1], c.args[2]) for c in calls], [(chunks[0], 11), (chunks[1], 12)])
+
+        # EOFでセッション終了
+        await self.protocol._flush_fec(sender_mac, end_session=True)
+        self.assertNotIn(sender_mac, self.protocol.fec_reassemblers)
+
+    async def test_complete_frame_dispatches_hash_then_eof(self):
+        """COMPLETEフレームがHASH処理→EOF処理の順に展開されることをテスト"""
+        sender_mac = "01:02:03:04:05:06"
+        calls = []
+        self.protocol._process_streaming_hash_frame = AsyncMock(
+            side_effect=lambda mac, data, seq: calls.append(("HASH", data, seq))
+        )
+        self.protocol._process_streaming_eof_frame = AsyncMock(
+            side_effect=lambda mac, seq: calls.append(("EOF", seq))
+        )
+
+        payload = b"FRAME_ID:1,BYTES:100,DEDUP:2,EOF:1;HASH:abcd,VOLT:80"
+        await self.protocol._process_streaming_complete_frame(sender_mac, payload, 42)
+
+        self.assertEqual(calls, [("HASH", b"HASH:abcd,VOLT:80", 42), ("EOF", 42)])
+
+        # EOF未受信（HASHのみ）の場合はEOF処理を行わない
+        calls.clear()
+        payload = b"FRAME_ID:2,BYTES:0,DEDUP:0,EOF:0;HASH:abcd,VOLT:80"
+        await self.protocol._process_streaming_complete_frame(sender_mac, payload, 43)
+        self.assertEqual(calls, [("HASH", b"HASH:abcd,VOLT:80", 43)])
+
+    async def test_partial_complete_frame_saves_with_gap_map(self):
+        """救済されたCOMPLETEフレームはEOFなしでも欠落範囲付きで保存されることをテスト"""
+        sender_mac = "01:02:03:04:05:06"
+        self.protocol.streaming_processor.finalize_image_stream = AsyncMock(return_value="/tmp/img_partial.jpg")
+        self.protocol._send_sleep_command_after_eof = AsyncMock()
+        self.protocol.has_image_data_cache[sender_mac] = True
+
+        payload = b"FRAME_ID:4,BYTES:9000,DEDUP:0,EOF:0,AIR_MS:10,USB_MS:2,GAPS:30-31,PARTIAL:1"
+        with patch('protocol.streaming_handler.config') as mock_config:
+            mock_config.DRY_RUN = False
+            await self.protocol._process_streaming_complete_frame(sender_mac, payload, 44)
+
+        self.protocol.streaming_processor.finalize_image_stream.assert_awaited_once_with(
+            sender_mac, self.stats, gaps=[(30, 31)]
+        )
+        self.protocol._send_sleep_command_after_eof.assert_awaited_once_with(sender_mac)
+        self.assertNotIn(sender_mac, self.protocol.pending_gap_maps)
+
+    async def test_corrupt_retained_image_requests_resend_before_sleep(self):
+        """ハッシュ不一致の画像が保持されていればスリープより先に再送を要求することをテスト"""
+        sender_mac = "01:02:03:04:05:06"
+        self.protocol.streaming_processor.finalize_image_stream = AsyncMock(
+            return_value="/tmp/img_corrupt.jpg"
+        )
+        self.protocol._send_sleep_command_after_eof = AsyncMock()
+        self.protocol.has_image_data_cache[sender_mac] = True
+        self.protocol.retained_frames[sender_mac] = "00000000000000aa"
+        self.protocol.transport = MagicMock()
+
+        with patch('protocol.streaming_handler.config') as mock_config:
+            mock_config.DRY_RUN = False
+            mock_config.MAX_IMAGE_RESENDS = 1
+            await self.protocol._process_streaming_eof_frame(sender_mac, 45)
+
+            self.protocol.transport.write.assert_called_once_with(
+                f"RESEND_LAST {sender_mac}\n".encode("utf-8")
+            )
+            self.protocol._send_sleep_command_after_eof.assert_not_awaited()
+            self.assertNotIn(sender_mac, self.protocol.eof_processed)
+
+            # 再送後も不一致なら上限に達しているためスリープさせる
+            self.protocol.retained_frames[sender_mac] = "00000000000000aa"
+            await self.protocol._process_streaming_eof_frame(sender_mac, 46)
+
+        self.protocol.transport.write.assert_called_once()
+        self.protocol._send_sleep_command_after_eof.assert_awaited_once_with(sender_mac)
+        self.assertNotIn(sender_mac, self.protocol.resend_counts)
+
+    async def test_thumbnail_eof_does_not_send_sleep_again(self):
+        """GET_THUMBS で届いたサムネイルのEOFではスリープコマンドを送らないことをテスト"""
+        sender_mac = "01:02:03:04:05:06"
+        self.protocol.streaming_processor.finalize_image_stream = AsyncMock(
+            return_value="/tmp/thumbnails/010203040506_000041.jpg"
+        )
+        self.protocol._send_sleep_command_after_eof = AsyncMock()
+        self.protocol.has_image_data_cache[sender_mac] = True
+        self.protocol.thumbnail_transfers[sender_mac] = {"index": 1, "count": 2, "seq": 41, "captured_at": None}
+
+        with patch('protocol.streaming_handler.config') as mock_config:
+            mock_config.DRY_RUN = False
+            await self.protocol._process_streaming_eof_frame(sender_mac, 47)
+
+        self.protocol._send_sleep_command_after_eof.assert_not_awaited()
+        self.assertNotIn(sender_mac, self.protocol.thumbnail_transfers)
+
+    async def test_abort_frame_discards_active_stream(self):
+        """ABORTフレームで受信途中のストリームとFECセッションが破棄されることをテスト"""
+        sender_mac = "01:02:03:04:05:06"
+        self.protocol.streaming_processor.active_streams = {sender_mac: MagicMock()}
+        self.protocol.streaming_processor.abort_stream = AsyncMock()
+        self.protocol.fec_reassemblers[sender_mac] = MagicMock()
+
+        payload = b"FRAME_ID:3,BYTES:400,REASON:QUEUE_OVERFLOW"
+        await self.protocol._process_streaming_abort_frame(sender_mac, payload)
+
+        self.protocol.streaming_processor.abort_stream.assert_awaited_once_with(
+            sender_mac, "gateway abort (QUEUE_OVERFLOW)"
+        )
+        self.assertNotIn(sender_mac, self.protocol.fec_reassemblers)
+
+    async def test_urgent_frame_leaves_active_stream_untouched(self):
+        """転送途中に届いた緊急フレームが受信途中のストリームを妨げないことをテスト"""
+        sender_mac = "01:02:03:04:05:06"
+        stream = MagicMock()
+        self.protocol.streaming_processor.active_streams = {sender_mac: stream}
+        self.protocol.streaming_processor.abort_stream = AsyncMock()
+
+        with self.assertLogs("protocol.streaming_handler", level="WARNING") as logs:
+            # setUp でモック化したフレームタイプ別の振り分けを実際に通す
+            await StreamingSerialProtocol._process_frame_by_type(
+                self.protocol, sender_mac, FRAME_TYPE_URGENT, 0, b"FROST:-1.5"
+            )
+
+        self.assertIn(f"Urgent alert from {sender_mac}: FROST:-1.5", logs.output[0])
+        self.protocol.streaming_processor.abort_stream.assert_not_awaited()
+        self.assertIs(self.protocol.streaming_processor.active_streams[sender_mac], stream)
+
+    async def test_gateway_reboot_detected_from_stats_discontinuity(self):
+        """統計フレームの起動回数の増加・稼働時間の減少でゲートウェイの再起動を検知することをテスト"""
+        check = self.protocol._check_gateway_reboot
+        self.assertFalse(check({"BOOTS": "3", "UPTIME_S": "600"}))
+        self.assertFalse(check({"BOOTS": "3", "UPTIME_S": "630"}))
+        self.assertTrue(check({"BOOTS": "4", "UPTIME_S": "20", "RESET_REASON": "PANIC"}))
+        # 起動回数を保存できなかった再起動も稼働時間の減少で分かる
+        self.assertTrue(check({"BOOTS": "4", "UPTIME_S": "5"}))
+        self.assertFalse(check({"DATA_Q": "0/32"}))
+
+    async def test_lost_frames_listed_per_frame_type(self):
+        """FLEET_SUMMARYのフレームタイプ別の集計から失われたフレームを列挙することをテスト"""
+        frame_types = {"HASH": [4, 4, 0], "DATA": [1200, 1198, 2], "EOF": [40, 37, 3]}
+        self.assertEqual(_format_lost_frames(frame_types), "DATA:2/1200,EOF:3/40")
+        self.assertEqual(_format_lost_frames({"HASH": [4, 4, 0]}), "")
+        self.assertEqual(_format_lost_frames(None), "")
+
+    async def test_retry_rate_listed_per_busy_bucket(self):
+        """FLEET_SUMMARYの `radio` からビジー率の区分ごとの再送率を列挙することをテスト"""
+        retry_by_busy = [[900, 9], [0, 0], [120, 12], [40, 10]]
+        self.assertEqual(_format_retry_by_busy(retry_by_busy), "0-24%:1%,50-74%:10%,75-100%:25%")
+        self.assertEqual(_format_retry_by_busy([[0, 0]] * 4), "")
+        self.assertEqual(_format_retry_by_busy(None), "")
+
 if __name__ == '__main__':
     unittest.main()
@@ -0,0 +1,63 @@
+import os
+import sys
+import unittest
+
+# テスト対象へのパスを通す
+sys.path.insert(0, os.path.join(os.path.dirname(__file__), "..", ".."))
+sys.path.insert(0, os.path.join(os.path.dirname(__file__), "..", "..", ".."))
+
+from protocol.telemetry_reassembler import TelemetryReassembler
+from utils.data_parser import DataParser
+
+SENDER = "aa:bb:cc:dd:ee:ff"
+TS = "2000/01/01 00:00:00.000"
+
+
+class TestTelemetryReassembler(unittest.TestCase):
+    def test_snapshot_is_stored_and_returned_unchanged(self):
+        reassembler = TelemetryReassembler()
+        payload = f"00ff,VOLT:100,TEMP:25.0,TDS_VOLT:-999.0,{TS},SNAP:3"
+
+        self.assertEqual(reassembler.reassemble(SENDER, payload), payload)
+        self.assertEqual(
+            reassembler.snapshot(SENDER),
+            ("3", {"VOLT": "100", "TEMP": "25.0", "TDS_VOLT": "-999.0"}),
+        )
+
+    def test_delta_is_filled_from_snapshot_in_legacy_order(self):
+        reassembler = TelemetryReassembler()
+        reassembler.reassemble(SENDER, f"00ff,VOLT:100,TEMP:25.0,TDS_VOLT:-999.0,{TS},SNAP:3")
+
+        changed = reassembler.reassemble(SENDER, f"00ff,TEMP:24.5,{TS},DELTA:3")
+        self.assertEqual(changed, f"00ff,VOLT:100,TEMP:24.5,TDS_VOLT:-999.0,{TS},DELTA:3")
+        # 従来の位置による解析で値が取れる
+        split = changed.split(",")
+        self.assertEqual(DataParser.extract_temperature_with_validation(split[2], SENDER), 24.5)
+
+        unchanged = reassembler.reassemble(SENDER, f"00ff,{TS},DELTA:3")
+        self.assertEqual(unchanged, f"00ff,VOLT:100,TEMP:25.0,TDS_VOLT:-999.0,{TS},DELTA:3")
+
+    def test_delta_without_matching_snapshot_keeps_only_sent_fields(self):
+        reassembler = TelemetryReassembler()
+        with self.assertLogs("protocol.telemetry_reassembler", level="WARNING"):
+            result = reassembler.reassemble(SENDER, f"00ff,TEMP:24.5,{TS},DELTA:3")
+        self.assertEqual(result, f"00ff,TEMP:24.5,{TS},DELTA:3")
+
+        # 別の番号のスナップショットは基準にしない
+        reassembler.reassemble(SENDER, f"00ff,VOLT:100,TEMP:25.0,TDS_VOLT:-999.0,{TS},SNAP:4")
+        with self.assertLogs("protocol.telemetry_reassembler", level="WARNING"):
+            result = reassembler.reassemble(SENDER, f"00ff,{TS},DELTA:3")
+        self.assertEqual(result, f"00ff,{TS},DELTA:3")
+
+    def test_snapshots_are_per_sender_and_legacy_payloads_pass_through(self):
+        reassembler = TelemetryReassembler()
+        reassembler.reassemble(SENDER, f"00ff,VOLT:100,TEMP:25.0,TDS_VOLT:-999.0,{TS},SNAP:0")
+        legacy = f"00ff,VOLT:80,TEMP:21.0,TDS_VOLT:1.2,{TS},TLM_V:1"
+
+        self.assertEqual(reassembler.reassemble("11:22:33:44:55:66", legacy), legacy)
+        self.assertIsNone(reassembler.snapshot("11:22:33:44:55:66"))
+        self.assertEqual(reassembler.snapshot(SENDER)[0], "0")
+
+
+if __name__ == "__main__":
+    unittest.main()
@@ -0,0 +1,37 @@
+import os
+import sys
+
+sys.path.insert(0, os.path.join(os.path.dirname(__file__), '..', '..'))
+
+from utils.tracing import PipelineTracer, build_pipeline_spans, parse_trace_id
+
+
+def test_parse_trace_id():
+    assert parse_trace_id("HASH:abcd,VOLT:80,TRACE:0123456789abcdef,CAP_MS:180") == 0x0123456789ABCDEF
+    assert parse_trace_id("HASH:abcd,VOLT:80") is None
+    assert parse_trace_id("TRACE:zz") is None
+    assert parse_trace_id("TRACE:0000000000000000") is None
+
+
+def test_build_pipeline_spans_anchor_on_completion():
+    spans = build_pipeline_spans(100.0, air_ms=800, usb_ms=40, capture_ms=200, storage=(100.1, 100.3))
+    by_name = {span.name: span for span in spans}
+
+    assert by_name["air_transfer"].start == 99.2
+    assert by_name["air_transfer"].end == 100.0
+    assert by_name["capture"].start == 99.0
+    assert by_name["capture"].end == 99.2
+    assert round(by_name["usb_transfer"].start, 3) == 99.96
+    assert by_name["storage"].start == 100.1
+
+
+def test_build_pipeline_spans_without_gateway_timing():
+    spans = build_pipeline_spans(100.0, air_ms=None, usb_ms=None, capture_ms=None)
+    assert spans == []
+
+
+def test_tracer_disabled_without_endpoint():
+    tracer = PipelineTracer(endpoint="")
+    assert not tracer.enabled
+    # 無効時のエクスポートは何もしない
+    tracer.export(1, "aa:bb:cc:dd:ee:ff", build_pipeline_spans(1.0, 10, 1, None))
@@ -1,10 +1,14 @@
 """Shared data parsing utilities to avoid duplication across modules."""
 
 import logging
-from typing import Optional
+import re
+from typing import Dict, Optional
 
 logger = logging.getLogger(__name__)
 
+# タイムラプスのシーケンス名（ディレクトリ名に使うため英数字・-・_ のみ）
+SEQUENCE_NAME_PATTERN = re.compile(r"^[A-Za-z0-9_-]{1,32}$")
+
 
 class DataParser:
     """共通データ解析ユーティリティクラス"""
@@ -36,6 +40,147 @@ class DataParser:
         except (ValueError, IndexError):
             return None
     
+    @staticmethod
+    def parse_lifecycle(payload: str) -> Optional[Dict]:
+        """
+        起動後最初のHASHに含まれるライフサイクル情報の解析
+
+        Args:
+            payload: 解析対象のペイロード文字列
+            
+        Returns:
+            ``boot_reason``・``wake_cause``・``previous_uptime_s``・``boot_log``・``panic`` の辞書、
+            ``BOOT`` がない場合はNone。``panic`` は前回の稼働中に発生したパニックの
+            ``message``・``location``（なければNone）
+        """
+        boot_reason = DataParser.extract_value_from_payload(payload, "BOOT:")
+        if boot_reason is None:
+            return None
+        uptime = DataParser.extract_value_from_payload(payload, "PREV_UP_S:")
+        boot_log = DataParser.extract_value_from_payload(payload, "BOOT_LOG:") or ""
+        panic_message = DataParser.extract_value_from_payload(payload, "PANIC_MSG:")
+        return {
+            "boot_reason": boot_reason,
+            "wake_cause": DataParser.extract_value_from_payload(payload, "WAKE:"),
+            "previous_uptime_s": int(uptime) if uptime and uptime.isdigit() else None,
+            "boot_log": [item for item in boot_log.split("|") if item],
+            "panic": {
+                "message": panic_message,
+                "location": DataParser.extract_value_from_payload(payload, "PANIC_AT:"),
+            } if panic_message is not None else None,
+        }
+
+    @staticmethod
+    def parse_build_info(payload: str) -> Optional[Dict]:
+        """
+        起動後最初のHASHに含まれるファームウェアのビルド情報と設定ハッシュの解析
+
+        Args:
+            payload: 解析対象のペイロード文字列
+
+        Returns:
+            ``firmware``・``built_at``・``features``・``config_hash`` の辞書、
+            ``FW`` がない場合はNone
+        """
+        firmware = DataParser.extract_value_from_payload(payload, "FW:")
+        if firmware is None:
+            return None
+        built_at = DataParser.extract_value_from_payload(payload, "BUILT:")
+        features = DataParser.extract_value_from_payload(payload, "FEAT:") or ""
+        return {
+            "firmware": firmware,
+            "built_at": int(built_at) if built_at and built_at.isdigit() else None,
+            "features": [item for item in features.split("+") if item],
+            "config_hash": DataParser.extract_value_from_payload(payload, "CFG:"),
+        }
+
+    @staticmethod
+    def parse_sequence(payload: str) -> Optional[Dict]:
+        """
+        タイムラプス撮影のシーケンス情報の解析
+
+        Args:
+            payload: 解析対象のペイロード文字列
+
+        Returns:
+            ``name``・``run``・``index``・``interval_seconds`` の辞書、
+            ``SEQ`` がない、または値が不正な場合はNone
+        """
+        name = DataParser.extract_value_from_payload(payload, "SEQ:")
+        if name is None:
+            return None
+        run = DataParser.extract_value_from_payload(payload, "SEQ_RUN:") or ""
+        index = DataParser.extract_value_from_payload(payload, "SEQ_IDX:") or ""
+        interval = DataParser.extract_value_from_payload(payload, "SEQ_INT:")
+        if not SEQUENCE_NAME_PATTERN.match(name) or not re.fullmatch(r"[0-9a-f]{8}", run) or not index.isdigit():
+            logger.warning(f"Invalid sequence metadata: SEQ={name}, SEQ_RUN={run}, SEQ_IDX={index}")
+            return None
+        return {
+            "name": name,
+            "run": run,
+            "index": int(index),
+            "interval_seconds": int(interval) if interval and interval.isdigit() else None,
+        }
+
+    @staticmethod
+    def parse_thumbnail(payload: str) -> Optional[Dict]:
+        """
+        ``GET_THUMBS`` に応えて送られたサムネイルの情報の解析
+
+        Args:
+            payload: 解析対象のペイロード文字列
+
+        Returns:
+            ``index``・``count``・``seq``・``captured_at`` の辞書（``captured_at`` は時刻が未同期ならNone）、
+            ``THUMB`` がない、または値が不正な場合はNone
+        """
+        position = DataParser.extract_value_from_payload(payload, "THUMB:")
+        if position is None:
+            return None
+        index, _, count = position.partition("/")
+        seq = DataParser.extract_value_from_payload(payload, "THUMB_SEQ:") or ""
+        captured_at = DataParser.extract_value_from_payload(payload, "THUMB_AT:") or "0"
+        if not (index.isdigit() and count.isdigit() and seq.isdigit() and captured_at.isdigit()):
+            logger.warning(f"Invalid thumbnail metadata: THUMB={position}, THUMB_SEQ={seq}, THUMB_AT={captured_at}")
+            return None
+        return {
+            "index": int(index),
+            "count": int(count),
+            "seq": int(seq),
+            "captured_at": int(captured_at) or None,
+        }
+
+    @staticmethod
+    def parse_ambient(payload: str) -> Optional[Dict]:
+        """
+        撮影直後に測った環境スナップショット（``AMB_*``）の解析
+
+        Args:
+            payload: 解析対象のペイロード文字列
+
+        Returns:
+            ``temperature``・``battery``・``lux``・``offset_ms`` の辞書（送られなかった値はNone）、
+            ``AMB_DT_MS`` がない場合はNone
+        """
+        offset_ms = DataParser.extract_value_from_payload(payload, "AMB_DT_MS:")
+        if offset_ms is None or not offset_ms.isdigit():
+            return None
+
+        def number(prefix: str) -> Optional[float]:
+            value = DataParser.extract_value_from_payload(payload, prefix)
+            try:
+                return float(value) if value is not None else None
+            except ValueError:
+                return None
+
+        battery = number("AMB_BATT:")
+        return {
+            "temperature": number("AMB_TEMP:"),
+            "battery": int(battery) if battery is not None else None,
+            "lux": number("AMB_LUX:"),
+            "offset_ms": int(offset_ms),
+        }
+
     @staticmethod
     def parse_voltage_data(payload: str) -> Optional[float]:
         """
@@ -0,0 +1,128 @@
+"""受信した画像のハッシュ検証
+
+デバイスはHASHフレームの ``HASH:`` に画像ハッシュを、``HASH_ALGO:`` にそのアルゴリズムを載せる。
+``HASH_ALGO`` がないHASHフレームは従来の ``SUM``（長さとバイト和）として扱う。
+"""
+
+import hashlib
+import logging
+import string
+from typing import Callable, Dict, Optional, Tuple
+
+from .data_parser import DataParser
+
+logger = logging.getLogger(__name__)
+
+# 画像がない場合にデバイスが送るダミーのハッシュ
+DUMMY_HASH = "0" * 64
+
+_MASK64 = 0xFFFFFFFFFFFFFFFF
+_PRIME64_1 = 0x9E3779B185EBCA87
+_PRIME64_2 = 0xC2B2AE3D27D4EB4F
+_PRIME64_3 = 0x165667B19E3779F9
+_PRIME64_4 = 0x85EBCA77C2B2AE63
+_PRIME64_5 = 0x27D4EB2F165667C5
+
+
+def _rotl64(value: int, bits: int) -> int:
+    return ((value << bits) | (value >> (64 - bits))) & _MASK64
+
+
+def _xxh64_round(acc: int, lane: int) -> int:
+    acc = (acc + lane * _PRIME64_2) & _MASK64
+    return (_rotl64(acc, 31) * _PRIME64_1) & _MASK64
+
+
+def _xxh64_merge_round(acc: int, val: int) -> int:
+    acc ^= _xxh64_round(0, val)
+    return (acc * _PRIME64_1 + _PRIME64_4) & _MASK64
+
+
+def xxh64(data: bytes, seed: int = 0) -> int:
+    """xxHash64（デバイスの ``xxhash-rust`` と同じ値）"""
+    length = len(data)
+    offset = 0
+    if length >= 32:
+        v1 = (seed + _PRIME64_1 + _PRIME64_2) & _MASK64
+        v2 = (seed + _PRIME64_2) & _MASK64
+        v3 = seed & _MASK64
+        v4 = (seed - _PRIME64_1) & _MASK64
+        limit = length - 32
+        while offset <= limit:
+            v1 = _xxh64_round(v1, int.from_bytes(data[offset:offset + 8], "little"))
+            v2 = _xxh64_round(v2, int.from_bytes(data[offset + 8:offset + 16], "little"))
+            v3 = _xxh64_round(v3, int.from_bytes(data[offset + 16:offset + 24], "little"))
+            v4 = _xxh64_round(v4, int.from_bytes(data[offset + 24:offset + 32], "little"))
+            offset += 32
+        acc = (_rotl64(v1, 1) + _rotl64(v2, 7) + _rotl64(v3, 12) + _rotl64(v4, 18)) & _MASK64
+        for v in (v1, v2, v3, v4):
+            acc = _xxh64_merge_round(acc, v)
+    else:
+        acc = (seed + _PRIME64_5) & _MASK64
+
+    acc = (acc + length) & _MASK64
+
+    while offset + 8 <= length:
+        lane = int.from_bytes(data[offset:offset + 8], "little")
+        acc ^= _xxh64_round(0, lane)
+        acc = (_rotl64(acc, 27) * _PRIME64_1 + _PRIME64_4) & _MASK64
+        offset += 8
+    if offset + 4 <= length:
+        lane = int.from_bytes(data[offset:offset + 4], "little")
+        acc ^= (lane * _PRIME64_1) & _MASK64
+        acc = (_rotl64(acc, 23) * _PRIME64_2 + _PRIME64_3) & _MASK64
+        offset += 4
+    while offset < length:
+        acc ^= (data[offset] * _PRIME64_5) & _MASK64
+        acc = (_rotl64(acc, 11) * _PRIME64_1) & _MASK64
+        offset += 1
+
+    acc ^= acc >> 33
+    acc = (acc * _PRIME64_2) & _MASK64
+    acc ^= acc >> 29
+    acc = (acc * _PRIME64_3) & _MASK64
+    acc ^= acc >> 32
+    return acc
+
+
+def _sum_hash(data: bytes) -> str:
+    return f"{len(data) & 0xFFFFFFFF:08x}{sum(data) & 0xFFFFFFFF:08x}"
+
+
+# アルゴリズム名（HASH_ALGO の値）と、ハッシュの16進数の桁数・ハッシュ関数
+IMAGE_HASH_ALGORITHMS: Dict[str, Tuple[int, Callable[[bytes], str]]] = {
+    "SUM": (16, _sum_hash),
+    "XXH64": (16, lambda data: f"{xxh64(data):016x}"),
+    "SHA256": (64, lambda data: hashlib.sha256(data).hexdigest()),
+}
+
+
+def verify_image_hash(data: bytes, hash_data: str) -> Optional[bool]:
+    """
+    受信した画像がHASHフレームのハッシュと一致するかを検証
+
+    Args:
+        data: 受信した画像データ
+        hash_data: HASHフレームのペイロード
+
+    Returns:
+        一致すればTrue、不一致ならFalse。ハッシュがない・ダミー・桁数が合わない・
+        未知のアルゴリズムで検証できない場合はNone
+    """
+    expected = (DataParser.extract_value_from_payload(hash_data, "HASH:") or "").lower()
+    if not expected or expected == DUMMY_HASH:
+        return None
+    algo = (DataParser.extract_value_from_payload(hash_data, "HASH_ALGO:") or "SUM").upper()
+    if algo not in IMAGE_HASH_ALGORITHMS:
+        logger.warning(f"Unknown image hash algorithm: {algo}")
+        return None
+    digits, hash_fn = IMAGE_HASH_ALGORITHMS[algo]
+    if len(expected) != digits or not all(c in string.hexdigits for c in expected):
+        return None
+    return hash_fn(data) == expected
+
+
+def verify_image_file(path: str, hash_data: str) -> Optional[bool]:
+    """保存した画像ファイルを ``verify_image_hash`` で検証"""
+    with open(path, "rb") as f:
+        return verify_image_hash(f.read(), hash_data)
@@ -0,0 +1,150 @@
+"""撮影〜保存までのパイプラインのトレース（OpenTelemetry互換）
+
+デバイスはサイクルごとに64ビットのトレースIDをHASHペイロードに ``TRACE:<16桁の16進数>`` として載せ、
+ゲートウェイは完了イベント（COMPLETEフレーム）に無線受信時間 ``AIR_MS`` とUSB転送時間 ``USB_MS`` を付加します。
+ホストはこれらと保存処理の時間から次のスパンを組み立て、OTLPエンドポイントが設定されていればエクスポートします。
+
+- ``capture``: デバイスでの撮影（``CAP_MS``）
+- ``air_transfer``: ゲートウェイでの最初のフレーム受信から最後のフレーム受信まで
+- ``usb_transfer``: ゲートウェイでのUSB転送時間の合計（フレームごとの転送は無線受信と並行するため近似）
+- ``storage``: ホストでの画像保存
+
+ゲートウェイとデバイスの時計はホストと同期していないため、各スパンは完了イベントの受信時刻を基準に
+所要時間から逆算して配置します。OpenTelemetryのトレースIDは128ビットのため、上位64ビットは0とします。
+"""
+
+from __future__ import annotations
+
+import logging
+import os
+from dataclasses import dataclass, field
+from typing import Dict, List, Optional
+
+logger = logging.getLogger(__name__)
+
+SERVICE_NAME = "sensor-data-reciver"
+
+
+@dataclass
+class PipelineSpan:
+    """1区間のスパン（時刻はエポック秒）"""
+
+    name: str
+    start: float
+    end: float
+    attributes: Dict[str, object] = field(default_factory=dict)
+
+
+def parse_trace_id(payload: str) -> Optional[int]:
+    """HASHペイロードからトレースIDを取り出す（無効値0や形式不正はNone）"""
+    for part in payload.split(","):
+        if part.startswith("TRACE:"):
+            try:
+                trace_id = int(part[len("TRACE:"):], 16)
+            except ValueError:
+                return None
+            return trace_id if 0 < trace_id < 1 << 64 else None
+    return None
+
+
+def build_pipeline_spans(
+    completed_at: float,
+    air_ms: Optional[int],
+    usb_ms: Optional[int],
+    capture_ms: Optional[int],
+    storage: Optional[tuple[float, float]] = None,
+) -> List[PipelineSpan]:
+    """完了イベントの受信時刻を基準にパイプラインのスパンを組み立てる"""
+    air_start = completed_at - (air_ms or 0) / 1000.0
+    spans = []
+    if capture_ms is not None:
+        spans.append(PipelineSpan("capture", air_start - capture_ms / 1000.0, air_start))
+    if air_ms is not None:
+        spans.append(PipelineSpan("air_transfer", air_start, completed_at))
+    if usb_ms is not None:
+        spans.append(
+            PipelineSpan(
+                "usb_transfer",
+                completed_at - usb_ms / 1000.0,
+                completed_at,
+                {"farmverse.usb_ms.aggregated": True},
+            )
+        )
+    if storage is not None:
+        spans.append(PipelineSpan("storage", storage[0], storage[1]))
+    return spans
+
+
+class PipelineTracer:
+    """スパンをOTLPエンドポイントへエクスポートする
+
+    ``OTEL_EXPORTER_OTLP_ENDPOINT`` が未設定、またはOpenTelemetry SDKが未インストールの場合は何もしません
+    （``uv sync --extra tracing`` でインストールできます）。
+    """
+
+    def __init__(self, endpoint: Optional[str] = None):
+        self._tracer = None
+        self._id_generator = None
+        endpoint = endpoint if endpoint is not None else os.environ.get("OTEL_EXPORTER_OTLP_ENDPOINT", "")
+        if not endpoint:
+            return
+        try:
+            from opentelemetry.exporter.otlp.proto.http.trace_exporter import OTLPSpanExporter
+            from opentelemetry.sdk.resources import Resource
+            from opentelemetry.sdk.trace import TracerProvider
+            from opentelemetry.sdk.trace.export import BatchSpanProcessor
+            from opentelemetry.sdk.trace.id_generator import RandomIdGenerator
+        except ImportError:
+            logger.warning("OTEL_EXPORTER_OTLP_ENDPOINT is set but opentelemetry-sdk is not installed")
+            return
+
+        class _DeviceTraceIdGenerator(RandomIdGenerator):
+            """ルートスパンにデバイスが発行したトレースIDを使う"""
+
+            next_trace_id: Optional[int] = None
+
+            def generate_trace_id(self) -> int:
+                trace_id, self.next_trace_id = self.next_trace_id, None
+                return trace_id or super().generate_trace_id()
+
+        self._id_generator = _DeviceTraceIdGenerator()
+        provider = TracerProvider(
+            resource=Resource.create({"service.name": os.environ.get("OTEL_SERVICE_NAME", SERVICE_NAME)}),
+            id_generator=self._id_generator,
+        )
+        provider.add_span_processor(BatchSpanProcessor(OTLPSpanExporter(endpoint=_traces_url(endpoint))))
+        self._tracer = provider.get_tracer(__name__)
+        logger.info(f"Pipeline tracing enabled (OTLP endpoint: {endpoint})")
+
+    @property
+    def enabled(self) -> bool:
+        return self._tracer is not None
+
+    def export(self, trace_id: int, sender_mac: str, spans: List[PipelineSpan]) -> None:
+        """1サイクル分のスパンをルートスパン ``image_cycle`` の子としてエクスポートする"""
+        if self._tracer is None or not spans:
+            return
+        from opentelemetry import trace
+
+        self._id_generator.next_trace_id = trace_id
+        root = self._tracer.start_span(
+            "image_cycle",
+            start_time=_to_ns(min(span.start for span in spans)),
+            attributes={"farmverse.sender_mac": sender_mac, "farmverse.trace_id": f"{trace_id:016x}"},
+        )
+        context = trace.set_span_in_context(root)
+        for span in spans:
+            child = self._tracer.start_span(
+                span.name, context=context, start_time=_to_ns(span.start), attributes=span.attributes
+            )
+            child.end(end_time=_to_ns(span.end))
+        root.end(end_time=_to_ns(max(span.end for span in spans)))
+
+
+def _traces_url(endpoint: str) -> str:
+    endpoint = endpoint.rstrip("/")
+    return endpoint if endpoint.endswith("/v1/traces") else f"{endpoint}/v1/traces"
+
+
+def _to_ns(seconds: float) -> int:
+    return int(seconds * 1_000_000_000)
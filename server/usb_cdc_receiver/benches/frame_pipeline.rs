@@ -0,0 +1,109 @@
+//! ESP-NOWフレーム処理のマイクロベンチマーク
+//!
+//! リファクタリング前後で数値を比較できるよう、ホストでビルド可能な
+//! フレームのシリアライズ・チェックサム計算・バイト列からのフレーム抽出を計測します。
+//!
+//! 実行方法: `./run_benches.sh`（結果は `target/criterion/` に保存され、次回実行時に差分が表示されます）
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use usb_cdc_receiver::esp_now::frame::{calculate_checksum, Frame, FRAME_OVERHEAD, START_MARKER};
+use usb_cdc_receiver::esp_now::FrameType;
+
+const MAC: [u8; 6] = [0x34, 0xab, 0x95, 0xfb, 0x3f, 0xc4];
+
+/// ESP-NOWの1パケットに収まるチャンクサイズ（250バイト - フレームのオーバーヘッド）を含む代表値
+const CHUNK_SIZES: [usize; 4] = [64, 128, 250 - FRAME_OVERHEAD, 1024];
+
+/// 破損させるフレームの割合（%）
+const CORRUPTION_PERCENTS: [usize; 3] = [0, 1, 10];
+
+/// 抽出ベンチマークで連結するフレーム数
+const FRAMES_PER_STREAM: usize = 256;
+
+fn payload(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i * 31 % 251) as u8).collect()
+}
+
+/// 指定割合のフレームのペイロードを1バイト書き換えた連続ストリームを作成
+fn build_stream(chunk_size: usize, corruption_percent: usize) -> Vec<u8> {
+    let data = payload(chunk_size);
+    let corrupt_every = (corruption_percent > 0).then(|| 100 / corruption_percent);
+    let mut stream = Vec::with_capacity(FRAMES_PER_STREAM * (chunk_size + FRAME_OVERHEAD));
+    for sequence in 0..FRAMES_PER_STREAM {
+        let mut bytes = Frame::new(MAC, FrameType::Data, sequence as u32, data.clone()).to_bytes();
+        if corrupt_every.is_some_and(|every| sequence % every == 0) {
+            let middle = FRAME_OVERHEAD / 2 + chunk_size / 2;
+            bytes[middle] ^= 0xFF;
+        }
+        stream.extend_from_slice(&bytes);
+    }
+    stream
+}
+
+/// ストリームからフレームを抽出し、解析に失敗した位置では次の開始マーカーまで読み飛ばす
+fn extract_frames(stream: &[u8]) -> usize {
+    let marker = START_MARKER.to_be_bytes();
+    let mut offset = 0;
+    let mut extracted = 0;
+    while offset < stream.len() {
+        match Frame::from_bytes(&stream[offset..]) {
+            Ok((_, consumed)) => {
+                extracted += 1;
+                offset += consumed;
+            }
+            Err(_) => {
+                match stream[offset + 1..]
+                    .windows(marker.len())
+                    .position(|window| window == marker)
+                {
+                    Some(skip) => offset += skip + 1,
+                    None => break,
+                }
+            }
+        }
+    }
+    extracted
+}
+
+fn bench_serialize(c: &mut Criterion) {
+    let mut group = c.benchmark_group("frame_serialize");
+    for chunk_size in CHUNK_SIZES {
+        let frame = Frame::new(MAC, FrameType::Data, 1, payload(chunk_size));
+        group.throughput(Throughput::Bytes(chunk_size as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(chunk_size), &frame, |b, frame| {
+            b.iter(|| black_box(frame).to_bytes())
+        });
+    }
+    group.finish();
+}
+
+fn bench_checksum(c: &mut Criterion) {
+    let mut group = c.benchmark_group("frame_checksum");
+    for chunk_size in CHUNK_SIZES {
+        let data = payload(chunk_size);
+        group.throughput(Throughput::Bytes(chunk_size as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(chunk_size), &data, |b, data| {
+            b.iter(|| calculate_checksum(black_box(data)))
+        });
+    }
+    group.finish();
+}
+
+fn bench_extract(c: &mut Criterion) {
+    let mut group = c.benchmark_group("frame_extract");
+    for chunk_size in CHUNK_SIZES {
+        for corruption_percent in CORRUPTION_PERCENTS {
+            let stream = build_stream(chunk_size, corruption_percent);
+            group.throughput(Throughput::Elements(FRAMES_PER_STREAM as u64));
+            group.bench_with_input(
+                BenchmarkId::new(format!("chunk_{}", chunk_size), format!("corrupt_{}pct", corruption_percent)),
+                &stream,
+                |b, stream| b.iter(|| extract_frames(black_box(stream))),
+            );
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_serialize, bench_checksum, bench_extract);
+criterion_main!(benches);
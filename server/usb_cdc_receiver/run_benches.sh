@@ -0,0 +1,13 @@
+#!/bin/bash
+
+# USB CDC Receiver ホストマシンベンチマーク実行スクリプト
+# 引数はcriterionのフィルタとして渡されます（例: ./run_benches.sh frame_extract）
+set -e  # エラーで停止
+
+# ホストアーキテクチャを検出
+HOST_TARGET=$(rustc --version --verbose | grep host | awk '{print $2}')
+echo "🖥️  ホストターゲット: $HOST_TARGET"
+echo ""
+
+# --targetオプションでホストターゲットを明示的に指定（.cargo/config.tomlのESP32-C3設定を上書き）
+cargo +stable bench --bench frame_pipeline --target "$HOST_TARGET" --no-default-features --features host -- "$@"
@@ -24,7 +24,7 @@ echo ""
 # --targetオプションでホストターゲットを明示的に指定
 # これにより.cargo/config.tomlのESP32-C3設定を上書きできます
 echo "🧪 すべてのユニットテスト実行..."
-RUST_BACKTRACE=1 cargo test --lib --tests --target "$HOST_TARGET" --no-default-features
+RUST_BACKTRACE=1 cargo +stable test --lib --tests --target "$HOST_TARGET" --no-default-features --features host
 
 echo ""
 echo "================================"
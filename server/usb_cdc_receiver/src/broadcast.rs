@@ -0,0 +1,188 @@
+//! 全デバイス向け設定の一斉配信と適用状況の集計
+//!
+//! `BROADCAST_CONFIG` で登録した設定は、メンテナンスタスクが署名してブロードキャストアドレスへ送信します。
+//! デバイスは起動中しか受信できないため、配信後に転送を終えたデバイスが適用済みの設定ID
+//! （HASHフレームの `BCAST_ID`）を報告しなければ、その場で再配信します。
+//! 報告されたIDからデバイスごとの適用状況を記録し、`BROADCAST_STATUS` で返します。
+
+use std::collections::BTreeMap;
+
+use crate::esp_now::telemetry::telemetry_field;
+use crate::mac_address::format_mac_address;
+
+/// 一斉配信コマンド応答の接頭辞
+pub const BROADCAST_RESPONSE_PREFIX: &str = "CMD_BROADCAST:";
+
+/// HASHペイロードからデバイスが適用済みの設定IDを解析します
+pub fn reported_broadcast_id(payload: &[u8]) -> Option<u32> {
+    telemetry_field(payload, "BCAST_ID")?.parse().ok()
+}
+
+/// 送信すべき配信
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PendingBroadcast {
+    /// 設定ID（初回送信前はNone、送信時にコマンドカウンタから払い出す）
+    pub config_id: Option<u32>,
+    /// 設定文字列
+    pub config: String,
+}
+
+/// 配信中の設定
+#[derive(Debug)]
+struct ActiveBroadcast {
+    config: String,
+    config_id: Option<u32>,
+    transmit_due: bool,
+    /// 配信後に転送を終えたデバイスと適用済みかどうか
+    devices: BTreeMap<[u8; 6], bool>,
+}
+
+/// 一斉配信の状態
+#[derive(Debug, Default)]
+pub struct BroadcastTracker {
+    active: Option<ActiveBroadcast>,
+}
+
+impl BroadcastTracker {
+    /// 配信なしの状態を作成します
+    pub const fn new() -> Self {
+        Self { active: None }
+    }
+
+    /// 新しい設定の配信を始めます（配信中の設定は置き換え）
+    pub fn start(&mut self, config: String) {
+        self.active = Some(ActiveBroadcast {
+            config,
+            config_id: None,
+            transmit_due: true,
+            devices: BTreeMap::new(),
+        });
+    }
+
+    /// 配信を取り消します
+    pub fn cancel(&mut self) {
+        self.active = None;
+    }
+
+    /// 送信すべき配信を取り出します（取り出すと次の再配信要求まで返さない）
+    pub fn take_due(&mut self) -> Option<PendingBroadcast> {
+        let active = self.active.as_mut().filter(|active| active.transmit_due)?;
+        active.transmit_due = false;
+        Some(PendingBroadcast {
+            config_id: active.config_id,
+            config: active.config.clone(),
+        })
+    }
+
+    /// 初回送信時に払い出した設定IDを記録します
+    pub fn assign_id(&mut self, config_id: u32) {
+        if let Some(active) = self.active.as_mut() {
+            active.config_id.get_or_insert(config_id);
+        }
+    }
+
+    /// デバイスの転送完了時に適用状況を記録します
+    ///
+    /// 一度も送信できていない配信（設定IDが未定）は集計せず、送信だけを予約し直します。
+    ///
+    /// # 戻り値
+    /// * 未適用のため再配信を予約した場合はtrue
+    pub fn record_check_in(&mut self, mac: [u8; 6], reported_id: Option<u32>) -> bool {
+        let Some(active) = self.active.as_mut() else {
+            return false;
+        };
+        let Some(config_id) = active.config_id else {
+            active.transmit_due = true;
+            return true;
+        };
+        let applied = reported_id.is_some_and(|id| id >= config_id);
+        active.devices.insert(mac, applied);
+        if !applied {
+            active.transmit_due = true;
+        }
+        !applied
+    }
+
+    /// 配信後に転送を終え、まだ適用を報告していないデバイスか
+    pub fn is_pending(&self, mac: &[u8; 6]) -> bool {
+        self.active
+            .as_ref()
+            .and_then(|active| active.devices.get(mac))
+            .is_some_and(|applied| !applied)
+    }
+
+    /// `BROADCAST_CONFIG`・`BROADCAST_STATUS` への応答（概要1行とデバイスごとの1行）
+    pub fn response(&self) -> String {
+        let Some(active) = &self.active else {
+            return format!("{}none\n", BROADCAST_RESPONSE_PREFIX);
+        };
+        let acked = active.devices.values().filter(|applied| **applied).count();
+        let mut response = format!(
+            "{}id={} config={} acked={} pending={}\n",
+            BROADCAST_RESPONSE_PREFIX,
+            active.config_id.map_or_else(|| "-".to_string(), |id| id.to_string()),
+            active.config,
+            acked,
+            active.devices.len() - acked
+        );
+        for (mac, applied) in &active.devices {
+            response.push_str(&format!(
+                "{}{} {}\n",
+                BROADCAST_RESPONSE_PREFIX,
+                format_mac_address(mac),
+                if *applied { "acked" } else { "pending" }
+            ));
+        }
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAC_A: [u8; 6] = [0x34, 0xab, 0x95, 0xfb, 0x3f, 0xc4];
+    const MAC_B: [u8; 6] = [0x34, 0xab, 0x95, 0xfb, 0x3f, 0xc5];
+
+    #[test]
+    fn test_parse_reported_broadcast_id() {
+        assert_eq!(reported_broadcast_id(b"HASH:ab,VOLT:80,BCAST_ID:12,TRACE:00"), Some(12));
+        assert_eq!(reported_broadcast_id(b"HASH:ab,VOLT:80"), None);
+        assert_eq!(reported_broadcast_id(b"HASH:ab,BCAST_ID:x"), None);
+    }
+
+    #[test]
+    fn test_unacked_check_in_schedules_rebroadcast() {
+        let mut tracker = BroadcastTracker::new();
+        assert_eq!(tracker.response(), "CMD_BROADCAST:none\n");
+        tracker.start("SLEEP=900".to_string());
+
+        // 初回送信に失敗した配信は集計せず、次のチェックインで送り直す
+        assert_eq!(tracker.take_due().unwrap().config_id, None);
+        assert!(tracker.record_check_in(MAC_A, None));
+        assert_eq!(tracker.take_due().unwrap().config_id, None);
+        tracker.assign_id(40);
+        assert_eq!(tracker.take_due(), None);
+
+        // 未適用のデバイスが転送を終えると同じIDで再配信する
+        assert!(tracker.record_check_in(MAC_A, Some(39)));
+        assert!(!tracker.record_check_in(MAC_B, Some(40)));
+        assert_eq!(
+            tracker.take_due(),
+            Some(PendingBroadcast { config_id: Some(40), config: "SLEEP=900".to_string() })
+        );
+        assert_eq!(
+            tracker.response(),
+            "CMD_BROADCAST:id=40 config=SLEEP=900 acked=1 pending=1\n\
+             CMD_BROADCAST:34:ab:95:fb:3f:c4 pending\n\
+             CMD_BROADCAST:34:ab:95:fb:3f:c5 acked\n"
+        );
+
+        assert!(!tracker.record_check_in(MAC_A, Some(40)));
+        assert_eq!(tracker.take_due(), None);
+
+        // 新しい設定は新しいIDで配信し直す
+        tracker.start("SLEEP=600".to_string());
+        assert_eq!(tracker.take_due().unwrap().config_id, None);
+    }
+}
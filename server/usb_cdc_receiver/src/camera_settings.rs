@@ -0,0 +1,183 @@
+//! PCからのフィードバックによるカメラ画質設定（JPEG品質・フレームサイズ）
+//!
+//! `SET_QUALITY` で登録した設定は、デバイスが次に転送を終えたとき（スリープコマンドを待って
+//! 受信中の間）に設定更新として送信します。デバイスは次回の撮影から適用し、HASHフレームの
+//! `JPEG_Q`・`FRAME_SIZE` で実際の設定を報告します。報告が登録した設定と一致した時点で登録を
+//! 取り除き、一致しない間は転送のたびに送り直します。
+
+use std::collections::BTreeMap;
+
+use crate::esp_now::telemetry::TelemetryFields;
+use crate::mac_address::format_mac_address;
+
+/// 画質設定コマンド応答の接頭辞
+pub const QUALITY_RESPONSE_PREFIX: &str = "CMD_QUALITY:";
+
+/// JPEG品質の範囲（小さいほど高画質、0はデバイスの既定値の意味で予約）
+pub const MIN_JPEG_QUALITY: u8 = 1;
+/// JPEG品質の上限
+pub const MAX_JPEG_QUALITY: u8 = 63;
+
+/// 指定可能なフレームサイズ（esp32-cameraの名称）
+pub const FRAME_SIZES: &[&str] = &[
+    "96X96", "QQVGA", "QCIF", "HQVGA", "240X240", "QVGA", "CIF", "HVGA", "VGA", "SVGA", "XGA", "HD", "SXGA",
+    "UXGA", "FHD", "P_HD", "P_3MP", "QXGA", "QHD", "WQXGA", "P_FHD", "QSXGA",
+];
+
+/// デバイスへ送る画質設定（未指定の項目は変更しない）
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CameraSettings {
+    /// JPEG品質
+    pub jpeg_quality: Option<u8>,
+    /// フレームサイズ（大文字の名称）
+    pub frame_size: Option<String>,
+}
+
+impl CameraSettings {
+    /// 設定更新メッセージの設定文字列（`JPEG_Q=<n>;FRAME_SIZE=<名称>`）
+    pub fn encode(&self) -> String {
+        let mut fields = Vec::new();
+        if let Some(quality) = self.jpeg_quality {
+            fields.push(format!("JPEG_Q={}", quality));
+        }
+        if let Some(frame_size) = &self.frame_size {
+            fields.push(format!("FRAME_SIZE={}", frame_size));
+        }
+        fields.join(";")
+    }
+
+    /// HASHペイロードで報告された設定が登録した設定と一致するか
+    pub fn is_reported_in(&self, payload: &[u8]) -> bool {
+        let Some(telemetry) = TelemetryFields::parse(payload) else {
+            return false;
+        };
+        let field = |key: &str| telemetry.get(key);
+        let quality_matches = self
+            .jpeg_quality
+            .is_none_or(|quality| field("JPEG_Q").and_then(|value| value.parse().ok()) == Some(quality));
+        let size_matches = self
+            .frame_size
+            .as_deref()
+            .is_none_or(|size| field("FRAME_SIZE").is_some_and(|value| value.eq_ignore_ascii_case(size)));
+        quality_matches && size_matches
+    }
+}
+
+/// 転送完了時の登録の扱い
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckIn {
+    /// 登録なし
+    Idle,
+    /// 報告が登録と一致したため登録を取り除いた
+    Confirmed,
+    /// 未適用のため送信を予約した
+    Deliver,
+}
+
+#[derive(Debug)]
+struct PendingSettings {
+    settings: CameraSettings,
+    due: bool,
+}
+
+/// デバイスごとの未適用の画質設定
+#[derive(Debug, Default)]
+pub struct CameraSettingsRegistry {
+    pending: BTreeMap<[u8; 6], PendingSettings>,
+}
+
+impl CameraSettingsRegistry {
+    /// 空の登録を作成します
+    pub const fn new() -> Self {
+        Self { pending: BTreeMap::new() }
+    }
+
+    /// 画質設定を登録します（未適用の登録は置き換え）
+    pub fn request(&mut self, mac: [u8; 6], settings: CameraSettings) {
+        self.pending.insert(mac, PendingSettings { settings, due: false });
+    }
+
+    /// デバイスの転送完了時にHASHペイロードの報告と照合します
+    pub fn record_check_in(&mut self, mac: &[u8; 6], payload: Option<&[u8]>) -> CheckIn {
+        let Some(pending) = self.pending.get_mut(mac) else {
+            return CheckIn::Idle;
+        };
+        if payload.is_some_and(|payload| pending.settings.is_reported_in(payload)) {
+            self.pending.remove(mac);
+            return CheckIn::Confirmed;
+        }
+        pending.due = true;
+        CheckIn::Deliver
+    }
+
+    /// 送信を予約した設定を1件取り出します
+    pub fn take_due(&mut self) -> Option<([u8; 6], CameraSettings)> {
+        let (mac, pending) = self.pending.iter_mut().find(|(_, pending)| pending.due)?;
+        pending.due = false;
+        Some((*mac, pending.settings.clone()))
+    }
+
+    /// 未適用の設定が登録されているか
+    pub fn is_pending(&self, mac: &[u8; 6]) -> bool {
+        self.pending.contains_key(mac)
+    }
+
+    /// `SET_QUALITY` への応答行
+    pub fn response(&self, mac: &[u8; 6]) -> String {
+        let mac_str = format_mac_address(mac);
+        match self.pending.get(mac) {
+            Some(pending) => format!(
+                "{}{} pending {}\n",
+                QUALITY_RESPONSE_PREFIX,
+                mac_str,
+                pending.settings.encode()
+            ),
+            None => format!("{}{} applied\n", QUALITY_RESPONSE_PREFIX, mac_str),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAC: [u8; 6] = [0x34, 0xab, 0x95, 0xfb, 0x3f, 0xc4];
+
+    #[test]
+    fn test_settings_are_delivered_until_reported() {
+        let settings = CameraSettings {
+            jpeg_quality: Some(20),
+            frame_size: Some("VGA".to_string()),
+        };
+        assert_eq!(settings.encode(), "JPEG_Q=20;FRAME_SIZE=VGA");
+
+        let mut registry = CameraSettingsRegistry::new();
+        assert_eq!(registry.record_check_in(&MAC, None), CheckIn::Idle);
+        registry.request(MAC, settings.clone());
+        assert_eq!(registry.take_due(), None);
+        assert_eq!(registry.response(&MAC), "CMD_QUALITY:34:ab:95:fb:3f:c4 pending JPEG_Q=20;FRAME_SIZE=VGA\n");
+
+        // 旧設定の報告では送信を予約する
+        let old = b"HASH:ab,JPEG_Q:12,FRAME_SIZE:SVGA";
+        assert_eq!(registry.record_check_in(&MAC, Some(old)), CheckIn::Deliver);
+        assert_eq!(registry.take_due(), Some((MAC, settings)));
+        assert_eq!(registry.take_due(), None);
+
+        // 適用後の報告で登録を取り除く
+        let applied = b"HASH:ab,JPEG_Q:20,FRAME_SIZE:vga";
+        assert_eq!(registry.record_check_in(&MAC, Some(applied)), CheckIn::Confirmed);
+        assert_eq!(registry.record_check_in(&MAC, Some(old)), CheckIn::Idle);
+        assert_eq!(registry.response(&MAC), "CMD_QUALITY:34:ab:95:fb:3f:c4 applied\n");
+    }
+
+    #[test]
+    fn test_partial_settings_only_check_given_fields() {
+        let settings = CameraSettings {
+            jpeg_quality: None,
+            frame_size: Some("QVGA".to_string()),
+        };
+        assert_eq!(settings.encode(), "FRAME_SIZE=QVGA");
+        assert!(settings.is_reported_in(b"HASH:ab,JPEG_Q:12,FRAME_SIZE:QVGA"));
+        assert!(!settings.is_reported_in(b"HASH:ab,JPEG_Q:12"));
+    }
+}
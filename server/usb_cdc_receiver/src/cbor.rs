@@ -0,0 +1,151 @@
+//! 最小限のCBOR（RFC 8949）エンコーダー
+//!
+//! PCへ送る集計フレームを、外部クレートなしでコンパクトに符号化するために使用します。
+//! 長さを先に指定する定長の配列・マップのみ扱います。
+
+/// メジャータイプ
+const MAJOR_UNSIGNED: u8 = 0;
+const MAJOR_NEGATIVE: u8 = 1;
+const MAJOR_TEXT: u8 = 3;
+const MAJOR_ARRAY: u8 = 4;
+const MAJOR_MAP: u8 = 5;
+/// null（単純値22）
+const NULL: u8 = 0xf6;
+
+/// CBORのバイト列を組み立てます
+#[derive(Debug, Default)]
+pub struct CborWriter {
+    buffer: Vec<u8>,
+}
+
+impl CborWriter {
+    /// 空のライターを作成します
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn head(&mut self, major: u8, value: u64) {
+        let major = major << 5;
+        match value {
+            0..=23 => self.buffer.push(major | value as u8),
+            24..=0xff => self.buffer.extend_from_slice(&[major | 24, value as u8]),
+            0x100..=0xffff => {
+                self.buffer.push(major | 25);
+                self.buffer.extend_from_slice(&(value as u16).to_be_bytes());
+            }
+            0x1_0000..=0xffff_ffff => {
+                self.buffer.push(major | 26);
+                self.buffer.extend_from_slice(&(value as u32).to_be_bytes());
+            }
+            _ => {
+                self.buffer.push(major | 27);
+                self.buffer.extend_from_slice(&value.to_be_bytes());
+            }
+        }
+    }
+
+    /// 符号なし整数
+    pub fn uint(&mut self, value: u64) -> &mut Self {
+        self.head(MAJOR_UNSIGNED, value);
+        self
+    }
+
+    /// 符号付き整数
+    pub fn int(&mut self, value: i64) -> &mut Self {
+        if value >= 0 {
+            self.head(MAJOR_UNSIGNED, value as u64);
+        } else {
+            // 負の整数は -1 - n として符号化する
+            self.head(MAJOR_NEGATIVE, !(value as u64));
+        }
+        self
+    }
+
+    /// UTF-8文字列
+    pub fn text(&mut self, value: &str) -> &mut Self {
+        self.head(MAJOR_TEXT, value.len() as u64);
+        self.buffer.extend_from_slice(value.as_bytes());
+        self
+    }
+
+    /// null
+    pub fn null(&mut self) -> &mut Self {
+        self.buffer.push(NULL);
+        self
+    }
+
+    /// 値があれば符号なし整数、なければnull
+    pub fn opt_uint(&mut self, value: Option<u64>) -> &mut Self {
+        match value {
+            Some(value) => self.uint(value),
+            None => self.null(),
+        }
+    }
+
+    /// 値があれば符号付き整数、なければnull
+    pub fn opt_int(&mut self, value: Option<i64>) -> &mut Self {
+        match value {
+            Some(value) => self.int(value),
+            None => self.null(),
+        }
+    }
+
+    /// 値があればUTF-8文字列、なければnull
+    pub fn opt_text(&mut self, value: Option<&str>) -> &mut Self {
+        match value {
+            Some(value) => self.text(value),
+            None => self.null(),
+        }
+    }
+
+    /// 要素数を指定した配列の開始（続けて要素を書き込む）
+    pub fn array(&mut self, len: usize) -> &mut Self {
+        self.head(MAJOR_ARRAY, len as u64);
+        self
+    }
+
+    /// 項目数を指定したマップの開始（続けてキーと値を交互に書き込む）
+    pub fn map(&mut self, len: usize) -> &mut Self {
+        self.head(MAJOR_MAP, len as u64);
+        self
+    }
+
+    /// 符号化したバイト列を取り出します
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.buffer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encodes_rfc8949_examples() {
+        let mut writer = CborWriter::new();
+        writer.uint(10).uint(24).uint(1000).uint(1_000_000).uint(1_000_000_000_000);
+        writer.int(-1).int(-100).null();
+        assert_eq!(
+            writer.into_bytes(),
+            [
+                &[0x0a][..],
+                &[0x18, 0x18],
+                &[0x19, 0x03, 0xe8],
+                &[0x1a, 0x00, 0x0f, 0x42, 0x40],
+                &[0x1b, 0x00, 0x00, 0x00, 0xe8, 0xd4, 0xa5, 0x10, 0x00],
+                &[0x20],
+                &[0x38, 0x63],
+                &[0xf6],
+            ]
+            .concat()
+        );
+
+        // {"a": 1, "b": [2, 3]}
+        let mut writer = CborWriter::new();
+        writer.map(2).text("a").uint(1).text("b").array(2).uint(2).uint(3);
+        assert_eq!(
+            writer.into_bytes(),
+            vec![0xa2, 0x61, 0x61, 0x01, 0x61, 0x62, 0x82, 0x02, 0x03]
+        );
+    }
+}
@@ -0,0 +1,264 @@
+//! チャンネルの混雑度（ビジー率）の推定
+//!
+//! 密集した現場で2.4GHz帯の混雑を数値で確かめられるよう、`channel-monitor` フィーチャーでは
+//! Wi-Fiのプロミスキャス受信で聞こえたすべてのフレーム（他のネットワークを含む）の通信時間を
+//! 長さと変調レートから見積もって積算し、メンテナンスタスクが一定間隔で前回との差分から
+//! チャンネルのビジー率（電波が使われていた時間の割合）を求めます。
+//! 復調できない弱い電波や他の方式の干渉は数えないため、実際の混雑より小さめの値になります。
+//!
+//! 直近の推定値はSTATSフレーム（`BUSY_CH`・`BUSY_PCT`・`RX_FPS`・`FOREIGN_PCT`）に、期間の集計は
+//! フリートサマリーの `radio` に載せます。フリートサマリーではビジー率の区分ごとにデバイスの再送の
+//! 割合を並べるため、混雑時だけ再送が増えるならチャンネルの変更、常に多いなら設置位置の見直しの目安になります。
+
+/// ビジー率の区分の数（25%ごと）
+pub const BUSY_BUCKET_COUNT: usize = 4;
+
+/// ESP-NOWのフレーム（ベンダー固有のActionフレーム）のカテゴリ
+const VENDOR_SPECIFIC_CATEGORY: u8 = 0x7f;
+/// ESP-NOWのフレームのOUI（Espressif）
+const ESPRESSIF_OUI: [u8; 3] = [0x18, 0xfe, 0x34];
+/// 802.11のMACヘッダ長（管理フレーム）
+const MGMT_HEADER_LEN: usize = 24;
+
+/// DSSS（1/2/5.5/11Mbps）のロングプリアンブルの時間（マイクロ秒）
+const DSSS_LONG_PREAMBLE_US: u32 = 192;
+/// DSSSのショートプリアンブルの時間（マイクロ秒）
+const DSSS_SHORT_PREAMBLE_US: u32 = 96;
+/// OFDM（802.11g）のプリアンブルとSIGNALの時間（マイクロ秒）
+const OFDM_PREAMBLE_US: u32 = 20;
+/// HT（802.11n、混在モード）のプリアンブルの時間（マイクロ秒）
+const HT_PREAMBLE_US: u32 = 36;
+
+/// 非HTの変調レート（`wifi_phy_rate_t` の値、kbps）と、DSSSならプリアンブルの時間
+fn legacy_rate(rate: u8) -> Option<(u32, u32)> {
+    Some(match rate {
+        0x00 => (1_000, DSSS_LONG_PREAMBLE_US),
+        0x01 => (2_000, DSSS_LONG_PREAMBLE_US),
+        0x02 => (5_500, DSSS_LONG_PREAMBLE_US),
+        0x03 => (11_000, DSSS_LONG_PREAMBLE_US),
+        0x05 => (2_000, DSSS_SHORT_PREAMBLE_US),
+        0x06 => (5_500, DSSS_SHORT_PREAMBLE_US),
+        0x07 => (11_000, DSSS_SHORT_PREAMBLE_US),
+        0x08 => (48_000, OFDM_PREAMBLE_US),
+        0x09 => (24_000, OFDM_PREAMBLE_US),
+        0x0a => (12_000, OFDM_PREAMBLE_US),
+        0x0b => (6_000, OFDM_PREAMBLE_US),
+        0x0c => (54_000, OFDM_PREAMBLE_US),
+        0x0d => (36_000, OFDM_PREAMBLE_US),
+        0x0e => (18_000, OFDM_PREAMBLE_US),
+        0x0f => (9_000, OFDM_PREAMBLE_US),
+        _ => return None,
+    })
+}
+
+/// HTのMCS 0-7の変調レート（20MHz、ロングGI、kbps）
+const HT20_RATES_KBPS: [u32; 8] = [6_500, 13_000, 19_500, 26_000, 39_000, 52_000, 58_500, 65_000];
+
+/// 受信フレームの変調の情報（`wifi_pkt_rx_ctrl_t` の値）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RxRate {
+    /// 0: 非HT（11b/g）、1: HT（11n）
+    pub sig_mode: u8,
+    /// 非HTの変調レート（`wifi_phy_rate_t` の値）
+    pub rate: u8,
+    /// HTのMCS
+    pub mcs: u8,
+    /// HTで40MHz幅か
+    pub cwb: bool,
+}
+
+/// フレームの通信時間の見積もり（マイクロ秒、変調が不明ならNone）
+pub fn frame_airtime_us(rx_rate: RxRate, len: u16) -> Option<u32> {
+    let (rate_kbps, preamble_us) = match rx_rate.sig_mode {
+        0 => legacy_rate(rx_rate.rate)?,
+        1 => {
+            let rate = *HT20_RATES_KBPS.get(usize::from(rx_rate.mcs))?;
+            (if rx_rate.cwb { rate * 27 / 13 } else { rate }, HT_PREAMBLE_US)
+        }
+        _ => return None,
+    };
+    Some(preamble_us + (u32::from(len) * 8 * 1000).div_ceil(rate_kbps))
+}
+
+/// 管理フレームがESP-NOWのフレームか（ベンダー固有のActionフレームでOUIがEspressif）
+pub fn is_esp_now_frame(mgmt_frame: &[u8]) -> bool {
+    mgmt_frame
+        .get(MGMT_HEADER_LEN..MGMT_HEADER_LEN + 4)
+        .is_some_and(|body| body[0] == VENDOR_SPECIFIC_CATEGORY && body[1..4] == ESPRESSIF_OUI)
+}
+
+/// プロミスキャス受信の累積カウンタ（いずれも桁あふれで一周する）
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RadioCounters {
+    /// 受信したフレーム数
+    pub frames: u32,
+    /// ESP-NOW以外のフレーム数
+    pub foreign_frames: u32,
+    /// 通信時間の見積もりの合計（マイクロ秒）
+    pub airtime_us: u32,
+}
+
+/// 1回の計測区間のチャンネルの混雑度
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChannelUtilization {
+    /// 計測したチャンネル
+    pub channel: u8,
+    /// ビジー率（%）
+    pub busy_percent: u8,
+    /// 1秒あたりの受信フレーム数
+    pub frames_per_s: u32,
+    /// 受信フレームのうちESP-NOW以外の割合（%、受信なしなら0）
+    pub foreign_percent: u8,
+}
+
+impl ChannelUtilization {
+    /// 統計フレームの項目
+    pub fn stats_fields(&self) -> [(&'static str, u32); 4] {
+        [
+            ("BUSY_CH", self.channel as u32),
+            ("BUSY_PCT", self.busy_percent as u32),
+            ("RX_FPS", self.frames_per_s),
+            ("FOREIGN_PCT", self.foreign_percent as u32),
+        ]
+    }
+}
+
+/// ビジー率の区分（0: 0-24%、1: 25-49%、2: 50-74%、3: 75-100%）
+pub fn busy_bucket(busy_percent: u8) -> usize {
+    (usize::from(busy_percent) * BUSY_BUCKET_COUNT / 100).min(BUSY_BUCKET_COUNT - 1)
+}
+
+/// 累積カウンタの差分からビジー率を求めます
+#[derive(Debug, Default)]
+pub struct ChannelSampler {
+    /// 前回のカウンタ・チャンネル・時刻（マイクロ秒）
+    previous: Option<(RadioCounters, u8, u64)>,
+}
+
+impl ChannelSampler {
+    /// 新しいサンプラーを作成します
+    pub const fn new() -> Self {
+        Self { previous: None }
+    }
+
+    /// 今回のカウンタを記録し、前回からの混雑度を返します
+    ///
+    /// 初回と、区間の途中でチャンネルが変わった場合（複数のチャンネルが混ざるため）はNoneです。
+    pub fn sample(&mut self, counters: RadioCounters, channel: u8, now_us: u64) -> Option<ChannelUtilization> {
+        let (previous, previous_channel, previous_us) = self.previous.replace((counters, channel, now_us))?;
+        let elapsed_us = now_us.saturating_sub(previous_us);
+        if previous_channel != channel || elapsed_us == 0 {
+            return None;
+        }
+        let frames = counters.frames.wrapping_sub(previous.frames);
+        let foreign = counters.foreign_frames.wrapping_sub(previous.foreign_frames);
+        let airtime_us = counters.airtime_us.wrapping_sub(previous.airtime_us);
+        Some(ChannelUtilization {
+            channel,
+            busy_percent: (u64::from(airtime_us) * 100 / elapsed_us).min(100) as u8,
+            frames_per_s: (u64::from(frames) * 1_000_000 / elapsed_us) as u32,
+            foreign_percent: if frames == 0 { 0 } else { (u64::from(foreign) * 100 / u64::from(frames)) as u8 },
+        })
+    }
+}
+
+/// ビジー率の区分ごとの登録デバイスのフレーム数
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RetryCounts {
+    /// 受信したフレーム数
+    pub frames: u32,
+    /// 再送されたフレーム数（重複して届いたHASH/EOFと欠落したDATA/FEC）
+    pub retried: u32,
+}
+
+/// チャンネルごとの期間の集計
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChannelTally {
+    /// 計測回数
+    pub samples: u32,
+    busy_sum: u32,
+    /// ビジー率の最大値（%）
+    pub busy_max_percent: u8,
+    foreign_sum: u32,
+    /// ビジー率の区分ごとのフレーム数
+    pub retry_by_busy: [RetryCounts; BUSY_BUCKET_COUNT],
+}
+
+impl ChannelTally {
+    /// 計測結果を加えます
+    pub fn record(&mut self, utilization: &ChannelUtilization) {
+        self.samples += 1;
+        self.busy_sum += utilization.busy_percent as u32;
+        self.busy_max_percent = self.busy_max_percent.max(utilization.busy_percent);
+        self.foreign_sum += utilization.foreign_percent as u32;
+    }
+
+    /// ビジー率の平均（%、計測なしならNone）
+    pub fn mean_busy_percent(&self) -> Option<u64> {
+        (self.samples > 0).then(|| (self.busy_sum / self.samples) as u64)
+    }
+
+    /// ESP-NOW以外のフレームの割合の平均（%、計測なしならNone）
+    pub fn mean_foreign_percent(&self) -> Option<u64> {
+        (self.samples > 0).then(|| (self.foreign_sum / self.samples) as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn legacy(rate: u8) -> RxRate {
+        RxRate { sig_mode: 0, rate, mcs: 0, cwb: false }
+    }
+
+    #[test]
+    fn test_frame_airtime_by_modulation() {
+        // 1Mbps（ロングプリアンブル）で250バイト: 192 + 2000us
+        assert_eq!(frame_airtime_us(legacy(0x00), 250), Some(2192));
+        // 6Mbps（OFDM）で250バイト: 20 + 334us
+        assert_eq!(frame_airtime_us(legacy(0x0b), 250), Some(354));
+        // HT MCS7（20MHz）で1500バイト: 36 + 185us、40MHz幅なら約2倍の速さ
+        let ht = RxRate { sig_mode: 1, rate: 0, mcs: 7, cwb: false };
+        assert_eq!(frame_airtime_us(ht, 1500), Some(221));
+        assert_eq!(frame_airtime_us(RxRate { cwb: true, ..ht }, 1500), Some(125));
+        assert_eq!(frame_airtime_us(legacy(0x04), 250), None);
+        assert_eq!(frame_airtime_us(RxRate { sig_mode: 3, ..ht }, 250), None);
+    }
+
+    #[test]
+    fn test_esp_now_frame_is_vendor_action_frame() {
+        let mut frame = vec![0xd0, 0x00];
+        frame.resize(MGMT_HEADER_LEN, 0);
+        frame.extend([0x7f, 0x18, 0xfe, 0x34, 0x00]);
+        assert!(is_esp_now_frame(&frame));
+        frame[MGMT_HEADER_LEN + 1] = 0x00;
+        assert!(!is_esp_now_frame(&frame));
+        assert!(!is_esp_now_frame(&frame[..MGMT_HEADER_LEN]));
+    }
+
+    #[test]
+    fn test_sampler_computes_busy_ratio_from_deltas() {
+        let mut sampler = ChannelSampler::new();
+        let start = RadioCounters { frames: u32::MAX - 9, foreign_frames: 5, airtime_us: u32::MAX - 99_999 };
+        assert_eq!(sampler.sample(start, 6, 0), None);
+
+        // 10秒間に400フレーム（うち100がESP-NOW以外）、通信時間3.5秒（カウンタは一周している）
+        let next = RadioCounters {
+            frames: start.frames.wrapping_add(400),
+            foreign_frames: 105,
+            airtime_us: start.airtime_us.wrapping_add(3_500_000),
+        };
+        let utilization = sampler.sample(next, 6, 10_000_000).unwrap();
+        assert_eq!(
+            utilization,
+            ChannelUtilization { channel: 6, busy_percent: 35, frames_per_s: 40, foreign_percent: 25 }
+        );
+        assert_eq!(busy_bucket(utilization.busy_percent), 1);
+        assert_eq!((busy_bucket(0), busy_bucket(24), busy_bucket(75), busy_bucket(100)), (0, 0, 3, 3));
+
+        // チャンネルが変わった区間は捨てる
+        assert_eq!(sampler.sample(next, 1, 20_000_000), None);
+        assert_eq!(sampler.sample(next, 1, 30_000_000).unwrap().busy_percent, 0);
+    }
+}
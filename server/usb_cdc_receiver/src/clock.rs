@@ -0,0 +1,156 @@
+//! ゲートウェイの時計
+//!
+//! 統計フレームやFLEET_SUMMARYの時刻は、通常は起動からの経過時間（FreeRTOSのティック）です。
+//! `sntp` フィーチャーでビルドし、Wi-FiのSTAがアップリンク（APへの接続）を持つ場合はSNTPで時刻を同期し、
+//! 同期後はUNIX時刻（ミリ秒）も報告します。同期した時点のティックとUNIX時刻を組で保持し、以降の
+//! UNIX時刻はティックの経過から求めます。
+//!
+//! このゲートウェイはESP-NOW専用にSSIDなしのSTAで起動するため、USBのみの構成では同期されず、
+//! ティックの時刻を使い続けます（統計フレームの `CLOCK:TICK`）。
+use std::sync::Mutex;
+
+#[cfg(feature = "esp")]
+use crate::sys_wrappers::esp as sys;
+
+/// これより前のUNIX時刻は未設定の時計とみなす（2024-01-01T00:00:00Z）
+pub const MIN_VALID_UNIX_MS: u64 = 1_704_067_200_000;
+
+/// 時刻の出どころ
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClockSource {
+    /// 起動からの経過時間のみ
+    Tick,
+    /// SNTPで同期したUNIX時刻
+    Sntp,
+}
+
+impl ClockSource {
+    /// 統計フレームでの表記
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ClockSource::Tick => "TICK",
+            ClockSource::Sntp => "SNTP",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct SyncPoint {
+    uptime_ms: u64,
+    unix_ms: u64,
+}
+
+/// ティックとSNTPの同期結果から時刻を求める時計
+#[derive(Debug, Default)]
+pub struct GatewayClock {
+    synced: Option<SyncPoint>,
+    syncs: u32,
+}
+
+impl GatewayClock {
+    pub const fn new() -> Self {
+        Self { synced: None, syncs: 0 }
+    }
+
+    /// SNTPの同期を記録します（未設定とみなす時刻は無視してfalse）
+    pub fn record_sync(&mut self, uptime_ms: u64, unix_ms: u64) -> bool {
+        if unix_ms < MIN_VALID_UNIX_MS {
+            return false;
+        }
+        self.synced = Some(SyncPoint { uptime_ms, unix_ms });
+        self.syncs = self.syncs.saturating_add(1);
+        true
+    }
+
+    /// 起動からの経過時間 `uptime_ms` の時点のUNIX時刻（ミリ秒、同期前はNone）
+    pub fn unix_ms_at(&self, uptime_ms: u64) -> Option<u64> {
+        self.synced
+            .map(|sync| sync.unix_ms + uptime_ms.saturating_sub(sync.uptime_ms))
+    }
+
+    /// 時刻の出どころ
+    pub fn source(&self) -> ClockSource {
+        if self.synced.is_some() {
+            ClockSource::Sntp
+        } else {
+            ClockSource::Tick
+        }
+    }
+
+    /// 統計フレームに付加する項目
+    ///
+    /// 同期前は `CLOCK:TICK` のみ、同期後は `CLOCK:SNTP,UNIX_MS:..,SYNC_AGE_S:..,SYNCS:..` です。
+    pub fn stats_fields(&self, uptime_ms: u64) -> Vec<(&'static str, String)> {
+        let mut fields = vec![("CLOCK", self.source().as_str().to_string())];
+        if let (Some(sync), Some(unix_ms)) = (self.synced, self.unix_ms_at(uptime_ms)) {
+            fields.push(("UNIX_MS", unix_ms.to_string()));
+            fields.push(("SYNC_AGE_S", (uptime_ms.saturating_sub(sync.uptime_ms) / 1000).to_string()));
+            fields.push(("SYNCS", self.syncs.to_string()));
+        }
+        fields
+    }
+}
+
+/// ゲートウェイの時計（SNTPの同期コールバックから更新する）
+pub static GATEWAY_CLOCK: Mutex<GatewayClock> = Mutex::new(GatewayClock::new());
+
+/// 現在のUNIX時刻（ミリ秒、同期前はNone）
+#[cfg(feature = "esp")]
+pub fn now_unix_ms() -> Option<u64> {
+    let uptime_ms = sys::tick_ms();
+    GATEWAY_CLOCK.lock().ok()?.unix_ms_at(uptime_ms)
+}
+
+/// SNTPの同期を記録します（同期コールバックから呼ぶ）
+#[cfg(feature = "sntp")]
+pub fn record_sntp_sync(unix_ms: u64) -> bool {
+    let uptime_ms = sys::tick_ms();
+    GATEWAY_CLOCK
+        .lock()
+        .is_ok_and(|mut clock| clock.record_sync(uptime_ms, unix_ms))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SYNCED_UNIX_MS: u64 = 1_760_000_000_000;
+
+    #[test]
+    fn test_tick_clock_until_synced() {
+        let clock = GatewayClock::new();
+        assert_eq!(clock.unix_ms_at(5_000), None);
+        assert_eq!(clock.source(), ClockSource::Tick);
+        assert_eq!(clock.stats_fields(5_000), vec![("CLOCK", "TICK".to_string())]);
+    }
+
+    #[test]
+    fn test_unix_time_follows_ticks_after_sync() {
+        let mut clock = GatewayClock::new();
+        assert!(clock.record_sync(10_000, SYNCED_UNIX_MS));
+        assert_eq!(clock.source(), ClockSource::Sntp);
+        assert_eq!(clock.unix_ms_at(12_500), Some(SYNCED_UNIX_MS + 2_500));
+        assert_eq!(
+            clock.stats_fields(70_000),
+            vec![
+                ("CLOCK", "SNTP".to_string()),
+                ("UNIX_MS", (SYNCED_UNIX_MS + 60_000).to_string()),
+                ("SYNC_AGE_S", "60".to_string()),
+                ("SYNCS", "1".to_string()),
+            ]
+        );
+
+        // 再同期で基準を置き換える
+        assert!(clock.record_sync(20_000, SYNCED_UNIX_MS + 9_000));
+        assert_eq!(clock.unix_ms_at(21_000), Some(SYNCED_UNIX_MS + 10_000));
+    }
+
+    #[test]
+    fn test_unset_system_time_is_ignored() {
+        // SNTP未同期のシステム時刻（1970年起点）は同期として扱わない
+        let mut clock = GatewayClock::new();
+        assert!(!clock.record_sync(1_000, 1_000));
+        assert_eq!(clock.source(), ClockSource::Tick);
+        assert_eq!(clock.unix_ms_at(2_000), None);
+    }
+}
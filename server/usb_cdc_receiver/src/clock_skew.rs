@@ -0,0 +1,195 @@
+//! デバイス間の時計のずれ（クロックスキュー）の推定
+//!
+//! 時刻同期済みのデバイスはHASHに撮影時刻（`CAPTURE_UNIX_MS`）を付加します。ゲートウェイは
+//! 完了イベント送出時刻からエンドツーエンド遅延（`E2E_MS`）とHASHの片道の無線遅延の推定
+//! （疎通確認のRTT `PROBE_RTT_MS` の半分）を差し引いて、自分の時計での撮影時刻を求めます。
+//! デバイスの時計との差（オフセット）をデバイスごとに記録し、フリート全体の中央値からの
+//! ずれと、オフセットの変化率（ppm）をFLEET_SUMMARYで報告します。ゲートウェイの時計自体の
+//! ずれは全デバイスに共通のため、中央値からのずれには影響しません。
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+use crate::esp_now::telemetry::TelemetryFields;
+
+/// 変化率を求めるのに必要な最初の記録からの経過時間
+pub const MIN_DRIFT_WINDOW: Duration = Duration::from_secs(600);
+
+/// 直前の記録からこれ以上オフセットが変わった場合は、時計の再設定（再起動・再同期）とみなして記録し直す（ミリ秒）
+pub const CLOCK_RESET_THRESHOLD_MS: i64 = 10_000;
+
+/// 中央値からのずれがこれを超えるデバイスを外れ値とする（ミリ秒）
+pub const SKEW_OUTLIER_MS: i64 = 2_000;
+
+/// 変化率がこれを超えるデバイスを外れ値とする（ppm）
+pub const DRIFT_OUTLIER_PPM: i64 = 100;
+
+/// HASHペイロードから撮影時刻（`CAPTURE_UNIX_MS`）と疎通確認のRTT（`PROBE_RTT_MS`）を取り出します
+pub fn reported_capture_clock(payload: &[u8]) -> Option<(u64, Option<u32>)> {
+    let telemetry = TelemetryFields::parse(payload)?;
+    let captured_unix_ms = telemetry.get("CAPTURE_UNIX_MS")?.parse().ok()?;
+    let rtt_ms = telemetry.get("PROBE_RTT_MS").and_then(|value| value.parse().ok());
+    Some((captured_unix_ms, rtt_ms))
+}
+
+/// デバイスの時計のずれ
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClockSkew {
+    /// フリートの中央値からのずれ（ミリ秒、正はデバイスの時計が進んでいる）
+    pub skew_ms: i64,
+    /// オフセットの変化率（ppm、記録期間が短い間はNone）
+    pub drift_ppm: Option<i64>,
+}
+
+impl ClockSkew {
+    /// 外れ値か（ずれ・変化率のどちらかが閾値を超える）
+    pub fn is_outlier(&self) -> bool {
+        self.skew_ms.abs() > SKEW_OUTLIER_MS || self.drift_ppm.is_some_and(|ppm| ppm.abs() > DRIFT_OUTLIER_PPM)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct OffsetSample {
+    at: Instant,
+    offset_ms: i64,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct DeviceClock {
+    first: OffsetSample,
+    latest: OffsetSample,
+}
+
+impl DeviceClock {
+    fn drift_ppm(&self) -> Option<i64> {
+        let elapsed = self.latest.at.saturating_duration_since(self.first.at);
+        if elapsed < MIN_DRIFT_WINDOW {
+            return None;
+        }
+        let change_ms = (self.latest.offset_ms - self.first.offset_ms) as i128;
+        Some((change_ms * 1_000_000 / elapsed.as_millis() as i128) as i64)
+    }
+}
+
+/// デバイスごとの時計のオフセット
+#[derive(Debug, Default)]
+pub struct ClockSkewTable {
+    /// オフセットの基準（最初の記録の撮影時刻）
+    epoch: Option<Instant>,
+    devices: BTreeMap<[u8; 6], DeviceClock>,
+}
+
+impl ClockSkewTable {
+    /// 空の記録を作成します
+    pub const fn new() -> Self {
+        Self {
+            epoch: None,
+            devices: BTreeMap::new(),
+        }
+    }
+
+    /// 完了した転送から撮影時刻を記録します
+    ///
+    /// # 引数
+    /// * `hash_payload` - HASHペイロード（撮影時刻がなければ何もしない）
+    /// * `end_to_end_ms` - 撮影から完了イベント送出までの時間（なければ何もしない）
+    /// * `now` - 完了イベントの送出時刻
+    pub fn record(&mut self, mac: [u8; 6], hash_payload: Option<&[u8]>, end_to_end_ms: Option<u32>, now: Instant) {
+        let (Some((captured_unix_ms, rtt_ms)), Some(end_to_end_ms)) =
+            (hash_payload.and_then(reported_capture_clock), end_to_end_ms)
+        else {
+            return;
+        };
+        let transfer_ms = end_to_end_ms as u64 + rtt_ms.unwrap_or(0) as u64 / 2;
+        let Some(at) = now.checked_sub(Duration::from_millis(transfer_ms)) else {
+            return;
+        };
+        let epoch = *self.epoch.get_or_insert(at);
+        let gateway_ms = match at.checked_duration_since(epoch) {
+            Some(since) => since.as_millis() as i64,
+            None => -(epoch.saturating_duration_since(at).as_millis() as i64),
+        };
+        let sample = OffsetSample {
+            at,
+            offset_ms: captured_unix_ms as i64 - gateway_ms,
+        };
+        match self.devices.get_mut(&mac) {
+            Some(clock) if (sample.offset_ms - clock.latest.offset_ms).abs() <= CLOCK_RESET_THRESHOLD_MS => {
+                clock.latest = sample;
+            }
+            _ => {
+                self.devices.insert(mac, DeviceClock { first: sample, latest: sample });
+            }
+        }
+    }
+
+    /// 記録のあるデバイスのずれ（中央値は記録のある全デバイスの最新のオフセットから求める）
+    pub fn skews(&self) -> BTreeMap<[u8; 6], ClockSkew> {
+        let mut offsets: Vec<i64> = self.devices.values().map(|clock| clock.latest.offset_ms).collect();
+        if offsets.is_empty() {
+            return BTreeMap::new();
+        }
+        offsets.sort_unstable();
+        let median = (offsets[(offsets.len() - 1) / 2] + offsets[offsets.len() / 2]) / 2;
+        self.devices
+            .iter()
+            .map(|(mac, clock)| {
+                let skew = ClockSkew {
+                    skew_ms: clock.latest.offset_ms - median,
+                    drift_ppm: clock.drift_ppm(),
+                };
+                (*mac, skew)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAC_A: [u8; 6] = [0x34, 0xab, 0x95, 0xfb, 0x3f, 0xc4];
+    const MAC_B: [u8; 6] = [0x34, 0xab, 0x95, 0xfb, 0x3f, 0xc5];
+    const MAC_C: [u8; 6] = [0x34, 0xab, 0x95, 0xfb, 0x3f, 0xc6];
+    const BASE_UNIX_MS: u64 = 1_760_000_000_000;
+
+    fn payload(captured_unix_ms: u64) -> Vec<u8> {
+        format!("HASH:ab,VOLT:80,PROBE_RTT_MS:40,CAPTURE_AGE_MS:3000,CAPTURE_UNIX_MS:{}", captured_unix_ms).into_bytes()
+    }
+
+    #[test]
+    fn test_parse_capture_clock() {
+        assert_eq!(reported_capture_clock(&payload(BASE_UNIX_MS)), Some((BASE_UNIX_MS, Some(40))));
+        assert_eq!(reported_capture_clock(b"HASH:ab,CAPTURE_UNIX_MS:5"), Some((5, None)));
+        assert_eq!(reported_capture_clock(b"HASH:ab,CAPTURE_AGE_MS:3000"), None);
+    }
+
+    #[test]
+    fn test_skew_is_relative_to_fleet_median_and_tracks_drift() {
+        let start = Instant::now() + Duration::from_secs(60);
+        let mut table = ClockSkewTable::new();
+        assert!(table.skews().is_empty());
+
+        // 撮影はどれも完了イベントの 3020ms（E2E + RTT/2）前
+        let at = |secs: u64| start + Duration::from_secs(secs) + Duration::from_millis(3020);
+        table.record(MAC_A, Some(&payload(BASE_UNIX_MS)), Some(3000), at(0));
+        table.record(MAC_B, Some(&payload(BASE_UNIX_MS + 150)), Some(3000), at(0));
+        table.record(MAC_C, Some(&payload(BASE_UNIX_MS + 30_000 + 5_000)), Some(3000), at(30));
+        // 撮影時刻やエンドツーエンド遅延がなければ記録しない
+        table.record(MAC_A, Some(b"HASH:ab"), Some(3000), at(40));
+        table.record(MAC_A, Some(&payload(BASE_UNIX_MS)), None, at(40));
+
+        let skews = table.skews();
+        assert_eq!(skews[&MAC_A], ClockSkew { skew_ms: -150, drift_ppm: None });
+        assert_eq!(skews[&MAC_B].skew_ms, 0);
+        assert!(skews[&MAC_C].is_outlier());
+
+        // 1時間で720ms進んだ（200ppm）
+        table.record(MAC_B, Some(&payload(BASE_UNIX_MS + 150 + 3_600_720)), Some(3000), at(3600));
+        assert_eq!(table.skews()[&MAC_B].drift_ppm, Some(200));
+        assert!(table.skews()[&MAC_B].is_outlier());
+
+        // 時計が再設定された場合は記録し直す
+        table.record(MAC_B, Some(&payload(BASE_UNIX_MS + 3_600_000 + 60_000)), Some(3000), at(3600));
+        assert_eq!(table.skews()[&MAC_B].drift_ppm, None);
+    }
+}
@@ -1,5 +1,16 @@
 /// USBコマンド解析機能
 
+use crate::camera_settings::{CameraSettings, FRAME_SIZES, MAX_JPEG_QUALITY, MIN_JPEG_QUALITY};
+use crate::debug_flags::{DebugRequest, DEBUG_FLAG_NAMES, DEFAULT_DEBUG_CYCLES, MAX_DEBUG_CYCLES};
+use crate::device_groups::{is_valid_group_name, MAX_GROUP_NAME_LEN};
+use crate::emulation::{
+    DEFAULT_EMULATION_INTERVAL_S, MAX_EMULATED_DEVICES, MAX_EMULATION_INTERVAL_S, MIN_EMULATION_INTERVAL_S,
+};
+use crate::esp_now::chaos::ChaosParams;
+use crate::esp_now::{MAX_CONFIG_TEXT_LEN, MAX_FILE_SLOT_LEN};
+use crate::file_transfer::{is_valid_slot, MAX_FILE_DATA_LEN, MAX_FILE_LEN};
+use crate::release_channel::{ReleaseChannel, MAX_FIRMWARE_LEN, RELEASE_CHANNEL_NAMES};
+
 #[cfg(target_os = "espidf")]
 use log::{debug, warn};
 #[cfg(not(target_os = "espidf"))]
@@ -12,10 +23,224 @@ macro_rules! warn {
 }
 
 // ESP-NOWコマンド解析の定数
-/// ESP-NOWコマンドの期待パーツ数
+/// ESP-NOW送信コマンド名
+const SEND_ESP_NOW_COMMAND: &str = "CMD_SEND_ESP_NOW";
+/// ヘルプコマンド名
+const HELP_COMMAND: &str = "HELP";
+/// デバイス一覧コマンド名
+const LIST_DEVICES_COMMAND: &str = "LIST_DEVICES";
+/// 一時停止コマンド名
+const PAUSE_COMMAND: &str = "PAUSE";
+/// 一時停止解除コマンド名
+const RESUME_COMMAND: &str = "RESUME";
+/// 設定の一斉配信コマンド名
+const BROADCAST_CONFIG_COMMAND: &str = "BROADCAST_CONFIG";
+/// 一斉配信の状況コマンド名
+const BROADCAST_STATUS_COMMAND: &str = "BROADCAST_STATUS";
+/// 画質設定コマンド名
+const SET_QUALITY_COMMAND: &str = "SET_QUALITY";
+/// デバッグフラグ設定コマンド名
+const SET_DEBUG_COMMAND: &str = "SET_DEBUG";
+/// 画像の再送要求コマンド名
+const RESEND_LAST_COMMAND: &str = "RESEND_LAST";
+const GET_THUMBS_COMMAND: &str = "GET_THUMBS";
+/// 鍵更新コマンド名
+const ROTATE_KEY_COMMAND: &str = "ROTATE_KEY";
+/// リリースチャンネル設定コマンド名
+const SET_CHANNEL_COMMAND: &str = "SET_CHANNEL";
+/// チャンネルごとの展開ファームウェア設定コマンド名
+const SET_ROLLOUT_COMMAND: &str = "SET_ROLLOUT";
+/// グループへのデバイス登録コマンド名
+const GROUP_ADD_COMMAND: &str = "GROUP_ADD";
+/// グループのスリープコマンド名
+const GROUP_SLEEP_COMMAND: &str = "GROUP_SLEEP";
+/// グループの撮影コマンド名
+const GROUP_CAPTURE_COMMAND: &str = "GROUP_CAPTURE";
+/// ファイル転送の開始コマンド名
+const PUSH_FILE_COMMAND: &str = "PUSH_FILE";
+/// ファイル転送の内容コマンド名
+const FILE_DATA_COMMAND: &str = "FILE_DATA";
+/// 停止処理後の再起動コマンド名
+const SOFT_RESET_COMMAND: &str = "SOFT_RESET";
+/// ソークテストの集計コマンド名
+const SOAK_REPORT_COMMAND: &str = "SOAK_REPORT";
+/// ゲートウェイの統計（今回の起動からと累積）コマンド名
+const GET_STATS_COMMAND: &str = "GET_STATS";
+/// 空きヒープの推移コマンド名
+const MEMORY_TREND_COMMAND: &str = "MEMORY_TREND";
+/// デッドレターキューの一覧コマンド名
+const DLQ_LIST_COMMAND: &str = "DLQ_LIST";
+/// デッドレターキューの再送コマンド名
+const DLQ_REPLAY_COMMAND: &str = "DLQ_REPLAY";
+/// 故障注入コマンド名
+const CHAOS_COMMAND: &str = "CHAOS";
+/// 仮想デバイスのエミュレーションコマンド名
+const EMULATE_COMMAND: &str = "EMULATE";
+/// ESP-NOWコマンドの期待引数数
 /// フォーマット: CMD_SEND_ESP_NOW:XX:XX:XX:XX:XX:XX:SLEEP_SECONDS
-/// = 1(コマンド) + 6(MACアドレス) + 1(スリープ時間) = 8パーツ
-const EXPECTED_ESP_NOW_PARTS: usize = 8;
+/// = 6(MACアドレス) + 1(スリープ時間) = 7引数
+const EXPECTED_ESP_NOW_ARGS: usize = 7;
+/// スリープ時間の最小値（秒）
+pub const MIN_SLEEP_SECONDS: u32 = 1;
+/// スリープ時間の最大値（秒、24時間）
+pub const MAX_SLEEP_SECONDS: u32 = 86400;
+/// 一時停止時間の最大値（分、7日間）
+pub const MAX_PAUSE_MINUTES: u32 = 7 * 24 * 60;
+
+/// エラー応答の接頭辞
+pub const ERROR_RESPONSE_PREFIX: &str = "CMD_ERR:";
+/// ヘルプ応答の接頭辞
+pub const HELP_RESPONSE_PREFIX: &str = "CMD_HELP:";
+
+/// コマンド定義（HELP応答の生成に使用）
+#[derive(Debug, Clone, Copy)]
+pub struct CommandSpec {
+    /// コマンド名
+    pub name: &'static str,
+    /// 構文
+    pub syntax: &'static str,
+    /// 説明
+    pub description: &'static str,
+}
+
+/// 利用可能なコマンド一覧
+pub const COMMAND_SPECS: &[CommandSpec] = &[
+    CommandSpec {
+        name: SEND_ESP_NOW_COMMAND,
+        syntax: "CMD_SEND_ESP_NOW:XX:XX:XX:XX:XX:XX:SLEEP_SECONDS",
+        description: "send sleep command (1-86400s) to device",
+    },
+    CommandSpec {
+        name: LIST_DEVICES_COMMAND,
+        syntax: "LIST_DEVICES",
+        description: "list provisioned device ID, firmware build and config hash reported by each device",
+    },
+    CommandSpec {
+        name: PAUSE_COMMAND,
+        syntax: "PAUSE XX:XX:XX:XX:XX:XX MINUTES",
+        description: "answer the device's next transfer with a long sleep until the pause expires (1-10080min)",
+    },
+    CommandSpec {
+        name: RESUME_COMMAND,
+        syntax: "RESUME XX:XX:XX:XX:XX:XX",
+        description: "clear a pause set with PAUSE",
+    },
+    CommandSpec {
+        name: SET_QUALITY_COMMAND,
+        syntax: "SET_QUALITY XX:XX:XX:XX:XX:XX [jpeg_q=1-63] [size=FRAME_SIZE]",
+        description: "deliver camera quality settings on the device's next transfer; applied from the next capture",
+    },
+    CommandSpec {
+        name: SET_DEBUG_COMMAND,
+        syntax: "SET_DEBUG XX:XX:XX:XX:XX:XX DEBUG+FORCE_CAMERA+BYPASS_VOLTAGE|OFF [CYCLES]",
+        description: "enable debug/bypass flags on the device for CYCLES wake cycles (1-100, default 3); OFF clears them",
+    },
+    CommandSpec {
+        name: RESEND_LAST_COMMAND,
+        syntax: "RESEND_LAST XX:XX:XX:XX:XX:XX",
+        description: "ask the device to retransmit its last image without a new capture; send before the sleep command",
+    },
+    CommandSpec {
+        name: GET_THUMBS_COMMAND,
+        syntax: "GET_THUMBS XX:XX:XX:XX:XX:XX",
+        description: "ask the device to send the thumbnails of its recent captures (tagged THUMB:i/n); send before the sleep command",
+    },
+    CommandSpec {
+        name: ROTATE_KEY_COMMAND,
+        syntax: "ROTATE_KEY XX:XX:XX:XX:XX:XX",
+        description: "generate a new downlink key for the device and deliver it on its next transfer; replies with the rotation status",
+    },
+    CommandSpec {
+        name: SET_CHANNEL_COMMAND,
+        syntax: "SET_CHANNEL XX:XX:XX:XX:XX:XX stable|beta",
+        description: "assign the device to a release channel (default stable); firmware is offered only to devices on the manifest's channel",
+    },
+    CommandSpec {
+        name: SET_ROLLOUT_COMMAND,
+        syntax: "SET_ROLLOUT stable|beta FW|OFF",
+        description: "set the firmware build rolled out to a channel (OFF withdraws it); replies with per-channel progress",
+    },
+    CommandSpec {
+        name: GROUP_ADD_COMMAND,
+        syntax: "GROUP_ADD GROUP XX:XX:XX:XX:XX:XX [XX:XX:XX:XX:XX:XX...]",
+        description: "add devices to a group (up to 16 letters, digits, '_' or '-'); a device belongs to one group and moves if added to another",
+    },
+    CommandSpec {
+        name: GROUP_SLEEP_COMMAND,
+        syntax: "GROUP_SLEEP GROUP SLEEP_SECONDS",
+        description: "queue a sleep command (1-86400s) for every device in the group, as CMD_SEND_ESP_NOW would per device",
+    },
+    CommandSpec {
+        name: GROUP_CAPTURE_COMMAND,
+        syntax: "GROUP_CAPTURE GROUP",
+        description: "queue the shortest sleep for every device in the group so each captures right after its next transfer",
+    },
+    CommandSpec {
+        name: PUSH_FILE_COMMAND,
+        syntax: "PUSH_FILE XX:XX:XX:XX:XX:XX SLOT SIZE CRC32",
+        description: "start a file transfer (up to 4096 bytes, CRC-32 in hex) to the device's named NVS slot; send the contents with FILE_DATA",
+    },
+    CommandSpec {
+        name: FILE_DATA_COMMAND,
+        syntax: "FILE_DATA XX:XX:XX:XX:XX:XX OFFSET HEX",
+        description: "append up to 96 bytes to the file started with PUSH_FILE; once complete and verified it is delivered on the device's next transfers, resuming where the device left off",
+    },
+    CommandSpec {
+        name: BROADCAST_CONFIG_COMMAND,
+        syntax: "BROADCAST_CONFIG SLEEP=SECONDS[;RECEIVER_MAC=XX:XX:XX:XX:XX:XX]",
+        description: "broadcast signed fleet-wide config; devices stage it and report it on later transfers",
+    },
+    CommandSpec {
+        name: BROADCAST_STATUS_COMMAND,
+        syntax: "BROADCAST_STATUS",
+        description: "show which devices have applied the last broadcast config",
+    },
+    CommandSpec {
+        name: SOAK_REPORT_COMMAND,
+        syntax: "SOAK_REPORT",
+        description: "show per-device soak-test cycles, success rate, error breakdown and heap trend",
+    },
+    CommandSpec {
+        name: GET_STATS_COMMAND,
+        syntax: "GET_STATS",
+        description: "show gateway uptime, frames and errors since boot and across reboots (boot count, last reset reason)",
+    },
+    CommandSpec {
+        name: MEMORY_TREND_COMMAND,
+        syntax: "MEMORY_TREND",
+        description: "show the gateway's per-minute free heap and largest free block history with the fitted trend and estimated time to exhaustion",
+    },
+    CommandSpec {
+        name: DLQ_LIST_COMMAND,
+        syntax: "DLQ_LIST",
+        description: "list frames that could not be written to USB and were kept in the dead-letter queue",
+    },
+    CommandSpec {
+        name: DLQ_REPLAY_COMMAND,
+        syntax: "DLQ_REPLAY [ID]",
+        description: "write dead-lettered frames to USB again (all, or only ID from DLQ_LIST); failures stay queued",
+    },
+    CommandSpec {
+        name: CHAOS_COMMAND,
+        syntax: "CHAOS [drop=N,dup=N,reorder=N,corrupt=N,seed=N|OFF]",
+        description: "inject faults into received ESP-NOW frames (percentages, `chaos` builds only); without arguments shows the injected counts",
+    },
+    CommandSpec {
+        name: EMULATE_COMMAND,
+        syntax: "EMULATE COUNT|OFF [INTERVAL_S]",
+        description: "run COUNT (1-8) virtual cameras in the gateway that send a small test image with synthetic telemetry (SYNTH:1, MAC 02:fe:ed:00:00:NN) every INTERVAL_S (5-3600, default 60); OFF stops them",
+    },
+    CommandSpec {
+        name: SOFT_RESET_COMMAND,
+        syntax: "SOFT_RESET",
+        description: "flush queued frames, stop ESP-NOW and USB, then restart the gateway",
+    },
+    CommandSpec {
+        name: HELP_COMMAND,
+        syntax: "HELP",
+        description: "list available commands",
+    },
+];
 
 /// 解析されたコマンド
 #[derive(Debug, Clone)]
@@ -28,22 +253,326 @@ pub enum Command {
         /// スリープ時間（秒）
         sleep_seconds: u32,
     },
+    /// デバイスごとのビルド情報の一覧の要求
+    ListDevices,
+    /// デバイスの一時停止
+    /// フォーマット: "PAUSE MAC_ADDRESS MINUTES"
+    Pause {
+        /// 対象のMACアドレス
+        mac_address: String,
+        /// 停止時間（分）
+        minutes: u32,
+    },
+    /// デバイスの一時停止の解除
+    /// フォーマット: "RESUME MAC_ADDRESS"
+    Resume {
+        /// 対象のMACアドレス
+        mac_address: String,
+    },
+    /// カメラ画質設定
+    /// フォーマット: "SET_QUALITY MAC_ADDRESS [jpeg_q=N] [size=RES]"
+    SetQuality {
+        /// 対象のMACアドレス
+        mac_address: String,
+        /// 変更する設定
+        settings: CameraSettings,
+    },
+    /// デバッグ・バイパスフラグの一時的な有効化
+    /// フォーマット: "SET_DEBUG MAC_ADDRESS FLAGS [CYCLES]"
+    SetDebug {
+        /// 対象のMACアドレス
+        mac_address: String,
+        /// 有効にするフラグとサイクル数
+        request: DebugRequest,
+    },
+    /// 直前に送信した画像の再送要求
+    /// フォーマット: "RESEND_LAST MAC_ADDRESS"
+    ResendLast {
+        /// 対象のMACアドレス
+        mac_address: String,
+    },
+    /// 直近の撮影のサムネイルの要求
+    /// フォーマット: "GET_THUMBS MAC_ADDRESS"
+    GetThumbs {
+        /// 対象のMACアドレス
+        mac_address: String,
+    },
+    /// ダウンリンク認証鍵の更新
+    /// フォーマット: "ROTATE_KEY MAC_ADDRESS"
+    RotateKey {
+        /// 対象のMACアドレス
+        mac_address: String,
+    },
+    /// リリースチャンネルの設定
+    /// フォーマット: "SET_CHANNEL MAC_ADDRESS CHANNEL"
+    SetChannel {
+        /// 対象のMACアドレス
+        mac_address: String,
+        /// 設定するチャンネル
+        channel: ReleaseChannel,
+    },
+    /// チャンネルに展開するファームウェアの設定
+    /// フォーマット: "SET_ROLLOUT CHANNEL FW|OFF"
+    SetRollout {
+        /// 対象のチャンネル
+        channel: ReleaseChannel,
+        /// 展開するファームウェア（Noneは取り下げ）
+        firmware: Option<String>,
+    },
+    /// デバイスのグループへの登録
+    /// フォーマット: "GROUP_ADD GROUP MAC_ADDRESS [MAC_ADDRESS...]"
+    GroupAdd {
+        /// グループ名
+        group: String,
+        /// 登録するMACアドレス
+        mac_addresses: Vec<String>,
+    },
+    /// グループのメンバーへのスリープコマンド
+    /// フォーマット: "GROUP_SLEEP GROUP SLEEP_SECONDS"
+    GroupSleep {
+        /// グループ名
+        group: String,
+        /// スリープ時間（秒）
+        sleep_seconds: u32,
+    },
+    /// グループのメンバーへの撮影の要求
+    /// フォーマット: "GROUP_CAPTURE GROUP"
+    GroupCapture {
+        /// グループ名
+        group: String,
+    },
+    /// デバイスへのファイル転送の開始
+    /// フォーマット: "PUSH_FILE MAC_ADDRESS SLOT SIZE CRC32"
+    PushFile {
+        /// 対象のMACアドレス
+        mac_address: String,
+        /// 保存先のスロット名
+        slot: String,
+        /// ファイルの長さ
+        size: usize,
+        /// ファイル全体のCRC-32
+        file_crc: u32,
+    },
+    /// ファイル転送の内容
+    /// フォーマット: "FILE_DATA MAC_ADDRESS OFFSET HEX"
+    FileData {
+        /// 対象のMACアドレス
+        mac_address: String,
+        /// 内容の先頭位置
+        offset: usize,
+        /// 内容
+        data: Vec<u8>,
+    },
+    /// 全デバイス向け設定の一斉配信
+    /// フォーマット: "BROADCAST_CONFIG KEY=VALUE[;KEY=VALUE]"
+    BroadcastConfig {
+        /// 設定文字列（デバイスのリモート設定と同じ形式）
+        config: String,
+    },
+    /// 一斉配信の適用状況の要求
+    BroadcastStatus,
+    /// ソークテストの集計の要求
+    SoakReport,
+    /// ゲートウェイの統計（今回の起動からと累積）の要求
+    GetStats,
+    /// 空きヒープの推移の要求
+    MemoryTrend,
+    /// デッドレターキューの一覧の要求
+    DeadLetterList,
+    /// デッドレターキューのフレームの再送
+    /// フォーマット: "DLQ_REPLAY [ID]"
+    DeadLetterReplay {
+        /// 再送するフレームの番号（Noneはすべて）
+        id: Option<u32>,
+    },
+    /// 受信フレームへの故障注入の変更・状況の要求
+    /// フォーマット: "CHAOS [SPEC|OFF]"
+    Chaos {
+        /// 変更する設定（Noneは状況の要求、`Some(None)` は無効化）
+        params: Option<Option<ChaosParams>>,
+    },
+    /// 仮想デバイスのエミュレーションの開始・停止
+    /// フォーマット: "EMULATE COUNT|OFF [INTERVAL_S]"
+    Emulate {
+        /// 仮想デバイスの台数（0は停止）
+        devices: usize,
+        /// 転送間隔（秒）
+        interval_s: u32,
+    },
+    /// サブシステムを停止してからの再起動
+    SoftReset,
+    /// コマンド一覧の要求
+    Help,
     /// 不明なコマンド
     Unknown(String),
 }
 
 /// コマンド解析エラー
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum CommandParseError {
-    /// 無効なフォーマット
-    InvalidFormat,
+    /// 引数の数が一致しない
+    InvalidFormat {
+        command: &'static str,
+        expected_args: usize,
+        actual_args: usize,
+    },
     /// 無効なスリープ時間
-    InvalidSleepTime,
+    InvalidSleepTime(String),
     /// 無効なMACアドレス
-    InvalidMacAddress,
+    InvalidMacAddress(String),
+    /// 無効な一時停止時間
+    InvalidPauseMinutes(String),
+    /// 無効な一斉配信設定
+    InvalidBroadcastConfig(String),
+    /// 無効な画質設定
+    InvalidQualitySetting(String),
+    /// 無効なデバッグフラグ指定
+    InvalidDebugFlags(String),
+    /// 無効なデッドレターキューの番号
+    InvalidDeadLetterId(String),
+    /// 無効な故障注入の指定
+    InvalidChaosSpec(String),
+    /// 無効なエミュレーションの指定
+    InvalidEmulation(String),
+    /// 無効なリリースチャンネル
+    InvalidReleaseChannel(String),
+    /// 無効なファームウェアの指定
+    InvalidFirmware(String),
+    /// 無効なファイル転送の指定
+    InvalidFileTransfer(String),
+    /// 無効なグループ名
+    InvalidGroup(String),
+}
+
+impl std::fmt::Display for CommandParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CommandParseError::InvalidFormat {
+                command,
+                expected_args,
+                actual_args,
+            } => write!(
+                f,
+                "{}: expected {} arguments, got {} (usage: {})",
+                command,
+                expected_args,
+                actual_args,
+                usage_of(command)
+            ),
+            CommandParseError::InvalidSleepTime(value) => write!(
+                f,
+                "invalid sleep time '{}' (expected {}-{})",
+                value, MIN_SLEEP_SECONDS, MAX_SLEEP_SECONDS
+            ),
+            CommandParseError::InvalidMacAddress(value) => write!(
+                f,
+                "invalid MAC address '{}' (expected XX:XX:XX:XX:XX:XX)",
+                value
+            ),
+            CommandParseError::InvalidPauseMinutes(value) => write!(
+                f,
+                "invalid pause time '{}' (expected 1-{} minutes)",
+                value, MAX_PAUSE_MINUTES
+            ),
+            CommandParseError::InvalidQualitySetting(value) => write!(
+                f,
+                "invalid quality setting '{}' (expected jpeg_q={}-{} and/or size=<{}>)",
+                value,
+                MIN_JPEG_QUALITY,
+                MAX_JPEG_QUALITY,
+                FRAME_SIZES.join("|")
+            ),
+            CommandParseError::InvalidDebugFlags(value) => write!(
+                f,
+                "invalid debug flags '{}' (expected {} joined with '+' or OFF, cycles 1-{})",
+                value,
+                DEBUG_FLAG_NAMES.join("|"),
+                MAX_DEBUG_CYCLES
+            ),
+            CommandParseError::InvalidDeadLetterId(value) => write!(
+                f,
+                "invalid dead-letter id '{}' (expected an ID from DLQ_LIST)",
+                value
+            ),
+            CommandParseError::InvalidChaosSpec(value) => write!(
+                f,
+                "invalid chaos spec '{}' (expected drop=N,dup=N,reorder=N,corrupt=N,seed=N with percentages adding up to 100 or less, or OFF)",
+                value
+            ),
+            CommandParseError::InvalidEmulation(value) => write!(
+                f,
+                "invalid emulation setting '{}' (expected 1-{} devices or OFF, interval {}-{}s)",
+                value, MAX_EMULATED_DEVICES, MIN_EMULATION_INTERVAL_S, MAX_EMULATION_INTERVAL_S
+            ),
+            CommandParseError::InvalidReleaseChannel(value) => write!(
+                f,
+                "invalid release channel '{}' (expected {})",
+                value,
+                RELEASE_CHANNEL_NAMES.join("|")
+            ),
+            CommandParseError::InvalidFirmware(value) => write!(
+                f,
+                "invalid firmware '{}' (expected the FW value reported by devices, up to {} chars, or OFF)",
+                value, MAX_FIRMWARE_LEN
+            ),
+            CommandParseError::InvalidFileTransfer(value) => write!(
+                f,
+                "invalid file transfer argument '{}' (expected a slot of up to {} letters, digits or '_', size 1-{}, CRC-32 in hex, and up to {} bytes of hex data per FILE_DATA)",
+                value, MAX_FILE_SLOT_LEN, MAX_FILE_LEN, MAX_FILE_DATA_LEN
+            ),
+            CommandParseError::InvalidGroup(value) => write!(
+                f,
+                "invalid group '{}' (expected up to {} letters, digits, '_' or '-')",
+                value, MAX_GROUP_NAME_LEN
+            ),
+            CommandParseError::InvalidBroadcastConfig(value) => write!(
+                f,
+                "invalid broadcast config '{}' (expected SLEEP={}-{} and/or RECEIVER_MAC=XX:XX:XX:XX:XX:XX, up to {} chars)",
+                value, MIN_SLEEP_SECONDS, MAX_SLEEP_SECONDS, MAX_CONFIG_TEXT_LEN
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CommandParseError {}
+
+impl CommandParseError {
+    /// USBへ返すエラー応答行を生成します
+    pub fn to_response(&self) -> String {
+        format!("{}{}\n", ERROR_RESPONSE_PREFIX, self)
+    }
+}
+
+/// コマンド名から構文を取得します
+fn usage_of(command: &str) -> &'static str {
+    COMMAND_SPECS
+        .iter()
+        .find(|spec| spec.name == command)
+        .map(|spec| spec.syntax)
+        .unwrap_or("")
+}
+
+/// HELP応答を生成します
+///
+/// `COMMAND_SPECS` から1コマンド1行で構文と説明を列挙します。
+pub fn help_text() -> String {
+    COMMAND_SPECS
+        .iter()
+        .map(|spec| {
+            format!(
+                "{}{} - {}\n",
+                HELP_RESPONSE_PREFIX, spec.syntax, spec.description
+            )
+        })
+        .collect()
 }
 
 /// コマンド文字列を解析します
+///
+/// 引数を取るコマンドは `NAME:ARGS` の形式、引数なしのコマンドは `NAME` のみで受け付けます。
+/// `PAUSE`・`RESUME`・`SET_QUALITY`・`SET_DEBUG`・`RESEND_LAST`・`GET_THUMBS`・`ROTATE_KEY`・`SET_CHANNEL`・`SET_ROLLOUT`・
+/// `GROUP_ADD`・`GROUP_SLEEP`・`GROUP_CAPTURE`・`PUSH_FILE`・`FILE_DATA`・`BROADCAST_CONFIG`・`DLQ_REPLAY`・`CHAOS`・`EMULATE` は
+/// 空白区切りの `NAME ARGS...` の形式です。
 /// 
 /// # 引数
 /// * `command_str` - 解析するコマンド文字列
@@ -54,64 +583,104 @@ pub fn parse_command(command_str: &str) -> Result<Command, CommandParseError> {
     debug!("Parsing command: '{}'", command_str);
     
     let trimmed = command_str.trim();
+
+    if let Some((name, args)) = trimmed.split_once(char::is_whitespace) {
+        match name {
+            PAUSE_COMMAND => return parse_pause_command(args),
+            RESUME_COMMAND => return parse_resume_command(args),
+            SET_QUALITY_COMMAND => return parse_set_quality_command(args),
+            SET_DEBUG_COMMAND => return parse_set_debug_command(args),
+            RESEND_LAST_COMMAND => {
+                return parse_mac_address_arg(RESEND_LAST_COMMAND, args)
+                    .map(|mac_address| Command::ResendLast { mac_address })
+            }
+            GET_THUMBS_COMMAND => {
+                return parse_mac_address_arg(GET_THUMBS_COMMAND, args)
+                    .map(|mac_address| Command::GetThumbs { mac_address })
+            }
+            ROTATE_KEY_COMMAND => {
+                return parse_mac_address_arg(ROTATE_KEY_COMMAND, args)
+                    .map(|mac_address| Command::RotateKey { mac_address })
+            }
+            SET_CHANNEL_COMMAND => return parse_set_channel_command(args),
+            SET_ROLLOUT_COMMAND => return parse_set_rollout_command(args),
+            GROUP_ADD_COMMAND => return parse_group_add_command(args),
+            GROUP_SLEEP_COMMAND => return parse_group_sleep_command(args),
+            GROUP_CAPTURE_COMMAND => return parse_group_capture_command(args),
+            PUSH_FILE_COMMAND => return parse_push_file_command(args),
+            FILE_DATA_COMMAND => return parse_file_data_command(args),
+            BROADCAST_CONFIG_COMMAND => return parse_broadcast_config_command(args),
+            DLQ_REPLAY_COMMAND => return parse_dlq_replay_command(args),
+            CHAOS_COMMAND => return parse_chaos_command(args),
+            EMULATE_COMMAND => return parse_emulate_command(args),
+            _ => {}
+        }
+    }
     
-    if trimmed.starts_with("CMD_SEND_ESP_NOW:") {
-        parse_esp_now_command(trimmed)
-    } else {
-        warn!("Unknown command format: '{}'", trimmed);
-        Ok(Command::Unknown(trimmed.to_string()))
+    match trimmed.split_once(':') {
+        Some((SEND_ESP_NOW_COMMAND, args)) => parse_esp_now_command(args),
+        None if trimmed == LIST_DEVICES_COMMAND => Ok(Command::ListDevices),
+        None if trimmed == BROADCAST_STATUS_COMMAND => Ok(Command::BroadcastStatus),
+        None if trimmed == SOAK_REPORT_COMMAND => Ok(Command::SoakReport),
+        None if trimmed == GET_STATS_COMMAND => Ok(Command::GetStats),
+        None if trimmed == MEMORY_TREND_COMMAND => Ok(Command::MemoryTrend),
+        None if trimmed == DLQ_LIST_COMMAND => Ok(Command::DeadLetterList),
+        None if trimmed == DLQ_REPLAY_COMMAND => Ok(Command::DeadLetterReplay { id: None }),
+        None if trimmed == CHAOS_COMMAND => Ok(Command::Chaos { params: None }),
+        None if trimmed == SOFT_RESET_COMMAND => Ok(Command::SoftReset),
+        None if trimmed == HELP_COMMAND => Ok(Command::Help),
+        _ => {
+            warn!("Unknown command format: '{}'", trimmed);
+            Ok(Command::Unknown(trimmed.to_string()))
+        }
     }
 }
 
-/// ESP-NOW送信コマンドを解析します
+/// ESP-NOW送信コマンドの引数を解析します
 /// 
 /// フォーマット: "CMD_SEND_ESP_NOW:MAC_ADDRESS:SLEEP_SECONDS"
 /// 例: "CMD_SEND_ESP_NOW:34:ab:95:fb:3f:c4:60"
 /// 
 /// # 引数
-/// * `command_str` - ESP-NOWコマンド文字列
+/// * `args` - コマンド名以降の引数文字列
 /// 
 /// # 戻り値
 /// * `Result<Command, CommandParseError>` - 解析されたコマンドまたはエラー
-fn parse_esp_now_command(command_str: &str) -> Result<Command, CommandParseError> {
-    let parts: Vec<&str> = command_str.split(':').collect();
-    
-    // フォーマット: CMD_SEND_ESP_NOW:XX:XX:XX:XX:XX:XX:SLEEP_SECONDS
-    // 最低8パーツ必要 (CMD_SEND_ESP_NOW + 6つのMACアドレス + SLEEP_SECONDS)
-    if parts.len() != EXPECTED_ESP_NOW_PARTS {
-        warn!("Invalid ESP-NOW command format. Expected {} parts, got {}: '{}'", 
-              EXPECTED_ESP_NOW_PARTS, parts.len(), command_str);
-        return Err(CommandParseError::InvalidFormat);
-    }
+fn parse_esp_now_command(args: &str) -> Result<Command, CommandParseError> {
+    let parts: Vec<&str> = args.split(':').collect();
     
-    // CMD_SEND_ESP_NOWの確認
-    if parts[0] != "CMD_SEND_ESP_NOW" {
-        return Err(CommandParseError::InvalidFormat);
+    // フォーマット: XX:XX:XX:XX:XX:XX:SLEEP_SECONDS
+    if parts.len() != EXPECTED_ESP_NOW_ARGS {
+        warn!("Invalid ESP-NOW command format. Expected {} args, got {}: '{}'", 
+              EXPECTED_ESP_NOW_ARGS, parts.len(), args);
+        return Err(CommandParseError::InvalidFormat {
+            command: SEND_ESP_NOW_COMMAND,
+            expected_args: EXPECTED_ESP_NOW_ARGS,
+            actual_args: parts.len(),
+        });
     }
     
-    // MACアドレスを再構築 (parts[1]～parts[6])
-    let mac_address = format!("{}:{}:{}:{}:{}:{}", 
-                             parts[1], parts[2], parts[3], 
-                             parts[4], parts[5], parts[6]);
+    // MACアドレスを再構築 (parts[0]～parts[5])
+    let mac_address = parts[..6].join(":");
     
     // MACアドレスの妥当性をチェック
     if !is_valid_mac_address(&mac_address) {
         warn!("Invalid MAC address format: '{}'", mac_address);
-        return Err(CommandParseError::InvalidMacAddress);
+        return Err(CommandParseError::InvalidMacAddress(mac_address));
     }
     
-    // スリープ時間を解析 (parts[7])
-    let sleep_seconds = parts[7]
+    // スリープ時間を解析 (parts[6])
+    let sleep_seconds = parts[6]
         .parse::<u32>()
         .map_err(|_| {
-            warn!("Invalid sleep time: '{}'", parts[7]);
-            CommandParseError::InvalidSleepTime
+            warn!("Invalid sleep time: '{}'", parts[6]);
+            CommandParseError::InvalidSleepTime(parts[6].to_string())
         })?;
     
     // スリープ時間の妥当性をチェック (1秒～24時間)
-    if sleep_seconds == 0 || sleep_seconds > 86400 {
-        warn!("Sleep time out of range (1-86400): {}", sleep_seconds);
-        return Err(CommandParseError::InvalidSleepTime);
+    if !(MIN_SLEEP_SECONDS..=MAX_SLEEP_SECONDS).contains(&sleep_seconds) {
+        warn!("Sleep time out of range ({}-{}): {}", MIN_SLEEP_SECONDS, MAX_SLEEP_SECONDS, sleep_seconds);
+        return Err(CommandParseError::InvalidSleepTime(sleep_seconds.to_string()));
     }
     
     debug!("Parsed ESP-NOW command: MAC={}, Sleep={}s", mac_address, sleep_seconds);
@@ -122,6 +691,431 @@ fn parse_esp_now_command(command_str: &str) -> Result<Command, CommandParseError
     })
 }
 
+/// 一時停止コマンドの引数を解析します
+///
+/// フォーマット: "PAUSE MAC_ADDRESS MINUTES"
+/// 例: "PAUSE 34:ab:95:fb:3f:c4 30"
+fn parse_pause_command(args: &str) -> Result<Command, CommandParseError> {
+    let parts: Vec<&str> = args.split_whitespace().collect();
+    let [mac_address, minutes] = parts[..] else {
+        return Err(CommandParseError::InvalidFormat {
+            command: PAUSE_COMMAND,
+            expected_args: 2,
+            actual_args: parts.len(),
+        });
+    };
+    if !is_valid_mac_address(mac_address) {
+        return Err(CommandParseError::InvalidMacAddress(mac_address.to_string()));
+    }
+    let minutes = minutes
+        .parse::<u32>()
+        .ok()
+        .filter(|minutes| (1..=MAX_PAUSE_MINUTES).contains(minutes))
+        .ok_or_else(|| CommandParseError::InvalidPauseMinutes(minutes.to_string()))?;
+
+    debug!("Parsed pause command: MAC={}, {}min", mac_address, minutes);
+    Ok(Command::Pause {
+        mac_address: mac_address.to_string(),
+        minutes,
+    })
+}
+
+/// 一時停止解除コマンドの引数を解析します
+///
+/// フォーマット: "RESUME MAC_ADDRESS"
+fn parse_resume_command(args: &str) -> Result<Command, CommandParseError> {
+    let parts: Vec<&str> = args.split_whitespace().collect();
+    let [mac_address] = parts[..] else {
+        return Err(CommandParseError::InvalidFormat {
+            command: RESUME_COMMAND,
+            expected_args: 1,
+            actual_args: parts.len(),
+        });
+    };
+    if !is_valid_mac_address(mac_address) {
+        return Err(CommandParseError::InvalidMacAddress(mac_address.to_string()));
+    }
+    Ok(Command::Resume {
+        mac_address: mac_address.to_string(),
+    })
+}
+
+/// MACアドレスだけを引数に取るコマンドの引数を解析します
+///
+/// フォーマット: "NAME MAC_ADDRESS"（`RESEND_LAST`・`GET_THUMBS`・`ROTATE_KEY`）
+fn parse_mac_address_arg(command: &'static str, args: &str) -> Result<String, CommandParseError> {
+    let parts: Vec<&str> = args.split_whitespace().collect();
+    let [mac_address] = parts[..] else {
+        return Err(CommandParseError::InvalidFormat {
+            command,
+            expected_args: 1,
+            actual_args: parts.len(),
+        });
+    };
+    if !is_valid_mac_address(mac_address) {
+        return Err(CommandParseError::InvalidMacAddress(mac_address.to_string()));
+    }
+    Ok(mac_address.to_string())
+}
+
+/// 画質設定コマンドの引数を解析します
+///
+/// フォーマット: "SET_QUALITY MAC_ADDRESS [jpeg_q=N] [size=RES]"（少なくとも1項目）
+/// 例: "SET_QUALITY 34:ab:95:fb:3f:c4 jpeg_q=20 size=VGA"
+fn parse_set_quality_command(args: &str) -> Result<Command, CommandParseError> {
+    let mut parts = args.split_whitespace();
+    let mac_address = parts.next().unwrap_or_default();
+    if !is_valid_mac_address(mac_address) {
+        return Err(CommandParseError::InvalidMacAddress(mac_address.to_string()));
+    }
+    let mut settings = CameraSettings {
+        jpeg_quality: None,
+        frame_size: None,
+    };
+    for part in parts {
+        let invalid = || CommandParseError::InvalidQualitySetting(part.to_string());
+        match part.split_once('=') {
+            Some(("jpeg_q", quality)) => {
+                let quality = quality
+                    .parse::<u8>()
+                    .ok()
+                    .filter(|quality| (MIN_JPEG_QUALITY..=MAX_JPEG_QUALITY).contains(quality))
+                    .ok_or_else(invalid)?;
+                settings.jpeg_quality = Some(quality);
+            }
+            Some(("size", size)) => {
+                let size = size.to_ascii_uppercase();
+                if !FRAME_SIZES.contains(&size.as_str()) {
+                    return Err(invalid());
+                }
+                settings.frame_size = Some(size);
+            }
+            _ => return Err(invalid()),
+        }
+    }
+    if settings.jpeg_quality.is_none() && settings.frame_size.is_none() {
+        return Err(CommandParseError::InvalidQualitySetting(args.trim().to_string()));
+    }
+    debug!("Parsed quality command: MAC={}, {}", mac_address, settings.encode());
+    Ok(Command::SetQuality {
+        mac_address: mac_address.to_string(),
+        settings,
+    })
+}
+
+/// デバッグフラグ設定コマンドの引数を解析します
+///
+/// フォーマット: "SET_DEBUG MAC_ADDRESS FLAGS [CYCLES]"（FLAGS は `+` 区切り、または OFF）
+/// 例: "SET_DEBUG 34:ab:95:fb:3f:c4 DEBUG+BYPASS_VOLTAGE 5"
+fn parse_set_debug_command(args: &str) -> Result<Command, CommandParseError> {
+    let parts: Vec<&str> = args.split_whitespace().collect();
+    if !(2..=3).contains(&parts.len()) {
+        return Err(CommandParseError::InvalidFormat {
+            command: SET_DEBUG_COMMAND,
+            expected_args: 3,
+            actual_args: parts.len(),
+        });
+    }
+    let mac_address = parts[0];
+    if !is_valid_mac_address(mac_address) {
+        return Err(CommandParseError::InvalidMacAddress(mac_address.to_string()));
+    }
+    let invalid = || CommandParseError::InvalidDebugFlags(parts[1..].join(" "));
+    let cycles = match parts.get(2) {
+        Some(cycles) => cycles.parse::<u16>().map_err(|_| invalid())?,
+        None => DEFAULT_DEBUG_CYCLES,
+    };
+    let request = DebugRequest::parse(parts[1], cycles).ok_or_else(invalid)?;
+    debug!("Parsed debug command: MAC={}, {}", mac_address, request.encode());
+    Ok(Command::SetDebug {
+        mac_address: mac_address.to_string(),
+        request,
+    })
+}
+
+/// リリースチャンネル設定コマンドの引数を解析します
+///
+/// フォーマット: "SET_CHANNEL MAC_ADDRESS CHANNEL"
+/// 例: "SET_CHANNEL 34:ab:95:fb:3f:c4 beta"
+fn parse_set_channel_command(args: &str) -> Result<Command, CommandParseError> {
+    let parts: Vec<&str> = args.split_whitespace().collect();
+    let [mac_address, channel] = parts[..] else {
+        return Err(CommandParseError::InvalidFormat {
+            command: SET_CHANNEL_COMMAND,
+            expected_args: 2,
+            actual_args: parts.len(),
+        });
+    };
+    if !is_valid_mac_address(mac_address) {
+        return Err(CommandParseError::InvalidMacAddress(mac_address.to_string()));
+    }
+    let channel =
+        ReleaseChannel::parse(channel).ok_or_else(|| CommandParseError::InvalidReleaseChannel(channel.to_string()))?;
+    Ok(Command::SetChannel {
+        mac_address: mac_address.to_string(),
+        channel,
+    })
+}
+
+/// 展開ファームウェア設定コマンドの引数を解析します
+///
+/// フォーマット: "SET_ROLLOUT CHANNEL FW"（"SET_ROLLOUT CHANNEL OFF" は取り下げ）
+/// 例: "SET_ROLLOUT beta 1a2b3c4d"
+fn parse_set_rollout_command(args: &str) -> Result<Command, CommandParseError> {
+    let parts: Vec<&str> = args.split_whitespace().collect();
+    let [channel, firmware] = parts[..] else {
+        return Err(CommandParseError::InvalidFormat {
+            command: SET_ROLLOUT_COMMAND,
+            expected_args: 2,
+            actual_args: parts.len(),
+        });
+    };
+    let channel =
+        ReleaseChannel::parse(channel).ok_or_else(|| CommandParseError::InvalidReleaseChannel(channel.to_string()))?;
+    if firmware.eq_ignore_ascii_case("OFF") {
+        return Ok(Command::SetRollout { channel, firmware: None });
+    }
+    // HASHフレームの区切り文字を含む表記はデバイスが報告できない
+    if firmware.len() > MAX_FIRMWARE_LEN || firmware.contains([',', ':']) {
+        return Err(CommandParseError::InvalidFirmware(firmware.to_string()));
+    }
+    Ok(Command::SetRollout {
+        channel,
+        firmware: Some(firmware.to_string()),
+    })
+}
+
+/// グループ登録コマンドの引数を解析します
+///
+/// フォーマット: "GROUP_ADD GROUP MAC_ADDRESS [MAC_ADDRESS...]"
+/// 例: "GROUP_ADD north 34:ab:95:fb:3f:c4 34:ab:95:fb:3f:c5"
+fn parse_group_add_command(args: &str) -> Result<Command, CommandParseError> {
+    let parts: Vec<&str> = args.split_whitespace().collect();
+    let [group, _, ..] = parts[..] else {
+        return Err(CommandParseError::InvalidFormat {
+            command: GROUP_ADD_COMMAND,
+            expected_args: 2,
+            actual_args: parts.len(),
+        });
+    };
+    let mac_addresses = &parts[1..];
+    let group = parse_group_name(group)?;
+    if let Some(invalid) = mac_addresses.iter().find(|mac_address| !is_valid_mac_address(mac_address)) {
+        return Err(CommandParseError::InvalidMacAddress(invalid.to_string()));
+    }
+    Ok(Command::GroupAdd {
+        group,
+        mac_addresses: mac_addresses.iter().map(|mac_address| mac_address.to_string()).collect(),
+    })
+}
+
+/// グループのスリープコマンドの引数を解析します
+///
+/// フォーマット: "GROUP_SLEEP GROUP SLEEP_SECONDS"
+/// 例: "GROUP_SLEEP north 600"
+fn parse_group_sleep_command(args: &str) -> Result<Command, CommandParseError> {
+    let parts: Vec<&str> = args.split_whitespace().collect();
+    let [group, sleep_seconds] = parts[..] else {
+        return Err(CommandParseError::InvalidFormat {
+            command: GROUP_SLEEP_COMMAND,
+            expected_args: 2,
+            actual_args: parts.len(),
+        });
+    };
+    let group = parse_group_name(group)?;
+    let sleep_seconds = sleep_seconds
+        .parse::<u32>()
+        .ok()
+        .filter(|seconds| (MIN_SLEEP_SECONDS..=MAX_SLEEP_SECONDS).contains(seconds))
+        .ok_or_else(|| CommandParseError::InvalidSleepTime(sleep_seconds.to_string()))?;
+    Ok(Command::GroupSleep { group, sleep_seconds })
+}
+
+/// グループの撮影コマンドの引数を解析します
+///
+/// フォーマット: "GROUP_CAPTURE GROUP"
+fn parse_group_capture_command(args: &str) -> Result<Command, CommandParseError> {
+    let parts: Vec<&str> = args.split_whitespace().collect();
+    let [group] = parts[..] else {
+        return Err(CommandParseError::InvalidFormat {
+            command: GROUP_CAPTURE_COMMAND,
+            expected_args: 1,
+            actual_args: parts.len(),
+        });
+    };
+    Ok(Command::GroupCapture {
+        group: parse_group_name(group)?,
+    })
+}
+
+/// グループ名を検証します
+fn parse_group_name(group: &str) -> Result<String, CommandParseError> {
+    if !is_valid_group_name(group) {
+        return Err(CommandParseError::InvalidGroup(group.to_string()));
+    }
+    Ok(group.to_string())
+}
+
+/// ファイル転送の開始コマンドの引数を解析します
+///
+/// フォーマット: "PUSH_FILE MAC_ADDRESS SLOT SIZE CRC32"（CRC32は16進）
+/// 例: "PUSH_FILE 34:ab:95:fb:3f:c4 calib 1024 1a2b3c4d"
+fn parse_push_file_command(args: &str) -> Result<Command, CommandParseError> {
+    let parts: Vec<&str> = args.split_whitespace().collect();
+    let [mac_address, slot, size, file_crc] = parts[..] else {
+        return Err(CommandParseError::InvalidFormat {
+            command: PUSH_FILE_COMMAND,
+            expected_args: 4,
+            actual_args: parts.len(),
+        });
+    };
+    if !is_valid_mac_address(mac_address) {
+        return Err(CommandParseError::InvalidMacAddress(mac_address.to_string()));
+    }
+    if !is_valid_slot(slot) {
+        return Err(CommandParseError::InvalidFileTransfer(slot.to_string()));
+    }
+    let size = size
+        .parse::<usize>()
+        .ok()
+        .filter(|size| (1..=MAX_FILE_LEN).contains(size))
+        .ok_or_else(|| CommandParseError::InvalidFileTransfer(size.to_string()))?;
+    let file_crc =
+        u32::from_str_radix(file_crc, 16).map_err(|_| CommandParseError::InvalidFileTransfer(file_crc.to_string()))?;
+    Ok(Command::PushFile {
+        mac_address: mac_address.to_string(),
+        slot: slot.to_string(),
+        size,
+        file_crc,
+    })
+}
+
+/// ファイル転送の内容コマンドの引数を解析します
+///
+/// フォーマット: "FILE_DATA MAC_ADDRESS OFFSET HEX"
+/// 例: "FILE_DATA 34:ab:95:fb:3f:c4 0 0102ff"
+fn parse_file_data_command(args: &str) -> Result<Command, CommandParseError> {
+    let parts: Vec<&str> = args.split_whitespace().collect();
+    let [mac_address, offset, data] = parts[..] else {
+        return Err(CommandParseError::InvalidFormat {
+            command: FILE_DATA_COMMAND,
+            expected_args: 3,
+            actual_args: parts.len(),
+        });
+    };
+    if !is_valid_mac_address(mac_address) {
+        return Err(CommandParseError::InvalidMacAddress(mac_address.to_string()));
+    }
+    let offset = offset
+        .parse::<usize>()
+        .ok()
+        .filter(|offset| *offset < MAX_FILE_LEN)
+        .ok_or_else(|| CommandParseError::InvalidFileTransfer(offset.to_string()))?;
+    let data = hex::decode(data)
+        .ok()
+        .filter(|bytes| (1..=MAX_FILE_DATA_LEN).contains(&bytes.len()))
+        .ok_or_else(|| CommandParseError::InvalidFileTransfer(data.to_string()))?;
+    Ok(Command::FileData {
+        mac_address: mac_address.to_string(),
+        offset,
+        data,
+    })
+}
+
+/// 一斉配信コマンドの引数を解析します
+///
+/// フォーマット: "BROADCAST_CONFIG KEY=VALUE[;KEY=VALUE]"
+/// 例: "BROADCAST_CONFIG SLEEP=900"
+///
+/// 全デバイスへ届くため、受け付ける項目はデバイスのリモート設定のうち `SLEEP` と
+/// `RECEIVER_MAC` に限り、値もここで検証します。
+fn parse_broadcast_config_command(args: &str) -> Result<Command, CommandParseError> {
+    let config = args.trim();
+    let invalid = || CommandParseError::InvalidBroadcastConfig(config.to_string());
+    if config.is_empty() || config.len() > MAX_CONFIG_TEXT_LEN || config.contains(char::is_whitespace) {
+        return Err(invalid());
+    }
+    for field in config.split(';') {
+        let valid = match field.split_once('=') {
+            Some(("SLEEP", seconds)) => seconds
+                .parse::<u32>()
+                .is_ok_and(|seconds| (MIN_SLEEP_SECONDS..=MAX_SLEEP_SECONDS).contains(&seconds)),
+            Some(("RECEIVER_MAC", mac_address)) => is_valid_mac_address(mac_address),
+            _ => false,
+        };
+        if !valid {
+            return Err(invalid());
+        }
+    }
+    Ok(Command::BroadcastConfig {
+        config: config.to_string(),
+    })
+}
+
+/// デッドレターキューの再送コマンドの引数を解析します
+///
+/// フォーマット: "DLQ_REPLAY ID"（番号なしの "DLQ_REPLAY" はすべてを再送）
+fn parse_dlq_replay_command(args: &str) -> Result<Command, CommandParseError> {
+    let parts: Vec<&str> = args.split_whitespace().collect();
+    let [id] = parts[..] else {
+        return Err(CommandParseError::InvalidFormat {
+            command: DLQ_REPLAY_COMMAND,
+            expected_args: 1,
+            actual_args: parts.len(),
+        });
+    };
+    match id.parse::<u32>() {
+        Ok(id) if id > 0 => Ok(Command::DeadLetterReplay { id: Some(id) }),
+        _ => Err(CommandParseError::InvalidDeadLetterId(id.to_string())),
+    }
+}
+
+/// 故障注入コマンドの引数を解析します
+///
+/// フォーマット: "CHAOS drop=N,dup=N,reorder=N,corrupt=N,seed=N" または "CHAOS OFF"
+fn parse_chaos_command(args: &str) -> Result<Command, CommandParseError> {
+    let spec = args.trim();
+    if spec.contains(char::is_whitespace) {
+        return Err(CommandParseError::InvalidChaosSpec(spec.to_string()));
+    }
+    let params = ChaosParams::parse(spec).map_err(|_| CommandParseError::InvalidChaosSpec(spec.to_string()))?;
+    Ok(Command::Chaos { params: Some(params) })
+}
+
+/// エミュレーションコマンドの引数を解析します
+///
+/// フォーマット: "EMULATE COUNT [INTERVAL_S]" または "EMULATE OFF"
+fn parse_emulate_command(args: &str) -> Result<Command, CommandParseError> {
+    let parts: Vec<&str> = args.split_whitespace().collect();
+    let (count, interval) = match parts[..] {
+        [count] => (count, None),
+        [count, interval] => (count, Some(interval)),
+        _ => {
+            return Err(CommandParseError::InvalidFormat {
+                command: EMULATE_COMMAND,
+                expected_args: 2,
+                actual_args: parts.len(),
+            })
+        }
+    };
+    let devices = if count.eq_ignore_ascii_case("off") {
+        0
+    } else {
+        match count.parse::<usize>() {
+            Ok(devices) if (1..=MAX_EMULATED_DEVICES).contains(&devices) => devices,
+            _ => return Err(CommandParseError::InvalidEmulation(count.to_string())),
+        }
+    };
+    let interval_s = match interval {
+        None => DEFAULT_EMULATION_INTERVAL_S,
+        Some(interval) => match interval.parse::<u32>() {
+            Ok(seconds) if (MIN_EMULATION_INTERVAL_S..=MAX_EMULATION_INTERVAL_S).contains(&seconds) => seconds,
+            _ => return Err(CommandParseError::InvalidEmulation(interval.to_string())),
+        },
+    };
+    Ok(Command::Emulate { devices, interval_s })
+}
+
 /// MACアドレスの妥当性をチェックします
 /// 
 /// # 引数
@@ -180,4 +1174,301 @@ mod tests {
         let result = parse_command(command);
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_parse_list_devices_command() {
+        assert!(matches!(parse_command("LIST_DEVICES\r\n"), Ok(Command::ListDevices)));
+        assert!(matches!(parse_command("LIST_DEVICES:x"), Ok(Command::Unknown(_))));
+    }
+
+    #[test]
+    fn test_parse_pause_and_resume_commands() {
+        match parse_command("PAUSE 34:ab:95:fb:3f:c4 30\r\n") {
+            Ok(Command::Pause { mac_address, minutes }) => {
+                assert_eq!(mac_address, "34:ab:95:fb:3f:c4");
+                assert_eq!(minutes, 30);
+            }
+            other => panic!("Expected Pause command, got {:?}", other),
+        }
+        assert!(matches!(
+            parse_command("RESUME 34:ab:95:fb:3f:c4"),
+            Ok(Command::Resume { mac_address }) if mac_address == "34:ab:95:fb:3f:c4"
+        ));
+
+        assert_eq!(
+            parse_command("PAUSE 34:ab:95:fb:3f:c4 0").unwrap_err(),
+            CommandParseError::InvalidPauseMinutes("0".to_string())
+        );
+        assert!(matches!(
+            parse_command("PAUSE 34:ab:95:fb:3f:c4"),
+            Err(CommandParseError::InvalidFormat { expected_args: 2, actual_args: 1, .. })
+        ));
+        assert!(matches!(parse_command("RESUME zz"), Err(CommandParseError::InvalidMacAddress(_))));
+    }
+
+    #[test]
+    fn test_parse_set_quality_command() {
+        match parse_command("SET_QUALITY 34:ab:95:fb:3f:c4 jpeg_q=20 size=vga") {
+            Ok(Command::SetQuality { mac_address, settings }) => {
+                assert_eq!(mac_address, "34:ab:95:fb:3f:c4");
+                assert_eq!(settings.encode(), "JPEG_Q=20;FRAME_SIZE=VGA");
+            }
+            other => panic!("Expected SetQuality command, got {:?}", other),
+        }
+        assert_eq!(
+            parse_command("SET_QUALITY 34:ab:95:fb:3f:c4 jpeg_q=64").unwrap_err(),
+            CommandParseError::InvalidQualitySetting("jpeg_q=64".to_string())
+        );
+        assert_eq!(
+            parse_command("SET_QUALITY 34:ab:95:fb:3f:c4 size=8K").unwrap_err(),
+            CommandParseError::InvalidQualitySetting("size=8K".to_string())
+        );
+        assert!(matches!(
+            parse_command("SET_QUALITY 34:ab:95:fb:3f:c4"),
+            Err(CommandParseError::InvalidQualitySetting(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_set_debug_command() {
+        match parse_command("SET_DEBUG 34:ab:95:fb:3f:c4 bypass_voltage+DEBUG 10") {
+            Ok(Command::SetDebug { mac_address, request }) => {
+                assert_eq!(mac_address, "34:ab:95:fb:3f:c4");
+                assert_eq!(request.encode(), "DEBUG=DEBUG+BYPASS_VOLTAGE/10");
+            }
+            other => panic!("Expected SetDebug command, got {:?}", other),
+        }
+        assert!(matches!(
+            parse_command("SET_DEBUG 34:ab:95:fb:3f:c4 FORCE_CAMERA"),
+            Ok(Command::SetDebug { request, .. }) if request.cycles == DEFAULT_DEBUG_CYCLES
+        ));
+        assert!(matches!(
+            parse_command("SET_DEBUG 34:ab:95:fb:3f:c4 OFF"),
+            Ok(Command::SetDebug { request, .. }) if request.encode() == "DEBUG=OFF"
+        ));
+        assert_eq!(
+            parse_command("SET_DEBUG 34:ab:95:fb:3f:c4 DEBUG 101").unwrap_err(),
+            CommandParseError::InvalidDebugFlags("DEBUG 101".to_string())
+        );
+        assert_eq!(
+            parse_command("SET_DEBUG 34:ab:95:fb:3f:c4 VERBOSE").unwrap_err(),
+            CommandParseError::InvalidDebugFlags("VERBOSE".to_string())
+        );
+        assert!(matches!(
+            parse_command("SET_DEBUG 34:ab:95:fb:3f:c4"),
+            Err(CommandParseError::InvalidFormat { expected_args: 3, actual_args: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn test_parse_mac_address_commands() {
+        assert!(matches!(
+            parse_command("RESEND_LAST 34:ab:95:fb:3f:c4\r\n"),
+            Ok(Command::ResendLast { mac_address }) if mac_address == "34:ab:95:fb:3f:c4"
+        ));
+        assert!(matches!(
+            parse_command("RESEND_LAST 34:ab:95:fb:3f:c4 2"),
+            Err(CommandParseError::InvalidFormat { expected_args: 1, actual_args: 2, .. })
+        ));
+        assert_eq!(
+            parse_command("RESEND_LAST 34:ab:95").unwrap_err(),
+            CommandParseError::InvalidMacAddress("34:ab:95".to_string())
+        );
+        assert!(matches!(
+            parse_command("GET_THUMBS 34:ab:95:fb:3f:c4"),
+            Ok(Command::GetThumbs { mac_address }) if mac_address == "34:ab:95:fb:3f:c4"
+        ));
+        assert!(matches!(
+            parse_command("ROTATE_KEY 34:ab:95:fb:3f:c4"),
+            Ok(Command::RotateKey { mac_address }) if mac_address == "34:ab:95:fb:3f:c4"
+        ));
+    }
+
+    #[test]
+    fn test_parse_broadcast_commands() {
+        assert!(matches!(
+            parse_command("BROADCAST_CONFIG SLEEP=900;RECEIVER_MAC=34:ab:95:fb:3f:c4\r\n"),
+            Ok(Command::BroadcastConfig { config }) if config == "SLEEP=900;RECEIVER_MAC=34:ab:95:fb:3f:c4"
+        ));
+        assert!(matches!(parse_command("BROADCAST_STATUS"), Ok(Command::BroadcastStatus)));
+        assert!(matches!(parse_command("SOFT_RESET\r\n"), Ok(Command::SoftReset)));
+        assert!(matches!(parse_command("SOAK_REPORT"), Ok(Command::SoakReport)));
+        assert!(matches!(parse_command("GET_STATS\r\n"), Ok(Command::GetStats)));
+        assert!(matches!(parse_command("MEMORY_TREND\r\n"), Ok(Command::MemoryTrend)));
+        assert!(matches!(parse_command("SOFT_RESET now"), Ok(Command::Unknown(_))));
+
+        for invalid in ["SLEEP=0", "SLEEP=900;", "BCAST=3", "RECEIVER_MAC=zz", "SLEEP=60 SLEEP=90"] {
+            assert_eq!(
+                parse_command(&format!("BROADCAST_CONFIG {}", invalid)).unwrap_err(),
+                CommandParseError::InvalidBroadcastConfig(invalid.to_string())
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_release_channel_commands() {
+        assert!(matches!(
+            parse_command("SET_CHANNEL 34:ab:95:fb:3f:c4 beta\r\n"),
+            Ok(Command::SetChannel { mac_address, channel: ReleaseChannel::Beta }) if mac_address == "34:ab:95:fb:3f:c4"
+        ));
+        assert_eq!(
+            parse_command("SET_CHANNEL 34:ab:95:fb:3f:c4 nightly").unwrap_err(),
+            CommandParseError::InvalidReleaseChannel("nightly".to_string())
+        );
+        assert!(matches!(parse_command("SET_CHANNEL zz beta"), Err(CommandParseError::InvalidMacAddress(_))));
+
+        assert!(matches!(
+            parse_command("SET_ROLLOUT beta 1a2b3c4d-dirty"),
+            Ok(Command::SetRollout { channel: ReleaseChannel::Beta, firmware: Some(firmware) }) if firmware == "1a2b3c4d-dirty"
+        ));
+        assert!(matches!(
+            parse_command("SET_ROLLOUT stable off"),
+            Ok(Command::SetRollout { channel: ReleaseChannel::Stable, firmware: None })
+        ));
+        assert_eq!(
+            parse_command("SET_ROLLOUT beta FW:1a2b").unwrap_err(),
+            CommandParseError::InvalidFirmware("FW:1a2b".to_string())
+        );
+        assert!(matches!(
+            parse_command("SET_ROLLOUT beta"),
+            Err(CommandParseError::InvalidFormat { command: "SET_ROLLOUT", actual_args: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn test_parse_group_commands() {
+        assert!(matches!(
+            parse_command("GROUP_ADD north 34:ab:95:fb:3f:c4 34:ab:95:fb:3f:c5\r\n"),
+            Ok(Command::GroupAdd { group, mac_addresses })
+                if group == "north" && mac_addresses == ["34:ab:95:fb:3f:c4", "34:ab:95:fb:3f:c5"]
+        ));
+        assert!(matches!(
+            parse_command("GROUP_ADD north"),
+            Err(CommandParseError::InvalidFormat { command: "GROUP_ADD", actual_args: 1, .. })
+        ));
+        assert!(matches!(
+            parse_command("GROUP_ADD north 34:ab:95:fb:3f:c4 zz"),
+            Err(CommandParseError::InvalidMacAddress(mac_address)) if mac_address == "zz"
+        ));
+        assert_eq!(
+            parse_command("GROUP_ADD north/field 34:ab:95:fb:3f:c4").unwrap_err(),
+            CommandParseError::InvalidGroup("north/field".to_string())
+        );
+
+        assert!(matches!(
+            parse_command("GROUP_SLEEP north 600"),
+            Ok(Command::GroupSleep { group, sleep_seconds: 600 }) if group == "north"
+        ));
+        assert_eq!(
+            parse_command("GROUP_SLEEP north 0").unwrap_err(),
+            CommandParseError::InvalidSleepTime("0".to_string())
+        );
+        assert!(matches!(
+            parse_command("GROUP_CAPTURE north"),
+            Ok(Command::GroupCapture { group }) if group == "north"
+        ));
+        assert!(matches!(
+            parse_command("GROUP_CAPTURE north 600"),
+            Err(CommandParseError::InvalidFormat { command: "GROUP_CAPTURE", actual_args: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn test_parse_file_transfer_commands() {
+        assert!(matches!(
+            parse_command("PUSH_FILE 34:ab:95:fb:3f:c4 calib 1024 CBF43926\r\n"),
+            Ok(Command::PushFile { mac_address, slot, size: 1024, file_crc: 0xCBF4_3926 })
+                if mac_address == "34:ab:95:fb:3f:c4" && slot == "calib"
+        ));
+        assert_eq!(
+            parse_command("PUSH_FILE 34:ab:95:fb:3f:c4 calib 4097 0").unwrap_err(),
+            CommandParseError::InvalidFileTransfer("4097".to_string())
+        );
+        assert_eq!(
+            parse_command("PUSH_FILE 34:ab:95:fb:3f:c4 calib/tds 16 0").unwrap_err(),
+            CommandParseError::InvalidFileTransfer("calib/tds".to_string())
+        );
+        assert!(matches!(
+            parse_command("PUSH_FILE 34:ab:95:fb:3f:c4 calib 16"),
+            Err(CommandParseError::InvalidFormat { command: "PUSH_FILE", actual_args: 3, .. })
+        ));
+
+        assert!(matches!(
+            parse_command("FILE_DATA 34:ab:95:fb:3f:c4 96 00ff10"),
+            Ok(Command::FileData { offset: 96, data, .. }) if data == [0x00, 0xff, 0x10]
+        ));
+        assert_eq!(
+            parse_command("FILE_DATA 34:ab:95:fb:3f:c4 0 0g").unwrap_err(),
+            CommandParseError::InvalidFileTransfer("0g".to_string())
+        );
+        // 1行の最大長でもUSBの1回の読み取り（256バイト）に収まる
+        let longest = format!("FILE_DATA 34:ab:95:fb:3f:c4 4000 {}\r\n", "ab".repeat(MAX_FILE_DATA_LEN));
+        assert!(longest.len() <= 256);
+        assert!(matches!(parse_command(&longest), Ok(Command::FileData { .. })));
+        assert!(matches!(
+            parse_command(&format!("FILE_DATA 34:ab:95:fb:3f:c4 0 {}", "ab".repeat(MAX_FILE_DATA_LEN + 1))),
+            Err(CommandParseError::InvalidFileTransfer(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_dead_letter_commands() {
+        assert!(matches!(parse_command("DLQ_LIST\r\n"), Ok(Command::DeadLetterList)));
+        assert!(matches!(parse_command("DLQ_REPLAY"), Ok(Command::DeadLetterReplay { id: None })));
+        assert!(matches!(parse_command("DLQ_REPLAY 12"), Ok(Command::DeadLetterReplay { id: Some(12) })));
+        assert_eq!(
+            parse_command("DLQ_REPLAY 0").unwrap_err(),
+            CommandParseError::InvalidDeadLetterId("0".to_string())
+        );
+        assert!(matches!(
+            parse_command("DLQ_REPLAY 1 2"),
+            Err(CommandParseError::InvalidFormat { command: "DLQ_REPLAY", actual_args: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn test_parse_emulate_command() {
+        assert!(matches!(
+            parse_command("EMULATE 3\r\n"),
+            Ok(Command::Emulate { devices: 3, interval_s: DEFAULT_EMULATION_INTERVAL_S })
+        ));
+        assert!(matches!(parse_command("EMULATE 2 15"), Ok(Command::Emulate { devices: 2, interval_s: 15 })));
+        assert!(matches!(parse_command("EMULATE off"), Ok(Command::Emulate { devices: 0, .. })));
+        assert_eq!(parse_command("EMULATE 9").unwrap_err(), CommandParseError::InvalidEmulation("9".to_string()));
+        assert_eq!(parse_command("EMULATE 0").unwrap_err(), CommandParseError::InvalidEmulation("0".to_string()));
+        assert_eq!(parse_command("EMULATE 1 1").unwrap_err(), CommandParseError::InvalidEmulation("1".to_string()));
+        assert!(matches!(
+            parse_command("EMULATE 1 10 x"),
+            Err(CommandParseError::InvalidFormat { command: "EMULATE", actual_args: 3, .. })
+        ));
+    }
+
+    #[test]
+    fn test_parse_chaos_command() {
+        assert!(matches!(parse_command("CHAOS\r\n"), Ok(Command::Chaos { params: None })));
+        assert!(matches!(parse_command("CHAOS off"), Ok(Command::Chaos { params: Some(None) })));
+        match parse_command("CHAOS drop=5,dup=2,seed=42") {
+            Ok(Command::Chaos { params: Some(Some(params)) }) => {
+                assert_eq!(params.encode(), "drop=5,dup=2,reorder=0,corrupt=0,seed=42");
+            }
+            other => panic!("Expected Chaos command, got {:?}", other),
+        }
+        assert_eq!(
+            parse_command("CHAOS drop=80,corrupt=30").unwrap_err(),
+            CommandParseError::InvalidChaosSpec("drop=80,corrupt=30".to_string())
+        );
+        assert_eq!(
+            parse_command("CHAOS drop=5 dup=2").unwrap_err(),
+            CommandParseError::InvalidChaosSpec("drop=5 dup=2".to_string())
+        );
+    }
+
+    #[test]
+    fn test_help_text_lists_all_commands() {
+        let help = help_text();
+        for spec in COMMAND_SPECS {
+            assert!(help.contains(spec.syntax));
+        }
+        assert_eq!(help.lines().count(), COMMAND_SPECS.len());
+    }
 }
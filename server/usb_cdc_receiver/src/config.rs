@@ -1,6 +1,15 @@
+use crate::esp_now::channel_hop::{parse_hop_channels, ChannelHopper};
+use crate::esp_now::chaos::ChaosParams;
+use crate::esp_now::chunk_size::{ChunkSizeAdvert, MIN_CHUNK_PAYLOAD_LEN};
+use crate::esp_now::downlink_auth::DownlinkKey;
+use crate::esp_now::radio::EspNowRate;
+use crate::fleet_summary::FleetSummary;
 use crate::mac_address::MacAddress;
+use crate::sleep_policy::{SleepPolicy, SleepPolicyRegistry};
+use crate::streaming::sla::DEFAULT_FRAME_DEADLINE_MS;
 use log::{info, warn};
 use std::str::FromStr;
+use std::time::{Duration, Instant};
 
 /// この設定はcompile時にbuild.rsによってcfg.tomlから読み込まれる
 #[toml_cfg::toml_config]
@@ -17,6 +26,64 @@ pub struct Config {
     image_sender_cam5: &'static str,
     #[default("")]
     image_sender_cam6: &'static str,
+    /// カメラごとのスリープ時間の許容範囲（"MIN-MAX" または "MIN-MAX:clamp"、空なら1-86400秒で拒否）
+    #[default("")]
+    sleep_range_cam1: &'static str,
+    #[default("")]
+    sleep_range_cam2: &'static str,
+    #[default("")]
+    sleep_range_cam3: &'static str,
+    #[default("")]
+    sleep_range_cam4: &'static str,
+    #[default("")]
+    sleep_range_cam5: &'static str,
+    #[default("")]
+    sleep_range_cam6: &'static str,
+    /// ダウンリンク認証鍵（64文字の16進数、空なら署名しない）
+    #[default("")]
+    downlink_auth_key: &'static str,
+    /// ESP-NOWの送信レート（"1M"、"24M"、"MCS3" など。空ならESP-IDFの既定値）
+    #[default("")]
+    espnow_rate: &'static str,
+    /// Wi-Fiの最大送信パワー（dBm、0ならESP-IDFの既定値）
+    #[default(0)]
+    wifi_tx_power_dbm: i8,
+    /// デバイスへ通知するDATAチャンクの推奨ペイロード長（バイト、0なら受け取れる上限の223）
+    #[default(0)]
+    preferred_chunk_size: u16,
+    /// 部分転送の救済（欠落があっても転送を中断せず、ギャップマップ付きで完了イベントを送出）
+    #[default(false)]
+    partial_salvage: bool,
+    /// 未登録のデバイスを拒否する（cameras に無いMACのフレームを破棄し、拒否メッセージを返す）
+    #[default(false)]
+    reject_unknown_devices: bool,
+    /// CPU使用率の警告閾値（%、0なら警告しない）
+    #[default(85)]
+    cpu_warn_percent: u8,
+    /// 撮影からPCへの送出までの期限（ミリ秒、0なら既定値）
+    #[default(30000)]
+    frame_deadline_ms: u32,
+    /// 登録デバイスの定期サマリーの送出間隔（秒、0なら送出しない）
+    #[default(3600)]
+    fleet_summary_interval_secs: u32,
+    /// 空きヒープの枯渇を警告する予測の範囲（時間、0なら警告しない）
+    #[default(24)]
+    memory_trend_horizon_hours: u32,
+    /// 同時に受け付ける転送数の上限（0なら無制限）
+    #[default(3)]
+    max_in_flight_frames: u32,
+    /// 上限超過で延期したデバイスへ返す再試行までの待機時間（ミリ秒）
+    #[default(3000)]
+    defer_retry_after_ms: u32,
+    /// ホームチャンネル以外に受信するチャンネル（"6,11" 形式、空ならチャンネルを切り替えない）
+    #[default("")]
+    channel_hop_channels: &'static str,
+    /// 受信チャンネルを切り替える間隔（秒）
+    #[default(60)]
+    channel_hop_slot_secs: u32,
+    /// 受信フレームへの故障注入（"drop=5,dup=2,reorder=1,corrupt=1,seed=42" 形式、`chaos` フィーチャーのみ。空なら無効）
+    #[default("")]
+    chaos: &'static str,
 }
 
 /// 設定から解析されたカメラ情報を格納する構造体
@@ -24,6 +91,8 @@ pub struct Config {
 pub struct CameraConfig {
     pub name: String,
     pub mac_address: MacAddress,
+    /// スリープ時間の許容範囲
+    pub sleep_policy: SleepPolicy,
 }
 
 /// 設定ファイルからカメラ設定を読み込む
@@ -60,37 +129,37 @@ pub fn load_camera_configs() -> Vec<CameraConfig> {
     // カメラ1の設定を確認
     if !config.image_sender_cam1.is_empty() {
         info!("Processing camera 1 config: {}", config.image_sender_cam1);
-        add_camera_if_valid(&mut cameras, "cam1", config.image_sender_cam1);
+        add_camera_if_valid(&mut cameras, "cam1", config.image_sender_cam1, config.sleep_range_cam1);
     }
 
     // カメラ2の設定を確認
     if !config.image_sender_cam2.is_empty() {
         info!("Processing camera 2 config: {}", config.image_sender_cam2);
-        add_camera_if_valid(&mut cameras, "cam2", config.image_sender_cam2);
+        add_camera_if_valid(&mut cameras, "cam2", config.image_sender_cam2, config.sleep_range_cam2);
     }
 
     // カメラ3の設定を確認
     if !config.image_sender_cam3.is_empty() {
         info!("Processing camera 3 config: {}", config.image_sender_cam3);
-        add_camera_if_valid(&mut cameras, "cam3", config.image_sender_cam3);
+        add_camera_if_valid(&mut cameras, "cam3", config.image_sender_cam3, config.sleep_range_cam3);
     }
 
     // カメラ4の設定を確認
     if !config.image_sender_cam4.is_empty() {
         info!("Processing camera 4 config: {}", config.image_sender_cam4);
-        add_camera_if_valid(&mut cameras, "cam4", config.image_sender_cam4);
+        add_camera_if_valid(&mut cameras, "cam4", config.image_sender_cam4, config.sleep_range_cam4);
     }
 
     // カメラ5の設定を確認
     if !config.image_sender_cam5.is_empty() {
         info!("Processing camera 5 config: {}", config.image_sender_cam5);
-        add_camera_if_valid(&mut cameras, "cam5", config.image_sender_cam5);
+        add_camera_if_valid(&mut cameras, "cam5", config.image_sender_cam5, config.sleep_range_cam5);
     }
 
     // カメラ6の設定を確認
     if !config.image_sender_cam6.is_empty() {
         info!("Processing camera 6 config: {}", config.image_sender_cam6);
-        add_camera_if_valid(&mut cameras, "cam6", config.image_sender_cam6);
+        add_camera_if_valid(&mut cameras, "cam6", config.image_sender_cam6, config.sleep_range_cam6);
     }
 
     // 設定の結果を報告
@@ -99,20 +168,167 @@ pub fn load_camera_configs() -> Vec<CameraConfig> {
     } else {
         info!("Found {} valid camera configs in cfg.toml", cameras.len());
         for camera in &cameras {
-            info!("Camera {} with MAC: {}", camera.name, camera.mac_address);
+            info!(
+                "Camera {} with MAC: {} (sleep {}-{}s, {})",
+                camera.name,
+                camera.mac_address,
+                camera.sleep_policy.min_seconds,
+                camera.sleep_policy.max_seconds,
+                if camera.sleep_policy.clamp { "clamp" } else { "reject" }
+            );
         }
     }
 
     cameras
 }
 
+/// ダウンリンク認証鍵を読み込む（未設定・不正な場合はNone）
+pub fn load_downlink_key() -> Option<DownlinkKey> {
+    let hex = CONFIG.downlink_auth_key;
+    if hex.is_empty() {
+        warn!("downlink_auth_key is not set; sleep commands are sent unsigned");
+        return None;
+    }
+    let key = DownlinkKey::from_hex(hex);
+    if key.is_none() {
+        warn!("Invalid downlink_auth_key (expected 64 hex chars); sleep commands are sent unsigned");
+    }
+    key
+}
+
+/// ESP-NOWの送信レートを読み込む（未設定・不正な場合はNone）
+pub fn load_espnow_rate() -> Option<EspNowRate> {
+    let name = CONFIG.espnow_rate;
+    if name.is_empty() {
+        return None;
+    }
+    let rate = EspNowRate::parse(name);
+    if rate.is_none() {
+        warn!("Invalid espnow_rate '{}' (e.g. \"24M\", \"MCS3\"); using the default rate", name);
+    }
+    rate
+}
+
+/// Wi-Fiの最大送信パワー（dBm、未設定ならNone）
+pub fn wifi_tx_power_dbm() -> Option<i8> {
+    (CONFIG.wifi_tx_power_dbm != 0).then_some(CONFIG.wifi_tx_power_dbm)
+}
+
+/// Pongで通知するDATAチャンクの大きさ
+pub fn chunk_size_advert() -> ChunkSizeAdvert {
+    let preferred = (CONFIG.preferred_chunk_size != 0).then_some(CONFIG.preferred_chunk_size);
+    let advert = ChunkSizeAdvert::gateway(preferred);
+    if preferred.is_some_and(|preferred| preferred != advert.preferred) {
+        warn!(
+            "preferred_chunk_size {} is out of range ({}-{}); using {}",
+            CONFIG.preferred_chunk_size, MIN_CHUNK_PAYLOAD_LEN, advert.max, advert.preferred
+        );
+    }
+    advert
+}
+
+/// 部分転送の救済が有効かどうか
+pub fn partial_salvage_enabled() -> bool {
+    CONFIG.partial_salvage
+}
+
+/// 未登録のデバイスを拒否するかどうか
+pub fn reject_unknown_devices() -> bool {
+    CONFIG.reject_unknown_devices
+}
+
+/// CPU使用率の警告閾値（%、0ならNone）
+pub fn cpu_warn_percent() -> Option<u8> {
+    (CONFIG.cpu_warn_percent != 0).then_some(CONFIG.cpu_warn_percent.min(100))
+}
+
+/// 撮影からPCへの送出までの期限（ミリ秒）
+pub fn frame_deadline_ms() -> u32 {
+    if CONFIG.frame_deadline_ms == 0 {
+        DEFAULT_FRAME_DEADLINE_MS
+    } else {
+        CONFIG.frame_deadline_ms
+    }
+}
+
+/// 登録デバイスの定期サマリーの送出間隔（無効ならNone）
+pub fn fleet_summary_interval() -> Option<Duration> {
+    (CONFIG.fleet_summary_interval_secs > 0).then(|| Duration::from_secs(CONFIG.fleet_summary_interval_secs as u64))
+}
+
+/// 空きヒープの枯渇を警告する予測の範囲（無効ならNone）
+pub fn memory_trend_horizon() -> Option<Duration> {
+    (CONFIG.memory_trend_horizon_hours > 0)
+        .then(|| Duration::from_secs(CONFIG.memory_trend_horizon_hours as u64 * 3600))
+}
+
+/// 同時に受け付ける転送数の上限と延期時の再試行までの待機時間（ミリ秒）
+pub fn admission_limits() -> (usize, u32) {
+    (CONFIG.max_in_flight_frames as usize, CONFIG.defer_retry_after_ms)
+}
+
+/// 受信チャンネルの予定を作成する（チャンネルホッピングが無効ならNone）
+pub fn channel_hopper(home: u8, now: Instant) -> Option<ChannelHopper> {
+    match parse_hop_channels(CONFIG.channel_hop_channels) {
+        Ok(channels) if channels.iter().any(|&channel| channel != home) => {
+            let slot_secs = CONFIG.channel_hop_slot_secs.min(u16::MAX as u32) as u16;
+            Some(ChannelHopper::new(home, &channels, slot_secs, now))
+        }
+        Ok(_) => None,
+        Err(e) => {
+            warn!("Invalid channel_hop_channels {:?}: {}; channel hopping disabled", CONFIG.channel_hop_channels, e);
+            None
+        }
+    }
+}
+
+/// 受信フレームへの故障注入の設定（未設定・不正な場合はNone）
+pub fn chaos_params() -> Option<ChaosParams> {
+    let params = ChaosParams::parse(CONFIG.chaos).unwrap_or_else(|e| {
+        warn!("Invalid chaos {:?}: {}; fault injection disabled", CONFIG.chaos, e);
+        None
+    });
+    if params.is_some() && !cfg!(feature = "chaos") {
+        warn!("chaos is set but the firmware was built without the `chaos` feature; ignored");
+        return None;
+    }
+    params
+}
+
+/// カメラ設定から定期サマリーの集計対象を作成する
+pub fn fleet_summary(cameras: &[CameraConfig]) -> FleetSummary {
+    let mut summary = FleetSummary::new();
+    let now = Instant::now();
+    for camera in cameras {
+        summary.register(camera.mac_address.into_bytes(), &camera.name, now);
+    }
+    summary
+}
+
+/// カメラ設定からスリープ時間の許容範囲の一覧を作成する
+pub fn sleep_policy_registry(cameras: &[CameraConfig]) -> SleepPolicyRegistry {
+    SleepPolicyRegistry::new(
+        cameras
+            .iter()
+            .map(|camera| (camera.mac_address.into_bytes(), camera.sleep_policy))
+            .collect(),
+    )
+}
+
 /// MACアドレスが有効であればカメラ設定を追加する
-fn add_camera_if_valid(cameras: &mut Vec<CameraConfig>, name: &str, mac_str: &str) {
+///
+/// 許容範囲の指定が不正な場合は警告を出して既定の範囲を使用する。
+fn add_camera_if_valid(cameras: &mut Vec<CameraConfig>, name: &str, mac_str: &str, sleep_range: &str) {
     match MacAddress::from_str(mac_str) {
         Ok(mac_address) => {
+            let sleep_policy = SleepPolicy::parse(sleep_range).unwrap_or_else(|e| {
+                warn!("Invalid sleep_range for camera {}: {}; using default range", name, e);
+                SleepPolicy::default()
+            });
             cameras.push(CameraConfig {
                 name: name.to_string(),
                 mac_address,
+                sleep_policy,
             });
         }
         Err(e) => {
@@ -130,14 +346,19 @@ mod tests {
         let mut cameras = Vec::new();
 
         // 有効なMACアドレス
-        add_camera_if_valid(&mut cameras, "test1", "12:34:56:78:9a:bc");
+        add_camera_if_valid(&mut cameras, "test1", "12:34:56:78:9a:bc", "60-7200:clamp");
         assert_eq!(cameras.len(), 1);
         assert_eq!(cameras[0].name, "test1");
+        assert!(cameras[0].sleep_policy.clamp);
 
         // 無効なMACアドレス
-        add_camera_if_valid(&mut cameras, "test2", "invalid");
+        add_camera_if_valid(&mut cameras, "test2", "invalid", "");
         // 無効なものは追加されないので数は変わらない
         assert_eq!(cameras.len(), 1);
+
+        // 無効な許容範囲は既定値
+        add_camera_if_valid(&mut cameras, "test3", "12:34:56:78:9a:bd", "7200-60");
+        assert_eq!(cameras[1].sleep_policy, SleepPolicy::default());
     }
 
     // 注: load_camera_configs()のテストは実際のcfg.tomlファイルに依存するため、
@@ -0,0 +1,179 @@
+/// ゲートウェイのCPU使用率の集計
+///
+/// FreeRTOSのランタイム統計（タスクごとの累積実行時間）を定期的に取得し、
+/// 前回との差分からタスクごとのCPU使用率を求めます。
+/// デュアルコアでは全タスクの差分の合計が全コアの実行時間になるため、
+/// IDLEタスク以外の割合をCPU使用率とします。
+/// 使用率が上限を一定回数続けて超えた場合に1回だけ警告します。
+use std::collections::HashMap;
+
+/// IDLEタスク名の接頭辞（コアごとに `IDLE0`、`IDLE1`）
+const IDLE_TASK_PREFIX: &str = "IDLE";
+
+/// 統計フレームに載せるタスク数の上限（使用率の高い順）
+pub const MAX_REPORTED_TASKS: usize = 6;
+
+/// タスクの累積実行時間
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TaskRuntime {
+    /// タスク名
+    pub name: String,
+    /// 累積実行時間（ランタイム統計のカウンタ値）
+    pub runtime: u64,
+}
+
+impl TaskRuntime {
+    /// タスク名と累積実行時間から作成します
+    pub fn new(name: impl Into<String>, runtime: u64) -> Self {
+        Self { name: name.into(), runtime }
+    }
+}
+
+/// 1回の計測区間のCPU使用率
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CpuUsage {
+    /// IDLEタスク以外の使用率（%）
+    pub busy_percent: u8,
+    /// タスクごとの使用率（%、IDLEを除き使用率の高い順）
+    pub tasks: Vec<(String, u8)>,
+}
+
+impl CpuUsage {
+    /// 統計フレーム用の表現（`usb_egress=12|cmd_handler=3`）
+    pub fn tasks_field(&self) -> String {
+        self.tasks
+            .iter()
+            .take(MAX_REPORTED_TASKS)
+            .map(|(name, percent)| format!("{}={}", name, percent))
+            .collect::<Vec<_>>()
+            .join("|")
+    }
+}
+
+/// 累積実行時間の差分からCPU使用率を求めます
+#[derive(Debug, Default)]
+pub struct CpuSampler {
+    previous: HashMap<String, u64>,
+}
+
+impl CpuSampler {
+    /// 新しいサンプラーを作成します
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 今回の累積実行時間を記録し、前回からの使用率を返します（初回や差分がない場合はNone）
+    pub fn sample(&mut self, tasks: &[TaskRuntime]) -> Option<CpuUsage> {
+        let first = self.previous.is_empty();
+        let deltas: Vec<(&str, u64)> = tasks
+            .iter()
+            .map(|task| {
+                // 新しいタスクは累積値全体、カウンタが巻き戻った場合は0とする
+                let previous = self.previous.get(&task.name).copied().unwrap_or(0);
+                (task.name.as_str(), task.runtime.saturating_sub(previous))
+            })
+            .collect();
+        self.previous = tasks.iter().map(|task| (task.name.clone(), task.runtime)).collect();
+
+        let total: u64 = deltas.iter().map(|(_, delta)| delta).sum();
+        if first || total == 0 {
+            return None;
+        }
+        let percent = |delta: u64| (delta * 100 / total).min(100) as u8;
+
+        let idle: u64 = deltas
+            .iter()
+            .filter(|(name, _)| name.starts_with(IDLE_TASK_PREFIX))
+            .map(|(_, delta)| delta)
+            .sum();
+        let mut task_usage: Vec<(String, u8)> = deltas
+            .iter()
+            .filter(|(name, _)| !name.starts_with(IDLE_TASK_PREFIX))
+            .map(|(name, delta)| (name.to_string(), percent(*delta)))
+            .collect();
+        task_usage.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+        Some(CpuUsage {
+            busy_percent: 100 - percent(idle),
+            tasks: task_usage,
+        })
+    }
+}
+
+/// CPU使用率の上限超過を監視します
+#[derive(Debug)]
+pub struct CpuLimitMonitor {
+    limit_percent: u8,
+    sustain_samples: u32,
+    consecutive: u32,
+    warned: bool,
+}
+
+impl CpuLimitMonitor {
+    /// `sustain_samples` 回続けて `limit_percent` を超えたら警告する監視を作成します
+    pub fn new(limit_percent: u8, sustain_samples: u32) -> Self {
+        Self {
+            limit_percent,
+            sustain_samples: sustain_samples.max(1),
+            consecutive: 0,
+            warned: false,
+        }
+    }
+
+    /// 使用率を記録し、今回警告すべき場合は true を返します（上限を下回るまで再警告しない）
+    pub fn observe(&mut self, busy_percent: u8) -> bool {
+        if busy_percent <= self.limit_percent {
+            self.consecutive = 0;
+            self.warned = false;
+            return false;
+        }
+        self.consecutive = self.consecutive.saturating_add(1);
+        if self.consecutive >= self.sustain_samples && !self.warned {
+            self.warned = true;
+            return true;
+        }
+        false
+    }
+
+    /// 上限（%）
+    pub fn limit_percent(&self) -> u8 {
+        self.limit_percent
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(runtimes: &[(&str, u64)]) -> Vec<TaskRuntime> {
+        runtimes.iter().map(|&(name, runtime)| TaskRuntime::new(name, runtime)).collect()
+    }
+
+    #[test]
+    fn test_sample_computes_per_task_usage() {
+        let mut sampler = CpuSampler::new();
+        assert_eq!(sampler.sample(&snapshot(&[("IDLE0", 100), ("IDLE1", 100), ("usb_egress", 0)])), None);
+
+        // 2コア分（合計400）のうちIDLEが300
+        let usage = sampler
+            .sample(&snapshot(&[("IDLE0", 220), ("IDLE1", 280), ("usb_egress", 60), ("cmd_handler", 40)]))
+            .unwrap();
+        assert_eq!(usage.busy_percent, 25);
+        assert_eq!(usage.tasks, vec![("usb_egress".to_string(), 15), ("cmd_handler".to_string(), 10)]);
+        assert_eq!(usage.tasks_field(), "usb_egress=15|cmd_handler=10");
+    }
+
+    #[test]
+    fn test_limit_monitor_warns_once_when_sustained() {
+        let mut monitor = CpuLimitMonitor::new(80, 3);
+        assert!(!monitor.observe(90));
+        assert!(!monitor.observe(95));
+        assert!(monitor.observe(85));
+        assert!(!monitor.observe(99));
+        // 一度下回れば再び警告できる
+        assert!(!monitor.observe(50));
+        assert!(!monitor.observe(90));
+        assert!(!monitor.observe(90));
+        assert!(monitor.observe(90));
+    }
+}
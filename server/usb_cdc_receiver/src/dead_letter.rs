@@ -0,0 +1,233 @@
+//! USBへ送出できなかったフレームの退避（デッドレターキュー）
+//!
+//! USB CDCの書き込みが再試行の上限を超えて失敗したフレームは、ログを残して破棄していたため、
+//! 無線では受信できた画像がPCに届かないまま失われていました。失敗したフレームは理由と共にRAMに退避し
+//! （件数・バイト数の上限を超えた分は古い順に破棄）、`DLQ_LIST` で一覧を返し、`DLQ_REPLAY` でUSBへ送り直します。
+//! 退避中の件数は統計フレームの `DLQ` で報告します。
+
+use std::collections::VecDeque;
+use std::time::Instant;
+
+use crate::esp_now::frame::Frame;
+use crate::mac_address::format_mac_address;
+use crate::usb::UsbError;
+
+/// デッドレターキュー応答の接頭辞
+pub const DLQ_RESPONSE_PREFIX: &str = "CMD_DLQ:";
+
+/// 退避するフレーム数の上限
+pub const DEAD_LETTER_CAPACITY: usize = 64;
+
+/// 退避するフレームの合計バイト数の上限
+pub const DEAD_LETTER_MAX_BYTES: usize = 16 * 1024;
+
+/// 退避したフレーム
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeadLetter {
+    /// 退避時に割り当てた番号（`DLQ_REPLAY <ID>` で指定）
+    pub id: u32,
+    /// 送出できなかったフレーム
+    pub frame: Vec<u8>,
+    /// 最後に失敗した理由
+    pub reason: String,
+    /// 送出に失敗した回数
+    pub attempts: u32,
+    /// 最後に失敗した時刻
+    pub failed_at: Instant,
+}
+
+impl DeadLetter {
+    /// 一覧の1行（`<ID>,<MAC>,<種類>,<バイト数>,AGE_S=..,ATTEMPTS=..,REASON=..`）
+    fn summary(&self, now: Instant) -> String {
+        let (mac, frame_type) = match Frame::from_bytes(&self.frame) {
+            Ok((frame, _)) => (format_mac_address(frame.mac_address()), frame.frame_type().as_str()),
+            Err(_) => ("-".to_string(), "RAW"),
+        };
+        format!(
+            "{},{},{},{}B,AGE_S={},ATTEMPTS={},REASON={}",
+            self.id,
+            mac,
+            frame_type,
+            self.frame.len(),
+            now.saturating_duration_since(self.failed_at).as_secs(),
+            self.attempts,
+            self.reason
+        )
+    }
+}
+
+/// 送出できなかったフレームの退避先
+#[derive(Debug)]
+pub struct DeadLetterQueue {
+    letters: VecDeque<DeadLetter>,
+    bytes: usize,
+    next_id: u32,
+    added: u32,
+    replayed: u32,
+    overflowed: u32,
+}
+
+impl DeadLetterQueue {
+    /// 空のキューを作成します
+    pub const fn new() -> Self {
+        Self {
+            letters: VecDeque::new(),
+            bytes: 0,
+            next_id: 1,
+            added: 0,
+            replayed: 0,
+            overflowed: 0,
+        }
+    }
+
+    /// 退避中のフレーム数
+    pub fn len(&self) -> usize {
+        self.letters.len()
+    }
+
+    /// 退避中のフレームがないか
+    pub fn is_empty(&self) -> bool {
+        self.letters.is_empty()
+    }
+
+    /// 送出に失敗したフレームを退避します
+    pub fn push(&mut self, frame: Vec<u8>, error: &UsbError, now: Instant) {
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1).max(1);
+        self.added += 1;
+        self.insert(DeadLetter {
+            id,
+            frame,
+            reason: error.to_string(),
+            attempts: 1,
+            failed_at: now,
+        });
+    }
+
+    /// 再送に失敗したフレームを戻します（番号は変えない）
+    pub fn requeue(&mut self, mut letter: DeadLetter, error: &UsbError, now: Instant) {
+        letter.reason = error.to_string();
+        letter.attempts += 1;
+        letter.failed_at = now;
+        self.insert(letter);
+    }
+
+    fn insert(&mut self, letter: DeadLetter) {
+        self.bytes += letter.frame.len();
+        self.letters.push_back(letter);
+        while self.letters.len() > DEAD_LETTER_CAPACITY || self.bytes > DEAD_LETTER_MAX_BYTES {
+            let Some(oldest) = self.letters.pop_front() else {
+                break;
+            };
+            self.bytes -= oldest.frame.len();
+            self.overflowed += 1;
+        }
+    }
+
+    /// 再送するフレームを取り出します（番号を指定しなければすべて）
+    pub fn take(&mut self, id: Option<u32>) -> Vec<DeadLetter> {
+        let (taken, kept): (Vec<DeadLetter>, Vec<DeadLetter>) = std::mem::take(&mut self.letters)
+            .into_iter()
+            .partition(|letter| id.is_none_or(|id| letter.id == id));
+        self.letters = kept.into();
+        self.bytes -= taken.iter().map(|letter| letter.frame.len()).sum::<usize>();
+        taken
+    }
+
+    /// 再送できたフレーム数を記録します
+    pub fn record_replayed(&mut self, count: u32) {
+        self.replayed += count;
+    }
+
+    /// `DLQ_LIST` への応答（1フレーム1行、退避中のフレームがなければ空の1行）
+    pub fn list_response(&self, now: Instant) -> String {
+        if self.letters.is_empty() {
+            return format!("{}\n", DLQ_RESPONSE_PREFIX);
+        }
+        self.letters
+            .iter()
+            .map(|letter| format!("{}{}\n", DLQ_RESPONSE_PREFIX, letter.summary(now)))
+            .collect()
+    }
+
+    /// `DLQ_REPLAY` への応答
+    pub fn replay_response(&self, replayed: usize, failed: usize) -> String {
+        format!(
+            "{}REPLAYED={},FAILED={},DEPTH={}\n",
+            DLQ_RESPONSE_PREFIX,
+            replayed,
+            failed,
+            self.letters.len()
+        )
+    }
+
+    /// 統計フレームの項目（`<退避中>/<退避>/<再送>/<破棄>` 形式）を返して回数をリセットします
+    pub fn take_stats_field(&mut self) -> String {
+        let field = format!("{}/{}/{}/{}", self.letters.len(), self.added, self.replayed, self.overflowed);
+        self.added = 0;
+        self.replayed = 0;
+        self.overflowed = 0;
+        field
+    }
+}
+
+impl Default for DeadLetterQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::esp_now::FrameType;
+
+    const MAC: [u8; 6] = [0x34, 0xab, 0x95, 0xfb, 0x3f, 0xc4];
+
+    fn data_frame(len: usize) -> Vec<u8> {
+        Frame::new(MAC, FrameType::Data, 7, vec![0xAB; len]).to_bytes()
+    }
+
+    #[test]
+    fn test_list_and_replay_dead_letters() {
+        let now = Instant::now();
+        let mut queue = DeadLetterQueue::new();
+        assert_eq!(queue.list_response(now), "CMD_DLQ:\n");
+
+        queue.push(data_frame(200), &UsbError::Timeout, now);
+        queue.push(vec![1, 2, 3], &UsbError::WriteError("stalled".to_string()), now);
+        let list = queue.list_response(now);
+        let lines: Vec<&str> = list.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0], "CMD_DLQ:1,34:ab:95:fb:3f:c4,DATA,227B,AGE_S=0,ATTEMPTS=1,REASON=USB operation timed out");
+        assert_eq!(lines[1], "CMD_DLQ:2,-,RAW,3B,AGE_S=0,ATTEMPTS=1,REASON=USB write error: stalled");
+
+        // 番号を指定すればそのフレームだけを取り出す
+        let taken = queue.take(Some(2));
+        assert_eq!(taken.len(), 1);
+        assert_eq!(queue.len(), 1);
+        queue.requeue(taken.into_iter().next().unwrap(), &UsbError::Timeout, now);
+        let all = queue.take(None);
+        assert_eq!(all.iter().map(|letter| (letter.id, letter.attempts)).collect::<Vec<_>>(), vec![(1, 1), (2, 2)]);
+        queue.record_replayed(2);
+        assert!(queue.is_empty());
+        assert_eq!(queue.replay_response(2, 0), "CMD_DLQ:REPLAYED=2,FAILED=0,DEPTH=0\n");
+        assert_eq!(queue.take_stats_field(), "0/2/2/0");
+    }
+
+    #[test]
+    fn test_drops_oldest_beyond_limits() {
+        let now = Instant::now();
+        let mut queue = DeadLetterQueue::new();
+        for _ in 0..DEAD_LETTER_CAPACITY + 2 {
+            queue.push(vec![0; 10], &UsbError::Timeout, now);
+        }
+        assert_eq!(queue.len(), DEAD_LETTER_CAPACITY);
+        assert_eq!(queue.take(Some(1)), vec![]);
+
+        // バイト数の上限を超えた場合も古い順に破棄する
+        queue.push(vec![0; DEAD_LETTER_MAX_BYTES - 100], &UsbError::Timeout, now);
+        assert!(queue.take(None).iter().map(|letter| letter.frame.len()).sum::<usize>() <= DEAD_LETTER_MAX_BYTES);
+        assert_eq!(queue.take_stats_field(), "0/67/0/56");
+    }
+}
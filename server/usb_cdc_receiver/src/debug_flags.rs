@@ -0,0 +1,192 @@
+//! デバイスのデバッグ・バイパスフラグの一時的な切り替え
+//!
+//! `SET_DEBUG` で登録したフラグは、デバイスが次に転送を終えたときに設定更新
+//! （`DEBUG=<フラグ>/<サイクル数>`）として送信します。デバイスは次回の起動から指定サイクル数だけ
+//! フラグを有効にし、HASHフレームの `DEBUG:<フラグ>/<残りサイクル数>` で報告します。
+//! 報告が登録したフラグと一致した時点で登録を取り除き、一致しない間は転送のたびに送り直します。
+
+use std::collections::BTreeMap;
+
+use crate::camera_settings::CheckIn;
+use crate::esp_now::telemetry::TelemetryFields;
+use crate::mac_address::format_mac_address;
+
+/// デバッグフラグコマンド応答の接頭辞
+pub const DEBUG_RESPONSE_PREFIX: &str = "CMD_DEBUG:";
+
+/// 指定できるフラグ名（デバイスの報告と同じ順）
+pub const DEBUG_FLAG_NAMES: &[&str] = &["DEBUG", "FORCE_CAMERA", "BYPASS_VOLTAGE"];
+
+/// すべてのフラグを解除する指定
+pub const DEBUG_OFF: &str = "OFF";
+
+/// サイクル数を省略したときの既定値
+pub const DEFAULT_DEBUG_CYCLES: u16 = 3;
+/// サイクル数の上限（デバイス側の上限と同じ）
+pub const MAX_DEBUG_CYCLES: u16 = 100;
+
+/// デバイスへ送るデバッグフラグ
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DebugRequest {
+    /// `+` 区切りのフラグ名（報告と同じ順）、解除なら `OFF`
+    pub flags: String,
+    /// 有効にするサイクル数（解除では無視）
+    pub cycles: u16,
+}
+
+impl DebugRequest {
+    /// `+` 区切りのフラグ名（大文字小文字は区別しない）または `OFF` から作成します
+    pub fn parse(flags: &str, cycles: u16) -> Option<Self> {
+        if flags.eq_ignore_ascii_case(DEBUG_OFF) {
+            return Some(Self { flags: DEBUG_OFF.to_string(), cycles: 0 });
+        }
+        let names: Vec<&str> = flags.split('+').collect();
+        let known = names
+            .iter()
+            .all(|name| DEBUG_FLAG_NAMES.iter().any(|flag| flag.eq_ignore_ascii_case(name)));
+        if !known || !(1..=MAX_DEBUG_CYCLES).contains(&cycles) {
+            return None;
+        }
+        let flags = DEBUG_FLAG_NAMES
+            .iter()
+            .filter(|flag| names.iter().any(|name| flag.eq_ignore_ascii_case(name)))
+            .copied()
+            .collect::<Vec<_>>()
+            .join("+");
+        Some(Self { flags, cycles })
+    }
+
+    /// 解除の指定か
+    pub fn is_off(&self) -> bool {
+        self.flags == DEBUG_OFF
+    }
+
+    /// 設定更新メッセージの設定文字列（`DEBUG=<フラグ>/<サイクル数>` または `DEBUG=OFF`）
+    pub fn encode(&self) -> String {
+        if self.is_off() {
+            format!("DEBUG={}", DEBUG_OFF)
+        } else {
+            format!("DEBUG={}/{}", self.flags, self.cycles)
+        }
+    }
+
+    /// HASHペイロードの報告が登録したフラグと一致するか（解除は報告がないことで確認する）
+    pub fn is_reported_in(&self, payload: &[u8]) -> bool {
+        let Some(telemetry) = TelemetryFields::parse(payload) else {
+            return false;
+        };
+        let reported = telemetry
+            .get("DEBUG")
+            .map(|value| value.split_once('/').map_or(value, |(flags, _)| flags).trim());
+        match reported {
+            Some(flags) => flags.eq_ignore_ascii_case(&self.flags),
+            None => self.is_off(),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct PendingRequest {
+    request: DebugRequest,
+    due: bool,
+}
+
+/// デバイスごとの未適用のデバッグフラグ
+#[derive(Debug, Default)]
+pub struct DebugRequestRegistry {
+    pending: BTreeMap<[u8; 6], PendingRequest>,
+}
+
+impl DebugRequestRegistry {
+    /// 空の登録を作成します
+    pub const fn new() -> Self {
+        Self { pending: BTreeMap::new() }
+    }
+
+    /// フラグを登録します（未適用の登録は置き換え）
+    pub fn request(&mut self, mac: [u8; 6], request: DebugRequest) {
+        self.pending.insert(mac, PendingRequest { request, due: false });
+    }
+
+    /// デバイスの転送完了時にHASHペイロードの報告と照合します
+    pub fn record_check_in(&mut self, mac: &[u8; 6], payload: Option<&[u8]>) -> CheckIn {
+        let Some(pending) = self.pending.get_mut(mac) else {
+            return CheckIn::Idle;
+        };
+        if payload.is_some_and(|payload| pending.request.is_reported_in(payload)) {
+            self.pending.remove(mac);
+            return CheckIn::Confirmed;
+        }
+        pending.due = true;
+        CheckIn::Deliver
+    }
+
+    /// 送信を予約したフラグを1件取り出します
+    pub fn take_due(&mut self) -> Option<([u8; 6], DebugRequest)> {
+        let (mac, pending) = self.pending.iter_mut().find(|(_, pending)| pending.due)?;
+        pending.due = false;
+        Some((*mac, pending.request.clone()))
+    }
+
+    /// 未適用のフラグが登録されているか
+    pub fn is_pending(&self, mac: &[u8; 6]) -> bool {
+        self.pending.contains_key(mac)
+    }
+
+    /// `SET_DEBUG` への応答行
+    pub fn response(&self, mac: &[u8; 6]) -> String {
+        let mac_str = format_mac_address(mac);
+        match self.pending.get(mac) {
+            Some(pending) => format!(
+                "{}{} pending {}\n",
+                DEBUG_RESPONSE_PREFIX,
+                mac_str,
+                pending.request.encode()
+            ),
+            None => format!("{}{} applied\n", DEBUG_RESPONSE_PREFIX, mac_str),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAC: [u8; 6] = [0x34, 0xab, 0x95, 0xfb, 0x3f, 0xc4];
+
+    #[test]
+    fn test_parse_canonicalizes_flag_order() {
+        let request = DebugRequest::parse("bypass_voltage+debug", 5).unwrap();
+        assert_eq!(request.encode(), "DEBUG=DEBUG+BYPASS_VOLTAGE/5");
+        assert_eq!(DebugRequest::parse("off", 5).unwrap().encode(), "DEBUG=OFF");
+        assert_eq!(DebugRequest::parse("VERBOSE", 5), None);
+        assert_eq!(DebugRequest::parse("DEBUG", 0), None);
+        assert_eq!(DebugRequest::parse("DEBUG", MAX_DEBUG_CYCLES + 1), None);
+    }
+
+    #[test]
+    fn test_flags_are_delivered_until_reported() {
+        let request = DebugRequest::parse("DEBUG+FORCE_CAMERA", 3).unwrap();
+        let mut registry = DebugRequestRegistry::new();
+        registry.request(MAC, request.clone());
+        assert_eq!(registry.response(&MAC), "CMD_DEBUG:34:ab:95:fb:3f:c4 pending DEBUG=DEBUG+FORCE_CAMERA/3\n");
+
+        let before = b"HASH:ab,VOLT:80";
+        assert_eq!(registry.record_check_in(&MAC, Some(before)), CheckIn::Deliver);
+        assert_eq!(registry.take_due(), Some((MAC, request)));
+        assert_eq!(registry.take_due(), None);
+
+        // 残りサイクル数は照合しない
+        let applied = b"HASH:ab,VOLT:80,DEBUG:DEBUG+FORCE_CAMERA/2";
+        assert_eq!(registry.record_check_in(&MAC, Some(applied)), CheckIn::Confirmed);
+        assert!(!registry.is_pending(&MAC));
+        assert_eq!(registry.response(&MAC), "CMD_DEBUG:34:ab:95:fb:3f:c4 applied\n");
+    }
+
+    #[test]
+    fn test_off_is_confirmed_by_absent_report() {
+        let off = DebugRequest::parse("OFF", DEFAULT_DEBUG_CYCLES).unwrap();
+        assert!(!off.is_reported_in(b"HASH:ab,DEBUG:DEBUG/4"));
+        assert!(off.is_reported_in(b"HASH:ab,VOLT:80"));
+    }
+}
@@ -0,0 +1,132 @@
+//! デバイスのグループ
+//!
+//! 運用者が「北側の圃場のカメラ」のような単位で扱えるよう、デバイスごとにグループ名を1つ登録します
+//! （`GROUP_ADD`）。別のグループに登録済みのデバイスは新しいグループへ移ります。
+//! `GROUP_SLEEP`・`GROUP_CAPTURE` はゲートウェイでメンバーごとのスリープコマンドに展開し、
+//! `CMD_SEND_ESP_NOW` と同じくデバイスごとの許容範囲と一時停止を適用してからキューへ渡します。
+//! デバイスは起床するたびに撮影するため、`GROUP_CAPTURE` は最短のスリープ
+//! （`GROUP_CAPTURE_SLEEP_SECONDS`）を送り、次の起床ですぐに撮影させます。どちらも次の転送後の
+//! コマンド待機中に届くため、PCは受信した画像へのスリープコマンドの代わりに送ります。
+//!
+//! グループはRAMのみに保持するため、ゲートウェイの再起動後はPCから設定し直します。
+//! FLEET_SUMMARYはデバイスごとの `group` と、グループごとの集計（`groups`）を報告します。
+
+use std::collections::BTreeMap;
+
+use crate::command::MIN_SLEEP_SECONDS;
+use crate::mac_address::format_mac_address;
+
+/// グループコマンド応答の接頭辞
+pub const GROUP_RESPONSE_PREFIX: &str = "CMD_GROUP:";
+
+/// グループ名の最大長
+pub const MAX_GROUP_NAME_LEN: usize = 16;
+
+/// `GROUP_CAPTURE` で送るスリープ時間（秒）
+pub const GROUP_CAPTURE_SLEEP_SECONDS: u32 = MIN_SLEEP_SECONDS;
+
+/// グループ名として使えるか（英数字・`_`・`-` のみ、1-16文字）
+pub fn is_valid_group_name(name: &str) -> bool {
+    (1..=MAX_GROUP_NAME_LEN).contains(&name.len())
+        && name.bytes().all(|byte| byte.is_ascii_alphanumeric() || byte == b'_' || byte == b'-')
+}
+
+/// デバイスごとのグループ
+#[derive(Debug, Clone, Default)]
+pub struct DeviceGroups {
+    groups: BTreeMap<[u8; 6], String>,
+}
+
+impl DeviceGroups {
+    /// グループなしで作成します
+    pub const fn new() -> Self {
+        Self { groups: BTreeMap::new() }
+    }
+
+    /// デバイスをグループに登録し、別のグループから移ったデバイスの数を返します
+    pub fn add(&mut self, group: &str, macs: &[[u8; 6]]) -> usize {
+        macs.iter()
+            .filter_map(|mac| self.groups.insert(*mac, group.to_string()))
+            .filter(|previous| previous != group)
+            .count()
+    }
+
+    /// デバイスのグループ
+    pub fn group_of(&self, mac: &[u8; 6]) -> Option<&str> {
+        self.groups.get(mac).map(String::as_str)
+    }
+
+    /// グループのメンバー（MACアドレス順）
+    pub fn members(&self, group: &str) -> Vec<[u8; 6]> {
+        self.groups
+            .iter()
+            .filter(|(_, name)| name.as_str() == group)
+            .map(|(mac, _)| *mac)
+            .collect()
+    }
+
+    /// `GROUP_ADD` への応答（`CMD_GROUP:north members=2 34:ab:..,34:ab:..`、メンバーがなければ `-`）
+    pub fn members_response(&self, group: &str) -> String {
+        let members = self.members(group);
+        let macs: Vec<String> = members.iter().map(format_mac_address).collect();
+        format!(
+            "{}{} members={} {}\n",
+            GROUP_RESPONSE_PREFIX,
+            group,
+            members.len(),
+            if macs.is_empty() { "-".to_string() } else { macs.join(",") }
+        )
+    }
+}
+
+/// `GROUP_SLEEP`・`GROUP_CAPTURE` への応答（`CMD_GROUP:north sleep=600 queued=2/3`）
+///
+/// # 引数
+/// * `action` - 展開したコマンド（`sleep=SECONDS` または `capture`）
+/// * `queued` - キューへ渡せたメンバーの数
+/// * `members` - グループのメンバーの数
+pub fn dispatch_response(group: &str, action: &str, queued: usize, members: usize) -> String {
+    format!(
+        "{}{} {} queued={}/{}\n",
+        GROUP_RESPONSE_PREFIX, group, action, queued, members
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAC_A: [u8; 6] = [0x34, 0xab, 0x95, 0xfb, 0x3f, 0xc4];
+    const MAC_B: [u8; 6] = [0x34, 0xab, 0x95, 0xfb, 0x3f, 0xc5];
+
+    #[test]
+    fn test_group_names() {
+        assert!(is_valid_group_name("north_field-1"));
+        assert!(!is_valid_group_name(""));
+        assert!(!is_valid_group_name("north field"));
+        assert!(!is_valid_group_name("a".repeat(MAX_GROUP_NAME_LEN + 1).as_str()));
+    }
+
+    #[test]
+    fn test_devices_belong_to_one_group() {
+        let mut groups = DeviceGroups::new();
+        assert_eq!(groups.add("north", &[MAC_A, MAC_B]), 0);
+        assert_eq!(groups.members("north"), vec![MAC_A, MAC_B]);
+        assert_eq!(
+            groups.members_response("north"),
+            "CMD_GROUP:north members=2 34:ab:95:fb:3f:c4,34:ab:95:fb:3f:c5\n"
+        );
+
+        // 登録し直しても移動には数えない。別のグループへ登録すると移る
+        assert_eq!(groups.add("north", &[MAC_A]), 0);
+        assert_eq!(groups.add("south", &[MAC_B]), 1);
+        assert_eq!(groups.group_of(&MAC_A), Some("north"));
+        assert_eq!(groups.group_of(&MAC_B), Some("south"));
+        assert_eq!(groups.group_of(&[0; 6]), None);
+        assert_eq!(groups.members("north"), vec![MAC_A]);
+        assert_eq!(groups.members("east"), Vec::<[u8; 6]>::new());
+        assert_eq!(groups.members_response("east"), "CMD_GROUP:east members=0 -\n");
+
+        assert_eq!(dispatch_response("north", "capture", 1, 1), "CMD_GROUP:north capture queued=1/1\n");
+    }
+}
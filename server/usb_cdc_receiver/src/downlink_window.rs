@@ -0,0 +1,186 @@
+//! 転送後のコマンド待機の短縮（HASHフレームへの応答）
+//!
+//! デバイスは転送後、スリープコマンドや設定更新などのダウンリンクのコマンドを一定時間待ってからスリープします。
+//! 疎通確認のPingで `CAPABILITY_COMMAND_WINDOW` を示したデバイスには、HASHフレームの受信時に送信待ちの
+//! コマンドの有無と件数を返し、コマンドがなければデバイスは待たずにスリープします。
+//! ホストは転送のたびにスリープコマンドを返すため、前回の転送の後にスリープコマンドが届いたデバイス
+//! （初めてのデバイスを含む）は、今回もスリープコマンドが届くとみなしてコマンドありと返します。
+//! `CAPABILITY_LOSS_REPORT` を示したデバイスには、前回の転送の完了イベントでギャップマップから求めた
+//! DATA/FECの損失率を付加し、デバイスは次の転送のFECパラメータをこの値から決めます。
+//! `CAPABILITY_GATEWAY_CLOCK` を示したデバイスにはゲートウェイの稼働時間も付加し、デバイスは前回の応答からの
+//! 経過時間を自身のRTCと比べてスリープ中のドリフトを推定します。
+
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use crate::esp_now::{HashAckMessage, PingMessage};
+
+/// デバイスへ送信待ちのコマンド数を数える関数
+pub type PendingCommandLookup = fn(&[u8; 6]) -> usize;
+
+/// デバイスごとのコマンド待機の状態（ESP-NOWコールバックとコマンド処理タスクで共有）
+pub static DOWNLINK_WINDOW: Mutex<DownlinkWindow> = Mutex::new(DownlinkWindow::new());
+
+#[derive(Debug, Clone, Copy)]
+struct DeviceWindow {
+    /// HASHフレームへの応答を受け取れる
+    command_window: bool,
+    /// 前回のHASHフレームの後にホストからスリープコマンドが届いた
+    host_replied: bool,
+    /// HASHフレームへの応答で損失率を受け取れる
+    loss_report: bool,
+    /// HASHフレームへの応答でゲートウェイの時計を受け取れる
+    gateway_clock: bool,
+    /// 前回の転送で観測したDATA/FECの損失率（%、完了イベントがまだなければNone）
+    loss_percent: Option<u8>,
+}
+
+/// デバイスごとのコマンド待機の状態と応答の集計
+#[derive(Debug, Default)]
+pub struct DownlinkWindow {
+    devices: BTreeMap<[u8; 6], DeviceWindow>,
+    acks: u32,
+    no_pending: u32,
+}
+
+impl DownlinkWindow {
+    /// 空の状態を作成します
+    pub const fn new() -> Self {
+        Self {
+            devices: BTreeMap::new(),
+            acks: 0,
+            no_pending: 0,
+        }
+    }
+
+    fn device(&mut self, mac: [u8; 6]) -> &mut DeviceWindow {
+        self.devices.entry(mac).or_insert(DeviceWindow {
+            command_window: false,
+            host_replied: true,
+            loss_report: false,
+            gateway_clock: false,
+            loss_percent: None,
+        })
+    }
+
+    /// 疎通確認のPingで示された機能を記録します
+    pub fn record_ping(&mut self, mac: [u8; 6], ping: &PingMessage) {
+        let device = self.device(mac);
+        device.command_window = ping.command_window;
+        device.loss_report = ping.loss_report;
+        device.gateway_clock = ping.gateway_clock;
+    }
+
+    /// 完了した転送のDATA/FECの損失率を記録します（次のHASHフレームへの応答で返す）
+    pub fn record_transfer_loss(&mut self, mac: [u8; 6], loss_percent: Option<u8>) {
+        if loss_percent.is_some() {
+            self.device(mac).loss_percent = loss_percent;
+        }
+    }
+
+    /// ホストからのスリープコマンドを記録します
+    pub fn record_sleep_command(&mut self, mac: [u8; 6]) {
+        self.device(mac).host_replied = true;
+    }
+
+    /// HASHフレームへの応答を作成します（応答を受け取れないデバイスにはNone）
+    ///
+    /// # 引数
+    /// * `queued` - 送信待ちのコマンド数（設定更新・再送要求など）
+    /// * `gateway_us` - ゲートウェイの稼働時間（マイクロ秒）
+    pub fn on_hash(&mut self, mac: [u8; 6], queued: usize, gateway_us: u64) -> Option<HashAckMessage> {
+        let device = self.devices.get_mut(&mac).filter(|device| device.command_window)?;
+        let expects_sleep_command = std::mem::replace(&mut device.host_replied, false);
+        let loss_percent = device.loss_percent.filter(|_| device.loss_report);
+        let gateway_us = device.gateway_clock.then_some(gateway_us);
+        let pending_count = (queued + usize::from(expects_sleep_command)).min(u8::MAX as usize) as u8;
+        self.acks += 1;
+        if pending_count == 0 {
+            self.no_pending += 1;
+        }
+        Some(HashAckMessage {
+            commands_pending: pending_count > 0,
+            pending_count,
+            loss_percent,
+            gateway_us,
+        })
+    }
+
+    /// 統計フレームの項目（`<応答数>/<コマンドなし>` 形式）を返して集計をリセットします
+    pub fn take_stats_field(&mut self) -> String {
+        let field = format!("{}/{}", self.acks, self.no_pending);
+        self.acks = 0;
+        self.no_pending = 0;
+        field
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAC: [u8; 6] = [0x34, 0xab, 0x95, 0xfb, 0x3f, 0xc4];
+    const LEGACY_MAC: [u8; 6] = [0x34, 0xab, 0x95, 0xfb, 0x3f, 0xc5];
+
+    fn ping(command_window: bool, loss_report: bool, gateway_clock: bool) -> PingMessage {
+        PingMessage {
+            nonce: 1,
+            radio: None,
+            privacy: false,
+            channel_hop: false,
+            command_window,
+            chunk_size: false,
+            loss_report,
+            gateway_clock,
+        }
+    }
+
+    #[test]
+    fn test_hash_ack_expects_sleep_command_while_host_replies() {
+        let mut window = DownlinkWindow::new();
+        window.record_ping(MAC, &ping(true, false, false));
+        window.record_ping(LEGACY_MAC, &ping(false, false, false));
+        assert_eq!(window.on_hash(LEGACY_MAC, 3, 0), None);
+
+        // 初めてのデバイスはスリープコマンドが届くとみなす
+        let ack = window.on_hash(MAC, 1, 0).unwrap();
+        assert_eq!(ack, HashAckMessage { commands_pending: true, pending_count: 2, loss_percent: None, gateway_us: None });
+        window.record_sleep_command(MAC);
+        assert!(window.on_hash(MAC, 0, 0).unwrap().commands_pending);
+
+        // 前回の転送にホストが応答しなかった場合は、送信待ちのコマンドがなければ待たせない
+        assert_eq!(
+            window.on_hash(MAC, 0, 0),
+            Some(HashAckMessage { commands_pending: false, pending_count: 0, loss_percent: None, gateway_us: None })
+        );
+        assert_eq!(window.on_hash(MAC, 1, 0).unwrap().pending_count, 1);
+        assert_eq!(window.take_stats_field(), "4/1");
+        assert_eq!(window.take_stats_field(), "0/0");
+    }
+
+    #[test]
+    fn test_hash_ack_reports_previous_transfer_loss() {
+        let mut window = DownlinkWindow::new();
+        window.record_ping(MAC, &ping(true, true, false));
+        window.record_ping(LEGACY_MAC, &ping(true, false, false));
+        // 完了イベントがまだなければ付加しない
+        assert_eq!(window.on_hash(MAC, 0, 0).unwrap().loss_percent, None);
+
+        window.record_transfer_loss(MAC, Some(12));
+        window.record_transfer_loss(LEGACY_MAC, Some(12));
+        // データを受信しなかった転送は前回の値を上書きしない
+        window.record_transfer_loss(MAC, None);
+        assert_eq!(window.on_hash(MAC, 0, 0).unwrap().loss_percent, Some(12));
+        // 損失率を受け取れないデバイスには従来の長さで返す
+        assert_eq!(window.on_hash(LEGACY_MAC, 0, 0).unwrap().loss_percent, None);
+    }
+
+    #[test]
+    fn test_hash_ack_carries_gateway_clock_when_requested() {
+        let mut window = DownlinkWindow::new();
+        window.record_ping(MAC, &ping(true, false, true));
+        window.record_ping(LEGACY_MAC, &ping(true, false, false));
+        assert_eq!(window.on_hash(MAC, 0, 5_000_000).unwrap().gateway_us, Some(5_000_000));
+        assert_eq!(window.on_hash(LEGACY_MAC, 0, 5_000_000).unwrap().gateway_us, None);
+    }
+}
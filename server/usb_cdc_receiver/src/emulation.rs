@@ -0,0 +1,294 @@
+//! 仮想デバイスのエミュレーション（ハードウェアなしでPC側を開発するため）
+//!
+//! `EMULATE <n> [INTERVAL_S]` で、ゲートウェイ内にn台の仮想カメラを作ります。仮想カメラは
+//! 間隔ごとに小さなテスト画像を実機と同じ順序（DATAチャンク、HASH、EOF）のフレームにして
+//! データキューへ投入するため、完了イベントへの集約・統計・USB送出は実機の転送と同じ経路を通ります。
+//!
+//! 合成したデータは次の2点で区別できます。
+//! - MACアドレスはローカル管理アドレスの `02:FE:ED:00:00:<番号>`
+//! - HASHペイロードに `SYNTH:1` とデバイスID `emu-<番号>` を付加
+//!
+//! 仮想カメラへのスリープコマンドは無線で送信せずに破棄します。
+
+use std::time::{Duration, Instant};
+
+use crate::esp_now::frame::create_frame;
+use crate::esp_now::FrameType;
+use crate::mac_address::format_mac_address;
+
+/// エミュレーションコマンド応答の接頭辞
+pub const EMULATE_RESPONSE_PREFIX: &str = "CMD_EMULATE:";
+
+/// 仮想カメラの最大台数
+pub const MAX_EMULATED_DEVICES: usize = 8;
+
+/// 転送間隔の既定値（秒）
+pub const DEFAULT_EMULATION_INTERVAL_S: u32 = 60;
+
+/// 転送間隔の最小値（秒）
+pub const MIN_EMULATION_INTERVAL_S: u32 = 5;
+
+/// 転送間隔の最大値（秒、1時間）
+pub const MAX_EMULATION_INTERVAL_S: u32 = 3600;
+
+/// 仮想カメラのMACアドレスの上位3バイト（ローカル管理アドレス）
+pub const EMULATED_MAC_PREFIX: [u8; 3] = [0x02, 0xFE, 0xED];
+
+/// DATAフレーム1つに載せる画像のバイト数（実機のESP-NOWペイロードに収まる大きさ）
+pub const EMULATED_CHUNK_SIZE: usize = 200;
+
+/// 仮想カメラが送るテスト画像（96x72のJPEG）
+pub const EMULATED_IMAGE: &[u8] = include_bytes!("emulated_camera.jpg");
+
+/// 時刻を持たないデバイスと同じダミーのタイムスタンプ
+const DUMMY_TIMESTAMP: &str = "1900/01/01 00:00:00.000";
+
+/// 仮想カメラのMACアドレス（番号は1から）
+pub fn emulated_mac(number: u8) -> [u8; 6] {
+    [EMULATED_MAC_PREFIX[0], EMULATED_MAC_PREFIX[1], EMULATED_MAC_PREFIX[2], 0, 0, number]
+}
+
+/// 仮想カメラのMACアドレスか
+pub fn is_emulated_mac(mac: &[u8; 6]) -> bool {
+    mac[..3] == EMULATED_MAC_PREFIX
+}
+
+/// 長さとバイト和のハッシュ（`HASH_ALGO:SUM`、デバイス・サーバーと同じ形式）
+fn sum_hash(data: &[u8]) -> String {
+    format!(
+        "{:08x}{:08x}",
+        data.len(),
+        data.iter().fold(0u32, |sum, &b| sum.wrapping_add(b as u32))
+    )
+}
+
+/// 1台の仮想カメラ
+#[derive(Debug, Clone)]
+struct EmulatedDevice {
+    number: u8,
+    next_sequence: u32,
+    transfers: u32,
+    next_due: Instant,
+}
+
+impl EmulatedDevice {
+    fn mac(&self) -> [u8; 6] {
+        emulated_mac(self.number)
+    }
+
+    /// 転送ごとに変わる合成テレメトリ（電圧は100%から下がって戻り、温度は0.5°C刻みで上下する）
+    fn hash_payload(&self, image: &[u8]) -> String {
+        let voltage = 100 - (self.transfers % 60);
+        let step = self.transfers % 20;
+        let offset = if step < 10 { step } else { 20 - step };
+        let temperature = 20.0 + self.number as f32 + offset as f32 * 0.5;
+        format!(
+            "HASH:{},VOLT:{},TEMP:{:.1},TDS_VOLT:-999.0,{},HASH_ALGO:SUM,SYNTH:1,DEV_ID:emu-{}",
+            sum_hash(image),
+            voltage,
+            temperature,
+            DUMMY_TIMESTAMP,
+            self.number
+        )
+    }
+
+    /// 1回の転送のフレーム（DATAチャンク、HASH、EOFの順）
+    fn transfer_frames(&mut self, image: &[u8]) -> Vec<Vec<u8>> {
+        let mac = self.mac();
+        let payloads = image
+            .chunks(EMULATED_CHUNK_SIZE)
+            .map(|chunk| (FrameType::Data, chunk.to_vec()))
+            .chain([
+                (FrameType::Hash, self.hash_payload(image).into_bytes()),
+                (FrameType::Eof, b"EOF".to_vec()),
+            ]);
+        let frames = payloads
+            .map(|(frame_type, payload)| {
+                let sequence = self.next_sequence;
+                self.next_sequence = sequence.wrapping_add(1);
+                create_frame(mac, &payload, frame_type, sequence)
+            })
+            .collect();
+        self.transfers = self.transfers.wrapping_add(1);
+        frames
+    }
+}
+
+/// 仮想カメラの1回の転送
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EmulatedTransfer {
+    /// 仮想カメラのMACアドレス
+    pub mac: [u8; 6],
+    /// 送信順のフレーム
+    pub frames: Vec<Vec<u8>>,
+}
+
+/// 仮想カメラの一覧と転送のスケジュール
+#[derive(Debug)]
+pub struct Emulator {
+    devices: Vec<EmulatedDevice>,
+    interval: Duration,
+    transfers: u32,
+}
+
+impl Emulator {
+    /// 仮想カメラなしで作成します
+    pub const fn new() -> Self {
+        Self {
+            devices: Vec::new(),
+            interval: Duration::from_secs(DEFAULT_EMULATION_INTERVAL_S as u64),
+            transfers: 0,
+        }
+    }
+
+    /// 仮想カメラの台数と転送間隔を設定します（0台で停止）
+    ///
+    /// 転送が同時に集中しないよう、各カメラの最初の転送を間隔内で等分にずらします。
+    pub fn configure(&mut self, count: usize, interval_s: u32, now: Instant) {
+        let count = count.min(MAX_EMULATED_DEVICES);
+        self.interval = Duration::from_secs(interval_s as u64);
+        self.devices = (0..count)
+            .map(|index| EmulatedDevice {
+                number: index as u8 + 1,
+                next_sequence: 0,
+                transfers: 0,
+                next_due: now + self.interval * index as u32 / count as u32,
+            })
+            .collect();
+    }
+
+    /// 仮想カメラが動いているか
+    pub fn is_active(&self) -> bool {
+        !self.devices.is_empty()
+    }
+
+    /// 次の転送まで待つ時間（仮想カメラがなければNone）
+    pub fn next_due_in(&self, now: Instant) -> Option<Duration> {
+        self.devices
+            .iter()
+            .map(|device| device.next_due.saturating_duration_since(now))
+            .min()
+    }
+
+    /// 予定時刻を過ぎた仮想カメラの転送を生成し、次の予定を設定します
+    pub fn poll(&mut self, now: Instant) -> Vec<EmulatedTransfer> {
+        let interval = self.interval;
+        let transfers: Vec<EmulatedTransfer> = self
+            .devices
+            .iter_mut()
+            .filter(|device| device.next_due <= now)
+            .map(|device| {
+                device.next_due = now + interval;
+                EmulatedTransfer {
+                    mac: device.mac(),
+                    frames: device.transfer_frames(EMULATED_IMAGE),
+                }
+            })
+            .collect();
+        self.transfers = self.transfers.wrapping_add(transfers.len() as u32);
+        transfers
+    }
+
+    /// エミュレーションコマンドへの応答行
+    pub fn response(&self) -> String {
+        if self.devices.is_empty() {
+            return format!("{}off (transfers={})\n", EMULATE_RESPONSE_PREFIX, self.transfers);
+        }
+        format!(
+            "{}devices={} interval_s={} macs={}..{} transfers={}\n",
+            EMULATE_RESPONSE_PREFIX,
+            self.devices.len(),
+            self.interval.as_secs(),
+            format_mac_address(&emulated_mac(1)),
+            format_mac_address(&emulated_mac(self.devices.len() as u8)),
+            self.transfers
+        )
+    }
+}
+
+impl Default for Emulator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::esp_now::frame::Frame;
+
+    fn parse(frames: &[Vec<u8>]) -> Vec<Frame> {
+        frames.iter().map(|bytes| Frame::from_bytes(bytes).unwrap().0).collect()
+    }
+
+    #[test]
+    fn test_transfer_frames_follow_device_order() {
+        let mut emulator = Emulator::new();
+        let now = Instant::now();
+        emulator.configure(1, 60, now);
+
+        let [transfer] = &emulator.poll(now)[..] else {
+            panic!("expected one transfer");
+        };
+        let frames = parse(&transfer.frames);
+        let chunks = EMULATED_IMAGE.len().div_ceil(EMULATED_CHUNK_SIZE);
+        assert_eq!(frames.len(), chunks + 2);
+        assert!(frames[..chunks].iter().all(|frame| frame.frame_type() == FrameType::Data));
+        assert_eq!(frames[chunks].frame_type(), FrameType::Hash);
+        assert_eq!(frames[chunks + 1].frame_type(), FrameType::Eof);
+        // シーケンス番号は送信元ごとの通し番号
+        assert!(frames.iter().enumerate().all(|(i, frame)| frame.sequence_number() == i as u32));
+        assert!(frames.iter().all(|frame| frame.mac_address() == &emulated_mac(1)));
+
+        let image: Vec<u8> = frames[..chunks].iter().flat_map(|frame| frame.data().to_vec()).collect();
+        assert_eq!(image, EMULATED_IMAGE);
+        assert!(image.starts_with(&[0xFF, 0xD8]) && image.ends_with(&[0xFF, 0xD9]));
+    }
+
+    #[test]
+    fn test_hash_payload_is_tagged_and_verifiable() {
+        let mut emulator = Emulator::new();
+        let now = Instant::now();
+        emulator.configure(2, 60, now);
+        let transfers = emulator.poll(now);
+        assert_eq!(transfers.len(), 1);
+
+        let frames = parse(&transfers[0].frames);
+        let hash = String::from_utf8(frames[frames.len() - 2].data().to_vec()).unwrap();
+        assert!(hash.starts_with(&format!("HASH:{},VOLT:100,TEMP:21.0,", sum_hash(EMULATED_IMAGE))));
+        assert!(hash.contains(",HASH_ALGO:SUM,SYNTH:1,DEV_ID:emu-1"));
+        assert!(is_emulated_mac(&transfers[0].mac));
+        assert!(!is_emulated_mac(&[0x24, 0xEC, 0x4A, 0xCA, 0x5E, 0xBC]));
+    }
+
+    #[test]
+    fn test_devices_are_staggered_and_rescheduled() {
+        let mut emulator = Emulator::new();
+        let start = Instant::now();
+        emulator.configure(3, 30, start);
+
+        assert_eq!(emulator.poll(start).len(), 1);
+        assert_eq!(emulator.next_due_in(start), Some(Duration::from_secs(10)));
+        let macs: Vec<[u8; 6]> = emulator.poll(start + Duration::from_secs(20)).iter().map(|t| t.mac).collect();
+        assert_eq!(macs, [emulated_mac(2), emulated_mac(3)]);
+        assert!(emulator.poll(start + Duration::from_secs(29)).is_empty());
+        assert_eq!(emulator.poll(start + Duration::from_secs(30)).len(), 1);
+        assert!(emulator.response().contains("devices=3 interval_s=30"));
+        assert!(emulator.response().ends_with("transfers=4\n"));
+    }
+
+    #[test]
+    fn test_configure_zero_stops_emulation() {
+        let mut emulator = Emulator::new();
+        let now = Instant::now();
+        emulator.configure(MAX_EMULATED_DEVICES + 5, 60, now);
+        assert!(emulator.is_active());
+        assert_eq!(emulator.poll(now + Duration::from_secs(60)).len(), MAX_EMULATED_DEVICES);
+
+        emulator.configure(0, 60, now);
+        assert!(!emulator.is_active());
+        assert_eq!(emulator.next_due_in(now), None);
+        assert!(emulator.poll(now + Duration::from_secs(600)).is_empty());
+        assert_eq!(emulator.response(), "CMD_EMULATE:off (transfers=8)\n");
+    }
+}
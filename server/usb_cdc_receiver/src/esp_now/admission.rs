@@ -0,0 +1,192 @@
+/// 同時転送数の制限（アドミッション制御）
+///
+/// 多数のデバイスが同時に起床するとデータキューが溢れて全員の転送が失敗するため、
+/// 転送開始前の疎通確認Pingの時点で同時転送数を確認し、上限に達していれば
+/// Pongの代わりに延期要求（再試行までの待機時間の目安付き）を返します。
+/// 転送中の扱いは転送完了・中断で解除し、一定時間フレームが届かなければ期限切れとして解除します。
+/// Pingを送らない旧形式のデバイスは最初のフレームで転送中として扱い、延期はしません。
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// 既定の同時転送数の上限
+pub const DEFAULT_MAX_IN_FLIGHT: usize = 3;
+
+/// 既定の再試行までの待機時間（ミリ秒）
+pub const DEFAULT_RETRY_AFTER_MS: u32 = 3000;
+
+/// フレームが届かない転送を期限切れとするまでの時間
+pub const IN_FLIGHT_IDLE_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// 再試行時刻をデバイスごとにずらす刻み（ミリ秒）
+const RETRY_JITTER_STEP_MS: u32 = 250;
+/// 再試行時刻のずらし幅の段数
+const RETRY_JITTER_SLOTS: u32 = 8;
+
+/// 転送開始の可否
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Admission {
+    /// 転送を許可した
+    Admitted,
+    /// 上限に達しているため延期を要求する
+    Defer {
+        /// 再試行までの待機時間の目安（ミリ秒）
+        retry_after_ms: u32,
+    },
+}
+
+/// 転送中のデバイスと同時転送数の上限
+#[derive(Debug)]
+pub struct AdmissionControl {
+    /// 同時転送数の上限（0は無制限）
+    max_in_flight: usize,
+    retry_after_ms: u32,
+    /// 転送中のデバイスと最後にフレームを受信した時刻
+    in_flight: BTreeMap<[u8; 6], Instant>,
+    deferred: u32,
+}
+
+impl Default for AdmissionControl {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AdmissionControl {
+    /// 既定の上限で作成します
+    pub const fn new() -> Self {
+        Self {
+            max_in_flight: DEFAULT_MAX_IN_FLIGHT,
+            retry_after_ms: DEFAULT_RETRY_AFTER_MS,
+            in_flight: BTreeMap::new(),
+            deferred: 0,
+        }
+    }
+
+    /// 上限と再試行までの待機時間を設定します
+    pub fn configure(&mut self, max_in_flight: usize, retry_after_ms: u32) {
+        self.max_in_flight = max_in_flight;
+        self.retry_after_ms = retry_after_ms;
+    }
+
+    /// 延期・拒否したデバイスへ返す再試行までの待機時間（ミリ秒）
+    pub fn retry_after_ms(&self) -> u32 {
+        self.retry_after_ms
+    }
+
+    /// 疎通確認Pingを受けて転送の開始を判定します
+    ///
+    /// 転送中のデバイスからの再Pingは常に許可します。
+    pub fn admit(&mut self, mac: [u8; 6], now: Instant) -> Admission {
+        self.expire(now);
+        if self.max_in_flight == 0
+            || self.in_flight.contains_key(&mac)
+            || self.in_flight.len() < self.max_in_flight
+        {
+            self.in_flight.insert(mac, now);
+            return Admission::Admitted;
+        }
+        self.deferred = self.deferred.saturating_add(1);
+        // 延期したデバイスが同時に再試行しないよう、MACアドレスで待機時間をずらす
+        let jitter = (mac[5] as u32 % RETRY_JITTER_SLOTS) * RETRY_JITTER_STEP_MS;
+        Admission::Defer {
+            retry_after_ms: self.retry_after_ms.saturating_add(jitter),
+        }
+    }
+
+    /// フレームの受信を記録します（Pingなしで始まった転送も転送中として扱う）
+    pub fn touch(&mut self, mac: [u8; 6], now: Instant) {
+        self.in_flight.insert(mac, now);
+    }
+
+    /// 転送の完了・中断で転送中の扱いを解除します
+    pub fn finish(&mut self, mac: &[u8; 6]) {
+        self.in_flight.remove(mac);
+    }
+
+    /// 現在の転送中のデバイス数
+    pub fn in_flight(&mut self, now: Instant) -> usize {
+        self.expire(now);
+        self.in_flight.len()
+    }
+
+    /// 前回の取り出し以降に延期した回数を取り出します
+    pub fn take_deferred(&mut self) -> u32 {
+        std::mem::take(&mut self.deferred)
+    }
+
+    fn expire(&mut self, now: Instant) {
+        self.in_flight
+            .retain(|_, last_seen| now.saturating_duration_since(*last_seen) < IN_FLIGHT_IDLE_TIMEOUT);
+    }
+}
+
+/// 同時転送数の制限（受信コールバックとUSB送信タスクで共有）
+pub static ADMISSION: Mutex<AdmissionControl> = Mutex::new(AdmissionControl::new());
+
+/// 転送の完了・中断を記録します
+pub fn finish_transfer(mac: &[u8; 6]) {
+    if let Ok(mut admission) = ADMISSION.lock() {
+        admission.finish(mac);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mac(last: u8) -> [u8; 6] {
+        [0x34, 0xab, 0x95, 0xfb, 0x3f, last]
+    }
+
+    #[test]
+    fn test_defers_beyond_limit_until_a_transfer_finishes() {
+        let now = Instant::now();
+        let mut admission = AdmissionControl::new();
+        admission.configure(2, 1000);
+
+        assert_eq!(admission.admit(mac(1), now), Admission::Admitted);
+        assert_eq!(admission.admit(mac(2), now), Admission::Admitted);
+        // 転送中のデバイスの再Pingは許可する
+        assert_eq!(admission.admit(mac(1), now), Admission::Admitted);
+        assert_eq!(admission.admit(mac(3), now), Admission::Defer { retry_after_ms: 1750 });
+        assert_eq!(admission.admit(mac(8), now), Admission::Defer { retry_after_ms: 1000 });
+        assert_eq!(admission.take_deferred(), 2);
+        assert_eq!(admission.take_deferred(), 0);
+
+        admission.finish(&mac(1));
+        assert_eq!(admission.admit(mac(3), now), Admission::Admitted);
+        assert_eq!(admission.in_flight(now), 2);
+    }
+
+    #[test]
+    fn test_idle_transfers_expire_and_frames_keep_them_alive() {
+        let start = Instant::now();
+        let mut admission = AdmissionControl::new();
+        admission.configure(1, 1000);
+
+        // Pingなしで始まった転送も数える
+        admission.touch(mac(1), start);
+        assert!(matches!(admission.admit(mac(2), start), Admission::Defer { .. }));
+
+        let later = start + IN_FLIGHT_IDLE_TIMEOUT - Duration::from_secs(1);
+        admission.touch(mac(1), later);
+        assert!(matches!(
+            admission.admit(mac(2), start + IN_FLIGHT_IDLE_TIMEOUT),
+            Admission::Defer { .. }
+        ));
+
+        // フレームが途絶えた転送は期限切れで解除する
+        assert_eq!(admission.admit(mac(2), later + IN_FLIGHT_IDLE_TIMEOUT), Admission::Admitted);
+    }
+
+    #[test]
+    fn test_zero_limit_is_unlimited() {
+        let now = Instant::now();
+        let mut admission = AdmissionControl::new();
+        admission.configure(0, 1000);
+        for last in 0..10 {
+            assert_eq!(admission.admit(mac(last), now), Admission::Admitted);
+        }
+    }
+}
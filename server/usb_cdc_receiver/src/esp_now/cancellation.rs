@@ -0,0 +1,140 @@
+/// 転送中断時のUSB送出キャンセル
+///
+/// デバイスからのABORTフレームや、ゲートウェイでのデータ欠落（データキュー溢れ）を検知した時点で
+/// 送信元の「世代」を進めます。受信データはキュー投入時の世代を持ち、USB送信タスクは
+/// 世代が古いデータ（中断された転送の残りチャンク）をPCへ送らずに破棄します。
+/// 中断の通知はUSB送信タスクが `take_pending` で取り出し、ABORTイベントとして送出します。
+/// 部分転送の救済が有効な場合、データキュー溢れでは中断せず欠落をギャップマップで報告します。
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+/// 部分転送の救済（データキュー溢れで転送を中断しない）
+static PARTIAL_SALVAGE: AtomicBool = AtomicBool::new(false);
+
+/// 部分転送の救済を設定します（起動時に1回）
+pub fn set_partial_salvage(enabled: bool) {
+    PARTIAL_SALVAGE.store(enabled, Ordering::Relaxed);
+}
+
+/// 部分転送の救済が有効かどうか
+pub fn partial_salvage_enabled() -> bool {
+    PARTIAL_SALVAGE.load(Ordering::Relaxed)
+}
+
+/// 中断の理由
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AbortReason {
+    /// デバイスがABORTフレームを送信した
+    Device,
+    /// データキュー溢れによりチャンクが欠落した
+    QueueOverflow,
+    /// チェックサムエラーが多発した（デバイスへ拒否メッセージを返す）
+    ChecksumStorm,
+}
+
+impl AbortReason {
+    /// ABORTイベントのペイロード用文字列
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AbortReason::Device => "DEVICE",
+            AbortReason::QueueOverflow => "QUEUE_OVERFLOW",
+            AbortReason::ChecksumStorm => "CHECKSUM_STORM",
+        }
+    }
+}
+
+/// 送信元ごとの中断世代と未通知の中断
+#[derive(Debug, Default)]
+pub struct CancellationRegistry {
+    epochs: BTreeMap<[u8; 6], u32>,
+    pending: Vec<([u8; 6], AbortReason)>,
+}
+
+impl CancellationRegistry {
+    /// 空のレジストリを作成します
+    pub const fn new() -> Self {
+        Self {
+            epochs: BTreeMap::new(),
+            pending: Vec::new(),
+        }
+    }
+
+    /// 送信元の現在の世代
+    pub fn epoch(&self, mac: &[u8; 6]) -> u32 {
+        self.epochs.get(mac).copied().unwrap_or(0)
+    }
+
+    /// 送信元の転送を中断し、新しい世代を返します
+    ///
+    /// 同じ送信元の未通知の中断がある場合は通知を重ねません。
+    pub fn cancel(&mut self, mac: [u8; 6], reason: AbortReason) -> u32 {
+        let epoch = self.epochs.entry(mac).or_insert(0);
+        *epoch = epoch.wrapping_add(1);
+        if !self.pending.iter().any(|(pending_mac, _)| *pending_mac == mac) {
+            self.pending.push((mac, reason));
+        }
+        *epoch
+    }
+
+    /// 指定世代のデータが中断済みの転送に属するかどうか
+    pub fn is_stale(&self, mac: &[u8; 6], epoch: u32) -> bool {
+        epoch != self.epoch(mac)
+    }
+
+    /// 未通知の中断を取り出します
+    pub fn take_pending(&mut self) -> Vec<([u8; 6], AbortReason)> {
+        std::mem::take(&mut self.pending)
+    }
+}
+
+/// 受信コールバックとUSB送信タスクで共有するレジストリ
+pub static CANCELLATIONS: Mutex<CancellationRegistry> = Mutex::new(CancellationRegistry::new());
+
+/// 共有レジストリで送信元の現在の世代を取得します（ロック失敗時は0）
+pub fn current_epoch(mac: &[u8; 6]) -> u32 {
+    CANCELLATIONS.lock().map_or(0, |registry| registry.epoch(mac))
+}
+
+/// 共有レジストリで送信元の転送を中断します
+pub fn cancel_transfer(mac: [u8; 6], reason: AbortReason) -> u32 {
+    match CANCELLATIONS.lock() {
+        Ok(mut registry) => registry.cancel(mac, reason),
+        Err(poisoned) => poisoned.into_inner().cancel(mac, reason),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAC: [u8; 6] = [0x11, 0x22, 0x33, 0x44, 0x55, 0x66];
+    const OTHER: [u8; 6] = [0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0xff];
+
+    #[test]
+    fn test_cancel_marks_earlier_epoch_stale() {
+        let mut registry = CancellationRegistry::new();
+        let queued_epoch = registry.epoch(&MAC);
+        assert!(!registry.is_stale(&MAC, queued_epoch));
+
+        let new_epoch = registry.cancel(MAC, AbortReason::Device);
+        assert!(registry.is_stale(&MAC, queued_epoch));
+        assert!(!registry.is_stale(&MAC, new_epoch));
+        // 他の送信元には影響しない
+        assert!(!registry.is_stale(&OTHER, registry.epoch(&OTHER)));
+    }
+
+    #[test]
+    fn test_pending_aborts_are_coalesced_per_sender() {
+        let mut registry = CancellationRegistry::new();
+        registry.cancel(MAC, AbortReason::QueueOverflow);
+        registry.cancel(MAC, AbortReason::QueueOverflow);
+        registry.cancel(OTHER, AbortReason::Device);
+
+        assert_eq!(
+            registry.take_pending(),
+            vec![(MAC, AbortReason::QueueOverflow), (OTHER, AbortReason::Device)]
+        );
+        assert!(registry.take_pending().is_empty());
+    }
+}
@@ -0,0 +1,399 @@
+//! 時間分割の受信チャンネル切り替え（チャンネルホッピング）
+//!
+//! 2.4GHz帯が混雑している配備では、ゲートウェイが一定時間（スロット）ごとに受信チャンネルを切り替え、
+//! 空いているチャンネルでも転送を受けられるようにします。スロット0は常にホームチャンネル（起動時のチャンネル）です。
+//! 予定は機能フラグ `CAPABILITY_CHANNEL_HOP` を付けたPingへのPongで通知し（`HopAnnouncement`）、
+//! デバイスは次の起床時刻のスロットのチャンネルに合わせてから疎通確認を行います。
+//!
+//! 転送中はチャンネルを切り替えず、転送が終わってから現在のスロットのチャンネルへ移ります。
+//! あるチャンネルで転送の失敗（中断、またはデバイスが予定のチャンネルで到達できなかった報告）が
+//! 続いた場合、そのチャンネルのスロットは一定ラウンドの間ホームチャンネルで受信します。
+
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use crate::sys_wrappers::{SysResult, WifiRadio};
+
+/// 予定に含められるスロット数の上限（ホームチャンネルを含む）
+pub const MAX_HOP_SLOTS: usize = 8;
+/// 予定の固定部の長さ: [HOME(1)] [SLOT_SECS(2, LE)] [ELAPSED_SECS(2, LE)] [COUNT(1)]
+pub const HOP_ANNOUNCEMENT_HEADER_LEN: usize = 6;
+/// スロット長の下限（秒）。短すぎると転送中の切り替え待ちでスロットが埋まる
+pub const MIN_HOP_SLOT_SECS: u16 = 10;
+/// チャンネルのスロットをホームチャンネルに戻すまでの連続失敗数
+pub const MAX_CONSECUTIVE_FAILURES: u32 = 3;
+/// ホームチャンネルに戻したスロットを再開するまでのラウンド数（全スロットを1周して1ラウンド）
+pub const SUSPEND_ROUNDS: u64 = 10;
+
+/// 2.4GHz帯で指定できるチャンネル
+const CHANNEL_RANGE: std::ops::RangeInclusive<u8> = 1..=13;
+
+/// ホームチャンネル以外に巡回するチャンネルの一覧（`"6,11"` 形式）を解析します
+pub fn parse_hop_channels(list: &str) -> Result<Vec<u8>, String> {
+    let mut channels = Vec::new();
+    for item in list.split(',').map(str::trim).filter(|item| !item.is_empty()) {
+        let channel: u8 = item.parse().map_err(|_| format!("invalid channel: {:?}", item))?;
+        if !CHANNEL_RANGE.contains(&channel) {
+            return Err(format!("channel out of range (1-13): {}", channel));
+        }
+        if !channels.contains(&channel) {
+            channels.push(channel);
+        }
+    }
+    if channels.len() >= MAX_HOP_SLOTS {
+        return Err(format!("too many channels (max {})", MAX_HOP_SLOTS - 1));
+    }
+    Ok(channels)
+}
+
+/// HASHフレームのペイロードから、デバイスが予定のチャンネルで到達できなかった報告（`HOP_MISS`）を取り出します
+pub fn reported_missed_channel(payload: &[u8]) -> Option<u8> {
+    std::str::from_utf8(payload)
+        .ok()?
+        .split(',')
+        .find_map(|item| item.strip_prefix("HOP_MISS")?.strip_prefix(':'))
+        .and_then(|value| value.trim().parse().ok())
+}
+
+/// Pongで通知する受信チャンネルの予定
+///
+/// `channels` は現在のスロットから順に並べたスロットごとのチャンネルで、末尾の後は先頭に戻ります。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HopAnnouncement {
+    /// ホームチャンネル
+    pub home: u8,
+    /// スロット長（秒）
+    pub slot_secs: u16,
+    /// 現在のスロットの経過時間（秒）
+    pub elapsed_secs: u16,
+    channels: [u8; MAX_HOP_SLOTS],
+    len: u8,
+}
+
+impl HopAnnouncement {
+    /// スロットごとのチャンネル（現在のスロットから）
+    pub fn channels(&self) -> &[u8] {
+        &self.channels[..self.len as usize]
+    }
+
+    /// ワイヤ表現に変換します: `[HOME] [SLOT_SECS] [ELAPSED_SECS] [COUNT] [CHANNELS(COUNT)]`
+    pub fn to_wire(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(HOP_ANNOUNCEMENT_HEADER_LEN + self.len as usize);
+        data.push(self.home);
+        data.extend_from_slice(&self.slot_secs.to_le_bytes());
+        data.extend_from_slice(&self.elapsed_secs.to_le_bytes());
+        data.push(self.len);
+        data.extend_from_slice(self.channels());
+        data
+    }
+
+    /// ワイヤ表現から変換します（長さが一致しない場合は None）
+    pub fn from_wire(data: &[u8]) -> Option<Self> {
+        let header = data.get(..HOP_ANNOUNCEMENT_HEADER_LEN)?;
+        let len = header[5] as usize;
+        if len == 0 || len > MAX_HOP_SLOTS || data.len() != HOP_ANNOUNCEMENT_HEADER_LEN + len {
+            return None;
+        }
+        let mut channels = [0u8; MAX_HOP_SLOTS];
+        channels[..len].copy_from_slice(&data[HOP_ANNOUNCEMENT_HEADER_LEN..]);
+        Some(Self {
+            home: header[0],
+            slot_secs: u16::from_le_bytes([header[1], header[2]]),
+            elapsed_secs: u16::from_le_bytes([header[3], header[4]]),
+            channels,
+            len: len as u8,
+        })
+    }
+}
+
+/// チャンネルごとの転送結果（統計フレームの報告ごとにリセット）
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ChannelStats {
+    /// 完了した転送数
+    pub transfers: u32,
+    /// 失敗した転送数（中断と、デバイスが到達できなかった報告）
+    pub failures: u32,
+}
+
+#[derive(Debug, Default)]
+struct ChannelState {
+    stats: ChannelStats,
+    consecutive_failures: u32,
+    /// このラウンドまでスロットをホームチャンネルで受信する
+    suspended_until_round: Option<u64>,
+}
+
+/// 受信チャンネルの予定と切り替え
+#[derive(Debug)]
+pub struct ChannelHopper {
+    home: u8,
+    /// スロットごとのチャンネル（先頭はホームチャンネル）
+    slots: Vec<u8>,
+    slot_secs: u16,
+    started: Instant,
+    /// 現在受信しているチャンネル
+    tuned: u8,
+    channels: BTreeMap<u8, ChannelState>,
+}
+
+impl ChannelHopper {
+    /// ホームチャンネルと巡回するチャンネルから予定を作成します（`now` がスロット0の開始）
+    pub fn new(home: u8, hop_channels: &[u8], slot_secs: u16, now: Instant) -> Self {
+        let mut slots = vec![home];
+        slots.extend(hop_channels.iter().copied().filter(|&channel| channel != home));
+        slots.truncate(MAX_HOP_SLOTS);
+        let channels = slots.iter().map(|&channel| (channel, ChannelState::default())).collect();
+        Self {
+            home,
+            slots,
+            slot_secs: slot_secs.max(MIN_HOP_SLOT_SECS),
+            started: now,
+            tuned: home,
+            channels,
+        }
+    }
+
+    /// ホームチャンネル
+    pub fn home(&self) -> u8 {
+        self.home
+    }
+
+    /// 現在受信しているチャンネル
+    pub fn tuned(&self) -> u8 {
+        self.tuned
+    }
+
+    /// ログ用の設定概要
+    pub fn summary(&self) -> String {
+        let slots: Vec<String> = self.slots.iter().map(u8::to_string).collect();
+        format!("home={} slots={} slot={}s", self.home, slots.join(","), self.slot_secs)
+    }
+
+    /// 開始からのスロット番号と、現在のスロットの経過秒数
+    fn position(&self, now: Instant) -> (u64, u64) {
+        let elapsed_secs = now.saturating_duration_since(self.started).as_secs();
+        (elapsed_secs / self.slot_secs as u64, elapsed_secs % self.slot_secs as u64)
+    }
+
+    /// 指定したスロット番号で受信するチャンネル（中断中のチャンネルはホームチャンネル）
+    fn channel_for(&self, index: u64) -> u8 {
+        let channel = self.slots[(index % self.slots.len() as u64) as usize];
+        let round = index / self.slots.len() as u64;
+        match self.channels.get(&channel).and_then(|state| state.suspended_until_round) {
+            Some(until) if round < until => self.home,
+            _ => channel,
+        }
+    }
+
+    /// 現在のスロットで受信するチャンネル
+    pub fn scheduled_channel(&self, now: Instant) -> u8 {
+        self.channel_for(self.position(now).0)
+    }
+
+    /// Pongで通知する予定（現在のスロットから1周分）
+    pub fn announcement(&self, now: Instant) -> HopAnnouncement {
+        let (index, elapsed) = self.position(now);
+        let mut channels = [0u8; MAX_HOP_SLOTS];
+        for (offset, channel) in channels.iter_mut().take(self.slots.len()).enumerate() {
+            *channel = self.channel_for(index + offset as u64);
+        }
+        HopAnnouncement {
+            home: self.home,
+            slot_secs: self.slot_secs,
+            elapsed_secs: elapsed as u16,
+            channels,
+            len: self.slots.len() as u8,
+        }
+    }
+
+    /// 現在のスロットのチャンネルへ切り替えるべきであれば、そのチャンネルを返します
+    ///
+    /// 転送中（`in_flight > 0`）は切り替えません。切り替えたら `tuned_to` で記録します。
+    pub fn due_channel(&self, now: Instant, in_flight: usize) -> Option<u8> {
+        let wanted = self.scheduled_channel(now);
+        (wanted != self.tuned && in_flight == 0).then_some(wanted)
+    }
+
+    /// チャンネルを切り替えたことを記録します
+    pub fn tuned_to(&mut self, channel: u8) {
+        self.tuned = channel;
+    }
+
+    /// 現在のスロットのチャンネルへ無線を切り替えます
+    ///
+    /// # 戻り値
+    /// * 切り替えたチャンネル（切り替え不要ならNone。失敗した場合は記録を変えず次の呼び出しで再試行する）
+    pub fn retune(&mut self, radio: &impl WifiRadio, now: Instant, in_flight: usize) -> SysResult<Option<u8>> {
+        let Some(channel) = self.due_channel(now, in_flight) else {
+            return Ok(None);
+        };
+        radio.set_channel(channel)?;
+        self.tuned_to(channel);
+        Ok(Some(channel))
+    }
+
+    /// 現在受信しているチャンネルでの転送結果を記録します
+    ///
+    /// # 戻り値
+    /// * 失敗が続いたためホームチャンネルに戻したチャンネル
+    pub fn record_transfer(&mut self, success: bool, now: Instant) -> Option<u8> {
+        if success {
+            let state = self.channels.entry(self.tuned).or_default();
+            state.stats.transfers = state.stats.transfers.saturating_add(1);
+            state.consecutive_failures = 0;
+            None
+        } else {
+            self.record_failure(self.tuned, now)
+        }
+    }
+
+    /// デバイスが予定のチャンネルでゲートウェイに到達できなかった報告を記録します
+    ///
+    /// # 戻り値
+    /// * 失敗が続いたためホームチャンネルに戻したチャンネル
+    pub fn record_miss(&mut self, channel: u8, now: Instant) -> Option<u8> {
+        if !self.slots.contains(&channel) {
+            return None;
+        }
+        self.record_failure(channel, now)
+    }
+
+    fn record_failure(&mut self, channel: u8, now: Instant) -> Option<u8> {
+        let round = self.position(now).0 / self.slots.len() as u64;
+        let home = self.home;
+        let state = self.channels.entry(channel).or_default();
+        state.stats.failures = state.stats.failures.saturating_add(1);
+        state.consecutive_failures += 1;
+        if channel == home || state.consecutive_failures < MAX_CONSECUTIVE_FAILURES {
+            return None;
+        }
+        state.consecutive_failures = 0;
+        state.suspended_until_round = Some(round + 1 + SUSPEND_ROUNDS);
+        Some(channel)
+    }
+
+    /// 統計フレームの項目（`1:12/0,6:3/1,11:0/3*` 形式。完了/失敗、`*` はホームチャンネルに戻し中）を返して
+    /// 転送結果をリセットします
+    pub fn take_stats_field(&mut self, now: Instant) -> String {
+        let round = self.position(now).0 / self.slots.len() as u64;
+        let mut fields = Vec::with_capacity(self.slots.len());
+        for channel in &self.slots {
+            let Some(state) = self.channels.get_mut(channel) else {
+                continue;
+            };
+            let stats = std::mem::take(&mut state.stats);
+            let suspended = state.suspended_until_round.is_some_and(|until| round < until);
+            fields.push(format!(
+                "{}:{}/{}{}",
+                channel,
+                stats.transfers,
+                stats.failures,
+                if suspended { "*" } else { "" }
+            ));
+        }
+        fields.join(",")
+    }
+}
+
+/// 受信チャンネルの予定（チャンネルホッピングが無効ならNone）
+pub static CHANNEL_HOP: Mutex<Option<ChannelHopper>> = Mutex::new(None);
+
+/// 現在の予定（チャンネルホッピングが無効ならNone）
+pub fn current_announcement(now: Instant) -> Option<HopAnnouncement> {
+    CHANNEL_HOP.lock().ok()?.as_ref().map(|hopper| hopper.announcement(now))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sys_wrappers::mock::MockSys;
+    use crate::sys_wrappers::SysError;
+    use std::time::Duration;
+
+    const SLOT: Duration = Duration::from_secs(60);
+
+    #[test]
+    fn test_parse_hop_channels() {
+        assert_eq!(parse_hop_channels(""), Ok(vec![]));
+        assert_eq!(parse_hop_channels(" 6, 11,6 "), Ok(vec![6, 11]));
+        assert!(parse_hop_channels("14").is_err());
+        assert!(parse_hop_channels("six").is_err());
+        assert!(parse_hop_channels("1,2,3,4,5,6,7,8").is_err());
+    }
+
+    #[test]
+    fn test_reported_missed_channel() {
+        assert_eq!(reported_missed_channel(b"HASH:00,HOP_CH:1,HOP_MISS:6"), Some(6));
+        assert_eq!(reported_missed_channel(b"HASH:00,HOP_CH:6"), None);
+    }
+
+    #[test]
+    fn test_schedule_rotates_from_home_and_announces_current_slot() {
+        let start = Instant::now();
+        let mut hopper = ChannelHopper::new(1, &[6, 1, 11], 60, start);
+        assert_eq!(hopper.scheduled_channel(start), 1);
+        assert_eq!(hopper.scheduled_channel(start + SLOT), 6);
+        assert_eq!(hopper.scheduled_channel(start + SLOT * 3), 1);
+
+        let announcement = hopper.announcement(start + SLOT + Duration::from_secs(15));
+        assert_eq!(announcement.channels(), &[6, 11, 1]);
+        assert_eq!(announcement.elapsed_secs, 15);
+        let wire = announcement.to_wire();
+        assert_eq!(wire, [1, 60, 0, 15, 0, 3, 6, 11, 1]);
+        assert_eq!(HopAnnouncement::from_wire(&wire), Some(announcement));
+        assert_eq!(HopAnnouncement::from_wire(&wire[..8]), None);
+
+        // 転送中は切り替えない
+        assert_eq!(hopper.due_channel(start + SLOT, 1), None);
+        assert_eq!(hopper.due_channel(start + SLOT, 0), Some(6));
+        hopper.tuned_to(6);
+        assert_eq!(hopper.due_channel(start + SLOT, 0), None);
+        assert_eq!(hopper.tuned(), 6);
+    }
+
+    #[test]
+    fn test_retune_switches_radio_and_retries_after_failure() {
+        let start = Instant::now();
+        let radio = MockSys::new();
+        let mut hopper = ChannelHopper::new(1, &[6], 60, start);
+        assert_eq!(hopper.retune(&radio, start, 0), Ok(None));
+
+        *radio.fail_with.lock().unwrap() = Some(-1);
+        assert_eq!(hopper.retune(&radio, start + SLOT, 0), Err(SysError(-1)));
+        assert_eq!(hopper.tuned(), 1);
+
+        *radio.fail_with.lock().unwrap() = None;
+        assert_eq!(hopper.retune(&radio, start + SLOT, 1), Ok(None));
+        assert_eq!(hopper.retune(&radio, start + SLOT, 0), Ok(Some(6)));
+        assert_eq!(radio.channel(), Ok(6));
+        assert_eq!(hopper.tuned(), 6);
+    }
+
+    #[test]
+    fn test_repeated_failures_fall_back_to_home_channel() {
+        let start = Instant::now();
+        let mut hopper = ChannelHopper::new(1, &[6], 60, start);
+        hopper.tuned_to(6);
+        assert_eq!(hopper.record_transfer(true, start + SLOT), None);
+        assert_eq!(hopper.record_transfer(false, start + SLOT), None);
+        assert_eq!(hopper.record_miss(6, start + SLOT), None);
+        assert_eq!(hopper.record_miss(11, start + SLOT), None);
+        assert_eq!(hopper.record_transfer(false, start + SLOT), Some(6));
+        assert_eq!(hopper.take_stats_field(start + SLOT), "1:0/0,6:1/3*");
+        assert_eq!(hopper.take_stats_field(start + SLOT), "1:0/0,6:0/0*");
+
+        // 戻している間はチャンネル6のスロットもホームチャンネルで受信し、予定にもそう通知する
+        assert_eq!(hopper.scheduled_channel(start + SLOT * 3), 1);
+        assert_eq!(hopper.announcement(start + SLOT * 2).channels(), &[1, 1]);
+        assert_eq!(hopper.due_channel(start + SLOT * 3, 0), Some(1));
+        let resumed = start + SLOT * 2 * (SUSPEND_ROUNDS as u32 + 1) + SLOT;
+        assert_eq!(hopper.scheduled_channel(resumed), 6);
+
+        // ホームチャンネルは戻す先のため中断しない
+        hopper.tuned_to(1);
+        for _ in 0..MAX_CONSECUTIVE_FAILURES {
+            assert_eq!(hopper.record_transfer(false, start + SLOT * 3), None);
+        }
+    }
+}
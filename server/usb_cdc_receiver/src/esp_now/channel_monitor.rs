@@ -0,0 +1,61 @@
+//! プロミスキャス受信によるチャンネルの混雑度の計測（`channel-monitor` フィーチャー）
+//!
+//! 受信コールバックは聞こえたフレームの数と通信時間の見積もりを積算するだけで、ビジー率は
+//! メンテナンスタスクが `counters` の差分から求めます（`channel_utilization` を参照）。
+
+use std::ffi::c_void;
+use std::slice;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use esp_idf_svc::sys::{wifi_promiscuous_pkt_t, wifi_promiscuous_pkt_type_t, wifi_promiscuous_pkt_type_t_WIFI_PKT_MGMT};
+
+use crate::channel_utilization::{frame_airtime_us, is_esp_now_frame, RadioCounters, RxRate};
+use crate::sys_wrappers::esp as sys;
+use crate::sys_wrappers::SysResult;
+
+/// 受信したフレーム数
+static FRAMES: AtomicU32 = AtomicU32::new(0);
+/// ESP-NOW以外のフレーム数
+static FOREIGN_FRAMES: AtomicU32 = AtomicU32::new(0);
+/// 通信時間の見積もりの合計（マイクロ秒）
+static AIRTIME_US: AtomicU32 = AtomicU32::new(0);
+
+/// プロミスキャス受信を始めます
+pub fn start() -> SysResult<()> {
+    sys::start_promiscuous(Some(promiscuous_rx_cb))
+}
+
+/// 起動からの累積カウンタ
+pub fn counters() -> RadioCounters {
+    RadioCounters {
+        frames: FRAMES.load(Ordering::Relaxed),
+        foreign_frames: FOREIGN_FRAMES.load(Ordering::Relaxed),
+        airtime_us: AIRTIME_US.load(Ordering::Relaxed),
+    }
+}
+
+/// プロミスキャス受信のコールバック（Wi-Fiタスクで呼ばれるため積算のみ）
+extern "C" fn promiscuous_rx_cb(buf: *mut c_void, pkt_type: wifi_promiscuous_pkt_type_t) {
+    let Some(packet) = (unsafe { buf.cast::<wifi_promiscuous_pkt_t>().as_ref() }) else {
+        return;
+    };
+    let rx_ctrl = &packet.rx_ctrl;
+    let len = rx_ctrl.sig_len() as u16;
+    let rx_rate = RxRate {
+        sig_mode: rx_ctrl.sig_mode() as u8,
+        rate: rx_ctrl.rate() as u8,
+        mcs: rx_ctrl.mcs() as u8,
+        cwb: rx_ctrl.cwb() != 0,
+    };
+
+    FRAMES.fetch_add(1, Ordering::Relaxed);
+    if let Some(airtime_us) = frame_airtime_us(rx_rate, len) {
+        AIRTIME_US.fetch_add(airtime_us, Ordering::Relaxed);
+    }
+    // ペイロードの長さは `sig_len`
+    let esp_now = pkt_type == wifi_promiscuous_pkt_type_t_WIFI_PKT_MGMT
+        && is_esp_now_frame(unsafe { slice::from_raw_parts(packet.payload.as_ptr(), len as usize) });
+    if !esp_now {
+        FOREIGN_FRAMES.fetch_add(1, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,386 @@
+//! 受信フレームへの故障注入（`chaos` フィーチャー）
+//!
+//! 再送・FEC・再組み立ての頑健性を実地試験の前に机上で確かめるため、受信したESP-NOWフレームを
+//! 設定した割合で破棄・重複・入れ替え・破損させてから通常の受信処理へ渡します。
+//! 判定はシード付きの擬似乱数で行うため、同じシードと同じ受信順なら同じ故障が再現します。
+//! 入れ替えは送信元ごとに行い、後ろへ回したフレームは同じ送信元の次のフレームの直後に渡します。
+//! 設定は `cfg.toml` の `chaos`、または USB の `CHAOS` コマンドで変更します。
+//!
+//! 指定の形式は `drop=5,dup=2,reorder=1,corrupt=1,seed=42`（割合は%、合計100以下、省略した項目は0、
+//! シードの省略時は1）です。デバイス側の `esp_now_chaos` と同じ形式です。
+
+use std::collections::BTreeMap;
+
+/// 故障注入コマンド応答の接頭辞
+pub const CHAOS_RESPONSE_PREFIX: &str = "CMD_CHAOS:";
+
+/// シードを省略したときの既定値
+pub const DEFAULT_CHAOS_SEED: u32 = 1;
+
+/// 故障注入の割合（%）とシード
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChaosParams {
+    /// 破棄する割合
+    pub drop_percent: u8,
+    /// 2回渡す割合
+    pub duplicate_percent: u8,
+    /// 次のフレームの後ろへ回す割合
+    pub reorder_percent: u8,
+    /// 1ビット反転させる割合
+    pub corrupt_percent: u8,
+    /// 擬似乱数のシード
+    pub seed: u32,
+}
+
+impl ChaosParams {
+    /// `drop=N,dup=N,reorder=N,corrupt=N,seed=N` 形式の指定を解析します
+    ///
+    /// 空の指定と `off` は無効（`None`）です。
+    pub fn parse(spec: &str) -> Result<Option<Self>, String> {
+        let spec = spec.trim();
+        if spec.is_empty() || spec.eq_ignore_ascii_case("off") {
+            return Ok(None);
+        }
+        let mut params = Self {
+            drop_percent: 0,
+            duplicate_percent: 0,
+            reorder_percent: 0,
+            corrupt_percent: 0,
+            seed: DEFAULT_CHAOS_SEED,
+        };
+        for field in spec.split(',') {
+            let invalid = || format!("invalid field '{}'", field);
+            let (key, value) = field.split_once('=').ok_or_else(invalid)?;
+            let key = key.trim().to_ascii_lowercase();
+            let value = value.trim();
+            if key == "seed" {
+                params.seed = value.parse().map_err(|_| invalid())?;
+                continue;
+            }
+            let percent = value.parse::<u8>().ok().filter(|percent| *percent <= 100).ok_or_else(invalid)?;
+            match key.as_str() {
+                "drop" => params.drop_percent = percent,
+                "dup" => params.duplicate_percent = percent,
+                "reorder" => params.reorder_percent = percent,
+                "corrupt" => params.corrupt_percent = percent,
+                _ => return Err(invalid()),
+            }
+        }
+        let total = params.total_percent();
+        if total > 100 {
+            return Err(format!("percentages add up to {} (max 100)", total));
+        }
+        Ok(Some(params))
+    }
+
+    /// 割合の合計
+    fn total_percent(&self) -> u32 {
+        [self.drop_percent, self.duplicate_percent, self.reorder_percent, self.corrupt_percent]
+            .iter()
+            .map(|&percent| percent as u32)
+            .sum()
+    }
+
+    /// 指定の形式の文字列
+    pub fn encode(&self) -> String {
+        format!(
+            "drop={},dup={},reorder={},corrupt={},seed={}",
+            self.drop_percent, self.duplicate_percent, self.reorder_percent, self.corrupt_percent, self.seed
+        )
+    }
+}
+
+/// 1フレームに対する故障の種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChaosAction {
+    /// そのまま渡す
+    Pass,
+    /// 破棄する
+    Drop,
+    /// 2回渡す
+    Duplicate,
+    /// 次に渡すフレームの後ろへ回す
+    Reorder,
+    /// 1ビット反転させて渡す
+    Corrupt,
+}
+
+/// 注入した故障の件数
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ChaosStats {
+    pub passed: u32,
+    pub dropped: u32,
+    pub duplicated: u32,
+    pub reordered: u32,
+    pub corrupted: u32,
+}
+
+impl ChaosStats {
+    fn add(&mut self, other: &ChaosStats) {
+        self.passed += other.passed;
+        self.dropped += other.dropped;
+        self.duplicated += other.duplicated;
+        self.reordered += other.reordered;
+        self.corrupted += other.corrupted;
+    }
+
+    /// 応答・ログ用の要約
+    pub fn summary(&self) -> String {
+        format!(
+            "passed={} dropped={} duplicated={} reordered={} corrupted={}",
+            self.passed, self.dropped, self.duplicated, self.reordered, self.corrupted
+        )
+    }
+}
+
+/// シード付きの擬似乱数で故障を注入する
+#[derive(Debug)]
+pub struct ChaosInjector {
+    params: ChaosParams,
+    state: u32,
+    /// 入れ替えのため後ろへ回しているフレーム
+    held: Option<Vec<u8>>,
+    stats: ChaosStats,
+}
+
+impl ChaosInjector {
+    /// 指定の割合とシードで作成します
+    pub fn new(params: ChaosParams) -> Self {
+        // xorshiftは0から抜け出せないため、0は固定値に置き換える
+        let state = if params.seed == 0 { 0x9E37_79B9 } else { params.seed };
+        Self {
+            params,
+            state,
+            held: None,
+            stats: ChaosStats::default(),
+        }
+    }
+
+    /// 注入した故障の件数
+    pub fn stats(&self) -> ChaosStats {
+        self.stats
+    }
+
+    fn next_random(&mut self) -> u32 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 17;
+        self.state ^= self.state << 5;
+        self.state
+    }
+
+    /// 次のフレームに注入する故障を決めます
+    pub fn decide(&mut self) -> ChaosAction {
+        let roll = (self.next_random() % 100) as u8;
+        let p = self.params;
+        let thresholds = [
+            (p.drop_percent, ChaosAction::Drop),
+            (p.duplicate_percent, ChaosAction::Duplicate),
+            (p.reorder_percent, ChaosAction::Reorder),
+            (p.corrupt_percent, ChaosAction::Corrupt),
+        ];
+        let mut upper = 0u8;
+        for (percent, action) in thresholds {
+            upper = upper.saturating_add(percent);
+            if roll < upper {
+                return action;
+            }
+        }
+        ChaosAction::Pass
+    }
+
+    /// フレームに故障を注入し、受信処理へ渡すフレームを順に返します
+    ///
+    /// 後ろへ回したフレームは、次に渡すフレームの直後に返します（すでに1件回している間は入れ替えない）。
+    pub fn apply(&mut self, frame: &[u8]) -> Vec<Vec<u8>> {
+        let mut action = self.decide();
+        if action == ChaosAction::Reorder && self.held.is_some() {
+            action = ChaosAction::Pass;
+        }
+        let mut frames = match action {
+            ChaosAction::Drop => {
+                self.stats.dropped += 1;
+                return Vec::new();
+            }
+            ChaosAction::Reorder => {
+                self.stats.reordered += 1;
+                self.held = Some(frame.to_vec());
+                return Vec::new();
+            }
+            ChaosAction::Pass => {
+                self.stats.passed += 1;
+                vec![frame.to_vec()]
+            }
+            ChaosAction::Duplicate => {
+                self.stats.duplicated += 1;
+                vec![frame.to_vec(), frame.to_vec()]
+            }
+            ChaosAction::Corrupt => {
+                self.stats.corrupted += 1;
+                let mut corrupted = frame.to_vec();
+                if !corrupted.is_empty() {
+                    let bit = self.next_random() as usize % (corrupted.len() * 8);
+                    corrupted[bit / 8] ^= 1 << (bit % 8);
+                }
+                vec![corrupted]
+            }
+        };
+        frames.extend(self.held.take());
+        frames
+    }
+
+    /// 後ろへ回しているフレームを取り出します
+    pub fn flush(&mut self) -> Option<Vec<u8>> {
+        self.held.take()
+    }
+}
+
+/// 送信元ごとの故障注入
+#[derive(Debug, Default)]
+pub struct ChaosRegistry {
+    params: Option<ChaosParams>,
+    devices: BTreeMap<[u8; 6], ChaosInjector>,
+}
+
+impl ChaosRegistry {
+    /// 無効な状態で作成します
+    pub const fn new() -> Self {
+        Self {
+            params: None,
+            devices: BTreeMap::new(),
+        }
+    }
+
+    /// 設定を変更します（`None` で無効。件数と後ろへ回しているフレームは破棄する）
+    pub fn configure(&mut self, params: Option<ChaosParams>) {
+        self.params = params;
+        self.devices.clear();
+    }
+
+    /// 有効かどうか
+    pub fn is_enabled(&self) -> bool {
+        self.params.is_some()
+    }
+
+    /// 送信元のフレームに故障を注入します（無効ならNone）
+    ///
+    /// 送信元ごとに同じシードから始めるため、送信元ごとの故障の並びが再現します。
+    pub fn apply(&mut self, mac: [u8; 6], frame: &[u8]) -> Option<Vec<Vec<u8>>> {
+        let params = self.params?;
+        Some(
+            self.devices
+                .entry(mac)
+                .or_insert_with(|| ChaosInjector::new(params))
+                .apply(frame),
+        )
+    }
+
+    /// 全送信元の件数の合計
+    pub fn stats(&self) -> ChaosStats {
+        let mut total = ChaosStats::default();
+        for injector in self.devices.values() {
+            total.add(&injector.stats());
+        }
+        total
+    }
+
+    /// `CHAOS` への応答行
+    pub fn response(&self) -> String {
+        match self.params {
+            Some(params) => format!("{}{} {}\n", CHAOS_RESPONSE_PREFIX, params.encode(), self.stats().summary()),
+            None => format!("{}off\n", CHAOS_RESPONSE_PREFIX),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_spec() {
+        let params = ChaosParams::parse("drop=5, dup=2,REORDER=1,corrupt=1,seed=42").unwrap().unwrap();
+        assert_eq!(params.encode(), "drop=5,dup=2,reorder=1,corrupt=1,seed=42");
+        assert_eq!(ChaosParams::parse("drop=10").unwrap().unwrap().seed, DEFAULT_CHAOS_SEED);
+        assert_eq!(ChaosParams::parse(""), Ok(None));
+        assert_eq!(ChaosParams::parse("OFF"), Ok(None));
+        assert!(ChaosParams::parse("drop=101").is_err());
+        assert!(ChaosParams::parse("drop=60,dup=50").is_err());
+        assert!(ChaosParams::parse("jitter=5").is_err());
+        assert!(ChaosParams::parse("drop").is_err());
+    }
+
+    #[test]
+    fn test_same_seed_reproduces_the_same_faults() {
+        let params = ChaosParams::parse("drop=20,dup=20,reorder=20,corrupt=20,seed=7").unwrap().unwrap();
+        let mut a = ChaosInjector::new(params);
+        let mut b = ChaosInjector::new(params);
+        let actions_a: Vec<_> = (0..200).map(|_| a.decide()).collect();
+        let actions_b: Vec<_> = (0..200).map(|_| b.decide()).collect();
+        assert_eq!(actions_a, actions_b);
+        for action in [
+            ChaosAction::Pass,
+            ChaosAction::Drop,
+            ChaosAction::Duplicate,
+            ChaosAction::Reorder,
+            ChaosAction::Corrupt,
+        ] {
+            assert!(actions_a.contains(&action));
+        }
+    }
+
+    #[test]
+    fn test_zero_percent_passes_everything() {
+        let mut injector = ChaosInjector::new(ChaosParams::parse("seed=3").unwrap().unwrap());
+        for i in 0..50u8 {
+            assert_eq!(injector.apply(&[i]), vec![vec![i]]);
+        }
+        assert_eq!(injector.stats().passed, 50);
+    }
+
+    #[test]
+    fn test_reordered_frame_follows_the_next_frame() {
+        let mut injector = ChaosInjector::new(ChaosParams::parse("reorder=100").unwrap().unwrap());
+        assert!(injector.apply(b"first").is_empty());
+        // 回している間は入れ替えず、回したフレームを後ろに付ける
+        assert_eq!(injector.apply(b"second"), vec![b"second".to_vec(), b"first".to_vec()]);
+        assert!(injector.apply(b"third").is_empty());
+        assert_eq!(injector.flush(), Some(b"third".to_vec()));
+        assert_eq!(injector.stats().reordered, 2);
+    }
+
+    #[test]
+    fn test_drop_duplicate_and_corrupt() {
+        let frame = [0u8; 16];
+        let mut dropper = ChaosInjector::new(ChaosParams::parse("drop=100").unwrap().unwrap());
+        assert!(dropper.apply(&frame).is_empty());
+
+        let mut duplicator = ChaosInjector::new(ChaosParams::parse("dup=100").unwrap().unwrap());
+        assert_eq!(duplicator.apply(&frame), vec![frame.to_vec(), frame.to_vec()]);
+
+        let mut corrupter = ChaosInjector::new(ChaosParams::parse("corrupt=100").unwrap().unwrap());
+        let corrupted = corrupter.apply(&frame);
+        let flipped: u32 = corrupted[0].iter().map(|byte| byte.count_ones()).sum();
+        assert_eq!(flipped, 1);
+    }
+
+    #[test]
+    fn test_registry_reorders_per_device() {
+        const MAC_A: [u8; 6] = [0x34, 0xab, 0x95, 0xfb, 0x3f, 0xc4];
+        const MAC_B: [u8; 6] = [0x34, 0xab, 0x95, 0xfb, 0x3f, 0xc5];
+        let mut registry = ChaosRegistry::new();
+        assert_eq!(registry.apply(MAC_A, b"a1"), None);
+        assert_eq!(registry.response(), "CMD_CHAOS:off\n");
+
+        registry.configure(ChaosParams::parse("reorder=100").unwrap());
+        assert_eq!(registry.apply(MAC_A, b"a1"), Some(Vec::new()));
+        assert_eq!(registry.apply(MAC_B, b"b1"), Some(Vec::new()));
+        assert_eq!(registry.apply(MAC_A, b"a2"), Some(vec![b"a2".to_vec(), b"a1".to_vec()]));
+        assert_eq!(
+            registry.response(),
+            "CMD_CHAOS:drop=0,dup=0,reorder=100,corrupt=0,seed=1 passed=1 dropped=0 duplicated=0 reordered=2 corrupted=0\n"
+        );
+
+        registry.configure(None);
+        assert!(!registry.is_enabled());
+    }
+}
@@ -0,0 +1,82 @@
+//! DATAチャンクの大きさの通知
+//!
+//! デバイスは受信側の制約を知らないため、以前はESP-NOWの上限に収まる大きさから順に
+//! 小さくして送信を試していました（223→150→100→50→30バイト）。ゲートウェイは受信したフレームを
+//! そのままの大きさでストリーミングバッファに溜めるため、受け取れる大きさを自分で知っています。
+//! そこで機能フラグ `CAPABILITY_CHUNK_SIZE` を付けたPingへのPongに、推奨と上限のペイロード長を
+//! 付加し（`ChunkSizeAdvert`）、デバイスは推奨の大きさで送信します。
+
+use super::frame::FRAME_OVERHEAD;
+
+/// 通知のワイヤ表現の長さ: [PREFERRED(2, LE)] [MAX(2, LE)]
+pub const CHUNK_SIZE_ADVERT_LEN: usize = 4;
+/// ESP-NOWで1回に送れるデータ長
+pub const ESP_NOW_MAX_DATA_LEN: usize = 250;
+/// 受け取れるDATAチャンクのペイロード長の上限（ESP-NOWの上限からヘッダ等を除く。
+/// フレーム全体はストリーミングバッファの512バイトにも収まる）
+pub const MAX_CHUNK_PAYLOAD_LEN: u16 = (ESP_NOW_MAX_DATA_LEN - FRAME_OVERHEAD) as u16;
+/// 推奨できるペイロード長の下限（デバイスの最小の候補と同じ）
+pub const MIN_CHUNK_PAYLOAD_LEN: u16 = 30;
+
+const _: () = assert!(MAX_CHUNK_PAYLOAD_LEN == 223);
+
+/// Pongで通知するペイロード長
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkSizeAdvert {
+    /// 推奨のペイロード長（バイト）
+    pub preferred: u16,
+    /// 受け取れるペイロード長の上限（バイト）
+    pub max: u16,
+}
+
+impl ChunkSizeAdvert {
+    /// ゲートウェイの通知を作成します（推奨の指定がなければ上限、指定は下限から上限の範囲へ収める）
+    pub fn gateway(preferred: Option<u16>) -> Self {
+        Self {
+            preferred: preferred.map_or(MAX_CHUNK_PAYLOAD_LEN, |preferred| {
+                preferred.clamp(MIN_CHUNK_PAYLOAD_LEN, MAX_CHUNK_PAYLOAD_LEN)
+            }),
+            max: MAX_CHUNK_PAYLOAD_LEN,
+        }
+    }
+
+    /// ワイヤ表現
+    pub fn to_wire(&self) -> [u8; CHUNK_SIZE_ADVERT_LEN] {
+        let [p0, p1] = self.preferred.to_le_bytes();
+        let [m0, m1] = self.max.to_le_bytes();
+        [p0, p1, m0, m1]
+    }
+
+    /// ワイヤ表現から復元します（長さが違う、または推奨が0か上限を超える場合はNone）
+    pub fn from_wire(data: &[u8]) -> Option<Self> {
+        let [p0, p1, m0, m1] = data.try_into().ok()?;
+        let advert = Self {
+            preferred: u16::from_le_bytes([p0, p1]),
+            max: u16::from_le_bytes([m0, m1]),
+        };
+        (advert.preferred > 0 && advert.preferred <= advert.max).then_some(advert)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gateway_advert_is_clamped_to_buffer() {
+        assert_eq!(ChunkSizeAdvert::gateway(None), ChunkSizeAdvert { preferred: 223, max: 223 });
+        assert_eq!(ChunkSizeAdvert::gateway(Some(120)).preferred, 120);
+        assert_eq!(ChunkSizeAdvert::gateway(Some(1000)).preferred, MAX_CHUNK_PAYLOAD_LEN);
+        assert_eq!(ChunkSizeAdvert::gateway(Some(5)).preferred, MIN_CHUNK_PAYLOAD_LEN);
+    }
+
+    #[test]
+    fn test_wire_roundtrip() {
+        let advert = ChunkSizeAdvert { preferred: 150, max: 223 };
+        assert_eq!(advert.to_wire(), [150, 0, 223, 0]);
+        assert_eq!(ChunkSizeAdvert::from_wire(&advert.to_wire()), Some(advert));
+        assert_eq!(ChunkSizeAdvert::from_wire(&[150, 0, 223]), None);
+        assert_eq!(ChunkSizeAdvert::from_wire(&[0, 0, 223, 0]), None);
+        assert_eq!(ChunkSizeAdvert::from_wire(&[224, 0, 223, 0]), None);
+    }
+}
@@ -0,0 +1,758 @@
+/// 画像転送完了イベントの集約
+///
+/// 送信側はHASHフレームを1回、EOFフレームを最大3回送信し、ESP-NOWの再送でも
+/// 同じフレームが重複して届きます。ゲートウェイで送信元ごとにこれらをまとめ、
+/// 1回の転送につき1つの `FrameComplete` フレームとしてUSBへ送出します。
+///
+/// - DATA/FECフレームはそのまま転送し、DATAのバイト数を積算します。
+/// - HASHフレームは保持し、同一内容の再受信は重複として破棄します。
+/// - 最初のEOF受信から `COMPLETION_HOLD` の間に届いた重複を数えてから送出します。
+/// - HASH受信後にEOFが届かないまま `HASH_IDLE_TIMEOUT` 経過した場合もEOFなしで送出します。
+/// - 転送が中断された場合は集約中の転送を破棄し、`FrameAbort` として送出します。
+/// - 完了イベントには転送にかかった時間（無線受信・USB転送）を付加し、
+///   HASHのトレースIDと合わせてホストでのトレース分析に使えるようにします。
+/// - DATA/FECのシーケンス番号の飛びをギャップマップとして完了イベントに付加します。
+/// - 部分転送の救済が有効な場合、HASHが届かないまま途絶えた転送も完了イベントとして送出し、
+///   欠落のある転送には `PARTIAL:1` を付けてサーバーが不完全な画像として保存できるようにします。
+/// - HASHに撮影からの経過時間（`CAPTURE_AGE_MS`）があれば、完了イベント送出までの
+///   エンドツーエンド遅延を `E2E_MS` として付加します。
+/// - HASHのテレメトリを解釈し、範囲内の値を `DEC`、範囲外の項目を `DQ` として付加します（`data_quality` を参照）。
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use super::cancellation::AbortReason;
+use super::data_quality::DecodedTelemetry;
+use super::frame::{create_frame, Frame};
+use super::gap_map::GapMap;
+use super::routing::FrameRoute;
+use super::telemetry::telemetry_field;
+use super::FrameType;
+
+/// 最初のEOF受信後、重複を待ってから完了イベントを送出するまでの時間
+pub const COMPLETION_HOLD: Duration = Duration::from_millis(500);
+
+/// HASH受信後、フレームが途絶えてから完了イベントを送出するまでの時間
+pub const HASH_IDLE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 完了イベントのサマリーとHASHペイロードの区切り文字
+pub const COMPLETE_PAYLOAD_SEPARATOR: u8 = b';';
+
+/// 1回の画像転送の完了イベント
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrameComplete {
+    /// 送信元MACアドレス
+    pub mac: [u8; 6],
+    /// 送信元ごとの転送番号（ゲートウェイ起動後の通し番号）
+    pub frame_id: u32,
+    /// 完了イベントのシーケンス番号（最初のEOF、EOFがなければ最後のHASHの番号）
+    pub sequence_number: u32,
+    /// HASHフレームのペイロード（ハッシュとテレメトリ、未受信ならNone）
+    pub hash_payload: Option<Vec<u8>>,
+    /// 転送されたDATAフレームのペイロード合計バイト数
+    pub byte_count: u32,
+    /// 破棄した重複HASH/EOFフレーム数
+    pub dedupe_count: u32,
+    /// EOFを受信したかどうか
+    pub eof_received: bool,
+    /// 最初のフレーム受信から最後のフレーム受信までの時間（ミリ秒）
+    pub air_ms: u32,
+    /// この転送のフレームのUSB転送時間の合計（ミリ秒）
+    pub usb_ms: u32,
+    /// 受信できなかったDATA/FECのシーケンス番号の範囲
+    pub gaps: GapMap,
+    /// 部分転送の救済により送出した不完全な転送かどうか
+    pub partial: bool,
+    /// 撮影から完了イベント送出までの時間（ミリ秒、HASHに撮影経過時間がなければNone）
+    pub end_to_end_ms: Option<u32>,
+}
+
+impl FrameComplete {
+    /// ペイロードを生成します
+    ///
+    /// 形式: `FRAME_ID:<id>,BYTES:<n>,DEDUP:<n>,EOF:<0|1>,AIR_MS:<n>,USB_MS:<n>[,GAPS:<a-b|c-d>][,PARTIAL:1][,E2E_MS:<n>][,DEC:<KEY=値|..>][,DQ:<KEY|..>][;<HASHペイロード>]`
+    pub fn to_payload(&self) -> Vec<u8> {
+        let mut summary = format!(
+            "FRAME_ID:{},BYTES:{},DEDUP:{},EOF:{},AIR_MS:{},USB_MS:{}",
+            self.frame_id,
+            self.byte_count,
+            self.dedupe_count,
+            u8::from(self.eof_received),
+            self.air_ms,
+            self.usb_ms
+        );
+        if !self.gaps.is_empty() {
+            summary.push_str(&format!(",GAPS:{}", self.gaps.to_field()));
+        }
+        if self.partial {
+            summary.push_str(",PARTIAL:1");
+        }
+        if let Some(end_to_end_ms) = self.end_to_end_ms {
+            summary.push_str(&format!(",E2E_MS:{}", end_to_end_ms));
+        }
+        if let Some(telemetry) = self.hash_payload.as_deref().and_then(DecodedTelemetry::decode) {
+            summary.push_str(&telemetry.summary_fields());
+        }
+        let mut payload = summary.into_bytes();
+        if let Some(hash_payload) = &self.hash_payload {
+            payload.push(COMPLETE_PAYLOAD_SEPARATOR);
+            payload.extend_from_slice(hash_payload);
+        }
+        payload
+    }
+
+    /// USBへ送出するフレームを生成します
+    pub fn to_frame(&self) -> Vec<u8> {
+        create_frame(
+            self.mac,
+            &self.to_payload(),
+            FrameType::Complete,
+            self.sequence_number,
+        )
+    }
+
+    /// HASHペイロードに含まれるトレースID（`TRACE:<16桁の16進数>`）
+    pub fn trace_id(&self) -> Option<u64> {
+        u64::from_str_radix(telemetry_field(self.hash_payload.as_deref()?, "TRACE")?, 16).ok()
+    }
+}
+
+/// HASHペイロードに含まれる撮影からHASH送信までの経過時間（`CAPTURE_AGE_MS:<ミリ秒>`）
+fn capture_age_ms(hash_payload: &[u8]) -> Option<u32> {
+    telemetry_field(hash_payload, "CAPTURE_AGE_MS")?.parse().ok()
+}
+
+/// 中断された画像転送のイベント
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrameAbort {
+    /// 送信元MACアドレス
+    pub mac: [u8; 6],
+    /// 中断された転送番号
+    pub frame_id: u32,
+    /// 中断までに転送されたDATAフレームのペイロード合計バイト数
+    pub byte_count: u32,
+    /// 中断の理由
+    pub reason: AbortReason,
+}
+
+impl FrameAbort {
+    /// ペイロードを生成します
+    ///
+    /// 形式: `FRAME_ID:<id>,BYTES:<n>,REASON:<DEVICE|QUEUE_OVERFLOW|CHECKSUM_STORM>`
+    pub fn to_payload(&self) -> Vec<u8> {
+        format!(
+            "FRAME_ID:{},BYTES:{},REASON:{}",
+            self.frame_id,
+            self.byte_count,
+            self.reason.as_str()
+        )
+        .into_bytes()
+    }
+
+    /// USBへ送出するフレームを生成します（シーケンス番号は転送番号）
+    pub fn to_frame(&self) -> Vec<u8> {
+        create_frame(self.mac, &self.to_payload(), FrameType::Abort, self.frame_id)
+    }
+}
+
+/// フレーム観測の結果
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Observation {
+    /// 観測したフレームより先に送出すべき完了イベント
+    pub completed: Option<FrameComplete>,
+    /// 観測したフレームをそのまま転送するかどうか
+    pub forward: bool,
+}
+
+/// 集約中の転送
+#[derive(Debug)]
+struct Session {
+    frame_id: u32,
+    hash_payload: Option<Vec<u8>>,
+    hash_sequence: u32,
+    hash_received_at: Option<Instant>,
+    eof_sequence: Option<u32>,
+    byte_count: u32,
+    dedupe_count: u32,
+    deadline: Option<Instant>,
+    started_at: Instant,
+    last_frame_at: Instant,
+    usb_ms: u32,
+    gaps: GapMap,
+}
+
+/// 直前に完了した転送（遅れて届いた重複の判定用）
+#[derive(Debug)]
+struct Completed {
+    hash_payload: Option<Vec<u8>>,
+    eof_received: bool,
+}
+
+/// 送信元ごとの状態
+#[derive(Debug, Default)]
+struct SenderState {
+    session: Option<Session>,
+    last_completed: Option<Completed>,
+    next_frame_id: u32,
+    late_duplicates: u32,
+    /// 欠落による中断後、残りのフレームを破棄する期限（EOF受信まで、無通信で延長しない）
+    discard_until: Option<Instant>,
+}
+
+impl SenderState {
+    fn open_session(&mut self, now: Instant) -> &mut Session {
+        self.last_completed = None;
+        let next_frame_id = &mut self.next_frame_id;
+        let session = self.session.get_or_insert_with(|| {
+            let frame_id = *next_frame_id;
+            *next_frame_id = frame_id.wrapping_add(1);
+            Session {
+                frame_id,
+                hash_payload: None,
+                hash_sequence: 0,
+                hash_received_at: None,
+                eof_sequence: None,
+                byte_count: 0,
+                dedupe_count: 0,
+                deadline: None,
+                started_at: now,
+                last_frame_at: now,
+                usb_ms: 0,
+                gaps: GapMap::default(),
+            }
+        });
+        session.last_frame_at = now;
+        session
+    }
+
+    fn finish(&mut self, mac: [u8; 6], partial_salvage: bool, now: Instant) -> Option<FrameComplete> {
+        let session = self.session.take()?;
+        let eof_received = session.eof_sequence.is_some();
+        let partial = partial_salvage && (!eof_received || !session.gaps.is_empty());
+        // 撮影からHASH送信までの経過時間に、HASH受信から送出までの時間を加える
+        let end_to_end_ms = session
+            .hash_payload
+            .as_deref()
+            .and_then(capture_age_ms)
+            .zip(session.hash_received_at)
+            .map(|(capture_age_ms, received_at)| {
+                let held_ms = now.saturating_duration_since(received_at).as_millis().min(u32::MAX as u128) as u32;
+                capture_age_ms.saturating_add(held_ms)
+            });
+        self.last_completed = Some(Completed {
+            hash_payload: session.hash_payload.clone(),
+            eof_received,
+        });
+        Some(FrameComplete {
+            mac,
+            frame_id: session.frame_id,
+            sequence_number: session.eof_sequence.unwrap_or(session.hash_sequence),
+            hash_payload: session.hash_payload,
+            byte_count: session.byte_count,
+            dedupe_count: session.dedupe_count,
+            eof_received,
+            air_ms: session
+                .last_frame_at
+                .saturating_duration_since(session.started_at)
+                .as_millis()
+                .min(u32::MAX as u128) as u32,
+            usb_ms: session.usb_ms,
+            gaps: session.gaps,
+            partial,
+            end_to_end_ms,
+        })
+    }
+}
+
+/// 送信元ごとにHASH/EOFを集約するトラッカー
+#[derive(Debug, Default)]
+pub struct CompletionTracker {
+    senders: HashMap<[u8; 6], SenderState>,
+    partial_salvage: bool,
+}
+
+impl CompletionTracker {
+    /// 新しいトラッカーを作成します
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 部分転送の救済を有効にしたトラッカーを作成します
+    ///
+    /// HASHを受信していない転送もフレームが途絶えて `HASH_IDLE_TIMEOUT` 経過すれば完了イベントとして送出します。
+    pub fn with_partial_salvage(enabled: bool) -> Self {
+        Self {
+            senders: HashMap::new(),
+            partial_salvage: enabled,
+        }
+    }
+
+    /// 受信フレームを観測し、転送可否と先行して送出すべき完了イベントを返します
+    pub fn observe(&mut self, frame: &Frame, now: Instant) -> Observation {
+        let mac = *frame.mac_address();
+        let partial_salvage = self.partial_salvage;
+        let sender = self.senders.entry(mac).or_default();
+
+        // 欠落により中断した転送の残りは、EOFまで（または無通信で期限切れまで）破棄する
+        if let Some(until) = sender.discard_until {
+            if now >= until {
+                sender.discard_until = None;
+            } else {
+                match FrameRoute::of(frame.frame_type()) {
+                    FrameRoute::Image | FrameRoute::Telemetry => {
+                        sender.discard_until = Some(now + HASH_IDLE_TIMEOUT);
+                        return Observation::default();
+                    }
+                    FrameRoute::EndOfTransfer => {
+                        sender.discard_until = None;
+                        sender.last_completed = Some(Completed {
+                            hash_payload: None,
+                            eof_received: true,
+                        });
+                        return Observation::default();
+                    }
+                    FrameRoute::Cancel | FrameRoute::Gateway | FrameRoute::Urgent => {}
+                }
+            }
+        }
+
+        match FrameRoute::of(frame.frame_type()) {
+            FrameRoute::Image => {
+                // EOF後のデータは次の転送の開始
+                let completed = match &sender.session {
+                    Some(session) if session.eof_sequence.is_some() => sender.finish(mac, partial_salvage, now),
+                    _ => None,
+                };
+                let session = sender.open_session(now);
+                session.gaps.observe(frame.sequence_number());
+                if frame.frame_type() == FrameType::Data {
+                    session.byte_count = session.byte_count.saturating_add(frame.data().len() as u32);
+                }
+                if session.hash_payload.is_some() || partial_salvage {
+                    session.deadline = Some(now + HASH_IDLE_TIMEOUT);
+                }
+                Observation { completed, forward: true }
+            }
+            FrameRoute::Telemetry => {
+                let hash = frame.data();
+                let mut completed = None;
+
+                match &mut sender.session {
+                    Some(session) if session.hash_payload.as_deref() == Some(hash) => {
+                        session.dedupe_count += 1;
+                        if session.eof_sequence.is_none() {
+                            session.deadline = Some(now + HASH_IDLE_TIMEOUT);
+                        }
+                        return Observation::default();
+                    }
+                    Some(session) if session.hash_payload.is_some() => {
+                        // 異なるHASHは次の転送の開始
+                        completed = sender.finish(mac, partial_salvage, now);
+                    }
+                    Some(_) => {}
+                    None => {
+                        let duplicate = sender
+                            .last_completed
+                            .as_ref()
+                            .is_some_and(|last| last.hash_payload.as_deref() == Some(hash));
+                        if duplicate {
+                            sender.late_duplicates += 1;
+                            return Observation::default();
+                        }
+                    }
+                }
+
+                let session = sender.open_session(now);
+                session.hash_payload = Some(hash.to_vec());
+                session.hash_sequence = frame.sequence_number();
+                session.hash_received_at = Some(now);
+                // HASHはDATA/FECの後に送られるため、直前までに届かなかった番号は末尾の欠落
+                session.gaps.close_before(frame.sequence_number());
+                if session.eof_sequence.is_none() {
+                    session.deadline = Some(now + HASH_IDLE_TIMEOUT);
+                }
+                Observation { completed, forward: false }
+            }
+            FrameRoute::EndOfTransfer => {
+                match &mut sender.session {
+                    Some(session) if session.eof_sequence.is_some() => {
+                        session.dedupe_count += 1;
+                        return Observation::default();
+                    }
+                    Some(_) => {}
+                    None => {
+                        let duplicate = sender
+                            .last_completed
+                            .as_ref()
+                            .is_some_and(|last| last.eof_received);
+                        if duplicate {
+                            sender.late_duplicates += 1;
+                            return Observation::default();
+                        }
+                    }
+                }
+
+                let session = sender.open_session(now);
+                session.eof_sequence = Some(frame.sequence_number());
+                session.deadline = Some(now + COMPLETION_HOLD);
+                Observation::default()
+            }
+            // 緊急フレームは転送の途中に割り込むため、集約中の転送に影響させない
+            FrameRoute::Gateway | FrameRoute::Urgent => Observation { completed: None, forward: true },
+            // 受信コールバックで中断として処理済みのため転送しない
+            FrameRoute::Cancel => Observation::default(),
+        }
+    }
+
+    /// 送信元の転送を中断し、集約中の転送を破棄して中断イベントを返します
+    ///
+    /// 欠落・チェックサムエラーの多発による中断の場合、同じ転送の残りフレームはEOFまで破棄します。
+    pub fn abort(&mut self, mac: [u8; 6], reason: AbortReason, now: Instant) -> FrameAbort {
+        let sender = self.senders.entry(mac).or_default();
+        let session = sender.open_session(now);
+        let (frame_id, byte_count) = (session.frame_id, session.byte_count);
+        sender.session = None;
+        if matches!(reason, AbortReason::QueueOverflow | AbortReason::ChecksumStorm) {
+            sender.discard_until = Some(now + HASH_IDLE_TIMEOUT);
+        }
+        FrameAbort { mac, frame_id, byte_count, reason }
+    }
+
+    /// 転送したフレームのUSB転送時間を集約中の転送に加算します
+    pub fn record_usb_transfer(&mut self, mac: &[u8; 6], elapsed_ms: u32) {
+        if let Some(session) = self.senders.get_mut(mac).and_then(|sender| sender.session.as_mut()) {
+            session.usb_ms = session.usb_ms.saturating_add(elapsed_ms);
+        }
+    }
+
+    /// 期限を過ぎた転送の完了イベントを返します
+    pub fn poll(&mut self, now: Instant) -> Vec<FrameComplete> {
+        let mut completed = Vec::new();
+        let partial_salvage = self.partial_salvage;
+        for (mac, sender) in self.senders.iter_mut() {
+            let expired = sender
+                .session
+                .as_ref()
+                .and_then(|session| session.deadline)
+                .is_some_and(|deadline| deadline <= now);
+            if expired {
+                completed.extend(sender.finish(*mac, partial_salvage, now));
+            }
+        }
+        completed.sort_by_key(|event| event.sequence_number);
+        completed
+    }
+
+    /// 次に完了イベントを送出する予定時刻を返します
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.senders
+            .values()
+            .filter_map(|sender| sender.session.as_ref()?.deadline)
+            .min()
+    }
+
+    /// 受信途中（EOF未受信）の転送の数を返します
+    pub fn active_transfers(&self) -> usize {
+        self.senders
+            .values()
+            .filter(|sender| sender.session.as_ref().is_some_and(|session| session.eof_sequence.is_none()))
+            .count()
+    }
+
+    /// 完了イベント送出後に届き、破棄した重複フレーム数を返します
+    pub fn late_duplicates(&self, mac: &[u8; 6]) -> u32 {
+        self.senders.get(mac).map_or(0, |sender| sender.late_duplicates)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAC: [u8; 6] = [0x11, 0x22, 0x33, 0x44, 0x55, 0x66];
+    const HASH: &[u8] = b"HASH:abcd,VOLT:80,TEMP:25.0,TDS_VOLT:-999.0,2026/10/16 12:00:00.000";
+
+    fn frame(frame_type: FrameType, seq: u32, data: &[u8]) -> Frame {
+        Frame::new(MAC, frame_type, seq, data.to_vec())
+    }
+
+    #[test]
+    fn test_hash_and_repeated_eof_become_single_event() {
+        let mut tracker = CompletionTracker::new();
+        let start = Instant::now();
+
+        assert!(tracker.observe(&frame(FrameType::Data, 0, &[0; 100]), start).forward);
+        assert!(tracker.observe(&frame(FrameType::Data, 1, &[0; 50]), start).forward);
+        assert_eq!(tracker.observe(&frame(FrameType::Hash, 2, HASH), start), Observation::default());
+        assert_eq!(tracker.active_transfers(), 1);
+        tracker.observe(&frame(FrameType::Eof, 3, b"EOF"), start);
+        tracker.observe(&frame(FrameType::Eof, 3, b"EOF"), start);
+        tracker.observe(&frame(FrameType::Eof, 3, b"EOF"), start);
+
+        // EOF受信後は受信途中に数えない。保持期間中は送出しない
+        assert_eq!(tracker.active_transfers(), 0);
+        assert!(tracker.poll(start).is_empty());
+        assert_eq!(tracker.next_deadline(), Some(start + COMPLETION_HOLD));
+
+        let events = tracker.poll(start + COMPLETION_HOLD);
+        assert_eq!(events.len(), 1);
+        let event = &events[0];
+        assert_eq!(event.frame_id, 0);
+        assert_eq!(event.sequence_number, 3);
+        assert_eq!(event.byte_count, 150);
+        assert_eq!(event.dedupe_count, 2);
+        assert!(event.eof_received);
+        assert_eq!(event.hash_payload.as_deref(), Some(HASH));
+
+        // 送出後に遅れて届いた重複も破棄される
+        assert!(!tracker.observe(&frame(FrameType::Eof, 3, b"EOF"), start).forward);
+        assert!(!tracker.observe(&frame(FrameType::Hash, 2, HASH), start).forward);
+        assert_eq!(tracker.late_duplicates(&MAC), 2);
+        assert!(tracker.poll(start + HASH_IDLE_TIMEOUT * 2).is_empty());
+    }
+
+    #[test]
+    fn test_new_data_flushes_pending_completion() {
+        let mut tracker = CompletionTracker::new();
+        let start = Instant::now();
+
+        tracker.observe(&frame(FrameType::Hash, 0, HASH), start);
+        tracker.observe(&frame(FrameType::Eof, 1, b"EOF"), start);
+
+        let observation = tracker.observe(&frame(FrameType::Data, 0, &[1; 10]), start);
+        assert!(observation.forward);
+        let completed = observation.completed.expect("pending completion");
+        assert_eq!(completed.frame_id, 0);
+        assert_eq!(completed.byte_count, 0);
+
+        tracker.observe(&frame(FrameType::Eof, 1, b"EOF"), start);
+        let events = tracker.poll(start + COMPLETION_HOLD);
+        assert_eq!(events[0].frame_id, 1);
+        assert_eq!(events[0].byte_count, 10);
+        assert_eq!(events[0].hash_payload, None);
+    }
+
+    #[test]
+    fn test_hash_without_eof_completes_after_idle_timeout() {
+        let mut tracker = CompletionTracker::new();
+        let start = Instant::now();
+
+        tracker.observe(&frame(FrameType::Hash, 7, HASH), start);
+        let later = start + Duration::from_secs(5);
+        tracker.observe(&frame(FrameType::Data, 8, &[0; 20]), later);
+
+        // データ受信で待機期限が延長される
+        assert!(tracker.poll(start + HASH_IDLE_TIMEOUT).is_empty());
+
+        let events = tracker.poll(later + HASH_IDLE_TIMEOUT);
+        assert_eq!(events.len(), 1);
+        assert!(!events[0].eof_received);
+        assert_eq!(events[0].sequence_number, 7);
+    }
+
+    #[test]
+    fn test_completion_reports_transfer_timing_and_trace_id() {
+        let mut tracker = CompletionTracker::new();
+        let start = Instant::now();
+
+        tracker.observe(&frame(FrameType::Data, 1, &[0; 100]), start);
+        tracker.record_usb_transfer(&MAC, 3);
+        tracker.observe(&frame(FrameType::Data, 2, &[0; 100]), start + Duration::from_millis(400));
+        tracker.record_usb_transfer(&MAC, 4);
+        tracker.observe(
+            &frame(FrameType::Hash, 3, b"HASH:ab,VOLT:80,TRACE:0123456789abcdef"),
+            start + Duration::from_millis(450),
+        );
+        tracker.observe(&frame(FrameType::Eof, 4, &[]), start + Duration::from_millis(600));
+        // 完了前の重複EOFは所要時間に含めない
+        tracker.observe(&frame(FrameType::Eof, 5, &[]), start + Duration::from_millis(900));
+
+        let events = tracker.poll(start + Duration::from_millis(600) + COMPLETION_HOLD);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].air_ms, 600);
+        assert_eq!(events[0].usb_ms, 7);
+        assert_eq!(events[0].trace_id(), Some(0x0123_4567_89ab_cdef));
+    }
+
+    #[test]
+    fn test_completion_forwards_newer_telemetry_unchanged() {
+        // このゲートウェイが知らない項目・新しいバージョンのテレメトリもそのままホストへ渡す
+        let hash_payload: &[u8] = b"HASH:ab,VOLT:80,2026/02/11 12:00:00.000,HUMIDITY:61.5,RSSI:-67,TLM_V:2,TLM_CAPS:4,CAPTURE_AGE_MS:100";
+        let mut tracker = CompletionTracker::new();
+        let start = Instant::now();
+        tracker.observe(&frame(FrameType::Hash, 3, hash_payload), start);
+        tracker.observe(&frame(FrameType::Eof, 4, &[]), start);
+
+        let events = tracker.poll(start + COMPLETION_HOLD);
+        assert_eq!(events.len(), 1);
+        let payload = events[0].to_payload();
+        assert!(payload.ends_with(&[&[COMPLETE_PAYLOAD_SEPARATOR][..], hash_payload].concat()));
+        // 知っている項目は従来どおり解釈する
+        assert!(events[0].end_to_end_ms.is_some_and(|ms| ms >= 100));
+    }
+
+    #[test]
+    fn test_frame_complete_payload_roundtrip() {
+        let event = FrameComplete {
+            mac: MAC,
+            frame_id: 3,
+            sequence_number: 42,
+            hash_payload: Some(b"HASH:ff,VOLT:50,TEMP:120.0".to_vec()),
+            byte_count: 1234,
+            dedupe_count: 1,
+            eof_received: true,
+            air_ms: 850,
+            usb_ms: 40,
+            gaps: GapMap::default(),
+            partial: false,
+            end_to_end_ms: None,
+        };
+        assert_eq!(
+            event.to_payload(),
+            b"FRAME_ID:3,BYTES:1234,DEDUP:1,EOF:1,AIR_MS:850,USB_MS:40,DEC:VOLT=50,DQ:TEMP;HASH:ff,VOLT:50,TEMP:120.0"
+                .to_vec()
+        );
+        assert_eq!(event.trace_id(), None);
+
+        let (parsed, _) = Frame::from_bytes(&event.to_frame()).unwrap();
+        assert_eq!(parsed.frame_type(), FrameType::Complete);
+        assert_eq!(parsed.sequence_number(), 42);
+        assert_eq!(parsed.data(), event.to_payload().as_slice());
+    }
+
+    #[test]
+    fn test_completion_reports_sequence_gaps() {
+        let mut tracker = CompletionTracker::new();
+        let start = Instant::now();
+
+        for seq in [1, 2, 4, 5, 7] {
+            tracker.observe(&frame(FrameType::Data, seq, &[0; 10]), start);
+        }
+        // 8は末尾の欠落、9がHASH
+        tracker.observe(&frame(FrameType::Hash, 9, HASH), start);
+        tracker.observe(&frame(FrameType::Eof, 10, b"EOF"), start);
+
+        let events = tracker.poll(start + COMPLETION_HOLD);
+        assert_eq!(events[0].gaps.ranges(), &[(3, 3), (6, 6), (8, 8)]);
+        assert!(!events[0].partial);
+        assert!(events[0]
+            .to_payload()
+            .starts_with(b"FRAME_ID:0,BYTES:50,DEDUP:0,EOF:1,AIR_MS:0,USB_MS:0,GAPS:3-3|6-6|8-8,DEC:VOLT=80|TEMP=25;"));
+    }
+
+    #[test]
+    fn test_partial_salvage_emits_stalled_transfer() {
+        let start = Instant::now();
+
+        // 救済なしではHASHのない転送は送出しない
+        let mut tracker = CompletionTracker::new();
+        tracker.observe(&frame(FrameType::Data, 1, &[0; 10]), start);
+        assert!(tracker.poll(start + HASH_IDLE_TIMEOUT).is_empty());
+
+        let mut tracker = CompletionTracker::with_partial_salvage(true);
+        tracker.observe(&frame(FrameType::Data, 1, &[0; 10]), start);
+        tracker.observe(&frame(FrameType::Data, 3, &[0; 10]), start);
+        let events = tracker.poll(start + HASH_IDLE_TIMEOUT);
+        assert_eq!(events.len(), 1);
+        assert!(events[0].partial);
+        assert!(!events[0].eof_received);
+        assert_eq!(events[0].hash_payload, None);
+        assert_eq!(events[0].gaps.to_field(), "2-2");
+        assert!(String::from_utf8(events[0].to_payload()).unwrap().ends_with(",GAPS:2-2,PARTIAL:1"));
+    }
+
+    #[test]
+    fn test_device_abort_drops_session() {
+        let mut tracker = CompletionTracker::new();
+        let start = Instant::now();
+
+        tracker.observe(&frame(FrameType::Data, 0, &[0; 40]), start);
+        assert_eq!(tracker.active_transfers(), 1);
+        let abort = tracker.abort(MAC, AbortReason::Device, start);
+        assert_eq!(tracker.active_transfers(), 0);
+        assert_eq!(abort.frame_id, 0);
+        assert_eq!(abort.byte_count, 40);
+        assert!(tracker.poll(start + HASH_IDLE_TIMEOUT).is_empty());
+
+        // 再送された転送は新しい転送番号で集約される
+        assert!(tracker.observe(&frame(FrameType::Data, 0, &[0; 10]), start).forward);
+        tracker.observe(&frame(FrameType::Eof, 1, b"EOF"), start);
+        let events = tracker.poll(start + COMPLETION_HOLD);
+        assert_eq!(events[0].frame_id, 1);
+        assert_eq!(events[0].byte_count, 10);
+        assert_eq!(
+            abort.to_payload(),
+            b"FRAME_ID:0,BYTES:40,REASON:DEVICE".to_vec()
+        );
+    }
+
+    #[test]
+    fn test_overflow_abort_discards_rest_of_transfer() {
+        let mut tracker = CompletionTracker::new();
+        let start = Instant::now();
+
+        tracker.observe(&frame(FrameType::Data, 0, &[0; 10]), start);
+        tracker.abort(MAC, AbortReason::QueueOverflow, start);
+
+        assert!(!tracker.observe(&frame(FrameType::Data, 2, &[0; 10]), start).forward);
+        assert!(!tracker.observe(&frame(FrameType::Hash, 3, HASH), start).forward);
+        assert!(!tracker.observe(&frame(FrameType::Eof, 4, b"EOF"), start).forward);
+        // 残りのEOF再送も重複として破棄される
+        assert!(!tracker.observe(&frame(FrameType::Eof, 4, b"EOF"), start).forward);
+        assert!(tracker.poll(start + HASH_IDLE_TIMEOUT).is_empty());
+
+        // 次の転送は通常どおり
+        assert!(tracker.observe(&frame(FrameType::Data, 0, &[0; 10]), start).forward);
+    }
+
+    #[test]
+    fn test_overflow_discard_expires_without_eof() {
+        let mut tracker = CompletionTracker::new();
+        let start = Instant::now();
+
+        tracker.abort(MAC, AbortReason::QueueOverflow, start);
+        let later = start + HASH_IDLE_TIMEOUT;
+        assert!(tracker.observe(&frame(FrameType::Data, 0, &[0; 10]), later).forward);
+    }
+
+    #[test]
+    fn test_urgent_frame_does_not_disturb_transfer() {
+        let mut tracker = CompletionTracker::new();
+        let start = Instant::now();
+
+        tracker.observe(&frame(FrameType::Data, 0, &[0; 10]), start);
+        // 転送の途中の緊急フレームはそのまま転送し、集約中の転送の番号・バイト数・欠落に含めない
+        let observation = tracker.observe(&frame(FrameType::Urgent, 0, b"FROST:-1.5"), start);
+        assert!(observation.forward);
+        assert!(observation.completed.is_none());
+        tracker.observe(&frame(FrameType::Data, 1, &[0; 10]), start);
+        tracker.observe(&frame(FrameType::Eof, 2, b"EOF"), start);
+        let events = tracker.poll(start + COMPLETION_HOLD);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].byte_count, 20);
+        assert!(events[0].gaps.is_empty());
+
+        // 欠落で中断した転送の残りを破棄している間も緊急フレームは転送する
+        tracker.abort(MAC, AbortReason::QueueOverflow, start);
+        assert!(tracker.observe(&frame(FrameType::Urgent, 1, b"FROST:-2.0"), start).forward);
+        assert!(!tracker.observe(&frame(FrameType::Data, 3, &[0; 10]), start).forward);
+    }
+
+    #[test]
+    fn test_end_to_end_delay_includes_capture_age() {
+        let mut tracker = CompletionTracker::new();
+        let start = Instant::now();
+
+        tracker.observe(&frame(FrameType::Data, 0, &[0; 10]), start);
+        tracker.observe(&frame(FrameType::Hash, 1, b"HASH:ab,VOLT:80,CAPTURE_AGE_MS:4200"), start);
+        tracker.observe(&frame(FrameType::Eof, 2, b"EOF"), start + Duration::from_millis(100));
+
+        let events = tracker.poll(start + Duration::from_millis(600));
+        assert_eq!(events[0].end_to_end_ms, Some(4800));
+        assert!(String::from_utf8_lossy(&events[0].to_payload()).contains(",E2E_MS:4800,DEC:VOLT=80;"));
+
+        // 撮影経過時間のないHASHでは付加しない
+        tracker.observe(&frame(FrameType::Hash, 3, HASH), start);
+        tracker.observe(&frame(FrameType::Eof, 4, b"EOF"), start);
+        assert_eq!(tracker.poll(start + COMPLETION_HOLD)[0].end_to_end_ms, None);
+    }
+}
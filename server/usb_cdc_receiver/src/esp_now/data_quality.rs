@@ -0,0 +1,143 @@
+//! HASHペイロードのテレメトリの解釈と値の範囲の確認
+//!
+//! センサーの故障や配線の不良による異常値をホストより1つ手前で見つけられるよう、ゲートウェイで
+//! 電池残量・温度・TDS電圧を数値として解釈し、範囲外の値をデータ品質の警告（`DataQuality`）として扱います。
+//! 完了イベントには元のHASHペイロードをそのまま付けたうえで、解釈した値（`DEC`）と範囲外だった
+//! 項目（`DQ`）をサマリーに付加します。
+//!
+//! センサーがないデバイスのダミー値（`TEMP:-999.0` など）と、測定できなかった電池残量（255）は
+//! 値なしとして扱い、警告しません。
+
+use std::fmt;
+
+use super::telemetry::TelemetryFields;
+
+/// 解釈した値のサマリーのキー
+pub const DECODED_KEY: &str = "DEC";
+/// 範囲外だった項目のサマリーのキー
+pub const DATA_QUALITY_KEY: &str = "DQ";
+
+/// センサーがないことを示すダミー値
+const DUMMY_VALUE: f32 = -999.0;
+/// 電池残量を測定できなかったことを示す値
+const INVALID_PERCENT: f32 = 255.0;
+
+/// 範囲を確認する項目
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct FieldRange {
+    key: &'static str,
+    min: f32,
+    max: f32,
+    /// 値なしとして扱う値
+    absent: f32,
+}
+
+/// 範囲を確認する項目（温度はセンサー・ESP32の動作温度範囲、TDS電圧はADCの入力範囲）
+const FIELD_RANGES: [FieldRange; 5] = [
+    FieldRange { key: "VOLT", min: 0.0, max: 100.0, absent: INVALID_PERCENT },
+    FieldRange { key: "TEMP", min: -40.0, max: 85.0, absent: DUMMY_VALUE },
+    FieldRange { key: "TDS_VOLT", min: 0.0, max: 3.3, absent: DUMMY_VALUE },
+    FieldRange { key: "AMB_TEMP", min: -40.0, max: 85.0, absent: DUMMY_VALUE },
+    FieldRange { key: "AMB_BATT", min: 0.0, max: 100.0, absent: INVALID_PERCENT },
+];
+
+/// 範囲外、または数値として解釈できなかった項目
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DataQualityIssue {
+    pub key: &'static str,
+    /// デバイスが送った値（表記のまま）
+    pub value: String,
+}
+
+impl fmt::Display for DataQualityIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match FIELD_RANGES.iter().find(|range| range.key == self.key) {
+            Some(range) => write!(f, "{}={} (expected {}..{})", self.key, self.value, range.min, range.max),
+            None => write!(f, "{}={}", self.key, self.value),
+        }
+    }
+}
+
+/// HASHペイロードから解釈したテレメトリ
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DecodedTelemetry {
+    /// 範囲内の値（ペイロードにあった項目のみ）
+    pub values: Vec<(&'static str, f32)>,
+    /// 範囲外・解釈できなかった項目
+    pub issues: Vec<DataQualityIssue>,
+}
+
+impl DecodedTelemetry {
+    /// HASHペイロードを解釈します（UTF-8でなければNone）
+    pub fn decode(payload: &[u8]) -> Option<Self> {
+        let fields = TelemetryFields::parse(payload)?;
+        let mut decoded = Self::default();
+        for range in &FIELD_RANGES {
+            let Some(raw) = fields.get(range.key) else {
+                continue;
+            };
+            match raw.parse::<f32>() {
+                Ok(value) if value == range.absent => {}
+                Ok(value) if (range.min..=range.max).contains(&value) => decoded.values.push((range.key, value)),
+                _ => decoded.issues.push(DataQualityIssue {
+                    key: range.key,
+                    value: raw.to_string(),
+                }),
+            }
+        }
+        Some(decoded)
+    }
+
+    /// 値の取得
+    pub fn get(&self, key: &str) -> Option<f32> {
+        self.values.iter().find(|(value_key, _)| *value_key == key).map(|(_, value)| *value)
+    }
+
+    /// 完了イベントのサマリーに付加するフィールド（`,DEC:VOLT=80|TEMP=23.5[,DQ:TDS_VOLT]`、なければ空）
+    pub fn summary_fields(&self) -> String {
+        let mut fields = String::new();
+        if !self.values.is_empty() {
+            let values: Vec<String> = self.values.iter().map(|(key, value)| format!("{}={}", key, value)).collect();
+            fields.push_str(&format!(",{}:{}", DECODED_KEY, values.join("|")));
+        }
+        if !self.issues.is_empty() {
+            let keys: Vec<&str> = self.issues.iter().map(|issue| issue.key).collect();
+            fields.push_str(&format!(",{}:{}", DATA_QUALITY_KEY, keys.join("|")));
+        }
+        fields
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decodes_values_in_range() {
+        let decoded =
+            DecodedTelemetry::decode(b"HASH:ab,VOLT:80,TEMP:23.5,TDS_VOLT:1.25,2026/02/11 12:00:00.000,AMB_TEMP:24.0")
+                .unwrap();
+        assert_eq!(decoded.get("VOLT"), Some(80.0));
+        assert_eq!(decoded.get("AMB_TEMP"), Some(24.0));
+        assert!(decoded.issues.is_empty());
+        assert_eq!(decoded.summary_fields(), ",DEC:VOLT=80|TEMP=23.5|TDS_VOLT=1.25|AMB_TEMP=24");
+    }
+
+    #[test]
+    fn test_flags_implausible_values() {
+        let decoded = DecodedTelemetry::decode(b"HASH:ab,VOLT:140,TEMP:-85.2,TDS_VOLT:abc,AMB_BATT:90").unwrap();
+        assert_eq!(decoded.values, [("AMB_BATT", 90.0)]);
+        let keys: Vec<&str> = decoded.issues.iter().map(|issue| issue.key).collect();
+        assert_eq!(keys, ["VOLT", "TEMP", "TDS_VOLT"]);
+        assert_eq!(decoded.issues[1].to_string(), "TEMP=-85.2 (expected -40..85)");
+        assert_eq!(decoded.summary_fields(), ",DEC:AMB_BATT=90,DQ:VOLT|TEMP|TDS_VOLT");
+    }
+
+    #[test]
+    fn test_missing_sensor_markers_are_not_flagged() {
+        let decoded = DecodedTelemetry::decode(b"HASH:ab,VOLT:255,TEMP:-999.0,TDS_VOLT:-999.0").unwrap();
+        assert_eq!(decoded, DecodedTelemetry::default());
+        assert_eq!(decoded.summary_fields(), "");
+        assert_eq!(DecodedTelemetry::decode(&[0xff, 0xfe]), None);
+    }
+}
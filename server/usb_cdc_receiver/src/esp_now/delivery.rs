@@ -0,0 +1,259 @@
+/// ESP-NOW送信の到達確認
+///
+/// `esp_now_send` の戻り値は送信キューへの投入結果にすぎず、実際に相手へ届いたかは
+/// 送信コールバックがリンク層の結果（相手からのMAC層ACKの有無）として通知します。
+/// 送信ごとに発行したトークンとコールバックを突き合わせ、確認できた送信だけを到達とみなします。
+///
+/// ESP-NOWは同じ宛先への送信結果を送信順に通知するため、コールバックは宛先ごとに
+/// 最も古い未確認の送信に対応付けます。コールバック内で送るPongなど結果を待たない送信も
+/// 登録しておき、待機中の送信と取り違えないようにします。
+use std::collections::VecDeque;
+
+use super::outbound::OutboundKind;
+
+/// 未確認として保持する送信の上限（コールバックが失われた場合の取り残しを防ぐ）
+const MAX_OUTSTANDING: usize = 32;
+
+/// 送信の到達結果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeliveryStatus {
+    /// 相手に届いた
+    Delivered,
+    /// リンク層で失敗した
+    Failed,
+    /// 期限内にコールバックがなかった
+    TimedOut,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Outstanding {
+    token: u32,
+    mac: [u8; 6],
+    awaited: bool,
+    result: Option<bool>,
+}
+
+/// 送信と送信コールバックの対応付け
+#[derive(Debug, Default)]
+pub struct DeliveryTracker {
+    next_token: u32,
+    outstanding: VecDeque<Outstanding>,
+}
+
+impl DeliveryTracker {
+    /// 空のトラッカーを作成します
+    pub const fn new() -> Self {
+        Self {
+            next_token: 0,
+            outstanding: VecDeque::new(),
+        }
+    }
+
+    /// 送信前に登録し、結果の取得に使うトークンを返します
+    ///
+    /// `awaited` が false の送信は結果を保持せず、対応付けのためだけに登録します。
+    pub fn register(&mut self, mac: [u8; 6], awaited: bool) -> u32 {
+        if self.outstanding.len() >= MAX_OUTSTANDING {
+            self.outstanding.pop_front();
+        }
+        let token = self.next_token;
+        self.next_token = self.next_token.wrapping_add(1);
+        self.outstanding.push_back(Outstanding {
+            token,
+            mac,
+            awaited,
+            result: None,
+        });
+        token
+    }
+
+    /// 送信コールバックの結果を、宛先への最も古い未確認の送信に対応付けます
+    ///
+    /// # 戻り値
+    /// * 対応付けた送信のトークン（該当なしならNone）
+    pub fn complete(&mut self, mac: [u8; 6], delivered: bool) -> Option<u32> {
+        let index = self
+            .outstanding
+            .iter()
+            .position(|send| send.mac == mac && send.result.is_none())?;
+        let send = &mut self.outstanding[index];
+        let token = send.token;
+        if send.awaited {
+            send.result = Some(delivered);
+        } else {
+            self.outstanding.remove(index);
+        }
+        Some(token)
+    }
+
+    /// 結果が届いていれば取り出します
+    pub fn take_result(&mut self, token: u32) -> Option<DeliveryStatus> {
+        let index = self
+            .outstanding
+            .iter()
+            .position(|send| send.token == token && send.result.is_some())?;
+        let send = self.outstanding.remove(index)?;
+        Some(if send.result == Some(true) {
+            DeliveryStatus::Delivered
+        } else {
+            DeliveryStatus::Failed
+        })
+    }
+
+    /// 結果を待つのをやめます（送信失敗・タイムアウト時）
+    ///
+    /// 未確認のまま残すと後続の送信と取り違えるため、未確認の場合は対応付けの位置を保持し、
+    /// 遅れて届いたコールバックで取り除かれるようにします。
+    pub fn abandon(&mut self, token: u32) {
+        if let Some(index) = self.outstanding.iter().position(|send| send.token == token) {
+            if self.outstanding[index].result.is_some() {
+                self.outstanding.remove(index);
+            } else {
+                self.outstanding[index].awaited = false;
+            }
+        }
+    }
+
+    /// 送信を取り消します（`esp_now_send` 自体が失敗し、コールバックが来ない場合）
+    pub fn cancel(&mut self, token: u32) {
+        self.outstanding.retain(|send| send.token != token);
+    }
+
+    /// 未確認の送信数
+    pub fn outstanding_len(&self) -> usize {
+        self.outstanding.len()
+    }
+}
+
+/// 種類ごとの到達数
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DeliveryCounts {
+    /// 到達を確認した送信数
+    pub delivered: u32,
+    /// 再送しても届かなかった送信数
+    pub failed: u32,
+}
+
+/// ダウンリンクの到達統計
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DeliveryStats {
+    /// ACK
+    pub ack: DeliveryCounts,
+    /// スリープコマンド
+    pub sleep: DeliveryCounts,
+    /// 設定の一斉配信
+    pub config: DeliveryCounts,
+    /// 到達を確認できず再送した回数
+    pub retries: u32,
+    /// コールバックが期限内に届かなかった回数
+    pub timeouts: u32,
+}
+
+impl DeliveryStats {
+    /// 送信の最終結果を記録します
+    pub fn record(&mut self, kind: OutboundKind, delivered: bool) {
+        let counts = match kind {
+            OutboundKind::Ack => &mut self.ack,
+            OutboundKind::Sleep => &mut self.sleep,
+            OutboundKind::Config => &mut self.config,
+        };
+        if delivered {
+            counts.delivered = counts.delivered.saturating_add(1);
+        } else {
+            counts.failed = counts.failed.saturating_add(1);
+        }
+    }
+
+    /// 1回の試行結果を記録します（最終結果は `record` で記録）
+    pub fn record_attempt(&mut self, status: DeliveryStatus, will_retry: bool) {
+        if status == DeliveryStatus::TimedOut {
+            self.timeouts = self.timeouts.saturating_add(1);
+        }
+        if will_retry {
+            self.retries = self.retries.saturating_add(1);
+        }
+    }
+
+    /// 統計フレームの項目
+    pub fn stats_fields(&self) -> [(&'static str, u32); 8] {
+        [
+            ("ACK_OK", self.ack.delivered),
+            ("ACK_FAIL", self.ack.failed),
+            ("SLEEP_OK", self.sleep.delivered),
+            ("SLEEP_FAIL", self.sleep.failed),
+            ("CONFIG_OK", self.config.delivered),
+            ("CONFIG_FAIL", self.config.failed),
+            ("SEND_RETRY", self.retries),
+            ("SEND_CB_TIMEOUT", self.timeouts),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAC_A: [u8; 6] = [0x24, 0x0A, 0xC4, 0x00, 0x00, 0x01];
+    const MAC_B: [u8; 6] = [0x24, 0x0A, 0xC4, 0x00, 0x00, 0x02];
+
+    #[test]
+    fn test_callbacks_are_matched_in_send_order_per_destination() {
+        let mut tracker = DeliveryTracker::new();
+        let pong = tracker.register(MAC_A, false);
+        let ack = tracker.register(MAC_A, true);
+        let sleep = tracker.register(MAC_B, true);
+
+        // 先に送ったPongのコールバックをACKの結果と取り違えない
+        assert_eq!(tracker.complete(MAC_A, true), Some(pong));
+        assert_eq!(tracker.take_result(ack), None);
+
+        assert_eq!(tracker.complete(MAC_B, false), Some(sleep));
+        assert_eq!(tracker.complete(MAC_A, true), Some(ack));
+        assert_eq!(tracker.take_result(ack), Some(DeliveryStatus::Delivered));
+        assert_eq!(tracker.take_result(sleep), Some(DeliveryStatus::Failed));
+        assert_eq!(tracker.outstanding_len(), 0);
+        assert_eq!(tracker.complete(MAC_A, true), None);
+    }
+
+    #[test]
+    fn test_abandoned_send_absorbs_late_callback() {
+        let mut tracker = DeliveryTracker::new();
+        let timed_out = tracker.register(MAC_A, true);
+        tracker.abandon(timed_out);
+        let retry = tracker.register(MAC_A, true);
+
+        // タイムアウトした送信の遅延コールバックは再送分の結果にしない
+        assert_eq!(tracker.complete(MAC_A, false), Some(timed_out));
+        assert_eq!(tracker.take_result(retry), None);
+        assert_eq!(tracker.complete(MAC_A, true), Some(retry));
+        assert_eq!(tracker.take_result(retry), Some(DeliveryStatus::Delivered));
+
+        let failed_send = tracker.register(MAC_A, true);
+        tracker.cancel(failed_send);
+        assert_eq!(tracker.outstanding_len(), 0);
+    }
+
+    #[test]
+    fn test_stats_fields_count_final_results() {
+        let mut stats = DeliveryStats::default();
+        stats.record_attempt(DeliveryStatus::TimedOut, true);
+        stats.record_attempt(DeliveryStatus::Delivered, false);
+        stats.record(OutboundKind::Ack, true);
+        stats.record(OutboundKind::Sleep, false);
+        stats.record(OutboundKind::Config, true);
+
+        assert_eq!(
+            stats.stats_fields(),
+            [
+                ("ACK_OK", 1),
+                ("ACK_FAIL", 0),
+                ("SLEEP_OK", 0),
+                ("SLEEP_FAIL", 1),
+                ("CONFIG_OK", 1),
+                ("CONFIG_FAIL", 0),
+                ("SEND_RETRY", 1),
+                ("SEND_CB_TIMEOUT", 1),
+            ]
+        );
+    }
+}
@@ -0,0 +1,247 @@
+//! デバイスのファームウェアビルド情報と設定ハッシュ
+//!
+//! デバイスは起動後最初のHASHフレームに `FW`/`BUILT`/`FEAT`/`CFG` を付加します。
+//! ゲートウェイは送信元ごとに最新の値を保持し、`LIST_DEVICES` コマンドで一覧を返します。
+//!
+//! プロビジョニング済みのデバイスは毎回のHASHフレームに `DEV_ID`（NVSに書き込んだUUID）を付加します。
+//! 一覧は `DEV_ID` を主キーにし、MACアドレスは変わりうる属性として扱うため、基板を交換して
+//! MACアドレスが変わっても同じデバイスの記録が続きます。`DEV_ID` のないデバイスはMACアドレスを
+//! キーにし、後から `DEV_ID` が届いたらその記録を引き継ぎます。
+use std::collections::BTreeMap;
+
+use crate::mac_address::format_mac_address;
+
+/// デバイス一覧応答の接頭辞
+pub const DEVICES_RESPONSE_PREFIX: &str = "CMD_DEVICES:";
+/// デバイスIDのキー
+pub const DEVICE_ID_KEY: &str = "DEV_ID";
+/// デバイスIDの最大長（ハイフン付きのUUID）
+pub const MAX_DEVICE_ID_LEN: usize = 36;
+
+/// HASHペイロードからデバイスIDを取り出します（英数字と `-` のみ、小文字に揃える）
+pub fn device_id_from_hash_payload(payload: &[u8]) -> Option<String> {
+    let payload = std::str::from_utf8(payload).ok()?;
+    let id = payload
+        .split(',')
+        .find_map(|item| item.strip_prefix(DEVICE_ID_KEY)?.strip_prefix(':'))?
+        .trim();
+    let valid = (1..=MAX_DEVICE_ID_LEN).contains(&id.len())
+        && id.bytes().all(|byte| byte.is_ascii_alphanumeric() || byte == b'-');
+    valid.then(|| id.to_ascii_lowercase())
+}
+
+/// HASHペイロードから解析したビルド情報
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceBuildInfo {
+    /// gitの短縮コミットハッシュ
+    pub firmware: String,
+    /// ビルド時刻（UNIX秒）
+    pub built_at: Option<u64>,
+    /// 有効なフィーチャー（`+` 区切り）
+    pub features: Option<String>,
+    /// 実効設定のハッシュ（16進8桁）
+    pub config_hash: Option<String>,
+}
+
+impl DeviceBuildInfo {
+    /// HASHペイロードから解析します（`FW` がなければNone）
+    pub fn from_hash_payload(payload: &[u8]) -> Option<Self> {
+        let payload = std::str::from_utf8(payload).ok()?;
+        let field = |key: &str| {
+            payload
+                .split(',')
+                .find_map(|item| item.strip_prefix(key)?.strip_prefix(':'))
+                .map(str::trim)
+        };
+        Some(Self {
+            firmware: field("FW")?.to_string(),
+            built_at: field("BUILT").and_then(|value| value.parse().ok()),
+            features: field("FEAT").map(str::to_string),
+            config_hash: field("CFG").map(str::to_string),
+        })
+    }
+
+    /// 一覧応答用の表現（`FW=..,BUILT=..,FEAT=..,CFG=..`）
+    pub fn summary(&self) -> String {
+        format!(
+            "FW={},BUILT={},FEAT={},CFG={}",
+            self.firmware,
+            self.built_at.map_or_else(|| "-".to_string(), |built_at| built_at.to_string()),
+            self.features.as_deref().unwrap_or("-"),
+            self.config_hash.as_deref().unwrap_or("-")
+        )
+    }
+}
+
+/// 一覧の1デバイス
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct DeviceEntry {
+    /// 最後に受信したときのMACアドレス
+    mac: [u8; 6],
+    device_id: Option<String>,
+    build: Option<DeviceBuildInfo>,
+}
+
+/// デバイスごとの最新のビルド情報（デバイスID、なければMACアドレスをキーにする）
+#[derive(Debug, Default)]
+pub struct DeviceDirectory {
+    devices: BTreeMap<String, DeviceEntry>,
+}
+
+impl DeviceDirectory {
+    /// 空の一覧を作成します
+    pub const fn new() -> Self {
+        Self { devices: BTreeMap::new() }
+    }
+
+    /// 送信元のデバイスIDを記録し、MACアドレスが変わった場合は以前のMACアドレスを返します
+    ///
+    /// MACアドレスをキーにした記録があれば、デバイスIDの記録として引き継ぎます。
+    pub fn record_identity(&mut self, mac: [u8; 6], device_id: &str) -> Option<[u8; 6]> {
+        let unprovisioned = self.devices.remove(&format_mac_address(&mac));
+        let entry = self.devices.entry(device_id.to_string()).or_insert_with(|| DeviceEntry {
+            mac,
+            device_id: Some(device_id.to_string()),
+            build: None,
+        });
+        let previous_mac = std::mem::replace(&mut entry.mac, mac);
+        if entry.build.is_none() {
+            entry.build = unprovisioned.and_then(|unprovisioned| unprovisioned.build);
+        }
+        (previous_mac != mac).then_some(previous_mac)
+    }
+
+    /// ビルド情報を記録し、前回から変わった場合は以前の値を返します
+    pub fn record(&mut self, mac: [u8; 6], info: DeviceBuildInfo) -> Option<DeviceBuildInfo> {
+        let key = self
+            .key_of(&mac)
+            .map_or_else(|| format_mac_address(&mac), str::to_string);
+        let entry = self.devices.entry(key).or_insert_with(|| DeviceEntry {
+            mac,
+            device_id: None,
+            build: None,
+        });
+        let previous = entry.build.replace(info)?;
+        (entry.build.as_ref() != Some(&previous)).then_some(previous)
+    }
+
+    /// 送信元のビルド情報
+    pub fn get(&self, mac: &[u8; 6]) -> Option<&DeviceBuildInfo> {
+        self.entry_of(mac)?.build.as_ref()
+    }
+
+    /// 送信元のデバイスID（`DEV_ID` が届いていなければNone）
+    pub fn device_id(&self, mac: &[u8; 6]) -> Option<&str> {
+        self.entry_of(mac)?.device_id.as_deref()
+    }
+
+    /// `LIST_DEVICES` への応答（1デバイス1行、記録がなければ空の1行）
+    ///
+    /// ビルド情報が届いていない項目は `-`、デバイスIDは末尾の `ID=` に付加します。
+    pub fn response(&self) -> String {
+        if self.devices.is_empty() {
+            return format!("{}\n", DEVICES_RESPONSE_PREFIX);
+        }
+        self.devices
+            .values()
+            .map(|entry| {
+                format!(
+                    "{}{},{},ID={}\n",
+                    DEVICES_RESPONSE_PREFIX,
+                    format_mac_address(&entry.mac),
+                    entry
+                        .build
+                        .as_ref()
+                        .map_or_else(|| "FW=-,BUILT=-,FEAT=-,CFG=-".to_string(), DeviceBuildInfo::summary),
+                    entry.device_id.as_deref().unwrap_or("-")
+                )
+            })
+            .collect()
+    }
+
+    /// 送信元の現在のMACアドレスを持つ記録（デバイスIDの記録を優先）
+    fn entry_of(&self, mac: &[u8; 6]) -> Option<&DeviceEntry> {
+        self.key_of(mac).and_then(|key| self.devices.get(key))
+    }
+
+    fn key_of(&self, mac: &[u8; 6]) -> Option<&str> {
+        self.devices
+            .iter()
+            .filter(|(_, entry)| entry.mac == *mac)
+            .max_by_key(|(_, entry)| entry.device_id.is_some())
+            .map(|(key, _)| key.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_build_info_fields() {
+        let payload = b"HASH:ab,VOLT:80,BOOT:POWERON,FW:1a2b3c4d-dirty,BUILT:1760000000,FEAT:esp,CFG:deadbeef";
+        let info = DeviceBuildInfo::from_hash_payload(payload).unwrap();
+        assert_eq!(info.firmware, "1a2b3c4d-dirty");
+        assert_eq!(info.built_at, Some(1_760_000_000));
+        assert_eq!(info.features.as_deref(), Some("esp"));
+        assert_eq!(info.config_hash.as_deref(), Some("deadbeef"));
+        assert_eq!(DeviceBuildInfo::from_hash_payload(b"HASH:ab,VOLT:80"), None);
+    }
+
+    #[test]
+    fn test_directory_lists_latest_build_per_device() {
+        let mut directory = DeviceDirectory::new();
+        assert_eq!(directory.response(), "CMD_DEVICES:\n");
+
+        let build = |firmware: &str, config_hash: &str| DeviceBuildInfo {
+            firmware: firmware.to_string(),
+            built_at: Some(1_760_000_000),
+            features: None,
+            config_hash: Some(config_hash.to_string()),
+        };
+        assert_eq!(directory.record([2; 6], build("aaaa", "00000001")), None);
+        assert_eq!(directory.record([2; 6], build("aaaa", "00000001")), None);
+        assert_eq!(
+            directory.record([2; 6], build("bbbb", "00000001")),
+            Some(build("aaaa", "00000001"))
+        );
+        directory.record([1; 6], build("aaaa", "00000002"));
+
+        assert_eq!(
+            directory.response(),
+            "CMD_DEVICES:01:01:01:01:01:01,FW=aaaa,BUILT=1760000000,FEAT=-,CFG=00000002,ID=-\n\
+             CMD_DEVICES:02:02:02:02:02:02,FW=bbbb,BUILT=1760000000,FEAT=-,CFG=00000001,ID=-\n"
+        );
+    }
+
+    #[test]
+    fn test_device_id_is_the_key_across_board_swaps() {
+        let id = "3f2a9c1e-0b7d-4e58-9a61-2c4f8d0e7b15";
+        let payload = format!("HASH:ab,VOLT:80,DEV_ID:{}", id.to_ascii_uppercase());
+        assert_eq!(device_id_from_hash_payload(payload.as_bytes()).as_deref(), Some(id));
+        assert_eq!(device_id_from_hash_payload(b"HASH:ab,DEV_ID:a/b"), None);
+        assert_eq!(device_id_from_hash_payload(b"HASH:ab,VOLT:80"), None);
+
+        let build = DeviceBuildInfo {
+            firmware: "aaaa".to_string(),
+            built_at: None,
+            features: None,
+            config_hash: None,
+        };
+        let mut directory = DeviceDirectory::new();
+        // 起動後最初のHASHでビルド情報が先に記録されても、デバイスIDの記録に引き継ぐ
+        directory.record([1; 6], build.clone());
+        assert_eq!(directory.record_identity([1; 6], id), None);
+        assert_eq!(directory.device_id(&[1; 6]), Some(id));
+        assert_eq!(directory.get(&[1; 6]), Some(&build));
+
+        // 基板を交換してMACアドレスが変わった
+        assert_eq!(directory.record_identity([2; 6], id), Some([1; 6]));
+        assert_eq!(directory.get(&[2; 6]), Some(&build));
+        assert_eq!(directory.get(&[1; 6]), None);
+        assert_eq!(
+            directory.response(),
+            format!("CMD_DEVICES:02:02:02:02:02:02,FW=aaaa,BUILT=-,FEAT=-,CFG=-,ID={}\n", id)
+        );
+    }
+}
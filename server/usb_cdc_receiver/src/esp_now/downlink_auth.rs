@@ -0,0 +1,179 @@
+//! ダウンリンク制御メッセージの認証（リプレイ対策）
+//!
+//! スリープコマンドには単調増加するカウンタとHMAC-SHA256タグを付加します。
+//! タグは宛先デバイスのMACアドレスも含めて計算するため、他のデバイス宛ての
+//! コマンドを流用することはできません。デバイスは最後に受理したカウンタを
+//! NVSに保持し、それ以下のカウンタを持つコマンドを拒否します。
+//!
+//! 鍵の更新（`key_rotation`）では、新しい鍵を現在の鍵から導出した鍵ストリームで
+//! 暗号化して配送します。
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+/// 認証鍵のバイト長
+pub const DOWNLINK_KEY_LEN: usize = 32;
+/// メッセージに付加するタグのバイト長（HMAC-SHA256の先頭）
+pub const DOWNLINK_TAG_LEN: usize = 8;
+/// 鍵の暗号化に使う鍵ストリームのラベル
+const KEY_WRAP_LABEL: &[u8] = b"KEYWRAP";
+/// カウンタをNVSへ予約する単位（書き込み回数を抑える）
+pub const COUNTER_RESERVATION_BLOCK: u32 = 64;
+
+/// ダウンリンク認証鍵
+#[derive(Clone, PartialEq, Eq)]
+pub struct DownlinkKey([u8; DOWNLINK_KEY_LEN]);
+
+impl DownlinkKey {
+    /// 16進文字列（64文字）から鍵を作成します
+    pub fn from_hex(hex_str: &str) -> Option<Self> {
+        let bytes = hex::decode(hex_str.trim()).ok()?;
+        Some(Self(bytes.try_into().ok()?))
+    }
+
+    /// 鍵のバイト列から作成します
+    pub fn from_bytes(bytes: [u8; DOWNLINK_KEY_LEN]) -> Self {
+        Self(bytes)
+    }
+
+    /// 鍵のバイト列（NVSへの保存用。ログに出さないこと）
+    pub fn to_bytes(&self) -> [u8; DOWNLINK_KEY_LEN] {
+        self.0
+    }
+
+    /// 新しい鍵をこの鍵で暗号化・復号します（同じ操作で元に戻る）
+    ///
+    /// 鍵ストリーム = HMAC-SHA256(この鍵, "KEYWRAP" || TARGET_MAC(6) || COUNTER(4, LE))。
+    /// カウンタはメッセージごとに異なるため、同じ鍵ストリームを使い回しません。
+    pub fn wrap_key(&self, target_mac: &[u8; 6], counter: u32, key: &[u8; DOWNLINK_KEY_LEN]) -> [u8; DOWNLINK_KEY_LEN] {
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(&self.0)
+            .expect("HMAC accepts keys of any length");
+        mac.update(KEY_WRAP_LABEL);
+        mac.update(target_mac);
+        mac.update(&counter.to_le_bytes());
+        let stream = mac.finalize().into_bytes();
+        let mut wrapped = *key;
+        for (byte, mask) in wrapped.iter_mut().zip(stream.iter()) {
+            *byte ^= mask;
+        }
+        wrapped
+    }
+
+    /// 宛先MACと本文に対するタグを計算します
+    pub fn tag(&self, target_mac: &[u8; 6], body: &[u8]) -> [u8; DOWNLINK_TAG_LEN] {
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(&self.0)
+            .expect("HMAC accepts keys of any length");
+        mac.update(target_mac);
+        mac.update(body);
+        let digest = mac.finalize().into_bytes();
+        let mut tag = [0u8; DOWNLINK_TAG_LEN];
+        tag.copy_from_slice(&digest[..DOWNLINK_TAG_LEN]);
+        tag
+    }
+
+    /// タグを検証します（定数時間比較）
+    pub fn verify(&self, target_mac: &[u8; 6], body: &[u8], tag: &[u8]) -> bool {
+        let expected = self.tag(target_mac, body);
+        tag.len() == DOWNLINK_TAG_LEN
+            && expected.iter().zip(tag).fold(0u8, |acc, (a, b)| acc | (a ^ b)) == 0
+    }
+}
+
+impl std::fmt::Debug for DownlinkKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // 鍵をログに出さない
+        f.write_str("DownlinkKey(..)")
+    }
+}
+
+/// ゲートウェイ側のコマンドカウンタ
+///
+/// 再起動後もカウンタが戻らないよう、`COUNTER_RESERVATION_BLOCK` 単位で
+/// 上限をNVSに予約してから払い出します。再起動時は予約済みの上限から再開します。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DownlinkCounter {
+    next: u32,
+    reserved_until: u32,
+}
+
+impl DownlinkCounter {
+    /// NVSに保存された予約上限から再開します（未保存は0）
+    pub fn resume(persisted_reserved_until: u32) -> Self {
+        Self {
+            next: persisted_reserved_until.saturating_add(1),
+            reserved_until: persisted_reserved_until,
+        }
+    }
+
+    /// 次のカウンタ値を払い出します
+    ///
+    /// # 戻り値
+    /// * `(counter, Some(new_reserved_until))` - 予約を更新した場合は新しい上限をNVSへ保存すること
+    /// * カウンタが上限に達した場合はNone（鍵の更新が必要）
+    pub fn allocate(&mut self) -> Option<(u32, Option<u32>)> {
+        if self.next == u32::MAX {
+            return None;
+        }
+        let counter = self.next;
+        let reservation = if counter > self.reserved_until {
+            self.reserved_until = counter.saturating_add(COUNTER_RESERVATION_BLOCK - 1);
+            Some(self.reserved_until)
+        } else {
+            None
+        };
+        self.next += 1;
+        Some((counter, reservation))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY_HEX: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+
+    #[test]
+    fn test_key_parse_and_tag_binding() {
+        assert!(DownlinkKey::from_hex("0011").is_none());
+        assert!(DownlinkKey::from_hex("zz").is_none());
+        let key = DownlinkKey::from_hex(KEY_HEX).unwrap();
+
+        let mac_a = [0x24, 0x0A, 0xC4, 0x00, 0x00, 0x01];
+        let mac_b = [0x24, 0x0A, 0xC4, 0x00, 0x00, 0x02];
+        let tag = key.tag(&mac_a, b"body");
+        assert!(key.verify(&mac_a, b"body", &tag));
+        assert!(!key.verify(&mac_b, b"body", &tag));
+        assert!(!key.verify(&mac_a, b"bodz", &tag));
+        assert!(!key.verify(&mac_a, b"body", &tag[..4]));
+    }
+
+    #[test]
+    fn test_wrap_key_round_trips_and_depends_on_counter() {
+        let key = DownlinkKey::from_hex(KEY_HEX).unwrap();
+        let mac = [0x24, 0x0A, 0xC4, 0x00, 0x00, 0x01];
+        let new_key = [0x5a; DOWNLINK_KEY_LEN];
+
+        let wrapped = key.wrap_key(&mac, 7, &new_key);
+        assert_ne!(wrapped, new_key);
+        assert_eq!(key.wrap_key(&mac, 7, &wrapped), new_key);
+        assert_ne!(key.wrap_key(&mac, 8, &new_key), wrapped);
+        assert_ne!(key.wrap_key(&[0; 6], 7, &new_key), wrapped);
+    }
+
+    #[test]
+    fn test_counter_reserves_blocks_and_resumes_past_reservation() {
+        let mut counter = DownlinkCounter::resume(0);
+        assert_eq!(counter.allocate(), Some((1, Some(COUNTER_RESERVATION_BLOCK))));
+        for expected in 2..=COUNTER_RESERVATION_BLOCK {
+            assert_eq!(counter.allocate(), Some((expected, None)));
+        }
+        let next_block = COUNTER_RESERVATION_BLOCK * 2;
+        assert_eq!(counter.allocate(), Some((COUNTER_RESERVATION_BLOCK + 1, Some(next_block))));
+
+        // 再起動後は予約済みの上限より大きい値から払い出す
+        let mut resumed = DownlinkCounter::resume(next_block);
+        assert_eq!(resumed.allocate().unwrap().0, next_block + 1);
+
+        assert_eq!(DownlinkCounter::resume(u32::MAX).allocate(), None);
+    }
+}
@@ -5,6 +5,7 @@ macro_rules! debug {
     ($($arg:tt)*) => {};
 }
 
+use super::wire::{WireDeserialize, WireSerialize};
 use super::FrameType;
 
 /// フレーム処理のための定数
@@ -18,12 +19,50 @@ pub const SEQUENCE_NUM_LEN: usize = 4;
 pub const DATA_LEN_FIELD_LEN: usize = 4;
 pub const CHECKSUM_LEN: usize = 4;
 
+crate::wire_struct! {
+    /// フレームヘッダ（ペイロードの直前までの固定部分）
+    pub struct FrameHeader {
+        pub start_marker: u32 => Be,
+        pub mac_address: [u8; 6] => Be,
+        pub frame_type: u8 => Be,
+        pub sequence_number: u32 => Le,
+        pub data_len: u32 => Le,
+    }
+}
+
+crate::wire_struct! {
+    /// フレームトレーラ（ペイロードの直後の固定部分）
+    pub struct FrameTrailer {
+        pub checksum: u32 => Le,
+        pub end_marker: u32 => Be,
+    }
+}
+
+/// ヘッダ長（ペイロード開始オフセット）
+pub const FRAME_HEADER_LEN: usize = FrameHeader::WIRE_SIZE;
+/// トレーラ長
+pub const FRAME_TRAILER_LEN: usize = FrameTrailer::WIRE_SIZE;
+/// ペイロード以外のオーバーヘッド
+pub const FRAME_OVERHEAD: usize = FRAME_HEADER_LEN + FRAME_TRAILER_LEN;
+
+const _: () = assert!(FRAME_HEADER_LEN == 19);
+const _: () = assert!(
+    FRAME_HEADER_LEN == MARKER_LEN + MAC_ADDRESS_LEN + FRAME_TYPE_LEN + SEQUENCE_NUM_LEN + DATA_LEN_FIELD_LEN
+);
+const _: () = assert!(FRAME_TRAILER_LEN == CHECKSUM_LEN + MARKER_LEN);
+const _: () = assert!(FRAME_OVERHEAD == 27);
+
+/// ペイロード長からチェックサムのオフセットを求めます
+pub const fn checksum_offset(data_len: usize) -> usize {
+    FRAME_HEADER_LEN + data_len
+}
+
 /// ESP-NOWフレームの構造
 /// 
 /// フレーム構造:
 /// - 開始マーカー (4バイト): 0xFACE_AABB
 /// - MACアドレス (6バイト): 送信元デバイスのMACアドレス
-/// - フレームタイプ (1バイト): 1=HASH, 2=DATA, 3=EOF
+/// - フレームタイプ (1バイト): 1=HASH, 2=DATA, 3=EOF, 4=FEC, 5=COMPLETE, 6=STATS, 7=ABORT
 /// - シーケンス番号 (4バイト): データの順序を保証するためのカウンター
 /// - データ長 (4バイト): ペイロードの長さ
 /// - データ本体 (可変長): 実際のペイロードデータ
@@ -75,33 +114,22 @@ impl Frame {
     /// - CHECKSUM: 4 bytes (little-endian)
     /// - END_MARKER: 4 bytes (big-endian: 0xCDEF5678)
     pub fn to_bytes(&self) -> Vec<u8> {
-        let start_marker_bytes = START_MARKER.to_be_bytes();
-        let end_marker_bytes = END_MARKER.to_be_bytes();
-        let data_len_bytes = (self.data.len() as u32).to_le_bytes(); // little-endian
-        let seq_bytes = self.sequence_number.to_le_bytes(); // little-endian
-        let checksum_bytes = calculate_checksum(&self.data).to_le_bytes(); // little-endian
-
-        // フレームの合計長を計算
-        let total_frame_len = MARKER_LEN + // 開始マーカー: 4バイト
-            MAC_ADDRESS_LEN +      // MACアドレス: 6バイト
-            FRAME_TYPE_LEN +       // フレームタイプ: 1バイト
-            SEQUENCE_NUM_LEN +     // シーケンス番号: 4バイト
-            DATA_LEN_FIELD_LEN +   // データ長: 4バイト
-            self.data.len() +     // 実データ: 可変長
-            CHECKSUM_LEN +         // チェックサム: 4バイト
-            MARKER_LEN;            // 終了マーカー: 4バイト
-
-        let mut framed_data = Vec::with_capacity(total_frame_len);
-
-        // フレームを構築
-        framed_data.extend_from_slice(&start_marker_bytes); // 開始マーカー (big-endian)
-        framed_data.extend_from_slice(&self.mac_address); // MACアドレス
-        framed_data.push(self.frame_type.to_byte()); // フレームタイプ
-        framed_data.extend_from_slice(&seq_bytes); // シーケンス番号 (little-endian)
-        framed_data.extend_from_slice(&data_len_bytes); // データ長 (little-endian)
-        framed_data.extend_from_slice(&self.data); // データ本体
-        framed_data.extend_from_slice(&checksum_bytes); // チェックサム (little-endian)
-        framed_data.extend_from_slice(&end_marker_bytes); // 終了マーカー (big-endian)
+        let header = FrameHeader {
+            start_marker: START_MARKER,
+            mac_address: self.mac_address,
+            frame_type: self.frame_type.to_byte(),
+            sequence_number: self.sequence_number,
+            data_len: self.data.len() as u32,
+        };
+        let trailer = FrameTrailer {
+            checksum: calculate_checksum(&self.data),
+            end_marker: END_MARKER,
+        };
+
+        let mut framed_data = Vec::with_capacity(FRAME_OVERHEAD + self.data.len());
+        header.write_wire(&mut framed_data);
+        framed_data.extend_from_slice(&self.data);
+        trailer.write_wire(&mut framed_data);
 
         framed_data
     }
@@ -112,125 +140,77 @@ impl Frame {
     /// * `Result<(Self, usize), FrameParseError>` - 解析に成功した場合はフレームと使用したバイト数のタプル、失敗した場合はエラー
     pub fn from_bytes(data: &[u8]) -> Result<(Self, usize), FrameParseError> {
         // 最小フレームサイズをチェック
-        const MIN_FRAME_SIZE: usize = MARKER_LEN + MAC_ADDRESS_LEN + FRAME_TYPE_LEN + SEQUENCE_NUM_LEN + DATA_LEN_FIELD_LEN + CHECKSUM_LEN + MARKER_LEN;
-        if data.len() < MIN_FRAME_SIZE {
+        if data.len() < FRAME_OVERHEAD {
             debug!("Frame data too short: {} bytes", data.len());
             return Err(FrameParseError::TooShort);
         }
+        let header = FrameHeader::read_wire(data).map_err(|_| FrameParseError::TooShort)?;
 
         // 開始マーカーのチェック
-        let mut offset = 0;
-        let start_marker = u32::from_be_bytes([data[0], data[1], data[2], data[3]]);
-        if start_marker != START_MARKER {
-            debug!("Invalid start marker: {:08x}", start_marker);
-            return Err(FrameParseError::InvalidStartMarker(start_marker));
+        if header.start_marker != START_MARKER {
+            debug!("Invalid start marker: {:08x}", header.start_marker);
+            return Err(FrameParseError::InvalidStartMarker(header.start_marker));
         }
-        offset += MARKER_LEN;
-
-        // MACアドレスの抽出
-        let mut mac_address = [0u8; 6];
-        mac_address.copy_from_slice(&data[offset..offset + MAC_ADDRESS_LEN]);
-        offset += MAC_ADDRESS_LEN;
 
         // フレームタイプの解析
-        let frame_type_byte = data[offset];
-        let frame_type = match FrameType::from_byte(frame_type_byte) {
+        let frame_type = match FrameType::from_byte(header.frame_type) {
             Some(frame_type) => frame_type,
             None => {
-                debug!("Invalid frame type: {}", frame_type_byte);
-                return Err(FrameParseError::InvalidFrameType(frame_type_byte));
+                debug!("Invalid frame type: {}", header.frame_type);
+                return Err(FrameParseError::InvalidFrameType(header.frame_type));
             }
         };
-        offset += FRAME_TYPE_LEN;
-
-        // シーケンス番号の解析 (little-endian)
-        let sequence_number = u32::from_le_bytes([
-            data[offset],
-            data[offset + 1],
-            data[offset + 2],
-            data[offset + 3],
-        ]);
-        offset += SEQUENCE_NUM_LEN;
-
-        // データ長の解析 (little-endian)
-        let data_len = u32::from_le_bytes([
-            data[offset],
-            data[offset + 1],
-            data[offset + 2],
-            data[offset + 3],
-        ]) as usize;
-        offset += DATA_LEN_FIELD_LEN;
 
         // データ長のバリデーション (オーバーフローチェック付き)
-        // required_len = offset + data_len + CHECKSUM_LEN + MARKER_LEN
-        let required_len = offset
-            .checked_add(data_len)
-            .and_then(|l| l.checked_add(CHECKSUM_LEN))
-            .and_then(|l| l.checked_add(MARKER_LEN));
-
+        let data_len = header.data_len as usize;
+        let required_len = FRAME_OVERHEAD.checked_add(data_len);
         if required_len.map_or(true, |len| len > data.len()) {
             debug!(
                 "Data length exceeds buffer: offset={}, data_len={}, buffer={}",
-                offset,
+                FRAME_HEADER_LEN,
                 data_len,
                 data.len()
             );
             return Err(FrameParseError::DataLengthExceedsBuffer {
-                offset,
+                offset: FRAME_HEADER_LEN,
                 data_len,
                 buffer_len: data.len(),
             });
         }
 
         // データのスライス取得（チェックサム検証前の不要なアロケーションを避けるため）
-        let payload_slice = &data[offset..offset + data_len];
-        offset += data_len;
-
-        // チェックサムの検証 (little-endian)
-        let expected_checksum = u32::from_le_bytes([
-            data[offset],
-            data[offset + 1],
-            data[offset + 2],
-            data[offset + 3],
-        ]);
+        let payload_slice = &data[FRAME_HEADER_LEN..checksum_offset(data_len)];
+        let trailer = FrameTrailer::read_wire(&data[checksum_offset(data_len)..])
+            .map_err(|_| FrameParseError::TooShort)?;
+
+        // チェックサムの検証
         let actual_checksum = calculate_checksum(payload_slice);
-        if expected_checksum != actual_checksum {
+        if trailer.checksum != actual_checksum {
             debug!(
                 "Checksum mismatch: expected={:08x}, actual={:08x}",
-                expected_checksum, actual_checksum
+                trailer.checksum, actual_checksum
             );
             return Err(FrameParseError::InvalidChecksum {
-                expected: expected_checksum,
+                expected: trailer.checksum,
                 actual: actual_checksum,
             });
         }
-        offset += CHECKSUM_LEN;
-
-        // チェックサム検証成功後にベクタを作成
-        let payload_data = payload_slice.to_vec();
 
         // 終了マーカーの検証
-        let end_marker = u32::from_be_bytes([
-            data[offset],
-            data[offset + 1],
-            data[offset + 2],
-            data[offset + 3],
-        ]);
-        if end_marker != END_MARKER {
-            debug!("Invalid end marker: {:08x}", end_marker);
-            return Err(FrameParseError::InvalidEndMarker(end_marker));
+        if trailer.end_marker != END_MARKER {
+            debug!("Invalid end marker: {:08x}", trailer.end_marker);
+            return Err(FrameParseError::InvalidEndMarker(trailer.end_marker));
         }
-        offset += MARKER_LEN;
 
-        // フレームオブジェクトの作成
+        // チェックサム検証成功後にベクタを作成
         let frame = Frame {
-            mac_address,
+            mac_address: header.mac_address,
             frame_type,
-            sequence_number,
-            data: payload_data,
+            sequence_number: header.sequence_number,
+            data: payload_slice.to_vec(),
         };
 
-        Ok((frame, offset))
+        Ok((frame, FRAME_OVERHEAD + data_len))
     }
 
     /// MACアドレスを取得
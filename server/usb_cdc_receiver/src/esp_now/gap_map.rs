@@ -0,0 +1,176 @@
+//! 転送中に受信できなかったシーケンス番号の範囲（ギャップマップ）
+//!
+//! デバイスが自前でフレーム化したDATA/FECフレームは転送内で連続したシーケンス番号を持つため、
+//! 番号の飛びを欠落として記録します。遅れて届いた番号は欠落から取り除きます。
+//! 範囲数が上限を超えた場合は最後の範囲を広げ、欠落を少なく見積もらないようにします。
+
+/// 保持する欠落範囲の上限（完了イベントのペイロード長を抑えるため）
+pub const MAX_GAP_RANGES: usize = 16;
+
+/// 欠落範囲の区切り文字（`GAPS:10-12|40-40`）
+const RANGE_SEPARATOR: char = '|';
+
+/// 転送内の欠落範囲
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GapMap {
+    /// 最初に受信したシーケンス番号（未受信ならNone）
+    first: Option<u32>,
+    /// 次に期待するシーケンス番号（未受信ならNone）
+    next_expected: Option<u32>,
+    /// 欠落範囲（開始, 終了）。両端を含み昇順
+    ranges: Vec<(u32, u32)>,
+}
+
+impl GapMap {
+    /// 受信したシーケンス番号を記録します
+    pub fn observe(&mut self, sequence: u32) {
+        match self.next_expected {
+            None => {
+                self.first = Some(sequence);
+                self.next_expected = Some(sequence.wrapping_add(1));
+            }
+            Some(next) if sequence == next => self.next_expected = Some(next.wrapping_add(1)),
+            Some(next) if sequence > next => {
+                self.push_range(next, sequence - 1);
+                self.next_expected = Some(sequence.wrapping_add(1));
+            }
+            Some(_) => self.fill(sequence),
+        }
+    }
+
+    /// 転送の終端（HASH/EOFのシーケンス番号）までに届かなかった番号を欠落として記録します
+    pub fn close_before(&mut self, sequence: u32) {
+        if let Some(next) = self.next_expected {
+            if sequence > next {
+                self.push_range(next, sequence - 1);
+                self.next_expected = Some(sequence);
+            }
+        }
+    }
+
+    /// 欠落がないかどうか
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    /// 欠落範囲
+    pub fn ranges(&self) -> &[(u32, u32)] {
+        &self.ranges
+    }
+
+    /// 欠落したシーケンス番号の数
+    pub fn missing_count(&self) -> u32 {
+        self.ranges.iter().map(|(start, end)| end - start + 1).sum()
+    }
+
+    /// 最初に受信した番号から転送の終端までのうち、欠落した番号の割合（%、未受信ならNone）
+    ///
+    /// デバイスは次の転送のFECパラメータをこの値から決めます。
+    pub fn loss_percent(&self) -> Option<u8> {
+        let span = self.next_expected?.wrapping_sub(self.first?);
+        if span == 0 {
+            return None;
+        }
+        Some((u64::from(self.missing_count()) * 100 / u64::from(span)).min(100) as u8)
+    }
+
+    /// 完了イベント用の表現（`10-12|40-40`）
+    pub fn to_field(&self) -> String {
+        self.ranges
+            .iter()
+            .map(|(start, end)| format!("{}-{}", start, end))
+            .collect::<Vec<_>>()
+            .join(&RANGE_SEPARATOR.to_string())
+    }
+
+    fn push_range(&mut self, start: u32, end: u32) {
+        if self.ranges.len() < MAX_GAP_RANGES {
+            self.ranges.push((start, end));
+        } else if let Some(last) = self.ranges.last_mut() {
+            last.1 = end;
+        }
+    }
+
+    /// 遅れて届いた番号を欠落範囲から取り除きます
+    fn fill(&mut self, sequence: u32) {
+        let Some(index) = self
+            .ranges
+            .iter()
+            .position(|&(start, end)| (start..=end).contains(&sequence))
+        else {
+            return;
+        };
+        let (start, end) = self.ranges[index];
+        match (sequence == start, sequence == end) {
+            (true, true) => {
+                self.ranges.remove(index);
+            }
+            (true, false) => self.ranges[index].0 = start + 1,
+            (false, true) => self.ranges[index].1 = end - 1,
+            (false, false) => {
+                self.ranges[index].1 = sequence - 1;
+                self.ranges.insert(index + 1, (sequence + 1, end));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gap_map(sequences: &[u32]) -> GapMap {
+        let mut map = GapMap::default();
+        for &sequence in sequences {
+            map.observe(sequence);
+        }
+        map
+    }
+
+    #[test]
+    fn test_records_skipped_sequences() {
+        let map = gap_map(&[10, 11, 14, 15, 20]);
+        assert_eq!(map.ranges(), &[(12, 13), (16, 19)]);
+        assert_eq!(map.missing_count(), 6);
+        assert_eq!(map.to_field(), "12-13|16-19");
+        assert_eq!(map.loss_percent(), Some(54));
+        assert!(gap_map(&[1, 2, 3]).is_empty());
+        assert_eq!(gap_map(&[1, 2, 3]).loss_percent(), Some(0));
+        assert_eq!(GapMap::default().loss_percent(), None);
+    }
+
+    #[test]
+    fn test_late_sequences_fill_gaps() {
+        let mut map = gap_map(&[0, 5]);
+        map.observe(2);
+        assert_eq!(map.ranges(), &[(1, 1), (3, 4)]);
+        map.observe(1);
+        map.observe(3);
+        map.observe(4);
+        assert!(map.is_empty());
+        // 重複は無視
+        map.observe(0);
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn test_close_before_records_trailing_loss() {
+        let mut map = gap_map(&[0, 1, 2]);
+        map.close_before(6);
+        assert_eq!(map.ranges(), &[(3, 5)]);
+        assert_eq!(map.loss_percent(), Some(50));
+        // データ未受信なら何もしない
+        let mut empty = GapMap::default();
+        empty.close_before(6);
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn test_overflowing_ranges_extend_last_range() {
+        let sequences: Vec<u32> = (0..=MAX_GAP_RANGES as u32 + 2).map(|i| i * 2).collect();
+        let map = gap_map(&sequences);
+        assert_eq!(map.ranges().len(), MAX_GAP_RANGES);
+        let (_, last_end) = *map.ranges().last().unwrap();
+        assert_eq!(last_end, *sequences.last().unwrap() - 1);
+    }
+}
@@ -0,0 +1,173 @@
+//! 旧形式（プロトコルv0）デバイスの互換シム
+//!
+//! 再書き込み前のm5stackは自前でフレーム化せず、`HASH:...` テキスト、生の画像チャンク、`EOF!` を
+//! そのまま送信します。シーケンス番号も転送の区切りも持たないため、ゲートウェイで
+//! HASHごとに転送番号を割り当て、到着順にチャンク番号を振った合成フレームに変換します。
+//! 以降の集約・統計・USB送出は新形式のフレームと同じ経路で処理されます。
+//!
+//! シーケンス番号は新形式のデバイスと同様に送信元ごとの通し番号です（HASH/EOFでもリセットしない）。
+//! 到着順に番号を振るため、旧形式の転送ではギャップマップで欠落を検出できません。
+use std::collections::HashMap;
+
+use super::frame::{detect_frame_type, Frame};
+use super::FrameType;
+
+/// 合成したフレーム
+pub struct LegacyFrame {
+    /// 合成したフレーム（シーケンス番号はゲートウェイが採番）
+    pub frame: Frame,
+    /// 送信元ごとの転送番号（HASHまたはEOF後の最初のチャンクで採番）
+    pub frame_id: u32,
+    /// 転送内のチャンク番号（到着順、DATA以外はNone）
+    pub chunk_index: Option<u32>,
+    /// この送信元から最初に受信した旧形式ペイロードかどうか
+    pub first_seen: bool,
+}
+
+/// 送信元ごとの変換状態
+#[derive(Debug, Default)]
+struct LegacySender {
+    next_sequence: u32,
+    frame_id: u32,
+    next_chunk_index: u32,
+    hash_payload: Option<Vec<u8>>,
+    /// 現在の転送でEOFを受信済み（次のHASH/DATAは新しい転送）
+    ended: bool,
+    /// 転送番号を割り当て済みか
+    started: bool,
+}
+
+impl LegacySender {
+    fn next_sequence(&mut self) -> u32 {
+        let sequence = self.next_sequence;
+        self.next_sequence = sequence.wrapping_add(1);
+        sequence
+    }
+
+    fn start_transfer(&mut self) {
+        if self.started {
+            self.frame_id = self.frame_id.wrapping_add(1);
+        }
+        self.started = true;
+        self.next_chunk_index = 0;
+        self.hash_payload = None;
+        self.ended = false;
+    }
+}
+
+/// 旧形式ペイロードを合成フレームに変換するシム
+#[derive(Debug, Default)]
+pub struct LegacyShim {
+    senders: HashMap<[u8; 6], LegacySender>,
+}
+
+impl LegacyShim {
+    /// 新しいシムを作成します
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 旧形式ペイロード（`HASH:...`、生チャンク、`EOF!`）を合成フレームに変換します
+    pub fn convert(&mut self, mac: [u8; 6], payload: &[u8]) -> LegacyFrame {
+        let first_seen = !self.senders.contains_key(&mac);
+        let sender = self.senders.entry(mac).or_default();
+        let frame_type = detect_frame_type(payload);
+
+        let chunk_index = match frame_type {
+            FrameType::Hash => {
+                // 同一内容のHASHは再送として同じ転送に含める
+                if sender.ended || sender.hash_payload.as_deref() != Some(payload) {
+                    sender.start_transfer();
+                }
+                sender.hash_payload = Some(payload.to_vec());
+                None
+            }
+            FrameType::Eof => {
+                if !sender.started {
+                    sender.start_transfer();
+                }
+                sender.ended = true;
+                None
+            }
+            _ => {
+                if sender.ended || !sender.started {
+                    sender.start_transfer();
+                }
+                let index = sender.next_chunk_index;
+                sender.next_chunk_index = index.wrapping_add(1);
+                Some(index)
+            }
+        };
+
+        let sequence = sender.next_sequence();
+        LegacyFrame {
+            frame: Frame::new(mac, frame_type, sequence, payload.to_vec()),
+            frame_id: sender.frame_id,
+            chunk_index,
+            first_seen,
+        }
+    }
+
+    /// 旧形式で送信してきた送信元の数
+    pub fn device_count(&self) -> usize {
+        self.senders.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAC: [u8; 6] = [0x11, 0x22, 0x33, 0x44, 0x55, 0x66];
+
+    fn convert_all(shim: &mut LegacyShim, payloads: &[&[u8]]) -> Vec<LegacyFrame> {
+        payloads.iter().map(|payload| shim.convert(MAC, payload)).collect()
+    }
+
+    #[test]
+    fn test_assigns_frame_ids_and_chunk_indexes() {
+        let mut shim = LegacyShim::new();
+        let frames = convert_all(
+            &mut shim,
+            &[b"HASH:aaaa,VOLT:80", b"\xff\xd8", b"\x01\x02", b"EOF!", b"EOF!", b"HASH:bbbb,VOLT:79", b"\xff\xd8"],
+        );
+
+        let types: Vec<FrameType> = frames.iter().map(|f| f.frame.frame_type()).collect();
+        assert_eq!(
+            types,
+            [FrameType::Hash, FrameType::Data, FrameType::Data, FrameType::Eof, FrameType::Eof, FrameType::Hash, FrameType::Data]
+        );
+        let frame_ids: Vec<u32> = frames.iter().map(|f| f.frame_id).collect();
+        assert_eq!(frame_ids, [0, 0, 0, 0, 0, 1, 1]);
+        let chunks: Vec<Option<u32>> = frames.iter().map(|f| f.chunk_index).collect();
+        assert_eq!(chunks, [None, Some(0), Some(1), None, None, None, Some(0)]);
+        // シーケンス番号はHASH/EOFでもリセットしない通し番号
+        let sequences: Vec<u32> = frames.iter().map(|f| f.frame.sequence_number()).collect();
+        assert_eq!(sequences, [0, 1, 2, 3, 4, 5, 6]);
+        assert!(frames[0].first_seen);
+        assert!(!frames[1].first_seen);
+    }
+
+    #[test]
+    fn test_duplicate_hash_stays_in_transfer() {
+        let mut shim = LegacyShim::new();
+        let frames = convert_all(&mut shim, &[b"HASH:aaaa", b"HASH:aaaa", b"\x01", b"HASH:aaaa"]);
+        assert!(frames.iter().all(|f| f.frame_id == 0));
+        assert_eq!(frames[2].chunk_index, Some(0));
+    }
+
+    #[test]
+    fn test_data_without_hash_starts_transfer() {
+        let mut shim = LegacyShim::new();
+        let frames = convert_all(&mut shim, &[b"\x01", b"EOF!", b"\x02", b"\x03"]);
+        let frame_ids: Vec<u32> = frames.iter().map(|f| f.frame_id).collect();
+        assert_eq!(frame_ids, [0, 0, 1, 1]);
+        assert_eq!(frames[3].chunk_index, Some(1));
+
+        // 送信元ごとに独立
+        let other = shim.convert([0xaa; 6], b"\x01");
+        assert_eq!((other.frame_id, other.chunk_index, other.frame.sequence_number()), (0, Some(0), 0));
+        assert!(other.first_seen);
+        assert_eq!(shim.device_count(), 2);
+    }
+}
@@ -0,0 +1,147 @@
+//! デバイスのライフサイクル情報（起動理由・復帰要因・前回の稼働時間）
+//!
+//! デバイスは起動後最初のHASHフレームに `BOOT`/`WAKE`/`PREV_UP_S`/`BOOT_LOG` を付加します。
+//! 前回の稼働中にパニックが発生していれば `PANIC_MSG`/`PANIC_AT` も付加されます。
+//! ゲートウェイは完了イベント送出時にこれを解析し、パニック・ウォッチドッグ・電圧低下による
+//! 再起動を送信元ごとに統計フレームの `RESETS` 項目として報告します。
+use std::collections::BTreeMap;
+
+use crate::mac_address::format_mac_address;
+
+/// 異常による再起動とみなす起動理由
+const ABNORMAL_BOOT_REASONS: [&str; 5] = ["PANIC", "INT_WDT", "TASK_WDT", "WDT", "BROWNOUT"];
+
+/// HASHペイロードから解析したライフサイクル情報
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceLifecycle {
+    /// 起動理由（`PANIC`、`DEEPSLEEP` など）
+    pub boot_reason: String,
+    /// Deep sleep からの復帰要因
+    pub wake_cause: Option<String>,
+    /// 前回起動時の稼働時間（秒）
+    pub previous_uptime_s: Option<u32>,
+    /// 直近の起動理由（古い順）
+    pub boot_log: Vec<String>,
+    /// 前回の稼働中に発生したパニックのメッセージと発生箇所
+    pub panic: Option<(String, String)>,
+}
+
+impl DeviceLifecycle {
+    /// HASHペイロードから解析します（`BOOT` がなければNone）
+    pub fn from_hash_payload(payload: &[u8]) -> Option<Self> {
+        let payload = std::str::from_utf8(payload).ok()?;
+        let field = |key: &str| {
+            payload
+                .split(',')
+                .find_map(|item| item.strip_prefix(key)?.strip_prefix(':'))
+                .map(str::trim)
+        };
+        Some(Self {
+            boot_reason: field("BOOT")?.to_string(),
+            wake_cause: field("WAKE").map(str::to_string),
+            previous_uptime_s: field("PREV_UP_S").and_then(|value| value.parse().ok()),
+            boot_log: field("BOOT_LOG")
+                .map(|value| value.split('|').filter(|item| !item.is_empty()).map(str::to_string).collect())
+                .unwrap_or_default(),
+            panic: field("PANIC_MSG")
+                .map(|message| (message.to_string(), field("PANIC_AT").unwrap_or("-").to_string())),
+        })
+    }
+
+    /// 異常による再起動かどうか
+    pub fn is_abnormal(&self) -> bool {
+        ABNORMAL_BOOT_REASONS.contains(&self.boot_reason.as_str())
+    }
+}
+
+/// 統計フレームの送出間隔内に発生した異常再起動
+#[derive(Debug, Default)]
+pub struct ResetLog {
+    resets: BTreeMap<[u8; 6], Vec<String>>,
+}
+
+impl ResetLog {
+    /// 空のログを作成します
+    pub const fn new() -> Self {
+        Self { resets: BTreeMap::new() }
+    }
+
+    /// 異常再起動であれば記録し、記録した場合は true を返します
+    pub fn record(&mut self, mac: [u8; 6], lifecycle: &DeviceLifecycle) -> bool {
+        if !lifecycle.is_abnormal() {
+            return false;
+        }
+        self.resets.entry(mac).or_default().push(lifecycle.boot_reason.clone());
+        true
+    }
+
+    /// 統計フレームの `RESETS` 項目（`aa:bb:..=PANIC+BROWNOUT|..`、記録がなければNone）
+    pub fn stats_field(&self) -> Option<String> {
+        if self.resets.is_empty() {
+            return None;
+        }
+        let devices: Vec<String> = self
+            .resets
+            .iter()
+            .map(|(mac, reasons)| format!("{}={}", format_mac_address(mac), reasons.join("+")))
+            .collect();
+        Some(devices.join("|"))
+    }
+
+    /// 記録を消去します
+    pub fn clear(&mut self) {
+        self.resets.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_lifecycle_fields() {
+        let payload = b"HASH:ab,VOLT:80,TRACE:01,BOOT:PANIC,WAKE:NONE,PREV_UP_S:37,BOOT_LOG:POWERON|PANIC";
+        let lifecycle = DeviceLifecycle::from_hash_payload(payload).unwrap();
+        assert_eq!(lifecycle.boot_reason, "PANIC");
+        assert_eq!(lifecycle.wake_cause.as_deref(), Some("NONE"));
+        assert_eq!(lifecycle.previous_uptime_s, Some(37));
+        assert_eq!(lifecycle.boot_log, ["POWERON", "PANIC"]);
+        assert_eq!(lifecycle.panic, None);
+        assert!(lifecycle.is_abnormal());
+
+        let payload = b"HASH:ab,BOOT:PANIC,WAKE:NONE,PANIC_MSG:index out of bounds,PANIC_AT:src/main.rs:42";
+        let lifecycle = DeviceLifecycle::from_hash_payload(payload).unwrap();
+        assert_eq!(
+            lifecycle.panic,
+            Some(("index out of bounds".to_string(), "src/main.rs:42".to_string()))
+        );
+
+        // BOOT_LOG の接頭辞に惑わされない
+        assert_eq!(DeviceLifecycle::from_hash_payload(b"HASH:ab,BOOT_LOG:PANIC"), None);
+        assert_eq!(DeviceLifecycle::from_hash_payload(b"HASH:ab,VOLT:80"), None);
+    }
+
+    #[test]
+    fn test_reset_log_reports_abnormal_resets_only() {
+        let mut log = ResetLog::new();
+        let boot = |reason: &str| DeviceLifecycle {
+            boot_reason: reason.to_string(),
+            wake_cause: None,
+            previous_uptime_s: None,
+            boot_log: Vec::new(),
+            panic: None,
+        };
+        assert!(!log.record([1; 6], &boot("DEEPSLEEP")));
+        assert_eq!(log.stats_field(), None);
+
+        assert!(log.record([1; 6], &boot("PANIC")));
+        assert!(log.record([1; 6], &boot("BROWNOUT")));
+        assert!(log.record([2; 6], &boot("TASK_WDT")));
+        assert_eq!(
+            log.stats_field().as_deref(),
+            Some("01:01:01:01:01:01=PANIC+BROWNOUT|02:02:02:02:02:02=TASK_WDT")
+        );
+        log.clear();
+        assert_eq!(log.stats_field(), None);
+    }
+}
@@ -4,6 +4,217 @@
 
 use log::{debug, warn};
 
+use super::channel_hop::HopAnnouncement;
+use super::chunk_size::{ChunkSizeAdvert, CHUNK_SIZE_ADVERT_LEN};
+use super::downlink_auth::{DownlinkKey, DOWNLINK_KEY_LEN, DOWNLINK_TAG_LEN};
+use super::radio::{RadioSettings, RADIO_SETTINGS_LEN};
+use super::wire::{WireDeserialize, WireSerialize};
+
+crate::wire_struct! {
+    /// ACKメッセージのワイヤ表現
+    struct AckWire {
+        message_type: u8 => Le,
+        sequence_number: u32 => Le,
+        acked_message_type: u8 => Le,
+        status: u8 => Le,
+    }
+}
+
+crate::wire_struct! {
+    /// スリープコマンドのワイヤ表現
+    struct SleepCommandWire {
+        message_type: u8 => Le,
+        sleep_seconds: u32 => Le,
+    }
+}
+
+crate::wire_struct! {
+    /// 認証付きスリープコマンドのワイヤ表現（タグは本文の後ろに付加）
+    struct SignedSleepCommandWire {
+        message_type: u8 => Le,
+        counter: u32 => Le,
+        sleep_seconds: u32 => Le,
+    }
+}
+
+crate::wire_struct! {
+    /// 一斉配信設定のヘッダのワイヤ表現（設定文字列とタグはヘッダの後ろに付加）
+    struct BroadcastConfigWire {
+        message_type: u8 => Le,
+        config_id: u32 => Le,
+        config_len: u8 => Le,
+    }
+}
+
+crate::wire_struct! {
+    /// 設定更新のヘッダのワイヤ表現（設定文字列とタグはヘッダの後ろに付加）
+    struct ConfigUpdateWire {
+        message_type: u8 => Le,
+        counter: u32 => Le,
+        config_len: u8 => Le,
+    }
+}
+
+crate::wire_struct! {
+    /// ファイルチャンクのヘッダのワイヤ表現（スロット名・データ・タグはヘッダの後ろに付加）
+    struct FileChunkWire {
+        message_type: u8 => Le,
+        counter: u32 => Le,
+        file_crc: u32 => Le,
+        total_len: u16 => Le,
+        offset: u16 => Le,
+        slot_len: u8 => Le,
+        data_len: u8 => Le,
+    }
+}
+
+crate::wire_struct! {
+    /// 疎通確認Pingのワイヤ表現
+    struct PingWire {
+        message_type: u8 => Le,
+        magic: [u8; 4] => Le,
+        nonce: u32 => Le,
+    }
+}
+
+crate::wire_struct! {
+    /// 疎通確認Pongのワイヤ表現
+    struct PongWire {
+        message_type: u8 => Le,
+        magic: [u8; 4] => Le,
+        nonce: u32 => Le,
+        queue_free_percent: u8 => Le,
+    }
+}
+
+crate::wire_struct! {
+    /// 転送の延期要求のワイヤ表現
+    struct DeferWire {
+        message_type: u8 => Le,
+        magic: [u8; 4] => Le,
+        nonce: u32 => Le,
+        retry_after_ms: u32 => Le,
+    }
+}
+
+crate::wire_struct! {
+    /// HASHフレームへの応答のワイヤ表現
+    struct HashAckWire {
+        message_type: u8 => Le,
+        magic: [u8; 4] => Le,
+        commands_pending: u8 => Le,
+        pending_count: u8 => Le,
+    }
+}
+
+crate::wire_struct! {
+    /// 制御フレームのヘッダのワイヤ表現（引数とCRCはヘッダの後ろに付加）
+    struct ControlFrameWire {
+        message_type: u8 => Le,
+        magic: [u8; 4] => Le,
+        command_id: u8 => Le,
+        args_len: u8 => Le,
+    }
+}
+
+crate::wire_struct! {
+    /// 鍵更新の本文のワイヤ表現（暗号化した鍵とタグは本文の後ろに付加）
+    struct KeyRotationWire {
+        message_type: u8 => Le,
+        counter: u32 => Le,
+        epoch: u32 => Le,
+        activate_counter: u32 => Le,
+    }
+}
+
+/// Pingの識別子（生のDATAチャンクとの誤認を防ぐ）
+const PING_MAGIC: [u8; 4] = *b"PING";
+/// Pongの識別子
+const PONG_MAGIC: [u8; 4] = *b"PONG";
+/// 延期要求の識別子
+const DEFER_MAGIC: [u8; 4] = *b"BUSY";
+/// HASHフレームへの応答の識別子
+const HASH_ACK_MAGIC: [u8; 4] = *b"HACK";
+/// 制御フレームの識別子
+const CONTROL_MAGIC: [u8; 4] = *b"CTRL";
+
+/// ACKメッセージのバイト長
+pub const ACK_MESSAGE_LEN: usize = AckWire::WIRE_SIZE;
+/// スリープコマンドのバイト長
+pub const SLEEP_COMMAND_LEN: usize = SleepCommandWire::WIRE_SIZE;
+/// 認証付きスリープコマンドのバイト長（本文 + タグ）
+pub const SIGNED_SLEEP_COMMAND_LEN: usize = SignedSleepCommandWire::WIRE_SIZE + DOWNLINK_TAG_LEN;
+
+/// ESP-NOWのブロードキャストアドレス（一斉配信設定の署名にも使用）
+pub const BROADCAST_MAC: [u8; 6] = [0xFF; 6];
+/// 設定文字列（一斉配信・デバイスごとの設定更新）の最大長
+pub const MAX_CONFIG_TEXT_LEN: usize = 64;
+
+/// 鍵更新のバイト長（本文 + 暗号化した鍵 + タグ）
+pub const KEY_ROTATION_LEN: usize = KeyRotationWire::WIRE_SIZE + DOWNLINK_KEY_LEN + DOWNLINK_TAG_LEN;
+
+/// ファイルチャンクのヘッダのバイト長
+pub const FILE_CHUNK_HEADER_LEN: usize = FileChunkWire::WIRE_SIZE;
+/// ファイルチャンクのスロット名の最大長（デバイスのNVSキーに収まる長さ）
+pub const MAX_FILE_SLOT_LEN: usize = 12;
+/// ファイルチャンク1個のデータの最大長（ヘッダ・スロット名・タグと合わせて250バイト以内）
+pub const MAX_FILE_CHUNK_DATA_LEN: usize = 192;
+
+/// Pingメッセージのバイト長
+pub const PING_MESSAGE_LEN: usize = PingWire::WIRE_SIZE;
+/// Pongメッセージのバイト長
+pub const PONG_MESSAGE_LEN: usize = PongWire::WIRE_SIZE;
+/// 無線設定を付加したPingメッセージのバイト長
+pub const PING_WITH_RADIO_LEN: usize = PING_MESSAGE_LEN + RADIO_SETTINGS_LEN;
+/// 無線設定を付加したPongメッセージのバイト長
+pub const PONG_WITH_RADIO_LEN: usize = PONG_MESSAGE_LEN + RADIO_SETTINGS_LEN;
+/// 延期要求のバイト長
+pub const DEFER_MESSAGE_LEN: usize = DeferWire::WIRE_SIZE;
+/// HASHフレームへの応答のバイト長
+pub const HASH_ACK_MESSAGE_LEN: usize = HashAckWire::WIRE_SIZE;
+/// 前回の転送の損失率を付加したHASHフレームへの応答のバイト長
+pub const HASH_ACK_WITH_LOSS_LEN: usize = HASH_ACK_MESSAGE_LEN + 1;
+/// ゲートウェイの時計を付加したHASHフレームへの応答のバイト長（損失率の後ろに8バイト）
+pub const HASH_ACK_WITH_CLOCK_LEN: usize = HASH_ACK_WITH_LOSS_LEN + 8;
+/// HASHフレームへの応答で損失率が未観測であることを示す値（時計を付加する場合のみ使う）
+const LOSS_UNKNOWN: u8 = u8::MAX;
+/// 制御フレームのヘッダのバイト長
+pub const CONTROL_FRAME_HEADER_LEN: usize = ControlFrameWire::WIRE_SIZE;
+/// 制御フレームの末尾のCRC-32のバイト長
+pub const CONTROL_FRAME_CRC_LEN: usize = 4;
+/// 制御フレームの引数の最大長
+pub const MAX_CONTROL_ARGS_LEN: usize = 32;
+/// Ping/Pongの末尾に付加する機能フラグのバイト長
+pub const CAPABILITY_FLAGS_LEN: usize = 1;
+
+/// 機能フラグ: プライバシーモード（詰め物とダミーフレームをゲートウェイが除去する）
+pub const CAPABILITY_PRIVACY: u8 = 0x01;
+/// 機能フラグ: 受信チャンネルの予定（チャンネルホッピング）に従う。Pongでは予定を付加したことを示す
+pub const CAPABILITY_CHANNEL_HOP: u8 = 0x02;
+/// 機能フラグ: HASHフレームへの応答（ダウンリンクのコマンドの有無）を受け取り、コマンド待機を短縮できる
+pub const CAPABILITY_COMMAND_WINDOW: u8 = 0x04;
+/// 機能フラグ: ゲートウェイが通知したDATAチャンクの大きさで送信できる。Pongでは通知を付加したことを示す
+pub const CAPABILITY_CHUNK_SIZE: u8 = 0x08;
+/// 機能フラグ: HASHフレームへの応答に付加した前回の転送の損失率を受け取れる
+pub const CAPABILITY_LOSS_REPORT: u8 = 0x10;
+/// 機能フラグ: HASHフレームへの応答に付加したゲートウェイの時計でスリープ中のRTCドリフトを計測できる
+pub const CAPABILITY_GATEWAY_CLOCK: u8 = 0x20;
+
+const _: () = assert!(ACK_MESSAGE_LEN == 7);
+const _: () = assert!(SLEEP_COMMAND_LEN == 5);
+const _: () = assert!(SIGNED_SLEEP_COMMAND_LEN == 17);
+const _: () = assert!(PING_MESSAGE_LEN == 9);
+const _: () = assert!(PONG_MESSAGE_LEN == 10);
+const _: () = assert!(PING_WITH_RADIO_LEN == 12);
+const _: () = assert!(PONG_WITH_RADIO_LEN == 13);
+const _: () = assert!(DEFER_MESSAGE_LEN == 13);
+const _: () = assert!(HASH_ACK_MESSAGE_LEN == 7);
+const _: () = assert!(CONTROL_FRAME_HEADER_LEN == 7);
+const _: () = assert!(KEY_ROTATION_LEN == 53);
+const _: () = assert!(FILE_CHUNK_HEADER_LEN == 15);
+// 最大のスロット名・データの署名付きファイルチャンクもESP-NOWの上限（250バイト）に収まる
+const _: () = assert!(FILE_CHUNK_HEADER_LEN + MAX_FILE_SLOT_LEN + MAX_FILE_CHUNK_DATA_LEN + DOWNLINK_TAG_LEN <= 250);
+
 /// メッセージタイプ
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum MessageType {
@@ -15,6 +226,28 @@ pub enum MessageType {
     SleepCommand = 0x03,
     /// ハートビート
     Heartbeat = 0x04,
+    /// 転送前の疎通確認（デバイス → ゲートウェイ）
+    Ping = 0x05,
+    /// 疎通確認への応答（ゲートウェイ → デバイス）
+    Pong = 0x06,
+    /// 認証付きスリープコマンド（カウンタ + HMACタグ）
+    SignedSleepCommand = 0x07,
+    /// 全デバイス向けの設定の一斉配信（設定ID + HMACタグ）
+    BroadcastConfig = 0x08,
+    /// デバイスごとの設定更新（カウンタ + 任意のHMACタグ）
+    ConfigUpdate = 0x09,
+    /// 同時転送数の上限による転送の延期要求（ゲートウェイ → デバイス、Pingへの応答）
+    Defer = 0x0A,
+    /// 制御フレーム（識別子 + コマンドID + 引数 + CRC、署名なしのスリープコマンドなど）
+    Control = 0x0B,
+    /// デバイスごとの鍵更新（暗号化した新しい鍵 + 切り替えるカウンタ + HMACタグ）
+    KeyRotation = 0x0C,
+    /// HASHフレームへの応答（ゲートウェイ → デバイス、送信待ちのコマンドの有無）
+    HashAck = 0x0D,
+    /// デバイスへのファイル転送の断片（カウンタ + ファイルのCRC + 位置 + 任意のHMACタグ）
+    FileChunk = 0x0E,
+    /// データを破棄したことの通知（ゲートウェイ → デバイス、理由と再試行までの時間）
+    Reject = 0x0F,
 }
 
 impl MessageType {
@@ -25,6 +258,17 @@ impl MessageType {
             0x02 => Some(MessageType::Ack),
             0x03 => Some(MessageType::SleepCommand),
             0x04 => Some(MessageType::Heartbeat),
+            0x05 => Some(MessageType::Ping),
+            0x06 => Some(MessageType::Pong),
+            0x07 => Some(MessageType::SignedSleepCommand),
+            0x08 => Some(MessageType::BroadcastConfig),
+            0x09 => Some(MessageType::ConfigUpdate),
+            0x0A => Some(MessageType::Defer),
+            0x0B => Some(MessageType::Control),
+            0x0C => Some(MessageType::KeyRotation),
+            0x0D => Some(MessageType::HashAck),
+            0x0E => Some(MessageType::FileChunk),
+            0x0F => Some(MessageType::Reject),
             _ => None,
         }
     }
@@ -99,30 +343,31 @@ impl AckMessage {
     /// [MSG_TYPE(1)] [SEQ_NUM(4)] [ACKED_TYPE(1)] [STATUS(1)]
     /// ```
     pub fn serialize(&self) -> Vec<u8> {
-        let mut data = Vec::with_capacity(7);
-        data.push(MessageType::Ack.to_u8());
-        data.extend_from_slice(&self.sequence_number.to_le_bytes());
-        data.push(self.acked_message_type.to_u8());
-        data.push(self.status.to_u8());
-        data
+        AckWire {
+            message_type: MessageType::Ack.to_u8(),
+            sequence_number: self.sequence_number,
+            acked_message_type: self.acked_message_type.to_u8(),
+            status: self.status.to_u8(),
+        }
+        .to_wire()
     }
     
     /// バイナリデータからACKメッセージをデシリアライズ
     pub fn deserialize(data: &[u8]) -> Option<Self> {
-        if data.len() < 7 {
+        let Ok(wire) = AckWire::read_wire(data) else {
             warn!("ACK message too short: {} bytes", data.len());
             return None;
-        }
+        };
         
         // メッセージタイプの確認
-        if MessageType::from_u8(data[0])? != MessageType::Ack {
-            warn!("Invalid ACK message type: {}", data[0]);
+        if MessageType::from_u8(wire.message_type)? != MessageType::Ack {
+            warn!("Invalid ACK message type: {}", wire.message_type);
             return None;
         }
         
-        let sequence_number = u32::from_le_bytes([data[1], data[2], data[3], data[4]]);
-        let acked_message_type = MessageType::from_u8(data[5])?;
-        let status = AckStatus::from_u8(data[6])?;
+        let sequence_number = wire.sequence_number;
+        let acked_message_type = MessageType::from_u8(wire.acked_message_type)?;
+        let status = AckStatus::from_u8(wire.status)?;
         
         debug!("Deserialized ACK: seq={}, type={:?}, status={:?}", 
                sequence_number, acked_message_type, status);
@@ -151,26 +396,27 @@ impl SleepCommandMessage {
     /// [MSG_TYPE(1)] [SLEEP_SECONDS(4)]
     /// ```
     pub fn serialize(&self) -> Vec<u8> {
-        let mut data = Vec::with_capacity(5);
-        data.push(MessageType::SleepCommand.to_u8());
-        data.extend_from_slice(&self.sleep_seconds.to_le_bytes());
-        data
+        SleepCommandWire {
+            message_type: MessageType::SleepCommand.to_u8(),
+            sleep_seconds: self.sleep_seconds,
+        }
+        .to_wire()
     }
     
     /// バイナリデータからスリープコマンドをデシリアライズ
     pub fn deserialize(data: &[u8]) -> Option<Self> {
-        if data.len() < 5 {
+        let Ok(wire) = SleepCommandWire::read_wire(data) else {
             warn!("Sleep command too short: {} bytes", data.len());
             return None;
-        }
+        };
         
         // メッセージタイプの確認
-        if MessageType::from_u8(data[0])? != MessageType::SleepCommand {
-            warn!("Invalid sleep command message type: {}", data[0]);
+        if MessageType::from_u8(wire.message_type)? != MessageType::SleepCommand {
+            warn!("Invalid sleep command message type: {}", wire.message_type);
             return None;
         }
         
-        let sleep_seconds = u32::from_le_bytes([data[1], data[2], data[3], data[4]]);
+        let sleep_seconds = wire.sleep_seconds;
         
         debug!("Deserialized sleep command: {} seconds", sleep_seconds);
         
@@ -178,9 +424,707 @@ impl SleepCommandMessage {
     }
 }
 
+/// 認証付きスリープコマンド
+///
+/// `counter` はゲートウェイが単調増加で払い出し、デバイスは最後に受理した値以下を拒否します。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SignedSleepCommand {
+    /// コマンドカウンタ
+    pub counter: u32,
+    /// スリープ時間（秒）
+    pub sleep_seconds: u32,
+}
+
+impl SignedSleepCommand {
+    /// 宛先MACに対して署名したバイナリ形式にシリアライズ
+    ///
+    /// フォーマット:
+    /// ```text
+    /// [MSG_TYPE(1)] [COUNTER(4)] [SLEEP_SECONDS(4)] [TAG(8)]
+    /// TAG = HMAC-SHA256(key, TARGET_MAC(6) || 先頭9バイト) の先頭8バイト
+    /// ```
+    pub fn serialize(&self, key: &DownlinkKey, target_mac: &[u8; 6]) -> Vec<u8> {
+        let mut data = SignedSleepCommandWire {
+            message_type: MessageType::SignedSleepCommand.to_u8(),
+            counter: self.counter,
+            sleep_seconds: self.sleep_seconds,
+        }
+        .to_wire();
+        let tag = key.tag(target_mac, &data);
+        data.extend_from_slice(&tag);
+        data
+    }
+
+    /// 署名を検証してデシリアライズ（カウンタの新しさは受信側で確認）
+    pub fn verify(data: &[u8], key: &DownlinkKey, target_mac: &[u8; 6]) -> Option<Self> {
+        if data.len() != SIGNED_SLEEP_COMMAND_LEN {
+            return None;
+        }
+        let (body, tag) = data.split_at(SignedSleepCommandWire::WIRE_SIZE);
+        let wire = SignedSleepCommandWire::read_wire(body).ok()?;
+        if wire.message_type != MessageType::SignedSleepCommand.to_u8() || !key.verify(target_mac, body, tag) {
+            return None;
+        }
+        Some(Self {
+            counter: wire.counter,
+            sleep_seconds: wire.sleep_seconds,
+        })
+    }
+}
+
+/// 全デバイス向けの設定の一斉配信
+///
+/// 設定文字列はデバイスのリモート設定と同じ `KEY=VALUE;...` 形式です。`config_id` はゲートウェイの
+/// コマンドカウンタから払い出し、デバイスは適用済みの値以下の配信を無視します。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BroadcastConfigMessage {
+    /// 設定ID（デバイスはHASHフレームで適用済みのIDを報告する）
+    pub config_id: u32,
+    /// 設定文字列
+    pub config: String,
+}
+
+impl BroadcastConfigMessage {
+    /// 署名したバイナリ形式にシリアライズ（設定文字列が長すぎる場合はNone）
+    ///
+    /// フォーマット:
+    /// ```text
+    /// [MSG_TYPE(1)] [CONFIG_ID(4)] [CONFIG_LEN(1)] [CONFIG(CONFIG_LEN)] [TAG(8)]
+    /// TAG = HMAC-SHA256(key, FF:FF:FF:FF:FF:FF || TAGより前の全体) の先頭8バイト
+    /// ```
+    pub fn serialize(&self, key: &DownlinkKey) -> Option<Vec<u8>> {
+        if self.config.len() > MAX_CONFIG_TEXT_LEN {
+            return None;
+        }
+        let mut data = BroadcastConfigWire {
+            message_type: MessageType::BroadcastConfig.to_u8(),
+            config_id: self.config_id,
+            config_len: self.config.len() as u8,
+        }
+        .to_wire();
+        data.extend_from_slice(self.config.as_bytes());
+        let tag = key.tag(&BROADCAST_MAC, &data);
+        data.extend_from_slice(&tag);
+        Some(data)
+    }
+
+    /// 署名を検証してデシリアライズ（設定IDの新しさは受信側で確認）
+    pub fn verify(data: &[u8], key: &DownlinkKey) -> Option<Self> {
+        let wire = BroadcastConfigWire::read_wire(data).ok()?;
+        let body_len = BroadcastConfigWire::WIRE_SIZE + wire.config_len as usize;
+        if wire.message_type != MessageType::BroadcastConfig.to_u8()
+            || data.len() != body_len + DOWNLINK_TAG_LEN
+        {
+            return None;
+        }
+        let (body, tag) = data.split_at(body_len);
+        if !key.verify(&BROADCAST_MAC, body, tag) {
+            return None;
+        }
+        Some(Self {
+            config_id: wire.config_id,
+            config: String::from_utf8(body[BroadcastConfigWire::WIRE_SIZE..].to_vec()).ok()?,
+        })
+    }
+}
+
+/// デバイスごとの設定更新
+///
+/// 設定文字列は一斉配信と同じ `KEY=VALUE;...` 形式です。認証鍵がある場合はスリープコマンドと
+/// 同じカウンタで署名し、鍵がない場合はカウンタ0・タグなしで送信します。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigUpdateMessage {
+    /// コマンドカウンタ（署名なしは0）
+    pub counter: u32,
+    /// 設定文字列
+    pub config: String,
+}
+
+impl ConfigUpdateMessage {
+    /// バイナリ形式にシリアライズ（設定文字列が長すぎる場合はNone）
+    ///
+    /// フォーマット:
+    /// ```text
+    /// [MSG_TYPE(1)] [COUNTER(4)] [CONFIG_LEN(1)] [CONFIG(CONFIG_LEN)] [TAG(8), 鍵がある場合]
+    /// TAG = HMAC-SHA256(key, TARGET_MAC(6) || TAGより前の全体) の先頭8バイト
+    /// ```
+    pub fn serialize(&self, key: Option<&DownlinkKey>, target_mac: &[u8; 6]) -> Option<Vec<u8>> {
+        if self.config.len() > MAX_CONFIG_TEXT_LEN {
+            return None;
+        }
+        let mut data = ConfigUpdateWire {
+            message_type: MessageType::ConfigUpdate.to_u8(),
+            counter: self.counter,
+            config_len: self.config.len() as u8,
+        }
+        .to_wire();
+        data.extend_from_slice(self.config.as_bytes());
+        if let Some(key) = key {
+            let tag = key.tag(target_mac, &data);
+            data.extend_from_slice(&tag);
+        }
+        Some(data)
+    }
+}
+
+/// デバイスへ転送するファイルの断片
+///
+/// スロット名はデバイスが保存先に使う名前、`file_crc` と `total_len` はファイル全体のCRC-32と長さです。
+/// 設定更新と同じく、認証鍵がある場合はカウンタで署名し、鍵がない場合はカウンタ0・タグなしで送信します。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileChunkMessage {
+    /// コマンドカウンタ（署名なしは0）
+    pub counter: u32,
+    /// 保存先のスロット名
+    pub slot: String,
+    /// ファイル全体のCRC-32
+    pub file_crc: u32,
+    /// ファイル全体の長さ
+    pub total_len: u16,
+    /// この断片の先頭位置
+    pub offset: u16,
+    /// 断片のデータ
+    pub data: Vec<u8>,
+}
+
+impl FileChunkMessage {
+    /// バイナリ形式にシリアライズ（スロット名・データが長すぎる場合はNone）
+    ///
+    /// フォーマット:
+    /// ```text
+    /// [MSG_TYPE(1)] [COUNTER(4)] [FILE_CRC(4)] [TOTAL_LEN(2)] [OFFSET(2)] [SLOT_LEN(1)] [DATA_LEN(1)]
+    /// [SLOT(SLOT_LEN)] [DATA(DATA_LEN)] [TAG(8), 鍵がある場合]
+    /// TAG = HMAC-SHA256(key, TARGET_MAC(6) || TAGより前の全体) の先頭8バイト
+    /// ```
+    pub fn serialize(&self, key: Option<&DownlinkKey>, target_mac: &[u8; 6]) -> Option<Vec<u8>> {
+        if self.slot.len() > MAX_FILE_SLOT_LEN || self.data.len() > MAX_FILE_CHUNK_DATA_LEN {
+            return None;
+        }
+        let mut data = FileChunkWire {
+            message_type: MessageType::FileChunk.to_u8(),
+            counter: self.counter,
+            file_crc: self.file_crc,
+            total_len: self.total_len,
+            offset: self.offset,
+            slot_len: self.slot.len() as u8,
+            data_len: self.data.len() as u8,
+        }
+        .to_wire();
+        data.extend_from_slice(self.slot.as_bytes());
+        data.extend_from_slice(&self.data);
+        if let Some(key) = key {
+            let tag = key.tag(target_mac, &data);
+            data.extend_from_slice(&tag);
+        }
+        Some(data)
+    }
+}
+
+/// デバイスごとの鍵更新
+///
+/// 新しい鍵は現在の鍵で暗号化し、本文全体に現在の鍵でタグを付けます。デバイスは新しい鍵を
+/// NVSに保存し、`activate_counter` 以上のカウンタを持つコマンドから新しい鍵で検証します。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyRotationMessage {
+    /// コマンドカウンタ
+    pub counter: u32,
+    /// 新しい鍵の世代（プロビジョニングした鍵が0）
+    pub epoch: u32,
+    /// 新しい鍵に切り替えるコマンドカウンタ
+    pub activate_counter: u32,
+    /// 新しい鍵
+    pub new_key: DownlinkKey,
+}
+
+impl KeyRotationMessage {
+    /// 現在の鍵で暗号化・署名したバイナリ形式にシリアライズ
+    ///
+    /// フォーマット:
+    /// ```text
+    /// [MSG_TYPE(1)] [COUNTER(4)] [EPOCH(4)] [ACTIVATE_COUNTER(4)] [WRAPPED_KEY(32)] [TAG(8)]
+    /// WRAPPED_KEY = NEW_KEY XOR HMAC-SHA256(key, "KEYWRAP" || TARGET_MAC(6) || COUNTER(4))
+    /// TAG = HMAC-SHA256(key, TARGET_MAC(6) || TAGより前の全体) の先頭8バイト
+    /// ```
+    pub fn serialize(&self, key: &DownlinkKey, target_mac: &[u8; 6]) -> Vec<u8> {
+        let mut data = KeyRotationWire {
+            message_type: MessageType::KeyRotation.to_u8(),
+            counter: self.counter,
+            epoch: self.epoch,
+            activate_counter: self.activate_counter,
+        }
+        .to_wire();
+        data.extend_from_slice(&key.wrap_key(target_mac, self.counter, &self.new_key.to_bytes()));
+        let tag = key.tag(target_mac, &data);
+        data.extend_from_slice(&tag);
+        data
+    }
+
+    /// 署名を検証して新しい鍵を復号（カウンタの新しさは受信側で確認）
+    pub fn verify(data: &[u8], key: &DownlinkKey, target_mac: &[u8; 6]) -> Option<Self> {
+        if data.len() != KEY_ROTATION_LEN {
+            return None;
+        }
+        let (body, tag) = data.split_at(KEY_ROTATION_LEN - DOWNLINK_TAG_LEN);
+        let wire = KeyRotationWire::read_wire(body).ok()?;
+        if wire.message_type != MessageType::KeyRotation.to_u8() || !key.verify(target_mac, body, tag) {
+            return None;
+        }
+        let wrapped: [u8; DOWNLINK_KEY_LEN] = body[KeyRotationWire::WIRE_SIZE..].try_into().ok()?;
+        Some(Self {
+            counter: wire.counter,
+            epoch: wire.epoch,
+            activate_counter: wire.activate_counter,
+            new_key: DownlinkKey::from_bytes(key.wrap_key(target_mac, wire.counter, &wrapped)),
+        })
+    }
+}
+
+/// 転送前の疎通確認Ping
+///
+/// デバイスは画像転送の前にPingを送り、Pongが返らなければ転送せずにスリープします。
+/// 無線設定と機能フラグの付加は任意で、付加しない旧ファームウェアのPingも受け付けます。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PingMessage {
+    /// 応答照合用の値（Pongでそのまま返す）
+    pub nonce: u32,
+    /// デバイスが適用した無線設定
+    pub radio: Option<RadioSettings>,
+    /// デバイスがプライバシーモードを要求している
+    pub privacy: bool,
+    /// デバイスが受信チャンネルの予定に従える
+    pub channel_hop: bool,
+    /// デバイスがHASHフレームへの応答でコマンド待機を短縮できる
+    pub command_window: bool,
+    /// デバイスが通知したDATAチャンクの大きさで送信できる
+    pub chunk_size: bool,
+    /// デバイスがHASHフレームへの応答で前回の転送の損失率を受け取れる
+    pub loss_report: bool,
+    /// デバイスがHASHフレームへの応答でゲートウェイの時計を受け取れる
+    pub gateway_clock: bool,
+}
+
+/// 固定部の後ろの任意部分を無線設定・機能フラグ・残りに分けます（長さが合わなければNone）
+///
+/// 任意部分は `[RADIO(3), 任意] [FLAGS(1), 任意] [...]` の順で、長さから有無を判別します。
+/// 機能フラグの後ろの付加情報（Pongのみ）は無線設定と機能フラグの両方がある場合のみ続きます。
+fn split_extensions(extensions: &[u8]) -> Option<(Option<RadioSettings>, u8, &[u8])> {
+    match extensions.len() {
+        0 => Some((None, 0, &[])),
+        CAPABILITY_FLAGS_LEN => Some((None, extensions[0], &[])),
+        RADIO_SETTINGS_LEN => Some((RadioSettings::from_wire(extensions), 0, &[])),
+        len if len >= RADIO_SETTINGS_LEN + CAPABILITY_FLAGS_LEN => {
+            let (radio, rest) = extensions.split_at(RADIO_SETTINGS_LEN);
+            Some((RadioSettings::from_wire(radio), rest[0], &rest[CAPABILITY_FLAGS_LEN..]))
+        }
+        _ => None,
+    }
+}
+
+/// Pongの機能フラグの後ろをチャンクの大きさの通知と受信チャンネルの予定に分けます（合わなければNone）
+///
+/// 通知（`CAPABILITY_CHUNK_SIZE`）、予定（`CAPABILITY_CHANNEL_HOP`）の順で、フラグが立っているものだけが続きます。
+fn split_pong_additions(flags: u8, additions: &[u8]) -> Option<(Option<ChunkSizeAdvert>, Option<HopAnnouncement>)> {
+    let (chunk_size, hop) = if flags & CAPABILITY_CHUNK_SIZE != 0 {
+        let (advert, hop) = additions.split_at_checked(CHUNK_SIZE_ADVERT_LEN)?;
+        (Some(ChunkSizeAdvert::from_wire(advert)?), hop)
+    } else {
+        (None, additions)
+    };
+    let hop = match hop.is_empty() {
+        true => None,
+        false if flags & CAPABILITY_CHANNEL_HOP != 0 => Some(HopAnnouncement::from_wire(hop)?),
+        false => return None,
+    };
+    Some((chunk_size, hop))
+}
+
+/// 機能フラグを付加します（要求する機能がなければ旧形式のまま）
+fn append_capabilities(
+    data: &mut Vec<u8>,
+    privacy: bool,
+    channel_hop: bool,
+    command_window: bool,
+    chunk_size: bool,
+    loss_report: bool,
+    gateway_clock: bool,
+) {
+    let flags = if privacy { CAPABILITY_PRIVACY } else { 0 }
+        | if channel_hop { CAPABILITY_CHANNEL_HOP } else { 0 }
+        | if command_window { CAPABILITY_COMMAND_WINDOW } else { 0 }
+        | if chunk_size { CAPABILITY_CHUNK_SIZE } else { 0 }
+        | if loss_report { CAPABILITY_LOSS_REPORT } else { 0 }
+        | if gateway_clock { CAPABILITY_GATEWAY_CLOCK } else { 0 };
+    if flags != 0 {
+        data.push(flags);
+    }
+}
+
+impl PingMessage {
+    /// Pingをバイナリ形式にシリアライズ
+    ///
+    /// フォーマット:
+    /// ```text
+    /// [MSG_TYPE(1)] ["PING"(4)] [NONCE(4)] [RADIO(3), 任意] [FLAGS(1), 任意]
+    /// ```
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut data = PingWire {
+            message_type: MessageType::Ping.to_u8(),
+            magic: PING_MAGIC,
+            nonce: self.nonce,
+        }
+        .to_wire();
+        if let Some(radio) = &self.radio {
+            data.extend_from_slice(&radio.to_wire());
+        }
+        append_capabilities(
+            &mut data,
+            self.privacy,
+            self.channel_hop,
+            self.command_window,
+            self.chunk_size,
+            self.loss_report,
+            self.gateway_clock,
+        );
+        data
+    }
+
+    /// バイナリデータからPingをデシリアライズ（長さが一致しない場合はPingではない）
+    pub fn deserialize(data: &[u8]) -> Option<Self> {
+        if data.len() < PING_MESSAGE_LEN {
+            return None;
+        }
+        let (radio, flags, additions) = split_extensions(&data[PING_MESSAGE_LEN..])?;
+        let wire = PingWire::read_wire(data).ok()?;
+        if wire.message_type != MessageType::Ping.to_u8() || wire.magic != PING_MAGIC || !additions.is_empty() {
+            return None;
+        }
+        Some(Self {
+            nonce: wire.nonce,
+            radio,
+            privacy: flags & CAPABILITY_PRIVACY != 0,
+            channel_hop: flags & CAPABILITY_CHANNEL_HOP != 0,
+            command_window: flags & CAPABILITY_COMMAND_WINDOW != 0,
+            chunk_size: flags & CAPABILITY_CHUNK_SIZE != 0,
+            loss_report: flags & CAPABILITY_LOSS_REPORT != 0,
+            gateway_clock: flags & CAPABILITY_GATEWAY_CLOCK != 0,
+        })
+    }
+}
+
+/// 疎通確認への応答Pong
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PongMessage {
+    /// 対応するPingのnonce
+    pub nonce: u32,
+    /// ゲートウェイのデータキュー空き率（%）
+    pub queue_free_percent: u8,
+    /// ゲートウェイが適用した無線設定
+    pub radio: Option<RadioSettings>,
+    /// プライバシーモードを受け入れた（詰め物とダミーフレームを除去する）
+    pub privacy: bool,
+    /// DATAチャンクの大きさの通知（要求されていなければNone）
+    pub chunk_size: Option<ChunkSizeAdvert>,
+    /// 受信チャンネルの予定（チャンネルホッピングが無効、または要求されていなければNone）
+    pub hop: Option<HopAnnouncement>,
+}
+
+impl PongMessage {
+    /// Pingへの応答を作成
+    ///
+    /// 無線設定はPingに付加されていた場合のみ返します（旧ファームウェアは10バイトのPongしか解釈しない）。
+    /// プライバシーモードは要求されていれば常に受け入れます。
+    /// 受信チャンネルの予定とDATAチャンクの大きさの通知は、無線設定を付加してそれぞれに対応することを
+    /// 示したPingにのみ返します。
+    pub fn reply_to(
+        ping: &PingMessage,
+        queue_free_percent: u8,
+        radio: Option<RadioSettings>,
+        chunk_size: Option<ChunkSizeAdvert>,
+        hop: Option<HopAnnouncement>,
+    ) -> Self {
+        let radio = ping.radio.and(radio);
+        Self {
+            nonce: ping.nonce,
+            queue_free_percent: queue_free_percent.min(100),
+            radio,
+            privacy: ping.privacy,
+            chunk_size: chunk_size.filter(|_| ping.chunk_size && radio.is_some()),
+            hop: hop.filter(|_| ping.channel_hop && radio.is_some()),
+        }
+    }
+
+    /// Pongをバイナリ形式にシリアライズ
+    ///
+    /// フォーマット:
+    /// ```text
+    /// [MSG_TYPE(1)] ["PONG"(4)] [NONCE(4)] [QUEUE_FREE_PERCENT(1)] [RADIO(3), 任意] [FLAGS(1), 任意]
+    ///   [CHUNK(4), 任意] [HOP(可変), 任意]
+    /// CHUNK = [PREFERRED(2)] [MAX(2)]
+    /// HOP = [HOME(1)] [SLOT_SECS(2)] [ELAPSED_SECS(2)] [COUNT(1)] [CHANNELS(COUNT)]
+    /// ```
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut data = PongWire {
+            message_type: MessageType::Pong.to_u8(),
+            magic: PONG_MAGIC,
+            nonce: self.nonce,
+            queue_free_percent: self.queue_free_percent,
+        }
+        .to_wire();
+        if let Some(radio) = &self.radio {
+            data.extend_from_slice(&radio.to_wire());
+        }
+        append_capabilities(&mut data, self.privacy, self.hop.is_some(), false, self.chunk_size.is_some(), false, false);
+        if let Some(chunk_size) = &self.chunk_size {
+            data.extend_from_slice(&chunk_size.to_wire());
+        }
+        if let Some(hop) = &self.hop {
+            data.extend_from_slice(&hop.to_wire());
+        }
+        data
+    }
+
+    /// バイナリデータからPongをデシリアライズ
+    pub fn deserialize(data: &[u8]) -> Option<Self> {
+        if data.len() < PONG_MESSAGE_LEN {
+            return None;
+        }
+        let (radio, flags, additions) = split_extensions(&data[PONG_MESSAGE_LEN..])?;
+        let (chunk_size, hop) = split_pong_additions(flags, additions)?;
+        let wire = PongWire::read_wire(data).ok()?;
+        if wire.message_type != MessageType::Pong.to_u8() || wire.magic != PONG_MAGIC {
+            return None;
+        }
+        Some(Self {
+            nonce: wire.nonce,
+            queue_free_percent: wire.queue_free_percent,
+            radio,
+            privacy: flags & CAPABILITY_PRIVACY != 0,
+            chunk_size,
+            hop,
+        })
+    }
+}
+
+/// 転送の延期要求（Pongの代わりに返す）
+///
+/// 同時に転送中のデバイスが上限に達している場合、デバイスは `retry_after_ms` 待ってから
+/// 疎通確認をやり直します。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeferMessage {
+    /// 対応するPingのnonce
+    pub nonce: u32,
+    /// 再試行までの待機時間の目安（ミリ秒）
+    pub retry_after_ms: u32,
+}
+
+impl DeferMessage {
+    /// バイナリ形式にシリアライズ
+    ///
+    /// フォーマット:
+    /// ```text
+    /// [MSG_TYPE(1)] ["BUSY"(4)] [NONCE(4)] [RETRY_AFTER_MS(4)]
+    /// ```
+    pub fn serialize(&self) -> Vec<u8> {
+        DeferWire {
+            message_type: MessageType::Defer.to_u8(),
+            magic: DEFER_MAGIC,
+            nonce: self.nonce,
+            retry_after_ms: self.retry_after_ms,
+        }
+        .to_wire()
+    }
+
+    /// バイナリデータから延期要求をデシリアライズ
+    pub fn deserialize(data: &[u8]) -> Option<Self> {
+        if data.len() != DEFER_MESSAGE_LEN {
+            return None;
+        }
+        let wire = DeferWire::read_wire(data).ok()?;
+        if wire.message_type != MessageType::Defer.to_u8() || wire.magic != DEFER_MAGIC {
+            return None;
+        }
+        Some(Self {
+            nonce: wire.nonce,
+            retry_after_ms: wire.retry_after_ms,
+        })
+    }
+}
+
+/// HASHフレームへの応答
+///
+/// デバイスは送信後にダウンリンクのコマンド（スリープコマンドや設定更新）を待つため、
+/// 送信待ちのコマンドがなければ待機せずにスリープできるよう、HASHフレームの受信時に返します。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HashAckMessage {
+    /// 転送後に送るコマンドがある（ホストからのスリープコマンドを待っている場合を含む）
+    pub commands_pending: bool,
+    /// 送信待ちのコマンド数の目安
+    pub pending_count: u8,
+    /// ゲートウェイが観測した前回の転送のDATA/FECの損失率（%、`CAPABILITY_LOSS_REPORT` を示したデバイスのみ）
+    pub loss_percent: Option<u8>,
+    /// 応答を送った時点のゲートウェイの稼働時間（マイクロ秒、`CAPABILITY_GATEWAY_CLOCK` を示したデバイスのみ）
+    ///
+    /// デバイスは前回の応答からの経過時間を自身のRTCの経過時間と比べ、スリープ中のドリフトを推定します。
+    pub gateway_us: Option<u64>,
+}
+
+impl HashAckMessage {
+    /// バイナリ形式にシリアライズ
+    ///
+    /// フォーマット:
+    /// ```text
+    /// [MSG_TYPE(1)] ["HACK"(4)] [COMMANDS_PENDING(1)] [PENDING_COUNT(1)] [LOSS_PERCENT(1), 任意] [GATEWAY_US(8, LE), 任意]
+    /// ```
+    ///
+    /// 時計を付加する場合、損失率が未観測なら `LOSS_PERCENT` に0xFFを入れます。
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut data = HashAckWire {
+            message_type: MessageType::HashAck.to_u8(),
+            magic: HASH_ACK_MAGIC,
+            commands_pending: self.commands_pending as u8,
+            pending_count: self.pending_count,
+        }
+        .to_wire();
+        match (self.loss_percent, self.gateway_us) {
+            (loss_percent, Some(gateway_us)) => {
+                data.push(loss_percent.unwrap_or(LOSS_UNKNOWN));
+                data.extend_from_slice(&gateway_us.to_le_bytes());
+            }
+            (Some(loss_percent), None) => data.push(loss_percent),
+            (None, None) => {}
+        }
+        data
+    }
+
+    /// バイナリデータからHASHフレームへの応答をデシリアライズ
+    pub fn deserialize(data: &[u8]) -> Option<Self> {
+        if !matches!(data.len(), HASH_ACK_MESSAGE_LEN | HASH_ACK_WITH_LOSS_LEN | HASH_ACK_WITH_CLOCK_LEN) {
+            return None;
+        }
+        let wire = HashAckWire::read_wire(data).ok()?;
+        if wire.message_type != MessageType::HashAck.to_u8() || wire.magic != HASH_ACK_MAGIC {
+            return None;
+        }
+        Some(Self {
+            commands_pending: wire.commands_pending != 0,
+            pending_count: wire.pending_count,
+            loss_percent: data.get(HASH_ACK_MESSAGE_LEN).copied().filter(|&loss| loss != LOSS_UNKNOWN),
+            gateway_us: data
+                .get(HASH_ACK_WITH_LOSS_LEN..)
+                .and_then(|clock| clock.try_into().ok())
+                .map(u64::from_le_bytes),
+        })
+    }
+}
+
+/// 制御フレームで送るコマンド
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ControlCommand {
+    /// 指定秒数のディープスリープ
+    Sleep { sleep_seconds: u32 },
+    /// 直前に送信した画像の再送（撮影し直さない）
+    ResendLast,
+    /// 保持している直近のサムネイルの送信
+    GetThumbs,
+}
+
+impl ControlCommand {
+    /// スリープコマンドのコマンドID
+    pub const SLEEP_ID: u8 = 0x01;
+    /// 再送要求のコマンドID
+    pub const RESEND_LAST_ID: u8 = 0x02;
+    /// サムネイル要求のコマンドID
+    pub const GET_THUMBS_ID: u8 = 0x03;
+
+    /// コマンドID
+    pub fn id(&self) -> u8 {
+        match self {
+            ControlCommand::Sleep { .. } => Self::SLEEP_ID,
+            ControlCommand::ResendLast => Self::RESEND_LAST_ID,
+            ControlCommand::GetThumbs => Self::GET_THUMBS_ID,
+        }
+    }
+
+    /// 引数のワイヤ表現
+    fn args(&self) -> Vec<u8> {
+        match self {
+            ControlCommand::Sleep { sleep_seconds } => sleep_seconds.to_le_bytes().to_vec(),
+            ControlCommand::ResendLast | ControlCommand::GetThumbs => Vec::new(),
+        }
+    }
+
+    /// コマンドIDと引数から復元（IDが未知、または引数の長さが合わない場合はNone）
+    fn from_args(command_id: u8, args: &[u8]) -> Option<Self> {
+        match command_id {
+            Self::SLEEP_ID => Some(ControlCommand::Sleep {
+                sleep_seconds: u32::from_le_bytes(args.try_into().ok()?),
+            }),
+            Self::RESEND_LAST_ID if args.is_empty() => Some(ControlCommand::ResendLast),
+            Self::GET_THUMBS_ID if args.is_empty() => Some(ControlCommand::GetThumbs),
+            _ => None,
+        }
+    }
+
+    /// 制御フレームにシリアライズ
+    ///
+    /// フォーマット:
+    /// ```text
+    /// [MSG_TYPE(1)] ["CTRL"(4)] [COMMAND_ID(1)] [ARGS_LEN(1)] [ARGS(ARGS_LEN)] [CRC32(4)]
+    /// CRC32 = CRC-32/ISO-HDLC(CRCより前の全体)
+    /// ```
+    pub fn serialize(&self) -> Vec<u8> {
+        let args = self.args();
+        let mut data = ControlFrameWire {
+            message_type: MessageType::Control.to_u8(),
+            magic: CONTROL_MAGIC,
+            command_id: self.id(),
+            args_len: args.len() as u8,
+        }
+        .to_wire();
+        data.extend_from_slice(&args);
+        let crc = crc32(&data);
+        data.extend_from_slice(&crc.to_le_bytes());
+        data
+    }
+
+    /// 制御フレームをデシリアライズ（長さ・識別子・CRCのいずれかが合わなければNone）
+    pub fn deserialize(data: &[u8]) -> Option<Self> {
+        let wire = ControlFrameWire::read_wire(data).ok()?;
+        let args_len = wire.args_len as usize;
+        let body_len = CONTROL_FRAME_HEADER_LEN + args_len;
+        if wire.message_type != MessageType::Control.to_u8()
+            || wire.magic != CONTROL_MAGIC
+            || args_len > MAX_CONTROL_ARGS_LEN
+            || data.len() != body_len + CONTROL_FRAME_CRC_LEN
+        {
+            return None;
+        }
+        let (body, crc) = data.split_at(body_len);
+        if crc32(body).to_le_bytes() != crc {
+            warn!("Control frame CRC mismatch ({} bytes)", data.len());
+            return None;
+        }
+        Self::from_args(wire.command_id, &body[CONTROL_FRAME_HEADER_LEN..])
+    }
+}
+
+/// CRC-32（ISO-HDLC、zlibと同じ）
+pub fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0u32, |crc, &byte| {
+        (0..8).fold(crc ^ byte as u32, |crc, _| {
+            if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            }
+        })
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::esp_now::channel_hop::ChannelHopper;
 
     #[test]
     fn test_ack_message_serialization() {
@@ -207,4 +1151,330 @@ mod tests {
         let deserialized = SleepCommandMessage::deserialize(&data).unwrap();
         assert_eq!(deserialized.sleep_seconds, 3600);
     }
+
+    #[test]
+    fn test_ping_pong_roundtrip() {
+        let ping = PingMessage { nonce: 0xDEAD_BEEF, radio: None, privacy: false, channel_hop: false, command_window: false, chunk_size: false, loss_report: false, gateway_clock: false };
+        let data = ping.serialize();
+        assert_eq!(&data[..5], &[MessageType::Ping.to_u8(), b'P', b'I', b'N', b'G']);
+        assert_eq!(PingMessage::deserialize(&data), Some(ping));
+        // 長さ違い・識別子違いはPingとみなさない
+        assert_eq!(PingMessage::deserialize(&data[..8]), None);
+        let mut raw_chunk = data.clone();
+        raw_chunk[1] = b'X';
+        assert_eq!(PingMessage::deserialize(&raw_chunk), None);
+
+        let pong = PongMessage::reply_to(&ping, 150, None, None, None);
+        assert_eq!(pong.queue_free_percent, 100);
+        assert_eq!(PongMessage::deserialize(&pong.serialize()), Some(pong));
+    }
+
+    #[test]
+    fn test_ping_pong_with_radio_settings() {
+        let device = RadioSettings {
+            rate: crate::esp_now::radio::EspNowRate::parse("24M"),
+            tx_power_dbm: 20,
+            ampdu_tx: false,
+        };
+        let gateway = RadioSettings { ampdu_tx: true, ..device };
+
+        let ping = PingMessage { nonce: 7, radio: Some(device), privacy: false, channel_hop: false, command_window: false, chunk_size: false, loss_report: false, gateway_clock: false };
+        let data = ping.serialize();
+        assert_eq!(data.len(), PING_WITH_RADIO_LEN);
+        assert_eq!(PingMessage::deserialize(&data), Some(ping));
+
+        let pong = PongMessage::reply_to(&ping, 80, Some(gateway), None, None);
+        let data = pong.serialize();
+        assert_eq!(data.len(), PONG_WITH_RADIO_LEN);
+        assert_eq!(PongMessage::deserialize(&data).unwrap().radio, Some(gateway));
+
+        // 無線設定のない旧Pingには旧形式のPongで応答する
+        let legacy = PingMessage { nonce: 7, radio: None, privacy: false, channel_hop: false, command_window: false, chunk_size: false, loss_report: false, gateway_clock: false };
+        assert_eq!(PongMessage::reply_to(&legacy, 80, Some(gateway), None, None).serialize().len(), PONG_MESSAGE_LEN);
+    }
+
+    #[test]
+    fn test_ping_pong_privacy_capability() {
+        // 機能フラグは無線設定の有無にかかわらず末尾に付加される
+        for radio in [None, Some(RadioSettings { rate: None, tx_power_dbm: 8, ampdu_tx: false })] {
+            let ping = PingMessage { nonce: 9, radio, privacy: true, channel_hop: false, command_window: false, chunk_size: false, loss_report: false, gateway_clock: false };
+            let data = ping.serialize();
+            assert_eq!(data.last(), Some(&CAPABILITY_PRIVACY));
+            assert_eq!(PingMessage::deserialize(&data), Some(ping));
+
+            let pong = PongMessage::reply_to(&ping, 50, radio, None, None);
+            assert!(pong.privacy);
+            assert_eq!(PongMessage::deserialize(&pong.serialize()), Some(pong));
+        }
+
+        // 長さが合わない任意部分はPingとみなさない
+        let mut data = PingMessage { nonce: 9, radio: None, privacy: true, channel_hop: false, command_window: false, chunk_size: false, loss_report: false, gateway_clock: false }.serialize();
+        data.push(0);
+        assert_eq!(PingMessage::deserialize(&data), None);
+    }
+
+    #[test]
+    fn test_hash_ack_and_command_window_capability() {
+        let ping = PingMessage { nonce: 5, radio: None, privacy: false, channel_hop: false, command_window: true, chunk_size: false, loss_report: false, gateway_clock: false };
+        let data = ping.serialize();
+        assert_eq!(data.last(), Some(&CAPABILITY_COMMAND_WINDOW));
+        assert!(PingMessage::deserialize(&data).unwrap().command_window);
+
+        let ack = HashAckMessage { commands_pending: true, pending_count: 2, loss_percent: None, gateway_us: None };
+        let data = ack.serialize();
+        assert_eq!(data, [MessageType::HashAck.to_u8(), b'H', b'A', b'C', b'K', 1, 2]);
+        assert_eq!(HashAckMessage::deserialize(&data), Some(ack));
+
+        // 損失率を受け取れるデバイスには前回の転送の損失率を付加する
+        let ping = PingMessage { loss_report: true, ..ping };
+        assert_eq!(ping.serialize().last(), Some(&(CAPABILITY_COMMAND_WINDOW | CAPABILITY_LOSS_REPORT)));
+        assert!(PingMessage::deserialize(&ping.serialize()).unwrap().loss_report);
+        let ack = HashAckMessage { loss_percent: Some(12), ..ack };
+        let data = ack.serialize();
+        assert_eq!(data.len(), HASH_ACK_WITH_LOSS_LEN);
+        assert_eq!(data.last(), Some(&12));
+        assert_eq!(HashAckMessage::deserialize(&data), Some(ack));
+
+        // ゲートウェイの時計は損失率の後ろに付け、損失率が未観測なら0xFFで埋める
+        let ack = HashAckMessage { loss_percent: None, gateway_us: Some(0x0102_0304_0506), ..ack };
+        let data = ack.serialize();
+        assert_eq!(data.len(), HASH_ACK_WITH_CLOCK_LEN);
+        assert_eq!(data[HASH_ACK_MESSAGE_LEN], LOSS_UNKNOWN);
+        assert_eq!(HashAckMessage::deserialize(&data), Some(ack));
+        assert_eq!(HashAckMessage::deserialize(&data[..HASH_ACK_WITH_CLOCK_LEN - 1]), None);
+        // 同じ長さのACKメッセージとは区別する
+        assert_eq!(HashAckMessage::deserialize(&AckMessage::success(1, MessageType::DataFrame).serialize()), None);
+    }
+
+    #[test]
+    fn test_pong_carries_hop_schedule_only_when_requested() {
+        let radio = RadioSettings { rate: None, tx_power_dbm: 8, ampdu_tx: false };
+        let hop = ChannelHopper::new(1, &[6, 11], 60, std::time::Instant::now())
+            .announcement(std::time::Instant::now());
+
+        let ping = PingMessage { nonce: 3, radio: Some(radio), privacy: false, channel_hop: true, command_window: false, chunk_size: false, loss_report: false, gateway_clock: false };
+        let data = ping.serialize();
+        assert_eq!(data.last(), Some(&CAPABILITY_CHANNEL_HOP));
+        assert_eq!(PingMessage::deserialize(&data), Some(ping));
+
+        let pong = PongMessage::reply_to(&ping, 50, Some(radio), None, Some(hop));
+        let data = pong.serialize();
+        assert_eq!(data[PONG_WITH_RADIO_LEN], CAPABILITY_CHANNEL_HOP);
+        assert_eq!(&data[PONG_WITH_RADIO_LEN + CAPABILITY_FLAGS_LEN..], &hop.to_wire()[..]);
+        assert_eq!(PongMessage::deserialize(&data), Some(pong));
+
+        // 予定に従えないPing・無線設定のないPingには予定を付けない
+        let legacy = PingMessage { channel_hop: false, ..ping };
+        assert_eq!(PongMessage::reply_to(&legacy, 50, Some(radio), None, Some(hop)).hop, None);
+        let no_radio = PingMessage { radio: None, ..ping };
+        assert_eq!(PongMessage::reply_to(&no_radio, 50, Some(radio), None, Some(hop)).hop, None);
+
+        // フラグのない余分なデータはPongとみなさない
+        let mut data = PongMessage::reply_to(&legacy, 50, Some(radio), None, None).serialize();
+        data.extend_from_slice(&hop.to_wire());
+        assert_eq!(PongMessage::deserialize(&data), None);
+    }
+
+    #[test]
+    fn test_pong_carries_chunk_size_before_hop_schedule() {
+        let radio = RadioSettings { rate: None, tx_power_dbm: 8, ampdu_tx: false };
+        let hop = ChannelHopper::new(1, &[6, 11], 60, std::time::Instant::now())
+            .announcement(std::time::Instant::now());
+        let advert = ChunkSizeAdvert { preferred: 150, max: 223 };
+
+        let ping = PingMessage {
+            nonce: 4,
+            radio: Some(radio),
+            privacy: false,
+            channel_hop: true,
+            command_window: false,
+            chunk_size: true,
+            loss_report: false,
+            gateway_clock: false,
+        };
+        let data = ping.serialize();
+        assert_eq!(data.last(), Some(&(CAPABILITY_CHANNEL_HOP | CAPABILITY_CHUNK_SIZE)));
+        assert_eq!(PingMessage::deserialize(&data), Some(ping));
+
+        let pong = PongMessage::reply_to(&ping, 50, Some(radio), Some(advert), Some(hop));
+        let data = pong.serialize();
+        assert_eq!(data[PONG_WITH_RADIO_LEN], CAPABILITY_CHANNEL_HOP | CAPABILITY_CHUNK_SIZE);
+        let additions = &data[PONG_WITH_RADIO_LEN + CAPABILITY_FLAGS_LEN..];
+        assert_eq!(additions[..CHUNK_SIZE_ADVERT_LEN], advert.to_wire());
+        assert_eq!(additions[CHUNK_SIZE_ADVERT_LEN..], hop.to_wire()[..]);
+        assert_eq!(PongMessage::deserialize(&data), Some(pong));
+
+        let no_hop_ping = PingMessage { channel_hop: false, ..ping };
+        let without_hop = PongMessage::reply_to(&no_hop_ping, 50, Some(radio), Some(advert), Some(hop));
+        assert_eq!(without_hop.serialize().len(), PONG_WITH_RADIO_LEN + CAPABILITY_FLAGS_LEN + CHUNK_SIZE_ADVERT_LEN);
+        assert_eq!(PongMessage::deserialize(&without_hop.serialize()), Some(without_hop));
+
+        // 通知に対応しないPing・無線設定のないPingには通知を付けない
+        let legacy = PingMessage { chunk_size: false, ..ping };
+        assert_eq!(PongMessage::reply_to(&legacy, 50, Some(radio), Some(advert), None).chunk_size, None);
+        let no_radio = PingMessage { radio: None, ..ping };
+        assert_eq!(PongMessage::reply_to(&no_radio, 50, Some(radio), Some(advert), None).chunk_size, None);
+
+        // フラグがあるのに通知が欠けたPongは解析しない
+        let mut data = without_hop.serialize();
+        data.truncate(data.len() - 1);
+        assert_eq!(PongMessage::deserialize(&data), None);
+    }
+
+    #[test]
+    fn test_signed_sleep_command_roundtrip() {
+        let key = DownlinkKey::from_hex(&"ab".repeat(32)).unwrap();
+        let other_key = DownlinkKey::from_hex(&"cd".repeat(32)).unwrap();
+        let mac = [0x24, 0x0A, 0xC4, 0x00, 0x00, 0x01];
+        let command = SignedSleepCommand { counter: 42, sleep_seconds: 600 };
+
+        let data = command.serialize(&key, &mac);
+        assert_eq!(data.len(), SIGNED_SLEEP_COMMAND_LEN);
+        assert_eq!(data[0], MessageType::SignedSleepCommand.to_u8());
+        assert_eq!(SignedSleepCommand::verify(&data, &key, &mac), Some(command));
+
+        // 鍵・宛先の違い、改ざんは拒否
+        assert_eq!(SignedSleepCommand::verify(&data, &other_key, &mac), None);
+        assert_eq!(SignedSleepCommand::verify(&data, &key, &[0; 6]), None);
+        let mut tampered = data.clone();
+        tampered[5] ^= 0x01;
+        assert_eq!(SignedSleepCommand::verify(&tampered, &key, &mac), None);
+    }
+
+    #[test]
+    fn test_broadcast_config_roundtrip() {
+        let key = DownlinkKey::from_hex(&"ab".repeat(32)).unwrap();
+        let other_key = DownlinkKey::from_hex(&"cd".repeat(32)).unwrap();
+        let message = BroadcastConfigMessage { config_id: 77, config: "SLEEP=900".to_string() };
+
+        let data = message.serialize(&key).unwrap();
+        assert_eq!(data.len(), 6 + 9 + DOWNLINK_TAG_LEN);
+        assert_eq!(data[0], MessageType::BroadcastConfig.to_u8());
+        assert_eq!(BroadcastConfigMessage::verify(&data, &key), Some(message.clone()));
+
+        // 鍵違い・改ざん・長さ違いは拒否
+        assert_eq!(BroadcastConfigMessage::verify(&data, &other_key), None);
+        let mut tampered = data.clone();
+        tampered[7] ^= 0x01;
+        assert_eq!(BroadcastConfigMessage::verify(&tampered, &key), None);
+        assert_eq!(BroadcastConfigMessage::verify(&data[..data.len() - 1], &key), None);
+
+        // 宛先付きのスリープコマンドとは署名の対象が異なる
+        let sleep = SignedSleepCommand { counter: 77, sleep_seconds: 900 }.serialize(&key, &BROADCAST_MAC);
+        assert_eq!(BroadcastConfigMessage::verify(&sleep, &key), None);
+
+        let too_long = BroadcastConfigMessage { config_id: 1, config: "X".repeat(MAX_CONFIG_TEXT_LEN + 1) };
+        assert_eq!(too_long.serialize(&key), None);
+    }
+
+    #[test]
+    fn test_config_update_signed_and_unsigned() {
+        let key = DownlinkKey::from_hex(&"ab".repeat(32)).unwrap();
+        let mac = [0x24, 0x0A, 0xC4, 0x00, 0x00, 0x01];
+        let update = ConfigUpdateMessage { counter: 5, config: "JPEG_Q=12;FRAME_SIZE=VGA".to_string() };
+
+        let unsigned = update.serialize(None, &mac).unwrap();
+        assert_eq!(unsigned.len(), 6 + update.config.len());
+        assert_eq!(&unsigned[..6], &[MessageType::ConfigUpdate.to_u8(), 5, 0, 0, 0, 24]);
+
+        let signed = update.serialize(Some(&key), &mac).unwrap();
+        assert_eq!(&signed[..unsigned.len()], &unsigned[..]);
+        assert!(key.verify(&mac, &unsigned, &signed[unsigned.len()..]));
+    }
+
+    #[test]
+    fn test_file_chunk_layout() {
+        let key = DownlinkKey::from_hex(&"ab".repeat(32)).unwrap();
+        let mac = [0x24, 0x0A, 0xC4, 0x00, 0x00, 0x01];
+        let chunk = FileChunkMessage {
+            counter: 7,
+            slot: "calib".to_string(),
+            file_crc: 0xCBF4_3926,
+            total_len: 300,
+            offset: 192,
+            data: b"123".to_vec(),
+        };
+
+        let unsigned = chunk.serialize(None, &mac).unwrap();
+        assert_eq!(unsigned.len(), FILE_CHUNK_HEADER_LEN + 5 + 3);
+        assert_eq!(
+            &unsigned[..FILE_CHUNK_HEADER_LEN],
+            &[MessageType::FileChunk.to_u8(), 7, 0, 0, 0, 0x26, 0x39, 0xF4, 0xCB, 0x2C, 0x01, 0xC0, 0x00, 5, 3]
+        );
+        assert_eq!(&unsigned[FILE_CHUNK_HEADER_LEN..], b"calib123");
+
+        let signed = chunk.serialize(Some(&key), &mac).unwrap();
+        assert_eq!(&signed[..unsigned.len()], &unsigned[..]);
+        assert!(key.verify(&mac, &unsigned, &signed[unsigned.len()..]));
+
+        let too_long = FileChunkMessage { data: vec![0; MAX_FILE_CHUNK_DATA_LEN + 1], ..chunk };
+        assert_eq!(too_long.serialize(None, &mac), None);
+    }
+
+    #[test]
+    fn test_key_rotation_roundtrip() {
+        let key = DownlinkKey::from_hex(&"ab".repeat(32)).unwrap();
+        let new_key = DownlinkKey::from_hex(&"cd".repeat(32)).unwrap();
+        let mac = [0x24, 0x0A, 0xC4, 0x00, 0x00, 0x01];
+        let message = KeyRotationMessage { counter: 90, epoch: 1, activate_counter: 91, new_key: new_key.clone() };
+
+        let data = message.serialize(&key, &mac);
+        assert_eq!(data.len(), KEY_ROTATION_LEN);
+        assert_eq!(&data[..13], &[MessageType::KeyRotation.to_u8(), 90, 0, 0, 0, 1, 0, 0, 0, 91, 0, 0, 0]);
+        // 新しい鍵は平文で載せない
+        assert!(!data.windows(DOWNLINK_KEY_LEN).any(|window| window == new_key.to_bytes()));
+        assert_eq!(KeyRotationMessage::verify(&data, &key, &mac), Some(message));
+
+        // 新しい鍵・宛先の違い、改ざんは拒否
+        assert_eq!(KeyRotationMessage::verify(&data, &new_key, &mac), None);
+        assert_eq!(KeyRotationMessage::verify(&data, &key, &[0; 6]), None);
+        let mut tampered = data.clone();
+        tampered[20] ^= 0x01;
+        assert_eq!(KeyRotationMessage::verify(&tampered, &key, &mac), None);
+    }
+
+    #[test]
+    fn test_defer_message_roundtrip() {
+        let defer = DeferMessage { nonce: 0x01020304, retry_after_ms: 3000 };
+        let data = defer.serialize();
+        assert_eq!(data.len(), DEFER_MESSAGE_LEN);
+        assert_eq!(&data[..5], &[0x0A, b'B', b'U', b'S', b'Y']);
+        assert_eq!(DeferMessage::deserialize(&data), Some(defer));
+        assert_eq!(PingMessage::deserialize(&data), None);
+        assert_eq!(DeferMessage::deserialize(&data[..12]), None);
+    }
+
+    #[test]
+    fn test_control_frame_roundtrip() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+
+        let command = ControlCommand::Sleep { sleep_seconds: 600 };
+        let data = command.serialize();
+        assert_eq!(data.len(), CONTROL_FRAME_HEADER_LEN + 4 + CONTROL_FRAME_CRC_LEN);
+        assert_eq!(&data[..7], &[0x0B, b'C', b'T', b'R', b'L', ControlCommand::SLEEP_ID, 4]);
+        assert_eq!(&data[7..11], &600u32.to_le_bytes());
+        assert_eq!(ControlCommand::deserialize(&data), Some(command));
+
+        // 途切れ・余分なバイト・CRC不一致・識別子違いは受理しない
+        assert_eq!(ControlCommand::deserialize(&data[..data.len() - 1]), None);
+        let mut extended = data.clone();
+        extended.push(0);
+        assert_eq!(ControlCommand::deserialize(&extended), None);
+        let mut corrupted = data.clone();
+        corrupted[8] ^= 0x01;
+        assert_eq!(ControlCommand::deserialize(&corrupted), None);
+        let mut wrong_magic = data.clone();
+        wrong_magic[1] = b'X';
+        assert_eq!(ControlCommand::deserialize(&wrong_magic), None);
+        // 旧形式（4バイトのu32）は制御フレームとみなさない
+        assert_eq!(ControlCommand::deserialize(&600u32.to_le_bytes()), None);
+
+        let resend = ControlCommand::ResendLast.serialize();
+        assert_eq!(&resend[..7], &[0x0B, b'C', b'T', b'R', b'L', ControlCommand::RESEND_LAST_ID, 0]);
+        assert_eq!(ControlCommand::deserialize(&resend), Some(ControlCommand::ResendLast));
+
+        let thumbs = ControlCommand::GetThumbs.serialize();
+        assert_eq!(&thumbs[..7], &[0x0B, b'C', b'T', b'R', b'L', ControlCommand::GET_THUMBS_ID, 0]);
+        assert_eq!(ControlCommand::deserialize(&thumbs), Some(ControlCommand::GetThumbs));
+    }
 }
@@ -1,5 +1,29 @@
+pub mod admission;
+pub mod cancellation;
+pub mod channel_hop;
+pub mod chaos;
+pub mod chunk_size;
+pub mod completion;
+pub mod data_quality;
+pub mod delivery;
+pub mod device_info;
+pub mod downlink_auth;
 pub mod frame;
+pub mod gap_map;
+pub mod legacy;
+pub mod lifecycle;
 pub mod message;
+pub mod outbound;
+pub mod peer_table;
+pub mod privacy;
+pub mod radio;
+pub mod reject;
+pub mod routing;
+pub mod telemetry;
+pub mod wire;
+
+#[cfg(feature = "channel-monitor")]
+pub mod channel_monitor;
 
 #[cfg(feature = "esp")]
 pub mod receiver;
@@ -18,15 +42,46 @@ pub enum FrameType {
     Data = 2,
     /// 転送終了を示すフレーム
     Eof = 3,
+    /// FEC告知/パリティフレーム（画像チャンクの欠落復元用）
+    Fec = 4,
+    /// 転送完了フレーム（ゲートウェイがHASH/EOFを集約して生成）
+    Complete = 5,
+    /// ゲートウェイ統計フレーム（ゲートウェイが定期的に生成）
+    Stats = 6,
+    /// 転送中断フレーム（デバイスが送信、またはゲートウェイが欠落検知時に生成）
+    Abort = 7,
+    /// 登録デバイスの定期サマリー（ゲートウェイが生成、CBOR）
+    FleetSummary = 9,
+    /// 緊急フレーム（デバイスが画像転送の途中にも割り込んで送信、ゲートウェイは溜まったチャンクより先に転送）
+    Urgent = 10,
 }
 
 impl FrameType {
+    /// すべてのフレームタイプ
+    pub const ALL: [FrameType; 9] = [
+        FrameType::Hash,
+        FrameType::Data,
+        FrameType::Eof,
+        FrameType::Fec,
+        FrameType::Complete,
+        FrameType::Stats,
+        FrameType::Abort,
+        FrameType::FleetSummary,
+        FrameType::Urgent,
+    ];
+
     /// バイト値からフレームタイプを取得
     pub fn from_byte(byte: u8) -> Option<Self> {
         match byte {
             1 => Some(FrameType::Hash),
             2 => Some(FrameType::Data),
             3 => Some(FrameType::Eof),
+            4 => Some(FrameType::Fec),
+            5 => Some(FrameType::Complete),
+            6 => Some(FrameType::Stats),
+            7 => Some(FrameType::Abort),
+            9 => Some(FrameType::FleetSummary),
+            10 => Some(FrameType::Urgent),
             _ => None,
         }
     }
@@ -42,6 +97,12 @@ impl FrameType {
             FrameType::Hash => "HASH",
             FrameType::Data => "DATA",
             FrameType::Eof => "EOF",
+            FrameType::Fec => "FEC",
+            FrameType::Complete => "COMPLETE",
+            FrameType::Stats => "STATS",
+            FrameType::Abort => "ABORT",
+            FrameType::FleetSummary => "FLEET_SUMMARY",
+            FrameType::Urgent => "URGENT",
         }
     }
 }
@@ -59,7 +120,13 @@ mod tests {
         assert_eq!(FrameType::from_byte(1), Some(FrameType::Hash));
         assert_eq!(FrameType::from_byte(2), Some(FrameType::Data));
         assert_eq!(FrameType::from_byte(3), Some(FrameType::Eof));
-        assert_eq!(FrameType::from_byte(4), None);
+        assert_eq!(FrameType::from_byte(4), Some(FrameType::Fec));
+        assert_eq!(FrameType::from_byte(5), Some(FrameType::Complete));
+        assert_eq!(FrameType::from_byte(6), Some(FrameType::Stats));
+        assert_eq!(FrameType::from_byte(7), Some(FrameType::Abort));
+        assert_eq!(FrameType::from_byte(8), None);
+        assert_eq!(FrameType::from_byte(9), Some(FrameType::FleetSummary));
+        assert_eq!(FrameType::from_byte(10), Some(FrameType::Urgent));
     }
 
     #[test]
@@ -67,5 +134,10 @@ mod tests {
         assert_eq!(FrameType::Hash.as_str(), "HASH");
         assert_eq!(FrameType::Data.as_str(), "DATA");
         assert_eq!(FrameType::Eof.as_str(), "EOF");
+        assert_eq!(FrameType::Fec.as_str(), "FEC");
+        assert_eq!(FrameType::Complete.as_str(), "COMPLETE");
+        assert_eq!(FrameType::Stats.as_str(), "STATS");
+        assert_eq!(FrameType::Abort.as_str(), "ABORT");
+        assert_eq!(FrameType::Urgent.as_str(), "URGENT");
     }
 }
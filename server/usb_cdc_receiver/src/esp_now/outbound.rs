@@ -0,0 +1,85 @@
+/// ESP-NOWで送信するダウンリンクメッセージの種類と送信時間の集計
+///
+/// 制御メッセージ（ACK・スリープコマンド・設定）はメンテナンスタスクから1件ずつ送り、
+/// 送信コールバックで到達を確認できるまで再送します。送信の開始から到達の確認までの時間を集計し、
+/// 統計フレームに含めます（再送で遅れたメッセージは最大値に現れる）。
+use std::time::Duration;
+
+/// 送信メッセージの種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutboundKind {
+    /// フレーム受信に対するACK
+    Ack,
+    /// スリープコマンド
+    Sleep,
+    /// 設定の一斉配信
+    Config,
+}
+
+impl OutboundKind {
+    /// ログ用の文字列表現
+    pub fn as_str(self) -> &'static str {
+        match self {
+            OutboundKind::Ack => "ACK",
+            OutboundKind::Sleep => "SLEEP",
+            OutboundKind::Config => "CONFIG",
+        }
+    }
+}
+
+/// 送信時間の集計
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LatencyStats {
+    /// 送信したメッセージ数
+    pub count: u32,
+    /// 送信時間の合計（ミリ秒）
+    pub total_ms: u64,
+    /// 最大送信時間（ミリ秒）
+    pub max_ms: u32,
+}
+
+impl LatencyStats {
+    /// 1件の送信時間を記録
+    pub fn record(&mut self, latency: Duration) {
+        let latency_ms = latency.as_millis().min(u32::MAX as u128) as u32;
+        self.count = self.count.saturating_add(1);
+        self.total_ms = self.total_ms.saturating_add(latency_ms as u64);
+        self.max_ms = self.max_ms.max(latency_ms);
+    }
+
+    /// 平均送信時間（ミリ秒）
+    pub fn average_ms(&self) -> u32 {
+        if self.count == 0 {
+            0
+        } else {
+            (self.total_ms / self.count as u64) as u32
+        }
+    }
+
+    /// 統計フレームの項目
+    pub fn stats_fields(&self) -> [(&'static str, u32); 3] {
+        [
+            ("CTRL_SENT", self.count),
+            ("CTRL_SEND_AVG_MS", self.average_ms()),
+            ("CTRL_SEND_MAX_MS", self.max_ms),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_latency_is_recorded_per_message() {
+        let mut stats = LatencyStats::default();
+        stats.record(Duration::from_millis(10));
+        stats.record(Duration::from_millis(30));
+
+        assert_eq!(stats.count, 2);
+        assert_eq!(stats.average_ms(), 20);
+        assert_eq!(stats.max_ms, 30);
+        assert_eq!(stats.stats_fields()[1], ("CTRL_SEND_AVG_MS", 20));
+        assert_eq!(LatencyStats::default().average_ms(), 0);
+    }
+}
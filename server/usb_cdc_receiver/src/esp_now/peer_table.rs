@@ -0,0 +1,201 @@
+/// ESP-NOWピアの登録管理
+///
+/// ESP-NOWに登録できるピアはブロードキャストピアを含めて20台までのため、設定したカメラが
+/// それより多い場合は全台を登録できません。ゲートウェイは最近通信したデバイスだけをピアとして登録し、
+/// 未登録のデバイスへ応答・送信する直前に登録します。空きがなければ最も長く通信していないピアを
+/// 解除します（LRU）。受信したフレームは送信元の最終通信時刻を更新するため、転送中のデバイスは解除されません。
+/// 登録・解除・失敗の回数は統計フレームで報告します。
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use log::info;
+
+use crate::esp_now::BROADCAST_MAC;
+use crate::mac_address::mac_str;
+use crate::sys_wrappers::{EspNowLink, SysError, SysResult, ESP_ERR_ESPNOW_EXIST, ESP_ERR_ESPNOW_NOT_FOUND};
+
+/// ESP-NOWに登録できるピア数（ESP-IDFの `ESP_NOW_MAX_TOTAL_PEER_NUM`）
+pub const ESP_NOW_MAX_PEERS: usize = 20;
+
+/// デバイスに使えるピア数（ブロードキャストピアの分を除く）
+pub const UNICAST_PEER_CAPACITY: usize = ESP_NOW_MAX_PEERS - 1;
+
+/// 登録中のピアの一覧（送信前の登録と受信時の最終通信時刻の更新に使用）
+pub static PEERS: Mutex<PeerTable> = Mutex::new(PeerTable::new(UNICAST_PEER_CAPACITY));
+
+/// ピアの登録・解除の回数
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PeerChurn {
+    /// 登録した回数
+    pub added: u32,
+    /// 空きを作るために解除した回数
+    pub evicted: u32,
+    /// 登録・解除に失敗した回数
+    pub failures: u32,
+}
+
+/// 登録中のピアと最終通信の順序
+#[derive(Debug)]
+pub struct PeerTable {
+    capacity: usize,
+    /// 登録中のピアと最後に通信した順番（小さいほど古い）
+    peers: BTreeMap<[u8; 6], u64>,
+    next_use: u64,
+    churn: PeerChurn,
+}
+
+impl PeerTable {
+    /// 登録数の上限を指定して作成します
+    pub const fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            peers: BTreeMap::new(),
+            next_use: 0,
+            churn: PeerChurn {
+                added: 0,
+                evicted: 0,
+                failures: 0,
+            },
+        }
+    }
+
+    /// 登録中のピア数
+    pub fn len(&self) -> usize {
+        self.peers.len()
+    }
+
+    /// 登録中のピアがないか
+    pub fn is_empty(&self) -> bool {
+        self.peers.is_empty()
+    }
+
+    /// 登録中か
+    pub fn contains(&self, mac: &[u8; 6]) -> bool {
+        self.peers.contains_key(mac)
+    }
+
+    fn next_use(&mut self) -> u64 {
+        self.next_use += 1;
+        self.next_use
+    }
+
+    /// 登録中のピアの最終通信を更新します（未登録なら何もしない）
+    pub fn touch(&mut self, mac: &[u8; 6]) {
+        let order = self.next_use();
+        if let Some(last_use) = self.peers.get_mut(mac) {
+            *last_use = order;
+        }
+    }
+
+    /// ピアを登録します（登録済みなら最終通信の更新のみ）
+    ///
+    /// # 戻り値
+    /// * 空きを作るために解除したピア（解除していなければNone）
+    pub fn ensure(&mut self, link: &impl EspNowLink, mac: &[u8; 6]) -> SysResult<Option<[u8; 6]>> {
+        if self.contains(mac) {
+            self.touch(mac);
+            return Ok(None);
+        }
+
+        let mut evicted = None;
+        if self.peers.len() >= self.capacity {
+            let Some(victim) = self.peers.iter().min_by_key(|(_, last_use)| **last_use).map(|(peer, _)| *peer) else {
+                return Err(SysError(ESP_ERR_ESPNOW_NOT_FOUND));
+            };
+            match link.remove_peer(&victim) {
+                Ok(()) => {}
+                Err(e) if e.code() == ESP_ERR_ESPNOW_NOT_FOUND => {}
+                Err(e) => {
+                    self.churn.failures += 1;
+                    return Err(e);
+                }
+            }
+            self.peers.remove(&victim);
+            self.churn.evicted += 1;
+            evicted = Some(victim);
+        }
+
+        match link.add_peer(mac) {
+            Ok(()) => self.churn.added += 1,
+            // 一覧の外で登録されていた場合はそのまま使う
+            Err(e) if e.code() == ESP_ERR_ESPNOW_EXIST => {}
+            Err(e) => {
+                self.churn.failures += 1;
+                return Err(e);
+            }
+        }
+        let order = self.next_use();
+        self.peers.insert(*mac, order);
+        Ok(evicted)
+    }
+
+    /// 統計フレームの項目（`<登録数>/<登録>/<解除>/<失敗>` 形式）を返して回数をリセットします
+    pub fn take_stats_field(&mut self) -> String {
+        let churn = std::mem::take(&mut self.churn);
+        format!("{}/{}/{}/{}", self.peers.len(), churn.added, churn.evicted, churn.failures)
+    }
+}
+
+/// 送信前にピアを登録します（ブロードキャストアドレスは起動時に登録済みのため何もしない）
+pub fn ensure_peer(link: &impl EspNowLink, mac: &[u8; 6]) -> SysResult<()> {
+    if *mac == BROADCAST_MAC {
+        return Ok(());
+    }
+    let mut peers = PEERS.lock().map_err(|_| SysError(-1))?;
+    if let Some(evicted) = peers.ensure(link, mac)? {
+        info!("ESP-NOW peer table full: evicted {} for {}", mac_str(&evicted), mac_str(mac));
+    }
+    Ok(())
+}
+
+/// 受信したデバイスの最終通信を更新します
+pub fn touch_peer(mac: &[u8; 6]) {
+    if let Ok(mut peers) = PEERS.lock() {
+        peers.touch(mac);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sys_wrappers::mock::MockSys;
+
+    const MAC_A: [u8; 6] = [0x34, 0xab, 0x95, 0xfb, 0x3f, 0xc4];
+    const MAC_B: [u8; 6] = [0x34, 0xab, 0x95, 0xfb, 0x3f, 0xc5];
+    const MAC_C: [u8; 6] = [0x34, 0xab, 0x95, 0xfb, 0x3f, 0xc6];
+
+    #[test]
+    fn test_evicts_least_recently_used_peer() {
+        let link = MockSys::new();
+        let mut table = PeerTable::new(2);
+        assert_eq!(table.ensure(&link, &MAC_A), Ok(None));
+        assert_eq!(table.ensure(&link, &MAC_B), Ok(None));
+        // Aから受信したので、次に空きを作るときはBを解除する
+        table.touch(&MAC_A);
+        assert_eq!(table.ensure(&link, &MAC_C), Ok(Some(MAC_B)));
+        assert_eq!(*link.peers.lock().unwrap(), vec![MAC_A, MAC_C]);
+        assert!(link.send(&MAC_C, b"PONG").is_ok());
+        assert!(link.send(&MAC_B, b"PONG").is_err());
+
+        // 登録済みなら登録し直さない
+        assert_eq!(table.ensure(&link, &MAC_C), Ok(None));
+        assert_eq!(table.take_stats_field(), "2/3/1/0");
+        assert_eq!(table.take_stats_field(), "2/0/0/0");
+    }
+
+    #[test]
+    fn test_counts_failures_and_reuses_existing_peers() {
+        let link = MockSys::new();
+        let mut table = PeerTable::new(1);
+        // 一覧の外で登録済みのピアはそのまま使う
+        link.peers.lock().unwrap().push(MAC_A);
+        assert_eq!(table.ensure(&link, &MAC_A), Ok(None));
+        assert!(table.contains(&MAC_A));
+
+        *link.fail_with.lock().unwrap() = Some(-1);
+        assert_eq!(table.ensure(&link, &MAC_B), Err(SysError(-1)));
+        // 解除に失敗した場合は一覧を変えない
+        assert!(table.contains(&MAC_A));
+        assert_eq!(table.take_stats_field(), "1/0/0/1");
+    }
+}
@@ -0,0 +1,81 @@
+//! プライバシーモードの詰め物とダミーフレームの除去
+//!
+//! プライバシーモードのデバイスは、チャンク長と送信間隔から撮影の有無や画像サイズを
+//! 推測されないよう、すべてのフレームをESP-NOWの最大長まで詰め、チャンクの間にダミー
+//! フレームを挟みます。どちらも再組み立ての前にここで取り除き、PCには通常のフレームだけを転送します。
+//! 対応はPing/Pongの機能フラグ（`CAPABILITY_PRIVACY`）でデバイスに伝えます。
+
+use super::frame::{is_preframed, FrameHeader, END_MARKER, FRAME_HEADER_LEN, FRAME_OVERHEAD, MARKER_LEN};
+use super::wire::WireDeserialize;
+
+/// ダミーフレームのタイプ（ゲートウェイで破棄し、PCへは転送しない）
+pub const FRAME_TYPE_DUMMY: u8 = 8;
+
+/// プライバシーモードの整形を除去した結果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PrivacyFrame<'a> {
+    /// ダミーフレーム（破棄する）
+    Dummy,
+    /// 転送するデータ（詰め物があれば取り除いた範囲）
+    Frame(&'a [u8]),
+}
+
+/// 受信データから詰め物を取り除き、ダミーフレームを判別します
+///
+/// 詰め物は終了マーカーの後ろに付くため、ヘッダのデータ長から求めた位置に終了マーカーが
+/// ある場合のみ切り詰めます。フレーム化されていないデータや形式の合わないデータはそのまま返します。
+pub fn strip_privacy(data: &[u8]) -> PrivacyFrame<'_> {
+    if !is_preframed(data) || data.len() < FRAME_HEADER_LEN {
+        return PrivacyFrame::Frame(data);
+    }
+    let Ok(header) = FrameHeader::read_wire(data) else {
+        return PrivacyFrame::Frame(data);
+    };
+    if header.frame_type == FRAME_TYPE_DUMMY {
+        return PrivacyFrame::Dummy;
+    }
+    let frame_len = match FRAME_OVERHEAD.checked_add(header.data_len as usize) {
+        Some(len) if len < data.len() => len,
+        _ => return PrivacyFrame::Frame(data),
+    };
+    if data[frame_len - MARKER_LEN..frame_len] != END_MARKER.to_be_bytes() {
+        return PrivacyFrame::Frame(data);
+    }
+    PrivacyFrame::Frame(&data[..frame_len])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::esp_now::frame::Frame;
+    use crate::esp_now::FrameType;
+
+    const MAC: [u8; 6] = [0x34, 0xab, 0x95, 0xfb, 0x3f, 0xc4];
+
+    #[test]
+    fn test_strip_padding_and_dummy_frames() {
+        let frame = Frame::new(MAC, FrameType::Eof, 12, b"EOF".to_vec()).to_bytes();
+        let mut padded = frame.clone();
+        padded.resize(250, 0);
+        assert_eq!(strip_privacy(&padded), PrivacyFrame::Frame(&frame[..]));
+        // 詰め物のないフレームはそのまま
+        assert_eq!(strip_privacy(&frame), PrivacyFrame::Frame(&frame[..]));
+
+        let mut dummy = frame.clone();
+        dummy[MARKER_LEN + 6] = FRAME_TYPE_DUMMY;
+        assert_eq!(strip_privacy(&dummy), PrivacyFrame::Dummy);
+    }
+
+    #[test]
+    fn test_unframed_or_mismatched_data_is_untouched() {
+        let raw = b"EOF!".to_vec();
+        assert_eq!(strip_privacy(&raw), PrivacyFrame::Frame(&raw[..]));
+
+        // データ長の位置に終了マーカーがなければ切り詰めない
+        let mut frame = Frame::new(MAC, FrameType::Data, 1, vec![0xAA; 10]).to_bytes();
+        let end = frame.len();
+        frame[end - 1] ^= 0xFF;
+        frame.resize(250, 0);
+        assert_eq!(strip_privacy(&frame), PrivacyFrame::Frame(&frame[..]));
+    }
+}
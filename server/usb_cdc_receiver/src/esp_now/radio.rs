@@ -0,0 +1,104 @@
+//! ESP-NOWの無線設定（送信レート・送信パワー・AMPDU）
+//!
+//! 既定のWi-Fi設定ではスループットが頭打ちになるため、配備ごとにESP-NOWの送信レートを固定できるようにします。
+//! 適用した設定は疎通確認（Ping/Pong）に付加してデバイスと交換し、不一致を診断できるようにします。
+//! ワイヤ形式はデバイス側の `communication::esp_now::radio` と同じです。
+
+/// 無線設定のワイヤ表現の長さ: [RATE(1)] [TX_POWER_DBM(1)] [FLAGS(1)]
+pub const RADIO_SETTINGS_LEN: usize = 3;
+/// 送信レート未指定（ESP-IDFの既定値）を表す値
+const RATE_DEFAULT: u8 = 0xFF;
+/// フラグ: AMPDU送信が有効
+const FLAG_AMPDU_TX: u8 = 0x01;
+
+#[path = "../../../../devices/m5stack_unit_cam/src/communication/esp_now/phy_rate.rs"]
+mod phy_rate;
+pub use phy_rate::EspNowRate;
+
+/// 適用した無線設定
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RadioSettings {
+    /// 固定した送信レート（Noneは既定値）
+    pub rate: Option<EspNowRate>,
+    /// 最大送信パワー（dBm）
+    pub tx_power_dbm: i8,
+    /// AMPDU送信が有効か（sdkconfig の CONFIG_ESP_WIFI_AMPDU_TX_ENABLED）
+    pub ampdu_tx: bool,
+}
+
+impl RadioSettings {
+    /// ワイヤ表現に変換します
+    pub fn to_wire(self) -> [u8; RADIO_SETTINGS_LEN] {
+        [
+            self.rate.map_or(RATE_DEFAULT, EspNowRate::phy_rate),
+            self.tx_power_dbm as u8,
+            if self.ampdu_tx { FLAG_AMPDU_TX } else { 0 },
+        ]
+    }
+
+    /// ワイヤ表現から変換します（長さが一致しない場合は None）
+    pub fn from_wire(data: &[u8]) -> Option<Self> {
+        let [rate, tx_power, flags] = <[u8; RADIO_SETTINGS_LEN]>::try_from(data).ok()?;
+        Some(Self {
+            rate: (rate != RATE_DEFAULT).then_some(EspNowRate::from_phy_rate(rate)),
+            tx_power_dbm: tx_power as i8,
+            ampdu_tx: flags & FLAG_AMPDU_TX != 0,
+        })
+    }
+
+    /// 相手と一致しない項目（送信パワーは配置に応じて異なってよいため比較しない）
+    pub fn mismatches(self, peer: &RadioSettings) -> Vec<&'static str> {
+        let mut fields = Vec::new();
+        if self.rate != peer.rate {
+            fields.push("rate");
+        }
+        if self.ampdu_tx != peer.ampdu_tx {
+            fields.push("ampdu");
+        }
+        fields
+    }
+
+    /// ログ用の要約（`rate=24M txp=20dBm ampdu=off`）
+    pub fn summary(self) -> String {
+        format!(
+            "rate={} txp={}dBm ampdu={}",
+            self.rate.map_or_else(|| "default".to_string(), EspNowRate::name),
+            self.tx_power_dbm,
+            if self.ampdu_tx { "on" } else { "off" }
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_radio_settings_wire_roundtrip() {
+        let settings = RadioSettings {
+            rate: EspNowRate::parse("MCS3"),
+            tx_power_dbm: -2,
+            ampdu_tx: true,
+        };
+        assert_eq!(RadioSettings::from_wire(&settings.to_wire()), Some(settings));
+        let default_rate = RadioSettings { rate: None, ..settings };
+        assert_eq!(default_rate.to_wire()[0], 0xFF);
+        assert_eq!(RadioSettings::from_wire(&default_rate.to_wire()), Some(default_rate));
+        assert_eq!(RadioSettings::from_wire(&[]), None);
+        assert_eq!(settings.summary(), "rate=MCS3 txp=-2dBm ampdu=on");
+    }
+
+    #[test]
+    fn test_mismatches_ignore_tx_power() {
+        let device = RadioSettings {
+            rate: EspNowRate::parse("24M"),
+            tx_power_dbm: 20,
+            ampdu_tx: false,
+        };
+        assert!(device.mismatches(&RadioSettings { tx_power_dbm: 8, ..device }).is_empty());
+        assert_eq!(
+            device.mismatches(&RadioSettings { rate: None, ampdu_tx: true, ..device }),
+            vec!["rate", "ampdu"]
+        );
+    }
+}
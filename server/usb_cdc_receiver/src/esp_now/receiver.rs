@@ -1,39 +1,302 @@
-use crate::esp_now::frame::{create_frame, detect_frame_type, is_preframed};
-use crate::esp_now::FrameType;
-use crate::mac_address::format_mac_address;
-use crate::queue::ReceivedData;
+use crate::esp_now::admission::{self, Admission, ADMISSION};
+use crate::esp_now::cancellation::{self, AbortReason};
+use crate::esp_now::channel_hop;
+#[cfg(feature = "chaos")]
+use crate::esp_now::chaos::ChaosRegistry;
+use crate::esp_now::chunk_size::ChunkSizeAdvert;
+use crate::esp_now::frame::{is_preframed, FRAME_HEADER_LEN, MARKER_LEN, MAC_ADDRESS_LEN};
+use crate::esp_now::legacy::{LegacyFrame, LegacyShim};
+use crate::esp_now::peer_table;
+use crate::esp_now::privacy::{strip_privacy, PrivacyFrame};
+use crate::esp_now::radio::RadioSettings;
+use crate::esp_now::reject::{RejectMessage, RejectReason, REJECT_LIMITER, UNKNOWN_DEVICE_RETRY_AFTER_MS};
+use crate::esp_now::routing::FrameRoute;
+use crate::esp_now::sender;
+use crate::downlink_window::{PendingCommandLookup, DOWNLINK_WINDOW};
+use crate::frame_stats::{self, FrameKind, FrameOutcome};
+use crate::lifetime_stats;
+use crate::esp_now::{DeferMessage, FrameType, PingMessage, PongMessage};
+use crate::mac_address::mac_str as format_mac_str;
+use crate::queue::{data_queue, ReceivedData};
+use crate::sys_wrappers::esp::{esp_now_send, uptime_us, EspSys};
 use esp_idf_svc::sys::{esp_now_recv_info_t, ESP_NOW_ETH_ALEN};
-use log::{debug, error, warn};
-use std::collections::HashMap;
+use log::{debug, error, info, warn};
 use std::slice;
-use std::sync::Mutex;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::Instant;
 
-/// ESP-NOW送信元ごとのシーケンス番号を管理するグローバル変数
-static SEQUENCE_COUNTERS: Mutex<Option<HashMap<[u8; 6], u32>>> = Mutex::new(None);
+/// 送信したPong数（統計フレーム用）
+pub static PONGS_SENT: AtomicU32 = AtomicU32::new(0);
 
-/// 送信元MACアドレスごとにシーケンス番号を管理するためのヘルパー関数
-fn get_sequence_number(mac_address: [u8; 6], reset: bool) -> u32 {
-    // シーケンスカウンターを取得または初期化
-    let mut counters = SEQUENCE_COUNTERS.lock().unwrap();
+/// 送信した延期要求数（統計フレーム用）
+pub static DEFERS_SENT: AtomicU32 = AtomicU32::new(0);
 
-    // 初めて使用される場合はHashMapを初期化
-    if counters.is_none() {
-        *counters = Some(HashMap::new());
+/// 送信した拒否メッセージ数（統計フレーム用）
+pub static REJECTS_SENT: AtomicU32 = AtomicU32::new(0);
+
+/// 起動時に適用した無線設定（Pongで返す）
+static RADIO_SETTINGS: OnceLock<RadioSettings> = OnceLock::new();
+
+/// Pongで通知するDATAチャンクの大きさ（起動時に登録）
+static CHUNK_SIZE_ADVERT: OnceLock<ChunkSizeAdvert> = OnceLock::new();
+
+/// 受け付けるデバイスのMACアドレス（登録した場合のみ、それ以外のデバイスには拒否メッセージを返す）
+static KNOWN_DEVICES: OnceLock<Vec<[u8; 6]>> = OnceLock::new();
+
+/// 送信待ちのコマンド数を数える関数（HASHフレームへの応答に使用）
+static PENDING_COMMANDS: OnceLock<PendingCommandLookup> = OnceLock::new();
+
+/// 適用した無線設定を登録します（起動時に1回）
+pub fn set_radio_settings(settings: RadioSettings) {
+    if RADIO_SETTINGS.set(settings).is_err() {
+        warn!("Radio settings are already registered");
+    }
+}
+
+/// Pongで通知するDATAチャンクの大きさを登録します（起動時に1回）
+pub fn set_chunk_size_advert(advert: ChunkSizeAdvert) {
+    if CHUNK_SIZE_ADVERT.set(advert).is_err() {
+        warn!("Chunk size advertisement is already registered");
     }
+}
+
+/// 受け付けるデバイスを登録します（起動時に1回、登録しなければすべてのデバイスを受け付ける）
+pub fn set_known_devices(macs: Vec<[u8; 6]>) {
+    if KNOWN_DEVICES.set(macs).is_err() {
+        warn!("Known devices are already registered");
+    }
+}
+
+/// 送信待ちのコマンド数を数える関数を登録します（起動時に1回）
+pub fn set_pending_commands(lookup: PendingCommandLookup) {
+    if PENDING_COMMANDS.set(lookup).is_err() {
+        warn!("Pending command lookup is already registered");
+    }
+}
 
-    if let Some(ref mut counter_map) = *counters {
-        // リセットフラグが立っている場合、シーケンス番号をリセット
-        if reset {
-            counter_map.insert(mac_address, 0);
-            0
-        } else {
-            // 既存のカウンターを取得するか、新しいカウンターを作成
-            let counter = counter_map.entry(mac_address).or_insert(0);
-            *counter = counter.wrapping_add(1); // オーバーフロー対策
-            *counter
+/// 受信フレームへの故障注入（`chaos` フィーチャー、`CHAOS` コマンドで変更）
+#[cfg(feature = "chaos")]
+pub static CHAOS: Mutex<ChaosRegistry> = Mutex::new(ChaosRegistry::new());
+
+/// 受信フレームに故障を注入し、処理するフレームを返します（無効ならNone）
+#[cfg(feature = "chaos")]
+fn inject_chaos(mac_address: [u8; 6], data: &[u8]) -> Option<Vec<Vec<u8>>> {
+    match CHAOS.lock() {
+        Ok(mut chaos) => chaos.apply(mac_address, data),
+        Err(_) => {
+            error!("ESP-NOW CB: Chaos registry lock poisoned.");
+            None
+        }
+    }
+}
+
+/// 旧形式（プロトコルv0）で受信したペイロード数（統計フレーム用）
+pub static LEGACY_FRAMES: AtomicU32 = AtomicU32::new(0);
+
+/// 旧形式デバイスのペイロードを合成フレームに変換するシム
+static LEGACY_SHIM: Mutex<Option<LegacyShim>> = Mutex::new(None);
+
+/// 自前でフレーム化されたペイロードのフレームタイプを返します
+fn preframed_type(data: &[u8]) -> Option<FrameType> {
+    if !is_preframed(data) || data.len() < FRAME_HEADER_LEN {
+        return None;
+    }
+    FrameType::from_byte(data[MARKER_LEN + MAC_ADDRESS_LEN])
+}
+
+/// 旧形式ペイロードを合成フレームに変換します
+fn convert_legacy_payload(mac_address: [u8; 6], data: &[u8]) -> Option<LegacyFrame> {
+    let mut shim = match LEGACY_SHIM.lock() {
+        Ok(shim) => shim,
+        Err(_) => {
+            error!("ESP-NOW CB: Legacy shim lock poisoned.");
+            return None;
+        }
+    };
+    LEGACY_FRAMES.fetch_add(1, Ordering::Relaxed);
+    Some(shim.get_or_insert_with(LegacyShim::new).convert(mac_address, data))
+}
+
+/// 同時転送数が上限に達していれば、Pongの代わりに延期要求を返します
+///
+/// # 戻り値
+/// * 延期を要求した（Pongを返さない）場合はtrue
+fn defer_if_busy(mac_address: [u8; 6], mac_str: &str, ping: &PingMessage) -> bool {
+    let admission = match ADMISSION.lock() {
+        Ok(mut admission) => admission.admit(mac_address, Instant::now()),
+        Err(_) => {
+            error!("ESP-NOW CB: Admission lock poisoned.");
+            return false;
+        }
+    };
+    let Admission::Defer { retry_after_ms } = admission else {
+        return false;
+    };
+    let defer = DeferMessage {
+        nonce: ping.nonce,
+        retry_after_ms,
+    }
+    .serialize();
+    let token = sender::register_unawaited_send(mac_address);
+    match esp_now_send(&mac_address, &defer) {
+        Ok(()) => {
+            DEFERS_SENT.fetch_add(1, Ordering::Relaxed);
+            info!(
+                "ESP-NOW CB [{}]: PING nonce={} -> DEFER (retry after {}ms)",
+                mac_str, ping.nonce, retry_after_ms
+            );
+        }
+        Err(e) => {
+            if let Some(token) = token {
+                sender::cancel_unawaited_send(token);
+            }
+            warn!("ESP-NOW CB [{}]: Failed to send DEFER: error code {}", mac_str, e.code());
+        }
+    }
+    true
+}
+
+/// デバイスのデータを破棄したことを拒否メッセージで知らせます
+///
+/// 同じデバイスへ同じ理由の拒否は `REJECT_RESEND_INTERVAL` に1回だけ送ります。
+/// 受信コールバックとUSB送信タスクから呼び出します。
+pub fn send_reject(mac_address: [u8; 6], mac_str: &str, reason: RejectReason, nonce: u32, retry_after_ms: u32) {
+    let should_send = match REJECT_LIMITER.lock() {
+        Ok(mut limiter) => limiter.should_send(mac_address, reason, Instant::now()),
+        Err(_) => {
+            error!("ESP-NOW CB: Reject limiter lock poisoned.");
+            return;
+        }
+    };
+    if !should_send {
+        return;
+    }
+    if let Err(e) = peer_table::ensure_peer(&EspSys, &mac_address) {
+        warn!("ESP-NOW CB [{}]: Failed to register peer: error code {}", mac_str, e.code());
+        return;
+    }
+    let reject = RejectMessage { reason, nonce, retry_after_ms }.serialize();
+    let token = sender::register_unawaited_send(mac_address);
+    match esp_now_send(&mac_address, &reject) {
+        Ok(()) => {
+            REJECTS_SENT.fetch_add(1, Ordering::Relaxed);
+            warn!(
+                "ESP-NOW CB [{}]: -> REJECT {} (retry after {}ms)",
+                mac_str,
+                reason.as_str(),
+                retry_after_ms
+            );
+        }
+        Err(e) => {
+            if let Some(token) = token {
+                sender::cancel_unawaited_send(token);
+            }
+            warn!("ESP-NOW CB [{}]: Failed to send REJECT: error code {}", mac_str, e.code());
+        }
+    }
+}
+
+/// 登録されていないデバイスのフレームか（登録がなければすべて受け付ける）
+fn is_unknown_device(mac_address: &[u8; 6]) -> bool {
+    KNOWN_DEVICES.get().is_some_and(|known| !known.contains(mac_address))
+}
+
+/// 疎通確認Pingに即座にPongを返します
+///
+/// デバイスは短いタイムアウトで待つため、送信キューを経由せずコールバック内で送信します。
+/// Pongにはデータキューの空き率を含め、デバイスが転送可否を判断できるようにします。
+/// Pingに無線設定が付加されていれば自身の設定を返し、不一致を警告します。
+/// チャンネルホッピングが有効で、デバイスが予定に従える場合は受信チャンネルの予定を付加します。
+/// デバイスが対応していれば、DATAチャンクの推奨と上限の大きさを付加します。
+/// 同時転送数が上限に達している場合は延期要求を返します。
+/// 送信元がピアとして登録されていなければ、応答の前に登録します。
+fn reply_to_ping(mac_address: [u8; 6], mac_str: &str, ping: &PingMessage) {
+    if let Err(e) = peer_table::ensure_peer(&EspSys, &mac_address) {
+        warn!("ESP-NOW CB [{}]: Failed to register peer: error code {}", mac_str, e.code());
+        return;
+    }
+    if let Ok(mut window) = DOWNLINK_WINDOW.lock() {
+        window.record_ping(mac_address, ping);
+    }
+    if defer_if_busy(mac_address, mac_str, ping) {
+        return;
+    }
+    let queue_free_percent = match data_queue::get_queue_usage() {
+        Ok((used, capacity)) if capacity > 0 => (capacity.saturating_sub(used) * 100 / capacity) as u8,
+        _ => 0,
+    };
+    let local_radio = RADIO_SETTINGS.get().copied();
+    if let (Some(device_radio), Some(local_radio)) = (ping.radio, local_radio) {
+        let mismatches = local_radio.mismatches(&device_radio);
+        if !mismatches.is_empty() {
+            warn!(
+                "ESP-NOW CB [{}]: radio settings differ ({}): device {}, gateway {}",
+                mac_str,
+                mismatches.join(","),
+                device_radio.summary(),
+                local_radio.summary()
+            );
+        }
+    }
+    let hop = channel_hop::current_announcement(Instant::now());
+    let chunk_size = CHUNK_SIZE_ADVERT.get().copied();
+    let pong = PongMessage::reply_to(ping, queue_free_percent, local_radio, chunk_size, hop).serialize();
+    let token = sender::register_unawaited_send(mac_address);
+    match esp_now_send(&mac_address, &pong) {
+        Ok(()) => {
+            PONGS_SENT.fetch_add(1, Ordering::Relaxed);
+            info!(
+                "ESP-NOW CB [{}]: PING nonce={} -> PONG (queue free {}%{})",
+                mac_str,
+                ping.nonce,
+                queue_free_percent,
+                if ping.privacy { ", privacy mode" } else { "" }
+            );
+        }
+        Err(e) => {
+            if let Some(token) = token {
+                sender::cancel_unawaited_send(token);
+            }
+            warn!("ESP-NOW CB [{}]: Failed to send PONG: error code {}", mac_str, e.code());
+        }
+    }
+}
+
+/// HASHフレームに送信待ちのコマンドの有無を返します（Pingでコマンド待機の短縮を示したデバイスのみ）
+///
+/// デバイスは応答を待たずにチャンクを送り続けるため、Pongと同じくコールバック内で送信します。
+fn acknowledge_hash(mac_address: [u8; 6], mac_str: &str) {
+    let queued = PENDING_COMMANDS.get().map_or(0, |count| count(&mac_address));
+    let ack = match DOWNLINK_WINDOW.lock() {
+        Ok(mut window) => window.on_hash(mac_address, queued, uptime_us()),
+        Err(_) => {
+            error!("ESP-NOW CB: Downlink window lock poisoned.");
+            return;
+        }
+    };
+    let Some(ack) = ack else {
+        return;
+    };
+    if let Err(e) = peer_table::ensure_peer(&EspSys, &mac_address) {
+        warn!("ESP-NOW CB [{}]: Failed to register peer: error code {}", mac_str, e.code());
+        return;
+    }
+    let token = sender::register_unawaited_send(mac_address);
+    match esp_now_send(&mac_address, &ack.serialize()) {
+        Ok(()) => info!(
+            "ESP-NOW CB [{}]: HASH -> ACK (commands pending: {}, count {}, last loss {})",
+            mac_str,
+            ack.commands_pending,
+            ack.pending_count,
+            ack.loss_percent.map_or_else(|| "-".to_string(), |loss| format!("{}%", loss))
+        ),
+        Err(e) => {
+            if let Some(token) = token {
+                sender::cancel_unawaited_send(token);
+            }
+            warn!("ESP-NOW CB [{}]: Failed to send HASH ACK: error code {}", mac_str, e.code());
         }
-    } else {
-        0 // 万が一ロックが取得できない場合のフォールバック
     }
 }
 
@@ -83,12 +346,55 @@ where
         }
     };
 
-    // ログ用MACアドレス文字列を作成
-    let mac_str = format_mac_address(&mac_array);
+    // ログ用MACアドレス文字列を作成（受信ごとに呼ばれるためヒープを使わない）
+    let mac_str = format_mac_str(&mac_array);
+    peer_table::touch_peer(&mac_array);
 
     // データスライスの取得
     let data_slice = unsafe { slice::from_raw_parts(data, data_len as usize) };
 
+    let rssi = unsafe { (*info).rx_ctrl.as_ref() }.map(|rx_ctrl| rx_ctrl.rssi() as i8);
+
+    #[cfg(feature = "chaos")]
+    if let Some(frames) = inject_chaos(mac_array, data_slice) {
+        let mut success = true;
+        for frame in frames {
+            success &= process_frame(producer, mac_array, &mac_str, &frame, rssi);
+        }
+        return success;
+    }
+
+    process_frame(producer, mac_array, &mac_str, data_slice, rssi)
+}
+
+/// 受信した1フレームを処理します（Pingへの応答、ABORTの処理、データキューへの投入）
+fn process_frame<P>(producer: &mut P, mac_array: [u8; 6], mac_str: &str, data_slice: &[u8], rssi: Option<i8>) -> bool
+where
+    P: FnMut(ReceivedData) -> bool,
+{
+    // 登録されていないデバイスのフレームは破棄し、ペアリングのやり直しを促す
+    if is_unknown_device(&mac_array) {
+        let nonce = PingMessage::deserialize(data_slice).map_or(0, |ping| ping.nonce);
+        debug!("ESP-NOW CB [{}]: Dropped frame from unknown device ({} bytes).", mac_str, data_slice.len());
+        send_reject(mac_array, mac_str, RejectReason::UnknownDevice, nonce, UNKNOWN_DEVICE_RETRY_AFTER_MS);
+        return true;
+    }
+
+    // 疎通確認Pingはキューに入れず、その場で応答する
+    if let Some(ping) = PingMessage::deserialize(data_slice) {
+        reply_to_ping(mac_array, mac_str, &ping);
+        return true;
+    }
+
+    // プライバシーモードのダミーフレームは破棄し、詰め物は再組み立ての前に取り除く
+    let data_slice = match strip_privacy(data_slice) {
+        PrivacyFrame::Dummy => {
+            debug!("ESP-NOW CB [{}]: Dropped privacy dummy frame ({} bytes).", mac_str, data_slice.len());
+            return true;
+        }
+        PrivacyFrame::Frame(frame) => frame,
+    };
+
     // フレーム化 or パススルー判定
     //
     // ESP-NOW ペイロードが既に START_MARKER (0xFACEAABB) で始まるバイナリフレームの場合
@@ -96,33 +402,66 @@ where
     // 再ラップすると外側フレームの DATA ペイロードにフレーム構造体が入り込み、
     // サーバー側で生 JPEG データとして解釈されて画像が破損する。
     //
-    // テキスト形式 ("HASH:...", "EOF!") は従来どおりバイナリフレームに包んで転送する。
+    // 旧形式 ("HASH:...", 生チャンク, "EOF!") は互換シムで転送番号・チャンク番号を補って合成フレームにする。
+    // ABORTフレームはキューに入れず、キュー内に残る同じ転送のチャンクを即座に無効化する
+    let route = preframed_type(data_slice).map(FrameRoute::of);
+    if route == Some(FrameRoute::Cancel) {
+        warn!("ESP-NOW CB [{}]: Received ABORT frame, cancelling queued chunks.", mac_str);
+        cancellation::cancel_transfer(mac_array, AbortReason::Device);
+        admission::finish_transfer(&mac_array);
+        return true;
+    }
+
+    if let Ok(mut admission) = ADMISSION.lock() {
+        admission.touch(mac_array, Instant::now());
+    }
+    if preframed_type(data_slice) == Some(FrameType::Hash) {
+        acknowledge_hash(mac_array, mac_str);
+    }
+    // 緊急フレームは緊急キューへ入れ、キュー内に溜まったチャンクより先にPCへ転送する
+    let urgent = route == Some(FrameRoute::Urgent);
+    if urgent {
+        info!(
+            "ESP-NOW CB [{}]: Received URGENT frame ({} bytes), forwarding ahead of queued chunks.",
+            mac_str,
+            data_slice.len()
+        );
+    }
+
     let (framed_data, drop_label, is_critical_eof) = if is_preframed(data_slice) {
         debug!(
             "ESP-NOW CB [{}]: Pre-framed binary payload ({} bytes), forwarding without re-wrapping.",
-            mac_str, data_len
+            mac_str, data_slice.len()
         );
         (data_slice.to_vec(), "preframed", false)
     } else {
-        let frame_type = detect_frame_type(data_slice);
+        let Some(legacy) = convert_legacy_payload(mac_array, data_slice) else {
+            return false;
+        };
+        let frame_type = legacy.frame.frame_type();
         let is_eof = frame_type == FrameType::Eof;
-        let is_hash = frame_type == FrameType::Hash;
 
+        if legacy.first_seen {
+            warn!(
+                "ESP-NOW CB [{}]: Legacy (protocol v0) payload detected. Converting until the device is reflashed.",
+                mac_str
+            );
+        }
         if is_eof {
             warn!("ESP-NOW CB [{}]: Received EOF marker (b\"EOF!\").", mac_str);
-        } else if is_hash {
-            warn!("ESP-NOW CB [{}]: Received HASH marker.", mac_str);
+        } else if frame_type == FrameType::Hash {
+            warn!("ESP-NOW CB [{}]: Received HASH marker (legacy frame_id={}).", mac_str, legacy.frame_id);
         }
 
-        let seq_num = get_sequence_number(mac_array, is_eof || is_hash);
-        let framed = create_frame(mac_array, data_slice, frame_type, seq_num);
-
+        let framed = legacy.frame.to_bytes();
         debug!(
-            "ESP-NOW CB [{}]: Received chunk ({} bytes, type={}, seq={}). Framed: {} bytes.",
+            "ESP-NOW CB [{}]: Received legacy chunk ({} bytes, type={}, seq={}, frame_id={}, chunk={:?}). Framed: {} bytes.",
             mac_str,
-            data_len,
+            data_slice.len(),
             frame_type.as_str(),
-            seq_num,
+            legacy.frame.sequence_number(),
+            legacy.frame_id,
+            legacy.chunk_index,
             framed.len()
         );
 
@@ -130,15 +469,23 @@ where
     };
 
     // フレーム化されたデータをキューに追加
+    let frame_kind = FrameKind::of_frame(&framed_data);
+    frame_stats::record(&mac_array, frame_kind, FrameOutcome::Received);
     let received_data = ReceivedData {
         mac: mac_array,
         data: framed_data,
+        epoch: cancellation::current_epoch(&mac_array),
+        received_at: Instant::now(),
+        rssi,
+        urgent,
     };
 
     // 生産者関数を呼び出して、キューへの追加を試みる
     let success = producer(received_data);
 
     if !success {
+        lifetime_stats::record_errors(1);
+        frame_stats::record(&mac_array, frame_kind, FrameOutcome::Errored);
         warn!(
             "ESP-NOW CB [{}]: Data queue full! Dropping {} frame.",
             mac_str, drop_label
@@ -149,6 +496,14 @@ where
                 mac_str
             );
         }
+        // 画像チャンクの欠落は転送全体を無効にするため、以降のチャンクをPCへ送らない
+        // （部分転送の救済が有効な場合は転送を続け、欠落は完了イベントのギャップマップで報告する）
+        let is_data = drop_label == FrameType::Data.as_str() || preframed_type(data_slice) == Some(FrameType::Data);
+        if is_data && !cancellation::partial_salvage_enabled() {
+            cancellation::cancel_transfer(mac_array, AbortReason::QueueOverflow);
+            let retry_after_ms = ADMISSION.lock().map_or(0, |admission| admission.retry_after_ms());
+            send_reject(mac_array, mac_str, RejectReason::Quota, 0, retry_after_ms);
+        }
     }
 
     success
@@ -159,29 +514,6 @@ mod tests {
     use super::*;
     use std::cell::RefCell;
 
-    #[test]
-    fn test_get_sequence_number() {
-        // テスト前にカウンターをリセット
-        {
-            let mut counters = SEQUENCE_COUNTERS.lock().unwrap();
-            *counters = Some(HashMap::new());
-        }
-
-        let mac1 = [0x11, 0x22, 0x33, 0x44, 0x55, 0x66];
-        let mac2 = [0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0xff];
-
-        // 初期値は0
-        assert_eq!(get_sequence_number(mac1, false), 1);
-        assert_eq!(get_sequence_number(mac1, false), 2);
-
-        // 別のMACアドレスは独立したカウンター
-        assert_eq!(get_sequence_number(mac2, false), 1);
-
-        // リセット後は0に戻る
-        assert_eq!(get_sequence_number(mac1, true), 0);
-        assert_eq!(get_sequence_number(mac1, false), 1);
-    }
-
     #[test]
     fn test_process_esp_now_data() {
         // mock_info と mock_data は実際のテストでは使わない
@@ -0,0 +1,241 @@
+//! デバイスのデータを破棄したことを知らせる拒否メッセージ
+//!
+//! ゲートウェイがデータキュー溢れ・未登録のデバイス・チェックサムエラーの多発でフレームを破棄しても、
+//! デバイスはそれを知らずに送り続けます。そこで理由と再試行までの時間を拒否メッセージ（`RejectMessage`）で
+//! 返し、デバイスが転送の打ち切り・待機・再ペアリングを選べるようにします。
+//! 同じデバイスへ同じ理由の拒否を送り続けないよう、`RejectLimiter` で `REJECT_RESEND_INTERVAL` の間隔を空けます。
+
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use super::message::MessageType;
+use super::wire::{WireDeserialize, WireSerialize};
+
+crate::wire_struct! {
+    /// 拒否メッセージのワイヤ表現
+    struct RejectWire {
+        message_type: u8 => Le,
+        magic: [u8; 4] => Le,
+        reason: u8 => Le,
+        nonce: u32 => Le,
+        retry_after_ms: u32 => Le,
+    }
+}
+
+/// 拒否メッセージの識別子
+const REJECT_MAGIC: [u8; 4] = *b"RJCT";
+/// 拒否メッセージのバイト長
+pub const REJECT_MESSAGE_LEN: usize = RejectWire::WIRE_SIZE;
+const _: () = assert!(REJECT_MESSAGE_LEN == 14);
+
+/// チェックサムエラーの多発で拒否したデバイスへ返す再試行までの時間（ミリ秒）
+pub const CHECKSUM_STORM_RETRY_AFTER_MS: u32 = 30_000;
+/// 未登録のデバイスへ返す再試行までの時間（ミリ秒）
+pub const UNKNOWN_DEVICE_RETRY_AFTER_MS: u32 = 600_000;
+
+/// 同じデバイスへ同じ理由の拒否を再び送るまでの間隔
+pub const REJECT_RESEND_INTERVAL: Duration = Duration::from_secs(5);
+/// 拒否の送信時刻を覚えておくデバイスと理由の組の上限（超えたら最も古い組を忘れる）
+pub const REJECT_LIMITER_CAPACITY: usize = 16;
+
+/// チェックサムエラーを数える期間
+pub const CHECKSUM_STORM_WINDOW: Duration = Duration::from_secs(10);
+/// 期間内にこの回数のチェックサムエラーがあれば多発とみなす
+pub const CHECKSUM_STORM_THRESHOLD: usize = 8;
+
+/// 拒否の理由（デバイスの対応の目安を併記）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RejectReason {
+    /// 受け付け数の上限（データキュー溢れ）。転送を打ち切り、再試行までの時間を待つ
+    Quota = 0x01,
+    /// 登録されていないデバイス。送信を止め、ペアリングをやり直す
+    UnknownDevice = 0x02,
+    /// チェックサムエラーの多発。転送を打ち切り、再試行までの時間を待つ
+    ChecksumStorm = 0x03,
+}
+
+impl RejectReason {
+    /// u8から変換します
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0x01 => Some(Self::Quota),
+            0x02 => Some(Self::UnknownDevice),
+            0x03 => Some(Self::ChecksumStorm),
+            _ => None,
+        }
+    }
+
+    /// ログと統計用の名前
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Quota => "QUOTA",
+            Self::UnknownDevice => "UNKNOWN_DEVICE",
+            Self::ChecksumStorm => "CHECKSUM_STORM",
+        }
+    }
+}
+
+/// 拒否メッセージ（ゲートウェイ → デバイス）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RejectMessage {
+    pub reason: RejectReason,
+    /// Pingへの応答ならそのnonce、それ以外は0
+    pub nonce: u32,
+    /// 再試行までの待機時間の目安（ミリ秒）
+    pub retry_after_ms: u32,
+}
+
+impl RejectMessage {
+    /// バイナリ形式にシリアライズ
+    ///
+    /// フォーマット:
+    /// ```text
+    /// [MSG_TYPE(1)] ["RJCT"(4)] [REASON(1)] [NONCE(4)] [RETRY_AFTER_MS(4)]
+    /// ```
+    pub fn serialize(&self) -> Vec<u8> {
+        RejectWire {
+            message_type: MessageType::Reject.to_u8(),
+            magic: REJECT_MAGIC,
+            reason: self.reason as u8,
+            nonce: self.nonce,
+            retry_after_ms: self.retry_after_ms,
+        }
+        .to_wire()
+    }
+
+    /// バイナリデータから拒否メッセージをデシリアライズ
+    pub fn deserialize(data: &[u8]) -> Option<Self> {
+        if data.len() != REJECT_MESSAGE_LEN {
+            return None;
+        }
+        let wire = RejectWire::read_wire(data).ok()?;
+        if wire.message_type != MessageType::Reject.to_u8() || wire.magic != REJECT_MAGIC {
+            return None;
+        }
+        Some(Self {
+            reason: RejectReason::from_u8(wire.reason)?,
+            nonce: wire.nonce,
+            retry_after_ms: wire.retry_after_ms,
+        })
+    }
+}
+
+/// デバイスと理由の組ごとに、拒否を送る間隔を空けます
+#[derive(Debug, Default)]
+pub struct RejectLimiter {
+    last_sent: BTreeMap<([u8; 6], RejectReason), Instant>,
+}
+
+impl RejectLimiter {
+    /// 空の状態で作成します
+    pub const fn new() -> Self {
+        Self { last_sent: BTreeMap::new() }
+    }
+
+    /// 拒否を送るか判定し、送る場合は送信時刻を記録します
+    pub fn should_send(&mut self, mac: [u8; 6], reason: RejectReason, now: Instant) -> bool {
+        let key = (mac, reason);
+        if let Some(&sent_at) = self.last_sent.get(&key) {
+            if now.saturating_duration_since(sent_at) < REJECT_RESEND_INTERVAL {
+                return false;
+            }
+        } else if self.last_sent.len() >= REJECT_LIMITER_CAPACITY {
+            if let Some(oldest) = self.last_sent.iter().min_by_key(|(_, &sent_at)| sent_at).map(|(&key, _)| key) {
+                self.last_sent.remove(&oldest);
+            }
+        }
+        self.last_sent.insert(key, now);
+        true
+    }
+}
+
+/// デバイスごとのチェックサムエラーを数え、多発を検知します
+#[derive(Debug, Default)]
+pub struct ChecksumStormDetector {
+    errors: BTreeMap<[u8; 6], VecDeque<Instant>>,
+}
+
+impl ChecksumStormDetector {
+    /// 空の状態で作成します
+    pub const fn new() -> Self {
+        Self { errors: BTreeMap::new() }
+    }
+
+    /// チェックサムエラーを記録し、多発に達したらtrueを返します（数え直しは次のエラーから）
+    pub fn record_error(&mut self, mac: [u8; 6], now: Instant) -> bool {
+        let errors = self.errors.entry(mac).or_default();
+        while errors
+            .front()
+            .is_some_and(|&at| now.saturating_duration_since(at) >= CHECKSUM_STORM_WINDOW)
+        {
+            errors.pop_front();
+        }
+        errors.push_back(now);
+        if errors.len() < CHECKSUM_STORM_THRESHOLD {
+            return false;
+        }
+        self.errors.remove(&mac);
+        true
+    }
+}
+
+/// 拒否の送信間隔（受信コールバックとUSB送信タスクで共有）
+pub static REJECT_LIMITER: Mutex<RejectLimiter> = Mutex::new(RejectLimiter::new());
+
+/// チェックサムエラーの多発の検知（USB送信タスクで記録）
+pub static CHECKSUM_STORMS: Mutex<ChecksumStormDetector> = Mutex::new(ChecksumStormDetector::new());
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAC: [u8; 6] = [0x24, 0x6F, 0x28, 0xAA, 0xBB, 0xCC];
+
+    #[test]
+    fn test_reject_message_roundtrip() {
+        let reject = RejectMessage { reason: RejectReason::ChecksumStorm, nonce: 7, retry_after_ms: 30_000 };
+        let data = reject.serialize();
+        assert_eq!(data.len(), REJECT_MESSAGE_LEN);
+        assert_eq!(&data[..6], &[0x0F, b'R', b'J', b'C', b'T', 0x03]);
+        assert_eq!(RejectMessage::deserialize(&data), Some(reject));
+        assert_eq!(RejectMessage::deserialize(&data[..13]), None);
+
+        let mut unknown_reason = data.clone();
+        unknown_reason[5] = 0x7F;
+        assert_eq!(RejectMessage::deserialize(&unknown_reason), None);
+    }
+
+    #[test]
+    fn test_limiter_spaces_rejects_per_device_and_reason() {
+        let mut limiter = RejectLimiter::new();
+        let start = Instant::now();
+        assert!(limiter.should_send(MAC, RejectReason::Quota, start));
+        assert!(!limiter.should_send(MAC, RejectReason::Quota, start + Duration::from_secs(1)));
+        assert!(limiter.should_send(MAC, RejectReason::ChecksumStorm, start + Duration::from_secs(1)));
+        assert!(limiter.should_send(MAC, RejectReason::Quota, start + REJECT_RESEND_INTERVAL));
+
+        // 上限を超えたら最も古い組を忘れる
+        for i in 0..REJECT_LIMITER_CAPACITY as u8 {
+            limiter.should_send([i; 6], RejectReason::Quota, start + Duration::from_secs(10 + u64::from(i)));
+        }
+        assert!(limiter.last_sent.len() <= REJECT_LIMITER_CAPACITY);
+    }
+
+    #[test]
+    fn test_checksum_storm_needs_threshold_within_window() {
+        let mut detector = ChecksumStormDetector::new();
+        let start = Instant::now();
+        // 期間をまたいだエラーは数えない
+        for i in 0..CHECKSUM_STORM_THRESHOLD as u64 {
+            assert!(!detector.record_error(MAC, start + CHECKSUM_STORM_WINDOW * i as u32));
+        }
+        let burst = start + CHECKSUM_STORM_WINDOW * 100;
+        for i in 1..CHECKSUM_STORM_THRESHOLD as u64 {
+            assert!(!detector.record_error(MAC, burst + Duration::from_millis(i)));
+        }
+        assert!(detector.record_error(MAC, burst + Duration::from_millis(100)));
+        // 検知の後は数え直す
+        assert!(!detector.record_error(MAC, burst + Duration::from_millis(101)));
+    }
+}
@@ -0,0 +1,98 @@
+//! フレームタイプごとの処理の振り分け
+//!
+//! フレームタイプを処理の系統（画像・テレメトリ・転送終了・中断・ゲートウェイ生成・緊急）に対応付けます。
+//! 振り分けは網羅的な `match` で書き、ワイルドカードを使いません。フレームタイプを追加すると
+//! `FrameRoute::of` と各系統の処理側（`CompletionTracker::observe` など）がコンパイルエラーになり、
+//! 処理の追加漏れを防ぎます（OTAなど新しい系統を増やす場合も同様）。
+
+use super::FrameType;
+
+/// フレームの処理系統
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameRoute {
+    /// 画像チャンク（DATA・FEC）。転送を集約してPCへ転送する
+    Image,
+    /// テレメトリ（HASH）。完了イベントに集約し、単体では転送しない
+    Telemetry,
+    /// 転送終了（EOF）
+    EndOfTransfer,
+    /// 転送中断（ABORT）。受信コールバックでキュー内の残りチャンクを無効化する
+    Cancel,
+    /// ゲートウェイが生成するフレーム。そのままPCへ転送する
+    Gateway,
+    /// 緊急フレーム（URGENT）。専用のキューで溜まったチャンクを追い越し、転送の集約に含めずPCへ転送する
+    Urgent,
+}
+
+impl FrameRoute {
+    /// フレームタイプの処理系統
+    pub fn of(frame_type: FrameType) -> Self {
+        match frame_type {
+            FrameType::Data | FrameType::Fec => FrameRoute::Image,
+            FrameType::Hash => FrameRoute::Telemetry,
+            FrameType::Eof => FrameRoute::EndOfTransfer,
+            FrameType::Abort => FrameRoute::Cancel,
+            FrameType::Complete | FrameType::Stats | FrameType::FleetSummary => FrameRoute::Gateway,
+            FrameType::Urgent => FrameRoute::Urgent,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// フレームタイプのワイヤ上の値（網羅的な match のため、型を追加すると更新が必要）
+    fn wire_value(frame_type: FrameType) -> u8 {
+        match frame_type {
+            FrameType::Hash => 1,
+            FrameType::Data => 2,
+            FrameType::Eof => 3,
+            FrameType::Fec => 4,
+            FrameType::Complete => 5,
+            FrameType::Stats => 6,
+            FrameType::Abort => 7,
+            FrameType::FleetSummary => 9,
+            FrameType::Urgent => 10,
+        }
+    }
+
+    #[test]
+    fn test_all_frame_types_round_trip() {
+        for frame_type in FrameType::ALL {
+            assert_eq!(frame_type.to_byte(), wire_value(frame_type));
+            assert_eq!(FrameType::from_byte(frame_type.to_byte()), Some(frame_type));
+        }
+        // ALL に漏れがあれば from_byte で解釈できる値の数と一致しない
+        let decodable = (0..=u8::MAX).filter(|&byte| FrameType::from_byte(byte).is_some()).count();
+        assert_eq!(decodable, FrameType::ALL.len());
+    }
+
+    #[test]
+    fn test_frame_type_names_are_unique() {
+        let mut names: Vec<&str> = FrameType::ALL.iter().map(FrameType::as_str).collect();
+        names.sort_unstable();
+        names.dedup();
+        assert_eq!(names.len(), FrameType::ALL.len());
+    }
+
+    #[test]
+    fn test_every_frame_type_has_a_route() {
+        let routes: Vec<(FrameType, FrameRoute)> =
+            FrameType::ALL.iter().map(|&frame_type| (frame_type, FrameRoute::of(frame_type))).collect();
+        assert_eq!(
+            routes,
+            [
+                (FrameType::Hash, FrameRoute::Telemetry),
+                (FrameType::Data, FrameRoute::Image),
+                (FrameType::Eof, FrameRoute::EndOfTransfer),
+                (FrameType::Fec, FrameRoute::Image),
+                (FrameType::Complete, FrameRoute::Gateway),
+                (FrameType::Stats, FrameRoute::Gateway),
+                (FrameType::Abort, FrameRoute::Cancel),
+                (FrameType::FleetSummary, FrameRoute::Gateway),
+                (FrameType::Urgent, FrameRoute::Urgent),
+            ]
+        );
+    }
+}
@@ -1,6 +1,78 @@
-use esp_idf_svc::sys::esp_now_send;
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use esp_idf_svc::nvs::{EspDefaultNvsPartition, EspNvs, NvsDefault};
+use esp_idf_svc::sys::EspError;
 use log::{error, info, warn};
 
+use super::delivery::{DeliveryStats, DeliveryStatus, DeliveryTracker};
+use super::downlink_auth::{DownlinkCounter, DownlinkKey};
+use super::message::{
+    BroadcastConfigMessage, ConfigUpdateMessage, ControlCommand, FileChunkMessage, KeyRotationMessage, SignedSleepCommand,
+    BROADCAST_MAC,
+};
+use super::peer_table;
+use super::outbound::{LatencyStats, OutboundKind};
+use crate::file_transfer::FileChunk;
+use crate::key_rotation::{KeyDelivery, KeyRotationRegistry, DEVICE_KEY_RECORD_LEN};
+use crate::sys_wrappers::esp::EspSys;
+use crate::sys_wrappers::EspNowLink;
+
+/// ダウンリンクカウンタのNVS名前空間
+const DOWNLINK_NVS_NAMESPACE: &str = "downlink";
+/// 予約済みカウンタ上限のキー
+const DOWNLINK_NVS_KEY_RESERVED: &str = "reserved";
+
+/// デバイスごとの署名鍵を引く関数（宛先MACとコマンドカウンタ、プロビジョニングした鍵ならNone）
+pub type DeviceKeyLookup = fn(&[u8; 6], u32) -> Option<DownlinkKey>;
+
+/// デバイスごとの鍵の記録のNVSキー（"k" + MACの16進12文字）
+fn device_key_nvs_key(mac: &[u8; 6]) -> String {
+    format!("k{}", hex::encode(mac))
+}
+
+/// 送信コールバックを待つ時間（ミリ秒、MAC層の再送を含めても通常は数ミリ秒で届く）
+const SEND_CONFIRM_TIMEOUT_MS: u64 = 100;
+/// 制御メッセージの到達を確認できなかった場合の最大試行回数
+const CONTROL_DELIVERY_ATTEMPTS: u32 = 3;
+/// 再送までの待機時間（ミリ秒）
+const DELIVERY_RETRY_DELAY_MS: u32 = 200;
+
+/// 送信と送信コールバックの対応付け
+static DELIVERIES: Mutex<DeliveryTracker> = Mutex::new(DeliveryTracker::new());
+/// 送信コールバックの到着通知
+static DELIVERY_SIGNAL: Condvar = Condvar::new();
+
+/// 送信コールバックの結果を記録します（ESP-NOWの送信コールバックから呼び出し）
+pub fn record_send_result(mac_address: [u8; 6], delivered: bool) {
+    match DELIVERIES.lock() {
+        Ok(mut deliveries) => {
+            if deliveries.complete(mac_address, delivered).is_none() {
+                warn!("ESP-NOW send CB: no outstanding send for {:02X?}", mac_address);
+            }
+        }
+        Err(_) => {
+            error!("ESP-NOW send CB: delivery tracker lock poisoned.");
+            return;
+        }
+    }
+    DELIVERY_SIGNAL.notify_all();
+}
+
+/// 結果を待たない送信（Pongなど）を登録します（`esp_now_send` の直前に呼び出す）
+///
+/// 送信コールバックを待機中の送信と取り違えないよう、結果を待たない送信も登録が必要です。
+pub fn register_unawaited_send(mac_address: [u8; 6]) -> Option<u32> {
+    DELIVERIES.lock().ok().map(|mut deliveries| deliveries.register(mac_address, false))
+}
+
+/// 結果を待たない送信を取り消します（`esp_now_send` が失敗した場合）
+pub fn cancel_unawaited_send(token: u32) {
+    if let Ok(mut deliveries) = DELIVERIES.lock() {
+        deliveries.cancel(token);
+    }
+}
+
 /// ESP-NOW送信エラー
 #[derive(Debug)]
 pub enum EspNowSendError {
@@ -10,17 +82,170 @@ pub enum EspNowSendError {
     SendFailed(i32),
     /// 無効なMACアドレス
     InvalidMacAddress,
+    /// コマンドカウンタを払い出せない（NVS保存失敗または上限到達）
+    CounterUnavailable,
+    /// 送信コールバックで到達を確認できない（リンク層の失敗またはタイムアウト）
+    NotDelivered(DeliveryStatus),
+    /// 署名器がない（設定の一斉配信には downlink_auth_key が必要）
+    SignerRequired,
+    /// メッセージが長すぎる
+    MessageTooLong,
+    /// デバイスごとの鍵をNVSへ保存できない
+    KeyStoreFailed,
+}
+
+/// スリープコマンドの署名器
+///
+/// カウンタの予約上限をNVSに保存し、ゲートウェイ再起動後もカウンタが戻らないようにします。
+pub struct DownlinkSigner {
+    key: DownlinkKey,
+    counter: DownlinkCounter,
+    nvs: EspNvs<NvsDefault>,
+    device_keys: Option<DeviceKeyLookup>,
+}
+
+impl DownlinkSigner {
+    /// NVSから予約済みのカウンタ上限を読み込んで署名器を作成
+    pub fn open(partition: EspDefaultNvsPartition, key: DownlinkKey) -> Result<Self, EspError> {
+        let nvs = EspNvs::new(partition, DOWNLINK_NVS_NAMESPACE, true)?;
+        let reserved_until = nvs.get_u32(DOWNLINK_NVS_KEY_RESERVED)?.unwrap_or(0);
+        info!("Downlink signer ready (counter resumes after {})", reserved_until);
+        Ok(Self {
+            key,
+            counter: DownlinkCounter::resume(reserved_until),
+            nvs,
+            device_keys: None,
+        })
+    }
+
+    /// 更新したデバイスの鍵でコマンドを署名するよう設定
+    pub fn with_device_keys(mut self, lookup: DeviceKeyLookup) -> Self {
+        self.device_keys = Some(lookup);
+        self
+    }
+
+    /// NVSに保存したデバイスごとの鍵を読み込みます（読めない記録はプロビジョニングした鍵のまま）
+    pub fn load_device_keys(&self, registry: &mut KeyRotationRegistry, macs: &[[u8; 6]]) {
+        let mut buffer = [0u8; DEVICE_KEY_RECORD_LEN];
+        for mac in macs {
+            match self.nvs.get_raw(&device_key_nvs_key(mac), &mut buffer) {
+                Ok(Some(record)) => {
+                    if !registry.restore(*mac, record) {
+                        warn!("Ignoring malformed downlink key record for {:02X?}", mac);
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => warn!("Failed to read downlink key record for {:02X?}: {:?}", mac, e),
+            }
+        }
+    }
+
+    /// 宛先MACとコマンドカウンタに対する署名鍵
+    fn key_for(&self, target_mac: &[u8; 6], counter: u32) -> DownlinkKey {
+        self.device_keys
+            .and_then(|lookup| lookup(target_mac, counter))
+            .unwrap_or_else(|| self.key.clone())
+    }
+
+    /// 宛先MAC向けに署名したスリープコマンドを作成
+    fn sign_sleep(&mut self, target_mac: &[u8; 6], sleep_seconds: u32) -> Result<Vec<u8>, EspNowSendError> {
+        let counter = self.allocate_counter()?;
+        let key = self.key_for(target_mac, counter);
+        Ok(SignedSleepCommand { counter, sleep_seconds }.serialize(&key, target_mac))
+    }
+
+    /// コマンドカウンタを払い出します（予約上限を更新した場合はNVSへ保存）
+    fn allocate_counter(&mut self) -> Result<u32, EspNowSendError> {
+        let (counter, reservation) = self.counter.allocate().ok_or_else(|| {
+            error!("Downlink counter exhausted; rotate downlink_auth_key");
+            EspNowSendError::CounterUnavailable
+        })?;
+        if let Some(reserved_until) = reservation {
+            // 予約を保存できなければ、再起動後にカウンタが戻る恐れがあるため署名しない
+            self.nvs.set_u32(DOWNLINK_NVS_KEY_RESERVED, reserved_until).map_err(|e| {
+                error!("Failed to persist downlink counter reservation: {:?}", e);
+                EspNowSendError::CounterUnavailable
+            })?;
+        }
+        Ok(counter)
+    }
 }
 
 /// ESP-NOW送信機能
+///
+/// ESP-NOWピアは main.rs で登録済み。制御メッセージはメンテナンスタスクから1件ずつ送信し、
+/// 送信の成否を送信コールバックで確認して、届かなかったメッセージは再送します。
 pub struct EspNowSender {
-    // 設定フィールドは特に不要 - ESP-NOWピアは main.rs で登録済み
+    signer: Option<DownlinkSigner>,
+    delivery_stats: DeliveryStats,
+    latency: LatencyStats,
 }
 
 impl EspNowSender {
     /// 新しいESP-NOW送信インスタンスを作成
     pub fn new() -> Self {
-        Self {}
+        Self {
+            signer: None,
+            delivery_stats: DeliveryStats::default(),
+            latency: LatencyStats::default(),
+        }
+    }
+
+    /// スリープコマンドを署名して送信するよう設定
+    pub fn with_downlink_signer(mut self, signer: DownlinkSigner) -> Self {
+        self.signer = Some(signer);
+        self
+    }
+
+    /// 制御メッセージを送信し、送信コールバックで到達を確認できるまで再送します
+    ///
+    /// # 戻り値
+    /// * `Result<(), EspNowSendError>` - このメッセージの送信結果
+    pub fn send_control(
+        &mut self,
+        mac_address: [u8; 6],
+        kind: OutboundKind,
+        data: &[u8],
+    ) -> Result<(), EspNowSendError> {
+        use esp_idf_svc::hal::delay::FreeRtos;
+
+        let started = Instant::now();
+        let mut result = Ok(());
+        for attempt in 1..=CONTROL_DELIVERY_ATTEMPTS {
+            result = self.send_data(mac_address, data);
+            let will_retry =
+                attempt < CONTROL_DELIVERY_ATTEMPTS && matches!(result, Err(EspNowSendError::NotDelivered(_)));
+            if let Err(EspNowSendError::NotDelivered(status)) = &result {
+                self.delivery_stats.record_attempt(*status, will_retry);
+                warn!(
+                    "✗ {} to {:02X?} not confirmed ({:?}, attempt {}/{})",
+                    kind.as_str(),
+                    mac_address,
+                    status,
+                    attempt,
+                    CONTROL_DELIVERY_ATTEMPTS
+                );
+            }
+            if !will_retry {
+                break;
+            }
+            FreeRtos::delay_ms(DELIVERY_RETRY_DELAY_MS);
+        }
+        self.delivery_stats.record(kind, result.is_ok());
+        if result.is_ok() {
+            self.latency.record(started.elapsed());
+        }
+        result
+    }
+
+    /// 制御メッセージの送信開始から到達確認までの時間の統計
+    pub fn latency_stats(&self) -> &LatencyStats {
+        &self.latency
+    }
+
+    /// 送信コールバックで確認した到達統計
+    pub fn delivery_stats(&self) -> &DeliveryStats {
+        &self.delivery_stats
     }
 
     /// MACアドレス文字列を[u8; 6]配列に変換
@@ -45,18 +270,17 @@ impl EspNowSender {
         Ok(mac)
     }
 
-    /// ESP-NOWでデータを送信
+    /// ESP-NOWでデータを送信し、送信コールバックで到達を確認
     /// 
     /// # 引数
     /// * `mac_address` - 送信先のMACアドレス
     /// * `data` - 送信するデータ
     /// 
     /// # 戻り値
-    /// * `Result<(), EspNowSendError>` - 成功時はOk(())、失敗時はエラー
+    /// * `Result<(), EspNowSendError>` - 到達を確認できればOk(())、失敗時はエラー
     pub fn send_data(&self, mac_address: [u8; 6], data: &[u8]) -> Result<(), EspNowSendError> {
         use esp_idf_svc::hal::delay::FreeRtos;
         
-        // ピアは register_esp_now_peers() で登録済みなので、直接送信
         info!("ESP-NOW low-level send: MAC={:02X}:{:02X}:{:02X}:{:02X}:{:02X}:{:02X}, {} bytes",
               mac_address[0], mac_address[1], mac_address[2],
               mac_address[3], mac_address[4], mac_address[5], data.len());
@@ -64,34 +288,66 @@ impl EspNowSender {
         // ESP-NOWの送信前に遅延を追加（チャンネル競合防止）
         FreeRtos::delay_ms(300);
         
-        let result = unsafe {
-            esp_now_send(
-                mac_address.as_ptr(),
-                data.as_ptr(),
-                data.len(),
-            )
-        };
+        // ピアの登録数には上限があるため、未登録なら最も長く通信していないピアと入れ替えて登録する
+        if let Err(e) = peer_table::ensure_peer(&EspSys, &mac_address) {
+            error!("✗ ESP-NOW peer registration failed: error code {}", e.code());
+            return Err(EspNowSendError::SendFailed(e.code()));
+        }
+
+        // コールバックが送信直後に届いても取りこぼさないよう、送信前に登録する
+        let token = DELIVERIES
+            .lock()
+            .map(|mut deliveries| deliveries.register(mac_address, true))
+            .map_err(|_| EspNowSendError::SendFailed(-1))?;
+
+        if let Err(e) = EspSys.send(&mac_address, data) {
+            error!("✗ ESP-NOW raw send failed: error code {}", e.code());
+            if let Ok(mut deliveries) = DELIVERIES.lock() {
+                deliveries.cancel(token);
+            }
+            return Err(EspNowSendError::SendFailed(e.code()));
+        }
+
+        match Self::wait_for_delivery(token) {
+            DeliveryStatus::Delivered => {
+                info!("✓ ESP-NOW send confirmed by send callback");
+                Ok(())
+            }
+            status => Err(EspNowSendError::NotDelivered(status)),
+        }
+    }
 
-        if result == 0 {
-            info!("✓ ESP-NOW raw send successful: error code {}", result);
-            Ok(())
-        } else {
-            error!("✗ ESP-NOW raw send failed: error code {}", result);
-            Err(EspNowSendError::SendFailed(result))
+    /// 送信コールバックの結果を待ちます（期限を過ぎたらTimedOut）
+    fn wait_for_delivery(token: u32) -> DeliveryStatus {
+        let deadline = Instant::now() + Duration::from_millis(SEND_CONFIRM_TIMEOUT_MS);
+        let Ok(mut deliveries) = DELIVERIES.lock() else {
+            return DeliveryStatus::TimedOut;
+        };
+        loop {
+            if let Some(status) = deliveries.take_result(token) {
+                return status;
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                deliveries.abandon(token);
+                return DeliveryStatus::TimedOut;
+            }
+            deliveries = match DELIVERY_SIGNAL.wait_timeout(deliveries, remaining) {
+                Ok((deliveries, _)) => deliveries,
+                Err(_) => return DeliveryStatus::TimedOut,
+            };
         }
     }
 
-    /// スリープコマンドを送信（リトライ機構付き）
+    /// スリープコマンドを送信（到達を確認できるまで再送）
     /// 
     /// # 引数
     /// * `mac_str` - 送信先のMACアドレス文字列 ("XX:XX:XX:XX:XX:XX")
     /// * `sleep_seconds` - スリープ時間（秒）
     /// 
     /// # 戻り値
-    /// * `Result<(), EspNowSendError>` - 成功時はOk(())、失敗時はエラー
-    pub fn send_sleep_command(&self, mac_str: &str, sleep_seconds: u32) -> Result<(), EspNowSendError> {
-        use esp_idf_svc::hal::delay::FreeRtos;
-        
+    /// * `Result<(), EspNowSendError>` - 到達を確認できればOk(())、失敗時はエラー
+    pub fn send_sleep_command(&mut self, mac_str: &str, sleep_seconds: u32) -> Result<(), EspNowSendError> {
         info!("=== ESP-NOW Sleep Command Sending ===");
         info!("Target MAC: {}", mac_str);
         info!("Sleep Duration: {} seconds", sleep_seconds);
@@ -101,36 +357,141 @@ impl EspNowSender {
               mac_address[0], mac_address[1], mac_address[2],
               mac_address[3], mac_address[4], mac_address[5]);
         
-        // バイナリ形式でスリープ時間を送信（4バイトのu32）
-        let sleep_data = sleep_seconds.to_le_bytes();
-        info!("Sleep data bytes: {:02X} {:02X} {:02X} {:02X}",
-              sleep_data[0], sleep_data[1], sleep_data[2], sleep_data[3]);
-        
-        // リトライ機構付きで送信
-        const MAX_RETRIES: u32 = 3;
-        const RETRY_DELAY_MS: u32 = 200;
+        // 署名器があれば認証付き形式、なければ制御フレーム
+        // （`legacy-sleep-command` フィーチャーでは未更新のデバイス向けに4バイトのu32のみを送る）
+        let sleep_data = match self.signer.as_mut() {
+            Some(signer) => signer.sign_sleep(&mac_address, sleep_seconds)?,
+            None if cfg!(feature = "legacy-sleep-command") => sleep_seconds.to_le_bytes().to_vec(),
+            None => ControlCommand::Sleep { sleep_seconds }.serialize(),
+        };
+        info!("Sleep data bytes: {:02X?}", sleep_data);
         
-        for attempt in 1..=MAX_RETRIES {
-            info!("Attempting ESP-NOW send (attempt {}/{})", attempt, MAX_RETRIES);
-            
-            let result = self.send_data(mac_address, &sleep_data);
-            
-            match &result {
-                Ok(()) => {
-                    info!("✓ Sleep command sent successfully via ESP-NOW (attempt {})", attempt);
-                    return Ok(());
-                }
-                Err(e) => {
-                    warn!("✗ ESP-NOW send attempt {} failed: {:?}", attempt, e);
-                    if attempt < MAX_RETRIES {
-                        info!("Waiting {}ms before retry...", RETRY_DELAY_MS);
-                        FreeRtos::delay_ms(RETRY_DELAY_MS);
-                    }
-                }
+        // 再送は send_control が送信コールバックの結果に応じて行う
+        match self.send_control(mac_address, OutboundKind::Sleep, &sleep_data) {
+            Ok(()) => {
+                info!("✓ Sleep command delivered via ESP-NOW to {}", mac_str);
+                Ok(())
+            }
+            Err(e) => {
+                error!("✗ Sleep command was not delivered to {}: {:?}", mac_str, e);
+                Err(e)
             }
         }
-        
-        error!("✗ All {} ESP-NOW send attempts failed for {}", MAX_RETRIES, mac_str);
-        Err(EspNowSendError::SendFailed(-1)) // All retries failed
+    }
+
+    /// 全デバイス向けの設定をブロードキャストアドレスへ署名して送信
+    ///
+    /// 設定IDが未定（初回送信）の場合はコマンドカウンタから払い出します。
+    /// 再配信では同じIDを使い、適用済みのデバイスには無視させます。
+    ///
+    /// # 戻り値
+    /// * `Result<u32, EspNowSendError>` - 送信した設定ID
+    pub fn send_broadcast_config(&mut self, config_id: Option<u32>, config: &str) -> Result<u32, EspNowSendError> {
+        let signer = self.signer.as_mut().ok_or(EspNowSendError::SignerRequired)?;
+        let config_id = match config_id {
+            Some(config_id) => config_id,
+            None => signer.allocate_counter()?,
+        };
+        let message = BroadcastConfigMessage {
+            config_id,
+            config: config.to_string(),
+        };
+        let data = message.serialize(&signer.key).ok_or(EspNowSendError::MessageTooLong)?;
+        info!("Broadcasting config {} (id={}, {} bytes)", config, config_id, data.len());
+        self.send_control(BROADCAST_MAC, OutboundKind::Config, &data)?;
+        Ok(config_id)
+    }
+
+    /// デバイスへ設定更新を送信（署名器があればコマンドカウンタで署名）
+    pub fn send_config_update(&mut self, mac_address: [u8; 6], config: &str) -> Result<(), EspNowSendError> {
+        let (counter, key) = match self.signer.as_mut() {
+            Some(signer) => {
+                let counter = signer.allocate_counter()?;
+                (counter, Some(signer.key_for(&mac_address, counter)))
+            }
+            None => (0, None),
+        };
+        let message = ConfigUpdateMessage {
+            counter,
+            config: config.to_string(),
+        };
+        let data = message.serialize(key.as_ref(), &mac_address).ok_or(EspNowSendError::MessageTooLong)?;
+        info!("Sending config update {} to {:02X?} (counter={})", config, mac_address, counter);
+        self.send_control(mac_address, OutboundKind::Config, &data)
+    }
+
+    /// デバイスへファイルの断片を送信（署名器があればコマンドカウンタで署名）
+    pub fn send_file_chunk(&mut self, chunk: &FileChunk) -> Result<(), EspNowSendError> {
+        let (counter, key) = match self.signer.as_mut() {
+            Some(signer) => {
+                let counter = signer.allocate_counter()?;
+                (counter, Some(signer.key_for(&chunk.mac, counter)))
+            }
+            None => (0, None),
+        };
+        let message = FileChunkMessage {
+            counter,
+            slot: chunk.slot.clone(),
+            file_crc: chunk.file_crc,
+            total_len: chunk.total_len,
+            offset: chunk.offset,
+            data: chunk.data.clone(),
+        };
+        let data = message.serialize(key.as_ref(), &chunk.mac).ok_or(EspNowSendError::MessageTooLong)?;
+        info!(
+            "Sending file chunk {}@{}+{} to {:02X?} (counter={})",
+            chunk.slot,
+            chunk.offset,
+            chunk.data.len(),
+            chunk.mac,
+            counter
+        );
+        self.send_control(chunk.mac, OutboundKind::Config, &data)
+    }
+
+    /// 現在の鍵で暗号化・署名した鍵更新を送信
+    ///
+    /// # 戻り値
+    /// * `Result<u32, EspNowSendError>` - 新しい鍵に切り替えるコマンドカウンタ（鍵更新のカウンタ + 1）
+    pub fn send_key_rotation(&mut self, delivery: &KeyDelivery) -> Result<u32, EspNowSendError> {
+        let signer = self.signer.as_mut().ok_or(EspNowSendError::SignerRequired)?;
+        let counter = signer.allocate_counter()?;
+        let activate_counter = counter.checked_add(1).ok_or(EspNowSendError::CounterUnavailable)?;
+        let message = KeyRotationMessage {
+            counter,
+            epoch: delivery.epoch,
+            activate_counter,
+            new_key: delivery.new_key.clone(),
+        };
+        let current_key = delivery.current_key.as_ref().unwrap_or(&signer.key);
+        let data = message.serialize(current_key, &delivery.mac);
+        info!(
+            "Sending key rotation to {:02X?} (epoch={}, counter={}, activate={})",
+            delivery.mac, delivery.epoch, counter, activate_counter
+        );
+        self.send_control(delivery.mac, OutboundKind::Config, &data)?;
+        Ok(activate_counter)
+    }
+
+    /// デバイスごとの鍵の記録をNVSへ保存
+    pub fn store_device_key(&mut self, mac_address: [u8; 6], record: &[u8]) -> Result<(), EspNowSendError> {
+        let signer = self.signer.as_mut().ok_or(EspNowSendError::SignerRequired)?;
+        signer.nvs.set_raw(&device_key_nvs_key(&mac_address), record).map_err(|e| {
+            error!("Failed to persist downlink key record for {:02X?}: {:?}", mac_address, e);
+            EspNowSendError::KeyStoreFailed
+        })?;
+        Ok(())
+    }
+
+    /// 直前に送信した画像の再送を要求する制御フレームを送信
+    pub fn send_resend_last(&mut self, mac_address: [u8; 6]) -> Result<(), EspNowSendError> {
+        info!("Sending resend request to {:02X?}", mac_address);
+        self.send_control(mac_address, OutboundKind::Config, &ControlCommand::ResendLast.serialize())
+    }
+
+    /// 保持している直近のサムネイルの送信を要求する制御フレームを送信
+    pub fn send_get_thumbs(&mut self, mac_address: [u8; 6]) -> Result<(), EspNowSendError> {
+        info!("Sending thumbnail request to {:02X?}", mac_address);
+        self.send_control(mac_address, OutboundKind::Config, &ControlCommand::GetThumbs.serialize())
     }
 }
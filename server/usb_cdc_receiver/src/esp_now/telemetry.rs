@@ -0,0 +1,158 @@
+//! HASHペイロードのテレメトリ（タグ付きフィールドの並び）
+//!
+//! HASHペイロードは `KEY:VALUE` をカンマで区切った並びです（撮影時刻のようにキーのない項目も含む）。
+//! 湿度・RSSI・画質スコアなどの項目が増えても古いゲートウェイ・ホストが壊れないよう、
+//! 解析は項目をキーで引くだけにし、知らないキーの項目・キーのない項目・並び順はそのまま保持して
+//! 完了イベントでホストへ転送します。デバイスは `TLM_V`（形式のバージョン）と
+//! `TLM_CAPS`（機能ビット、16進数）を付加します。付加していない旧デバイスはバージョン0とみなします。
+
+/// このゲートウェイが解釈できるテレメトリのバージョン
+pub const TELEMETRY_VERSION: u32 = 1;
+
+/// バージョンのキー
+pub const TELEMETRY_VERSION_KEY: &str = "TLM_V";
+/// 機能ビットのキー
+pub const TELEMETRY_CAPS_KEY: &str = "TLM_CAPS";
+
+/// 機能ビット: 温度センサーを搭載（なければ `TEMP` はダミー値）
+pub const TELEMETRY_CAP_TEMPERATURE: u32 = 1 << 0;
+/// 機能ビット: TDSセンサーを搭載（なければ `TDS_VOLT` はダミー値）
+pub const TELEMETRY_CAP_TDS: u32 = 1 << 1;
+/// 機能ビット: 中継時に知らない項目をそのまま転送する
+pub const TELEMETRY_CAP_PASSTHROUGH: u32 = 1 << 2;
+
+/// テレメトリの1項目
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TelemetryField<'a> {
+    /// `KEY:VALUE` 形式の項目
+    Tagged { key: &'a str, value: &'a str },
+    /// キーのない項目（撮影時刻など）
+    Bare(&'a str),
+}
+
+impl<'a> TelemetryField<'a> {
+    fn parse(item: &'a str) -> Self {
+        match item.split_once(':') {
+            Some((key, value)) if is_key(key) => TelemetryField::Tagged { key, value },
+            _ => TelemetryField::Bare(item),
+        }
+    }
+
+    /// キー（キーのない項目ならNone）
+    pub fn key(&self) -> Option<&'a str> {
+        match self {
+            TelemetryField::Tagged { key, .. } => Some(key),
+            TelemetryField::Bare(_) => None,
+        }
+    }
+}
+
+/// キーは英大文字・数字・`_` のみ（`2026/02/11 12:00:00` のような時刻をキーと誤認しない）
+fn is_key(key: &str) -> bool {
+    !key.is_empty() && key.bytes().all(|byte| byte.is_ascii_uppercase() || byte.is_ascii_digit() || byte == b'_')
+}
+
+/// 解析したHASHペイロード（元の並びと表記を保持）
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TelemetryFields<'a> {
+    fields: Vec<TelemetryField<'a>>,
+}
+
+impl<'a> TelemetryFields<'a> {
+    /// HASHペイロードを解析します（UTF-8でなければNone）
+    pub fn parse(payload: &'a [u8]) -> Option<Self> {
+        let payload = std::str::from_utf8(payload).ok()?;
+        Some(Self {
+            fields: payload.split(',').map(TelemetryField::parse).collect(),
+        })
+    }
+
+    /// すべての項目（元の並び順）
+    pub fn fields(&self) -> &[TelemetryField<'a>] {
+        &self.fields
+    }
+
+    /// キーの値（前後の空白を除く、同じキーが複数あれば最初の値）
+    pub fn get(&self, key: &str) -> Option<&'a str> {
+        self.fields.iter().find_map(|field| match field {
+            TelemetryField::Tagged { key: field_key, value } if *field_key == key => Some(value.trim()),
+            _ => None,
+        })
+    }
+
+    /// 形式のバージョン（`TLM_V` がなければ0）
+    pub fn version(&self) -> u32 {
+        self.get(TELEMETRY_VERSION_KEY).and_then(|value| value.parse().ok()).unwrap_or(0)
+    }
+
+    /// 機能ビット（`TLM_CAPS` がなければ0）
+    pub fn capabilities(&self) -> u32 {
+        self.get(TELEMETRY_CAPS_KEY)
+            .and_then(|value| u32::from_str_radix(value, 16).ok())
+            .unwrap_or(0)
+    }
+
+    /// このゲートウェイより新しい形式か（知らない項目はそのまま転送する）
+    pub fn is_newer_than_supported(&self) -> bool {
+        self.version() > TELEMETRY_VERSION
+    }
+
+    /// ペイロードに戻します（解析前とバイト単位で一致する）
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let items: Vec<String> = self
+            .fields
+            .iter()
+            .map(|field| match field {
+                TelemetryField::Tagged { key, value } => format!("{}:{}", key, value),
+                TelemetryField::Bare(item) => item.to_string(),
+            })
+            .collect();
+        items.join(",").into_bytes()
+    }
+}
+
+/// HASHペイロードから1項目の値を取り出します
+pub fn telemetry_field<'a>(payload: &'a [u8], key: &str) -> Option<&'a str> {
+    TelemetryFields::parse(payload)?.get(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 将来のデバイスが送る、このゲートウェイが知らない項目を含むペイロード
+    const FUTURE_PAYLOAD: &[u8] =
+        b"HASH:ab,VOLT:80,TEMP:-999.0,TDS_VOLT:-999.0,2026/02/11 12:00:00.000,HUMIDITY:61.5,RSSI:-67,Q_SCORE:0.82,TLM_V:2,TLM_CAPS:7";
+
+    #[test]
+    fn test_unknown_fields_round_trip_unchanged() {
+        let telemetry = TelemetryFields::parse(FUTURE_PAYLOAD).unwrap();
+        assert_eq!(telemetry.to_bytes(), FUTURE_PAYLOAD);
+        assert_eq!(telemetry.get("VOLT"), Some("80"));
+        assert_eq!(telemetry.get("HUMIDITY"), Some("61.5"));
+        assert_eq!(telemetry.fields()[4], TelemetryField::Bare("2026/02/11 12:00:00.000"));
+        assert_eq!(telemetry.version(), 2);
+        assert!(telemetry.is_newer_than_supported());
+        assert_eq!(
+            telemetry.capabilities(),
+            TELEMETRY_CAP_TEMPERATURE | TELEMETRY_CAP_TDS | TELEMETRY_CAP_PASSTHROUGH
+        );
+
+        // 空の項目や空白も含めて元の表記を保つ
+        for payload in [&b""[..], b"HASH:ab,,VOLT: 80 ,:x,lower:1", b"HASH:ab,DEBUG:DEBUG+FORCE_CAMERA/2"] {
+            assert_eq!(TelemetryFields::parse(payload).unwrap().to_bytes(), payload);
+        }
+    }
+
+    #[test]
+    fn test_legacy_payload_is_version_zero() {
+        let telemetry = TelemetryFields::parse(b"HASH:ab,VOLT:80,TEMP:25.0").unwrap();
+        assert_eq!(telemetry.version(), 0);
+        assert_eq!(telemetry.capabilities(), 0);
+        assert!(!telemetry.is_newer_than_supported());
+        assert_eq!(telemetry_field(b"HASH:ab,VOLT: 80", "VOLT"), Some("80"));
+        // 接頭辞が同じ別のキーとは区別する
+        assert_eq!(telemetry_field(b"HASH:ab,TDS_VOLT:1.2", "VOLT"), None);
+        assert_eq!(telemetry_field(&[0xff, 0xfe], "VOLT"), None);
+    }
+}
@@ -0,0 +1,208 @@
+//! ワイヤフォーマットの宣言的コーデック
+//!
+//! 構造体ごとにフィールド順とバイトオーダーを `wire_struct!` で一度だけ宣言し、
+//! エンコード/デコードとバイト長 (`WIRE_SIZE`) を生成します。
+//! 呼び出し側はオフセットを手計算せず、`const _: () = assert!(...)` で
+//! ヘッダ長をコンパイル時に固定してください。
+
+/// フィールドのバイトオーダー
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WireOrder {
+    /// リトルエンディアン
+    Le,
+    /// ビッグエンディアン（ネットワークバイトオーダー）
+    Be,
+}
+
+/// ワイヤデコードエラー
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WireError {
+    /// 入力が構造体の長さに満たない
+    TooShort { needed: usize, available: usize },
+}
+
+impl std::fmt::Display for WireError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WireError::TooShort { needed, available } => {
+                write!(f, "Wire data too short: needed {} bytes, got {}", needed, available)
+            }
+        }
+    }
+}
+
+impl std::error::Error for WireError {}
+
+/// 固定長のワイヤフィールド
+///
+/// `[u8; N]` と `u8` はバイト列をそのまま扱うため、バイトオーダーは無視されます。
+pub trait WireField: Sized + Copy {
+    /// フィールドのバイト長
+    const SIZE: usize;
+    /// `out` の末尾に書き込みます
+    fn put(self, order: WireOrder, out: &mut Vec<u8>);
+    /// 先頭 `SIZE` バイトから読み込みます（呼び出し側で長さを保証）
+    fn get(order: WireOrder, bytes: &[u8]) -> Self;
+}
+
+macro_rules! impl_wire_int {
+    ($($ty:ty),*) => {$(
+        impl WireField for $ty {
+            const SIZE: usize = std::mem::size_of::<$ty>();
+
+            fn put(self, order: WireOrder, out: &mut Vec<u8>) {
+                match order {
+                    WireOrder::Le => out.extend_from_slice(&self.to_le_bytes()),
+                    WireOrder::Be => out.extend_from_slice(&self.to_be_bytes()),
+                }
+            }
+
+            fn get(order: WireOrder, bytes: &[u8]) -> Self {
+                let mut raw = [0u8; std::mem::size_of::<$ty>()];
+                raw.copy_from_slice(&bytes[..Self::SIZE]);
+                match order {
+                    WireOrder::Le => <$ty>::from_le_bytes(raw),
+                    WireOrder::Be => <$ty>::from_be_bytes(raw),
+                }
+            }
+        }
+    )*};
+}
+
+impl_wire_int!(u8, u16, u32, u64, i16, i32);
+
+impl<const N: usize> WireField for [u8; N] {
+    const SIZE: usize = N;
+
+    fn put(self, _order: WireOrder, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self);
+    }
+
+    fn get(_order: WireOrder, bytes: &[u8]) -> Self {
+        let mut raw = [0u8; N];
+        raw.copy_from_slice(&bytes[..N]);
+        raw
+    }
+}
+
+/// 固定長構造体のエンコード
+pub trait WireSerialize {
+    /// エンコード後のバイト長
+    const WIRE_SIZE: usize;
+    /// `out` の末尾に書き込みます
+    fn write_wire(&self, out: &mut Vec<u8>);
+
+    /// 新しいバッファにエンコードします
+    fn to_wire(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(Self::WIRE_SIZE);
+        self.write_wire(&mut out);
+        out
+    }
+}
+
+/// 固定長構造体のデコード
+pub trait WireDeserialize: WireSerialize + Sized {
+    /// 先頭 `WIRE_SIZE` バイトからデコードします（残りは無視）
+    fn read_wire(bytes: &[u8]) -> Result<Self, WireError>;
+}
+
+/// フィールド順とバイトオーダーを宣言して固定長のワイヤ構造体を定義します
+///
+/// ```ignore
+/// wire_struct! {
+///     pub struct Header {
+///         pub marker: u32 => Be,
+///         pub sequence: u32 => Le,
+///     }
+/// }
+/// const _: () = assert!(Header::WIRE_SIZE == 8);
+/// ```
+#[macro_export]
+macro_rules! wire_struct {
+    (
+        $(#[$meta:meta])*
+        $vis:vis struct $name:ident {
+            $(
+                $(#[$fmeta:meta])*
+                $fvis:vis $field:ident : $ty:ty => $order:ident
+            ),* $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        $vis struct $name {
+            $(
+                $(#[$fmeta])*
+                $fvis $field: $ty,
+            )*
+        }
+
+        impl $crate::esp_now::wire::WireSerialize for $name {
+            const WIRE_SIZE: usize =
+                0 $(+ <$ty as $crate::esp_now::wire::WireField>::SIZE)*;
+
+            fn write_wire(&self, out: &mut Vec<u8>) {
+                $(
+                    $crate::esp_now::wire::WireField::put(
+                        self.$field,
+                        $crate::esp_now::wire::WireOrder::$order,
+                        out,
+                    );
+                )*
+            }
+        }
+
+        impl $crate::esp_now::wire::WireDeserialize for $name {
+            fn read_wire(bytes: &[u8]) -> Result<Self, $crate::esp_now::wire::WireError> {
+                use $crate::esp_now::wire::{WireField, WireSerialize};
+                if bytes.len() < Self::WIRE_SIZE {
+                    return Err($crate::esp_now::wire::WireError::TooShort {
+                        needed: Self::WIRE_SIZE,
+                        available: bytes.len(),
+                    });
+                }
+                let mut _offset = 0usize;
+                $(
+                    let $field = <$ty as WireField>::get(
+                        $crate::esp_now::wire::WireOrder::$order,
+                        &bytes[_offset..],
+                    );
+                    _offset += <$ty as WireField>::SIZE;
+                )*
+                Ok(Self { $($field),* })
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    crate::wire_struct! {
+        struct Sample {
+            marker: u32 => Be,
+            id: [u8; 2] => Be,
+            kind: u8 => Le,
+            value: u32 => Le,
+        }
+    }
+
+    const _: () = assert!(Sample::WIRE_SIZE == 11);
+
+    #[test]
+    fn test_wire_struct_roundtrip() {
+        let sample = Sample { marker: 0xFACE_AABB, id: [1, 2], kind: 7, value: 0x0403_0201 };
+        let bytes = sample.to_wire();
+        assert_eq!(bytes, vec![0xFA, 0xCE, 0xAA, 0xBB, 1, 2, 7, 1, 2, 3, 4]);
+        assert_eq!(Sample::read_wire(&bytes), Ok(sample));
+    }
+
+    #[test]
+    fn test_wire_struct_too_short() {
+        assert_eq!(
+            Sample::read_wire(&[0u8; 10]),
+            Err(WireError::TooShort { needed: 11, available: 10 })
+        );
+    }
+}
@@ -0,0 +1,331 @@
+//! デバイスへのファイル転送（校正テーブルなどの資産）
+//!
+//! PCは `PUSH_FILE` でスロット名・長さ・CRC-32を指定して転送を始め、内容を `FILE_DATA` で先頭から
+//! 順に送ります（USBコマンドは1回の読み取りに収まる長さのため分割する）。全体が揃ってCRC-32が
+//! 一致したら、デバイスが次に転送を終えたとき（スリープコマンドを待って受信中の間）にファイルの
+//! 断片として送信します。1回の待機で送るのは `MAX_CHUNKS_PER_CHECK_IN` 個までです。
+//!
+//! デバイスは受信途中のファイルをNVSに保存し、HASHフレームの `FILE_SLOT`・`FILE_OFF`・`FILE_CRC` で
+//! 受信済みの位置を報告します。送信は報告された位置から再開するため、待機中に届かなかった断片や
+//! 電源断で途切れた転送も続きから送ります。デバイスが全体を受信してCRC-32を確認した（`FILE_OFF` が
+//! ファイルの長さに達した）報告で登録を取り除きます。
+//!
+//! 登録はRAMのみに保持するため、ゲートウェイの再起動後はPCから送り直します。
+
+use std::collections::BTreeMap;
+
+use crate::camera_settings::CheckIn;
+use crate::esp_now::message::crc32;
+use crate::esp_now::telemetry::TelemetryFields;
+use crate::esp_now::{MAX_FILE_CHUNK_DATA_LEN, MAX_FILE_SLOT_LEN};
+use crate::mac_address::format_mac_address;
+
+/// ファイル転送コマンド応答の接頭辞
+pub const FILE_RESPONSE_PREFIX: &str = "CMD_FILE:";
+
+/// 転送できるファイルの最大長（デバイスのNVSに1つのブロブとして保存する）
+pub const MAX_FILE_LEN: usize = 4096;
+
+/// `FILE_DATA` 1行で送れるデータの最大長（16進で192文字、コマンド全体が256バイトの読み取りに収まる）
+pub const MAX_FILE_DATA_LEN: usize = 96;
+
+/// 1回の転送完了（コマンド待機）で送る断片の上限（スリープコマンドを遅らせすぎないため）
+pub const MAX_CHUNKS_PER_CHECK_IN: usize = 8;
+
+/// スロット名として使えるか（英数字と `_`、デバイスのNVSキーに収まる長さ）
+pub fn is_valid_slot(slot: &str) -> bool {
+    (1..=MAX_FILE_SLOT_LEN).contains(&slot.len())
+        && slot.bytes().all(|byte| byte.is_ascii_alphanumeric() || byte == b'_')
+}
+
+/// デバイスへ送るファイルの断片
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileChunk {
+    /// 宛先のMAC
+    pub mac: [u8; 6],
+    /// 保存先のスロット名
+    pub slot: String,
+    /// ファイル全体のCRC-32
+    pub file_crc: u32,
+    /// ファイル全体の長さ
+    pub total_len: u16,
+    /// 断片の先頭位置
+    pub offset: u16,
+    /// 断片のデータ
+    pub data: Vec<u8>,
+}
+
+/// PCからのファイルの受け取りの失敗
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UploadError {
+    /// `PUSH_FILE` で開始していない、または送信を始めた転送への追加
+    NotStarted,
+    /// 受け取り済みの位置と一致しない
+    OffsetMismatch { expected: usize },
+    /// 指定した長さを超える
+    TooLong,
+    /// 揃った内容のCRC-32が指定と一致しない（転送は取り消し）
+    CrcMismatch { actual: u32 },
+}
+
+impl std::fmt::Display for UploadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UploadError::NotStarted => write!(f, "no file upload in progress (start with PUSH_FILE)"),
+            UploadError::OffsetMismatch { expected } => {
+                write!(f, "file data out of order (expected offset {})", expected)
+            }
+            UploadError::TooLong => write!(f, "file data exceeds the size given to PUSH_FILE"),
+            UploadError::CrcMismatch { actual } => {
+                write!(f, "file CRC mismatch (received {:08x}); upload cancelled", actual)
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+struct FileTransfer {
+    slot: String,
+    size: usize,
+    file_crc: u32,
+    data: Vec<u8>,
+    /// 内容が揃ってCRC-32を確認した
+    ready: bool,
+    /// デバイスが最後に報告した受信済みの位置
+    device_offset: usize,
+    /// 次に送る位置
+    next_offset: usize,
+    /// 今回の待機で送れる残りの断片数
+    chunks_left: usize,
+}
+
+/// デバイスごとのファイル転送
+#[derive(Debug, Default)]
+pub struct FileTransfers {
+    transfers: BTreeMap<[u8; 6], FileTransfer>,
+}
+
+impl FileTransfers {
+    /// 空の登録を作成します
+    pub const fn new() -> Self {
+        Self {
+            transfers: BTreeMap::new(),
+        }
+    }
+
+    /// PCからのファイルの受け取りを始めます（同じデバイスの転送は置き換え）
+    pub fn start(&mut self, mac: [u8; 6], slot: String, size: usize, file_crc: u32) {
+        self.transfers.insert(
+            mac,
+            FileTransfer {
+                slot,
+                size,
+                file_crc,
+                data: Vec::with_capacity(size),
+                ready: false,
+                device_offset: 0,
+                next_offset: 0,
+                chunks_left: 0,
+            },
+        );
+    }
+
+    /// PCから受け取った内容を追加し、揃ったらCRC-32を確認します
+    pub fn append(&mut self, mac: &[u8; 6], offset: usize, data: &[u8]) -> Result<(), UploadError> {
+        let transfer = self
+            .transfers
+            .get_mut(mac)
+            .filter(|transfer| !transfer.ready)
+            .ok_or(UploadError::NotStarted)?;
+        if offset != transfer.data.len() {
+            return Err(UploadError::OffsetMismatch {
+                expected: transfer.data.len(),
+            });
+        }
+        if offset + data.len() > transfer.size {
+            return Err(UploadError::TooLong);
+        }
+        transfer.data.extend_from_slice(data);
+        if transfer.data.len() == transfer.size {
+            let actual = crc32(&transfer.data);
+            if actual != transfer.file_crc {
+                self.transfers.remove(mac);
+                return Err(UploadError::CrcMismatch { actual });
+            }
+            transfer.ready = true;
+        }
+        Ok(())
+    }
+
+    /// デバイスの転送完了時にHASHペイロードの受信済みの位置を確認します
+    pub fn record_check_in(&mut self, mac: &[u8; 6], payload: Option<&[u8]>) -> CheckIn {
+        let Some(transfer) = self.transfers.get_mut(mac).filter(|transfer| transfer.ready) else {
+            return CheckIn::Idle;
+        };
+        let reported = payload.and_then(TelemetryFields::parse).and_then(|telemetry| {
+            let slot = telemetry.get("FILE_SLOT")?;
+            let crc = u32::from_str_radix(telemetry.get("FILE_CRC")?, 16).ok()?;
+            let offset: usize = telemetry.get("FILE_OFF")?.parse().ok()?;
+            (slot == transfer.slot && crc == transfer.file_crc && offset <= transfer.size).then_some(offset)
+        });
+        // 別のファイルの報告や報告なしは先頭から送る
+        transfer.device_offset = reported.unwrap_or(0);
+        if transfer.device_offset == transfer.size {
+            self.transfers.remove(mac);
+            return CheckIn::Confirmed;
+        }
+        transfer.next_offset = transfer.device_offset;
+        transfer.chunks_left = MAX_CHUNKS_PER_CHECK_IN;
+        CheckIn::Deliver
+    }
+
+    /// 送信を予約した断片を1個取り出します
+    pub fn take_due(&mut self) -> Option<FileChunk> {
+        let (mac, transfer) = self
+            .transfers
+            .iter_mut()
+            .find(|(_, transfer)| transfer.chunks_left > 0 && transfer.next_offset < transfer.size)?;
+        let offset = transfer.next_offset;
+        let end = (offset + MAX_FILE_CHUNK_DATA_LEN).min(transfer.size);
+        transfer.next_offset = end;
+        transfer.chunks_left -= 1;
+        Some(FileChunk {
+            mac: *mac,
+            slot: transfer.slot.clone(),
+            file_crc: transfer.file_crc,
+            total_len: transfer.size as u16,
+            offset: offset as u16,
+            data: transfer.data[offset..end].to_vec(),
+        })
+    }
+
+    /// 今回の待機での送信をやめます（断片が届かなかった場合。次の転送完了時に報告の位置から再開する）
+    pub fn stop(&mut self, mac: &[u8; 6]) {
+        if let Some(transfer) = self.transfers.get_mut(mac) {
+            transfer.chunks_left = 0;
+        }
+    }
+
+    /// デバイスへ送る転送があるか（PCからの受け取り中は含めない）
+    pub fn is_pending(&self, mac: &[u8; 6]) -> bool {
+        self.transfers.get(mac).is_some_and(|transfer| transfer.ready)
+    }
+
+    /// `PUSH_FILE`・`FILE_DATA` への応答行
+    pub fn response(&self, mac: &[u8; 6]) -> String {
+        let mac_str = format_mac_address(mac);
+        match self.transfers.get(mac) {
+            Some(transfer) if transfer.ready => format!(
+                "{}{} {} delivering {}/{} crc={:08x}\n",
+                FILE_RESPONSE_PREFIX, mac_str, transfer.slot, transfer.device_offset, transfer.size, transfer.file_crc
+            ),
+            Some(transfer) => format!(
+                "{}{} {} uploading {}/{}\n",
+                FILE_RESPONSE_PREFIX,
+                mac_str,
+                transfer.slot,
+                transfer.data.len(),
+                transfer.size
+            ),
+            None => format!("{}{} none\n", FILE_RESPONSE_PREFIX, mac_str),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAC: [u8; 6] = [0x34, 0xab, 0x95, 0xfb, 0x3f, 0xc4];
+
+    fn ready_transfer(contents: &[u8]) -> FileTransfers {
+        let mut transfers = FileTransfers::new();
+        transfers.start(MAC, "calib".to_string(), contents.len(), crc32(contents));
+        for (index, part) in contents.chunks(MAX_FILE_DATA_LEN).enumerate() {
+            transfers.append(&MAC, index * MAX_FILE_DATA_LEN, part).unwrap();
+        }
+        transfers
+    }
+
+    #[test]
+    fn test_upload_is_verified_before_delivery() {
+        let contents = b"0123456789";
+        let mut transfers = FileTransfers::new();
+        transfers.start(MAC, "calib".to_string(), contents.len(), crc32(contents));
+        assert!(!transfers.is_pending(&MAC));
+        assert_eq!(transfers.record_check_in(&MAC, None), CheckIn::Idle);
+
+        transfers.append(&MAC, 0, &contents[..4]).unwrap();
+        assert_eq!(transfers.response(&MAC), "CMD_FILE:34:ab:95:fb:3f:c4 calib uploading 4/10\n");
+        assert_eq!(
+            transfers.append(&MAC, 2, b"23"),
+            Err(UploadError::OffsetMismatch { expected: 4 })
+        );
+        assert_eq!(transfers.append(&MAC, 4, b"4567890"), Err(UploadError::TooLong));
+        transfers.append(&MAC, 4, &contents[4..]).unwrap();
+        assert!(transfers.is_pending(&MAC));
+        assert_eq!(transfers.append(&MAC, 10, b"x"), Err(UploadError::NotStarted));
+
+        // 内容が指定のCRC-32と一致しなければ取り消す
+        transfers.start(MAC, "calib".to_string(), 3, 0);
+        assert!(matches!(transfers.append(&MAC, 0, b"abc"), Err(UploadError::CrcMismatch { .. })));
+        assert_eq!(transfers.response(&MAC), "CMD_FILE:34:ab:95:fb:3f:c4 none\n");
+    }
+
+    #[test]
+    fn test_chunks_per_check_in_are_limited() {
+        let contents: Vec<u8> = (0..=255u8).cycle().take(MAX_FILE_LEN).collect();
+        let mut transfers = ready_transfer(&contents);
+        assert_eq!(transfers.take_due(), None);
+
+        assert_eq!(transfers.record_check_in(&MAC, None), CheckIn::Deliver);
+        let chunks: Vec<FileChunk> = std::iter::from_fn(|| transfers.take_due()).collect();
+        assert_eq!(chunks.len(), MAX_CHUNKS_PER_CHECK_IN);
+        assert_eq!(chunks[0].offset, 0);
+        assert_eq!(chunks[1].offset as usize, MAX_FILE_CHUNK_DATA_LEN);
+        assert_eq!(chunks[0].total_len as usize, MAX_FILE_LEN);
+        assert_eq!(chunks[0].data, contents[..MAX_FILE_CHUNK_DATA_LEN]);
+    }
+
+    #[test]
+    fn test_delivery_resumes_from_reported_offset() {
+        let contents: Vec<u8> = (0..250u8).collect();
+        let crc = crc32(&contents);
+        let mut transfers = ready_transfer(&contents);
+
+        assert_eq!(transfers.record_check_in(&MAC, None), CheckIn::Deliver);
+        assert_eq!(transfers.take_due().map(|chunk| chunk.offset), Some(0));
+        // 2個目が届かなかった
+        assert_eq!(transfers.take_due().map(|chunk| chunk.offset), Some(192));
+        transfers.stop(&MAC);
+        assert_eq!(transfers.take_due(), None);
+
+        let partial = format!("HASH:ab,FILE_SLOT:calib,FILE_OFF:192,FILE_CRC:{:08x}", crc);
+        assert_eq!(transfers.record_check_in(&MAC, Some(partial.as_bytes())), CheckIn::Deliver);
+        assert_eq!(
+            transfers.response(&MAC),
+            format!("CMD_FILE:34:ab:95:fb:3f:c4 calib delivering 192/250 crc={:08x}\n", crc)
+        );
+        let chunk = transfers.take_due().unwrap();
+        assert_eq!((chunk.offset, chunk.data.len()), (192, 58));
+        assert_eq!(transfers.take_due(), None);
+
+        // 別のファイルの報告は先頭から送り直す
+        let other = b"HASH:ab,FILE_SLOT:calib,FILE_OFF:192,FILE_CRC:00000000";
+        assert_eq!(transfers.record_check_in(&MAC, Some(other)), CheckIn::Deliver);
+        assert_eq!(transfers.take_due().map(|chunk| chunk.offset), Some(0));
+
+        let complete = format!("HASH:ab,FILE_SLOT:calib,FILE_OFF:250,FILE_CRC:{:08x}", crc);
+        assert_eq!(transfers.record_check_in(&MAC, Some(complete.as_bytes())), CheckIn::Confirmed);
+        assert!(!transfers.is_pending(&MAC));
+        assert_eq!(transfers.record_check_in(&MAC, Some(complete.as_bytes())), CheckIn::Idle);
+    }
+
+    #[test]
+    fn test_slot_names() {
+        assert!(is_valid_slot("calib_tds"));
+        assert!(!is_valid_slot(""));
+        assert!(!is_valid_slot("calib-tds"));
+        assert!(!is_valid_slot("calibration_2"));
+    }
+}
@@ -0,0 +1,720 @@
+//! 登録デバイスの定期サマリー（FLEET_SUMMARYフレーム）
+//!
+//! 統計フレームを追い続けなくても済むよう、メンテナンスタスクが一定間隔（既定1時間）で
+//! 登録デバイスごとの集計をCBORにまとめてUSBへ送出します。受信数・成功率・平均RSSIは
+//! 送出ごとにリセットし、最後の電池残量と転送完了時刻は引き継ぎます。
+//!
+//! ペイロード（CBORマップ）:
+//! ```text
+//! {"v": 1, "period_s": 3600, "devices": [
+//!   {"mac": "34:ab:95:fb:3f:c4", "name": "cam1", "frames": 12, "aborts": 1, "skips": {"LOW_VOLTAGE": 2},
+//!    "success_pct": 92,
+//!    "rssi": -67, "battery": 80, "last_seen_s": 120, "last_seen_unix_ms": 1760000000000, "pending": 0,
+//!    "key_epoch": 1, "key_rotation": "staged", "skew_ms": -120, "drift_ppm": 8,
+//!    "channel": "beta", "fw": "1a2b3c4d", "rollout": "updated",
+//!    "frame_types": {"HASH": [3, 3, 0], "DATA": [120, 118, 2], ...}, "retry_pct": 2, "group": "north"}, ...],
+//!  "groups": [{"group": "north", "devices": 3, "frames": 30, "aborts": 2, "success_pct": 93, "battery_min": 41}, ...],
+//!  "rollout": [{"channel": "beta", "fw": "1a2b3c4d", "targeted": 2, "updated": 1}, ...],
+//!  "radio": [{"channel": 6, "samples": 360, "busy_pct": 31, "busy_max_pct": 78, "foreign_pct": 64,
+//!             "retry_by_busy": [[900, 4], [700, 6], [120, 9], [0, 0]]}, ...],
+//!  "frame_types": {"HASH": [6, 6, 0], ...}}
+//! ```
+//! `skips` は画像なしで届いた転送の数を、デバイスが報告した撮影しなかった理由（HASHフレームの `SKIP`）ごとに
+//! 数えたもので、受信数と同じく送出ごとにリセットします。
+//! 該当する値がない項目（受信なしの成功率・RSSIなど）はnullです。`key_rotation` は鍵の更新中のみ
+//! `"delivering"`・`"staged"` で、それ以外はnullです。`skew_ms`・`drift_ppm` は撮影時刻を報告した
+//! デバイスの時計のずれ（`clock_skew` を参照）で、ずれは送出ごとにリセットしません。
+//! `last_seen_unix_ms` はゲートウェイの時計がSNTPで同期済みの場合のみ値が入ります（`clock` を参照）。
+//! `channel` はリリースチャンネル、`fw` は最後に報告されたファームウェアで、`rollout` はチャンネルに
+//! 展開中のファームウェアがある場合のみ `"pending"`・`"updated"` です。最上位の `rollout` は展開中の
+//! チャンネルごとの進み具合です（`release_channel` を参照）。
+//! `frame_types` はフレームタイプごとの `[受信, 転送, エラー]` で、送出ごとにリセットしない
+//! 今回の起動からの累積です（`frame_stats` を参照）。
+//! `retry_pct` は受信したフレームに対する再送（重複して届いたHASH/EOFと欠落したDATA/FEC）の割合で、
+//! 受信数と同じく送出ごとにリセットします。`radio` は `channel-monitor` フィーチャーで計測したチャンネルごとの
+//! ビジー率と、ビジー率の区分（25%ごと）ごとの登録デバイスの `[受信, 再送]` です（`channel_utilization` を参照）。
+//! 計測しないビルドでは空の配列です。
+//! `group` は `GROUP_ADD` で登録したグループ（未登録はnull）で、最上位の `groups` はグループごとのメンバー数・
+//! 受信数・中断数・成功率と、メンバーが最後に報告した電池残量の最小値です（`device_groups` を参照）。
+use std::collections::BTreeMap;
+use std::time::Instant;
+
+use crate::cbor::CborWriter;
+use crate::channel_utilization::{busy_bucket, ChannelTally, ChannelUtilization, RetryCounts};
+use crate::clock_skew::ClockSkewTable;
+use crate::device_groups::DeviceGroups;
+use crate::esp_now::frame::create_frame;
+use crate::esp_now::telemetry::telemetry_field;
+use crate::esp_now::FrameType;
+use crate::frame_stats::{FrameKind, FrameKindCounts, FrameStats};
+use crate::key_rotation::KeyStatus;
+use crate::mac_address::format_mac_address;
+use crate::release_channel::ReleaseChannels;
+use crate::stats::GATEWAY_STATS_MAC;
+
+/// ペイロードの形式バージョン
+pub const FLEET_SUMMARY_VERSION: u64 = 1;
+
+/// 撮影しなかった理由のキー
+pub const CAPTURE_SKIP_KEY: &str = "SKIP";
+
+/// 既定の送出間隔（秒）
+pub const DEFAULT_FLEET_SUMMARY_INTERVAL_SECS: u64 = 3600;
+
+/// デバイスごとの集計
+#[derive(Debug, Default)]
+struct DeviceTally {
+    name: String,
+    completed: u32,
+    aborted: u32,
+    /// 撮影しなかった理由ごとの転送数
+    skips: BTreeMap<String, u32>,
+    rssi_sum: i64,
+    rssi_count: u32,
+    battery_percent: Option<u8>,
+    last_check_in: Option<Instant>,
+    /// 受信したフレーム数
+    rx_frames: u32,
+    /// 重複して届いたHASH/EOFの数
+    duplicated_frames: u32,
+    /// 欠落したDATA/FECの数
+    missing_frames: u32,
+}
+
+impl DeviceTally {
+    fn success_percent(&self) -> Option<u64> {
+        let total = self.completed + self.aborted;
+        (total > 0).then(|| self.completed as u64 * 100 / total as u64)
+    }
+
+    fn mean_rssi(&self) -> Option<i64> {
+        (self.rssi_count > 0).then(|| self.rssi_sum / self.rssi_count as i64)
+    }
+
+    fn retry_percent(&self) -> Option<u64> {
+        let total = self.rx_frames as u64 + self.missing_frames as u64;
+        (total > 0).then(|| (self.duplicated_frames as u64 + self.missing_frames as u64) * 100 / total)
+    }
+}
+
+/// グループごとの集計
+#[derive(Debug, Default)]
+struct GroupTally {
+    devices: u32,
+    completed: u32,
+    aborted: u32,
+    /// メンバーが最後に報告した電池残量の最小値
+    battery_min_percent: Option<u8>,
+}
+
+impl GroupTally {
+    fn add(&mut self, device: &DeviceTally) {
+        self.devices += 1;
+        self.completed += device.completed;
+        self.aborted += device.aborted;
+        self.battery_min_percent = match (self.battery_min_percent, device.battery_percent) {
+            (Some(min), Some(percent)) => Some(min.min(percent)),
+            (min, percent) => min.or(percent),
+        };
+    }
+
+    fn success_percent(&self) -> Option<u64> {
+        let total = self.completed + self.aborted;
+        (total > 0).then(|| self.completed as u64 * 100 / total as u64)
+    }
+}
+
+/// HASHペイロードから電池残量（`VOLT`、%）を解析します
+pub fn reported_battery_percent(payload: &[u8]) -> Option<u8> {
+    telemetry_field(payload, "VOLT")?
+        .parse()
+        .ok()
+        .filter(|percent| *percent <= 100)
+}
+
+/// HASHペイロードから撮影しなかった理由（`SKIP`）を取り出します
+pub fn reported_capture_skip(payload: &[u8]) -> Option<&str> {
+    telemetry_field(payload, CAPTURE_SKIP_KEY).filter(|reason| !reason.is_empty())
+}
+
+/// 登録デバイスの集計
+#[derive(Debug)]
+pub struct FleetSummary {
+    devices: BTreeMap<[u8; 6], DeviceTally>,
+    period_start: Option<Instant>,
+    clocks: ClockSkewTable,
+    /// チャンネルごとの混雑度の集計
+    radio: BTreeMap<u8, ChannelTally>,
+    /// 直近に計測したチャンネルとビジー率の区分（受信したフレームをこの区分に数える）
+    current_busy: Option<(u8, usize)>,
+}
+
+impl Default for FleetSummary {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FleetSummary {
+    /// 空の集計を作成します
+    pub const fn new() -> Self {
+        Self {
+            devices: BTreeMap::new(),
+            period_start: None,
+            clocks: ClockSkewTable::new(),
+            radio: BTreeMap::new(),
+            current_busy: None,
+        }
+    }
+
+    /// 集計対象のデバイスを登録します（受信がなくてもサマリーに含める）
+    pub fn register(&mut self, mac: [u8; 6], name: &str, now: Instant) {
+        self.devices.entry(mac).or_default().name = name.to_string();
+        self.period_start.get_or_insert(now);
+    }
+
+    /// 登録デバイスのMACアドレス
+    pub fn macs(&self) -> impl Iterator<Item = [u8; 6]> + '_ {
+        self.devices.keys().copied()
+    }
+
+    /// 受信したESP-NOWフレームのRSSIを記録します（未登録のデバイスは無視）
+    pub fn record_rssi(&mut self, mac: &[u8; 6], rssi: i8) {
+        if let Some(device) = self.devices.get_mut(mac) {
+            device.rssi_sum += rssi as i64;
+            device.rssi_count += 1;
+        }
+    }
+
+    /// 登録デバイスからフレームを受信したことを記録します（未登録のデバイスは無視）
+    pub fn record_received(&mut self, mac: &[u8; 6]) {
+        if let Some(device) = self.devices.get_mut(mac) {
+            device.rx_frames += 1;
+            if let Some(counts) = self.current_retry_counts() {
+                counts.frames += 1;
+            }
+        }
+    }
+
+    /// 転送で再送されたフレームを記録します（未登録のデバイスは無視）
+    ///
+    /// 重複したHASH/EOFはデバイスが届いたことを確認できずに送り直したもの、欠落したDATA/FECは
+    /// 届かなかったものです。
+    pub fn record_retries(&mut self, mac: &[u8; 6], duplicated: u32, missing: u32) {
+        if let Some(device) = self.devices.get_mut(mac) {
+            device.duplicated_frames += duplicated;
+            device.missing_frames += missing;
+            if let Some(counts) = self.current_retry_counts() {
+                counts.retried += duplicated + missing;
+            }
+        }
+    }
+
+    /// チャンネルの混雑度の計測結果を記録します
+    pub fn record_channel(&mut self, utilization: &ChannelUtilization) {
+        self.radio.entry(utilization.channel).or_default().record(utilization);
+        self.current_busy = Some((utilization.channel, busy_bucket(utilization.busy_percent)));
+    }
+
+    /// 直近に計測したビジー率の区分の集計
+    fn current_retry_counts(&mut self) -> Option<&mut RetryCounts> {
+        let (channel, bucket) = self.current_busy?;
+        Some(&mut self.radio.entry(channel).or_default().retry_by_busy[bucket])
+    }
+
+    /// 転送完了を記録します
+    pub fn record_complete(&mut self, mac: &[u8; 6], hash_payload: Option<&[u8]>, now: Instant) {
+        if let Some(device) = self.devices.get_mut(mac) {
+            device.completed += 1;
+            device.last_check_in = Some(now);
+            if let Some(percent) = hash_payload.and_then(reported_battery_percent) {
+                device.battery_percent = Some(percent);
+            }
+            if let Some(reason) = hash_payload.and_then(reported_capture_skip) {
+                *device.skips.entry(reason.to_string()).or_default() += 1;
+            }
+        }
+    }
+
+    /// 完了した転送の撮影時刻を時計のずれの推定に記録します（未登録のデバイスは無視）
+    pub fn record_capture_clock(
+        &mut self,
+        mac: &[u8; 6],
+        hash_payload: Option<&[u8]>,
+        end_to_end_ms: Option<u32>,
+        now: Instant,
+    ) {
+        if self.devices.contains_key(mac) {
+            self.clocks.record(*mac, hash_payload, end_to_end_ms, now);
+        }
+    }
+
+    /// 転送の中断を記録します
+    pub fn record_abort(&mut self, mac: &[u8; 6]) {
+        if let Some(device) = self.devices.get_mut(mac) {
+            device.aborted += 1;
+        }
+    }
+
+    /// CBORペイロードを生成し、次の期間の集計を始めます
+    ///
+    /// # 引数
+    /// * `now_unix_ms` - 現在のUNIX時刻（ゲートウェイの時計が同期前ならNone）
+    /// * `pending_commands` - デバイスごとの未完了のコマンド数
+    /// * `key_status` - デバイスごとのダウンリンク認証鍵の世代と更新状況
+    /// * `release` - デバイスごとのリリースチャンネルとチャンネルごとの展開ファームウェア
+    /// * `groups` - デバイスごとのグループ
+    /// * `firmware` - デバイスが最後に報告したファームウェア
+    /// * `frame_stats` - 全体とデバイスごとのフレームタイプ別の集計
+    #[allow(clippy::too_many_arguments)]
+    pub fn take_payload(
+        &mut self,
+        now: Instant,
+        now_unix_ms: Option<u64>,
+        pending_commands: impl Fn(&[u8; 6]) -> u32,
+        key_status: impl Fn(&[u8; 6]) -> KeyStatus,
+        release: &ReleaseChannels,
+        groups: &DeviceGroups,
+        firmware: impl Fn(&[u8; 6]) -> Option<String>,
+        frame_stats: &FrameStats,
+    ) -> Vec<u8> {
+        let period_s = self
+            .period_start
+            .replace(now)
+            .map_or(0, |start| now.saturating_duration_since(start).as_secs());
+
+        let firmware: BTreeMap<[u8; 6], Option<String>> =
+            self.devices.keys().map(|mac| (*mac, firmware(mac))).collect();
+        let rollout = release.progress(firmware.iter().map(|(mac, fw)| (*mac, fw.as_deref())));
+
+        let mut group_tallies: BTreeMap<&str, GroupTally> = BTreeMap::new();
+        let mut writer = CborWriter::new();
+        writer.map(7);
+        writer.text("v").uint(FLEET_SUMMARY_VERSION);
+        writer.text("period_s").uint(period_s);
+        writer.text("devices").array(self.devices.len());
+        let skews = self.clocks.skews();
+        for (mac, device) in &mut self.devices {
+            writer.map(21);
+            writer.text("mac").text(&format_mac_address(mac));
+            writer.text("name").text(&device.name);
+            writer.text("frames").uint(device.completed as u64);
+            writer.text("aborts").uint(device.aborted as u64);
+            writer.text("skips").map(device.skips.len());
+            for (reason, count) in &device.skips {
+                writer.text(reason).uint(*count as u64);
+            }
+            writer.text("success_pct").opt_uint(device.success_percent());
+            writer.text("rssi").opt_int(device.mean_rssi());
+            writer.text("battery").opt_uint(device.battery_percent.map(u64::from));
+            let last_seen = device.last_check_in.map(|at| now.saturating_duration_since(at));
+            writer.text("last_seen_s").opt_uint(last_seen.map(|elapsed| elapsed.as_secs()));
+            writer.text("last_seen_unix_ms").opt_uint(
+                now_unix_ms
+                    .zip(last_seen)
+                    .map(|(unix_ms, elapsed)| unix_ms.saturating_sub(elapsed.as_millis() as u64)),
+            );
+            writer.text("pending").uint(pending_commands(mac) as u64);
+            let key = key_status(mac);
+            writer.text("key_epoch").uint(key.epoch as u64);
+            writer.text("key_rotation").opt_text(key.rotation.map(|rotation| rotation.as_str()));
+            let skew = skews.get(mac);
+            writer.text("skew_ms").opt_int(skew.map(|skew| skew.skew_ms));
+            writer.text("drift_ppm").opt_int(skew.and_then(|skew| skew.drift_ppm));
+            let reported_firmware = firmware.get(mac).and_then(Option::as_deref);
+            writer.text("channel").text(release.channel(mac).as_str());
+            writer.text("fw").opt_text(reported_firmware);
+            writer
+                .text("rollout")
+                .opt_text(release.rollout_state(mac, reported_firmware).map(|state| state.as_str()));
+            writer.text("frame_types");
+            write_frame_counts(&mut writer, &frame_stats.device(mac).copied().unwrap_or_default());
+            writer.text("retry_pct").opt_uint(device.retry_percent());
+            let group = groups.group_of(mac);
+            writer.text("group").opt_text(group);
+            if let Some(group) = group {
+                group_tallies.entry(group).or_default().add(device);
+            }
+
+            device.completed = 0;
+            device.aborted = 0;
+            device.skips.clear();
+            device.rssi_sum = 0;
+            device.rssi_count = 0;
+            device.rx_frames = 0;
+            device.duplicated_frames = 0;
+            device.missing_frames = 0;
+        }
+        writer.text("groups").array(group_tallies.len());
+        for (group, tally) in &group_tallies {
+            writer.map(6);
+            writer.text("group").text(group);
+            writer.text("devices").uint(tally.devices as u64);
+            writer.text("frames").uint(tally.completed as u64);
+            writer.text("aborts").uint(tally.aborted as u64);
+            writer.text("success_pct").opt_uint(tally.success_percent());
+            writer.text("battery_min").opt_uint(tally.battery_min_percent.map(u64::from));
+        }
+        writer.text("rollout").array(rollout.len());
+        for progress in &rollout {
+            writer.map(4);
+            writer.text("channel").text(progress.manifest.channel.as_str());
+            writer.text("fw").text(&progress.manifest.firmware);
+            writer.text("targeted").uint(progress.targeted as u64);
+            writer.text("updated").uint(progress.updated as u64);
+        }
+        writer.text("radio").array(self.radio.len());
+        for (channel, tally) in &self.radio {
+            writer.map(6);
+            writer.text("channel").uint(*channel as u64);
+            writer.text("samples").uint(tally.samples as u64);
+            writer.text("busy_pct").opt_uint(tally.mean_busy_percent());
+            writer.text("busy_max_pct").uint(tally.busy_max_percent as u64);
+            writer.text("foreign_pct").opt_uint(tally.mean_foreign_percent());
+            writer.text("retry_by_busy").array(tally.retry_by_busy.len());
+            for counts in &tally.retry_by_busy {
+                writer.array(2);
+                writer.uint(counts.frames as u64);
+                writer.uint(counts.retried as u64);
+            }
+        }
+        self.radio.clear();
+        writer.text("frame_types");
+        write_frame_counts(&mut writer, frame_stats.total());
+        writer.into_bytes()
+    }
+}
+
+/// フレームタイプごとの `[受信, 転送, エラー]` のマップを書き込みます
+fn write_frame_counts(writer: &mut CborWriter, counts: &FrameKindCounts) {
+    writer.map(FrameKind::ALL.len());
+    for (kind, counts) in counts.iter() {
+        writer.text(kind.as_str()).array(3);
+        writer.uint(counts.received as u64);
+        writer.uint(counts.forwarded as u64);
+        writer.uint(counts.errored as u64);
+    }
+}
+
+/// USBへ送出するFLEET_SUMMARYフレームを生成します
+pub fn to_frame(payload: &[u8], sequence_number: u32) -> Vec<u8> {
+    create_frame(GATEWAY_STATS_MAC, payload, FrameType::FleetSummary, sequence_number)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::esp_now::frame::Frame;
+    use crate::frame_stats::FrameOutcome;
+    use crate::key_rotation::RotationState;
+    use crate::release_channel::ReleaseChannel;
+    use std::time::Duration;
+
+    const MAC_A: [u8; 6] = [0x34, 0xab, 0x95, 0xfb, 0x3f, 0xc4];
+    const MAC_B: [u8; 6] = [0x34, 0xab, 0x95, 0xfb, 0x3f, 0xc5];
+
+    fn text(value: &str) -> Vec<u8> {
+        let mut bytes = vec![0x60 | value.len() as u8];
+        bytes.extend_from_slice(value.as_bytes());
+        bytes
+    }
+
+    /// HASH・DATA・EOF・FEC・OTHERの順の `[受信, 転送, エラー]`
+    fn frame_counts(counts: [[u8; 3]; 5]) -> Vec<u8> {
+        let mut bytes = text("frame_types");
+        bytes.push(0xa5);
+        for (kind, [rx, fwd, err]) in ["HASH", "DATA", "EOF", "FEC", "OTHER"].into_iter().zip(counts) {
+            bytes.extend(text(kind));
+            bytes.extend([0x83, rx, fwd, err]);
+        }
+        bytes
+    }
+
+    #[test]
+    fn test_parse_reported_capture_skip() {
+        assert_eq!(reported_capture_skip(b"HASH:ab,VOLT:5,SKIP:LOW_VOLTAGE"), Some("LOW_VOLTAGE"));
+        assert_eq!(reported_capture_skip(b"HASH:ab,LUMA:20,SHARP:3,SKIP:DARK"), Some("DARK"));
+        assert_eq!(reported_capture_skip(b"HASH:ab,PROBE_SKIPPED:2"), None);
+    }
+
+    #[test]
+    fn test_parse_reported_battery_percent() {
+        assert_eq!(reported_battery_percent(b"HASH:ab,VOLT:80,TEMP:25.0"), Some(80));
+        assert_eq!(reported_battery_percent(b"HASH:ab,VOLT:255"), None);
+        assert_eq!(reported_battery_percent(b"HASH:ab,TDS_VOLT:1.2"), None);
+    }
+
+    #[test]
+    fn test_summary_counts_and_resets_per_period() {
+        let start = Instant::now();
+        let mut summary = FleetSummary::new();
+        summary.register(MAC_A, "cam1", start);
+        summary.register(MAC_B, "cam2", start);
+
+        summary.record_rssi(&MAC_A, -60);
+        summary.record_rssi(&MAC_A, -70);
+        summary.record_rssi(&[0; 6], -20);
+        summary.record_complete(&MAC_A, Some(b"HASH:ab,VOLT:80"), start + Duration::from_secs(10));
+        summary.record_complete(&MAC_A, Some(b"HASH:ab,SKIP:CAMERA_FAILED"), start + Duration::from_secs(20));
+        summary.record_complete(&MAC_A, None, start + Duration::from_secs(30));
+        summary.record_abort(&MAC_A);
+        // 撮影時刻を報告したのはcam1のみ（中央値は自身のため、ずれは0）
+        let clock_payload = b"HASH:ab,CAPTURE_UNIX_MS:1760000000000";
+        summary.record_capture_clock(&MAC_A, Some(clock_payload), Some(3000), start + Duration::from_secs(30));
+        summary.record_capture_clock(&[0; 6], Some(clock_payload), Some(3000), start + Duration::from_secs(30));
+
+        // cam2をbetaにしてファームウェアを展開中（cam2はまだ報告なし）
+        let mut release = ReleaseChannels::new();
+        release.set_channel(MAC_B, ReleaseChannel::Beta);
+        release.set_manifest(ReleaseChannel::Beta, Some("1a2b3c4d".to_string()));
+
+        // cam1だけをnorthグループに登録
+        let mut groups = DeviceGroups::new();
+        groups.add("north", &[MAC_A]);
+
+        // cam1のDATAフレームが1つ失われた
+        let mut frame_stats = FrameStats::new();
+        frame_stats.record(&MAC_A, FrameKind::Data, FrameOutcome::Received);
+        frame_stats.record(&MAC_A, FrameKind::Data, FrameOutcome::Received);
+        frame_stats.record(&MAC_A, FrameKind::Data, FrameOutcome::Forwarded);
+        frame_stats.record(&MAC_A, FrameKind::Data, FrameOutcome::Errored);
+
+        let now = start + Duration::from_secs(3600);
+        let payload = summary.take_payload(
+            now,
+            Some(1_760_000_000_000),
+            |mac| u32::from(*mac == MAC_B),
+            |mac| KeyStatus {
+                epoch: u32::from(*mac == MAC_A),
+                rotation: (*mac == MAC_B).then_some(RotationState::Delivering),
+            },
+            &release,
+            &groups,
+            |mac| (*mac == MAC_A).then(|| "0f0f0f0f".to_string()),
+            &frame_stats,
+        );
+
+        let mut expected = vec![0xa7];
+        expected.extend(text("v"));
+        expected.push(0x01);
+        expected.extend(text("period_s"));
+        expected.extend([0x19, 0x0e, 0x10]);
+        expected.extend(text("devices"));
+        expected.push(0x82);
+        // cam1: 3件完了（1件は撮影失敗で画像なし）・1件中断（75%）、RSSI平均-65、最後の報告の電池残量80%、3570秒前
+        expected.push(0xb5);
+        expected.extend(text("mac"));
+        expected.extend(text("34:ab:95:fb:3f:c4"));
+        expected.extend(text("name"));
+        expected.extend(text("cam1"));
+        expected.extend(text("frames"));
+        expected.push(0x03);
+        expected.extend(text("aborts"));
+        expected.push(0x01);
+        expected.extend(text("skips"));
+        expected.push(0xa1);
+        expected.extend(text("CAMERA_FAILED"));
+        expected.push(0x01);
+        expected.extend(text("success_pct"));
+        expected.extend([0x18, 75]);
+        expected.extend(text("rssi"));
+        expected.extend([0x38, 64]);
+        expected.extend(text("battery"));
+        expected.extend([0x18, 80]);
+        expected.extend(text("last_seen_s"));
+        expected.extend([0x19, 0x0d, 0xf2]);
+        expected.extend(text("last_seen_unix_ms"));
+        expected.push(0x1b);
+        expected.extend((1_760_000_000_000u64 - 3_570_000).to_be_bytes());
+        expected.extend(text("pending"));
+        expected.push(0x00);
+        expected.extend(text("key_epoch"));
+        expected.push(0x01);
+        expected.extend(text("key_rotation"));
+        expected.push(0xf6);
+        expected.extend(text("skew_ms"));
+        expected.push(0x00);
+        expected.extend(text("drift_ppm"));
+        expected.push(0xf6);
+        expected.extend(text("channel"));
+        expected.extend(text("stable"));
+        expected.extend(text("fw"));
+        expected.extend(text("0f0f0f0f"));
+        expected.extend(text("rollout"));
+        expected.push(0xf6);
+        expected.extend(frame_counts([[0; 3], [2, 1, 1], [0; 3], [0; 3], [0; 3]]));
+        expected.extend(text("retry_pct"));
+        expected.push(0xf6);
+        expected.extend(text("group"));
+        expected.extend(text("north"));
+        // cam2: 受信なし
+        expected.push(0xb5);
+        expected.extend(text("mac"));
+        expected.extend(text("34:ab:95:fb:3f:c5"));
+        expected.extend(text("name"));
+        expected.extend(text("cam2"));
+        expected.extend(text("frames"));
+        expected.push(0x00);
+        expected.extend(text("aborts"));
+        expected.push(0x00);
+        expected.extend(text("skips"));
+        expected.push(0xa0);
+        expected.extend(text("success_pct"));
+        expected.push(0xf6);
+        expected.extend(text("rssi"));
+        expected.push(0xf6);
+        expected.extend(text("battery"));
+        expected.push(0xf6);
+        expected.extend(text("last_seen_s"));
+        expected.push(0xf6);
+        expected.extend(text("last_seen_unix_ms"));
+        expected.push(0xf6);
+        expected.extend(text("pending"));
+        expected.push(0x01);
+        expected.extend(text("key_epoch"));
+        expected.push(0x00);
+        expected.extend(text("key_rotation"));
+        expected.extend(text("delivering"));
+        expected.extend(text("skew_ms"));
+        expected.push(0xf6);
+        expected.extend(text("drift_ppm"));
+        expected.push(0xf6);
+        expected.extend(text("channel"));
+        expected.extend(text("beta"));
+        expected.extend(text("fw"));
+        expected.push(0xf6);
+        expected.extend(text("rollout"));
+        expected.extend(text("pending"));
+        expected.extend(frame_counts([[0; 3]; 5]));
+        expected.extend(text("retry_pct"));
+        expected.push(0xf6);
+        expected.extend(text("group"));
+        expected.push(0xf6);
+        // グループに登録したcam1のみを集計
+        expected.extend(text("groups"));
+        expected.push(0x81);
+        expected.push(0xa6);
+        expected.extend(text("group"));
+        expected.extend(text("north"));
+        expected.extend(text("devices"));
+        expected.push(0x01);
+        expected.extend(text("frames"));
+        expected.push(0x03);
+        expected.extend(text("aborts"));
+        expected.push(0x01);
+        expected.extend(text("success_pct"));
+        expected.extend([0x18, 75]);
+        expected.extend(text("battery_min"));
+        expected.extend([0x18, 80]);
+        // 展開中のチャンネル
+        expected.extend(text("rollout"));
+        expected.push(0x81);
+        expected.push(0xa4);
+        expected.extend(text("channel"));
+        expected.extend(text("beta"));
+        expected.extend(text("fw"));
+        expected.extend(text("1a2b3c4d"));
+        expected.extend(text("targeted"));
+        expected.push(0x01);
+        expected.extend(text("updated"));
+        expected.push(0x00);
+        // チャンネルの計測なし
+        expected.extend(text("radio"));
+        expected.push(0x80);
+        // 全体
+        expected.extend(frame_counts([[0; 3], [2, 1, 1], [0; 3], [0; 3], [0; 3]]));
+        assert_eq!(payload, expected);
+
+        // 次の期間は受信数をリセットし、電池残量と最終報告は引き継ぐ
+        let next = summary.take_payload(
+            now + Duration::from_secs(60),
+            None,
+            |_| 0,
+            |_| KeyStatus { epoch: 0, rotation: None },
+            &ReleaseChannels::new(),
+            &DeviceGroups::new(),
+            |_| None,
+            &FrameStats::new(),
+        );
+        let frames_a = [text("frames"), vec![0x00]].concat();
+        assert!(next.windows(frames_a.len()).any(|window| window == frames_a.as_slice()));
+        let skips_a = [text("skips"), vec![0xa0]].concat();
+        assert!(next.windows(skips_a.len()).any(|window| window == skips_a.as_slice()));
+        let battery_a = [text("battery"), vec![0x18, 80]].concat();
+        assert!(next.windows(battery_a.len()).any(|window| window == battery_a.as_slice()));
+
+        let (frame, _) = Frame::from_bytes(&to_frame(&next, 7)).unwrap();
+        assert_eq!(frame.frame_type(), FrameType::FleetSummary);
+        assert_eq!(frame.mac_address(), &GATEWAY_STATS_MAC);
+        assert_eq!(frame.data(), next.as_slice());
+    }
+
+    #[test]
+    fn test_retries_are_counted_per_busy_bucket() {
+        let start = Instant::now();
+        let mut summary = FleetSummary::new();
+        summary.register(MAC_A, "cam1", start);
+
+        // 計測前の受信は区分に数えない
+        summary.record_received(&MAC_A);
+        let quiet = ChannelUtilization { channel: 6, busy_percent: 10, frames_per_s: 20, foreign_percent: 50 };
+        summary.record_channel(&quiet);
+        for _ in 0..9 {
+            summary.record_received(&MAC_A);
+        }
+        summary.record_retries(&MAC_A, 1, 0);
+        let busy = ChannelUtilization { channel: 6, busy_percent: 80, frames_per_s: 300, foreign_percent: 90 };
+        summary.record_channel(&busy);
+        for _ in 0..10 {
+            summary.record_received(&MAC_A);
+        }
+        summary.record_retries(&MAC_A, 2, 3);
+        // 未登録のデバイスは数えない
+        summary.record_received(&MAC_B);
+        summary.record_retries(&MAC_B, 5, 5);
+
+        let payload = summary.take_payload(
+            start + Duration::from_secs(60),
+            None,
+            |_| 0,
+            |_| KeyStatus { epoch: 0, rotation: None },
+            &ReleaseChannels::new(),
+            &DeviceGroups::new(),
+            |_| None,
+            &FrameStats::new(),
+        );
+        // (1 + 2 + 3) / (20 + 3) = 26%
+        let retry = [text("retry_pct"), vec![0x18, 26]].concat();
+        assert!(payload.windows(retry.len()).any(|window| window == retry.as_slice()));
+
+        let mut radio = text("radio");
+        radio.extend([0x81, 0xa6]);
+        radio.extend(text("channel"));
+        radio.push(0x06);
+        radio.extend(text("samples"));
+        radio.push(0x02);
+        radio.extend(text("busy_pct"));
+        radio.extend([0x18, 45]);
+        radio.extend(text("busy_max_pct"));
+        radio.extend([0x18, 80]);
+        radio.extend(text("foreign_pct"));
+        radio.extend([0x18, 70]);
+        radio.extend(text("retry_by_busy"));
+        radio.extend([0x84, 0x82, 0x09, 0x01, 0x82, 0x00, 0x00, 0x82, 0x00, 0x00, 0x82, 0x0a, 0x05]);
+        assert!(payload.windows(radio.len()).any(|window| window == radio.as_slice()));
+
+        // 次の期間は集計をリセットする
+        let next = summary.take_payload(
+            start + Duration::from_secs(120),
+            None,
+            |_| 0,
+            |_| KeyStatus { epoch: 0, rotation: None },
+            &ReleaseChannels::new(),
+            &DeviceGroups::new(),
+            |_| None,
+            &FrameStats::new(),
+        );
+        let empty_radio = [text("radio"), vec![0x80]].concat();
+        assert!(next.windows(empty_radio.len()).any(|window| window == empty_radio.as_slice()));
+    }
+}
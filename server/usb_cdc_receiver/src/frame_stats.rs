@@ -0,0 +1,281 @@
+//! フレームタイプ別の受信・転送・エラー数
+//!
+//! 統計はすべてのフレームをまとめて数えるため、例えばEOFフレームだけが多く失われていても分かりません。
+//! データキューへ入るフレームをフレームタイプ（HASH・DATA・EOF・FEC・その他）ごとに、
+//! 全体とデバイスごとに数えます。センサー値のテレメトリはHASHフレームで届くため、HASHに含まれます。
+//!
+//! - 受信（`rx`）: ESP-NOWコールバックでデータキューへ入れようとしたフレーム
+//! - 転送（`fwd`）: USBへ送出したフレーム（転送完了イベントに集約したHASH/EOFを含む）
+//! - エラー（`err`）: データキューの溢れ・中断した転送の破棄・USBへの送出失敗で失われたフレーム
+//!
+//! 受信から転送・エラーまでの間はキューにあるため、`rx` は `fwd + err` 以上になります。
+//! 値は今回の起動からの累積で、`GET_STATS` の `CMD_STATS:` 行・統計フレーム・FLEET_SUMMARYに含めます。
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+
+use crate::esp_now::frame::{is_preframed, FRAME_HEADER_LEN, MAC_ADDRESS_LEN, MARKER_LEN};
+use crate::esp_now::FrameType;
+use crate::lifetime_stats::STATS_RESPONSE_PREFIX;
+use crate::mac_address::format_mac_address;
+
+/// デバイスごとに数える最大のデバイス数（超えた分は全体のみに数える）
+pub const MAX_TRACKED_DEVICES: usize = 32;
+
+/// 今回の起動からのフレームタイプ別の集計
+pub static FRAME_STATS: Mutex<FrameStats> = Mutex::new(FrameStats::new());
+
+/// 集計の区分
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameKind {
+    Hash,
+    Data,
+    Eof,
+    Fec,
+    /// 上記以外のフレームタイプ、またはフレームとして解析できないもの
+    Other,
+}
+
+impl FrameKind {
+    /// すべての区分（集計の並び順）
+    pub const ALL: [FrameKind; 5] = [
+        FrameKind::Hash,
+        FrameKind::Data,
+        FrameKind::Eof,
+        FrameKind::Fec,
+        FrameKind::Other,
+    ];
+
+    /// フレームのバイト列から区分を判定します
+    pub fn of_frame(frame: &[u8]) -> Self {
+        if !is_preframed(frame) || frame.len() < FRAME_HEADER_LEN {
+            return FrameKind::Other;
+        }
+        match FrameType::from_byte(frame[MARKER_LEN + MAC_ADDRESS_LEN]) {
+            Some(FrameType::Hash) => FrameKind::Hash,
+            Some(FrameType::Data) => FrameKind::Data,
+            Some(FrameType::Eof) => FrameKind::Eof,
+            Some(FrameType::Fec) => FrameKind::Fec,
+            _ => FrameKind::Other,
+        }
+    }
+
+    /// 応答行・統計フレームでの表記
+    pub fn as_str(self) -> &'static str {
+        match self {
+            FrameKind::Hash => "HASH",
+            FrameKind::Data => "DATA",
+            FrameKind::Eof => "EOF",
+            FrameKind::Fec => "FEC",
+            FrameKind::Other => "OTHER",
+        }
+    }
+
+    /// 統計フレームの項目名（受信・転送・エラー）
+    fn stats_keys(self) -> [&'static str; 3] {
+        match self {
+            FrameKind::Hash => ["HASH_RX", "HASH_FWD", "HASH_ERR"],
+            FrameKind::Data => ["DATA_RX", "DATA_FWD", "DATA_ERR"],
+            FrameKind::Eof => ["EOF_RX", "EOF_FWD", "EOF_ERR"],
+            FrameKind::Fec => ["FEC_RX", "FEC_FWD", "FEC_ERR"],
+            FrameKind::Other => ["OTHER_RX", "OTHER_FWD", "OTHER_ERR"],
+        }
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// 1区分の集計
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FrameCounts {
+    pub received: u32,
+    pub forwarded: u32,
+    pub errored: u32,
+}
+
+/// 区分ごとの集計
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FrameKindCounts([FrameCounts; FrameKind::ALL.len()]);
+
+impl FrameKindCounts {
+    const fn new() -> Self {
+        Self(
+            [FrameCounts {
+                received: 0,
+                forwarded: 0,
+                errored: 0,
+            }; FrameKind::ALL.len()],
+        )
+    }
+
+    /// 区分の集計
+    pub fn get(&self, kind: FrameKind) -> FrameCounts {
+        self.0[kind.index()]
+    }
+
+    /// 区分と集計の並び
+    pub fn iter(&self) -> impl Iterator<Item = (FrameKind, FrameCounts)> + '_ {
+        FrameKind::ALL.into_iter().map(|kind| (kind, self.get(kind)))
+    }
+
+    fn record(&mut self, kind: FrameKind, outcome: FrameOutcome) {
+        let counts = &mut self.0[kind.index()];
+        let counter = match outcome {
+            FrameOutcome::Received => &mut counts.received,
+            FrameOutcome::Forwarded => &mut counts.forwarded,
+            FrameOutcome::Errored => &mut counts.errored,
+        };
+        *counter = counter.saturating_add(1);
+    }
+
+    /// `HASH=rx/fwd/err DATA=...` 形式の表記
+    fn summary(&self) -> String {
+        let mut line = String::new();
+        for (kind, counts) in self.iter() {
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            // Stringへの書き込みは失敗しない
+            let _ = write!(line, "{}={}/{}/{}", kind.as_str(), counts.received, counts.forwarded, counts.errored);
+        }
+        line
+    }
+}
+
+/// フレームの扱われ方
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameOutcome {
+    Received,
+    Forwarded,
+    Errored,
+}
+
+/// 全体とデバイスごとの集計
+#[derive(Debug, Clone, Default)]
+pub struct FrameStats {
+    total: FrameKindCounts,
+    devices: BTreeMap<[u8; 6], FrameKindCounts>,
+}
+
+impl FrameStats {
+    /// 空の集計を作成します
+    pub const fn new() -> Self {
+        Self {
+            total: FrameKindCounts::new(),
+            devices: BTreeMap::new(),
+        }
+    }
+
+    /// フレームを数えます
+    pub fn record(&mut self, mac: &[u8; 6], kind: FrameKind, outcome: FrameOutcome) {
+        self.total.record(kind, outcome);
+        if !self.devices.contains_key(mac) && self.devices.len() >= MAX_TRACKED_DEVICES {
+            return;
+        }
+        self.devices.entry(*mac).or_default().record(kind, outcome);
+    }
+
+    /// 全体の集計
+    pub fn total(&self) -> &FrameKindCounts {
+        &self.total
+    }
+
+    /// デバイスの集計（受信がなければNone）
+    pub fn device(&self, mac: &[u8; 6]) -> Option<&FrameKindCounts> {
+        self.devices.get(mac)
+    }
+
+    /// 統計フレームの項目（全体のみ、`HASH_RX` のような区分ごとの3項目）
+    pub fn stats_fields(&self) -> Vec<(&'static str, u32)> {
+        self.total
+            .iter()
+            .flat_map(|(kind, counts)| {
+                let [rx, fwd, err] = kind.stats_keys();
+                [(rx, counts.received), (fwd, counts.forwarded), (err, counts.errored)]
+            })
+            .collect()
+    }
+
+    /// `GET_STATS` の応答に加える行（全体とデバイスごと、値は `rx/fwd/err`）
+    pub fn response(&self) -> String {
+        let mut response = format!("{}frame_types {}\n", STATS_RESPONSE_PREFIX, self.total.summary());
+        for (mac, counts) in &self.devices {
+            response.push_str(&format!(
+                "{}frame_types {} {}\n",
+                STATS_RESPONSE_PREFIX,
+                format_mac_address(mac),
+                counts.summary()
+            ));
+        }
+        response
+    }
+}
+
+/// フレームを数えます（ロックがポイズン状態なら数えない）
+pub fn record(mac: &[u8; 6], kind: FrameKind, outcome: FrameOutcome) {
+    if let Ok(mut stats) = FRAME_STATS.lock() {
+        stats.record(mac, kind, outcome);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::esp_now::frame::create_frame;
+
+    const MAC_A: [u8; 6] = [0x34, 0xab, 0x95, 0xfb, 0x3f, 0xc4];
+    const MAC_B: [u8; 6] = [0x34, 0xab, 0x95, 0xfb, 0x3f, 0xc5];
+
+    #[test]
+    fn test_kind_of_frame() {
+        assert_eq!(FrameKind::of_frame(&create_frame(MAC_A, b"HASH:ab", FrameType::Hash, 1)), FrameKind::Hash);
+        assert_eq!(FrameKind::of_frame(&create_frame(MAC_A, b"EOF!", FrameType::Eof, 2)), FrameKind::Eof);
+        assert_eq!(FrameKind::of_frame(&create_frame(MAC_A, b"", FrameType::Abort, 3)), FrameKind::Other);
+        assert_eq!(FrameKind::of_frame(b"EOF!"), FrameKind::Other);
+    }
+
+    #[test]
+    fn test_counts_per_kind_and_device() {
+        let mut stats = FrameStats::new();
+        stats.record(&MAC_A, FrameKind::Data, FrameOutcome::Received);
+        stats.record(&MAC_A, FrameKind::Data, FrameOutcome::Forwarded);
+        stats.record(&MAC_A, FrameKind::Eof, FrameOutcome::Received);
+        stats.record(&MAC_A, FrameKind::Eof, FrameOutcome::Errored);
+        stats.record(&MAC_B, FrameKind::Hash, FrameOutcome::Received);
+
+        assert_eq!(
+            stats.total().get(FrameKind::Data),
+            FrameCounts {
+                received: 1,
+                forwarded: 1,
+                errored: 0
+            }
+        );
+        assert_eq!(stats.device(&MAC_A).unwrap().get(FrameKind::Eof).errored, 1);
+        assert_eq!(stats.device(&MAC_B).unwrap().get(FrameKind::Eof), FrameCounts::default());
+        assert!(stats.device(&[0; 6]).is_none());
+
+        assert_eq!(
+            stats.response(),
+            "CMD_STATS:frame_types HASH=1/0/0 DATA=1/1/0 EOF=1/0/1 FEC=0/0/0 OTHER=0/0/0\n\
+             CMD_STATS:frame_types 34:ab:95:fb:3f:c4 HASH=0/0/0 DATA=1/1/0 EOF=1/0/1 FEC=0/0/0 OTHER=0/0/0\n\
+             CMD_STATS:frame_types 34:ab:95:fb:3f:c5 HASH=1/0/0 DATA=0/0/0 EOF=0/0/0 FEC=0/0/0 OTHER=0/0/0\n"
+        );
+        let fields = stats.stats_fields();
+        assert_eq!(fields.len(), 15);
+        assert!(fields.contains(&("EOF_ERR", 1)));
+    }
+
+    #[test]
+    fn test_tracked_devices_are_bounded() {
+        let mut stats = FrameStats::new();
+        for index in 0..=MAX_TRACKED_DEVICES {
+            stats.record(&[0, 0, 0, 0, 0, index as u8], FrameKind::Data, FrameOutcome::Received);
+        }
+        assert_eq!(stats.total().get(FrameKind::Data).received, MAX_TRACKED_DEVICES as u32 + 1);
+        assert!(stats.device(&[0, 0, 0, 0, 0, MAX_TRACKED_DEVICES as u8]).is_none());
+    }
+}
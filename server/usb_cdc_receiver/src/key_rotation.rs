@@ -0,0 +1,363 @@
+//! デバイスごとのダウンリンク認証鍵の更新
+//!
+//! `ROTATE_KEY` でゲートウェイが新しい鍵を生成し、デバイスが次に転送を終えたとき（スリープコマンドを
+//! 待って受信中の間）に現在の鍵で暗号化した鍵更新メッセージを送ります。メッセージには切り替えるコマンド
+//! カウンタ（鍵更新メッセージのカウンタ + 1）を載せ、ゲートウェイとデバイスはそのカウンタ以上の
+//! コマンドから新しい鍵で署名・検証します。
+//!
+//! デバイスはHASHフレームで現在の鍵の世代（`KEY_EPOCH`）と保存済みの新しい鍵の世代（`KEY_PENDING`）を
+//! 報告します。`KEY_EPOCH` が新しい世代になった時点で更新を完了し、`KEY_PENDING` の報告がない間は
+//! 転送のたびに送り直します（送り直すまでのコマンドは現在の鍵で署名します）。
+//! 一斉配信はプロビジョニングした鍵のまま署名します。
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::camera_settings::CheckIn;
+use crate::esp_now::downlink_auth::{DownlinkKey, DOWNLINK_KEY_LEN};
+use crate::mac_address::format_mac_address;
+
+/// 鍵更新コマンド応答の接頭辞
+pub const KEY_ROTATION_RESPONSE_PREFIX: &str = "CMD_ROTATE_KEY:";
+
+/// NVSに保存する記録のバイト長
+///
+/// `[EPOCH(4)] [KEY(32)] [PENDING_EPOCH(4)] [PENDING_KEY(32)] [ACTIVATE_COUNTER(4)]`
+/// （世代0はプロビジョニングした鍵で、鍵の欄は使わない。更新中でなければ PENDING_EPOCH は0）
+pub const DEVICE_KEY_RECORD_LEN: usize = 4 + DOWNLINK_KEY_LEN + 4 + DOWNLINK_KEY_LEN + 4;
+
+/// 鍵更新の進み具合
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RotationState {
+    /// デバイスが新しい鍵の保存を報告していない（転送のたびに送り直す）
+    Delivering,
+    /// デバイスが新しい鍵を保存済み（切り替え後のコマンドで有効になる）
+    Staged,
+}
+
+impl RotationState {
+    /// 応答行・サマリーでの表記
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RotationState::Delivering => "delivering",
+            RotationState::Staged => "staged",
+        }
+    }
+}
+
+/// デバイスの鍵の状態（定期サマリー用）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyStatus {
+    /// 現在の鍵の世代
+    pub epoch: u32,
+    /// 更新中であればその進み具合
+    pub rotation: Option<RotationState>,
+}
+
+/// 送信を予約した鍵更新
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyDelivery {
+    /// 宛先
+    pub mac: [u8; 6],
+    /// 新しい鍵の世代
+    pub epoch: u32,
+    /// 新しい鍵
+    pub new_key: DownlinkKey,
+    /// 暗号化・署名に使う現在の鍵（プロビジョニングした鍵ならNone）
+    pub current_key: Option<DownlinkKey>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct PendingRotation {
+    epoch: u32,
+    key: DownlinkKey,
+    /// 最後に送った鍵更新で指定した切り替えカウンタ（未送信・送り直し待ちはNone）
+    activate_counter: Option<u32>,
+    state: RotationState,
+    due: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct DeviceKeys {
+    epoch: u32,
+    /// 更新済みの鍵（プロビジョニングした鍵ならNone）
+    key: Option<DownlinkKey>,
+    pending: Option<PendingRotation>,
+}
+
+/// HASHペイロードから鍵の世代の報告（`KEY_EPOCH`・`KEY_PENDING`）を解析します
+fn reported_epochs(payload: &[u8]) -> (Option<u32>, Option<u32>) {
+    let Ok(payload) = std::str::from_utf8(payload) else {
+        return (None, None);
+    };
+    let field = |name: &str| {
+        payload
+            .split(',')
+            .find_map(|item| item.strip_prefix(name)?.strip_prefix(':'))
+            .and_then(|value| value.trim().parse().ok())
+    };
+    (field("KEY_EPOCH"), field("KEY_PENDING"))
+}
+
+/// デバイスごとの鍵と更新の進み具合
+#[derive(Debug, Default)]
+pub struct KeyRotationRegistry {
+    devices: BTreeMap<[u8; 6], DeviceKeys>,
+    /// NVSへ保存していない変更があるデバイス
+    dirty: BTreeSet<[u8; 6]>,
+}
+
+impl KeyRotationRegistry {
+    /// 空の登録を作成します
+    pub const fn new() -> Self {
+        Self {
+            devices: BTreeMap::new(),
+            dirty: BTreeSet::new(),
+        }
+    }
+
+    /// NVSに保存した記録から復元します（長さが合わなければfalse）
+    pub fn restore(&mut self, mac: [u8; 6], record: &[u8]) -> bool {
+        if record.len() != DEVICE_KEY_RECORD_LEN {
+            return false;
+        }
+        let u32_at = |offset: usize| u32::from_le_bytes(record[offset..offset + 4].try_into().unwrap());
+        let key_at = |offset: usize| {
+            DownlinkKey::from_bytes(record[offset..offset + DOWNLINK_KEY_LEN].try_into().unwrap())
+        };
+        let epoch = u32_at(0);
+        let pending_epoch = u32_at(4 + DOWNLINK_KEY_LEN);
+        let activate_counter = u32_at(DEVICE_KEY_RECORD_LEN - 4);
+        let device = DeviceKeys {
+            epoch,
+            key: (epoch > 0).then(|| key_at(4)),
+            pending: (pending_epoch > 0).then(|| PendingRotation {
+                epoch: pending_epoch,
+                key: key_at(8 + DOWNLINK_KEY_LEN),
+                activate_counter: (activate_counter > 0).then_some(activate_counter),
+                state: RotationState::Delivering,
+                due: false,
+            }),
+        };
+        self.devices.insert(mac, device);
+        true
+    }
+
+    /// 新しい鍵で更新を始めます
+    ///
+    /// # 戻り値
+    /// * 新しい鍵の世代。更新中のデバイスはNone（切り替えカウンタを送った後に鍵を差し替えると、
+    ///   デバイスが保存した鍵と食い違うため）
+    pub fn schedule(&mut self, mac: [u8; 6], new_key: DownlinkKey) -> Option<u32> {
+        let device = self.devices.entry(mac).or_default();
+        if device.pending.is_some() {
+            return None;
+        }
+        let epoch = device.epoch.checked_add(1)?;
+        device.pending = Some(PendingRotation {
+            epoch,
+            key: new_key,
+            activate_counter: None,
+            state: RotationState::Delivering,
+            due: false,
+        });
+        self.dirty.insert(mac);
+        Some(epoch)
+    }
+
+    /// デバイスの転送完了時にHASHペイロードの報告と照合します
+    pub fn record_check_in(&mut self, mac: &[u8; 6], payload: Option<&[u8]>) -> CheckIn {
+        let Some(device) = self.devices.get_mut(mac) else {
+            return CheckIn::Idle;
+        };
+        let Some(pending) = device.pending.as_mut() else {
+            return CheckIn::Idle;
+        };
+        let (reported_epoch, reported_pending) = payload.map_or((None, None), reported_epochs);
+        if reported_epoch == Some(pending.epoch) {
+            device.epoch = pending.epoch;
+            device.key = Some(pending.key.clone());
+            device.pending = None;
+            self.dirty.insert(*mac);
+            return CheckIn::Confirmed;
+        }
+        if reported_pending == Some(pending.epoch) && pending.activate_counter.is_some() {
+            pending.state = RotationState::Staged;
+            return CheckIn::Idle;
+        }
+        if reported_epoch.is_none() && pending.activate_counter.is_some() {
+            // 報告がなければ、デバイスが切り替え済みの可能性があるため現在の鍵に戻さない
+            return CheckIn::Idle;
+        }
+        // 届いていなければ送り直すまで現在の鍵で署名する
+        pending.state = RotationState::Delivering;
+        pending.activate_counter = None;
+        pending.due = true;
+        self.dirty.insert(*mac);
+        CheckIn::Deliver
+    }
+
+    /// 送信を予約した鍵更新を1件取り出します
+    pub fn take_due(&mut self) -> Option<KeyDelivery> {
+        self.devices.iter_mut().find_map(|(mac, device)| {
+            let pending = device.pending.as_mut().filter(|pending| pending.due)?;
+            pending.due = false;
+            Some(KeyDelivery {
+                mac: *mac,
+                epoch: pending.epoch,
+                new_key: pending.key.clone(),
+                current_key: device.key.clone(),
+            })
+        })
+    }
+
+    /// 鍵更新を送ったことを記録し、切り替えカウンタ以降のコマンドを新しい鍵で署名します
+    pub fn mark_sent(&mut self, mac: &[u8; 6], epoch: u32, activate_counter: u32) {
+        let pending = self
+            .devices
+            .get_mut(mac)
+            .and_then(|device| device.pending.as_mut())
+            .filter(|pending| pending.epoch == epoch);
+        if let Some(pending) = pending {
+            pending.activate_counter = Some(activate_counter);
+            self.dirty.insert(*mac);
+        }
+    }
+
+    /// 送る前の更新を取り消します（署名器がなく送れない場合）
+    pub fn cancel(&mut self, mac: &[u8; 6]) {
+        if let Some(device) = self.devices.get_mut(mac) {
+            if device.pending.take().is_some() {
+                self.dirty.insert(*mac);
+            }
+        }
+    }
+
+    /// コマンドの署名に使う鍵（プロビジョニングした鍵を使う場合はNone）
+    pub fn signing_key(&self, mac: &[u8; 6], counter: u32) -> Option<DownlinkKey> {
+        let device = self.devices.get(mac)?;
+        match &device.pending {
+            Some(pending) if pending.activate_counter.is_some_and(|activate| counter >= activate) => {
+                Some(pending.key.clone())
+            }
+            _ => device.key.clone(),
+        }
+    }
+
+    /// 更新中か
+    pub fn is_pending(&self, mac: &[u8; 6]) -> bool {
+        self.devices.get(mac).is_some_and(|device| device.pending.is_some())
+    }
+
+    /// 鍵の状態（一度も更新していないデバイスは世代0）
+    pub fn status(&self, mac: &[u8; 6]) -> KeyStatus {
+        let device = self.devices.get(mac);
+        KeyStatus {
+            epoch: device.map_or(0, |device| device.epoch),
+            rotation: device.and_then(|device| device.pending.as_ref()).map(|pending| pending.state),
+        }
+    }
+
+    /// NVSへ保存していない記録を1件取り出します
+    pub fn take_dirty(&mut self) -> Option<([u8; 6], [u8; DEVICE_KEY_RECORD_LEN])> {
+        let mac = self.dirty.pop_first()?;
+        let device = self.devices.get(&mac)?;
+        let mut record = [0u8; DEVICE_KEY_RECORD_LEN];
+        record[..4].copy_from_slice(&device.epoch.to_le_bytes());
+        if let Some(key) = &device.key {
+            record[4..4 + DOWNLINK_KEY_LEN].copy_from_slice(&key.to_bytes());
+        }
+        if let Some(pending) = &device.pending {
+            let offset = 4 + DOWNLINK_KEY_LEN;
+            record[offset..offset + 4].copy_from_slice(&pending.epoch.to_le_bytes());
+            record[offset + 4..offset + 4 + DOWNLINK_KEY_LEN].copy_from_slice(&pending.key.to_bytes());
+            record[DEVICE_KEY_RECORD_LEN - 4..]
+                .copy_from_slice(&pending.activate_counter.unwrap_or(0).to_le_bytes());
+        }
+        Some((mac, record))
+    }
+
+    /// `ROTATE_KEY` への応答行
+    pub fn response(&self, mac: &[u8; 6]) -> String {
+        let status = self.status(mac);
+        let state = match (status.rotation, self.devices.get(mac).and_then(|device| device.pending.as_ref())) {
+            (Some(rotation), Some(pending)) => format!("{} epoch={}", rotation.as_str(), pending.epoch),
+            _ => format!("active epoch={}", status.epoch),
+        };
+        format!("{}{} {}\n", KEY_ROTATION_RESPONSE_PREFIX, format_mac_address(mac), state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAC: [u8; 6] = [0x34, 0xab, 0x95, 0xfb, 0x3f, 0xc4];
+
+    fn key(byte: u8) -> DownlinkKey {
+        DownlinkKey::from_bytes([byte; DOWNLINK_KEY_LEN])
+    }
+
+    #[test]
+    fn test_rotation_switches_at_activation_counter_and_completes_on_report() {
+        let mut registry = KeyRotationRegistry::new();
+        assert_eq!(registry.schedule(MAC, key(1)), Some(1));
+        assert_eq!(registry.schedule(MAC, key(2)), None);
+        assert_eq!(registry.response(&MAC), "CMD_ROTATE_KEY:34:ab:95:fb:3f:c4 delivering epoch=1\n");
+
+        assert_eq!(registry.record_check_in(&MAC, Some(b"HASH:ab,KEY_EPOCH:0")), CheckIn::Deliver);
+        let delivery = registry.take_due().unwrap();
+        assert_eq!((delivery.epoch, delivery.current_key), (1, None));
+        assert_eq!(registry.take_due(), None);
+
+        // 鍵更新を送るまでは現在の鍵、送った後は切り替えカウンタ以降だけ新しい鍵
+        assert_eq!(registry.signing_key(&MAC, 50), None);
+        registry.mark_sent(&MAC, 1, 51);
+        assert_eq!(registry.signing_key(&MAC, 50), None);
+        assert_eq!(registry.signing_key(&MAC, 51), Some(key(1)));
+
+        assert_eq!(registry.record_check_in(&MAC, Some(b"HASH:ab,KEY_EPOCH:0,KEY_PENDING:1")), CheckIn::Idle);
+        assert_eq!(registry.status(&MAC), KeyStatus { epoch: 0, rotation: Some(RotationState::Staged) });
+
+        assert_eq!(registry.record_check_in(&MAC, Some(b"HASH:ab,KEY_EPOCH:1")), CheckIn::Confirmed);
+        assert_eq!(registry.status(&MAC), KeyStatus { epoch: 1, rotation: None });
+        assert_eq!(registry.signing_key(&MAC, 10), Some(key(1)));
+        assert_eq!(registry.response(&MAC), "CMD_ROTATE_KEY:34:ab:95:fb:3f:c4 active epoch=1\n");
+
+        // 次の更新は更新済みの鍵で暗号化する
+        assert_eq!(registry.schedule(MAC, key(2)), Some(2));
+        registry.record_check_in(&MAC, None);
+        assert_eq!(registry.take_due().unwrap().current_key, Some(key(1)));
+    }
+
+    #[test]
+    fn test_lost_delivery_reverts_to_current_key_until_resent() {
+        let mut registry = KeyRotationRegistry::new();
+        registry.schedule(MAC, key(1));
+        registry.record_check_in(&MAC, None);
+        registry.take_due();
+        registry.mark_sent(&MAC, 1, 20);
+
+        assert_eq!(registry.record_check_in(&MAC, None), CheckIn::Idle);
+        assert_eq!(registry.signing_key(&MAC, 30), Some(key(1)));
+
+        assert_eq!(registry.record_check_in(&MAC, Some(b"HASH:ab,KEY_EPOCH:0")), CheckIn::Deliver);
+        assert_eq!(registry.signing_key(&MAC, 30), None);
+        assert!(registry.take_due().is_some());
+    }
+
+    #[test]
+    fn test_record_round_trip_keeps_pending_rotation() {
+        let mut registry = KeyRotationRegistry::new();
+        registry.schedule(MAC, key(1));
+        registry.mark_sent(&MAC, 1, 77);
+        let (mac, record) = registry.take_dirty().unwrap();
+        assert_eq!(registry.take_dirty(), None);
+
+        let mut restored = KeyRotationRegistry::new();
+        assert!(!restored.restore(mac, &record[1..]));
+        assert!(restored.restore(mac, &record));
+        assert_eq!(restored.signing_key(&MAC, 77), Some(key(1)));
+        assert_eq!(restored.signing_key(&MAC, 76), None);
+        assert_eq!(restored.status(&MAC), KeyStatus { epoch: 0, rotation: Some(RotationState::Delivering) });
+    }
+}
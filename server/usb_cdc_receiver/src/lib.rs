@@ -4,20 +4,115 @@
 pub mod esp_now;
 pub mod mac_address;
 
+// ゲートウェイ統計フレーム（ホストテストでも使用可能）
+pub mod stats;
+
+// CBORエンコーダー・ゲートウェイの時計・時計のずれの推定と登録デバイスの定期サマリー（ホストテストでも使用可能）
+pub mod cbor;
+#[cfg_attr(not(feature = "esp"), allow(dead_code))]
+pub mod clock;
+pub mod clock_skew;
+#[cfg_attr(not(feature = "esp"), allow(dead_code))]
+pub mod fleet_summary;
+
+// CPU使用率の集計（ホストテストでも使用可能）
+pub mod cpu_usage;
+
+// チャンネルの混雑度の推定（ホストテストでも使用可能）
+#[cfg_attr(not(feature = "esp"), allow(dead_code))]
+pub mod channel_utilization;
+
+// 空きヒープの推移と枯渇の予測（ホストテストでも使用可能）
+#[cfg_attr(not(feature = "esp"), allow(dead_code))]
+pub mod memory_trend;
+
 // コマンド解析（ホストテストでも使用可能）
 #[cfg_attr(not(feature = "esp"), allow(dead_code))]
 pub mod command;
 
+// スリープコマンドの許容範囲チェック（ホストテストでも使用可能）
+#[cfg_attr(not(feature = "esp"), allow(dead_code))]
+pub mod sleep_policy;
+
+// デバイスの一時停止（ホストテストでも使用可能）
+#[cfg_attr(not(feature = "esp"), allow(dead_code))]
+pub mod pause;
+
+// カメラ画質設定（ホストテストでも使用可能）
+#[cfg_attr(not(feature = "esp"), allow(dead_code))]
+pub mod camera_settings;
+
+// デバッグフラグの一時的な切り替え（ホストテストでも使用可能）
+#[cfg_attr(not(feature = "esp"), allow(dead_code))]
+pub mod debug_flags;
+
+// 送信済み画像の再送要求（ホストテストでも使用可能）
+#[cfg_attr(not(feature = "esp"), allow(dead_code))]
+pub mod resend;
+
+// 転送後のコマンド待機の短縮（ホストテストでも使用可能）
+#[cfg_attr(not(feature = "esp"), allow(dead_code))]
+pub mod downlink_window;
+
+// デバイスごとのダウンリンク認証鍵の更新（ホストテストでも使用可能）
+#[cfg_attr(not(feature = "esp"), allow(dead_code))]
+pub mod key_rotation;
+
+// デバイスへのファイル転送（ホストテストでも使用可能）
+#[cfg_attr(not(feature = "esp"), allow(dead_code))]
+pub mod file_transfer;
+
+// ファームウェアのリリースチャンネル（ホストテストでも使用可能）
+#[cfg_attr(not(feature = "esp"), allow(dead_code))]
+pub mod release_channel;
+
+// デバイスのグループ（ホストテストでも使用可能）
+#[cfg_attr(not(feature = "esp"), allow(dead_code))]
+pub mod device_groups;
+
+// 設定の一斉配信（ホストテストでも使用可能）
+#[cfg_attr(not(feature = "esp"), allow(dead_code))]
+pub mod broadcast;
+
+// USBへ送出できなかったフレームの退避（ホストテストでも使用可能）
+#[cfg_attr(not(feature = "esp"), allow(dead_code))]
+pub mod dead_letter;
+
+// 再起動前のサブシステムの停止（ホストテストでも使用可能）
+#[cfg_attr(not(feature = "esp"), allow(dead_code))]
+pub mod shutdown;
+
+// ソークテストの集計（ホストテストでも使用可能）
+#[cfg_attr(not(feature = "esp"), allow(dead_code))]
+pub mod soak;
+
+// 再起動をまたぐゲートウェイの累積統計（ホストテストでも使用可能）
+#[cfg_attr(not(feature = "esp"), allow(dead_code))]
+pub mod lifetime_stats;
+
+// フレームタイプ別の受信・転送・エラー数（ホストテストでも使用可能）
+#[cfg_attr(not(feature = "esp"), allow(dead_code))]
+pub mod frame_stats;
+
+// 仮想デバイスのエミュレーション（ホストテストでも使用可能）
+#[cfg_attr(not(feature = "esp"), allow(dead_code))]
+pub mod emulation;
+
+// ESP-IDF呼び出しのラッパー（常に公開 - Mock実装を含む）
+#[cfg_attr(not(feature = "esp"), allow(dead_code))]
+pub mod sys_wrappers;
+
 // USB モジュール（常に公開 - Mock実装を含む）
 pub mod usb;
 
+// 受信コールバックからUSB送信タスクへのデータキュー（ホストテストでも使用可能）
+#[cfg_attr(not(feature = "esp"), allow(dead_code))]
+pub mod queue;
+
 // 以下のモジュールはESP-IDF依存のため、"esp"フィーチャー有効時のみコンパイル
 #[cfg(feature = "esp")]
 pub mod config;
 
-#[cfg(feature = "esp")]
-pub mod queue;
-
 // streaming モジュール内の device_manager はロジックのみなのでホストテストでも有効化したい
 // そのため、streaming モジュール自体は常に有効化し、内部で制御する
 pub mod streaming;
@@ -25,4 +120,7 @@ pub mod streaming;
 #[cfg(feature = "esp")]
 pub mod sleep_command_queue;
 
+#[cfg(feature = "esp")]
+pub mod tasks;
+
 // 必要に応じてユーティリティ関数もエクスポート
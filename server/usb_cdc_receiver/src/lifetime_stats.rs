@@ -0,0 +1,335 @@
+//! 再起動をまたぐゲートウェイの累積統計
+//!
+//! 統計は再起動で0に戻るため、夜間のクラッシュのような再起動がPCからは見えません。
+//! 稼働時間・受信フレーム数・エラー数の累積と起動回数・今回の起動理由をNVSへ保存し、
+//! 次の起動時に読み込んで続きから数えます。フラッシュの書き込みを抑えるため、保存は
+//! メンテナンスタスクから `PERSIST_INTERVAL` ごとと、停止処理（`SOFT_RESET`・パニック）のときだけ行います。
+//! `GET_STATS` は今回の起動からの値（`since_boot`）と累積（`lifetime`）を `CMD_STATS:` の2行で返し、
+//! 統計フレームにも同じ値を付加します。PCは起動回数の増加や `since_boot` の値の減少から、
+//! ゲートウェイの気付かれない再起動を検知できます。
+//! 電源断やウォッチドッグによる再起動では、最後の保存以降の分は累積に含まれません。
+
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Mutex;
+#[cfg(feature = "esp")]
+use std::sync::PoisonError;
+use std::time::Duration;
+
+#[cfg(feature = "esp")]
+use esp_idf_svc::nvs::{EspDefaultNvsPartition, EspNvs, NvsDefault};
+#[cfg(feature = "esp")]
+use esp_idf_svc::sys::EspError;
+
+#[cfg(feature = "esp")]
+use crate::shutdown::{Shutdown, ShutdownReason};
+#[cfg(feature = "esp")]
+use crate::sys_wrappers::esp as sys;
+
+/// 統計応答の接頭辞
+pub const STATS_RESPONSE_PREFIX: &str = "CMD_STATS:";
+
+/// 累積統計をNVSへ保存する間隔
+pub const PERSIST_INTERVAL: Duration = Duration::from_secs(600);
+
+/// 保存形式のバージョン
+const LIFETIME_RECORD_VERSION: u8 = 1;
+
+/// 保存形式の長さ: [VERSION(1)] [BOOTS(4)] [UPTIME_S(8)] [FRAMES(8)] [ERRORS(8)] [RESET_REASON(1)]（LE）
+pub const LIFETIME_RECORD_LEN: usize = 30;
+
+/// 累積統計のNVS名前空間
+#[cfg(feature = "esp")]
+const LIFETIME_NVS_NAMESPACE: &str = "gw_stats";
+/// 累積統計のキー
+#[cfg(feature = "esp")]
+const LIFETIME_NVS_KEY: &str = "lifetime";
+
+/// 今回の起動から受信したフレーム数
+static FRAMES_SINCE_BOOT: AtomicU32 = AtomicU32::new(0);
+
+/// 今回の起動からのエラー数（データキューの溢れ・USBへの送出失敗）
+static ERRORS_SINCE_BOOT: AtomicU32 = AtomicU32::new(0);
+
+/// 前回までの累積と今回の起動理由（起動時にNVSから読み込む）
+pub static LIFETIME_STATS: Mutex<LifetimeStats> = Mutex::new(LifetimeStats::new());
+
+/// 受信したフレームを数えます
+pub fn record_frame() {
+    FRAMES_SINCE_BOOT.fetch_add(1, Ordering::Relaxed);
+}
+
+/// エラーを数えます
+pub fn record_errors(count: u32) {
+    ERRORS_SINCE_BOOT.fetch_add(count, Ordering::Relaxed);
+}
+
+/// 今回の起動からの値
+pub fn since_boot(uptime_s: u64) -> SinceBoot {
+    SinceBoot {
+        uptime_s,
+        frames: FRAMES_SINCE_BOOT.load(Ordering::Relaxed),
+        errors: ERRORS_SINCE_BOOT.load(Ordering::Relaxed),
+    }
+}
+
+/// `esp_reset_reason_t` の名前（デバイスの `BOOT` 項目と同じ表記）
+pub fn reset_reason_name(code: u8) -> &'static str {
+    match code {
+        1 => "POWERON",
+        2 => "EXT",
+        3 => "SW",
+        4 => "PANIC",
+        5 => "INT_WDT",
+        6 => "TASK_WDT",
+        7 => "WDT",
+        8 => "DEEPSLEEP",
+        9 => "BROWNOUT",
+        10 => "SDIO",
+        _ => "UNKNOWN",
+    }
+}
+
+/// 今回の起動からの値
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SinceBoot {
+    /// 稼働時間（秒）
+    pub uptime_s: u64,
+    /// 受信したフレーム数
+    pub frames: u32,
+    /// エラー数
+    pub errors: u32,
+}
+
+/// NVSに保存する累積統計
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LifetimeRecord {
+    /// 起動回数
+    pub boots: u32,
+    /// 稼働時間の累積（秒）
+    pub uptime_s: u64,
+    /// 受信フレーム数の累積
+    pub frames: u64,
+    /// エラー数の累積
+    pub errors: u64,
+    /// 直近の起動理由（`esp_reset_reason_t`）
+    pub reset_reason: u8,
+}
+
+impl LifetimeRecord {
+    /// 保存形式に変換します
+    pub fn to_bytes(&self) -> [u8; LIFETIME_RECORD_LEN] {
+        let mut bytes = [0u8; LIFETIME_RECORD_LEN];
+        bytes[0] = LIFETIME_RECORD_VERSION;
+        bytes[1..5].copy_from_slice(&self.boots.to_le_bytes());
+        bytes[5..13].copy_from_slice(&self.uptime_s.to_le_bytes());
+        bytes[13..21].copy_from_slice(&self.frames.to_le_bytes());
+        bytes[21..29].copy_from_slice(&self.errors.to_le_bytes());
+        bytes[29] = self.reset_reason;
+        bytes
+    }
+
+    /// 保存形式から復元します（長さかバージョンが合わなければNone）
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != LIFETIME_RECORD_LEN || bytes[0] != LIFETIME_RECORD_VERSION {
+            return None;
+        }
+        let u64_at = |offset: usize| u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap());
+        Some(Self {
+            boots: u32::from_le_bytes(bytes[1..5].try_into().unwrap()),
+            uptime_s: u64_at(5),
+            frames: u64_at(13),
+            errors: u64_at(21),
+            reset_reason: bytes[29],
+        })
+    }
+}
+
+/// 前回までの累積に今回の起動からの値を加えて累積統計を求めます
+#[derive(Debug, Clone)]
+pub struct LifetimeStats {
+    /// 前回の起動までの累積（起動回数は今回を含む）
+    previous: LifetimeRecord,
+}
+
+impl LifetimeStats {
+    /// 読み込み前の統計を作成します（今回の起動のみ）
+    pub const fn new() -> Self {
+        Self {
+            previous: LifetimeRecord {
+                boots: 1,
+                uptime_s: 0,
+                frames: 0,
+                errors: 0,
+                reset_reason: 0,
+            },
+        }
+    }
+
+    /// 保存済みの累積（なければNone）に今回の起動を加えます
+    pub fn on_boot(saved: Option<LifetimeRecord>, reset_reason: u8) -> Self {
+        let saved = saved.unwrap_or_default();
+        Self {
+            previous: LifetimeRecord {
+                boots: saved.boots.saturating_add(1),
+                reset_reason,
+                ..saved
+            },
+        }
+    }
+
+    /// 今回の起動からの値を加えた累積（保存する内容）
+    pub fn record(&self, since_boot: SinceBoot) -> LifetimeRecord {
+        LifetimeRecord {
+            uptime_s: self.previous.uptime_s.saturating_add(since_boot.uptime_s),
+            frames: self.previous.frames.saturating_add(u64::from(since_boot.frames)),
+            errors: self.previous.errors.saturating_add(u64::from(since_boot.errors)),
+            ..self.previous
+        }
+    }
+
+    /// 統計フレームの項目
+    pub fn stats_fields(&self, since_boot: SinceBoot) -> [(&'static str, String); 8] {
+        let lifetime = self.record(since_boot);
+        [
+            ("BOOTS", lifetime.boots.to_string()),
+            ("RESET_REASON", reset_reason_name(lifetime.reset_reason).to_string()),
+            ("UPTIME_S", since_boot.uptime_s.to_string()),
+            ("FRAMES", since_boot.frames.to_string()),
+            ("ERRORS", since_boot.errors.to_string()),
+            ("LIFE_UPTIME_S", lifetime.uptime_s.to_string()),
+            ("LIFE_FRAMES", lifetime.frames.to_string()),
+            ("LIFE_ERRORS", lifetime.errors.to_string()),
+        ]
+    }
+
+    /// `GET_STATS` の応答（今回の起動からの値と累積の2行）
+    pub fn response(&self, since_boot: SinceBoot) -> String {
+        let lifetime = self.record(since_boot);
+        format!(
+            "{prefix}since_boot uptime_s={} frames={} errors={}\n\
+             {prefix}lifetime boots={} uptime_s={} frames={} errors={} last_reset={}\n",
+            since_boot.uptime_s,
+            since_boot.frames,
+            since_boot.errors,
+            lifetime.boots,
+            lifetime.uptime_s,
+            lifetime.frames,
+            lifetime.errors,
+            reset_reason_name(lifetime.reset_reason),
+            prefix = STATS_RESPONSE_PREFIX,
+        )
+    }
+}
+
+impl Default for LifetimeStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// 今回の起動からの稼働時間（秒）
+#[cfg(feature = "esp")]
+pub fn uptime_s() -> u64 {
+    sys::tick_ms() / 1000
+}
+
+/// 累積統計のNVSの保存先
+#[cfg(feature = "esp")]
+pub struct LifetimeStatsStore {
+    nvs: EspNvs<NvsDefault>,
+}
+
+#[cfg(feature = "esp")]
+impl LifetimeStatsStore {
+    /// NVSの名前空間を開きます
+    pub fn open(partition: EspDefaultNvsPartition) -> Result<Self, EspError> {
+        Ok(Self {
+            nvs: EspNvs::new(partition, LIFETIME_NVS_NAMESPACE, true)?,
+        })
+    }
+
+    /// 保存済みの累積を読み込みます（未保存・読めない記録はNone）
+    pub fn load(&self) -> Result<Option<LifetimeRecord>, EspError> {
+        let mut buffer = [0u8; LIFETIME_RECORD_LEN];
+        Ok(self
+            .nvs
+            .get_raw(LIFETIME_NVS_KEY, &mut buffer)?
+            .and_then(LifetimeRecord::from_bytes))
+    }
+
+    /// 現在の累積を保存し、保存した内容を返します
+    pub fn save(&mut self) -> Result<LifetimeRecord, EspError> {
+        let record = LIFETIME_STATS
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .record(since_boot(uptime_s()));
+        self.nvs.set_raw(LIFETIME_NVS_KEY, &record.to_bytes())?;
+        Ok(record)
+    }
+}
+
+/// 再起動の前に、最後の保存以降の分を保存します
+#[cfg(feature = "esp")]
+impl Shutdown for LifetimeStatsStore {
+    fn shutdown(&mut self, _reason: ShutdownReason) -> Result<(), String> {
+        self.save().map(|_| ()).map_err(|e| format!("NVS write failed: {}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_round_trips_and_rejects_other_versions() {
+        let record = LifetimeRecord {
+            boots: 7,
+            uptime_s: 86_400 * 30,
+            frames: 1 << 33,
+            errors: 12,
+            reset_reason: 4,
+        };
+        let bytes = record.to_bytes();
+        assert_eq!(LifetimeRecord::from_bytes(&bytes), Some(record));
+
+        let mut other_version = bytes;
+        other_version[0] = 2;
+        assert_eq!(LifetimeRecord::from_bytes(&other_version), None);
+        assert_eq!(LifetimeRecord::from_bytes(&bytes[..LIFETIME_RECORD_LEN - 1]), None);
+    }
+
+    #[test]
+    fn test_boot_continues_saved_totals() {
+        let saved = LifetimeRecord {
+            boots: 3,
+            uptime_s: 1000,
+            frames: 500,
+            errors: 2,
+            reset_reason: 1,
+        };
+        let stats = LifetimeStats::on_boot(Some(saved), 6);
+        let since_boot = SinceBoot { uptime_s: 60, frames: 10, errors: 1 };
+        assert_eq!(
+            stats.record(since_boot),
+            LifetimeRecord { boots: 4, uptime_s: 1060, frames: 510, errors: 3, reset_reason: 6 }
+        );
+
+        let first_boot = LifetimeStats::on_boot(None, 1);
+        assert_eq!(first_boot.record(SinceBoot::default()).boots, 1);
+    }
+
+    #[test]
+    fn test_response_separates_since_boot_and_lifetime() {
+        let saved = LifetimeRecord { boots: 1, uptime_s: 100, frames: 40, errors: 0, reset_reason: 1 };
+        let stats = LifetimeStats::on_boot(Some(saved), 4);
+        let since_boot = SinceBoot { uptime_s: 5, frames: 2, errors: 1 };
+        assert_eq!(
+            stats.response(since_boot),
+            "CMD_STATS:since_boot uptime_s=5 frames=2 errors=1\n\
+             CMD_STATS:lifetime boots=2 uptime_s=105 frames=42 errors=1 last_reset=PANIC\n"
+        );
+        let fields = stats.stats_fields(since_boot);
+        assert_eq!(fields[0], ("BOOTS", "2".to_string()));
+        assert_eq!(fields[1], ("RESET_REASON", "PANIC".to_string()));
+    }
+}
@@ -5,9 +5,12 @@ macro_rules! debug {
     ($($arg:tt)*) => {};
 }
 
-use std::fmt;
+use std::fmt::{self, Write};
 use std::str::FromStr;
 
+/// `xx:xx:xx:xx:xx:xx` 形式のMACアドレス文字列（スタック上に確保）
+pub type MacString = heapless::String<17>;
+
 /// MACアドレスを表す構造体
 /// IEEE 802規格に従った6バイトのMACアドレスを保持します。
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -72,7 +75,17 @@ impl fmt::Display for MacAddress {
     }
 }
 
-/// MACアドレスをログ出力用にフォーマットする便利関数
+/// MACアドレスをヒープ確保なしでフォーマットします
+///
+/// 受信コールバックやUSB転送など、フレームごとに呼ばれる経路ではこちらを使用します。
+pub fn mac_str(mac: &[u8; 6]) -> MacString {
+    let mut text = MacString::new();
+    // 17文字ちょうどのため容量不足にはならない
+    let _ = write!(text, "{}", MacAddress(*mac));
+    text
+}
+
+/// MACアドレスをログ出力用にフォーマットする便利関数（コマンド応答など頻度の低い経路用）
 pub fn format_mac_address(mac: &[u8; 6]) -> String {
     format!(
         "{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}",
@@ -113,5 +126,7 @@ mod tests {
     fn test_format_mac_address() {
         let mac = [0x12, 0x34, 0x56, 0x78, 0x9a, 0xbc];
         assert_eq!(format_mac_address(&mac), "12:34:56:78:9a:bc");
+        assert_eq!(mac_str(&mac).as_str(), "12:34:56:78:9a:bc");
+        assert_eq!(mac_str(&[0xff; 6]).as_str(), "ff:ff:ff:ff:ff:ff");
     }
 }
@@ -1,37 +1,63 @@
+mod broadcast;
+mod camera_settings;
+mod cbor;
+mod channel_utilization;
+mod clock;
+mod clock_skew;
 mod command;
 mod config;
+mod dead_letter;
+mod debug_flags;
+mod device_groups;
+mod emulation;
+mod downlink_window;
 mod esp_now;
+mod file_transfer;
+mod fleet_summary;
+mod frame_stats;
+mod key_rotation;
+mod lifetime_stats;
 mod mac_address;
+mod memory_trend;
+mod pause;
 mod queue;
+mod release_channel;
+mod resend;
+mod shutdown;
+mod soak;
+mod sys_wrappers;
 mod usb;
 mod streaming;
 mod sleep_command_queue;
+mod sleep_policy;
+mod stats;
+mod cpu_usage;
+mod tasks;
 
 use anyhow::Result;
-use command::{parse_command, Command};
 use esp_idf_svc::eventloop::EspSystemEventLoop;
-use esp_idf_svc::hal::delay::FreeRtos;
 use esp_idf_svc::hal::modem::Modem;
 use esp_idf_svc::hal::peripherals::Peripherals;
 use esp_idf_svc::nvs::EspDefaultNvsPartition;
-use esp_idf_svc::sys::{
-    esp_now_init, esp_now_register_recv_cb, esp_wifi_set_ps, esp_wifi_set_storage,
-    wifi_ps_type_t_WIFI_PS_NONE, wifi_storage_t_WIFI_STORAGE_RAM, vTaskDelay,
-};
+#[cfg(feature = "sntp")]
+use esp_idf_svc::sntp::{EspSntp, SntpConf};
+use esp_idf_svc::sys::{esp_now_send_status_t, esp_now_send_status_t_ESP_NOW_SEND_SUCCESS};
+use esp_now::peer_table;
+use esp_now::radio::RadioSettings;
 use esp_idf_svc::wifi::{AuthMethod, ClientConfiguration, Configuration, EspWifi};
-use esp_now::sender::EspNowSender;
-use log::{debug, error, info, warn};
-use mac_address::format_mac_address;
-use sleep_command_queue::{init_sleep_command_queue, enqueue_sleep_command, process_sleep_command_queue};
+use esp_now::sender::{DownlinkSigner, EspNowSender};
+use key_rotation::KeyRotationRegistry;
+use lifetime_stats::{LifetimeStats, LifetimeStatsStore, SinceBoot};
+use log::{error, info, warn};
+use shutdown::{Shutdown, ShutdownReason, ShutdownStage};
+use sys_wrappers::esp::{self as sys, EspSys};
 use usb::cdc::UsbCdc;
-use usb::UsbInterface;
 
-// PythonからのコマンドやESP-NOWのデータを橋渡しするグローバルコントローラー
-// NOTE: A global `STREAMING_CONTROLLER` was previously defined here to bridge
-// Python commands and ESP-NOW data, but it is not currently used in the
-// actual ESP-NOW/USB data forwarding path. It has been removed to avoid
-// confusion and unnecessary binary size. Reintroduce it here only when the
-// streaming controller is actually wired into the main data-processing loop.
+// ESP-NOWのデータとPCからのコマンドの橋渡し
+// 以前はグローバルの `Mutex<Option<StreamingController>>` を受信コールバックとメインループの両方がロックしていたが、
+// 現在はグローバルのコントローラーを持たない。受信コールバックはフレームをデータキューへ入れるだけで、
+// 処理状態はUSB送信タスクが単独で所有する（`queue::data_queue` の「並行性」を参照）。
+// コマンド処理タスクはデバイスごとのレジストリを介してやり取りし、データキューには触れない。
 
 /// ESP-NOWの受信コールバック関数
 ///
@@ -47,53 +73,58 @@ extern "C" fn esp_now_recv_cb(
     esp_now::receiver::process_esp_now_data(&mut callback, info, data, data_len);
 }
 
+/// ESP-NOWの送信コールバック関数
+///
+/// リンク層の送信結果を記録し、到達確認を待っている送信へ通知します。
+extern "C" fn esp_now_send_cb(mac_addr: *const u8, status: esp_now_send_status_t) {
+    if mac_addr.is_null() {
+        return;
+    }
+    let mut mac_address = [0u8; 6];
+    unsafe { std::ptr::copy_nonoverlapping(mac_addr, mac_address.as_mut_ptr(), mac_address.len()) };
+    esp_now::sender::record_send_result(mac_address, status == esp_now_send_status_t_ESP_NOW_SEND_SUCCESS);
+}
+
 /// ESP-NOWピアを登録する関数
 ///
-/// カメラのMACアドレスをESP-NOWピアとして登録します。
+/// カメラのMACアドレスと、設定の一斉配信用のブロードキャストアドレスをESP-NOWピアとして登録します。
+/// ピアの上限を超えるカメラは、通信したときに最も長く通信していないピアと入れ替えて登録します。
 fn register_esp_now_peers(cameras: &[config::CameraConfig]) -> Result<()> {
     info!("=== ESP-NOWピア登録開始 ===");
     info!("登録するカメラ数: {}", cameras.len());
 
-    unsafe {
-        for (i, camera) in cameras.iter().enumerate() {
-            info!("カメラ {}/{}: {}", i + 1, cameras.len(), camera.name);
-            info!("  MAC: {}", camera.mac_address);
-
-            let mut peer_info = esp_idf_svc::sys::esp_now_peer_info_t::default();
-            peer_info.channel = 0; // 現在のチャンネルを使用
-            peer_info.ifidx = esp_idf_svc::sys::wifi_interface_t_WIFI_IF_STA; // STA interface
-            peer_info.encrypt = false; // 暗号化なし
-            peer_info.peer_addr = camera.mac_address.into_bytes();
-            
-            info!("  チャンネル: {}", peer_info.channel);
-            info!("  インターフェース: {}", peer_info.ifidx);
-            info!("  暗号化: {}", peer_info.encrypt);
-            info!("  ピアアドレス: {:02X?}", peer_info.peer_addr);
-
-            let add_result = esp_idf_svc::sys::esp_now_add_peer(&peer_info);
-            if add_result == 0 {
-                info!("  ✓ ESP-NOWピア登録成功: {}", camera.name);
-            } else {
-                error!("  ✗ ESP-NOWピア登録失敗: {} (エラーコード: {})", camera.name, add_result);
-            }
-        }
+    for (i, camera) in cameras.iter().enumerate() {
+        info!("カメラ {}/{}: {}", i + 1, cameras.len(), camera.name);
+        info!("  MAC: {}", camera.mac_address);
 
-        info!("=== PMK設定 ===");
-        // ESP-NOW添付ファイル(PMK)の拡張設定
-        let pmk: [u8; 16] = [
-            0x50, 0x4d, 0x4b, 0x5f, 0x4b, 0x45, 0x59, 0x5f, 0x42, 0x59, 0x5f, 0x43, 0x55, 0x53,
-            0x54, 0x4f,
-        ];
-        info!("PMKデータ: {:02X?}", pmk);
-        let pmk_result = esp_idf_svc::sys::esp_now_set_pmk(pmk.as_ptr());
-
-        if pmk_result == 0 {
-            info!("✓ PMK設定成功");
-        } else {
-            error!("✗ PMK設定失敗: エラーコード {}", pmk_result);
+        if i >= peer_table::UNICAST_PEER_CAPACITY {
+            info!("  ピアの上限に達したため、通信時に登録します: {}", camera.name);
+            continue;
+        }
+        match peer_table::ensure_peer(&EspSys, &camera.mac_address.into_bytes()) {
+            Ok(()) => info!("  ✓ ESP-NOWピア登録成功: {}", camera.name),
+            Err(e) => error!("  ✗ ESP-NOWピア登録失敗: {} (エラーコード: {})", camera.name, e.code()),
         }
     }
 
+    // 設定の一斉配信（BROADCAST_CONFIG）用のブロードキャストピア
+    match sys::esp_now_add_peer(esp_now::BROADCAST_MAC) {
+        Ok(()) => info!("  ✓ ブロードキャストピア登録成功"),
+        Err(e) => error!("  ✗ ブロードキャストピア登録失敗 (エラーコード: {})", e.code()),
+    }
+
+    info!("=== PMK設定 ===");
+    // ESP-NOW添付ファイル(PMK)の拡張設定
+    let pmk: [u8; 16] = [
+        0x50, 0x4d, 0x4b, 0x5f, 0x4b, 0x45, 0x59, 0x5f, 0x42, 0x59, 0x5f, 0x43, 0x55, 0x53,
+        0x54, 0x4f,
+    ];
+    info!("PMKデータ: {:02X?}", pmk);
+    match sys::esp_now_set_pmk(&pmk) {
+        Ok(()) => info!("✓ PMK設定成功"),
+        Err(e) => error!("✗ PMK設定失敗: エラーコード {}", e.code()),
+    }
+
     Ok(())
 }
 
@@ -104,21 +135,21 @@ fn register_esp_now_peers(cameras: &[config::CameraConfig]) -> Result<()> {
 /// # 引数
 ///
 /// * `modem` - Wi-Fiモデムペリフェラル
+/// * `nvs` - NVSパーティション（Wi-Fi初期化に必要）
 ///
 /// # 戻り値
 ///
 /// * `Result<EspWifi<'static>>` - 初期化されたWi-Fiインスタンス
-fn initialize_wifi(modem: Modem) -> Result<EspWifi<'static>> {
+fn initialize_wifi(modem: Modem, nvs: EspDefaultNvsPartition) -> Result<EspWifi<'static>> {
     info!("Initializing Wi-Fi in STA mode for ESP-NOW...");
 
     let sysloop = EspSystemEventLoop::take()?;
-    let nvs = EspDefaultNvsPartition::take()?; // NVSはWi-Fi初期化に必要
 
     let mut wifi = EspWifi::new(modem, sysloop.clone(), Some(nvs))?;
 
     // Wi-Fi設定をRAMに保存（NVS書き込み回避）
-    unsafe {
-        esp_wifi_set_storage(wifi_storage_t_WIFI_STORAGE_RAM);
+    if let Err(e) = sys::set_wifi_storage_ram() {
+        warn!("Failed to set Wi-Fi storage to RAM: {}", e);
     }
 
     // STAモードで設定（接続は不要）
@@ -132,132 +163,85 @@ fn initialize_wifi(modem: Modem) -> Result<EspWifi<'static>> {
     info!("Wi-Fi driver started in STA mode.");
 
     // Wi-Fiパワーセーブを無効化（ESP-NOWの応答性向上）
-    unsafe {
-        esp_wifi_set_ps(wifi_ps_type_t_WIFI_PS_NONE);
+    if let Err(e) = sys::disable_wifi_power_save() {
+        warn!("Failed to disable Wi-Fi power save: {}", e);
     }
     info!("Wi-Fi Power Save disabled.");
 
     Ok(wifi)
 }
 
+/// SNTPによる時刻同期を開始する関数
+///
+/// 同期はWi-FiのSTAがアップリンク（APへの接続）を持つ場合のみ行われます。
+/// 同期のたびにゲートウェイの時計（`clock::GATEWAY_CLOCK`）を更新します。
+#[cfg(feature = "sntp")]
+fn start_sntp() -> Result<EspSntp<'static>> {
+    let sntp = EspSntp::new_with_callback(&SntpConf::default(), |since_epoch| {
+        let unix_ms = since_epoch.as_millis() as u64;
+        if clock::record_sntp_sync(unix_ms) {
+            info!("Gateway clock synced via SNTP: {} ms since epoch", unix_ms);
+        } else {
+            warn!("Ignoring implausible SNTP time: {} ms since epoch", unix_ms);
+        }
+    })?;
+    info!("SNTP started (syncs only when the Wi-Fi STA has an uplink)");
+    Ok(sntp)
+}
+
+/// ESP-NOWの無線設定を適用する関数
+///
+/// cfg.toml の送信レート・送信パワーを適用し、実際に有効な設定を返します。
+/// AMPDU送信は sdkconfig（CONFIG_ESP_WIFI_AMPDU_TX_ENABLED）で決まるため、ここでは読み取りのみ行います。
+fn apply_radio_settings() -> RadioSettings {
+    let rate = config::load_espnow_rate();
+    if let Some(tx_power_dbm) = config::wifi_tx_power_dbm() {
+        if let Err(e) = sys::set_max_tx_power_dbm(tx_power_dbm) {
+            warn!("Failed to set Wi-Fi TX power to {}dBm: {}", tx_power_dbm, e);
+        }
+    }
+    if let Some(rate) = rate {
+        if let Err(e) = sys::set_espnow_rate(rate.phy_rate()) {
+            warn!("Failed to set ESP-NOW rate {}: {}", rate.name(), e);
+        }
+    }
+
+    #[allow(unexpected_cfgs)]
+    let ampdu_tx = cfg!(esp_idf_esp_wifi_ampdu_tx_enabled);
+    RadioSettings {
+        rate,
+        tx_power_dbm: sys::max_tx_power_dbm().unwrap_or(0),
+        ampdu_tx,
+    }
+}
+
 /// ESP-NOWを初期化する関数
 ///
-/// ESP-NOWを初期化し、受信コールバックを登録します。
+/// ESP-NOWを初期化し、受信・送信コールバックを登録します。
 fn initialize_esp_now() -> Result<()> {
     info!("Initializing ESP-NOW...");
 
-    unsafe {
-        esp_now_init();
-        esp_now_register_recv_cb(Some(esp_now_recv_cb));
+    sys::esp_now_start(Some(esp_now_recv_cb), Some(esp_now_send_cb))?;
 
-        // ESP-NOWの最大ピア数を確認
-        let mut esp_now_peer_num = esp_idf_svc::sys::esp_now_peer_num_t {
-            total_num: 0,
-            encrypt_num: 0,
-        };
-
-        if esp_idf_svc::sys::esp_now_get_peer_num(&mut esp_now_peer_num) == 0 {
-            info!(
-                "ESP-NOW: Current peer count: {}",
-                esp_now_peer_num.total_num
-            );
+    // ESP-NOWの最大ピア数を確認
+    match sys::esp_now_peer_count() {
+        Ok(total_num) => {
+            info!("ESP-NOW: Current peer count: {}", total_num);
             info!("ESP-NOW: Maximum supported peers: 20"); // ESP-IDF 4.xでは20ピアをサポート
-        } else {
-            error!("ESP-NOW: Failed to get peer count");
         }
+        Err(_) => error!("ESP-NOW: Failed to get peer count"),
     }
 
     info!("ESP-NOW Initialized and receive callback registered.");
     Ok(())
 }
 
-/// データ処理メインループ
-///
-/// キューからデータを取得し、USB CDC経由でPCに転送します。
-/// スリープコマンドもUSB経由で受信し、ESP-NOWで送信します。
-#[allow(unused_assignments)]
-fn process_data_loop(
-    usb_cdc: &mut UsbCdc, 
-    esp_now_sender: &mut EspNowSender,
-) -> Result<()> {
-    info!("Entering data processing loop...");
-    
-    loop {
-        let mut processed_any_data = false;
-        
-        // 1. キューからデータを取得してUSB転送
-        match queue::data_queue::dequeue() {
-            Ok(received_data) => {
-                let mac_str = format_mac_address(&received_data.mac);
-                debug!("Processing data from {}: {} bytes", mac_str, received_data.data.len());
-                
-                match usb_cdc.send_frame(&received_data.data, &mac_str) {
-                    Ok(bytes_sent) => {
-                        debug!("USB transfer successful: {} bytes", bytes_sent);
-                        processed_any_data = true;
-                    }
-                    Err(usb_err) => {
-                        error!("USB transfer failed for {}: {}", mac_str, usb_err);
-                    }
-                }
-                processed_any_data = true;
-            }
-            Err(queue::QueueError::Empty) => {
-                // キューが空の場合は正常（処理なし）
-            }
-            Err(e) => {
-                error!("Error dequeuing data: {:?}", e);
-            }
-        }
-        
-        // 2. USBコマンドの処理（スリープコマンドなど）
-        match usb_cdc.read_command(10) { // 10ms timeout
-            Ok(Some(command_str)) => {
-                info!("=== Received USB command: '{}' ===", command_str);
-                
-                match parse_command(command_str.as_str()) {
-                    Ok(Command::SendEspNow { mac_address, sleep_seconds }) => {
-                        info!("Processing ESP-NOW send command: {} -> {}s", mac_address, sleep_seconds);
-                        
-                        // スリープコマンドをキューに追加（直接送信せず）
-                        match enqueue_sleep_command(mac_address.clone(), sleep_seconds) {
-                            Ok(()) => {
-                                info!("✓ Sleep command queued for {}: {}s", mac_address, sleep_seconds);
-                            }
-                            Err(e) => {
-                                error!("✗ Failed to queue sleep command for {}: {}", mac_address, e);
-                            }
-                        }
-                    }
-                    Ok(Command::Unknown(cmd)) => {
-                        warn!("Unknown command received: '{}'", cmd);
-                    }
-                    Err(e) => {
-                        error!("Failed to parse command '{}': {:?}", command_str, e);
-                    }
-                }
-                processed_any_data = true;
-            }
-            Ok(None) => {
-                // コマンドなし
-            }
-            Err(e) => {
-                error!("Error reading USB command: {:?}", e);
-                FreeRtos::delay_ms(50);
-            }
-        }
-        
-        // 3. スリープコマンドキューの処理
-        process_sleep_command_queue(&esp_now_sender);
-        
-        // 4. 新しいデバイス（現在は従来のキューデータを処理）
-        
-        // ここで将来的に新しいデータソースを追加可能
-        
-        // 5. データ処理がない場合は短い遅延
-        if !processed_any_data {
-            FreeRtos::delay_ms(5); // 遅延を短縮してレスポンス向上
-        }
+/// ESP-NOWの停止（コールバックの登録を解除して受信を止め、ドライバーを解放する）
+struct EspNowDriver;
+
+impl Shutdown for EspNowDriver {
+    fn shutdown(&mut self, _reason: ShutdownReason) -> Result<(), String> {
+        sys::esp_now_stop().map_err(|e| format!("esp_now_deinit failed: {}", e))
     }
 }
 
@@ -269,13 +253,38 @@ fn main() -> Result<()> {
 
     info!("Starting ESP-NOW USB CDC Receiver with Streaming Architecture...");
 
+    // パニック時も受信済みのデータを書き出してから再起動する
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |panic_info| {
+        tasks::run_shutdown(ShutdownReason::Panic);
+        default_hook(panic_info);
+    }));
+
     // キューの初期化（互換性のため継続）
     queue::data_queue::initialize_data_queue();
+    // 通常のキューが満杯の間のオーバーフロー段（PSRAMを搭載していればPSRAMへ退避）
+    queue::data_queue::attach_overflow(streaming::buffer::StreamingBuffer::with_detected_psram());
     info!("✓ Queue initialized");
 
-    // スリープコマンドキューの初期化
-    init_sleep_command_queue();
-    info!("✓ Sleep command queue initialized");
+    // 部分転送の救済（欠落した転送も中断せずギャップマップ付きで送出）
+    esp_now::cancellation::set_partial_salvage(config::partial_salvage_enabled());
+    info!("Partial-frame salvage: {}", if config::partial_salvage_enabled() { "enabled" } else { "disabled" });
+
+    // 受信フレームへの故障注入（`chaos` フィーチャーのみ、`CHAOS` コマンドでも変更できる）
+    #[cfg(feature = "chaos")]
+    if let Some(params) = config::chaos_params() {
+        warn!("Fault injection enabled: {}", params.encode());
+        if let Ok(mut chaos) = esp_now::receiver::CHAOS.lock() {
+            chaos.configure(Some(params));
+        }
+    }
+
+    // 同時転送数の上限（超えたデバイスには延期要求を返す）
+    let (max_in_flight, retry_after_ms) = config::admission_limits();
+    if let Ok(mut admission) = esp_now::admission::ADMISSION.lock() {
+        admission.configure(max_in_flight, retry_after_ms);
+    }
+    info!("Max in-flight transfers: {} (retry after {}ms)", max_in_flight, retry_after_ms);
 
     // 設定からカメラ情報を読み込み
     info!("Loading camera configurations...");
@@ -289,35 +298,68 @@ fn main() -> Result<()> {
 
     // Wi-Fi初期化（モデムを渡す）
     info!("Initializing Wi-Fi...");
-    let _wifi = initialize_wifi(peripherals.modem)?;
+    let nvs = EspDefaultNvsPartition::take()?;
+    let _wifi = initialize_wifi(peripherals.modem, nvs.clone())?;
     info!("✓ Wi-Fi initialized");
 
+    // チャンネルの混雑度の計測（`channel-monitor` フィーチャーのみ）
+    #[cfg(feature = "channel-monitor")]
+    match esp_now::channel_monitor::start() {
+        Ok(()) => info!("✓ Channel utilization monitor started"),
+        Err(e) => warn!("Failed to start channel utilization monitor: {:?}", e),
+    }
+
+    // SNTPによる時刻同期（アップリンクがなければ同期されず、ティックの時刻を使う）
+    #[cfg(feature = "sntp")]
+    let _sntp = match start_sntp() {
+        Ok(sntp) => Some(sntp),
+        Err(e) => {
+            warn!("Failed to start SNTP; using tick-based time: {:?}", e);
+            None
+        }
+    };
+
+    // 再起動をまたぐ累積統計（保存済みの累積に今回の起動を加える）
+    let lifetime_store = match LifetimeStatsStore::open(nvs.clone()) {
+        Ok(store) => {
+            let saved = store.load().unwrap_or_else(|e| {
+                warn!("Failed to read lifetime stats; starting from zero: {:?}", e);
+                None
+            });
+            let stats = LifetimeStats::on_boot(saved, sys::reset_reason());
+            let record = stats.record(SinceBoot::default());
+            info!(
+                "Gateway boot #{} (reset reason: {}, lifetime uptime {}s)",
+                record.boots,
+                lifetime_stats::reset_reason_name(record.reset_reason),
+                record.uptime_s
+            );
+            if let Ok(mut lifetime) = lifetime_stats::LIFETIME_STATS.lock() {
+                *lifetime = stats;
+            }
+            Some(store)
+        }
+        Err(e) => {
+            error!("Failed to open lifetime stats store; statistics reset on reboot: {:?}", e);
+            None
+        }
+    };
+
     // デバイス情報の表示
     info!("=== USBゲートウェイ デバイス情報 ===");
     
     // 実際のMACアドレスを取得・表示
-    let wifi_mac = unsafe {
-        let mut mac = [0u8; 6];
-        let result = esp_idf_sys::esp_wifi_get_mac(esp_idf_sys::wifi_interface_t_WIFI_IF_STA, mac.as_mut_ptr());
-        if result == 0 {
-            format!("{:02X}:{:02X}:{:02X}:{:02X}:{:02X}:{:02X}", 
-                    mac[0], mac[1], mac[2], mac[3], mac[4], mac[5])
-        } else {
-            "UNKNOWN".to_string()
-        }
+    let wifi_mac = match sys::sta_mac() {
+        Ok(mac) => format!("{:02X}:{:02X}:{:02X}:{:02X}:{:02X}:{:02X}",
+                           mac[0], mac[1], mac[2], mac[3], mac[4], mac[5]),
+        Err(_) => "UNKNOWN".to_string(),
     };
     info!("実際のWiFi STA MAC: {}", wifi_mac);
     
     // WiFiチャンネル情報を取得・表示
-    let wifi_channel = unsafe {
-        let mut primary = 0u8;
-        let mut second = 0;
-        let result = esp_idf_sys::esp_wifi_get_channel(&mut primary, &mut second);
-        if result == 0 {
-            format!("Primary: {}, Secondary: {}", primary, second)
-        } else {
-            "UNKNOWN".to_string()
-        }
+    let wifi_channel = match sys::wifi_channel() {
+        Ok(primary) => format!("Primary: {}", primary),
+        Err(_) => "UNKNOWN".to_string(),
     };
     info!("WiFiチャンネル: {}", wifi_channel);
     
@@ -328,25 +370,71 @@ fn main() -> Result<()> {
     
     // ESP-NOW初期化
     initialize_esp_now()?;
+    if let Ok(mut sequence) = shutdown::SHUTDOWN.lock() {
+        sequence.register("esp_now", ShutdownStage::Ingress, Box::new(EspNowDriver));
+    }
+
+    // 無線設定の適用（疎通確認のPongでデバイスへ通知）
+    let radio_settings = apply_radio_settings();
+    info!("ESP-NOW radio: {}", radio_settings.summary());
+    esp_now::receiver::set_radio_settings(radio_settings);
+    esp_now::receiver::set_pending_commands(tasks::queued_downlink_commands);
+    let chunk_size = config::chunk_size_advert();
+    info!("ESP-NOW chunk size: preferred {}B, max {}B", chunk_size.preferred, chunk_size.max);
+    esp_now::receiver::set_chunk_size_advert(chunk_size);
 
     // カメラをピアとして登録
     register_esp_now_peers(&cameras)?;
+    if config::reject_unknown_devices() {
+        info!("Rejecting frames from devices not listed in cameras");
+        esp_now::receiver::set_known_devices(cameras.iter().map(|camera| camera.mac_address.into_bytes()).collect());
+    }
+
+    // 時間分割の受信チャンネル切り替え（起動時のチャンネルをホームチャンネルとする）
+    if let Ok(home_channel) = sys::wifi_channel() {
+        if let Some(hopper) = config::channel_hopper(home_channel, std::time::Instant::now()) {
+            info!("Channel hopping enabled: {}", hopper.summary());
+            if let Ok(mut hop) = esp_now::channel_hop::CHANNEL_HOP.lock() {
+                *hop = Some(hopper);
+            }
+        }
+    }
 
     // ESP-NOW送信機能を初期化
     info!("Initializing ESP-NOW sender...");
     let mut esp_now_sender = EspNowSender::new();
+    let mut key_rotations = KeyRotationRegistry::new();
+    if let Some(key) = config::load_downlink_key() {
+        match DownlinkSigner::open(nvs, key) {
+            Ok(signer) => {
+                // 鍵を更新したデバイスには更新後の鍵で署名する
+                let macs: Vec<[u8; 6]> = cameras.iter().map(|camera| camera.mac_address.into_bytes()).collect();
+                signer.load_device_keys(&mut key_rotations, &macs);
+                esp_now_sender =
+                    esp_now_sender.with_downlink_signer(signer.with_device_keys(tasks::device_signing_key));
+            }
+            Err(e) => error!("Failed to open downlink counter store; sleep commands are sent unsigned: {:?}", e),
+        }
+    }
     info!("✓ ESP-NOW sender initialized.");
 
     // USB CDC初期化（Wi-Fi初期化で取得したペリフェラルを使用）
     info!("Initializing USB CDC...");
-    let mut usb_cdc = UsbCdc::new(
+    let usb_cdc = UsbCdc::new(
         peripherals.usb_serial,
         peripherals.pins.gpio18, // XIAO ESP32C3のUSB D-ピン
         peripherals.pins.gpio19, // XIAO ESP32C3のUSB D+ピン
     )?;
     info!("✓ USB CDC initialized.");
 
-    // メインデータ処理ループ
-    info!("Starting data processing loop...");
-    process_data_loop(&mut usb_cdc, &mut esp_now_sender)
+    // タスクを起動（メンテナンスはこのタスクで実行し、戻らない）
+    info!("Starting gateway tasks...");
+    tasks::run(
+        usb_cdc,
+        esp_now_sender,
+        config::sleep_policy_registry(&cameras),
+        config::fleet_summary(&cameras),
+        key_rotations,
+        lifetime_store,
+    )
 }
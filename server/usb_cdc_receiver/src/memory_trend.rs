@@ -0,0 +1,303 @@
+//! ゲートウェイの空きヒープの推移と枯渇の予測
+//!
+//! 長時間の稼働で少しずつ進むメモリリークを、停止する前に見つけるための集計です。
+//! 空きヒープと最大の空きブロックを定期的に記録し、直近の窓に最小二乗法で直線を当てはめます。
+//! 傾きのままでどちらかが `EXHAUSTION_THRESHOLD_BYTES` を下回るまでの時間が予測の範囲内になったら、
+//! 統計フレームの `MEM_TREND` 項目で1回だけ警告します（予測が範囲外に戻るまで再警告しない）。
+//! 記録は `MEMORY_TREND` コマンドで `CMD_MEMORY:` 応答として参照できます。
+
+use std::collections::VecDeque;
+
+/// メモリ推移応答の接頭辞
+pub const MEMORY_RESPONSE_PREFIX: &str = "CMD_MEMORY:";
+
+/// 保持するサンプル数（1分ごとの記録で2時間分）
+pub const MEMORY_TREND_WINDOW: usize = 120;
+
+/// 傾きを求めるのに必要なサンプル数
+pub const MIN_TREND_SAMPLES: usize = 10;
+
+/// 枯渇とみなす空き容量（ESP-NOWの受信やUSB送出のバッファを確保できなくなる目安）
+pub const EXHAUSTION_THRESHOLD_BYTES: u32 = 8 * 1024;
+
+/// 1回の記録
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemorySample {
+    /// 起動からの経過時間（秒）
+    pub uptime_s: u64,
+    /// 空きヒープ（バイト）
+    pub free_heap: u32,
+    /// 最大の空きブロック（バイト）
+    pub largest_block: u32,
+}
+
+/// 枯渇を予測した項目
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryLimit {
+    /// 空きヒープ
+    FreeHeap,
+    /// 最大の空きブロック（断片化）
+    LargestBlock,
+}
+
+impl MemoryLimit {
+    /// 統計フレーム・応答での表記
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::FreeHeap => "FREE",
+            Self::LargestBlock => "LARGEST",
+        }
+    }
+}
+
+/// 窓内の傾きと枯渇までの予測
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MemoryForecast {
+    /// 空きヒープの傾き（バイト/時）
+    pub free_slope_per_hour: f64,
+    /// 最大の空きブロックの傾き（バイト/時）
+    pub largest_slope_per_hour: f64,
+    /// 枯渇までの予測（秒、減少していなければNone）
+    pub exhaustion_s: Option<u64>,
+    /// 先に枯渇する項目
+    pub limit: Option<MemoryLimit>,
+    /// 最新の記録
+    pub latest: MemorySample,
+}
+
+impl MemoryForecast {
+    /// 警告の統計フレームの項目
+    pub fn stats_fields(&self, horizon_s: u64) -> Vec<(&'static str, String)> {
+        vec![
+            ("MEM_TREND", optional(self.exhaustion_s)),
+            ("MEM_HORIZON_S", horizon_s.to_string()),
+            ("MEM_LIMIT", self.limit.map_or("-", MemoryLimit::as_str).to_string()),
+            ("HEAP_FREE", self.latest.free_heap.to_string()),
+            ("HEAP_LARGEST", self.latest.largest_block.to_string()),
+            ("HEAP_SLOPE", format!("{:.0}", self.free_slope_per_hour)),
+            ("LARGEST_SLOPE", format!("{:.0}", self.largest_slope_per_hour)),
+        ]
+    }
+}
+
+fn optional(value: Option<u64>) -> String {
+    value.map_or_else(|| "-".to_string(), |value| value.to_string())
+}
+
+/// 最小二乗法で求めた直線の傾き（yの単位/秒）
+fn least_squares_slope(points: impl Iterator<Item = (f64, f64)> + Clone) -> Option<f64> {
+    let count = points.clone().count() as f64;
+    let (sum_x, sum_y) = points.clone().fold((0.0, 0.0), |(sx, sy), (x, y)| (sx + x, sy + y));
+    let (mean_x, mean_y) = (sum_x / count, sum_y / count);
+    let (covariance, variance) = points.fold((0.0, 0.0), |(cov, var), (x, y)| {
+        (cov + (x - mean_x) * (y - mean_y), var + (x - mean_x) * (x - mean_x))
+    });
+    (variance > 0.0).then(|| covariance / variance)
+}
+
+/// 現在値が傾きのままで閾値を下回るまでの時間（秒、減少していなければNone）
+fn seconds_until_exhausted(current: u32, slope_per_s: f64) -> Option<u64> {
+    if slope_per_s >= 0.0 {
+        return None;
+    }
+    let remaining = current.saturating_sub(EXHAUSTION_THRESHOLD_BYTES) as f64;
+    Some((remaining / -slope_per_s) as u64)
+}
+
+/// 空きヒープの推移
+#[derive(Debug, Default)]
+pub struct MemoryTrend {
+    samples: VecDeque<MemorySample>,
+    warned: bool,
+}
+
+impl MemoryTrend {
+    /// 記録なしで作成します
+    pub const fn new() -> Self {
+        Self {
+            samples: VecDeque::new(),
+            warned: false,
+        }
+    }
+
+    /// 記録を追加し、今回警告すべき予測を返します
+    ///
+    /// 枯渇までの予測が `horizon_s` 以内になった最初の記録でのみ返します（`horizon_s` がNoneなら警告しない）。
+    pub fn record(&mut self, sample: MemorySample, horizon_s: Option<u64>) -> Option<MemoryForecast> {
+        if self.samples.len() == MEMORY_TREND_WINDOW {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(sample);
+
+        let horizon_s = horizon_s?;
+        let forecast = self.forecast()?;
+        let within_horizon = forecast.exhaustion_s.is_some_and(|seconds| seconds <= horizon_s);
+        if !within_horizon {
+            self.warned = false;
+            return None;
+        }
+        if self.warned {
+            return None;
+        }
+        self.warned = true;
+        Some(forecast)
+    }
+
+    /// 窓内の記録から傾きと枯渇までの時間を予測します（記録が足りなければNone）
+    pub fn forecast(&self) -> Option<MemoryForecast> {
+        if self.samples.len() < MIN_TREND_SAMPLES {
+            return None;
+        }
+        let latest = *self.samples.back()?;
+        let series = |value: fn(&MemorySample) -> u32| {
+            self.samples.iter().map(move |sample| (sample.uptime_s as f64, value(sample) as f64))
+        };
+        let free_slope = least_squares_slope(series(|sample| sample.free_heap))?;
+        let largest_slope = least_squares_slope(series(|sample| sample.largest_block))?;
+
+        let candidates = [
+            (MemoryLimit::FreeHeap, seconds_until_exhausted(latest.free_heap, free_slope)),
+            (MemoryLimit::LargestBlock, seconds_until_exhausted(latest.largest_block, largest_slope)),
+        ];
+        let earliest = candidates
+            .iter()
+            .filter_map(|&(limit, seconds)| seconds.map(|seconds| (limit, seconds)))
+            .min_by_key(|&(_, seconds)| seconds);
+
+        Some(MemoryForecast {
+            free_slope_per_hour: free_slope * 3600.0,
+            largest_slope_per_hour: largest_slope * 3600.0,
+            exhaustion_s: earliest.map(|(_, seconds)| seconds),
+            limit: earliest.map(|(limit, _)| limit),
+            latest,
+        })
+    }
+
+    /// 記録数
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    /// 記録がないか
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// `MEMORY_TREND` への応答（1行目に予測、続けて古い順に1記録1行）
+    pub fn response(&self) -> String {
+        let mut response = match self.forecast() {
+            Some(forecast) => format!(
+                "{}samples={},free_slope_bph={:.0},largest_slope_bph={:.0},exhaustion_s={},limit={}\n",
+                MEMORY_RESPONSE_PREFIX,
+                self.samples.len(),
+                forecast.free_slope_per_hour,
+                forecast.largest_slope_per_hour,
+                optional(forecast.exhaustion_s),
+                forecast.limit.map_or("-", MemoryLimit::as_str)
+            ),
+            None => format!(
+                "{}samples={} (at least {} needed for a trend)\n",
+                MEMORY_RESPONSE_PREFIX,
+                self.samples.len(),
+                MIN_TREND_SAMPLES
+            ),
+        };
+        for sample in &self.samples {
+            response.push_str(&format!(
+                "{}uptime_s={},free={},largest={}\n",
+                MEMORY_RESPONSE_PREFIX, sample.uptime_s, sample.free_heap, sample.largest_block
+            ));
+        }
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 1分ごとに空きヒープが `leak` バイトずつ減る記録
+    fn leaking(minute: u64, leak: u32) -> MemorySample {
+        MemorySample {
+            uptime_s: minute * 60,
+            free_heap: 200_000 - minute as u32 * leak,
+            largest_block: 100_000,
+        }
+    }
+
+    #[test]
+    fn test_forecast_fits_linear_leak() {
+        let mut trend = MemoryTrend::new();
+        for minute in 0..MIN_TREND_SAMPLES as u64 {
+            assert_eq!(trend.forecast(), None);
+            trend.record(leaking(minute, 60), None);
+        }
+        let forecast = trend.forecast().unwrap();
+        assert!((forecast.free_slope_per_hour + 3600.0).abs() < 1e-6);
+        assert_eq!(forecast.largest_slope_per_hour, 0.0);
+        assert_eq!(forecast.limit, Some(MemoryLimit::FreeHeap));
+        // 最新は 200000 - 9*60 = 199460 バイト、閾値まで 191268 バイトを毎秒1バイト
+        assert_eq!(forecast.exhaustion_s, Some(191_268));
+    }
+
+    #[test]
+    fn test_warns_once_within_horizon() {
+        let mut trend = MemoryTrend::new();
+        // 毎分1000バイトの減少は約3.2時間で枯渇する
+        let horizon = Some(4 * 3600);
+        let warnings: Vec<u64> = (0..20)
+            .filter_map(|minute| trend.record(leaking(minute, 1000), horizon).map(|_| minute))
+            .collect();
+        assert_eq!(warnings, [MIN_TREND_SAMPLES as u64 - 1]);
+
+        // 回復して予測が範囲外に戻れば再び警告できる
+        for minute in 20..20 + MEMORY_TREND_WINDOW as u64 {
+            let sample = MemorySample { free_heap: 200_000, ..leaking(minute, 0) };
+            assert_eq!(trend.record(sample, horizon), None);
+        }
+        assert_eq!(trend.len(), MEMORY_TREND_WINDOW);
+        let start = 20 + MEMORY_TREND_WINDOW as u64;
+        let rewarned = (start..start + MEMORY_TREND_WINDOW as u64)
+            .any(|minute| {
+                let sample = MemorySample { uptime_s: minute * 60, ..leaking(minute - start, 1000) };
+                trend.record(sample, horizon).is_some()
+            });
+        assert!(rewarned);
+    }
+
+    #[test]
+    fn test_fragmentation_limits_before_free_heap() {
+        let mut trend = MemoryTrend::new();
+        for minute in 0..30u64 {
+            let sample = MemorySample {
+                uptime_s: minute * 60,
+                free_heap: 150_000,
+                largest_block: 40_000 - minute as u32 * 500,
+            };
+            trend.record(sample, None);
+        }
+        let forecast = trend.forecast().unwrap();
+        assert_eq!(forecast.limit, Some(MemoryLimit::LargestBlock));
+        let fields = forecast.stats_fields(86_400);
+        assert_eq!(fields[2], ("MEM_LIMIT", "LARGEST".to_string()));
+        assert_eq!(fields[5], ("HEAP_SLOPE", "0".to_string()));
+        assert_eq!(fields[6], ("LARGEST_SLOPE", "-30000".to_string()));
+    }
+
+    #[test]
+    fn test_response_lists_history() {
+        let mut trend = MemoryTrend::new();
+        assert_eq!(trend.response(), "CMD_MEMORY:samples=0 (at least 10 needed for a trend)\n");
+        for minute in 0..MIN_TREND_SAMPLES as u64 {
+            trend.record(leaking(minute, 0), None);
+        }
+        let response = trend.response();
+        let lines: Vec<&str> = response.lines().collect();
+        assert_eq!(lines.len(), MIN_TREND_SAMPLES + 1);
+        assert_eq!(
+            lines[0],
+            "CMD_MEMORY:samples=10,free_slope_bph=0,largest_slope_bph=0,exhaustion_s=-,limit=-"
+        );
+        assert_eq!(lines[1], "CMD_MEMORY:uptime_s=0,free=200000,largest=100000");
+    }
+}
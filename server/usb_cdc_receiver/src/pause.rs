@@ -0,0 +1,134 @@
+//! デバイスの一時停止（保守作業中に特定のカメラを送信させない）
+//!
+//! `PAUSE` で登録したデバイスが次に転送を終えたとき、ゲートウェイが停止の残り時間だけの
+//! スリープコマンドを返します。デバイス側の設定（通常のスリープ間隔）は変更しません。
+//! 停止期間を過ぎた登録は参照時に取り除きます。
+
+use std::time::{Duration, Instant};
+
+use crate::command::{MAX_SLEEP_SECONDS, MIN_SLEEP_SECONDS};
+use crate::mac_address::format_mac_address;
+
+/// 一時停止コマンド応答の接頭辞
+pub const PAUSE_RESPONSE_PREFIX: &str = "CMD_PAUSE:";
+
+/// 一時停止中のデバイス
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct PausedDevice {
+    mac: [u8; 6],
+    until: Instant,
+}
+
+/// 一時停止中のデバイスの登録
+#[derive(Debug, Default)]
+pub struct PauseRegistry {
+    paused: Vec<PausedDevice>,
+}
+
+impl PauseRegistry {
+    /// 空の登録を作成します
+    pub const fn new() -> Self {
+        Self { paused: Vec::new() }
+    }
+
+    /// デバイスを一時停止します（停止中なら期限を置き換え）
+    pub fn pause(&mut self, mac: [u8; 6], duration: Duration, now: Instant) {
+        let until = now + duration;
+        match self.paused.iter_mut().find(|device| device.mac == mac) {
+            Some(device) => device.until = until,
+            None => self.paused.push(PausedDevice { mac, until }),
+        }
+    }
+
+    /// 一時停止を解除します
+    ///
+    /// # 戻り値
+    /// * 停止中だった場合はtrue
+    pub fn resume(&mut self, mac: &[u8; 6], now: Instant) -> bool {
+        self.expire(now);
+        let before = self.paused.len();
+        self.paused.retain(|device| &device.mac != mac);
+        self.paused.len() != before
+    }
+
+    /// 停止の残り時間（停止中でなければNone）
+    pub fn remaining(&mut self, mac: &[u8; 6], now: Instant) -> Option<Duration> {
+        self.expire(now);
+        self.paused
+            .iter()
+            .find(|device| &device.mac == mac)
+            .map(|device| device.until.saturating_duration_since(now))
+    }
+
+    /// 停止中のデバイスに返すスリープ時間（秒）
+    ///
+    /// 残り時間を切り上げ、スリープコマンドの上限を超える分は次回の転送時に改めて返します。
+    pub fn sleep_seconds_for(&mut self, mac: &[u8; 6], now: Instant) -> Option<u32> {
+        let remaining = self.remaining(mac, now)?;
+        let seconds = remaining.as_millis().div_ceil(1000);
+        Some(seconds.clamp(MIN_SLEEP_SECONDS as u128, MAX_SLEEP_SECONDS as u128) as u32)
+    }
+
+    /// 一時停止コマンドへの応答行
+    pub fn response(&mut self, mac: &[u8; 6], now: Instant) -> String {
+        let mac_str = format_mac_address(mac);
+        match self.remaining(mac, now) {
+            Some(remaining) => format!(
+                "{}{} paused for {}s\n",
+                PAUSE_RESPONSE_PREFIX,
+                mac_str,
+                remaining.as_secs()
+            ),
+            None => format!("{}{} active\n", PAUSE_RESPONSE_PREFIX, mac_str),
+        }
+    }
+
+    /// 停止中のデバイス数
+    pub fn len(&self) -> usize {
+        self.paused.len()
+    }
+
+    /// 停止中のデバイスがないか
+    pub fn is_empty(&self) -> bool {
+        self.paused.is_empty()
+    }
+
+    fn expire(&mut self, now: Instant) {
+        self.paused.retain(|device| device.until > now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAC: [u8; 6] = [0x34, 0xab, 0x95, 0xfb, 0x3f, 0xc4];
+
+    #[test]
+    fn test_paused_device_gets_remaining_time_until_expiry() {
+        let mut registry = PauseRegistry::new();
+        let start = Instant::now();
+        registry.pause(MAC, Duration::from_secs(30 * 60), start);
+
+        assert_eq!(registry.sleep_seconds_for(&MAC, start + Duration::from_millis(500)), Some(1800));
+        assert_eq!(registry.sleep_seconds_for(&MAC, start + Duration::from_secs(600)), Some(1200));
+        assert_eq!(registry.response(&MAC, start), "CMD_PAUSE:34:ab:95:fb:3f:c4 paused for 1800s\n");
+
+        // 期限を過ぎると自動的に解除される
+        assert_eq!(registry.sleep_seconds_for(&MAC, start + Duration::from_secs(1800)), None);
+        assert!(registry.is_empty());
+    }
+
+    #[test]
+    fn test_long_pause_is_split_and_resume_clears() {
+        let mut registry = PauseRegistry::new();
+        let start = Instant::now();
+        registry.pause(MAC, Duration::from_secs(2 * 86400), start);
+
+        assert_eq!(registry.sleep_seconds_for(&MAC, start), Some(MAX_SLEEP_SECONDS));
+        assert!(registry.resume(&MAC, start));
+        assert!(!registry.resume(&MAC, start));
+        assert_eq!(registry.sleep_seconds_for(&MAC, start), None);
+        assert_eq!(registry.response(&MAC, start), "CMD_PAUSE:34:ab:95:fb:3f:c4 active\n");
+    }
+}
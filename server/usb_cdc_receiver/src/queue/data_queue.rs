@@ -1,12 +1,63 @@
+//! ESP-NOW受信コールバックからUSB送信タスクへのデータキュー
+//!
+//! ## 並行性
+//!
+//! - 生産者はESP-NOW受信コールバック（Wi-Fiタスク）です。生産者側のロックはコールバック以外が取らないため、
+//!   キューへの投入で待たされることはありません。
+//!   例外は開発用の `EMULATE`（`crate::emulation`）で、動作中はメンテナンスタスクも仮想デバイスの
+//!   フレームを入れます。このときだけ、コールバックがフレーム1つの投入の間待たされることがあります。
+//! - ただし、コールバックの処理（`esp_now::receiver::process_frame`）はキューへの投入だけではありません。
+//!   Ping・HASHフレームへの応答と拒否メッセージをその場でESP-NOWで送信し、そのためにピア表
+//!   （`peer_table::PEERS`、`ensure_peer` / `touch_peer`）・結果を待たない送信の登録（`sender::DELIVERIES`）・
+//!   `DOWNLINK_WINDOW`・`REJECT_LIMITER` のロックを取ります。ほかに `ADMISSION`・`LEGACY_SHIM`・
+//!   中断の記録（`cancellation`）・フレーム統計、`chaos` フィーチャーでは `CHAOS` のロックも取るため、
+//!   これらを保持する他のタスクがあればコールバックも待たされます。
+//! - 消費者はUSB送信タスクだけで、転送完了の集約などの処理状態はこのタスクが単独で所有します。
+//! - コマンド処理タスク・メンテナンスタスクはキューに触れません。使用量は `QUEUE_LEN`
+//!   （アトミック）から読み、消費者側のロックを取りません（Pingへの応答で使用量を返すコールバックも同様）。
+//! - このモジュールでコールバックが取るロックは、生産者側と到着通知用の2つです。到着通知用のロックを
+//!   保持するのは消費者が待機に入る直前の短い間だけです（通知の取り逃しを防ぐために必要）。
+//!   通常のキューが満杯の間はオーバーフロー段のロックも取り、これは消費者と共有します。
+//! - 通知は取り出し済みのフレームに対するものが残ることがあり、条件変数は通知なしに起床することもあるため、
+//!   `dequeue_timeout` は取り出せるか期限が来るまで待機を繰り返します。
+//!
+//! ## 緊急フレームの順序
+//!
+//! 霜の検知などの緊急フレーム（`ReceivedData::urgent`）は、画像転送のチャンクが数百個溜まっていても
+//! 待たされないよう、通常のキューとは別の緊急キューへ入れます。
+//!
+//! - `dequeue` は緊急キューを先に取り出します。緊急フレームはキューに溜まったフレーム
+//!   （同じデバイスの転送途中のチャンクを含む）を追い越します。
+//! - 緊急フレーム同士、通常のフレーム同士はそれぞれ受信順のままです。
+//! - 緊急キューが満杯の場合は通常のキューの末尾へ入れます（追い越さないが失わない）。
+//! - 緊急フレームは転送の集約・欠落による中断の破棄の対象外です（`esp_now::routing::FrameRoute::Urgent`）。
+//! - 追い越すのはキュー内のフレームだけです。USB送信タスクが取り出し済みのフレームより先には送出しません。
+//!
+//! ## オーバーフロー段
+//!
+//! 起動時に `attach_overflow` でストリーミングバッファ（`StreamingBuffer::with_detected_psram`）を渡すと、
+//! 通常のキューが満杯の間に届いたフレームをそこへ溜めます（PSRAM搭載ボードではPSRAMへ退避）。
+//! オーバーフロー段にフレームが残っている間は、受信順を保つため新しいフレームも続けてそちらへ入れ、
+//! `dequeue` は通常のキューを取り出し終えてからオーバーフロー段を取り出します。
+//! オーバーフロー段も満杯なら、従来どおり `QueueError::Full` を返します（古いフレームは破棄しない）。
+
 use core::mem::MaybeUninit;
 use heapless::spsc::{Consumer, Producer, Queue};
 use log::{debug, error, warn};
-use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
 use super::{QueueError, QueueResult, ReceivedData};
+use crate::shutdown::{Shutdown, ShutdownReason};
+#[cfg(feature = "esp")]
+use crate::streaming::buffer::{BufferStats, BufferedData, StreamingBuffer};
 
 /// キューの容量定数
 pub const QUEUE_CAPACITY: usize = 512 + 1; // 512データ要素 + 余裕
 
+/// 緊急キューの容量定数（緊急フレームは稀なため小さくする）
+pub const URGENT_QUEUE_CAPACITY: usize = 16 + 1;
+
 /// 受信データのグローバルプロデューサー
 static RECEIVED_DATA_PRODUCER: Mutex<Option<Producer<'static, ReceivedData, QUEUE_CAPACITY>>> =
     Mutex::new(None);
@@ -15,9 +66,42 @@ static RECEIVED_DATA_PRODUCER: Mutex<Option<Producer<'static, ReceivedData, QUEU
 static RECEIVED_DATA_CONSUMER: Mutex<Option<Consumer<'static, ReceivedData, QUEUE_CAPACITY>>> =
     Mutex::new(None);
 
+/// 緊急フレームのグローバルプロデューサー
+static URGENT_DATA_PRODUCER: Mutex<Option<Producer<'static, ReceivedData, URGENT_QUEUE_CAPACITY>>> =
+    Mutex::new(None);
+
+/// 緊急フレームのグローバルコンシューマー
+static URGENT_DATA_CONSUMER: Mutex<Option<Consumer<'static, ReceivedData, URGENT_QUEUE_CAPACITY>>> =
+    Mutex::new(None);
+
+/// データ到着通知用のロック（条件変数と組で使用）
+static DATA_NOTIFY_LOCK: Mutex<()> = Mutex::new(());
+
+/// データ到着通知用の条件変数
+static DATA_NOTIFY: Condvar = Condvar::new();
+
+/// キュー内の要素数（消費者側のロックを取らずに使用量を読むため）
+static QUEUE_LEN: AtomicUsize = AtomicUsize::new(0);
+
+/// 緊急キュー内の要素数
+static URGENT_QUEUE_LEN: AtomicUsize = AtomicUsize::new(0);
+
+/// 通常のキューが満杯の間に届いたフレームのオーバーフロー段（`attach_overflow` で設定）
+#[cfg(feature = "esp")]
+static OVERFLOW: Mutex<Option<StreamingBuffer>> = Mutex::new(None);
+
+/// オーバーフロー段のフレーム数（生産者がロックを取らずに空かどうかを判断するため）
+static OVERFLOW_LEN: AtomicUsize = AtomicUsize::new(0);
+
+/// 停止処理でキューを閉じた（以降の追加を拒否する）
+static CLOSED: AtomicBool = AtomicBool::new(false);
+
 /// キュー自体のための静的バッファ（MaybeUninitで初期化）
 static mut Q_BUFFER: MaybeUninit<Queue<ReceivedData, QUEUE_CAPACITY>> = MaybeUninit::uninit();
 
+/// 緊急キューのための静的バッファ
+static mut URGENT_Q_BUFFER: MaybeUninit<Queue<ReceivedData, URGENT_QUEUE_CAPACITY>> = MaybeUninit::uninit();
+
 /// データキューを初期化します
 ///
 /// # 安全性
@@ -27,22 +111,36 @@ static mut Q_BUFFER: MaybeUninit<Queue<ReceivedData, QUEUE_CAPACITY>> = MaybeUni
 pub fn initialize_data_queue() -> bool {
     unsafe {
         // 静的バッファ内にキューを初期化
-        Q_BUFFER.write(Queue::new());
+        let buffer = &mut *core::ptr::addr_of_mut!(Q_BUFFER);
+        buffer.write(Queue::new());
         
         // 初期化されたキューへの可変参照を取得し、分割
-        let (p, c) = Q_BUFFER.assume_init_mut().split();
+        let (p, c) = buffer.assume_init_mut().split();
         
         // グローバル変数に格納
         *RECEIVED_DATA_PRODUCER.lock().unwrap() = Some(p);
         *RECEIVED_DATA_CONSUMER.lock().unwrap() = Some(c);
+
+        let urgent_buffer = &mut *core::ptr::addr_of_mut!(URGENT_Q_BUFFER);
+        urgent_buffer.write(Queue::new());
+        let (p, c) = urgent_buffer.assume_init_mut().split();
+        *URGENT_DATA_PRODUCER.lock().unwrap() = Some(p);
+        *URGENT_DATA_CONSUMER.lock().unwrap() = Some(c);
     }
+    QUEUE_LEN.store(0, Ordering::Release);
+    URGENT_QUEUE_LEN.store(0, Ordering::Release);
     
-    debug!("Data queue initialized with capacity: {}", QUEUE_CAPACITY);
+    debug!(
+        "Data queue initialized with capacity: {} (urgent: {})",
+        QUEUE_CAPACITY, URGENT_QUEUE_CAPACITY
+    );
     true
 }
 
 /// キューにデータを追加します
 ///
+/// 緊急フレームは緊急キューへ入れ、緊急キューが満杯なら通常のキューへ入れます。
+///
 /// # 引数
 ///
 /// * `data` - キューに追加するデータ
@@ -51,32 +149,132 @@ pub fn initialize_data_queue() -> bool {
 ///
 /// * `QueueResult<()>` - 成功した場合は`Ok(())`、失敗した場合は`Err(QueueError)`
 pub fn enqueue(data: ReceivedData) -> QueueResult<()> {
+    if CLOSED.load(Ordering::Acquire) {
+        return Err(QueueError::Other("Queue closed"));
+    }
+
+    let data = if data.urgent {
+        match push(&URGENT_DATA_PRODUCER, &URGENT_QUEUE_LEN, data) {
+            Ok(()) => return notify_consumer(),
+            Err((QueueError::Full, data)) => {
+                warn!("Urgent queue full, falling back to the data queue");
+                data
+            }
+            Err((e, _)) => return Err(e),
+        }
+    } else {
+        data
+    };
+    // オーバーフロー段に残っている間は受信順を保つため続けてそちらへ入れる
+    #[cfg(feature = "esp")]
+    if OVERFLOW_LEN.load(Ordering::Acquire) > 0 {
+        push_overflow(data)?;
+        return notify_consumer();
+    }
+    match push(&RECEIVED_DATA_PRODUCER, &QUEUE_LEN, data) {
+        Ok(()) => {}
+        #[cfg(feature = "esp")]
+        Err((QueueError::Full, data)) => push_overflow(data)?,
+        Err((e, _)) => return Err(e),
+    }
+    notify_consumer()
+}
+
+/// オーバーフロー段へ追加します（未設定または満杯なら `QueueError::Full`）
+#[cfg(feature = "esp")]
+fn push_overflow(data: ReceivedData) -> QueueResult<()> {
+    let mut overflow = OVERFLOW.lock().map_err(|_| QueueError::LockError)?;
+    let buffer = overflow.as_mut().ok_or(QueueError::Full)?;
+    let buffered = BufferedData::from_received(&data).map_err(|_| QueueError::Full)?;
+    buffer.try_push(buffered).map_err(|_| QueueError::Full)?;
+    OVERFLOW_LEN.fetch_add(1, Ordering::AcqRel);
+    Ok(())
+}
+
+/// オーバーフロー段から最も古いフレームを取り出します
+#[cfg(feature = "esp")]
+fn pop_overflow() -> QueueResult<ReceivedData> {
+    if OVERFLOW_LEN.load(Ordering::Acquire) == 0 {
+        return Err(QueueError::Empty);
+    }
+    let mut overflow = OVERFLOW.lock().map_err(|_| QueueError::LockError)?;
+    let data = overflow.as_mut().and_then(StreamingBuffer::pop).ok_or(QueueError::Empty)?;
+    OVERFLOW_LEN.fetch_sub(1, Ordering::AcqRel);
+    Ok(data.into_received())
+}
+
+/// 通常のキューが満杯の間に使うオーバーフロー段を設定します（起動時に一度だけ呼び出す）
+#[cfg(feature = "esp")]
+pub fn attach_overflow(buffer: StreamingBuffer) {
+    match OVERFLOW.lock() {
+        Ok(mut overflow) => *overflow = Some(buffer),
+        Err(_) => error!("Failed to attach the data queue overflow buffer: lock poisoned"),
+    }
+}
+
+/// オーバーフロー段の統計（未設定ならNone）
+#[cfg(feature = "esp")]
+pub fn overflow_stats() -> Option<BufferStats> {
+    OVERFLOW.lock().ok()?.as_ref().map(StreamingBuffer::stats)
+}
+
+/// キューにデータを追加します（失敗した場合はデータを返す）
+fn push<const N: usize>(
+    producer: &Mutex<Option<Producer<'static, ReceivedData, N>>>,
+    len: &AtomicUsize,
+    data: ReceivedData,
+) -> Result<(), (QueueError, ReceivedData)> {
     // プロデューサーのロックを取得
-    let mut producer_guard = RECEIVED_DATA_PRODUCER
-        .lock()
-        .map_err(|_| QueueError::LockError)?;
-    
+    let mut producer_guard = match producer.lock() {
+        Ok(guard) => guard,
+        Err(_) => return Err((QueueError::LockError, data)),
+    };
+
     // プロデューサーの参照を取得
-    let producer = producer_guard
-        .as_mut()
-        .ok_or(QueueError::Other("Queue not initialized"))?;
-    
+    let Some(producer) = producer_guard.as_mut() else {
+        return Err((QueueError::Other("Queue not initialized"), data));
+    };
+
     // データをキューに追加
-    producer
-        .enqueue(data)
-        .map_err(|_| QueueError::Full)
+    producer.enqueue(data).map_err(|data| (QueueError::Full, data))?;
+    len.fetch_add(1, Ordering::AcqRel);
+    Ok(())
+}
+
+/// 待機中のコンシューマーへ通知します
+fn notify_consumer() -> QueueResult<()> {
+    let _notify_guard = DATA_NOTIFY_LOCK.lock().map_err(|_| QueueError::LockError)?;
+    DATA_NOTIFY.notify_one();
+    Ok(())
 }
 
 /// キューからデータを取り出します
 ///
+/// 緊急キューにデータがあれば、通常のキューより先に取り出します。
+///
 /// # 戻り値
 ///
 /// * `QueueResult<ReceivedData>` - データがある場合は`Ok(ReceivedData)`、ない場合は`Err(QueueError)`
 pub fn dequeue() -> QueueResult<ReceivedData> {
+    match pop(&URGENT_DATA_CONSUMER, &URGENT_QUEUE_LEN) {
+        Err(QueueError::Empty) => {}
+        other => return other,
+    }
+    match pop(&RECEIVED_DATA_CONSUMER, &QUEUE_LEN) {
+        // オーバーフロー段のフレームは通常のキューのどのフレームよりも新しい
+        #[cfg(feature = "esp")]
+        Err(QueueError::Empty) => pop_overflow(),
+        other => other,
+    }
+}
+
+/// キューからデータを取り出します
+fn pop<const N: usize>(
+    consumer: &Mutex<Option<Consumer<'static, ReceivedData, N>>>,
+    len: &AtomicUsize,
+) -> QueueResult<ReceivedData> {
     // コンシューマーのロックを取得
-    let mut consumer_guard = RECEIVED_DATA_CONSUMER
-        .lock()
-        .map_err(|_| QueueError::LockError)?;
+    let mut consumer_guard = consumer.lock().map_err(|_| QueueError::LockError)?;
     
     // コンシューマーの参照を取得
     let consumer = consumer_guard
@@ -84,9 +282,41 @@ pub fn dequeue() -> QueueResult<ReceivedData> {
         .ok_or(QueueError::Other("Queue not initialized"))?;
     
     // キューからデータを取り出す
-    consumer
-        .dequeue()
-        .ok_or(QueueError::Empty)
+    let data = consumer.dequeue().ok_or(QueueError::Empty)?;
+    len.fetch_sub(1, Ordering::AcqRel);
+    Ok(data)
+}
+
+/// データが届くまで最大`timeout_ms`待機してキューから取り出します
+///
+/// # 戻り値
+///
+/// * `QueueResult<ReceivedData>` - タイムアウトまでにデータがない場合は`Err(QueueError::Empty)`
+pub fn dequeue_timeout(timeout_ms: u32) -> QueueResult<ReceivedData> {
+    match dequeue() {
+        Err(QueueError::Empty) => {}
+        other => return other,
+    }
+
+    let deadline = Instant::now() + Duration::from_millis(timeout_ms as u64);
+    let mut notify_guard = DATA_NOTIFY_LOCK.lock().map_err(|_| QueueError::LockError)?;
+    loop {
+        // 通知の取り逃しを防ぐため、ロックを保持したまま確認してから待機する
+        match dequeue() {
+            Err(QueueError::Empty) => {}
+            other => return other,
+        }
+
+        // 古い通知や通知のない起床では戻らず、期限まで待ち直す
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Err(QueueError::Empty);
+        }
+        notify_guard = DATA_NOTIFY
+            .wait_timeout(notify_guard, remaining)
+            .map_err(|_| QueueError::LockError)?
+            .0;
+    }
 }
 
 /// ESP-NOW受信コールバックからデータをキューに追加するためのヘルパー関数
@@ -112,30 +342,74 @@ pub fn try_enqueue_from_callback(data: ReceivedData) -> bool {
     }
 }
 
-/// キューの現在のサイズを取得します（デバッグ用）
+/// キューを閉じ、USB送信タスクが残りのデータを送り出すのを待つ停止処理
+///
+/// パニック時は送出を待たずに閉じるだけにします（送信タスク自体が停止している可能性があるため）。
+pub struct DataQueueDrain {
+    /// 送出を待つ最長時間
+    pub timeout: Duration,
+}
+
+impl Shutdown for DataQueueDrain {
+    fn shutdown(&mut self, reason: ShutdownReason) -> Result<(), String> {
+        CLOSED.store(true, Ordering::Release);
+        if let Ok(_notify_guard) = DATA_NOTIFY_LOCK.try_lock() {
+            DATA_NOTIFY.notify_all();
+        }
+        if reason == ShutdownReason::Panic {
+            return Ok(());
+        }
+        let started = Instant::now();
+        loop {
+            let (queued, _) = get_queue_usage().map_err(|e| e.to_string())?;
+            let remaining =
+                queued + URGENT_QUEUE_LEN.load(Ordering::Acquire) + OVERFLOW_LEN.load(Ordering::Acquire);
+            if remaining == 0 {
+                return Ok(());
+            }
+            if started.elapsed() >= self.timeout {
+                return Err(format!("{} items left in the data queue", remaining));
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+}
+
+/// キューの現在のサイズを取得します（緊急キューを含まない）
+///
+/// 消費者側のロックを取らないため、ESP-NOW受信コールバックからも呼び出せます。
 pub fn get_queue_usage() -> QueueResult<(usize, usize)> {
-    let consumer_guard = RECEIVED_DATA_CONSUMER
-        .lock()
-        .map_err(|_| QueueError::LockError)?;
-    
-    let consumer = consumer_guard
-        .as_ref()
-        .ok_or(QueueError::Other("Queue not initialized"))?;
-    
-    // heaplessのQueueは直接サイズを確認する方法を提供していないため、
-    // 実際の実装ではコンシューマーから得られる情報に基づいて推定することになります。
-    // ここでは例として単純な値を返します。
-    Ok((consumer.len(), QUEUE_CAPACITY))
+    Ok((QUEUE_LEN.load(Ordering::Acquire), QUEUE_CAPACITY))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     
-    // 注: テスト用のキューを使用するため、テストを逐次実行する必要があります
-    
+    // 注: グローバルのキューを使用するため、テストはこのロックで逐次実行します
+    static TEST_LOCK: Mutex<()> = Mutex::new(());
+
+    fn received(index: u32) -> ReceivedData {
+        ReceivedData {
+            mac: [0x12, 0x34, 0x56, 0x78, 0x9a, 0xbc],
+            data: index.to_le_bytes().to_vec(),
+            epoch: 0,
+            received_at: Instant::now(),
+            rssi: None,
+            urgent: false,
+        }
+    }
+
+    fn urgent(index: u32) -> ReceivedData {
+        ReceivedData {
+            urgent: true,
+            ..received(index)
+        }
+    }
+
     #[test]
     fn test_queue_operations() {
+        let _serial = TEST_LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
         // テスト用にキューを初期化
         initialize_data_queue();
         
@@ -147,6 +421,10 @@ mod tests {
         let data = ReceivedData {
             mac: test_mac,
             data: test_data.clone(),
+            epoch: 0,
+            received_at: std::time::Instant::now(),
+            rssi: None,
+            urgent: false,
         };
         
         assert!(try_enqueue_from_callback(data));
@@ -161,5 +439,107 @@ mod tests {
         
         // キューが空になったことを確認
         assert!(dequeue().is_err());
+        assert_eq!(get_queue_usage(), Ok((0, QUEUE_CAPACITY)));
+    }
+
+    #[test]
+    fn test_concurrent_producer_and_consumer() {
+        let _serial = TEST_LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        initialize_data_queue();
+        const COUNT: u32 = 2000;
+
+        // 受信コールバックの代わりのスレッドが追加し、満杯なら消費者が取り出すまで再試行する
+        let producer = std::thread::spawn(|| {
+            for index in 0..COUNT {
+                while !try_enqueue_from_callback(received(index)) {
+                    std::thread::yield_now();
+                }
+            }
+        });
+        // 使用量の読み取りは消費者と並行しても待たされない
+        let observer = std::thread::spawn(|| {
+            for _ in 0..COUNT {
+                let (used, capacity) = get_queue_usage().unwrap();
+                assert!(used <= capacity);
+            }
+        });
+
+        let mut next = 0;
+        while next < COUNT {
+            match dequeue_timeout(1000) {
+                Ok(data) => {
+                    assert_eq!(data.data, next.to_le_bytes().to_vec(), "frames must arrive in order");
+                    next += 1;
+                }
+                Err(QueueError::Empty) => panic!("consumer timed out after {} frames", next),
+                Err(e) => panic!("dequeue failed: {}", e),
+            }
+        }
+        producer.join().unwrap();
+        observer.join().unwrap();
+        assert_eq!(get_queue_usage(), Ok((0, QUEUE_CAPACITY)));
+    }
+
+    #[test]
+    fn test_urgent_frames_overtake_queued_chunks() {
+        let _serial = TEST_LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        initialize_data_queue();
+
+        // 転送途中のチャンクの間に緊急フレームが割り込む
+        for index in 0..10 {
+            assert!(try_enqueue_from_callback(received(index)));
+        }
+        assert!(try_enqueue_from_callback(urgent(100)));
+        for index in 10..20 {
+            assert!(try_enqueue_from_callback(received(index)));
+        }
+        assert!(try_enqueue_from_callback(urgent(101)));
+        assert_eq!(get_queue_usage(), Ok((20, QUEUE_CAPACITY)));
+
+        // 緊急フレームが受信順に先に取り出され、チャンクの順序は変わらない
+        let order: Vec<u32> = std::iter::from_fn(|| dequeue().ok())
+            .map(|data| u32::from_le_bytes(data.data.try_into().unwrap()))
+            .collect();
+        let expected: Vec<u32> = [100, 101].into_iter().chain(0..20).collect();
+        assert_eq!(order, expected);
+    }
+
+    #[test]
+    fn test_full_urgent_queue_falls_back_to_data_queue() {
+        let _serial = TEST_LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        initialize_data_queue();
+        let urgent_slots = URGENT_QUEUE_CAPACITY as u32 - 1;
+
+        assert!(try_enqueue_from_callback(received(0)));
+        for index in 0..=urgent_slots {
+            assert!(try_enqueue_from_callback(urgent(100 + index)));
+        }
+
+        // 溢れた緊急フレームは失わず、先に入っていたチャンクの後に取り出す
+        let order: Vec<u32> = std::iter::from_fn(|| dequeue().ok())
+            .map(|data| u32::from_le_bytes(data.data.try_into().unwrap()))
+            .collect();
+        let mut expected: Vec<u32> = (100..100 + urgent_slots).collect();
+        expected.extend([0, 100 + urgent_slots]);
+        assert_eq!(order, expected);
+        assert_eq!(get_queue_usage(), Ok((0, QUEUE_CAPACITY)));
+    }
+
+    #[test]
+    fn test_drain_waits_for_urgent_frames() {
+        let _serial = TEST_LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        initialize_data_queue();
+        assert!(try_enqueue_from_callback(urgent(1)));
+
+        let mut drain = DataQueueDrain {
+            timeout: Duration::from_millis(20),
+        };
+        // 緊急キューに残ったフレームも送出待ちとして数え、閉じた後は緊急フレームも受け付けない
+        let result = drain.shutdown(ShutdownReason::SoftReset);
+        let accepted_after_close = try_enqueue_from_callback(urgent(2));
+        CLOSED.store(false, Ordering::Release);
+
+        assert_eq!(result, Err("1 items left in the data queue".to_string()));
+        assert!(!accepted_after_close);
     }
 }
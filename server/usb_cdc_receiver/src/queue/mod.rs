@@ -1,5 +1,7 @@
 pub mod data_queue;
 
+use std::time::Instant;
+
 /// 受信データを表す構造体
 /// 
 /// MACアドレスとフレームデータを保持します。
@@ -9,6 +11,14 @@ pub struct ReceivedData {
     pub mac: [u8; 6],
     /// 受信したフレームデータ
     pub data: Vec<u8>,
+    /// キュー投入時の中断世代（`esp_now::cancellation`、古い世代のデータは破棄）
+    pub epoch: u32,
+    /// ESP-NOW受信時刻（フレーム処理時間の計測用）
+    pub received_at: Instant,
+    /// 受信信号強度（dBm、取得できない場合はNone）
+    pub rssi: Option<i8>,
+    /// 緊急フレーム（`data_queue` の緊急キューへ入れ、溜まったチャンクより先に取り出す）
+    pub urgent: bool,
 }
 
 /// キューの操作結果を表す型
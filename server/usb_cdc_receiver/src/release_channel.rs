@@ -0,0 +1,307 @@
+//! ファームウェアのリリースチャンネル（段階的な展開）
+//!
+//! デバイスは既定で `stable` チャンネルに属し、`SET_CHANNEL` で特定のデバイスを `beta` にできます。
+//! `SET_ROLLOUT` はチャンネルごとに展開するファームウェア（マニフェスト）を登録し、マニフェストは
+//! チャンネルが一致するデバイスにのみ提供します（`offers`）。デバイスがHASHフレームで報告する
+//! ファームウェア（`FW`）がマニフェストと一致したら更新済みとして数え、展開の進み具合を
+//! FLEET_SUMMARYで報告します。
+//!
+//! チャンネルとマニフェストはRAMのみに保持するため、ゲートウェイの再起動後はPCから設定し直します。
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::mac_address::format_mac_address;
+
+/// チャンネル設定コマンド応答の接頭辞
+pub const CHANNEL_RESPONSE_PREFIX: &str = "CMD_CHANNEL:";
+
+/// 展開設定コマンド応答の接頭辞
+pub const ROLLOUT_RESPONSE_PREFIX: &str = "CMD_ROLLOUT:";
+
+/// ファームウェアのバージョン表記の最大長（gitの短縮ハッシュと `-dirty` を想定）
+pub const MAX_FIRMWARE_LEN: usize = 32;
+
+/// リリースチャンネル
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum ReleaseChannel {
+    /// 通常のデバイス
+    #[default]
+    Stable,
+    /// 先行して新しいファームウェアを受け取るデバイス
+    Beta,
+}
+
+/// チャンネル名の一覧（コマンドの構文表示用）
+pub const RELEASE_CHANNEL_NAMES: &[&str] = &["stable", "beta"];
+
+impl ReleaseChannel {
+    /// 応答行・サマリーでの表記
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ReleaseChannel::Stable => "stable",
+            ReleaseChannel::Beta => "beta",
+        }
+    }
+
+    /// チャンネル名を解析します（大文字・小文字を区別しない）
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "stable" => Some(ReleaseChannel::Stable),
+            "beta" => Some(ReleaseChannel::Beta),
+            _ => None,
+        }
+    }
+}
+
+/// チャンネルに展開するファームウェア
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FirmwareManifest {
+    /// 対象のチャンネル
+    pub channel: ReleaseChannel,
+    /// デバイスが `FW` で報告するバージョン表記
+    pub firmware: String,
+}
+
+/// デバイスの展開状況
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RolloutState {
+    /// マニフェストのファームウェアをまだ報告していない
+    Pending,
+    /// マニフェストのファームウェアを報告した
+    Updated,
+}
+
+impl RolloutState {
+    /// サマリーでの表記
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RolloutState::Pending => "pending",
+            RolloutState::Updated => "updated",
+        }
+    }
+}
+
+/// チャンネルごとの展開の進み具合
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RolloutProgress {
+    pub manifest: FirmwareManifest,
+    /// チャンネルに属する登録デバイスの数
+    pub targeted: u32,
+    /// マニフェストのファームウェアを報告したデバイスの数
+    pub updated: u32,
+}
+
+/// デバイスごとのチャンネルとチャンネルごとのマニフェスト
+#[derive(Debug, Clone, Default)]
+pub struct ReleaseChannels {
+    beta: BTreeSet<[u8; 6]>,
+    manifests: BTreeMap<ReleaseChannel, String>,
+}
+
+impl ReleaseChannels {
+    /// すべて `stable`・マニフェストなしで作成します
+    pub const fn new() -> Self {
+        Self {
+            beta: BTreeSet::new(),
+            manifests: BTreeMap::new(),
+        }
+    }
+
+    /// デバイスのチャンネル
+    pub fn channel(&self, mac: &[u8; 6]) -> ReleaseChannel {
+        if self.beta.contains(mac) {
+            ReleaseChannel::Beta
+        } else {
+            ReleaseChannel::Stable
+        }
+    }
+
+    /// デバイスのチャンネルを設定し、変わった場合はtrueを返します
+    pub fn set_channel(&mut self, mac: [u8; 6], channel: ReleaseChannel) -> bool {
+        match channel {
+            ReleaseChannel::Beta => self.beta.insert(mac),
+            ReleaseChannel::Stable => self.beta.remove(&mac),
+        }
+    }
+
+    /// チャンネルのマニフェストを登録します（`None` で展開を取り下げる）
+    pub fn set_manifest(&mut self, channel: ReleaseChannel, firmware: Option<String>) {
+        match firmware {
+            Some(firmware) => self.manifests.insert(channel, firmware),
+            None => self.manifests.remove(&channel),
+        };
+    }
+
+    /// チャンネルのマニフェスト
+    pub fn manifest(&self, channel: ReleaseChannel) -> Option<FirmwareManifest> {
+        self.manifests.get(&channel).map(|firmware| FirmwareManifest {
+            channel,
+            firmware: firmware.clone(),
+        })
+    }
+
+    /// マニフェストのファームウェアをデバイスに提供してよいか（チャンネルが一致するか）
+    pub fn offers(&self, mac: &[u8; 6], manifest: &FirmwareManifest) -> bool {
+        self.channel(mac) == manifest.channel
+    }
+
+    /// デバイスの展開状況（チャンネルにマニフェストがなければNone）
+    pub fn rollout_state(&self, mac: &[u8; 6], reported_firmware: Option<&str>) -> Option<RolloutState> {
+        let manifest = self
+            .manifests
+            .keys()
+            .filter_map(|channel| self.manifest(*channel))
+            .find(|manifest| self.offers(mac, manifest))?;
+        Some(if reported_firmware == Some(manifest.firmware.as_str()) {
+            RolloutState::Updated
+        } else {
+            RolloutState::Pending
+        })
+    }
+
+    /// マニフェストのあるチャンネルごとの展開の進み具合
+    ///
+    /// # 引数
+    /// * `devices` - 登録デバイスと最後に報告されたファームウェア
+    pub fn progress<'a>(
+        &self,
+        devices: impl IntoIterator<Item = ([u8; 6], Option<&'a str>)>,
+    ) -> Vec<RolloutProgress> {
+        let mut progress: Vec<RolloutProgress> = self
+            .manifests
+            .keys()
+            .filter_map(|channel| self.manifest(*channel))
+            .map(|manifest| RolloutProgress {
+                manifest,
+                targeted: 0,
+                updated: 0,
+            })
+            .collect();
+        for (mac, reported_firmware) in devices {
+            let Some(state) = self.rollout_state(&mac, reported_firmware) else {
+                continue;
+            };
+            let channel = self.channel(&mac);
+            if let Some(entry) = progress.iter_mut().find(|entry| entry.manifest.channel == channel) {
+                entry.targeted += 1;
+                if state == RolloutState::Updated {
+                    entry.updated += 1;
+                }
+            }
+        }
+        progress
+    }
+
+    /// `SET_CHANNEL` への応答
+    pub fn channel_response(&self, mac: &[u8; 6]) -> String {
+        let channel = self.channel(mac);
+        format!(
+            "{}{} {} target={}\n",
+            CHANNEL_RESPONSE_PREFIX,
+            format_mac_address(mac),
+            channel.as_str(),
+            self.manifests.get(&channel).map_or("-", String::as_str)
+        )
+    }
+
+    /// `SET_ROLLOUT` への応答（チャンネル1つにつき1行）
+    pub fn rollout_response(&self, progress: &[RolloutProgress]) -> String {
+        if progress.is_empty() {
+            return format!("{}none\n", ROLLOUT_RESPONSE_PREFIX);
+        }
+        progress
+            .iter()
+            .map(|entry| {
+                format!(
+                    "{}{} fw={} updated={}/{}\n",
+                    ROLLOUT_RESPONSE_PREFIX,
+                    entry.manifest.channel.as_str(),
+                    entry.manifest.firmware,
+                    entry.updated,
+                    entry.targeted
+                )
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAC_A: [u8; 6] = [0x34, 0xab, 0x95, 0xfb, 0x3f, 0xc4];
+    const MAC_B: [u8; 6] = [0x34, 0xab, 0x95, 0xfb, 0x3f, 0xc5];
+    const MAC_C: [u8; 6] = [0x34, 0xab, 0x95, 0xfb, 0x3f, 0xc6];
+
+    #[test]
+    fn test_channel_defaults_to_stable() {
+        let mut channels = ReleaseChannels::new();
+        assert_eq!(channels.channel(&MAC_A), ReleaseChannel::Stable);
+        assert!(channels.set_channel(MAC_A, ReleaseChannel::Beta));
+        assert!(!channels.set_channel(MAC_A, ReleaseChannel::Beta));
+        assert_eq!(channels.channel(&MAC_A), ReleaseChannel::Beta);
+        assert!(channels.set_channel(MAC_A, ReleaseChannel::Stable));
+        assert_eq!(channels.channel(&MAC_A), ReleaseChannel::Stable);
+
+        assert_eq!(ReleaseChannel::parse("BETA"), Some(ReleaseChannel::Beta));
+        assert_eq!(ReleaseChannel::parse("nightly"), None);
+    }
+
+    #[test]
+    fn test_manifest_is_offered_only_to_matching_channel() {
+        let mut channels = ReleaseChannels::new();
+        channels.set_channel(MAC_A, ReleaseChannel::Beta);
+        channels.set_manifest(ReleaseChannel::Beta, Some("1a2b3c4d".to_string()));
+        let manifest = channels.manifest(ReleaseChannel::Beta).unwrap();
+
+        assert!(channels.offers(&MAC_A, &manifest));
+        assert!(!channels.offers(&MAC_B, &manifest));
+        assert_eq!(channels.rollout_state(&MAC_A, Some("0f0f0f0f")), Some(RolloutState::Pending));
+        assert_eq!(channels.rollout_state(&MAC_A, Some("1a2b3c4d")), Some(RolloutState::Updated));
+        // stableにはマニフェストがない
+        assert_eq!(channels.rollout_state(&MAC_B, Some("1a2b3c4d")), None);
+        assert_eq!(
+            channels.channel_response(&MAC_A),
+            "CMD_CHANNEL:34:ab:95:fb:3f:c4 beta target=1a2b3c4d\n"
+        );
+        assert_eq!(channels.channel_response(&MAC_B), "CMD_CHANNEL:34:ab:95:fb:3f:c5 stable target=-\n");
+
+        channels.set_manifest(ReleaseChannel::Beta, None);
+        assert_eq!(channels.rollout_state(&MAC_A, Some("1a2b3c4d")), None);
+    }
+
+    #[test]
+    fn test_progress_per_channel() {
+        let mut channels = ReleaseChannels::new();
+        channels.set_channel(MAC_A, ReleaseChannel::Beta);
+        channels.set_channel(MAC_B, ReleaseChannel::Beta);
+        channels.set_manifest(ReleaseChannel::Beta, Some("1a2b3c4d".to_string()));
+        channels.set_manifest(ReleaseChannel::Stable, Some("0f0f0f0f".to_string()));
+
+        let progress = channels.progress([
+            (MAC_A, Some("1a2b3c4d")),
+            (MAC_B, None),
+            (MAC_C, Some("0f0f0f0f")),
+        ]);
+        assert_eq!(
+            progress,
+            vec![
+                RolloutProgress {
+                    manifest: channels.manifest(ReleaseChannel::Stable).unwrap(),
+                    targeted: 1,
+                    updated: 1,
+                },
+                RolloutProgress {
+                    manifest: channels.manifest(ReleaseChannel::Beta).unwrap(),
+                    targeted: 2,
+                    updated: 1,
+                },
+            ]
+        );
+        assert_eq!(
+            channels.rollout_response(&progress),
+            "CMD_ROLLOUT:stable fw=0f0f0f0f updated=1/1\nCMD_ROLLOUT:beta fw=1a2b3c4d updated=1/2\n"
+        );
+        assert_eq!(ReleaseChannels::new().rollout_response(&[]), "CMD_ROLLOUT:none\n");
+    }
+}
@@ -0,0 +1,86 @@
+//! 送信済み画像の再送要求
+//!
+//! デバイスは転送を終えてからスリープコマンドを受信するまでのコマンド待機中に限り、直前に送信した
+//! 画像を保持しています（HASHフレームの `RETAIN_BYTES`・`RETAIN_FRAME` で報告）。`RESEND_LAST` で
+//! 登録した要求は次のメンテナンス周期で制御フレームとして送信し、デバイスは撮影せずに同じ画像を送り直します。
+//! スリープコマンドを送った後の要求は届かないため、PCはスリープコマンドより先に要求してください。
+//! デバイスが保持している直近のサムネイルの要求（`GET_THUMBS`）も、同じくコマンド待機中に届ける
+//! 必要があるため、応答の接頭辞だけを変えた同じ登録で扱います。
+
+use std::collections::BTreeSet;
+
+use crate::mac_address::format_mac_address;
+
+/// 再送要求コマンド応答の接頭辞
+pub const RESEND_RESPONSE_PREFIX: &str = "CMD_RESEND:";
+/// サムネイル要求コマンド応答の接頭辞
+pub const THUMBS_RESPONSE_PREFIX: &str = "CMD_THUMBS:";
+
+/// 送信待ちの再送要求
+#[derive(Debug)]
+pub struct ResendRequests {
+    pending: BTreeSet<[u8; 6]>,
+    response_prefix: &'static str,
+}
+
+impl Default for ResendRequests {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ResendRequests {
+    /// 空の登録を作成します
+    pub const fn new() -> Self {
+        Self::with_prefix(RESEND_RESPONSE_PREFIX)
+    }
+
+    /// 応答の接頭辞を指定して空の登録を作成します（`GET_THUMBS` は `THUMBS_RESPONSE_PREFIX`）
+    pub const fn with_prefix(response_prefix: &'static str) -> Self {
+        Self { pending: BTreeSet::new(), response_prefix }
+    }
+
+    /// 再送要求を登録します（送信待ちの要求があればまとめる）
+    pub fn request(&mut self, mac: [u8; 6]) {
+        self.pending.insert(mac);
+    }
+
+    /// 送信する要求を1件取り出します
+    pub fn take_next(&mut self) -> Option<[u8; 6]> {
+        self.pending.pop_first()
+    }
+
+    /// 送信待ちの要求があるか
+    pub fn is_pending(&self, mac: &[u8; 6]) -> bool {
+        self.pending.contains(mac)
+    }
+
+    /// `RESEND_LAST`・`GET_THUMBS` への応答行
+    pub fn response(&self, mac: &[u8; 6]) -> String {
+        let state = if self.is_pending(mac) { "queued" } else { "sent" };
+        format!("{}{} {}\n", self.response_prefix, format_mac_address(mac), state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAC: [u8; 6] = [0x34, 0xab, 0x95, 0xfb, 0x3f, 0xc4];
+
+    #[test]
+    fn test_duplicate_requests_are_sent_once() {
+        let mut requests = ResendRequests::new();
+        requests.request(MAC);
+        requests.request(MAC);
+        assert_eq!(requests.response(&MAC), "CMD_RESEND:34:ab:95:fb:3f:c4 queued\n");
+
+        assert_eq!(requests.take_next(), Some(MAC));
+        assert_eq!(requests.take_next(), None);
+        assert_eq!(requests.response(&MAC), "CMD_RESEND:34:ab:95:fb:3f:c4 sent\n");
+
+        let mut thumbnails = ResendRequests::with_prefix(THUMBS_RESPONSE_PREFIX);
+        thumbnails.request(MAC);
+        assert_eq!(thumbnails.response(&MAC), "CMD_THUMBS:34:ab:95:fb:3f:c4 queued\n");
+    }
+}
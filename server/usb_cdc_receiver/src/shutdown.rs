@@ -0,0 +1,215 @@
+//! 再起動前のサブシステムの停止
+//!
+//! ESP-NOW・データキュー・USB CDCなどのサブシステムは `Shutdown` を実装し、停止の段階
+//! （`ShutdownStage`）とともに `SHUTDOWN` へ登録します。`SOFT_RESET` コマンドやパニック時に
+//! 段階の順（受信の停止 → キューの送出 → USBのバッファの書き出しとドライバーの解放）で停止し、
+//! 受信済みのデータを送り出してから再起動します。
+
+use std::sync::{Arc, Mutex, TryLockError};
+
+/// 停止開始の応答の接頭辞
+pub const SHUTDOWN_RESPONSE_PREFIX: &str = "CMD_SHUTDOWN:";
+
+/// 停止の段階（この順に停止する）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ShutdownStage {
+    /// 新しいデータの受信を止める（ESP-NOW）
+    Ingress,
+    /// 受信済みのデータを送り出す（データキュー・ストリーミングのバッファ）
+    Drain,
+    /// 書き出しを終えてドライバーを解放する（USB CDC）
+    Teardown,
+}
+
+/// 停止の理由
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShutdownReason {
+    /// `SOFT_RESET` コマンド
+    SoftReset,
+    /// パニック（他のタスクが保持するロックや待機中のキューを待たない）
+    Panic,
+}
+
+impl ShutdownReason {
+    /// ログ・応答用の名前
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ShutdownReason::SoftReset => "soft_reset",
+            ShutdownReason::Panic => "panic",
+        }
+    }
+
+    /// 停止開始の応答行
+    pub fn response(self) -> String {
+        format!("{}started reason={}\n", SHUTDOWN_RESPONSE_PREFIX, self.as_str())
+    }
+}
+
+/// 再起動前に停止するサブシステム
+pub trait Shutdown {
+    /// 停止します（送出しきれなかった場合などはエラーの内容を返す）
+    fn shutdown(&mut self, reason: ShutdownReason) -> Result<(), String>;
+}
+
+/// タスク間で共有するサブシステム（パニック時はロックを待たない）
+impl<T: Shutdown> Shutdown for Arc<Mutex<T>> {
+    fn shutdown(&mut self, reason: ShutdownReason) -> Result<(), String> {
+        let mut guard = match (reason, self.try_lock()) {
+            (_, Ok(guard)) => guard,
+            (_, Err(TryLockError::Poisoned(poisoned))) => poisoned.into_inner(),
+            (ShutdownReason::SoftReset, Err(TryLockError::WouldBlock)) => {
+                self.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+            }
+            (ShutdownReason::Panic, Err(TryLockError::WouldBlock)) => return Err("locked by another task".to_string()),
+        };
+        guard.shutdown(reason)
+    }
+}
+
+/// 登録したサブシステムの停止結果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShutdownOutcome {
+    /// サブシステム名
+    pub name: &'static str,
+    /// 停止の段階
+    pub stage: ShutdownStage,
+    /// 停止結果
+    pub result: Result<(), String>,
+}
+
+struct Hook {
+    name: &'static str,
+    stage: ShutdownStage,
+    subsystem: Box<dyn Shutdown + Send>,
+}
+
+/// 登録したサブシステムの停止手順
+pub struct ShutdownSequence {
+    hooks: Vec<Hook>,
+    completed: bool,
+}
+
+impl ShutdownSequence {
+    /// 空の停止手順を作成します
+    pub const fn new() -> Self {
+        Self {
+            hooks: Vec::new(),
+            completed: false,
+        }
+    }
+
+    /// サブシステムを登録します（同じ段階では登録順に停止する）
+    pub fn register(&mut self, name: &'static str, stage: ShutdownStage, subsystem: Box<dyn Shutdown + Send>) {
+        self.hooks.push(Hook { name, stage, subsystem });
+    }
+
+    /// 登録済みのサブシステム数
+    pub fn len(&self) -> usize {
+        self.hooks.len()
+    }
+
+    /// 登録済みのサブシステムがないか
+    pub fn is_empty(&self) -> bool {
+        self.hooks.is_empty()
+    }
+
+    /// 段階の順にすべてのサブシステムを停止します
+    ///
+    /// 停止は1回だけ行います（停止中のパニックなどで再度呼ばれた場合は何もしない）。
+    /// 失敗したサブシステムがあっても残りの停止を続けます。
+    pub fn run(&mut self, reason: ShutdownReason) -> Vec<ShutdownOutcome> {
+        if self.completed {
+            return Vec::new();
+        }
+        self.completed = true;
+        self.hooks.sort_by_key(|hook| hook.stage);
+        self.hooks
+            .iter_mut()
+            .map(|hook| ShutdownOutcome {
+                name: hook.name,
+                stage: hook.stage,
+                result: hook.subsystem.shutdown(reason),
+            })
+            .collect()
+    }
+}
+
+impl Default for ShutdownSequence {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// ゲートウェイのサブシステムの停止手順（起動時に登録する）
+pub static SHUTDOWN: Mutex<ShutdownSequence> = Mutex::new(ShutdownSequence::new());
+
+/// 登録したサブシステムを停止します（パニック時は停止手順のロックを待たない）
+pub fn shutdown_all(reason: ShutdownReason) -> Vec<ShutdownOutcome> {
+    let mut sequence = match (reason, SHUTDOWN.try_lock()) {
+        (_, Ok(sequence)) => sequence,
+        (_, Err(TryLockError::Poisoned(poisoned))) => poisoned.into_inner(),
+        (ShutdownReason::SoftReset, Err(TryLockError::WouldBlock)) => {
+            SHUTDOWN.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+        }
+        (ShutdownReason::Panic, Err(TryLockError::WouldBlock)) => return Vec::new(),
+    };
+    sequence.run(reason)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Recorder {
+        name: &'static str,
+        log: Arc<Mutex<Vec<&'static str>>>,
+        fail: bool,
+    }
+
+    impl Shutdown for Recorder {
+        fn shutdown(&mut self, _reason: ShutdownReason) -> Result<(), String> {
+            self.log.lock().unwrap().push(self.name);
+            if self.fail {
+                Err(format!("{} failed", self.name))
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    fn recorder(name: &'static str, log: &Arc<Mutex<Vec<&'static str>>>, fail: bool) -> Box<Recorder> {
+        Box::new(Recorder { name, log: log.clone(), fail })
+    }
+
+    #[test]
+    fn test_subsystems_stop_in_stage_order_once() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut sequence = ShutdownSequence::new();
+        sequence.register("usb", ShutdownStage::Teardown, recorder("usb", &log, false));
+        sequence.register("queue", ShutdownStage::Drain, recorder("queue", &log, true));
+        sequence.register("esp_now", ShutdownStage::Ingress, recorder("esp_now", &log, false));
+        sequence.register("streaming", ShutdownStage::Drain, recorder("streaming", &log, false));
+
+        let outcomes = sequence.run(ShutdownReason::SoftReset);
+        // 失敗しても残りの停止を続ける
+        assert_eq!(*log.lock().unwrap(), ["esp_now", "queue", "streaming", "usb"]);
+        assert_eq!(outcomes[1].result, Err("queue failed".to_string()));
+        assert!(outcomes.iter().filter(|outcome| outcome.name != "queue").all(|outcome| outcome.result.is_ok()));
+
+        assert!(sequence.run(ShutdownReason::Panic).is_empty());
+        assert_eq!(log.lock().unwrap().len(), 4);
+    }
+
+    #[test]
+    fn test_shared_subsystem_is_skipped_while_locked_during_panic() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut shared = Arc::new(Mutex::new(Recorder { name: "usb", log: log.clone(), fail: false }));
+        let held = shared.clone();
+        let guard = held.lock().unwrap();
+        assert!(shared.shutdown(ShutdownReason::Panic).is_err());
+        drop(guard);
+        assert_eq!(shared.shutdown(ShutdownReason::Panic), Ok(()));
+        assert_eq!(*log.lock().unwrap(), ["usb"]);
+        assert_eq!(ShutdownReason::SoftReset.response(), "CMD_SHUTDOWN:started reason=soft_reset\n");
+    }
+}
@@ -10,7 +10,7 @@ use crate::esp_now::sender::EspNowSender;
 const SLEEP_COMMAND_QUEUE_SIZE: usize = 10;
 
 /// スリープコマンド送信間隔（ミリ秒）
-const SLEEP_COMMAND_INTERVAL_MS: u32 = 500;
+pub const SLEEP_COMMAND_INTERVAL_MS: u32 = 500;
 
 /// スリープコマンド情報
 #[derive(Debug, Clone)]
@@ -69,7 +69,7 @@ impl SleepCommandQueue {
     }
 
     /// キューからスリープコマンドを処理
-    pub fn process_queue(&mut self, esp_now_sender: &EspNowSender) -> bool {
+    pub fn process_queue(&mut self, esp_now_sender: &mut EspNowSender) -> bool {
         let current_time = self.get_current_time_ms();
         
         // 送信間隔チェック
@@ -129,64 +129,6 @@ impl SleepCommandQueue {
 
     /// 現在時刻を取得（ミリ秒）
     fn get_current_time_ms(&self) -> u64 {
-        unsafe {
-            esp_idf_svc::sys::xTaskGetTickCount() as u64 * 1000 / esp_idf_svc::sys::configTICK_RATE_HZ as u64
-        }
-    }
-}
-
-/// グローバルスリープコマンドキュー
-static mut SLEEP_QUEUE: Option<SleepCommandQueue> = None;
-
-/// グローバルキューを初期化
-pub fn init_sleep_command_queue() {
-    unsafe {
-        SLEEP_QUEUE = Some(SleepCommandQueue::new());
-    }
-    info!("Sleep command queue initialized");
-}
-
-/// スリープコマンドをグローバルキューに追加
-pub fn enqueue_sleep_command(mac_address: String, sleep_seconds: u32) -> Result<(), &'static str> {
-    unsafe {
-        if let Some(queue) = &mut SLEEP_QUEUE {
-            queue.enqueue(mac_address, sleep_seconds)
-        } else {
-            error!("Sleep command queue not initialized");
-            Err("Queue not initialized")
-        }
-    }
-}
-
-/// グローバルキューを処理
-pub fn process_sleep_command_queue(esp_now_sender: &EspNowSender) -> bool {
-    unsafe {
-        if let Some(queue) = &mut SLEEP_QUEUE {
-            queue.process_queue(esp_now_sender)
-        } else {
-            false
-        }
-    }
-}
-
-/// キューが空かどうか確認
-pub fn is_sleep_command_queue_empty() -> bool {
-    unsafe {
-        if let Some(queue) = &SLEEP_QUEUE {
-            queue.is_empty()
-        } else {
-            true
-        }
-    }
-}
-
-/// キューのサイズを取得
-pub fn get_sleep_command_queue_len() -> usize {
-    unsafe {
-        if let Some(queue) = &SLEEP_QUEUE {
-            queue.len()
-        } else {
-            0
-        }
+        crate::sys_wrappers::esp::tick_ms()
     }
 }
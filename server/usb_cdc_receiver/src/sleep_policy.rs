@@ -0,0 +1,180 @@
+//! スリープコマンドの妥当性チェック（デバイスごとの許容範囲）
+//!
+//! 入力ミス（例: 60秒のつもりで `60000`）でカメラが長時間オフラインになるのを防ぐため、
+//! cfg.toml のカメラ登録ごとに許容範囲を持ち、範囲外のスリープ時間は拒否または範囲内へ補正します。
+//! 未登録のデバイスにはコマンド解析と同じ全体の範囲（`MIN_SLEEP_SECONDS`〜`MAX_SLEEP_SECONDS`）を適用します。
+
+use crate::command::{ERROR_RESPONSE_PREFIX, MAX_SLEEP_SECONDS, MIN_SLEEP_SECONDS};
+use crate::mac_address::format_mac_address;
+
+/// 範囲外の値を補正する指定（`"60-7200:clamp"`）
+const CLAMP_SUFFIX: &str = ":clamp";
+
+/// デバイスごとのスリープ時間の許容範囲
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SleepPolicy {
+    /// 最小値（秒）
+    pub min_seconds: u32,
+    /// 最大値（秒）
+    pub max_seconds: u32,
+    /// 範囲外の値を拒否せず範囲内へ補正する
+    pub clamp: bool,
+}
+
+impl Default for SleepPolicy {
+    fn default() -> Self {
+        Self {
+            min_seconds: MIN_SLEEP_SECONDS,
+            max_seconds: MAX_SLEEP_SECONDS,
+            clamp: false,
+        }
+    }
+}
+
+/// 許容範囲外のスリープコマンド
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SleepPolicyViolation {
+    pub mac: [u8; 6],
+    pub requested: u32,
+    pub min_seconds: u32,
+    pub max_seconds: u32,
+}
+
+impl std::fmt::Display for SleepPolicyViolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "sleep time {}s for {} is outside the allowed range {}-{}s",
+            self.requested,
+            format_mac_address(&self.mac),
+            self.min_seconds,
+            self.max_seconds
+        )
+    }
+}
+
+impl std::error::Error for SleepPolicyViolation {}
+
+impl SleepPolicyViolation {
+    /// USBへ返すエラー応答行を生成します
+    pub fn to_response(&self) -> String {
+        format!("{}{}\n", ERROR_RESPONSE_PREFIX, self)
+    }
+}
+
+impl SleepPolicy {
+    /// `"MIN-MAX"` または `"MIN-MAX:clamp"` 形式の設定値を解析します（空文字列は既定値）
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let spec = spec.trim();
+        if spec.is_empty() {
+            return Ok(Self::default());
+        }
+        let (range, clamp) = match spec.strip_suffix(CLAMP_SUFFIX) {
+            Some(range) => (range, true),
+            None => (spec, false),
+        };
+        let (min, max) = range
+            .split_once('-')
+            .ok_or_else(|| format!("expected MIN-MAX[:clamp], got '{}'", spec))?;
+        let min_seconds = min.trim().parse::<u32>().map_err(|_| format!("invalid minimum '{}'", min))?;
+        let max_seconds = max.trim().parse::<u32>().map_err(|_| format!("invalid maximum '{}'", max))?;
+        if min_seconds < MIN_SLEEP_SECONDS || max_seconds > MAX_SLEEP_SECONDS || min_seconds > max_seconds {
+            return Err(format!(
+                "range {}-{} must lie within {}-{}",
+                min_seconds, max_seconds, MIN_SLEEP_SECONDS, MAX_SLEEP_SECONDS
+            ));
+        }
+        Ok(Self {
+            min_seconds,
+            max_seconds,
+            clamp,
+        })
+    }
+
+    /// スリープ時間をチェックし、送信する値を返します
+    ///
+    /// 補正が有効な場合は範囲内へ丸めた値、無効な場合は範囲外をエラーとします。
+    pub fn apply(&self, mac: [u8; 6], requested: u32) -> Result<u32, SleepPolicyViolation> {
+        if (self.min_seconds..=self.max_seconds).contains(&requested) {
+            Ok(requested)
+        } else if self.clamp {
+            Ok(requested.clamp(self.min_seconds, self.max_seconds))
+        } else {
+            Err(SleepPolicyViolation {
+                mac,
+                requested,
+                min_seconds: self.min_seconds,
+                max_seconds: self.max_seconds,
+            })
+        }
+    }
+}
+
+/// 登録済みデバイスの許容範囲
+#[derive(Debug, Clone, Default)]
+pub struct SleepPolicyRegistry {
+    policies: Vec<([u8; 6], SleepPolicy)>,
+}
+
+impl SleepPolicyRegistry {
+    /// デバイスごとの許容範囲から作成
+    pub fn new(policies: Vec<([u8; 6], SleepPolicy)>) -> Self {
+        Self { policies }
+    }
+
+    /// デバイスの許容範囲（未登録は既定値）
+    pub fn policy_for(&self, mac: &[u8; 6]) -> SleepPolicy {
+        self.policies
+            .iter()
+            .find(|(registered, _)| registered == mac)
+            .map(|(_, policy)| *policy)
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAC: [u8; 6] = [0x34, 0xab, 0x95, 0xfb, 0x3f, 0xc4];
+
+    #[test]
+    fn test_parse_policy_spec() {
+        assert_eq!(SleepPolicy::parse("").unwrap(), SleepPolicy::default());
+        assert_eq!(
+            SleepPolicy::parse("60-7200:clamp").unwrap(),
+            SleepPolicy {
+                min_seconds: 60,
+                max_seconds: 7200,
+                clamp: true,
+            }
+        );
+        assert!(!SleepPolicy::parse("60-7200").unwrap().clamp);
+        assert!(SleepPolicy::parse("7200-60").is_err());
+        assert!(SleepPolicy::parse("0-60").is_err());
+        assert!(SleepPolicy::parse("60-100000").is_err());
+        assert!(SleepPolicy::parse("60").is_err());
+    }
+
+    #[test]
+    fn test_apply_rejects_or_clamps_out_of_range() {
+        let reject = SleepPolicy::parse("60-7200").unwrap();
+        assert_eq!(reject.apply(MAC, 600), Ok(600));
+        let violation = reject.apply(MAC, 60000).unwrap_err();
+        assert_eq!(violation.requested, 60000);
+        assert!(violation.to_response().starts_with(ERROR_RESPONSE_PREFIX));
+        assert!(violation.to_response().contains("60-7200"));
+
+        let clamp = SleepPolicy::parse("60-7200:clamp").unwrap();
+        assert_eq!(clamp.apply(MAC, 60000), Ok(7200));
+        assert_eq!(clamp.apply(MAC, 5), Ok(60));
+    }
+
+    #[test]
+    fn test_registry_falls_back_to_default() {
+        let policy = SleepPolicy::parse("60-7200").unwrap();
+        let registry = SleepPolicyRegistry::new(vec![(MAC, policy)]);
+        assert_eq!(registry.policy_for(&MAC), policy);
+        assert_eq!(registry.policy_for(&[0; 6]), SleepPolicy::default());
+    }
+}
@@ -0,0 +1,204 @@
+//! ソークテスト（長時間の信頼性試験）の集計
+//!
+//! `soak-test` フィーチャーのデバイスは、HASHフレームにサイクル結果の累計
+//! （`SOAK`・`SOAK_OK`・`SOAK_ERR`・`HEAP_FREE`）を付加します。ゲートウェイはデバイスごとに最新の累計と
+//! 空きヒープの推移を保持し、`SOAK_REPORT` コマンドで1デバイス1行の `CMD_SOAK:` 応答として返します。
+
+use std::collections::BTreeMap;
+
+use crate::mac_address::format_mac_address;
+
+/// ソークテスト集計応答の接頭辞
+pub const SOAK_RESPONSE_PREFIX: &str = "CMD_SOAK:";
+
+/// HASHペイロードから解析したサイクル結果の累計
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SoakSample {
+    /// 記録したサイクル数
+    pub cycles: u32,
+    /// 成功したサイクル数
+    pub successes: u32,
+    /// 失敗の種類ごとの回数（`CAPTURE=1+SEND=2`）
+    pub errors: Vec<(String, u32)>,
+    /// 直近のサイクル終了時の空きヒープ（バイト）
+    pub free_heap: Option<u32>,
+}
+
+impl SoakSample {
+    /// HASHペイロードから解析します（`SOAK` がなければNone）
+    pub fn from_hash_payload(payload: &[u8]) -> Option<Self> {
+        let payload = std::str::from_utf8(payload).ok()?;
+        let field = |key: &str| {
+            payload
+                .split(',')
+                .find_map(|item| item.strip_prefix(key)?.strip_prefix(':'))
+                .map(str::trim)
+        };
+        let errors = field("SOAK_ERR")
+            .unwrap_or_default()
+            .split('+')
+            .filter_map(|item| {
+                let (kind, count) = item.split_once('=')?;
+                Some((kind.to_string(), count.parse().ok()?))
+            })
+            .collect();
+        Some(Self {
+            cycles: field("SOAK")?.parse().ok()?,
+            successes: field("SOAK_OK").and_then(|value| value.parse().ok()).unwrap_or(0),
+            errors,
+            free_heap: field("HEAP_FREE").and_then(|value| value.parse().ok()),
+        })
+    }
+}
+
+/// デバイスごとのソークテストの集計
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SoakReport {
+    /// 最新の累計
+    pub latest: SoakSample,
+    /// 最初に報告された（サイクル数, 空きヒープ）
+    pub first_heap: Option<(u32, u32)>,
+    /// 空きヒープの最小値
+    pub min_heap: Option<u32>,
+}
+
+impl SoakReport {
+    fn new(sample: SoakSample) -> Self {
+        Self {
+            first_heap: sample.free_heap.map(|heap| (sample.cycles, heap)),
+            min_heap: sample.free_heap,
+            latest: sample,
+        }
+    }
+
+    fn update(&mut self, sample: SoakSample) {
+        if let Some(heap) = sample.free_heap {
+            self.first_heap.get_or_insert((sample.cycles, heap));
+            self.min_heap = Some(self.min_heap.map_or(heap, |min| min.min(heap)));
+        }
+        self.latest = sample;
+    }
+
+    /// 成功率（%）
+    pub fn success_percent(&self) -> f32 {
+        if self.latest.cycles == 0 {
+            return 0.0;
+        }
+        self.latest.successes as f32 * 100.0 / self.latest.cycles as f32
+    }
+
+    /// 空きヒープの1サイクルあたりの変化（バイト、負はリークの疑い）
+    pub fn heap_slope_per_cycle(&self) -> Option<f32> {
+        let (first_cycle, first_heap) = self.first_heap?;
+        let last_heap = self.latest.free_heap?;
+        let cycles = self.latest.cycles.checked_sub(first_cycle).filter(|&cycles| cycles > 0)?;
+        Some((last_heap as f32 - first_heap as f32) / cycles as f32)
+    }
+
+    /// 応答用の表現（`CYCLES=..,OK=..,SUCCESS_PCT=..,ERR=..,HEAP_FIRST=..,HEAP_MIN=..,HEAP_LAST=..,HEAP_SLOPE=..`）
+    pub fn summary(&self) -> String {
+        let errors = if self.latest.errors.is_empty() {
+            "-".to_string()
+        } else {
+            self.latest
+                .errors
+                .iter()
+                .map(|(kind, count)| format!("{}:{}", kind, count))
+                .collect::<Vec<_>>()
+                .join("+")
+        };
+        let optional = |value: Option<String>| value.unwrap_or_else(|| "-".to_string());
+        format!(
+            "CYCLES={},OK={},SUCCESS_PCT={:.1},ERR={},HEAP_FIRST={},HEAP_MIN={},HEAP_LAST={},HEAP_SLOPE={}",
+            self.latest.cycles,
+            self.latest.successes,
+            self.success_percent(),
+            errors,
+            optional(self.first_heap.map(|(_, heap)| heap.to_string())),
+            optional(self.min_heap.map(|heap| heap.to_string())),
+            optional(self.latest.free_heap.map(|heap| heap.to_string())),
+            optional(self.heap_slope_per_cycle().map(|slope| format!("{:.1}", slope))),
+        )
+    }
+}
+
+/// 送信元ごとのソークテストの集計
+#[derive(Debug, Default)]
+pub struct SoakReports {
+    devices: BTreeMap<[u8; 6], SoakReport>,
+}
+
+impl SoakReports {
+    /// 空の集計を作成します
+    pub const fn new() -> Self {
+        Self { devices: BTreeMap::new() }
+    }
+
+    /// 累計を記録します（サイクル数が減った場合はデバイスが再起動したとみなして集計し直す）
+    pub fn record(&mut self, mac: [u8; 6], sample: SoakSample) {
+        match self.devices.get_mut(&mac) {
+            Some(report) if sample.cycles >= report.latest.cycles => report.update(sample),
+            _ => {
+                self.devices.insert(mac, SoakReport::new(sample));
+            }
+        }
+    }
+
+    /// 送信元の集計
+    pub fn get(&self, mac: &[u8; 6]) -> Option<&SoakReport> {
+        self.devices.get(mac)
+    }
+
+    /// `SOAK_REPORT` への応答（1デバイス1行、記録がなければ空の1行）
+    pub fn response(&self) -> String {
+        if self.devices.is_empty() {
+            return format!("{}\n", SOAK_RESPONSE_PREFIX);
+        }
+        self.devices
+            .iter()
+            .map(|(mac, report)| format!("{}{},{}\n", SOAK_RESPONSE_PREFIX, format_mac_address(mac), report.summary()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAC: [u8; 6] = [0x34, 0xab, 0x95, 0xfb, 0x3f, 0xc4];
+
+    fn sample(payload: &str) -> SoakSample {
+        SoakSample::from_hash_payload(payload.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn test_parse_soak_fields() {
+        let parsed = sample("HASH:ab,VOLT:80,SOAK:12,SOAK_OK:10,SOAK_ERR:CAPTURE=0+PROBE=1+SEND=1,HEAP_FREE:120000");
+        assert_eq!(parsed.cycles, 12);
+        assert_eq!(parsed.successes, 10);
+        assert_eq!(parsed.errors[2], ("SEND".to_string(), 1));
+        assert_eq!(parsed.free_heap, Some(120_000));
+        assert_eq!(SoakSample::from_hash_payload(b"HASH:ab,VOLT:80"), None);
+    }
+
+    #[test]
+    fn test_report_tracks_heap_trend_and_restarts() {
+        let mut reports = SoakReports::new();
+        assert_eq!(reports.response(), "CMD_SOAK:\n");
+        reports.record(MAC, sample("SOAK:1,SOAK_OK:1,SOAK_ERR:CAPTURE=0+PROBE=0+SEND=0,HEAP_FREE:120000"));
+        reports.record(MAC, sample("SOAK:5,SOAK_OK:4,SOAK_ERR:CAPTURE=0+PROBE=0+SEND=1,HEAP_FREE:110000"));
+        reports.record(MAC, sample("SOAK:11,SOAK_OK:9,SOAK_ERR:CAPTURE=1+PROBE=0+SEND=1,HEAP_FREE:115000"));
+        assert_eq!(
+            reports.response(),
+            "CMD_SOAK:34:ab:95:fb:3f:c4,CYCLES=11,OK=9,SUCCESS_PCT=81.8,ERR=CAPTURE:1+PROBE:0+SEND:1,\
+             HEAP_FIRST=120000,HEAP_MIN=110000,HEAP_LAST=115000,HEAP_SLOPE=-500.0\n"
+        );
+
+        // サイクル数が戻った（再起動した）場合は集計し直す
+        reports.record(MAC, sample("SOAK:1,SOAK_OK:0,SOAK_ERR:CAPTURE=1+PROBE=0+SEND=0"));
+        let report = reports.get(&MAC).unwrap();
+        assert_eq!(report.latest.cycles, 1);
+        assert_eq!(report.heap_slope_per_cycle(), None);
+        assert!(report.summary().ends_with("HEAP_FIRST=-,HEAP_MIN=-,HEAP_LAST=-,HEAP_SLOPE=-"));
+    }
+}
@@ -0,0 +1,79 @@
+/// ゲートウェイ統計フレーム
+///
+/// メンテナンスタスクが定期的に収集した統計を `KEY:値` のカンマ区切りで
+/// ペイロードにまとめ、STATSフレームとしてUSBへ送出します。
+/// 送信元MACにはゲートウェイ自身を表す `GATEWAY_STATS_MAC` を使用します。
+/// 項目は追加時に1つのバッファへ直接書き込み、項目ごとの文字列確保を避けます。
+use std::fmt::{Display, Write};
+
+use crate::esp_now::frame::create_frame;
+use crate::esp_now::FrameType;
+
+/// STATSフレームの送信元MAC（ゲートウェイ自身）
+pub const GATEWAY_STATS_MAC: [u8; 6] = [0; 6];
+
+/// ペイロードのバッファの初期容量（通常の統計フレームが再確保なしで収まる大きさ）
+const PAYLOAD_CAPACITY: usize = 512;
+
+/// 統計フレームの内容
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StatsReport {
+    /// `KEY:値,KEY:値,...` 形式のペイロード
+    payload: String,
+}
+
+impl StatsReport {
+    /// 空のレポートを作成
+    pub fn new() -> Self {
+        Self {
+            payload: String::with_capacity(PAYLOAD_CAPACITY),
+        }
+    }
+
+    /// 項目を追加
+    pub fn push(&mut self, key: &'static str, value: impl Display) {
+        if !self.payload.is_empty() {
+            self.payload.push(',');
+        }
+        // Stringへの書き込みは失敗しない
+        let _ = write!(self.payload, "{}:{}", key, value);
+    }
+
+    /// ペイロードを生成します
+    ///
+    /// 形式: `KEY:値,KEY:値,...`
+    pub fn to_payload(&self) -> Vec<u8> {
+        self.payload.as_bytes().to_vec()
+    }
+
+    /// USBへ送出するフレームを生成します
+    pub fn to_frame(&self, sequence_number: u32) -> Vec<u8> {
+        create_frame(
+            GATEWAY_STATS_MAC,
+            self.payload.as_bytes(),
+            FrameType::Stats,
+            sequence_number,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::esp_now::frame::Frame;
+
+    #[test]
+    fn test_stats_report_frame() {
+        let mut report = StatsReport::new();
+        report.push("DATA_Q", "3/32");
+        report.push("CTRL_SEND_AVG_MS", 12);
+
+        assert_eq!(report.to_payload(), b"DATA_Q:3/32,CTRL_SEND_AVG_MS:12".to_vec());
+
+        let (frame, _) = Frame::from_bytes(&report.to_frame(5)).unwrap();
+        assert_eq!(frame.frame_type(), FrameType::Stats);
+        assert_eq!(frame.mac_address(), &GATEWAY_STATS_MAC);
+        assert_eq!(frame.sequence_number(), 5);
+        assert_eq!(frame.data(), report.to_payload().as_slice());
+    }
+}
@@ -8,9 +8,14 @@
 /// - **循環利用**: 古いデータを上書きして継続的な処理を可能に
 /// - **スレッドセーフ**: Mutexによる排他制御
 /// - **バックプレッシャー対応**: バッファフル時の制御
+/// - **PSRAMオーバーフロー**: PSRAM搭載ボードでは内部RAMが満杯の間だけPSRAMへ退避
 
+use super::tier::{psram_overflow_capacity, BufferTier, Placement, TierUsage, DEFAULT_PSRAM_OVERFLOW_BYTES};
 use super::{StreamingError, StreamingResult};
-use log::{debug, warn};
+use crate::queue::ReceivedData;
+use log::{debug, info, warn};
+use std::collections::VecDeque;
+use std::time::Instant;
 
 /// ストリーミングバッファの容量定数
 pub const STREAMING_BUFFER_SIZE: usize = 512; // 512バイトに削減（メモリ制約のため）
@@ -24,6 +29,14 @@ pub struct BufferedData {
     pub timestamp: u64,
     /// 送信元MACアドレス
     pub source_mac: [u8; 6],
+    /// キュー投入時の中断世代（`ReceivedData::epoch`）
+    pub epoch: u32,
+    /// ESP-NOW受信時刻（`ReceivedData::received_at`）
+    pub received_at: Instant,
+    /// 受信信号強度（dBm、取得できない場合はNone）
+    pub rssi: Option<i8>,
+    /// 緊急フレーム
+    pub urgent: bool,
 }
 
 impl BufferedData {
@@ -39,8 +52,35 @@ impl BufferedData {
             data: vec,
             timestamp: get_current_timestamp(),
             source_mac,
+            epoch: 0,
+            received_at: Instant::now(),
+            rssi: None,
+            urgent: false,
         })
     }
+
+    /// データキューの受信データから作成（受信時刻などの付随情報を引き継ぐ）
+    pub fn from_received(received: &ReceivedData) -> StreamingResult<Self> {
+        Ok(BufferedData {
+            epoch: received.epoch,
+            received_at: received.received_at,
+            rssi: received.rssi,
+            urgent: received.urgent,
+            ..Self::new(&received.data, received.mac)?
+        })
+    }
+
+    /// データキューの受信データへ戻す
+    pub fn into_received(self) -> ReceivedData {
+        ReceivedData {
+            mac: self.source_mac,
+            data: self.data.to_vec(),
+            epoch: self.epoch,
+            received_at: self.received_at,
+            rssi: self.rssi,
+            urgent: self.urgent,
+        }
+    }
     
     /// データサイズを取得
     pub fn len(&self) -> usize {
@@ -63,81 +103,162 @@ impl BufferedData {
 /// 固定サイズのバッファを循環利用してメモリ効率的なストリーミング処理を実現
 #[derive(Debug)]
 pub struct StreamingBuffer {
-    /// バッファ
+    /// 内部RAMのバッファ（ホットデータ）
     buffer: heapless::Deque<BufferedData, 4>, // メモリ削減: 8→4個
-    /// バッファの最大サイズ
-    max_size: usize,
-    /// 現在の使用サイズ
-    current_size: usize,
+    /// PSRAMのオーバーフロー領域（PSRAMなしの場合は容量0）
+    overflow: VecDeque<BufferedData>,
+    /// 階層ごとの使用状況
+    tiers: TierUsage,
     /// ドロップされたデータ数（統計用）
     dropped_count: u64,
 }
 
 impl StreamingBuffer {
-    /// 新しいストリーミングバッファを作成
+    /// 新しいストリーミングバッファを作成（内部RAMのみ）
     pub fn new() -> Self {
         StreamingBuffer {
             buffer: heapless::Deque::new(),
-            max_size: STREAMING_BUFFER_SIZE,
-            current_size: 0,
+            overflow: VecDeque::new(),
+            tiers: TierUsage::internal_only(STREAMING_BUFFER_SIZE),
             dropped_count: 0,
         }
     }
+
+    /// PSRAMを検出し、搭載されていればオーバーフロー領域を持つバッファを作成
+    ///
+    /// オーバーフロー領域は起動時に一括確保します。`CONFIG_SPIRAM_USE_MALLOC` が有効なら
+    /// `CONFIG_SPIRAM_MALLOC_ALWAYSINTERNAL` を超える確保はPSRAMに配置されます。
+    pub fn with_detected_psram() -> Self {
+        let psram_free = psram_free_bytes();
+        let capacity = psram_overflow_capacity(psram_free, DEFAULT_PSRAM_OVERFLOW_BYTES);
+        let slots = capacity / std::mem::size_of::<BufferedData>();
+        let mut buffer = Self::new();
+        if slots == 0 {
+            info!("StreamingBuffer: PSRAM not available, using internal RAM only");
+            return buffer;
+        }
+        buffer.overflow = VecDeque::with_capacity(slots);
+        buffer.tiers.psram_capacity = capacity;
+        info!(
+            "StreamingBuffer: PSRAM overflow tier enabled ({} bytes, {} slots, PSRAM free {} bytes)",
+            capacity, slots, psram_free
+        );
+        buffer
+    }
     
     /// バッファにデータを追加
     /// 
-    /// バッファが満杯の場合、最も古いデータを削除してから新しいデータを追加
+    /// 内部RAMが満杯ならPSRAMへ退避し、両方とも満杯の場合は最も古いデータを削除してから追加
     pub fn push(&mut self, data: BufferedData) -> StreamingResult<()> {
-        // バッファサイズ制限をチェック
-        if self.current_size + data.len() > self.max_size {
-            // 古いデータを削除してスペースを確保
-            while !self.buffer.is_empty() && 
-                  self.current_size + data.len() > self.max_size {
-                if let Some(old_data) = self.buffer.pop_front() {
-                    self.current_size -= old_data.len();
+        let data_size = data.len();
+        let tier = loop {
+            match self.placement(data_size) {
+                Placement::Store(tier) => break tier,
+                Placement::EvictOldest => {
+                    let Some(old_data) = self.pop() else {
+                        return Err(StreamingError::BufferFull);
+                    };
                     self.dropped_count += 1;
                     warn!("Buffer full: dropped {} bytes from {:02X?}", 
                           old_data.len(), old_data.source_mac);
                 }
             }
+        };
+
+        self.store(tier, data)
+    }
+
+    /// バッファにデータを追加（両方とも満杯なら古いデータを破棄せず、`BufferFull` を返す）
+    pub fn try_push(&mut self, data: BufferedData) -> StreamingResult<()> {
+        match self.placement(data.len()) {
+            Placement::Store(tier) => self.store(tier, data),
+            Placement::EvictOldest => Err(StreamingError::BufferFull),
         }
-        
-        // 新しいデータを追加
+    }
+
+    /// 指定の階層へ追加
+    fn store(&mut self, tier: BufferTier, data: BufferedData) -> StreamingResult<()> {
         let data_size = data.len();
-        self.buffer.push_back(data).map_err(|_| StreamingError::BufferFull)?;
-        self.current_size += data_size;
+        match tier {
+            BufferTier::Internal => {
+                self.buffer.push_back(data).map_err(|_| StreamingError::BufferFull)?;
+            }
+            BufferTier::Psram => self.overflow.push_back(data),
+        }
+        self.tiers.record_store(tier, data_size);
         
-        debug!("Buffer: added {} bytes, total size: {}/{}", 
-               data_size, self.current_size, self.max_size);
+        debug!("Buffer: added {} bytes to {:?}, total size: {}/{}", 
+               data_size, tier, self.tiers.bytes_used(), self.tiers.bytes_total());
         
         Ok(())
     }
+
+    /// 配置先を決定（内部RAMの要素数上限とPSRAMのスロット数も考慮）
+    fn placement(&self, len: usize) -> Placement {
+        match self.tiers.place(len) {
+            Placement::Store(BufferTier::Internal) if self.buffer.is_full() => {
+                if self.tiers.has_psram() && self.overflow.len() < self.overflow.capacity() {
+                    Placement::Store(BufferTier::Psram)
+                } else {
+                    Placement::EvictOldest
+                }
+            }
+            Placement::Store(BufferTier::Psram) if self.overflow.len() >= self.overflow.capacity() => {
+                Placement::EvictOldest
+            }
+            placement => placement,
+        }
+    }
     
-    /// バッファからデータを取得
+    /// バッファからデータを取得（内部RAMに空きができたらPSRAMのデータを昇格）
     pub fn pop(&mut self) -> Option<BufferedData> {
-        if let Some(data) = self.buffer.pop_front() {
-            self.current_size -= data.len();
-            debug!("Buffer: removed {} bytes, remaining size: {}", 
-                   data.len(), self.current_size);
-            Some(data)
-        } else {
-            None
+        let data = match self.buffer.pop_front() {
+            Some(data) => {
+                self.tiers.record_remove(BufferTier::Internal, data.len());
+                data
+            }
+            None => {
+                let data = self.overflow.pop_front()?;
+                self.tiers.record_remove(BufferTier::Psram, data.len());
+                data
+            }
+        };
+        self.promote();
+        debug!("Buffer: removed {} bytes, remaining size: {}", 
+               data.len(), self.tiers.bytes_used());
+        Some(data)
+    }
+
+    /// PSRAMの古いデータから順に内部RAMへ戻す
+    fn promote(&mut self) {
+        while let Some(front) = self.overflow.front() {
+            if self.buffer.is_full() || !self.tiers.can_promote(front.len()) {
+                break;
+            }
+            let Some(data) = self.overflow.pop_front() else {
+                break;
+            };
+            let len = data.len();
+            if self.buffer.push_back(data).is_err() {
+                break;
+            }
+            self.tiers.record_promotion(len);
         }
     }
     
     /// バッファが空かどうか確認
     pub fn is_empty(&self) -> bool {
-        self.buffer.is_empty()
+        self.buffer.is_empty() && self.overflow.is_empty()
     }
     
     /// バッファの使用状況を取得
     pub fn usage(&self) -> (usize, usize) {
-        (self.current_size, self.max_size)
+        (self.tiers.bytes_used(), self.tiers.bytes_total())
     }
     
     /// バッファ内のアイテム数を取得
     pub fn len(&self) -> usize {
-        self.buffer.len()
+        self.buffer.len() + self.overflow.len()
     }
     
     /// ドロップされたデータ数を取得
@@ -152,10 +273,10 @@ impl StreamingBuffer {
         let current_time = get_current_timestamp();
         let mut removed_count = 0;
         
-        while let Some(front_data) = self.buffer.front() {
-            if current_time - front_data.timestamp > timeout_ms {
-                if let Some(old_data) = self.buffer.pop_front() {
-                    self.current_size -= old_data.len();
+        // 内部RAM→PSRAMの順で受信順に並んでいるため、先頭から確認すればよい
+        while let Some(front_data) = self.buffer.front().or_else(|| self.overflow.front()) {
+            if current_time.saturating_sub(front_data.timestamp) > timeout_ms {
+                if let Some(old_data) = self.pop() {
                     removed_count += 1;
                     debug!("Cleaned up old data: {} bytes from {:02X?}", 
                            old_data.len(), old_data.source_mac);
@@ -176,17 +297,20 @@ impl StreamingBuffer {
     pub fn stats(&self) -> BufferStats {
         BufferStats {
             items: self.len(),
-            bytes_used: self.current_size,
-            bytes_total: self.max_size,
+            bytes_used: self.tiers.bytes_used(),
+            bytes_total: self.tiers.bytes_total(),
             dropped_count: self.dropped_count,
-            usage_percent: (self.current_size as f32 / self.max_size as f32) * 100.0,
+            usage_percent: (self.tiers.bytes_used() as f32 / self.tiers.bytes_total() as f32) * 100.0,
+            tiers: self.tiers,
         }
     }
     
     /// バッファをクリア
     pub fn clear(&mut self) {
         self.buffer.clear();
-        self.current_size = 0;
+        self.overflow.clear();
+        self.tiers.record_remove(BufferTier::Internal, self.tiers.internal_bytes);
+        self.tiers.record_remove(BufferTier::Psram, self.tiers.psram_bytes);
         debug!("Buffer cleared");
     }
 }
@@ -210,6 +334,8 @@ pub struct BufferStats {
     pub dropped_count: u64,
     /// 使用率（パーセンテージ）
     pub usage_percent: f32,
+    /// 階層（内部RAM / PSRAM）ごとの使用状況と昇格・退避回数
+    pub tiers: TierUsage,
 }
 
 impl BufferStats {
@@ -224,15 +350,15 @@ impl BufferStats {
     }
 }
 
+/// PSRAMの空き容量（バイト、PSRAMなしは0）
+fn psram_free_bytes() -> usize {
+    crate::sys_wrappers::esp::free_psram()
+}
+
 /// 現在のタイムスタンプを取得（ミリ秒）
 fn get_current_timestamp() -> u64 {
-    // FreeRTOSのシステムティック（より安全）
-    unsafe {
-        // FreeRTOS tick count を使用（WDTリセットを避けるため）
-        let ticks = esp_idf_sys::xTaskGetTickCount();
-        // tick を ミリ秒に変換 (通常 1 tick = 1ms)
-        ticks as u64
-    }
+    // FreeRTOSのシステムティック（WDTリセットを避けるため）
+    crate::sys_wrappers::esp::tick_ms()
 }
 
 #[cfg(test)]
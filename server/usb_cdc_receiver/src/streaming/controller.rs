@@ -12,10 +12,13 @@
 
 use super::{StreamingError, StreamingResult, StreamingStatistics};
 use super::device_manager::{DeviceStreamManager, ProcessedFrame, StreamManagerConfig};
+use super::latency::LatencyHistogram;
 use crate::usb::cdc::UsbCdc;
 use crate::usb::UsbInterface;
+use crate::esp_now::outbound::OutboundKind;
 use crate::esp_now::sender::EspNowSender;
 use crate::esp_now::{AckMessage, MessageType, AckStatus};
+use crate::shutdown::{Shutdown, ShutdownReason};
 use log::{debug, info, warn, error};
 
 /// ストリーミング設定
@@ -65,6 +68,9 @@ pub struct StreamingStats {
     /// 処理時間統計
     pub total_processing_time_ms: u64,
     pub max_processing_time_ms: u64,
+    /// 処理時間・USB転送時間の分布（p50/p95/p99用）
+    pub processing_latency: LatencyHistogram,
+    pub usb_transfer_latency: LatencyHistogram,
     /// ACK送信統計
     pub acks_sent: u64,
     pub ack_errors: u64,
@@ -111,6 +117,12 @@ impl StreamingStats {
         if time_ms > self.max_processing_time_ms {
             self.max_processing_time_ms = time_ms;
         }
+        self.processing_latency.record(time_ms.min(u32::MAX as u64) as u32);
+    }
+
+    /// USB転送時間を記録（リトライを含む1フレーム分）
+    pub fn record_usb_transfer_time(&mut self, time_ms: u64) {
+        self.usb_transfer_latency.record(time_ms.min(u32::MAX as u64) as u32);
     }
     
     /// ACK送信成功をカウント
@@ -228,7 +240,10 @@ impl StreamingController {
 
         // 処理されたフレームを即座にUSB CDCに転送
         for frame in &processed_frames {
-            match self.transfer_frame_to_usb(&frame, usb_cdc) {
+            let transfer_start = get_current_timestamp();
+            let transfer_result = self.transfer_frame_to_usb(&frame, usb_cdc);
+            self.stats.record_usb_transfer_time(get_current_timestamp() - transfer_start);
+            match transfer_result {
                 Ok(bytes_sent) => {
                     total_transferred += bytes_sent;
                     self.stats.count_usb_transfer(bytes_sent);
@@ -268,14 +283,14 @@ impl StreamingController {
         let ack = AckMessage::new(frame.sequence, acked_message_type, status);
         let ack_data = ack.serialize();
         
-        match self.esp_now_sender.send_data(mac_address, &ack_data) {
+        match self.esp_now_sender.send_control(mac_address, OutboundKind::Ack, &ack_data) {
             Ok(()) => {
-                info!("✓ ACK sent successfully for frame seq {} to {:02X?} (status: {:?})", 
+                info!("✓ ACK delivered for frame seq {} to {:02X?} (status: {:?})", 
                       frame.sequence, mac_address, status);
                 self.stats.count_ack_sent();
             }
             Err(e) => {
-                warn!("✗ Failed to send ACK for frame seq {} to {:02X?}: {:?}", 
+                warn!("✗ ACK not delivered for frame seq {} to {:02X?}: {:?}", 
                       frame.sequence, mac_address, e);
                 self.stats.count_ack_error();
             }
@@ -399,6 +414,10 @@ impl StreamingController {
         
         info!("Average processing time: {:.2}ms", self.stats.average_processing_time_ms());
         info!("Max processing time: {}ms", self.stats.max_processing_time_ms);
+        let processing = self.stats.processing_latency.percentiles();
+        let usb = self.stats.usb_transfer_latency.percentiles();
+        info!("Processing time p50/p95/p99: {}/{}/{}ms", processing.p50_ms, processing.p95_ms, processing.p99_ms);
+        info!("USB transfer time p50/p95/p99: {}/{}/{}ms", usb.p50_ms, usb.p95_ms, usb.p99_ms);
         
         if global_stats.frames_error > 0 {
             warn!("Frame errors: {} (Checksum: {})",
@@ -469,6 +488,14 @@ impl StreamingController {
     }
 }
 
+/// 再起動前にデバイスごとのバッファを解放する（`ShutdownStage::Drain` で登録する）
+impl Shutdown for StreamingController {
+    fn shutdown(&mut self, _reason: ShutdownReason) -> Result<(), String> {
+        self.force_cleanup();
+        Ok(())
+    }
+}
+
 impl Default for StreamingController {
     fn default() -> Self {
         Self::new(StreamingConfig::default())
@@ -477,13 +504,8 @@ impl Default for StreamingController {
 
 /// 現在のタイムスタンプを取得（ミリ秒）
 fn get_current_timestamp() -> u64 {
-    // FreeRTOSのシステムティック（より安全）
-    unsafe {
-        // FreeRTOS tick count を使用（WDTリセットを避けるため）
-        let ticks = esp_idf_sys::xTaskGetTickCount();
-        // tick を ミリ秒に変換 (通常 1 tick = 1ms)
-        ticks as u64
-    }
+    // FreeRTOSのシステムティック（WDTリセットを避けるため）
+    crate::sys_wrappers::esp::tick_ms()
 }
 
 #[cfg(test)]
@@ -516,6 +538,10 @@ mod tests {
         stats.record_processing_time(10);
         stats.record_processing_time(20);
         assert_eq!(stats.max_processing_time_ms, 20);
+        assert_eq!(stats.processing_latency.count(), 2);
+        assert_eq!(stats.processing_latency.percentiles().p99_ms, 20);
+        stats.record_usb_transfer_time(3);
+        assert_eq!(stats.usb_transfer_latency.percentiles().p50_ms, 3);
         
         // 基本統計との連携が必要な場合
         stats.basic.count_frame_processed(50);
@@ -0,0 +1,208 @@
+//! 固定バケットのレイテンシヒストグラム
+//!
+//! 平均値では送信側のタイムアウトにつながる裾の遅延が見えないため、
+//! 処理時間をバケットに集計して p50/p95/p99 を求めます。
+//! メモリ確保を行わず、値はバケット上限（ミリ秒）で近似します。
+
+/// バケットの上限（ミリ秒、この値以下を含む）。最後のバケットの後ろにオーバーフロー用のバケットを持つ
+pub const LATENCY_BUCKET_BOUNDS_MS: [u32; 12] = [1, 2, 5, 10, 20, 50, 100, 200, 500, 1000, 2000, 5000];
+
+/// バケット数（オーバーフローを含む）
+const BUCKET_COUNT: usize = LATENCY_BUCKET_BOUNDS_MS.len() + 1;
+
+/// パーセンタイル値（ミリ秒）
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LatencyPercentiles {
+    pub p50_ms: u32,
+    pub p95_ms: u32,
+    pub p99_ms: u32,
+}
+
+/// レイテンシヒストグラム
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LatencyHistogram {
+    buckets: [u32; BUCKET_COUNT],
+    count: u32,
+    max_ms: u32,
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LatencyHistogram {
+    /// 空のヒストグラムを作成
+    pub const fn new() -> Self {
+        Self {
+            buckets: [0; BUCKET_COUNT],
+            count: 0,
+            max_ms: 0,
+        }
+    }
+
+    /// 値が属するバケットの添字
+    pub fn bucket_index(value_ms: u32) -> usize {
+        LATENCY_BUCKET_BOUNDS_MS
+            .iter()
+            .position(|&bound| value_ms <= bound)
+            .unwrap_or(LATENCY_BUCKET_BOUNDS_MS.len())
+    }
+
+    /// 値を記録
+    pub fn record(&mut self, value_ms: u32) {
+        let index = Self::bucket_index(value_ms);
+        self.buckets[index] = self.buckets[index].saturating_add(1);
+        self.count = self.count.saturating_add(1);
+        self.max_ms = self.max_ms.max(value_ms);
+    }
+
+    /// 記録数
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    /// 最大値（ミリ秒）
+    pub fn max_ms(&self) -> u32 {
+        self.max_ms
+    }
+
+    /// パーセンタイル値（ミリ秒、記録がなければNone）
+    ///
+    /// 該当バケットの上限を返します。上限が最大値を超える場合やオーバーフローバケットでは最大値を返します。
+    pub fn percentile(&self, percent: u32) -> Option<u32> {
+        if self.count == 0 {
+            return None;
+        }
+        let percent = percent.clamp(1, 100) as u64;
+        // 順位（1始まり）= ceil(count * percent / 100)
+        let rank = (self.count as u64 * percent).div_ceil(100);
+        let mut cumulative = 0u64;
+        for (index, &bucket) in self.buckets.iter().enumerate() {
+            cumulative += bucket as u64;
+            if cumulative >= rank {
+                let upper = LATENCY_BUCKET_BOUNDS_MS.get(index).copied().unwrap_or(self.max_ms);
+                return Some(upper.min(self.max_ms));
+            }
+        }
+        Some(self.max_ms)
+    }
+
+    /// p50/p95/p99（記録がなければすべて0）
+    pub fn percentiles(&self) -> LatencyPercentiles {
+        LatencyPercentiles {
+            p50_ms: self.percentile(50).unwrap_or(0),
+            p95_ms: self.percentile(95).unwrap_or(0),
+            p99_ms: self.percentile(99).unwrap_or(0),
+        }
+    }
+
+    /// 記録をクリア
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+}
+
+/// USB送信タスクのレイテンシ（フレーム処理・USB転送）
+///
+/// フレーム処理はESP-NOW受信からUSB転送完了まで、USB転送は書き込み1回の所要時間です。
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EgressLatency {
+    /// フレーム処理時間
+    pub processing: LatencyHistogram,
+    /// USB転送時間
+    pub usb_transfer: LatencyHistogram,
+}
+
+impl EgressLatency {
+    /// 空の集計を作成
+    pub const fn new() -> Self {
+        Self {
+            processing: LatencyHistogram::new(),
+            usb_transfer: LatencyHistogram::new(),
+        }
+    }
+
+    /// 統計フレームに載せる項目
+    pub fn stats_fields(&self) -> [(&'static str, u32); 6] {
+        let processing = self.processing.percentiles();
+        let usb = self.usb_transfer.percentiles();
+        [
+            ("PROC_P50_MS", processing.p50_ms),
+            ("PROC_P95_MS", processing.p95_ms),
+            ("PROC_P99_MS", processing.p99_ms),
+            ("USB_P50_MS", usb.p50_ms),
+            ("USB_P95_MS", usb.p95_ms),
+            ("USB_P99_MS", usb.p99_ms),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bucket_index_boundaries() {
+        assert_eq!(LatencyHistogram::bucket_index(0), 0);
+        assert_eq!(LatencyHistogram::bucket_index(1), 0);
+        assert_eq!(LatencyHistogram::bucket_index(2), 1);
+        assert_eq!(LatencyHistogram::bucket_index(3), 2);
+        assert_eq!(LatencyHistogram::bucket_index(5000), 11);
+        assert_eq!(LatencyHistogram::bucket_index(5001), 12);
+        assert_eq!(LatencyHistogram::bucket_index(u32::MAX), 12);
+    }
+
+    #[test]
+    fn test_percentiles_follow_the_tail() {
+        let mut histogram = LatencyHistogram::new();
+        assert_eq!(histogram.percentile(50), None);
+        assert_eq!(histogram.percentiles(), LatencyPercentiles::default());
+
+        // 90件は3ms、9件は150ms、1件は800ms
+        for _ in 0..90 {
+            histogram.record(3);
+        }
+        for _ in 0..9 {
+            histogram.record(150);
+        }
+        histogram.record(800);
+
+        assert_eq!(histogram.count(), 100);
+        assert_eq!(histogram.max_ms(), 800);
+        assert_eq!(
+            histogram.percentiles(),
+            LatencyPercentiles {
+                p50_ms: 5,
+                p95_ms: 200,
+                p99_ms: 200,
+            }
+        );
+        assert_eq!(histogram.percentile(100), Some(800));
+    }
+
+    #[test]
+    fn test_percentile_capped_by_max_and_overflow() {
+        let mut histogram = LatencyHistogram::new();
+        histogram.record(12);
+        // バケット上限（20ms）ではなく実測の最大値
+        assert_eq!(histogram.percentile(50), Some(12));
+
+        histogram.record(9_000);
+        assert_eq!(histogram.percentile(99), Some(9_000));
+
+        histogram.reset();
+        assert_eq!(histogram.count(), 0);
+    }
+
+    #[test]
+    fn test_egress_latency_stats_fields() {
+        let mut latency = EgressLatency::new();
+        latency.processing.record(40);
+        latency.usb_transfer.record(2);
+        let fields = latency.stats_fields();
+        assert_eq!(fields[0], ("PROC_P50_MS", 40));
+        assert_eq!(fields[3], ("USB_P50_MS", 2));
+    }
+}
@@ -0,0 +1,168 @@
+//! 転送中を考慮したメンテナンスウィンドウ
+//!
+//! UXGAフレームなどの受信途中に統計フレームの送出などを実行すると、
+//! 転送のジッタが増えるため、受信途中の転送がある間は実行を延期します。
+//! ただし延期は `max_deferral_ms` までとし、それを超えた場合は転送中でも強制実行します。
+
+/// メンテナンス実行判定の結果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MaintenanceDecision {
+    /// 実行時刻に達していない
+    NotDue,
+    /// 転送中のため延期
+    Deferred,
+    /// 実行する
+    Run,
+    /// 延期の上限に達したため転送中でも実行する
+    ForcedRun,
+}
+
+impl MaintenanceDecision {
+    /// メンテナンスを実行すべきかどうか
+    pub fn should_run(self) -> bool {
+        matches!(self, MaintenanceDecision::Run | MaintenanceDecision::ForcedRun)
+    }
+}
+
+/// 延期に関する統計
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DeferralStats {
+    /// 延期が発生した周期の数
+    pub deferred: u64,
+    /// 延期上限により強制実行した回数
+    pub forced: u64,
+    /// 実行予定時刻からの最大遅延（ミリ秒）
+    pub max_delay_ms: u64,
+}
+
+impl DeferralStats {
+    /// 別のウィンドウの統計を合算します
+    pub fn merge(&mut self, other: DeferralStats) {
+        self.deferred += other.deferred;
+        self.forced += other.forced;
+        self.max_delay_ms = self.max_delay_ms.max(other.max_delay_ms);
+    }
+
+    /// 統計フレームの項目
+    pub fn stats_fields(&self) -> [(&'static str, u64); 3] {
+        [
+            ("MAINT_DEFERRED", self.deferred),
+            ("MAINT_FORCED", self.forced),
+            ("MAINT_DELAY_MS", self.max_delay_ms),
+        ]
+    }
+}
+
+/// 一定間隔で実行するメンテナンス処理の延期制御
+#[derive(Debug, Clone)]
+pub struct MaintenanceWindow {
+    interval_ms: u64,
+    max_deferral_ms: u64,
+    last_run: u64,
+    deferred_since: Option<u64>,
+    stats: DeferralStats,
+}
+
+impl MaintenanceWindow {
+    /// 新しいウィンドウを作成（`now` を最終実行時刻とする）
+    pub fn new(interval_ms: u64, max_deferral_ms: u64, now: u64) -> Self {
+        Self {
+            interval_ms,
+            max_deferral_ms,
+            last_run: now,
+            deferred_since: None,
+            stats: DeferralStats::default(),
+        }
+    }
+
+    /// 実行すべきか判定します
+    ///
+    /// `Run` / `ForcedRun` を返した場合は実行済みとして次の周期に進みます。
+    pub fn poll(&mut self, now: u64, transfer_active: bool) -> MaintenanceDecision {
+        if now.saturating_sub(self.last_run) <= self.interval_ms {
+            return MaintenanceDecision::NotDue;
+        }
+
+        let due_at = self.last_run.saturating_add(self.interval_ms);
+        if transfer_active {
+            let since = *self.deferred_since.get_or_insert_with(|| {
+                self.stats.deferred += 1;
+                now
+            });
+            if now.saturating_sub(since) < self.max_deferral_ms {
+                return MaintenanceDecision::Deferred;
+            }
+        }
+
+        let decision = if transfer_active {
+            self.stats.forced += 1;
+            MaintenanceDecision::ForcedRun
+        } else {
+            MaintenanceDecision::Run
+        };
+        self.stats.max_delay_ms = self.stats.max_delay_ms.max(now.saturating_sub(due_at));
+        self.last_run = now;
+        self.deferred_since = None;
+        decision
+    }
+
+    /// 延期統計を取得
+    pub fn stats(&self) -> DeferralStats {
+        self.stats
+    }
+
+    /// 延期統計をリセット
+    pub fn reset_stats(&mut self) {
+        self.stats = DeferralStats::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_runs_when_idle() {
+        let mut window = MaintenanceWindow::new(100, 50, 0);
+        assert_eq!(window.poll(100, false), MaintenanceDecision::NotDue);
+        assert_eq!(window.poll(101, false), MaintenanceDecision::Run);
+        assert_eq!(window.poll(150, false), MaintenanceDecision::NotDue);
+        assert_eq!(window.stats(), DeferralStats { deferred: 0, forced: 0, max_delay_ms: 1 });
+    }
+
+    #[test]
+    fn test_defers_during_transfer_until_idle() {
+        let mut window = MaintenanceWindow::new(100, 50, 0);
+        assert_eq!(window.poll(110, true), MaintenanceDecision::Deferred);
+        assert_eq!(window.poll(130, true), MaintenanceDecision::Deferred);
+        assert_eq!(window.poll(140, false), MaintenanceDecision::Run);
+
+        let stats = window.stats();
+        assert_eq!(stats.deferred, 1);
+        assert_eq!(stats.forced, 0);
+        assert_eq!(stats.max_delay_ms, 40);
+    }
+
+    #[test]
+    fn test_forced_after_max_deferral() {
+        let mut window = MaintenanceWindow::new(100, 50, 0);
+        assert_eq!(window.poll(110, true), MaintenanceDecision::Deferred);
+        assert_eq!(window.poll(159, true), MaintenanceDecision::Deferred);
+        assert_eq!(window.poll(160, true), MaintenanceDecision::ForcedRun);
+        assert!(MaintenanceDecision::ForcedRun.should_run());
+
+        // 次の周期は強制実行時刻から数える
+        assert_eq!(window.poll(260, true), MaintenanceDecision::NotDue);
+        assert_eq!(window.poll(261, true), MaintenanceDecision::Deferred);
+
+        let mut stats = window.stats();
+        assert_eq!(stats.deferred, 2);
+        assert_eq!(stats.forced, 1);
+
+        stats.merge(DeferralStats { deferred: 1, forced: 0, max_delay_ms: 90 });
+        assert_eq!(
+            stats.stats_fields(),
+            [("MAINT_DEFERRED", 3), ("MAINT_FORCED", 1), ("MAINT_DELAY_MS", 90)]
+        );
+    }
+}
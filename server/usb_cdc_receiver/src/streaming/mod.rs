@@ -9,12 +9,20 @@
 #[cfg(feature = "esp")]
 pub mod controller;
 pub mod device_manager;
+pub mod latency;
+pub mod maintenance;
+pub mod sla;
+pub mod tier;
 #[cfg(feature = "esp")]
 pub mod buffer;
 
 #[cfg(feature = "esp")]
 pub use controller::{StreamingController, StreamingConfig};
 pub use device_manager::{DeviceStreamManager, ProcessedFrame, StreamManagerConfig};
+pub use latency::{EgressLatency, LatencyHistogram, LatencyPercentiles};
+pub use maintenance::{DeferralStats, MaintenanceWindow};
+pub use sla::{DeadlineTracker, DeviceDeadlineStats};
+pub use tier::{BufferTier, TierUsage};
 #[cfg(feature = "esp")]
 pub use buffer::BufferedData;
 
@@ -0,0 +1,139 @@
+//! 画像転送のエンドツーエンド遅延の期限（ソフトリアルタイム）
+//!
+//! 撮影からPCへの送出までの遅延を完了イベントごとに記録し、期限を超えた転送を
+//! 送信元ごとに数えます。統計フレームのSLA項目として送出し、送出ごとにリセットします。
+use std::collections::BTreeMap;
+
+use crate::mac_address::format_mac_address;
+
+/// 既定の期限（撮影から30秒以内にPCへ届ける）
+pub const DEFAULT_FRAME_DEADLINE_MS: u32 = 30_000;
+
+/// 送信元ごとの遅延の集計
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DeviceDeadlineStats {
+    /// 期限内に届いた転送数
+    pub met: u32,
+    /// 期限を超えた転送数
+    pub missed: u32,
+    /// 最大遅延（ミリ秒）
+    pub max_ms: u32,
+}
+
+/// 送信元ごとに期限超過を数えるトラッカー
+#[derive(Debug, Clone)]
+pub struct DeadlineTracker {
+    deadline_ms: u32,
+    devices: BTreeMap<[u8; 6], DeviceDeadlineStats>,
+}
+
+impl Default for DeadlineTracker {
+    fn default() -> Self {
+        Self::new(DEFAULT_FRAME_DEADLINE_MS)
+    }
+}
+
+impl DeadlineTracker {
+    /// 期限（ミリ秒）を指定してトラッカーを作成します
+    pub const fn new(deadline_ms: u32) -> Self {
+        Self {
+            deadline_ms,
+            devices: BTreeMap::new(),
+        }
+    }
+
+    /// 期限（ミリ秒）
+    pub fn deadline_ms(&self) -> u32 {
+        self.deadline_ms
+    }
+
+    /// 期限を変更します（集計は保持）
+    pub fn set_deadline_ms(&mut self, deadline_ms: u32) {
+        self.deadline_ms = deadline_ms;
+    }
+
+    /// 遅延を記録し、期限を超えた場合は true を返します
+    pub fn record(&mut self, mac: [u8; 6], delay_ms: u32) -> bool {
+        let stats = self.devices.entry(mac).or_default();
+        let missed = delay_ms > self.deadline_ms;
+        if missed {
+            stats.missed = stats.missed.saturating_add(1);
+        } else {
+            stats.met = stats.met.saturating_add(1);
+        }
+        stats.max_ms = stats.max_ms.max(delay_ms);
+        missed
+    }
+
+    /// 送信元の集計
+    pub fn device_stats(&self, mac: &[u8; 6]) -> DeviceDeadlineStats {
+        self.devices.get(mac).copied().unwrap_or_default()
+    }
+
+    /// 集計をリセットします（期限は保持）
+    pub fn reset(&mut self) {
+        self.devices.clear();
+    }
+
+    /// 統計フレームの項目
+    ///
+    /// `SLA_MS`（期限）、`SLA_OK`/`SLA_MISS`（全送信元の合計）、`SLA_MAX_MS`（最大遅延）、
+    /// 期限超過があれば `SLA_MISS_DEV`（`aa:bb:..=2|..`）を返します。
+    pub fn stats_fields(&self) -> Vec<(&'static str, String)> {
+        let met: u32 = self.devices.values().map(|stats| stats.met).sum();
+        let missed: u32 = self.devices.values().map(|stats| stats.missed).sum();
+        let max_ms = self.devices.values().map(|stats| stats.max_ms).max().unwrap_or(0);
+        let mut fields = vec![
+            ("SLA_MS", self.deadline_ms.to_string()),
+            ("SLA_OK", met.to_string()),
+            ("SLA_MISS", missed.to_string()),
+            ("SLA_MAX_MS", max_ms.to_string()),
+        ];
+        let per_device: Vec<String> = self
+            .devices
+            .iter()
+            .filter(|(_, stats)| stats.missed > 0)
+            .map(|(mac, stats)| format!("{}={}", format_mac_address(mac), stats.missed))
+            .collect();
+        if !per_device.is_empty() {
+            fields.push(("SLA_MISS_DEV", per_device.join("|")));
+        }
+        fields
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAC_A: [u8; 6] = [0x11, 0x22, 0x33, 0x44, 0x55, 0x66];
+    const MAC_B: [u8; 6] = [0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0xff];
+
+    #[test]
+    fn test_counts_misses_per_device() {
+        let mut tracker = DeadlineTracker::new(30_000);
+        assert!(!tracker.record(MAC_A, 12_000));
+        assert!(!tracker.record(MAC_A, 30_000));
+        assert!(tracker.record(MAC_A, 31_500));
+        assert!(!tracker.record(MAC_B, 8_000));
+
+        assert_eq!(
+            tracker.device_stats(&MAC_A),
+            DeviceDeadlineStats { met: 2, missed: 1, max_ms: 31_500 }
+        );
+        assert_eq!(
+            tracker.stats_fields(),
+            vec![
+                ("SLA_MS", "30000".to_string()),
+                ("SLA_OK", "3".to_string()),
+                ("SLA_MISS", "1".to_string()),
+                ("SLA_MAX_MS", "31500".to_string()),
+                ("SLA_MISS_DEV", "11:22:33:44:55:66=1".to_string()),
+            ]
+        );
+
+        tracker.reset();
+        assert_eq!(tracker.stats_fields().len(), 4);
+        assert_eq!(tracker.device_stats(&MAC_A), DeviceDeadlineStats::default());
+    }
+}
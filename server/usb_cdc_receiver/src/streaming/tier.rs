@@ -0,0 +1,186 @@
+//! ストリーミングバッファの階層配置（内部RAM / PSRAM）
+//!
+//! 内部RAMのリングは容量が小さく、複数デバイスが同時に送信すると古いデータが
+//! すぐに押し出されます。PSRAMを搭載したボード（ESP32-S3など）では、
+//! 内部RAMが満杯の間だけPSRAM側のオーバーフロー領域へ退避（降格）し、
+//! 内部RAMに空きができたら古い順に戻します（昇格）。
+//! 取り出し順序は常に受信順（FIFO）です。
+
+/// PSRAMオーバーフロー領域の既定サイズ（バイト）
+pub const DEFAULT_PSRAM_OVERFLOW_BYTES: usize = 64 * 1024;
+
+/// データを格納している階層
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BufferTier {
+    /// 内部RAMのリング（ホットデータ）
+    Internal,
+    /// PSRAMのオーバーフロー領域
+    Psram,
+}
+
+/// 新しいデータの配置先
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Placement {
+    /// 指定の階層へ追加
+    Store(BufferTier),
+    /// どちらにも入らないため最古のデータを破棄する必要がある
+    EvictOldest,
+}
+
+/// 階層ごとの使用状況
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TierUsage {
+    /// 内部RAMの使用バイト数
+    pub internal_bytes: usize,
+    /// 内部RAMの容量
+    pub internal_capacity: usize,
+    /// PSRAMの使用バイト数
+    pub psram_bytes: usize,
+    /// PSRAMの容量（PSRAMなしは0）
+    pub psram_capacity: usize,
+    /// PSRAMから内部RAMへ戻した回数
+    pub promotions: u64,
+    /// 内部RAMが満杯のためPSRAMへ退避した回数
+    pub demotions: u64,
+}
+
+impl TierUsage {
+    /// 内部RAMのみの構成
+    pub fn internal_only(internal_capacity: usize) -> Self {
+        Self {
+            internal_capacity,
+            ..Default::default()
+        }
+    }
+
+    /// PSRAMのオーバーフロー領域を持つか
+    pub fn has_psram(&self) -> bool {
+        self.psram_capacity > 0
+    }
+
+    /// 新しいデータ（`len` バイト）の配置先を決めます
+    ///
+    /// PSRAMに退避中のデータがある間は、順序を保つため内部RAMに空きがあってもPSRAMへ追加します。
+    pub fn place(&self, len: usize) -> Placement {
+        if self.psram_bytes == 0 && self.internal_bytes + len <= self.internal_capacity {
+            Placement::Store(BufferTier::Internal)
+        } else if self.psram_bytes + len <= self.psram_capacity {
+            Placement::Store(BufferTier::Psram)
+        } else {
+            Placement::EvictOldest
+        }
+    }
+
+    /// PSRAM先頭のデータ（`len` バイト）を内部RAMへ昇格できるか
+    pub fn can_promote(&self, len: usize) -> bool {
+        self.psram_bytes >= len && self.internal_bytes + len <= self.internal_capacity
+    }
+
+    /// 追加を記録
+    pub fn record_store(&mut self, tier: BufferTier, len: usize) {
+        match tier {
+            BufferTier::Internal => self.internal_bytes += len,
+            BufferTier::Psram => {
+                self.psram_bytes += len;
+                self.demotions += 1;
+            }
+        }
+    }
+
+    /// 取り出し・破棄を記録
+    pub fn record_remove(&mut self, tier: BufferTier, len: usize) {
+        match tier {
+            BufferTier::Internal => self.internal_bytes = self.internal_bytes.saturating_sub(len),
+            BufferTier::Psram => self.psram_bytes = self.psram_bytes.saturating_sub(len),
+        }
+    }
+
+    /// 昇格を記録
+    pub fn record_promotion(&mut self, len: usize) {
+        self.psram_bytes = self.psram_bytes.saturating_sub(len);
+        self.internal_bytes += len;
+        self.promotions += 1;
+    }
+
+    /// 全体の使用バイト数
+    pub fn bytes_used(&self) -> usize {
+        self.internal_bytes + self.psram_bytes
+    }
+
+    /// 全体の容量
+    pub fn bytes_total(&self) -> usize {
+        self.internal_capacity + self.psram_capacity
+    }
+
+    /// 統計フレームの項目（階層ごとの使用量/容量と昇格・退避回数）
+    pub fn stats_fields(&self) -> [(&'static str, String); 4] {
+        [
+            ("OVERFLOW_INT", format!("{}/{}", self.internal_bytes, self.internal_capacity)),
+            ("OVERFLOW_PSRAM", format!("{}/{}", self.psram_bytes, self.psram_capacity)),
+            ("OVERFLOW_PROMOTE", self.promotions.to_string()),
+            ("OVERFLOW_DEMOTE", self.demotions.to_string()),
+        ]
+    }
+}
+
+/// 実行時に検出したPSRAM空き容量からオーバーフロー領域のサイズを決めます
+///
+/// 他の用途（カメラ画像の再送バッファなど）のため、空き容量の半分までに制限します。
+pub fn psram_overflow_capacity(psram_free_bytes: usize, requested_bytes: usize) -> usize {
+    requested_bytes.min(psram_free_bytes / 2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spills_to_psram_and_keeps_fifo_order() {
+        let mut usage = TierUsage {
+            psram_capacity: 1000,
+            ..TierUsage::internal_only(512)
+        };
+        assert_eq!(usage.place(400), Placement::Store(BufferTier::Internal));
+        usage.record_store(BufferTier::Internal, 400);
+
+        // 内部RAMに入らない分はPSRAMへ退避
+        assert_eq!(usage.place(200), Placement::Store(BufferTier::Psram));
+        usage.record_store(BufferTier::Psram, 200);
+        // 退避中は小さなデータもPSRAMへ（順序維持）
+        assert_eq!(usage.place(10), Placement::Store(BufferTier::Psram));
+        assert_eq!(usage.demotions, 1);
+
+        // 内部RAMが空いたら昇格
+        usage.record_remove(BufferTier::Internal, 400);
+        assert!(usage.can_promote(200));
+        usage.record_promotion(200);
+        assert_eq!(usage.internal_bytes, 200);
+        assert_eq!(usage.psram_bytes, 0);
+        assert_eq!(usage.promotions, 1);
+
+        let fields = usage.stats_fields();
+        assert_eq!(fields[0], ("OVERFLOW_INT", "200/512".to_string()));
+        assert_eq!(fields[1], ("OVERFLOW_PSRAM", "0/1000".to_string()));
+        assert_eq!(fields[2], ("OVERFLOW_PROMOTE", "1".to_string()));
+        assert_eq!(fields[3], ("OVERFLOW_DEMOTE", "1".to_string()));
+    }
+
+    #[test]
+    fn test_internal_only_evicts_when_full() {
+        let mut usage = TierUsage::internal_only(512);
+        assert!(!usage.has_psram());
+        usage.record_store(BufferTier::Internal, 500);
+        assert_eq!(usage.place(20), Placement::EvictOldest);
+        assert_eq!(usage.bytes_total(), 512);
+    }
+
+    #[test]
+    fn test_psram_overflow_capacity_is_bounded_by_free_memory() {
+        assert_eq!(psram_overflow_capacity(0, DEFAULT_PSRAM_OVERFLOW_BYTES), 0);
+        assert_eq!(psram_overflow_capacity(40_000, DEFAULT_PSRAM_OVERFLOW_BYTES), 20_000);
+        assert_eq!(
+            psram_overflow_capacity(4 * 1024 * 1024, DEFAULT_PSRAM_OVERFLOW_BYTES),
+            DEFAULT_PSRAM_OVERFLOW_BYTES
+        );
+    }
+}
@@ -0,0 +1,234 @@
+//! ESP-IDF呼び出しの実装（このモジュールの外には `unsafe` なESP-IDF呼び出しを置かない）
+//!
+//! ESP-NOWのコールバックで受け取るポインタの解釈（`esp_now::receiver`）と、
+//! 静的バッファ上のデータキューの初期化（`queue::data_queue`）は呼び出し元に残しています。
+
+use esp_idf_svc::sys::{self, esp_err_t, esp_now_recv_cb_t, esp_now_send_cb_t, ESP_OK};
+
+use super::{EspNowLink, SysError, SysResult, WifiRadio};
+use crate::cpu_usage::TaskRuntime;
+
+/// ESP-IDFの戻り値をResultに変換します
+pub fn check(code: esp_err_t) -> SysResult<()> {
+    if code == ESP_OK {
+        Ok(())
+    } else {
+        Err(SysError(code))
+    }
+}
+
+/// ESP-IDFを使う `WifiRadio`・`EspNowLink` の実装（状態を持たない）
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EspSys;
+
+impl WifiRadio for EspSys {
+    fn sta_mac(&self) -> SysResult<[u8; 6]> {
+        sta_mac()
+    }
+
+    fn channel(&self) -> SysResult<u8> {
+        wifi_channel()
+    }
+
+    fn set_channel(&self, channel: u8) -> SysResult<()> {
+        set_wifi_channel(channel)
+    }
+}
+
+impl EspNowLink for EspSys {
+    fn send(&self, mac: &[u8; 6], data: &[u8]) -> SysResult<()> {
+        esp_now_send(mac, data)
+    }
+
+    fn add_peer(&self, mac: &[u8; 6]) -> SysResult<()> {
+        esp_now_add_peer(*mac)
+    }
+
+    fn remove_peer(&self, mac: &[u8; 6]) -> SysResult<()> {
+        esp_now_del_peer(mac)
+    }
+}
+
+/// STAインターフェースのMACアドレス
+pub fn sta_mac() -> SysResult<[u8; 6]> {
+    let mut mac = [0u8; 6];
+    check(unsafe { sys::esp_wifi_get_mac(sys::wifi_interface_t_WIFI_IF_STA, mac.as_mut_ptr()) })?;
+    Ok(mac)
+}
+
+/// 現在のプライマリチャンネル
+pub fn wifi_channel() -> SysResult<u8> {
+    let mut primary = 0u8;
+    let mut second = 0;
+    check(unsafe { sys::esp_wifi_get_channel(&mut primary, &mut second) })?;
+    Ok(primary)
+}
+
+/// プライマリチャンネルを切り替えます（セカンダリチャンネルなし）
+pub fn set_wifi_channel(channel: u8) -> SysResult<()> {
+    check(unsafe { sys::esp_wifi_set_channel(channel, sys::wifi_second_chan_t_WIFI_SECOND_CHAN_NONE) })
+}
+
+/// Wi-Fi設定の保存先をRAMにします（NVSへの書き込みを避ける）
+pub fn set_wifi_storage_ram() -> SysResult<()> {
+    check(unsafe { sys::esp_wifi_set_storage(sys::wifi_storage_t_WIFI_STORAGE_RAM) })
+}
+
+/// Wi-Fiのパワーセーブを無効にします
+pub fn disable_wifi_power_save() -> SysResult<()> {
+    check(unsafe { sys::esp_wifi_set_ps(sys::wifi_ps_type_t_WIFI_PS_NONE) })
+}
+
+/// 最大送信パワーを設定します（dBm。ESP-IDFの0.25dBm単位の範囲 2〜21dBm に丸める）
+pub fn set_max_tx_power_dbm(dbm: i8) -> SysResult<()> {
+    check(unsafe { sys::esp_wifi_set_max_tx_power((i16::from(dbm) * 4).clamp(8, 84) as i8) })
+}
+
+/// 現在の最大送信パワー（dBm）
+pub fn max_tx_power_dbm() -> SysResult<i8> {
+    let mut power_quarter_dbm: i8 = 0;
+    check(unsafe { sys::esp_wifi_get_max_tx_power(&mut power_quarter_dbm) })?;
+    Ok(power_quarter_dbm / 4)
+}
+
+/// ESP-NOWの送信レート（`wifi_phy_rate_t` の値）を設定します
+pub fn set_espnow_rate(phy_rate: u8) -> SysResult<()> {
+    check(unsafe { sys::esp_wifi_config_espnow_rate(sys::wifi_interface_t_WIFI_IF_STA, phy_rate.into()) })
+}
+
+/// プロミスキャス受信を始めます（管理・データ・制御のすべてのフレームをコールバックへ渡す）
+///
+/// ESP-NOWの受信はプロミスキャス受信中も続きます。
+#[cfg(feature = "channel-monitor")]
+pub fn start_promiscuous(rx_cb: sys::wifi_promiscuous_cb_t) -> SysResult<()> {
+    let filter = sys::wifi_promiscuous_filter_t { filter_mask: sys::WIFI_PROMIS_FILTER_MASK_ALL };
+    let ctrl_filter = sys::wifi_promiscuous_filter_t { filter_mask: sys::WIFI_PROMIS_CTRL_FILTER_MASK_ALL };
+    check(unsafe { sys::esp_wifi_set_promiscuous_rx_cb(rx_cb) })?;
+    check(unsafe { sys::esp_wifi_set_promiscuous_filter(&filter) })?;
+    check(unsafe { sys::esp_wifi_set_promiscuous_ctrl_filter(&ctrl_filter) })?;
+    check(unsafe { sys::esp_wifi_set_promiscuous(true) })
+}
+
+/// ESP-NOWを初期化し、受信・送信コールバックを登録します
+pub fn esp_now_start(recv_cb: esp_now_recv_cb_t, send_cb: esp_now_send_cb_t) -> SysResult<()> {
+    check(unsafe { sys::esp_now_init() })?;
+    check(unsafe { sys::esp_now_register_recv_cb(recv_cb) })?;
+    check(unsafe { sys::esp_now_register_send_cb(send_cb) })
+}
+
+/// コールバックの登録を解除してESP-NOWを停止します
+pub fn esp_now_stop() -> SysResult<()> {
+    unsafe {
+        sys::esp_now_unregister_recv_cb();
+        sys::esp_now_unregister_send_cb();
+    }
+    check(unsafe { sys::esp_now_deinit() })
+}
+
+/// 登録済みのピア数
+pub fn esp_now_peer_count() -> SysResult<i32> {
+    let mut peer_num = sys::esp_now_peer_num_t {
+        total_num: 0,
+        encrypt_num: 0,
+    };
+    check(unsafe { sys::esp_now_get_peer_num(&mut peer_num) })?;
+    Ok(peer_num.total_num)
+}
+
+/// 暗号化なしのピアを登録します（現在のチャンネル・STAインターフェース）
+pub fn esp_now_add_peer(mac: [u8; 6]) -> SysResult<()> {
+    let peer_info = sys::esp_now_peer_info_t {
+        peer_addr: mac,
+        channel: 0,
+        ifidx: sys::wifi_interface_t_WIFI_IF_STA,
+        encrypt: false,
+        ..Default::default()
+    };
+    check(unsafe { sys::esp_now_add_peer(&peer_info) })
+}
+
+/// ピアの登録を解除します
+pub fn esp_now_del_peer(mac: &[u8; 6]) -> SysResult<()> {
+    check(unsafe { sys::esp_now_del_peer(mac.as_ptr()) })
+}
+
+/// ESP-NOWのPMKを設定します
+pub fn esp_now_set_pmk(pmk: &[u8; 16]) -> SysResult<()> {
+    check(unsafe { sys::esp_now_set_pmk(pmk.as_ptr()) })
+}
+
+/// 登録済みのピアへデータを送信します
+pub fn esp_now_send(mac: &[u8; 6], data: &[u8]) -> SysResult<()> {
+    check(unsafe { sys::esp_now_send(mac.as_ptr(), data.as_ptr(), data.len()) })
+}
+
+/// ハードウェア乱数でバッファを埋めます（Wi-Fi起動中は暗号論的に安全な乱数）
+pub fn fill_random(buf: &mut [u8]) {
+    unsafe { sys::esp_fill_random(buf.as_mut_ptr().cast(), buf.len()) };
+}
+
+/// チップを再起動します
+pub fn restart() -> ! {
+    unsafe { sys::esp_restart() }
+}
+
+/// 今回の起動理由（`esp_reset_reason_t`）
+pub fn reset_reason() -> u8 {
+    unsafe { sys::esp_reset_reason() as u8 }
+}
+
+/// 起動からの経過時間（FreeRTOSのティックをミリ秒に換算）
+pub fn tick_ms() -> u64 {
+    (unsafe { sys::xTaskGetTickCount() }) as u64 * 1000 / sys::configTICK_RATE_HZ as u64
+}
+
+/// 起動からの経過時間（マイクロ秒、`esp_timer` の値）
+pub fn uptime_us() -> u64 {
+    unsafe { sys::esp_timer_get_time() }.max(0) as u64
+}
+
+/// 空きヒープ（バイト）
+pub fn free_heap() -> u32 {
+    unsafe { sys::esp_get_free_heap_size() }
+}
+
+/// 内部RAMの最大の空きブロック（バイト、一度に確保できる大きさの上限）
+pub fn largest_free_block() -> u32 {
+    unsafe { sys::heap_caps_get_largest_free_block(sys::MALLOC_CAP_8BIT) as u32 }
+}
+
+/// PSRAMの空き容量（バイト、PSRAMなしは0）
+pub fn free_psram() -> usize {
+    unsafe { sys::heap_caps_get_free_size(sys::MALLOC_CAP_SPIRAM) }
+}
+
+/// FreeRTOSのランタイム統計からタスクごとの累積実行時間を取得します
+#[cfg(esp_idf_freertos_generate_run_time_stats)]
+pub fn task_runtimes() -> Option<Vec<TaskRuntime>> {
+    use sys::{uxTaskGetNumberOfTasks, uxTaskGetSystemState, TaskStatus_t};
+
+    // 取得までにタスクが増えても収まるよう余裕を持たせる
+    let capacity = unsafe { uxTaskGetNumberOfTasks() } as usize + 4;
+    let mut statuses: Vec<TaskStatus_t> = Vec::with_capacity(capacity);
+    let mut total_runtime = 0;
+    let count = unsafe { uxTaskGetSystemState(statuses.as_mut_ptr(), capacity as _, &mut total_runtime) } as usize;
+    if count == 0 {
+        return None;
+    }
+    unsafe { statuses.set_len(count) };
+    Some(
+        statuses
+            .iter()
+            .map(|status| {
+                let name = unsafe { std::ffi::CStr::from_ptr(status.pcTaskName) };
+                TaskRuntime::new(name.to_string_lossy(), status.ulRunTimeCounter as u64)
+            })
+            .collect(),
+    )
+}
+
+/// ランタイム統計が無効なビルドでは計測しません
+#[cfg(not(esp_idf_freertos_generate_run_time_stats))]
+pub fn task_runtimes() -> Option<Vec<TaskRuntime>> {
+    None
+}
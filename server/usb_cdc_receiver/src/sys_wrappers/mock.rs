@@ -0,0 +1,106 @@
+use super::{
+    EspNowLink, SysError, SysResult, WifiRadio, ESP_ERR_ESPNOW_EXIST, ESP_ERR_ESPNOW_FULL, ESP_ERR_ESPNOW_NOT_FOUND,
+};
+use std::sync::{Arc, Mutex};
+
+/// 登録できるピア数（ESP-IDFの `ESP_NOW_MAX_TOTAL_PEER_NUM`）
+const MAX_PEERS: usize = 20;
+
+/// 送信したデータ（宛先, データ）
+type SentFrames = Arc<Mutex<Vec<([u8; 6], Vec<u8>)>>>;
+
+/// テスト用のESP-IDF呼び出しのモック実装
+///
+/// 切り替えたチャンネル、登録中のピア、送信したデータを記録します。
+/// `MockUsbCdc` と同様に、クローン同士は同じ内部状態を共有します。
+#[derive(Debug, Clone)]
+pub struct MockSys {
+    /// STAインターフェースのMACアドレス
+    pub mac: [u8; 6],
+    /// 現在のチャンネル
+    pub channel: Arc<Mutex<u8>>,
+    /// 登録中のピア（登録順）
+    pub peers: Arc<Mutex<Vec<[u8; 6]>>>,
+    /// 送信したデータ
+    pub sent: SentFrames,
+    /// 設定すると以降の呼び出しはこのエラーコードで失敗する
+    pub fail_with: Arc<Mutex<Option<i32>>>,
+}
+
+impl Default for MockSys {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MockSys {
+    /// チャンネル1のモックを作成します
+    pub fn new() -> Self {
+        Self {
+            mac: [0x24, 0x6f, 0x28, 0x00, 0x00, 0x01],
+            channel: Arc::new(Mutex::new(1)),
+            peers: Arc::new(Mutex::new(Vec::new())),
+            sent: Arc::new(Mutex::new(Vec::new())),
+            fail_with: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    fn check(&self) -> SysResult<()> {
+        match *self.fail_with.lock().unwrap() {
+            Some(code) => Err(SysError(code)),
+            None => Ok(()),
+        }
+    }
+}
+
+impl WifiRadio for MockSys {
+    fn sta_mac(&self) -> SysResult<[u8; 6]> {
+        self.check()?;
+        Ok(self.mac)
+    }
+
+    fn channel(&self) -> SysResult<u8> {
+        self.check()?;
+        Ok(*self.channel.lock().unwrap())
+    }
+
+    fn set_channel(&self, channel: u8) -> SysResult<()> {
+        self.check()?;
+        *self.channel.lock().unwrap() = channel;
+        Ok(())
+    }
+}
+
+impl EspNowLink for MockSys {
+    fn send(&self, mac: &[u8; 6], data: &[u8]) -> SysResult<()> {
+        self.check()?;
+        if !self.peers.lock().unwrap().contains(mac) {
+            return Err(SysError(ESP_ERR_ESPNOW_NOT_FOUND));
+        }
+        self.sent.lock().unwrap().push((*mac, data.to_vec()));
+        Ok(())
+    }
+
+    fn add_peer(&self, mac: &[u8; 6]) -> SysResult<()> {
+        self.check()?;
+        let mut peers = self.peers.lock().unwrap();
+        if peers.contains(mac) {
+            return Err(SysError(ESP_ERR_ESPNOW_EXIST));
+        }
+        if peers.len() >= MAX_PEERS {
+            return Err(SysError(ESP_ERR_ESPNOW_FULL));
+        }
+        peers.push(*mac);
+        Ok(())
+    }
+
+    fn remove_peer(&self, mac: &[u8; 6]) -> SysResult<()> {
+        self.check()?;
+        let mut peers = self.peers.lock().unwrap();
+        let Some(index) = peers.iter().position(|peer| peer == mac) else {
+            return Err(SysError(ESP_ERR_ESPNOW_NOT_FOUND));
+        };
+        peers.remove(index);
+        Ok(())
+    }
+}
@@ -0,0 +1,65 @@
+//! ESP-IDFの生の呼び出し（`esp_idf_svc::sys`）をまとめた安全なラッパー
+//!
+//! `unsafe` なFFI呼び出しは `esp` モジュールのみに置き、他のモジュールはここの関数とトレイトを使います。
+//! 受信チャンネルの切り替えやESP-NOWの送信のように判断を伴う処理は `WifiRadio`・`EspNowLink` を受け取り、
+//! ホストテストではモック（`mock`）に差し替えます。
+
+#[cfg(feature = "esp")]
+pub mod esp;
+
+// Mock実装（テストと"mock-hw"フィーチャー有効時に使用可能）
+#[cfg(any(test, feature = "mock-hw"))]
+pub mod mock;
+
+/// ESP-IDF呼び出しの結果の型
+pub type SysResult<T> = Result<T, SysError>;
+
+/// ピアの登録数が上限に達している（`ESP_ERR_ESPNOW_FULL`）
+pub const ESP_ERR_ESPNOW_FULL: i32 = 0x3068;
+/// ピアが登録されていない（`ESP_ERR_ESPNOW_NOT_FOUND`）
+pub const ESP_ERR_ESPNOW_NOT_FOUND: i32 = 0x3069;
+/// ピアが登録済み（`ESP_ERR_ESPNOW_EXIST`）
+pub const ESP_ERR_ESPNOW_EXIST: i32 = 0x306b;
+
+/// ESP-IDFのエラーコード（`esp_err_t`）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SysError(pub i32);
+
+impl SysError {
+    /// エラーコード
+    pub fn code(self) -> i32 {
+        self.0
+    }
+}
+
+impl std::fmt::Display for SysError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ESP-IDF error {}", self.0)
+    }
+}
+
+impl std::error::Error for SysError {}
+
+/// Wi-Fi（ESP-NOWの無線）の状態の取得と変更
+pub trait WifiRadio {
+    /// STAインターフェースのMACアドレス
+    fn sta_mac(&self) -> SysResult<[u8; 6]>;
+
+    /// 現在のプライマリチャンネル
+    fn channel(&self) -> SysResult<u8>;
+
+    /// プライマリチャンネルを切り替えます
+    fn set_channel(&self, channel: u8) -> SysResult<()>;
+}
+
+/// ESP-NOWのピア管理と送信
+pub trait EspNowLink {
+    /// 登録済みのピアへデータを送信します（送信キューへの投入まで。到達は送信コールバックで通知される）
+    fn send(&self, mac: &[u8; 6], data: &[u8]) -> SysResult<()>;
+
+    /// 暗号化なしのピアを登録します
+    fn add_peer(&self, mac: &[u8; 6]) -> SysResult<()>;
+
+    /// ピアの登録を解除します
+    fn remove_peer(&self, mac: &[u8; 6]) -> SysResult<()>;
+}
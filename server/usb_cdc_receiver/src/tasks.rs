@@ -0,0 +1,1875 @@
+/// ゲートウェイのタスク構成
+///
+/// 単一ループで行っていた処理を以下のタスクに分割します。
+/// - ESP-NOW受信: 受信コールバックがデータキューへ投入（`main.rs`）
+/// - USB送信: データキューの到着を待ち、USB CDCへフレームを転送（HASH/EOFは完了イベントに集約、
+///   中断された転送の残りチャンクは破棄してABORTイベントを送出、送出できなかったフレームはデッドレターキューへ退避）
+/// - コマンド処理: USBからコマンドを読み取り、解析結果を各タスクへ振り分け
+/// - メンテナンス: スリープコマンドのESP-NOW送信、CPU使用率の計測と統計フレーム・
+///   登録デバイスの定期サマリーの送出、再起動をまたぐ累積統計のNVSへの保存、
+///   仮想デバイス（`EMULATE`）の転送のデータキューへの投入
+///
+/// タスク間はチャネルで接続し、固定遅延によるポーリングは行いません。
+
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use esp_idf_svc::hal::task::thread::ThreadSpawnConfiguration;
+use log::{debug, error, info, warn};
+
+use crate::broadcast::{reported_broadcast_id, BroadcastTracker};
+use crate::camera_settings::{CameraSettingsRegistry, CheckIn};
+#[cfg(feature = "channel-monitor")]
+use crate::channel_utilization::ChannelSampler;
+use crate::channel_utilization::ChannelUtilization;
+use crate::clock::{self, GATEWAY_CLOCK};
+use crate::debug_flags::DebugRequestRegistry;
+use crate::device_groups::{dispatch_response, DeviceGroups, GROUP_CAPTURE_SLEEP_SECONDS};
+use crate::downlink_window::DOWNLINK_WINDOW;
+use crate::command::{self, parse_command, Command, ERROR_RESPONSE_PREFIX};
+use crate::config;
+use crate::cpu_usage::{CpuLimitMonitor, CpuSampler, CpuUsage, TaskRuntime};
+use crate::dead_letter::DeadLetterQueue;
+use crate::emulation::{is_emulated_mac, Emulator};
+use crate::esp_now::admission::{self, ADMISSION};
+use crate::esp_now::cancellation::{self, AbortReason, CANCELLATIONS};
+use crate::esp_now::channel_hop::{reported_missed_channel, CHANNEL_HOP};
+#[cfg(feature = "channel-monitor")]
+use crate::esp_now::channel_monitor;
+use crate::esp_now::chaos::ChaosParams;
+use crate::esp_now::completion::{CompletionTracker, FrameAbort, FrameComplete};
+use crate::esp_now::data_quality::DecodedTelemetry;
+use crate::esp_now::device_info::{device_id_from_hash_payload, DeviceBuildInfo, DeviceDirectory};
+use crate::esp_now::downlink_auth::{DownlinkKey, DOWNLINK_KEY_LEN};
+use crate::esp_now::frame::{Frame, FrameParseError};
+use crate::esp_now::lifecycle::{DeviceLifecycle, ResetLog};
+use crate::esp_now::peer_table::PEERS;
+use crate::esp_now::receiver::{self, LEGACY_FRAMES, PONGS_SENT, REJECTS_SENT};
+use crate::esp_now::reject::{RejectReason, CHECKSUM_STORMS, CHECKSUM_STORM_RETRY_AFTER_MS};
+#[cfg(feature = "chaos")]
+use crate::esp_now::receiver::CHAOS;
+use crate::esp_now::sender::{EspNowSendError, EspNowSender};
+use crate::file_transfer::FileTransfers;
+use crate::fleet_summary::{self, FleetSummary};
+use crate::frame_stats::{self, FrameKind, FrameOutcome, FrameStats, FRAME_STATS};
+use crate::key_rotation::{KeyRotationRegistry, KeyStatus};
+use crate::lifetime_stats::{self, LifetimeStatsStore, LIFETIME_STATS, PERSIST_INTERVAL};
+use crate::mac_address::{format_mac_address, mac_str as format_mac_str, MacAddress};
+use crate::memory_trend::{MemoryForecast, MemorySample, MemoryTrend};
+use crate::pause::PauseRegistry;
+use crate::queue::{data_queue, QueueError, ReceivedData};
+use crate::release_channel::{ReleaseChannel, ReleaseChannels};
+use crate::resend::{ResendRequests, THUMBS_RESPONSE_PREFIX};
+use crate::shutdown::{self, ShutdownReason, ShutdownStage};
+use crate::sleep_command_queue::{SleepCommand, SleepCommandQueue, SLEEP_COMMAND_INTERVAL_MS};
+use crate::sleep_policy::SleepPolicyRegistry;
+use crate::soak::{SoakReports, SoakSample};
+use crate::stats::{StatsReport, GATEWAY_STATS_MAC};
+use crate::streaming::sla::DEFAULT_FRAME_DEADLINE_MS;
+use crate::streaming::{DeadlineTracker, DeferralStats, EgressLatency, MaintenanceWindow};
+use crate::sys_wrappers::esp::{self as sys, EspSys};
+use crate::usb::batch::BatchedUsb;
+use crate::usb::cdc::UsbCdc;
+use crate::usb::{UsbError, UsbInterface, UsbResult};
+
+/// タスクのスタックサイズ（バイト）
+const TASK_STACK_SIZE: usize = 8192;
+
+/// USB送信タスクの優先度（コマンド処理より高くしてUSBロックの取得を優先）
+const USB_EGRESS_PRIORITY: u8 = 6;
+
+/// コマンド処理タスクの優先度
+const COMMAND_HANDLER_PRIORITY: u8 = 5;
+
+/// USB送信タスクがデータ到着を待つ最大時間（ミリ秒）
+const EGRESS_WAIT_TIMEOUT_MS: u32 = 1000;
+
+/// コマンド読み取りのタイムアウト（ミリ秒）
+const COMMAND_READ_TIMEOUT_MS: u32 = 10;
+
+/// コマンド応答書き込みのタイムアウト（ミリ秒）
+const RESPONSE_WRITE_TIMEOUT_MS: u32 = 100;
+
+/// 停止時にデータキューの送出を待つ最長時間（ミリ秒）
+const SHUTDOWN_DRAIN_TIMEOUT_MS: u64 = 500;
+
+/// コマンド処理タスクからメンテナンスタスクへのチャネル容量
+const SLEEP_COMMAND_CHANNEL_CAPACITY: usize = 10;
+
+/// スリープコマンドがない場合のメンテナンス周期
+const MAINTENANCE_IDLE_WAIT: Duration = Duration::from_millis(1000);
+
+/// 統計フレーム（データキュー使用量・制御メッセージの送信時間）の送出間隔
+const STATS_REPORT_INTERVAL: Duration = Duration::from_secs(30);
+
+/// 受信途中の転送がある間に統計フレーム・累積統計の保存を延期できる最長時間
+const MAINTENANCE_MAX_DEFERRAL: Duration = Duration::from_secs(5);
+
+/// CPU使用率の計測間隔
+const CPU_SAMPLE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// CPU使用率の警告までに閾値を続けて超える計測回数（30秒間）
+const CPU_SUSTAIN_SAMPLES: u32 = 6;
+
+/// チャンネルの混雑度の計測間隔
+#[cfg(feature = "channel-monitor")]
+const CHANNEL_SAMPLE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// 空きヒープを記録する間隔
+const MEMORY_SAMPLE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// 受信途中（EOF未受信）の転送の数（USB送信タスクが更新し、メンテナンスタスクが参照）
+static ACTIVE_TRANSFERS: AtomicU32 = AtomicU32::new(0);
+
+/// 中断された転送に属するため破棄したチャンク数（統計フレーム用）
+static ABORTED_CHUNKS_DROPPED: AtomicU32 = AtomicU32::new(0);
+
+/// 送出したABORTイベント数（統計フレーム用）
+static ABORT_EVENTS_SENT: AtomicU32 = AtomicU32::new(0);
+
+/// フレーム処理・USB転送時間の分布（統計フレームの送出ごとにリセット）
+static EGRESS_LATENCY: Mutex<EgressLatency> = Mutex::new(EgressLatency::new());
+
+/// デバイスの異常再起動（統計フレームの送出ごとにリセット）
+static DEVICE_RESETS: Mutex<ResetLog> = Mutex::new(ResetLog::new());
+
+/// デバイスごとのデバイスID・ファームウェアビルド情報・設定ハッシュ（`LIST_DEVICES` で参照）
+static DEVICE_DIRECTORY: Mutex<DeviceDirectory> = Mutex::new(DeviceDirectory::new());
+
+/// デバイスごとのソークテストの集計（`SOAK_REPORT` で参照）
+static SOAK_REPORTS: Mutex<SoakReports> = Mutex::new(SoakReports::new());
+
+/// 一時停止中のデバイス（`PAUSE`・`RESUME` で更新し、転送完了時に参照）
+static PAUSED_DEVICES: Mutex<PauseRegistry> = Mutex::new(PauseRegistry::new());
+
+/// 設定の一斉配信（`BROADCAST_CONFIG` で開始し、転送完了時に適用状況を記録）
+static BROADCASTS: Mutex<BroadcastTracker> = Mutex::new(BroadcastTracker::new());
+
+/// 未適用のカメラ画質設定（`SET_QUALITY` で登録し、転送完了時に送信・確認）
+static CAMERA_SETTINGS: Mutex<CameraSettingsRegistry> = Mutex::new(CameraSettingsRegistry::new());
+
+/// 未適用のデバッグフラグ（`SET_DEBUG` で登録し、転送完了時に送信・確認）
+static DEBUG_REQUESTS: Mutex<DebugRequestRegistry> = Mutex::new(DebugRequestRegistry::new());
+
+/// 送信待ちの画像の再送要求（`RESEND_LAST` で登録し、次のメンテナンス周期で送信）
+static RESEND_REQUESTS: Mutex<ResendRequests> = Mutex::new(ResendRequests::new());
+
+/// 送信待ちのサムネイル要求（`GET_THUMBS` で登録し、次のメンテナンス周期で送信）
+static THUMBNAIL_REQUESTS: Mutex<ResendRequests> = Mutex::new(ResendRequests::with_prefix(THUMBS_RESPONSE_PREFIX));
+
+/// デバイスごとのダウンリンク認証鍵（`ROTATE_KEY` で更新を始め、転送完了時に送信・確認）
+static KEY_ROTATIONS: Mutex<KeyRotationRegistry> = Mutex::new(KeyRotationRegistry::new());
+
+/// デバイスへのファイル転送（`PUSH_FILE`・`FILE_DATA` で受け取り、転送完了時に断片を送信）
+static FILE_TRANSFERS: Mutex<FileTransfers> = Mutex::new(FileTransfers::new());
+
+/// デバイスごとのリリースチャンネルと展開ファームウェア（`SET_CHANNEL`・`SET_ROLLOUT` で設定）
+static RELEASE_CHANNELS: Mutex<ReleaseChannels> = Mutex::new(ReleaseChannels::new());
+
+/// デバイスごとのグループ（`GROUP_ADD` で登録し、`GROUP_SLEEP`・`GROUP_CAPTURE` でメンバーへ展開）
+static DEVICE_GROUPS: Mutex<DeviceGroups> = Mutex::new(DeviceGroups::new());
+
+/// 撮影からPCへの送出までの期限超過（統計フレームの送出ごとにリセット）
+static FRAME_DEADLINES: Mutex<DeadlineTracker> = Mutex::new(DeadlineTracker::new(DEFAULT_FRAME_DEADLINE_MS));
+
+/// USBへ送出できなかったフレーム（`DLQ_LIST` で参照し、`DLQ_REPLAY` で送り直す）
+static DEAD_LETTERS: Mutex<DeadLetterQueue> = Mutex::new(DeadLetterQueue::new());
+
+/// 登録デバイスごとの受信状況（定期サマリーの送出ごとに受信数をリセット）
+static FLEET_SUMMARY: Mutex<FleetSummary> = Mutex::new(FleetSummary::new());
+
+/// 仮想デバイスのエミュレーション（`EMULATE` コマンドで開始・停止）
+static EMULATOR: Mutex<Emulator> = Mutex::new(Emulator::new());
+
+/// ゲートウェイの空きヒープの推移（`MEMORY_TREND` で参照）
+static MEMORY_TREND: Mutex<MemoryTrend> = Mutex::new(MemoryTrend::new());
+
+/// 直近のチャンネルの混雑度（`channel-monitor` フィーチャーのみ計測、統計フレームで参照）
+static CHANNEL_UTILIZATION: Mutex<Option<ChannelUtilization>> = Mutex::new(None);
+
+/// タスク間で共有するUSB CDC（小さなフレームはまとめて書き込む）
+pub type SharedUsb = Arc<Mutex<BatchedUsb<UsbCdc<'static>>>>;
+
+/// ゲートウェイのタスクを起動します
+///
+/// USB送信タスクとコマンド処理タスクを生成し、呼び出し元のタスクで
+/// メンテナンス処理を実行します（戻りません）。
+pub fn run(
+    usb_cdc: UsbCdc<'static>,
+    esp_now_sender: EspNowSender,
+    sleep_policies: SleepPolicyRegistry,
+    fleet: FleetSummary,
+    key_rotations: KeyRotationRegistry,
+    lifetime_store: Option<LifetimeStatsStore>,
+) -> Result<()> {
+    if let Ok(mut summary) = FLEET_SUMMARY.lock() {
+        *summary = fleet;
+    }
+    if let Ok(mut registry) = KEY_ROTATIONS.lock() {
+        *registry = key_rotations;
+    }
+    if let Ok(mut deadlines) = FRAME_DEADLINES.lock() {
+        deadlines.set_deadline_ms(config::frame_deadline_ms());
+        info!("Frame deadline: {}ms", deadlines.deadline_ms());
+    }
+
+    let usb: SharedUsb = Arc::new(Mutex::new(BatchedUsb::new(usb_cdc)));
+    let lifetime_store = lifetime_store.map(|store| Arc::new(Mutex::new(store)));
+    if let Ok(mut sequence) = shutdown::SHUTDOWN.lock() {
+        let drain = data_queue::DataQueueDrain {
+            timeout: Duration::from_millis(SHUTDOWN_DRAIN_TIMEOUT_MS),
+        };
+        sequence.register("data_queue", ShutdownStage::Drain, Box::new(drain));
+        // 送出し終えたフレームまで数えてから保存する
+        if let Some(store) = &lifetime_store {
+            sequence.register("lifetime_stats", ShutdownStage::Drain, Box::new(store.clone()));
+        }
+        sequence.register("usb", ShutdownStage::Teardown, Box::new(usb.clone()));
+    }
+    let (sleep_tx, sleep_rx) = mpsc::sync_channel(SLEEP_COMMAND_CHANNEL_CAPACITY);
+
+    let egress_usb = usb.clone();
+    let egress_sleep_tx = sleep_tx.clone();
+    spawn_task(b"usb_egress\0", USB_EGRESS_PRIORITY, move || {
+        run_usb_egress(egress_usb, egress_sleep_tx)
+    })?;
+    info!("✓ USB egress task started");
+
+    let command_usb = usb.clone();
+    spawn_task(b"cmd_handler\0", COMMAND_HANDLER_PRIORITY, move || {
+        run_command_handler(command_usb, sleep_tx, sleep_policies)
+    })?;
+    info!("✓ Command handler task started");
+
+    info!("Running maintenance task on main thread...");
+    run_maintenance(usb, esp_now_sender, sleep_rx, lifetime_store);
+    Ok(())
+}
+
+/// 更新したデバイスの署名鍵を引きます（`DownlinkSigner::with_device_keys` に渡す）
+pub fn device_signing_key(mac: &[u8; 6], counter: u32) -> Option<DownlinkKey> {
+    KEY_ROTATIONS.lock().ok()?.signing_key(mac, counter)
+}
+
+/// 登録したサブシステムを停止し、結果をログに出力します
+pub fn run_shutdown(reason: ShutdownReason) {
+    for outcome in shutdown::shutdown_all(reason) {
+        match outcome.result {
+            Ok(()) => info!("Shutdown {:?}: {} stopped", outcome.stage, outcome.name),
+            Err(e) => warn!("Shutdown {:?}: {} failed: {}", outcome.stage, outcome.name, e),
+        }
+    }
+}
+
+/// 名前と優先度を指定してFreeRTOSタスク（std::thread）を生成します
+fn spawn_task<F>(name: &'static [u8], priority: u8, task: F) -> Result<()>
+where
+    F: FnOnce() + Send + 'static,
+{
+    ThreadSpawnConfiguration {
+        name: Some(name),
+        stack_size: TASK_STACK_SIZE,
+        priority,
+        ..Default::default()
+    }
+    .set()?;
+
+    let spawn_result = std::thread::Builder::new()
+        .stack_size(TASK_STACK_SIZE)
+        .spawn(task);
+
+    // 以降のスレッド生成に設定が引き継がれないよう元に戻す
+    ThreadSpawnConfiguration::default().set()?;
+
+    spawn_result?;
+    Ok(())
+}
+
+/// USBのロックを取得します（ポイズン状態でも継続利用）
+fn lock_usb(usb: &SharedUsb) -> MutexGuard<'_, BatchedUsb<UsbCdc<'static>>> {
+    usb.lock().unwrap_or_else(|poisoned| {
+        warn!("USB mutex poisoned, recovering");
+        poisoned.into_inner()
+    })
+}
+
+/// USB送信タスク
+///
+/// データキューへの到着を待機し、届いたフレームをUSB CDCへ転送します。
+/// HASH/EOFフレームは送信元ごとに集約し、転送完了イベントとして1回だけ送出します。
+/// 一時停止中のデバイスには転送完了時に停止の残り時間のスリープコマンドを返します。
+/// まとめて書き込むために溜めたフレームは、送出時刻を過ぎた時点でここから送出します。
+fn run_usb_egress(usb: SharedUsb, sleep_tx: SyncSender<SleepCommand>) {
+    let mut tracker = CompletionTracker::with_partial_salvage(cancellation::partial_salvage_enabled());
+
+    loop {
+        let flush_deadline = lock_usb(&usb).flush_deadline();
+        let dequeued = data_queue::dequeue_timeout(egress_wait_ms(&tracker, flush_deadline));
+
+        // 待機中に登録された中断を先に通知し、残りのチャンクを破棄できるようにする
+        send_pending_aborts(&usb, &mut tracker);
+
+        match dequeued {
+            Ok(received_data) => {
+                lifetime_stats::record_frame();
+                if is_aborted_chunk(&received_data) {
+                    ABORTED_CHUNKS_DROPPED.fetch_add(1, Ordering::Relaxed);
+                    frame_stats::record(
+                        &received_data.mac,
+                        FrameKind::of_frame(&received_data.data),
+                        FrameOutcome::Errored,
+                    );
+                    debug!(
+                        "Dropped queued chunk of aborted transfer from {}",
+                        format_mac_str(&received_data.mac)
+                    );
+                } else {
+                    forward_received_data(&usb, &sleep_tx, &mut tracker, received_data);
+                }
+            }
+            Err(QueueError::Empty) => {
+                // タイムアウト（データなし）
+            }
+            Err(e) => {
+                error!("Error dequeuing data: {:?}", e);
+            }
+        }
+
+        for event in tracker.poll(Instant::now()) {
+            send_frame_complete(&usb, &sleep_tx, &event);
+        }
+        ACTIVE_TRANSFERS.store(tracker.active_transfers() as u32, Ordering::Relaxed);
+
+        let mut usb_guard = lock_usb(&usb);
+        if let Err(usb_err) = usb_guard.flush_if_due(Instant::now()) {
+            error!("USB transfer failed for batched frames: {}", usb_err);
+        }
+        // コマンド応答の書き込み前の送出で失敗したフレームもここで退避する
+        let failed = usb_guard.take_failed_frames();
+        drop(usb_guard);
+        dead_letter_frames(failed);
+    }
+}
+
+/// フレームをUSBへ送出し、送出できなかったフレームはデッドレターキューへ退避します
+///
+/// 溜めたフレームの送出に失敗した場合は、溜めていたフレームも退避します。
+fn send_usb_frame(usb: &SharedUsb, frame: &[u8], mac_str: &str) -> UsbResult<usize> {
+    let mut usb_guard = lock_usb(usb);
+    let result = usb_guard.send_frame(frame, mac_str);
+    let mut failed = usb_guard.take_failed_frames();
+    drop(usb_guard);
+    if let Err(usb_err) = &result {
+        failed.push((frame.to_vec(), usb_err.clone()));
+    }
+    dead_letter_frames(failed);
+    result
+}
+
+/// 緊急フレームをUSBへ送出し、溜めたフレームごとすぐに書き出します（送出時刻を待たない）
+fn send_urgent_usb_frame(usb: &SharedUsb, frame: &[u8], mac_str: &str) -> UsbResult<usize> {
+    let mut usb_guard = lock_usb(usb);
+    let mut result = usb_guard.send_frame(frame, mac_str);
+    let mut failed = usb_guard.take_failed_frames();
+    match &result {
+        Err(usb_err) => failed.push((frame.to_vec(), usb_err.clone())),
+        // 溜めた後の書き出しに失敗した場合、緊急フレームも溜めたフレームとともに取り出される
+        Ok(_) => {
+            if let Err(usb_err) = usb_guard.flush() {
+                result = Err(usb_err);
+                failed.extend(usb_guard.take_failed_frames());
+            }
+        }
+    }
+    drop(usb_guard);
+    dead_letter_frames(failed);
+    result
+}
+
+/// 送出できなかったフレームをデッドレターキューへ退避します
+fn dead_letter_frames(failed: Vec<(Vec<u8>, UsbError)>) {
+    if failed.is_empty() {
+        return;
+    }
+    lifetime_stats::record_errors(failed.len() as u32);
+    match DEAD_LETTERS.lock() {
+        Ok(mut dead_letters) => {
+            for (frame, usb_err) in failed {
+                warn!("Dead-lettered {} byte frame after USB failure: {}", frame.len(), usb_err);
+                dead_letters.push(frame, &usb_err, Instant::now());
+            }
+        }
+        Err(_) => error!("Dead-letter queue lock poisoned; dropped {} frames", failed.len()),
+    }
+}
+
+/// デッドレターキューのフレームをUSBへ送り直し、結果を応答します（失敗したフレームはキューに戻す）
+fn replay_dead_letters(usb: &SharedUsb, id: Option<u32>) {
+    let letters = match DEAD_LETTERS.lock() {
+        Ok(mut dead_letters) => dead_letters.take(id),
+        Err(_) => {
+            error!("Dead-letter queue lock poisoned");
+            return;
+        }
+    };
+
+    let mut usb_guard = lock_usb(usb);
+    // 溜めていたフレームを先に送出し、再送するフレームと混ざらないようにする
+    if let Err(usb_err) = usb_guard.flush() {
+        warn!("USB transfer failed for batched frames before dead-letter replay: {}", usb_err);
+    }
+    let stranded = usb_guard.take_failed_frames();
+    let mut replayed = 0;
+    let mut failed = Vec::new();
+    for letter in letters {
+        let result = usb_guard.send_frame(&letter.frame, "DLQ").and_then(|_| usb_guard.flush());
+        // 失敗したフレームは番号を保ってキューに戻すため、送出待ちから外した分は使わない
+        usb_guard.take_failed_frames();
+        match result {
+            Ok(_) => replayed += 1,
+            Err(usb_err) => failed.push((letter, usb_err)),
+        }
+    }
+    drop(usb_guard);
+    dead_letter_frames(stranded);
+
+    let failures = failed.len();
+    let response = match DEAD_LETTERS.lock() {
+        Ok(mut dead_letters) => {
+            for (letter, usb_err) in failed {
+                dead_letters.requeue(letter, &usb_err, Instant::now());
+            }
+            dead_letters.record_replayed(replayed as u32);
+            dead_letters.replay_response(replayed, failures)
+        }
+        Err(_) => {
+            error!("Dead-letter queue lock poisoned");
+            return;
+        }
+    };
+    info!("Dead-letter replay: {} replayed, {} failed", replayed, failures);
+    write_response(usb, &response);
+}
+
+/// 中断された転送に属するデータかどうか（緊急フレームは転送に属さないため破棄しない）
+fn is_aborted_chunk(received_data: &ReceivedData) -> bool {
+    !received_data.urgent
+        && CANCELLATIONS
+            .lock()
+            .is_ok_and(|registry| registry.is_stale(&received_data.mac, received_data.epoch))
+}
+
+/// 未通知の中断を集約中の転送に反映し、ABORTイベントをUSBへ送出します
+fn send_pending_aborts(usb: &SharedUsb, tracker: &mut CompletionTracker) {
+    let pending = match CANCELLATIONS.lock() {
+        Ok(mut registry) => registry.take_pending(),
+        Err(_) => return,
+    };
+    for (mac, reason) in pending {
+        let event = tracker.abort(mac, reason, Instant::now());
+        send_frame_abort(usb, &event);
+    }
+}
+
+/// 次の完了イベント送出予定と溜めたフレームの送出時刻を考慮したデータ待機時間（ミリ秒）
+fn egress_wait_ms(tracker: &CompletionTracker, flush_deadline: Option<Instant>) -> u32 {
+    match tracker.next_deadline().into_iter().chain(flush_deadline).min() {
+        Some(deadline) => {
+            let remaining = deadline.saturating_duration_since(Instant::now()).as_millis();
+            remaining.clamp(1, EGRESS_WAIT_TIMEOUT_MS as u128) as u32
+        }
+        None => EGRESS_WAIT_TIMEOUT_MS,
+    }
+}
+
+/// 受信データを完了トラッカーに通し、必要なフレームをUSBへ送出します
+fn forward_received_data(
+    usb: &SharedUsb,
+    sleep_tx: &SyncSender<SleepCommand>,
+    tracker: &mut CompletionTracker,
+    received_data: ReceivedData,
+) {
+    let mac_str = format_mac_str(&received_data.mac);
+    debug!("Processing data from {}: {} bytes", mac_str, received_data.data.len());
+    if let Ok(mut summary) = FLEET_SUMMARY.lock() {
+        summary.record_received(&received_data.mac);
+        if let Some(rssi) = received_data.rssi {
+            summary.record_rssi(&received_data.mac, rssi);
+        }
+    }
+
+    // 解析できないフレームは集約対象外としてそのまま転送
+    let frame_kind = FrameKind::of_frame(&received_data.data);
+    let mut frame_mac = None;
+    let parsed = Frame::from_bytes(&received_data.data);
+    if matches!(parsed, Err(FrameParseError::InvalidChecksum { .. })) && is_checksum_storm(&received_data.mac, &mac_str) {
+        frame_stats::record(&received_data.mac, frame_kind, FrameOutcome::Errored);
+        return;
+    }
+    if let Ok((frame, _)) = parsed {
+        frame_mac = Some(*frame.mac_address());
+        let observation = tracker.observe(&frame, Instant::now());
+        if let Some(event) = observation.completed {
+            send_frame_complete(usb, sleep_tx, &event);
+        }
+        if !observation.forward {
+            // 転送完了イベントに集約したフレームは転送済みとして数える
+            frame_stats::record(&received_data.mac, frame_kind, FrameOutcome::Forwarded);
+            debug!(
+                "Held {} frame from {} (seq={}) for completion event",
+                frame.frame_type().as_str(),
+                mac_str,
+                frame.sequence_number()
+            );
+            return;
+        }
+    }
+
+    let transfer_start = Instant::now();
+    let result = if received_data.urgent {
+        warn!("Forwarding URGENT frame from {} ahead of queued chunks", mac_str);
+        send_urgent_usb_frame(usb, &received_data.data, &mac_str)
+    } else {
+        send_usb_frame(usb, &received_data.data, &mac_str)
+    };
+    let usb_ms = record_egress_latency(received_data.received_at, transfer_start);
+    if let Some(mac) = frame_mac {
+        tracker.record_usb_transfer(&mac, usb_ms);
+    }
+    match result {
+        Ok(bytes_sent) => {
+            frame_stats::record(&received_data.mac, frame_kind, FrameOutcome::Forwarded);
+            debug!("USB transfer successful: {} bytes", bytes_sent);
+        }
+        Err(usb_err) => {
+            frame_stats::record(&received_data.mac, frame_kind, FrameOutcome::Errored);
+            error!("USB transfer failed for {}: {}", mac_str, usb_err);
+        }
+    }
+}
+
+/// チェックサムエラーを記録し、多発に達したら転送を中断してデバイスへ拒否メッセージを返します
+///
+/// 多発に達したフレームは破棄し、trueを返します（同じ転送の残りはEOFまで破棄）。
+fn is_checksum_storm(mac: &[u8; 6], mac_str: &str) -> bool {
+    let storm = CHECKSUM_STORMS.lock().is_ok_and(|mut storms| storms.record_error(*mac, Instant::now()));
+    if storm {
+        warn!("Checksum errors from {} exceed the storm threshold; aborting its transfer", mac_str);
+        cancellation::cancel_transfer(*mac, AbortReason::ChecksumStorm);
+        admission::finish_transfer(mac);
+        receiver::send_reject(*mac, mac_str, RejectReason::ChecksumStorm, 0, CHECKSUM_STORM_RETRY_AFTER_MS);
+    }
+    storm
+}
+
+/// 受信からUSB転送完了までの時間と、USB転送（ロック待ちを含む）の時間を記録します
+///
+/// 戻り値はUSB転送時間（ミリ秒）です。
+fn record_egress_latency(received_at: Instant, transfer_start: Instant) -> u32 {
+    let now = Instant::now();
+    let usb_ms = elapsed_ms(transfer_start, now);
+    if let Ok(mut latency) = EGRESS_LATENCY.lock() {
+        latency.processing.record(elapsed_ms(received_at, now));
+        latency.usb_transfer.record(usb_ms);
+    }
+    usb_ms
+}
+
+fn elapsed_ms(since: Instant, now: Instant) -> u32 {
+    now.saturating_duration_since(since).as_millis().min(u32::MAX as u128) as u32
+}
+
+/// 転送完了イベントをUSBへ送出します
+fn send_frame_complete(usb: &SharedUsb, sleep_tx: &SyncSender<SleepCommand>, event: &FrameComplete) {
+    let mac_str = format_mac_address(&event.mac);
+    info!(
+        "Frame complete for {}: frame_id={}, bytes={}, dedupe={}, hash={}, eof={}, air={}ms, usb={}ms, e2e={}, trace={}",
+        mac_str,
+        event.frame_id,
+        event.byte_count,
+        event.dedupe_count,
+        event.hash_payload.is_some(),
+        event.eof_received,
+        event.air_ms,
+        event.usb_ms,
+        event.end_to_end_ms.map_or_else(|| "-".to_string(), |ms| format!("{}ms", ms)),
+        event.trace_id().map_or_else(|| "-".to_string(), |id| format!("{:016x}", id))
+    );
+
+    admission::finish_transfer(&event.mac);
+    record_hop_result(&mac_str, true, event.hash_payload.as_deref());
+    if let Ok(mut window) = DOWNLINK_WINDOW.lock() {
+        window.record_transfer_loss(event.mac, event.gaps.loss_percent());
+    }
+    if let Ok(mut summary) = FLEET_SUMMARY.lock() {
+        let now = Instant::now();
+        summary.record_complete(&event.mac, event.hash_payload.as_deref(), now);
+        summary.record_retries(&event.mac, event.dedupe_count, event.gaps.missing_count());
+        summary.record_capture_clock(&event.mac, event.hash_payload.as_deref(), event.end_to_end_ms, now);
+    }
+    if let Some(reason) = event.hash_payload.as_deref().and_then(fleet_summary::reported_capture_skip) {
+        info!("Device {} sent no image: {}", mac_str, reason);
+    }
+    if let Some(telemetry) = event.hash_payload.as_deref().and_then(DecodedTelemetry::decode) {
+        for issue in &telemetry.issues {
+            warn!("DataQuality: {} reported an implausible value {}", mac_str, issue);
+        }
+    }
+
+    if let Some(lifecycle) = event.hash_payload.as_deref().and_then(DeviceLifecycle::from_hash_payload) {
+        log_device_lifecycle(&mac_str, &lifecycle);
+        if let Ok(mut resets) = DEVICE_RESETS.lock() {
+            resets.record(event.mac, &lifecycle);
+        }
+    }
+
+    if let Some(device_id) = event.hash_payload.as_deref().and_then(device_id_from_hash_payload) {
+        if let Ok(mut directory) = DEVICE_DIRECTORY.lock() {
+            if let Some(previous_mac) = directory.record_identity(event.mac, &device_id) {
+                warn!(
+                    "Device {} now reports MAC {} (was {}); keeping its records",
+                    device_id,
+                    mac_str,
+                    format_mac_address(&previous_mac)
+                );
+            }
+        }
+    }
+
+    if let Some(build) = event.hash_payload.as_deref().and_then(DeviceBuildInfo::from_hash_payload) {
+        debug!("Device build for {}: {}", mac_str, build.summary());
+        if let Ok(mut directory) = DEVICE_DIRECTORY.lock() {
+            if let Some(previous) = directory.record(event.mac, build) {
+                info!("Device {} changed build or config (was {})", mac_str, previous.summary());
+            }
+        }
+    }
+
+    if let Some(sample) = event.hash_payload.as_deref().and_then(SoakSample::from_hash_payload) {
+        if let Ok(mut reports) = SOAK_REPORTS.lock() {
+            reports.record(event.mac, sample);
+        }
+    }
+
+    if let (Some(delay_ms), Ok(mut deadlines)) = (event.end_to_end_ms, FRAME_DEADLINES.lock()) {
+        if deadlines.record(event.mac, delay_ms) {
+            warn!(
+                "Frame deadline missed for {}: frame_id={}, e2e={}ms > {}ms",
+                mac_str,
+                event.frame_id,
+                delay_ms,
+                deadlines.deadline_ms()
+            );
+        }
+    }
+
+    // 未適用のデバイスはスリープコマンドを待って受信中のため、スリープコマンドより先に再配信する
+    let reported_id = event.hash_payload.as_deref().and_then(reported_broadcast_id);
+    if let Ok(mut broadcasts) = BROADCASTS.lock() {
+        if broadcasts.record_check_in(event.mac, reported_id) {
+            info!("Device {} has not applied the broadcast config; rebroadcasting", mac_str);
+        }
+    }
+    if let Ok(mut camera_settings) = CAMERA_SETTINGS.lock() {
+        match camera_settings.record_check_in(&event.mac, event.hash_payload.as_deref()) {
+            CheckIn::Idle => {}
+            CheckIn::Confirmed => info!("Device {} confirmed the requested camera settings", mac_str),
+            CheckIn::Deliver => info!("Delivering pending camera settings to {}", mac_str),
+        }
+    }
+    if let Ok(mut debug_requests) = DEBUG_REQUESTS.lock() {
+        match debug_requests.record_check_in(&event.mac, event.hash_payload.as_deref()) {
+            CheckIn::Idle => {}
+            CheckIn::Confirmed => info!("Device {} confirmed the requested debug flags", mac_str),
+            CheckIn::Deliver => info!("Delivering pending debug flags to {}", mac_str),
+        }
+    }
+
+    if let Ok(mut key_rotations) = KEY_ROTATIONS.lock() {
+        match key_rotations.record_check_in(&event.mac, event.hash_payload.as_deref()) {
+            CheckIn::Idle => {}
+            CheckIn::Confirmed => info!("Device {} switched to the rotated downlink key", mac_str),
+            CheckIn::Deliver => info!("Delivering pending downlink key to {}", mac_str),
+        }
+    }
+    if let Ok(mut transfers) = FILE_TRANSFERS.lock() {
+        match transfers.record_check_in(&event.mac, event.hash_payload.as_deref()) {
+            CheckIn::Idle => {}
+            CheckIn::Confirmed => info!("Device {} received and verified the pushed file", mac_str),
+            CheckIn::Deliver => info!("Resuming file transfer to {}", mac_str),
+        }
+    }
+
+    if let Err(usb_err) = send_usb_frame(usb, &event.to_frame(), &mac_str) {
+        error!("USB transfer failed for completion event of {}: {}", mac_str, usb_err);
+    }
+
+    answer_paused_device(sleep_tx, &event.mac, &mac_str);
+}
+
+/// 受信チャンネルごとの転送結果を記録します
+///
+/// デバイスが予定のチャンネルで届かなかったと報告した場合（`HOP_MISS`）は、そのチャンネルの失敗も数えます。
+fn record_hop_result(mac_str: &str, success: bool, hash_payload: Option<&[u8]>) {
+    let Ok(mut hop) = CHANNEL_HOP.lock() else {
+        return;
+    };
+    let Some(hopper) = hop.as_mut() else {
+        return;
+    };
+    let now = Instant::now();
+    let mut suspended = hopper.record_transfer(success, now);
+    if let Some(missed) = hash_payload.and_then(reported_missed_channel) {
+        debug!("Device {} missed the gateway on channel {}", mac_str, missed);
+        suspended = suspended.or(hopper.record_miss(missed, now));
+    }
+    if let Some(channel) = suspended {
+        warn!("Channel {} failed repeatedly; receiving on home channel {} instead", channel, hopper.home());
+    }
+}
+
+/// 一時停止中のデバイスに停止の残り時間のスリープコマンドを返します
+fn answer_paused_device(sleep_tx: &SyncSender<SleepCommand>, mac: &[u8; 6], mac_str: &str) {
+    let sleep_seconds = match PAUSED_DEVICES.lock() {
+        Ok(mut paused) => paused.sleep_seconds_for(mac, Instant::now()),
+        Err(_) => None,
+    };
+    let Some(sleep_seconds) = sleep_seconds else {
+        return;
+    };
+    match sleep_tx.try_send(SleepCommand::new(mac_str.to_string(), sleep_seconds)) {
+        Ok(()) => info!("Device {} is paused; answering with {}s sleep", mac_str, sleep_seconds),
+        Err(e) => error!("✗ Failed to queue pause sleep command for {}: {:?}", mac_str, e),
+    }
+}
+
+/// 起動後最初の転送に含まれるライフサイクル情報をログに出力します
+fn log_device_lifecycle(mac_str: &str, lifecycle: &DeviceLifecycle) {
+    let message = format!(
+        "Device lifecycle for {}: boot={}, wake={}, prev_uptime={}, boot_log={}",
+        mac_str,
+        lifecycle.boot_reason,
+        lifecycle.wake_cause.as_deref().unwrap_or("-"),
+        lifecycle.previous_uptime_s.map_or_else(|| "-".to_string(), |s| format!("{}s", s)),
+        lifecycle.boot_log.join("|")
+    );
+    if lifecycle.is_abnormal() {
+        warn!("{}", message);
+    } else {
+        debug!("{}", message);
+    }
+    if let Some((panic_message, panic_location)) = &lifecycle.panic {
+        warn!("Device {} panicked at {}: {}", mac_str, panic_location, panic_message);
+    }
+}
+
+/// 中断イベントをUSBへ送出します
+fn send_frame_abort(usb: &SharedUsb, event: &FrameAbort) {
+    let mac_str = format_mac_address(&event.mac);
+    warn!(
+        "Frame aborted for {}: frame_id={}, bytes={}, reason={}",
+        mac_str,
+        event.frame_id,
+        event.byte_count,
+        event.reason.as_str()
+    );
+
+    ABORT_EVENTS_SENT.fetch_add(1, Ordering::Relaxed);
+    admission::finish_transfer(&event.mac);
+    record_hop_result(&mac_str, false, None);
+    if let Ok(mut summary) = FLEET_SUMMARY.lock() {
+        summary.record_abort(&event.mac);
+    }
+    if let Err(usb_err) = send_usb_frame(usb, &event.to_frame(), &mac_str) {
+        error!("USB transfer failed for abort event of {}: {}", mac_str, usb_err);
+    }
+}
+
+/// コマンド処理タスク
+///
+/// USBからコマンドを読み取り、スリープコマンドはデバイスごとの許容範囲を確認してから
+/// メンテナンスタスクへ転送します。
+fn run_command_handler(usb: SharedUsb, sleep_tx: SyncSender<SleepCommand>, sleep_policies: SleepPolicyRegistry) {
+    loop {
+        // ロックは読み取り中のみ保持し、USB送信タスクを長時間ブロックしない
+        let read_result = lock_usb(&usb).read_command(COMMAND_READ_TIMEOUT_MS);
+
+        match read_result {
+            Ok(Some(command_str)) => {
+                info!("=== Received USB command: '{}' ===", command_str);
+                handle_command(&usb, &sleep_tx, &sleep_policies, &command_str);
+            }
+            Ok(None) => {
+                // コマンドなし
+            }
+            Err(e) => {
+                error!("Error reading USB command: {:?}", e);
+                std::thread::sleep(Duration::from_millis(50));
+            }
+        }
+    }
+}
+
+/// 受信したコマンド文字列を解析して処理します
+fn handle_command(
+    usb: &SharedUsb,
+    sleep_tx: &SyncSender<SleepCommand>,
+    sleep_policies: &SleepPolicyRegistry,
+    command_str: &str,
+) {
+    match parse_command(command_str) {
+        Ok(Command::SendEspNow { mac_address, sleep_seconds: requested }) => {
+            info!("Processing ESP-NOW send command: {} -> {}s", mac_address, requested);
+            match mac_address.parse::<MacAddress>() {
+                Ok(mac) => {
+                    queue_sleep_command(usb, sleep_tx, sleep_policies, mac.into_bytes(), requested);
+                }
+                Err(e) => error!("Invalid MAC address in sleep command '{}': {}", mac_address, e),
+            }
+        }
+        Ok(Command::ListDevices) => match DEVICE_DIRECTORY.lock() {
+            Ok(directory) => write_response(usb, &directory.response()),
+            Err(_) => error!("Device directory lock poisoned"),
+        },
+        Ok(Command::Pause { mac_address, minutes }) => match mac_address.parse::<MacAddress>() {
+            Ok(mac) => update_pause(usb, &mac.into_bytes(), |paused, mac, now| {
+                paused.pause(*mac, Duration::from_secs(u64::from(minutes) * 60), now);
+                info!("Device {} paused for {}min", mac_address, minutes);
+            }),
+            Err(e) => error!("Invalid MAC address in pause command '{}': {}", mac_address, e),
+        },
+        Ok(Command::Resume { mac_address }) => match mac_address.parse::<MacAddress>() {
+            Ok(mac) => update_pause(usb, &mac.into_bytes(), |paused, mac, now| {
+                if paused.resume(mac, now) {
+                    info!("Device {} resumed", mac_address);
+                } else {
+                    warn!("Device {} was not paused", mac_address);
+                }
+            }),
+            Err(e) => error!("Invalid MAC address in resume command '{}': {}", mac_address, e),
+        },
+        Ok(Command::SetQuality { mac_address, settings }) => match mac_address.parse::<MacAddress>() {
+            Ok(mac) => match CAMERA_SETTINGS.lock() {
+                Ok(mut camera_settings) => {
+                    let mac = mac.into_bytes();
+                    info!("Camera settings for {} queued: {}", mac_address, settings.encode());
+                    camera_settings.request(mac, settings);
+                    write_response(usb, &camera_settings.response(&mac));
+                }
+                Err(_) => error!("Camera settings registry lock poisoned"),
+            },
+            Err(e) => error!("Invalid MAC address in quality command '{}': {}", mac_address, e),
+        },
+        Ok(Command::SetDebug { mac_address, request }) => match mac_address.parse::<MacAddress>() {
+            Ok(mac) => match DEBUG_REQUESTS.lock() {
+                Ok(mut debug_requests) => {
+                    let mac = mac.into_bytes();
+                    info!("Debug flags for {} queued: {}", mac_address, request.encode());
+                    debug_requests.request(mac, request);
+                    write_response(usb, &debug_requests.response(&mac));
+                }
+                Err(_) => error!("Debug flag registry lock poisoned"),
+            },
+            Err(e) => error!("Invalid MAC address in debug command '{}': {}", mac_address, e),
+        },
+        Ok(Command::ResendLast { mac_address }) => match mac_address.parse::<MacAddress>() {
+            Ok(mac) => match RESEND_REQUESTS.lock() {
+                Ok(mut resend_requests) => {
+                    let mac = mac.into_bytes();
+                    info!("Resend of the last image requested for {}", mac_address);
+                    resend_requests.request(mac);
+                    write_response(usb, &resend_requests.response(&mac));
+                }
+                Err(_) => error!("Resend request lock poisoned"),
+            },
+            Err(e) => error!("Invalid MAC address in resend command '{}': {}", mac_address, e),
+        },
+        Ok(Command::GetThumbs { mac_address }) => match mac_address.parse::<MacAddress>() {
+            Ok(mac) => match THUMBNAIL_REQUESTS.lock() {
+                Ok(mut thumbnail_requests) => {
+                    let mac = mac.into_bytes();
+                    info!("Thumbnails requested for {}", mac_address);
+                    thumbnail_requests.request(mac);
+                    write_response(usb, &thumbnail_requests.response(&mac));
+                }
+                Err(_) => error!("Thumbnail request lock poisoned"),
+            },
+            Err(e) => error!("Invalid MAC address in thumbnail command '{}': {}", mac_address, e),
+        },
+        Ok(Command::RotateKey { mac_address }) => match mac_address.parse::<MacAddress>() {
+            Ok(mac) => match KEY_ROTATIONS.lock() {
+                Ok(mut key_rotations) => {
+                    let mac = mac.into_bytes();
+                    match key_rotations.schedule(mac, generate_downlink_key()) {
+                        Some(epoch) => info!("Downlink key rotation for {} queued (epoch {})", mac_address, epoch),
+                        None => warn!("Downlink key rotation for {} is already in progress", mac_address),
+                    }
+                    write_response(usb, &key_rotations.response(&mac));
+                }
+                Err(_) => error!("Key rotation registry lock poisoned"),
+            },
+            Err(e) => error!("Invalid MAC address in key rotation command '{}': {}", mac_address, e),
+        },
+        Ok(Command::SetChannel { mac_address, channel }) => match mac_address.parse::<MacAddress>() {
+            Ok(mac) => match RELEASE_CHANNELS.lock() {
+                Ok(mut release) => {
+                    let mac = mac.into_bytes();
+                    if release.set_channel(mac, channel) {
+                        info!("Device {} moved to the {} channel", mac_address, channel.as_str());
+                    }
+                    write_response(usb, &release.channel_response(&mac));
+                }
+                Err(_) => error!("Release channel lock poisoned"),
+            },
+            Err(e) => error!("Invalid MAC address in channel command '{}': {}", mac_address, e),
+        },
+        Ok(Command::SetRollout { channel, firmware }) => update_rollout(usb, channel, firmware),
+        Ok(Command::GroupAdd { group, mac_addresses }) => {
+            let macs: Vec<[u8; 6]> = mac_addresses
+                .iter()
+                .filter_map(|mac_address| mac_address.parse::<MacAddress>().ok())
+                .map(MacAddress::into_bytes)
+                .collect();
+            match DEVICE_GROUPS.lock() {
+                Ok(mut groups) => {
+                    let moved = groups.add(&group, &macs);
+                    info!(
+                        "{} device(s) added to group {} ({} moved from another group)",
+                        macs.len(),
+                        group,
+                        moved
+                    );
+                    write_response(usb, &groups.members_response(&group));
+                }
+                Err(_) => error!("Device group lock poisoned"),
+            }
+        }
+        Ok(Command::GroupSleep { group, sleep_seconds }) => {
+            info!("Processing group sleep command: {} -> {}s", group, sleep_seconds);
+            let action = format!("sleep={}", sleep_seconds);
+            queue_group_sleep(usb, sleep_tx, sleep_policies, &group, sleep_seconds, &action);
+        }
+        Ok(Command::GroupCapture { group }) => {
+            info!("Processing group capture command: {}", group);
+            queue_group_sleep(usb, sleep_tx, sleep_policies, &group, GROUP_CAPTURE_SLEEP_SECONDS, "capture");
+        }
+        Ok(Command::PushFile {
+            mac_address,
+            slot,
+            size,
+            file_crc,
+        }) => match mac_address.parse::<MacAddress>() {
+            Ok(mac) => match FILE_TRANSFERS.lock() {
+                Ok(mut transfers) => {
+                    let mac = mac.into_bytes();
+                    info!("File transfer of {} ({} bytes) to {} started", slot, size, mac_address);
+                    transfers.start(mac, slot, size, file_crc);
+                    write_response(usb, &transfers.response(&mac));
+                }
+                Err(_) => error!("File transfer registry lock poisoned"),
+            },
+            Err(e) => error!("Invalid MAC address in file transfer command '{}': {}", mac_address, e),
+        },
+        Ok(Command::FileData {
+            mac_address,
+            offset,
+            data,
+        }) => match mac_address.parse::<MacAddress>() {
+            Ok(mac) => match FILE_TRANSFERS.lock() {
+                Ok(mut transfers) => {
+                    let mac = mac.into_bytes();
+                    match transfers.append(&mac, offset, &data) {
+                        Ok(()) => {
+                            if transfers.is_pending(&mac) {
+                                info!("File for {} received and verified; delivering on its next transfer", mac_address);
+                            }
+                            write_response(usb, &transfers.response(&mac));
+                        }
+                        Err(e) => {
+                            warn!("File data for {} rejected: {}", mac_address, e);
+                            write_response(usb, &format!("{}{}\n", ERROR_RESPONSE_PREFIX, e));
+                        }
+                    }
+                }
+                Err(_) => error!("File transfer registry lock poisoned"),
+            },
+            Err(e) => error!("Invalid MAC address in file data command '{}': {}", mac_address, e),
+        },
+        Ok(Command::BroadcastConfig { config }) => match BROADCASTS.lock() {
+            Ok(mut broadcasts) => {
+                info!("Broadcast config queued: {}", config);
+                broadcasts.start(config);
+                write_response(usb, &broadcasts.response());
+            }
+            Err(_) => error!("Broadcast tracker lock poisoned"),
+        },
+        Ok(Command::BroadcastStatus) => match BROADCASTS.lock() {
+            Ok(broadcasts) => write_response(usb, &broadcasts.response()),
+            Err(_) => error!("Broadcast tracker lock poisoned"),
+        },
+        Ok(Command::SoakReport) => match SOAK_REPORTS.lock() {
+            Ok(reports) => write_response(usb, &reports.response()),
+            Err(_) => error!("Soak report lock poisoned"),
+        },
+        Ok(Command::GetStats) => match LIFETIME_STATS.lock() {
+            Ok(stats) => {
+                let mut response = stats.response(lifetime_stats::since_boot(lifetime_stats::uptime_s()));
+                drop(stats);
+                if let Ok(frame_stats) = FRAME_STATS.lock() {
+                    response.push_str(&frame_stats.response());
+                }
+                write_response(usb, &response);
+            }
+            Err(_) => error!("Lifetime stats lock poisoned"),
+        },
+        Ok(Command::MemoryTrend) => match MEMORY_TREND.lock() {
+            Ok(trend) => write_response(usb, &trend.response()),
+            Err(_) => error!("Memory trend lock poisoned"),
+        },
+        Ok(Command::DeadLetterList) => match DEAD_LETTERS.lock() {
+            Ok(dead_letters) => write_response(usb, &dead_letters.list_response(Instant::now())),
+            Err(_) => error!("Dead-letter queue lock poisoned"),
+        },
+        Ok(Command::DeadLetterReplay { id }) => replay_dead_letters(usb, id),
+        Ok(Command::Help) => {
+            write_response(usb, &command::help_text());
+        }
+        Ok(Command::Chaos { params }) => update_chaos(usb, params),
+        Ok(Command::Emulate { devices, interval_s }) => update_emulation(usb, devices, interval_s),
+        Ok(Command::SoftReset) => {
+            info!("Soft reset requested; shutting down subsystems");
+            write_response(usb, &ShutdownReason::SoftReset.response());
+            run_shutdown(ShutdownReason::SoftReset);
+            sys::restart();
+        }
+        Ok(Command::Unknown(cmd)) => {
+            warn!("Unknown command received: '{}'", cmd);
+        }
+        Err(e) => {
+            error!("Failed to parse command '{}': {}", command_str, e);
+            write_response(usb, &e.to_response());
+        }
+    }
+}
+
+/// デバイスへのスリープコマンドをメンテナンスタスクのキューへ渡し、渡せた場合はtrueを返します
+///
+/// デバイスごとの許容範囲を確認し（範囲外は拒否してUSBへエラー応答、または範囲内へ補正）、
+/// 一時停止中は停止の残り時間で上書きします。
+fn queue_sleep_command(
+    usb: &SharedUsb,
+    sleep_tx: &SyncSender<SleepCommand>,
+    sleep_policies: &SleepPolicyRegistry,
+    mac: [u8; 6],
+    requested: u32,
+) -> bool {
+    let mac_address = format_mac_address(&mac);
+    if is_emulated_mac(&mac) {
+        info!("Sleep command for emulated device {} dropped ({}s)", mac_address, requested);
+        return false;
+    }
+    let sleep_seconds = match sleep_policies.policy_for(&mac).apply(mac, requested) {
+        Ok(seconds) => seconds,
+        Err(violation) => {
+            error!("✗ Sleep command rejected: {}", violation);
+            write_response(usb, &violation.to_response());
+            return false;
+        }
+    };
+    if sleep_seconds != requested {
+        warn!(
+            "Sleep time for {} clamped to the allowed range: {}s -> {}s",
+            mac_address, requested, sleep_seconds
+        );
+    }
+
+    // 一時停止中は停止の残り時間で上書きする
+    let paused_seconds = PAUSED_DEVICES
+        .lock()
+        .ok()
+        .and_then(|mut paused| paused.sleep_seconds_for(&mac, Instant::now()));
+    let sleep_seconds = match paused_seconds {
+        Some(paused_seconds) => {
+            warn!(
+                "Device {} is paused; overriding sleep time {}s -> {}s",
+                mac_address, sleep_seconds, paused_seconds
+            );
+            paused_seconds
+        }
+        None => sleep_seconds,
+    };
+
+    // スリープコマンドをメンテナンスタスクのキューへ渡す（直接送信せず）
+    match sleep_tx.try_send(SleepCommand::new(mac_address.clone(), sleep_seconds)) {
+        Ok(()) => {
+            info!("✓ Sleep command queued for {}: {}s", mac_address, sleep_seconds);
+            if let Ok(mut window) = DOWNLINK_WINDOW.lock() {
+                window.record_sleep_command(mac);
+            }
+            true
+        }
+        Err(TrySendError::Full(_)) => {
+            error!("✗ Failed to queue sleep command for {}: channel full", mac_address);
+            false
+        }
+        Err(TrySendError::Disconnected(_)) => {
+            error!("✗ Failed to queue sleep command for {}: maintenance task stopped", mac_address);
+            false
+        }
+    }
+}
+
+/// グループのメンバーごとにスリープコマンドをキューへ渡し、渡せた数をUSBへ応答します
+fn queue_group_sleep(
+    usb: &SharedUsb,
+    sleep_tx: &SyncSender<SleepCommand>,
+    sleep_policies: &SleepPolicyRegistry,
+    group: &str,
+    sleep_seconds: u32,
+    action: &str,
+) {
+    let members = match DEVICE_GROUPS.lock() {
+        Ok(groups) => groups.members(group),
+        Err(_) => {
+            error!("Device group lock poisoned");
+            return;
+        }
+    };
+    if members.is_empty() {
+        warn!("Group {} has no devices", group);
+    }
+    let queued = members
+        .iter()
+        .filter(|mac| queue_sleep_command(usb, sleep_tx, sleep_policies, **mac, sleep_seconds))
+        .count();
+    write_response(usb, &dispatch_response(group, action, queued, members.len()));
+}
+
+/// チャンネルの展開ファームウェアを設定し、登録デバイスの進み具合をUSBへ応答します
+fn update_rollout(usb: &SharedUsb, channel: ReleaseChannel, firmware: Option<String>) {
+    let devices: Vec<([u8; 6], Option<String>)> = match (FLEET_SUMMARY.lock(), DEVICE_DIRECTORY.lock()) {
+        (Ok(summary), Ok(directory)) => summary
+            .macs()
+            .map(|mac| (mac, directory.get(&mac).map(|info| info.firmware.clone())))
+            .collect(),
+        _ => {
+            error!("Fleet summary or device directory lock poisoned");
+            return;
+        }
+    };
+    let response = match RELEASE_CHANNELS.lock() {
+        Ok(mut release) => {
+            match &firmware {
+                Some(firmware) => info!("Rolling out {} to the {} channel", firmware, channel.as_str()),
+                None => info!("Rollout to the {} channel withdrawn", channel.as_str()),
+            }
+            release.set_manifest(channel, firmware);
+            let progress = release.progress(devices.iter().map(|(mac, fw)| (*mac, fw.as_deref())));
+            release.rollout_response(&progress)
+        }
+        Err(_) => {
+            error!("Release channel lock poisoned");
+            return;
+        }
+    };
+    write_response(usb, &response);
+}
+
+/// 一時停止の登録を更新し、デバイスの状態をUSBへ応答します
+fn update_pause(usb: &SharedUsb, mac: &[u8; 6], update: impl FnOnce(&mut PauseRegistry, &[u8; 6], Instant)) {
+    let response = match PAUSED_DEVICES.lock() {
+        Ok(mut paused) => {
+            let now = Instant::now();
+            update(&mut paused, mac, now);
+            paused.response(mac, now)
+        }
+        Err(_) => {
+            error!("Pause registry lock poisoned");
+            return;
+        }
+    };
+    write_response(usb, &response);
+}
+
+/// 受信フレームへの故障注入を変更し、状況をUSBへ応答します（`params` がNoneなら状況のみ）
+#[cfg(feature = "chaos")]
+fn update_chaos(usb: &SharedUsb, params: Option<Option<ChaosParams>>) {
+    let response = match CHAOS.lock() {
+        Ok(mut chaos) => {
+            if let Some(params) = params {
+                match params {
+                    Some(params) => warn!("Fault injection enabled: {}", params.encode()),
+                    None => info!("Fault injection disabled"),
+                }
+                chaos.configure(params);
+            }
+            chaos.response()
+        }
+        Err(_) => {
+            error!("Chaos registry lock poisoned");
+            return;
+        }
+    };
+    write_response(usb, &response);
+}
+
+/// `chaos` フィーチャーなしのビルドでは故障注入を受け付けません
+#[cfg(not(feature = "chaos"))]
+fn update_chaos(usb: &SharedUsb, _params: Option<Option<ChaosParams>>) {
+    warn!("CHAOS command ignored: built without the `chaos` feature");
+    write_response(usb, &format!("{}chaos feature is not enabled in this build\n", ERROR_RESPONSE_PREFIX));
+}
+
+/// 仮想デバイスのエミュレーションを開始・停止し、状況をUSBへ応答します
+fn update_emulation(usb: &SharedUsb, devices: usize, interval_s: u32) {
+    let response = match EMULATOR.lock() {
+        Ok(mut emulator) => {
+            emulator.configure(devices, interval_s, Instant::now());
+            if devices > 0 {
+                warn!("Emulating {} synthetic device(s) every {}s", devices, interval_s);
+            } else {
+                info!("Device emulation stopped");
+            }
+            emulator.response()
+        }
+        Err(_) => {
+            error!("Emulator lock poisoned");
+            return;
+        }
+    };
+    write_response(usb, &response);
+}
+
+/// 予定時刻を過ぎた仮想デバイスの転送を、受信したフレームと同じくデータキューへ投入します
+fn inject_emulated_transfers() {
+    let transfers = match EMULATOR.lock() {
+        Ok(mut emulator) if emulator.is_active() => emulator.poll(Instant::now()),
+        Ok(_) => return,
+        Err(_) => {
+            error!("Emulator lock poisoned");
+            return;
+        }
+    };
+    for transfer in transfers {
+        let mac_str = format_mac_str(&transfer.mac);
+        info!("Injecting synthetic transfer from {} ({} frames)", mac_str, transfer.frames.len());
+        for frame in transfer.frames {
+            let frame_kind = FrameKind::of_frame(&frame);
+            frame_stats::record(&transfer.mac, frame_kind, FrameOutcome::Received);
+            let received_data = ReceivedData {
+                mac: transfer.mac,
+                data: frame,
+                epoch: cancellation::current_epoch(&transfer.mac),
+                received_at: Instant::now(),
+                rssi: None,
+                urgent: false,
+            };
+            if let Err(e) = data_queue::enqueue(received_data) {
+                frame_stats::record(&transfer.mac, frame_kind, FrameOutcome::Errored);
+                warn!("Dropped synthetic {:?} frame from {}: {}", frame_kind, mac_str, e);
+            }
+        }
+    }
+}
+
+/// コマンド応答をUSBへ書き込みます
+fn write_response(usb: &SharedUsb, response: &str) {
+    if let Err(e) = lock_usb(usb).write(response.as_bytes(), RESPONSE_WRITE_TIMEOUT_MS) {
+        error!("Failed to send command response: {}", e);
+    }
+}
+
+/// メンテナンスタスク
+///
+/// スリープコマンドキューを所有し、送信間隔を守りながらESP-NOWで送信します。
+/// 併せてデータキュー使用量と制御メッセージの送信時間を統計フレームとして定期的に送出し、
+/// 再起動をまたぐ累積統計を `PERSIST_INTERVAL` ごとにNVSへ保存します。
+/// 統計フレームと累積統計の保存は、受信途中の転送がある間は `MAINTENANCE_MAX_DEFERRAL` まで延期します。
+/// 空きヒープを1分ごとに記録し、枯渇が予測の範囲内に迫ったら統計フレームで警告します。
+fn run_maintenance(
+    usb: SharedUsb,
+    mut esp_now_sender: EspNowSender,
+    sleep_rx: Receiver<SleepCommand>,
+    lifetime_store: Option<Arc<Mutex<LifetimeStatsStore>>>,
+) {
+    let mut sleep_queue = SleepCommandQueue::new();
+    let started = Instant::now();
+    let max_deferral_ms = MAINTENANCE_MAX_DEFERRAL.as_millis() as u64;
+    let mut stats_window = MaintenanceWindow::new(STATS_REPORT_INTERVAL.as_millis() as u64, max_deferral_ms, 0);
+    let mut persist_window = MaintenanceWindow::new(PERSIST_INTERVAL.as_millis() as u64, max_deferral_ms, 0);
+    let mut stats_sequence: u32 = 0;
+    let mut cpu_sampler = CpuSampler::new();
+    let mut cpu_monitor = config::cpu_warn_percent().map(|limit| CpuLimitMonitor::new(limit, CPU_SUSTAIN_SAMPLES));
+    let mut cpu_usage: Option<CpuUsage> = None;
+    let mut last_cpu_sample = Instant::now();
+    let fleet_summary_interval = config::fleet_summary_interval();
+    let mut last_fleet_summary = Instant::now();
+    let memory_trend_horizon = config::memory_trend_horizon();
+    let mut last_memory_sample: Option<Instant> = None;
+    #[cfg(feature = "channel-monitor")]
+    let mut channel_sampler = ChannelSampler::new();
+    #[cfg(feature = "channel-monitor")]
+    let mut last_channel_sample = Instant::now();
+    if read_task_runtimes().is_none() {
+        warn!("FreeRTOS run-time stats are unavailable; CPU usage is not reported");
+    }
+
+    loop {
+        let wait = if sleep_queue.is_empty() {
+            MAINTENANCE_IDLE_WAIT
+        } else {
+            Duration::from_millis(SLEEP_COMMAND_INTERVAL_MS as u64)
+        };
+
+        match sleep_rx.recv_timeout(wait) {
+            Ok(command) => {
+                if let Err(e) = sleep_queue.enqueue(command.mac_address, command.sleep_seconds) {
+                    error!("✗ Failed to queue sleep command: {}", e);
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => {
+                error!("Sleep command channel disconnected");
+                std::thread::sleep(MAINTENANCE_IDLE_WAIT);
+            }
+        }
+
+        inject_emulated_transfers();
+        send_due_broadcast(&usb, &mut esp_now_sender);
+        send_due_camera_settings(&mut esp_now_sender);
+        send_due_debug_flags(&mut esp_now_sender);
+        send_due_key_rotations(&usb, &mut esp_now_sender);
+        send_due_file_chunks(&mut esp_now_sender);
+        send_resend_requests(&mut esp_now_sender);
+        sleep_queue.process_queue(&mut esp_now_sender);
+        retune_channel(!sleep_queue.is_empty());
+
+        if last_cpu_sample.elapsed() >= CPU_SAMPLE_INTERVAL {
+            last_cpu_sample = Instant::now();
+            if let Some(usage) = read_task_runtimes().and_then(|tasks| cpu_sampler.sample(&tasks)) {
+                let sustained = cpu_monitor
+                    .as_mut()
+                    .and_then(|monitor| monitor.observe(usage.busy_percent).then(|| monitor.limit_percent()));
+                if let Some(limit_percent) = sustained {
+                    send_cpu_warning(&usb, &usage, limit_percent, stats_sequence);
+                    stats_sequence = stats_sequence.wrapping_add(1);
+                }
+                cpu_usage = Some(usage);
+            }
+        }
+
+        #[cfg(feature = "channel-monitor")]
+        if last_channel_sample.elapsed() >= CHANNEL_SAMPLE_INTERVAL {
+            last_channel_sample = Instant::now();
+            sample_channel(&mut channel_sampler);
+        }
+
+        if last_memory_sample.is_none_or(|sampled| sampled.elapsed() >= MEMORY_SAMPLE_INTERVAL) {
+            last_memory_sample = Some(Instant::now());
+            if let Some(forecast) = sample_memory(memory_trend_horizon) {
+                send_memory_warning(&usb, &forecast, memory_trend_horizon.unwrap_or_default(), stats_sequence);
+                stats_sequence = stats_sequence.wrapping_add(1);
+            }
+        }
+
+        let now_ms = started.elapsed().as_millis() as u64;
+        let transfer_active = ACTIVE_TRANSFERS.load(Ordering::Relaxed) > 0;
+        if stats_window.poll(now_ms, transfer_active).should_run() {
+            let mut deferral = stats_window.stats();
+            deferral.merge(persist_window.stats());
+            stats_window.reset_stats();
+            persist_window.reset_stats();
+            send_stats_report(&usb, &esp_now_sender, cpu_usage.as_ref(), deferral, stats_sequence);
+            stats_sequence = stats_sequence.wrapping_add(1);
+        }
+
+        if fleet_summary_interval.is_some_and(|interval| last_fleet_summary.elapsed() >= interval) {
+            send_fleet_summary(&usb, stats_sequence);
+            stats_sequence = stats_sequence.wrapping_add(1);
+            last_fleet_summary = Instant::now();
+        }
+
+        if persist_window.poll(now_ms, transfer_active).should_run() {
+            if let Some(store) = &lifetime_store {
+                persist_lifetime_stats(store);
+            }
+        }
+    }
+}
+
+/// 再起動をまたぐ累積統計をNVSへ保存します（失敗しても次の周期で再試行）
+fn persist_lifetime_stats(store: &Mutex<LifetimeStatsStore>) {
+    let mut store = store.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    match store.save() {
+        Ok(record) => debug!(
+            "Lifetime stats saved: boots={}, uptime={}s, frames={}, errors={}",
+            record.boots, record.uptime_s, record.frames, record.errors
+        ),
+        Err(e) => warn!("Failed to save lifetime stats: {:?}", e),
+    }
+}
+
+/// チャンネルの混雑度を計測し、統計フレーム用に保持してフリートサマリーに記録します
+#[cfg(feature = "channel-monitor")]
+fn sample_channel(sampler: &mut ChannelSampler) {
+    let Ok(channel) = sys::wifi_channel() else {
+        return;
+    };
+    let Some(utilization) = sampler.sample(channel_monitor::counters(), channel, sys::tick_ms() * 1000) else {
+        return;
+    };
+    debug!(
+        "Channel {} busy {}% ({} frames/s, {}% foreign)",
+        utilization.channel, utilization.busy_percent, utilization.frames_per_s, utilization.foreign_percent
+    );
+    if let Ok(mut summary) = FLEET_SUMMARY.lock() {
+        summary.record_channel(&utilization);
+    }
+    if let Ok(mut latest) = CHANNEL_UTILIZATION.lock() {
+        *latest = Some(utilization);
+    }
+}
+
+/// 登録デバイスの定期サマリーをUSBへ送出します
+///
+/// 未完了のコマンドは未適用のカメラ画質設定・デバッグフラグ・一斉配信とファイル転送を数えます。
+/// 鍵の更新は `key_epoch`・`key_rotation` で別に報告します。
+fn send_fleet_summary(usb: &SharedUsb, sequence: u32) {
+    let pending_commands = |mac: &[u8; 6]| {
+        let camera = CAMERA_SETTINGS.lock().is_ok_and(|registry| registry.is_pending(mac));
+        let debug = DEBUG_REQUESTS.lock().is_ok_and(|registry| registry.is_pending(mac));
+        let broadcast = BROADCASTS.lock().is_ok_and(|broadcasts| broadcasts.is_pending(mac));
+        let file = FILE_TRANSFERS.lock().is_ok_and(|transfers| transfers.is_pending(mac));
+        u32::from(camera) + u32::from(debug) + u32::from(broadcast) + u32::from(file)
+    };
+    let key_status = |mac: &[u8; 6]| match KEY_ROTATIONS.lock() {
+        Ok(registry) => registry.status(mac),
+        Err(_) => KeyStatus { epoch: 0, rotation: None },
+    };
+    let firmware = |mac: &[u8; 6]| match DEVICE_DIRECTORY.lock() {
+        Ok(directory) => directory.get(mac).map(|info| info.firmware.clone()),
+        Err(_) => None,
+    };
+    let release = match RELEASE_CHANNELS.lock() {
+        Ok(release) => release.clone(),
+        Err(_) => ReleaseChannels::new(),
+    };
+    let groups = match DEVICE_GROUPS.lock() {
+        Ok(groups) => groups.clone(),
+        Err(_) => DeviceGroups::new(),
+    };
+    let frame_stats = match FRAME_STATS.lock() {
+        Ok(frame_stats) => frame_stats.clone(),
+        Err(_) => FrameStats::new(),
+    };
+    let payload = match FLEET_SUMMARY.lock() {
+        Ok(mut summary) => summary.take_payload(
+            Instant::now(),
+            clock::now_unix_ms(),
+            pending_commands,
+            key_status,
+            &release,
+            &groups,
+            firmware,
+            &frame_stats,
+        ),
+        Err(_) => return,
+    };
+    info!("Fleet summary: {} bytes", payload.len());
+    let mac_str = format_mac_address(&GATEWAY_STATS_MAC);
+    if let Err(usb_err) = send_usb_frame(usb, &fleet_summary::to_frame(&payload, sequence), &mac_str) {
+        error!("USB transfer failed for fleet summary: {}", usb_err);
+    }
+}
+
+/// 予約された設定の一斉配信を送信します
+///
+/// 初回送信で払い出した設定IDを記録します。署名器がなければ配信を取り消してUSBへエラーを返します。
+fn send_due_broadcast(usb: &SharedUsb, esp_now_sender: &mut EspNowSender) {
+    let Some(pending) = BROADCASTS.lock().ok().and_then(|mut broadcasts| broadcasts.take_due()) else {
+        return;
+    };
+    match esp_now_sender.send_broadcast_config(pending.config_id, &pending.config) {
+        Ok(config_id) => {
+            if let Ok(mut broadcasts) = BROADCASTS.lock() {
+                broadcasts.assign_id(config_id);
+            }
+        }
+        Err(EspNowSendError::SignerRequired) => {
+            error!("✗ Broadcast config requires downlink_auth_key; cancelled");
+            if let Ok(mut broadcasts) = BROADCASTS.lock() {
+                broadcasts.cancel();
+            }
+            write_response(
+                usb,
+                &format!("{}broadcast config requires downlink_auth_key\n", ERROR_RESPONSE_PREFIX),
+            );
+        }
+        Err(e) => warn!("✗ Failed to broadcast config (retried on the next check-in): {:?}", e),
+    }
+}
+
+/// 転送を終えたデバイスへ未適用のカメラ画質設定を送信します
+///
+/// 届かなかった場合は次の転送完了時に送り直します。
+fn send_due_camera_settings(esp_now_sender: &mut EspNowSender) {
+    while let Some((mac, settings)) = CAMERA_SETTINGS.lock().ok().and_then(|mut registry| registry.take_due()) {
+        if let Err(e) = esp_now_sender.send_config_update(mac, &settings.encode()) {
+            warn!("✗ Failed to send camera settings to {}: {:?}", format_mac_address(&mac), e);
+        }
+    }
+}
+
+/// 転送を終えたデバイスへ未適用のデバッグフラグを送信します
+fn send_due_debug_flags(esp_now_sender: &mut EspNowSender) {
+    while let Some((mac, request)) = DEBUG_REQUESTS.lock().ok().and_then(|mut registry| registry.take_due()) {
+        if let Err(e) = esp_now_sender.send_config_update(mac, &request.encode()) {
+            warn!("✗ Failed to send debug flags to {}: {:?}", format_mac_address(&mac), e);
+        }
+    }
+}
+
+/// 転送を終えたデバイスへファイルの断片を送信します
+///
+/// 届かなかった断片があればそのデバイスへの今回の送信をやめ、次の転送完了時にデバイスが報告した位置から再開します。
+fn send_due_file_chunks(esp_now_sender: &mut EspNowSender) {
+    while let Some(chunk) = FILE_TRANSFERS.lock().ok().and_then(|mut transfers| transfers.take_due()) {
+        if let Err(e) = esp_now_sender.send_file_chunk(&chunk) {
+            warn!(
+                "✗ Failed to send file chunk {}@{} to {} (resumed on the next check-in): {:?}",
+                chunk.slot,
+                chunk.offset,
+                format_mac_address(&chunk.mac),
+                e
+            );
+            if let Ok(mut transfers) = FILE_TRANSFERS.lock() {
+                transfers.stop(&chunk.mac);
+            }
+        }
+    }
+}
+
+/// ハードウェア乱数で新しいダウンリンク認証鍵を生成します（Wi-Fi起動中は暗号論的に安全な乱数）
+fn generate_downlink_key() -> DownlinkKey {
+    let mut bytes = [0u8; DOWNLINK_KEY_LEN];
+    sys::fill_random(&mut bytes);
+    DownlinkKey::from_bytes(bytes)
+}
+
+/// 転送を終えたデバイスへ未適用の鍵更新を送信し、鍵の記録をNVSへ保存します
+///
+/// 届かなかった場合は次の転送完了時に送り直します。署名器がなければ更新を取り消してUSBへエラーを返します。
+fn send_due_key_rotations(usb: &SharedUsb, esp_now_sender: &mut EspNowSender) {
+    while let Some(delivery) = KEY_ROTATIONS.lock().ok().and_then(|mut registry| registry.take_due()) {
+        match esp_now_sender.send_key_rotation(&delivery) {
+            Ok(activate_counter) => {
+                if let Ok(mut registry) = KEY_ROTATIONS.lock() {
+                    registry.mark_sent(&delivery.mac, delivery.epoch, activate_counter);
+                }
+            }
+            Err(EspNowSendError::SignerRequired) => {
+                error!("✗ Key rotation requires downlink_auth_key; cancelled");
+                if let Ok(mut registry) = KEY_ROTATIONS.lock() {
+                    registry.cancel(&delivery.mac);
+                }
+                write_response(
+                    usb,
+                    &format!("{}key rotation requires downlink_auth_key\n", ERROR_RESPONSE_PREFIX),
+                );
+            }
+            Err(e) => warn!(
+                "✗ Failed to send key rotation to {} (retried on the next check-in): {:?}",
+                format_mac_address(&delivery.mac),
+                e
+            ),
+        }
+    }
+    while let Some((mac, record)) = KEY_ROTATIONS.lock().ok().and_then(|mut registry| registry.take_dirty()) {
+        if let Err(e) = esp_now_sender.store_device_key(mac, &record) {
+            warn!("✗ Downlink key record for {} was not saved: {:?}", format_mac_address(&mac), e);
+        }
+    }
+}
+
+/// 予定のスロットに合わせて受信チャンネルを切り替えます
+///
+/// 転送中のデバイスや送信待ちのダウンリンク（`pending_downlink`）がある間は切り替えず、
+/// 応答を待つデバイスが同じチャンネルで受け取れるようにします。
+fn retune_channel(pending_downlink: bool) {
+    if pending_downlink {
+        return;
+    }
+    let in_flight = match ADMISSION.lock() {
+        Ok(mut admission) => admission.in_flight(Instant::now()),
+        Err(_) => return,
+    };
+    let Ok(mut hop) = CHANNEL_HOP.lock() else {
+        return;
+    };
+    let Some(hopper) = hop.as_mut() else {
+        return;
+    };
+    match hopper.retune(&EspSys, Instant::now(), in_flight) {
+        Ok(Some(channel)) => debug!("Receive channel switched to {}", channel),
+        Ok(None) => {}
+        Err(e) => warn!("Failed to switch receive channel to {}: {}", hopper.scheduled_channel(Instant::now()), e),
+    }
+}
+
+/// コマンド待機中のデバイスへ画像の再送要求とサムネイル要求を送信します
+///
+/// 届かなかった要求は送り直しません（デバイスは待機を終えると画像を破棄するため）。
+fn send_resend_requests(esp_now_sender: &mut EspNowSender) {
+    while let Some(mac) = RESEND_REQUESTS.lock().ok().and_then(|mut requests| requests.take_next()) {
+        if let Err(e) = esp_now_sender.send_resend_last(mac) {
+            warn!("✗ Failed to send resend request to {}: {:?}", format_mac_address(&mac), e);
+        }
+    }
+    while let Some(mac) = THUMBNAIL_REQUESTS.lock().ok().and_then(|mut requests| requests.take_next()) {
+        if let Err(e) = esp_now_sender.send_get_thumbs(mac) {
+            warn!("✗ Failed to send thumbnail request to {}: {:?}", format_mac_address(&mac), e);
+        }
+    }
+}
+
+/// デバイスへ送信待ちのコマンド数（設定更新・デバッグフラグ・再送要求・サムネイル要求・鍵更新・一斉配信・ファイル転送）
+///
+/// ESP-NOWコールバックがHASHフレームへの応答に使用します。
+pub fn queued_downlink_commands(mac: &[u8; 6]) -> usize {
+    [
+        CAMERA_SETTINGS.lock().is_ok_and(|registry| registry.is_pending(mac)),
+        DEBUG_REQUESTS.lock().is_ok_and(|registry| registry.is_pending(mac)),
+        RESEND_REQUESTS.lock().is_ok_and(|requests| requests.is_pending(mac)),
+        THUMBNAIL_REQUESTS.lock().is_ok_and(|requests| requests.is_pending(mac)),
+        KEY_ROTATIONS.lock().is_ok_and(|registry| registry.is_pending(mac)),
+        BROADCASTS.lock().is_ok_and(|broadcasts| broadcasts.is_pending(mac)),
+        FILE_TRANSFERS.lock().is_ok_and(|transfers| transfers.is_pending(mac)),
+    ]
+    .into_iter()
+    .filter(|pending| *pending)
+    .count()
+}
+
+/// 統計フレームをUSBへ送出します
+fn send_stats_report(
+    usb: &SharedUsb,
+    esp_now_sender: &EspNowSender,
+    cpu_usage: Option<&CpuUsage>,
+    deferral: DeferralStats,
+    sequence: u32,
+) {
+    let mut report = StatsReport::new();
+
+    match data_queue::get_queue_usage() {
+        Ok((used, capacity)) => {
+            info!("Data queue usage: {}/{}", used, capacity);
+            report.push("DATA_Q", format_args!("{}/{}", used, capacity));
+        }
+        Err(e) => warn!("Failed to get data queue usage: {}", e),
+    }
+    if let Some(overflow) = data_queue::overflow_stats() {
+        if overflow.items > 0 {
+            info!("Data queue overflow: {} frames, {}/{} bytes", overflow.items, overflow.bytes_used, overflow.bytes_total);
+        }
+        for (key, value) in overflow.tiers.stats_fields() {
+            report.push(key, value);
+        }
+    }
+
+    let latency_stats = esp_now_sender.latency_stats();
+    info!(
+        "ESP-NOW control send latency: avg={}ms max={}ms",
+        latency_stats.average_ms(),
+        latency_stats.max_ms
+    );
+    for (key, value) in latency_stats.stats_fields() {
+        report.push(key, value);
+    }
+    // 転送中のため延期したメンテナンス（延期しすぎていないかの確認用）
+    if deferral.deferred > 0 {
+        info!(
+            "Maintenance deferred {} time(s) (forced {}, max delay {}ms)",
+            deferral.deferred, deferral.forced, deferral.max_delay_ms
+        );
+    }
+    for (key, value) in deferral.stats_fields() {
+        report.push(key, value);
+    }
+    let delivery_stats = esp_now_sender.delivery_stats();
+    info!(
+        "ESP-NOW delivery: ACK ok={} fail={}, SLEEP ok={} fail={}, retries={}, callback timeouts={}",
+        delivery_stats.ack.delivered,
+        delivery_stats.ack.failed,
+        delivery_stats.sleep.delivered,
+        delivery_stats.sleep.failed,
+        delivery_stats.retries,
+        delivery_stats.timeouts
+    );
+    for (key, value) in delivery_stats.stats_fields() {
+        report.push(key, value);
+    }
+    report.push("ABORTS", ABORT_EVENTS_SENT.load(Ordering::Relaxed));
+    report.push("ABORT_DROPPED", ABORTED_CHUNKS_DROPPED.load(Ordering::Relaxed));
+    report.push("PONGS", PONGS_SENT.load(Ordering::Relaxed));
+    report.push("REJECTS", REJECTS_SENT.load(Ordering::Relaxed));
+    if let Ok(mut admission) = ADMISSION.lock() {
+        report.push("IN_FLIGHT", admission.in_flight(Instant::now()));
+        report.push("DEFERRED", admission.take_deferred());
+    }
+    report.push("LEGACY", LEGACY_FRAMES.load(Ordering::Relaxed));
+    if let Ok(mut hop) = CHANNEL_HOP.lock() {
+        if let Some(hopper) = hop.as_mut() {
+            report.push("HOP", hopper.take_stats_field(Instant::now()));
+        }
+    }
+    if let Ok(mut window) = DOWNLINK_WINDOW.lock() {
+        report.push("HASH_ACK", window.take_stats_field());
+    }
+    if let Ok(mut peers) = PEERS.lock() {
+        report.push("PEERS", peers.take_stats_field());
+    }
+    if let Ok(mut dead_letters) = DEAD_LETTERS.lock() {
+        report.push("DLQ", dead_letters.take_stats_field());
+    }
+    let batch_stats = lock_usb(usb).take_stats();
+    report.push("USB_BATCH", format_args!("{}/{}", batch_stats.frames, batch_stats.flushes));
+
+    if let Some(usage) = cpu_usage {
+        info!("CPU usage: {}% ({})", usage.busy_percent, usage.tasks_field());
+        report.push("CPU", usage.busy_percent);
+        report.push("CPU_TASKS", usage.tasks_field());
+    }
+
+    if let Some(utilization) = CHANNEL_UTILIZATION.lock().ok().and_then(|latest| *latest) {
+        info!(
+            "Channel {} busy: {}% ({} frames/s, {}% foreign)",
+            utilization.channel, utilization.busy_percent, utilization.frames_per_s, utilization.foreign_percent
+        );
+        for (key, value) in utilization.stats_fields() {
+            report.push(key, value);
+        }
+    }
+
+    // 直近の送出間隔のパーセンタイル（平均では見えない裾の遅延を確認する）
+    if let Ok(mut latency) = EGRESS_LATENCY.lock() {
+        let processing = latency.processing.percentiles();
+        let usb_transfer = latency.usb_transfer.percentiles();
+        info!(
+            "Egress latency ({} frames): processing p50/p95/p99={}/{}/{}ms, USB p50/p95/p99={}/{}/{}ms",
+            latency.processing.count(),
+            processing.p50_ms,
+            processing.p95_ms,
+            processing.p99_ms,
+            usb_transfer.p50_ms,
+            usb_transfer.p95_ms,
+            usb_transfer.p99_ms
+        );
+        for (key, value) in latency.stats_fields() {
+            report.push(key, value);
+        }
+        *latency = EgressLatency::new();
+    }
+
+    if let Ok(mut resets) = DEVICE_RESETS.lock() {
+        if let Some(field) = resets.stats_field() {
+            report.push("RESETS", field);
+        }
+        resets.clear();
+    }
+
+    if let Ok(mut deadlines) = FRAME_DEADLINES.lock() {
+        for (key, value) in deadlines.stats_fields() {
+            report.push(key, value);
+        }
+        deadlines.reset();
+    }
+
+    // 再起動の検知用（BOOTSの増加やUPTIME_Sの減少で再起動が分かる）
+    if let Ok(stats) = LIFETIME_STATS.lock() {
+        for (key, value) in stats.stats_fields(lifetime_stats::since_boot(lifetime_stats::uptime_s())) {
+            report.push(key, value);
+        }
+    }
+
+    // フレームタイプ別の受信・転送・エラー数（今回の起動からの累積）
+    if let Ok(frame_stats) = FRAME_STATS.lock() {
+        for (key, value) in frame_stats.stats_fields() {
+            report.push(key, value);
+        }
+    }
+
+    // 時刻の出どころ（SNTPで同期済みならUNIX時刻も）
+    if let Ok(clock) = GATEWAY_CLOCK.lock() {
+        for (key, value) in clock.stats_fields(sys::tick_ms()) {
+            report.push(key, value);
+        }
+    }
+
+    let mac_str = format_mac_address(&GATEWAY_STATS_MAC);
+    if let Err(usb_err) = send_usb_frame(usb, &report.to_frame(sequence), &mac_str) {
+        error!("USB transfer failed for stats frame: {}", usb_err);
+    }
+}
+
+/// CPU使用率の上限超過をSTATSフレームで即座に通知します
+fn send_cpu_warning(usb: &SharedUsb, usage: &CpuUsage, limit_percent: u8, sequence: u32) {
+    warn!(
+        "CPU usage has stayed above {}% for {}s: {}% ({})",
+        limit_percent,
+        CPU_SAMPLE_INTERVAL.as_secs() * CPU_SUSTAIN_SAMPLES as u64,
+        usage.busy_percent,
+        usage.tasks_field()
+    );
+    let mut report = StatsReport::new();
+    report.push("CPU_WARN", usage.busy_percent);
+    report.push("CPU_LIMIT", limit_percent);
+    report.push("CPU_TASKS", usage.tasks_field());
+
+    let mac_str = format_mac_address(&GATEWAY_STATS_MAC);
+    if let Err(usb_err) = send_usb_frame(usb, &report.to_frame(sequence), &mac_str) {
+        error!("USB transfer failed for CPU warning: {}", usb_err);
+    }
+}
+
+/// 空きヒープと最大の空きブロックを記録し、枯渇の予測が範囲内になったら警告すべき予測を返します
+fn sample_memory(horizon: Option<Duration>) -> Option<MemoryForecast> {
+    let sample = MemorySample {
+        uptime_s: lifetime_stats::uptime_s(),
+        free_heap: sys::free_heap(),
+        largest_block: sys::largest_free_block(),
+    };
+    debug!("Heap: free={} largest={}", sample.free_heap, sample.largest_block);
+    MEMORY_TREND
+        .lock()
+        .ok()
+        .and_then(|mut trend| trend.record(sample, horizon.map(|horizon| horizon.as_secs())))
+}
+
+/// 空きヒープの枯渇の予測を統計フレームでUSBへ送出します
+fn send_memory_warning(usb: &SharedUsb, forecast: &MemoryForecast, horizon: Duration, sequence: u32) {
+    warn!(
+        "Free heap is trending towards exhaustion in ~{}s: free={} ({:.0} B/h), largest block={} ({:.0} B/h)",
+        forecast.exhaustion_s.unwrap_or_default(),
+        forecast.latest.free_heap,
+        forecast.free_slope_per_hour,
+        forecast.latest.largest_block,
+        forecast.largest_slope_per_hour
+    );
+    let mut report = StatsReport::new();
+    for (key, value) in forecast.stats_fields(horizon.as_secs()) {
+        report.push(key, value);
+    }
+
+    let mac_str = format_mac_address(&GATEWAY_STATS_MAC);
+    if let Err(usb_err) = send_usb_frame(usb, &report.to_frame(sequence), &mac_str) {
+        error!("USB transfer failed for memory trend warning: {}", usb_err);
+    }
+}
+
+/// FreeRTOSのランタイム統計からタスクごとの累積実行時間を取得します（無効なビルドではNone）
+fn read_task_runtimes() -> Option<Vec<TaskRuntime>> {
+    sys::task_runtimes()
+}
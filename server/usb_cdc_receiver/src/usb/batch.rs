@@ -0,0 +1,249 @@
+//! 小さなフレームをまとめてUSBへ書き込む送出経路
+//!
+//! 転送完了・中断イベントや統計フレームなどの小さなフレームは、USB CDCの送信バッファ
+//! （4096バイト）に収まるまでメモリに溜め、1回の書き込みで送出します。フレームごとの
+//! 64バイト分割・待機をなくし、頻度の高い小さなメッセージのドライバー呼び出しを減らします。
+//! 溜めたフレームは、最初のフレームから `flush_interval` を過ぎた時点で `flush_if_due` により送出します。
+//! 書き込みに失敗したフレームは `take_failed_frames` で取り出し、デッドレターキューへ退避します。
+
+use std::time::{Duration, Instant};
+
+use super::{UsbError, UsbInterface, UsbResult};
+use crate::shutdown::{Shutdown, ShutdownReason};
+
+/// まとめて書き込むバッファの大きさ（USB CDCの送信バッファと同じ）
+pub const BATCH_CAPACITY: usize = 4096;
+/// まとめる対象とするフレームの上限（これより大きいフレームは溜めたフレームの後にそのまま送出）
+pub const BATCH_FRAME_MAX: usize = 512;
+/// 溜めたフレームを送出するまでの最長時間
+pub const BATCH_FLUSH_INTERVAL: Duration = Duration::from_millis(20);
+
+/// まとめた書き込み1回あたりのタイムアウト（ミリ秒）
+const BATCH_WRITE_TIMEOUT_MS: u32 = 100;
+/// まとめた書き込みが進まない場合の再試行回数
+const MAX_BATCH_WRITE_STALLS: u32 = 50;
+
+/// 送出待ちのフレーム
+#[derive(Debug)]
+pub struct FrameBatch {
+    buffer: Vec<u8>,
+    /// 溜めたフレームそれぞれの終端の位置
+    frame_ends: Vec<usize>,
+    oldest: Option<Instant>,
+    flush_interval: Duration,
+}
+
+impl FrameBatch {
+    /// 送出までの最長時間を指定して作成します
+    pub fn new(flush_interval: Duration) -> Self {
+        Self {
+            buffer: Vec::with_capacity(BATCH_CAPACITY),
+            frame_ends: Vec::new(),
+            oldest: None,
+            flush_interval,
+        }
+    }
+
+    /// まとめる対象の大きさか
+    pub fn accepts(len: usize) -> bool {
+        len <= BATCH_FRAME_MAX
+    }
+
+    /// 送出せずに追加できるか
+    pub fn has_room(&self, len: usize) -> bool {
+        self.buffer.len() + len <= BATCH_CAPACITY
+    }
+
+    /// フレームを追加します（`has_room` を確認してから呼び出す）
+    pub fn push(&mut self, frame: &[u8], now: Instant) {
+        self.buffer.extend_from_slice(frame);
+        self.frame_ends.push(self.buffer.len());
+        self.oldest.get_or_insert(now);
+    }
+
+    /// 溜めたフレームがないか
+    pub fn is_empty(&self) -> bool {
+        self.frame_ends.is_empty()
+    }
+
+    /// 溜めたフレーム数
+    pub fn frames(&self) -> usize {
+        self.frame_ends.len()
+    }
+
+    /// 溜めたフレームを送出すべき時刻
+    pub fn deadline(&self) -> Option<Instant> {
+        self.oldest.map(|oldest| oldest + self.flush_interval)
+    }
+
+    /// 送出すべき時刻を過ぎたか
+    pub fn is_due(&self, now: Instant) -> bool {
+        self.deadline().is_some_and(|deadline| now >= deadline)
+    }
+
+    /// 溜めたフレームの連結
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buffer
+    }
+
+    /// 溜めたフレームを1つずつ取り出します
+    pub fn split_frames(&self) -> Vec<Vec<u8>> {
+        let mut start = 0;
+        self.frame_ends
+            .iter()
+            .map(|&end| {
+                let frame = self.buffer[start..end].to_vec();
+                start = end;
+                frame
+            })
+            .collect()
+    }
+
+    /// 溜めたフレームを破棄します
+    pub fn clear(&mut self) {
+        self.buffer.clear();
+        self.frame_ends.clear();
+        self.oldest = None;
+    }
+}
+
+/// まとめた送出の累計
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BatchStats {
+    /// まとめて送出したフレーム数
+    pub frames: u32,
+    /// まとめた書き込みの回数
+    pub flushes: u32,
+}
+
+/// 小さなフレームをまとめて送出するUSBインターフェース
+///
+/// `send_frame` は小さなフレームを溜め、大きなフレームは溜めたフレームを送出してから送ります。
+/// コマンド応答などの `write` も溜めたフレームの後に書き込み、送出順を保ちます。
+pub struct BatchedUsb<U: UsbInterface> {
+    inner: U,
+    batch: FrameBatch,
+    stats: BatchStats,
+    /// 書き込みに失敗して送出待ちから外したフレームと失敗の理由
+    failed: Vec<(Vec<u8>, UsbError)>,
+}
+
+impl<U: UsbInterface> BatchedUsb<U> {
+    /// 送出までの最長時間を `BATCH_FLUSH_INTERVAL` として作成します
+    pub fn new(inner: U) -> Self {
+        Self::with_flush_interval(inner, BATCH_FLUSH_INTERVAL)
+    }
+
+    /// 送出までの最長時間を指定して作成します
+    pub fn with_flush_interval(inner: U, flush_interval: Duration) -> Self {
+        Self {
+            inner,
+            batch: FrameBatch::new(flush_interval),
+            stats: BatchStats::default(),
+            failed: Vec::new(),
+        }
+    }
+
+    /// 溜めたフレームを送出すべき時刻
+    pub fn flush_deadline(&self) -> Option<Instant> {
+        self.batch.deadline()
+    }
+
+    /// 送出すべき時刻を過ぎていれば溜めたフレームを送出します
+    pub fn flush_if_due(&mut self, now: Instant) -> UsbResult<usize> {
+        if self.batch.is_due(now) {
+            self.flush()
+        } else {
+            Ok(0)
+        }
+    }
+
+    /// 溜めたフレームを1回の書き込みで送出します
+    ///
+    /// 失敗した場合も溜めたフレームは送出待ちから外し（送出が止まり続けるのを防ぐため）、
+    /// `take_failed_frames` で取り出せるようにします。
+    pub fn flush(&mut self) -> UsbResult<usize> {
+        if self.batch.is_empty() {
+            return Ok(0);
+        }
+        let result = write_all(&mut self.inner, self.batch.as_bytes());
+        if result.is_ok() {
+            self.stats.frames = self.stats.frames.saturating_add(self.batch.frames() as u32);
+            self.stats.flushes = self.stats.flushes.saturating_add(1);
+        }
+        if let Err(e) = &result {
+            self.failed
+                .extend(self.batch.split_frames().into_iter().map(|frame| (frame, e.clone())));
+        }
+        self.batch.clear();
+        result
+    }
+
+    /// 書き込みに失敗して送出待ちから外したフレームを取り出します
+    pub fn take_failed_frames(&mut self) -> Vec<(Vec<u8>, UsbError)> {
+        std::mem::take(&mut self.failed)
+    }
+
+    /// 累計を返してリセットします
+    pub fn take_stats(&mut self) -> BatchStats {
+        std::mem::take(&mut self.stats)
+    }
+}
+
+impl<U: UsbInterface> UsbInterface for BatchedUsb<U> {
+    fn write(&mut self, data: &[u8], timeout_ms: u32) -> UsbResult<usize> {
+        self.flush()?;
+        self.inner.write(data, timeout_ms)
+    }
+
+    fn read(&mut self, buffer: &mut [u8], timeout_ms: u32) -> UsbResult<usize> {
+        self.inner.read(buffer, timeout_ms)
+    }
+
+    fn read_command(&mut self, timeout_ms: u32) -> UsbResult<Option<String>> {
+        self.inner.read_command(timeout_ms)
+    }
+
+    fn send_frame(&mut self, data: &[u8], mac_str: &str) -> UsbResult<usize> {
+        if !FrameBatch::accepts(data.len()) {
+            self.flush()?;
+            return self.inner.send_frame(data, mac_str);
+        }
+        if !self.batch.has_room(data.len()) {
+            self.flush()?;
+        }
+        self.batch.push(data, Instant::now());
+        Ok(data.len())
+    }
+}
+
+/// 溜めたフレームを書き出してから下位のUSBを停止します
+impl<U: UsbInterface + Shutdown> Shutdown for BatchedUsb<U> {
+    fn shutdown(&mut self, reason: ShutdownReason) -> Result<(), String> {
+        let flushed = self.flush().map_err(|e| format!("flush failed: {}", e));
+        self.inner.shutdown(reason)?;
+        flushed.map(|_| ())
+    }
+}
+
+/// 部分的な書き込みを繰り返してすべて書き込みます
+fn write_all<U: UsbInterface>(usb: &mut U, data: &[u8]) -> UsbResult<usize> {
+    let mut written = 0;
+    let mut stalls = 0;
+    while written < data.len() {
+        match usb.write(&data[written..], BATCH_WRITE_TIMEOUT_MS) {
+            Ok(0) | Err(UsbError::Timeout) => {
+                stalls += 1;
+                if stalls >= MAX_BATCH_WRITE_STALLS {
+                    return Err(UsbError::Timeout);
+                }
+            }
+            Ok(bytes) => {
+                written += bytes;
+                stalls = 0;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(written)
+}
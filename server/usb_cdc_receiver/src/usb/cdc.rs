@@ -1,12 +1,33 @@
+use super::chunked::{send_frame_chunked, WriteClock};
 use super::{UsbError, UsbInterface, UsbResult};
+use crate::shutdown::{Shutdown, ShutdownReason};
 use esp_idf_svc::hal::delay::FreeRtos;
 use esp_idf_svc::hal::usb_serial::{UsbDMinGpio, UsbDPlusGpio, UsbSerialConfig, UsbSerialDriver};
-use esp_idf_svc::sys;
-use log::{debug, error, warn};
+use log::debug;
+use std::time::Instant;
+
+/// ドライバー解放前に送信FIFOの送出を待つ時間（ミリ秒）
+const TX_DRAIN_WAIT_MS: u32 = 50;
+
+/// FreeRTOSの遅延で待機する書き込み用の時計
+struct FreeRtosClock {
+    started: Instant,
+}
+
+impl WriteClock for FreeRtosClock {
+    fn delay_ms(&mut self, ms: u32) {
+        FreeRtos::delay_ms(ms);
+    }
+
+    fn elapsed_ms(&self) -> u64 {
+        self.started.elapsed().as_millis() as u64
+    }
+}
 
 /// USB CDCドライバーを管理する構造体
 pub struct UsbCdc<'d> {
-    driver: UsbSerialDriver<'d>,
+    /// USBシリアルドライバー（停止後はNone）
+    driver: Option<UsbSerialDriver<'d>>,
 }
 
 impl<'d> UsbCdc<'d> {
@@ -39,7 +60,26 @@ impl<'d> UsbCdc<'d> {
             .map_err(|e| UsbError::InitError(format!("USB CDC initialization failed: {}", e)))?;
 
         debug!("USB CDC Initialized with buffer sizes: TX/RX: 4096 bytes");
-        Ok(UsbCdc { driver })
+        Ok(UsbCdc { driver: Some(driver) })
+    }
+
+    /// 停止前のドライバー
+    fn driver(&mut self) -> UsbResult<&mut UsbSerialDriver<'d>> {
+        self.driver
+            .as_mut()
+            .ok_or_else(|| UsbError::Other("USB CDC driver is shut down".to_string()))
+    }
+}
+
+/// 送信FIFOに残ったデータを送り出してからドライバーを解放します
+impl<'d> Shutdown for UsbCdc<'d> {
+    fn shutdown(&mut self, _reason: ShutdownReason) -> Result<(), String> {
+        if let Some(driver) = self.driver.take() {
+            FreeRtos::delay_ms(TX_DRAIN_WAIT_MS);
+            drop(driver);
+            debug!("USB CDC driver uninstalled");
+        }
+        Ok(())
     }
 }
 
@@ -55,7 +95,7 @@ impl<'d> UsbInterface for UsbCdc<'d> {
     ///
     /// * `UsbResult<usize>` - 送信されたバイト数、または`UsbError`
     fn write(&mut self, data: &[u8], timeout_ms: u32) -> UsbResult<usize> {
-        self.driver.write(data, timeout_ms).map_err(|e| e.into())
+        self.driver()?.write(data, timeout_ms).map_err(|e| e.into())
     }
 
     /// USB経由でデータを読み取ります
@@ -69,7 +109,7 @@ impl<'d> UsbInterface for UsbCdc<'d> {
     ///
     /// * `UsbResult<usize>` - 読み取ったバイト数、または`UsbError`
     fn read(&mut self, buffer: &mut [u8], timeout_ms: u32) -> UsbResult<usize> {
-        self.driver.read(buffer, timeout_ms).map_err(|e| e.into())
+        self.driver()?.read(buffer, timeout_ms).map_err(|e| e.into())
     }
 
     /// USBからコマンドを読み取り、解析します
@@ -105,7 +145,7 @@ impl<'d> UsbInterface for UsbCdc<'d> {
 
     /// フレームデータをUSB CDC経由で送信します
     ///
-    /// データを小さなチャンクに分割し、タイムアウトと再試行処理を実装します
+    /// データを小さなチャンクに分割し、タイムアウトと再試行処理を行います（`chunked` を参照）
     ///
     /// # 引数
     ///
@@ -117,100 +157,8 @@ impl<'d> UsbInterface for UsbCdc<'d> {
     /// * `UsbResult<usize>` - 送信に成功した場合は送信バイト数、
     ///   失敗した場合は`UsbError`
     fn send_frame(&mut self, data: &[u8], mac_str: &str) -> UsbResult<usize> {
-        // 送信設定パラメータ
-        const MAX_CHUNK_SIZE: usize = 64; // USBバッファサイズに合わせて調整
-        const WRITE_TIMEOUT_MS: u32 = 30000; // 30秒のタイムアウト
-        const MAX_RETRIES: u32 = 5; // 最大リトライ回数
-
-        let mut bytes_sent = 0;
-        let mut timeout = core::mem::MaybeUninit::<sys::TimeOut_t>::uninit();
-        let mut write_timeout_ticks =
-            (WRITE_TIMEOUT_MS as u64 * sys::configTICK_RATE_HZ as u64 / 1000) as u32;
-        unsafe {
-            sys::vTaskSetTimeOutState(timeout.as_mut_ptr());
-        }
-        let mut timeout = unsafe { timeout.assume_init() };
-        let mut timeout_logged = false;
-        let mut retry_count = 0;
-
-        while bytes_sent < data.len() {
-            // タイムアウトチェック
-            if unsafe { sys::xTaskCheckForTimeOut(&mut timeout, &mut write_timeout_ticks) } != 0 {
-                return Err(UsbError::Timeout);
-            }
-
-            // 小さなバッファで書き込み
-            let remaining = data.len() - bytes_sent;
-            let write_size = if remaining > MAX_CHUNK_SIZE {
-                MAX_CHUNK_SIZE
-            } else {
-                remaining
-            };
-            let chunk_to_write = &data[bytes_sent..(bytes_sent + write_size)];
-
-            // タイムアウト10msで書き込み試行
-            match self.write(chunk_to_write, 10) {
-                Ok(written) => {
-                    if written > 0 {
-                        bytes_sent += written;
-                        retry_count = 0; // リトライカウンタリセット
-                        timeout_logged = false;
-
-                        // データ書き込みに成功した場合のログ（詳細レベル）
-                        debug!(
-                            "USB Write: {} bytes (Total: {}/{} - {:.1}%)",
-                            written,
-                            bytes_sent,
-                            data.len(),
-                            (bytes_sent as f32 / data.len() as f32) * 100.0
-                        );
-                    } else {
-                        // 書き込みは成功したが0バイト
-                        retry_count += 1;
-                        if retry_count >= MAX_RETRIES {
-                            warn!(
-                                "USB CDC: Max retries ({}) reached with 0 bytes written",
-                                MAX_RETRIES
-                            );
-                            FreeRtos::delay_ms(50); // より長く待機
-                            retry_count = 0; // リトライカウンタリセット
-                        }
-                        FreeRtos::delay_ms(5);
-                    }
-                }
-                Err(UsbError::Timeout) => {
-                    // タイムアウト（バッファフル）の場合
-                    retry_count += 1;
-                    if !timeout_logged {
-                        debug!("USB Write Timeout (Buffer Full?) for {}", mac_str);
-                        timeout_logged = true;
-                    }
-
-                    if retry_count >= MAX_RETRIES {
-                        warn!(
-                            "USB CDC: Max retries ({}) reached due to timeouts",
-                            MAX_RETRIES
-                        );
-                        FreeRtos::delay_ms(50); // より長く待機
-                        retry_count = 0;
-                    } else {
-                        FreeRtos::delay_ms(10);
-                    }
-                }
-                Err(e) => {
-                    error!(
-                        "USB CDC: Error writing chunk to USB CDC for {}: {}",
-                        mac_str, e
-                    );
-                    return Err(e);
-                }
-            }
-        } // 送信ループの終了
-
-        // 送信成功後に少し待機（ホスト側の処理時間を考慮）
-        FreeRtos::delay_ms(5);
-
-        Ok(bytes_sent)
+        let mut clock = FreeRtosClock { started: Instant::now() };
+        send_frame_chunked(|chunk, timeout_ms| self.write(chunk, timeout_ms), data, mac_str, &mut clock)
     }
 }
 
@@ -0,0 +1,113 @@
+//! フレームを小さなチャンクに分けてUSBへ書き込む送出経路
+//!
+//! USB CDCの1回の書き込みで受け付けられるのは送信バッファの空きまでのため、フレームを
+//! `MAX_CHUNK_SIZE` バイトずつ書き込みます。タイムアウト（バッファフル）や0バイトの書き込みは
+//! 待機を挟んで再試行し、`MAX_RETRIES` 回続いた場合は長めに待ちます。フレーム全体が
+//! `FRAME_WRITE_TIMEOUT_MS` 以内に書き込めなければタイムアウトとします。
+//! 待機と経過時間は `WriteClock` を通すため、ホストテストでは仮想時計で再試行の順序を確認できます。
+
+use log::{debug, error, warn};
+
+use super::{UsbError, UsbResult};
+
+/// 1回の書き込みの最大バイト数（USBバッファサイズに合わせて調整）
+pub const MAX_CHUNK_SIZE: usize = 64;
+/// フレーム全体の書き込みのタイムアウト（ミリ秒）
+pub const FRAME_WRITE_TIMEOUT_MS: u64 = 30_000;
+/// チャンク1回の書き込みのタイムアウト（ミリ秒）
+pub const CHUNK_WRITE_TIMEOUT_MS: u32 = 10;
+/// 長めに待つまでの連続再試行回数
+pub const MAX_RETRIES: u32 = 5;
+/// タイムアウト後の再試行までの待機（ミリ秒）
+pub const TIMEOUT_RETRY_DELAY_MS: u32 = 10;
+/// 0バイトの書き込み後の再試行までの待機（ミリ秒）
+pub const STALL_RETRY_DELAY_MS: u32 = 5;
+/// 再試行が `MAX_RETRIES` 回続いた場合の待機（ミリ秒）
+pub const BACKOFF_DELAY_MS: u32 = 50;
+/// 書き込み完了後の待機（ホスト側の処理時間を考慮、ミリ秒）
+pub const SETTLE_DELAY_MS: u32 = 5;
+
+/// 書き込みの待機と経過時間の計測
+pub trait WriteClock {
+    /// 指定した時間だけ待機します
+    fn delay_ms(&mut self, ms: u32);
+
+    /// 送信開始からの経過時間（ミリ秒）
+    fn elapsed_ms(&self) -> u64;
+}
+
+/// フレームをチャンクに分けて書き込みます
+///
+/// # 引数
+/// * `write` - 1回の書き込み（書き込んだバイト数を返す。受け付けきれない分は次のチャンクで再送）
+/// * `data` - 送信するフレーム化されたデータ
+/// * `mac_str` - ログ表示用のMACアドレス文字列
+/// * `clock` - 待機と経過時間の計測
+///
+/// # 戻り値
+/// * 送信バイト数（タイムアウトまたは書き込みエラーの場合は `UsbError`）
+pub fn send_frame_chunked<W, C>(mut write: W, data: &[u8], mac_str: &str, clock: &mut C) -> UsbResult<usize>
+where
+    W: FnMut(&[u8], u32) -> UsbResult<usize>,
+    C: WriteClock,
+{
+    let mut bytes_sent = 0;
+    let mut timeout_logged = false;
+    let mut retry_count = 0;
+
+    while bytes_sent < data.len() {
+        if clock.elapsed_ms() >= FRAME_WRITE_TIMEOUT_MS {
+            return Err(UsbError::Timeout);
+        }
+
+        let write_size = (data.len() - bytes_sent).min(MAX_CHUNK_SIZE);
+        let chunk_to_write = &data[bytes_sent..bytes_sent + write_size];
+
+        match write(chunk_to_write, CHUNK_WRITE_TIMEOUT_MS) {
+            Ok(written) if written > 0 => {
+                bytes_sent += written;
+                retry_count = 0;
+                timeout_logged = false;
+                debug!(
+                    "USB Write: {} bytes (Total: {}/{} - {:.1}%)",
+                    written,
+                    bytes_sent,
+                    data.len(),
+                    (bytes_sent as f32 / data.len() as f32) * 100.0
+                );
+            }
+            Ok(_) => {
+                // 書き込みは成功したが0バイト
+                retry_count += 1;
+                if retry_count >= MAX_RETRIES {
+                    warn!("USB CDC: Max retries ({}) reached with 0 bytes written", MAX_RETRIES);
+                    clock.delay_ms(BACKOFF_DELAY_MS);
+                    retry_count = 0;
+                }
+                clock.delay_ms(STALL_RETRY_DELAY_MS);
+            }
+            Err(UsbError::Timeout) => {
+                // タイムアウト（バッファフル）の場合
+                retry_count += 1;
+                if !timeout_logged {
+                    debug!("USB Write Timeout (Buffer Full?) for {}", mac_str);
+                    timeout_logged = true;
+                }
+                if retry_count >= MAX_RETRIES {
+                    warn!("USB CDC: Max retries ({}) reached due to timeouts", MAX_RETRIES);
+                    clock.delay_ms(BACKOFF_DELAY_MS);
+                    retry_count = 0;
+                } else {
+                    clock.delay_ms(TIMEOUT_RETRY_DELAY_MS);
+                }
+            }
+            Err(e) => {
+                error!("USB CDC: Error writing chunk to USB CDC for {}: {}", mac_str, e);
+                return Err(e);
+            }
+        }
+    }
+
+    clock.delay_ms(SETTLE_DELAY_MS);
+    Ok(bytes_sent)
+}
@@ -1,12 +1,74 @@
+use super::chunked::{send_frame_chunked, WriteClock};
 use super::{UsbError, UsbInterface, UsbResult, COMMAND_BUFFER_SIZE};
+use crate::shutdown::{Shutdown, ShutdownReason};
 use std::collections::VecDeque;
 use std::sync::{Arc, Mutex};
 
+/// 書き込み1回に挿入する障害
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WriteFault {
+    /// タイムアウト（送信バッファが空かない。書き込みのタイムアウト分だけ仮想時計を進める）
+    Timeout,
+    /// 0バイトの書き込み
+    Stall,
+}
+
+/// 書き込みの待機を記録する仮想時計
+///
+/// 待機しても実時間は経過せず、仮想時刻を進めて待機時間を記録します。
+/// クローン同士は仮想時刻と待機の記録を共有します。
+#[derive(Debug, Clone, Default)]
+pub struct MockClock {
+    now_ms: Arc<Mutex<u64>>,
+    delays: Arc<Mutex<Vec<u32>>>,
+    started_ms: u64,
+}
+
+impl MockClock {
+    /// 現在の仮想時刻を起点とする時計（`elapsed_ms` はここからの経過時間）
+    pub fn start(&self) -> Self {
+        Self {
+            started_ms: self.now_ms(),
+            ..self.clone()
+        }
+    }
+
+    /// 仮想時刻を進めます
+    pub fn advance(&self, ms: u64) {
+        *self.now_ms.lock().unwrap() += ms;
+    }
+
+    /// 仮想時刻（ミリ秒）
+    pub fn now_ms(&self) -> u64 {
+        *self.now_ms.lock().unwrap()
+    }
+
+    /// これまでの待機時間の記録を取り出します
+    pub fn take_delays(&self) -> Vec<u32> {
+        std::mem::take(&mut *self.delays.lock().unwrap())
+    }
+}
+
+impl WriteClock for MockClock {
+    fn delay_ms(&mut self, ms: u32) {
+        self.delays.lock().unwrap().push(ms);
+        self.advance(ms as u64);
+    }
+
+    fn elapsed_ms(&self) -> u64 {
+        self.now_ms() - self.started_ms
+    }
+}
+
 /// テスト用のUSB CDCモック実装
 /// 
 /// 実際のUSBハードウェアを使わずにUSB CDC通信をシミュレートします。
 /// 送信されたデータと受信コマンドを記録し、テストで検証できます。
-/// 
+///
+/// `send_frame` は実機と同じチャンク分割・再試行の処理（`chunked`）で書き込み、書き込めたバイト列を
+/// 1件として `sent_data` に記録します。1回の書き込みで受け付けるバイト数の上限や、タイムアウトなどの
+/// 障害を設定すると、分割と再試行の経路を確認できます（待機は仮想時計 `clock` に記録）。
+///
 /// # Clone動作について
 /// `MockUsbCdc`は`Clone`を実装していますが、これは各フィールド（`sent_data`, `command_queue`など）の
 /// `Arc<Mutex<...>>`を浅くコピーします。つまり、クローンしたインスタンス同士は同じ内部状態を共有します。
@@ -23,6 +85,16 @@ pub struct MockUsbCdc {
     pub simulate_write_error: Arc<Mutex<bool>>,
     pub simulate_read_error: Arc<Mutex<bool>>,
     pub simulate_timeout: Arc<Mutex<bool>>,
+    /// 停止済み（以降の書き込みはエラー）
+    pub shut_down: Arc<Mutex<bool>>,
+    /// 1回の書き込みで受け付ける最大バイト数（Noneは無制限）
+    pub max_write_bytes: Arc<Mutex<Option<usize>>>,
+    /// 次の書き込みから順に挿入する障害
+    pub write_faults: Arc<Mutex<VecDeque<WriteFault>>>,
+    /// 受け付けた書き込みごとのバイト数
+    pub write_sizes: Arc<Mutex<Vec<usize>>>,
+    /// 書き込みの待機を記録する仮想時計
+    pub clock: MockClock,
 }
 
 impl Default for MockUsbCdc {
@@ -41,6 +113,11 @@ impl MockUsbCdc {
             simulate_write_error: Arc::new(Mutex::new(false)),
             simulate_read_error: Arc::new(Mutex::new(false)),
             simulate_timeout: Arc::new(Mutex::new(false)),
+            shut_down: Arc::new(Mutex::new(false)),
+            max_write_bytes: Arc::new(Mutex::new(None)),
+            write_faults: Arc::new(Mutex::new(VecDeque::new())),
+            write_sizes: Arc::new(Mutex::new(Vec::new())),
+            clock: MockClock::default(),
         }
     }
 
@@ -78,21 +155,60 @@ impl MockUsbCdc {
     pub fn set_timeout(&self, enable: bool) {
         *self.simulate_timeout.lock().unwrap() = enable;
     }
-}
 
-impl UsbInterface for MockUsbCdc {
-    fn write(&mut self, data: &[u8], _timeout_ms: u32) -> UsbResult<usize> {
+    /// テスト用: 1回の書き込みで受け付ける最大バイト数を設定（Noneは無制限）
+    pub fn set_max_write_bytes(&self, max_bytes: Option<usize>) {
+        *self.max_write_bytes.lock().unwrap() = max_bytes;
+    }
+
+    /// テスト用: 次の書き込みから `count` 回、障害を挿入
+    pub fn queue_write_faults(&self, fault: WriteFault, count: usize) {
+        self.write_faults.lock().unwrap().extend(std::iter::repeat_n(fault, count));
+    }
+
+    /// テスト用: 受け付けた書き込みごとのバイト数を取得
+    pub fn get_write_sizes(&self) -> Vec<usize> {
+        self.write_sizes.lock().unwrap().clone()
+    }
+
+    /// 書き込み1回を処理し、受け付けたバイト数を返します
+    fn accept_write(&self, data: &[u8], timeout_ms: u32) -> UsbResult<usize> {
+        if *self.shut_down.lock().unwrap() {
+            return Err(UsbError::Other("USB CDC is shut down".to_string()));
+        }
         // エラーシミュレーション
-        if *self.simulate_timeout.lock().unwrap() {
+        let fault = self.write_faults.lock().unwrap().pop_front();
+        if *self.simulate_timeout.lock().unwrap() || fault == Some(WriteFault::Timeout) {
+            self.clock.advance(timeout_ms as u64);
             return Err(UsbError::Timeout);
         }
         if *self.simulate_write_error.lock().unwrap() {
             return Err(UsbError::WriteError("Simulated write error".to_string()));
         }
+        if fault == Some(WriteFault::Stall) {
+            return Ok(0);
+        }
+
+        let accepted = self.max_write_bytes.lock().unwrap().map_or(data.len(), |max| data.len().min(max));
+        self.write_sizes.lock().unwrap().push(accepted);
+        Ok(accepted)
+    }
+}
+
+impl Shutdown for MockUsbCdc {
+    fn shutdown(&mut self, _reason: ShutdownReason) -> Result<(), String> {
+        *self.shut_down.lock().unwrap() = true;
+        Ok(())
+    }
+}
+
+impl UsbInterface for MockUsbCdc {
+    fn write(&mut self, data: &[u8], timeout_ms: u32) -> UsbResult<usize> {
+        let accepted = self.accept_write(data, timeout_ms)?;
 
         // データを記録
-        self.sent_data.lock().unwrap().push(data.to_vec());
-        Ok(data.len())
+        self.sent_data.lock().unwrap().push(data[..accepted].to_vec());
+        Ok(accepted)
     }
 
     fn read(&mut self, buffer: &mut [u8], _timeout_ms: u32) -> UsbResult<usize> {
@@ -147,9 +263,24 @@ impl UsbInterface for MockUsbCdc {
         }
     }
 
-    fn send_frame(&mut self, data: &[u8], _mac_str: &str) -> UsbResult<usize> {
-        // Mockでは簡略化: チャンキングなしで全データを送信
-        self.write(data, 0)
+    fn send_frame(&mut self, data: &[u8], mac_str: &str) -> UsbResult<usize> {
+        // 実機と同じ分割・再試行で書き込み、書き込めたバイト列を1件として記録する
+        let mut clock = self.clock.start();
+        let mut frame = Vec::with_capacity(data.len());
+        let result = send_frame_chunked(
+            |chunk, timeout_ms| {
+                let accepted = self.accept_write(chunk, timeout_ms)?;
+                frame.extend_from_slice(&chunk[..accepted]);
+                Ok(accepted)
+            },
+            data,
+            mac_str,
+            &mut clock,
+        );
+        if !frame.is_empty() {
+            self.sent_data.lock().unwrap().push(frame);
+        }
+        result
     }
 }
 
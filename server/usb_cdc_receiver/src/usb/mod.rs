@@ -1,8 +1,10 @@
+pub mod batch;
+pub mod chunked;
 #[cfg(feature = "esp")]
 pub mod cdc;
 
-// Mock実装（テストとnon-espビルドで使用可能）
-#[cfg(not(feature = "esp"))]
+// Mock実装（テストと"mock-hw"フィーチャー有効時に使用可能）
+#[cfg(any(test, feature = "mock-hw"))]
 pub mod mock;
 
 /// USB通信での結果の型
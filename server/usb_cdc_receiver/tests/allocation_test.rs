@@ -0,0 +1,107 @@
+// データ経路のヒープ確保回数のテスト
+// カウント付きアロケータで、フレームごとに呼ばれる整形処理の確保回数を計測します。
+// `cargo test --test allocation_test -- --nocapture` で計測値を表示します。
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use usb_cdc_receiver::mac_address::{format_mac_address, mac_str};
+use usb_cdc_receiver::stats::StatsReport;
+
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    // 並列に動く他のテストの確保を数えないよう、計測中のスレッドだけを数える
+    static COUNTING: Cell<bool> = const { Cell::new(false) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if COUNTING.with(Cell::get) {
+            ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        }
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        if COUNTING.with(Cell::get) {
+            ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        }
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+/// クロージャ実行中のヒープ確保回数を数えます
+fn count_allocations<T>(f: impl FnOnce() -> T) -> (T, usize) {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    COUNTING.with(|counting| counting.set(true));
+    let value = f();
+    COUNTING.with(|counting| counting.set(false));
+    (value, ALLOCATIONS.load(Ordering::Relaxed) - before)
+}
+
+const MAC: [u8; 6] = [0x34, 0xab, 0x95, 0xfb, 0x3f, 0xc4];
+
+#[test]
+fn test_mac_str_does_not_allocate() {
+    let (heap, heap_allocations) = count_allocations(|| format_mac_address(&MAC));
+    let (stack, stack_allocations) = count_allocations(|| mac_str(&MAC));
+
+    assert_eq!(stack.as_str(), heap);
+    assert!(heap_allocations >= 1);
+    assert_eq!(stack_allocations, 0);
+}
+
+#[test]
+fn test_stats_report_allocates_once() {
+    let fill = |report: &mut StatsReport| {
+        report.push("UPTIME", 86_400u64);
+        report.push("HEAP", 123_456u32);
+        report.push("MIN_HEAP", 98_765u32);
+        report.push("DATA_Q", format_args!("{}/{}", 3, 64));
+        report.push("DROPPED", 0u32);
+        report.push("USB_ERR", 2u32);
+        report.push("PEERS", 12u32);
+        report.push("LAT_P95", 41u32);
+    };
+
+    // 変更前の方式（項目ごとに文字列を作り、最後に連結）
+    let (_, per_field_allocations) = count_allocations(|| {
+        let mut fields: Vec<(&'static str, String)> = Vec::new();
+        fields.push(("UPTIME", 86_400u64.to_string()));
+        fields.push(("HEAP", 123_456u32.to_string()));
+        fields.push(("MIN_HEAP", 98_765u32.to_string()));
+        fields.push(("DATA_Q", format!("{}/{}", 3, 64)));
+        fields.push(("DROPPED", 0u32.to_string()));
+        fields.push(("USB_ERR", 2u32.to_string()));
+        fields.push(("PEERS", 12u32.to_string()));
+        fields.push(("LAT_P95", 41u32.to_string()));
+        fields
+            .iter()
+            .map(|(key, value)| format!("{}:{}", key, value))
+            .collect::<Vec<_>>()
+            .join(",")
+    });
+    let (report, single_buffer_allocations) = count_allocations(|| {
+        let mut report = StatsReport::new();
+        fill(&mut report);
+        report
+    });
+
+    assert_eq!(
+        report.to_payload(),
+        b"UPTIME:86400,HEAP:123456,MIN_HEAP:98765,DATA_Q:3/64,DROPPED:0,USB_ERR:2,PEERS:12,LAT_P95:41"
+    );
+    assert_eq!(single_buffer_allocations, 1);
+    assert!(per_field_allocations > single_buffer_allocations);
+}
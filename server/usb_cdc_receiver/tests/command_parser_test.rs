@@ -1,7 +1,7 @@
 // Command Parser Unit Tests
 // これらのテストはホストマシンで実行されます
 
-use usb_cdc_receiver::command::{parse_command, Command};
+use usb_cdc_receiver::command::{help_text, parse_command, Command, CommandParseError};
 
 #[test]
 fn test_valid_esp_now_command() {
@@ -216,3 +216,51 @@ fn test_command_with_extra_colons() {
     let result = parse_command(command);
     assert!(result.is_err()); // パーツ過多
 }
+
+#[test]
+fn test_help_command() {
+    let result = parse_command("HELP").unwrap();
+    assert!(matches!(result, Command::Help));
+
+    // 引数付きのHELPは不明なコマンドとして扱われる
+    let result = parse_command("HELP:extra").unwrap();
+    assert!(matches!(result, Command::Unknown(_)));
+}
+
+#[test]
+fn test_help_text_contains_command_syntax() {
+    let help = help_text();
+    assert!(help.contains("CMD_SEND_ESP_NOW:XX:XX:XX:XX:XX:XX:SLEEP_SECONDS"));
+    assert!(help.lines().all(|line| line.starts_with("CMD_HELP:")));
+}
+
+#[test]
+fn test_parse_error_details() {
+    let err = parse_command("CMD_SEND_ESP_NOW:34:ab:95:fb:3f:60").unwrap_err();
+    assert_eq!(
+        err,
+        CommandParseError::InvalidFormat {
+            command: "CMD_SEND_ESP_NOW",
+            expected_args: 7,
+            actual_args: 6,
+        }
+    );
+
+    let err = parse_command("CMD_SEND_ESP_NOW:34:ab:95:fb:3f:ZZ:60").unwrap_err();
+    assert_eq!(
+        err,
+        CommandParseError::InvalidMacAddress("34:ab:95:fb:3f:ZZ".to_string())
+    );
+
+    let err = parse_command("CMD_SEND_ESP_NOW:34:ab:95:fb:3f:c4:86401").unwrap_err();
+    assert_eq!(err, CommandParseError::InvalidSleepTime("86401".to_string()));
+}
+
+#[test]
+fn test_parse_error_response_line() {
+    let err = parse_command("CMD_SEND_ESP_NOW:34:ab:95:fb:3f:c4:abc").unwrap_err();
+    let response = err.to_response();
+    assert!(response.starts_with("CMD_ERR:"));
+    assert!(response.ends_with('\n'));
+    assert!(response.contains("'abc'"));
+}
@@ -3,8 +3,7 @@ mod tests {
     use usb_cdc_receiver::streaming::device_manager::{DeviceStreamManager, StreamManagerConfig};
     use usb_cdc_receiver::esp_now::FrameType;
     use usb_cdc_receiver::esp_now::frame::{
-        calculate_checksum, START_MARKER, END_MARKER,
-        MARKER_LEN, MAC_ADDRESS_LEN, FRAME_TYPE_LEN, SEQUENCE_NUM_LEN, DATA_LEN_FIELD_LEN
+        calculate_checksum, START_MARKER, END_MARKER, FRAME_HEADER_LEN
     };
 
     // ヘルパー：フレームを作成する
@@ -70,9 +69,7 @@ mod tests {
         
         // データを改変してチェックサム不整合を起こす
         // ペイロード部分のバイトを変更
-        // HEADER(MARKER + MAC + TYPE + SEQ + LEN) + PAYLOAD
-        let header_len = MARKER_LEN + MAC_ADDRESS_LEN + FRAME_TYPE_LEN + SEQUENCE_NUM_LEN + DATA_LEN_FIELD_LEN;
-        let payload_idx = header_len;
+        let payload_idx = FRAME_HEADER_LEN;
         frame_bytes[payload_idx] = frame_bytes[payload_idx].wrapping_add(1); 
         
         let result = manager.process_data(mac, &frame_bytes);
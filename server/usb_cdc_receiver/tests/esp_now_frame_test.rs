@@ -2,7 +2,10 @@
 // これらのテストはホストマシンで実行されます
 
 use usb_cdc_receiver::esp_now::FrameType;
-use usb_cdc_receiver::esp_now::frame::{Frame, calculate_checksum, detect_frame_type};
+use usb_cdc_receiver::esp_now::frame::{
+    Frame, FrameParseError, calculate_checksum, checksum_offset, detect_frame_type,
+    FRAME_HEADER_LEN, FRAME_OVERHEAD,
+};
 
 #[test]
 fn test_checksum_calculation() {
@@ -102,11 +105,15 @@ fn test_invalid_checksum() {
     let frame = Frame::new(mac, FrameType::Data, 1, vec![1, 2, 3]);
     let mut bytes = frame.to_bytes();
     
-    // チェックサムの位置を特定して破壊 (終了マーカーの4バイト前)
-    let checksum_pos = bytes.len() - 8;
+    // チェックサムの位置を特定して破壊
+    let checksum_pos = checksum_offset(3);
+    assert_eq!(checksum_pos, bytes.len() - 8);
     bytes[checksum_pos] ^= 0xFF;
     
-    assert!(Frame::from_bytes(&bytes).is_err(), "Invalid checksum should fail");
+    assert!(
+        matches!(Frame::from_bytes(&bytes), Err(FrameParseError::InvalidChecksum { .. })),
+        "Invalid checksum should fail"
+    );
 }
 
 #[test]
@@ -180,3 +187,17 @@ fn test_sequence_number_overflow() {
     
     assert_eq!(parsed.sequence_number(), u32::MAX);
 }
+
+#[test]
+fn test_frame_layout_constants() {
+    let mac = [0x12, 0x34, 0x56, 0x78, 0x9a, 0xbc];
+    let payload = vec![9u8; 5];
+    let bytes = Frame::new(mac, FrameType::Data, 7, payload.clone()).to_bytes();
+
+    assert_eq!(bytes.len(), FRAME_OVERHEAD + payload.len());
+    assert_eq!(&bytes[FRAME_HEADER_LEN..checksum_offset(payload.len())], &payload[..]);
+
+    let (parsed, consumed) = Frame::from_bytes(&bytes).unwrap();
+    assert_eq!(consumed, bytes.len());
+    assert_eq!(parsed.sequence_number(), 7);
+}
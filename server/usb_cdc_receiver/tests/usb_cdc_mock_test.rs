@@ -7,8 +7,18 @@
 
 use usb_cdc_receiver::esp_now::frame::{Frame, START_MARKER, END_MARKER};
 use usb_cdc_receiver::esp_now::FrameType;
-use usb_cdc_receiver::usb::mock::MockUsbCdc;
-use usb_cdc_receiver::usb::UsbInterface;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use usb_cdc_receiver::shutdown::{ShutdownReason, ShutdownSequence, ShutdownStage};
+use usb_cdc_receiver::dead_letter::DeadLetterQueue;
+use usb_cdc_receiver::usb::batch::{BatchStats, BatchedUsb, BATCH_CAPACITY};
+use usb_cdc_receiver::usb::chunked::{
+    BACKOFF_DELAY_MS, FRAME_WRITE_TIMEOUT_MS, MAX_CHUNK_SIZE, SETTLE_DELAY_MS, STALL_RETRY_DELAY_MS,
+    TIMEOUT_RETRY_DELAY_MS,
+};
+use usb_cdc_receiver::usb::mock::{MockUsbCdc, WriteFault};
+use usb_cdc_receiver::usb::{UsbError, UsbInterface};
 
 #[test]
 fn test_usb_send_esp_now_frame() {
@@ -75,6 +85,75 @@ fn test_usb_send_large_frame() {
     assert_eq!(sent_data[0].len(), frame_bytes.len());
 }
 
+#[test]
+fn test_send_frame_splits_into_chunks_within_write_limit() {
+    let mut mock_usb = MockUsbCdc::new();
+    let frame_bytes = Frame::new([0xAA, 0xBB, 0xCC, 0xDD, 0xEE, 0xFF], FrameType::Data, 1, vec![0x5A; 123]).to_bytes();
+    assert_eq!(frame_bytes.len(), 150);
+
+    // チャンクは64バイトずつ、受け付けきれなかった分は次のチャンクで送る
+    assert_eq!(mock_usb.send_frame(&frame_bytes, "AA:BB:CC:DD:EE:FF").unwrap(), 150);
+    assert_eq!(mock_usb.get_write_sizes(), vec![MAX_CHUNK_SIZE, MAX_CHUNK_SIZE, 22]);
+    mock_usb.write_sizes.lock().unwrap().clear();
+    mock_usb.clear_sent_data();
+
+    mock_usb.set_max_write_bytes(Some(40));
+    assert_eq!(mock_usb.send_frame(&frame_bytes, "AA:BB:CC:DD:EE:FF").unwrap(), 150);
+    assert_eq!(mock_usb.get_write_sizes(), vec![40, 40, 40, 30]);
+    assert_eq!(mock_usb.get_sent_data(), vec![frame_bytes]);
+    assert_eq!(mock_usb.clock.take_delays(), vec![SETTLE_DELAY_MS, SETTLE_DELAY_MS]);
+}
+
+#[test]
+fn test_send_frame_retries_timeouts_and_stalls_with_backoff() {
+    let mut mock_usb = MockUsbCdc::new();
+    let frame_bytes = Frame::new([0xAA, 0xBB, 0xCC, 0xDD, 0xEE, 0xFF], FrameType::Data, 1, vec![0x5A; 73]).to_bytes();
+    mock_usb.queue_write_faults(WriteFault::Timeout, 6);
+    mock_usb.queue_write_faults(WriteFault::Stall, 1);
+
+    assert_eq!(mock_usb.send_frame(&frame_bytes, "AA:BB:CC:DD:EE:FF").unwrap(), 100);
+    // 5回続けてタイムアウトしたら長めに待ち、再試行回数を数え直す
+    assert_eq!(
+        mock_usb.clock.take_delays(),
+        vec![
+            TIMEOUT_RETRY_DELAY_MS,
+            TIMEOUT_RETRY_DELAY_MS,
+            TIMEOUT_RETRY_DELAY_MS,
+            TIMEOUT_RETRY_DELAY_MS,
+            BACKOFF_DELAY_MS,
+            TIMEOUT_RETRY_DELAY_MS,
+            STALL_RETRY_DELAY_MS,
+            SETTLE_DELAY_MS,
+        ]
+    );
+    assert_eq!(mock_usb.get_write_sizes(), vec![MAX_CHUNK_SIZE, 36]);
+    assert_eq!(mock_usb.get_sent_data(), vec![frame_bytes]);
+}
+
+#[test]
+fn test_send_frame_gives_up_after_frame_timeout() {
+    let mut mock_usb = MockUsbCdc::new();
+    let frame_bytes = Frame::new([0xAA, 0xBB, 0xCC, 0xDD, 0xEE, 0xFF], FrameType::Data, 1, vec![0x5A; 200]).to_bytes();
+
+    // 送信バッファが空かないまま、フレーム全体のタイムアウトを過ぎる
+    mock_usb.set_timeout(true);
+
+    let started = mock_usb.clock.now_ms();
+    assert_eq!(mock_usb.send_frame(&frame_bytes, "AA:BB:CC:DD:EE:FF"), Err(UsbError::Timeout));
+    assert!(mock_usb.clock.now_ms() - started >= FRAME_WRITE_TIMEOUT_MS);
+    assert!(mock_usb.get_sent_data().is_empty());
+
+    // 書き込みエラーは再試行せずに返す
+    mock_usb.set_timeout(false);
+    mock_usb.set_write_error(true);
+    mock_usb.clock.take_delays();
+    assert!(matches!(
+        mock_usb.send_frame(&frame_bytes, "AA:BB:CC:DD:EE:FF"),
+        Err(UsbError::WriteError(_))
+    ));
+    assert!(mock_usb.clock.take_delays().is_empty());
+}
+
 #[test]
 fn test_usb_error_handling_write_error() {
     // Mock USB CDCを作成
@@ -203,3 +282,109 @@ fn test_usb_data_flow_integration() {
     assert_eq!(sent.len(), 1);
     assert_eq!(sent[0], frame_bytes);
 }
+
+#[test]
+fn test_batched_usb_coalesces_small_frames() {
+    let mock_usb = MockUsbCdc::new();
+    let mut usb = BatchedUsb::with_flush_interval(mock_usb.clone(), Duration::from_millis(20));
+    let mac = [0xAA, 0xBB, 0xCC, 0xDD, 0xEE, 0xFF];
+
+    let frames: Vec<Vec<u8>> = (0..3)
+        .map(|i| Frame::new(mac, FrameType::Data, i, vec![i as u8; 40]).to_bytes())
+        .collect();
+    for frame in &frames {
+        assert_eq!(usb.send_frame(frame, "AA:BB:CC:DD:EE:FF").unwrap(), frame.len());
+    }
+    // 送出時刻までは書き込まない
+    assert!(mock_usb.get_sent_data().is_empty());
+    assert_eq!(usb.flush_if_due(Instant::now()).unwrap(), 0);
+
+    let deadline = usb.flush_deadline().unwrap();
+    assert_eq!(usb.flush_if_due(deadline).unwrap(), frames.concat().len());
+    assert_eq!(mock_usb.get_sent_data(), vec![frames.concat()]);
+    assert_eq!(usb.take_stats(), BatchStats { frames: 3, flushes: 1 });
+    assert_eq!(usb.flush_deadline(), None);
+}
+
+#[test]
+fn test_batched_usb_preserves_order_and_capacity() {
+    let mock_usb = MockUsbCdc::new();
+    let mut usb = BatchedUsb::new(mock_usb.clone());
+    let mac = [0xAA, 0xBB, 0xCC, 0xDD, 0xEE, 0xFF];
+    let small = Frame::new(mac, FrameType::Data, 1, vec![0x11; 200]).to_bytes();
+    let large = Frame::new(mac, FrameType::Data, 2, vec![0x22; 1000]).to_bytes();
+
+    // 大きなフレームとコマンド応答は、溜めたフレームの後に送出する
+    usb.send_frame(&small, "AA:BB:CC:DD:EE:FF").unwrap();
+    usb.send_frame(&large, "AA:BB:CC:DD:EE:FF").unwrap();
+    usb.send_frame(&small, "AA:BB:CC:DD:EE:FF").unwrap();
+    usb.write(b"CMD_OK\n", 100).unwrap();
+    assert_eq!(
+        mock_usb.get_sent_data(),
+        vec![small.clone(), large, small.clone(), b"CMD_OK\n".to_vec()]
+    );
+
+    // 送信バッファを超える分は次の書き込みに回す
+    mock_usb.clear_sent_data();
+    let per_write = BATCH_CAPACITY / small.len();
+    for _ in 0..=per_write {
+        usb.send_frame(&small, "AA:BB:CC:DD:EE:FF").unwrap();
+    }
+    usb.flush().unwrap();
+    let sent = mock_usb.get_sent_data();
+    assert_eq!(sent.len(), 2);
+    assert_eq!(sent[0].len(), per_write * small.len());
+    assert_eq!(sent[1], small);
+}
+
+#[test]
+fn test_failed_batch_frames_are_dead_lettered_and_replayed() {
+    let mock_usb = MockUsbCdc::new();
+    let mut usb = BatchedUsb::new(mock_usb.clone());
+    let mac = [0xAA, 0xBB, 0xCC, 0xDD, 0xEE, 0xFF];
+    let frames: Vec<Vec<u8>> = (0..2)
+        .map(|i| Frame::new(mac, FrameType::Data, i, vec![i as u8; 40]).to_bytes())
+        .collect();
+    for frame in &frames {
+        usb.send_frame(frame, "AA:BB:CC:DD:EE:FF").unwrap();
+    }
+
+    // 書き込みに失敗したフレームは1つずつ取り出せる
+    mock_usb.set_write_error(true);
+    assert!(usb.flush().is_err());
+    let failed = usb.take_failed_frames();
+    assert_eq!(failed.iter().map(|(frame, _)| frame.clone()).collect::<Vec<_>>(), frames);
+    assert!(usb.take_failed_frames().is_empty());
+
+    let mut dead_letters = DeadLetterQueue::new();
+    for (frame, error) in failed {
+        dead_letters.push(frame, &error, Instant::now());
+    }
+    assert_eq!(dead_letters.len(), 2);
+
+    // 復旧後に送り直す
+    mock_usb.set_write_error(false);
+    for letter in dead_letters.take(None) {
+        usb.send_frame(&letter.frame, "AA:BB:CC:DD:EE:FF").unwrap();
+    }
+    usb.flush().unwrap();
+    assert_eq!(mock_usb.get_sent_data(), vec![frames.concat()]);
+    assert!(dead_letters.is_empty());
+}
+
+#[test]
+fn test_batched_usb_shutdown_flushes_before_closing() {
+    let mock_usb = MockUsbCdc::new();
+    let usb = Arc::new(Mutex::new(BatchedUsb::new(mock_usb.clone())));
+    let mut sequence = ShutdownSequence::new();
+    sequence.register("usb", ShutdownStage::Teardown, Box::new(usb.clone()));
+    let frame = Frame::new([0xAA, 0xBB, 0xCC, 0xDD, 0xEE, 0xFF], FrameType::Data, 1, vec![0x11; 40]).to_bytes();
+    usb.lock().unwrap().send_frame(&frame, "AA:BB:CC:DD:EE:FF").unwrap();
+
+    let outcomes = sequence.run(ShutdownReason::SoftReset);
+    assert_eq!(outcomes.len(), 1);
+    assert_eq!(outcomes[0].result, Ok(()));
+    // 溜めていたフレームを書き出してから停止し、以降の書き込みは失敗する
+    assert_eq!(mock_usb.get_sent_data(), vec![frame]);
+    assert!(usb.lock().unwrap().write(b"CMD_OK\n", 100).is_err());
+}
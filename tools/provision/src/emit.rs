@@ -0,0 +1,118 @@
+//! デバイス別の出力ファイル生成
+
+use crate::device_identity;
+use crate::manifest::{DeviceBundle, MAX_GATEWAY_CAMERAS};
+
+/// ファームウェアの toml_cfg セクション名（パッケージ名）
+const FIRMWARE_SECTION: &str = "sensor-data-sender";
+/// ゲートウェイの toml_cfg セクション名
+const GATEWAY_SECTION: &str = "usb_cdc_receiver";
+/// リモート設定のNVS名前空間（core/config_store.rs と同じ）
+const NVS_NAMESPACE: &str = "remote_cfg";
+/// NVSパーティションのサイズ（partitions.csv の nvs エントリ）
+pub const NVS_PARTITION_SIZE: &str = "0x6000";
+
+/// デバイスの cfg.toml を生成します
+pub fn device_cfg_toml(bundle: &DeviceBundle) -> String {
+    let mut out = format!(
+        "# provision により生成 (device: {}, mac: {}, id: {})\n[{}]\n",
+        bundle.name,
+        bundle.mac,
+        bundle.device_id.as_deref().unwrap_or("-"),
+        FIRMWARE_SECTION
+    );
+    for (key, value) in &bundle.values {
+        out.push_str(&format!("{} = {}\n", key, value));
+    }
+    out
+}
+
+/// NVSパーティション生成ツール（nvs_partition_gen.py）用のCSVを生成します
+///
+/// リモート設定を確定済みとして書き込むため、ファームウェアを共通ビルドのまま
+/// 受信機MACとスリープ時間をデバイスごとに切り替えられます。
+/// デバイスIDがあれば、ファームウェアが毎回のHASHフレームで報告するよう別の名前空間に書き込みます。
+pub fn nvs_csv(bundle: &DeviceBundle) -> String {
+    let mut csv = format!(
+        "key,type,encoding,value\n{},namespace,,\ncommitted,data,string,{}\nstate,data,u8,0\n",
+        NVS_NAMESPACE,
+        bundle.remote_config.encode()
+    );
+    if let Some(id) = &bundle.device_id {
+        csv.push_str(&format!(
+            "{},namespace,,\n{},data,string,{}\n",
+            device_identity::NVS_NAMESPACE,
+            device_identity::NVS_KEY_DEVICE_ID,
+            id
+        ));
+    }
+    csv
+}
+
+/// ゲートウェイ（usb_cdc_receiver）の cfg.toml を生成します
+///
+/// # 戻り値
+/// ゲートウェイの登録上限を超えた場合はNone
+pub fn gateway_cfg_toml(bundles: &[DeviceBundle]) -> Option<String> {
+    if bundles.len() > MAX_GATEWAY_CAMERAS {
+        return None;
+    }
+    let mut out = format!("# provision により生成\n[{}]\n", GATEWAY_SECTION);
+    for (index, bundle) in bundles.iter().enumerate() {
+        out.push_str(&format!(
+            "# {}\nimage_sender_cam{} = \"{}\"\n",
+            bundle.name,
+            index + 1,
+            bundle.mac.to_string().to_ascii_uppercase()
+        ));
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest::Manifest;
+    use crate::schema::Schema;
+
+    fn bundles() -> Vec<DeviceBundle> {
+        Manifest::parse(
+            r#"
+receiver_mac = "AA:BB:CC:DD:EE:01"
+[defaults]
+frame_size = "UXGA"
+debug_mode = false
+[[devices]]
+name = "cam1"
+mac = "24:0A:C4:00:00:01"
+device_id = "3f2a9c1e-0b7d-4e58-9a61-2c4f8d0e7b15"
+schedule = { sleep_seconds = 600 }
+"#,
+        )
+        .unwrap()
+        .resolve(&Schema::firmware())
+        .unwrap()
+    }
+
+    #[test]
+    fn test_device_cfg_toml_roundtrips_through_toml() {
+        let text = device_cfg_toml(&bundles()[0]);
+        let parsed: toml::Table = toml::from_str(&text).unwrap();
+        let section = parsed[FIRMWARE_SECTION].as_table().unwrap();
+        assert_eq!(section["receiver_mac"].as_str(), Some("AA:BB:CC:DD:EE:01"));
+        assert_eq!(section["sleep_duration_seconds"].as_integer(), Some(600));
+        assert_eq!(section["frame_size"].as_str(), Some("UXGA"));
+        assert_eq!(section["debug_mode"].as_bool(), Some(false));
+    }
+
+    #[test]
+    fn test_nvs_csv_and_gateway_cfg() {
+        let bundles = bundles();
+        assert!(nvs_csv(&bundles[0])
+            .contains("committed,data,string,RECEIVER_MAC=aa:bb:cc:dd:ee:01;SLEEP=600\n"));
+        assert!(nvs_csv(&bundles[0])
+            .ends_with("identity,namespace,,\ndevice_id,data,string,3f2a9c1e-0b7d-4e58-9a61-2c4f8d0e7b15\n"));
+        let gateway = gateway_cfg_toml(&bundles).unwrap();
+        assert!(gateway.contains("image_sender_cam1 = \"24:0A:C4:00:00:01\""));
+    }
+}
@@ -0,0 +1,159 @@
+//! フリートマニフェストからデバイス別の cfg.toml を生成するホストツール
+//!
+//! 使い方:
+//!   provision <manifest.toml> [--out <dir>] [--nvs] [--nvs-bin] [--check]
+//!
+//! 出力:
+//!   <dir>/<name>/cfg.toml       ファームウェアのビルド用設定
+//!   <dir>/<name>/nvs.csv        リモート設定とデバイスIDのNVS初期値（--nvs）
+//!   <dir>/<name>/nvs.bin        NVSパーティションイメージ（--nvs-bin, IDF_PATH が必要）
+//!   <dir>/gateway.cfg.toml      ゲートウェイのカメラ登録
+//!
+//! 設定スキーマと検証はファームウェアのソースを `#[path]` で共有しています。
+
+#[path = "../../../devices/m5stack_unit_cam/src/core/config_staging.rs"]
+#[allow(dead_code)]
+mod config_staging;
+#[path = "../../../devices/m5stack_unit_cam/src/core/config_validation.rs"]
+#[allow(dead_code)]
+mod config_validation;
+#[path = "../../../devices/m5stack_unit_cam/src/core/device_identity.rs"]
+#[allow(dead_code)]
+mod device_identity;
+#[path = "../../../devices/m5stack_unit_cam/src/hardware/camera/fb_policy.rs"]
+#[allow(dead_code)]
+mod fb_policy;
+#[path = "../../../devices/m5stack_unit_cam/src/mac_address.rs"]
+#[allow(dead_code)]
+mod mac_address;
+
+mod emit;
+mod manifest;
+mod schema;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitCode};
+
+use manifest::{DeviceBundle, Manifest};
+use schema::Schema;
+
+/// コマンドライン引数
+#[derive(Debug)]
+struct Args {
+    manifest: PathBuf,
+    out_dir: PathBuf,
+    nvs_csv: bool,
+    nvs_bin: bool,
+    check_only: bool,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut manifest = None;
+    let mut out_dir = PathBuf::from("provision_out");
+    let mut nvs_csv = false;
+    let mut nvs_bin = false;
+    let mut check_only = false;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--out" => out_dir = args.next().ok_or("--out には出力先ディレクトリが必要です")?.into(),
+            "--nvs" => nvs_csv = true,
+            "--nvs-bin" => {
+                nvs_csv = true;
+                nvs_bin = true;
+            }
+            "--check" => check_only = true,
+            other if other.starts_with("--") => return Err(format!("不明なオプション: {}", other)),
+            other if manifest.is_none() => manifest = Some(PathBuf::from(other)),
+            other => return Err(format!("余分な引数: {}", other)),
+        }
+    }
+
+    Ok(Args {
+        manifest: manifest.ok_or("マニフェストファイルを指定してください")?,
+        out_dir,
+        nvs_csv,
+        nvs_bin,
+        check_only,
+    })
+}
+
+fn write_bundle(args: &Args, bundle: &DeviceBundle) -> Result<(), String> {
+    let dir = args.out_dir.join(&bundle.name);
+    fs::create_dir_all(&dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+    write_file(&dir.join("cfg.toml"), &emit::device_cfg_toml(bundle))?;
+
+    if args.nvs_csv {
+        let csv_path = dir.join("nvs.csv");
+        write_file(&csv_path, &emit::nvs_csv(bundle))?;
+        if args.nvs_bin {
+            generate_nvs_bin(&csv_path, &dir.join("nvs.bin"))?;
+        }
+    }
+    Ok(())
+}
+
+fn write_file(path: &Path, contents: &str) -> Result<(), String> {
+    fs::write(path, contents).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// ESP-IDF の nvs_partition_gen.py でNVSパーティションイメージを生成します
+fn generate_nvs_bin(csv_path: &Path, bin_path: &Path) -> Result<(), String> {
+    let idf_path = std::env::var("IDF_PATH")
+        .map_err(|_| "--nvs-bin には IDF_PATH（ESP-IDF環境）が必要です".to_string())?;
+    let script = Path::new(&idf_path).join("components/nvs_flash/nvs_partition_generator/nvs_partition_gen.py");
+    let status = Command::new("python3")
+        .arg(&script)
+        .arg("generate")
+        .arg(csv_path)
+        .arg(bin_path)
+        .arg(emit::NVS_PARTITION_SIZE)
+        .status()
+        .map_err(|e| format!("{} を実行できません: {}", script.display(), e))?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!("nvs_partition_gen.py が失敗しました: {}", status))
+    }
+}
+
+fn run(args: &Args) -> Result<(), String> {
+    let source = fs::read_to_string(&args.manifest)
+        .map_err(|e| format!("{}: {}", args.manifest.display(), e))?;
+    let bundles = Manifest::parse(&source)
+        .and_then(|manifest| manifest.resolve(&Schema::firmware()))
+        .map_err(|e| e.to_string())?;
+
+    if args.check_only {
+        println!("OK: {} デバイス", bundles.len());
+        return Ok(());
+    }
+
+    for bundle in &bundles {
+        write_bundle(args, bundle)?;
+        println!("{} ({}) -> {}", bundle.name, bundle.mac, args.out_dir.join(&bundle.name).display());
+    }
+
+    match emit::gateway_cfg_toml(&bundles) {
+        Some(gateway) => write_file(&args.out_dir.join("gateway.cfg.toml"), &gateway)?,
+        None => eprintln!(
+            "警告: デバイス数 {} がゲートウェイの登録上限 {} を超えるため gateway.cfg.toml を生成しません",
+            bundles.len(),
+            manifest::MAX_GATEWAY_CAMERAS
+        ),
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let result = parse_args().and_then(|args| run(&args));
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("エラー: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,383 @@
+//! フリートマニフェストの読み込みとデバイス別設定の解決
+//!
+//! 設定値は「マニフェストの `[defaults]` → デバイスの `schedule` → デバイスの `[devices.config]`
+//! → デバイスの `receiver_mac`」の順に上書きされます。解決後の値はファームウェアの
+//! スキーマと検証関数で検査します。
+
+use std::collections::{BTreeMap, HashSet};
+
+use serde::Deserialize;
+use toml::Value;
+
+use crate::config_staging::RemoteConfig;
+use crate::device_identity::is_valid_device_id;
+use crate::config_validation::{
+    parse_camera_warmup_frames, parse_receiver_mac, parse_target_minute_last_digit,
+    parse_target_second_tens_digit, ValidationError,
+};
+use crate::fb_policy::{FrameBufferPlacement, MAX_FB_COUNT, MIN_FB_COUNT};
+use crate::mac_address::MacAddress;
+use crate::schema::Schema;
+
+/// camera_standby_mode の有効値（core/config.rs と同じ）
+const CAMERA_STANDBY_MODES: [&str; 4] = ["auto", "off", "minimal", "full"];
+
+/// ゲートウェイが登録できるカメラ数（usb_cdc_receiver の image_sender_cam1..6）
+pub const MAX_GATEWAY_CAMERAS: usize = 6;
+
+/// マニフェストのエラー
+#[derive(Debug, thiserror::Error)]
+pub enum ManifestError {
+    #[error("マニフェストを解析できません: {0}")]
+    Parse(#[from] toml::de::Error),
+    #[error("デバイスが定義されていません")]
+    NoDevices,
+    #[error("デバイス名が不正です（英数字, '-', '_' のみ）: {0:?}")]
+    InvalidName(String),
+    #[error("デバイス名が重複しています: {0}")]
+    DuplicateName(String),
+    #[error("{device}: デバイスMACアドレスが不正です: {mac}")]
+    InvalidDeviceMac { device: String, mac: String },
+    #[error("{device}: デバイスMACアドレスが重複しています: {mac}")]
+    DuplicateMac { device: String, mac: String },
+    #[error("{device}: デバイスIDが不正です（ハイフン付きのUUID）: {id}")]
+    InvalidDeviceId { device: String, id: String },
+    #[error("{device}: デバイスIDが重複しています: {id}")]
+    DuplicateDeviceId { device: String, id: String },
+    #[error("{device}: {message}")]
+    InvalidValue { device: String, message: String },
+    #[error("{device}: {error:?}")]
+    Validation { device: String, error: ValidationError },
+    #[error("{device}: デバイスMACアドレスと受信機MACアドレスが同じです")]
+    ReceiverIsDevice { device: String },
+}
+
+/// 起動スケジュール
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Schedule {
+    /// ディープスリープ時間（秒）
+    pub sleep_seconds: Option<u64>,
+    /// 起動する「分」の下一桁
+    pub minute_last_digit: Option<u8>,
+    /// 起動する「秒」の桁
+    pub second_digit: Option<u8>,
+}
+
+/// マニフェストのデバイス定義
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DeviceEntry {
+    /// デバイス名（出力ディレクトリ名）
+    pub name: String,
+    /// デバイス自身のMACアドレス（ゲートウェイ登録用）
+    pub mac: String,
+    /// デバイスID（UUID）。基板を交換してMACアドレスが変わっても同じ値を使い続ける
+    pub device_id: Option<String>,
+    /// 受信機のMACアドレス（フリート既定値を上書き）
+    pub receiver_mac: Option<String>,
+    #[serde(default)]
+    pub schedule: Schedule,
+    /// 任意のcfg.toml項目の上書き
+    #[serde(default)]
+    pub config: BTreeMap<String, Value>,
+}
+
+/// フリートマニフェスト
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    /// フリート共通の受信機MACアドレス
+    pub receiver_mac: Option<String>,
+    /// 全デバイス共通のcfg.toml項目
+    #[serde(default)]
+    pub defaults: BTreeMap<String, Value>,
+    #[serde(default)]
+    pub devices: Vec<DeviceEntry>,
+}
+
+/// 解決済みのデバイス設定
+#[derive(Debug, Clone)]
+pub struct DeviceBundle {
+    pub name: String,
+    pub mac: MacAddress,
+    /// NVSに書き込むデバイスID（小文字）
+    pub device_id: Option<String>,
+    /// cfg.toml に書き出す項目（スキーマの宣言順）
+    pub values: Vec<(String, Value)>,
+    /// NVSに書き込むリモート設定
+    pub remote_config: RemoteConfig,
+}
+
+impl Manifest {
+    /// TOML文字列から読み込みます
+    pub fn parse(source: &str) -> Result<Self, ManifestError> {
+        Ok(toml::from_str(source)?)
+    }
+
+    /// 全デバイスの設定を解決・検証します
+    pub fn resolve(&self, schema: &Schema) -> Result<Vec<DeviceBundle>, ManifestError> {
+        if self.devices.is_empty() {
+            return Err(ManifestError::NoDevices);
+        }
+
+        let mut names = HashSet::new();
+        let mut macs = HashSet::new();
+        let mut device_ids = HashSet::new();
+        let mut bundles = Vec::with_capacity(self.devices.len());
+        for device in &self.devices {
+            if !is_valid_name(&device.name) {
+                return Err(ManifestError::InvalidName(device.name.clone()));
+            }
+            if !names.insert(device.name.as_str()) {
+                return Err(ManifestError::DuplicateName(device.name.clone()));
+            }
+            let mac = MacAddress::from_str(&device.mac).map_err(|_| ManifestError::InvalidDeviceMac {
+                device: device.name.clone(),
+                mac: device.mac.clone(),
+            })?;
+            if !macs.insert(mac.to_string()) {
+                return Err(ManifestError::DuplicateMac {
+                    device: device.name.clone(),
+                    mac: device.mac.clone(),
+                });
+            }
+            if let Some(id) = &device.device_id {
+                if !is_valid_device_id(id) {
+                    return Err(ManifestError::InvalidDeviceId {
+                        device: device.name.clone(),
+                        id: id.clone(),
+                    });
+                }
+                if !device_ids.insert(id.to_ascii_lowercase()) {
+                    return Err(ManifestError::DuplicateDeviceId {
+                        device: device.name.clone(),
+                        id: id.clone(),
+                    });
+                }
+            }
+            bundles.push(self.resolve_device(schema, device, mac)?);
+        }
+        Ok(bundles)
+    }
+
+    fn resolve_device(
+        &self,
+        schema: &Schema,
+        device: &DeviceEntry,
+        mac: MacAddress,
+    ) -> Result<DeviceBundle, ManifestError> {
+        let mut merged: BTreeMap<String, Value> = self.defaults.clone();
+        let schedule = &device.schedule;
+        if let Some(seconds) = schedule.sleep_seconds {
+            merged.insert("sleep_duration_seconds".into(), Value::Integer(seconds as i64));
+        }
+        if let Some(digit) = schedule.minute_last_digit {
+            merged.insert("target_minute_last_digit".into(), Value::Integer(digit as i64));
+        }
+        if let Some(digit) = schedule.second_digit {
+            merged.insert("target_second_last_digit".into(), Value::Integer(digit as i64));
+        }
+        merged.extend(device.config.clone());
+        if let Some(receiver) = device.receiver_mac.as_ref().or(self.receiver_mac.as_ref()) {
+            merged.insert("receiver_mac".into(), Value::String(receiver.clone()));
+        }
+
+        let invalid = |message: String| ManifestError::InvalidValue {
+            device: device.name.clone(),
+            message,
+        };
+        let validation = |error: ValidationError| ManifestError::Validation {
+            device: device.name.clone(),
+            error,
+        };
+
+        for (key, value) in &merged {
+            schema.check_value(key, value).map_err(invalid)?;
+        }
+
+        // ファームウェアの AppConfig::load と同じ検証
+        let receiver_mac = parse_receiver_mac(str_value(&merged, "receiver_mac").unwrap_or(""))
+            .map_err(validation)?;
+        if receiver_mac == mac {
+            return Err(ManifestError::ReceiverIsDevice {
+                device: device.name.clone(),
+            });
+        }
+        if let Some(frames) = u8_value(&merged, "camera_warmup_frames") {
+            parse_camera_warmup_frames(frames).map_err(validation)?;
+        }
+        if let Some(digit) = u8_value(&merged, "target_minute_last_digit") {
+            parse_target_minute_last_digit(digit).map_err(validation)?;
+        }
+        if let Some(digit) = u8_value(&merged, "target_second_last_digit") {
+            parse_target_second_tens_digit(digit).map_err(validation)?;
+        }
+        if let Some(mode) = str_value(&merged, "camera_standby_mode") {
+            let mode = mode.trim().to_ascii_lowercase();
+            if !CAMERA_STANDBY_MODES.contains(&mode.as_str()) {
+                return Err(invalid(format!("camera_standby_mode の値が無効です: {}", mode)));
+            }
+        }
+        if let Some(placement) = str_value(&merged, "camera_fb_placement") {
+            if FrameBufferPlacement::parse(placement).is_none() {
+                return Err(invalid(format!("camera_fb_placement の値が無効です: {}", placement)));
+            }
+        }
+        if let Some(count) = u8_value(&merged, "camera_fb_count") {
+            if !(MIN_FB_COUNT..=MAX_FB_COUNT).contains(&count) {
+                return Err(invalid(format!("camera_fb_count の値が無効です (1-3): {}", count)));
+            }
+        }
+        let sleep_duration_seconds = match merged.get("sleep_duration_seconds") {
+            Some(Value::Integer(0)) => {
+                return Err(invalid("sleep_duration_seconds は1以上が必要です".into()));
+            }
+            Some(Value::Integer(seconds)) => Some(*seconds as u64),
+            _ => None,
+        };
+
+        let values = schema
+            .fields()
+            .iter()
+            .filter_map(|field| merged.get(&field.name).map(|v| (field.name.clone(), v.clone())))
+            .collect();
+
+        Ok(DeviceBundle {
+            name: device.name.clone(),
+            mac,
+            device_id: device.device_id.as_ref().map(|id| id.to_ascii_lowercase()),
+            values,
+            remote_config: RemoteConfig {
+                receiver_mac: Some(receiver_mac),
+                sleep_duration_seconds,
+                ..RemoteConfig::default()
+            },
+        })
+    }
+}
+
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+fn str_value<'a>(values: &'a BTreeMap<String, Value>, key: &str) -> Option<&'a str> {
+    values.get(key).and_then(Value::as_str)
+}
+
+fn u8_value(values: &BTreeMap<String, Value>, key: &str) -> Option<u8> {
+    values
+        .get(key)
+        .and_then(Value::as_integer)
+        .and_then(|n| u8::try_from(n).ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MANIFEST: &str = r#"
+receiver_mac = "AA:BB:CC:DD:EE:01"
+
+[defaults]
+sleep_duration_seconds = 600
+frame_size = "UXGA"
+
+[[devices]]
+name = "cam1"
+mac = "24:0A:C4:00:00:01"
+device_id = "3F2A9C1E-0B7D-4E58-9A61-2C4F8D0E7B15"
+schedule = { minute_last_digit = 0, second_digit = 1 }
+
+[[devices]]
+name = "cam2"
+mac = "24:0A:C4:00:00:02"
+receiver_mac = "AA:BB:CC:DD:EE:02"
+schedule = { sleep_seconds = 1200 }
+config = { camera_fb_count = 2 }
+"#;
+
+    fn value<'a>(bundle: &'a DeviceBundle, key: &str) -> Option<&'a Value> {
+        bundle.values.iter().find(|(k, _)| k == key).map(|(_, v)| v)
+    }
+
+    #[test]
+    fn test_resolve_applies_overrides_in_order() {
+        let bundles = Manifest::parse(MANIFEST)
+            .unwrap()
+            .resolve(&Schema::firmware())
+            .unwrap();
+        assert_eq!(bundles.len(), 2);
+
+        let cam1 = &bundles[0];
+        assert_eq!(value(cam1, "receiver_mac"), Some(&Value::String("AA:BB:CC:DD:EE:01".into())));
+        assert_eq!(value(cam1, "target_minute_last_digit"), Some(&Value::Integer(0)));
+        assert_eq!(cam1.values[0].0, "receiver_mac");
+        assert_eq!(cam1.device_id.as_deref(), Some("3f2a9c1e-0b7d-4e58-9a61-2c4f8d0e7b15"));
+
+        let cam2 = &bundles[1];
+        assert_eq!(value(cam2, "sleep_duration_seconds"), Some(&Value::Integer(1200)));
+        assert_eq!(value(cam2, "camera_fb_count"), Some(&Value::Integer(2)));
+        assert_eq!(cam2.remote_config.encode(), "RECEIVER_MAC=aa:bb:cc:dd:ee:02;SLEEP=1200");
+        assert_eq!(cam2.device_id, None);
+    }
+
+    #[test]
+    fn test_resolve_rejects_invalid_devices() {
+        let schema = Schema::firmware();
+        let resolve = |extra: &str| {
+            let source = format!("receiver_mac = \"AA:BB:CC:DD:EE:01\"\n{}", extra);
+            Manifest::parse(&source).and_then(|m| m.resolve(&schema))
+        };
+
+        assert!(matches!(resolve(""), Err(ManifestError::NoDevices)));
+        assert!(matches!(
+            resolve("[[devices]]\nname = \"a\"\nmac = \"01:02:03:04:05:06\"\n[[devices]]\nname = \"b\"\nmac = \"01:02:03:04:05:06\""),
+            Err(ManifestError::DuplicateMac { .. })
+        ));
+        assert!(matches!(
+            resolve("[[devices]]\nname = \"a\"\nmac = \"01:02:03:04:05:06\"\ndevice_id = \"cam-a\""),
+            Err(ManifestError::InvalidDeviceId { .. })
+        ));
+        let id = "3f2a9c1e-0b7d-4e58-9a61-2c4f8d0e7b15";
+        assert!(matches!(
+            resolve(&format!(
+                "[[devices]]\nname = \"a\"\nmac = \"01:02:03:04:05:06\"\ndevice_id = \"{}\"\n\
+                 [[devices]]\nname = \"b\"\nmac = \"01:02:03:04:05:07\"\ndevice_id = \"{}\"",
+                id,
+                id.to_ascii_uppercase()
+            )),
+            Err(ManifestError::DuplicateDeviceId { .. })
+        ));
+        assert!(matches!(
+            resolve("[[devices]]\nname = \"a\"\nmac = \"01:02:03:04:05:06\"\nconfig = { camera_fb_count = 4 }"),
+            Err(ManifestError::InvalidValue { .. })
+        ));
+        assert!(matches!(
+            resolve("[[devices]]\nname = \"a\"\nmac = \"01:02:03:04:05:06\"\nschedule = { minute_last_digit = 12 }"),
+            Err(ManifestError::Validation {
+                error: ValidationError::InvalidTargetMinuteLastDigit(12),
+                ..
+            })
+        ));
+        assert!(matches!(
+            resolve("[[devices]]\nname = \"a\"\nmac = \"AA:BB:CC:DD:EE:01\""),
+            Err(ManifestError::ReceiverIsDevice { .. })
+        ));
+    }
+
+    #[test]
+    fn test_missing_receiver_mac_is_rejected() {
+        let manifest = Manifest::parse("[[devices]]\nname = \"a\"\nmac = \"01:02:03:04:05:06\"").unwrap();
+        assert!(matches!(
+            manifest.resolve(&Schema::firmware()),
+            Err(ManifestError::Validation {
+                error: ValidationError::MissingReceiverMac,
+                ..
+            })
+        ));
+    }
+}
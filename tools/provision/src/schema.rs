@@ -0,0 +1,160 @@
+//! ファームウェアの `Config` 構造体から抽出した設定スキーマ
+//!
+//! `devices/m5stack_unit_cam/src/core/config.rs` の `#[toml_cfg::toml_config]` 定義を
+//! ビルド時に取り込み、キー名と型を読み取ります。ファームウェアに設定項目を追加すると
+//! 再ビルドだけでこのツールにも反映されます。
+
+use toml::Value;
+
+/// ファームウェアの設定定義ソース
+const FIRMWARE_CONFIG_SOURCE: &str =
+    include_str!("../../../devices/m5stack_unit_cam/src/core/config.rs");
+
+/// 設定項目の型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldType {
+    Str,
+    Bool,
+    U8,
+    U16,
+    U32,
+    U64,
+    I8,
+    I64,
+}
+
+impl FieldType {
+    fn from_rust(ty: &str) -> Option<Self> {
+        match ty {
+            "&'static str" => Some(FieldType::Str),
+            "bool" => Some(FieldType::Bool),
+            "u8" => Some(FieldType::U8),
+            "u16" => Some(FieldType::U16),
+            "u32" => Some(FieldType::U32),
+            "u64" => Some(FieldType::U64),
+            "i8" => Some(FieldType::I8),
+            "i64" => Some(FieldType::I64),
+            _ => None,
+        }
+    }
+
+    /// 整数型の値域
+    fn int_range(self) -> Option<(i64, i64)> {
+        match self {
+            FieldType::U8 => Some((0, u8::MAX as i64)),
+            FieldType::U16 => Some((0, u16::MAX as i64)),
+            FieldType::U32 => Some((0, u32::MAX as i64)),
+            FieldType::U64 => Some((0, i64::MAX)),
+            FieldType::I8 => Some((i8::MIN as i64, i8::MAX as i64)),
+            FieldType::I64 => Some((i64::MIN, i64::MAX)),
+            FieldType::Str | FieldType::Bool => None,
+        }
+    }
+
+    fn describe(self) -> &'static str {
+        match self {
+            FieldType::Str => "string",
+            FieldType::Bool => "bool",
+            FieldType::U8 => "u8",
+            FieldType::U16 => "u16",
+            FieldType::U32 => "u32",
+            FieldType::U64 => "u64",
+            FieldType::I8 => "i8",
+            FieldType::I64 => "i64",
+        }
+    }
+}
+
+/// 設定項目
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Field {
+    pub name: String,
+    pub ty: FieldType,
+}
+
+/// 設定スキーマ（ファームウェアの宣言順）
+#[derive(Debug, Clone)]
+pub struct Schema {
+    fields: Vec<Field>,
+}
+
+impl Schema {
+    /// ファームウェアの `Config` 定義からスキーマを構築します
+    pub fn firmware() -> Self {
+        Self::parse(FIRMWARE_CONFIG_SOURCE).expect("ファームウェアのConfig定義を解析できません")
+    }
+
+    /// `#[toml_cfg::toml_config]` 直後の構造体定義を解析します
+    pub fn parse(source: &str) -> Option<Self> {
+        let start = source.find("#[toml_cfg::toml_config]")?;
+        let body_start = start + source[start..].find('{')? + 1;
+        let body_end = body_start + source[body_start..].find('}')?;
+
+        let mut fields = Vec::new();
+        for line in source[body_start..body_end].lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with("//") || line.starts_with("#[") {
+                continue;
+            }
+            let (name, ty) = line.trim_end_matches(',').split_once(':')?;
+            fields.push(Field {
+                name: name.trim().to_string(),
+                ty: FieldType::from_rust(ty.trim())?,
+            });
+        }
+        Some(Self { fields })
+    }
+
+    /// 宣言順の設定項目
+    pub fn fields(&self) -> &[Field] {
+        &self.fields
+    }
+
+    /// キーの型を取得します
+    pub fn field_type(&self, key: &str) -> Option<FieldType> {
+        self.fields.iter().find(|f| f.name == key).map(|f| f.ty)
+    }
+
+    /// 値がキーの型に合うか検査します
+    ///
+    /// # 戻り値
+    /// 不一致の場合はエラーメッセージ
+    pub fn check_value(&self, key: &str, value: &Value) -> Result<(), String> {
+        let ty = self
+            .field_type(key)
+            .ok_or_else(|| format!("ファームウェアに存在しない設定項目です: {}", key))?;
+        let ok = match (ty, value) {
+            (FieldType::Str, Value::String(_)) | (FieldType::Bool, Value::Boolean(_)) => true,
+            (_, Value::Integer(n)) => ty.int_range().is_some_and(|(min, max)| (min..=max).contains(n)),
+            _ => false,
+        };
+        if ok {
+            Ok(())
+        } else {
+            Err(format!("{} は {} 型の値が必要です: {}", key, ty.describe(), value))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_firmware_schema_contains_known_fields() {
+        let schema = Schema::firmware();
+        assert_eq!(schema.field_type("receiver_mac"), Some(FieldType::Str));
+        assert_eq!(schema.field_type("sleep_duration_seconds"), Some(FieldType::U64));
+        assert_eq!(schema.field_type("wifi_tx_power_dbm"), Some(FieldType::I8));
+        assert_eq!(schema.fields()[0].name, "receiver_mac");
+    }
+
+    #[test]
+    fn test_check_value_rejects_type_and_range_errors() {
+        let schema = Schema::firmware();
+        assert!(schema.check_value("camera_fb_count", &Value::Integer(2)).is_ok());
+        assert!(schema.check_value("camera_fb_count", &Value::Integer(256)).is_err());
+        assert!(schema.check_value("debug_mode", &Value::String("yes".into())).is_err());
+        assert!(schema.check_value("no_such_key", &Value::Boolean(true)).is_err());
+    }
+}
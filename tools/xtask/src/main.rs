@@ -0,0 +1,199 @@
+//! ゲートウェイ・カメラのビルド・書き込み・モニター・ホストテストをまとめて実行する開発用ツール
+//!
+//! 使い方（リポジトリのルートで `cargo xtask ...`）:
+//!   xtask build --target <xiao|m5stack|gateway> [--release] [--features <a,b>]
+//!   xtask flash --target <xiao|m5stack|gateway> [--port <port>] [--release] [--features <a,b>] [--monitor]
+//!   xtask monitor [--port <port>]
+//!   xtask test-host [--target <xiao|m5stack|gateway>]
+//!
+//! `--dry-run` を付けると実行するコマンドを表示するだけにします。
+//! クレートごとのターゲット・機能フラグ・パーティションテーブルは `target.rs` にまとめています。
+
+mod plan;
+mod target;
+
+use std::process::{Command, ExitCode};
+
+use plan::{FirmwareOptions, Step};
+use target::{repo_root, Target};
+
+const USAGE: &str = "使い方: xtask <build|flash|monitor|test-host> [--target <xiao|m5stack|gateway>] \
+                     [--port <port>] [--release] [--features <a,b>] [--monitor] [--dry-run]";
+
+/// サブコマンド
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Task {
+    Build(Target),
+    Flash { target: Target, port: Option<String>, monitor: bool },
+    Monitor { port: Option<String> },
+    /// 対象を指定しなければすべて
+    TestHost(Option<Target>),
+}
+
+/// コマンドライン引数
+#[derive(Debug)]
+struct Args {
+    task: Task,
+    firmware: FirmwareOptions,
+    dry_run: bool,
+}
+
+fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Args, String> {
+    let mut args = args.into_iter();
+    let command = args.next().ok_or(USAGE)?;
+    let mut target = None;
+    let mut port = None;
+    let mut monitor = false;
+    let mut firmware = FirmwareOptions::default();
+    let mut dry_run = false;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--target" => {
+                let name = args.next().ok_or("--target には xiao / m5stack / gateway が必要です")?;
+                target = Some(Target::from_name(&name).ok_or(format!("不明なターゲット: {}", name))?);
+            }
+            "--port" => port = Some(args.next().ok_or("--port にはシリアルポートが必要です")?),
+            "--release" => firmware.release = true,
+            "--features" => {
+                let features = args.next().ok_or("--features には機能フラグが必要です")?;
+                firmware
+                    .features
+                    .extend(features.split(',').map(str::trim).filter(|f| !f.is_empty()).map(String::from));
+            }
+            "--monitor" => monitor = true,
+            "--dry-run" => dry_run = true,
+            other => return Err(format!("不明な引数: {}\n{}", other, USAGE)),
+        }
+    }
+
+    let require_target = || target.ok_or(format!("{} には --target が必要です", command));
+    let task = match command.as_str() {
+        "build" => Task::Build(require_target()?),
+        "flash" => Task::Flash {
+            target: require_target()?,
+            port,
+            monitor,
+        },
+        "monitor" => Task::Monitor { port },
+        "test-host" => Task::TestHost(target),
+        other => return Err(format!("不明なコマンド: {}\n{}", other, USAGE)),
+    };
+    if let Task::Build(target) | Task::Flash { target, .. } = &task {
+        firmware.validate(*target)?;
+    }
+    Ok(Args { task, firmware, dry_run })
+}
+
+/// ホストのターゲットを `rustc -vV` から取得します
+fn host_triple() -> Result<String, String> {
+    let output = Command::new("rustc")
+        .arg("-vV")
+        .output()
+        .map_err(|e| format!("rustc を実行できません: {}", e))?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    plan::parse_host_triple(&stdout)
+        .map(String::from)
+        .ok_or_else(|| "rustc -vV の出力にホストのターゲットがありません".to_string())
+}
+
+fn steps(args: &Args) -> Result<Vec<Step>, String> {
+    Ok(match &args.task {
+        Task::Build(target) => plan::build(*target, &args.firmware),
+        Task::Flash { target, port, monitor } => plan::flash(*target, &args.firmware, port.as_deref(), *monitor),
+        Task::Monitor { port } => plan::monitor(port.as_deref()),
+        Task::TestHost(target) => {
+            let targets = target.map_or(Target::ALL.to_vec(), |target| vec![target]);
+            plan::test_host(&targets, &host_triple()?)
+        }
+    })
+}
+
+/// コマンドを実行します（成功したらtrue）
+fn run_step(step: &Step) -> Result<bool, String> {
+    println!("$ {}", step);
+    let status = Command::new(step.program)
+        .args(&step.args)
+        .current_dir(repo_root().join(step.dir))
+        // `cargo xtask` のツールチェーンを引き継がず、各クレートの rust-toolchain.toml に従う
+        .env_remove("RUSTUP_TOOLCHAIN")
+        .status()
+        .map_err(|e| format!("{} を実行できません: {}", step.program, e))?;
+    Ok(status.success())
+}
+
+fn run(args: &Args) -> Result<(), String> {
+    let steps = steps(args)?;
+    if args.dry_run {
+        for step in &steps {
+            println!("{}", step);
+        }
+        return Ok(());
+    }
+
+    // ホストテストは失敗しても残りを実行し、最後にまとめて報告する
+    let keep_going = matches!(args.task, Task::TestHost(_));
+    let mut failed: Vec<&str> = Vec::new();
+    for step in &steps {
+        if failed.contains(&step.label) {
+            continue;
+        }
+        if !run_step(step)? {
+            if !keep_going {
+                return Err(format!("{} が失敗しました", step));
+            }
+            failed.push(step.label);
+        }
+    }
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("失敗: {}", failed.join(", ")))
+    }
+}
+
+fn main() -> ExitCode {
+    let result = parse_args(std::env::args().skip(1)).and_then(|args| run(&args));
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("エラー: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(line: &str) -> Result<Args, String> {
+        parse_args(line.split_whitespace().map(String::from))
+    }
+
+    #[test]
+    fn test_parse_flash() {
+        let args = parse("flash --target m5stack --port /dev/ttyUSB0 --release --features chaos,soak-test --monitor")
+            .unwrap();
+        assert_eq!(
+            args.task,
+            Task::Flash {
+                target: Target::M5stack,
+                port: Some("/dev/ttyUSB0".to_string()),
+                monitor: true,
+            }
+        );
+        assert!(args.firmware.release);
+        assert_eq!(args.firmware.features, ["chaos", "soak-test"]);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(parse("build").unwrap_err().contains("--target"));
+        assert!(parse("build --target esp32").unwrap_err().contains("esp32"));
+        assert!(parse("build --target xiao --features chaos").unwrap_err().contains("chaos"));
+        assert!(parse("deploy").unwrap_err().contains("deploy"));
+        assert_eq!(parse("test-host").unwrap().task, Task::TestHost(None));
+        assert_eq!(parse("monitor").unwrap().task, Task::Monitor { port: None });
+    }
+}
@@ -0,0 +1,228 @@
+//! サブコマンドごとに実行するコマンドの組み立て
+//!
+//! 実行せずに組み立てるだけにして、`--dry-run` での表示とテストに使います。
+
+use std::fmt;
+
+use crate::target::Target;
+
+/// 実行する1つのコマンド
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Step {
+    /// 結果の集計に使う名前
+    pub label: &'static str,
+    /// 実行するディレクトリ（リポジトリのルートからの相対パス）
+    pub dir: &'static str,
+    pub program: &'static str,
+    pub args: Vec<String>,
+}
+
+impl Step {
+    fn new(label: &'static str, dir: &'static str, program: &'static str, args: &[&str]) -> Self {
+        Self {
+            label,
+            dir,
+            program,
+            args: args.iter().map(|arg| arg.to_string()).collect(),
+        }
+    }
+
+    fn arg(mut self, arg: impl Into<String>) -> Self {
+        self.args.push(arg.into());
+        self
+    }
+
+    fn args_if(mut self, condition: bool, args: &[&str]) -> Self {
+        if condition {
+            self.args.extend(args.iter().map(|arg| arg.to_string()));
+        }
+        self
+    }
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "({}) {}", self.dir, self.program)?;
+        for arg in &self.args {
+            write!(f, " {}", arg)?;
+        }
+        Ok(())
+    }
+}
+
+/// ファームウェアのビルドのオプション
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FirmwareOptions {
+    pub release: bool,
+    /// 追加の機能フラグ（`Target::firmware_features` のいずれか）
+    pub features: Vec<String>,
+}
+
+impl FirmwareOptions {
+    /// 機能フラグが対象のクレートにあるか確認します
+    pub fn validate(&self, target: Target) -> Result<(), String> {
+        match self
+            .features
+            .iter()
+            .find(|feature| !target.firmware_features().contains(&feature.as_str()))
+        {
+            Some(feature) => Err(format!(
+                "{} に機能フラグ {} はありません（指定できるもの: {}）",
+                target.name(),
+                feature,
+                target.firmware_features().join(", ")
+            )),
+            None => Ok(()),
+        }
+    }
+
+    fn apply(&self, target: Target, step: Step) -> Step {
+        let mut step = step.args_if(self.release, &["--release"]);
+        step.args.extend(target.firmware_build_args().iter().map(|arg| arg.to_string()));
+        if self.features.is_empty() {
+            step
+        } else {
+            step.arg("--features").arg(self.features.join(","))
+        }
+    }
+}
+
+/// `build`: ファームウェアをビルドします
+pub fn build(target: Target, options: &FirmwareOptions) -> Vec<Step> {
+    let step = Step::new(target.name(), target.crate_dir(), "cargo", &["build"]);
+    vec![options.apply(target, step)]
+}
+
+/// `flash`: ファームウェアをビルドして書き込みます（`cargo espflash` がビルドも行う）
+pub fn flash(target: Target, options: &FirmwareOptions, port: Option<&str>, monitor: bool) -> Vec<Step> {
+    let mut step = Step::new(
+        target.name(),
+        target.crate_dir(),
+        "cargo",
+        &["espflash", "flash", "--partition-table", target.partition_table()],
+    );
+    if let Some(port) = port {
+        step = step.arg("--port").arg(port);
+    }
+    let step = options.apply(target, step).args_if(monitor, &["--monitor"]);
+    vec![step]
+}
+
+/// `monitor`: シリアルモニターを開きます
+pub fn monitor(port: Option<&str>) -> Vec<Step> {
+    let mut step = Step::new("monitor", ".", "espflash", &["monitor"]);
+    if let Some(port) = port {
+        step = step.arg("--port").arg(port);
+    }
+    vec![step]
+}
+
+/// `test-host`: ホストで実行できるテストをすべて実行します
+///
+/// # 引数
+/// * `targets` - テストするファームウェア（プロビジョニングツールのテストは常に実行）
+/// * `host_triple` - ホストのターゲット（各クレートの `.cargo/config.toml` のターゲットを上書きする）
+pub fn test_host(targets: &[Target], host_triple: &str) -> Vec<Step> {
+    let mut steps = Vec::new();
+    for &target in targets {
+        if target == Target::M5stack {
+            // ESP-IDF非依存のモジュールがホストでビルドできることを確認
+            steps.push(
+                Step::new(
+                    target.name(),
+                    target.crate_dir(),
+                    "cargo",
+                    &["+stable", "build", "--lib", "--no-default-features", "--features", "host"],
+                )
+                .arg("--target")
+                .arg(host_triple),
+            );
+        }
+        let (dir, args) = target.host_test();
+        let mut step = Step::new(target.name(), dir, "cargo", &["+stable", "test"]);
+        step.args.extend(args.iter().map(|arg| arg.to_string()));
+        steps.push(step.arg("--target").arg(host_triple));
+    }
+    steps.push(Step::new("provision", "tools/provision", "cargo", &["+stable", "test"]));
+    steps
+}
+
+/// `rustc -vV` の出力からホストのターゲットを取り出します
+pub fn parse_host_triple(rustc_version: &str) -> Option<&str> {
+    rustc_version.lines().find_map(|line| line.strip_prefix("host: ")).map(str::trim)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command_lines(steps: &[Step]) -> Vec<String> {
+        steps.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn test_build_adds_per_crate_arguments() {
+        let options = FirmwareOptions {
+            release: true,
+            features: vec!["legacy-sleep-command".to_string()],
+        };
+        assert_eq!(
+            command_lines(&build(Target::Xiao, &options)),
+            ["(devices/xiao_esp32s3_sense) cargo build --release --target xtensa-esp32s3-espidf \
+              -Zbuild-std=std,panic_abort --features legacy-sleep-command"]
+        );
+        assert_eq!(
+            command_lines(&build(Target::Gateway, &FirmwareOptions::default())),
+            ["(server/usb_cdc_receiver) cargo build"]
+        );
+    }
+
+    #[test]
+    fn test_flash_uses_partition_table_and_port() {
+        let options = FirmwareOptions {
+            release: true,
+            features: Vec::new(),
+        };
+        assert_eq!(
+            command_lines(&flash(Target::M5stack, &options, Some("/dev/ttyUSB0"), true)),
+            ["(devices/m5stack_unit_cam) cargo espflash flash --partition-table partitions.csv \
+              --port /dev/ttyUSB0 --release --monitor"]
+        );
+        assert_eq!(command_lines(&monitor(None)), ["(.) espflash monitor"]);
+    }
+
+    #[test]
+    fn test_unknown_feature_is_rejected() {
+        let options = FirmwareOptions {
+            release: false,
+            features: vec!["soak-test".to_string()],
+        };
+        assert!(options.validate(Target::M5stack).is_ok());
+        assert!(options.validate(Target::Gateway).unwrap_err().contains("soak-test"));
+    }
+
+    #[test]
+    fn test_host_matrix_covers_every_crate() {
+        let steps = test_host(&Target::ALL, "x86_64-unknown-linux-gnu");
+        assert_eq!(
+            command_lines(&steps),
+            [
+                "(server/usb_cdc_receiver) cargo +stable test --lib --tests --no-default-features --features host \
+                 --target x86_64-unknown-linux-gnu",
+                "(devices/m5stack_unit_cam) cargo +stable build --lib --no-default-features --features host \
+                 --target x86_64-unknown-linux-gnu",
+                "(devices/m5stack_unit_cam/host_frame_tests) cargo +stable test --target x86_64-unknown-linux-gnu",
+                "(devices/xiao_esp32s3_sense) cargo +stable test --lib --no-default-features --features host \
+                 --target x86_64-unknown-linux-gnu",
+                "(tools/provision) cargo +stable test",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_host_triple() {
+        let output = "rustc 1.80.0 (051478957 2024-07-21)\nbinary: rustc\nhost: aarch64-apple-darwin\nrelease: 1.80.0\n";
+        assert_eq!(parse_host_triple(output), Some("aarch64-apple-darwin"));
+        assert_eq!(parse_host_triple("rustc 1.80.0"), None);
+    }
+}
@@ -0,0 +1,131 @@
+//! ビルド対象のクレートとクレートごとの設定
+
+use std::path::PathBuf;
+
+/// リポジトリのルート（このクレートは `tools/xtask` にある）
+pub fn repo_root() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../..")
+}
+
+/// ビルド対象
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    /// XIAO ESP32S3 Sense（ESP32-S3）
+    Xiao,
+    /// M5Stack Unit Cam（ESP32）
+    M5stack,
+    /// USB CDCゲートウェイ（ESP32-C3）
+    Gateway,
+}
+
+impl Target {
+    /// すべての対象（ホストテストの実行順）
+    pub const ALL: [Target; 3] = [Target::Gateway, Target::M5stack, Target::Xiao];
+
+    /// `--target` の値から対象を決めます
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "xiao" => Some(Target::Xiao),
+            "m5stack" => Some(Target::M5stack),
+            "gateway" => Some(Target::Gateway),
+            _ => None,
+        }
+    }
+
+    /// `--target` の値
+    pub fn name(self) -> &'static str {
+        match self {
+            Target::Xiao => "xiao",
+            Target::M5stack => "m5stack",
+            Target::Gateway => "gateway",
+        }
+    }
+
+    /// クレートのディレクトリ（リポジトリのルートからの相対パス）
+    pub fn crate_dir(self) -> &'static str {
+        match self {
+            Target::Xiao => "devices/xiao_esp32s3_sense",
+            Target::M5stack => "devices/m5stack_unit_cam",
+            Target::Gateway => "server/usb_cdc_receiver",
+        }
+    }
+
+    /// ファームウェアのビルドに追加する引数
+    ///
+    /// M5Stack Unit Camとゲートウェイはクレートの `.cargo/config.toml` がターゲットと `build-std` を
+    /// 指定します。XIAOには `.cargo/config.toml` がないため、ここで指定します。
+    pub fn firmware_build_args(self) -> &'static [&'static str] {
+        match self {
+            Target::Xiao => &["--target", "xtensa-esp32s3-espidf", "-Zbuild-std=std,panic_abort"],
+            Target::M5stack | Target::Gateway => &[],
+        }
+    }
+
+    /// 書き込むパーティションテーブル（クレートのディレクトリからの相対パス）
+    pub fn partition_table(self) -> &'static str {
+        "partitions.csv"
+    }
+
+    /// `--features` に指定できるファームウェアの機能フラグ（`esp` 以外）
+    pub fn firmware_features(self) -> &'static [&'static str] {
+        match self {
+            Target::Xiao => &["legacy-sleep-command"],
+            Target::M5stack => &["legacy-sleep-command", "soak-test", "chaos", "qemu-smoke"],
+            Target::Gateway => &["legacy-sleep-command", "sntp", "chaos"],
+        }
+    }
+
+    /// ホストテストで `cargo test` を実行するディレクトリと追加の引数
+    ///
+    /// 各クレートの `run_tests.sh` と同じく、ESP-IDF非依存のモジュールだけを `host` 機能でビルドします。
+    /// M5Stack Unit Camのロジックのテストは `host_frame_tests` クレートにまとめています。
+    pub fn host_test(self) -> (&'static str, &'static [&'static str]) {
+        match self {
+            Target::Xiao => ("devices/xiao_esp32s3_sense", &["--lib", "--no-default-features", "--features", "host"]),
+            Target::M5stack => ("devices/m5stack_unit_cam/host_frame_tests", &[]),
+            Target::Gateway => (
+                "server/usb_cdc_receiver",
+                &["--lib", "--tests", "--no-default-features", "--features", "host"],
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_names_round_trip() {
+        for target in Target::ALL {
+            assert_eq!(Target::from_name(target.name()), Some(target));
+        }
+        assert_eq!(Target::from_name("esp32"), None);
+    }
+
+    #[test]
+    fn test_crate_dirs_exist() {
+        for target in Target::ALL {
+            let dir = repo_root().join(target.crate_dir());
+            assert!(dir.join("Cargo.toml").is_file(), "{}", dir.display());
+            assert!(dir.join(target.partition_table()).is_file(), "{}", dir.display());
+            assert!(repo_root().join(target.host_test().0).join("Cargo.toml").is_file());
+        }
+    }
+
+    #[test]
+    fn test_firmware_features_are_declared() {
+        // クレートの機能フラグを削除・改名したら一覧も合わせる
+        for target in Target::ALL {
+            let manifest = std::fs::read_to_string(repo_root().join(target.crate_dir()).join("Cargo.toml")).unwrap();
+            for feature in target.firmware_features() {
+                assert!(
+                    manifest.lines().any(|line| line.starts_with(&format!("{} =", feature))),
+                    "{} has no feature {}",
+                    target.name(),
+                    feature
+                );
+            }
+        }
+    }
+}